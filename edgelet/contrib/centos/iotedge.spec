@@ -71,6 +71,9 @@ fi
 exit 0
 
 %post
+# Earlier versions could leave files in the home directory that are owned by root,
+# which the daemon can't update now that it runs as the iotedge user.
+chown -R %{iotedge_user}:%{iotedge_group} %{iotedge_home}
 sed -i "s/hostname: \"<ADD HOSTNAME HERE>\"/hostname: \"$(hostname)\"/g" /etc/iotedge/config.yaml
 echo "==============================================================================="
 echo ""
@@ -36,7 +36,7 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         path: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn container_archive_info(
         &self,
         id: &str,
@@ -117,6 +117,7 @@ pub trait ContainerApi: Send + Sync {
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: i32,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
@@ -132,7 +133,7 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         name: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn container_resize(
         &self,
         id: &str,
@@ -153,7 +154,7 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         stream: bool,
-    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send>;
+    ) -> Box<dyn Stream<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send>;
     fn container_stop(
         &self,
         id: &str,
@@ -185,9 +186,9 @@ pub trait ContainerApi: Send + Sync {
         &self,
         id: &str,
         path: &str,
-        input_stream: &str,
+        input_stream: Vec<u8>,
         no_overwrite_dir_non_dir: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
 }
 
 impl<C> ContainerApi for ContainerApiClient<C>
@@ -200,7 +201,7 @@ where
         &self,
         id: &str,
         path: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -232,18 +233,16 @@ where
                 .map_err(|e| Error::from(e))
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                    body.concat2()
-                        .and_then(move |body| Ok((status, body)))
-                        .map_err(|e| Error::from(e))
-                })
-                .and_then(|(status, body)| {
                     if status.is_success() {
-                        Ok(body)
+                        futures::future::Either::A(futures::future::ok(body))
                     } else {
-                        Err(Error::from((status, &*body)))
+                        futures::future::Either::B(
+                            body.concat2()
+                                .map_err(|e| Error::from(e))
+                                .and_then(move |body| Err(Error::from((status, &*body)))),
+                        )
                     }
-                })
-                .and_then(|_| futures::future::ok(())),
+                }),
         )
     }
 
@@ -819,6 +818,7 @@ where
         stdout: bool,
         stderr: bool,
         since: i32,
+        until: i32,
         timestamps: bool,
         tail: &str,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
@@ -826,14 +826,20 @@ where
 
         let method = hyper::Method::GET;
 
-        let query = ::url::form_urlencoded::Serializer::new(String::new())
+        let mut query = ::url::form_urlencoded::Serializer::new(String::new());
+        query
             .append_pair("follow", &follow.to_string())
             .append_pair("stdout", &stdout.to_string())
             .append_pair("stderr", &stderr.to_string())
             .append_pair("since", &since.to_string())
             .append_pair("timestamps", &timestamps.to_string())
-            .append_pair("tail", &tail.to_string())
-            .finish();
+            .append_pair("tail", &tail.to_string());
+        // until is only known to engines with API version 1.35 and later, so
+        // it is left out unless it is set
+        if until != 0 {
+            query.append_pair("until", &until.to_string());
+        }
+        let query = query.finish();
         let uri_str = format!("/containers/{id}/logs?{}", query, id = id);
 
         let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
@@ -859,10 +865,13 @@ where
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
                     if status.is_success() {
-                        Ok(body)
+                        futures::future::Either::A(futures::future::ok(body))
                     } else {
-                        let b: &[u8] = &[];
-                        Err(Error::from((status, b)))
+                        futures::future::Either::B(
+                            body.concat2()
+                                .map_err(|e| Error::from(e))
+                                .and_then(move |body| Err(Error::from((status, &*body)))),
+                        )
                     }
                 }),
         )
@@ -974,7 +983,7 @@ where
         &self,
         id: &str,
         name: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -1184,7 +1193,7 @@ where
         &self,
         id: &str,
         stream: bool,
-    ) -> Box<dyn Future<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send> {
+    ) -> Box<dyn Stream<Item = serde_json::Value, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -1216,21 +1225,18 @@ where
                 .map_err(|e| Error::from(e))
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                    body.concat2()
-                        .and_then(move |body| Ok((status, body)))
-                        .map_err(|e| Error::from(e))
-                })
-                .and_then(|(status, body)| {
                     if status.is_success() {
-                        Ok(body)
+                        futures::future::Either::A(futures::future::ok(body))
                     } else {
-                        Err(Error::from((status, &*body)))
+                        futures::future::Either::B(
+                            body.concat2()
+                                .map_err(|e| Error::from(e))
+                                .and_then(move |body| Err(Error::from((status, &*body)))),
+                        )
                     }
                 })
-                .and_then(|body| {
-                    let parsed: Result<serde_json::Value, _> = serde_json::from_slice(&body);
-                    parsed.map_err(|e| Error::from(e))
-                }),
+                .map(crate::utils::JsonLines::new)
+                .flatten_stream(),
         )
     }
 
@@ -1514,9 +1520,9 @@ where
         &self,
         id: &str,
         path: &str,
-        input_stream: &str,
+        input_stream: Vec<u8>,
         no_overwrite_dir_non_dir: &str,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::PUT;
@@ -1535,8 +1541,7 @@ where
         // if let Err(e) = uri {
         //     return Box::new(futures::future::err(e));
         // }
-        let serialized = serde_json::to_string(&input_stream).unwrap();
-        let serialized_len = serialized.len();
+        let input_stream_len = input_stream.len();
 
         let mut req = hyper::Request::builder();
         req.method(method).uri(uri.unwrap());
@@ -1544,12 +1549,13 @@ where
             req.header(http::header::USER_AGENT, &**user_agent);
         }
         let mut req = req
-            .body(hyper::Body::from(serialized))
+            .body(hyper::Body::from(input_stream))
             .expect("could not build hyper::Request");
+        req.headers_mut().typed_insert(&typed_headers::ContentType(
+            "application/x-tar".parse().expect("valid mime type"),
+        ));
         req.headers_mut()
-            .typed_insert(&typed_headers::ContentType(mime::APPLICATION_JSON));
-        req.headers_mut()
-            .typed_insert(&typed_headers::ContentLength(serialized_len as u64));
+            .typed_insert(&typed_headers::ContentLength(input_stream_len as u64));
 
         // send request
         Box::new(
@@ -91,11 +91,11 @@ pub trait ImageApi: Send + Sync {
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>;
     fn image_get(
         &self,
         name: &str,
-    ) -> Box<dyn Future<Item = Vec<u8>, Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn image_get_all(
         &self,
         names: Vec<String>,
@@ -115,16 +115,22 @@ pub trait ImageApi: Send + Sync {
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn image_load(
         &self,
         images_tarball: Vec<u8>,
         quiet: bool,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send>;
     fn image_prune(
         &self,
         filters: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse2009, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse2009, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn image_push(
         &self,
         name: &str,
@@ -471,7 +477,7 @@ where
         name: &str,
         force: bool,
         noprune: bool,
-    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>>>
+    ) -> Box<dyn Future<Item = Vec<ImageDeleteResponseItem>, Error = Error<serde_json::Value>> + Send>
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -527,7 +533,7 @@ where
     fn image_get(
         &self,
         name: &str,
-    ) -> Box<dyn Future<Item = Vec<u8>, Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -556,20 +562,15 @@ where
                 .map_err(|e| Error::from(e))
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                    body.concat2()
-                        .and_then(move |body| Ok((status, body)))
-                        .map_err(|e| Error::from(e))
-                })
-                .and_then(|(status, body)| {
                     if status.is_success() {
-                        Ok(body)
+                        futures::future::Either::A(futures::future::ok(body))
                     } else {
-                        Err(Error::from((status, &*body)))
+                        futures::future::Either::B(
+                            body.concat2()
+                                .map_err(|e| Error::from(e))
+                                .and_then(move |body| Err(Error::from((status, &*body)))),
+                        )
                     }
-                })
-                .and_then(|body| {
-                    let parsed: Result<Vec<u8>, _> = serde_json::from_slice(&body);
-                    parsed.map_err(|e| Error::from(e))
                 }),
         )
     }
@@ -735,8 +736,10 @@ where
         all: bool,
         filters: &str,
         digests: bool,
-    ) -> Box<dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = Vec<crate::models::ImageSummary>, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
@@ -793,7 +796,7 @@ where
         &self,
         images_tarball: Vec<u8>,
         quiet: bool,
-    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::POST;
@@ -808,8 +811,7 @@ where
         // if let Err(e) = uri {
         //     return Box::new(futures::future::err(e));
         // }
-        let serialized = serde_json::to_string(&images_tarball).unwrap();
-        let serialized_len = serialized.len();
+        let images_tarball_len = images_tarball.len();
 
         let mut req = hyper::Request::builder();
         req.method(method).uri(uri.unwrap());
@@ -817,12 +819,13 @@ where
             req.header(http::header::USER_AGENT, &**user_agent);
         }
         let mut req = req
-            .body(hyper::Body::from(serialized))
+            .body(hyper::Body::from(images_tarball))
             .expect("could not build hyper::Request");
+        req.headers_mut().typed_insert(&typed_headers::ContentType(
+            "application/x-tar".parse().expect("valid mime type"),
+        ));
         req.headers_mut()
-            .typed_insert(&typed_headers::ContentType(mime::APPLICATION_JSON));
-        req.headers_mut()
-            .typed_insert(&typed_headers::ContentLength(serialized_len as u64));
+            .typed_insert(&typed_headers::ContentLength(images_tarball_len as u64));
 
         // send request
         Box::new(
@@ -850,7 +853,10 @@ where
     fn image_prune(
         &self,
         filters: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse2009, Error = Error<serde_json::Value>>>
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse2009, Error = Error<serde_json::Value>>
+            + Send,
+    >
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -43,14 +43,20 @@ pub trait SystemApi: Send + Sync {
         since: &str,
         until: &str,
         filters: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse20012, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Stream<Item = crate::models::InlineResponse20012, Error = Error<serde_json::Value>>
+            + Send,
+    >;
     fn system_info(
         &self,
     ) -> Box<dyn Future<Item = crate::models::SystemInfo, Error = Error<serde_json::Value>> + Send>;
     fn system_ping(&self) -> Box<dyn Future<Item = String, Error = Error<serde_json::Value>>>;
     fn system_version(
         &self,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse20011, Error = Error<serde_json::Value>>>;
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse20011, Error = Error<serde_json::Value>>
+            + Send,
+    >;
 }
 
 impl<C> SystemApi for SystemApiClient<C>
@@ -174,7 +180,10 @@ where
         since: &str,
         until: &str,
         filters: &str,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse20012, Error = Error<serde_json::Value>>>
+    ) -> Box<
+        dyn Stream<Item = crate::models::InlineResponse20012, Error = Error<serde_json::Value>>
+            + Send,
+    >
     {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -209,22 +218,18 @@ where
                 .map_err(|e| Error::from(e))
                 .and_then(|resp| {
                     let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                    body.concat2()
-                        .and_then(move |body| Ok((status, body)))
-                        .map_err(|e| Error::from(e))
-                })
-                .and_then(|(status, body)| {
                     if status.is_success() {
-                        Ok(body)
+                        futures::future::Either::A(futures::future::ok(body))
                     } else {
-                        Err(Error::from((status, &*body)))
+                        futures::future::Either::B(
+                            body.concat2()
+                                .map_err(|e| Error::from(e))
+                                .and_then(move |body| Err(Error::from((status, &*body)))),
+                        )
                     }
                 })
-                .and_then(|body| {
-                    let parsed: Result<crate::models::InlineResponse20012, _> =
-                        serde_json::from_slice(&body);
-                    parsed.map_err(|e| Error::from(e))
-                }),
+                .map(crate::utils::JsonLines::new)
+                .flatten_stream(),
         )
     }
 
@@ -328,8 +333,10 @@ where
 
     fn system_version(
         &self,
-    ) -> Box<dyn Future<Item = crate::models::InlineResponse20011, Error = Error<serde_json::Value>>>
-    {
+    ) -> Box<
+        dyn Future<Item = crate::models::InlineResponse20011, Error = Error<serde_json::Value>>
+            + Send,
+    > {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
         let method = hyper::Method::GET;
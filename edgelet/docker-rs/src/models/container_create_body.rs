@@ -101,12 +101,12 @@ pub struct ContainerCreateBody {
     /// User-defined key/value metadata.
     #[serde(rename = "Labels", skip_serializing_if = "Option::is_none")]
     labels: Option<::std::collections::HashMap<String, String>>,
-    // /// Signal to stop a container as a string or unsigned integer.
-    // #[serde(rename = "StopSignal", skip_serializing_if = "Option::is_none")]
-    // stop_signal: Option<String>,
-    // /// Timeout to stop a container in seconds.
-    // #[serde(rename = "StopTimeout", skip_serializing_if = "Option::is_none")]
-    // stop_timeout: Option<i32>,
+    /// Signal to stop a container as a string or unsigned integer.
+    #[serde(rename = "StopSignal", skip_serializing_if = "Option::is_none")]
+    stop_signal: Option<String>,
+    /// Timeout to stop a container in seconds.
+    #[serde(rename = "StopTimeout", skip_serializing_if = "Option::is_none")]
+    stop_timeout: Option<i32>,
     // /// Shell for when `RUN`, `CMD`, and `ENTRYPOINT` uses a shell.
     // #[serde(rename = "Shell", skip_serializing_if = "Option::is_none")]
     // shell: Option<Vec<String>>,
@@ -143,8 +143,8 @@ impl ContainerCreateBody {
             // mac_address: None,
             // on_build: None,
             labels: None,
-            // stop_signal: None,
-            // stop_timeout: None,
+            stop_signal: None,
+            stop_timeout: None,
             // shell: None,
             host_config: None,
             networking_config: None,
@@ -529,39 +529,39 @@ impl ContainerCreateBody {
         self.labels = None;
     }
 
-    // pub fn set_stop_signal(&mut self, stop_signal: String) {
-    //     self.stop_signal = Some(stop_signal);
-    // }
+    pub fn set_stop_signal(&mut self, stop_signal: String) {
+        self.stop_signal = Some(stop_signal);
+    }
 
-    // pub fn with_stop_signal(mut self, stop_signal: String) -> Self {
-    //     self.stop_signal = Some(stop_signal);
-    //     self
-    // }
+    pub fn with_stop_signal(mut self, stop_signal: String) -> Self {
+        self.stop_signal = Some(stop_signal);
+        self
+    }
 
-    // pub fn stop_signal(&self) -> Option<&str> {
-    //     self.stop_signal.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn stop_signal(&self) -> Option<&str> {
+        self.stop_signal.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_stop_signal(&mut self) {
-    //     self.stop_signal = None;
-    // }
+    pub fn reset_stop_signal(&mut self) {
+        self.stop_signal = None;
+    }
 
-    // pub fn set_stop_timeout(&mut self, stop_timeout: i32) {
-    //     self.stop_timeout = Some(stop_timeout);
-    // }
+    pub fn set_stop_timeout(&mut self, stop_timeout: i32) {
+        self.stop_timeout = Some(stop_timeout);
+    }
 
-    // pub fn with_stop_timeout(mut self, stop_timeout: i32) -> Self {
-    //     self.stop_timeout = Some(stop_timeout);
-    //     self
-    // }
+    pub fn with_stop_timeout(mut self, stop_timeout: i32) -> Self {
+        self.stop_timeout = Some(stop_timeout);
+        self
+    }
 
-    // pub fn stop_timeout(&self) -> Option<i32> {
-    //     self.stop_timeout
-    // }
+    pub fn stop_timeout(&self) -> Option<i32> {
+        self.stop_timeout
+    }
 
-    // pub fn reset_stop_timeout(&mut self) {
-    //     self.stop_timeout = None;
-    // }
+    pub fn reset_stop_timeout(&mut self) {
+        self.stop_timeout = None;
+    }
 
     // pub fn set_shell(&mut self, shell: Vec<String>) {
     //     self.shell = Some(shell);
@@ -143,15 +143,15 @@ pub struct HostConfig {
     // container_id_file: Option<String>,
     // #[serde(rename = "LogConfig", skip_serializing_if = "Option::is_none")]
     // log_config: Option<crate::models::HostConfigLogConfig>,
-    // /// Network mode to use for this container. Supported standard values are: `bridge`, `host`, `none`, and `container:<name|id>`. Any other value is taken as a custom network's name to which this container should connect to.
-    // #[serde(rename = "NetworkMode", skip_serializing_if = "Option::is_none")]
-    // network_mode: Option<String>,
+    /// Network mode to use for this container. Supported standard values are: `bridge`, `host`, `none`, and `container:<name|id>`. Any other value is taken as a custom network's name to which this container should connect to.
+    #[serde(rename = "NetworkMode", skip_serializing_if = "Option::is_none")]
+    network_mode: Option<String>,
     /// A map of exposed container ports and the host port they should map to.
     #[serde(rename = "PortBindings", skip_serializing_if = "Option::is_none")]
     port_bindings:
         Option<::std::collections::HashMap<String, Vec<crate::models::HostConfigPortBindings>>>,
-    // #[serde(rename = "RestartPolicy", skip_serializing_if = "Option::is_none")]
-    // restart_policy: Option<crate::models::RestartPolicy>,
+    #[serde(rename = "RestartPolicy", skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<crate::models::RestartPolicy>,
     // /// Automatically remove the container when the container's process exits. This has no effect if `RestartPolicy` is set.
     // #[serde(rename = "AutoRemove", skip_serializing_if = "Option::is_none")]
     // auto_remove: Option<bool>,
@@ -197,9 +197,9 @@ pub struct HostConfig {
     // /// An integer value containing the score given to the container in order to tune OOM killer preferences.
     // #[serde(rename = "OomScoreAdj", skip_serializing_if = "Option::is_none")]
     // oom_score_adj: Option<i32>,
-    // /// Set the PID (Process) Namespace mode for the container. It can be either:  - `\"container:<name|id>\"`: joins another container's PID namespace - `\"host\"`: use the host's PID namespace inside the container
-    // #[serde(rename = "PidMode", skip_serializing_if = "Option::is_none")]
-    // pid_mode: Option<String>,
+    /// Set the PID (Process) Namespace mode for the container. It can be either:  - `\"container:<name|id>\"`: joins another container's PID namespace - `\"host\"`: use the host's PID namespace inside the container
+    #[serde(rename = "PidMode", skip_serializing_if = "Option::is_none")]
+    pid_mode: Option<String>,
     /// Gives the container full access to the host.
     #[serde(rename = "Privileged", skip_serializing_if = "Option::is_none")]
     privileged: Option<bool>,
@@ -215,9 +215,9 @@ pub struct HostConfig {
     // /// Storage driver options for this container, in the form `{\"size\": \"120G\"}`.
     // #[serde(rename = "StorageOpt", skip_serializing_if = "Option::is_none")]
     // storage_opt: Option<::std::collections::HashMap<String, String>>,
-    // /// A map of container directories which should be replaced by tmpfs mounts, and their corresponding mount options. For example: `{ \"/run\": \"rw,noexec,nosuid,size=65536k\" }`.
-    // #[serde(rename = "Tmpfs", skip_serializing_if = "Option::is_none")]
-    // tmpfs: Option<::std::collections::HashMap<String, String>>,
+    /// A map of container directories which should be replaced by tmpfs mounts, and their corresponding mount options. For example: `{ \"/run\": \"rw,noexec,nosuid,size=65536k\" }`.
+    #[serde(rename = "Tmpfs", skip_serializing_if = "Option::is_none")]
+    tmpfs: Option<::std::collections::HashMap<String, String>>,
     // /// UTS namespace to use for the container.
     // #[serde(rename = "UTSMode", skip_serializing_if = "Option::is_none")]
     // uts_mode: Option<String>,
@@ -230,9 +230,9 @@ pub struct HostConfig {
     // /// A list of kernel parameters (sysctls) to set in the container. For example: `{\"net.ipv4.ip_forward\": \"1\"}`
     // #[serde(rename = "Sysctls", skip_serializing_if = "Option::is_none")]
     // sysctls: Option<::std::collections::HashMap<String, String>>,
-    // /// Runtime to use with this container.
-    // #[serde(rename = "Runtime", skip_serializing_if = "Option::is_none")]
-    // runtime: Option<String>,
+    /// Runtime to use with this container.
+    #[serde(rename = "Runtime", skip_serializing_if = "Option::is_none")]
+    runtime: Option<String>,
     // /// Initial console size, as an `[height, width]` array. (Windows only)
     // #[serde(rename = "ConsoleSize", skip_serializing_if = "Option::is_none")]
     // console_size: Option<Vec<i32>>,
@@ -280,9 +280,9 @@ impl HostConfig {
             binds: None,
             // container_id_file: None,
             // log_config: None,
-            // network_mode: None,
+            network_mode: None,
             port_bindings: None,
-            // restart_policy: None,
+            restart_policy: None,
             // auto_remove: None,
             // volume_driver: None,
             // volumes_from: None,
@@ -298,18 +298,18 @@ impl HostConfig {
             // cgroup: None,
             // links: None,
             // oom_score_adj: None,
-            // pid_mode: None,
+            pid_mode: None,
             privileged: None,
             // publish_all_ports: None,
             // readonly_rootfs: None,
             // security_opt: None,
             // storage_opt: None,
-            // tmpfs: None,
+            tmpfs: None,
             // uts_mode: None,
             // userns_mode: None,
             // shm_size: None,
             // sysctls: None,
-            // runtime: None,
+            runtime: None,
             // console_size: None,
             // isolation: None,
             other_properties: Default::default(),
@@ -907,22 +907,22 @@ impl HostConfig {
     //     self.log_config = None;
     // }
 
-    // pub fn set_network_mode(&mut self, network_mode: String) {
-    //     self.network_mode = Some(network_mode);
-    // }
+    pub fn set_network_mode(&mut self, network_mode: String) {
+        self.network_mode = Some(network_mode);
+    }
 
-    // pub fn with_network_mode(mut self, network_mode: String) -> Self {
-    //     self.network_mode = Some(network_mode);
-    //     self
-    // }
+    pub fn with_network_mode(mut self, network_mode: String) -> Self {
+        self.network_mode = Some(network_mode);
+        self
+    }
 
-    // pub fn network_mode(&self) -> Option<&str> {
-    //     self.network_mode.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn network_mode(&self) -> Option<&str> {
+        self.network_mode.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_network_mode(&mut self) {
-    //     self.network_mode = None;
-    // }
+    pub fn reset_network_mode(&mut self) {
+        self.network_mode = None;
+    }
 
     pub fn set_port_bindings(
         &mut self,
@@ -956,22 +956,22 @@ impl HostConfig {
         self.port_bindings = None;
     }
 
-    // pub fn set_restart_policy(&mut self, restart_policy: crate::models::RestartPolicy) {
-    //     self.restart_policy = Some(restart_policy);
-    // }
+    pub fn set_restart_policy(&mut self, restart_policy: crate::models::RestartPolicy) {
+        self.restart_policy = Some(restart_policy);
+    }
 
-    // pub fn with_restart_policy(mut self, restart_policy: crate::models::RestartPolicy) -> Self {
-    //     self.restart_policy = Some(restart_policy);
-    //     self
-    // }
+    pub fn with_restart_policy(mut self, restart_policy: crate::models::RestartPolicy) -> Self {
+        self.restart_policy = Some(restart_policy);
+        self
+    }
 
-    // pub fn restart_policy(&self) -> Option<&crate::models::RestartPolicy> {
-    //     self.restart_policy.as_ref()
-    // }
+    pub fn restart_policy(&self) -> Option<&crate::models::RestartPolicy> {
+        self.restart_policy.as_ref()
+    }
 
-    // pub fn reset_restart_policy(&mut self) {
-    //     self.restart_policy = None;
-    // }
+    pub fn reset_restart_policy(&mut self) {
+        self.restart_policy = None;
+    }
 
     // pub fn set_auto_remove(&mut self, auto_remove: bool) {
     //     self.auto_remove = Some(auto_remove);
@@ -1228,22 +1228,22 @@ impl HostConfig {
     //     self.oom_score_adj = None;
     // }
 
-    // pub fn set_pid_mode(&mut self, pid_mode: String) {
-    //     self.pid_mode = Some(pid_mode);
-    // }
+    pub fn set_pid_mode(&mut self, pid_mode: String) {
+        self.pid_mode = Some(pid_mode);
+    }
 
-    // pub fn with_pid_mode(mut self, pid_mode: String) -> Self {
-    //     self.pid_mode = Some(pid_mode);
-    //     self
-    // }
+    pub fn with_pid_mode(mut self, pid_mode: String) -> Self {
+        self.pid_mode = Some(pid_mode);
+        self
+    }
 
-    // pub fn pid_mode(&self) -> Option<&str> {
-    //     self.pid_mode.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn pid_mode(&self) -> Option<&str> {
+        self.pid_mode.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_pid_mode(&mut self) {
-    //     self.pid_mode = None;
-    // }
+    pub fn reset_pid_mode(&mut self) {
+        self.pid_mode = None;
+    }
 
     pub fn set_privileged(&mut self, privileged: bool) {
         self.privileged = Some(privileged);
@@ -1333,22 +1333,22 @@ impl HostConfig {
     //     self.storage_opt = None;
     // }
 
-    // pub fn set_tmpfs(&mut self, tmpfs: ::std::collections::HashMap<String, String>) {
-    //     self.tmpfs = Some(tmpfs);
-    // }
+    pub fn set_tmpfs(&mut self, tmpfs: ::std::collections::HashMap<String, String>) {
+        self.tmpfs = Some(tmpfs);
+    }
 
-    // pub fn with_tmpfs(mut self, tmpfs: ::std::collections::HashMap<String, String>) -> Self {
-    //     self.tmpfs = Some(tmpfs);
-    //     self
-    // }
+    pub fn with_tmpfs(mut self, tmpfs: ::std::collections::HashMap<String, String>) -> Self {
+        self.tmpfs = Some(tmpfs);
+        self
+    }
 
-    // pub fn tmpfs(&self) -> Option<&::std::collections::HashMap<String, String>> {
-    //     self.tmpfs.as_ref()
-    // }
+    pub fn tmpfs(&self) -> Option<&::std::collections::HashMap<String, String>> {
+        self.tmpfs.as_ref()
+    }
 
-    // pub fn reset_tmpfs(&mut self) {
-    //     self.tmpfs = None;
-    // }
+    pub fn reset_tmpfs(&mut self) {
+        self.tmpfs = None;
+    }
 
     // pub fn set_uts_mode(&mut self, uts_mode: String) {
     //     self.uts_mode = Some(uts_mode);
@@ -1418,22 +1418,22 @@ impl HostConfig {
     //     self.sysctls = None;
     // }
 
-    // pub fn set_runtime(&mut self, runtime: String) {
-    //     self.runtime = Some(runtime);
-    // }
+    pub fn set_runtime(&mut self, runtime: String) {
+        self.runtime = Some(runtime);
+    }
 
-    // pub fn with_runtime(mut self, runtime: String) -> Self {
-    //     self.runtime = Some(runtime);
-    //     self
-    // }
+    pub fn with_runtime(mut self, runtime: String) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
 
-    // pub fn runtime(&self) -> Option<&str> {
-    //     self.runtime.as_ref().map(AsRef::as_ref)
-    // }
+    pub fn runtime(&self) -> Option<&str> {
+        self.runtime.as_ref().map(AsRef::as_ref)
+    }
 
-    // pub fn reset_runtime(&mut self) {
-    //     self.runtime = None;
-    // }
+    pub fn reset_runtime(&mut self) {
+        self.runtime = None;
+    }
 
     // pub fn set_console_size(&mut self, console_size: Vec<i32>) {
     //     self.console_size = Some(console_size);
@@ -24,11 +24,11 @@ pub struct ImageSummary {
     #[serde(rename = "Created")]
     created: i32,
     #[serde(rename = "Size")]
-    size: i32,
+    size: i64,
     #[serde(rename = "SharedSize")]
-    shared_size: i32,
+    shared_size: i64,
     #[serde(rename = "VirtualSize")]
-    virtual_size: i32,
+    virtual_size: i64,
     #[serde(rename = "Labels")]
     labels: ::std::collections::HashMap<String, String>,
     #[serde(rename = "Containers")]
@@ -42,9 +42,9 @@ impl ImageSummary {
         repo_tags: Vec<String>,
         repo_digests: Vec<String>,
         created: i32,
-        size: i32,
-        shared_size: i32,
-        virtual_size: i32,
+        size: i64,
+        shared_size: i64,
+        virtual_size: i64,
         labels: ::std::collections::HashMap<String, String>,
         containers: i32,
     ) -> Self {
@@ -127,42 +127,42 @@ impl ImageSummary {
         &self.created
     }
 
-    pub fn set_size(&mut self, size: i32) {
+    pub fn set_size(&mut self, size: i64) {
         self.size = size;
     }
 
-    pub fn with_size(mut self, size: i32) -> Self {
+    pub fn with_size(mut self, size: i64) -> Self {
         self.size = size;
         self
     }
 
-    pub fn size(&self) -> &i32 {
+    pub fn size(&self) -> &i64 {
         &self.size
     }
 
-    pub fn set_shared_size(&mut self, shared_size: i32) {
+    pub fn set_shared_size(&mut self, shared_size: i64) {
         self.shared_size = shared_size;
     }
 
-    pub fn with_shared_size(mut self, shared_size: i32) -> Self {
+    pub fn with_shared_size(mut self, shared_size: i64) -> Self {
         self.shared_size = shared_size;
         self
     }
 
-    pub fn shared_size(&self) -> &i32 {
+    pub fn shared_size(&self) -> &i64 {
         &self.shared_size
     }
 
-    pub fn set_virtual_size(&mut self, virtual_size: i32) {
+    pub fn set_virtual_size(&mut self, virtual_size: i64) {
         self.virtual_size = virtual_size;
     }
 
-    pub fn with_virtual_size(mut self, virtual_size: i32) -> Self {
+    pub fn with_virtual_size(mut self, virtual_size: i64) -> Self {
         self.virtual_size = virtual_size;
         self
     }
 
-    pub fn virtual_size(&self) -> &i32 {
+    pub fn virtual_size(&self) -> &i64 {
         &self.virtual_size
     }
 
@@ -49,12 +49,12 @@ pub struct Mount {
     // /// The consistency requirement for the mount: `default`, `consistent`, `cached`, or `delegated`.
     // #[serde(rename = "Consistency", skip_serializing_if = "Option::is_none")]
     // consistency: Option<String>,
-    // #[serde(rename = "BindOptions", skip_serializing_if = "Option::is_none")]
-    // bind_options: Option<crate::models::MountBindOptions>,
+    #[serde(rename = "BindOptions", skip_serializing_if = "Option::is_none")]
+    bind_options: Option<crate::models::MountBindOptions>,
     // #[serde(rename = "VolumeOptions", skip_serializing_if = "Option::is_none")]
     // volume_options: Option<crate::models::MountVolumeOptions>,
-    // #[serde(rename = "TmpfsOptions", skip_serializing_if = "Option::is_none")]
-    // tmpfs_options: Option<crate::models::MountTmpfsOptions>,
+    #[serde(rename = "TmpfsOptions", skip_serializing_if = "Option::is_none")]
+    tmpfs_options: Option<crate::models::MountTmpfsOptions>,
     #[serde(flatten)]
     other_properties: std::collections::HashMap<String, serde_json::Value>,
 }
@@ -67,9 +67,9 @@ impl Mount {
             _type: None,
             read_only: None,
             // consistency: None,
-            // bind_options: None,
+            bind_options: None,
             // volume_options: None,
-            // tmpfs_options: None,
+            tmpfs_options: None,
             other_properties: Default::default(),
         }
     }
@@ -159,22 +159,22 @@ impl Mount {
     //     self.consistency = None;
     // }
 
-    // pub fn set_bind_options(&mut self, bind_options: crate::models::MountBindOptions) {
-    //     self.bind_options = Some(bind_options);
-    // }
+    pub fn set_bind_options(&mut self, bind_options: crate::models::MountBindOptions) {
+        self.bind_options = Some(bind_options);
+    }
 
-    // pub fn with_bind_options(mut self, bind_options: crate::models::MountBindOptions) -> Self {
-    //     self.bind_options = Some(bind_options);
-    //     self
-    // }
+    pub fn with_bind_options(mut self, bind_options: crate::models::MountBindOptions) -> Self {
+        self.bind_options = Some(bind_options);
+        self
+    }
 
-    // pub fn bind_options(&self) -> Option<&crate::models::MountBindOptions> {
-    //     self.bind_options.as_ref()
-    // }
+    pub fn bind_options(&self) -> Option<&crate::models::MountBindOptions> {
+        self.bind_options.as_ref()
+    }
 
-    // pub fn reset_bind_options(&mut self) {
-    //     self.bind_options = None;
-    // }
+    pub fn reset_bind_options(&mut self) {
+        self.bind_options = None;
+    }
 
     // pub fn set_volume_options(&mut self, volume_options: crate::models::MountVolumeOptions) {
     //     self.volume_options = Some(volume_options);
@@ -196,20 +196,20 @@ impl Mount {
     //     self.volume_options = None;
     // }
 
-    // pub fn set_tmpfs_options(&mut self, tmpfs_options: crate::models::MountTmpfsOptions) {
-    //     self.tmpfs_options = Some(tmpfs_options);
-    // }
+    pub fn set_tmpfs_options(&mut self, tmpfs_options: crate::models::MountTmpfsOptions) {
+        self.tmpfs_options = Some(tmpfs_options);
+    }
 
-    // pub fn with_tmpfs_options(mut self, tmpfs_options: crate::models::MountTmpfsOptions) -> Self {
-    //     self.tmpfs_options = Some(tmpfs_options);
-    //     self
-    // }
+    pub fn with_tmpfs_options(mut self, tmpfs_options: crate::models::MountTmpfsOptions) -> Self {
+        self.tmpfs_options = Some(tmpfs_options);
+        self
+    }
 
-    // pub fn tmpfs_options(&self) -> Option<&crate::models::MountTmpfsOptions> {
-    //     self.tmpfs_options.as_ref()
-    // }
+    pub fn tmpfs_options(&self) -> Option<&crate::models::MountTmpfsOptions> {
+        self.tmpfs_options.as_ref()
+    }
 
-    // pub fn reset_tmpfs_options(&mut self) {
-    //     self.tmpfs_options = None;
-    // }
+    pub fn reset_tmpfs_options(&mut self) {
+        self.tmpfs_options = None;
+    }
 }
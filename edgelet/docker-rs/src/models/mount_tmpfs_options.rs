@@ -13,6 +13,27 @@
 #[allow(unused_imports)]
 use serde_json::Value;
 
+// DEVNOTE: Why is most of this type commented out?
+//
+// We do not want to restrict the properties that the user can set in their create options, because future versions of Docker can add new properties
+// that we don't define here.
+//
+// So this type has a `#[serde(flatten)] HashMap` field to collect all the extra properties that we don't have a struct field for.
+//
+// But if an existing field references another type under `crate::models::`, then that would still be parsed lossily, so we would have to also add
+// a `#[serde(flatten)] HashMap` field there. And if that type has fields that reference types under `crate::models::` ...
+//
+// To avoid having to do this for effectively the whole crate, instead we've just commented out the fields we don't use in our code.
+//
+// ---
+//
+// If you need to access a commented out field, uncomment it.
+//
+// - If it's a simple built-in type, then that is all you need to do.
+//
+// - Otherwise if it references another type under `crate::models::`, then ensure that that type also has a `#[serde(flatten)] HashMap` property
+//   and is commented out as much as possible. Also copy this devnote there for future readers.
+
 #[derive(Debug, serde_derive::Serialize, serde_derive::Deserialize, Clone)]
 pub struct MountTmpfsOptions {
     /// The size for the tmpfs mount in bytes.
@@ -21,6 +42,8 @@ pub struct MountTmpfsOptions {
     /// The permission mode for the tmpfs mount in an integer.
     #[serde(rename = "Mode", skip_serializing_if = "Option::is_none")]
     mode: Option<i32>,
+    #[serde(flatten)]
+    other_properties: std::collections::HashMap<String, serde_json::Value>,
 }
 
 impl MountTmpfsOptions {
@@ -29,6 +52,7 @@ impl MountTmpfsOptions {
         MountTmpfsOptions {
             size_bytes: None,
             mode: None,
+            other_properties: Default::default(),
         }
     }
 
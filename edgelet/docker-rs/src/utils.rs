@@ -1,10 +1,14 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use futures::{Async, Poll, Stream};
+use serde::de::DeserializeOwned;
 use serde_json;
 use std::borrow::Cow;
+use std::marker::PhantomData;
 use std::str::FromStr;
 use typed_headers::{self, http};
 
+use crate::apis::Error;
 use crate::models::ContainerCreateBody;
 
 impl FromStr for ContainerCreateBody {
@@ -48,3 +52,107 @@ impl<'a> typed_headers::Header for UserAgent<'a> {
         typed_headers::util::encode_single_value(&self.0, values);
     }
 }
+
+/// The values of a streaming response body, like the ones of the events and
+/// stats endpoints, which send one JSON document per line for as long as the
+/// request is open. Every value is yielded as soon as its line is complete,
+/// so the body is never buffered as a whole.
+pub(crate) struct JsonLines<T> {
+    body: hyper::Body,
+    buffer: Vec<u8>,
+    done: bool,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<T> JsonLines<T> {
+    pub(crate) fn new(body: hyper::Body) -> Self {
+        JsonLines {
+            body,
+            buffer: Vec::new(),
+            done: false,
+            item: PhantomData,
+        }
+    }
+
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let line = if let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+                let mut line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                line.pop();
+                line
+            } else if self.done && !self.buffer.is_empty() {
+                self.buffer.split_off(0)
+            } else {
+                return None;
+            };
+
+            if line.iter().any(|b| !b.is_ascii_whitespace()) {
+                return Some(line);
+            }
+        }
+    }
+}
+
+impl<T> Stream for JsonLines<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = T;
+    type Error = Error<serde_json::Value>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(line) = self.next_line() {
+                let value = serde_json::from_slice(&line)?;
+                return Ok(Async::Ready(Some(value)));
+            }
+
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            match self.body.poll()? {
+                Async::Ready(Some(chunk)) => self.buffer.extend_from_slice(&chunk),
+                Async::Ready(None) => self.done = true,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, Future};
+
+    use super::*;
+
+    #[test]
+    fn json_lines_splits_values_across_chunks() {
+        let chunks: Vec<Result<_, std::io::Error>> = vec![
+            Ok("{\"id\": 1}\n{\"id\"".to_string()),
+            Ok(": 2}\n\n".to_string()),
+            Ok("{\"id\": 3}".to_string()),
+        ];
+        let body = hyper::Body::wrap_stream(stream::iter_result(chunks));
+
+        let values: Vec<serde_json::Value> = JsonLines::new(body).collect().wait().unwrap();
+
+        let ids: Vec<_> = values.iter().map(|value| value["id"].as_i64()).collect();
+        assert_eq!(vec![Some(1), Some(2), Some(3)], ids);
+    }
+
+    #[test]
+    fn json_lines_fails_on_malformed_value() {
+        let chunks: Vec<Result<_, std::io::Error>> =
+            vec![Ok("{\"id\": 1}\nnot json\n".to_string())];
+        let body = hyper::Body::wrap_stream(stream::iter_result(chunks));
+
+        let mut values = JsonLines::<serde_json::Value>::new(body).wait();
+
+        assert!(values.next().unwrap().is_ok());
+        match values.next() {
+            Some(Err(Error::Serde(_))) => (),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+}
@@ -12,7 +12,7 @@ pub struct Error {
     inner: Context<ErrorKind>,
 }
 
-#[derive(Clone, Copy, Debug, Fail)]
+#[derive(Clone, Debug, Fail)]
 pub enum ErrorKind {
     #[fail(display = "Could not get device registration result")]
     GetDeviceRegistrationResult,
@@ -38,6 +38,18 @@ pub enum ErrorKind {
     #[fail(display = "Could not get TPM challenge key because the TPM token is invalid")]
     InvalidTpmToken,
 
+    #[fail(display = "Could not communicate with DPS over MQTT")]
+    MqttConnection,
+
+    #[fail(display = "DPS refused the MQTT connection with return code {}", _0)]
+    MqttConnectionRefused(u8),
+
+    #[fail(display = "DPS sent an unexpected MQTT packet")]
+    MqttProtocol,
+
+    #[fail(display = "DPS request over MQTT failed with status {}", _0)]
+    MqttResponse(u16),
+
     #[fail(display = "DPS registration failed")]
     RegisterWithAuthUnexpectedlyFailed,
 
@@ -47,6 +59,12 @@ pub enum ErrorKind {
     #[fail(display = "DPS registration succeeded but returned an empty response")]
     RegisterWithAuthUnexpectedlySucceeded,
 
+    #[fail(display = "DPS registration failed with status {}", _0)]
+    RegistrationFailed(String),
+
+    #[fail(display = "Could not wait to retry the DPS request")]
+    RetryDelay,
+
     #[fail(display = "Symmetric key based registration failed")]
     RegisterWithSymmetricChallengeKey,
 
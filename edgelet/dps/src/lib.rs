@@ -13,6 +13,7 @@
 pub mod dps;
 pub mod error;
 mod model;
+pub mod mqtt;
 pub mod registration;
 
 pub use error::{Error, ErrorKind};
@@ -20,6 +21,6 @@ pub use model::{
     DeviceRegistration, DeviceRegistrationResult, RegistrationOperationStatus,
     TpmRegistrationResult, X509CertificateInfo, X509RegistrationResult,
 };
-pub use registration::{DpsClient, DpsTokenSource};
+pub use registration::{DpsClient, DpsTokenSource, RegistrationInfo};
 
 pub const DPS_API_VERSION: &str = "2018-11-01";
@@ -0,0 +1,1079 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Registration with DPS over MQTT, for networks that only allow outbound
+//! connections on port 8883.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use chrono::{Duration as TokenDuration, Utc};
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either, Loop};
+use futures::{Future, Sink, Stream};
+use log::{debug, info};
+use native_tls::TlsConnector;
+use serde_json::{self, Value};
+use tokio::codec::{Decoder, Encoder, Framed};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::timer::{Delay, Timeout};
+use url::form_urlencoded;
+
+use edgelet_core::crypto::Sign;
+use edgelet_http::client::TokenSource;
+use edgelet_http::PemCertificate;
+
+use crate::error::{Error, ErrorKind};
+use crate::model::{DeviceRegistrationResult, RegistrationOperationStatus};
+use crate::registration::{
+    device_registration, get_device_info, registration_failure, DpsTokenSource, RegistrationInfo,
+    DPS_ASSIGNMENT_RETRY_INTERVAL_SECS, DPS_REGISTRATION_TIMEOUT_SECS,
+};
+use crate::DPS_API_VERSION;
+
+/// The port DPS accepts MQTT connections on.
+const DPS_MQTT_PORT: u16 = 8883;
+
+/// How long the connection may stay idle before DPS closes it.
+const KEEP_ALIVE_SECS: u16 = 240;
+
+const RESPONSE_TOPIC_PREFIX: &str = "$dps/registrations/res/";
+const RESPONSE_TOPIC_FILTER: &str = "$dps/registrations/res/#";
+
+const PACKET_ID_SUBSCRIBE: u16 = 1;
+
+/// How the device authenticates with DPS over MQTT.
+pub enum MqttAuth<K> {
+    /// With a SAS token signed by the device's symmetric key.
+    SymmetricKey(K),
+    /// With the device's identity certificate, during the TLS handshake.
+    X509(PemCertificate),
+}
+
+pub struct MqttDpsClient<K> {
+    host: String,
+    scope_id: String,
+    registration_id: String,
+    auth: MqttAuth<K>,
+    payload: Option<Value>,
+}
+
+impl<K> MqttDpsClient<K>
+where
+    K: 'static + Sign + Clone + Send,
+{
+    pub fn new(host: String, scope_id: String, registration_id: String, auth: MqttAuth<K>) -> Self {
+        MqttDpsClient {
+            host,
+            scope_id,
+            registration_id,
+            auth,
+            payload: None,
+        }
+    }
+
+    pub fn with_payload(mut self, payload: Option<Value>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub fn register(&self) -> Box<dyn Future<Item = RegistrationInfo, Error = Error> + Send> {
+        info!(
+            "Starting DPS registration over MQTT with scope_id \"{}\", registration_id \"{}\"",
+            self.scope_id, self.registration_id,
+        );
+
+        let prepared = self
+            .connect_packet()
+            .and_then(|connect| Ok((connect, self.tls_connector()?)))
+            .and_then(|(connect, connector)| {
+                let registration =
+                    device_registration(self.registration_id.clone(), self.payload.clone());
+                let body = serde_json::to_vec(&registration).context(ErrorKind::MqttProtocol)?;
+                Ok((connect, connector, Bytes::from(body)))
+            });
+        let host = self.host.clone();
+
+        let registration = future::result(prepared)
+            .and_then(move |(connect, connector, body)| {
+                connect_tls(host, connector).and_then(|stream| register_over(stream, connect, body))
+            })
+            .and_then(|registration_result| {
+                if let Some(failure) = registration_failure(&registration_result) {
+                    return Err(Error::from(ErrorKind::RegistrationFailed(failure)));
+                }
+                get_device_info(&registration_result)
+            });
+
+        let registration = Timeout::new(
+            registration,
+            Duration::from_secs(DPS_REGISTRATION_TIMEOUT_SECS),
+        )
+        .map_err(|err| {
+            err.into_inner().unwrap_or_else(|| {
+                debug!("DPS did not complete the registration in time");
+                Error::from(ErrorKind::RegisterWithAuthUnexpectedlyFailedOperationNotAssigned)
+            })
+        });
+        Box::new(registration)
+    }
+
+    fn connect_packet(&self) -> Result<Packet, Error> {
+        let password = match &self.auth {
+            MqttAuth::SymmetricKey(key) => {
+                let token_source = DpsTokenSource::new(
+                    self.scope_id.clone(),
+                    self.registration_id.clone(),
+                    key.clone(),
+                );
+                let token = token_source.get(&(Utc::now() + TokenDuration::hours(1)))?;
+                Some(format!("SharedAccessSignature {}", token))
+            }
+            MqttAuth::X509(_) => None,
+        };
+
+        Ok(Packet::Connect {
+            client_id: self.registration_id.clone(),
+            username: format!(
+                "{}/registrations/{}/api-version={}",
+                self.scope_id, self.registration_id, DPS_API_VERSION
+            ),
+            password,
+            keep_alive: KEEP_ALIVE_SECS,
+        })
+    }
+
+    fn tls_connector(&self) -> Result<TlsConnector, Error> {
+        let mut builder = TlsConnector::builder();
+        if let MqttAuth::X509(identity_cert) = &self.auth {
+            let identity = identity_cert
+                .get_identity()
+                .context(ErrorKind::MqttConnection)?;
+            builder.identity(identity);
+        }
+        let connector = builder.build().context(ErrorKind::MqttConnection)?;
+        Ok(connector)
+    }
+}
+
+fn connect_tls(
+    host: String,
+    connector: TlsConnector,
+) -> impl Future<Item = tokio_tls::TlsStream<TcpStream>, Error = Error> {
+    future::lazy(move || {
+        let addrs: Vec<SocketAddr> = (host.as_str(), DPS_MQTT_PORT)
+            .to_socket_addrs()
+            .context(ErrorKind::MqttConnection)?
+            .collect();
+        Ok((host, addrs))
+    })
+    .and_then(|(host, addrs)| connect_any(addrs).map(|stream| (host, stream)))
+    .and_then(move |(host, stream)| {
+        tokio_tls::TlsConnector::from(connector)
+            .connect(&host, stream)
+            .map_err(|err| Error::from(err.context(ErrorKind::MqttConnection)))
+    })
+}
+
+// Connects to the addresses in turn until one accepts, as the DPS host name
+// resolves to both IPv4 and IPv6 addresses and the host may only reach one
+// of the families.
+fn connect_any(addrs: Vec<SocketAddr>) -> impl Future<Item = TcpStream, Error = Error> {
+    future::loop_fn(
+        (addrs.into_iter(), None),
+        |(mut addrs, last_err): (_, Option<io::Error>)| match addrs.next() {
+            Some(addr) => Either::A(TcpStream::connect(&addr).then(move |result| match result {
+                Ok(stream) => Ok(Loop::Break(stream)),
+                Err(err) => {
+                    debug!("Could not connect to {}: {}", addr, err);
+                    Ok(Loop::Continue((addrs, Some(err))))
+                }
+            })),
+            None => Either::B(future::err(match last_err {
+                Some(err) => Error::from(err.context(ErrorKind::MqttConnection)),
+                None => Error::from(ErrorKind::MqttConnection),
+            })),
+        },
+    )
+}
+
+type Connection<S> = Framed<S, MqttCodec>;
+
+// Registers the device on an established connection to DPS, and polls the
+// registration until DPS has completed it.
+fn register_over<S>(
+    stream: S,
+    connect: Packet,
+    body: Bytes,
+) -> impl Future<Item = DeviceRegistrationResult, Error = Error>
+where
+    S: 'static + AsyncRead + AsyncWrite + Send,
+{
+    MqttCodec
+        .framed(stream)
+        .send(connect)
+        .map_err(connection_error)
+        .and_then(receive)
+        .and_then(|(packet, connection)| match packet {
+            Packet::ConnAck { return_code: 0 } => Ok(connection),
+            Packet::ConnAck { return_code } => {
+                Err(Error::from(ErrorKind::MqttConnectionRefused(return_code)))
+            }
+            _ => Err(Error::from(ErrorKind::MqttProtocol)),
+        })
+        .and_then(|connection| {
+            connection
+                .send(Packet::Subscribe {
+                    packet_id: PACKET_ID_SUBSCRIBE,
+                    topic_filter: RESPONSE_TOPIC_FILTER.to_string(),
+                    qos: 1,
+                })
+                .map_err(connection_error)
+        })
+        .and_then(receive)
+        .and_then(|(packet, connection)| match packet {
+            Packet::SubAck {
+                packet_id: PACKET_ID_SUBSCRIBE,
+                return_code,
+            } if return_code != 0x80 => Ok(connection),
+            _ => Err(Error::from(ErrorKind::MqttProtocol)),
+        })
+        .and_then(move |connection| {
+            future::loop_fn(
+                (connection, PACKET_ID_SUBSCRIBE + 1, None, None),
+                move |(connection, packet_id, operation_id, delay)| {
+                    poll_registration(connection, packet_id, operation_id, delay, body.clone())
+                },
+            )
+        })
+        .and_then(|(registration_result, connection)| {
+            // The registration is complete, so failing to disconnect cleanly doesn't matter.
+            connection
+                .send(Packet::Disconnect)
+                .then(move |_| Ok(registration_result))
+        })
+}
+
+type PollState<S> = (Connection<S>, u16, Option<String>, Option<Duration>);
+
+// Sends the registration request, or asks for the status of the registration
+// once DPS has accepted it, and decides what to do with the response.
+fn poll_registration<S>(
+    connection: Connection<S>,
+    packet_id: u16,
+    operation_id: Option<String>,
+    delay: Option<Duration>,
+    body: Bytes,
+) -> impl Future<Item = Loop<(DeviceRegistrationResult, Connection<S>), PollState<S>>, Error = Error>
+where
+    S: 'static + AsyncRead + AsyncWrite + Send,
+{
+    let wait = match delay {
+        Some(delay) => Either::A(
+            Delay::new(Instant::now() + delay)
+                .map_err(|err| Error::from(err.context(ErrorKind::RetryDelay))),
+        ),
+        None => Either::B(future::ok(())),
+    };
+
+    let request = match &operation_id {
+        None => Packet::Publish {
+            packet_id: Some(packet_id),
+            topic: format!("$dps/registrations/PUT/iotdps-register/?$rid={}", packet_id),
+            payload: body,
+        },
+        Some(operation_id) => {
+            debug!("Ask DPS for registration status");
+            Packet::Publish {
+                packet_id: Some(packet_id),
+                topic: format!(
+                    "$dps/registrations/GET/iotdps-get-operationstatus/?$rid={}&operationId={}",
+                    packet_id, operation_id
+                ),
+                payload: Bytes::new(),
+            }
+        }
+    };
+
+    wait.and_then(move |()| connection.send(request).map_err(connection_error))
+        .and_then(receive_response)
+        .and_then(move |(response, connection)| {
+            let next_packet_id = packet_id.wrapping_add(1).max(PACKET_ID_SUBSCRIBE + 1);
+            let retry_after = response
+                .retry_after
+                .unwrap_or_else(|| Duration::from_secs(DPS_ASSIGNMENT_RETRY_INTERVAL_SECS));
+
+            match response.status {
+                200 | 202 => {
+                    let status: RegistrationOperationStatus =
+                        serde_json::from_slice(&response.body)
+                            .context(ErrorKind::MqttResponse(response.status))?;
+                    let pending = status.status().map_or(true, |status| {
+                        status.eq_ignore_ascii_case("assigning")
+                            || status.eq_ignore_ascii_case("unassigned")
+                    });
+
+                    match status.registration_state() {
+                        Some(registration_result) if !pending => {
+                            Ok(Loop::Break((registration_result.clone(), connection)))
+                        }
+                        _ => {
+                            if let Some(status) = status.status() {
+                                info!("DPS registration status is \"{}\"", status);
+                            }
+                            Ok(Loop::Continue((
+                                connection,
+                                next_packet_id,
+                                Some(status.operation_id().clone()),
+                                Some(retry_after),
+                            )))
+                        }
+                    }
+                }
+
+                // DPS is throttling or unavailable, so the same request is sent again.
+                status if status == 429 || status >= 500 => {
+                    info!(
+                        "DPS request failed with status {}, retrying in {} s",
+                        status,
+                        retry_after.as_secs()
+                    );
+                    Ok(Loop::Continue((
+                        connection,
+                        next_packet_id,
+                        operation_id,
+                        Some(retry_after),
+                    )))
+                }
+
+                status => Err(Error::from(ErrorKind::MqttResponse(status))),
+            }
+        })
+}
+
+fn connection_error(err: io::Error) -> Error {
+    Error::from(err.context(ErrorKind::MqttConnection))
+}
+
+fn receive<S>(
+    connection: Connection<S>,
+) -> impl Future<Item = (Packet, Connection<S>), Error = Error>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    connection
+        .into_future()
+        .map_err(|(err, _)| connection_error(err))
+        .and_then(|(packet, connection)| match packet {
+            Some(packet) => Ok((packet, connection)),
+            None => Err(Error::from(ErrorKind::MqttConnection)),
+        })
+}
+
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    retry_after: Option<Duration>,
+    body: Bytes,
+}
+
+// Waits for the next response from DPS, acknowledging it if DPS asks to.
+fn receive_response<S>(
+    connection: Connection<S>,
+) -> impl Future<Item = (Response, Connection<S>), Error = Error>
+where
+    S: 'static + AsyncRead + AsyncWrite + Send,
+{
+    future::loop_fn(connection, |connection| {
+        receive(connection)
+            .and_then(|(packet, connection)| match packet {
+                Packet::Publish {
+                    packet_id,
+                    topic,
+                    payload,
+                } => {
+                    let response = parse_response(&topic, payload)?;
+                    let ack = match packet_id {
+                        Some(packet_id) => Either::A(
+                            connection
+                                .send(Packet::PubAck { packet_id })
+                                .map_err(connection_error),
+                        ),
+                        None => Either::B(future::ok(connection)),
+                    };
+                    Ok(Either::A(
+                        ack.map(|connection| Loop::Break((response, connection))),
+                    ))
+                }
+                Packet::PubAck { .. } | Packet::PingResp => {
+                    Ok(Either::B(future::ok(Loop::Continue(connection))))
+                }
+                _ => Err(Error::from(ErrorKind::MqttProtocol)),
+            })
+            .and_then(|next| next)
+    })
+}
+
+// Responses are published to "$dps/registrations/res/{status}/?$rid={request id}",
+// with a "retry-after" parameter when DPS wants the request to be sent again later.
+fn parse_response(topic: &str, body: Bytes) -> Result<Response, Error> {
+    if !topic.starts_with(RESPONSE_TOPIC_PREFIX) {
+        return Err(Error::from(ErrorKind::MqttProtocol));
+    }
+    let rest = &topic[RESPONSE_TOPIC_PREFIX.len()..];
+    let (status, query) = match rest.find("/?") {
+        Some(index) => (&rest[..index], &rest[index + 2..]),
+        None => (rest, ""),
+    };
+    let status = status.parse::<u16>().context(ErrorKind::MqttProtocol)?;
+    let retry_after = form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "retry-after")
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .map(Duration::from_secs);
+
+    Ok(Response {
+        status,
+        retry_after,
+        body,
+    })
+}
+
+/// The MQTT 3.1.1 packets used to register with DPS.
+#[derive(Clone, Debug, PartialEq)]
+enum Packet {
+    Connect {
+        client_id: String,
+        username: String,
+        password: Option<String>,
+        keep_alive: u16,
+    },
+    ConnAck {
+        return_code: u8,
+    },
+    Subscribe {
+        packet_id: u16,
+        topic_filter: String,
+        qos: u8,
+    },
+    SubAck {
+        packet_id: u16,
+        return_code: u8,
+    },
+    /// Publishes with a packet ID are sent at least once, others at most once.
+    Publish {
+        packet_id: Option<u16>,
+        topic: String,
+        payload: Bytes,
+    },
+    PubAck {
+        packet_id: u16,
+    },
+    PingResp,
+    Disconnect,
+}
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGRESP: u8 = 0xD0;
+const DISCONNECT: u8 = 0xE0;
+
+const PROTOCOL_NAME: &str = "MQTT";
+const PROTOCOL_LEVEL: u8 = 4;
+
+const CONNECT_FLAG_USERNAME: u8 = 0x80;
+const CONNECT_FLAG_PASSWORD: u8 = 0x40;
+const CONNECT_FLAG_CLEAN_SESSION: u8 = 0x02;
+
+const PUBLISH_QOS_1: u8 = 0x02;
+
+/// The largest remaining length that fits the four bytes MQTT allows for it.
+const MAX_REMAINING_LENGTH: usize = 268_435_455;
+
+struct MqttCodec;
+
+impl Encoder for MqttCodec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> io::Result<()> {
+        let mut body = Vec::new();
+        let packet_type = match packet {
+            Packet::Connect {
+                client_id,
+                username,
+                password,
+                keep_alive,
+            } => {
+                put_string(&mut body, PROTOCOL_NAME)?;
+                body.put_u8(PROTOCOL_LEVEL);
+                let mut flags = CONNECT_FLAG_USERNAME | CONNECT_FLAG_CLEAN_SESSION;
+                if password.is_some() {
+                    flags |= CONNECT_FLAG_PASSWORD;
+                }
+                body.put_u8(flags);
+                body.put_u16_be(keep_alive);
+                put_string(&mut body, &client_id)?;
+                put_string(&mut body, &username)?;
+                if let Some(password) = password {
+                    put_string(&mut body, &password)?;
+                }
+                CONNECT
+            }
+            Packet::ConnAck { return_code } => {
+                body.put_u8(0);
+                body.put_u8(return_code);
+                CONNACK
+            }
+            Packet::Subscribe {
+                packet_id,
+                topic_filter,
+                qos,
+            } => {
+                body.put_u16_be(packet_id);
+                put_string(&mut body, &topic_filter)?;
+                body.put_u8(qos);
+                SUBSCRIBE
+            }
+            Packet::SubAck {
+                packet_id,
+                return_code,
+            } => {
+                body.put_u16_be(packet_id);
+                body.put_u8(return_code);
+                SUBACK
+            }
+            Packet::Publish {
+                packet_id,
+                topic,
+                payload,
+            } => {
+                put_string(&mut body, &topic)?;
+                if let Some(packet_id) = packet_id {
+                    body.put_u16_be(packet_id);
+                }
+                body.extend_from_slice(&payload);
+                if packet_id.is_some() {
+                    PUBLISH | PUBLISH_QOS_1
+                } else {
+                    PUBLISH
+                }
+            }
+            Packet::PubAck { packet_id } => {
+                body.put_u16_be(packet_id);
+                PUBACK
+            }
+            Packet::PingResp => PINGRESP,
+            Packet::Disconnect => DISCONNECT,
+        };
+
+        if body.len() > MAX_REMAINING_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "MQTT packet is too large",
+            ));
+        }
+
+        dst.reserve(body.len() + 5);
+        dst.put_u8(packet_type);
+        let mut remaining_length = body.len();
+        loop {
+            #[allow(clippy::cast_possible_truncation)]
+            let mut byte = (remaining_length % 0x80) as u8;
+            remaining_length /= 0x80;
+            if remaining_length > 0 {
+                byte |= 0x80;
+            }
+            dst.put_u8(byte);
+            if remaining_length == 0 {
+                break;
+            }
+        }
+        dst.put_slice(&body);
+        Ok(())
+    }
+}
+
+impl Decoder for MqttCodec {
+    type Item = Packet;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Packet>> {
+        // The fixed header is the packet type and flags, followed by the
+        // remaining length in one to four bytes.
+        let mut remaining_length = 0;
+        let mut header_length = 1;
+        loop {
+            let byte = match src.get(header_length) {
+                Some(byte) => *byte,
+                None => return Ok(None),
+            };
+            remaining_length |= usize::from(byte & 0x7F) << (7 * (header_length - 1));
+            header_length += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if header_length == 5 {
+                return Err(malformed());
+            }
+        }
+
+        if src.len() < header_length + remaining_length {
+            src.reserve(header_length + remaining_length - src.len());
+            return Ok(None);
+        }
+
+        let packet_type = src.split_to(header_length)[0];
+        let mut body = src.split_to(remaining_length).freeze();
+        let packet = match packet_type & 0xF0 {
+            CONNECT => {
+                if get_string(&mut body)? != PROTOCOL_NAME || get_u8(&mut body)? != PROTOCOL_LEVEL {
+                    return Err(malformed());
+                }
+                let flags = get_u8(&mut body)?;
+                let keep_alive = get_u16(&mut body)?;
+                let client_id = get_string(&mut body)?;
+                let username = if flags & CONNECT_FLAG_USERNAME == 0 {
+                    String::new()
+                } else {
+                    get_string(&mut body)?
+                };
+                let password = if flags & CONNECT_FLAG_PASSWORD == 0 {
+                    None
+                } else {
+                    Some(get_string(&mut body)?)
+                };
+                Packet::Connect {
+                    client_id,
+                    username,
+                    password,
+                    keep_alive,
+                }
+            }
+            CONNACK => {
+                let _flags = get_u8(&mut body)?;
+                Packet::ConnAck {
+                    return_code: get_u8(&mut body)?,
+                }
+            }
+            PUBLISH => {
+                let topic = get_string(&mut body)?;
+                let packet_id = if packet_type & 0x06 == 0 {
+                    None
+                } else {
+                    Some(get_u16(&mut body)?)
+                };
+                Packet::Publish {
+                    packet_id,
+                    topic,
+                    payload: body.split_off(0),
+                }
+            }
+            PUBACK => Packet::PubAck {
+                packet_id: get_u16(&mut body)?,
+            },
+            0x80 => {
+                let packet_id = get_u16(&mut body)?;
+                let topic_filter = get_string(&mut body)?;
+                Packet::Subscribe {
+                    packet_id,
+                    topic_filter,
+                    qos: get_u8(&mut body)?,
+                }
+            }
+            SUBACK => Packet::SubAck {
+                packet_id: get_u16(&mut body)?,
+                return_code: get_u8(&mut body)?,
+            },
+            PINGRESP => Packet::PingResp,
+            DISCONNECT => Packet::Disconnect,
+            _ => return Err(malformed()),
+        };
+        Ok(Some(packet))
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed MQTT packet")
+}
+
+fn put_string(dst: &mut Vec<u8>, s: &str) -> io::Result<()> {
+    if s.len() > usize::from(u16::max_value()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "MQTT string is too long",
+        ));
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    dst.put_u16_be(s.len() as u16);
+    dst.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn take(src: &mut Bytes, len: usize) -> io::Result<Bytes> {
+    if src.len() < len {
+        Err(malformed())
+    } else {
+        Ok(src.split_to(len))
+    }
+}
+
+fn get_u8(src: &mut Bytes) -> io::Result<u8> {
+    Ok(take(src, 1)?[0])
+}
+
+fn get_u16(src: &mut Bytes) -> io::Result<u16> {
+    let bytes = take(src, 2)?;
+    Ok(u16::from(bytes[0]) << 8 | u16::from(bytes[1]))
+}
+
+fn get_string(src: &mut Bytes) -> io::Result<String> {
+    let len = get_u16(src)?;
+    let bytes = take(src, usize::from(len))?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| malformed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    use futures::Async;
+    use serde_json::json;
+
+    use super::*;
+
+    // A connection that plays back the packets DPS would send, and records
+    // the packets the client sends.
+    struct ScriptedStream {
+        input: Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl ScriptedStream {
+        fn new(packets: Vec<Packet>) -> (Self, Arc<Mutex<Vec<u8>>>) {
+            let mut input = BytesMut::new();
+            for packet in packets {
+                MqttCodec.encode(packet, &mut input).unwrap();
+            }
+            let output = Arc::new(Mutex::new(Vec::new()));
+            let stream = ScriptedStream {
+                input: Cursor::new(input.to_vec()),
+                output: output.clone(),
+            };
+            (stream, output)
+        }
+    }
+
+    impl Read for ScriptedStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for ScriptedStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl AsyncRead for ScriptedStream {}
+
+    impl AsyncWrite for ScriptedStream {
+        fn shutdown(&mut self) -> futures::Poll<(), io::Error> {
+            Ok(Async::Ready(()))
+        }
+    }
+
+    fn sent_packets(output: &Arc<Mutex<Vec<u8>>>) -> Vec<Packet> {
+        let mut src = BytesMut::from(output.lock().unwrap().clone());
+        let mut packets = vec![];
+        while let Some(packet) = MqttCodec.decode(&mut src).unwrap() {
+            packets.push(packet);
+        }
+        packets
+    }
+
+    fn response(packet_id: Option<u16>, topic: &str, body: &Value) -> Packet {
+        Packet::Publish {
+            packet_id,
+            topic: topic.to_string(),
+            payload: Bytes::from(serde_json::to_vec(body).unwrap()),
+        }
+    }
+
+    fn connect() -> Packet {
+        Packet::Connect {
+            client_id: "reg".to_string(),
+            username: "scope/registrations/reg/api-version=2018-11-01".to_string(),
+            password: Some("SharedAccessSignature token".to_string()),
+            keep_alive: KEEP_ALIVE_SECS,
+        }
+    }
+
+    #[test]
+    fn codec_round_trips_packets() {
+        let packets = vec![
+            connect(),
+            Packet::Connect {
+                client_id: "reg".to_string(),
+                username: "user".to_string(),
+                password: None,
+                keep_alive: 0,
+            },
+            Packet::ConnAck { return_code: 5 },
+            Packet::Subscribe {
+                packet_id: 1,
+                topic_filter: RESPONSE_TOPIC_FILTER.to_string(),
+                qos: 1,
+            },
+            Packet::SubAck {
+                packet_id: 1,
+                return_code: 1,
+            },
+            Packet::Publish {
+                packet_id: Some(300),
+                topic: "topic".to_string(),
+                payload: Bytes::from(vec![0x5A; 200]),
+            },
+            Packet::Publish {
+                packet_id: None,
+                topic: "topic".to_string(),
+                payload: Bytes::new(),
+            },
+            Packet::PubAck { packet_id: 300 },
+            Packet::PingResp,
+            Packet::Disconnect,
+        ];
+
+        for packet in packets {
+            let mut buf = BytesMut::new();
+            MqttCodec.encode(packet.clone(), &mut buf).unwrap();
+            assert_eq!(Some(packet), MqttCodec.decode(&mut buf).unwrap());
+            assert!(buf.is_empty());
+        }
+    }
+
+    #[test]
+    fn codec_waits_for_whole_packet() {
+        let mut buf = BytesMut::new();
+        MqttCodec
+            .encode(
+                Packet::Publish {
+                    packet_id: Some(2),
+                    topic: "topic".to_string(),
+                    payload: Bytes::from(vec![0x5A; 200]),
+                },
+                &mut buf,
+            )
+            .unwrap();
+        let mut partial = buf.split_to(100);
+
+        assert_eq!(None, MqttCodec.decode(&mut partial).unwrap());
+        partial.unsplit(buf);
+        assert!(MqttCodec.decode(&mut partial).unwrap().is_some());
+    }
+
+    #[test]
+    fn codec_rejects_malformed_remaining_length() {
+        let mut buf = BytesMut::from(vec![PUBLISH, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+        assert!(MqttCodec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn parse_response_reads_status_and_retry_after() {
+        let response = parse_response(
+            "$dps/registrations/res/202/?$rid=2&retry-after=3",
+            Bytes::new(),
+        )
+        .unwrap();
+        assert_eq!(202, response.status);
+        assert_eq!(Some(Duration::from_secs(3)), response.retry_after);
+
+        let response = parse_response("$dps/registrations/res/200/?$rid=3", Bytes::new()).unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!(None, response.retry_after);
+
+        assert!(parse_response("$dps/other/200/?$rid=3", Bytes::new()).is_err());
+    }
+
+    #[test]
+    fn register_polls_until_assigned() {
+        let (stream, output) = ScriptedStream::new(vec![
+            Packet::ConnAck { return_code: 0 },
+            Packet::SubAck {
+                packet_id: PACKET_ID_SUBSCRIBE,
+                return_code: 1,
+            },
+            Packet::PubAck { packet_id: 2 },
+            response(
+                Some(7),
+                "$dps/registrations/res/202/?$rid=2&retry-after=0",
+                &json!({ "operationId": "op", "status": "assigning" }),
+            ),
+            response(
+                None,
+                "$dps/registrations/res/200/?$rid=3",
+                &json!({
+                    "operationId": "op",
+                    "status": "assigned",
+                    "registrationState": {
+                        "registrationId": "reg",
+                        "deviceId": "device",
+                        "assignedHub": "hub",
+                        "status": "assigned",
+                    },
+                }),
+            ),
+        ]);
+        let body = Bytes::from(&b"{\"registrationId\":\"reg\"}"[..]);
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let result = runtime
+            .block_on(register_over(stream, connect(), body.clone()))
+            .unwrap();
+
+        assert_eq!(Some("device"), result.device_id());
+        assert_eq!(Some("hub"), result.assigned_hub());
+        assert_eq!(
+            vec![
+                connect(),
+                Packet::Subscribe {
+                    packet_id: PACKET_ID_SUBSCRIBE,
+                    topic_filter: RESPONSE_TOPIC_FILTER.to_string(),
+                    qos: 1,
+                },
+                Packet::Publish {
+                    packet_id: Some(2),
+                    topic: "$dps/registrations/PUT/iotdps-register/?$rid=2".to_string(),
+                    payload: body,
+                },
+                Packet::PubAck { packet_id: 7 },
+                Packet::Publish {
+                    packet_id: Some(3),
+                    topic:
+                        "$dps/registrations/GET/iotdps-get-operationstatus/?$rid=3&operationId=op"
+                            .to_string(),
+                    payload: Bytes::new(),
+                },
+                Packet::Disconnect,
+            ],
+            sent_packets(&output)
+        );
+    }
+
+    #[test]
+    fn register_retries_throttled_request() {
+        let (stream, output) = ScriptedStream::new(vec![
+            Packet::ConnAck { return_code: 0 },
+            Packet::SubAck {
+                packet_id: PACKET_ID_SUBSCRIBE,
+                return_code: 1,
+            },
+            response(
+                None,
+                "$dps/registrations/res/429/?$rid=2&retry-after=0",
+                &json!({}),
+            ),
+            response(
+                None,
+                "$dps/registrations/res/200/?$rid=3",
+                &json!({
+                    "operationId": "op",
+                    "status": "assigned",
+                    "registrationState": {
+                        "registrationId": "reg",
+                        "deviceId": "device",
+                        "assignedHub": "hub",
+                        "status": "assigned",
+                    },
+                }),
+            ),
+        ]);
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime
+            .block_on(register_over(stream, connect(), Bytes::new()))
+            .unwrap();
+
+        let topics: Vec<_> = sent_packets(&output)
+            .into_iter()
+            .filter_map(|packet| match packet {
+                Packet::Publish { topic, .. } => Some(topic),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                "$dps/registrations/PUT/iotdps-register/?$rid=2".to_string(),
+                "$dps/registrations/PUT/iotdps-register/?$rid=3".to_string(),
+            ],
+            topics
+        );
+    }
+
+    #[test]
+    fn register_fails_when_connection_refused() {
+        let (stream, _) = ScriptedStream::new(vec![Packet::ConnAck { return_code: 5 }]);
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let err = runtime
+            .block_on(register_over(stream, connect(), Bytes::new()))
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::MqttConnectionRefused(5) => (),
+            kind => panic!("Expected `MqttConnectionRefused` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn register_fails_on_unauthorized_response() {
+        let (stream, _) = ScriptedStream::new(vec![
+            Packet::ConnAck { return_code: 0 },
+            Packet::SubAck {
+                packet_id: PACKET_ID_SUBSCRIBE,
+                return_code: 1,
+            },
+            response(
+                None,
+                "$dps/registrations/res/401/?$rid=2",
+                &json!({ "errorCode": 401_002 }),
+            ),
+        ]);
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let err = runtime
+            .block_on(register_over(stream, connect(), Bytes::new()))
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::MqttResponse(401) => (),
+            kind => panic!("Expected `MqttResponse` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn register_fails_when_connection_closes() {
+        let (stream, _) = ScriptedStream::new(vec![Packet::ConnAck { return_code: 0 }]);
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let err = runtime
+            .block_on(register_over(stream, connect(), Bytes::new()))
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::MqttConnection => (),
+            kind => panic!("Expected `MqttConnection` but got {:?}", kind),
+        }
+    }
+}
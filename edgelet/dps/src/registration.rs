@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::cmp;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
@@ -7,19 +8,22 @@ use base64;
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use failure::{Fail, ResultExt};
-use futures::future::Either;
+use futures::future::{Either, Loop};
 use futures::{future, Future};
+use hyper::header::{HeaderMap, RETRY_AFTER};
 use hyper::{Method, StatusCode};
 use log::{debug, info};
 use percent_encoding::{define_encode_set, percent_encode, PATH_SEGMENT_ENCODE_SET};
 use serde_json;
+use serde_json::Value;
 use tokio::prelude::*;
-use tokio::timer::Interval;
+use tokio::timer::Delay;
 use url::form_urlencoded::Serializer as UrlSerializer;
 
 use edgelet_core::crypto::{Activate, KeyIdentity, KeyStore, Sign, Signature, SignatureAlgorithm};
 use edgelet_http::client::{Client, ClientImpl, TokenSource};
-use edgelet_http::ErrorKind as HttpErrorKind;
+use edgelet_http::{Error as HttpError, ErrorKind as HttpErrorKind};
+use edgelet_utils::RetryPolicy;
 
 use crate::error::{Error, ErrorKind};
 use crate::model::{
@@ -27,12 +31,27 @@ use crate::model::{
     TpmRegistrationResult,
 };
 
-/// This is the interval at which to poll DPS for registration assignment status
-const DPS_ASSIGNMENT_RETRY_INTERVAL_SECS: u64 = 10;
+/// This is the interval at which to poll DPS for registration assignment status, when DPS
+/// doesn't ask for a different one with a Retry-After header
+pub(crate) const DPS_ASSIGNMENT_RETRY_INTERVAL_SECS: u64 = 10;
 
 /// This is the number of seconds to wait for DPS to complete assignment to a hub
 const DPS_ASSIGNMENT_TIMEOUT_SECS: u64 = 120;
 
+/// This is the number of seconds to keep retrying the registration request while DPS is
+/// throttling or unavailable, unless the retry policy says otherwise
+pub(crate) const DPS_REGISTRATION_TIMEOUT_SECS: u64 = 300;
+
+/// This is the delay before the first retry of a DPS request that failed with a transient error
+const DPS_BACKOFF_INITIAL_MILLIS: u64 = 1000;
+
+/// This is the longest delay between retries of a DPS request
+const DPS_BACKOFF_MAX_SECS: u64 = 60;
+
+/// The device id, assigned hub, registration substatus and custom allocation
+/// payload of a completed registration.
+pub type RegistrationInfo = (String, String, Option<String>, Option<Value>);
+
 define_encode_set! {
     pub IOTHUB_ENCODE_SET = [PATH_SEGMENT_ENCODE_SET] | { '=' }
 }
@@ -51,7 +70,7 @@ impl<K> DpsTokenSource<K>
 where
     K: Sign + Clone,
 {
-    fn new(scope_id: String, registration_id: String, key: K) -> Self {
+    pub(crate) fn new(scope_id: String, registration_id: String, key: K) -> Self {
         DpsTokenSource {
             scope_id,
             registration_id,
@@ -106,6 +125,8 @@ where
     registration_id: String,
     auth: DpsAuthKind,
     key_store: A,
+    payload: Option<Value>,
+    retry_policy: RetryPolicy,
 }
 
 impl<C, K, A> DpsClient<C, K, A>
@@ -127,9 +148,27 @@ where
             registration_id,
             auth,
             key_store,
+            payload: None,
+            retry_policy: default_retry_policy(),
         })
     }
 
+    /// Sends a custom allocation payload with the registration request.
+    pub fn with_payload(mut self, payload: Option<Value>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Retries the requests that fail because DPS is throttling or unavailable
+    /// by `retry_policy`, if any, instead of doubling the delay from a second
+    /// up to a minute for up to five minutes.
+    pub fn with_retry_policy(mut self, retry_policy: Option<RetryPolicy>) -> Self {
+        if let Some(retry_policy) = retry_policy {
+            self.retry_policy = retry_policy;
+        }
+        self
+    }
+
     fn get_tpm_challenge_key(body: &str, key_store: &mut A) -> Result<K, Error> {
         let tpm_challenge: TpmRegistrationResult =
             serde_json::from_str(body).context(ErrorKind::GetTpmChallengeKey)?;
@@ -193,7 +232,10 @@ where
         registration_id: &str,
         operation_id: &str,
         token_source: Option<DpsTokenSource<K>>,
-    ) -> Box<dyn Future<Item = Option<DeviceRegistrationResult>, Error = Error> + Send> {
+    ) -> Box<
+        dyn Future<Item = (Option<DeviceRegistrationResult>, Option<Duration>), Error = Error>
+            + Send,
+    > {
         let c = if let Some(ts) = token_source {
             client
                 .read()
@@ -203,7 +245,7 @@ where
         } else {
             client.read().expect("RwLock read failure").clone()
         };
-        let request = c.request::<(), RegistrationOperationStatus>(
+        let request = c.request_with_headers::<(), RegistrationOperationStatus>(
                 Method::GET,
                 &format!(
                     "{}/registrations/{}/operations/{}",
@@ -214,8 +256,8 @@ where
                 false,
             ).map_err(|err| Error::from(err.context(ErrorKind::GetOperationStatus)))
             .map(
-                |operation_status: Option<RegistrationOperationStatus>| ->
-                Option<DeviceRegistrationResult> {
+                |(operation_status, headers): (Option<RegistrationOperationStatus>, HeaderMap)| ->
+                (Option<DeviceRegistrationResult>, Option<Duration>) {
                     let status: Option<DeviceRegistrationResult> = operation_status.map_or_else(
                         || None,
                         |op| {
@@ -224,7 +266,7 @@ where
                             })
                         },
                     );
-                    status
+                    (status, retry_after(&headers))
                 },
             );
         Box::new(request)
@@ -251,49 +293,86 @@ where
     }
 
     // The purpose of this function is to poll DPS till it sends either an error or the device
-    // credentials back. This function calls get_operation_status on a timer which in turns calls
-    // in to DPS. The way polling is implemented is by generating a stream of timer events and
-    // calling get_operation_status on each timer event. Stream processing is aborted if either the
-    // timer generates an error or if get_operation_status returns an error. All results from
-    // get_operation_status are discarded, but for the one that returns the desired result. The
-    // skip_while and take(1) implement discarding all but the desired result. Finally fold is
-    // called on the desired result to format and return it from the function.
+    // credentials back. This function calls get_operation_status in a loop, waiting between calls
+    // for as long as DPS asks to with the Retry-After header of its response, or for the default
+    // retry interval if it doesn't. Calls that fail with a transient error are retried with
+    // backoff. Polling ends with no result if DPS hasn't completed the registration by the
+    // timeout.
     fn get_device_registration_result(
         client: Arc<RwLock<Client<C, DpsTokenSource<K>>>>,
         scope_id: String,
         registration_id: String,
         operation_id: String,
         token_source: Option<DpsTokenSource<K>>,
-        retry_count: u64,
+        timeout: Duration,
+        retry_policy: RetryPolicy,
     ) -> Box<dyn Future<Item = Option<DeviceRegistrationResult>, Error = Error> + Send> {
         debug!(
-            "DPS registration result will retry {} times every {} seconds",
-            retry_count, DPS_ASSIGNMENT_RETRY_INTERVAL_SECS
+            "DPS registration result will be polled for up to {} seconds",
+            timeout.as_secs()
         );
-        let chain = Interval::new(
-            Instant::now(),
-            Duration::from_secs(DPS_ASSIGNMENT_RETRY_INTERVAL_SECS),
-        )
-        .take(retry_count)
-        .map_err(|err| Error::from(err.context(ErrorKind::GetDeviceRegistrationResult)))
-        .and_then(move |_instant: Instant| {
-            debug!("Ask DPS for registration status");
-            Self::get_operation_status(
-                &client.clone(),
-                &scope_id,
-                &registration_id,
-                &operation_id,
-                token_source.clone(),
-            )
-        })
-        .skip_while(Self::is_skippable_result)
-        .take(1)
-        .fold(
-            None,
-            |_final_result: Option<DeviceRegistrationResult>,
-             result_from_service: Option<DeviceRegistrationResult>| {
-                debug!("{:?}", result_from_service);
-                future::ok::<Option<DeviceRegistrationResult>, Error>(result_from_service)
+        let deadline = Instant::now() + timeout;
+        let chain = future::loop_fn(
+            (None, 0),
+            move |(last_status, failures): (Option<String>, u32)| {
+                debug!("Ask DPS for registration status");
+                let retry_policy = retry_policy.clone();
+                Self::get_operation_status(
+                    &client,
+                    &scope_id,
+                    &registration_id,
+                    &operation_id,
+                    token_source.clone(),
+                )
+                .then(move |result| {
+                    let (delay, state) = match result {
+                        Ok((result, retry_after)) => {
+                            let status = result
+                                .as_ref()
+                                .and_then(DeviceRegistrationResult::status)
+                                .map(ToString::to_string);
+                            if status != last_status {
+                                if let Some(status) = &status {
+                                    info!("DPS registration status is \"{}\"", status);
+                                }
+                            }
+
+                            match Self::is_skippable_result(&result) {
+                                Ok(false) => return Either::A(future::ok(Loop::Break(result))),
+                                Ok(true) => (),
+                                Err(err) => return Either::A(future::err(err)),
+                            }
+
+                            let delay = retry_after.unwrap_or_else(|| {
+                                Duration::from_secs(DPS_ASSIGNMENT_RETRY_INTERVAL_SECS)
+                            });
+                            (delay, (status, 0))
+                        }
+                        Err(ref err) if is_transient(err) => {
+                            let delay = retry_policy.delay(failures);
+                            info!(
+                                "Could not get DPS registration status, retrying in {} ms: {}",
+                                delay.as_secs() * 1000 + u64::from(delay.subsec_millis()),
+                                err
+                            );
+                            (delay, (last_status, failures + 1))
+                        }
+                        Err(err) => return Either::A(future::err(err)),
+                    };
+
+                    if Instant::now() + delay >= deadline {
+                        debug!("DPS did not complete the registration in time");
+                        return Either::A(future::ok(Loop::Break(None)));
+                    }
+
+                    Either::B(
+                        Delay::new(Instant::now() + delay)
+                            .map_err(|err| {
+                                Error::from(err.context(ErrorKind::GetDeviceRegistrationResult))
+                            })
+                            .map(move |()| Loop::Continue(state)),
+                    )
+                })
             },
         );
         Box::new(chain)
@@ -303,11 +382,12 @@ where
         client: &Arc<RwLock<Client<C, DpsTokenSource<K>>>>,
         scope_id: &str,
         registration_id: String,
+        payload: Option<Value>,
         _key_store: &A,
     ) -> Box<dyn Future<Item = Option<RegistrationOperationStatus>, Error = Error> + Send> {
         let cli = client.clone();
         let uri_path = format!("{}/registrations/{}/register", scope_id, registration_id);
-        let registration = DeviceRegistration::new().with_registration_id(registration_id);
+        let registration = device_registration(registration_id, payload);
         let cli = cli.read().expect("RwLock read failure").clone();
         let f = cli
             .request::<DeviceRegistration, RegistrationOperationStatus>(
@@ -332,10 +412,11 @@ where
         client: &Arc<RwLock<Client<C, DpsTokenSource<K>>>>,
         scope_id: String,
         registration_id: String,
+        payload: Option<Value>,
         key_store: &A,
     ) -> Box<dyn Future<Item = Option<RegistrationOperationStatus>, Error = Error> + Send> {
         let cli = client.clone();
-        let registration = DeviceRegistration::new().with_registration_id(registration_id.clone());
+        let registration = device_registration(registration_id.clone(), payload);
         let f = Self::get_symmetric_challenge_key(key_store)
             .map_err(|err| Error::from(err.context(ErrorKind::GetOperationStatusForSymmetricKey)))
             .into_future()
@@ -373,13 +454,13 @@ where
         registration_id: String,
         tpm_ek: &Bytes,
         tpm_srk: &Bytes,
+        payload: Option<Value>,
         key_store: &A,
     ) -> Box<dyn Future<Item = Option<RegistrationOperationStatus>, Error = Error> + Send> {
         let tpm_attestation = TpmAttestation::new(base64::encode(&tpm_ek))
             .with_storage_root_key(base64::encode(&tpm_srk));
-        let registration = DeviceRegistration::new()
-            .with_registration_id(registration_id.clone())
-            .with_tpm(tpm_attestation);
+        let registration =
+            device_registration(registration_id.clone(), payload).with_tpm(tpm_attestation);
         let client_inner = client.clone();
         let mut key_store_inner = key_store.clone();
         let r = client
@@ -452,9 +533,7 @@ where
         Box::new(r)
     }
 
-    pub fn register(
-        &self,
-    ) -> Box<dyn Future<Item = (String, String, Option<String>), Error = Error> + Send> {
+    pub fn register(&self) -> Box<dyn Future<Item = RegistrationInfo, Error = Error> + Send> {
         let key_store = self.key_store.clone();
         let mut key_store_status = self.key_store.clone();
         let client_with_token_status = self.client.clone();
@@ -467,34 +546,57 @@ where
             scope_id, registration_id,
         );
 
+        // requests that fail because DPS is throttling or unavailable are retried by the retry
+        // policy, with its maximum elapsed time counted across the whole registration
+        let started = Instant::now();
+        let retry_policy = self.retry_policy.clone();
+        let retry_policy_status = self.retry_policy.clone();
+        let client = self.client.clone();
+        let key_store_register = self.key_store.clone();
+        let scope_id_register = scope_id.clone();
+        let registration_id_register = registration_id.clone();
+        let payload = self.payload.clone();
+
         let mut use_tpm_auth = false;
         let mut use_x509_auth = false;
         let r = match &self.auth {
             DpsAuthKind::Tpm { ek, srk } => {
                 use_tpm_auth = true;
-                Self::register_with_tpm_auth(
-                    &self.client,
-                    scope_id.clone(),
-                    registration_id.clone(),
-                    &ek,
-                    &srk,
-                    &self.key_store,
-                )
+                let (ek, srk) = (ek.clone(), srk.clone());
+                retry_transient_failures(retry_policy, started, move || {
+                    Self::register_with_tpm_auth(
+                        &client,
+                        scope_id_register.clone(),
+                        registration_id_register.clone(),
+                        &ek,
+                        &srk,
+                        payload.clone(),
+                        &key_store_register,
+                    )
+                })
+            }
+            DpsAuthKind::SymmetricKey => {
+                retry_transient_failures(retry_policy, started, move || {
+                    Self::register_with_symmetric_key_auth(
+                        &client,
+                        scope_id_register.clone(),
+                        registration_id_register.clone(),
+                        payload.clone(),
+                        &key_store_register,
+                    )
+                })
             }
-            DpsAuthKind::SymmetricKey => Self::register_with_symmetric_key_auth(
-                &self.client,
-                scope_id.clone(),
-                registration_id.clone(),
-                &self.key_store,
-            ),
             DpsAuthKind::X509 => {
                 use_x509_auth = true;
-                Self::register_with_x509_auth(
-                    &self.client,
-                    &scope_id,
-                    registration_id.clone(),
-                    &self.key_store,
-                )
+                retry_transient_failures(retry_policy, started, move || {
+                    Self::register_with_x509_auth(
+                        &client,
+                        &scope_id_register,
+                        registration_id_register.clone(),
+                        payload.clone(),
+                        &key_store_register,
+                    )
+                })
             }
         }
         .and_then(
@@ -506,8 +608,6 @@ where
                         )))
                     },
                     move |s| {
-                        let retry_count =
-                            (DPS_ASSIGNMENT_TIMEOUT_SECS / DPS_ASSIGNMENT_RETRY_INTERVAL_SECS) + 1;
                         let token_key: Result<Option<K>, ()> = if use_x509_auth {
                             Ok(None)
                         } else {
@@ -533,7 +633,8 @@ where
                                     registration_id_status,
                                     s.operation_id().clone(),
                                     ts,
-                                    retry_count,
+                                    Duration::from_secs(DPS_ASSIGNMENT_TIMEOUT_SECS),
+                                    retry_policy_status,
                                 ))
                             }
                             Err(_err) => Either::B(future::err(Error::from(
@@ -548,6 +649,9 @@ where
             let s = operation_status.ok_or_else(|| {
                 Error::from(ErrorKind::RegisterWithAuthUnexpectedlyFailedOperationNotAssigned)
             })?;
+            if let Some(failure) = registration_failure(&s) {
+                return Err(Error::from(ErrorKind::RegistrationFailed(failure)));
+            }
             if use_tpm_auth {
                 let tpm_result = s.tpm();
                 let r = tpm_result.ok_or_else(|| {
@@ -568,9 +672,117 @@ where
     }
 }
 
-fn get_device_info(
+// Retries an operation while it fails with a transient error, waiting between the attempts as
+// the retry policy says, for as long as it allows counting from `started`.
+fn retry_transient_failures<T, F>(
+    retry_policy: RetryPolicy,
+    started: Instant,
+    operation: F,
+) -> Box<dyn Future<Item = T, Error = Error> + Send>
+where
+    T: 'static + Send,
+    F: 'static + FnMut() -> Box<dyn Future<Item = T, Error = Error> + Send> + Send,
+{
+    let retries = future::loop_fn((operation, 0), move |(mut operation, attempt)| {
+        let retry_policy = retry_policy.clone();
+        operation().then(move |result| match result {
+            Ok(value) => Either::A(future::ok(Loop::Break(value))),
+            Err(err) => {
+                let delay = retry_policy.delay(attempt);
+                if is_transient(&err) && retry_policy.allows(started, delay) {
+                    info!(
+                        "DPS request failed, retrying in {} ms: {}",
+                        delay.as_secs() * 1000 + u64::from(delay.subsec_millis()),
+                        err
+                    );
+                    Either::B(
+                        Delay::new(Instant::now() + delay)
+                            .map_err(|err| Error::from(err.context(ErrorKind::RetryDelay)))
+                            .map(move |()| Loop::Continue((operation, attempt + 1))),
+                    )
+                } else {
+                    Either::A(future::err(err))
+                }
+            }
+        })
+    });
+    Box::new(retries)
+}
+
+// Requests that DPS throttled, that failed with a server error or that couldn't reach DPS at all
+// are worth retrying.
+fn is_transient(err: &Error) -> bool {
+    let mut cause = err.cause();
+    while let Some(fail) = cause {
+        if let Some(err) = fail.downcast_ref::<HttpError>() {
+            return match err.kind() {
+                HttpErrorKind::HttpWithErrorResponse(status, _) => {
+                    *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                }
+                HttpErrorKind::Http => true,
+                _ => false,
+            };
+        }
+        cause = fail.cause();
+    }
+    false
+}
+
+// The delay doubles with every attempt up to DPS_BACKOFF_MAX_SECS, and a random part of up to
+// half of it is taken off so that devices that failed together don't all retry together.
+pub(crate) fn default_retry_policy() -> RetryPolicy {
+    RetryPolicy::exponential(
+        Duration::from_millis(DPS_BACKOFF_INITIAL_MILLIS),
+        Duration::from_secs(DPS_BACKOFF_MAX_SECS),
+    )
+    .with_jitter(true)
+    .with_max_elapsed(Some(Duration::from_secs(DPS_REGISTRATION_TIMEOUT_SECS)))
+}
+
+// DPS sends the number of seconds to wait before polling an operation again.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+// Describes the status of a registration that DPS failed, with the error it gave.
+pub(crate) fn registration_failure(
     registration_result: &DeviceRegistrationResult,
-) -> Result<(String, String, Option<String>), Error> {
+) -> Option<String> {
+    let status = registration_result.status()?;
+    if !status.eq_ignore_ascii_case("failed") && !status.eq_ignore_ascii_case("disabled") {
+        return None;
+    }
+
+    let failure = match (
+        registration_result.error_code(),
+        registration_result.error_message(),
+    ) {
+        (Some(code), Some(message)) => format!("{} (error code {}: {})", status, code, message),
+        (Some(code), None) => format!("{} (error code {})", status, code),
+        (None, Some(message)) => format!("{} ({})", status, message),
+        (None, None) => status.to_string(),
+    };
+    Some(failure)
+}
+
+pub(crate) fn device_registration(
+    registration_id: String,
+    payload: Option<Value>,
+) -> DeviceRegistration {
+    let registration = DeviceRegistration::new().with_registration_id(registration_id);
+    match payload {
+        Some(payload) => registration.with_payload(payload),
+        None => registration,
+    }
+}
+
+pub(crate) fn get_device_info(
+    registration_result: &DeviceRegistrationResult,
+) -> Result<RegistrationInfo, Error> {
     Ok((
         registration_result
             .device_id()
@@ -585,6 +797,7 @@ fn get_device_info(
             })?
             .to_string(),
         registration_result.substatus().map(ToString::to_string),
+        registration_result.payload().cloned(),
     ))
 }
 
@@ -654,6 +867,7 @@ mod tests {
             "reg".to_string(),
             &Bytes::from("ek".to_string().into_bytes()),
             &Bytes::from("srk".to_string().into_bytes()),
+            None,
             &MemoryKeyStore::new(),
         )
         .map(|result| match result {
@@ -718,6 +932,7 @@ mod tests {
             &client,
             "scope".to_string(),
             "reg".to_string(),
+            None,
             &key_store,
         )
         .map(|result| match result {
@@ -744,10 +959,16 @@ mod tests {
                     headers,
                     ..
                 },
-                _body,
+                body,
             ) = req.into_parts();
             assert_eq!(uri, expected_uri);
             assert_eq!(method, Method::PUT);
+            let registration: DeviceRegistration =
+                serde_json::from_slice(&body.concat2().wait().unwrap()).unwrap();
+            assert_eq!(
+                Some(&serde_json::json!({ "modelId": "edge-model" })),
+                registration.payload()
+            );
             // If authorization header does not have the shared access signature, request one
             let auth = headers.get(hyper::header::AUTHORIZATION);
             match auth {
@@ -778,6 +999,7 @@ mod tests {
             &client,
             "scope",
             "reg".to_string(),
+            Some(serde_json::json!({ "modelId": "edge-model" })),
             &empty_key_store,
         )
         .map(|result| match result {
@@ -1146,7 +1368,8 @@ mod tests {
             "reg".to_string(),
             "operation".to_string(),
             Some(token_source),
-            3,
+            Duration::from_secs(25),
+            default_retry_policy(),
         );
         let task = dps_operation.map(|result| match result {
             Some(r) => assert_eq!(*r.registration_id().unwrap(), "reg".to_string()),
@@ -1189,7 +1412,8 @@ mod tests {
             "reg".to_string(),
             "operation".to_string(),
             Some(token_source),
-            3,
+            Duration::from_secs(25),
+            default_retry_policy(),
         );
         let task = dps_operation.map(|result| match result {
             Some(_) => panic!("Shouldn't have passed because every attempt failed"),
@@ -1202,6 +1426,200 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn get_device_registration_result_honors_retry_after() {
+        let reg_op_status_assigning = Response::builder()
+            .header(hyper::header::RETRY_AFTER, "1")
+            .body(
+                serde_json::to_string(
+                    &RegistrationOperationStatus::new("operation".to_string())
+                        .with_registration_state(
+                            DeviceRegistrationResult::new()
+                                .with_registration_id("reg".to_string())
+                                .with_status("assigning".to_string()),
+                        ),
+                )
+                .unwrap()
+                .into(),
+            )
+            .unwrap();
+
+        let reg_op_status_final = Response::new(
+            serde_json::to_string(
+                &RegistrationOperationStatus::new("operation".to_string()).with_registration_state(
+                    DeviceRegistrationResult::new()
+                        .with_registration_id("reg".to_string())
+                        .with_status("assigned".to_string()),
+                ),
+            )
+            .unwrap()
+            .into(),
+        );
+
+        let stream = Mutex::new(stream::iter_result(vec![
+            Ok::<_, Error>(reg_op_status_assigning),
+            Ok(reg_op_status_final),
+        ]));
+        let handler = move |_req: Request<Body>| {
+            if let Async::Ready(opt) = stream.lock().unwrap().poll().unwrap() {
+                future::ok(opt.unwrap())
+            } else {
+                unimplemented!();
+            }
+        };
+        let client = Arc::new(RwLock::new(
+            Client::new(
+                handler,
+                None,
+                DPS_API_VERSION.to_string(),
+                Url::parse("https://global.azure-devices-provisioning.net/").unwrap(),
+            )
+            .unwrap(),
+        ));
+
+        // The default polling interval is longer than the timeout, so the registration only
+        // completes if DPS's Retry-After is used instead.
+        let dps_operation =
+            DpsClient::<_, MemoryKey, MemoryKeyStore>::get_device_registration_result(
+                client,
+                "scope_id".to_string(),
+                "reg".to_string(),
+                "operation".to_string(),
+                None,
+                Duration::from_secs(5),
+                default_retry_policy(),
+            );
+        let task = dps_operation.map(|result| match result {
+            Some(r) => assert_eq!(r.status().unwrap(), "assigned"),
+            None => panic!("Expected registration result"),
+        });
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn register_retries_when_throttled() {
+        let attempts = Arc::new(Mutex::new(0));
+        let attempts_handler = attempts.clone();
+        let handler = move |_req: Request<Body>| {
+            let mut attempts = attempts_handler.lock().unwrap();
+            *attempts += 1;
+            if *attempts == 1 {
+                let response = Response::builder()
+                    .status(StatusCode::TOO_MANY_REQUESTS)
+                    .body(Body::empty())
+                    .expect("could not build hyper::Response");
+                future::ok(response)
+            } else {
+                let result = RegistrationOperationStatus::new("something".to_string())
+                    .with_status("assigning".to_string());
+                future::ok(Response::new(
+                    serde_json::to_string(&result).unwrap().into(),
+                ))
+            }
+        };
+        let client = Arc::new(RwLock::new(
+            Client::new(
+                handler,
+                None,
+                DPS_API_VERSION.to_string(),
+                Url::parse("https://global.azure-devices-provisioning.net/").unwrap(),
+            )
+            .unwrap(),
+        ));
+
+        let key_store = MemoryKeyStore::new();
+        let retry_policy = default_retry_policy().with_max_elapsed(Some(Duration::from_secs(30)));
+        let task = retry_transient_failures(retry_policy, Instant::now(), move || {
+            DpsClient::register_with_x509_auth(
+                &client,
+                "scope",
+                "reg".to_string(),
+                None,
+                &key_store,
+            )
+        })
+        .map(|result| {
+            assert_eq!(result.unwrap().operation_id(), "something");
+        });
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+        assert_eq!(2, *attempts.lock().unwrap());
+    }
+
+    #[test]
+    fn register_does_not_retry_client_errors() {
+        let attempts = Arc::new(Mutex::new(0));
+        let attempts_handler = attempts.clone();
+        let handler = move |_req: Request<Body>| {
+            *attempts_handler.lock().unwrap() += 1;
+            let response = Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(Body::empty())
+                .expect("could not build hyper::Response");
+            future::ok(response)
+        };
+        let client = Arc::new(RwLock::new(
+            Client::new(
+                handler,
+                None,
+                DPS_API_VERSION.to_string(),
+                Url::parse("https://global.azure-devices-provisioning.net/").unwrap(),
+            )
+            .unwrap(),
+        ));
+
+        let key_store = MemoryKeyStore::new();
+        let retry_policy = default_retry_policy().with_max_elapsed(Some(Duration::from_secs(30)));
+        let task = retry_transient_failures(retry_policy, Instant::now(), move || {
+            DpsClient::register_with_x509_auth(
+                &client,
+                "scope",
+                "reg".to_string(),
+                None,
+                &key_store,
+            )
+        });
+        let result = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task);
+        assert!(result.is_err());
+        assert_eq!(1, *attempts.lock().unwrap());
+    }
+
+    #[test]
+    fn default_retry_policy_grows_up_to_max() {
+        let retry_policy = default_retry_policy();
+        for attempt in 0..64 {
+            let delay = retry_policy.delay(attempt);
+            let max = cmp::min(
+                Duration::from_millis(DPS_BACKOFF_INITIAL_MILLIS * (1 << cmp::min(attempt, 16))),
+                Duration::from_secs(DPS_BACKOFF_MAX_SECS),
+            );
+            assert!(delay <= max);
+            assert!(delay >= max / 2);
+        }
+    }
+
+    #[test]
+    fn registration_failure_includes_error() {
+        let result = DeviceRegistrationResult::new()
+            .with_status("failed".to_string())
+            .with_error_code(400_209)
+            .with_error_message("Enrollment not found".to_string());
+        assert_eq!(
+            Some("failed (error code 400209: Enrollment not found)".to_string()),
+            registration_failure(&result)
+        );
+
+        let result = DeviceRegistrationResult::new().with_status("assigned".to_string());
+        assert_eq!(None, registration_failure(&result));
+    }
+
     #[test]
     fn get_operation_status_success() {
         let expected_uri = "https://global.azure-devices-provisioning.net/scope_id/registrations/reg/operations/operation?api-version=2018-11-01";
@@ -1238,10 +1656,10 @@ mod tests {
             Some(token_source),
         );
         let task = dps_operation.map(|result| match result {
-            Some(op) => {
+            (Some(op), _) => {
                 assert_eq!(*op.registration_id().unwrap(), "reg".to_string());
             }
-            None => panic!("Unexpected"),
+            (None, _) => panic!("Unexpected"),
         });
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
@@ -1306,8 +1724,23 @@ mod tests {
             (
                 "device".to_string(),
                 "hub".to_string(),
-                Some("initialAssignment".to_string())
+                Some("initialAssignment".to_string()),
+                None
             )
         )
     }
+
+    #[test]
+    fn get_device_info_returns_custom_payload() {
+        let (_, _, _, payload) = get_device_info(
+            &DeviceRegistrationResult::new()
+                .with_registration_id("reg".to_string())
+                .with_status("assigned".to_string())
+                .with_device_id("device".to_string())
+                .with_assigned_hub("hub".to_string())
+                .with_payload(serde_json::json!({ "tier": "gold" })),
+        )
+        .unwrap();
+        assert_eq!(Some(serde_json::json!({ "tier": "gold" })), payload);
+    }
 }
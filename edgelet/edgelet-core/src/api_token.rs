@@ -0,0 +1,179 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use consistenttime::ct_u8_slice_eq;
+use failure::ResultExt;
+use rand::Rng;
+
+use crate::error::{Error, ErrorKind};
+use crate::settings::ManagementAuthSettings;
+
+const API_TOKEN_LEN: usize = 32;
+
+/// A token that grants access to the management API until it expires.
+#[derive(Clone, Debug)]
+pub struct ApiToken {
+    token: String,
+    expiration: DateTime<Utc>,
+}
+
+impl ApiToken {
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn expiration(&self) -> DateTime<Utc> {
+        self.expiration
+    }
+}
+
+/// Who a valid token was given to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiTokenOwner {
+    /// The token is one of the config file.
+    Config,
+    /// The token was minted for the module.
+    Module(String),
+}
+
+#[derive(Clone, Debug)]
+struct MintedToken {
+    module_id: String,
+    expiration: DateTime<Utc>,
+}
+
+/// The tokens that callers of the management API have to present when it
+/// listens on TCP, where the caller's process can't be told from the
+/// connection. These are the static tokens of the config file, and the ones
+/// minted through the workload API for the modules that are allowed to.
+#[derive(Clone, Debug)]
+pub struct ApiTokens {
+    tokens: Arc<Vec<String>>,
+    token_modules: Arc<Vec<String>>,
+    token_lifetime: Duration,
+    minted: Arc<Mutex<HashMap<String, MintedToken>>>,
+}
+
+impl ApiTokens {
+    pub fn new(settings: &ManagementAuthSettings) -> Self {
+        ApiTokens {
+            tokens: Arc::new(
+                settings
+                    .tokens()
+                    .iter()
+                    .filter(|token| !token.is_empty())
+                    .cloned()
+                    .collect(),
+            ),
+            token_modules: Arc::new(settings.token_modules().to_vec()),
+            token_lifetime: settings.token_lifetime(),
+            minted: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Mints a new token for `module_id`, if it is one of the modules that
+    /// are allowed to get tokens.
+    pub fn mint(&self, module_id: &str) -> Result<ApiToken, Error> {
+        if !self.token_modules.iter().any(|m| m == module_id) {
+            return Err(Error::from(ErrorKind::ApiTokenNotAllowed(
+                module_id.to_string(),
+            )));
+        }
+
+        let mut bytes = [0_u8; API_TOKEN_LEN];
+        rand::thread_rng()
+            .try_fill(&mut bytes[..])
+            .context(ErrorKind::MakeRandom)?;
+        let token = base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD);
+        let expiration = Utc::now()
+            + chrono::Duration::from_std(self.token_lifetime)
+                .unwrap_or_else(|_| chrono::Duration::max_value());
+
+        let mut minted = self.lock();
+        let now = Utc::now();
+        minted.retain(|_, minted| minted.expiration > now);
+        minted.insert(
+            token.clone(),
+            MintedToken {
+                module_id: module_id.to_string(),
+                expiration,
+            },
+        );
+
+        Ok(ApiToken { token, expiration })
+    }
+
+    /// Who `token` was given to, if it is valid.
+    pub fn verify(&self, token: &str) -> Option<ApiTokenOwner> {
+        // The tokens are compared in constant time, so that the time it takes
+        // doesn't tell how much of a guessed token was right.
+        if self
+            .tokens
+            .iter()
+            .any(|t| ct_u8_slice_eq(t.as_bytes(), token.as_bytes()))
+        {
+            return Some(ApiTokenOwner::Config);
+        }
+
+        self.lock()
+            .get(token)
+            .filter(|minted| minted.expiration > Utc::now())
+            .map(|minted| ApiTokenOwner::Module(minted.module_id.clone()))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, MintedToken>> {
+        self.minted
+            .lock()
+            .expect("Failed to acquire the API tokens lock")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(json: &str) -> ManagementAuthSettings {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn static_tokens_are_valid() {
+        let tokens = ApiTokens::new(&settings(r#"{ "tokens": ["secret1", "secret2"] }"#));
+
+        assert_eq!(Some(ApiTokenOwner::Config), tokens.verify("secret2"));
+        assert_eq!(None, tokens.verify("secret"));
+        assert_eq!(None, tokens.verify(""));
+    }
+
+    #[test]
+    fn minted_tokens_are_valid_until_they_expire() {
+        let tokens = ApiTokens::new(&settings(r#"{ "token_modules": ["edgeAgent"] }"#));
+
+        let token = tokens.mint("edgeAgent").unwrap();
+        assert_eq!(
+            Some(ApiTokenOwner::Module("edgeAgent".to_string())),
+            tokens.verify(token.token())
+        );
+        assert!(token.expiration() > Utc::now());
+
+        let expired = ApiTokens::new(&settings(
+            r#"{ "token_modules": ["edgeAgent"], "token_lifetime_secs": 0 }"#,
+        ));
+        let token = expired.mint("edgeAgent").unwrap();
+        assert_eq!(None, expired.verify(token.token()));
+    }
+
+    #[test]
+    fn only_allowed_modules_can_mint_tokens() {
+        let tokens = ApiTokens::new(&settings(r#"{ "token_modules": ["edgeAgent"] }"#));
+
+        match tokens.mint("tempSensor").unwrap_err().kind() {
+            ErrorKind::ApiTokenNotAllowed(module_id) => assert_eq!("tempSensor", module_id),
+            kind => panic!("expected ApiTokenNotAllowed but got {:?}", kind),
+        }
+    }
+}
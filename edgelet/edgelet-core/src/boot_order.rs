@@ -0,0 +1,696 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use futures::future::{self, Either, Loop};
+use futures::{stream, Future, Stream};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+use tokio::timer::Delay;
+
+use crate::module::{Module, ModuleRuntime, ModuleStatus};
+
+/// Name of the file in the home directory that holds the boot order of the
+/// modules.
+pub const BOOT_ORDER_FILENAME: &str = "module_boot_order.json";
+
+/// How often a module that others wait for is checked while it comes up.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A module counts as healthy once it has been running for this long, so that
+/// a module that crashes right after it starts doesn't let the ones after it
+/// start.
+const HEALTHY_AFTER: Duration = Duration::from_secs(10);
+
+/// How long the modules after a module that doesn't become healthy wait for
+/// it before they start anyway.
+const HEALTHY_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct BootEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<u32>,
+    #[serde(default)]
+    wait_for_healthy: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
+}
+
+/// The priorities and dependencies of the modules that were created through
+/// the management API, so that the daemon can start them in order when the
+/// device boots, before the edge agent is up to apply the deployment, and
+/// stop them in order.
+///
+/// The priorities are kept in a file, since the container runtime has no
+/// place for them.
+#[derive(Clone, Debug, Default)]
+pub struct BootOrder {
+    modules: Arc<Mutex<BTreeMap<String, BootEntry>>>,
+    path: Option<PathBuf>,
+}
+
+impl BootOrder {
+    /// Keeps the priorities in memory only.
+    pub fn new() -> Self {
+        BootOrder::default()
+    }
+
+    /// Keeps the priorities in the file at `path`.
+    pub fn load(path: PathBuf) -> Self {
+        let modules = fs::read(&path)
+            .ok()
+            .and_then(|modules| serde_json::from_slice(&modules).ok())
+            .unwrap_or_default();
+
+        BootOrder {
+            modules: Arc::new(Mutex::new(modules)),
+            path: Some(path),
+        }
+    }
+
+    /// Records the priority and the dependencies of module `name`, which was
+    /// just created or updated. A module without a priority is left to the
+    /// edge agent to start.
+    pub fn record(
+        &self,
+        name: &str,
+        priority: Option<u32>,
+        wait_for_healthy: bool,
+        depends_on: Vec<String>,
+    ) {
+        let depends_on: Vec<String> = depends_on
+            .into_iter()
+            .filter(|dependency| dependency != name)
+            .collect();
+
+        let mut modules = self.lock();
+        if priority.is_none() && depends_on.is_empty() {
+            if modules.remove(name).is_none() {
+                return;
+            }
+        } else {
+            modules.insert(
+                name.to_string(),
+                BootEntry {
+                    priority,
+                    wait_for_healthy,
+                    depends_on,
+                },
+            );
+        }
+        self.save(&modules);
+    }
+
+    /// Forgets the priority of module `name`, once it is removed.
+    pub fn remove(&self, name: &str) {
+        let mut modules = self.lock();
+        if modules.remove(name).is_some() {
+            self.save(&modules);
+        }
+    }
+
+    /// Starts the modules with a priority that aren't running, one priority
+    /// after the other, lowest first. The modules of a priority start
+    /// together, and the ones after them wait for those that are to be waited
+    /// for to become healthy, or for a timeout. A module also waits for the
+    /// modules it depends on that started before it or were already running.
+    /// It is meant to run when the daemon starts, before the edge runtime
+    /// module `skip` is started. Failures are logged, and don't keep the other
+    /// modules from starting.
+    pub fn start_modules<M>(&self, runtime: &M, skip: &str) -> impl Future<Item = (), Error = ()>
+    where
+        M: 'static + ModuleRuntime + Clone + Send,
+    {
+        let boot_order = self.clone();
+        let runtime = runtime.clone();
+        let skip = skip.to_string();
+
+        runtime.list_with_details().collect().then(move |modules| {
+            let modules = match modules {
+                Ok(modules) => modules,
+                Err(err) => {
+                    warn!("Could not list the modules to start them in order: {}", err);
+                    return Either::A(future::ok(()));
+                }
+            };
+            let stopped: Vec<String> = modules
+                .iter()
+                .filter(|(module, state)| {
+                    module.name() != skip && *state.status() != ModuleStatus::Running
+                })
+                .map(|(module, _)| module.name().to_string())
+                .collect();
+            let existing: Vec<String> = modules
+                .iter()
+                .map(|(module, _)| module.name().to_string())
+                .collect();
+
+            let groups = boot_order.groups(&stopped);
+            let gates = boot_order.gates(&groups, &existing);
+            let groups: Vec<Vec<(String, bool, Vec<String>)>> = groups
+                .into_iter()
+                .map(|group| {
+                    group
+                        .into_iter()
+                        .map(|(name, wait_for_healthy)| {
+                            let dependencies = gates.get(&name).cloned().unwrap_or_default();
+                            (name, wait_for_healthy, dependencies)
+                        })
+                        .collect()
+                })
+                .collect();
+
+            Either::B(
+                stream::iter_ok(groups).for_each(move |group| start_group(runtime.clone(), group)),
+            )
+        })
+    }
+
+    /// Stops the running modules in the reverse of the boot order: first the
+    /// modules without a priority, then those with one, highest priority
+    /// first, and every module before the modules it depends on. It is meant
+    /// to run before the device is rebooted or shut down, once the edge
+    /// runtime module `skip` is stopped. Failures are logged, and don't keep
+    /// the other modules from stopping.
+    pub fn stop_modules<M>(&self, runtime: &M, skip: &str) -> impl Future<Item = (), Error = ()>
+    where
+        M: 'static + ModuleRuntime + Clone + Send,
+    {
+        self.stop(runtime, skip, false)
+    }
+
+    /// Stops the running modules that depend on other modules, or that other
+    /// modules depend on, in the same order as `stop_modules`. The other
+    /// modules are left running. It is meant to run when the daemon stops, so
+    /// that the modules a module depends on don't go away while it is still
+    /// sending to them, e.g. when the host shuts down.
+    pub fn stop_dependent_modules<M>(
+        &self,
+        runtime: &M,
+        skip: &str,
+    ) -> impl Future<Item = (), Error = ()>
+    where
+        M: 'static + ModuleRuntime + Clone + Send,
+    {
+        self.stop(runtime, skip, true)
+    }
+
+    fn stop<M>(
+        &self,
+        runtime: &M,
+        skip: &str,
+        dependent_only: bool,
+    ) -> impl Future<Item = (), Error = ()>
+    where
+        M: 'static + ModuleRuntime + Clone + Send,
+    {
+        let boot_order = self.clone();
+        let runtime = runtime.clone();
+        let skip = skip.to_string();
+
+        runtime.list_with_details().collect().then(move |modules| {
+            let modules = match modules {
+                Ok(modules) => modules,
+                Err(err) => {
+                    warn!("Could not list the modules to stop them in order: {}", err);
+                    return Either::A(future::ok(()));
+                }
+            };
+            let dependent = boot_order.dependent();
+            let running: Vec<String> = modules
+                .iter()
+                .filter(|(module, state)| {
+                    module.name() != skip
+                        && *state.status() == ModuleStatus::Running
+                        && (!dependent_only || dependent.contains(module.name()))
+                })
+                .map(|(module, _)| module.name().to_string())
+                .collect();
+
+            Either::B(
+                stream::iter_ok(boot_order.stop_groups(&running))
+                    .for_each(move |group| stop_group(runtime.clone(), group)),
+            )
+        })
+    }
+
+    // The modules of `names` that have a priority, grouped by it, lowest first.
+    fn groups(&self, names: &[String]) -> Vec<Vec<(String, bool)>> {
+        let modules = self.lock();
+        let mut groups: BTreeMap<u32, Vec<(String, bool)>> = BTreeMap::new();
+        for name in names {
+            if let Some(entry) = modules.get(name) {
+                if let Some(priority) = entry.priority {
+                    groups
+                        .entry(priority)
+                        .or_default()
+                        .push((name.clone(), entry.wait_for_healthy));
+                }
+            }
+        }
+        groups.values().cloned().collect()
+    }
+
+    // The modules of `groups` that depend on modules of `existing` that start
+    // in an earlier group or aren't started at all, with those modules. The
+    // modules of the same or a later group aren't waited for, as they are
+    // only started after the wait.
+    fn gates(
+        &self,
+        groups: &[Vec<(String, bool)>],
+        existing: &[String],
+    ) -> HashMap<String, Vec<String>> {
+        let modules = self.lock();
+        let mut gates = HashMap::new();
+        let mut not_started: HashSet<&str> = groups
+            .iter()
+            .flatten()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        for group in groups {
+            for (name, _) in group {
+                let dependencies: Vec<String> = modules
+                    .get(name)
+                    .map(|entry| entry.depends_on.as_slice())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter(|dependency| {
+                        existing.contains(*dependency) && !not_started.contains(dependency.as_str())
+                    })
+                    .cloned()
+                    .collect();
+                if !dependencies.is_empty() {
+                    gates.insert(name.clone(), dependencies);
+                }
+            }
+            for (name, _) in group {
+                not_started.remove(name.as_str());
+            }
+        }
+        gates
+    }
+
+    // The modules that depend on other modules or that other modules depend on.
+    fn dependent(&self) -> HashSet<String> {
+        let modules = self.lock();
+        modules
+            .iter()
+            .filter(|(_, entry)| !entry.depends_on.is_empty())
+            .flat_map(|(name, entry)| {
+                Some(name.clone())
+                    .into_iter()
+                    .chain(entry.depends_on.iter().cloned())
+            })
+            .collect()
+    }
+
+    // The modules of `names` in the order they are stopped in: those without
+    // a priority together, then those with one, grouped by it, highest first.
+    // The modules a module depends on are then moved to a group after it.
+    fn stop_groups(&self, names: &[String]) -> Vec<Vec<String>> {
+        let unordered: Vec<String> = {
+            let modules = self.lock();
+            names
+                .iter()
+                .filter(|name| {
+                    modules
+                        .get(*name)
+                        .and_then(|entry| entry.priority)
+                        .is_none()
+                })
+                .cloned()
+                .collect()
+        };
+        let ordered = self
+            .groups(names)
+            .into_iter()
+            .rev()
+            .map(|group| group.into_iter().map(|(name, _)| name).collect());
+
+        let groups: Vec<Vec<String>> = Some(unordered)
+            .filter(|unordered| !unordered.is_empty())
+            .into_iter()
+            .chain(ordered)
+            .collect();
+        self.order_by_dependencies(groups)
+    }
+
+    // Moves the modules that a module depends on to a group after the
+    // module's, keeping the order of the modules otherwise. Modules that
+    // depend on each other in a cycle end up in some order.
+    fn order_by_dependencies(&self, groups: Vec<Vec<String>>) -> Vec<Vec<String>> {
+        let modules = self.lock();
+        let names: Vec<String> = groups.iter().flatten().cloned().collect();
+        let mut levels: HashMap<String, usize> = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(level, group)| group.iter().map(move |name| (name.clone(), level)))
+            .collect();
+
+        for _ in 0..names.len() {
+            let mut changed = false;
+            for name in &names {
+                let level = levels[name];
+                let depends_on = match modules.get(name) {
+                    Some(entry) => &entry.depends_on,
+                    None => continue,
+                };
+                for dependency in depends_on {
+                    if let Some(dependency_level) = levels.get_mut(dependency) {
+                        if *dependency_level <= level {
+                            *dependency_level = level + 1;
+                            changed = true;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut ordered: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for name in names {
+            let level = levels[&name];
+            ordered.entry(level).or_default().push(name);
+        }
+        ordered.into_iter().map(|(_, group)| group).collect()
+    }
+
+    // The file is replaced as a whole, so that it holds either the old or the
+    // new priorities if the device loses power while it is written.
+    fn save(&self, modules: &BTreeMap<String, BootEntry>) {
+        if let Some(path) = &self.path {
+            if let Err(err) = write_atomically(path, modules) {
+                warn!(
+                    "Could not write the module boot order to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, BootEntry>> {
+        self.modules
+            .lock()
+            .expect("Failed to acquire the boot order lock")
+    }
+}
+
+fn write_atomically(
+    path: &Path,
+    modules: &BTreeMap<String, BootEntry>,
+) -> Result<(), failure::Error> {
+    let contents = serde_json::to_vec(modules)?;
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+// Starts the modules of `group` together, each once the modules it depends on
+// are healthy, and waits for the ones that are to be waited for.
+fn start_group<M>(
+    runtime: M,
+    group: Vec<(String, bool, Vec<String>)>,
+) -> impl Future<Item = (), Error = ()>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    let waits: Vec<String> = group
+        .iter()
+        .filter(|(_, wait_for_healthy, _)| *wait_for_healthy)
+        .map(|(name, _, _)| name.clone())
+        .collect();
+
+    let starts = group.into_iter().map({
+        let runtime = runtime.clone();
+        move |(name, _, dependencies)| {
+            let runtime = runtime.clone();
+            future::join_all(dependencies.into_iter().map({
+                let runtime = runtime.clone();
+                move |dependency| wait_until_healthy(runtime.clone(), dependency)
+            }))
+            .and_then(move |_| {
+                info!("Starting module {} in boot order", name);
+                runtime.start(&name).then(move |result| {
+                    if let Err(err) = result {
+                        warn!("Could not start module {}: {}", name, err);
+                    }
+                    Ok(())
+                })
+            })
+        }
+    });
+
+    future::join_all(starts).and_then(move |_| {
+        future::join_all(
+            waits
+                .into_iter()
+                .map(move |name| wait_until_healthy(runtime.clone(), name)),
+        )
+        .map(|_| ())
+    })
+}
+
+// Stops the modules of `group` together.
+fn stop_group<M>(runtime: M, group: Vec<String>) -> impl Future<Item = (), Error = ()>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    future::join_all(group.into_iter().map(move |name| {
+        info!("Stopping module {} in boot order", name);
+        runtime.stop(&name, None).then(move |result| {
+            if let Err(err) = result {
+                warn!("Could not stop module {}: {}", name, err);
+            }
+            Ok(())
+        })
+    }))
+    .map(|_| ())
+}
+
+fn wait_until_healthy<M>(runtime: M, name: String) -> impl Future<Item = (), Error = ()>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    let deadline = Instant::now() + HEALTHY_TIMEOUT;
+
+    future::loop_fn((runtime, name), move |(runtime, name)| {
+        runtime.get(&name).then(move |result| {
+            let running_for = match &result {
+                Ok((_, state)) if *state.status() == ModuleStatus::Running => {
+                    state.started_at().and_then(|started_at| {
+                        Utc::now().signed_duration_since(*started_at).to_std().ok()
+                    })
+                }
+                _ => None,
+            };
+            let healthy =
+                matches!(running_for, Some(running_for) if running_for >= HEALTHY_AFTER);
+
+            if healthy {
+                info!("Module {} is healthy", name);
+                Either::A(future::ok(Loop::Break(())))
+            } else if Instant::now() >= deadline {
+                warn!(
+                    "Module {} is not healthy after {} seconds, starting the modules after it anyway",
+                    name,
+                    HEALTHY_TIMEOUT.as_secs()
+                );
+                Either::A(future::ok(Loop::Break(())))
+            } else {
+                Either::B(
+                    Delay::new(Instant::now() + HEALTH_CHECK_INTERVAL)
+                        .then(move |_| Ok(Loop::Continue((runtime, name)))),
+                )
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn modules_are_grouped_by_priority() {
+        let boot_order = BootOrder::new();
+        boot_order.record("edgeHub", Some(0), true, vec![]);
+        boot_order.record("sensor", Some(5), false, vec![]);
+        boot_order.record("filter", Some(5), false, vec![]);
+        boot_order.record("other", None, false, vec![]);
+
+        let names: Vec<String> = ["sensor", "filter", "edgeHub", "other", "unknown"]
+            .iter()
+            .map(|name| (*name).to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                vec![("edgeHub".to_string(), true)],
+                vec![("sensor".to_string(), false), ("filter".to_string(), false)],
+            ],
+            boot_order.groups(&names)
+        );
+
+        // The module lost its priority with its update.
+        boot_order.record("edgeHub", None, false, vec![]);
+        boot_order.remove("sensor");
+        assert_eq!(
+            vec![vec![("filter".to_string(), false)]],
+            boot_order.groups(&names)
+        );
+    }
+
+    #[test]
+    fn modules_are_stopped_in_reverse_priority() {
+        let boot_order = BootOrder::new();
+        boot_order.record("edgeHub", Some(0), true, vec![]);
+        boot_order.record("sensor", Some(5), false, vec![]);
+        boot_order.record("filter", Some(5), false, vec![]);
+
+        let names: Vec<String> = ["sensor", "other", "filter", "edgeHub", "another"]
+            .iter()
+            .map(|name| (*name).to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                vec!["other".to_string(), "another".to_string()],
+                vec!["sensor".to_string(), "filter".to_string()],
+                vec!["edgeHub".to_string()],
+            ],
+            boot_order.stop_groups(&names)
+        );
+
+        assert_eq!(
+            vec![vec!["edgeHub".to_string()]],
+            boot_order.stop_groups(&["edgeHub".to_string()])
+        );
+    }
+
+    #[test]
+    fn priorities_are_kept_in_the_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(BOOT_ORDER_FILENAME);
+
+        let boot_order = BootOrder::load(path.clone());
+        boot_order.record("edgeHub", Some(0), true, vec![]);
+
+        let boot_order = BootOrder::load(path);
+        assert_eq!(
+            vec![vec![("edgeHub".to_string(), true)]],
+            boot_order.groups(&["edgeHub".to_string()])
+        );
+    }
+    #[test]
+    fn dependencies_are_stopped_after_their_dependents() {
+        let boot_order = BootOrder::new();
+        boot_order.record("edgeHub", Some(0), true, vec![]);
+        boot_order.record("sensor", Some(5), false, vec![]);
+        boot_order.record("filter", Some(5), false, vec!["sensor".to_string()]);
+        boot_order.record("custom", None, false, vec!["edgeHub".to_string()]);
+
+        let names: Vec<String> = ["sensor", "other", "filter", "edgeHub", "custom"]
+            .iter()
+            .map(|name| (*name).to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                vec!["other".to_string(), "custom".to_string()],
+                vec!["filter".to_string()],
+                vec!["sensor".to_string(), "edgeHub".to_string()],
+            ],
+            boot_order.stop_groups(&names)
+        );
+    }
+
+    #[test]
+    fn dependency_cycles_are_stopped() {
+        let boot_order = BootOrder::new();
+        boot_order.record("a", None, false, vec!["b".to_string()]);
+        boot_order.record("b", None, false, vec!["a".to_string()]);
+
+        let names = vec!["a".to_string(), "b".to_string()];
+        let mut stopped: Vec<String> = boot_order.stop_groups(&names).concat();
+        stopped.sort();
+        assert_eq!(names, stopped);
+    }
+
+    #[test]
+    fn modules_wait_for_dependencies_started_before_them() {
+        let boot_order = BootOrder::new();
+        boot_order.record("edgeHub", Some(0), true, vec![]);
+        boot_order.record(
+            "sensor",
+            Some(5),
+            false,
+            vec![
+                "edgeHub".to_string(),
+                "filter".to_string(),
+                "running".to_string(),
+                "unknown".to_string(),
+            ],
+        );
+        boot_order.record("filter", Some(5), false, vec![]);
+
+        let stopped: Vec<String> = ["sensor", "filter", "edgeHub"]
+            .iter()
+            .map(|name| (*name).to_string())
+            .collect();
+        let existing: Vec<String> = ["sensor", "filter", "edgeHub", "running"]
+            .iter()
+            .map(|name| (*name).to_string())
+            .collect();
+        let groups = boot_order.groups(&stopped);
+
+        let mut expected = HashMap::new();
+        expected.insert(
+            "sensor".to_string(),
+            vec!["edgeHub".to_string(), "running".to_string()],
+        );
+        assert_eq!(expected, boot_order.gates(&groups, &existing));
+    }
+
+    #[test]
+    fn dependencies_are_kept_without_priority() {
+        let boot_order = BootOrder::new();
+        boot_order.record("custom", None, false, vec!["custom".to_string()]);
+        assert!(boot_order.dependent().is_empty());
+
+        boot_order.record("custom", None, false, vec!["edgeHub".to_string()]);
+        let dependent = boot_order.dependent();
+        assert!(dependent.contains("custom"));
+        assert!(dependent.contains("edgeHub"));
+        assert!(boot_order.groups(&["custom".to_string()]).is_empty());
+    }
+
+    #[test]
+    fn old_files_are_loaded() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(BOOT_ORDER_FILENAME);
+        fs::write(
+            &path,
+            r#"{"edgeHub":{"priority":0,"wait_for_healthy":true}}"#,
+        )
+        .unwrap();
+
+        let boot_order = BootOrder::load(path);
+        assert_eq!(
+            vec![vec![("edgeHub".to_string(), true)]],
+            boot_order.groups(&["edgeHub".to_string()])
+        );
+    }
+}
@@ -0,0 +1,112 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+/// What the edge runtime module needs to know to connect to the hub and to the
+/// daemon. The daemon passes it to the module in environment variables, and
+/// serves it on the management API for agents that rather ask for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AgentBootstrap {
+    iothub_hostname: String,
+    device_id: String,
+    module_id: String,
+    gateway_hostname: String,
+    parent_hostname: Option<String>,
+    auth_scheme: String,
+    workload_uri: String,
+    management_uri: String,
+}
+
+impl AgentBootstrap {
+    pub fn new(
+        iothub_hostname: String,
+        device_id: String,
+        module_id: String,
+        gateway_hostname: String,
+        auth_scheme: String,
+        workload_uri: String,
+        management_uri: String,
+    ) -> Self {
+        AgentBootstrap {
+            iothub_hostname,
+            device_id,
+            module_id,
+            gateway_hostname,
+            parent_hostname: None,
+            auth_scheme,
+            workload_uri,
+            management_uri,
+        }
+    }
+
+    pub fn with_parent_hostname(mut self, parent_hostname: Option<String>) -> Self {
+        self.parent_hostname = parent_hostname;
+        self
+    }
+
+    pub fn iothub_hostname(&self) -> &str {
+        &self.iothub_hostname
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    pub fn module_id(&self) -> &str {
+        &self.module_id
+    }
+
+    pub fn gateway_hostname(&self) -> &str {
+        &self.gateway_hostname
+    }
+
+    pub fn parent_hostname(&self) -> Option<&str> {
+        self.parent_hostname.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn auth_scheme(&self) -> &str {
+        &self.auth_scheme
+    }
+
+    pub fn workload_uri(&self) -> &str {
+        &self.workload_uri
+    }
+
+    pub fn management_uri(&self) -> &str {
+        &self.management_uri
+    }
+
+    /// The environment the edge agent gives the modules it creates, for the
+    /// modules that the daemon creates from a local deployment instead.
+    pub fn module_env(&self, module_id: &str, generation_id: &str) -> HashMap<String, String> {
+        let mut env = HashMap::new();
+        env.insert(
+            "IOTEDGE_IOTHUBHOSTNAME".to_string(),
+            self.iothub_hostname.clone(),
+        );
+        env.insert(
+            "IOTEDGE_GATEWAYHOSTNAME".to_string(),
+            self.gateway_hostname.clone(),
+        );
+        // The edge hub takes the name it serves from this one.
+        env.insert(
+            "EDGEDEVICEHOSTNAME".to_string(),
+            self.gateway_hostname.clone(),
+        );
+        if let Some(parent_hostname) = &self.parent_hostname {
+            env.insert(
+                "IOTEDGE_PARENTHOSTNAME".to_string(),
+                parent_hostname.clone(),
+            );
+        }
+        env.insert("IOTEDGE_DEVICEID".to_string(), self.device_id.clone());
+        env.insert("IOTEDGE_MODULEID".to_string(), module_id.to_string());
+        env.insert(
+            "IOTEDGE_MODULEGENERATIONID".to_string(),
+            generation_id.to_string(),
+        );
+        env.insert("IOTEDGE_WORKLOADURI".to_string(), self.workload_uri.clone());
+        env.insert("IOTEDGE_AUTHSCHEME".to_string(), self.auth_scheme.clone());
+        env
+    }
+}
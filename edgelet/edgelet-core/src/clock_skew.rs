@@ -0,0 +1,115 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Differences to the time of a server below this are put down to network
+/// latency and the one second resolution of the `Date` header, and ignored.
+const MIN_CLOCK_SKEW_SECS: i64 = 30;
+
+/// Differences to the time of a server from this on are reported, since they
+/// point at a device without a working time source, and can break the
+/// validation of TLS certificates even when SAS tokens are compensated.
+const CLOCK_SKEW_WARNING_SECS: i64 = 300;
+
+/// How far the local clock is off from the time of the hub and the
+/// provisioning service, as seen in the `Date` headers of their responses.
+/// Devices without a real-time clock often start with a time that is hours or
+/// days off, and SAS tokens that expire by the local clock are then rejected
+/// with a 401 that doesn't say why. Tokens are instead given an expiry by the
+/// time of the server.
+///
+/// The skew is shared by all the clones, so that all the clients that talk to
+/// the cloud compensate once one of them has seen a response.
+#[derive(Clone, Debug, Default)]
+pub struct ClockSkew {
+    offset_secs: Arc<AtomicI64>,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        ClockSkew::default()
+    }
+
+    /// How far the time of the server is ahead of the local clock.
+    pub fn offset(&self) -> Duration {
+        Duration::seconds(self.offset_secs.load(Ordering::SeqCst))
+    }
+
+    /// The current time by the clock of the server.
+    pub fn now(&self) -> DateTime<Utc> {
+        Utc::now() + self.offset()
+    }
+
+    /// Records the time a server sent with a response that was just received.
+    pub fn record(&self, server_time: DateTime<Utc>) {
+        self.record_at(server_time, Utc::now());
+    }
+
+    fn record_at(&self, server_time: DateTime<Utc>, local_time: DateTime<Utc>) {
+        let offset = (server_time - local_time).num_seconds();
+        let offset = if offset.abs() < MIN_CLOCK_SKEW_SECS {
+            0
+        } else {
+            offset
+        };
+        self.offset_secs.store(offset, Ordering::SeqCst);
+    }
+
+    /// A warning for `/systeminfo` if the local clock is too far off.
+    pub fn warning(&self) -> Option<String> {
+        clock_skew_warning(self.offset())
+    }
+}
+
+/// A warning about the local clock if it is `offset` behind the time of the
+/// cloud, or ahead of it for a negative `offset`, and that is too far off.
+pub fn clock_skew_warning(offset: Duration) -> Option<String> {
+    let secs = offset.num_seconds();
+    if secs.abs() < CLOCK_SKEW_WARNING_SECS {
+        return None;
+    }
+
+    Some(format!(
+        "The device clock is {} seconds {} the time of the cloud. SAS tokens are compensated \
+         for it, but TLS certificates may be rejected. Make sure the device synchronizes its \
+         time, for example with an NTP daemon.",
+        secs.abs(),
+        if secs > 0 { "behind" } else { "ahead of" },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_skew_is_ignored() {
+        let clock_skew = ClockSkew::new();
+        let local_time = Utc::now();
+
+        clock_skew.record_at(local_time + Duration::seconds(5), local_time);
+
+        assert_eq!(Duration::zero(), clock_skew.offset());
+        assert_eq!(None, clock_skew.warning());
+    }
+
+    #[test]
+    fn large_skew_is_compensated_and_shared() {
+        let clock_skew = ClockSkew::new();
+        let clone = clock_skew.clone();
+        let local_time = Utc::now();
+
+        clock_skew.record_at(local_time + Duration::hours(2), local_time);
+
+        assert_eq!(Duration::hours(2), clone.offset());
+        assert!(clone.now() - Utc::now() > Duration::minutes(119));
+        assert!(clone.warning().unwrap().contains("7200 seconds behind"));
+
+        clock_skew.record_at(local_time - Duration::minutes(10), local_time);
+        assert_eq!(Duration::minutes(-10), clone.offset());
+        assert!(clone.warning().unwrap().contains("600 seconds ahead of"));
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use failure::ResultExt;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+
+use edgelet_utils::validate_env_var_name;
+
+use crate::error::{Error, ErrorKind};
+
+/// The protocol of a port binding.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PortProtocol {
+    Tcp,
+    Udp,
+    Sctp,
+}
+
+impl fmt::Display for PortProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            PortProtocol::Tcp => "tcp",
+            PortProtocol::Udp => "udp",
+            PortProtocol::Sctp => "sctp",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// The restart policy the container runtime applies to the container, as
+/// opposed to the module's `RestartPolicy` that the runtime translates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerRestartPolicy {
+    No,
+    Always,
+    UnlessStopped,
+    /// Restarts the container when it exits with an error, at most the given
+    /// number of times if there is one.
+    OnFailure(Option<u32>),
+}
+
+#[derive(Clone, Debug)]
+struct PortBinding {
+    container_port: u16,
+    protocol: PortProtocol,
+    host_port: u16,
+}
+
+#[derive(Clone, Debug)]
+struct Bind {
+    source: String,
+    target: String,
+    read_only: bool,
+}
+
+/// Builds the create options of a module. They follow the schema of the
+/// docker container create API, which all the module runtimes read, so the
+/// result can be deserialized into the runtime's own create options type.
+///
+/// ```
+/// # use edgelet_core::{CreateOptions, PortProtocol};
+/// let create_options: serde_json::Value = CreateOptions::new()
+///     .with_env("RuntimeLogLevel", "debug")
+///     .with_port(8883, PortProtocol::Tcp, 8883)
+///     .with_bind("/etc/iotedge/config", "/config", true)
+///     .build()
+///     .unwrap();
+/// assert_eq!(
+///     create_options["HostConfig"]["Binds"],
+///     serde_json::json!(["/etc/iotedge/config:/config:ro"])
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CreateOptions {
+    env: Vec<(String, String)>,
+    ports: Vec<PortBinding>,
+    binds: Vec<Bind>,
+    restart_policy: Option<ContainerRestartPolicy>,
+}
+
+impl CreateOptions {
+    pub fn new() -> Self {
+        CreateOptions::default()
+    }
+
+    /// Sets an environment variable, replacing an earlier value of the same
+    /// variable.
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.env.retain(|(k, _)| k != key);
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Exposes `container_port` and binds it to `host_port`. A container port
+    /// can be bound to several host ports.
+    pub fn with_port(
+        mut self,
+        container_port: u16,
+        protocol: PortProtocol,
+        host_port: u16,
+    ) -> Self {
+        self.ports.push(PortBinding {
+            container_port,
+            protocol,
+            host_port,
+        });
+        self
+    }
+
+    /// Mounts `source`, a host path or a volume name, at `target` in the
+    /// container.
+    pub fn with_bind(mut self, source: &str, target: &str, read_only: bool) -> Self {
+        self.binds.push(Bind {
+            source: source.to_string(),
+            target: target.to_string(),
+            read_only,
+        });
+        self
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: ContainerRestartPolicy) -> Self {
+        self.restart_policy = Some(restart_policy);
+        self
+    }
+
+    /// Validates the options and serializes them into `T`, which is either a
+    /// `serde_json::Value` or a runtime's create options type.
+    pub fn build<T>(&self) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.validate()?;
+
+        let mut create_options = Map::new();
+        let mut host_config = Map::new();
+
+        if !self.env.is_empty() {
+            let env: Vec<String> = self
+                .env
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            create_options.insert("Env".to_string(), json!(env));
+        }
+
+        if !self.ports.is_empty() {
+            let mut exposed_ports = Map::new();
+            let mut port_bindings = Map::new();
+            for binding in &self.ports {
+                let port = format!("{}/{}", binding.container_port, binding.protocol);
+                exposed_ports.insert(port.clone(), json!({}));
+                if let Value::Array(host_ports) = port_bindings
+                    .entry(port)
+                    .or_insert_with(|| Value::Array(vec![]))
+                {
+                    host_ports.push(json!({ "HostPort": binding.host_port.to_string() }));
+                }
+            }
+            create_options.insert("ExposedPorts".to_string(), Value::Object(exposed_ports));
+            host_config.insert("PortBindings".to_string(), Value::Object(port_bindings));
+        }
+
+        if !self.binds.is_empty() {
+            let binds: Vec<String> = self
+                .binds
+                .iter()
+                .map(|bind| {
+                    if bind.read_only {
+                        format!("{}:{}:ro", bind.source, bind.target)
+                    } else {
+                        format!("{}:{}", bind.source, bind.target)
+                    }
+                })
+                .collect();
+            host_config.insert("Binds".to_string(), json!(binds));
+        }
+
+        if let Some(restart_policy) = self.restart_policy {
+            let restart_policy = match restart_policy {
+                ContainerRestartPolicy::No => json!({ "Name": "no" }),
+                ContainerRestartPolicy::Always => json!({ "Name": "always" }),
+                ContainerRestartPolicy::UnlessStopped => json!({ "Name": "unless-stopped" }),
+                ContainerRestartPolicy::OnFailure(None) => json!({ "Name": "on-failure" }),
+                ContainerRestartPolicy::OnFailure(Some(max_retries)) => {
+                    json!({ "Name": "on-failure", "MaximumRetryCount": max_retries })
+                }
+            };
+            host_config.insert("RestartPolicy".to_string(), restart_policy);
+        }
+
+        if !host_config.is_empty() {
+            create_options.insert("HostConfig".to_string(), Value::Object(host_config));
+        }
+
+        let create_options =
+            serde_json::from_value(Value::Object(create_options)).with_context(|_| {
+                ErrorKind::InvalidCreateOptions(
+                    "They don't match the runtime's create options".to_string(),
+                )
+            })?;
+        Ok(create_options)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        for (key, _) in &self.env {
+            validate_env_var_name(key).map_err(|err| invalid(err.to_string()))?;
+        }
+
+        let mut bound = HashSet::new();
+        for binding in &self.ports {
+            if binding.container_port == 0 || binding.host_port == 0 {
+                return Err(invalid(format!(
+                    "Invalid port binding {}/{} to host port {}",
+                    binding.container_port, binding.protocol, binding.host_port
+                )));
+            }
+            if !bound.insert((binding.host_port, binding.protocol)) {
+                return Err(invalid(format!(
+                    "Host port {}/{} is bound more than once",
+                    binding.host_port, binding.protocol
+                )));
+            }
+        }
+
+        for bind in &self.binds {
+            if bind.source.trim().is_empty() || bind.target.trim().is_empty() {
+                return Err(invalid(format!(
+                    "Invalid bind of {:?} to {:?}",
+                    bind.source, bind.target
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::from(ErrorKind::InvalidCreateOptions(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_options_are_empty() {
+        let create_options: Value = CreateOptions::new().build().unwrap();
+        assert_eq!(json!({}), create_options);
+    }
+
+    #[test]
+    fn options_are_serialized() {
+        let create_options: Value = CreateOptions::new()
+            .with_env("K1", "v1")
+            .with_env("K2", "a=b")
+            .with_env("K1", "v2")
+            .with_port(8883, PortProtocol::Tcp, 8883)
+            .with_port(443, PortProtocol::Tcp, 443)
+            .with_port(443, PortProtocol::Tcp, 8443)
+            .with_port(5353, PortProtocol::Udp, 5353)
+            .with_bind("/etc/iotedge", "/config", true)
+            .with_bind("edgehub_data", "/data", false)
+            .with_restart_policy(ContainerRestartPolicy::OnFailure(Some(3)))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            json!({
+                "Env": ["K2=a=b", "K1=v2"],
+                "ExposedPorts": {
+                    "443/tcp": {},
+                    "5353/udp": {},
+                    "8883/tcp": {},
+                },
+                "HostConfig": {
+                    "PortBindings": {
+                        "443/tcp": [{ "HostPort": "443" }, { "HostPort": "8443" }],
+                        "5353/udp": [{ "HostPort": "5353" }],
+                        "8883/tcp": [{ "HostPort": "8883" }],
+                    },
+                    "Binds": ["/etc/iotedge:/config:ro", "edgehub_data:/data"],
+                    "RestartPolicy": { "Name": "on-failure", "MaximumRetryCount": 3 },
+                },
+            }),
+            create_options
+        );
+    }
+
+    #[test]
+    fn restart_policies_are_serialized() {
+        for (restart_policy, expected) in &[
+            (ContainerRestartPolicy::No, json!({ "Name": "no" })),
+            (ContainerRestartPolicy::Always, json!({ "Name": "always" })),
+            (
+                ContainerRestartPolicy::UnlessStopped,
+                json!({ "Name": "unless-stopped" }),
+            ),
+            (
+                ContainerRestartPolicy::OnFailure(None),
+                json!({ "Name": "on-failure" }),
+            ),
+        ] {
+            let create_options: Value = CreateOptions::new()
+                .with_restart_policy(*restart_policy)
+                .build()
+                .unwrap();
+            assert_eq!(expected, &create_options["HostConfig"]["RestartPolicy"]);
+        }
+    }
+
+    #[test]
+    fn invalid_options_fail() {
+        let invalid = vec![
+            (
+                CreateOptions::new().with_env("", "v"),
+                "Invalid environment variable name \"\"",
+            ),
+            (
+                CreateOptions::new().with_env("K=V", "v"),
+                "Invalid environment variable name \"K=V\"",
+            ),
+            (
+                CreateOptions::new().with_port(0, PortProtocol::Tcp, 80),
+                "Invalid port binding 0/tcp to host port 80",
+            ),
+            (
+                CreateOptions::new()
+                    .with_port(80, PortProtocol::Tcp, 8080)
+                    .with_port(81, PortProtocol::Tcp, 8080),
+                "Host port 8080/tcp is bound more than once",
+            ),
+            (
+                CreateOptions::new().with_bind(" ", "/data", false),
+                "Invalid bind of \" \" to \"/data\"",
+            ),
+        ];
+
+        for (create_options, message) in invalid {
+            let err = create_options.build::<Value>().unwrap_err();
+            match err.kind() {
+                ErrorKind::InvalidCreateOptions(m) => assert_eq!(message, m),
+                kind => panic!("expected InvalidCreateOptions but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn same_host_port_can_be_bound_for_other_protocols() {
+        let create_options: Value = CreateOptions::new()
+            .with_port(53, PortProtocol::Tcp, 53)
+            .with_port(53, PortProtocol::Udp, 53)
+            .build()
+            .unwrap();
+        assert_eq!(
+            json!({
+                "53/tcp": [{ "HostPort": "53" }],
+                "53/udp": [{ "HostPort": "53" }],
+            }),
+            create_options["HostConfig"]["PortBindings"]
+        );
+    }
+}
@@ -4,13 +4,14 @@ use std::collections::HashMap;
 use std::convert::{AsRef, From};
 use std::fmt;
 use std::string::ToString;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use consistenttime::ct_u8_slice_eq;
-use failure::ResultExt;
+use failure::{Fail, ResultExt};
 use hmac::{Hmac, Mac};
+use rand::Rng;
 use sha2::Sha256;
 
 use crate::certificate_properties::{CertificateIssuer, CertificateProperties};
@@ -19,6 +20,9 @@ use crate::error::{Error, ErrorKind};
 /// This is the issuer alias used when `CertificateIssuer::DefaultCa` is provided by the caller
 pub const IOTEDGED_CA_ALIAS: &str = "iotedged-workload-ca";
 
+/// Length in bytes of the keys created by `MemoryKeyStore`
+const MEMORY_KEY_LEN: usize = 32;
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum KeyIdentity {
     Device,
@@ -65,6 +69,41 @@ pub trait KeyStore {
     fn get(&self, identity: &KeyIdentity, key_name: &str) -> Result<Self::Key, Error>;
 }
 
+/// Manages the lifetime of named keys in a key store. Workload features that
+/// need keys of their own should create them through this trait rather than
+/// handling key material directly.
+pub trait ManageKeys: KeyStore {
+    /// Creates a new key with the given name. Stores that hold key material
+    /// fail if a key with that name already exists.
+    fn create_key(&self, identity: &KeyIdentity, key_name: &str) -> Result<Self::Key, Error>;
+
+    /// Stores the given key material under the given name, replacing any
+    /// existing key with that name.
+    fn import_key(
+        &self,
+        identity: &KeyIdentity,
+        key_name: &str,
+        key: &[u8],
+    ) -> Result<Self::Key, Error>;
+
+    fn delete_key(&self, identity: &KeyIdentity, key_name: &str) -> Result<(), Error>;
+
+    /// Derives a new key from the named key and `data`. The derived key is not
+    /// stored, so the same inputs always derive the same key.
+    fn derive_key(
+        &self,
+        identity: &KeyIdentity,
+        key_name: &str,
+        data: &[u8],
+    ) -> Result<MemoryKey, Error> {
+        let signature = self
+            .get(identity, key_name)?
+            .sign(SignatureAlgorithm::HMACSHA256, data)
+            .context(ErrorKind::Sign)?;
+        Ok(MemoryKey::new(signature.as_bytes()))
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum SignatureAlgorithm {
     HMACSHA256,
@@ -158,6 +197,75 @@ pub trait GetTrustBundle {
     fn get_trust_bundle(&self) -> Result<Self::Certificate, Error>;
 }
 
+/// The largest nonce a quote of the TPM takes, which is the size of a SHA-512
+/// digest.
+pub const MAX_ATTESTATION_NONCE_LENGTH: usize = 64;
+
+/// A quote of the platform configuration registers of the TPM over a nonce of
+/// the caller, signed by an attestation key of the TPM, with the public parts
+/// of that key and of the endorsement key that vouches for the TPM. All of
+/// them are the TPM structures as the TPM returns them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttestationQuote {
+    quoted: Vec<u8>,
+    signature: Vec<u8>,
+    attestation_key: Vec<u8>,
+    endorsement_key: Vec<u8>,
+    endorsement_key_certificate: Option<Vec<u8>>,
+}
+
+impl AttestationQuote {
+    pub fn new(
+        quoted: Vec<u8>,
+        signature: Vec<u8>,
+        attestation_key: Vec<u8>,
+        endorsement_key: Vec<u8>,
+    ) -> Self {
+        AttestationQuote {
+            quoted,
+            signature,
+            attestation_key,
+            endorsement_key,
+            endorsement_key_certificate: None,
+        }
+    }
+
+    pub fn with_endorsement_key_certificate(mut self, certificate: Option<Vec<u8>>) -> Self {
+        self.endorsement_key_certificate = certificate;
+        self
+    }
+
+    /// The `TPMS_ATTEST` structure that was signed.
+    pub fn quoted(&self) -> &[u8] {
+        &self.quoted
+    }
+
+    /// The `TPMT_SIGNATURE` over the quoted structure.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// The `TPMT_PUBLIC` area of the attestation key.
+    pub fn attestation_key(&self) -> &[u8] {
+        &self.attestation_key
+    }
+
+    /// The `TPMT_PUBLIC` area of the endorsement key.
+    pub fn endorsement_key(&self) -> &[u8] {
+        &self.endorsement_key
+    }
+
+    /// The DER certificate of the endorsement key that the manufacturer of the
+    /// TPM provisioned, if it did.
+    pub fn endorsement_key_certificate(&self) -> Option<&[u8]> {
+        self.endorsement_key_certificate.as_ref().map(AsRef::as_ref)
+    }
+}
+
+pub trait Attest {
+    fn quote(&self, nonce: &[u8]) -> Result<AttestationQuote, Error>;
+}
+
 pub trait MakeRandom {
     fn get_random_bytes(&self, buffer: &mut [u8]) -> Result<(), Error>;
 }
@@ -350,6 +458,53 @@ impl KeyStore for MemoryKeyStore {
     }
 }
 
+impl ManageKeys for MemoryKeyStore {
+    fn create_key(&self, identity: &KeyIdentity, key_name: &str) -> Result<Self::Key, Error> {
+        let mut key = [0_u8; MEMORY_KEY_LEN];
+        rand::thread_rng()
+            .try_fill(&mut key[..])
+            .context(ErrorKind::MakeRandom)?;
+        let key = MemoryKey::new(&key[..]);
+
+        let mut keys = self.keys.write().expect("Failed to acquire a write lock");
+        let key_identity = MemoryKeyIdentity::new(identity.clone(), key_name.to_string());
+        if keys.contains_key(&key_identity) {
+            return Err(Error::from(ErrorKind::KeyStoreItemExists));
+        }
+        keys.insert(key_identity, key.clone());
+        Ok(key)
+    }
+
+    fn import_key(
+        &self,
+        identity: &KeyIdentity,
+        key_name: &str,
+        key: &[u8],
+    ) -> Result<Self::Key, Error> {
+        let key = MemoryKey::new(key);
+        self.keys
+            .write()
+            .expect("Failed to acquire a write lock")
+            .insert(
+                MemoryKeyIdentity::new(identity.clone(), key_name.to_string()),
+                key.clone(),
+            );
+        Ok(key)
+    }
+
+    fn delete_key(&self, identity: &KeyIdentity, key_name: &str) -> Result<(), Error> {
+        self.keys
+            .write()
+            .expect("Failed to acquire a write lock")
+            .remove(&MemoryKeyIdentity::new(
+                identity.clone(),
+                key_name.to_string(),
+            ))
+            .map(|_| ())
+            .ok_or_else(|| Error::from(ErrorKind::KeyStoreItemNotFound))
+    }
+}
+
 #[derive(Clone)]
 pub struct DerivedKeyStore<K> {
     root: Arc<K>,
@@ -382,6 +537,152 @@ impl<K: Sign> KeyStore for DerivedKeyStore<K> {
     }
 }
 
+/// Name of the file under the iotedged home directory that holds the result of
+/// the most recent self-test of the crypto backend.
+pub const HSM_SELF_TEST_FILENAME: &str = "hsm_self_test.json";
+
+/// Name of the self-test step that signs with the device key and verifies the
+/// signature.
+pub const SELF_TEST_SIGN_VERIFY: &str = "sign-verify";
+
+const SELF_TEST_SIGN_DATA: &[u8] = b"$iotedge-self-test";
+const SELF_TEST_SIGN_OTHER_DATA: &[u8] = b"$iotedge-self-test-other";
+
+/// Outcome of a single step of a crypto backend self-test.
+#[derive(Clone, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct SelfTestResult {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl SelfTestResult {
+    pub fn passed(name: &str) -> Self {
+        SelfTestResult {
+            name: name.to_string(),
+            error: None,
+        }
+    }
+
+    pub fn failed(name: &str, error: String) -> Self {
+        SelfTestResult {
+            name: name.to_string(),
+            error: Some(error),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Results of a full self-test run of the crypto backend.
+#[derive(Clone, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+pub struct SelfTestReport {
+    timestamp: DateTime<Utc>,
+    results: Vec<SelfTestResult>,
+}
+
+impl SelfTestReport {
+    pub fn new(results: Vec<SelfTestResult>) -> Self {
+        SelfTestReport {
+            timestamp: Utc::now(),
+            results,
+        }
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+
+    pub fn results(&self) -> &[SelfTestResult] {
+        &self.results
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.results.iter().all(SelfTestResult::is_ok)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestResult> {
+        self.results.iter().filter(|result| !result.is_ok())
+    }
+
+    pub fn with_result(mut self, result: SelfTestResult) -> Self {
+        self.results.push(result);
+        self
+    }
+}
+
+/// Signs data with `key` and verifies the signature. The signatures are HMACs,
+/// so a signature is verified by signing the same data again.
+pub fn self_test_sign<K: Sign>(key: &K) -> SelfTestResult {
+    match sign_and_verify(key) {
+        Ok(()) => SelfTestResult::passed(SELF_TEST_SIGN_VERIFY),
+        Err(err) => SelfTestResult::failed(SELF_TEST_SIGN_VERIFY, err),
+    }
+}
+
+fn sign_and_verify<K: Sign>(key: &K) -> Result<(), String> {
+    let sign = |data| {
+        key.sign(SignatureAlgorithm::HMACSHA256, data)
+            .map(|signature| signature.as_bytes().to_vec())
+            .map_err(|err| {
+                <dyn Fail>::iter_chain(&err)
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(": ")
+            })
+    };
+
+    let signature = sign(SELF_TEST_SIGN_DATA)?;
+    if signature.is_empty() {
+        return Err("HSM returned an empty signature".to_string());
+    }
+    if !ct_u8_slice_eq(&sign(SELF_TEST_SIGN_DATA)?, &signature) {
+        return Err("HSM signature could not be verified".to_string());
+    }
+    if ct_u8_slice_eq(&sign(SELF_TEST_SIGN_OTHER_DATA)?, &signature) {
+        return Err("HSM returned the same signature for different data".to_string());
+    }
+
+    Ok(())
+}
+
+/// The report of the most recent self-test of the crypto backend, shared with
+/// the management API.
+#[derive(Clone, Debug, Default)]
+pub struct SelfTestStatus {
+    report: Arc<Mutex<Option<SelfTestReport>>>,
+}
+
+impl SelfTestStatus {
+    pub fn new() -> Self {
+        SelfTestStatus::default()
+    }
+
+    pub fn record(&self, report: SelfTestReport) {
+        *self
+            .report
+            .lock()
+            .expect("Failed to acquire the self-test report lock") = Some(report);
+    }
+
+    pub fn report(&self) -> Option<SelfTestReport> {
+        self.report
+            .lock()
+            .expect("Failed to acquire the self-test report lock")
+            .clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -561,4 +862,150 @@ mod tests {
             base64::encode(digest.as_bytes())
         );
     }
+
+    #[test]
+    fn memory_keystore_create_key() {
+        let key_store = MemoryKeyStore::new();
+        let identity = KeyIdentity::Module("mod1".to_string());
+
+        let key = key_store.create_key(&identity, "key1").unwrap();
+
+        assert_eq!(MEMORY_KEY_LEN, key.as_ref().len());
+        assert_eq!(
+            key.as_ref(),
+            key_store.get(&identity, "key1").unwrap().as_ref()
+        );
+    }
+
+    #[test]
+    fn memory_keystore_create_key_twice_fails() {
+        let key_store = MemoryKeyStore::new();
+        let identity = KeyIdentity::Module("mod1".to_string());
+        let key = key_store.create_key(&identity, "key1").unwrap();
+
+        let err = key_store.create_key(&identity, "key1").unwrap_err();
+
+        match err.kind() {
+            ErrorKind::KeyStoreItemExists => (),
+            _ => panic!("Expected KeyStoreItemExists error"),
+        }
+        assert_eq!(
+            key.as_ref(),
+            key_store.get(&identity, "key1").unwrap().as_ref()
+        );
+    }
+
+    #[test]
+    fn memory_keystore_import_key_replaces_key() {
+        let key_store = MemoryKeyStore::new();
+        let identity = KeyIdentity::Module("mod1".to_string());
+        key_store.create_key(&identity, "key1").unwrap();
+
+        key_store
+            .import_key(&identity, "key1", b"imported")
+            .unwrap();
+
+        assert_eq!(
+            b"imported",
+            key_store.get(&identity, "key1").unwrap().as_ref()
+        );
+    }
+
+    #[test]
+    fn memory_keystore_delete_key() {
+        let key_store = MemoryKeyStore::new();
+        let identity = KeyIdentity::Module("mod1".to_string());
+        key_store.import_key(&identity, "key1", b"anykey").unwrap();
+
+        key_store.delete_key(&identity, "key1").unwrap();
+
+        assert!(key_store.is_empty());
+        match key_store.delete_key(&identity, "key1").unwrap_err().kind() {
+            ErrorKind::KeyStoreItemNotFound => (),
+            _ => panic!("Expected KeyStoreItemNotFound error"),
+        }
+    }
+
+    #[test]
+    fn memory_keystore_derive_key() {
+        let key_store = MemoryKeyStore::new();
+        key_store
+            .import_key(&KeyIdentity::Device, "primary", b"key")
+            .unwrap();
+
+        let derived = key_store
+            .derive_key(
+                &KeyIdentity::Device,
+                "primary",
+                b"The quick brown fox jumps over the lazy dog",
+            )
+            .unwrap();
+
+        // HMACSHA256("key", "The quick brown fox jumps over the lazy dog")
+        assert_eq!(
+            "97yD9DBThCSxMpjmqm+xQ+9NWaFJRhdZl0edvC0aPNg=",
+            base64::encode(derived.as_ref())
+        );
+        assert_eq!(1, key_store.len());
+    }
+
+    #[test]
+    fn memory_keystore_derive_key_fails_for_missing_key() {
+        let key_store = MemoryKeyStore::new();
+
+        let err = key_store
+            .derive_key(&KeyIdentity::Device, "primary", b"data")
+            .unwrap_err();
+
+        match err.kind() {
+            ErrorKind::KeyStoreItemNotFound => (),
+            _ => panic!("Expected KeyStoreItemNotFound error"),
+        }
+    }
+
+    #[test]
+    fn self_test_report_reports_failures() {
+        let report = SelfTestReport::new(vec![
+            SelfTestResult::passed("random"),
+            SelfTestResult::failed("encrypt-decrypt", "HSM failure".to_string()),
+        ]);
+        assert!(!report.is_ok());
+
+        let failures: Vec<_> = report.failures().map(SelfTestResult::name).collect();
+        assert_eq!(vec!["encrypt-decrypt"], failures);
+
+        let json = serde_json::to_string(&report).unwrap();
+        let parsed: SelfTestReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(report, parsed);
+    }
+
+    #[test]
+    fn self_test_report_ok_when_all_pass() {
+        let report = SelfTestReport::new(vec![
+            SelfTestResult::passed("random"),
+            SelfTestResult::passed("trust-bundle"),
+        ]);
+        assert!(report.is_ok());
+        assert_eq!(0, report.failures().count());
+    }
+
+    #[test]
+    fn self_test_sign_verifies_signature() {
+        let result = self_test_sign(&MemoryKey::new("key"));
+        assert_eq!(SELF_TEST_SIGN_VERIFY, result.name());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn self_test_status_keeps_last_report() {
+        let status = SelfTestStatus::new();
+        assert_eq!(None, status.report());
+
+        let report = SelfTestReport::new(vec![SelfTestResult::failed(
+            "random",
+            "HSM failure".to_string(),
+        )]);
+        status.record(report.clone());
+        assert_eq!(Some(report), status.report());
+    }
 }
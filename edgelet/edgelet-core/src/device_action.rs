@@ -0,0 +1,20 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+
+/// A power action on the host that the management API can ask the daemon to
+/// take, once it has stopped the modules.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceAction {
+    Reboot,
+    Shutdown,
+}
+
+impl fmt::Display for DeviceAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceAction::Reboot => write!(f, "reboot"),
+            DeviceAction::Shutdown => write!(f, "shutdown"),
+        }
+    }
+}
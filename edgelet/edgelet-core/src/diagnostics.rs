@@ -0,0 +1,152 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use failure::Fail;
+use futures::Future;
+use serde_derive::Serialize;
+
+use crate::startup::StartupStage;
+
+/// Diagnostics of the daemon for the health analytics of a fleet of devices.
+///
+/// The report is anonymized: it holds no names of the hub, the device or its
+/// modules, and no error messages, which could hold any of those. Errors are
+/// only counted by their kind.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsReport {
+    version: String,
+    reported_at: DateTime<Utc>,
+    os_type: String,
+    architecture: String,
+    provisioning: String,
+    watchdog_restarts: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    startup_failure: Option<StartupStage>,
+    errors: BTreeMap<String, u64>,
+}
+
+impl DiagnosticsReport {
+    pub fn new(version: String, reported_at: DateTime<Utc>) -> Self {
+        DiagnosticsReport {
+            version,
+            reported_at,
+            os_type: String::new(),
+            architecture: String::new(),
+            provisioning: String::new(),
+            watchdog_restarts: 0,
+            startup_failure: None,
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// The host the daemon runs on, e.g. "linux" on "x86_64".
+    pub fn with_platform(mut self, os_type: String, architecture: String) -> Self {
+        self.os_type = os_type;
+        self.architecture = architecture;
+        self
+    }
+
+    /// How the device was provisioned, e.g. "dps".
+    pub fn with_provisioning(mut self, provisioning: String) -> Self {
+        self.provisioning = provisioning;
+        self
+    }
+
+    /// How many times the watchdog started the edge runtime module since the
+    /// daemon started.
+    pub fn with_watchdog_restarts(mut self, watchdog_restarts: u64) -> Self {
+        self.watchdog_restarts = watchdog_restarts;
+        self
+    }
+
+    /// The stage the daemon last failed to start in.
+    pub fn with_startup_failure(mut self, startup_failure: Option<StartupStage>) -> Self {
+        self.startup_failure = startup_failure;
+        self
+    }
+
+    /// How many errors of each kind the daemon ran into since it started.
+    pub fn with_errors(mut self, errors: BTreeMap<String, u64>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn reported_at(&self) -> DateTime<Utc> {
+        self.reported_at
+    }
+
+    pub fn os_type(&self) -> &str {
+        &self.os_type
+    }
+
+    pub fn architecture(&self) -> &str {
+        &self.architecture
+    }
+
+    pub fn provisioning(&self) -> &str {
+        &self.provisioning
+    }
+
+    pub fn watchdog_restarts(&self) -> u64 {
+        self.watchdog_restarts
+    }
+
+    pub fn startup_failure(&self) -> Option<StartupStage> {
+        self.startup_failure
+    }
+
+    pub fn errors(&self) -> &BTreeMap<String, u64> {
+        &self.errors
+    }
+}
+
+/// Where the daemon publishes its diagnostics to, once the owner of the device
+/// consented to it.
+pub trait DiagnosticsSink {
+    type Error: Fail;
+    type PublishFuture: Future<Item = (), Error = Self::Error> + Send;
+
+    fn publish(&self, report: &DiagnosticsReport) -> Self::PublishFuture;
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn report_is_serialized_in_camel_case() {
+        let mut errors = BTreeMap::new();
+        errors.insert("workload 500".to_string(), 2);
+        let report =
+            DiagnosticsReport::new("1.0.9".to_string(), Utc.ymd(2019, 11, 5).and_hms(12, 0, 0))
+                .with_platform("linux".to_string(), "x86_64".to_string())
+                .with_provisioning("dps".to_string())
+                .with_watchdog_restarts(1)
+                .with_startup_failure(Some(StartupStage::Provisioning))
+                .with_errors(errors);
+
+        assert_eq!(
+            json!({
+                "version": "1.0.9",
+                "reportedAt": "2019-11-05T12:00:00Z",
+                "osType": "linux",
+                "architecture": "x86_64",
+                "provisioning": "dps",
+                "watchdogRestarts": 1,
+                "startupFailure": "provisioning",
+                "errors": { "workload 500": 2 },
+            }),
+            serde_json::to_value(&report).unwrap()
+        );
+    }
+}
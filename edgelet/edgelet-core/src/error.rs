@@ -14,6 +14,15 @@ pub struct Error {
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(
+        display = "Module {:?} is not allowed to get management API tokens",
+        _0
+    )]
+    ApiTokenNotAllowed(String),
+
+    #[fail(display = "Could not quote the state of the device with its TPM")]
+    Attestation,
+
     // Only used by edgelet-test-utils
     #[cfg(test)]
     #[fail(display = "Identity error")]
@@ -37,6 +46,9 @@ pub enum ErrorKind {
     #[fail(display = "An error occurred obtaining the certificate's key")]
     CertificateKey,
 
+    #[fail(display = "An error occurred updating the certificate revocation list")]
+    CertificateRevocationList,
+
     #[fail(
         display = "The Connection String is empty. Please update the config.yaml and provide the IoTHub connection information."
     )]
@@ -57,6 +69,9 @@ pub enum ErrorKind {
     )]
     ConnectionStringNotConfigured(&'static str),
 
+    #[fail(display = "Could not decrypt the setting {}", _0)]
+    DecryptSetting(String),
+
     #[fail(display = "An error occurred when obtaining the device identity certificate.")]
     DeviceIdentityCertificate,
 
@@ -71,18 +86,27 @@ pub enum ErrorKind {
     #[fail(display = "The timer that checks the edge runtime status encountered an error.")]
     EdgeRuntimeStatusCheckerTimer,
 
+    #[fail(display = "Could not encrypt the setting")]
+    EncryptSetting,
+
     #[fail(display = "An identity manager error occurred.")]
     IdentityManager,
 
     #[fail(display = "An error occurred when obtaining the HSM version")]
     HsmVersion,
 
+    #[fail(display = "Invalid create options: {}", _0)]
+    InvalidCreateOptions(String),
+
     #[fail(display = "Invalid image pull policy configuration {:?}", _0)]
     InvalidImagePullPolicy(String),
 
     #[fail(display = "Invalid or unsupported certificate issuer.")]
     InvalidIssuer,
 
+    #[fail(display = "Invalid log format {:?}", _0)]
+    InvalidLogFormat(String),
+
     #[fail(display = "Invalid log tail {:?}", _0)]
     InvalidLogTail(String),
 
@@ -92,6 +116,9 @@ pub enum ErrorKind {
     #[fail(display = "Invalid module type {:?}", _0)]
     InvalidModuleType(String),
 
+    #[fail(display = "Invalid restart policy configuration {:?}", _0)]
+    InvalidRestartPolicy(String),
+
     #[fail(
         display = "Error parsing URI {} specified for '{}'. Please check the config.yaml file.",
         _0, _1
@@ -110,9 +137,24 @@ pub enum ErrorKind {
     #[fail(display = "An error occurred in the key store.")]
     KeyStore,
 
+    #[fail(display = "Item already exists.")]
+    KeyStoreItemExists,
+
     #[fail(display = "Item not found.")]
     KeyStoreItemNotFound,
 
+    #[fail(display = "The lifecycle hook for {} failed.", _0)]
+    LifecycleHook(String),
+
+    #[fail(display = "The lifecycle hook for {} exited with {}.", _0, _1)]
+    LifecycleHookExit(String, String),
+
+    #[fail(
+        display = "The lifecycle hook for {} did not finish within {} seconds.",
+        _0, _1
+    )]
+    LifecycleHookTimeout(String, u64),
+
     #[fail(display = "An error occured when generating a random number.")]
     MakeRandom,
 
@@ -122,6 +164,12 @@ pub enum ErrorKind {
     #[fail(display = "Unable to parse since.")]
     ParseSince,
 
+    #[fail(
+        display = "Module {} references secret {:?}, which is not in the secret store",
+        _0, _1
+    )]
+    SecretNotFound(String, String),
+
     #[fail(display = "Signing error occurred.")]
     Sign,
 
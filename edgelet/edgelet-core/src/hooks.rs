@@ -0,0 +1,315 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use failure::{Fail, ResultExt};
+use futures::sync::oneshot;
+use futures::{future, Future};
+use log::{debug, Level};
+
+use edgelet_utils::log_failure;
+
+use crate::error::{Error, ErrorKind};
+
+/// How often a running hook command is checked for having exited.
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Something that happened to a module or to its identity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LifecycleEvent {
+    IdentityCreated,
+    IdentityDeleted,
+    ModuleStarted,
+    ModuleStopped,
+}
+
+impl fmt::Display for LifecycleEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleEvent::IdentityCreated => write!(f, "identity_created"),
+            LifecycleEvent::IdentityDeleted => write!(f, "identity_deleted"),
+            LifecycleEvent::ModuleStarted => write!(f, "module_started"),
+            LifecycleEvent::ModuleStopped => write!(f, "module_stopped"),
+        }
+    }
+}
+
+/// Gets told about the lifecycle events of modules, e.g. to keep the module
+/// identities of an external system in sync with the device.
+pub trait LifecycleHook {
+    /// Called after `event` happened to the module `module`.
+    fn on_event(
+        &self,
+        event: LifecycleEvent,
+        module: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send>;
+}
+
+/// The lifecycle hooks registered with the daemon.
+#[derive(Clone, Default)]
+pub struct LifecycleHooks {
+    hooks: Vec<Arc<dyn LifecycleHook + Send + Sync>>,
+}
+
+impl LifecycleHooks {
+    pub fn new() -> Self {
+        LifecycleHooks::default()
+    }
+
+    pub fn with_hook<H>(mut self, hook: H) -> Self
+    where
+        H: LifecycleHook + Send + Sync + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Calls all the hooks, and resolves once they are done. A failing hook
+    /// is logged, it doesn't fail the operation the event is about, nor the
+    /// other hooks.
+    pub fn notify(
+        &self,
+        event: LifecycleEvent,
+        module: &str,
+    ) -> impl Future<Item = (), Error = ()> + Send {
+        let hooks = self.hooks.clone();
+        let module = module.to_string();
+
+        future::lazy(move || {
+            let calls: Vec<_> = hooks
+                .iter()
+                .map(|hook| {
+                    hook.on_event(event, &module).then(|result| {
+                        if let Err(err) = result {
+                            log_failure(Level::Warn, &err);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+
+            future::join_all(calls).map(|_| ())
+        })
+    }
+}
+
+/// A lifecycle hook that runs an executable for every event, with the event
+/// and the name of the module appended to its arguments.
+#[derive(Clone, Debug)]
+pub struct CommandHook {
+    command: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+}
+
+impl CommandHook {
+    pub fn new(command: PathBuf, args: Vec<String>, timeout: Duration) -> Self {
+        CommandHook {
+            command,
+            args,
+            timeout,
+        }
+    }
+
+    fn run(&self, event: LifecycleEvent, module: &str) -> Result<(), Error> {
+        let operation = || format!("{} {}", event, module);
+        debug!(
+            "Running lifecycle hook {} for {}",
+            self.command.display(),
+            operation()
+        );
+
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .arg(event.to_string())
+            .arg(module)
+            .spawn()
+            .with_context(|_| ErrorKind::LifecycleHook(operation()))?;
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            match child
+                .try_wait()
+                .with_context(|_| ErrorKind::LifecycleHook(operation()))?
+            {
+                Some(status) if status.success() => return Ok(()),
+                Some(status) => {
+                    return Err(Error::from(ErrorKind::LifecycleHookExit(
+                        operation(),
+                        status.to_string(),
+                    )))
+                }
+                None if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(Error::from(ErrorKind::LifecycleHookTimeout(
+                        operation(),
+                        self.timeout.as_secs(),
+                    )));
+                }
+                None => thread::sleep(COMMAND_POLL_INTERVAL),
+            }
+        }
+    }
+}
+
+impl LifecycleHook for CommandHook {
+    fn on_event(
+        &self,
+        event: LifecycleEvent,
+        module: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+        // The command is waited for on a thread of its own, so that a slow
+        // hook doesn't hold up the event loop.
+        let (tx, rx) = oneshot::channel();
+        let hook = self.clone();
+        let module = module.to_string();
+        let operation = format!("{} {}", event, module);
+        let spawned = thread::Builder::new()
+            .name("lifecycle-hook".to_string())
+            .spawn(move || {
+                let _ = tx.send(hook.run(event, &module));
+            });
+
+        match spawned {
+            Ok(_) => Box::new(rx.then(move |result| match result {
+                Ok(result) => result,
+                Err(_) => Err(Error::from(ErrorKind::LifecycleHook(operation))),
+            })),
+            Err(err) => Box::new(future::err(Error::from(
+                err.context(ErrorKind::LifecycleHook(operation)),
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct RecordingHook {
+        events: Arc<Mutex<Vec<(LifecycleEvent, String)>>>,
+        fail: bool,
+    }
+
+    impl LifecycleHook for RecordingHook {
+        fn on_event(
+            &self,
+            event: LifecycleEvent,
+            module: &str,
+        ) -> Box<dyn Future<Item = (), Error = Error> + Send> {
+            self.events
+                .lock()
+                .unwrap()
+                .push((event, module.to_string()));
+            if self.fail {
+                Box::new(future::err(Error::from(ErrorKind::LifecycleHook(
+                    module.to_string(),
+                ))))
+            } else {
+                Box::new(future::ok(()))
+            }
+        }
+    }
+
+    #[test]
+    fn notify_calls_every_hook_even_if_one_fails() {
+        let failing = RecordingHook {
+            fail: true,
+            ..RecordingHook::default()
+        };
+        let failing_events = failing.events.clone();
+        let recording = RecordingHook::default();
+        let events = recording.events.clone();
+
+        let hooks = LifecycleHooks::new()
+            .with_hook(failing)
+            .with_hook(recording);
+        hooks
+            .notify(LifecycleEvent::ModuleStarted, "tempSensor")
+            .wait()
+            .unwrap();
+
+        let expected = vec![(LifecycleEvent::ModuleStarted, "tempSensor".to_string())];
+        assert_eq!(expected, *failing_events.lock().unwrap());
+        assert_eq!(expected, *events.lock().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_hook_gets_event_and_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        let hook = CommandHook::new(
+            "/bin/sh".into(),
+            vec![
+                "-c".to_string(),
+                format!("echo \"$0 $1\" > {}", output.display()),
+            ],
+            Duration::from_secs(10),
+        );
+
+        hook.on_event(LifecycleEvent::IdentityCreated, "tempSensor")
+            .wait()
+            .unwrap();
+
+        assert_eq!(
+            "identity_created tempSensor\n",
+            std::fs::read_to_string(output).unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_hook_fails_on_exit_code() {
+        let hook = CommandHook::new(
+            "/bin/sh".into(),
+            vec!["-c".to_string(), "exit 3".to_string()],
+            Duration::from_secs(10),
+        );
+
+        let err = hook
+            .on_event(LifecycleEvent::ModuleStopped, "tempSensor")
+            .wait()
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::LifecycleHookExit(operation, _) => {
+                assert_eq!("module_stopped tempSensor", operation);
+            }
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn command_hook_is_killed_after_timeout() {
+        let hook = CommandHook::new(
+            "/bin/sh".into(),
+            vec!["-c".to_string(), "sleep 10".to_string()],
+            Duration::from_millis(200),
+        );
+
+        let err = hook
+            .on_event(LifecycleEvent::ModuleStarted, "tempSensor")
+            .wait()
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::LifecycleHookTimeout(operation, _) => {
+                assert_eq!("module_started tempSensor", operation);
+            }
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+}
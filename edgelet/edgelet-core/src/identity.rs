@@ -76,12 +76,18 @@ pub trait IdentityManager {
     type ListFuture: Future<Item = Vec<Self::Identity>, Error = Self::Error> + Send;
     type GetFuture: Future<Item = Option<Self::Identity>, Error = Self::Error> + Send;
     type DeleteFuture: Future<Item = (), Error = Self::Error> + Send;
+    type RegenerateFuture: Future<Item = Self::Identity, Error = Self::Error> + Send;
 
     fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture;
     fn update(&mut self, id: IdentitySpec) -> Self::UpdateFuture;
     fn list(&self) -> Self::ListFuture;
     fn get(&self, id: IdentitySpec) -> Self::GetFuture;
     fn delete(&mut self, id: IdentitySpec) -> Self::DeleteFuture;
+
+    /// Gives the identity new keys, which changes its generation ID. The
+    /// modules that use the identity have to be started again with the new
+    /// generation ID to authenticate with the new keys.
+    fn regenerate(&mut self, id: IdentitySpec) -> Self::RegenerateFuture;
 }
 
 // Useful for error contexts
@@ -91,6 +97,7 @@ pub enum IdentityOperation {
     DeleteIdentity(String),
     GetIdentity(String),
     ListIdentities,
+    RegenerateIdentity(String),
     UpdateIdentity(String),
 }
 
@@ -105,6 +112,9 @@ impl fmt::Display for IdentityOperation {
             }
             IdentityOperation::GetIdentity(name) => write!(f, "Could not get identity {}", name),
             IdentityOperation::ListIdentities => write!(f, "Could not list identities"),
+            IdentityOperation::RegenerateIdentity(name) => {
+                write!(f, "Could not regenerate the keys of identity {}", name)
+            }
             IdentityOperation::UpdateIdentity(name) => {
                 write!(f, "Could not update identity {}", name)
             }
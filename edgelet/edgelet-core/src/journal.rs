@@ -0,0 +1,358 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use failure::Fail;
+use futures::{future, stream, Future, Stream};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::identity::{IdentityManager, IdentitySpec};
+use crate::module::{ModuleRuntime, ModuleRuntimeErrorReason};
+
+/// Name of the file in the home directory that holds the module operations
+/// that are in progress.
+pub const OPERATION_JOURNAL_FILENAME: &str = "operation_journal.json";
+
+/// A module or identity operation of the management API that changes more
+/// than one thing, or that can't be undone by repeating it.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    CreateModule {
+        name: String,
+    },
+    /// The update of module `name`. An update that keeps the old module
+    /// running while the new one is created moves it to `previous`.
+    UpdateModule {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<String>,
+    },
+    RemoveModule {
+        name: String,
+    },
+    CreateIdentity {
+        name: String,
+    },
+    DeleteIdentity {
+        name: String,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::CreateModule { name } => write!(f, "create module {}", name),
+            Operation::UpdateModule { name, .. } => write!(f, "update module {}", name),
+            Operation::RemoveModule { name } => write!(f, "remove module {}", name),
+            Operation::CreateIdentity { name } => write!(f, "create identity {}", name),
+            Operation::DeleteIdentity { name } => write!(f, "delete identity {}", name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct OperationId(u64);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct JournalEntry {
+    id: OperationId,
+    operation: Operation,
+    started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct JournalState {
+    next_id: u64,
+    entries: Vec<JournalEntry>,
+}
+
+/// Records the module operations that are in progress, so that the ones a
+/// daemon that lost power was in the middle of can be finished or rolled back
+/// when it starts again, instead of leaving half created modules and orphaned
+/// identities behind.
+///
+/// An operation is written to the file before it starts, and dropped from it
+/// once it is done, whether it succeeded or not.
+#[derive(Clone, Debug)]
+pub struct OperationJournal {
+    state: Arc<Mutex<JournalState>>,
+    path: Option<PathBuf>,
+}
+
+impl OperationJournal {
+    /// Keeps the operations in memory only.
+    pub fn new() -> Self {
+        OperationJournal {
+            state: Arc::new(Mutex::new(JournalState::default())),
+            path: None,
+        }
+    }
+
+    /// Keeps the operations in the file at `path`, picking up the ones an
+    /// earlier run of the daemon didn't finish.
+    pub fn load(path: PathBuf) -> Self {
+        let entries: Vec<JournalEntry> = fs::read(&path)
+            .ok()
+            .and_then(|entries| serde_json::from_slice(&entries).ok())
+            .unwrap_or_default();
+        let next_id = entries
+            .iter()
+            .map(|entry| entry.id.0 + 1)
+            .max()
+            .unwrap_or(0);
+
+        OperationJournal {
+            state: Arc::new(Mutex::new(JournalState { next_id, entries })),
+            path: Some(path),
+        }
+    }
+
+    /// Records that `operation` is about to start.
+    pub fn begin(&self, operation: Operation) -> OperationId {
+        let mut state = self.lock();
+        let id = OperationId(state.next_id);
+        state.next_id += 1;
+        state.entries.push(JournalEntry {
+            id,
+            operation,
+            started_at: Utc::now(),
+        });
+        self.save(&state);
+        id
+    }
+
+    /// Records that the operation `id` is done.
+    pub fn finish(&self, id: OperationId) {
+        let mut state = self.lock();
+        state.entries.retain(|entry| entry.id != id);
+        self.save(&state);
+    }
+
+    /// The operations that are in progress, oldest first.
+    pub fn pending(&self) -> Vec<Operation> {
+        self.lock()
+            .entries
+            .iter()
+            .map(|entry| entry.operation.clone())
+            .collect()
+    }
+
+    /// Finishes or rolls back the operations that are in progress, one after
+    /// the other. It is meant to run when the daemon starts, before the
+    /// management API takes new operations.
+    ///
+    /// Creating a module or an identity is rolled back, since whoever asked
+    /// for it never heard back and asks again. Removing a module or deleting
+    /// an identity is finished. An update is rolled back to the old module if
+    /// it was kept aside, otherwise the module is removed for the edge agent
+    /// to create it again. Operations that can't be recovered from are logged
+    /// and dropped.
+    pub fn replay<M, I>(
+        &self,
+        runtime: &M,
+        identity_manager: &I,
+    ) -> impl Future<Item = (), Error = ()> + Send
+    where
+        M: 'static + ModuleRuntime + Clone + Send,
+        for<'r> &'r M::Error: Into<ModuleRuntimeErrorReason>,
+        I: 'static + IdentityManager + Clone + Send,
+    {
+        let entries = self.lock().entries.clone();
+        let journal = self.clone();
+        let runtime = runtime.clone();
+        let identity_manager = identity_manager.clone();
+
+        stream::iter_ok(entries).for_each(move |entry| {
+            info!(
+                "Recovering from interrupted operation to {}, started at {}",
+                entry.operation, entry.started_at
+            );
+            let journal = journal.clone();
+            let JournalEntry { id, operation, .. } = entry;
+            replay_operation(runtime.clone(), identity_manager.clone(), operation.clone()).then(
+                move |result| {
+                    match result {
+                        Ok(()) => info!("Recovered from interrupted operation to {}", operation),
+                        Err(err) => warn!(
+                            "Could not recover from interrupted operation to {}: {}",
+                            operation, err
+                        ),
+                    }
+                    journal.finish(id);
+                    Ok(())
+                },
+            )
+        })
+    }
+
+    // The file is replaced as a whole, so that it holds either the old or the
+    // new entries if the device loses power while it is written.
+    fn save(&self, state: &JournalState) {
+        if let Some(path) = &self.path {
+            if let Err(err) = write_atomically(path, &state.entries) {
+                warn!(
+                    "Could not write the operation journal to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JournalState> {
+        self.state
+            .lock()
+            .expect("Failed to acquire the operation journal lock")
+    }
+}
+
+impl Default for OperationJournal {
+    fn default() -> Self {
+        OperationJournal::new()
+    }
+}
+
+fn write_atomically(path: &Path, entries: &[JournalEntry]) -> Result<(), failure::Error> {
+    let contents = serde_json::to_vec(entries)?;
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn replay_operation<M, I>(
+    runtime: M,
+    mut identity_manager: I,
+    operation: Operation,
+) -> Box<dyn Future<Item = (), Error = failure::Error> + Send>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    for<'r> &'r M::Error: Into<ModuleRuntimeErrorReason>,
+    I: 'static + IdentityManager + Send,
+{
+    match operation {
+        Operation::CreateModule { name } | Operation::RemoveModule { name } => {
+            Box::new(remove_module(&runtime, &name))
+        }
+        Operation::UpdateModule {
+            name,
+            previous: None,
+        } => Box::new(remove_module(&runtime, &name)),
+        Operation::UpdateModule {
+            name,
+            previous: Some(previous),
+        } => {
+            // The old module is only kept aside while the update is in
+            // progress, so if it isn't there the update either didn't get to
+            // move it or is done with it.
+            Box::new(runtime.get(&previous).then(move |result| match result {
+                Ok(_) => future::Either::A(remove_module(&runtime, &name).and_then(move |()| {
+                    runtime
+                        .rename(&previous, &name)
+                        .map_err(|err| failure::Error::from(err.compat()))
+                })),
+                Err(_) => future::Either::B(future::ok(())),
+            }))
+        }
+        Operation::CreateIdentity { name } | Operation::DeleteIdentity { name } => Box::new(
+            identity_manager
+                .delete(IdentitySpec::new(name))
+                .map_err(|err| failure::Error::from(err.compat())),
+        ),
+    }
+}
+
+// Removes the module, which is fine if it is already gone.
+fn remove_module<M>(runtime: &M, name: &str) -> impl Future<Item = (), Error = failure::Error>
+where
+    M: ModuleRuntime,
+    for<'r> &'r M::Error: Into<ModuleRuntimeErrorReason>,
+{
+    runtime.remove(name).or_else(|err| match (&err).into() {
+        ModuleRuntimeErrorReason::NotFound => Ok(()),
+        ModuleRuntimeErrorReason::Other => Err(failure::Error::from(err.compat())),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn operations_are_pending_until_finished() {
+        let journal = OperationJournal::new();
+        let create = journal.begin(Operation::CreateModule {
+            name: "tempSensor".to_string(),
+        });
+        let delete = journal.begin(Operation::DeleteIdentity {
+            name: "tempSensor".to_string(),
+        });
+
+        journal.finish(create);
+
+        assert_eq!(
+            vec![Operation::DeleteIdentity {
+                name: "tempSensor".to_string()
+            }],
+            journal.pending()
+        );
+
+        journal.finish(delete);
+        assert!(journal.pending().is_empty());
+    }
+
+    #[test]
+    fn pending_operations_are_kept_across_runs() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(OPERATION_JOURNAL_FILENAME);
+
+        let journal = OperationJournal::load(path.clone());
+        let first = journal.begin(Operation::RemoveModule {
+            name: "m1".to_string(),
+        });
+        journal.begin(Operation::UpdateModule {
+            name: "m2".to_string(),
+            previous: Some("m2_previous".to_string()),
+        });
+        journal.finish(first);
+
+        let journal = OperationJournal::load(path.clone());
+        assert_eq!(
+            vec![Operation::UpdateModule {
+                name: "m2".to_string(),
+                previous: Some("m2_previous".to_string()),
+            }],
+            journal.pending()
+        );
+
+        // ids of the next operations don't clash with the pending ones
+        let next = journal.begin(Operation::CreateIdentity {
+            name: "m3".to_string(),
+        });
+        assert_eq!(OperationId(2), next);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn unreadable_journal_is_empty() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(OPERATION_JOURNAL_FILENAME);
+        fs::write(&path, b"not json").unwrap();
+
+        let journal = OperationJournal::load(path);
+        assert!(journal.pending().is_empty());
+    }
+}
@@ -16,50 +16,109 @@ use failure::ResultExt;
 use lazy_static::lazy_static;
 use url::Url;
 
+mod api_token;
 mod authentication;
 mod authorization;
+mod boot_order;
+mod bootstrap;
 mod certificate_properties;
+mod clock_skew;
+mod create_options;
 pub mod crypto;
+mod device_action;
+mod diagnostics;
 mod error;
+mod hooks;
 mod identity;
+mod journal;
+mod logging;
 mod logs;
+mod metrics;
 mod module;
 mod network;
+mod operations;
+mod orphans;
 mod parse_since;
+mod revocation;
+mod secret_store;
+mod secrets;
 mod settings;
+mod startup;
 pub mod watchdog;
 pub mod workload;
 
+pub use api_token::{ApiToken, ApiTokenOwner, ApiTokens};
 pub use authentication::Authenticator;
 pub use authorization::{AuthId, ModuleId, Policy};
+pub use boot_order::{BootOrder, BOOT_ORDER_FILENAME};
+pub use bootstrap::AgentBootstrap;
 pub use certificate_properties::{CertificateIssuer, CertificateProperties, CertificateType};
+pub use clock_skew::{clock_skew_warning, ClockSkew};
+pub use create_options::{ContainerRestartPolicy, CreateOptions, PortProtocol};
 pub use crypto::{
-    Certificate, CreateCertificate, Decrypt, Encrypt, GetDeviceIdentityCertificate, GetHsmVersion,
-    GetIssuerAlias, GetTrustBundle, KeyBytes, KeyIdentity, KeyStore, MakeRandom,
-    MasterEncryptionKey, PrivateKey, Signature, IOTEDGED_CA_ALIAS,
+    self_test_sign, Attest, AttestationQuote, Certificate, CreateCertificate, Decrypt, Encrypt,
+    GetDeviceIdentityCertificate, GetHsmVersion, GetIssuerAlias, GetTrustBundle, KeyBytes,
+    KeyIdentity, KeyStore, MakeRandom, ManageKeys, MasterEncryptionKey, PrivateKey, SelfTestReport,
+    SelfTestResult, SelfTestStatus, Signature, HSM_SELF_TEST_FILENAME, IOTEDGED_CA_ALIAS,
+    MAX_ATTESTATION_NONCE_LENGTH, SELF_TEST_SIGN_VERIFY,
 };
+pub use device_action::DeviceAction;
+pub use diagnostics::{DiagnosticsReport, DiagnosticsSink};
 pub use error::{Error, ErrorKind};
+pub use hooks::{CommandHook, LifecycleEvent, LifecycleHook, LifecycleHooks};
 pub use identity::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
+pub use journal::{Operation, OperationId, OperationJournal, OPERATION_JOURNAL_FILENAME};
+pub use logging::{LogBuffer, LogController, LogFormat, LogRecord, LogSettings};
 pub use logs::{Chunked, LogChunk, LogDecode};
+pub use metrics::Metrics;
 pub use module::{
-    DiskInfo, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module, ModuleOperation,
-    ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec,
-    ModuleStatus, ModuleTop, ProvisioningResult, RegistryOperation, RuntimeOperation, SystemInfo,
-    SystemResources,
+    DiskInfo, ImageInfo, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module,
+    ModuleEvent, ModuleExecResult, ModuleOperation, ModuleProcesses, ModuleRegistry, ModuleRuntime,
+    ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, ModuleStatus, ModuleTop,
+    ProvisioningResult, PrunedImages, RegistryOperation, RestartPolicy, RuntimeOperation,
+    SystemInfo, SystemResources,
 };
 pub use network::{Ipam, IpamConfig, MobyNetwork, Network};
+pub use operations::{
+    OperationKind, OperationProgress, OperationReporter, OperationStatus, OperationTracker,
+};
+pub use orphans::remove_orphaned_modules;
 pub use parse_since::parse_since;
+pub use revocation::{CertificateRevocationList, IssuedCertificate};
+pub use secret_store::{resolve_secret_refs, LocalSecretStore, SecretStore, SECRET_REF_PREFIX};
+pub use secrets::{decrypt_setting, decrypt_settings, encrypt_setting, ENCRYPTED_SETTING_PREFIX};
 pub use settings::{
-    AttestationMethod, Certificates, Connect, Dps, External, Listen, Manual, ManualAuthMethod,
-    ManualDeviceConnectionString, ManualX509Auth, Protocol, Provisioning, ProvisioningType,
-    RetryLimit, RuntimeSettings, Settings, SymmetricKeyAttestationInfo, TpmAttestationInfo,
-    WatchdogSettings, X509AttestationInfo,
+    AttestationMethod, Certificates, Connect, DeviceActionSettings, DiagnosticsSettings,
+    DiagnosticsSinkSettings, Dps, DpsTransport, Est, External, HealthReportSettings,
+    HealthReportTarget, LifecycleHookSettings, Listen, ManagementAuthSettings, Manual,
+    ManualAuthMethod, ManualDeviceConnectionString, ManualX509Auth, OrphanedModuleSettings,
+    Protocol, Provisioning, ProvisioningType, RateLimitSettings, RetryLimit, RetrySettings,
+    RuntimeSettings, Settings, SocketPermissions, SymmetricKeyAttestationInfo, Tenant,
+    TpmAttestationInfo, TpmTcti, WatchdogSettings, X509AttestationInfo, REQUIRED_SETTINGS,
+    SETTINGS_ALIASES,
 };
+pub use startup::{StartupFailure, StartupStage, StartupState, STARTUP_STATE_FILENAME};
 pub use workload::WorkloadConfig;
 
+/// This is the default time a management API token minted for a module is valid for
+pub const DEFAULT_API_TOKEN_LIFETIME_SECS: u64 = 3600;
+
 /// This is the default auto generated certificate life
 pub const DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS: u16 = 90;
 
+/// This is the default time before CA expiry at which the daemon renews it
+pub const DEFAULT_CA_RENEWAL_THRESHOLD_DAYS: u16 = 7;
+
+/// This is the default time a lifecycle hook executable may run for
+pub const DEFAULT_LIFECYCLE_HOOK_TIMEOUT_SECS: u64 = 30;
+
+/// This is the default frequency with which the watchdog checks the edge runtime module
+pub const DEFAULT_WATCHDOG_CHECK_INTERVAL_SECS: u64 = 60;
+
+/// This is the default number of failed health checks after which the watchdog
+/// restarts the edge runtime module
+pub const DEFAULT_WATCHDOG_FAILURE_THRESHOLD: u32 = 3;
+
 lazy_static! {
     static ref VERSION: &'static str =
         option_env!("VERSION").unwrap_or_else(|| include_str!("../../version.txt").trim());
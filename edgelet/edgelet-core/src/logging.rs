@@ -0,0 +1,297 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::Level;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorKind};
+use crate::module::LogTail;
+
+/// How the daemon writes its log records.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(Error::from(ErrorKind::InvalidLogFormat(s.to_string()))),
+        }
+    }
+}
+
+/// The format of the log records and the levels they are filtered by. The
+/// levels use the syntax of the `IOTEDGE_LOG` environment variable, e.g.
+/// `info,edgelet_docker=debug`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogSettings {
+    format: LogFormat,
+    level: String,
+}
+
+impl LogSettings {
+    pub fn new(format: LogFormat, level: String) -> Self {
+        LogSettings { format, level }
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    pub fn level(&self) -> &str {
+        &self.level
+    }
+}
+
+/// Gives access to the log settings of the daemon while it is running, so
+/// they can be changed without a restart.
+#[derive(Clone)]
+pub struct LogController {
+    current: Arc<dyn Fn() -> LogSettings + Send + Sync>,
+    apply: Arc<dyn Fn(&LogSettings) + Send + Sync>,
+}
+
+impl LogController {
+    pub fn new<C, A>(current: C, apply: A) -> Self
+    where
+        C: Fn() -> LogSettings + Send + Sync + 'static,
+        A: Fn(&LogSettings) + Send + Sync + 'static,
+    {
+        LogController {
+            current: Arc::new(current),
+            apply: Arc::new(apply),
+        }
+    }
+
+    pub fn settings(&self) -> LogSettings {
+        (self.current)()
+    }
+
+    pub fn set_settings(&self, settings: &LogSettings) {
+        (self.apply)(settings);
+    }
+}
+
+impl fmt::Debug for LogController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogController")
+            .field("settings", &self.settings())
+            .finish()
+    }
+}
+
+/// A log record of the daemon, as kept by a `LogBuffer`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogRecord {
+    timestamp: DateTime<Utc>,
+    level: Level,
+    target: String,
+    message: String,
+}
+
+impl LogRecord {
+    pub fn new(timestamp: DateTime<Utc>, level: Level, target: String, message: String) -> Self {
+        LogRecord {
+            timestamp,
+            level,
+            target,
+            message,
+        }
+    }
+
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        self.timestamp
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] - [{}] {}",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// Keeps the most recent log records of the daemon in memory, so that they
+/// can be retrieved through the management API where there is no journal to
+/// read them from. The oldest records are dropped once `capacity` is reached.
+#[derive(Clone)]
+pub struct LogBuffer {
+    records: Arc<Mutex<VecDeque<LogRecord>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        LogBuffer {
+            records: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, record: LogRecord) {
+        let mut records = self.records.lock().expect("log buffer lock poisoned");
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        if self.capacity > 0 {
+            records.push_back(record);
+        }
+    }
+
+    /// Returns the records at `level` or more severe, oldest first. With a
+    /// `LogTail::Num` tail, only that many of the most recent ones are returned.
+    pub fn records(&self, level: Level, tail: &LogTail) -> Vec<LogRecord> {
+        let records = self.records.lock().expect("log buffer lock poisoned");
+        let mut records: Vec<LogRecord> = records
+            .iter()
+            .filter(|record| record.level() <= level)
+            .cloned()
+            .collect();
+        if let LogTail::Num(tail) = tail {
+            if let Ok(tail) = usize::try_from(*tail) {
+                if records.len() > tail {
+                    records.drain(..records.len() - tail);
+                }
+            }
+        }
+        records
+    }
+}
+
+impl fmt::Debug for LogBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LogBuffer")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn log_format_round_trips_through_its_name() {
+        for format in &[LogFormat::Text, LogFormat::Json] {
+            assert_eq!(*format, format.to_string().parse::<LogFormat>().unwrap());
+        }
+    }
+
+    #[test]
+    fn unknown_log_format_is_rejected() {
+        match "xml".parse::<LogFormat>() {
+            Ok(_) => panic!("Expected an error"),
+            Err(err) => match err.kind() {
+                ErrorKind::InvalidLogFormat(format) => assert_eq!("xml", format),
+                _ => panic!("Expected an InvalidLogFormat error, got {}", err),
+            },
+        }
+    }
+
+    #[test]
+    fn controller_applies_settings() {
+        let state = Arc::new(Mutex::new(LogSettings::new(
+            LogFormat::Text,
+            "info".to_string(),
+        )));
+        let current = state.clone();
+        let controller = LogController::new(
+            move || current.lock().unwrap().clone(),
+            move |settings| *state.lock().unwrap() = settings.clone(),
+        );
+
+        let settings = LogSettings::new(LogFormat::Json, "info,edgelet_docker=debug".to_string());
+        controller.set_settings(&settings);
+
+        assert_eq!(settings, controller.settings());
+    }
+
+    fn record(level: Level, message: &str) -> LogRecord {
+        LogRecord::new(
+            Utc::now(),
+            level,
+            "iotedged".to_string(),
+            message.to_string(),
+        )
+    }
+
+    #[test]
+    fn buffer_drops_oldest_records_when_full() {
+        let buffer = LogBuffer::new(2);
+        buffer.push(record(Level::Info, "one"));
+        buffer.push(record(Level::Info, "two"));
+        buffer.push(record(Level::Info, "three"));
+
+        let messages: Vec<_> = buffer
+            .records(Level::Trace, &LogTail::All)
+            .iter()
+            .map(|record| record.message().to_string())
+            .collect();
+        assert_eq!(vec!["two", "three"], messages);
+    }
+
+    #[test]
+    fn buffer_filters_by_level_and_tail() {
+        let buffer = LogBuffer::new(10);
+        buffer.push(record(Level::Error, "one"));
+        buffer.push(record(Level::Debug, "two"));
+        buffer.push(record(Level::Warn, "three"));
+        buffer.push(record(Level::Info, "four"));
+        buffer.push(record(Level::Error, "five"));
+
+        let messages: Vec<_> = buffer
+            .records(Level::Warn, &LogTail::Num(2))
+            .iter()
+            .map(|record| record.message().to_string())
+            .collect();
+        assert_eq!(vec!["three", "five"], messages);
+    }
+}
@@ -0,0 +1,409 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+
+const CERTIFICATE_EXPIRY_METRIC: &str = "iotedged_certificate_expiry_days";
+const CERTIFICATE_EXPIRY_HELP: &str = "Days until the certificate expires";
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+    Summary,
+}
+
+impl MetricKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+            MetricKind::Summary => "summary",
+        }
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Default)]
+struct Sample {
+    value: f64,
+    count: u64,
+    // The number of observations in each bucket of a histogram, not counting
+    // the ones in the buckets below.
+    bucket_counts: Vec<u64>,
+}
+
+struct Family {
+    help: &'static str,
+    kind: MetricKind,
+    buckets: &'static [f64],
+    samples: BTreeMap<Labels, Sample>,
+}
+
+#[derive(Default)]
+struct Registry {
+    families: BTreeMap<&'static str, Family>,
+    certificate_expirations: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl Registry {
+    fn sample(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        kind: MetricKind,
+        buckets: &'static [f64],
+        labels: &[(&str, &str)],
+    ) -> &mut Sample {
+        let family = self.families.entry(name).or_insert_with(|| Family {
+            help,
+            kind,
+            buckets,
+            samples: BTreeMap::new(),
+        });
+        let labels = labels
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+        let sample = family.samples.entry(labels).or_default();
+        if sample.bucket_counts.len() < family.buckets.len() {
+            sample.bucket_counts.resize(family.buckets.len(), 0);
+        }
+        sample
+    }
+}
+
+/// Metrics of the daemon, which are exposed in the Prometheus text format so
+/// that they can be scraped along with the metrics of the edge modules.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn inc_counter(&self, name: &'static str, help: &'static str, labels: &[(&str, &str)]) {
+        let mut registry = self.lock();
+        registry
+            .sample(name, help, MetricKind::Counter, &[], labels)
+            .value += 1.0;
+    }
+
+    pub fn set_gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        let mut registry = self.lock();
+        registry
+            .sample(name, help, MetricKind::Gauge, &[], labels)
+            .value = value;
+    }
+
+    /// Adds `delta`, which may be negative, to a gauge, e.g. to track how
+    /// many requests are being served.
+    pub fn add_gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        delta: f64,
+    ) {
+        let mut registry = self.lock();
+        registry
+            .sample(name, help, MetricKind::Gauge, &[], labels)
+            .value += delta;
+    }
+
+    /// Adds an observation, e.g. the duration of a request in seconds, to a
+    /// summary that tracks the count and the sum of the observations.
+    pub fn observe(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        let mut registry = self.lock();
+        let sample = registry.sample(name, help, MetricKind::Summary, &[], labels);
+        sample.value += value;
+        sample.count += 1;
+    }
+
+    /// Adds an observation to a histogram, which also counts the observations
+    /// that are at most each of the upper bounds in `buckets`. The buckets of
+    /// a histogram are the ones of its first observation.
+    pub fn observe_histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        buckets: &'static [f64],
+        value: f64,
+    ) {
+        let mut registry = self.lock();
+        let sample = registry.sample(name, help, MetricKind::Histogram, buckets, labels);
+        sample.value += value;
+        sample.count += 1;
+        // Observations above the last bucket are only in the implicit +Inf one.
+        if let Some(index) = buckets.iter().position(|bound| value <= *bound) {
+            if let Some(count) = sample.bucket_counts.get_mut(index) {
+                *count += 1;
+            }
+        }
+    }
+
+    /// The values of counter `name`, by the labels they were counted with.
+    pub fn counter_values(&self, name: &str) -> Vec<(Vec<(String, String)>, f64)> {
+        let registry = self.lock();
+        registry
+            .families
+            .get(name)
+            .filter(|family| family.kind == MetricKind::Counter)
+            .map(|family| {
+                family
+                    .samples
+                    .iter()
+                    .map(|(labels, sample)| (labels.clone(), sample.value))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Tracks the expiration of a certificate, which is reported as the number
+    /// of days left at the time the metrics are rendered.
+    pub fn set_certificate_expiration(&self, certificate: &str, expiration: DateTime<Utc>) {
+        let mut registry = self.lock();
+        registry
+            .certificate_expirations
+            .insert(certificate.to_string(), expiration);
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.render_at(Utc::now())
+    }
+
+    fn render_at(&self, now: DateTime<Utc>) -> String {
+        let registry = self.lock();
+        let mut output = String::new();
+
+        for (name, family) in &registry.families {
+            write_header(&mut output, name, family.help, family.kind);
+            for (labels, sample) in &family.samples {
+                match family.kind {
+                    MetricKind::Counter | MetricKind::Gauge => {
+                        write_sample(&mut output, name, labels, sample.value);
+                    }
+                    MetricKind::Histogram => {
+                        let bounds = family
+                            .buckets
+                            .iter()
+                            .map(ToString::to_string)
+                            .chain(std::iter::once("+Inf".to_string()));
+                        let counts = sample
+                            .bucket_counts
+                            .iter()
+                            .scan(0, |cumulative_count, count| {
+                                *cumulative_count += count;
+                                Some(*cumulative_count)
+                            })
+                            .chain(std::iter::once(sample.count));
+                        for (bound, count) in bounds.zip(counts) {
+                            let mut bucket_labels = labels.clone();
+                            bucket_labels.push(("le".to_string(), bound));
+                            #[allow(clippy::cast_precision_loss)]
+                            write_sample(
+                                &mut output,
+                                &format!("{}_bucket", name),
+                                &bucket_labels,
+                                count as f64,
+                            );
+                        }
+                        write_sum_and_count(&mut output, name, labels, sample);
+                    }
+                    MetricKind::Summary => {
+                        write_sum_and_count(&mut output, name, labels, sample);
+                    }
+                }
+            }
+        }
+
+        if !registry.certificate_expirations.is_empty() {
+            write_header(
+                &mut output,
+                CERTIFICATE_EXPIRY_METRIC,
+                CERTIFICATE_EXPIRY_HELP,
+                MetricKind::Gauge,
+            );
+            for (certificate, expiration) in &registry.certificate_expirations {
+                #[allow(clippy::cast_precision_loss)]
+                let days = (*expiration - now).num_seconds() as f64 / SECONDS_PER_DAY;
+                write_sample(
+                    &mut output,
+                    CERTIFICATE_EXPIRY_METRIC,
+                    &[("certificate".to_string(), certificate.clone())],
+                    days,
+                );
+            }
+        }
+
+        output
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry
+            .lock()
+            .expect("Failed to acquire the metrics lock")
+    }
+}
+
+fn write_header(output: &mut String, name: &str, help: &str, kind: MetricKind) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind.as_str());
+}
+
+fn write_sum_and_count(
+    output: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    sample: &Sample,
+) {
+    write_sample(output, &format!("{}_sum", name), labels, sample.value);
+    #[allow(clippy::cast_precision_loss)]
+    write_sample(
+        output,
+        &format!("{}_count", name),
+        labels,
+        sample.count as f64,
+    );
+}
+
+fn write_sample(output: &mut String, name: &str, labels: &[(String, String)], value: f64) {
+    output.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label_value(value)))
+            .collect();
+        let _ = write!(output, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(output, " {}", value);
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_gauges() {
+        let metrics = Metrics::new();
+        metrics.inc_counter("test_total", "A test counter", &[]);
+        metrics.inc_counter("test_total", "A test counter", &[]);
+        metrics.set_gauge("test_state", "A test gauge", &[("state", "on")], 1.0);
+
+        assert_eq!(
+            "# HELP test_state A test gauge\n\
+             # TYPE test_state gauge\n\
+             test_state{state=\"on\"} 1\n\
+             # HELP test_total A test counter\n\
+             # TYPE test_total counter\n\
+             test_total 2\n",
+            metrics.render()
+        );
+    }
+
+    #[test]
+    fn gauges_go_up_and_down() {
+        let metrics = Metrics::new();
+        metrics.add_gauge("test_in_flight", "A test gauge", &[], 1.0);
+        metrics.add_gauge("test_in_flight", "A test gauge", &[], 1.0);
+        metrics.add_gauge("test_in_flight", "A test gauge", &[], -1.0);
+
+        assert!(metrics.render().contains("test_in_flight 1\n"));
+    }
+
+    #[test]
+    fn renders_summaries_as_sum_and_count() {
+        let metrics = Metrics::new();
+        let labels = [("route", "/modules"), ("status", "200")];
+        metrics.observe("test_seconds", "A test summary", &labels, 0.5);
+        metrics.observe("test_seconds", "A test summary", &labels, 0.25);
+
+        assert_eq!(
+            "# HELP test_seconds A test summary\n\
+             # TYPE test_seconds summary\n\
+             test_seconds_sum{route=\"/modules\",status=\"200\"} 0.75\n\
+             test_seconds_count{route=\"/modules\",status=\"200\"} 2\n",
+            metrics.render()
+        );
+    }
+
+    #[test]
+    fn renders_histograms_as_cumulative_buckets() {
+        let metrics = Metrics::new();
+        let labels = [("operation", "sign")];
+        let buckets = &[0.01, 0.1, 1.0];
+        metrics.observe_histogram("test_seconds", "A test histogram", &labels, buckets, 0.005);
+        metrics.observe_histogram("test_seconds", "A test histogram", &labels, buckets, 0.05);
+        metrics.observe_histogram("test_seconds", "A test histogram", &labels, buckets, 0.075);
+        metrics.observe_histogram("test_seconds", "A test histogram", &labels, buckets, 2.0);
+
+        assert_eq!(
+            "# HELP test_seconds A test histogram\n\
+             # TYPE test_seconds histogram\n\
+             test_seconds_bucket{operation=\"sign\",le=\"0.01\"} 1\n\
+             test_seconds_bucket{operation=\"sign\",le=\"0.1\"} 3\n\
+             test_seconds_bucket{operation=\"sign\",le=\"1\"} 3\n\
+             test_seconds_bucket{operation=\"sign\",le=\"+Inf\"} 4\n\
+             test_seconds_sum{operation=\"sign\"} 2.13\n\
+             test_seconds_count{operation=\"sign\"} 4\n",
+            metrics.render()
+        );
+    }
+
+    #[test]
+    fn renders_days_until_certificate_expiry() {
+        let metrics = Metrics::new();
+        let now = Utc::now();
+        metrics.set_certificate_expiration("device_ca", now + Duration::hours(36));
+
+        assert_eq!(
+            "# HELP iotedged_certificate_expiry_days Days until the certificate expires\n\
+             # TYPE iotedged_certificate_expiry_days gauge\n\
+             iotedged_certificate_expiry_days{certificate=\"device_ca\"} 1.5\n",
+            metrics.render_at(now)
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let metrics = Metrics::new();
+        metrics.inc_counter("test_total", "A test counter", &[("name", "a\"b\\c")]);
+
+        assert!(metrics
+            .render()
+            .contains("test_total{name=\"a\\\"b\\\\c\"} 1\n"));
+    }
+}
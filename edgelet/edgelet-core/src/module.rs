@@ -52,11 +52,13 @@ impl fmt::Display for ModuleStatus {
 pub struct ModuleRuntimeState {
     status: ModuleStatus,
     exit_code: Option<i64>,
+    exit_reason: Option<String>,
     status_description: Option<String>,
     started_at: Option<DateTime<Utc>>,
     finished_at: Option<DateTime<Utc>>,
     image_id: Option<String>,
     pid: Option<i32>,
+    restart_count: Option<i32>,
 }
 
 impl Default for ModuleRuntimeState {
@@ -64,11 +66,13 @@ impl Default for ModuleRuntimeState {
         ModuleRuntimeState {
             status: ModuleStatus::Unknown,
             exit_code: None,
+            exit_reason: None,
             status_description: None,
             started_at: None,
             finished_at: None,
             image_id: None,
             pid: None,
+            restart_count: None,
         }
     }
 }
@@ -92,6 +96,17 @@ impl ModuleRuntimeState {
         self
     }
 
+    /// Why the module exited, when there is more to it than the exit code,
+    /// e.g. that it was killed for running out of memory.
+    pub fn exit_reason(&self) -> Option<&str> {
+        self.exit_reason.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_exit_reason(mut self, exit_reason: Option<String>) -> Self {
+        self.exit_reason = exit_reason;
+        self
+    }
+
     pub fn status_description(&self) -> Option<&str> {
         self.status_description.as_ref().map(AsRef::as_ref)
     }
@@ -136,6 +151,17 @@ impl ModuleRuntimeState {
         self.pid = pid;
         self
     }
+
+    /// The number of times the container runtime has restarted the module
+    /// on its own since it was last started.
+    pub fn restart_count(&self) -> Option<i32> {
+        self.restart_count
+    }
+
+    pub fn with_restart_count(mut self, restart_count: Option<i32>) -> Self {
+        self.restart_count = restart_count;
+        self
+    }
 }
 
 #[derive(serde_derive::Deserialize, Debug, serde_derive::Serialize)]
@@ -150,6 +176,21 @@ pub struct ModuleSpec<T> {
     #[serde(default)]
     #[serde(rename = "imagePullPolicy")]
     image_pull_policy: ImagePullPolicy,
+    #[serde(default)]
+    #[serde(rename = "restartPolicy")]
+    restart_policy: RestartPolicy,
+    #[serde(rename = "restartMaxRetries")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart_max_retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<u32>,
+    #[serde(default)]
+    #[serde(rename = "waitForHealthy")]
+    wait_for_healthy: bool,
+    #[serde(default)]
+    #[serde(rename = "dependsOn")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<String>,
 }
 
 impl<T> Clone for ModuleSpec<T>
@@ -163,6 +204,11 @@ where
             config: self.config.clone(),
             env: self.env.clone(),
             image_pull_policy: self.image_pull_policy,
+            restart_policy: self.restart_policy,
+            restart_max_retries: self.restart_max_retries,
+            priority: self.priority,
+            wait_for_healthy: self.wait_for_healthy,
+            depends_on: self.depends_on.clone(),
         }
     }
 }
@@ -184,6 +230,11 @@ impl<T> ModuleSpec<T> {
             config,
             env,
             image_pull_policy,
+            restart_policy: RestartPolicy::default(),
+            restart_max_retries: None,
+            priority: None,
+            wait_for_healthy: false,
+            depends_on: Vec::new(),
         })
     }
 
@@ -243,6 +294,61 @@ impl<T> ModuleSpec<T> {
         self.image_pull_policy = image_pull_policy;
         self
     }
+
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// The number of times the container runtime may restart a failed module
+    /// before giving up. `None` means the runtime retries indefinitely.
+    pub fn restart_max_retries(&self) -> Option<u32> {
+        self.restart_max_retries
+    }
+
+    pub fn with_restart_max_retries(mut self, restart_max_retries: Option<u32>) -> Self {
+        self.restart_max_retries = restart_max_retries;
+        self
+    }
+
+    /// The order in which the daemon starts the module when the device boots.
+    /// Modules with a lower priority start first, like they do when the edge
+    /// agent applies a deployment. `None` leaves starting the module to the
+    /// edge agent.
+    pub fn priority(&self) -> Option<u32> {
+        self.priority
+    }
+
+    pub fn with_priority(mut self, priority: Option<u32>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Whether the modules with a higher priority value wait for this module
+    /// to be healthy before they start.
+    pub fn wait_for_healthy(&self) -> bool {
+        self.wait_for_healthy
+    }
+
+    pub fn with_wait_for_healthy(mut self, wait_for_healthy: bool) -> Self {
+        self.wait_for_healthy = wait_for_healthy;
+        self
+    }
+
+    /// The modules this module depends on, e.g. edgeHub. The daemon stops the
+    /// module before them, and starts it after them when the device boots.
+    pub fn depends_on(&self) -> &[String] {
+        &self.depends_on
+    }
+
+    pub fn with_depends_on(mut self, depends_on: Vec<String>) -> Self {
+        self.depends_on = depends_on;
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -287,6 +393,8 @@ pub struct LogOptions {
     follow: bool,
     tail: LogTail,
     since: i32,
+    until: i32,
+    timestamps: bool,
 }
 
 impl LogOptions {
@@ -295,6 +403,8 @@ impl LogOptions {
             follow: false,
             tail: LogTail::All,
             since: 0,
+            until: 0,
+            timestamps: false,
         }
     }
 
@@ -313,6 +423,19 @@ impl LogOptions {
         self
     }
 
+    /// Only returns the logs up to this UNIX timestamp, or all of them if it
+    /// is 0.
+    pub fn with_until(mut self, until: i32) -> Self {
+        self.until = until;
+        self
+    }
+
+    /// Prefixes every line of the logs with the time it was logged at.
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
     pub fn follow(&self) -> bool {
         self.follow
     }
@@ -324,6 +447,14 @@ impl LogOptions {
     pub fn since(&self) -> i32 {
         self.since
     }
+
+    pub fn until(&self) -> i32 {
+        self.until
+    }
+
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
 }
 
 pub trait Module {
@@ -355,6 +486,8 @@ pub struct SystemInfo {
     architecture: String,
     /// iotedge version string
     version: &'static str,
+    /// Problems with the host that the runtime noticed, e.g. that it is running out of disk space
+    warnings: Vec<String>,
 }
 
 impl SystemInfo {
@@ -363,9 +496,15 @@ impl SystemInfo {
             os_type,
             architecture,
             version: super::version_with_source_version(),
+            warnings: vec![],
         }
     }
 
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
     pub fn os_type(&self) -> &str {
         &self.os_type
     }
@@ -377,6 +516,10 @@ impl SystemInfo {
     pub fn version(&self) -> &str {
         self.version
     }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
 }
 
 #[derive(Debug, serde_derive::Serialize)]
@@ -461,6 +604,154 @@ impl ModuleTop {
     }
 }
 
+/// A change of the state of a module that the runtime noticed as it
+/// happened, e.g. the module's process exiting on its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleEvent {
+    name: String,
+    status: ModuleStatus,
+}
+
+impl ModuleEvent {
+    pub fn new(name: String, status: ModuleStatus) -> Self {
+        ModuleEvent { name, status }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> &ModuleStatus {
+        &self.status
+    }
+}
+
+/// The processes running inside a module, as a table like the one `ps` prints.
+#[derive(Debug, Default, serde_derive::Serialize)]
+pub struct ModuleProcesses {
+    /// Column titles. Example: `["PID", "CMD"]`
+    titles: Vec<String>,
+    /// One row per process, with a value for each title.
+    processes: Vec<Vec<String>>,
+}
+
+impl ModuleProcesses {
+    pub fn new(titles: Vec<String>, processes: Vec<Vec<String>>) -> Self {
+        ModuleProcesses { titles, processes }
+    }
+
+    pub fn titles(&self) -> &[String] {
+        &self.titles
+    }
+
+    pub fn processes(&self) -> &[Vec<String>] {
+        &self.processes
+    }
+}
+
+/// An image that the runtime has stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageInfo {
+    id: String,
+    tags: Vec<String>,
+    size: u64,
+    in_use: bool,
+}
+
+impl ImageInfo {
+    pub fn new(id: String, tags: Vec<String>, size: u64) -> Self {
+        ImageInfo {
+            id,
+            tags,
+            size,
+            in_use: false,
+        }
+    }
+
+    /// Marks the image as the image of a current module.
+    pub fn with_in_use(mut self, in_use: bool) -> Self {
+        self.in_use = in_use;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Size of the image in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether a current module was created from the image.
+    pub fn in_use(&self) -> bool {
+        self.in_use
+    }
+}
+
+/// The images that pruning removed, and how much disk space that freed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PrunedImages {
+    images: Vec<String>,
+    space_reclaimed: u64,
+}
+
+impl PrunedImages {
+    pub fn new(images: Vec<String>, space_reclaimed: u64) -> Self {
+        PrunedImages {
+            images,
+            space_reclaimed,
+        }
+    }
+
+    /// IDs of the removed images.
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    /// Freed disk space in bytes.
+    pub fn space_reclaimed(&self) -> u64 {
+        self.space_reclaimed
+    }
+}
+
+/// The outcome of a command that was run inside a module.
+#[derive(Debug, Default)]
+pub struct ModuleExecResult {
+    /// Exit code of the command.
+    exit_code: i64,
+    /// Everything the command wrote to its standard output.
+    stdout: Vec<u8>,
+    /// Everything the command wrote to its standard error.
+    stderr: Vec<u8>,
+}
+
+impl ModuleExecResult {
+    pub fn new(exit_code: i64, stdout: Vec<u8>, stderr: Vec<u8>) -> Self {
+        ModuleExecResult {
+            exit_code,
+            stdout,
+            stderr,
+        }
+    }
+
+    pub fn exit_code(&self) -> i64 {
+        self.exit_code
+    }
+
+    pub fn stdout(&self) -> &[u8] {
+        &self.stdout
+    }
+
+    pub fn stderr(&self) -> &[u8] {
+        &self.stderr
+    }
+}
+
 pub trait ProvisioningResult {
     fn device_id(&self) -> &str;
     fn hub_name(&self) -> &str;
@@ -497,12 +788,23 @@ pub trait ModuleRuntime: Sized {
         + Send;
     type LogsFuture: Future<Item = Self::Logs, Error = Self::Error> + Send;
     type RemoveFuture: Future<Item = (), Error = Self::Error> + Send;
+    type RenameFuture: Future<Item = (), Error = Self::Error> + Send;
     type RestartFuture: Future<Item = (), Error = Self::Error> + Send;
     type StartFuture: Future<Item = (), Error = Self::Error> + Send;
     type StopFuture: Future<Item = (), Error = Self::Error> + Send;
     type SystemInfoFuture: Future<Item = SystemInfo, Error = Self::Error> + Send;
     type SystemResourcesFuture: Future<Item = SystemResources, Error = Self::Error> + Send;
     type RemoveAllFuture: Future<Item = (), Error = Self::Error> + Send;
+    type ExecFuture: Future<Item = ModuleExecResult, Error = Self::Error> + Send;
+    type CopyToFuture: Future<Item = (), Error = Self::Error> + Send;
+    type CopyFromFuture: Future<Item = Vec<u8>, Error = Self::Error> + Send;
+    type ExportImageFuture: Future<Item = Vec<u8>, Error = Self::Error> + Send;
+    type ImportImagesFuture: Future<Item = (), Error = Self::Error> + Send;
+    type ListImagesFuture: Future<Item = Vec<ImageInfo>, Error = Self::Error> + Send;
+    type PruneImagesFuture: Future<Item = PrunedImages, Error = Self::Error> + Send;
+    type TopFuture: Future<Item = ModuleProcesses, Error = Self::Error> + Send;
+    type ValidateFuture: Future<Item = (), Error = Self::Error> + Send;
+    type EventStream: Stream<Item = ModuleEvent, Error = Self::Error> + Send;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture;
     fn get(&self, id: &str) -> Self::GetFuture;
@@ -510,6 +812,10 @@ pub trait ModuleRuntime: Sized {
     fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture;
     fn restart(&self, id: &str) -> Self::RestartFuture;
     fn remove(&self, id: &str) -> Self::RemoveFuture;
+
+    /// Renames module `id` to `new_id`, without stopping it.
+    fn rename(&self, id: &str, new_id: &str) -> Self::RenameFuture;
+
     fn system_info(&self) -> Self::SystemInfoFuture;
     fn system_resources(&self) -> Self::SystemResourcesFuture;
     fn list(&self) -> Self::ListFuture;
@@ -517,6 +823,43 @@ pub trait ModuleRuntime: Sized {
     fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture;
     fn registry(&self) -> &Self::ModuleRegistry;
     fn remove_all(&self) -> Self::RemoveAllFuture;
+
+    /// Runs `command` inside the running module `id` and waits for it to exit.
+    fn exec(&self, id: &str, command: &[String]) -> Self::ExecFuture;
+
+    /// Extracts the tar `archive` into the directory `path` of module `id`.
+    fn copy_to(&self, id: &str, path: &str, archive: Vec<u8>) -> Self::CopyToFuture;
+
+    /// Returns a tar archive of the file or directory `path` of module `id`.
+    fn copy_from(&self, id: &str, path: &str) -> Self::CopyFromFuture;
+
+    /// Returns an archive of the image of module `id`, tags included, that
+    /// `import_images` loads on a device that can't reach a registry.
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture;
+
+    /// Loads the images of `archive`, as written by `export_image`.
+    fn import_images(&self, archive: Vec<u8>) -> Self::ImportImagesFuture;
+
+    /// Lists the images the runtime has stored, and which of them current
+    /// modules were created from.
+    fn list_images(&self) -> Self::ListImagesFuture;
+
+    /// Removes the images that no container was created from, to free disk
+    /// space after modules were updated to newer images.
+    fn prune_images(&self) -> Self::PruneImagesFuture;
+
+    /// Lists the processes running inside module `id`.
+    fn top(&self, id: &str) -> Self::TopFuture;
+
+    /// Checks that `module` could be created, without creating anything, so
+    /// that a bad deployment can be rejected before running modules are
+    /// torn down.
+    fn validate(&self, module: ModuleSpec<Self::Config>) -> Self::ValidateFuture;
+
+    /// Reports the changes of module states as they happen. Runtimes that
+    /// can't tell return a stream that ends right away, and their modules
+    /// are only polled.
+    fn events(&self) -> Self::EventStream;
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -542,6 +885,8 @@ impl fmt::Display for ModuleOperation {
 // Useful for error contexts
 #[derive(Clone, Debug)]
 pub enum RegistryOperation {
+    ListImages,
+    LoadImage(String),
     PullImage(String),
     RemoveImage(String),
 }
@@ -549,6 +894,10 @@ pub enum RegistryOperation {
 impl fmt::Display for RegistryOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            RegistryOperation::ListImages => write!(f, "Could not list images"),
+            RegistryOperation::LoadImage(name) => {
+                write!(f, "Could not load image {} from its archive", name)
+            }
             RegistryOperation::PullImage(name) => write!(f, "Could not pull image {}", name),
             RegistryOperation::RemoveImage(name) => write!(f, "Could not remove image {}", name),
         }
@@ -558,37 +907,67 @@ impl fmt::Display for RegistryOperation {
 // Useful for error contexts
 #[derive(Clone, Debug, PartialEq)]
 pub enum RuntimeOperation {
+    CopyFromModule(String),
+    CopyToModule(String),
     CreateModule(String),
+    ExecModule(String),
+    ExportModuleImage(String),
     GetModule(String),
     GetModuleLogs(String),
+    ImportImages,
     Init,
+    ListImages,
     ListModules,
+    ModuleEvents,
+    PruneImages,
     RemoveModule(String),
+    RenameModule(String),
     RestartModule(String),
     StartModule(String),
     StopModule(String),
     SystemInfo,
     SystemResources,
     TopModule(String),
+    ValidateModule(String),
 }
 
 impl fmt::Display for RuntimeOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            RuntimeOperation::CopyFromModule(name) => {
+                write!(f, "Could not copy files from module {}", name)
+            }
+            RuntimeOperation::CopyToModule(name) => {
+                write!(f, "Could not copy files to module {}", name)
+            }
             RuntimeOperation::CreateModule(name) => write!(f, "Could not create module {}", name),
+            RuntimeOperation::ExecModule(name) => {
+                write!(f, "Could not run command in module {}", name)
+            }
+            RuntimeOperation::ExportModuleImage(name) => {
+                write!(f, "Could not export the image of module {}", name)
+            }
             RuntimeOperation::GetModule(name) => write!(f, "Could not get module {}", name),
             RuntimeOperation::GetModuleLogs(name) => {
                 write!(f, "Could not get logs for module {}", name)
             }
+            RuntimeOperation::ImportImages => write!(f, "Could not import images"),
             RuntimeOperation::Init => write!(f, "Could not initialize module runtime"),
+            RuntimeOperation::ListImages => write!(f, "Could not list images"),
             RuntimeOperation::ListModules => write!(f, "Could not list modules"),
+            RuntimeOperation::ModuleEvents => write!(f, "Could not watch module events"),
+            RuntimeOperation::PruneImages => write!(f, "Could not prune images"),
             RuntimeOperation::RemoveModule(name) => write!(f, "Could not remove module {}", name),
+            RuntimeOperation::RenameModule(name) => write!(f, "Could not rename module {}", name),
             RuntimeOperation::RestartModule(name) => write!(f, "Could not restart module {}", name),
             RuntimeOperation::StartModule(name) => write!(f, "Could not start module {}", name),
             RuntimeOperation::StopModule(name) => write!(f, "Could not stop module {}", name),
             RuntimeOperation::SystemInfo => write!(f, "Could not query system info"),
             RuntimeOperation::SystemResources => write!(f, "Could not query system resources"),
             RuntimeOperation::TopModule(name) => write!(f, "Could not top module {}", name),
+            RuntimeOperation::ValidateModule(name) => {
+                write!(f, "Could not validate module {}", name)
+            }
         }
     }
 }
@@ -621,6 +1000,39 @@ impl FromStr for ImagePullPolicy {
     }
 }
 
+/// Describes when a module should be restarted after it exits.
+///
+/// `Never` leaves restarts to edgeAgent and the watchdog. The other policies
+/// are handed to the container runtime, which restarts the module itself.
+#[derive(Clone, Copy, Debug, serde_derive::Deserialize, PartialEq, serde_derive::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    Never,
+    OnFailure,
+    OnUnhealthy,
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::Never
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> StdResult<RestartPolicy, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(RestartPolicy::Never),
+            "on-failure" => Ok(RestartPolicy::OnFailure),
+            "on-unhealthy" => Ok(RestartPolicy::OnUnhealthy),
+            "always" => Ok(RestartPolicy::Always),
+            _ => Err(Error::from(ErrorKind::InvalidRestartPolicy(s.to_string()))),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -761,4 +1173,34 @@ mod tests {
             current_value_architecture_type
         );
     }
+
+    #[test]
+    fn restart_policy_from_str() {
+        assert_eq!(RestartPolicy::Never, "never".parse().unwrap());
+        assert_eq!(RestartPolicy::OnFailure, "On-Failure".parse().unwrap());
+        assert_eq!(RestartPolicy::OnUnhealthy, "on-unhealthy".parse().unwrap());
+        assert_eq!(RestartPolicy::Always, "always".parse().unwrap());
+
+        let err = "sometimes".parse::<RestartPolicy>().unwrap_err();
+        if let ErrorKind::InvalidRestartPolicy(s) = err.kind() {
+            assert_eq!(s, "sometimes");
+        } else {
+            panic!("Expected `InvalidRestartPolicy` but got {:?}", err);
+        }
+    }
+
+    #[test]
+    fn module_spec_restart_policy_defaults_to_never() {
+        let spec: ModuleSpec<i32> =
+            serde_json::from_str(r#"{ "name": "m", "type": "docker", "config": 10 }"#).unwrap();
+        assert_eq!(RestartPolicy::Never, spec.restart_policy());
+        assert_eq!(None, spec.restart_max_retries());
+
+        let spec: ModuleSpec<i32> = serde_json::from_str(
+            r#"{ "name": "m", "type": "docker", "config": 10, "restartPolicy": "on-failure", "restartMaxRetries": 5 }"#,
+        )
+        .unwrap();
+        assert_eq!(RestartPolicy::OnFailure, spec.restart_policy());
+        assert_eq!(Some(5), spec.restart_max_retries());
+    }
 }
@@ -0,0 +1,270 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+
+/// How many finished operations are kept for their clients to look up. The
+/// oldest ones are forgotten first.
+const MAX_FINISHED_OPERATIONS: usize = 100;
+
+/// The actions of the management API that can take minutes, e.g. because they
+/// pull a large image over a slow connection first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperationKind {
+    CreateModule,
+}
+
+impl fmt::Display for OperationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationKind::CreateModule => write!(f, "create_module"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OperationStatus {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl fmt::Display for OperationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationStatus::Running => write!(f, "running"),
+            OperationStatus::Succeeded => write!(f, "succeeded"),
+            OperationStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// How far an operation got, as its clients see it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperationProgress {
+    id: String,
+    kind: OperationKind,
+    target: String,
+    status: OperationStatus,
+    percent: u8,
+    message: Option<String>,
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+}
+
+impl OperationProgress {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn kind(&self) -> OperationKind {
+        self.kind
+    }
+
+    /// The module that the operation acts on.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn status(&self) -> OperationStatus {
+        self.status
+    }
+
+    /// An estimate of how much of the operation is done, from 0 to 100.
+    pub fn percent(&self) -> u8 {
+        self.percent
+    }
+
+    /// What the operation is doing while it runs, or why it failed.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.finished_at
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    next_id: u64,
+    operations: BTreeMap<String, OperationProgress>,
+    finished: VecDeque<String>,
+}
+
+/// Tracks the long-running operations of the management API, so that their
+/// clients can start them, hang up, and come back for how far they got
+/// instead of holding a connection open for minutes.
+///
+/// Operations are only tracked in memory, and are forgotten when the daemon
+/// stops.
+#[derive(Clone, Debug)]
+pub struct OperationTracker {
+    // The IDs of operations start with the time the tracker was created, so
+    // that an ID from before the daemon restarted doesn't find a new operation.
+    prefix: String,
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl OperationTracker {
+    pub fn new() -> Self {
+        OperationTracker {
+            prefix: format!("{:x}", Utc::now().timestamp_millis()),
+            state: Arc::new(Mutex::new(TrackerState::default())),
+        }
+    }
+
+    /// Starts tracking an operation of `kind` on `target`. The operation is
+    /// updated through the returned reporter.
+    pub fn start(&self, kind: OperationKind, target: &str) -> OperationReporter {
+        let mut state = self.lock();
+        let id = format!("{}-{}", self.prefix, state.next_id);
+        state.next_id += 1;
+        state.operations.insert(
+            id.clone(),
+            OperationProgress {
+                id: id.clone(),
+                kind,
+                target: target.to_string(),
+                status: OperationStatus::Running,
+                percent: 0,
+                message: None,
+                started_at: Utc::now(),
+                finished_at: None,
+            },
+        );
+
+        OperationReporter {
+            id,
+            tracker: self.clone(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<OperationProgress> {
+        self.lock().operations.get(id).cloned()
+    }
+
+    fn update<F>(&self, id: &str, f: F)
+    where
+        F: FnOnce(&mut OperationProgress),
+    {
+        let mut state = self.lock();
+        let finished = match state.operations.get_mut(id) {
+            Some(operation) if operation.status == OperationStatus::Running => {
+                f(operation);
+                operation.status != OperationStatus::Running
+            }
+            _ => false,
+        };
+
+        if finished {
+            state.finished.push_back(id.to_string());
+            while state.finished.len() > MAX_FINISHED_OPERATIONS {
+                if let Some(oldest) = state.finished.pop_front() {
+                    state.operations.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, TrackerState> {
+        self.state.lock().expect("operation tracker lock poisoned")
+    }
+}
+
+impl Default for OperationTracker {
+    fn default() -> Self {
+        OperationTracker::new()
+    }
+}
+
+/// Reports the progress of one operation to its tracker. An operation that
+/// finished can't be updated anymore.
+#[derive(Clone, Debug)]
+pub struct OperationReporter {
+    id: String,
+    tracker: OperationTracker,
+}
+
+impl OperationReporter {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn progress(&self, percent: u8, message: &str) {
+        self.tracker.update(&self.id, |operation| {
+            operation.percent = percent.min(100);
+            operation.message = Some(message.to_string());
+        });
+    }
+
+    pub fn succeed(&self) {
+        self.tracker.update(&self.id, |operation| {
+            operation.status = OperationStatus::Succeeded;
+            operation.percent = 100;
+            operation.message = None;
+            operation.finished_at = Some(Utc::now());
+        });
+    }
+
+    pub fn fail(&self, message: String) {
+        self.tracker.update(&self.id, |operation| {
+            operation.status = OperationStatus::Failed;
+            operation.message = Some(message);
+            operation.finished_at = Some(Utc::now());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations_report_progress_until_they_finish() {
+        let tracker = OperationTracker::new();
+        let reporter = tracker.start(OperationKind::CreateModule, "tempSensor");
+
+        let operation = tracker.get(reporter.id()).unwrap();
+        assert_eq!(OperationStatus::Running, operation.status());
+        assert_eq!("tempSensor", operation.target());
+        assert_eq!(0, operation.percent());
+
+        reporter.progress(50, "Creating module");
+        let operation = tracker.get(reporter.id()).unwrap();
+        assert_eq!(50, operation.percent());
+        assert_eq!(Some("Creating module"), operation.message());
+
+        reporter.fail("Image not found".to_string());
+        reporter.succeed();
+        let operation = tracker.get(reporter.id()).unwrap();
+        assert_eq!(OperationStatus::Failed, operation.status());
+        assert_eq!(Some("Image not found"), operation.message());
+        assert!(operation.finished_at().is_some());
+
+        assert!(tracker.get("unknown").is_none());
+    }
+
+    #[test]
+    fn oldest_finished_operations_are_forgotten() {
+        let tracker = OperationTracker::new();
+        let running = tracker.start(OperationKind::CreateModule, "running");
+        let reporters: Vec<_> = (0..=MAX_FINISHED_OPERATIONS)
+            .map(|i| tracker.start(OperationKind::CreateModule, &i.to_string()))
+            .collect();
+        for reporter in &reporters {
+            reporter.succeed();
+        }
+
+        assert!(tracker.get(reporters[0].id()).is_none());
+        assert!(tracker.get(reporters[1].id()).is_some());
+        assert!(tracker.get(running.id()).is_some());
+    }
+}
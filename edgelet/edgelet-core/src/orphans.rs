@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashSet;
+
+use failure::Fail;
+use futures::{stream, Future, Stream};
+use log::{info, warn};
+
+use crate::error::{Error, ErrorKind};
+use crate::identity::{Identity, IdentityManager};
+use crate::journal::{Operation, OperationJournal};
+use crate::module::{Module, ModuleRuntime, ModuleRuntimeErrorReason};
+
+/// Removes the modules that no module identity of the device corresponds to
+/// anymore, like those of a deployment that failed halfway or of a module
+/// that was renamed, and returns their names.
+///
+/// The edge runtime module, and the modules of operations that are still in
+/// progress, are always kept. A module that can't be removed is logged and
+/// left for the next time.
+pub fn remove_orphaned_modules<M, I>(
+    runtime: &M,
+    identity_manager: &I,
+    journal: &OperationJournal,
+    agent: &str,
+) -> impl Future<Item = Vec<String>, Error = Error> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+    for<'r> &'r M::Error: Into<ModuleRuntimeErrorReason>,
+    I: 'static + IdentityManager + Clone + Send,
+{
+    let runtime = runtime.clone();
+    let identity_manager = identity_manager.clone();
+    let journal = journal.clone();
+    let agent = agent.to_string();
+
+    // The modules are listed before the identities, since the edge agent
+    // creates the identity of a module before the module itself. A module
+    // that is created in between is then already known by its identity.
+    runtime
+        .list()
+        .map_err(|err| Error::from(err.context(ErrorKind::ModuleRuntime)))
+        .and_then(move |modules| {
+            identity_manager
+                .list()
+                .map_err(|err| Error::from(err.context(ErrorKind::IdentityManager)))
+                .map(move |identities| {
+                    let modules: Vec<String> = modules
+                        .iter()
+                        .map(|module| module.name().to_string())
+                        .collect();
+                    let module_ids: Vec<&str> =
+                        identities.iter().map(Identity::module_id).collect();
+                    orphans(&modules, &module_ids, &journal.pending(), &agent)
+                        .into_iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                })
+        })
+        .and_then(move |orphans| {
+            stream::iter_ok(orphans)
+                .and_then(move |name| {
+                    info!("Removing module {}, which has no identity anymore", name);
+                    runtime
+                        .remove(&name)
+                        .then(move |result| -> Result<_, Error> {
+                            match result {
+                                Ok(()) => Ok(Some(name)),
+                                Err(err) => {
+                                    if let ModuleRuntimeErrorReason::Other = (&err).into() {
+                                        warn!("Could not remove orphaned module {}: {}", name, err);
+                                    }
+                                    Ok(None)
+                                }
+                            }
+                        })
+                })
+                .filter_map(|name| name)
+                .collect()
+        })
+}
+
+/// The modules that neither the identities, the edge runtime module nor the
+/// pending operations account for. Module names are the ids of their
+/// identities without the `$` of the system modules.
+fn orphans<'a>(
+    modules: &'a [String],
+    module_ids: &[&str],
+    pending: &[Operation],
+    agent: &str,
+) -> Vec<&'a str> {
+    let mut known: HashSet<&str> = module_ids
+        .iter()
+        .map(|module_id| module_id.trim_start_matches('$'))
+        .collect();
+    known.insert(agent);
+    for operation in pending {
+        match operation {
+            Operation::UpdateModule {
+                name,
+                previous: Some(previous),
+            } => {
+                known.insert(name);
+                known.insert(previous);
+            }
+            Operation::CreateModule { name }
+            | Operation::UpdateModule { name, .. }
+            | Operation::RemoveModule { name }
+            | Operation::CreateIdentity { name }
+            | Operation::DeleteIdentity { name } => {
+                known.insert(name);
+            }
+        }
+    }
+
+    modules
+        .iter()
+        .map(String::as_str)
+        .filter(|module| !known.contains(module))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modules_without_identity_are_orphans() {
+        let modules: Vec<String> = [
+            "edgeAgent",
+            "edgeHub",
+            "tempSensor",
+            "oldName",
+            "m2_previous",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        let pending = vec![Operation::UpdateModule {
+            name: "m2".to_string(),
+            previous: Some("m2_previous".to_string()),
+        }];
+
+        assert_eq!(
+            vec!["oldName"],
+            orphans(&modules, &["$edgeHub", "tempSensor"], &pending, "edgeAgent")
+        );
+        assert_eq!(
+            vec!["edgeHub", "tempSensor", "oldName", "m2_previous"],
+            orphans(&modules, &["$edgeAgent"], &[], "edgeAgent")
+        );
+    }
+}
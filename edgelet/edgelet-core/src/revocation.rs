@@ -0,0 +1,247 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, ErrorKind};
+
+const PEM_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &str = "-----END CERTIFICATE-----";
+
+/// A certificate that the workload CA issued to a module.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct IssuedCertificate {
+    module_id: String,
+    thumbprint: String,
+    expiration: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl IssuedCertificate {
+    pub fn module_id(&self) -> &str {
+        &self.module_id
+    }
+
+    /// The hex encoded SHA-256 digest of the DER encoding of the certificate.
+    pub fn thumbprint(&self) -> &str {
+        &self.thumbprint
+    }
+
+    pub fn expiration(&self) -> DateTime<Utc> {
+        self.expiration
+    }
+
+    pub fn revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at
+    }
+}
+
+/// Keeps track of the certificates that the workload CA issued to modules, so
+/// that the certificates of a module can be revoked. Certificates are dropped
+/// from the list once they expire, since they are rejected anyway by then.
+///
+/// When the list is backed by a file, every change is written to it so that
+/// revocations survive restarts of the daemon.
+#[derive(Clone, Debug, Default)]
+pub struct CertificateRevocationList {
+    certificates: Arc<Mutex<Vec<IssuedCertificate>>>,
+    path: Option<PathBuf>,
+}
+
+impl CertificateRevocationList {
+    pub fn new() -> Self {
+        CertificateRevocationList::default()
+    }
+
+    /// Loads the list from `path`, or starts an empty one if the file doesn't
+    /// exist yet.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let certificates = if path.exists() {
+            let contents = fs::read(path).context(ErrorKind::CertificateRevocationList)?;
+            serde_json::from_slice(&contents).context(ErrorKind::CertificateRevocationList)?
+        } else {
+            vec![]
+        };
+
+        Ok(CertificateRevocationList {
+            certificates: Arc::new(Mutex::new(certificates)),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Records a certificate that was issued to `module_id`.
+    pub fn record(
+        &self,
+        module_id: &str,
+        pem: &[u8],
+        expiration: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let thumbprint = thumbprint(pem)?;
+        let now = Utc::now();
+
+        let mut certificates = self
+            .certificates
+            .lock()
+            .expect("Failed to acquire the revocation list lock");
+        certificates.retain(|cert| cert.expiration > now);
+        certificates.push(IssuedCertificate {
+            module_id: module_id.to_string(),
+            thumbprint,
+            expiration,
+            revoked_at: None,
+        });
+        self.save(&certificates)
+    }
+
+    /// Revokes all the certificates issued to `module_id`, and returns how
+    /// many were revoked.
+    pub fn revoke_module(&self, module_id: &str) -> Result<usize, Error> {
+        let now = Utc::now();
+
+        let mut certificates = self
+            .certificates
+            .lock()
+            .expect("Failed to acquire the revocation list lock");
+        certificates.retain(|cert| cert.expiration > now);
+
+        let mut count = 0;
+        for cert in certificates
+            .iter_mut()
+            .filter(|cert| cert.module_id == module_id && cert.revoked_at.is_none())
+        {
+            cert.revoked_at = Some(now);
+            count += 1;
+        }
+
+        if count > 0 {
+            self.save(&certificates)?;
+        }
+        Ok(count)
+    }
+
+    /// Returns the revoked certificates that haven't expired yet.
+    pub fn revoked(&self) -> Vec<IssuedCertificate> {
+        let now = Utc::now();
+
+        self.certificates
+            .lock()
+            .expect("Failed to acquire the revocation list lock")
+            .iter()
+            .filter(|cert| cert.revoked_at.is_some() && cert.expiration > now)
+            .cloned()
+            .collect()
+    }
+
+    fn save(&self, certificates: &[IssuedCertificate]) -> Result<(), Error> {
+        if let Some(path) = &self.path {
+            let contents =
+                serde_json::to_vec(certificates).context(ErrorKind::CertificateRevocationList)?;
+            fs::write(path, contents).context(ErrorKind::CertificateRevocationList)?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the thumbprint of the first certificate in a PEM bundle, which is
+/// the leaf certificate for the bundles the workload CA issues.
+fn thumbprint(pem: &[u8]) -> Result<String, Error> {
+    let pem = std::str::from_utf8(pem).context(ErrorKind::CertificateContent)?;
+    let begin = pem.find(PEM_BEGIN).ok_or(ErrorKind::CertificateContent)? + PEM_BEGIN.len();
+    let end = pem[begin..]
+        .find(PEM_END)
+        .ok_or(ErrorKind::CertificateContent)?
+        + begin;
+    let base64: String = pem[begin..end]
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let der = base64::decode(&base64).context(ErrorKind::CertificateContent)?;
+
+    let mut thumbprint = String::new();
+    for byte in Sha256::digest(&der) {
+        write!(thumbprint, "{:02X}", byte).expect("Writing to a String cannot fail");
+    }
+    Ok(thumbprint)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    // "test" base64 encoded as a stand-in for a DER encoded certificate
+    const TEST_PEM: &[u8] =
+        b"-----BEGIN CERTIFICATE-----\ndGVzdA==\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\nY2E=\n-----END CERTIFICATE-----\n";
+
+    // SHA-256 of "test"
+    const TEST_THUMBPRINT: &str =
+        "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+
+    #[test]
+    fn thumbprint_of_first_certificate() {
+        assert_eq!(TEST_THUMBPRINT, thumbprint(TEST_PEM).unwrap());
+    }
+
+    #[test]
+    fn thumbprint_fails_without_certificate() {
+        assert!(thumbprint(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn revoke_module_revokes_only_its_certificates() {
+        let crl = CertificateRevocationList::new();
+        let expiration = Utc::now() + Duration::days(1);
+        crl.record("mod1", TEST_PEM, expiration).unwrap();
+        crl.record(
+            "mod2",
+            b"-----BEGIN CERTIFICATE-----\nb3RoZXI=\n-----END CERTIFICATE-----\n",
+            expiration,
+        )
+        .unwrap();
+
+        assert_eq!(1, crl.revoke_module("mod1").unwrap());
+        assert_eq!(0, crl.revoke_module("mod1").unwrap());
+        assert_eq!(0, crl.revoke_module("mod3").unwrap());
+
+        let revoked = crl.revoked();
+        assert_eq!(1, revoked.len());
+        assert_eq!("mod1", revoked[0].module_id());
+        assert_eq!(TEST_THUMBPRINT, revoked[0].thumbprint());
+        assert!(revoked[0].revoked_at().is_some());
+    }
+
+    #[test]
+    fn expired_certificates_are_dropped() {
+        let crl = CertificateRevocationList::new();
+        crl.record("mod1", TEST_PEM, Utc::now() - Duration::seconds(1))
+            .unwrap();
+
+        assert_eq!(0, crl.revoke_module("mod1").unwrap());
+        assert!(crl.revoked().is_empty());
+    }
+
+    #[test]
+    fn revocations_are_persisted() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = tmp_dir.path().join("crl.json");
+
+        let crl = CertificateRevocationList::load(&path).unwrap();
+        crl.record("mod1", TEST_PEM, Utc::now() + Duration::days(1))
+            .unwrap();
+        crl.revoke_module("mod1").unwrap();
+
+        let loaded = CertificateRevocationList::load(&path).unwrap();
+        assert_eq!(crl.revoked(), loaded.revoked());
+        assert_eq!(1, loaded.revoked().len());
+    }
+}
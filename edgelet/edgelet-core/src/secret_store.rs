@@ -0,0 +1,116 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::error::{Error, ErrorKind};
+use crate::module::ModuleSpec;
+
+/// Env values of a module that start with this name a secret in the secret
+/// store. The daemon puts the secret in place of the reference when it creates
+/// the module, so that deployments never carry the secret itself.
+pub const SECRET_REF_PREFIX: &str = "secretref:";
+
+/// Where the secrets that modules reference in their env come from.
+pub trait SecretStore {
+    fn get_secret(&self, name: &str) -> Option<String>;
+}
+
+/// The secrets of the `secrets` section of the config file. Their values can
+/// be encrypted like any other setting, and are decrypted with the rest of
+/// the settings.
+#[derive(Clone, Debug, Default)]
+pub struct LocalSecretStore {
+    secrets: Arc<BTreeMap<String, String>>,
+}
+
+impl LocalSecretStore {
+    pub fn new(secrets: BTreeMap<String, String>) -> Self {
+        LocalSecretStore {
+            secrets: Arc::new(secrets),
+        }
+    }
+}
+
+impl SecretStore for LocalSecretStore {
+    fn get_secret(&self, name: &str) -> Option<String> {
+        self.secrets.get(name).cloned()
+    }
+}
+
+/// Replaces the secret references in the env of `spec` with the secrets they
+/// name. A reference to a secret that isn't in `store` fails the whole spec,
+/// rather than starting the module with the reference as its value.
+pub fn resolve_secret_refs<T, S>(mut spec: ModuleSpec<T>, store: &S) -> Result<ModuleSpec<T>, Error>
+where
+    S: SecretStore + ?Sized,
+{
+    let module = spec.name().to_string();
+    for value in spec.env_mut().values_mut() {
+        if value.starts_with(SECRET_REF_PREFIX) {
+            let name = &value[SECRET_REF_PREFIX.len()..];
+            *value = store.get_secret(name).ok_or_else(|| {
+                Error::from(ErrorKind::SecretNotFound(module.clone(), name.to_string()))
+            })?;
+        }
+    }
+
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::module::ImagePullPolicy;
+
+    fn spec(env: &[(&str, &str)]) -> ModuleSpec<()> {
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+        ModuleSpec::new(
+            "mod1".to_string(),
+            "docker".to_string(),
+            (),
+            env,
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn store() -> LocalSecretStore {
+        let mut secrets = BTreeMap::new();
+        secrets.insert("db-password".to_string(), "hunter2".to_string());
+        LocalSecretStore::new(secrets)
+    }
+
+    #[test]
+    fn secret_refs_are_resolved() {
+        let spec = spec(&[
+            ("DB_PASSWORD", "secretref:db-password"),
+            ("DB_USER", "admin"),
+        ]);
+
+        let spec = resolve_secret_refs(spec, &store()).unwrap();
+
+        assert_eq!("hunter2", spec.env()["DB_PASSWORD"]);
+        assert_eq!("admin", spec.env()["DB_USER"]);
+    }
+
+    #[test]
+    fn unknown_secret_fails() {
+        let spec = spec(&[("API_KEY", "secretref:api-key")]);
+
+        let err = resolve_secret_refs(spec, &store()).unwrap_err();
+
+        match err.kind() {
+            ErrorKind::SecretNotFound(module, name) => {
+                assert_eq!("mod1", module);
+                assert_eq!("api-key", name);
+            }
+            kind => panic!("expected SecretNotFound but got {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::crypto::{Decrypt, Encrypt, MakeRandom};
+use crate::error::{Error, ErrorKind};
+
+/// Settings whose value starts with this are encrypted with the master encryption key of the HSM,
+/// so that secrets like the device connection string or registry passwords aren't kept in plain
+/// text in config.yaml.
+pub const ENCRYPTED_SETTING_PREFIX: &str = "enc:";
+
+/// The client ID the HSM derives the key that encrypts settings with.
+const SETTINGS_CLIENT_ID: &[u8] = b"iotedge-config";
+
+const INITIALIZATION_VECTOR_LEN: usize = 16;
+
+/// Encrypts the value of a setting. The result is the prefix, followed by the base64 of the
+/// random initialization vector and the ciphertext.
+pub fn encrypt_setting<C>(crypto: &C, plaintext: &str) -> Result<String, Error>
+where
+    C: Encrypt + MakeRandom,
+{
+    let mut initialization_vector = [0; INITIALIZATION_VECTOR_LEN];
+    crypto
+        .get_random_bytes(&mut initialization_vector)
+        .context(ErrorKind::EncryptSetting)?;
+
+    let ciphertext = crypto
+        .encrypt(
+            SETTINGS_CLIENT_ID,
+            plaintext.as_bytes(),
+            &initialization_vector,
+        )
+        .context(ErrorKind::EncryptSetting)?;
+
+    let mut encrypted = initialization_vector.to_vec();
+    encrypted.extend_from_slice(ciphertext.as_ref());
+    Ok(format!(
+        "{}{}",
+        ENCRYPTED_SETTING_PREFIX,
+        base64::encode(&encrypted)
+    ))
+}
+
+/// Decrypts the value of a setting if it is encrypted, and returns it as is if it isn't.
+/// `key` is the setting's key, for the error.
+pub fn decrypt_setting<C>(crypto: &C, key: &str, value: &str) -> Result<String, Error>
+where
+    C: Decrypt,
+{
+    if !value.starts_with(ENCRYPTED_SETTING_PREFIX) {
+        return Ok(value.to_string());
+    }
+
+    let encrypted = base64::decode(&value[ENCRYPTED_SETTING_PREFIX.len()..])
+        .with_context(|_| ErrorKind::DecryptSetting(key.to_string()))?;
+    if encrypted.len() <= INITIALIZATION_VECTOR_LEN {
+        return Err(Error::from(ErrorKind::DecryptSetting(key.to_string())));
+    }
+
+    let (initialization_vector, ciphertext) = encrypted.split_at(INITIALIZATION_VECTOR_LEN);
+    let plaintext = crypto
+        .decrypt(SETTINGS_CLIENT_ID, ciphertext, initialization_vector)
+        .with_context(|_| ErrorKind::DecryptSetting(key.to_string()))?;
+
+    let plaintext = String::from_utf8(plaintext.as_ref().to_vec())
+        .with_context(|_| ErrorKind::DecryptSetting(key.to_string()))?;
+    Ok(plaintext)
+}
+
+/// Decrypts every encrypted setting in `settings`.
+///
+/// This can only happen once the HSM is initialized, which needs some of the settings,
+/// so the settings are first loaded with the encrypted values, and decrypted after.
+pub fn decrypt_settings<C, S>(crypto: &C, settings: &S) -> Result<S, Error>
+where
+    C: Decrypt,
+    S: Serialize + DeserializeOwned,
+{
+    let mut value = serde_json::to_value(settings)
+        .with_context(|_| ErrorKind::DecryptSetting(String::new()))?;
+    decrypt_value(crypto, "", &mut value)?;
+
+    let settings =
+        serde_json::from_value(value).with_context(|_| ErrorKind::DecryptSetting(String::new()))?;
+    Ok(settings)
+}
+
+fn decrypt_value<C>(crypto: &C, key: &str, value: &mut Value) -> Result<(), Error>
+where
+    C: Decrypt,
+{
+    match value {
+        Value::String(s) => *s = decrypt_setting(crypto, key, s)?,
+        Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                decrypt_value(crypto, &format!("{}[{}]", key, index), value)?;
+            }
+        }
+        Value::Object(values) => {
+            for (name, value) in values.iter_mut() {
+                let key = if key.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", key, name)
+                };
+                decrypt_value(crypto, &key, value)?;
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::*;
+
+    /// XORs with a key derived from the client ID and the initialization vector,
+    /// which is enough to check that both are passed along.
+    struct TestCrypto;
+
+    impl TestCrypto {
+        fn xor(client_id: &[u8], data: &[u8], initialization_vector: &[u8]) -> Vec<u8> {
+            data.iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    b ^ client_id[i % client_id.len()]
+                        ^ initialization_vector[i % initialization_vector.len()]
+                })
+                .collect()
+        }
+    }
+
+    impl Encrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn encrypt(
+            &self,
+            client_id: &[u8],
+            plaintext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, Error> {
+            Ok(Self::xor(client_id, plaintext, initialization_vector))
+        }
+    }
+
+    impl Decrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn decrypt(
+            &self,
+            client_id: &[u8],
+            ciphertext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, Error> {
+            Ok(Self::xor(client_id, ciphertext, initialization_vector))
+        }
+    }
+
+    impl MakeRandom for TestCrypto {
+        fn get_random_bytes(&self, buffer: &mut [u8]) -> Result<(), Error> {
+            for (i, b) in buffer.iter_mut().enumerate() {
+                #[allow(clippy::cast_possible_truncation)]
+                {
+                    *b = i as u8 + 1;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct TestSettings {
+        connection_string: String,
+        registries: Vec<TestRegistry>,
+        port: u16,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct TestRegistry {
+        address: String,
+        password: Option<String>,
+    }
+
+    #[test]
+    fn encrypted_setting_round_trips() {
+        let encrypted = encrypt_setting(&TestCrypto, "HostName=h;SharedAccessKey=k").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_SETTING_PREFIX));
+        assert!(!encrypted.contains("SharedAccessKey"));
+
+        assert_eq!(
+            "HostName=h;SharedAccessKey=k",
+            decrypt_setting(&TestCrypto, "key", &encrypted).unwrap()
+        );
+    }
+
+    #[test]
+    fn plain_setting_is_unchanged() {
+        assert_eq!(
+            "plain",
+            decrypt_setting(&TestCrypto, "key", "plain").unwrap()
+        );
+    }
+
+    #[test]
+    fn malformed_setting_reports_key() {
+        for value in &["enc:not base64!", "enc:", "enc:AQID"] {
+            let err = decrypt_setting(&TestCrypto, "registries[0].password", value).unwrap_err();
+            match err.kind() {
+                ErrorKind::DecryptSetting(key) => assert_eq!("registries[0].password", key),
+                kind => panic!("expected DecryptSetting but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn nested_settings_are_decrypted() {
+        let settings = TestSettings {
+            connection_string: encrypt_setting(&TestCrypto, "secret").unwrap(),
+            registries: vec![
+                TestRegistry {
+                    address: "a.io".to_string(),
+                    password: Some(encrypt_setting(&TestCrypto, "password").unwrap()),
+                },
+                TestRegistry {
+                    address: "b.io".to_string(),
+                    password: None,
+                },
+            ],
+            port: 443,
+        };
+
+        let expected = TestSettings {
+            connection_string: "secret".to_string(),
+            registries: vec![
+                TestRegistry {
+                    address: "a.io".to_string(),
+                    password: Some("password".to_string()),
+                },
+                TestRegistry {
+                    address: "b.io".to_string(),
+                    password: None,
+                },
+            ],
+            port: 443,
+        };
+        assert_eq!(expected, decrypt_settings(&TestCrypto, &settings).unwrap());
+    }
+}
@@ -1,19 +1,29 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use regex::Regex;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use url::Url;
 use url_serde;
 
+use edgelet_utils::{RequiredSetting, RetryPolicy};
+
 use crate::crypto::MemoryKey;
+use crate::device_action::DeviceAction;
 use crate::error::{Error, ErrorKind};
+use crate::logging::LogFormat;
 use crate::module::ModuleSpec;
-use crate::DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS;
+use crate::{
+    DEFAULT_API_TOKEN_LIFETIME_SECS, DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS,
+    DEFAULT_CA_RENEWAL_THRESHOLD_DAYS, DEFAULT_LIFECYCLE_HOOK_TIMEOUT_SECS,
+    DEFAULT_WATCHDOG_CHECK_INTERVAL_SECS, DEFAULT_WATCHDOG_FAILURE_THRESHOLD,
+};
 
 const DEVICEID_KEY: &str = "DeviceId";
 const HOSTNAME_KEY: &str = "HostName";
@@ -25,6 +35,49 @@ const HOSTNAME_REGEX: &str = r"^[a-zA-Z0-9_\-\.]+$";
 /// This is the default connection string
 pub const DEFAULT_CONNECTION_STRING: &str = "<ADD DEVICE CONNECTION STRING HERE>";
 
+/// The settings the config file has to set depending on how the device is provisioned.
+/// They are checked before the settings are deserialized, so that a missing setting is
+/// reported with the line of the section it is missing from.
+pub const REQUIRED_SETTINGS: &[RequiredSetting] = &[
+    RequiredSetting {
+        when: "provisioning.source",
+        is: "dps",
+        required: &["provisioning.global_endpoint", "provisioning.scope_id"],
+    },
+    RequiredSetting {
+        when: "provisioning.source",
+        is: "external",
+        required: &["provisioning.endpoint"],
+    },
+    RequiredSetting {
+        when: "provisioning.authentication.method",
+        is: "device_connection_string",
+        required: &["provisioning.authentication.device_connection_string"],
+    },
+    RequiredSetting {
+        when: "provisioning.authentication.method",
+        is: "x509",
+        required: &[
+            "provisioning.authentication.iothub_hostname",
+            "provisioning.authentication.device_id",
+            "provisioning.authentication.identity_cert",
+            "provisioning.authentication.identity_pk",
+        ],
+    },
+    RequiredSetting {
+        when: "provisioning.attestation.method",
+        is: "symmetric_key",
+        required: &["provisioning.attestation.registration_id"],
+    },
+];
+
+/// The settings that are read from one key of the config file but kept under another,
+/// so they aren't reported as unknown keys.
+pub const SETTINGS_ALIASES: &[&str] = &[
+    "provisioning.device_connection_string",
+    "provisioning.registration_id",
+];
+
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct ManualX509Auth {
@@ -57,7 +110,7 @@ impl ManualX509Auth {
     }
 
     pub fn identity_pk_uri(&self) -> Result<&Url, Error> {
-        if is_supported_uri(&self.identity_pk) {
+        if is_supported_uri(&self.identity_pk) || is_pkcs11_uri(&self.identity_pk) {
             Ok(&self.identity_pk)
         } else {
             Err(Error::from(ErrorKind::UnsupportedSettingsUri(
@@ -67,6 +120,12 @@ impl ManualX509Auth {
         }
     }
 
+    /// The PKCS#11 URI (RFC 7512) of the identity private key, when the key is
+    /// kept in a PKCS#11 token rather than in a file.
+    pub fn identity_pk_pkcs11_uri(&self) -> Option<&Url> {
+        Some(&self.identity_pk).filter(|uri| is_pkcs11_uri(uri))
+    }
+
     pub fn identity_cert_uri(&self) -> Result<&Url, Error> {
         if is_supported_uri(&self.identity_cert) {
             Ok(&self.identity_cert)
@@ -239,23 +298,158 @@ pub enum AttestationMethod {
 #[serde(rename_all = "lowercase")]
 pub struct TpmAttestationInfo {
     registration_id: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    tcti: Option<TpmTcti>,
 }
 
 impl TpmAttestationInfo {
     pub fn new(registration_id: String) -> Self {
-        TpmAttestationInfo { registration_id }
+        TpmAttestationInfo {
+            registration_id,
+            tcti: None,
+        }
     }
 
     pub fn registration_id(&self) -> &str {
         &self.registration_id
     }
+
+    /// How the HSM library reaches the TPM. When this isn't set, the HSM
+    /// library looks for the TPM itself.
+    pub fn tcti(&self) -> Option<&TpmTcti> {
+        self.tcti.as_ref()
+    }
 }
 
-#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+const TPM_DEFAULT_DEVICE: &str = "/dev/tpm0";
+const SWTPM_DEFAULT_HOST: &str = "localhost";
+const SWTPM_DEFAULT_PORT: u16 = 2321;
+
+/// The TPM Command Transmission Interface (TCTI) used to reach the TPM,
+/// written as `<name>[:<config>]` like the TCTI strings of the TPM2 tools.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TpmTcti {
+    /// A TPM character device, e.g. `device:/dev/tpmrm0` for the kernel's
+    /// resource manager. `device` alone is `/dev/tpm0`.
+    Device(PathBuf),
+    /// The user-space resource manager, with its optional configuration,
+    /// e.g. `tabrmd:bus_type=session`.
+    Tabrmd(Option<String>),
+    /// A software TPM listening on a TCP socket, e.g.
+    /// `swtpm:host=localhost,port=2321`.
+    Swtpm { host: String, port: u16 },
+}
+
+impl Display for TpmTcti {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TpmTcti::Device(path) => write!(f, "device:{}", path.display()),
+            TpmTcti::Tabrmd(None) => write!(f, "tabrmd"),
+            TpmTcti::Tabrmd(Some(config)) => write!(f, "tabrmd:{}", config),
+            TpmTcti::Swtpm { host, port } => write!(f, "swtpm:host={},port={}", host, port),
+        }
+    }
+}
+
+impl FromStr for TpmTcti {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        let name = parts.next().unwrap_or_default();
+        let config = parts
+            .next()
+            .map(str::trim)
+            .filter(|config| !config.is_empty());
+
+        match (name.to_lowercase().as_ref(), config) {
+            ("device", None) => Ok(TpmTcti::Device(PathBuf::from(TPM_DEFAULT_DEVICE))),
+            ("device", Some(path)) => Ok(TpmTcti::Device(PathBuf::from(path))),
+            ("tabrmd", config) => Ok(TpmTcti::Tabrmd(config.map(ToString::to_string))),
+            ("swtpm", config) => {
+                let mut host = SWTPM_DEFAULT_HOST.to_string();
+                let mut port = SWTPM_DEFAULT_PORT;
+                for option in config.into_iter().flat_map(|config| config.split(',')) {
+                    let mut option = option.splitn(2, '=');
+                    match (option.next().map(str::trim), option.next().map(str::trim)) {
+                        (Some("host"), Some(value)) if !value.is_empty() => {
+                            host = value.to_string();
+                        }
+                        (Some("port"), Some(value)) => {
+                            port = value
+                                .parse()
+                                .map_err(|_| format!("Invalid TPM TCTI port: {}", s))?;
+                        }
+                        _ => return Err(format!("Invalid TPM TCTI option: {}", s)),
+                    }
+                }
+                Ok(TpmTcti::Swtpm { host, port })
+            }
+            _ => Err(format!("Unsupported TPM TCTI: {}", s)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TpmTcti {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for TpmTcti {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{}", self))
+    }
+}
+
+/// Symmetric key attestation uses either the device's own key, for an
+/// individual enrollment, or the key of a group enrollment, from which the
+/// device's key is derived.
+#[derive(Clone, Debug, serde_derive::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct SymmetricKeyAttestationInfo {
     registration_id: String,
-    symmetric_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    symmetric_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group_symmetric_key: Option<String>,
+}
+
+impl<'de> serde::Deserialize<'de> for SymmetricKeyAttestationInfo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Debug, serde_derive::Deserialize)]
+        struct Inner {
+            registration_id: String,
+            symmetric_key: Option<String>,
+            group_symmetric_key: Option<String>,
+        }
+
+        let value: Inner = serde::Deserialize::deserialize(deserializer)?;
+
+        match (&value.symmetric_key, &value.group_symmetric_key) {
+            (Some(_), Some(_)) => Err(serde::de::Error::custom(
+                "Only one of provisioning.attestation.symmetric_key or provisioning.attestation.group_symmetric_key must be set in the config.yaml.",
+            )),
+            (None, None) => Err(serde::de::Error::custom(
+                "One of provisioning.attestation.symmetric_key or provisioning.attestation.group_symmetric_key must be set in the config.yaml.",
+            )),
+            _ => Ok(SymmetricKeyAttestationInfo {
+                registration_id: value.registration_id,
+                symmetric_key: value.symmetric_key,
+                group_symmetric_key: value.group_symmetric_key,
+            }),
+        }
+    }
 }
 
 impl SymmetricKeyAttestationInfo {
@@ -263,53 +457,102 @@ impl SymmetricKeyAttestationInfo {
         &self.registration_id
     }
 
-    pub fn symmetric_key(&self) -> &str {
-        &self.symmetric_key
+    /// The device's own key, for an individual enrollment.
+    pub fn symmetric_key(&self) -> Option<&str> {
+        self.symmetric_key.as_ref().map(AsRef::as_ref)
+    }
+
+    /// The key of the group enrollment the device registers through.
+    pub fn group_symmetric_key(&self) -> Option<&str> {
+        self.group_symmetric_key.as_ref().map(AsRef::as_ref)
     }
 }
 
-#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+/// X.509 attestation uses an identity certificate and private key, either
+/// read from the files given in the config or, when neither is set, held by
+/// the HSM.
+#[derive(Clone, Debug, serde_derive::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct X509AttestationInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     registration_id: Option<String>,
-    #[serde(with = "url_serde")]
-    identity_cert: Url,
-    #[serde(with = "url_serde")]
-    identity_pk: Url,
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    identity_cert: Option<Url>,
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    identity_pk: Option<Url>,
+}
+
+impl<'de> serde::Deserialize<'de> for X509AttestationInfo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Debug, serde_derive::Deserialize)]
+        struct Inner {
+            registration_id: Option<String>,
+            #[serde(with = "url_serde", default)]
+            identity_cert: Option<Url>,
+            #[serde(with = "url_serde", default)]
+            identity_pk: Option<Url>,
+        }
+
+        let value: Inner = serde::Deserialize::deserialize(deserializer)?;
+
+        match (&value.identity_cert, &value.identity_pk) {
+            (Some(_), None) | (None, Some(_)) => Err(serde::de::Error::custom(
+                "Either both or neither of provisioning.attestation.identity_cert and provisioning.attestation.identity_pk must be set in the config.yaml.",
+            )),
+            _ => Ok(X509AttestationInfo {
+                registration_id: value.registration_id,
+                identity_cert: value.identity_cert,
+                identity_pk: value.identity_pk,
+            }),
+        }
+    }
 }
 
 impl X509AttestationInfo {
-    pub fn identity_cert(&self) -> Result<PathBuf, Error> {
-        get_path_from_uri(
-            &self.identity_cert,
-            "provisioning.attestation.identity_cert",
-        )
+    /// Whether the identity certificate and private key are held by the HSM
+    /// rather than read from files.
+    pub fn is_hsm_identity(&self) -> bool {
+        self.identity_cert.is_none()
     }
 
-    pub fn identity_pk(&self) -> Result<PathBuf, Error> {
-        get_path_from_uri(&self.identity_pk, "provisioning.attestation.identity_pk")
+    pub fn identity_cert(&self) -> Result<Option<PathBuf>, Error> {
+        self.identity_cert
+            .as_ref()
+            .map(|uri| get_path_from_uri(uri, "provisioning.attestation.identity_cert"))
+            .transpose()
     }
 
-    pub fn identity_pk_uri(&self) -> Result<&Url, Error> {
-        if is_supported_uri(&self.identity_pk) {
-            Ok(&self.identity_pk)
-        } else {
-            Err(Error::from(ErrorKind::UnsupportedSettingsUri(
-                self.identity_pk.to_string(),
-                "provisioning.attestation.identity_pk",
-            )))
+    pub fn identity_pk(&self) -> Result<Option<PathBuf>, Error> {
+        self.identity_pk
+            .as_ref()
+            .map(|uri| get_path_from_uri(uri, "provisioning.attestation.identity_pk"))
+            .transpose()
+    }
+
+    pub fn identity_pk_uri(&self) -> Result<Option<&Url>, Error> {
+        match &self.identity_pk {
+            Some(uri) if !is_supported_uri(uri) => {
+                Err(Error::from(ErrorKind::UnsupportedSettingsUri(
+                    uri.to_string(),
+                    "provisioning.attestation.identity_pk",
+                )))
+            }
+            uri => Ok(uri.as_ref()),
         }
     }
 
-    pub fn identity_cert_uri(&self) -> Result<&Url, Error> {
-        if is_supported_uri(&self.identity_cert) {
-            Ok(&self.identity_cert)
-        } else {
-            Err(Error::from(ErrorKind::UnsupportedSettingsUri(
-                self.identity_cert.to_string(),
-                "provisioning.attestation.identity_cert",
-            )))
+    pub fn identity_cert_uri(&self) -> Result<Option<&Url>, Error> {
+        match &self.identity_cert {
+            Some(uri) if !is_supported_uri(uri) => {
+                Err(Error::from(ErrorKind::UnsupportedSettingsUri(
+                    uri.to_string(),
+                    "provisioning.attestation.identity_cert",
+                )))
+            }
+            uri => Ok(uri.as_ref()),
         }
     }
 
@@ -324,6 +567,10 @@ pub struct Dps {
     global_endpoint: Url,
     scope_id: String,
     attestation: AttestationMethod,
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    payload: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transport: Option<DpsTransport>,
 }
 
 impl<'de> serde::Deserialize<'de> for Dps {
@@ -339,6 +586,9 @@ impl<'de> serde::Deserialize<'de> for Dps {
             registration_id: Option<String>,
             #[serde(skip_serializing_if = "Option::is_none")]
             attestation: Option<AttestationMethod>,
+            #[serde(with = "url_serde", default)]
+            payload: Option<Url>,
+            transport: Option<DpsTransport>,
         }
 
         let value: Inner = serde::Deserialize::deserialize(deserializer)?;
@@ -362,6 +612,8 @@ impl<'de> serde::Deserialize<'de> for Dps {
             global_endpoint: value.global_endpoint,
             scope_id: value.scope_id,
             attestation,
+            payload: value.payload,
+            transport: value.transport,
         })
     }
 }
@@ -378,6 +630,34 @@ impl Dps {
     pub fn attestation(&self) -> &AttestationMethod {
         &self.attestation
     }
+
+    /// The file holding the custom allocation payload to send with the
+    /// registration request, if any.
+    pub fn payload(&self) -> Result<Option<PathBuf>, Error> {
+        self.payload
+            .as_ref()
+            .map(|uri| get_path_from_uri(uri, "provisioning.payload"))
+            .transpose()
+    }
+
+    pub fn transport(&self) -> DpsTransport {
+        self.transport.unwrap_or_default()
+    }
+}
+
+/// The protocol used to register with DPS.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DpsTransport {
+    Https,
+    /// MQTT over TLS on port 8883, for networks that block outbound HTTPS.
+    Mqtt,
+}
+
+impl Default for DpsTransport {
+    fn default() -> Self {
+        DpsTransport::Https
+    }
 }
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -452,6 +732,14 @@ pub struct Listen {
     management_uri: Url,
     #[serde(default = "Protocol::default")]
     min_tls_version: Protocol,
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    metrics_uri: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    management_auth: Option<ManagementAuthSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    management_socket: Option<SocketPermissions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workload_socket: Option<SocketPermissions>,
 }
 
 impl Listen {
@@ -466,6 +754,129 @@ impl Listen {
     pub fn min_tls_version(&self) -> Protocol {
         self.min_tls_version
     }
+
+    pub fn metrics_uri(&self) -> Option<&Url> {
+        self.metrics_uri.as_ref()
+    }
+
+    /// The tokens callers of the management API have to present when it
+    /// listens on TCP.
+    pub fn management_auth(&self) -> Option<&ManagementAuthSettings> {
+        self.management_auth.as_ref()
+    }
+
+    /// The permissions of the management API's socket file, when it listens
+    /// on a unix socket.
+    pub fn management_socket(&self) -> Option<&SocketPermissions> {
+        self.management_socket.as_ref()
+    }
+
+    /// The permissions of the workload API's socket file, when it listens on
+    /// a unix socket.
+    pub fn workload_socket(&self) -> Option<&SocketPermissions> {
+        self.workload_socket.as_ref()
+    }
+}
+
+/// The mode, owner and group a listener's socket file is given after it is
+/// bound. Whatever isn't set is left as the umask and the user the daemon runs
+/// as make it.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct SocketPermissions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<SocketMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+}
+
+impl SocketPermissions {
+    /// The permission bits, e.g. `0o660`.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode.map(|mode| mode.0)
+    }
+
+    /// The name or id of the user that owns the socket file.
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_ref().map(AsRef::as_ref)
+    }
+
+    /// The name or id of the group that owns the socket file.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_ref().map(AsRef::as_ref)
+    }
+}
+
+/// Permission bits written in octal, like chmod takes them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SocketMode(u32);
+
+impl FromStr for SocketMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.trim_start_matches("0o");
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(SocketMode(mode)),
+            _ => Err(format!(
+                "Invalid socket mode {:?}, expected octal permission bits like 0660",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SocketMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for SocketMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{:04o}", self.0))
+    }
+}
+
+/// Callers of a management API that listens on TCP have to present one of
+/// `tokens` as a bearer token, or a token minted through the workload API by
+/// one of `token_modules`.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ManagementAuthSettings {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tokens: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    token_modules: Vec<String>,
+    #[serde(default = "default_api_token_lifetime_secs")]
+    token_lifetime_secs: u64,
+}
+
+impl ManagementAuthSettings {
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    /// The modules that can mint tokens through the workload API.
+    pub fn token_modules(&self) -> &[String] {
+        &self.token_modules
+    }
+
+    /// How long a minted token is valid for.
+    pub fn token_lifetime(&self) -> Duration {
+        Duration::from_secs(self.token_lifetime_secs)
+    }
+}
+
+fn default_api_token_lifetime_secs() -> u64 {
+    DEFAULT_API_TOKEN_LIFETIME_SECS
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -529,6 +940,16 @@ pub struct Certificates {
     device_cert: Option<DeviceCertificate>,
     #[serde(default = "default_auto_generated_ca_lifetime_days")]
     auto_generated_ca_lifetime_days: u16,
+    #[serde(default = "default_ca_renewal_threshold_days")]
+    ca_renewal_threshold_days: u16,
+    #[serde(default)]
+    restart_modules_on_ca_renewal: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    est: Option<Est>,
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    workload_ca_pk: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pkcs11_lib_path: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -538,10 +959,35 @@ pub struct DeviceCertificate {
     trusted_ca_certs: String,
 }
 
+/// Enrollment of the device identity and device CA certificates over EST
+/// (RFC 7030). The enrolled certificates and keys are written to the paths
+/// configured for them, so that the HSM picks them up from there.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Est {
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    identity_url: Option<Url>,
+    #[serde(with = "url_serde", skip_serializing_if = "Option::is_none", default)]
+    device_ca_url: Option<Url>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bootstrap_identity_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bootstrap_identity_pk: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trusted_certs: Option<String>,
+}
+
 fn default_auto_generated_ca_lifetime_days() -> u16 {
     DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS
 }
 
+fn default_ca_renewal_threshold_days() -> u16 {
+    DEFAULT_CA_RENEWAL_THRESHOLD_DAYS
+}
+
 fn is_supported_uri(uri: &Url) -> bool {
     if uri.scheme() == "file" && uri.port().is_none() && uri.query().is_none() {
         if let Some(host) = uri.host_str() {
@@ -552,6 +998,10 @@ fn is_supported_uri(uri: &Url) -> bool {
     false
 }
 
+fn is_pkcs11_uri(uri: &Url) -> bool {
+    uri.scheme() == "pkcs11"
+}
+
 fn get_path_from_uri(uri: &Url, setting_name: &'static str) -> Result<PathBuf, Error> {
     if is_supported_uri(&uri) {
         let path = uri
@@ -632,6 +1082,51 @@ impl DeviceCertificate {
     pub fn trusted_ca_certs_uri(&self) -> Result<Url, Error> {
         convert_to_uri(&self.trusted_ca_certs, "certificates.trusted_ca_certs")
     }
+
+    /// The PKCS#11 URI (RFC 7512) of the device CA private key, when the key is
+    /// kept in a PKCS#11 token rather than in a file.
+    pub fn device_ca_pk_pkcs11_uri(&self) -> Option<Url> {
+        Url::parse(&self.device_ca_pk).ok().filter(is_pkcs11_uri)
+    }
+}
+
+impl Est {
+    pub fn identity_url(&self) -> Option<&Url> {
+        self.identity_url.as_ref()
+    }
+
+    pub fn device_ca_url(&self) -> Option<&Url> {
+        self.device_ca_url.as_ref()
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn bootstrap_identity_cert(&self) -> Result<Option<PathBuf>, Error> {
+        self.bootstrap_identity_cert
+            .as_ref()
+            .map(|path| convert_to_path(path, "certificates.est.bootstrap_identity_cert"))
+            .transpose()
+    }
+
+    pub fn bootstrap_identity_pk(&self) -> Result<Option<PathBuf>, Error> {
+        self.bootstrap_identity_pk
+            .as_ref()
+            .map(|path| convert_to_path(path, "certificates.est.bootstrap_identity_pk"))
+            .transpose()
+    }
+
+    pub fn trusted_certs(&self) -> Result<Option<PathBuf>, Error> {
+        self.trusted_certs
+            .as_ref()
+            .map(|path| convert_to_path(path, "certificates.est.trusted_certs"))
+            .transpose()
+    }
 }
 
 impl Certificates {
@@ -639,10 +1134,49 @@ impl Certificates {
         self.device_cert.as_ref()
     }
 
+    pub fn est(&self) -> Option<&Est> {
+        self.est.as_ref()
+    }
+
     pub fn auto_generated_ca_lifetime_seconds(&self) -> u64 {
         // Convert days to seconds (86,400 seconds per day)
         u64::from(self.auto_generated_ca_lifetime_days) * 86_400
     }
+
+    /// How long before the workload CA, or the auto-generated device CA, expires
+    /// that it gets renewed. Zero disables automatic renewal.
+    pub fn ca_renewal_threshold_seconds(&self) -> u64 {
+        let threshold = u64::from(self.ca_renewal_threshold_days) * 86_400;
+        let lifetime = self.auto_generated_ca_lifetime_seconds();
+
+        // A renewed auto-generated device CA must not be due for renewal right away
+        if self.device_cert.is_none() && threshold >= lifetime {
+            lifetime / 2
+        } else {
+            threshold
+        }
+    }
+
+    pub fn restart_modules_on_ca_renewal(&self) -> bool {
+        self.restart_modules_on_ca_renewal
+    }
+
+    /// The PKCS#11 URI (RFC 7512) of a key in a PKCS#11 token that the workload CA
+    /// certificate is issued for. Without it, the HSM generates the key in a file.
+    pub fn workload_ca_pk(&self) -> Result<Option<&Url>, Error> {
+        match &self.workload_ca_pk {
+            Some(uri) if !is_pkcs11_uri(uri) => Err(Error::from(
+                ErrorKind::UnsupportedSettingsUri(uri.to_string(), "certificates.workload_ca_pk"),
+            )),
+            uri => Ok(uri.as_ref()),
+        }
+    }
+
+    /// The PKCS#11 library that keys referenced by PKCS#11 URIs are accessed
+    /// through. Without it, the OpenSSL pkcs11 engine uses its default library.
+    pub fn pkcs11_lib_path(&self) -> Option<&Path> {
+        self.pkcs11_lib_path.as_ref().map(AsRef::as_ref)
+    }
 }
 
 #[derive(Clone, Copy, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -667,16 +1201,315 @@ impl Default for RetryLimit {
     }
 }
 
-#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
 pub struct WatchdogSettings {
     #[serde(default)]
     max_retries: RetryLimit,
+    #[serde(default = "default_watchdog_check_interval_secs")]
+    check_interval_secs: u64,
+    #[serde(default = "default_watchdog_failure_threshold")]
+    failure_threshold: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    activity_timeout_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keepalive_interval_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rollback_period_secs: Option<u64>,
 }
 
 impl WatchdogSettings {
     pub fn max_retries(&self) -> RetryLimit {
         self.max_retries
     }
+
+    /// How often the watchdog checks on the edge runtime module.
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs)
+    }
+
+    /// How many checks in a row the edge runtime module has to be found
+    /// unresponsive before the watchdog restarts it.
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    /// How long the management API can go without requests before the edge
+    /// runtime module is considered unresponsive. Without it, the watchdog
+    /// only checks that the module is running.
+    pub fn activity_timeout(&self) -> Option<Duration> {
+        self.activity_timeout_secs.map(Duration::from_secs)
+    }
+
+    /// How often the daemon tells the service manager that it is still alive,
+    /// when the service manager watches it. Without it, the daemon does so
+    /// twice within the timeout the service manager asked for.
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive_interval_secs.map(Duration::from_secs)
+    }
+
+    /// How long after an update of the edge runtime module the watchdog rolls
+    /// it back to the spec it had before, if it keeps failing. Without it,
+    /// updates of the edge runtime module are never rolled back.
+    pub fn rollback_period(&self) -> Option<Duration> {
+        self.rollback_period_secs.map(Duration::from_secs)
+    }
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        WatchdogSettings {
+            max_retries: RetryLimit::default(),
+            check_interval_secs: default_watchdog_check_interval_secs(),
+            failure_threshold: default_watchdog_failure_threshold(),
+            activity_timeout_secs: None,
+            keepalive_interval_secs: None,
+            rollback_period_secs: None,
+        }
+    }
+}
+
+fn default_watchdog_check_interval_secs() -> u64 {
+    DEFAULT_WATCHDOG_CHECK_INTERVAL_SECS
+}
+
+fn default_watchdog_failure_threshold() -> u32 {
+    DEFAULT_WATCHDOG_FAILURE_THRESHOLD
+}
+
+/// Limits how often each module can call the workload API operations that use
+/// the HSM, like signing and issuing certificates, so that a module calling
+/// them in a loop can't starve the others.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct RateLimitSettings {
+    requests_per_second: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    burst: Option<u32>,
+}
+
+impl RateLimitSettings {
+    /// How many requests a module can make per second on average.
+    pub fn requests_per_second(&self) -> u32 {
+        self.requests_per_second
+    }
+
+    /// How many requests a module can make at once after it was idle.
+    /// Defaults to the requests per second.
+    pub fn burst(&self) -> u32 {
+        self.burst.unwrap_or(self.requests_per_second)
+    }
+}
+
+/// How the daemon retries the operations that fail for reasons expected to
+/// pass, like a service that is busy or a network that is down. Each
+/// operation left out keeps retrying the way it does by default.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct RetrySettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provisioning: Option<RetryPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iothub: Option<RetryPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_pull: Option<RetryPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    watchdog: Option<RetryPolicy>,
+}
+
+impl RetrySettings {
+    /// Retries of the registration with the Device Provisioning Service.
+    pub fn provisioning(&self) -> Option<&RetryPolicy> {
+        self.provisioning.as_ref()
+    }
+
+    /// Retries of the requests to the hub that it throttled.
+    pub fn iothub(&self) -> Option<&RetryPolicy> {
+        self.iothub.as_ref()
+    }
+
+    /// Retries of module image pulls. Without it, an image pull is attempted
+    /// once.
+    pub fn image_pull(&self) -> Option<&RetryPolicy> {
+        self.image_pull.as_ref()
+    }
+
+    /// The delays between the starts of the edge runtime module by the
+    /// watchdog.
+    pub fn watchdog(&self) -> Option<&RetryPolicy> {
+        self.watchdog.as_ref()
+    }
+}
+
+/// Where the daemon reports its own health in the hub, so that the health of
+/// a device can be seen even while the edge runtime module is down.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthReportTarget {
+    /// The tags of the twin of the device.
+    DeviceTwin,
+    /// The tags of the twin of the edge runtime module.
+    AgentTwin,
+}
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct HealthReportSettings {
+    target: HealthReportTarget,
+    #[serde(default = "default_health_report_interval_secs")]
+    interval_secs: u64,
+}
+
+fn default_health_report_interval_secs() -> u64 {
+    3600
+}
+
+impl HealthReportSettings {
+    pub fn target(&self) -> HealthReportTarget {
+        self.target
+    }
+
+    /// How often the health of the daemon is reported, the first time right
+    /// after it starts.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Publishing anonymized diagnostics of the daemon, like how often the edge
+/// runtime module had to be restarted, for the health analytics of a fleet of
+/// devices. Nothing is published unless `consent` is given explicitly.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct DiagnosticsSettings {
+    consent: bool,
+    sink: DiagnosticsSinkSettings,
+    #[serde(default = "default_diagnostics_interval_secs")]
+    interval_secs: u64,
+}
+
+fn default_diagnostics_interval_secs() -> u64 {
+    86_400
+}
+
+impl DiagnosticsSettings {
+    /// Whether the owner of the device agreed to have diagnostics published.
+    pub fn consent(&self) -> bool {
+        self.consent
+    }
+
+    pub fn sink(&self) -> &DiagnosticsSinkSettings {
+        &self.sink
+    }
+
+    /// How often diagnostics are published, the first time right after the
+    /// daemon starts.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Where the diagnostics of the daemon are published to.
+#[derive(Clone, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticsSinkSettings {
+    /// A device-to-cloud message to the hub of the device.
+    IotHub,
+    /// A POST request to an HTTP endpoint.
+    Http {
+        #[serde(with = "url_serde")]
+        url: Url,
+    },
+}
+
+/// Removing the modules that have no module identity anymore, which a
+/// deployment that failed halfway or a module that was renamed can leave
+/// behind, holding on to their ports and volumes.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct OrphanedModuleSettings {
+    #[serde(default = "default_orphaned_modules_interval_secs")]
+    interval_secs: u64,
+}
+
+fn default_orphaned_modules_interval_secs() -> u64 {
+    3600
+}
+
+impl OrphanedModuleSettings {
+    /// How often orphaned modules are looked for, the first time right after
+    /// the daemon starts.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// The power actions on the host that the edge runtime module may ask for
+/// through the management API, e.g. when it gets a direct method to reboot a
+/// remote gateway. All of them are disabled by default.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct DeviceActionSettings {
+    #[serde(default)]
+    reboot: bool,
+    #[serde(default)]
+    shutdown: bool,
+}
+
+impl DeviceActionSettings {
+    pub fn allows(&self, action: DeviceAction) -> bool {
+        match action {
+            DeviceAction::Reboot => self.reboot,
+            DeviceAction::Shutdown => self.shutdown,
+        }
+    }
+}
+
+/// Another device identity run by the daemon, isolated from the host's own.
+/// Its configuration lives in a separate config file, with its own home
+/// directory, API sockets and module runtime.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Tenant {
+    name: String,
+    config_file: PathBuf,
+}
+
+impl Tenant {
+    pub fn new(name: String, config_file: PathBuf) -> Self {
+        Tenant { name, config_file }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn config_file(&self) -> &Path {
+        &self.config_file
+    }
+}
+
+/// An executable that is run on the lifecycle events of modules, with the
+/// event and the name of the module appended to `args`.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct LifecycleHookSettings {
+    command: PathBuf,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(default = "default_lifecycle_hook_timeout_secs")]
+    timeout_secs: u64,
+}
+
+impl LifecycleHookSettings {
+    pub fn command(&self) -> &Path {
+        &self.command
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// How long the executable may run before it is killed.
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+fn default_lifecycle_hook_timeout_secs() -> u64 {
+    DEFAULT_LIFECYCLE_HOOK_TIMEOUT_SECS
 }
 
 pub trait RuntimeSettings {
@@ -686,11 +1519,23 @@ pub trait RuntimeSettings {
     fn agent(&self) -> &ModuleSpec<Self::Config>;
     fn agent_mut(&mut self) -> &mut ModuleSpec<Self::Config>;
     fn hostname(&self) -> &str;
+    fn parent_hostname(&self) -> Option<&str>;
     fn connect(&self) -> &Connect;
     fn listen(&self) -> &Listen;
     fn homedir(&self) -> &Path;
     fn certificates(&self) -> &Certificates;
     fn watchdog(&self) -> &WatchdogSettings;
+    fn log_level(&self) -> Option<&str>;
+    fn log_format(&self) -> LogFormat;
+    fn tenants(&self) -> &[Tenant];
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings>;
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings>;
+    fn retry(&self) -> &RetrySettings;
+    fn device_actions(&self) -> &DeviceActionSettings;
+    fn health_report(&self) -> Option<&HealthReportSettings>;
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings>;
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings>;
+    fn secrets(&self) -> &BTreeMap<String, String>;
 }
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -698,12 +1543,36 @@ pub struct Settings<T> {
     provisioning: Provisioning,
     agent: ModuleSpec<T>,
     hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_hostname: Option<String>,
     connect: Connect,
     listen: Listen,
     homedir: PathBuf,
     certificates: Option<Certificates>,
     #[serde(default)]
     watchdog: WatchdogSettings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_level: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    log_format: Option<LogFormat>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tenants: Vec<Tenant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lifecycle_hook: Option<LifecycleHookSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workload_rate_limit: Option<RateLimitSettings>,
+    #[serde(default)]
+    retry: RetrySettings,
+    #[serde(default)]
+    device_actions: DeviceActionSettings,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health_report: Option<HealthReportSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    orphaned_modules: Option<OrphanedModuleSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagnostics: Option<DiagnosticsSettings>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    secrets: BTreeMap<String, String>,
 }
 
 impl<T> RuntimeSettings for Settings<T>
@@ -728,6 +1597,10 @@ where
         &self.hostname
     }
 
+    fn parent_hostname(&self) -> Option<&str> {
+        self.parent_hostname.as_ref().map(AsRef::as_ref)
+    }
+
     fn connect(&self) -> &Connect {
         &self.connect
     }
@@ -746,6 +1619,11 @@ where
             None => &Certificates {
                 device_cert: None,
                 auto_generated_ca_lifetime_days: DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS,
+                ca_renewal_threshold_days: DEFAULT_CA_RENEWAL_THRESHOLD_DAYS,
+                restart_modules_on_ca_renewal: false,
+                est: None,
+                workload_ca_pk: None,
+                pkcs11_lib_path: None,
             },
             Some(c) => c,
         }
@@ -754,6 +1632,50 @@ where
     fn watchdog(&self) -> &WatchdogSettings {
         &self.watchdog
     }
+
+    fn log_level(&self) -> Option<&str> {
+        self.log_level.as_ref().map(AsRef::as_ref)
+    }
+
+    fn log_format(&self) -> LogFormat {
+        self.log_format.unwrap_or_default()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        &self.tenants
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        self.lifecycle_hook.as_ref()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.workload_rate_limit.as_ref()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        &self.retry
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        &self.device_actions
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        self.health_report.as_ref()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        self.orphaned_modules.as_ref()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        self.diagnostics.as_ref()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        &self.secrets
+    }
 }
 
 #[cfg(test)]
@@ -949,4 +1871,43 @@ mod tests {
             Err(format!("Unsupported TLS protocol version: {}", value))
         )
     }
+
+    #[test_case("device", TpmTcti::Device(PathBuf::from("/dev/tpm0")); "when device provided")]
+    #[test_case("device:/dev/tpmrm0", TpmTcti::Device(PathBuf::from("/dev/tpmrm0")); "when device path provided")]
+    #[test_case("tabrmd", TpmTcti::Tabrmd(None); "when tabrmd provided")]
+    #[test_case("tabrmd:bus_type=session", TpmTcti::Tabrmd(Some("bus_type=session".to_string())); "when tabrmd config provided")]
+    #[test_case("swtpm", TpmTcti::Swtpm { host: "localhost".to_string(), port: 2321 }; "when swtpm provided")]
+    #[test_case("swtpm:host=10.0.0.2,port=2400", TpmTcti::Swtpm { host: "10.0.0.2".to_string(), port: 2400 }; "when swtpm host and port provided")]
+    #[test_case("SWTPM:port=2400", TpmTcti::Swtpm { host: "localhost".to_string(), port: 2400 }; "when uppercase swtpm provided")]
+    fn it_parses_tpm_tcti(value: &str, expected: TpmTcti) {
+        let actual = TpmTcti::from_str(value);
+        assert_eq!(actual, Ok(expected.clone()));
+        assert_eq!(TpmTcti::from_str(&expected.to_string()), Ok(expected));
+    }
+
+    #[test_case(""; "when empty string provided")]
+    #[test_case("spi:/dev/spidev0"; "when unsupported interface provided")]
+    #[test_case("swtpm:port=socket"; "when invalid port provided")]
+    #[test_case("swtpm:address=localhost"; "when unknown option provided")]
+    fn it_fails_to_parse_tpm_tcti(value: &str) {
+        assert!(TpmTcti::from_str(value).is_err());
+    }
+
+    #[test_case("0660", 0o660; "when octal mode provided")]
+    #[test_case("660", 0o660; "when mode without leading zero provided")]
+    #[test_case("0o600", 0o600; "when rust style octal mode provided")]
+    fn it_parses_socket_mode(value: &str, expected: u32) {
+        assert_eq!(SocketMode::from_str(value), Ok(SocketMode(expected)));
+        assert_eq!(
+            SocketMode::from_str(&format!("{:04o}", expected)),
+            Ok(SocketMode(expected))
+        );
+    }
+
+    #[test_case(""; "when empty string provided")]
+    #[test_case("0689"; "when non octal digits provided")]
+    #[test_case("4755"; "when setuid bit provided")]
+    fn it_fails_to_parse_socket_mode(value: &str) {
+        assert!(SocketMode::from_str(value).is_err());
+    }
 }
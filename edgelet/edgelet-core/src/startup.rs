@@ -0,0 +1,224 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde_derive::{Deserialize, Serialize};
+
+/// Name of the file in the home directory that records how far the daemon got
+/// while starting and why it last failed to start.
+pub const STARTUP_STATE_FILENAME: &str = "startup_state.json";
+
+/// The stages the daemon goes through while starting, in order.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupStage {
+    LoadingConfig,
+    CertificateInit,
+    Provisioning,
+    RuntimeInit,
+    Serving,
+}
+
+impl Default for StartupStage {
+    fn default() -> Self {
+        StartupStage::LoadingConfig
+    }
+}
+
+impl fmt::Display for StartupStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartupStage::LoadingConfig => write!(f, "loading_config"),
+            StartupStage::CertificateInit => write!(f, "certificate_init"),
+            StartupStage::Provisioning => write!(f, "provisioning"),
+            StartupStage::RuntimeInit => write!(f, "runtime_init"),
+            StartupStage::Serving => write!(f, "serving"),
+        }
+    }
+}
+
+/// The stage the daemon failed in, and why.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct StartupFailure {
+    stage: StartupStage,
+    message: String,
+    time: DateTime<Utc>,
+}
+
+impl StartupFailure {
+    pub fn new(stage: StartupStage, message: String, time: DateTime<Utc>) -> Self {
+        StartupFailure {
+            stage,
+            message,
+            time,
+        }
+    }
+
+    pub fn stage(&self) -> StartupStage {
+        self.stage
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct StartupRecord {
+    stage: StartupStage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_failure: Option<StartupFailure>,
+}
+
+/// Tracks the startup stage of the daemon. Every change is written to a file,
+/// so that the stage a daemon that doesn't start is stuck in, and the last
+/// failure, can still be looked at after it exited.
+#[derive(Clone, Debug)]
+pub struct StartupState {
+    record: Arc<Mutex<StartupRecord>>,
+    path: Option<PathBuf>,
+}
+
+impl StartupState {
+    /// Starts tracking a new startup, keeping the last failure recorded in the
+    /// file at `path` by an earlier run of the daemon.
+    pub fn load(path: PathBuf) -> Self {
+        let last_failure = fs::read(&path)
+            .ok()
+            .and_then(|record| serde_json::from_slice::<StartupRecord>(&record).ok())
+            .and_then(|record| record.last_failure);
+
+        let state = StartupState {
+            record: Arc::new(Mutex::new(StartupRecord {
+                stage: StartupStage::LoadingConfig,
+                last_failure,
+            })),
+            path: Some(path),
+        };
+        state.save(&state.lock());
+        state
+    }
+
+    pub fn stage(&self) -> StartupStage {
+        self.lock().stage
+    }
+
+    pub fn last_failure(&self) -> Option<StartupFailure> {
+        self.lock().last_failure.clone()
+    }
+
+    /// Moves on to `stage`. Stages can only be entered in order, so entering
+    /// the current or an earlier stage again is ignored.
+    pub fn enter(&self, stage: StartupStage) {
+        let mut record = self.lock();
+        if stage > record.stage {
+            info!("Startup stage: {}", stage);
+            record.stage = stage;
+            self.save(&record);
+        }
+    }
+
+    /// Records that the daemon failed in the current stage.
+    pub fn fail(&self, message: String) {
+        let mut record = self.lock();
+        record.last_failure = Some(StartupFailure::new(record.stage, message, Utc::now()));
+        self.save(&record);
+    }
+
+    fn save(&self, record: &StartupRecord) {
+        if let Some(path) = &self.path {
+            let result = serde_json::to_vec(record)
+                .map_err(failure::Error::from)
+                .and_then(|record| fs::write(path, record).map_err(failure::Error::from));
+            if let Err(err) = result {
+                warn!(
+                    "Could not write startup state to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StartupRecord> {
+        self.record
+            .lock()
+            .expect("Failed to acquire the startup state lock")
+    }
+}
+
+impl Default for StartupState {
+    /// A startup state that is only kept in memory.
+    fn default() -> Self {
+        StartupState {
+            record: Arc::new(Mutex::new(StartupRecord::default())),
+            path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_are_entered_in_order() {
+        let state = StartupState::default();
+        assert_eq!(StartupStage::LoadingConfig, state.stage());
+
+        state.enter(StartupStage::Provisioning);
+        assert_eq!(StartupStage::Provisioning, state.stage());
+
+        state.enter(StartupStage::CertificateInit);
+        assert_eq!(StartupStage::Provisioning, state.stage());
+    }
+
+    #[test]
+    fn failure_is_recorded_in_current_stage() {
+        let state = StartupState::default();
+        state.enter(StartupStage::RuntimeInit);
+        state.fail("Could not connect to the container engine".to_string());
+
+        let failure = state.last_failure().unwrap();
+        assert_eq!(StartupStage::RuntimeInit, failure.stage());
+        assert_eq!(
+            "Could not connect to the container engine",
+            failure.message()
+        );
+    }
+
+    #[test]
+    fn last_failure_is_kept_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STARTUP_STATE_FILENAME);
+
+        let state = StartupState::load(path.clone());
+        state.enter(StartupStage::CertificateInit);
+        state.fail("HSM failure".to_string());
+
+        let state = StartupState::load(path);
+        assert_eq!(StartupStage::LoadingConfig, state.stage());
+        let failure = state.last_failure().unwrap();
+        assert_eq!(StartupStage::CertificateInit, failure.stage());
+        assert_eq!("HSM failure", failure.message());
+    }
+
+    #[test]
+    fn missing_or_corrupt_file_starts_without_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(STARTUP_STATE_FILENAME);
+        assert!(StartupState::load(path.clone()).last_failure().is_none());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(StartupState::load(path).last_failure().is_none());
+    }
+}
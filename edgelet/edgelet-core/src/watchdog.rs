@@ -1,24 +1,36 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{self, AtomicU64};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use failure::Fail;
 use futures::future::{self, Either, FutureResult};
 use futures::Future;
-use log::{info, warn, Level};
+use log::{error, info, warn, Level};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
 use tokio::prelude::*;
 use tokio::timer::Interval;
 
-use edgelet_utils::log_failure;
+use edgelet_utils::{log_failure, RetryPolicy};
 
 use crate::error::{Error, ErrorKind};
 use crate::identity::{Identity, IdentityManager, IdentitySpec};
+use crate::metrics::Metrics;
 use crate::module::{
-    ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleSpec,
-    ModuleStatus,
+    ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleRuntimeState, ModuleSpec, ModuleStatus, RestartPolicy,
 };
-use crate::settings::RetryLimit;
+use crate::settings::{RetryLimit, WatchdogSettings};
 
 // Time to allow EdgeAgent to gracefully shutdown (including stopping all modules, and updating reported properties)
 const EDGE_RUNTIME_STOP_TIME: Duration = Duration::from_secs(60);
@@ -26,30 +38,573 @@ const EDGE_RUNTIME_STOP_TIME: Duration = Duration::from_secs(60);
 /// This variable holds the generation ID associated with the Edge Agent module.
 const MODULE_GENERATIONID: &str = "IOTEDGE_MODULEGENERATIONID";
 
-/// This is the frequency with which the watchdog checks for the status of the edge runtime module.
-const WATCHDOG_FREQUENCY_SECS: u64 = 60;
+/// This is the delay before the watchdog starts the edge runtime module again
+/// after it had to start it once, unless the settings say otherwise. The delay
+/// doubles with every further start.
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(60);
 
-pub struct Watchdog<M, I> {
+/// This is the longest the watchdog waits between two starts of the edge
+/// runtime module, unless the settings say otherwise.
+const RESTART_BACKOFF_CAP: Duration = Duration::from_secs(600);
+
+/// This is the window in which starts of the edge runtime module are counted.
+const RESTART_WINDOW: Duration = Duration::from_secs(3600);
+
+/// This is the number of starts within the window after which the watchdog
+/// gives up on the edge runtime module.
+const MAX_RESTARTS_IN_WINDOW: usize = 6;
+
+/// This is the number of checks within the rollback period after an update of
+/// the edge runtime module that have to find it not running before the watchdog
+/// rolls the update back.
+const ROLLBACK_FAILURE_THRESHOLD: u32 = 3;
+
+/// Name of the file in the home directory that records the spec the edge
+/// runtime module had before an update that isn't confirmed yet.
+pub const AGENT_ROLLBACK_FILENAME: &str = "agent_rollback.json";
+
+const WATCHDOG_RESTARTS_METRIC: &str = "iotedged_watchdog_restarts_total";
+const WATCHDOG_RESTARTS_HELP: &str = "Number of times the watchdog started the edge runtime module";
+
+/// Keeps track of when the management API last served a request. The edge
+/// runtime module polls the management API as part of its reconcile loop, so a
+/// long silence means that it stopped making progress even if it's running.
+#[derive(Clone, Debug)]
+pub struct ActivityMonitor {
+    last_activity: Arc<Mutex<Instant>>,
+}
+
+impl ActivityMonitor {
+    pub fn new() -> Self {
+        ActivityMonitor {
+            last_activity: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn record(&self) {
+        *self
+            .last_activity
+            .lock()
+            .expect("Failed to acquire the activity lock") = Instant::now();
+    }
+
+    pub fn idle_time(&self) -> Duration {
+        self.last_activity
+            .lock()
+            .expect("Failed to acquire the activity lock")
+            .elapsed()
+    }
+}
+
+impl Default for ActivityMonitor {
+    fn default() -> Self {
+        ActivityMonitor::new()
+    }
+}
+
+/// The state of the edge runtime module as far as the watchdog is concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeRuntimeState {
+    Healthy,
+    BackingOff,
+    Broken,
+}
+
+impl fmt::Display for EdgeRuntimeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EdgeRuntimeState::Healthy => write!(f, "healthy"),
+            EdgeRuntimeState::BackingOff => write!(
+                f,
+                "restarting repeatedly, the watchdog is backing off between restarts"
+            ),
+            EdgeRuntimeState::Broken => write!(
+                f,
+                "crash looping, the watchdog stopped restarting it until the daemon is restarted"
+            ),
+        }
+    }
+}
+
+/// Shares the state of the edge runtime module with the management API.
+#[derive(Clone, Debug)]
+pub struct WatchdogStatus {
+    state: Arc<Mutex<EdgeRuntimeState>>,
+    restarts: Arc<AtomicU64>,
+}
+
+impl WatchdogStatus {
+    pub fn new() -> Self {
+        WatchdogStatus {
+            state: Arc::new(Mutex::new(EdgeRuntimeState::Healthy)),
+            restarts: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// How many times the watchdog started the edge runtime module since the
+    /// daemon started.
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(atomic::Ordering::SeqCst)
+    }
+
+    fn record_restart(&self) {
+        self.restarts.fetch_add(1, atomic::Ordering::SeqCst);
+    }
+
+    pub fn state(&self) -> EdgeRuntimeState {
+        *self
+            .state
+            .lock()
+            .expect("Failed to acquire the watchdog status lock")
+    }
+
+    pub fn set_state(&self, state: EdgeRuntimeState) {
+        *self
+            .state
+            .lock()
+            .expect("Failed to acquire the watchdog status lock") = state;
+    }
+}
+
+impl Default for WatchdogStatus {
+    fn default() -> Self {
+        WatchdogStatus::new()
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct PendingRollback<T> {
+    previous: ModuleSpec<T>,
+    updated_at: DateTime<Utc>,
+}
+
+struct RollbackState<T> {
+    current: Option<ModuleSpec<T>>,
+    pending: Option<PendingRollback<T>>,
+    failures: u32,
+}
+
+/// Keeps the spec the edge runtime module had before it was updated through
+/// the management API, so that the watchdog can put it back if the updated
+/// module keeps failing. An update is confirmed once the rollback period passed
+/// since it, and the spec from before it is dropped then.
+///
+/// The pending update is written to a file, so that it is still rolled back if
+/// the daemon restarts within the rollback period.
+#[derive(Clone)]
+pub struct AgentRollback<T> {
+    name: String,
+    period: Duration,
+    state: Arc<Mutex<RollbackState<T>>>,
+    path: Option<PathBuf>,
+}
+
+impl<T> AgentRollback<T>
+where
+    T: Clone + Serialize + DeserializeOwned,
+{
+    /// Tracks the updates of the module called `name` in memory only.
+    pub fn new(name: String, period: Duration) -> Self {
+        AgentRollback {
+            name,
+            period,
+            state: Arc::new(Mutex::new(RollbackState {
+                current: None,
+                pending: None,
+                failures: 0,
+            })),
+            path: None,
+        }
+    }
+
+    /// Tracks the updates of the module called `name`, picking up an update
+    /// that an earlier run of the daemon recorded in the file at `path`.
+    pub fn load(name: String, period: Duration, path: PathBuf) -> Self {
+        let pending = fs::read(&path)
+            .ok()
+            .and_then(|pending| serde_json::from_slice::<PendingRollback<T>>(&pending).ok())
+            .filter(|pending| pending.previous.name() == name);
+
+        AgentRollback {
+            name,
+            period,
+            state: Arc::new(Mutex::new(RollbackState {
+                current: None,
+                pending,
+                failures: 0,
+            })),
+            path: Some(path),
+        }
+    }
+
+    /// Records that the module is being replaced with `spec`. Updates of other
+    /// modules are ignored.
+    pub fn record_update(&self, spec: &ModuleSpec<T>) {
+        if spec.name() != self.name {
+            return;
+        }
+
+        let mut state = self.lock();
+        // An update that isn't confirmed yet is no better than this one, so the
+        // module still rolls back to the spec from before that update.
+        let previous = match (state.pending.take(), state.current.take()) {
+            (Some(pending), _) => Some(pending.previous),
+            (None, current) => current,
+        };
+        state.pending = previous.map(|previous| PendingRollback {
+            previous,
+            updated_at: Utc::now(),
+        });
+        state.current = Some(spec.clone());
+        state.failures = 0;
+        self.save(&state);
+    }
+
+    // Sets the spec the module was started with, unless it was updated since.
+    fn start(&self, spec: &ModuleSpec<T>) {
+        let mut state = self.lock();
+        if state.current.is_none() {
+            state.current = Some(spec.clone());
+        }
+    }
+
+    // Returns whether the pending update should be rolled back, given whether
+    // the module is running now. The update is confirmed once the rollback
+    // period passed.
+    fn observe(&self, running: bool) -> bool {
+        let mut state = self.lock();
+        let updated_at = match &state.pending {
+            Some(pending) => pending.updated_at,
+            None => return false,
+        };
+
+        let elapsed = Utc::now()
+            .signed_duration_since(updated_at)
+            .to_std()
+            .unwrap_or_else(|_| Duration::from_secs(0));
+        if elapsed >= self.period {
+            info!(
+                "Update of edge runtime module {} is confirmed, it will not be rolled back.",
+                self.name,
+            );
+            state.pending = None;
+            state.failures = 0;
+            self.save(&state);
+            return false;
+        }
+
+        if !running {
+            state.failures += 1;
+            warn!(
+                "Edge runtime module {} is not running {} seconds after its update (check {} of {}).",
+                self.name,
+                elapsed.as_secs(),
+                state.failures,
+                ROLLBACK_FAILURE_THRESHOLD,
+            );
+        }
+        state.failures >= ROLLBACK_FAILURE_THRESHOLD
+    }
+
+    // Drops the pending update and returns the spec from before it.
+    fn roll_back(&self) -> Option<ModuleSpec<T>> {
+        let mut state = self.lock();
+        let previous = state.pending.take()?.previous;
+        state.current = Some(previous.clone());
+        state.failures = 0;
+        self.save(&state);
+        Some(previous)
+    }
+
+    fn save(&self, state: &RollbackState<T>) {
+        if let Some(path) = &self.path {
+            let result = match &state.pending {
+                Some(pending) => serde_json::to_vec(pending)
+                    .map_err(failure::Error::from)
+                    .and_then(|pending| fs::write(path, pending).map_err(failure::Error::from)),
+                None => match fs::remove_file(path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        Err(failure::Error::from(err))
+                    }
+                    _ => Ok(()),
+                },
+            };
+            if let Err(err) = result {
+                warn!(
+                    "Could not write the edge runtime rollback state to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RollbackState<T>> {
+        self.state
+            .lock()
+            .expect("Failed to acquire the rollback state lock")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RestartDecision {
+    Restart,
+    Wait(Duration),
+    GiveUp,
+}
+
+// Spaces out the starts of the edge runtime module exponentially, so that a
+// module that keeps crashing doesn't make the watchdog hot-loop on creating
+// and starting it. Too many starts within the window put the module in a
+// terminal broken state.
+#[derive(Clone)]
+struct RestartBackoff {
+    restarts: Arc<Mutex<Vec<Instant>>>,
+    policy: RetryPolicy,
+    status: WatchdogStatus,
+    metrics: Metrics,
+}
+
+impl RestartBackoff {
+    fn new(policy: RetryPolicy, status: WatchdogStatus, metrics: Metrics) -> Self {
+        RestartBackoff {
+            restarts: Arc::new(Mutex::new(vec![])),
+            policy,
+            status,
+            metrics,
+        }
+    }
+
+    // Returns whether the watchdog may start the module now, and counts the
+    // start if so.
+    fn allow_restart(&self) -> bool {
+        if self.status.state() == EdgeRuntimeState::Broken {
+            return false;
+        }
+
+        let mut restarts = self
+            .restarts
+            .lock()
+            .expect("Failed to acquire the restart backoff lock");
+        match next_restart(&self.policy, &mut restarts, Instant::now()) {
+            RestartDecision::Restart => {
+                if restarts.len() > 1 {
+                    self.status.set_state(EdgeRuntimeState::BackingOff);
+                }
+                self.metrics
+                    .inc_counter(WATCHDOG_RESTARTS_METRIC, WATCHDOG_RESTARTS_HELP, &[]);
+                self.status.record_restart();
+                true
+            }
+            RestartDecision::Wait(remaining) => {
+                info!(
+                    "Edge runtime was restarted {} times recently, waiting {} seconds before starting it again.",
+                    restarts.len(),
+                    remaining.as_secs(),
+                );
+                self.status.set_state(EdgeRuntimeState::BackingOff);
+                false
+            }
+            RestartDecision::GiveUp => {
+                error!(
+                    "Edge runtime was restarted {} times in the last {} minutes, the watchdog will not restart it again until the daemon is restarted.",
+                    restarts.len(),
+                    RESTART_WINDOW.as_secs() / 60,
+                );
+                self.status.set_state(EdgeRuntimeState::Broken);
+                false
+            }
+        }
+    }
+
+    // Clears the backing off state once the module stayed up for the window.
+    fn observe_running(&self) {
+        if self.status.state() != EdgeRuntimeState::BackingOff {
+            return;
+        }
+
+        let mut restarts = self
+            .restarts
+            .lock()
+            .expect("Failed to acquire the restart backoff lock");
+        let now = Instant::now();
+        restarts.retain(|restart| now.duration_since(*restart) < RESTART_WINDOW);
+        if restarts.len() <= 1 {
+            self.status.set_state(EdgeRuntimeState::Healthy);
+        }
+    }
+}
+
+fn next_restart(
+    policy: &RetryPolicy,
+    restarts: &mut Vec<Instant>,
+    now: Instant,
+) -> RestartDecision {
+    restarts.retain(|restart| now.duration_since(*restart) < RESTART_WINDOW);
+
+    if restarts.len() >= MAX_RESTARTS_IN_WINDOW {
+        return RestartDecision::GiveUp;
+    }
+
+    if let Some(last) = restarts.last() {
+        let delay = restart_delay(policy, restarts.len());
+        match delay.checked_sub(now.duration_since(*last)) {
+            Some(remaining) if remaining > Duration::from_secs(0) => {
+                return RestartDecision::Wait(remaining);
+            }
+            _ => (),
+        }
+    }
+
+    restarts.push(now);
+    RestartDecision::Restart
+}
+
+// The delay after `restarts` starts is the delay of the policy before the
+// retry that follows them. It leaves out the jitter, since the delay is worked
+// out again on every check and there is only the one module to space out.
+fn restart_delay(policy: &RetryPolicy, restarts: usize) -> Duration {
+    let retry = u32::try_from(restarts.saturating_sub(1))
+        .expect("restarts are bounded by MAX_RESTARTS_IN_WINDOW");
+    policy.base_delay(retry)
+}
+
+fn default_restart_policy() -> RetryPolicy {
+    RetryPolicy::exponential(RESTART_BACKOFF_BASE, RESTART_BACKOFF_CAP)
+}
+
+// Decides when a running edge runtime module is wedged, which is when the
+// management API saw no activity for the configured timeout on enough checks
+// in a row.
+#[derive(Clone)]
+struct HealthCheck {
+    activity: Option<(ActivityMonitor, Duration)>,
+    failure_threshold: u32,
+    failures: Arc<Mutex<u32>>,
+}
+
+impl HealthCheck {
+    fn new(activity: Option<(ActivityMonitor, Duration)>, failure_threshold: u32) -> Self {
+        HealthCheck {
+            activity,
+            failure_threshold,
+            failures: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    // Returns whether the module should be restarted. Once it returns true, the
+    // module gets a fresh timeout to come back up.
+    fn is_wedged(&self) -> bool {
+        let (activity, timeout) = match &self.activity {
+            Some(activity) => activity,
+            None => return false,
+        };
+
+        let idle_time = activity.idle_time();
+        let mut failures = self
+            .failures
+            .lock()
+            .expect("Failed to acquire the health check lock");
+        if idle_time < *timeout {
+            *failures = 0;
+            return false;
+        }
+
+        *failures += 1;
+        warn!(
+            "Edge runtime has not called the management API in {} seconds (check {} of {}).",
+            idle_time.as_secs(),
+            *failures,
+            self.failure_threshold,
+        );
+        if *failures < self.failure_threshold {
+            return false;
+        }
+
+        *failures = 0;
+        activity.record();
+        true
+    }
+
+    // Gives a module that was just started a fresh timeout to come up.
+    fn reset(&self) {
+        if let Some((activity, _)) = &self.activity {
+            *self
+                .failures
+                .lock()
+                .expect("Failed to acquire the health check lock") = 0;
+            activity.record();
+        }
+    }
+}
+
+pub struct Watchdog<M, I>
+where
+    M: ModuleRuntime,
+{
     runtime: M,
     id_mgr: I,
-    max_retries: RetryLimit,
+    settings: WatchdogSettings,
+    restart_policy: RetryPolicy,
+    activity: Option<ActivityMonitor>,
+    status: WatchdogStatus,
+    metrics: Metrics,
+    rollback: Option<AgentRollback<<M::Module as Module>::Config>>,
 }
 
 impl<M, I> Watchdog<M, I>
 where
     M: 'static + ModuleRuntime + Clone,
     for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
-    <M::Module as Module>::Config: Clone,
+    <M::Module as Module>::Config: Clone + Serialize + DeserializeOwned,
     I: 'static + IdentityManager + Clone,
 {
-    pub fn new(runtime: M, id_mgr: I, max_retries: RetryLimit) -> Self {
+    pub fn new(runtime: M, id_mgr: I, settings: WatchdogSettings) -> Self {
         Watchdog {
             runtime,
             id_mgr,
-            max_retries,
+            settings,
+            restart_policy: default_restart_policy(),
+            activity: None,
+            status: WatchdogStatus::new(),
+            metrics: Metrics::new(),
+            rollback: None,
         }
     }
 
+    /// Spaces out the starts of the edge runtime module by `policy` instead of
+    /// doubling the delay from a minute up to ten.
+    pub fn with_restart_policy(mut self, policy: RetryPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Judges the liveness of the edge runtime module by the activity on the
+    /// management API, when an activity timeout is configured.
+    pub fn with_activity_monitor(mut self, activity: ActivityMonitor) -> Self {
+        self.activity = Some(activity);
+        self
+    }
+
+    /// Reports the state of the edge runtime module through `status`.
+    pub fn with_status(mut self, status: WatchdogStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Counts the starts of the edge runtime module in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Rolls the edge runtime module back to the spec it had before its last
+    /// update through the management API, if it keeps failing after it.
+    pub fn with_rollback(mut self, rollback: AgentRollback<<M::Module as Module>::Config>) -> Self {
+        self.rollback = Some(rollback);
+        self
+    }
+
     // Start the edge runtime module (EdgeAgent). This also updates the identity of the module (module_id)
     // to make sure it is configured for the right authentication type (sas token)
     // spec.name = edgeAgent / module_id = $edgeAgent
@@ -67,9 +622,31 @@ where
         let name = spec.name().to_string();
         let id_mgr = self.id_mgr;
         let module_id = module_id.to_string();
-        let max_retries = self.max_retries;
+        let max_retries = self.settings.max_retries();
+        let check_interval = self.settings.check_interval();
+        let activity_timeout = self.settings.activity_timeout();
+        let health = HealthCheck::new(
+            self.activity
+                .and_then(|activity| Some((activity, activity_timeout?))),
+            self.settings.failure_threshold(),
+        );
+        let backoff = RestartBackoff::new(self.restart_policy, self.status, self.metrics);
+        let rollback = self.rollback;
+        if let Some(rollback) = &rollback {
+            rollback.start(&spec);
+        }
 
-        let watchdog = start_watchdog(runtime, id_mgr, spec, module_id, max_retries);
+        let watchdog = start_watchdog(
+            runtime,
+            id_mgr,
+            spec,
+            module_id,
+            max_retries,
+            check_interval,
+            health,
+            backoff,
+            rollback,
+        );
 
         // Swallow any errors from shutdown_signal
         let shutdown_signal = shutdown_signal.then(|_| Ok(()));
@@ -103,33 +680,44 @@ where
         })
 }
 
-// Start watchdog on a timer for 1 minute
-pub fn start_watchdog<M, I>(
+// Start watchdog on a timer for the configured check interval
+fn start_watchdog<M, I>(
     runtime: M,
     id_mgr: I,
     spec: ModuleSpec<<M::Module as Module>::Config>,
     module_id: String,
     max_retries: RetryLimit,
+    check_interval: Duration,
+    health: HealthCheck,
+    backoff: RestartBackoff,
+    rollback: Option<AgentRollback<<M::Module as Module>::Config>>,
 ) -> impl Future<Item = (), Error = Error>
 where
     M: 'static + ModuleRuntime + Clone,
-    <M::Module as Module>::Config: Clone,
+    <M::Module as Module>::Config: Clone + Serialize + DeserializeOwned,
     I: 'static + IdentityManager + Clone,
 {
     info!(
         "Starting watchdog with {} second frequency...",
-        WATCHDOG_FREQUENCY_SECS
+        check_interval.as_secs()
     );
 
-    Interval::new(Instant::now(), Duration::from_secs(WATCHDOG_FREQUENCY_SECS))
+    let stopped = edge_runtime_stopped(&runtime, spec.name().to_string());
+
+    Interval::new(Instant::now(), check_interval)
+        .map(|_| ())
         .map_err(|err| Error::from(err.context(ErrorKind::EdgeRuntimeStatusCheckerTimer)))
-        .and_then(move |_| {
+        .select(stopped)
+        .and_then(move |()| {
             info!("Checking edge runtime status");
             check_runtime(
                 runtime.clone(),
                 id_mgr.clone(),
                 spec.clone(),
                 module_id.clone(),
+                health.clone(),
+                backoff.clone(),
+                rollback.clone(),
             )
             .and_then(|_| future::ok(None))
             .or_else(|e| {
@@ -152,19 +740,51 @@ where
         .map(|_| ())
 }
 
-// Check if the edge runtime module is running, and if not, start it.
+// Yields whenever the runtime reports that the edge runtime module stopped, so
+// that it is checked right away instead of at the next interval. Runtimes that
+// don't report events leave the watchdog to the interval alone.
+fn edge_runtime_stopped<M>(runtime: &M, name: String) -> impl Stream<Item = (), Error = Error>
+where
+    M: ModuleRuntime,
+{
+    runtime
+        .events()
+        .then(|event| match event {
+            Ok(event) => Ok(Some(event)),
+            Err(err) => {
+                let err = Error::from(err.context(ErrorKind::ModuleRuntime));
+                log_failure(Level::Warn, &err);
+                Ok(None)
+            }
+        })
+        .filter_map(move |event| match event {
+            Some(ref event) if event.name() == name && *event.status() != ModuleStatus::Running => {
+                info!("Edge runtime module {} stopped", name);
+                Some(())
+            }
+            _ => None,
+        })
+}
+
+// Check if the edge runtime module is running, and if not, start it. A running
+// module that stopped responding is restarted.
 fn check_runtime<M, I>(
     runtime: M,
     id_mgr: I,
     spec: ModuleSpec<<M::Module as Module>::Config>,
     module_id: String,
+    health: HealthCheck,
+    backoff: RestartBackoff,
+    rollback: Option<AgentRollback<<M::Module as Module>::Config>>,
 ) -> impl Future<Item = (), Error = Error>
 where
     M: 'static + ModuleRuntime + Clone,
-    <M::Module as Module>::Config: Clone,
+    <M::Module as Module>::Config: Clone + Serialize + DeserializeOwned,
     I: 'static + IdentityManager + Clone,
 {
     let module = spec.name().to_string();
+    let restart_policy = spec.restart_policy();
+    let restart_max_retries = spec.restart_max_retries();
     get_edge_runtime_mod(&runtime, module.clone())
         .and_then(|m| {
             m.map(|m| {
@@ -172,30 +792,101 @@ where
                     .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime)))
             })
         })
-        .and_then(move |state| match state {
-            Some(state) => {
-                let res = if *state.status() == ModuleStatus::Running {
-                    info!("Edge runtime is running.");
-                    future::Either::A(future::ok(()))
-                } else {
-                    info!(
-                        "Edge runtime status is {}, starting module now...",
-                        *state.status(),
-                    );
-                    future::Either::B(
-                        runtime
-                            .start(&module)
-                            .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime))),
-                    )
-                };
-                Either::A(res)
+        .and_then(move |state| {
+            let rollback_due = match (&rollback, &state) {
+                (Some(rollback), Some(state)) => {
+                    rollback.observe(*state.status() == ModuleStatus::Running)
+                }
+                _ => false,
+            };
+            let previous = if rollback_due && backoff.allow_restart() {
+                rollback.as_ref().and_then(AgentRollback::roll_back)
+            } else {
+                None
+            };
+            if let Some(previous) = previous {
+                warn!("Edge runtime keeps failing after its update, rolling it back now...");
+                health.reset();
+                return Either::A(roll_back(runtime, &id_mgr, previous, module_id));
             }
 
-            None => Either::B(create_and_start(runtime, &id_mgr, spec, module_id)),
+            Either::B(match state {
+                Some(state) => {
+                    let res = if *state.status() == ModuleStatus::Running {
+                        if health.is_wedged() && backoff.allow_restart() {
+                            warn!("Edge runtime is running but unresponsive, restarting module now...");
+                            future::Either::B(future::Either::B(
+                                runtime
+                                    .restart(&module)
+                                    .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime))),
+                            ))
+                        } else {
+                            info!("Edge runtime is running.");
+                            backoff.observe_running();
+                            future::Either::A(future::ok(()))
+                        }
+                    } else if is_runtime_restarting(restart_policy, &state) {
+                        info!("Edge runtime is being restarted by the container runtime.");
+                        future::Either::A(future::ok(()))
+                    } else if !backoff.allow_restart() {
+                        future::Either::A(future::ok(()))
+                    } else {
+                        if has_runtime_given_up(restart_policy, restart_max_retries, &state) {
+                            warn!(
+                                "Container runtime gave up restarting the edge runtime after {} attempts.",
+                                state.restart_count().unwrap_or_default(),
+                            );
+                        }
+                        info!(
+                            "Edge runtime status is {}, starting module now...",
+                            *state.status(),
+                        );
+                        health.reset();
+                        future::Either::B(future::Either::A(
+                            runtime
+                                .start(&module)
+                                .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime))),
+                        ))
+                    };
+                    Either::A(res)
+                }
+
+                None => {
+                    if backoff.allow_restart() {
+                        health.reset();
+                        Either::B(Either::A(create_and_start(
+                            runtime, &id_mgr, spec, module_id,
+                        )))
+                    } else {
+                        Either::B(Either::B(future::ok(())))
+                    }
+                }
+            })
         })
         .map(|_| ())
 }
 
+// When the restart policy is handed to the container runtime, the watchdog must
+// not start the module while the container runtime is in the middle of
+// restarting it. Once the container runtime gives up, the watchdog takes over.
+fn is_runtime_restarting(restart_policy: RestartPolicy, state: &ModuleRuntimeState) -> bool {
+    restart_policy != RestartPolicy::Never && state.status_description() == Some("restarting")
+}
+
+fn has_runtime_given_up(
+    restart_policy: RestartPolicy,
+    max_retries: Option<u32>,
+    state: &ModuleRuntimeState,
+) -> bool {
+    match (restart_policy, max_retries, state.restart_count()) {
+        (RestartPolicy::Never, _, _) => false,
+        (_, Some(max_retries), Some(restart_count)) => {
+            i64::from(restart_count) >= i64::from(max_retries)
+        }
+        _ => false,
+    }
+}
+
 // Gets the edge runtime module, if it exists.
 fn get_edge_runtime_mod<M>(
     runtime: &M,
@@ -241,6 +932,25 @@ where
         })
 }
 
+// Replaces the edge runtime module with the spec it had before its update
+fn roll_back<M, I>(
+    runtime: M,
+    id_mgr: &I,
+    spec: ModuleSpec<<M::Module as Module>::Config>,
+    module_id: String,
+) -> impl Future<Item = (), Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone,
+    <M::Module as Module>::Config: Clone,
+    I: 'static + IdentityManager + Clone,
+{
+    let id_mgr = id_mgr.clone();
+    runtime
+        .remove(spec.name())
+        .map_err(|e| Error::from(e.context(ErrorKind::ModuleRuntime)))
+        .and_then(move |()| create_and_start(runtime, &id_mgr, spec, module_id))
+}
+
 // Edge agent does not exist - pull, create and start the container
 fn create_and_start<M, I>(
     runtime: M,
@@ -283,6 +993,7 @@ where
 mod tests {
     use super::*;
     use std::cell::RefCell;
+    use std::collections::HashMap;
     use std::rc::Rc;
 
     use futures::future::{self, FutureResult};
@@ -390,6 +1101,7 @@ mod tests {
         type ListFuture = FutureResult<Vec<Self::Identity>, Self::Error>;
         type GetFuture = FutureResult<Option<Self::Identity>, Self::Error>;
         type DeleteFuture = FutureResult<(), Self::Error>;
+        type RegenerateFuture = FutureResult<Self::Identity, Self::Error>;
 
         fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
             self.state.borrow_mut().gen_id_sentinel += 1;
@@ -465,6 +1177,10 @@ mod tests {
                 .map(|index| self.state.borrow_mut().identities.remove(index))
                 .map_or_else(|| future::err(Error::ModuleNotFound), |_| future::ok(()))
         }
+
+        fn regenerate(&mut self, _id: IdentitySpec) -> Self::RegenerateFuture {
+            unimplemented!()
+        }
     }
 
     #[test]
@@ -523,4 +1239,239 @@ mod tests {
                 .auth_type
         );
     }
+
+    #[test]
+    fn watchdog_defers_to_restarting_runtime() {
+        let restarting = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Stopped)
+            .with_status_description(Some("restarting".to_string()));
+        let exited = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Failed)
+            .with_status_description(Some("exited".to_string()));
+
+        assert!(is_runtime_restarting(RestartPolicy::OnFailure, &restarting));
+        assert!(!is_runtime_restarting(RestartPolicy::Never, &restarting));
+        assert!(!is_runtime_restarting(RestartPolicy::OnFailure, &exited));
+    }
+
+    #[test]
+    fn watchdog_detects_runtime_giving_up() {
+        let state = ModuleRuntimeState::default()
+            .with_status(ModuleStatus::Failed)
+            .with_restart_count(Some(3));
+
+        assert!(has_runtime_given_up(
+            RestartPolicy::OnFailure,
+            Some(3),
+            &state
+        ));
+        assert!(!has_runtime_given_up(
+            RestartPolicy::OnFailure,
+            Some(5),
+            &state
+        ));
+        assert!(!has_runtime_given_up(
+            RestartPolicy::OnFailure,
+            None,
+            &state
+        ));
+        assert!(!has_runtime_given_up(RestartPolicy::Never, Some(3), &state));
+    }
+
+    #[test]
+    fn restart_delay_doubles_up_to_cap() {
+        let policy = default_restart_policy();
+        assert_eq!(Duration::from_secs(60), restart_delay(&policy, 1));
+        assert_eq!(Duration::from_secs(120), restart_delay(&policy, 2));
+        assert_eq!(Duration::from_secs(240), restart_delay(&policy, 3));
+        assert_eq!(Duration::from_secs(480), restart_delay(&policy, 4));
+        assert_eq!(RESTART_BACKOFF_CAP, restart_delay(&policy, 5));
+        assert_eq!(RESTART_BACKOFF_CAP, restart_delay(&policy, 100));
+    }
+
+    #[test]
+    fn restart_delay_follows_configured_policy() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(5)).with_jitter(true);
+        assert_eq!(Duration::from_secs(5), restart_delay(&policy, 1));
+        assert_eq!(Duration::from_secs(5), restart_delay(&policy, 4));
+    }
+
+    #[test]
+    fn next_restart_backs_off_and_gives_up() {
+        let policy = default_restart_policy();
+        let start = Instant::now();
+        let mut restarts = vec![];
+
+        // the first start is immediate
+        assert_eq!(
+            RestartDecision::Restart,
+            next_restart(&policy, &mut restarts, start)
+        );
+
+        // the second one has to wait for the base delay
+        let now = start + Duration::from_secs(10);
+        assert_eq!(
+            RestartDecision::Wait(Duration::from_secs(50)),
+            next_restart(&policy, &mut restarts, now)
+        );
+        let mut now = start + RESTART_BACKOFF_BASE;
+        assert_eq!(
+            RestartDecision::Restart,
+            next_restart(&policy, &mut restarts, now)
+        );
+
+        for _ in 2..MAX_RESTARTS_IN_WINDOW {
+            now += restart_delay(&policy, restarts.len());
+            assert_eq!(
+                RestartDecision::Restart,
+                next_restart(&policy, &mut restarts, now)
+            );
+        }
+
+        now += restart_delay(&policy, restarts.len());
+        assert_eq!(
+            RestartDecision::GiveUp,
+            next_restart(&policy, &mut restarts, now)
+        );
+    }
+
+    #[test]
+    fn next_restart_forgets_restarts_outside_window() {
+        let policy = default_restart_policy();
+        let start = Instant::now();
+        let mut restarts = vec![start];
+
+        let now = start + RESTART_WINDOW;
+        assert_eq!(
+            RestartDecision::Restart,
+            next_restart(&policy, &mut restarts, now)
+        );
+        assert_eq!(vec![now], restarts);
+    }
+
+    #[test]
+    fn restart_backoff_reports_state() {
+        let status = WatchdogStatus::new();
+        let metrics = Metrics::new();
+        let backoff =
+            RestartBackoff::new(default_restart_policy(), status.clone(), metrics.clone());
+
+        assert!(backoff.allow_restart());
+        assert_eq!(EdgeRuntimeState::Healthy, status.state());
+        assert!(metrics
+            .render()
+            .contains("iotedged_watchdog_restarts_total 1\n"));
+        assert_eq!(1, status.restarts());
+
+        assert!(!backoff.allow_restart());
+        assert_eq!(EdgeRuntimeState::BackingOff, status.state());
+        assert_eq!(1, status.restarts());
+
+        status.set_state(EdgeRuntimeState::Broken);
+        assert!(!backoff.allow_restart());
+    }
+
+    #[test]
+    fn health_check_without_activity_timeout_never_restarts() {
+        let health = HealthCheck::new(None, 1);
+
+        assert!(!health.is_wedged());
+        assert!(!health.is_wedged());
+    }
+
+    #[test]
+    fn health_check_restarts_after_failure_threshold() {
+        let health = HealthCheck::new(Some((ActivityMonitor::new(), Duration::from_secs(0))), 2);
+
+        assert!(!health.is_wedged());
+        assert!(health.is_wedged());
+        assert!(!health.is_wedged());
+        assert!(health.is_wedged());
+    }
+
+    #[test]
+    fn health_check_is_satisfied_by_recent_activity() {
+        let activity = ActivityMonitor::new();
+        let health = HealthCheck::new(Some((activity.clone(), Duration::from_secs(3600))), 1);
+
+        activity.record();
+
+        assert!(!health.is_wedged());
+        assert!(activity.idle_time() < Duration::from_secs(3600));
+    }
+
+    fn agent_spec(image: &str) -> ModuleSpec<String> {
+        ModuleSpec::new(
+            "edgeAgent".to_string(),
+            "docker".to_string(),
+            image.to_string(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn rollback_restores_spec_before_update() {
+        let rollback = AgentRollback::new("edgeAgent".to_string(), Duration::from_secs(3600));
+        rollback.start(&agent_spec("agent:1.0"));
+        rollback.record_update(&agent_spec("agent:2.0"));
+
+        assert!(!rollback.observe(true));
+        assert!(!rollback.observe(false));
+        assert!(!rollback.observe(false));
+        assert!(rollback.observe(false));
+
+        assert_eq!("agent:1.0", rollback.roll_back().unwrap().config());
+        assert!(!rollback.observe(false));
+        assert!(rollback.roll_back().is_none());
+    }
+
+    #[test]
+    fn rollback_skips_unconfirmed_updates_and_other_modules() {
+        let rollback = AgentRollback::new("edgeAgent".to_string(), Duration::from_secs(3600));
+        rollback.start(&agent_spec("agent:1.0"));
+
+        let other = ModuleSpec::new(
+            "tempSensor".to_string(),
+            "docker".to_string(),
+            "sensor:1.0".to_string(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap();
+        rollback.record_update(&other);
+        assert!(rollback.roll_back().is_none());
+
+        rollback.record_update(&agent_spec("agent:2.0"));
+        rollback.record_update(&agent_spec("agent:3.0"));
+        assert_eq!("agent:1.0", rollback.roll_back().unwrap().config());
+    }
+
+    #[test]
+    fn rollback_period_confirms_update() {
+        let rollback = AgentRollback::new("edgeAgent".to_string(), Duration::from_secs(0));
+        rollback.start(&agent_spec("agent:1.0"));
+        rollback.record_update(&agent_spec("agent:2.0"));
+
+        for _ in 0..ROLLBACK_FAILURE_THRESHOLD {
+            assert!(!rollback.observe(false));
+        }
+        assert!(rollback.roll_back().is_none());
+    }
+
+    #[test]
+    fn pending_rollback_is_kept_across_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(AGENT_ROLLBACK_FILENAME);
+        let period = Duration::from_secs(3600);
+
+        let rollback = AgentRollback::load("edgeAgent".to_string(), period, path.clone());
+        rollback.start(&agent_spec("agent:1.0"));
+        rollback.record_update(&agent_spec("agent:2.0"));
+
+        let rollback = AgentRollback::<String>::load("edgeAgent".to_string(), period, path.clone());
+        assert_eq!("agent:1.0", rollback.roll_back().unwrap().config());
+        assert!(!path.exists());
+    }
 }
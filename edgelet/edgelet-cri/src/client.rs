@@ -0,0 +1,226 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! A minimal gRPC client for talking to a CRI runtime over its socket.
+//!
+//! gRPC is plain HTTP/2: every call is a `POST /<service>/<method>` whose body
+//! is a single length-prefixed protobuf message, and whose outcome is reported
+//! in the `grpc-status` trailer (or header, for responses without a body).
+
+use std::convert::TryFrom;
+
+use failure::{Fail, ResultExt};
+use futures::{future, try_ready, Async, Future, Poll};
+use hyper::body::Payload;
+use hyper::header::{HeaderMap, CONTENT_TYPE, TE};
+use hyper::{Body, Client, Request, StatusCode};
+use url::percent_encoding::percent_decode;
+use url::Url;
+
+use edgelet_core::UrlExt;
+use edgelet_http::UrlConnector;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::proto::Message;
+
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_MESSAGE: &str = "grpc-message";
+
+const GRPC_STATUS_OK: u32 = 0;
+const GRPC_STATUS_NOT_FOUND: u32 = 5;
+
+#[derive(Clone)]
+pub struct CriClient {
+    client: Client<UrlConnector, Body>,
+    scheme: String,
+    base_path: String,
+}
+
+impl CriClient {
+    pub fn new(url: &Url) -> Result<Self> {
+        let client = Client::builder()
+            .http2_only(true)
+            .build(UrlConnector::new(url).context(ErrorKind::Initialization)?);
+
+        // extract base path - the bit that comes after the scheme
+        let base_path = url
+            .to_base_path()
+            .context(ErrorKind::Initialization)?
+            .to_str()
+            .ok_or(ErrorKind::Initialization)?
+            .to_string();
+
+        Ok(CriClient {
+            client,
+            scheme: url.scheme().to_string(),
+            base_path,
+        })
+    }
+
+    pub fn call<Req, Res>(
+        &self,
+        service: &str,
+        method: &str,
+        message: &Req,
+    ) -> impl Future<Item = Res, Error = Error> + Send
+    where
+        Req: Message,
+        Res: Message + Send + 'static,
+    {
+        let path = format!("/{}/{}", service, method);
+        let request = UrlConnector::build_hyper_uri(&self.scheme, &self.base_path, &path)
+            .context(ErrorKind::Hyper)
+            .and_then(|uri| {
+                Request::post(uri)
+                    .header(CONTENT_TYPE, "application/grpc")
+                    .header(TE, "trailers")
+                    .body(Body::from(encode_frame(&message.encode_to_vec())))
+                    .context(ErrorKind::Hyper)
+            })
+            .map_err(Error::from);
+
+        let client = self.client.clone();
+        future::result(request)
+            .and_then(move |request| {
+                client
+                    .request(request)
+                    .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+            })
+            .and_then(|response| {
+                if response.status() != StatusCode::OK {
+                    return future::Either::A(future::err(Error::from(ErrorKind::GrpcResponse)));
+                }
+
+                let (parts, body) = response.into_parts();
+                if parts.headers.contains_key(GRPC_STATUS) {
+                    // A "trailers-only" response, which is how errors are usually reported
+                    return future::Either::A(future::result(
+                        check_status(&parts.headers).map(|_| Res::default()),
+                    ));
+                }
+
+                future::Either::B(
+                    ReadBody {
+                        body,
+                        buf: Vec::new(),
+                    }
+                    .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                    .and_then(|(buf, trailers)| {
+                        check_status(&trailers.unwrap_or_default())?;
+                        Res::decode(decode_frame(&buf)?)
+                    }),
+                )
+            })
+    }
+}
+
+fn check_status(headers: &HeaderMap) -> Result<()> {
+    let status = headers
+        .get(GRPC_STATUS)
+        .and_then(|status| status.to_str().ok())
+        .and_then(|status| status.parse::<u32>().ok())
+        .ok_or(ErrorKind::GrpcResponse)?;
+    let message = headers
+        .get(GRPC_MESSAGE)
+        .map(|message| {
+            percent_decode(message.as_bytes())
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+        .unwrap_or_default();
+
+    match status {
+        GRPC_STATUS_OK => Ok(()),
+        GRPC_STATUS_NOT_FOUND => Err(Error::from(ErrorKind::NotFound(message))),
+        status => Err(Error::from(ErrorKind::Grpc(status, message))),
+    }
+}
+
+// Each gRPC message is prefixed with a compression flag byte and its length
+// as a big endian u32.
+fn encode_frame(message: &[u8]) -> Vec<u8> {
+    let len = u32::try_from(message.len()).expect("gRPC request is too large");
+
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(0);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(message);
+    frame
+}
+
+fn decode_frame(frame: &[u8]) -> Result<&[u8]> {
+    if frame.is_empty() {
+        return Ok(frame);
+    }
+
+    if frame.len() < 5 || frame[0] != 0 {
+        return Err(Error::from(ErrorKind::GrpcResponse));
+    }
+
+    let mut len = [0; 4];
+    len.copy_from_slice(&frame[1..5]);
+    let len = usize::try_from(u32::from_be_bytes(len)).map_err(|_| ErrorKind::GrpcResponse)?;
+
+    frame
+        .get(5..5 + len)
+        .ok_or_else(|| Error::from(ErrorKind::GrpcResponse))
+}
+
+// Reads a response body along with the trailers that follow it.
+struct ReadBody {
+    body: Body,
+    buf: Vec<u8>,
+}
+
+impl Future for ReadBody {
+    type Item = (Vec<u8>, Option<HeaderMap>);
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        while let Some(chunk) = try_ready!(self.body.poll_data()) {
+            self.buf.extend_from_slice(&chunk);
+        }
+
+        let trailers = try_ready!(self.body.poll_trailers());
+        Ok(Async::Ready((std::mem::take(&mut self.buf), trailers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn frame_round_trips() {
+        let frame = encode_frame(b"abc");
+        assert_eq!(vec![0, 0, 0, 0, 3, b'a', b'b', b'c'], frame);
+        assert_eq!(b"abc", decode_frame(&frame).unwrap());
+    }
+
+    #[test]
+    fn truncated_frame_fails() {
+        assert!(decode_frame(&[0, 0, 0, 0, 3, b'a']).is_err());
+        assert!(decode_frame(&[1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn status_not_found_maps_to_not_found() {
+        let mut headers = HeaderMap::new();
+        headers.insert(GRPC_STATUS, HeaderValue::from_static("5"));
+        headers.insert(
+            GRPC_MESSAGE,
+            HeaderValue::from_static("container%20not%20found"),
+        );
+
+        match check_status(&headers).unwrap_err().kind() {
+            ErrorKind::NotFound(message) => assert_eq!("container not found", message),
+            kind => panic!("Expected `NotFound` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn missing_status_fails() {
+        assert!(check_status(&HeaderMap::new()).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The subset of the CRI `runtime.v1` API that the module runtime uses.
+//!
+//! Field numbers are taken from the upstream `api.proto` in
+//! `k8s.io/cri-api/pkg/apis/runtime/v1`. Fields that aren't listed here are
+//! skipped when decoding.
+
+use std::collections::BTreeMap;
+
+use crate::error::Result;
+use crate::proto::{Encoder, Message, Value};
+
+pub const RUNTIME_SERVICE: &str = "runtime.v1.RuntimeService";
+pub const IMAGE_SERVICE: &str = "runtime.v1.ImageService";
+
+/// Values of the `ContainerState` enum.
+pub const CONTAINER_CREATED: i32 = 0;
+pub const CONTAINER_RUNNING: i32 = 1;
+pub const CONTAINER_EXITED: i32 = 2;
+
+/// Values of the `NamespaceMode` enum.
+pub const NAMESPACE_MODE_NODE: i32 = 2;
+
+macro_rules! message {
+    ($(#[$attr:meta])* pub struct $name:ident {
+        $($num:literal => $field:ident: $ty:ty as $kind:ident,)+
+    }) => {
+        $(#[$attr])*
+        #[derive(Clone, Debug, Default, PartialEq)]
+        pub struct $name {
+            $(pub $field: $ty,)+
+        }
+
+        impl Message for $name {
+            fn encode(&self, encoder: &mut Encoder) {
+                $(message!(@encode encoder, $kind, $num, self.$field);)+
+            }
+
+            fn merge_field(&mut self, field: u32, value: Value<'_>) -> Result<()> {
+                match field {
+                    $($num => message!(@merge self.$field, $kind, value),)+
+                    _ => (),
+                }
+                Ok(())
+            }
+        }
+    };
+
+    (@encode $encoder:ident, string, $num:literal, $value:expr) => { $encoder.string($num, &$value) };
+    (@encode $encoder:ident, bytes, $num:literal, $value:expr) => { $encoder.bytes($num, &$value) };
+    (@encode $encoder:ident, strings, $num:literal, $value:expr) => { $encoder.strings($num, &$value) };
+    (@encode $encoder:ident, int64, $num:literal, $value:expr) => { $encoder.int64($num, $value) };
+    (@encode $encoder:ident, int32, $num:literal, $value:expr) => { $encoder.int32($num, $value) };
+    (@encode $encoder:ident, uint32, $num:literal, $value:expr) => { $encoder.uint64($num, u64::from($value)) };
+    (@encode $encoder:ident, bool, $num:literal, $value:expr) => { $encoder.bool($num, $value) };
+    (@encode $encoder:ident, message, $num:literal, $value:expr) => { $encoder.message($num, $value.as_ref()) };
+    (@encode $encoder:ident, messages, $num:literal, $value:expr) => { $encoder.messages($num, &$value) };
+    (@encode $encoder:ident, map, $num:literal, $value:expr) => { $encoder.map($num, &$value) };
+
+    (@merge $field:expr, string, $value:ident) => { $field = $value.as_string()? };
+    (@merge $field:expr, bytes, $value:ident) => { $field = $value.as_bytes()?.to_vec() };
+    (@merge $field:expr, strings, $value:ident) => { $field.push($value.as_string()?) };
+    (@merge $field:expr, int64, $value:ident) => { $field = $value.as_i64()? };
+    (@merge $field:expr, int32, $value:ident) => { $field = $value.as_i32()? };
+    (@merge $field:expr, uint32, $value:ident) => { $field = $value.as_u32()? };
+    (@merge $field:expr, bool, $value:ident) => { $field = $value.as_bool()? };
+    (@merge $field:expr, message, $value:ident) => { $field = Some($value.as_message()?) };
+    (@merge $field:expr, messages, $value:ident) => { $field.push($value.as_message()?) };
+    (@merge $field:expr, map, $value:ident) => { $value.merge_map_entry(&mut $field)? };
+}
+
+/// A message without fields, used for the responses the runtime doesn't need
+/// anything from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Empty;
+
+impl Message for Empty {
+    fn encode(&self, _: &mut Encoder) {}
+
+    fn merge_field(&mut self, _: u32, _: Value<'_>) -> Result<()> {
+        Ok(())
+    }
+}
+
+message! {
+    pub struct VersionRequest {
+        1 => version: String as string,
+    }
+}
+
+message! {
+    pub struct VersionResponse {
+        1 => version: String as string,
+        2 => runtime_name: String as string,
+        3 => runtime_version: String as string,
+        4 => runtime_api_version: String as string,
+    }
+}
+
+message! {
+    pub struct NamespaceOption {
+        1 => network: i32 as int32,
+        2 => pid: i32 as int32,
+        3 => ipc: i32 as int32,
+    }
+}
+
+message! {
+    pub struct LinuxSandboxSecurityContext {
+        1 => namespace_options: Option<NamespaceOption> as message,
+    }
+}
+
+message! {
+    pub struct LinuxPodSandboxConfig {
+        1 => cgroup_parent: String as string,
+        2 => security_context: Option<LinuxSandboxSecurityContext> as message,
+    }
+}
+
+message! {
+    pub struct PodSandboxMetadata {
+        1 => name: String as string,
+        2 => uid: String as string,
+        3 => namespace: String as string,
+        4 => attempt: u32 as uint32,
+    }
+}
+
+message! {
+    pub struct PodSandboxConfig {
+        1 => metadata: Option<PodSandboxMetadata> as message,
+        2 => hostname: String as string,
+        3 => log_directory: String as string,
+        6 => labels: BTreeMap<String, String> as map,
+        7 => annotations: BTreeMap<String, String> as map,
+        8 => linux: Option<LinuxPodSandboxConfig> as message,
+    }
+}
+
+message! {
+    pub struct RunPodSandboxRequest {
+        1 => config: Option<PodSandboxConfig> as message,
+        2 => runtime_handler: String as string,
+    }
+}
+
+message! {
+    pub struct RunPodSandboxResponse {
+        1 => pod_sandbox_id: String as string,
+    }
+}
+
+message! {
+    pub struct PodSandboxIdRequest {
+        1 => pod_sandbox_id: String as string,
+    }
+}
+
+message! {
+    pub struct ContainerMetadata {
+        1 => name: String as string,
+        2 => attempt: u32 as uint32,
+    }
+}
+
+message! {
+    pub struct ImageSpec {
+        1 => image: String as string,
+    }
+}
+
+message! {
+    pub struct KeyValue {
+        1 => key: String as string,
+        2 => value: String as string,
+    }
+}
+
+message! {
+    pub struct Mount {
+        1 => container_path: String as string,
+        2 => host_path: String as string,
+        3 => readonly: bool as bool,
+    }
+}
+
+message! {
+    pub struct ContainerConfig {
+        1 => metadata: Option<ContainerMetadata> as message,
+        2 => image: Option<ImageSpec> as message,
+        3 => command: Vec<String> as strings,
+        4 => args: Vec<String> as strings,
+        5 => working_dir: String as string,
+        6 => envs: Vec<KeyValue> as messages,
+        7 => mounts: Vec<Mount> as messages,
+        9 => labels: BTreeMap<String, String> as map,
+        10 => annotations: BTreeMap<String, String> as map,
+        11 => log_path: String as string,
+    }
+}
+
+message! {
+    pub struct CreateContainerRequest {
+        1 => pod_sandbox_id: String as string,
+        2 => config: Option<ContainerConfig> as message,
+        3 => sandbox_config: Option<PodSandboxConfig> as message,
+    }
+}
+
+message! {
+    pub struct CreateContainerResponse {
+        1 => container_id: String as string,
+    }
+}
+
+message! {
+    pub struct ContainerIdRequest {
+        1 => container_id: String as string,
+    }
+}
+
+message! {
+    pub struct StopContainerRequest {
+        1 => container_id: String as string,
+        2 => timeout: i64 as int64,
+    }
+}
+
+message! {
+    pub struct ContainerFilter {
+        1 => id: String as string,
+        3 => pod_sandbox_id: String as string,
+        4 => label_selector: BTreeMap<String, String> as map,
+    }
+}
+
+message! {
+    pub struct ListContainersRequest {
+        1 => filter: Option<ContainerFilter> as message,
+    }
+}
+
+message! {
+    pub struct Container {
+        1 => id: String as string,
+        2 => pod_sandbox_id: String as string,
+        3 => metadata: Option<ContainerMetadata> as message,
+        4 => image: Option<ImageSpec> as message,
+        5 => image_ref: String as string,
+        6 => state: i32 as int32,
+        7 => created_at: i64 as int64,
+        8 => labels: BTreeMap<String, String> as map,
+        9 => annotations: BTreeMap<String, String> as map,
+    }
+}
+
+message! {
+    pub struct ListContainersResponse {
+        1 => containers: Vec<Container> as messages,
+    }
+}
+
+message! {
+    pub struct ContainerStatusRequest {
+        1 => container_id: String as string,
+        2 => verbose: bool as bool,
+    }
+}
+
+message! {
+    pub struct ContainerStatus {
+        1 => id: String as string,
+        2 => metadata: Option<ContainerMetadata> as message,
+        3 => state: i32 as int32,
+        4 => created_at: i64 as int64,
+        5 => started_at: i64 as int64,
+        6 => finished_at: i64 as int64,
+        7 => exit_code: i32 as int32,
+        8 => image: Option<ImageSpec> as message,
+        9 => image_ref: String as string,
+        10 => reason: String as string,
+        11 => message: String as string,
+        12 => labels: BTreeMap<String, String> as map,
+        13 => annotations: BTreeMap<String, String> as map,
+        15 => log_path: String as string,
+    }
+}
+
+message! {
+    pub struct ContainerStatusResponse {
+        1 => status: Option<ContainerStatus> as message,
+        2 => info: BTreeMap<String, String> as map,
+    }
+}
+
+message! {
+    pub struct ExecSyncRequest {
+        1 => container_id: String as string,
+        2 => cmd: Vec<String> as strings,
+        3 => timeout: i64 as int64,
+    }
+}
+
+message! {
+    pub struct ExecSyncResponse {
+        1 => stdout: Vec<u8> as bytes,
+        2 => stderr: Vec<u8> as bytes,
+        3 => exit_code: i32 as int32,
+    }
+}
+
+message! {
+    pub struct AuthConfig {
+        1 => username: String as string,
+        2 => password: String as string,
+        3 => auth: String as string,
+        4 => server_address: String as string,
+        5 => identity_token: String as string,
+        6 => registry_token: String as string,
+    }
+}
+
+message! {
+    pub struct PullImageRequest {
+        1 => image: Option<ImageSpec> as message,
+        2 => auth: Option<AuthConfig> as message,
+    }
+}
+
+message! {
+    pub struct PullImageResponse {
+        1 => image_ref: String as string,
+    }
+}
+
+message! {
+    pub struct RemoveImageRequest {
+        1 => image: Option<ImageSpec> as message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn container_config_round_trips() {
+        let mut labels = BTreeMap::new();
+        labels.insert("net.azure-devices.edge.owner".to_string(), "x".to_string());
+
+        let config = ContainerConfig {
+            metadata: Some(ContainerMetadata {
+                name: "edgeHub".to_string(),
+                attempt: 2,
+            }),
+            image: Some(ImageSpec {
+                image: "mcr.microsoft.com/azureiotedge-hub:1.0".to_string(),
+            }),
+            command: vec!["dotnet".to_string()],
+            args: vec!["Microsoft.Azure.Devices.Edge.Hub.Service.dll".to_string()],
+            envs: vec![KeyValue {
+                key: "k1".to_string(),
+                value: "v1".to_string(),
+            }],
+            mounts: vec![Mount {
+                container_path: "/var/run/iotedge/workload.sock".to_string(),
+                host_path: "/var/run/iotedge/workload.sock".to_string(),
+                readonly: true,
+            }],
+            labels,
+            ..ContainerConfig::default()
+        };
+
+        assert_eq!(
+            config,
+            ContainerConfig::decode(&config.encode_to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn exec_sync_response_round_trips_output() {
+        let response = ExecSyncResponse {
+            stdout: b"hello\n".to_vec(),
+            stderr: vec![0xff, 0x00],
+            exit_code: 3,
+        };
+
+        assert_eq!(
+            response,
+            ExecSyncResponse::decode(&response.encode_to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn container_status_response_decodes_info_map() {
+        // ContainerStatusResponse { status: { id: "c1", state: EXITED, exit_code: 1 }, info: { "pid": "42" } }
+        let buf = [
+            0x0a, 0x08, 0x0a, 0x02, b'c', b'1', 0x18, 0x02, 0x38, 0x01, 0x12, 0x09, 0x0a, 0x03,
+            b'p', b'i', b'd', 0x12, 0x02, b'4', b'2',
+        ];
+
+        let response = ContainerStatusResponse::decode(&buf).unwrap();
+        let status = response.status.unwrap();
+        assert_eq!("c1", status.id);
+        assert_eq!(CONTAINER_EXITED, status.state);
+        assert_eq!(1, status.exit_code);
+        assert_eq!(Some(&"42".to_string()), response.info.get("pid"));
+    }
+}
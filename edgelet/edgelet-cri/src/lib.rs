@@ -0,0 +1,29 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::module_name_repetitions,
+    clippy::must_use_candidate,
+    clippy::too_many_lines,
+    clippy::use_self
+)]
+
+//! Module runtime backed by a container runtime that implements the
+//! Kubernetes Container Runtime Interface (CRI), such as containerd. This
+//! allows modules to run on hosts that don't have dockerd installed, like
+//! k3s nodes.
+
+mod client;
+mod cri;
+mod error;
+mod module;
+mod proto;
+mod runtime;
+mod settings;
+
+pub use error::{Error, ErrorKind, Result};
+pub use module::CriModule;
+pub use runtime::CriModuleRuntime;
+pub use settings::{CriRuntime, Settings};
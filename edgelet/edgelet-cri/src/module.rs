@@ -0,0 +1,526 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use chrono::prelude::*;
+use failure::ResultExt;
+use futures::Future;
+
+use docker::models::ContainerCreateBody;
+use edgelet_core::{Module, ModuleRuntimeState, ModuleSpec, ModuleStatus, RuntimeOperation};
+use edgelet_docker::{DockerConfig, MODULE_TYPE};
+use edgelet_utils::ensure_not_empty_with_context;
+
+use crate::client::CriClient;
+use crate::cri::{
+    self, ContainerConfig, ContainerMetadata, ContainerStatus, ContainerStatusRequest,
+    ContainerStatusResponse, ImageSpec, KeyValue, LinuxPodSandboxConfig,
+    LinuxSandboxSecurityContext, Mount, NamespaceOption, PodSandboxConfig, PodSandboxMetadata,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::proto::Message;
+
+pub const LABEL_KEY: &str = "net.azure-devices.edge.owner";
+pub const LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
+pub const MODULE_NAME_LABEL_KEY: &str = "net.azure-devices.edge.module";
+
+/// The container config a module was created with, kept on the container so
+/// that it can be recreated. CRI runtimes can't start a container again once it
+/// has exited.
+pub const CONTAINER_CONFIG_ANNOTATION_KEY: &str = "net.azure-devices.edge.container-config";
+
+/// Pod sandboxes are created in this namespace, one sandbox per module.
+const SANDBOX_NAMESPACE: &str = "iotedge";
+
+pub struct CriModule {
+    client: CriClient,
+    id: String,
+    name: String,
+    config: DockerConfig,
+}
+
+impl std::fmt::Debug for CriModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CriModule")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl CriModule {
+    pub(crate) fn new(
+        client: CriClient,
+        id: String,
+        name: String,
+        config: DockerConfig,
+    ) -> Result<Self> {
+        ensure_not_empty_with_context(&name, || ErrorKind::InvalidModuleName(name.clone()))?;
+
+        Ok(CriModule {
+            client,
+            id,
+            name,
+            config,
+        })
+    }
+
+    /// The id the CRI runtime assigned to the module's container.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Module for CriModule {
+    type Config = DockerConfig;
+    type Error = Error;
+    type RuntimeStateFuture =
+        Box<dyn Future<Item = ModuleRuntimeState, Error = Self::Error> + Send>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn type_(&self) -> &str {
+        MODULE_TYPE
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn runtime_state(&self) -> Self::RuntimeStateFuture {
+        let name = self.name.clone();
+        Box::new(
+            self.client
+                .call(
+                    cri::RUNTIME_SERVICE,
+                    "ContainerStatus",
+                    &ContainerStatusRequest {
+                        container_id: self.id.clone(),
+                        verbose: true,
+                    },
+                )
+                .map(|response: ContainerStatusResponse| runtime_state(&response))
+                .map_err(|err| {
+                    Error::from_cri_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name)),
+                    )
+                }),
+        )
+    }
+}
+
+pub fn runtime_state(response: &ContainerStatusResponse) -> ModuleRuntimeState {
+    let status = response.status.clone().unwrap_or_default();
+
+    let (module_status, status_description) = match status.state {
+        cri::CONTAINER_CREATED => (ModuleStatus::Stopped, "created"),
+        cri::CONTAINER_RUNNING => (ModuleStatus::Running, "running"),
+        cri::CONTAINER_EXITED if status.exit_code == 0 => (ModuleStatus::Stopped, "exited"),
+        cri::CONTAINER_EXITED => (ModuleStatus::Failed, "exited"),
+        _ => (ModuleStatus::Unknown, "unknown"),
+    };
+
+    let exit_code = if status.state == cri::CONTAINER_EXITED {
+        Some(i64::from(status.exit_code))
+    } else {
+        None
+    };
+
+    let image_id = if status.image_ref.is_empty() {
+        None
+    } else {
+        Some(status.image_ref.clone())
+    };
+
+    ModuleRuntimeState::default()
+        .with_status(module_status)
+        .with_exit_code(exit_code)
+        .with_status_description(Some(status_description.to_string()))
+        .with_started_at(timestamp(status.started_at))
+        .with_finished_at(timestamp(status.finished_at))
+        .with_image_id(image_id)
+        .with_pid(pid(response))
+        .with_restart_count(attempt(&status))
+}
+
+fn timestamp(nanos: i64) -> Option<DateTime<Utc>> {
+    if nanos > 0 {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let subsec_nanos = (nanos % 1_000_000_000) as u32;
+        Some(Utc.timestamp(nanos / 1_000_000_000, subsec_nanos))
+    } else {
+        None
+    }
+}
+
+// containerd reports the pid of the container's process in the "info" JSON
+// document of a verbose status response.
+fn pid(response: &ContainerStatusResponse) -> Option<i32> {
+    response
+        .info
+        .get("info")
+        .and_then(|info| serde_json::from_str::<serde_json::Value>(info).ok())
+        .and_then(|info| info["pid"].as_i64())
+        .and_then(|pid| std::convert::TryFrom::try_from(pid).ok())
+        .filter(|pid| *pid > 0)
+}
+
+fn attempt(status: &ContainerStatus) -> Option<i32> {
+    status
+        .metadata
+        .as_ref()
+        .and_then(|metadata| std::convert::TryFrom::try_from(metadata.attempt).ok())
+}
+
+/// The pod sandbox a module's container runs in. Sandboxes share the host's
+/// network namespace, so modules reach each other the same way they reach
+/// the host.
+pub fn sandbox_config(
+    name: &str,
+    hostname: Option<&str>,
+    log_directory: &Path,
+) -> PodSandboxConfig {
+    let mut labels = BTreeMap::new();
+    labels.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+    labels.insert(MODULE_NAME_LABEL_KEY.to_string(), name.to_string());
+
+    PodSandboxConfig {
+        metadata: Some(PodSandboxMetadata {
+            name: name.to_string(),
+            uid: name.to_string(),
+            namespace: SANDBOX_NAMESPACE.to_string(),
+            attempt: 0,
+        }),
+        hostname: hostname.unwrap_or_default().to_string(),
+        log_directory: log_directory.join(name).to_string_lossy().into_owned(),
+        labels,
+        linux: Some(LinuxPodSandboxConfig {
+            security_context: Some(LinuxSandboxSecurityContext {
+                namespace_options: Some(NamespaceOption {
+                    network: cri::NAMESPACE_MODE_NODE,
+                    ..NamespaceOption::default()
+                }),
+            }),
+            ..LinuxPodSandboxConfig::default()
+        }),
+        ..PodSandboxConfig::default()
+    }
+}
+
+/// Translates a module spec into a CRI container config.
+///
+/// Only the parts of the docker create options that have an equivalent in CRI
+/// are used: `Entrypoint`, `Cmd`, `Env`, `Labels` and `HostConfig.Binds` for
+/// host paths. Binds of named volumes and port bindings that publish a port on
+/// another host port are rejected, rather than dropped, since the module would
+/// not run as it does with the docker runtime.
+pub fn container_config(module: &ModuleSpec<DockerConfig>) -> Result<ContainerConfig> {
+    let create_options = module
+        .config()
+        .clone_create_options()
+        .context(ErrorKind::CloneCreateOptions)?;
+    check_port_bindings(&create_options)?;
+
+    let mut config = ContainerConfig {
+        metadata: Some(ContainerMetadata {
+            name: module.name().to_string(),
+            attempt: 0,
+        }),
+        image: Some(ImageSpec {
+            image: module.config().image().to_string(),
+        }),
+        command: create_options
+            .entrypoint()
+            .map_or_else(Vec::new, ToOwned::to_owned),
+        args: create_options
+            .cmd()
+            .map_or_else(Vec::new, ToOwned::to_owned),
+        envs: envs(&create_options, module),
+        mounts: mounts(&create_options)?,
+        log_path: format!("{}.log", module.name()),
+        ..ContainerConfig::default()
+    };
+
+    if let Some(labels) = create_options.labels() {
+        config.labels.extend(labels.clone());
+    }
+    config
+        .labels
+        .insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+    config
+        .labels
+        .insert(MODULE_NAME_LABEL_KEY.to_string(), module.name().to_string());
+
+    let stashed = base64::encode(&config.encode_to_vec());
+    config
+        .annotations
+        .insert(CONTAINER_CONFIG_ANNOTATION_KEY.to_string(), stashed);
+
+    Ok(config)
+}
+
+/// Reads back the container config stashed by `container_config`.
+pub fn stashed_container_config(status: &ContainerStatus) -> Option<ContainerConfig> {
+    let stashed = status.annotations.get(CONTAINER_CONFIG_ANNOTATION_KEY)?;
+    let mut config = base64::decode(stashed)
+        .ok()
+        .and_then(|buf| ContainerConfig::decode(&buf).ok())?;
+    config
+        .annotations
+        .insert(CONTAINER_CONFIG_ANNOTATION_KEY.to_string(), stashed.clone());
+    Some(config)
+}
+
+// Variables in the create options take precedence over the module's, as with
+// the docker runtime.
+fn envs(create_options: &ContainerCreateBody, module: &ModuleSpec<DockerConfig>) -> Vec<KeyValue> {
+    let mut envs: BTreeMap<&str, &str> = module
+        .env()
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+
+    if let Some(env) = create_options.env() {
+        envs.extend(env.iter().filter_map(|s| {
+            let mut tokens = s.splitn(2, '=');
+            tokens.next().map(|key| (key, tokens.next().unwrap_or("")))
+        }));
+    }
+
+    envs.into_iter()
+        .map(|(key, value)| KeyValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+        .collect()
+}
+
+fn mounts(create_options: &ContainerCreateBody) -> Result<Vec<Mount>> {
+    let binds = create_options
+        .host_config()
+        .and_then(|host_config| host_config.binds())
+        .unwrap_or_default();
+
+    binds
+        .iter()
+        .map(|bind| {
+            let mut parts = bind.splitn(3, ':');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(host_path), Some(container_path), options)
+                    if Path::new(host_path).is_absolute() =>
+                {
+                    Ok(Mount {
+                        container_path: container_path.to_string(),
+                        host_path: host_path.to_string(),
+                        readonly: options.map_or(false, |options| {
+                            options.split(',').any(|option| option == "ro")
+                        }),
+                    })
+                }
+                _ => Err(Error::from(ErrorKind::UnsupportedCreateOption(format!(
+                    "HostConfig.Binds {:?}, only host paths can be mounted",
+                    bind
+                )))),
+            }
+        })
+        .collect()
+}
+
+// The sandboxes use the network of the host, so a port is published on the
+// host port of the same number and on no other.
+fn check_port_bindings(create_options: &ContainerCreateBody) -> Result<()> {
+    let port_bindings = create_options
+        .host_config()
+        .and_then(|host_config| host_config.port_bindings());
+
+    for (port, bindings) in port_bindings.into_iter().flatten() {
+        let container_port = port.split('/').next().unwrap_or_default();
+        for host_port in bindings.iter().filter_map(|binding| binding.host_port()) {
+            if !host_port.is_empty() && host_port != container_port {
+                return Err(Error::from(ErrorKind::UnsupportedCreateOption(format!(
+                    "HostConfig.PortBindings {:?} to host port {}, ports can only be published on the same host port",
+                    port, host_port
+                ))));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use docker::models::{HostConfig, HostConfigPortBindings};
+    use edgelet_core::ImagePullPolicy;
+
+    use super::*;
+
+    fn module_spec(create_options: ContainerCreateBody) -> ModuleSpec<DockerConfig> {
+        let mut env = HashMap::new();
+        env.insert("k1".to_string(), "v1".to_string());
+        env.insert("k2".to_string(), "v2".to_string());
+
+        ModuleSpec::new(
+            "mod1".to_string(),
+            "docker".to_string(),
+            DockerConfig::new("ubuntu:18.04".to_string(), create_options, None).unwrap(),
+            env,
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn container_config_translates_create_options() {
+        let mut labels = HashMap::new();
+        labels.insert("l1".to_string(), "lv1".to_string());
+
+        let create_options = ContainerCreateBody::new()
+            .with_entrypoint(vec!["/bin/sh".to_string()])
+            .with_cmd(vec!["-c".to_string(), "sleep 1".to_string()])
+            .with_env(vec!["k2=v02".to_string(), "k3=v3".to_string()])
+            .with_labels(labels)
+            .with_host_config(HostConfig::new().with_binds(vec![
+                "/var/run/iotedge/workload.sock:/var/run/iotedge/workload.sock".to_string(),
+                "/etc/data:/data:ro".to_string(),
+            ]));
+
+        let config = container_config(&module_spec(create_options)).unwrap();
+
+        assert_eq!("mod1", config.metadata.as_ref().unwrap().name);
+        assert_eq!("ubuntu:18.04", config.image.as_ref().unwrap().image);
+        assert_eq!(vec!["/bin/sh"], config.command);
+        assert_eq!(vec!["-c", "sleep 1"], config.args);
+        assert_eq!(
+            vec![("k1", "v1"), ("k2", "v02"), ("k3", "v3")],
+            config
+                .envs
+                .iter()
+                .map(|kv| (kv.key.as_str(), kv.value.as_str()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(2, config.mounts.len());
+        assert!(!config.mounts[0].readonly);
+        assert_eq!("/data", config.mounts[1].container_path);
+        assert!(config.mounts[1].readonly);
+        assert_eq!(Some(&"lv1".to_string()), config.labels.get("l1"));
+        assert_eq!(Some(&LABEL_VALUE.to_string()), config.labels.get(LABEL_KEY));
+        assert_eq!(
+            Some(&"mod1".to_string()),
+            config.labels.get(MODULE_NAME_LABEL_KEY)
+        );
+        assert_eq!("mod1.log", config.log_path);
+    }
+
+    #[test]
+    fn container_config_rejects_named_volumes() {
+        let create_options = ContainerCreateBody::new()
+            .with_host_config(HostConfig::new().with_binds(vec!["volume:/volume".to_string()]));
+
+        let err = container_config(&module_spec(create_options)).unwrap_err();
+        assert_eq!(
+            "Create option not supported by the CRI runtime: HostConfig.Binds \"volume:/volume\", only host paths can be mounted",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn container_config_rejects_port_bindings_to_other_host_ports() {
+        let port_bindings = |host_port: &str| {
+            let mut port_bindings = HashMap::new();
+            port_bindings.insert(
+                "8080/tcp".to_string(),
+                vec![HostConfigPortBindings::new().with_host_port(host_port.to_string())],
+            );
+            ContainerCreateBody::new()
+                .with_host_config(HostConfig::new().with_port_bindings(port_bindings))
+        };
+
+        assert!(container_config(&module_spec(port_bindings("8080"))).is_ok());
+        assert!(container_config(&module_spec(port_bindings("80"))).is_err());
+    }
+
+    #[test]
+    fn container_config_is_stashed_in_annotations() {
+        let config = container_config(&module_spec(ContainerCreateBody::new())).unwrap();
+
+        let status = ContainerStatus {
+            annotations: config.annotations.clone(),
+            ..ContainerStatus::default()
+        };
+
+        assert_eq!(Some(config), stashed_container_config(&status));
+    }
+
+    #[test]
+    fn sandbox_uses_host_network() {
+        let config = sandbox_config("mod1", None, Path::new("/var/log/iotedge/modules"));
+
+        assert_eq!("/var/log/iotedge/modules/mod1", config.log_directory);
+        assert_eq!(
+            cri::NAMESPACE_MODE_NODE,
+            config
+                .linux
+                .and_then(|linux| linux.security_context)
+                .and_then(|context| context.namespace_options)
+                .unwrap()
+                .network
+        );
+    }
+
+    #[test]
+    fn runtime_state_of_exited_container() {
+        let mut info = BTreeMap::new();
+        info.insert("info".to_string(), r#"{"pid":0}"#.to_string());
+
+        let response = ContainerStatusResponse {
+            status: Some(ContainerStatus {
+                state: cri::CONTAINER_EXITED,
+                exit_code: 137,
+                started_at: 1_500_000_000_000_000_000,
+                finished_at: 1_500_000_001_000_000_000,
+                image_ref: "sha256:abc".to_string(),
+                metadata: Some(ContainerMetadata {
+                    name: "mod1".to_string(),
+                    attempt: 3,
+                }),
+                ..ContainerStatus::default()
+            }),
+            info,
+        };
+
+        let state = runtime_state(&response);
+        assert_eq!(&ModuleStatus::Failed, state.status());
+        assert_eq!(Some(137), state.exit_code());
+        assert_eq!(Some("exited"), state.status_description());
+        assert_eq!(Some(&Utc.timestamp(1_500_000_000, 0)), state.started_at());
+        assert_eq!(Some("sha256:abc"), state.image_id());
+        assert_eq!(None, state.pid());
+        assert_eq!(Some(3), state.restart_count());
+    }
+
+    #[test]
+    fn runtime_state_of_running_container_has_pid() {
+        let mut info = BTreeMap::new();
+        info.insert("info".to_string(), r#"{"pid":4242}"#.to_string());
+
+        let response = ContainerStatusResponse {
+            status: Some(ContainerStatus {
+                state: cri::CONTAINER_RUNNING,
+                ..ContainerStatus::default()
+            }),
+            info,
+        };
+
+        let state = runtime_state(&response);
+        assert_eq!(&ModuleStatus::Running, state.status());
+        assert_eq!(None, state.exit_code());
+        assert_eq!(Some(4242), state.pid());
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! A minimal encoder and decoder for the protobuf (proto3) wire format.
+//!
+//! The CRI API is only a handful of small messages, so rather than pulling in
+//! a protobuf code generator the messages in `cri.rs` implement `Message` by
+//! hand on top of the primitives in this module.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::error::{Error, ErrorKind, Result};
+
+const WIRE_TYPE_VARINT: u8 = 0;
+const WIRE_TYPE_FIXED64: u8 = 1;
+const WIRE_TYPE_LENGTH_DELIMITED: u8 = 2;
+const WIRE_TYPE_FIXED32: u8 = 5;
+
+pub trait Message: Default {
+    fn encode(&self, encoder: &mut Encoder);
+
+    fn merge_field(&mut self, field: u32, value: Value<'_>) -> Result<()>;
+
+    fn encode_to_vec(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        self.encode(&mut encoder);
+        encoder.buf
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        let mut message = Self::default();
+        let mut decoder = Decoder { buf };
+        while let Some((field, value)) = decoder.next_field()? {
+            message.merge_field(field, value)?;
+        }
+        Ok(message)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            #[allow(clippy::cast_possible_truncation)]
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(u64::from(field) << 3 | u64::from(wire_type));
+    }
+
+    fn length_delimited(&mut self, field: u32, value: &[u8]) {
+        self.key(field, WIRE_TYPE_LENGTH_DELIMITED);
+        self.varint(value.len() as u64);
+        self.buf.extend_from_slice(value);
+    }
+
+    // Scalars that hold their default value are not written, as in proto3.
+
+    pub fn uint64(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WIRE_TYPE_VARINT);
+            self.varint(value);
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub fn int64(&mut self, field: u32, value: i64) {
+        self.uint64(field, value as u64);
+    }
+
+    pub fn int32(&mut self, field: u32, value: i32) {
+        self.int64(field, i64::from(value));
+    }
+
+    pub fn bool(&mut self, field: u32, value: bool) {
+        self.uint64(field, u64::from(value));
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) {
+        if !value.is_empty() {
+            self.length_delimited(field, value.as_bytes());
+        }
+    }
+
+    pub fn bytes(&mut self, field: u32, value: &[u8]) {
+        if !value.is_empty() {
+            self.length_delimited(field, value);
+        }
+    }
+
+    pub fn strings(&mut self, field: u32, values: &[String]) {
+        for value in values {
+            self.length_delimited(field, value.as_bytes());
+        }
+    }
+
+    pub fn message<M: Message>(&mut self, field: u32, value: Option<&M>) {
+        if let Some(value) = value {
+            self.length_delimited(field, &value.encode_to_vec());
+        }
+    }
+
+    pub fn messages<M: Message>(&mut self, field: u32, values: &[M]) {
+        for value in values {
+            self.length_delimited(field, &value.encode_to_vec());
+        }
+    }
+
+    pub fn map(&mut self, field: u32, values: &BTreeMap<String, String>) {
+        for (key, value) in values {
+            let mut entry = Encoder::default();
+            entry.string(1, key);
+            entry.string(2, value);
+            self.length_delimited(field, &entry.buf);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Value<'a> {
+    Varint(u64),
+    Fixed64,
+    LengthDelimited(&'a [u8]),
+    Fixed32,
+}
+
+impl<'a> Value<'a> {
+    pub fn as_u64(self) -> Result<u64> {
+        match self {
+            Value::Varint(value) => Ok(value),
+            _ => Err(Error::from(ErrorKind::InvalidProtobuf("expected varint"))),
+        }
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn as_i64(self) -> Result<i64> {
+        self.as_u64().map(|value| value as i64)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn as_i32(self) -> Result<i32> {
+        self.as_i64().map(|value| value as i32)
+    }
+
+    pub fn as_u32(self) -> Result<u32> {
+        self.as_u64().and_then(|value| {
+            u32::try_from(value)
+                .map_err(|_| Error::from(ErrorKind::InvalidProtobuf("uint32 out of range")))
+        })
+    }
+
+    pub fn as_bool(self) -> Result<bool> {
+        self.as_u64().map(|value| value != 0)
+    }
+
+    pub fn as_bytes(self) -> Result<&'a [u8]> {
+        match self {
+            Value::LengthDelimited(value) => Ok(value),
+            _ => Err(Error::from(ErrorKind::InvalidProtobuf(
+                "expected length-delimited field",
+            ))),
+        }
+    }
+
+    pub fn as_string(self) -> Result<String> {
+        self.as_bytes().and_then(|value| {
+            String::from_utf8(value.to_vec())
+                .map_err(|_| Error::from(ErrorKind::InvalidProtobuf("string is not valid UTF-8")))
+        })
+    }
+
+    pub fn as_message<M: Message>(self) -> Result<M> {
+        self.as_bytes().and_then(M::decode)
+    }
+
+    pub fn merge_map_entry(self, map: &mut BTreeMap<String, String>) -> Result<()> {
+        let mut decoder = Decoder {
+            buf: self.as_bytes()?,
+        };
+        let (mut key, mut value) = (String::new(), String::new());
+        while let Some((field, field_value)) = decoder.next_field()? {
+            match field {
+                1 => key = field_value.as_string()?,
+                2 => value = field_value.as_string()?,
+                _ => (),
+            }
+        }
+        map.insert(key, value);
+        Ok(())
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(Error::from(ErrorKind::InvalidProtobuf(
+                "unexpected end of message",
+            )));
+        }
+        let (value, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(value)
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::from(ErrorKind::InvalidProtobuf(
+            "varint is too long",
+        )))
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u32, Value<'a>)>> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+        let field = u32::try_from(key >> 3)
+            .map_err(|_| Error::from(ErrorKind::InvalidProtobuf("field number out of range")))?;
+        #[allow(clippy::cast_possible_truncation)]
+        let wire_type = (key & 0x7) as u8;
+        let value = match wire_type {
+            WIRE_TYPE_VARINT => Value::Varint(self.varint()?),
+            WIRE_TYPE_FIXED64 => {
+                self.take(8)?;
+                Value::Fixed64
+            }
+            WIRE_TYPE_LENGTH_DELIMITED => {
+                let len = usize::try_from(self.varint()?).map_err(|_| {
+                    Error::from(ErrorKind::InvalidProtobuf("field length out of range"))
+                })?;
+                Value::LengthDelimited(self.take(len)?)
+            }
+            WIRE_TYPE_FIXED32 => {
+                self.take(4)?;
+                Value::Fixed32
+            }
+            _ => return Err(Error::from(ErrorKind::InvalidProtobuf("unknown wire type"))),
+        };
+
+        Ok(Some((field, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct TestMessage {
+        id: String,
+        count: i64,
+        enabled: bool,
+        names: Vec<String>,
+        labels: BTreeMap<String, String>,
+        child: Option<Box<TestMessage>>,
+    }
+
+    impl Message for Box<TestMessage> {
+        fn encode(&self, encoder: &mut Encoder) {
+            (**self).encode(encoder);
+        }
+
+        fn merge_field(&mut self, field: u32, value: Value<'_>) -> Result<()> {
+            (**self).merge_field(field, value)
+        }
+    }
+
+    impl Message for TestMessage {
+        fn encode(&self, encoder: &mut Encoder) {
+            encoder.string(1, &self.id);
+            encoder.int64(2, self.count);
+            encoder.bool(3, self.enabled);
+            encoder.strings(4, &self.names);
+            encoder.map(5, &self.labels);
+            encoder.message(6, self.child.as_ref());
+        }
+
+        fn merge_field(&mut self, field: u32, value: Value<'_>) -> Result<()> {
+            match field {
+                1 => self.id = value.as_string()?,
+                2 => self.count = value.as_i64()?,
+                3 => self.enabled = value.as_bool()?,
+                4 => self.names.push(value.as_string()?),
+                5 => value.merge_map_entry(&mut self.labels)?,
+                6 => self.child = Some(value.as_message()?),
+                _ => (),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encodes_known_wire_format() {
+        let message = TestMessage {
+            id: "abc".to_string(),
+            count: 300,
+            enabled: true,
+            ..TestMessage::default()
+        };
+
+        assert_eq!(
+            vec![0x0a, 0x03, b'a', b'b', b'c', 0x10, 0xac, 0x02, 0x18, 0x01],
+            message.encode_to_vec()
+        );
+    }
+
+    #[test]
+    fn default_scalars_are_not_encoded() {
+        assert!(TestMessage::default().encode_to_vec().is_empty());
+    }
+
+    #[test]
+    fn negative_numbers_round_trip() {
+        let message = TestMessage {
+            count: -1,
+            ..TestMessage::default()
+        };
+
+        let buf = message.encode_to_vec();
+        assert_eq!(11, buf.len());
+        assert_eq!(message, TestMessage::decode(&buf).unwrap());
+    }
+
+    #[test]
+    fn nested_messages_round_trip() {
+        let mut labels = BTreeMap::new();
+        labels.insert("k1".to_string(), "v1".to_string());
+        labels.insert("k2".to_string(), String::new());
+
+        let message = TestMessage {
+            id: "parent".to_string(),
+            names: vec!["a".to_string(), String::new(), "c".to_string()],
+            labels,
+            child: Some(Box::new(TestMessage {
+                id: "child".to_string(),
+                count: 5,
+                ..TestMessage::default()
+            })),
+            ..TestMessage::default()
+        };
+
+        assert_eq!(
+            message,
+            TestMessage::decode(&message.encode_to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_fields_are_skipped() {
+        // field 15 as fixed64, field 16 as fixed32, field 17 as a string, then field 1
+        let buf = [
+            0x79, 1, 2, 3, 4, 5, 6, 7, 8, 0x85, 0x01, 1, 2, 3, 4, 0x8a, 0x01, 0x01, b'x', 0x0a,
+            0x01, b'y',
+        ];
+
+        let message = TestMessage::decode(&buf).unwrap();
+        assert_eq!("y", message.id);
+    }
+
+    #[test]
+    fn truncated_message_fails() {
+        let buf = [0x0a, 0x05, b'a'];
+        let err = TestMessage::decode(&buf).unwrap_err();
+        match err.kind() {
+            ErrorKind::InvalidProtobuf(_) => (),
+            kind => panic!("Expected `InvalidProtobuf` but got {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,1133 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::DateTime;
+use failure::{Fail, ResultExt};
+use futures::future::Either;
+use futures::prelude::*;
+use futures::{future, stream, Async, Stream};
+use hyper::{Body, Chunk as HyperChunk, Request};
+use log::{debug, info, warn, Level};
+
+use docker::models::ContainerCreateBody;
+use edgelet_core::{
+    AuthId, Authenticator, GetTrustBundle, ImageInfo, LogOptions, LogTail, MakeModuleRuntime,
+    Module, ModuleEvent, ModuleExecResult, ModuleId, ModuleProcesses, ModuleRegistry,
+    ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, PrunedImages,
+    RegistryOperation, RuntimeOperation, SystemInfo, SystemResources,
+};
+use edgelet_docker::{validate_module, DockerConfig, MODULE_TYPE};
+use edgelet_http::Pid;
+use edgelet_utils::log_failure;
+use provisioning::ProvisioningResult;
+
+use crate::client::CriClient;
+use crate::cri::{
+    self, AuthConfig, Container, ContainerFilter, ContainerIdRequest, ContainerStatusRequest,
+    ContainerStatusResponse, CreateContainerRequest, CreateContainerResponse, Empty,
+    ExecSyncRequest, ExecSyncResponse, ImageSpec, ListContainersRequest, ListContainersResponse,
+    PodSandboxIdRequest, PullImageRequest, PullImageResponse, RemoveImageRequest,
+    RunPodSandboxRequest, RunPodSandboxResponse, StopContainerRequest, VersionRequest,
+    VersionResponse,
+};
+use crate::error::{Error, ErrorKind, Result};
+use crate::module::{
+    container_config, runtime_state, sandbox_config, stashed_container_config, CriModule,
+    LABEL_KEY, LABEL_VALUE, MODULE_NAME_LABEL_KEY,
+};
+use crate::settings::Settings;
+
+/// How long a module is given to stop before it is killed, when the caller
+/// doesn't say. This matches docker's default.
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub struct CriModuleRuntime {
+    client: CriClient,
+    settings: Settings,
+}
+
+impl CriModuleRuntime {
+    fn container_filter(name: Option<&str>) -> ContainerFilter {
+        let mut label_selector = BTreeMap::new();
+        label_selector.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+        if let Some(name) = name {
+            label_selector.insert(MODULE_NAME_LABEL_KEY.to_string(), name.to_string());
+        }
+
+        ContainerFilter {
+            label_selector,
+            ..ContainerFilter::default()
+        }
+    }
+
+    fn list_containers(
+        &self,
+        name: Option<&str>,
+    ) -> impl Future<Item = Vec<Container>, Error = Error> + Send {
+        self.client
+            .call(
+                cri::RUNTIME_SERVICE,
+                "ListContainers",
+                &ListContainersRequest {
+                    filter: Some(Self::container_filter(name)),
+                },
+            )
+            .map(|response: ListContainersResponse| response.containers)
+    }
+
+    // Finds the most recently created container of a module.
+    fn find_container(&self, name: &str) -> impl Future<Item = Container, Error = Error> + Send {
+        let name = name.to_string();
+        self.list_containers(Some(&name))
+            .and_then(move |containers| {
+                containers
+                    .into_iter()
+                    .max_by_key(|container| container.created_at)
+                    .ok_or_else(|| {
+                        Error::from(ErrorKind::NotFound(format!("No such module: {}", name)))
+                    })
+            })
+    }
+
+    fn container_status(
+        &self,
+        id: &str,
+    ) -> impl Future<Item = ContainerStatusResponse, Error = Error> + Send {
+        self.client.call(
+            cri::RUNTIME_SERVICE,
+            "ContainerStatus",
+            &ContainerStatusRequest {
+                container_id: id.to_string(),
+                verbose: true,
+            },
+        )
+    }
+
+    fn module(&self, container: Container) -> Result<CriModule> {
+        let name = container
+            .metadata
+            .map(|metadata| metadata.name)
+            .unwrap_or_default();
+        let image = container.image.map(|image| image.image).unwrap_or_default();
+
+        let mut config = DockerConfig::new(image, ContainerCreateBody::new(), None).context(
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone())),
+        )?;
+        if !container.image_ref.is_empty() {
+            config = config.with_image_id(container.image_ref);
+        }
+
+        CriModule::new(self.client.clone(), container.id, name, config)
+    }
+
+    // CRI runtimes only start containers that were just created, so a module
+    // that has exited is started again by replacing its container with a new
+    // one in the same pod sandbox.
+    fn recreate(
+        &self,
+        name: &str,
+        container: &Container,
+        response: &ContainerStatusResponse,
+    ) -> impl Future<Item = String, Error = Error> + Send {
+        let config = response
+            .status
+            .as_ref()
+            .and_then(stashed_container_config)
+            .map(|mut config| {
+                if let Some(metadata) = config.metadata.as_mut() {
+                    metadata.attempt = container
+                        .metadata
+                        .as_ref()
+                        .map_or(0, |metadata| metadata.attempt)
+                        + 1;
+                }
+                config
+            })
+            .ok_or_else(|| {
+                Error::from(ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(
+                    name.to_string(),
+                )))
+            });
+
+        let request = config.map(|config| CreateContainerRequest {
+            pod_sandbox_id: container.pod_sandbox_id.clone(),
+            config: Some(config),
+            sandbox_config: Some(sandbox_config(
+                name,
+                None,
+                self.settings.cri_runtime().log_directory(),
+            )),
+        });
+
+        let client = self.client.clone();
+        let old_id = container.id.clone();
+        future::result(request).and_then(move |request| {
+            client
+                .call(
+                    cri::RUNTIME_SERVICE,
+                    "RemoveContainer",
+                    &ContainerIdRequest {
+                        container_id: old_id,
+                    },
+                )
+                .and_then(move |_: Empty| {
+                    client.call(cri::RUNTIME_SERVICE, "CreateContainer", &request)
+                })
+                .map(|response: CreateContainerResponse| response.container_id)
+        })
+    }
+}
+
+impl ModuleRegistry for CriModuleRuntime {
+    type Error = Error;
+    type PullFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type Config = DockerConfig;
+
+    fn pull(&self, config: &Self::Config) -> Self::PullFuture {
+        let image = config.image().to_string();
+        if let Some(image_archive) = config.image_archive() {
+            // CRI has no way to import images, that is left to the container runtime.
+            warn!(
+                "Ignoring image archive {} of image {}, the CRI runtime can't load images from archives.",
+                image_archive.display(),
+                image
+            );
+        }
+        info!("Pulling image {}...", image);
+
+        let auth = config.auth().map(|auth| AuthConfig {
+            username: auth.username().unwrap_or_default().to_string(),
+            password: auth.password().unwrap_or_default().to_string(),
+            server_address: auth.serveraddress().unwrap_or_default().to_string(),
+            ..AuthConfig::default()
+        });
+
+        Box::new(
+            self.client
+                .call(
+                    cri::IMAGE_SERVICE,
+                    "PullImage",
+                    &PullImageRequest {
+                        image: Some(ImageSpec {
+                            image: image.clone(),
+                        }),
+                        auth,
+                    },
+                )
+                .then(move |result: Result<PullImageResponse>| match result {
+                    Ok(_) => {
+                        info!("Successfully pulled image {}", image);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn remove(&self, name: &str) -> Self::RemoveFuture {
+        info!("Removing image {}...", name);
+
+        let name = name.to_string();
+        Box::new(
+            self.client
+                .call(
+                    cri::IMAGE_SERVICE,
+                    "RemoveImage",
+                    &RemoveImageRequest {
+                        image: Some(ImageSpec {
+                            image: name.clone(),
+                        }),
+                    },
+                )
+                .then(move |result: Result<Empty>| match result {
+                    Ok(_) => {
+                        info!("Successfully removed image {}", name);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(name)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+}
+
+impl MakeModuleRuntime for CriModuleRuntime {
+    type Config = DockerConfig;
+    type Settings = Settings;
+    type ProvisioningResult = ProvisioningResult;
+    type ModuleRuntime = Self;
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Self, Error = Self::Error> + Send>;
+
+    fn make_runtime(
+        settings: Settings,
+        _: ProvisioningResult,
+        _: impl GetTrustBundle,
+    ) -> Self::Future {
+        info!("Initializing module runtime...");
+
+        let client = match CriClient::new(settings.cri_runtime().uri()) {
+            Ok(client) => client,
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
+        let fut = client
+            .call(
+                cri::RUNTIME_SERVICE,
+                "Version",
+                &VersionRequest {
+                    version: "v1".to_string(),
+                },
+            )
+            .then(move |result: Result<VersionResponse>| match result {
+                Ok(version) => {
+                    info!(
+                        "Using container runtime {} {} (CRI {})",
+                        version.runtime_name, version.runtime_version, version.runtime_api_version
+                    );
+                    info!("Successfully initialized module runtime");
+                    Ok(CriModuleRuntime { client, settings })
+                }
+                Err(err) => {
+                    let err = Error::from_cri_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::Init),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        Box::new(fut)
+    }
+}
+
+impl ModuleRuntime for CriModuleRuntime {
+    type Error = Error;
+    type Config = DockerConfig;
+    type Module = CriModule;
+    type ModuleRegistry = Self;
+    type Chunk = Chunk;
+    type Logs = Logs;
+
+    type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type GetFuture =
+        Box<dyn Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type ListFuture = Box<dyn Future<Item = Vec<Self::Module>, Error = Self::Error> + Send>;
+    type ListWithDetailsStream =
+        Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = future::FutureResult<(), Self::Error>;
+    type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type SystemInfoFuture = Box<dyn Future<Item = SystemInfo, Error = Self::Error> + Send>;
+    type SystemResourcesFuture =
+        Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
+    type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = ModuleExecResult, Error = Self::Error> + Send>;
+    type CopyToFuture = future::FutureResult<(), Self::Error>;
+    type CopyFromFuture = future::FutureResult<Vec<u8>, Self::Error>;
+    type ExportImageFuture = future::FutureResult<Vec<u8>, Self::Error>;
+    type ImportImagesFuture = future::FutureResult<(), Self::Error>;
+    type ListImagesFuture = future::FutureResult<Vec<ImageInfo>, Self::Error>;
+    type PruneImagesFuture = future::FutureResult<PrunedImages, Self::Error>;
+    type TopFuture = future::FutureResult<ModuleProcesses, Self::Error>;
+    type ValidateFuture = future::FutureResult<(), Self::Error>;
+    type EventStream = stream::Empty<ModuleEvent, Self::Error>;
+
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        info!("Creating module {}...", module.name());
+
+        // we only want "docker" modules
+        if module.type_() != MODULE_TYPE {
+            return Box::new(future::err(Error::from(ErrorKind::InvalidModuleType(
+                module.type_().to_string(),
+            ))));
+        }
+
+        let name = module.name().to_string();
+        let hostname = module
+            .config()
+            .create_options()
+            .hostname()
+            .map(ToOwned::to_owned);
+        let sandbox_config = sandbox_config(
+            &name,
+            hostname.as_ref().map(AsRef::as_ref),
+            self.settings.cri_runtime().log_directory(),
+        );
+        let config = match container_config(&module) {
+            Ok(config) => config,
+            Err(err) => {
+                let err = Error::from_cri_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+                );
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
+        debug!(
+            "Creating container {} with image {}",
+            name,
+            module.config().image()
+        );
+
+        let client = self.client.clone();
+        let request = RunPodSandboxRequest {
+            config: Some(sandbox_config.clone()),
+            runtime_handler: self
+                .settings
+                .cri_runtime()
+                .runtime_handler()
+                .unwrap_or_default()
+                .to_string(),
+        };
+
+        Box::new(
+            self.client
+                .call(cri::RUNTIME_SERVICE, "RunPodSandbox", &request)
+                .and_then(move |response: RunPodSandboxResponse| {
+                    let pod_sandbox_id = response.pod_sandbox_id;
+                    let request = CreateContainerRequest {
+                        pod_sandbox_id: pod_sandbox_id.clone(),
+                        config: Some(config),
+                        sandbox_config: Some(sandbox_config),
+                    };
+                    client
+                        .call(cri::RUNTIME_SERVICE, "CreateContainer", &request)
+                        .or_else(move |err| {
+                            // The sandbox was only run for this container, so it is
+                            // removed rather than left in the way of the next create.
+                            remove_sandbox(client, pod_sandbox_id).then(move |result| {
+                                if let Err(remove_err) = result {
+                                    log_failure(Level::Warn, &remove_err);
+                                }
+                                Err(err)
+                            })
+                        })
+                })
+                .then(
+                    move |result: Result<CreateContainerResponse>| match result {
+                        Ok(_) => {
+                            info!("Successfully created module {}", name);
+                            Ok(())
+                        }
+                        Err(err) => {
+                            let err = Error::from_cri_error(
+                                err,
+                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+                            );
+                            log_failure(Level::Warn, &err);
+                            Err(err)
+                        }
+                    },
+                ),
+        )
+    }
+
+    fn get(&self, id: &str) -> Self::GetFuture {
+        debug!("Getting module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        Box::new(
+            self.find_container(&id)
+                .and_then(move |container| {
+                    let status = runtime.container_status(&container.id);
+                    runtime
+                        .module(container)
+                        .into_future()
+                        .join(status)
+                        .map(|(module, response)| (module, runtime_state(&response)))
+                })
+                .map_err(|err| {
+                    Error::from_cri_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
+                    )
+                }),
+        )
+    }
+
+    fn start(&self, id: &str) -> Self::StartFuture {
+        info!("Starting module {}...", id);
+
+        let runtime = self.clone();
+        let client = self.client.clone();
+        let id = id.to_string();
+        let name = id.clone();
+        Box::new(
+            self.find_container(&id)
+                .and_then(move |container| {
+                    runtime
+                        .container_status(&container.id)
+                        .map(|response| (runtime, container, response))
+                })
+                .and_then(move |(runtime, container, response)| {
+                    let state = response.status.as_ref().map(|status| status.state);
+                    match state {
+                        Some(cri::CONTAINER_RUNNING) => Either::A(future::ok(None)),
+                        Some(cri::CONTAINER_EXITED) => Either::B(Either::A(
+                            runtime.recreate(&name, &container, &response).map(Some),
+                        )),
+                        _ => Either::B(Either::B(future::ok(Some(container.id)))),
+                    }
+                })
+                .and_then(move |container_id| match container_id {
+                    Some(container_id) => Either::A(
+                        client
+                            .call(
+                                cri::RUNTIME_SERVICE,
+                                "StartContainer",
+                                &ContainerIdRequest { container_id },
+                            )
+                            .map(|_: Empty| ()),
+                    ),
+                    None => Either::B(future::ok(())),
+                })
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!("Successfully started module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        info!("Stopping module {}...", id);
+
+        let client = self.client.clone();
+        #[allow(clippy::cast_possible_wrap)]
+        let timeout = wait_before_kill.unwrap_or(DEFAULT_STOP_TIMEOUT).as_secs() as i64;
+        let id = id.to_string();
+        Box::new(
+            self.find_container(&id)
+                .and_then(move |container| {
+                    client.call(
+                        cri::RUNTIME_SERVICE,
+                        "StopContainer",
+                        &StopContainerRequest {
+                            container_id: container.id,
+                            timeout,
+                        },
+                    )
+                })
+                .then(move |result: Result<Empty>| match result {
+                    Ok(_) => {
+                        info!("Successfully stopped module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        info!("Restarting module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        let name = id.clone();
+        Box::new(
+            self.stop(&id, None)
+                .and_then(move |_| runtime.start(&name))
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!("Successfully restarted module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        info!("Removing module {}...", id);
+
+        let client = self.client.clone();
+        let id = id.to_string();
+        Box::new(
+            self.find_container(&id)
+                .and_then(move |container| remove_sandbox(client, container.pod_sandbox_id))
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!("Successfully removed module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    // CRI has no way to rename a container, its name is fixed in its
+    // metadata when it is created.
+    fn rename(&self, id: &str, _new_id: &str) -> Self::RenameFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Renaming modules").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::RenameModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn system_info(&self) -> Self::SystemInfoFuture {
+        // CRI has no equivalent of docker's system info, and modules always
+        // run on this host.
+        Box::new(future::ok(SystemInfo::new(
+            std::env::consts::OS.to_string(),
+            std::env::consts::ARCH.to_string(),
+        )))
+    }
+
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        // TODO: add support for system resources with CRI runtimes
+        Box::new(future::ok(SystemResources::new(
+            0,
+            0,
+            0.0,
+            0,
+            0,
+            vec![],
+            "".to_owned(),
+        )))
+    }
+
+    fn list(&self) -> Self::ListFuture {
+        debug!("Listing modules...");
+
+        let runtime = self.clone();
+        Box::new(
+            self.list_containers(None)
+                .and_then(move |containers| {
+                    containers
+                        .into_iter()
+                        .map(|container| runtime.module(container))
+                        .collect::<Result<Vec<_>>>()
+                })
+                .map_err(|err| {
+                    let err = Error::from_cri_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
+                    );
+                    log_failure(Level::Warn, &err);
+                    err
+                }),
+        )
+    }
+
+    fn list_with_details(&self) -> Self::ListWithDetailsStream {
+        Box::new(
+            self.list()
+                .map(|modules| {
+                    stream::iter_ok(modules).and_then(|module| {
+                        module.runtime_state().then(|result| match result {
+                            Ok(state) => Ok(Some((module, state))),
+                            // The module was removed after it was listed
+                            Err(ref err) if is_not_found(err) => Ok(None),
+                            Err(err) => Err(err),
+                        })
+                    })
+                })
+                .flatten_stream()
+                .filter_map(|module| module),
+        )
+    }
+
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        info!("Getting logs for module {}...", id);
+
+        if options.follow() {
+            warn!("Following module logs is not supported with CRI runtimes");
+        }
+
+        let runtime = self.clone();
+        let options = LogOptions::new()
+            .with_tail(*options.tail())
+            .with_since(options.since())
+            .with_until(options.until());
+        let id = id.to_string();
+        let name = id.clone();
+        let log_directory = self.settings.cri_runtime().log_directory().join(&id);
+        Box::new(
+            self.find_container(&id)
+                .and_then(move |container| runtime.container_status(&container.id))
+                .and_then(move |response| {
+                    let log_path = response.status.map(|status| status.log_path);
+                    let log_path = match log_path {
+                        Some(ref log_path) if !log_path.is_empty() => log_directory.join(log_path),
+                        _ => log_directory.join(format!("{}.log", name)),
+                    };
+
+                    read_logs(&log_path, &options)
+                })
+                .then(move |result| match result {
+                    Ok(logs) => Ok(Logs(id, Body::from(logs))),
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn registry(&self) -> &Self::ModuleRegistry {
+        self
+    }
+
+    fn remove_all(&self) -> Self::RemoveAllFuture {
+        let client = self.client.clone();
+        Box::new(self.list_containers(None).and_then(move |containers| {
+            let mut sandboxes: Vec<String> = containers
+                .into_iter()
+                .map(|container| container.pod_sandbox_id)
+                .collect();
+            sandboxes.sort();
+            sandboxes.dedup();
+
+            future::join_all(
+                sandboxes
+                    .into_iter()
+                    .map(move |sandbox| remove_sandbox(client.clone(), sandbox)),
+            )
+            .map(|_| ())
+        }))
+    }
+
+    fn exec(&self, id: &str, command: &[String]) -> Self::ExecFuture {
+        info!("Running command in module {}...", id);
+
+        let client = self.client.clone();
+        let id = id.to_string();
+        let cmd = command.to_vec();
+        Box::new(
+            self.find_container(&id)
+                .and_then(move |container| {
+                    let request = ExecSyncRequest {
+                        container_id: container.id,
+                        cmd,
+                        ..ExecSyncRequest::default()
+                    };
+                    client.call(cri::RUNTIME_SERVICE, "ExecSync", &request)
+                })
+                .then(move |result| match result {
+                    Ok(response) => {
+                        let ExecSyncResponse {
+                            stdout,
+                            stderr,
+                            exit_code,
+                        } = response;
+                        info!("Successfully ran command in module {}", id);
+                        Ok(ModuleExecResult::new(i64::from(exit_code), stdout, stderr))
+                    }
+                    Err(err) => {
+                        let err = Error::from_cri_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    // CRI has no file transfer of its own; kubelet implements `kubectl cp`
+    // on top of exec and tar, which needs streaming exec.
+    fn copy_to(&self, id: &str, _path: &str, _archive: Vec<u8>) -> Self::CopyToFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Copying files").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CopyToModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn copy_from(&self, id: &str, _path: &str) -> Self::CopyFromFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Copying files").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CopyFromModule(id.to_string()),
+            )),
+        ))
+    }
+
+    // The CRI has no way to save or load images, only to pull them.
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Exporting images").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ExportModuleImage(id.to_string()),
+            )),
+        ))
+    }
+
+    fn import_images(&self, _archive: Vec<u8>) -> Self::ImportImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Importing images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages)),
+        ))
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Listing images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages)),
+        ))
+    }
+
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Pruning images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages)),
+        ))
+    }
+
+    fn top(&self, id: &str) -> Self::TopFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Listing processes").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::TopModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn validate(&self, module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        debug!("Validating module {}...", module.name());
+        let context = || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::ValidateModule(module.name().to_string()))
+        };
+
+        if module.type_() != MODULE_TYPE {
+            return future::err(Error::from(
+                ErrorKind::InvalidModuleType(module.type_().to_string()).context(context()),
+            ));
+        }
+
+        let result = validate_module(&module).map_err(|err| Error::from(err.context(context())));
+        if let Err(err) = &result {
+            log_failure(Level::Warn, err);
+        }
+        future::result(result)
+    }
+
+    fn events(&self) -> Self::EventStream {
+        stream::empty()
+    }
+}
+
+impl Authenticator for CriModuleRuntime {
+    type Error = Error;
+    type Request = Request<Body>;
+    type AuthenticateFuture = Box<dyn Future<Item = AuthId, Error = Self::Error> + Send>;
+
+    fn authenticate(&self, req: &Self::Request) -> Self::AuthenticateFuture {
+        let pid = req
+            .extensions()
+            .get::<Pid>()
+            .cloned()
+            .unwrap_or_else(|| Pid::None);
+
+        let expected_module_id = req.extensions().get::<ModuleId>().cloned();
+
+        Box::new(match (pid, expected_module_id) {
+            (Pid::None, _) | (Pid::Value(_), None) => Either::A(future::ok(AuthId::None)),
+            (Pid::Any, _) => Either::A(future::ok(AuthId::Any)),
+            // The caller is the module if it runs in the same pid namespace as
+            // the module's container.
+            (Pid::Value(pid), Some(expected_module_id)) => {
+                let runtime = self.clone();
+                Either::B(
+                    self.find_container(&expected_module_id.to_string())
+                        .and_then(move |container| runtime.container_status(&container.id))
+                        .then(move |result| match result {
+                            Ok(response) => {
+                                let state = runtime_state(&response);
+                                match state.pid() {
+                                    Some(module_pid) if same_pid_namespace(pid, module_pid) => {
+                                        Ok(AuthId::Value(expected_module_id))
+                                    }
+                                    _ => {
+                                        info!("Unable to find a module for caller pid: {}", pid);
+                                        Ok(AuthId::None)
+                                    }
+                                }
+                            }
+                            Err(ref err) if is_not_found(err) => Ok(AuthId::None),
+                            Err(err) => {
+                                log_failure(Level::Warn, &err);
+                                Err(err)
+                            }
+                        }),
+                )
+            }
+        })
+    }
+}
+
+fn remove_sandbox(
+    client: CriClient,
+    pod_sandbox_id: String,
+) -> impl Future<Item = (), Error = Error> + Send {
+    let request = PodSandboxIdRequest { pod_sandbox_id };
+    client
+        .call(cri::RUNTIME_SERVICE, "StopPodSandbox", &request)
+        .and_then(move |_: Empty| client.call(cri::RUNTIME_SERVICE, "RemovePodSandbox", &request))
+        .map(|_: Empty| ())
+}
+
+fn is_not_found(err: &Error) -> bool {
+    match (err.kind(), ModuleRuntimeErrorReason::from(err)) {
+        (ErrorKind::NotFound(_), _) | (_, ModuleRuntimeErrorReason::NotFound) => true,
+        _ => false,
+    }
+}
+
+fn same_pid_namespace(pid: i32, other: i32) -> bool {
+    let namespace = |pid: i32| fs::read_link(format!("/proc/{}/ns/pid", pid)).ok();
+    match (namespace(pid), namespace(other)) {
+        (Some(namespace), Some(other)) => namespace == other,
+        _ => false,
+    }
+}
+
+fn read_logs(path: &Path, options: &LogOptions) -> Result<Vec<u8>> {
+    let contents = fs::read_to_string(path).map_err(|err| {
+        Error::from(err.context(ErrorKind::NotFound(format!(
+            "Could not read log file {}",
+            path.display()
+        ))))
+    })?;
+    Ok(convert_logs(&contents, options))
+}
+
+// CRI runtimes write logs as lines of "<timestamp> <stream> <tag> <message>",
+// where the tag is "P" for a partial line and "F" for the end of one. These
+// are converted to docker's log stream format, which is what callers of the
+// management API expect.
+fn convert_logs(contents: &str, options: &LogOptions) -> Vec<u8> {
+    let lines: Vec<(u8, String)> = contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, ' ');
+            let timestamp = DateTime::parse_from_rfc3339(parts.next()?).ok()?;
+            let stream = match parts.next()? {
+                "stdout" => 1,
+                "stderr" => 2,
+                _ => return None,
+            };
+            let tag = parts.next()?;
+            let message = parts.next().unwrap_or_default();
+
+            if timestamp.timestamp() < i64::from(options.since()) {
+                return None;
+            }
+            if options.until() != 0 && timestamp.timestamp() > i64::from(options.until()) {
+                return None;
+            }
+
+            let message = if tag == "P" {
+                message.to_string()
+            } else {
+                format!("{}\n", message)
+            };
+            Some((stream, message))
+        })
+        .collect();
+
+    let skip = match options.tail() {
+        LogTail::All => 0,
+        LogTail::Num(tail) => {
+            let tail = std::convert::TryFrom::try_from(*tail).unwrap_or(usize::max_value());
+            lines.len().saturating_sub(tail)
+        }
+    };
+
+    let mut logs = vec![];
+    for (stream, message) in lines.into_iter().skip(skip) {
+        #[allow(clippy::cast_possible_truncation)]
+        let len = message.len() as u32;
+        logs.extend_from_slice(&[stream, 0, 0, 0]);
+        logs.extend_from_slice(&len.to_be_bytes());
+        logs.extend_from_slice(message.as_bytes());
+    }
+    logs
+}
+
+#[derive(Debug)]
+pub struct Logs(String, Body);
+
+impl Stream for Logs {
+    type Item = Chunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.1.poll() {
+            Ok(Async::Ready(chunk)) => Ok(Async::Ready(chunk.map(Chunk))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::GetModuleLogs(self.0.clone()),
+            )))),
+        }
+    }
+}
+
+impl From<Logs> for Body {
+    fn from(logs: Logs) -> Self {
+        logs.1
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk(HyperChunk);
+
+impl IntoIterator for Chunk {
+    type Item = u8;
+    type IntoIter = <HyperChunk as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Extend<u8> for Chunk {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = u8>,
+    {
+        self.0.extend(iter)
+    }
+}
+
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGS: &str = "\
+2019-10-01T00:00:00.000000000Z stdout F first
+2019-10-01T00:00:01.000000000Z stderr F second
+2019-10-01T00:00:02.000000000Z stdout P thi
+2019-10-01T00:00:02.000000000Z stdout F rd
+";
+
+    fn frame(stream: u8, message: &str) -> Vec<u8> {
+        let mut frame = vec![stream, 0, 0, 0];
+        #[allow(clippy::cast_possible_truncation)]
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(message.as_bytes());
+        frame
+    }
+
+    #[test]
+    fn convert_logs_to_docker_stream_format() {
+        let logs = convert_logs(LOGS, &LogOptions::new());
+
+        let expected = [
+            frame(1, "first\n"),
+            frame(2, "second\n"),
+            frame(1, "thi"),
+            frame(1, "rd\n"),
+        ]
+        .concat();
+        assert_eq!(expected, logs);
+    }
+
+    #[test]
+    fn convert_logs_honors_tail() {
+        let logs = convert_logs(LOGS, &LogOptions::new().with_tail(LogTail::Num(2)));
+        assert_eq!([frame(1, "thi"), frame(1, "rd\n")].concat(), logs);
+    }
+
+    #[test]
+    fn convert_logs_honors_since() {
+        // 2019-10-01T00:00:01Z
+        let logs = convert_logs(LOGS, &LogOptions::new().with_since(1_569_888_001));
+        assert_eq!(
+            [frame(2, "second\n"), frame(1, "thi"), frame(1, "rd\n")].concat(),
+            logs
+        );
+    }
+
+    #[test]
+    fn convert_logs_honors_until() {
+        // 2019-10-01T00:00:01Z
+        let logs = convert_logs(LOGS, &LogOptions::new().with_until(1_569_888_001));
+        assert_eq!([frame(1, "first\n"), frame(2, "second\n")].concat(), logs);
+    }
+
+    #[test]
+    fn container_filter_selects_module() {
+        let filter = CriModuleRuntime::container_filter(Some("mod1"));
+        assert_eq!(
+            Some(&LABEL_VALUE.to_string()),
+            filter.label_selector.get(LABEL_KEY)
+        );
+        assert_eq!(
+            Some(&"mod1".to_string()),
+            filter.label_selector.get(MODULE_NAME_LABEL_KEY)
+        );
+
+        let filter = CriModuleRuntime::container_filter(None);
+        assert_eq!(None, filter.label_selector.get(MODULE_NAME_LABEL_KEY));
+    }
+}
@@ -0,0 +1,308 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use config::{Config, Environment};
+use docker::models::HostConfig;
+use edgelet_core::{
+    Certificates, Connect, DeviceActionSettings, DiagnosticsSettings, HealthReportSettings,
+    LifecycleHookSettings, Listen, LogFormat, ModuleSpec, OrphanedModuleSettings, Provisioning,
+    RateLimitSettings, RetrySettings, RuntimeSettings, Settings as BaseSettings, Tenant, UrlExt,
+    WatchdogSettings, REQUIRED_SETTINGS, SETTINGS_ALIASES,
+};
+use edgelet_docker::{DockerConfig, DEFAULTS};
+use edgelet_utils::{SettingsFile, YamlFileSource};
+use failure::ResultExt;
+use url::Url;
+
+use crate::error::{Error, ErrorKind};
+
+const DEFAULT_URI: &str = "unix:///run/containerd/containerd.sock";
+const DEFAULT_LOG_DIRECTORY: &str = "/var/log/iotedge/modules";
+
+const UNIX_SCHEME: &str = "unix";
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct CriRuntime {
+    #[serde(with = "url_serde", default = "default_uri")]
+    uri: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    runtime_handler: Option<String>,
+    #[serde(default = "default_log_directory")]
+    log_directory: PathBuf,
+}
+
+impl Default for CriRuntime {
+    fn default() -> Self {
+        CriRuntime {
+            uri: default_uri(),
+            runtime_handler: None,
+            log_directory: default_log_directory(),
+        }
+    }
+}
+
+impl CriRuntime {
+    /// The socket of the CRI runtime, for example containerd's.
+    pub fn uri(&self) -> &Url {
+        &self.uri
+    }
+
+    /// The runtime handler configured in the CRI runtime to run modules with.
+    /// The runtime's default handler is used when this is not set.
+    pub fn runtime_handler(&self) -> Option<&str> {
+        self.runtime_handler.as_ref().map(AsRef::as_ref)
+    }
+
+    /// The directory the CRI runtime writes module logs to.
+    pub fn log_directory(&self) -> &Path {
+        &self.log_directory
+    }
+}
+
+fn default_uri() -> Url {
+    Url::parse(DEFAULT_URI).expect("default CRI runtime uri is valid")
+}
+
+fn default_log_directory() -> PathBuf {
+    PathBuf::from(DEFAULT_LOG_DIRECTORY)
+}
+
+/// This struct is the same as the Settings type from the `edgelet_docker`
+/// crate except that modules are run by a CRI runtime instead of docker.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Settings {
+    #[serde(flatten)]
+    base: BaseSettings<DockerConfig>,
+    #[serde(default)]
+    cri_runtime: CriRuntime,
+}
+
+impl Settings {
+    pub fn new(filename: &Path) -> Result<Self, Error> {
+        let file = SettingsFile::open(filename).context(ErrorKind::Config)?;
+        file.check(REQUIRED_SETTINGS).context(ErrorKind::Config)?;
+
+        let mut config = Config::default();
+        config
+            .merge(YamlFileSource::String(DEFAULTS))
+            .context(ErrorKind::Config)?;
+
+        file.merge_into(&mut config).context(ErrorKind::Config)?;
+
+        config
+            .merge(Environment::with_prefix("iotedge"))
+            .context(ErrorKind::Config)?;
+
+        let mut settings: Self = file
+            .deserialize::<Self, BaseSettings<DockerConfig>>(config)
+            .context(ErrorKind::Config)?;
+        file.warn_unknown_keys(&settings, SETTINGS_ALIASES);
+
+        agent_vol_mount(&mut settings)?;
+
+        Ok(settings)
+    }
+
+    pub fn cri_runtime(&self) -> &CriRuntime {
+        &self.cri_runtime
+    }
+}
+
+impl RuntimeSettings for Settings {
+    type Config = DockerConfig;
+
+    fn provisioning(&self) -> &Provisioning {
+        self.base.provisioning()
+    }
+
+    fn agent(&self) -> &ModuleSpec<DockerConfig> {
+        self.base.agent()
+    }
+
+    fn agent_mut(&mut self) -> &mut ModuleSpec<DockerConfig> {
+        self.base.agent_mut()
+    }
+
+    fn hostname(&self) -> &str {
+        self.base.hostname()
+    }
+
+    fn parent_hostname(&self) -> Option<&str> {
+        self.base.parent_hostname()
+    }
+
+    fn connect(&self) -> &Connect {
+        self.base.connect()
+    }
+
+    fn listen(&self) -> &Listen {
+        self.base.listen()
+    }
+
+    fn homedir(&self) -> &Path {
+        self.base.homedir()
+    }
+
+    fn certificates(&self) -> &Certificates {
+        self.base.certificates()
+    }
+
+    fn watchdog(&self) -> &WatchdogSettings {
+        self.base.watchdog()
+    }
+
+    fn log_level(&self) -> Option<&str> {
+        self.base.log_level()
+    }
+
+    fn log_format(&self) -> LogFormat {
+        self.base.log_format()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        self.base.tenants()
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        self.base.lifecycle_hook()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.base.workload_rate_limit()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        self.base.retry()
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        self.base.device_actions()
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        self.base.health_report()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        self.base.orphaned_modules()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        self.base.diagnostics()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        self.base.secrets()
+    }
+}
+
+// Mounts the workload and management sockets into the edge agent, the same
+// way the docker runtime does.
+fn agent_vol_mount(settings: &mut Settings) -> Result<(), Error> {
+    let create_options = settings
+        .agent()
+        .config()
+        .clone_create_options()
+        .context(ErrorKind::Config)?;
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    let mut binds = host_config.binds().map_or_else(Vec::new, ToOwned::to_owned);
+
+    for uri in &[
+        settings.connect().management_uri(),
+        settings.connect().workload_uri(),
+    ] {
+        if uri.scheme() == UNIX_SCHEME {
+            let path = uri.to_uds_file_path().context(ErrorKind::Config)?;
+            let path = path.to_str().ok_or(ErrorKind::Config)?.to_string();
+            let bind = format!("{}:{}", &path, &path);
+            if !binds.contains(&bind) {
+                binds.push(bind);
+            }
+        }
+    }
+
+    if !binds.is_empty() {
+        let host_config = host_config.with_binds(binds);
+        let create_options = create_options.with_host_config(host_config);
+
+        settings
+            .agent_mut()
+            .config_mut()
+            .set_create_options(create_options);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    fn load(yaml: &str) -> Settings {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+        Settings::new(file.path()).unwrap()
+    }
+
+    const SETTINGS: &str = r#"
+provisioning:
+  source: "manual"
+  device_connection_string: "HostName=moo.azure-devices.net;DeviceId=boo;SharedAccessKey=boo"
+"#;
+
+    #[test]
+    fn cri_runtime_defaults_to_containerd() {
+        let settings = load(SETTINGS);
+
+        assert_eq!(
+            "unix:///run/containerd/containerd.sock",
+            settings.cri_runtime().uri().as_str()
+        );
+        assert_eq!(None, settings.cri_runtime().runtime_handler());
+        assert_eq!(
+            Path::new("/var/log/iotedge/modules"),
+            settings.cri_runtime().log_directory()
+        );
+    }
+
+    #[test]
+    fn cri_runtime_is_configurable() {
+        let settings = load(&format!(
+            r#"{}
+cri_runtime:
+  uri: "unix:///run/k3s/containerd/containerd.sock"
+  runtime_handler: "runc"
+"#,
+            SETTINGS
+        ));
+
+        assert_eq!(
+            "unix:///run/k3s/containerd/containerd.sock",
+            settings.cri_runtime().uri().as_str()
+        );
+        assert_eq!(Some("runc"), settings.cri_runtime().runtime_handler());
+    }
+
+    #[test]
+    fn agent_gets_socket_mounts() {
+        let settings = load(SETTINGS);
+
+        let create_options = settings.agent().config().create_options();
+        let binds = create_options.host_config().unwrap().binds().unwrap();
+        assert!(
+            binds.contains(&"/var/run/iotedge/mgmt.sock:/var/run/iotedge/mgmt.sock".to_string())
+        );
+        assert!(binds.contains(
+            &"/var/run/iotedge/workload.sock:/var/run/iotedge/workload.sock".to_string()
+        ));
+    }
+}
@@ -1,5 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::path::{Path, PathBuf};
+
 use failure::ResultExt;
 
 use docker::models::{AuthConfig, ContainerCreateBody};
@@ -18,6 +20,10 @@ pub struct DockerConfig {
     create_options: ContainerCreateBody,
     #[serde(skip_serializing_if = "Option::is_none")]
     auth: Option<AuthConfig>,
+    /// An image tarball, as written by `docker save`, or an OCI image layout
+    /// directory that the image is loaded from instead of being pulled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    image_archive: Option<PathBuf>,
 }
 
 impl DockerConfig {
@@ -33,6 +39,7 @@ impl DockerConfig {
             image_id: None,
             create_options,
             auth,
+            image_archive: None,
         };
         Ok(config)
     }
@@ -80,6 +87,15 @@ impl DockerConfig {
         self.auth = Some(auth);
         self
     }
+
+    pub fn image_archive(&self) -> Option<&Path> {
+        self.image_archive.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_image_archive(mut self, image_archive: PathBuf) -> Self {
+        self.image_archive = Some(image_archive);
+        self
+    }
 }
 
 #[cfg(test)]
@@ -209,6 +225,23 @@ mod tests {
         assert_eq!(config.image, "ubuntu");
     }
 
+    #[test]
+    fn docker_config_deser_image_archive() {
+        let input_json = json!({
+            "image": "mcr.microsoft.com/azureiotedge-agent:1.0",
+            "imageArchive": "/var/lib/iotedge/edge-agent.tar"
+        });
+        let config = serde_json::from_str::<DockerConfig>(&input_json.to_string()).unwrap();
+        assert_eq!(
+            Some(Path::new("/var/lib/iotedge/edge-agent.tar")),
+            config.image_archive()
+        );
+        assert_eq!(
+            input_json["imageArchive"],
+            serde_json::to_value(&config).unwrap()["imageArchive"]
+        );
+    }
+
     #[test]
     fn docker_config_deser_from_map() {
         let input_json = json!({
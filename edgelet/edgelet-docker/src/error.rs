@@ -12,6 +12,8 @@ use edgelet_core::{
     ModuleOperation, ModuleRuntimeErrorReason, RegistryOperation, RuntimeOperation,
 };
 
+use crate::policy::Privileges;
+
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -44,8 +46,8 @@ pub enum ErrorKind {
     #[fail(display = "Could not clone create options")]
     CloneCreateOptions,
 
-    #[fail(display = "Conflict with current operation")]
-    Conflict,
+    #[fail(display = "{}", _0)]
+    Conflict(String),
 
     #[fail(display = "Container runtime error")]
     Docker,
@@ -59,15 +61,27 @@ pub enum ErrorKind {
     #[fail(display = "Could not initialize module runtime")]
     Initialization,
 
+    #[fail(display = "Could not install the CA certificate of registry {}", _0)]
+    InstallCaCert(String),
+
+    #[fail(display = "Invalid docker API version {:?}", _0)]
+    InvalidApiVersion(String),
+
     #[fail(display = "Invalid docker image {:?}", _0)]
     InvalidImage(String),
 
     #[fail(display = "Invalid module name {:?}", _0)]
     InvalidModuleName(String),
 
+    #[fail(display = "{}", _0)]
+    InvalidModuleSpec(String),
+
     #[fail(display = "Invalid module type {:?}", _0)]
     InvalidModuleType(String),
 
+    #[fail(display = "Invalid settings for registry {}: {}", _0, _1)]
+    InvalidRegistrySettings(String, String),
+
     #[fail(display = "Invalid socket URI: {:?}", _0)]
     InvalidSocketUri(String),
 
@@ -80,11 +94,75 @@ pub enum ErrorKind {
     #[fail(display = "Target of operation already in this state")]
     NotModified,
 
+    #[fail(
+        display = "Host port {}/{} is already used by {}",
+        port, protocol, owner
+    )]
+    PortConflict {
+        port: u16,
+        protocol: String,
+        owner: PortOwner,
+    },
+
+    #[fail(
+        display = "Module {} asks for privileges that the privileged policy denies: {}",
+        module, privileges
+    )]
+    PrivilegedModule {
+        module: String,
+        privileges: Privileges,
+    },
+
     #[fail(display = "{}", _0)]
     RegistryOperation(RegistryOperation),
 
     #[fail(display = "{}", _0)]
     RuntimeOperation(RuntimeOperation),
+
+    #[fail(
+        display = "Permission denied on the container engine socket {}. The user the daemon runs as has to be a member of the group that owns the socket, usually docker.",
+        _0
+    )]
+    SocketPermissionDenied(String),
+
+    #[fail(
+        display = "OCI runtime {} is not configured on the container engine. Available runtimes: {}",
+        runtime, available
+    )]
+    UnknownOciRuntime { runtime: String, available: String },
+
+    #[fail(
+        display = "Container engine API version {} is older than the minimum supported version {}",
+        _0, _1
+    )]
+    UnsupportedApiVersion(String, String),
+
+    #[fail(
+        display = "The TLS certificate of registry {} could not be verified. {}",
+        registry, advice
+    )]
+    UntrustedRegistry { registry: String, advice: String },
+}
+
+/// What already uses a host port that a module asks for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PortOwner {
+    /// Another module, by name.
+    Module(String),
+    /// A container that isn't a module, by name.
+    Container(String),
+    /// A process on the host outside of any container.
+    Host,
+}
+
+impl Display for PortOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortOwner::Module(name) => write!(f, "module {}", name),
+            PortOwner::Container(name) => write!(f, "container {}", name),
+            PortOwner::Host => write!(f, "another process on the host"),
+        }
+    }
 }
 
 impl Fail for Error {
@@ -117,7 +195,11 @@ impl Error {
                     Ok(message) => ErrorKind::NotFound(message).context(context),
                     Err(e) => ErrorKind::DockerRuntime(DockerError::Api(e)).context(context),
                 },
-                StatusCode::CONFLICT => ErrorKind::Conflict.context(context),
+                StatusCode::CONFLICT => {
+                    let message = get_message(error)
+                        .unwrap_or_else(|_| "Conflict with current operation".to_string());
+                    ErrorKind::Conflict(message).context(context)
+                }
                 StatusCode::NOT_MODIFIED => ErrorKind::NotModified.context(context),
                 _ => match get_message(error) {
                     Ok(message) => ErrorKind::FormattedDockerRuntime(message).context(context),
@@ -152,3 +234,88 @@ impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ModuleOperation;
+    use serde_json::json;
+
+    use super::*;
+
+    fn api_error(code: StatusCode, content: Option<serde_json::Value>) -> Error {
+        Error::from_docker_error(
+            DockerError::Api(DockerApiError { code, content }),
+            ErrorKind::ModuleOperation(ModuleOperation::RuntimeState),
+        )
+    }
+
+    fn root_kind(err: &Error) -> Option<&ErrorKind> {
+        Fail::find_root_cause(err).downcast_ref::<ErrorKind>()
+    }
+
+    #[test]
+    fn not_found_keeps_message() {
+        let err = api_error(
+            StatusCode::NOT_FOUND,
+            Some(json!({ "message": "No such image: alpine:latest" })),
+        );
+
+        match root_kind(&err) {
+            Some(ErrorKind::NotFound(message)) => {
+                assert_eq!("No such image: alpine:latest", message);
+            }
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+        match ModuleRuntimeErrorReason::from(&err) {
+            ModuleRuntimeErrorReason::NotFound => (),
+            ModuleRuntimeErrorReason::Other => panic!("expected the not found reason"),
+        }
+    }
+
+    #[test]
+    fn conflict_keeps_message() {
+        let err = api_error(
+            StatusCode::CONFLICT,
+            Some(json!({ "message": "Conflict. The container name \"/m1\" is already in use" })),
+        );
+
+        match root_kind(&err) {
+            Some(ErrorKind::Conflict(message)) => assert_eq!(
+                "Conflict. The container name \"/m1\" is already in use",
+                message
+            ),
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn conflict_without_message() {
+        let err = api_error(StatusCode::CONFLICT, None);
+
+        match root_kind(&err) {
+            Some(ErrorKind::Conflict(message)) => {
+                assert_eq!("Conflict with current operation", message);
+            }
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn server_error_keeps_message() {
+        let err = api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Some(json!({ "message": "driver failed programming external connectivity" })),
+        );
+
+        match root_kind(&err) {
+            Some(ErrorKind::FormattedDockerRuntime(message)) => {
+                assert_eq!("driver failed programming external connectivity", message);
+            }
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+        match ModuleRuntimeErrorReason::from(&err) {
+            ModuleRuntimeErrorReason::Other => (),
+            ModuleRuntimeErrorReason::NotFound => panic!("unexpected not found reason"),
+        }
+    }
+}
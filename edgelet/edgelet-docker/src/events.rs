@@ -0,0 +1,171 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future, Poll, Stream};
+use log::{debug, Level};
+use tokio::timer::Delay;
+
+use docker::apis::Error as DockerError;
+use docker::models::InlineResponse20012 as EventMessage;
+use edgelet_core::{ModuleEvent, ModuleStatus, RuntimeOperation};
+use edgelet_http::UrlConnector;
+use edgelet_utils::log_failure;
+
+use crate::client::DockerClient;
+use crate::error::{Error, ErrorKind};
+use crate::runtime::LABELS;
+
+/// How long to wait before subscribing to the events of the container engine
+/// again after the subscription ended, e.g. because the engine restarted.
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(5);
+
+/// The container events that change the state of a module.
+const EVENTS: &[&str] = &["start", "die", "oom"];
+
+type EventMessages =
+    Box<dyn Stream<Item = EventMessage, Error = DockerError<serde_json::Value>> + Send>;
+
+/// The state changes of the modules, as reported by the container engine.
+/// The stream subscribes again whenever the engine ends the subscription,
+/// starting from the time of the last event it saw, so it never ends.
+pub struct ModuleEvents {
+    client: DockerClient<UrlConnector>,
+    since: String,
+    state: State,
+}
+
+enum State {
+    Subscribed(EventMessages),
+    Waiting(Delay),
+}
+
+impl ModuleEvents {
+    pub(crate) fn new(client: DockerClient<UrlConnector>) -> Self {
+        let since = String::new();
+        let state = State::Subscribed(subscribe(&client, &since));
+        ModuleEvents {
+            client,
+            since,
+            state,
+        }
+    }
+}
+
+impl Stream for ModuleEvents {
+    type Item = ModuleEvent;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let next = match &mut self.state {
+                State::Subscribed(messages) => match messages.poll() {
+                    Ok(Async::Ready(Some(message))) => {
+                        if let Some(time) = message.time() {
+                            self.since = time.to_string();
+                        }
+                        if let Some(event) = module_event(&message) {
+                            return Ok(Async::Ready(Some(event)));
+                        }
+                        continue;
+                    }
+                    Ok(Async::Ready(None)) => {
+                        debug!("Container engine ended the subscription to its events");
+                        State::Waiting(Delay::new(Instant::now() + RESUBSCRIBE_DELAY))
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ModuleEvents),
+                        );
+                        log_failure(Level::Warn, &err);
+                        State::Waiting(Delay::new(Instant::now() + RESUBSCRIBE_DELAY))
+                    }
+                },
+                State::Waiting(delay) => match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) | Err(_) => {
+                        debug!("Subscribing to the events of the container engine again");
+                        State::Subscribed(subscribe(&self.client, &self.since))
+                    }
+                },
+            };
+            self.state = next;
+        }
+    }
+}
+
+fn subscribe(client: &DockerClient<UrlConnector>, since: &str) -> EventMessages {
+    let mut filters = HashMap::new();
+    filters.insert("type", vec!["container"]);
+    filters.insert("event", EVENTS.to_vec());
+    filters.insert("label", LABELS.deref().clone());
+    let filters =
+        serde_json::to_string(&filters).expect("serializing the event filters cannot fail");
+
+    client.system_api().system_events(since, "", &filters)
+}
+
+/// The new state of the module an event is about, if the event changes it.
+fn module_event(message: &EventMessage) -> Option<ModuleEvent> {
+    let attributes = message.actor()?.attributes()?;
+    let name = attributes.get("name")?;
+
+    let status = match message.action()? {
+        "start" => ModuleStatus::Running,
+        "die" if attributes.get("exitCode").map(String::as_str) == Some("0") => {
+            ModuleStatus::Stopped
+        }
+        "die" | "oom" => ModuleStatus::Failed,
+        _ => return None,
+    };
+
+    Some(ModuleEvent::new(name.clone(), status))
+}
+
+#[cfg(test)]
+mod tests {
+    use docker::models::InlineResponse20012Actor as EventActor;
+
+    use super::*;
+
+    fn message(action: &str, attributes: &[(&str, &str)]) -> EventMessage {
+        let attributes = attributes
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        EventMessage::new()
+            .with__type("container".to_string())
+            .with_action(action.to_string())
+            .with_actor(EventActor::new().with_attributes(attributes))
+    }
+
+    #[test]
+    fn module_events_from_container_events() {
+        assert_eq!(
+            Some(ModuleEvent::new("m1".to_string(), ModuleStatus::Running)),
+            module_event(&message("start", &[("name", "m1")]))
+        );
+        assert_eq!(
+            Some(ModuleEvent::new("m1".to_string(), ModuleStatus::Stopped)),
+            module_event(&message("die", &[("name", "m1"), ("exitCode", "0")]))
+        );
+        assert_eq!(
+            Some(ModuleEvent::new("m1".to_string(), ModuleStatus::Failed)),
+            module_event(&message("die", &[("name", "m1"), ("exitCode", "137")]))
+        );
+        assert_eq!(
+            Some(ModuleEvent::new("m1".to_string(), ModuleStatus::Failed)),
+            module_event(&message("oom", &[("name", "m1")]))
+        );
+    }
+
+    #[test]
+    fn other_container_events_are_ignored() {
+        assert_eq!(None, module_event(&message("pause", &[("name", "m1")])));
+        assert_eq!(None, module_event(&message("start", &[])));
+    }
+}
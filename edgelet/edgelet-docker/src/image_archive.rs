@@ -0,0 +1,214 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The file that marks a directory as an OCI image layout.
+const OCI_LAYOUT_FILE: &str = "oci-layout";
+
+const BLOCK_SIZE: usize = 512;
+
+/// Reads an image archive for the container engine to load, so that an image
+/// can be created without a registry. The archive is either a tarball, as
+/// written by `docker save`, or an OCI image layout directory, which is packed
+/// into a tarball since that is what the engine takes.
+pub fn read_image_archive(path: &Path) -> io::Result<Vec<u8>> {
+    if !path.is_dir() {
+        return fs::read(path);
+    }
+
+    if !path.join(OCI_LAYOUT_FILE).is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} is a directory but not an OCI image layout, it has no {} file",
+                path.display(),
+                OCI_LAYOUT_FILE
+            ),
+        ));
+    }
+
+    let mut archive = vec![];
+    append_dir(&mut archive, path, "")?;
+    archive.resize(archive.len() + 2 * BLOCK_SIZE, 0);
+    Ok(archive)
+}
+
+fn append_dir(archive: &mut Vec<u8>, dir: &Path, prefix: &str) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid file name", entry.path().display()),
+            )
+        })?;
+        let name = format!("{}{}", prefix, file_name);
+
+        if entry.file_type()?.is_dir() {
+            append_header(archive, &format!("{}/", name), 0, b'5')?;
+            append_dir(archive, &entry.path(), &format!("{}/", name))?;
+        } else {
+            let contents = fs::read(entry.path())?;
+            append_header(archive, &name, contents.len() as u64, b'0')?;
+            archive.extend_from_slice(&contents);
+            let padding = (BLOCK_SIZE - contents.len() % BLOCK_SIZE) % BLOCK_SIZE;
+            archive.resize(archive.len() + padding, 0);
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends a ustar header. Names longer than 100 bytes are split into the
+/// prefix and name fields at a `/`.
+fn append_header(archive: &mut Vec<u8>, name: &str, size: u64, type_flag: u8) -> io::Result<()> {
+    let (prefix, name) = split_name(name).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is too long to be archived", name),
+        )
+    })?;
+
+    let mut header = [0_u8; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    let mode: &[u8] = if type_flag == b'5' {
+        b"0000755"
+    } else {
+        b"0000644"
+    };
+    header[100..107].copy_from_slice(mode);
+    header[108..115].copy_from_slice(b"0000000");
+    header[116..123].copy_from_slice(b"0000000");
+    header[124..135].copy_from_slice(format!("{:011o}", size).as_bytes());
+    header[136..147].copy_from_slice(b"00000000000");
+    header[156] = type_flag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // The checksum is computed with the checksum field itself set to spaces.
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+
+    archive.extend_from_slice(&header);
+    Ok(())
+}
+
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+
+    // A trailing `/` of a directory must stay in the name field.
+    let search = &name[..name.len() - 1];
+    search
+        .char_indices()
+        .filter(|(_, c)| *c == '/')
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn entries(archive: &[u8]) -> Vec<(String, u8, Vec<u8>)> {
+        let mut entries = vec![];
+        let mut offset = 0;
+        while archive[offset..offset + BLOCK_SIZE].iter().any(|b| *b != 0) {
+            let header = &archive[offset..offset + BLOCK_SIZE];
+            let field = |range: std::ops::Range<usize>| {
+                let field = &header[range];
+                let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+                String::from_utf8(field[..end].to_vec()).unwrap()
+            };
+
+            let checksum: u32 = header[..148]
+                .iter()
+                .chain(&[b' '; 8])
+                .chain(&header[156..])
+                .map(|b| u32::from(*b))
+                .sum();
+            assert_eq!(checksum, u32::from_str_radix(&field(148..154), 8).unwrap());
+            assert_eq!("ustar", field(257..263));
+
+            let prefix = field(345..500);
+            let name = if prefix.is_empty() {
+                field(0..100)
+            } else {
+                format!("{}/{}", prefix, field(0..100))
+            };
+            let size = usize::from_str_radix(&field(124..135), 8).unwrap();
+            offset += BLOCK_SIZE;
+            entries.push((name, header[156], archive[offset..offset + size].to_vec()));
+            offset += size + (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+        }
+
+        assert_eq!(offset + 2 * BLOCK_SIZE, archive.len());
+        entries
+    }
+
+    #[test]
+    fn tarball_is_read_as_is() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("edge-agent.tar");
+        fs::write(&path, b"tarball").unwrap();
+
+        assert_eq!(b"tarball".to_vec(), read_image_archive(&path).unwrap());
+    }
+
+    #[test]
+    fn oci_layout_is_packed() {
+        let dir = TempDir::new().unwrap();
+        let blob = format!("{:064}", 7);
+        fs::create_dir_all(dir.path().join("blobs/sha256")).unwrap();
+        fs::write(dir.path().join("blobs/sha256").join(&blob), vec![1; 600]).unwrap();
+        fs::write(dir.path().join("index.json"), b"{}").unwrap();
+        fs::write(
+            dir.path().join(OCI_LAYOUT_FILE),
+            br#"{"imageLayoutVersion":"1.0.0"}"#,
+        )
+        .unwrap();
+
+        let archive = read_image_archive(dir.path()).unwrap();
+
+        assert_eq!(
+            vec![
+                ("blobs/".to_string(), b'5', vec![]),
+                ("blobs/sha256/".to_string(), b'5', vec![]),
+                (format!("blobs/sha256/{}", blob), b'0', vec![1; 600]),
+                ("index.json".to_string(), b'0', b"{}".to_vec()),
+                (
+                    OCI_LAYOUT_FILE.to_string(),
+                    b'0',
+                    br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec()
+                ),
+            ],
+            entries(&archive)
+        );
+    }
+
+    #[test]
+    fn directory_without_oci_layout_fails() {
+        let dir = TempDir::new().unwrap();
+        fs::write(dir.path().join("index.json"), b"{}").unwrap();
+
+        let err = read_image_archive(dir.path()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn long_names_are_split() {
+        let name = format!("blobs/sha256/{}", "a".repeat(99));
+        assert_eq!(Some(("blobs/sha256", &*"a".repeat(99))), split_name(&name));
+        assert_eq!(None, split_name(&"a".repeat(101)));
+    }
+}
@@ -0,0 +1,249 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::time::Instant;
+
+use futures::{Future, Stream};
+use log::{warn, Level};
+use tokio::timer::Interval;
+
+use docker::models::ImageSummary;
+use edgelet_utils::log_failure;
+
+use crate::runtime::DockerModuleRuntime;
+use crate::settings::ImageGarbageCollection;
+
+/// Tag docker reports for images that have no tag
+const UNTAGGED: &str = "<none>:<none>";
+
+/// Periodically removes unused images from the container engine, as configured by `settings`.
+pub(crate) fn start(
+    runtime: DockerModuleRuntime,
+    settings: ImageGarbageCollection,
+) -> impl Future<Item = (), Error = ()> + Send {
+    let interval = settings.interval();
+
+    // The first run is deferred by one interval so that modules that are being (re)created
+    // when the daemon starts are not mistaken for unused images.
+    Interval::new(Instant::now() + interval, interval)
+        .map_err(|err| warn!("Image garbage collection timer failed: {}", err))
+        .for_each(move |_| {
+            runtime.collect_images(&settings).then(|result| {
+                if let Err(err) = result {
+                    log_failure(Level::Warn, &err);
+                }
+                Ok(())
+            })
+        })
+}
+
+/// Returns the names of the images that should be removed, given the images known to the
+/// container engine and the ids of the images used by containers.
+///
+/// Tagged images are returned by tag, since docker refuses to remove an image by id when
+/// it is referenced by more than one repository. Untagged images are returned by id.
+pub(crate) fn images_to_remove(
+    images: &[ImageSummary],
+    in_use: &HashSet<String>,
+    settings: &ImageGarbageCollection,
+    now: i64,
+) -> Vec<String> {
+    let min_age = i64::try_from(settings.min_age().as_secs()).unwrap_or(i64::max_value());
+
+    // Group images by repository so that the most recent ones of each can be kept
+    let mut by_repository: HashMap<&str, Vec<&ImageSummary>> = HashMap::new();
+    for image in images {
+        for tag in tags(image) {
+            by_repository
+                .entry(repository(tag))
+                .or_default()
+                .push(image);
+        }
+    }
+
+    let mut kept: HashSet<&str> = HashSet::new();
+    for repository_images in by_repository.values_mut() {
+        repository_images.sort_by(|a, b| b.created().cmp(a.created()));
+        repository_images.dedup_by_key(|image| image.id());
+        kept.extend(
+            repository_images
+                .iter()
+                .take(settings.keep_last())
+                .map(|image| image.id().as_str()),
+        );
+    }
+
+    images
+        .iter()
+        .filter(|image| !in_use.contains(image.id()))
+        .filter(|image| !kept.contains(image.id().as_str()))
+        .filter(|image| now.saturating_sub(i64::from(*image.created())) >= min_age)
+        .flat_map(|image| {
+            let tags: Vec<String> = tags(image).map(ToString::to_string).collect();
+            if tags.is_empty() {
+                vec![image.id().clone()]
+            } else {
+                tags
+            }
+        })
+        .collect()
+}
+
+/// The tags of `image`, without the placeholder docker reports for untagged images.
+pub(crate) fn tags(image: &ImageSummary) -> impl Iterator<Item = &str> {
+    image
+        .repo_tags()
+        .iter()
+        .map(String::as_str)
+        .filter(|tag| *tag != UNTAGGED)
+}
+
+/// Strips the tag or digest from an image reference, taking care not to
+/// mistake the port of a registry host for a tag.
+fn repository(image: &str) -> &str {
+    let image = image.split('@').next().unwrap_or(image);
+    match image.rfind(':') {
+        Some(index) if !image[index..].contains('/') => &image[..index],
+        _ => image,
+    }
+}
+
+/// Returns the percentage of free space of the disk that holds `path`.
+#[cfg(not(windows))]
+pub(crate) fn free_disk_percent(path: &str) -> Option<f64> {
+    use std::path::Path;
+    use sysinfo::{DiskExt, SystemExt};
+
+    let mut system_info = sysinfo::System::new();
+    system_info.refresh_all();
+
+    // Pick the disk with the longest mount point that contains the path
+    system_info
+        .get_disks()
+        .iter()
+        .filter(|disk| Path::new(path).starts_with(disk.get_mount_point()))
+        .max_by_key(|disk| disk.get_mount_point().as_os_str().len())
+        .filter(|disk| disk.get_total_space() > 0)
+        .map(|disk| {
+            #[allow(clippy::cast_precision_loss)]
+            let percent = disk.get_available_space() as f64 * 100.0 / disk.get_total_space() as f64;
+            percent
+        })
+}
+
+/// Returns the percentage of free space of the disk that holds `path`.
+#[cfg(windows)]
+pub(crate) fn free_disk_percent(_path: &str) -> Option<f64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+    const NOW: i64 = 100 * DAY;
+
+    fn image(id: &str, tags: &[&str], age_days: i64) -> ImageSummary {
+        #[allow(clippy::cast_possible_truncation)]
+        let created = (NOW - age_days * DAY) as i32;
+        ImageSummary::new(
+            id.to_string(),
+            String::new(),
+            tags.iter().map(ToString::to_string).collect(),
+            vec![],
+            created,
+            0,
+            0,
+            0,
+            HashMap::new(),
+            0,
+        )
+    }
+
+    fn settings() -> ImageGarbageCollection {
+        ImageGarbageCollection::default()
+            .with_enabled(true)
+            .with_min_age(Duration::from_secs(7 * 24 * 60 * 60))
+    }
+
+    #[test]
+    fn in_use_images_are_kept() {
+        let images = vec![
+            image("sha256:1", &["edgehub:1.0"], 30),
+            image("sha256:2", &["sensor:1.0"], 30),
+        ];
+        let in_use = vec!["sha256:1".to_string()].into_iter().collect();
+
+        let removed = images_to_remove(&images, &in_use, &settings(), NOW);
+
+        assert_eq!(vec!["sensor:1.0".to_string()], removed);
+    }
+
+    #[test]
+    fn recent_images_are_kept() {
+        let images = vec![
+            image("sha256:1", &["sensor:1.0"], 30),
+            image("sha256:2", &["sensor:1.1"], 1),
+        ];
+
+        let removed = images_to_remove(&images, &HashSet::new(), &settings(), NOW);
+
+        assert_eq!(vec!["sensor:1.0".to_string()], removed);
+    }
+
+    #[test]
+    fn keep_last_keeps_most_recent_images_per_repository() {
+        let images = vec![
+            image("sha256:1", &["localhost:5000/sensor:1.0"], 30),
+            image("sha256:2", &["localhost:5000/sensor:1.1"], 20),
+            image("sha256:3", &["localhost:5000/sensor:1.2"], 10),
+            image("sha256:4", &["filter:1.0"], 30),
+        ];
+        let settings = settings().with_keep_last(2);
+
+        let removed = images_to_remove(&images, &HashSet::new(), &settings, NOW);
+
+        assert_eq!(vec!["localhost:5000/sensor:1.0".to_string()], removed);
+    }
+
+    #[test]
+    fn untagged_images_are_removed_by_id() {
+        let images = vec![
+            image("sha256:1", &[UNTAGGED], 30),
+            image("sha256:2", &["sensor:1.0", "sensor:latest"], 30),
+        ];
+
+        let removed = images_to_remove(&images, &HashSet::new(), &settings(), NOW);
+
+        assert_eq!(
+            vec![
+                "sha256:1".to_string(),
+                "sensor:1.0".to_string(),
+                "sensor:latest".to_string(),
+            ],
+            removed
+        );
+    }
+
+    #[test]
+    fn repository_strips_tag_but_not_registry_port() {
+        assert_eq!("sensor", repository("sensor:1.0"));
+        assert_eq!(
+            "localhost:5000/sensor",
+            repository("localhost:5000/sensor:1.0")
+        );
+        assert_eq!("localhost:5000/sensor", repository("localhost:5000/sensor"));
+        assert_eq!("sensor", repository("sensor"));
+        assert_eq!("sensor", repository("sensor@sha256:abc"));
+        assert_eq!("sensor", repository("sensor:1.0@sha256:abc"));
+        assert_eq!(
+            "localhost:5000/sensor",
+            repository("localhost:5000/sensor@sha256:abc")
+        );
+    }
+}
@@ -13,12 +13,27 @@
 mod client;
 mod config;
 mod error;
+mod events;
+mod image_archive;
+mod image_gc;
 mod module;
+mod policy;
+mod registry;
+mod resource_monitor;
 mod runtime;
 mod settings;
+mod validate;
+mod version;
 
 pub use crate::config::DockerConfig;
-pub use error::{Error, ErrorKind};
+pub use error::{Error, ErrorKind, PortOwner};
+pub use events::ModuleEvents;
+pub use image_archive::read_image_archive;
 pub use module::{DockerModule, MODULE_TYPE};
+pub use policy::{Privilege, Privileges};
 pub use runtime::DockerModuleRuntime;
-pub use settings::{LoadSettingsError, Settings, DEFAULTS};
+pub use settings::{
+    ImageGarbageCollection, LoadSettingsError, PrivilegedPolicy, PrivilegedPolicyMode,
+    RegistrySettings, ResourceMonitor, Settings, DEFAULTS,
+};
+pub use validate::{parse_stop_signal, validate_module};
@@ -135,6 +135,46 @@ fn status_from_exit_code(exit_code: Option<i64>) -> Option<ModuleStatus> {
     })
 }
 
+/// Why a container exited, when the container engine knows more than the
+/// exit code: that the kernel killed it for running out of memory, that the
+/// engine failed to run it, or the signal it was killed by.
+fn exit_reason(state: &InlineResponse200State) -> Option<String> {
+    if state.oom_killed() == Some(&true) {
+        return Some("OOM killed".to_string());
+    }
+
+    if let Some(error) = state.error().filter(|error| !error.is_empty()) {
+        return Some(error.to_string());
+    }
+
+    state
+        .exit_code()
+        .and_then(signal_name)
+        .map(|signal| format!("Killed by signal {}", signal))
+}
+
+/// The name of the signal that killed a process, from the exit code of 128
+/// plus the signal number that the container engine reports for it.
+fn signal_name(exit_code: i64) -> Option<String> {
+    let name = match exit_code - 128 {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        14 => "SIGALRM",
+        15 => "SIGTERM",
+        signal if signal > 0 && signal <= 64 => return Some(signal.to_string()),
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
 pub fn runtime_state(
     id: Option<&str>,
     response_state: Option<&InlineResponse200State>,
@@ -149,10 +189,25 @@ pub fn runtime_state(
                 _ => Some(ModuleStatus::Unknown),
             })
             .unwrap_or_else(|| ModuleStatus::Unknown);
+        let exit_reason = match status {
+            ModuleStatus::Stopped | ModuleStatus::Failed => exit_reason(state),
+            ModuleStatus::Running | ModuleStatus::Unknown => None,
+        };
+        // The reason is more telling than the container status "exited", so
+        // it is what the edge agent reports as the module's status. A container
+        // that is "restarting" keeps that status, which the watchdog relies on
+        // to leave it to the restart policy of the container engine.
+        let status_description = match state.status() {
+            Some("restarting") => Some("restarting".to_string()),
+            status => exit_reason
+                .clone()
+                .or_else(|| status.map(ToOwned::to_owned)),
+        };
         ModuleRuntimeState::default()
             .with_status(status)
             .with_exit_code(state.exit_code())
-            .with_status_description(state.status().map(ToOwned::to_owned))
+            .with_exit_reason(exit_reason)
+            .with_status_description(status_description)
             .with_started_at(
                 state
                     .started_at()
@@ -193,7 +248,9 @@ impl<C: 'static + Connect> Module for DockerModule<C> {
             self.client
                 .container_api()
                 .container_inspect(&self.name, false)
-                .map(|resp| runtime_state(resp.id(), resp.state()))
+                .map(|resp| {
+                    runtime_state(resp.id(), resp.state()).with_restart_count(resp.restart_count())
+                })
                 .map_err(|err| {
                     Error::from_docker_error(
                         err,
@@ -327,7 +384,8 @@ mod tests {
                             .with_pid(1234),
                     )
                     .with_id("mod1".to_string())
-                    .with_exec_i_ds(vec!["id1".to_string(), "id2".to_string()]),
+                    .with_exec_i_ds(vec!["id1".to_string(), "id2".to_string()])
+                    .with_restart_count(3),
             ),
             "mod1".to_string(),
             DockerConfig::new("ubuntu".to_string(), ContainerCreateBody::new(), None).unwrap(),
@@ -348,6 +406,7 @@ mod tests {
             runtime_state.finished_at().unwrap().to_rfc3339()
         );
         assert_eq!(Some(1234), runtime_state.pid());
+        assert_eq!(Some(3), runtime_state.restart_count());
     }
 
     #[test]
@@ -386,6 +445,68 @@ mod tests {
         );
     }
 
+    fn exited_runtime_state(state: InlineResponse200State) -> ModuleRuntimeState {
+        runtime_state(Some("mod1"), Some(&state.with_status("exited".to_string())))
+    }
+
+    #[test]
+    fn module_runtime_state_oom_killed() {
+        let runtime_state = exited_runtime_state(
+            InlineResponse200State::new()
+                .with_exit_code(137)
+                .with_oom_killed(true),
+        );
+
+        assert_eq!(ModuleStatus::Failed, *runtime_state.status());
+        assert_eq!(Some(137), runtime_state.exit_code());
+        assert_eq!(Some("OOM killed"), runtime_state.exit_reason());
+        assert_eq!(Some("OOM killed"), runtime_state.status_description());
+    }
+
+    #[test]
+    fn module_runtime_state_restarting_after_oom_killed() {
+        let state = InlineResponse200State::new()
+            .with_status("restarting".to_string())
+            .with_exit_code(137)
+            .with_oom_killed(true);
+        let runtime_state = runtime_state(Some("mod1"), Some(&state));
+
+        assert_eq!(ModuleStatus::Stopped, *runtime_state.status());
+        assert_eq!(Some("OOM killed"), runtime_state.exit_reason());
+        assert_eq!(Some("restarting"), runtime_state.status_description());
+    }
+
+    #[test]
+    fn module_runtime_state_killed_by_signal() {
+        let runtime_state = exited_runtime_state(
+            InlineResponse200State::new()
+                .with_exit_code(139)
+                .with_oom_killed(false),
+        );
+        assert_eq!(
+            Some("Killed by signal SIGSEGV"),
+            runtime_state.exit_reason()
+        );
+
+        let runtime_state = exited_runtime_state(InlineResponse200State::new().with_exit_code(1));
+        assert_eq!(None, runtime_state.exit_reason());
+        assert_eq!(Some("exited"), runtime_state.status_description());
+    }
+
+    #[test]
+    fn module_runtime_state_engine_error() {
+        let runtime_state = exited_runtime_state(
+            InlineResponse200State::new()
+                .with_exit_code(128)
+                .with_error("OCI runtime create failed: executable file not found".to_string()),
+        );
+
+        assert_eq!(
+            Some("OCI runtime create failed: executable file not found"),
+            runtime_state.exit_reason()
+        );
+    }
+
     #[test]
     fn module_runtime_state_with_bad_started_at() {
         let started_at = "not really a date".to_string();
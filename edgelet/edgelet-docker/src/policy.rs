@@ -0,0 +1,350 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Enforcement of the privileged policy, which keeps modules from getting
+//! control over the host through their create options.
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use log::warn;
+
+use docker::models::ContainerCreateBody;
+
+use crate::error::ErrorKind;
+use crate::settings::{PrivilegedPolicy, PrivilegedPolicyMode};
+
+/// A privilege over the host that a module's create options ask for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Privilege {
+    Privileged,
+    HostNetwork,
+    HostPid,
+    SensitiveMount(String),
+}
+
+impl fmt::Display for Privilege {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Privilege::Privileged => write!(f, "privileged mode"),
+            Privilege::HostNetwork => write!(f, "the host's network"),
+            Privilege::HostPid => write!(f, "the host's PID namespace"),
+            Privilege::SensitiveMount(source) => write!(f, "a bind mount of {}", source),
+        }
+    }
+}
+
+/// The privileges a module asks for, in the order its create options list them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Privileges(Vec<Privilege>);
+
+impl Privileges {
+    pub fn iter(&self) -> impl Iterator<Item = &Privilege> {
+        self.0.iter()
+    }
+}
+
+impl fmt::Display for Privileges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, privilege) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", privilege)?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies the privileged policy to the create options of module `name`. A
+/// denied module fails with the privileges it asked for; an audited one is
+/// only logged.
+pub(crate) fn check_privileges(
+    policy: &PrivilegedPolicy,
+    name: &str,
+    create_options: &ContainerCreateBody,
+) -> std::result::Result<(), ErrorKind> {
+    if policy.mode() == PrivilegedPolicyMode::Allow
+        || policy.exempt_modules().iter().any(|exempt| exempt == name)
+    {
+        return Ok(());
+    }
+
+    let privileges = privileges(create_options, policy.sensitive_paths());
+    if privileges.is_empty() {
+        return Ok(());
+    }
+    let privileges = Privileges(privileges);
+
+    if policy.mode() == PrivilegedPolicyMode::Deny {
+        return Err(ErrorKind::PrivilegedModule {
+            module: name.to_string(),
+            privileges,
+        });
+    }
+
+    warn!(
+        "Module {} asks for privileges over the host: {}",
+        name, privileges
+    );
+    Ok(())
+}
+
+fn privileges(create_options: &ContainerCreateBody, sensitive_paths: &[PathBuf]) -> Vec<Privilege> {
+    let mut privileges = vec![];
+    let host_config = match create_options.host_config() {
+        Some(host_config) => host_config,
+        None => return privileges,
+    };
+
+    if host_config.privileged() == Some(&true) {
+        privileges.push(Privilege::Privileged);
+    }
+    if host_config.network_mode() == Some("host") {
+        privileges.push(Privilege::HostNetwork);
+    }
+    if host_config.pid_mode() == Some("host") {
+        privileges.push(Privilege::HostPid);
+    }
+
+    let binds = host_config
+        .binds()
+        .unwrap_or_default()
+        .iter()
+        .map(|bind| bind_source(bind));
+    let mounts = host_config
+        .mounts()
+        .unwrap_or_default()
+        .iter()
+        .filter(|mount| mount._type() == Some("bind"))
+        .filter_map(|mount| mount.source());
+    for source in binds.chain(mounts) {
+        if is_sensitive(Path::new(source), sensitive_paths) {
+            privileges.push(Privilege::SensitiveMount(source.to_string()));
+        }
+    }
+
+    privileges
+}
+
+/// The host side of a bind of the form `source:destination[:options]`. On
+/// Windows, the source may start with a drive letter, as in `C:\data:C:\data`.
+fn bind_source(bind: &str) -> &str {
+    let start = if cfg!(windows) && bind.as_bytes().get(1) == Some(&b':') {
+        2
+    } else {
+        0
+    };
+    match bind[start..].find(':') {
+        Some(end) => &bind[..start + end],
+        None => bind,
+    }
+}
+
+/// Whether `source` is one of `sensitive_paths` or under one of them. The
+/// root directory only matches itself, or every bind would be sensitive.
+fn is_sensitive(source: &Path, sensitive_paths: &[PathBuf]) -> bool {
+    let source = normalize(source);
+    sensitive_paths
+        .iter()
+        .map(PathBuf::as_path)
+        .map(normalize)
+        .any(|path| source == path || (path.parent().is_some() && source.starts_with(&path)))
+}
+
+/// `path` with its `.` and `..` components resolved without looking at the
+/// file system, so that `/var/../etc` is compared as `/etc`. A `..` at the
+/// root stays at the root, as it does when the path is resolved.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component.as_os_str()),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use docker::models::{HostConfig, Mount};
+
+    use super::*;
+
+    fn policy(mode: PrivilegedPolicyMode) -> PrivilegedPolicy {
+        PrivilegedPolicy::default()
+            .with_mode(mode)
+            .with_sensitive_paths(vec![PathBuf::from("/"), PathBuf::from("/etc")])
+    }
+
+    fn create_options(host_config: HostConfig) -> ContainerCreateBody {
+        ContainerCreateBody::new().with_host_config(host_config)
+    }
+
+    #[test]
+    fn privileged_host_network_and_host_pid_are_found() {
+        let create_options = create_options(
+            HostConfig::new()
+                .with_privileged(true)
+                .with_network_mode("host".to_string())
+                .with_pid_mode("host".to_string()),
+        );
+
+        assert_eq!(
+            vec![
+                Privilege::Privileged,
+                Privilege::HostNetwork,
+                Privilege::HostPid
+            ],
+            privileges(&create_options, &[]),
+        );
+    }
+
+    #[test]
+    fn other_modes_are_not_privileges() {
+        let create_options = create_options(
+            HostConfig::new()
+                .with_privileged(false)
+                .with_network_mode("azure-iot-edge".to_string())
+                .with_pid_mode("container:edgeHub".to_string()),
+        );
+
+        assert!(privileges(&create_options, &[]).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sensitive_binds_and_mounts_are_found() {
+        let create_options = create_options(
+            HostConfig::new()
+                .with_binds(vec![
+                    "/etc/ssl:/etc/ssl:ro".to_string(),
+                    "/data:/data".to_string(),
+                    "/:/host".to_string(),
+                    "etc:/etc".to_string(),
+                ])
+                .with_mounts(vec![
+                    Mount::new()
+                        .with__type("bind".to_string())
+                        .with_source("/etc".to_string()),
+                    Mount::new()
+                        .with__type("volume".to_string())
+                        .with_source("/etc".to_string()),
+                    Mount::new()
+                        .with__type("bind".to_string())
+                        .with_source("/etcetera".to_string()),
+                ]),
+        );
+
+        assert_eq!(
+            vec![
+                Privilege::SensitiveMount("/etc/ssl".to_string()),
+                Privilege::SensitiveMount("/".to_string()),
+                Privilege::SensitiveMount("/etc".to_string()),
+            ],
+            privileges(
+                &create_options,
+                &[PathBuf::from("/"), PathBuf::from("/etc")]
+            ),
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn binds_that_climb_into_sensitive_paths_are_found() {
+        let create_options = create_options(HostConfig::new().with_binds(vec![
+            "/var/../etc:/host-etc".to_string(),
+            "/etc/../:/host".to_string(),
+            "/data/./../etc/ssl:/ssl".to_string(),
+            "/etc/../data:/data".to_string(),
+        ]));
+
+        assert_eq!(
+            vec![
+                Privilege::SensitiveMount("/var/../etc".to_string()),
+                Privilege::SensitiveMount("/etc/../".to_string()),
+                Privilege::SensitiveMount("/data/./../etc/ssl".to_string()),
+            ],
+            privileges(
+                &create_options,
+                &[PathBuf::from("/"), PathBuf::from("/etc")]
+            ),
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parent_of_root_is_root() {
+        assert_eq!(PathBuf::from("/"), normalize(Path::new("/../..")));
+        assert_eq!(
+            PathBuf::from("/etc"),
+            normalize(Path::new("/var/./../etc/"))
+        );
+    }
+
+    #[test]
+    fn allow_creates_privileged_module() {
+        let create_options = create_options(HostConfig::new().with_privileged(true));
+
+        assert!(check_privileges(
+            &policy(PrivilegedPolicyMode::Allow),
+            "mod1",
+            &create_options
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn audit_creates_privileged_module() {
+        let create_options = create_options(HostConfig::new().with_privileged(true));
+
+        assert!(check_privileges(
+            &policy(PrivilegedPolicyMode::Audit),
+            "mod1",
+            &create_options
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn deny_fails_privileged_module() {
+        let create_options = create_options(
+            HostConfig::new()
+                .with_privileged(true)
+                .with_network_mode("host".to_string()),
+        );
+
+        match check_privileges(&policy(PrivilegedPolicyMode::Deny), "mod1", &create_options) {
+            Err(ErrorKind::PrivilegedModule { module, privileges }) => {
+                assert_eq!("mod1", module);
+                assert_eq!(
+                    vec![&Privilege::Privileged, &Privilege::HostNetwork],
+                    privileges.iter().collect::<Vec<_>>(),
+                );
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn deny_creates_unprivileged_and_exempt_modules() {
+        let policy =
+            policy(PrivilegedPolicyMode::Deny).with_exempt_modules(vec!["mod2".to_string()]);
+
+        assert!(check_privileges(
+            &policy,
+            "mod1",
+            &create_options(HostConfig::new().with_privileged(false))
+        )
+        .is_ok());
+        assert!(check_privileges(
+            &policy,
+            "mod2",
+            &create_options(HostConfig::new().with_privileged(true))
+        )
+        .is_ok());
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::{Future, Stream};
+use log::{warn, Level};
+use tokio::timer::Interval;
+
+use edgelet_utils::log_failure;
+
+use crate::image_gc;
+use crate::runtime::DockerModuleRuntime;
+use crate::settings::{ImageGarbageCollection, ResourceMonitor};
+
+/// Periodically checks the free disk space, inodes and memory of the host, as configured by
+/// `settings`. Unused images are removed according to `image_gc` when the disk is critically low.
+pub(crate) fn start(
+    runtime: DockerModuleRuntime,
+    settings: ResourceMonitor,
+    image_gc: ImageGarbageCollection,
+) -> impl Future<Item = (), Error = ()> + Send {
+    Interval::new(Instant::now(), settings.interval())
+        .map_err(|err| warn!("Resource monitor timer failed: {}", err))
+        .for_each(move |_| {
+            runtime
+                .check_resources(&settings, &image_gc)
+                .then(|result| {
+                    if let Err(err) = result {
+                        log_failure(Level::Warn, &err);
+                    }
+                    Ok(())
+                })
+        })
+}
+
+/// The free resources of the host in percent. A resource that could not be measured is `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct HostResources {
+    disk: Option<f64>,
+    inodes: Option<f64>,
+    memory: Option<f64>,
+}
+
+impl HostResources {
+    /// Measures the free resources of the host. Disk space and inodes are measured on the
+    /// disk that holds `path`.
+    pub(crate) fn measure(path: Option<&str>) -> Self {
+        HostResources {
+            disk: path.and_then(image_gc::free_disk_percent),
+            inodes: path.and_then(free_inodes_percent),
+            memory: free_memory_percent(),
+        }
+    }
+
+    /// Returns a warning for every resource that is below its threshold in `settings`.
+    pub(crate) fn warnings(&self, settings: &ResourceMonitor) -> Vec<String> {
+        let checks = [
+            (self.disk, settings.min_free_disk_percent(), "disk space"),
+            (self.inodes, settings.min_free_inodes_percent(), "inodes"),
+            (self.memory, settings.min_free_memory_percent(), "memory"),
+        ];
+
+        checks
+            .iter()
+            .filter_map(|(free, min_free, resource)| match free {
+                Some(free) if *free < f64::from(*min_free) => Some(format!(
+                    "Only {:.1}% of {} is free, which is below the threshold of {}%.",
+                    free, resource, min_free
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the disk is low enough that unused images should be removed right away.
+    pub(crate) fn should_collect_images(&self, settings: &ResourceMonitor) -> bool {
+        match (self.disk, settings.collect_images_below_disk_percent()) {
+            (Some(free), Some(threshold)) => free < f64::from(threshold),
+            _ => false,
+        }
+    }
+}
+
+/// The warnings raised by the last check of the host's resources.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ResourceWarnings {
+    warnings: Arc<Mutex<Vec<String>>>,
+}
+
+impl ResourceWarnings {
+    pub(crate) fn get(&self) -> Vec<String> {
+        self.warnings
+            .lock()
+            .expect("Failed to acquire the resource warnings lock")
+            .clone()
+    }
+
+    pub(crate) fn set(&self, warnings: Vec<String>) {
+        *self
+            .warnings
+            .lock()
+            .expect("Failed to acquire the resource warnings lock") = warnings;
+    }
+}
+
+/// Returns the percentage of free inodes of the disk that holds `path`.
+#[cfg(not(windows))]
+fn free_inodes_percent(path: &str) -> Option<f64> {
+    use std::ffi::CString;
+    use std::mem;
+
+    let path = CString::new(path).ok()?;
+    let mut stats: libc::statvfs = unsafe { mem::zeroed() };
+    let ret = unsafe { libc::statvfs(path.as_ptr(), &mut stats) };
+
+    // File systems without a fixed number of inodes report a total of zero
+    if ret != 0 || stats.f_files == 0 {
+        return None;
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::unnecessary_cast)]
+    let percent = stats.f_favail as f64 * 100.0 / stats.f_files as f64;
+    Some(percent)
+}
+
+/// Returns the percentage of free inodes of the disk that holds `path`.
+#[cfg(windows)]
+fn free_inodes_percent(_path: &str) -> Option<f64> {
+    None
+}
+
+/// Returns the percentage of free memory of the host.
+#[cfg(not(windows))]
+fn free_memory_percent() -> Option<f64> {
+    use sysinfo::SystemExt;
+
+    let mut system_info = sysinfo::System::new();
+    system_info.refresh_memory();
+
+    let total = system_info.get_total_memory();
+    if total == 0 {
+        return None;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let percent = total.saturating_sub(system_info.get_used_memory()) as f64 * 100.0 / total as f64;
+    Some(percent)
+}
+
+/// Returns the percentage of free memory of the host.
+#[cfg(windows)]
+fn free_memory_percent() -> Option<f64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(disk: f64, inodes: f64, memory: f64) -> HostResources {
+        HostResources {
+            disk: Some(disk),
+            inodes: Some(inodes),
+            memory: Some(memory),
+        }
+    }
+
+    #[test]
+    fn no_warnings_above_thresholds() {
+        let settings = ResourceMonitor::default();
+
+        assert!(resources(50.0, 50.0, 50.0).warnings(&settings).is_empty());
+    }
+
+    #[test]
+    fn warns_for_each_resource_below_threshold() {
+        let settings = ResourceMonitor::default()
+            .with_min_free_disk_percent(20)
+            .with_min_free_memory_percent(5);
+
+        assert_eq!(
+            vec![
+                "Only 12.5% of disk space is free, which is below the threshold of 20%."
+                    .to_string(),
+                "Only 4.0% of memory is free, which is below the threshold of 5%.".to_string(),
+            ],
+            resources(12.5, 50.0, 4.0).warnings(&settings)
+        );
+    }
+
+    #[test]
+    fn unmeasured_resources_do_not_warn() {
+        let settings = ResourceMonitor::default();
+
+        assert!(HostResources::default().warnings(&settings).is_empty());
+        assert!(!HostResources::default()
+            .should_collect_images(&settings.with_collect_images_below_disk_percent(Some(5))));
+    }
+
+    #[test]
+    fn collects_images_only_when_configured_and_critically_low() {
+        let settings = ResourceMonitor::default();
+        assert!(!resources(1.0, 50.0, 50.0).should_collect_images(&settings));
+
+        let settings = settings.with_collect_images_below_disk_percent(Some(5));
+        assert!(resources(4.0, 50.0, 50.0).should_collect_images(&settings));
+        assert!(!resources(6.0, 50.0, 50.0).should_collect_images(&settings));
+    }
+}
@@ -1,39 +1,60 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, UdpSocket};
 use std::ops::Deref;
-use std::time::Duration;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use base64;
 use failure::{Fail, ResultExt};
-use futures::future::Either;
+use futures::future::{Either, Loop};
 use futures::prelude::*;
 use futures::{future, stream, Async, Stream};
 use hyper::{Body, Chunk as HyperChunk, Client, Request};
 use lazy_static::lazy_static;
-use log::{debug, info, Level};
+use log::{debug, info, warn, Level};
 use serde_json;
+use tokio::timer::Delay;
 use url::Url;
 
 use docker::apis::client::APIClient;
 use docker::apis::configuration::Configuration;
-use docker::models::{ContainerCreateBody, InlineResponse200, Ipam, NetworkConfig};
+use docker::apis::Error as DockerError;
+use docker::models::{
+    ContainerCreateBody, ContainerSummary, ExecConfig, ExecStartConfig, HostConfig,
+    InlineResponse200, Ipam, NetworkConfig, RestartPolicy as DockerRestartPolicy,
+};
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, Ipam as CoreIpam, LogOptions, MakeModuleRuntime,
-    MobyNetwork, Module, ModuleId, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec,
-    RegistryOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
+    AuthId, Authenticator, Chunked, GetTrustBundle, ImageInfo, Ipam as CoreIpam, LogChunk,
+    LogDecode, LogOptions, MakeModuleRuntime, MobyNetwork, Module, ModuleExecResult, ModuleId,
+    ModuleProcesses, ModuleRegistry, ModuleRuntime, ModuleRuntimeState, ModuleSpec, PrunedImages,
+    RegistryOperation, RestartPolicy, RuntimeOperation, RuntimeSettings,
+    SystemInfo as CoreSystemInfo, SystemResources, UrlExt, UNIX_SCHEME,
 };
 use edgelet_http::{Pid, UrlConnector};
-use edgelet_utils::{ensure_not_empty_with_context, log_failure};
+use edgelet_utils::{ensure_not_empty_with_context, log_failure, RetryPolicy};
 use provisioning::ProvisioningResult;
 
 use crate::client::DockerClient;
 use crate::config::DockerConfig;
-use crate::error::{Error, ErrorKind, Result};
+use crate::error::{Error, ErrorKind, PortOwner, Result};
+use crate::events::ModuleEvents;
+use crate::image_archive::read_image_archive;
+use crate::image_gc;
 use crate::module::{
     runtime_state, DockerModule, DockerModuleTop, MODULE_TYPE as DOCKER_MODULE_TYPE,
 };
-use crate::settings::Settings;
+use crate::policy;
+use crate::registry::{self, CERTS_DIR};
+use crate::resource_monitor::{self, HostResources, ResourceWarnings};
+use crate::settings::{
+    ImageGarbageCollection, PrivilegedPolicy, RegistrySettings, ResourceMonitor, Settings,
+};
+use crate::validate::{host_ports, validate_module};
+use crate::version::{self, ApiVersion};
 
 #[cfg(not(windows))]
 use edgelet_core::DiskInfo;
@@ -44,8 +65,6 @@ use std::mem;
 #[cfg(not(windows))]
 use std::process;
 #[cfg(not(windows))]
-use std::time::{SystemTime, UNIX_EPOCH};
-#[cfg(not(windows))]
 use sysinfo::{DiskExt, ProcessExt, ProcessorExt, SystemExt};
 
 type Deserializer = &'static mut serde_json::Deserializer<serde_json::de::IoRead<std::io::Empty>>;
@@ -53,8 +72,10 @@ type Deserializer = &'static mut serde_json::Deserializer<serde_json::de::IoRead
 static LABEL_KEY: &str = "net.azure-devices.edge.owner";
 static LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
 
+const PIPE_SCHEME: &str = "npipe";
+
 lazy_static! {
-    static ref LABELS: Vec<&'static str> = {
+    pub(crate) static ref LABELS: Vec<&'static str> = {
         let mut labels = vec![];
         labels.push("net.azure-devices.edge.owner=Microsoft.Azure.Devices.Edge.Agent");
         labels
@@ -64,6 +85,11 @@ lazy_static! {
 #[derive(Clone)]
 pub struct DockerModuleRuntime {
     client: DockerClient<UrlConnector>,
+    resource_warnings: ResourceWarnings,
+    oci_runtime: Option<String>,
+    image_pull_retry: Option<RetryPolicy>,
+    registries: BTreeMap<String, RegistrySettings>,
+    privileged_policy: PrivilegedPolicy,
 }
 
 impl DockerModuleRuntime {
@@ -88,6 +114,253 @@ impl DockerModuleRuntime {
             .map(|(key, value)| format!("{}={}", key, value))
             .collect()
     }
+
+    // Translates the module's restart policy into a docker restart policy. A
+    // restart policy that is already set in the create options takes precedence.
+    fn apply_restart_policy(
+        create_options: ContainerCreateBody,
+        restart_policy: RestartPolicy,
+        max_retries: Option<u32>,
+    ) -> ContainerCreateBody {
+        let host_config = create_options
+            .host_config()
+            .cloned()
+            .unwrap_or_else(HostConfig::new);
+        if host_config
+            .restart_policy()
+            .and_then(DockerRestartPolicy::name)
+            .map_or(false, |name| !name.is_empty())
+        {
+            return create_options;
+        }
+
+        let docker_restart_policy = match restart_policy {
+            RestartPolicy::Never => return create_options,
+            // Docker can't tell an unhealthy container apart from a healthy one,
+            // so it can only take care of the failures.
+            RestartPolicy::OnFailure | RestartPolicy::OnUnhealthy => {
+                let policy = DockerRestartPolicy::new().with_name("on-failure".to_string());
+                match max_retries {
+                    Some(max_retries) => policy.with_maximum_retry_count(
+                        i32::try_from(max_retries).unwrap_or(i32::max_value()),
+                    ),
+                    None => policy,
+                }
+            }
+            // "always" would bring back modules that were deliberately stopped
+            // whenever the docker daemon restarts.
+            RestartPolicy::Always => {
+                DockerRestartPolicy::new().with_name("unless-stopped".to_string())
+            }
+        };
+
+        create_options.with_host_config(host_config.with_restart_policy(docker_restart_policy))
+    }
+
+    /// Sets the OCI runtime of `create_options` to the one configured for all
+    /// modules, unless the create options already name a runtime.
+    fn apply_oci_runtime(
+        create_options: ContainerCreateBody,
+        oci_runtime: Option<&str>,
+    ) -> ContainerCreateBody {
+        let oci_runtime = match oci_runtime {
+            Some(oci_runtime) => oci_runtime,
+            None => return create_options,
+        };
+
+        let host_config = create_options
+            .host_config()
+            .cloned()
+            .unwrap_or_else(HostConfig::new);
+        if host_config.runtime().is_some() {
+            return create_options;
+        }
+
+        create_options.with_host_config(host_config.with_runtime(oci_runtime.to_string()))
+    }
+}
+
+impl DockerModuleRuntime {
+    /// Removes the images that are not used by any container, according to `settings`.
+    pub fn collect_images(
+        &self,
+        settings: &ImageGarbageCollection,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let should_collect = match settings.min_free_disk_percent() {
+            Some(min_free_disk_percent) => Either::A(
+                self.client
+                    .system_api()
+                    .system_info()
+                    .map_err(|err| {
+                        Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                        )
+                    })
+                    .map(move |system_info| {
+                        let free_disk_percent = system_info
+                            .docker_root_dir()
+                            .and_then(image_gc::free_disk_percent);
+                        match free_disk_percent {
+                            Some(free) if free >= f64::from(min_free_disk_percent) => {
+                                debug!(
+                                    "Skipping image garbage collection, {:.1}% of disk space is free",
+                                    free
+                                );
+                                false
+                            }
+                            _ => true,
+                        }
+                    }),
+            ),
+            None => Either::B(future::ok(true)),
+        };
+
+        let client = self.client.clone();
+        let runtime = self.clone();
+        let settings = settings.clone();
+
+        should_collect.and_then(move |should_collect| {
+            if !should_collect {
+                return Either::A(future::ok(()));
+            }
+
+            info!("Collecting unused images...");
+
+            let in_use = client
+                .container_api()
+                .container_list(true, 0, false, "")
+                .map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
+                    )
+                })
+                .map(|containers| {
+                    containers
+                        .iter()
+                        .map(|container| container.image_id().clone())
+                        .collect::<HashSet<_>>()
+                });
+
+            let images = client
+                .image_api()
+                .image_list(false, "", false)
+                .map_err(|err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RegistryOperation(RegistryOperation::ListImages),
+                    )
+                });
+
+            Either::B(
+                in_use
+                    .join(images)
+                    .and_then(move |(in_use, images)| {
+                        #[allow(clippy::cast_possible_wrap)]
+                        let now = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs() as i64;
+                        let names = image_gc::images_to_remove(&images, &in_use, &settings, now);
+
+                        // Failing to remove one image (for example because a container started
+                        // using it in the meantime) should not prevent removing the others.
+                        stream::iter_ok(names)
+                            .and_then(move |name| {
+                                ModuleRegistry::remove(&runtime, &name)
+                                    .then(|result| Ok(result.is_ok()))
+                            })
+                            .fold(0, |removed, success| {
+                                Ok::<_, Error>(if success { removed + 1 } else { removed })
+                            })
+                    })
+                    .map(|removed| info!("Finished collecting unused images, removed {}", removed)),
+            )
+        })
+    }
+
+    /// Checks the free resources of the host against `settings`, and removes unused images
+    /// according to `image_gc` if the disk is critically low. The warnings raised by the check
+    /// are reported along with the system info.
+    pub fn check_resources(
+        &self,
+        settings: &ResourceMonitor,
+        image_gc: &ImageGarbageCollection,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let runtime = self.clone();
+        let settings = settings.clone();
+        // The disk is already known to be low, so there's no point in checking it again
+        let image_gc = image_gc.clone().with_min_free_disk_percent(None);
+
+        self.client
+            .system_api()
+            .system_info()
+            .map_err(|err| {
+                Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                )
+            })
+            .and_then(move |system_info| {
+                let resources = HostResources::measure(system_info.docker_root_dir());
+                debug!("Measured host resources: {:?}", resources);
+
+                let warnings = resources.warnings(&settings);
+                for warning in &warnings {
+                    warn!("{}", warning);
+                }
+                runtime.resource_warnings.set(warnings);
+
+                if resources.should_collect_images(&settings) {
+                    warn!("Disk space is critically low, removing unused images");
+                    Either::A(runtime.collect_images(&image_gc))
+                } else {
+                    Either::B(future::ok(()))
+                }
+            })
+    }
+}
+
+impl DockerModuleRuntime {
+    /// Loads an image from its archive instead of pulling it from a registry,
+    /// so that modules can be created on devices that can't reach one.
+    fn load(&self, image: String, image_archive: &Path) -> impl Future<Item = (), Error = Error> {
+        info!(
+            "Loading image {} from {}...",
+            image,
+            image_archive.display()
+        );
+
+        let client = self.client.clone();
+        let archive = read_image_archive(image_archive).with_context(|_| {
+            ErrorKind::RegistryOperation(RegistryOperation::LoadImage(image.clone()))
+        });
+        future::result(archive)
+            .from_err()
+            .and_then(move |archive| {
+                client
+                    .image_api()
+                    .image_load(archive, true)
+                    .then(|result| match result {
+                        Ok(()) => Ok(image),
+                        Err(err) => Err(Error::from_docker_error(
+                            err,
+                            ErrorKind::RegistryOperation(RegistryOperation::LoadImage(image)),
+                        )),
+                    })
+            })
+            .then(|result| match result {
+                Ok(image) => {
+                    info!("Successfully loaded image {}", image);
+                    Ok(())
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            })
+    }
 }
 
 impl std::fmt::Debug for DockerModuleRuntime {
@@ -99,12 +372,16 @@ impl std::fmt::Debug for DockerModuleRuntime {
 impl ModuleRegistry for DockerModuleRuntime {
     type Error = Error;
     type PullFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
-    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error>>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type Config = DockerConfig;
 
     fn pull(&self, config: &Self::Config) -> Self::PullFuture {
         let image = config.image().to_string();
 
+        if let Some(image_archive) = config.image_archive() {
+            return Box::new(self.load(image, image_archive));
+        }
+
         info!("Pulling image {}...", image);
 
         let creds: Result<String> = config.auth().map_or_else(
@@ -117,31 +394,87 @@ impl ModuleRegistry for DockerModuleRuntime {
             },
         );
 
-        let response = creds
-            .map(|creds| {
-                self.client
-                    .image_api()
-                    .image_create(&image, "", "", "", "", &creds, "")
-                    .then(|result| match result {
-                        Ok(()) => Ok(image),
-                        Err(err) => Err(Error::from_docker_error(
-                            err,
-                            ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
-                        )),
+        // Pulls that fail because the registry or the engine is unavailable
+        // are retried by the image pull retry policy, if there is one.
+        let client = self.client.clone();
+        let retry_policy = self.image_pull_retry.clone();
+        let registries = self.registries.clone();
+        let started = Instant::now();
+        let response =
+            creds
+                .map(move |creds| {
+                    future::loop_fn(0, move |retry: u32| {
+                        let image = image.clone();
+                        let retry_policy = retry_policy.clone();
+                        let registries = registries.clone();
+                        client
+                            .image_api()
+                            .image_create(&image, "", "", "", "", &creds, "")
+                            .then(move |result| {
+                                let err = match result {
+                                    Ok(()) => return Either::A(future::ok(Loop::Break(image))),
+                                    Err(err) => err,
+                                };
+                                // A registry whose certificate can't be verified
+                                // won't be trusted on the next attempt either.
+                                if registry::is_tls_failure(&err) {
+                                    let registry = registry::registry_of(&image).to_string();
+                                    let advice = registry::tls_failure_advice(
+                                        &registry,
+                                        registries.get(&registry),
+                                    );
+                                    let err = Error::from_docker_error(
+                                        err,
+                                        ErrorKind::UntrustedRegistry { registry, advice },
+                                    )
+                                    .context(
+                                        ErrorKind::RegistryOperation(RegistryOperation::PullImage(
+                                            image,
+                                        )),
+                                    );
+                                    return Either::A(future::err(Error::from(err)));
+                                }
+
+                                let transient = is_transient_pull_error(&err);
+                                let err = Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(
+                                        image.clone(),
+                                    )),
+                                );
+                                match retry_policy {
+                                    Some(retry_policy) if transient => {
+                                        let delay = retry_policy.delay(retry);
+                                        if !retry_policy.allows(started, delay) {
+                                            return Either::A(future::err(err));
+                                        }
+                                        info!(
+                                            "Could not pull image {}, retrying in {} ms: {}",
+                                            image,
+                                            delay.as_millis(),
+                                            err
+                                        );
+                                        Either::B(Delay::new(Instant::now() + delay).then(
+                                            move |_| Ok(Loop::Continue(retry.saturating_add(1))),
+                                        ))
+                                    }
+                                    _ => Either::A(future::err(err)),
+                                }
+                            })
                     })
-            })
-            .into_future()
-            .flatten()
-            .then(move |result| match result {
-                Ok(image) => {
-                    info!("Successfully pulled image {}", image);
-                    Ok(())
-                }
-                Err(err) => {
-                    log_failure(Level::Warn, &err);
-                    Err(err)
-                }
-            });
+                })
+                .into_future()
+                .flatten()
+                .then(move |result| match result {
+                    Ok(image) => {
+                        info!("Successfully pulled image {}", image);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                });
 
         Box::new(response)
     }
@@ -179,6 +512,16 @@ impl ModuleRegistry for DockerModuleRuntime {
     }
 }
 
+// Pulls that couldn't reach the engine, or that the engine failed with a
+// server error, like when it couldn't reach the registry, are worth retrying.
+fn is_transient_pull_error(err: &DockerError<serde_json::Value>) -> bool {
+    match err {
+        DockerError::Hyper(_) => true,
+        DockerError::Api(err) => err.code.is_server_error(),
+        DockerError::Serde(_) => false,
+    }
+}
+
 fn parse_get_response<'de, D>(resp: &InlineResponse200) -> std::result::Result<String, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -190,6 +533,212 @@ where
     Ok(name)
 }
 
+impl DockerModuleRuntime {
+    /// Warns about the registries that are allowed to be insecure, but that
+    /// the container engine would still verify the certificates of.
+    fn check_insecure_registries(&self) -> impl Future<Item = (), Error = ()> {
+        let registries = self.registries.clone();
+        self.client
+            .system_api()
+            .system_info()
+            .map(move |system_info| {
+                for registry in registry::missing_insecure_registries(&system_info, &registries) {
+                    warn!(
+                        "Registry {} is allowed to be insecure, but the container engine doesn't list it in its insecure registries. Pulls from it fail until it is added to insecure-registries in the container engine's daemon.json.",
+                        registry
+                    );
+                }
+            })
+            .map_err(|err| {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                );
+                log_failure(Level::Warn, &err);
+            })
+    }
+
+    /// Fails with `ErrorKind::PortConflict` if a host port in `ports` is
+    /// already used by a running container, a module or not, or by a process
+    /// on the host. The container of module `name` is left out, as it is the
+    /// one being replaced.
+    fn check_host_ports(
+        &self,
+        name: String,
+        ports: HashSet<(u16, String)>,
+        operation: RuntimeOperation,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.client
+            .container_api()
+            .container_list(false, 0, false, "")
+            .then(move |result| {
+                let containers = result.map_err(|err| {
+                    Error::from_docker_error(err, ErrorKind::RuntimeOperation(operation.clone()))
+                })?;
+
+                // Sorted so that the reported conflict doesn't depend on set order.
+                let mut ports: Vec<(u16, String)> = ports.into_iter().collect();
+                ports.sort_unstable();
+
+                let conflict =
+                    port_conflict_with_containers(&name, &ports, &containers).or_else(|| {
+                        ports
+                            .iter()
+                            .find(|(port, protocol)| host_port_in_use(*port, protocol))
+                            .map(|(port, protocol)| (*port, protocol.clone(), PortOwner::Host))
+                    });
+                match conflict {
+                    Some((port, protocol, owner)) => Err(Error::from(
+                        ErrorKind::PortConflict {
+                            port,
+                            protocol,
+                            owner,
+                        }
+                        .context(ErrorKind::RuntimeOperation(operation)),
+                    )),
+                    None => Ok(()),
+                }
+            })
+    }
+
+    /// Checks that the container engine has the OCI runtime a module asks for,
+    /// so that a module isn't torn down for a replacement that can't be
+    /// created. Engines that don't report their runtimes are left to fail the
+    /// creation themselves.
+    fn check_oci_runtime(
+        &self,
+        oci_runtime: String,
+        operation: RuntimeOperation,
+    ) -> impl Future<Item = (), Error = Error> {
+        self.client.system_api().system_info().then(move |result| {
+            let system_info = result.map_err(|err| {
+                Error::from_docker_error(err, ErrorKind::RuntimeOperation(operation.clone()))
+            })?;
+
+            let runtimes = match system_info.runtimes() {
+                Some(runtimes) => runtimes,
+                None => return Ok(()),
+            };
+            if runtimes.contains_key(&oci_runtime) {
+                return Ok(());
+            }
+
+            let mut available: Vec<&str> = runtimes.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            Err(Error::from(
+                ErrorKind::UnknownOciRuntime {
+                    runtime: oci_runtime,
+                    available: available.join(", "),
+                }
+                .context(ErrorKind::RuntimeOperation(operation)),
+            ))
+        })
+    }
+
+    /// Runs the checks against the state of the container engine that have to
+    /// pass before module `name` is created: its host ports must be free and
+    /// its OCI runtime must exist.
+    fn check_engine(
+        &self,
+        name: &str,
+        create_options: &ContainerCreateBody,
+        operation: &RuntimeOperation,
+    ) -> impl Future<Item = (), Error = Error> {
+        let ports = host_ports(create_options);
+        let ports_check = if ports.is_empty() {
+            Either::A(future::ok(()))
+        } else {
+            Either::B(self.check_host_ports(name.to_string(), ports, operation.clone()))
+        };
+
+        let oci_runtime = oci_runtime(create_options, self.oci_runtime.as_ref().map(AsRef::as_ref));
+        let runtime_check = match oci_runtime {
+            Some(oci_runtime) => {
+                Either::A(self.check_oci_runtime(oci_runtime.to_string(), operation.clone()))
+            }
+            None => Either::B(future::ok(())),
+        };
+
+        ports_check.join(runtime_check).map(|_| ())
+    }
+}
+
+/// The OCI runtime a module runs with: the one its create options name, or
+/// else the one configured for all modules.
+fn oci_runtime<'a>(
+    create_options: &'a ContainerCreateBody,
+    default: Option<&'a str>,
+) -> Option<&'a str> {
+    create_options
+        .host_config()
+        .and_then(HostConfig::runtime)
+        .or(default)
+}
+
+/// The first of `ports` that one of `containers` other than the container of
+/// module `name` publishes, with what that container is.
+fn port_conflict_with_containers(
+    name: &str,
+    ports: &[(u16, String)],
+    containers: &[ContainerSummary],
+) -> Option<(u16, String, PortOwner)> {
+    for container in containers {
+        let other = container
+            .names()
+            .iter()
+            .next()
+            .map_or("Unknown", |s| &s[1..]);
+        if other == name {
+            continue;
+        }
+
+        let published: HashSet<(u16, &str)> = container
+            .ports()
+            .iter()
+            .filter_map(|port| {
+                let public_port = port.public_port()?;
+                let public_port = u16::try_from(public_port).ok()?;
+                Some((public_port, port._type().as_str()))
+            })
+            .collect();
+        if let Some((port, protocol)) = ports
+            .iter()
+            .find(|(port, protocol)| published.contains(&(*port, protocol.as_str())))
+        {
+            let owner =
+                if container.labels().get(LABEL_KEY).map(String::as_str) == Some(LABEL_VALUE) {
+                    PortOwner::Module(other.to_string())
+                } else {
+                    PortOwner::Container(other.to_string())
+                };
+            return Some((*port, protocol.clone(), owner));
+        }
+    }
+
+    None
+}
+
+/// Whether a process on the host already uses `port`. Ports that can't be
+/// bound for any other reason, such as privileged ports, are left to docker.
+/// Both IPv4 and IPv6 are checked, as docker publishes ports on both and a
+/// process may listen on either; a family the host has no stack for can't
+/// be bound, so it doesn't count as in use.
+fn host_port_in_use(port: u16, protocol: &str) -> bool {
+    let addresses: [IpAddr; 2] = [Ipv4Addr::UNSPECIFIED.into(), Ipv6Addr::UNSPECIFIED.into()];
+    addresses.iter().any(|ip| {
+        let address = SocketAddr::new(*ip, port);
+        let result = match protocol {
+            "tcp" => TcpListener::bind(address).map(drop),
+            "udp" => UdpSocket::bind(address).map(drop),
+            _ => return false,
+        };
+        match result {
+            Ok(()) => false,
+            Err(err) => err.kind() == io::ErrorKind::AddrInUse,
+        }
+    })
+}
+
 impl MakeModuleRuntime for DockerModuleRuntime {
     type Config = DockerConfig;
     type Settings = Settings;
@@ -210,46 +759,118 @@ impl MakeModuleRuntime for DockerModuleRuntime {
         // So we suppress this lint. There's an open issue for this on the Clippy repo:
         //      https://github.com/rust-lang/rust-clippy/issues/3730
         #[allow(clippy::result_map_unwrap_or_else)]
-        let created = init_client(settings.moby_runtime().uri())
+        let docker_url = settings.moby_runtime().uri().clone();
+        let created = check_socket_access(&docker_url)
+            .and_then(|()| init_client(&docker_url, None))
             .map(|client| {
                 let network_id = settings.moby_runtime().network().name().to_string();
+                let image_gc = settings.moby_runtime().image_garbage_collection().clone();
+                let resource_monitor = settings.moby_runtime().resource_monitor().clone();
+                let oci_runtime = settings.moby_runtime().oci_runtime().map(ToOwned::to_owned);
+                let image_pull_retry = settings.retry().image_pull().cloned();
+                let registries = settings.moby_runtime().registries().clone();
+                let privileged_policy = settings.moby_runtime().privileged_policy().clone();
+                // The certificates can only be installed for a container engine
+                // on this host.
+                if docker_url.scheme() == UNIX_SCHEME || docker_url.scheme() == PIPE_SCHEME {
+                    if let Err(err) = registry::install_ca_certs(&registries, Path::new(CERTS_DIR))
+                    {
+                        log_failure(Level::Warn, &err);
+                    }
+                }
                 let (enable_i_pv6, ipam) = get_ipv6_settings(settings.moby_runtime().network());
                 info!("Using runtime network id {}", network_id);
 
                 let filter = format!(r#"{{"name":{{"{}":true}}}}"#, network_id);
-                let client_copy = client.clone();
-                let fut = client
-                    .network_api()
-                    .network_list(&filter)
-                    .and_then(move |existing_networks| {
-                        if existing_networks.is_empty() {
-                            let mut network_config =
-                                NetworkConfig::new(network_id).with_enable_i_pv6(enable_i_pv6);
-
-                            if let Some(ipam_config) = ipam {
-                                network_config.set_IPAM(ipam_config);
-                            };
-
-                            let fut = client_copy
-                                .network_api()
-                                .network_create(network_config)
-                                .map(move |_| client_copy);
-                            future::Either::A(fut)
-                        } else {
-                            future::Either::B(future::ok(client_copy))
-                        }
+                let fut = negotiate_api_version(&client, docker_url)
+                    .and_then(move |client| {
+                        let client_copy = client.clone();
+                        client
+                            .network_api()
+                            .network_list(&filter)
+                            .and_then(move |existing_networks| {
+                                if existing_networks.is_empty() {
+                                    let mut network_config = NetworkConfig::new(network_id)
+                                        .with_enable_i_pv6(enable_i_pv6);
+
+                                    if let Some(ipam_config) = ipam {
+                                        network_config.set_IPAM(ipam_config);
+                                    };
+
+                                    let fut = client_copy
+                                        .network_api()
+                                        .network_create(network_config)
+                                        .map(move |_| client_copy);
+                                    future::Either::A(fut)
+                                } else {
+                                    // Docker can't turn on IPv6 for an existing network.
+                                    if enable_i_pv6
+                                        && existing_networks
+                                            .iter()
+                                            .all(|network| network.enable_i_pv6() != Some(&true))
+                                    {
+                                        warn!(
+                                            "Network {} exists without IPv6. Remove it so that it is recreated with IPv6 the next time the daemon starts.",
+                                            network_id
+                                        );
+                                    }
+                                    future::Either::B(future::ok(client_copy))
+                                }
+                            })
+                            .map_err(|err| {
+                                Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::Init),
+                                )
+                            })
                     })
-                    .map_err(|err| {
-                        let e = Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::Init),
-                        );
+                    .map_err(|e| {
                         log_failure(Level::Warn, &e);
                         e
                     })
-                    .map(|client| {
+                    .map(move |client| {
                         info!("Successfully initialized module runtime");
-                        DockerModuleRuntime { client }
+                        let runtime = DockerModuleRuntime {
+                            client,
+                            resource_warnings: ResourceWarnings::default(),
+                            oci_runtime,
+                            image_pull_retry,
+                            registries,
+                            privileged_policy,
+                        };
+
+                        if runtime
+                            .registries
+                            .values()
+                            .any(RegistrySettings::allow_insecure)
+                        {
+                            tokio::spawn(runtime.check_insecure_registries());
+                        }
+
+                        if resource_monitor.enabled() {
+                            info!(
+                                "Starting resource monitor, checking every {} seconds",
+                                resource_monitor.interval().as_secs()
+                            );
+                            tokio::spawn(resource_monitor::start(
+                                runtime.clone(),
+                                resource_monitor,
+                                image_gc.clone(),
+                            ));
+                        }
+
+                        // The timer of the garbage collection can't tick every 0 seconds.
+                        if image_gc.enabled() && image_gc.interval().as_secs() == 0 {
+                            warn!("Image garbage collection is disabled, its interval_secs is 0");
+                        } else if image_gc.enabled() {
+                            info!(
+                                "Starting image garbage collection, running every {} seconds",
+                                image_gc.interval().as_secs()
+                            );
+                            tokio::spawn(image_gc::start(runtime.clone(), image_gc));
+                        }
+
+                        runtime
                     });
 
                 future::Either::A(fut)
@@ -313,6 +934,7 @@ impl ModuleRuntime for DockerModuleRuntime {
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
@@ -320,6 +942,16 @@ impl ModuleRuntime for DockerModuleRuntime {
     type SystemResourcesFuture =
         Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = ModuleExecResult, Error = Self::Error> + Send>;
+    type CopyToFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type CopyFromFuture = Box<dyn Future<Item = Vec<u8>, Error = Self::Error> + Send>;
+    type ExportImageFuture = Box<dyn Future<Item = Vec<u8>, Error = Self::Error> + Send>;
+    type ImportImagesFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ListImagesFuture = Box<dyn Future<Item = Vec<ImageInfo>, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PrunedImages, Error = Self::Error> + Send>;
+    type TopFuture = Box<dyn Future<Item = ModuleProcesses, Error = Self::Error> + Send>;
+    type ValidateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type EventStream = ModuleEvents;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         info!("Creating module {}...", module.name());
@@ -331,49 +963,83 @@ impl ModuleRuntime for DockerModuleRuntime {
             ))));
         }
 
-        let result = module
-            .config()
-            .clone_create_options()
-            .and_then(|create_options| {
-                // merge environment variables
-                let merged_env = DockerModuleRuntime::merge_env(create_options.env(), module.env());
-
-                let mut labels = create_options
-                    .labels()
-                    .cloned()
-                    .unwrap_or_else(HashMap::new);
-                labels.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+        if let Err(err) = policy::check_privileges(
+            &self.privileged_policy,
+            module.name(),
+            module.config().create_options(),
+        ) {
+            return Box::new(future::err(Error::from(err.context(
+                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                    module.name().to_string(),
+                )),
+            ))));
+        }
 
-                debug!(
-                    "Creating container {} with image {}",
-                    module.name(),
-                    module.config().image()
-                );
+        // Fail before docker does, as docker only finds out that a host port
+        // is taken or a runtime is missing once the module's old container is gone.
+        let check = self.check_engine(
+            module.name(),
+            module.config().create_options(),
+            &RuntimeOperation::CreateModule(module.name().to_string()),
+        );
 
-                let create_options = create_options
-                    .with_image(module.config().image().to_string())
-                    .with_env(merged_env)
-                    .with_labels(labels);
+        let client = self.client.clone();
+        let oci_runtime = self.oci_runtime.clone();
+        let result = check
+            .and_then(move |()| {
+                module
+                    .config()
+                    .clone_create_options()
+                    .and_then(|create_options| {
+                        // merge environment variables
+                        let merged_env =
+                            DockerModuleRuntime::merge_env(create_options.env(), module.env());
+
+                        let mut labels = create_options
+                            .labels()
+                            .cloned()
+                            .unwrap_or_else(HashMap::new);
+                        labels.insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+
+                        debug!(
+                            "Creating container {} with image {}",
+                            module.name(),
+                            module.config().image()
+                        );
 
-                // Here we don't add the container to the iot edge docker network as the edge-agent is expected to do that.
-                // It contains the logic to add a container to the iot edge network only if a network is not already specified.
+                        let create_options = create_options
+                            .with_image(module.config().image().to_string())
+                            .with_env(merged_env)
+                            .with_labels(labels);
+                        let create_options = DockerModuleRuntime::apply_restart_policy(
+                            create_options,
+                            module.restart_policy(),
+                            module.restart_max_retries(),
+                        );
+                        let create_options = DockerModuleRuntime::apply_oci_runtime(
+                            create_options,
+                            oci_runtime.as_ref().map(AsRef::as_ref),
+                        );
 
-                Ok(self
-                    .client
-                    .container_api()
-                    .container_create(create_options, module.name())
-                    .then(|result| match result {
-                        Ok(_) => Ok(module),
-                        Err(err) => Err(Error::from_docker_error(
-                            err,
-                            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                module.name().to_string(),
-                            )),
-                        )),
-                    }))
+                        // Here we don't add the container to the iot edge docker network as the edge-agent is expected to do that.
+                        // It contains the logic to add a container to the iot edge network only if a network is not already specified.
+
+                        Ok(client
+                            .container_api()
+                            .container_create(create_options, module.name())
+                            .then(|result| match result {
+                                Ok(_) => Ok(module),
+                                Err(err) => Err(Error::from_docker_error(
+                                    err,
+                                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                        module.name().to_string(),
+                                    )),
+                                )),
+                            }))
+                    })
+                    .into_future()
+                    .flatten()
             })
-            .into_future()
-            .flatten()
             .then(|result| match result {
                 Ok(module) => {
                     info!("Successfully created module {}", module.name());
@@ -421,7 +1087,8 @@ impl ModuleRuntime for DockerModuleRuntime {
                             DockerModule::new(client_copy, name, config).with_context(|_| {
                                 ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id.clone()))
                             })?;
-                        let state = runtime_state(container.id(), container.state());
+                        let state = runtime_state(container.id(), container.state())
+                            .with_restart_count(container.restart_count());
                         Ok((module, state))
                     }
                     Err(err) => {
@@ -546,13 +1213,22 @@ impl ModuleRuntime for DockerModuleRuntime {
             return Box::new(future::err(Error::from(err)));
         }
 
+        // Removing a running container kills it, so it is stopped first with
+        // its own stop signal and timeout, to let it shut down cleanly. A
+        // container that is already stopped or doesn't exist is left to the
+        // removal.
+        let client = self.client.clone();
+        let delete_id = id.clone();
         Box::new(
             self.client
                 .container_api()
-                .container_delete(
-                    &id, /* remove volumes */ false, /* force */ true,
-                    /* remove link */ false,
-                )
+                .container_stop(&id, None)
+                .then(move |_| {
+                    client.container_api().container_delete(
+                        &delete_id, /* remove volumes */ false, /* force */ true,
+                        /* remove link */ false,
+                    )
+                })
                 .then(|result| match result {
                     Ok(_) => {
                         info!("Successfully removed module {}", id);
@@ -570,14 +1246,48 @@ impl ModuleRuntime for DockerModuleRuntime {
         )
     }
 
+    fn rename(&self, id: &str, new_id: &str) -> Self::RenameFuture {
+        info!("Renaming module {} to {}...", id, new_id);
+
+        let id = id.to_string();
+        let new_id = new_id.to_string();
+
+        if let Err(err) = ensure_not_empty_with_context(&new_id, || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(id.clone()))
+        }) {
+            return Box::new(future::err(Error::from(err)));
+        }
+
+        Box::new(
+            self.client
+                .container_api()
+                .container_rename(&id, &new_id)
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully renamed module {} to {}", id, new_id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
     fn system_info(&self) -> Self::SystemInfoFuture {
         info!("Querying system info...");
 
+        let warnings = self.resource_warnings.get();
         Box::new(
             self.client
                 .system_api()
                 .system_info()
-                .then(|result| match result {
+                .then(move |result| match result {
                     Ok(system_info) => {
                         let system_info = CoreSystemInfo::new(
                             system_info
@@ -588,7 +1298,8 @@ impl ModuleRuntime for DockerModuleRuntime {
                                 .architecture()
                                 .unwrap_or(&String::from("Unknown"))
                                 .to_string(),
-                        );
+                        )
+                        .with_warnings(warnings);
                         info!("Successfully queried system info");
                         Ok(system_info)
                     }
@@ -615,7 +1326,13 @@ impl ModuleRuntime for DockerModuleRuntime {
                 remove_not_found(
                     stream::iter_ok(modules)
                         .and_then(move |module| {
-                            client.container_api().container_stats(module.name(), false)
+                            // Without streaming the engine sends a single sample.
+                            client
+                                .container_api()
+                                .container_stats(module.name(), false)
+                                .into_future()
+                                .map(|(stats, _)| stats.unwrap_or(serde_json::Value::Null))
+                                .map_err(|(err, _)| err)
                         })
                         .map_err(|err| {
                             Error::from_docker_error(
@@ -770,49 +1487,421 @@ impl ModuleRuntime for DockerModuleRuntime {
                         )
                     })
             })
-            .into_future()
-            .flatten()
-            .then(|result| {
-                match result {
-                    Ok(_) => debug!("Successfully listed modules"),
-                    Err(ref err) => log_failure(Level::Warn, err),
+            .into_future()
+            .flatten()
+            .then(|result| {
+                match result {
+                    Ok(_) => debug!("Successfully listed modules"),
+                    Err(ref err) => log_failure(Level::Warn, err),
+                }
+
+                result
+            });
+        Box::new(result)
+    }
+
+    fn list_with_details(&self) -> Self::ListWithDetailsStream {
+        list_with_details(self)
+    }
+
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        info!("Getting logs for module {}...", id);
+        let id = id.to_string();
+
+        let tail = &options.tail().to_string();
+        let result = self
+            .client
+            .container_api()
+            .container_logs(
+                &id,
+                options.follow(),
+                true,
+                true,
+                options.since(),
+                options.until(),
+                options.timestamps(),
+                tail,
+            )
+            .then(|result| match result {
+                Ok(logs) => {
+                    info!("Successfully got logs for module {}", id);
+                    Ok(Logs(id, logs))
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn registry(&self) -> &Self::ModuleRegistry {
+        self
+    }
+
+    fn remove_all(&self) -> Self::RemoveAllFuture {
+        let self_for_remove = self.clone();
+        Box::new(self.list().and_then(move |list| {
+            let n = list.into_iter().map(move |c| {
+                <DockerModuleRuntime as ModuleRuntime>::remove(&self_for_remove, c.name())
+            });
+            future::join_all(n).map(|_| ())
+        }))
+    }
+
+    fn exec(&self, id: &str, command: &[String]) -> Self::ExecFuture {
+        info!("Running command in module {}...", id);
+        let id = id.to_string();
+
+        let exec_config = ExecConfig::new()
+            .with_attach_stdout(true)
+            .with_attach_stderr(true)
+            .with_cmd(command.to_vec());
+
+        // The exec's output is multiplexed the same way as container logs
+        // since it isn't run with a TTY.
+        let client = self.client.clone();
+        let client_for_inspect = self.client.clone();
+        let result = self
+            .client
+            .exec_api()
+            .container_exec(exec_config, &id)
+            .and_then(move |exec| {
+                let exec_id = exec.id().clone();
+                client
+                    .exec_api()
+                    .exec_start(ExecStartConfig::new().with_tty(false), &exec_id)
+                    .map(|output| (exec_id, output))
+            })
+            .map_err({
+                let id = id.clone();
+                move |err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                    )
+                }
+            })
+            .and_then({
+                let id = id.clone();
+                move |(exec_id, output)| {
+                    exec_output(output)
+                        .map(|(stdout, stderr)| (exec_id, stdout, stderr))
+                        .map_err(|err| {
+                            Error::from(err.context(ErrorKind::Docker).context(
+                                ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                            ))
+                        })
+                }
+            })
+            .and_then({
+                let id = id.clone();
+                move |(exec_id, stdout, stderr)| {
+                    client_for_inspect
+                        .exec_api()
+                        .exec_inspect(&exec_id)
+                        .map(|exec| {
+                            let exit_code = exec.exit_code().unwrap_or_default();
+                            ModuleExecResult::new(i64::from(exit_code), stdout, stderr)
+                        })
+                        .map_err(|err| {
+                            Error::from_docker_error(
+                                err,
+                                ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                            )
+                        })
+                }
+            })
+            .then(move |result| match result {
+                Ok(result) => {
+                    info!(
+                        "Command in module {} exited with code {}",
+                        id,
+                        result.exit_code()
+                    );
+                    Ok(result)
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn copy_to(&self, id: &str, path: &str, archive: Vec<u8>) -> Self::CopyToFuture {
+        info!("Copying files to {} in module {}...", path, id);
+        let id = id.to_string();
+        let path = path.to_string();
+
+        let result = self
+            .client
+            .container_api()
+            .put_container_archive(&id, &path, archive, "false")
+            .then(move |result| match result {
+                Ok(()) => {
+                    info!("Successfully copied files to {} in module {}", path, id);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CopyToModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn copy_from(&self, id: &str, path: &str) -> Self::CopyFromFuture {
+        info!("Copying {} from module {}...", path, id);
+        let id = id.to_string();
+        let path = path.to_string();
+
+        let result = self
+            .client
+            .container_api()
+            .container_archive(&id, &path)
+            .map_err({
+                let id = id.clone();
+                move |err| {
+                    Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CopyFromModule(id)),
+                    )
+                }
+            })
+            .and_then({
+                let id = id.clone();
+                move |archive| {
+                    archive.concat2().map_err(|err| {
+                        Error::from(err.context(ErrorKind::Docker).context(
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CopyFromModule(id)),
+                        ))
+                    })
+                }
+            })
+            .then(move |result| match result {
+                Ok(archive) => {
+                    info!("Successfully copied {} from module {}", path, id);
+                    Ok(archive.to_vec())
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture {
+        info!("Exporting the image of module {}...", id);
+        let id = id.to_string();
+        let context = {
+            let id = id.clone();
+            move || ErrorKind::RuntimeOperation(RuntimeOperation::ExportModuleImage(id.clone()))
+        };
+        let client = self.client.clone();
+
+        let result = self
+            .client
+            .container_api()
+            .container_inspect(&id, false)
+            .map_err({
+                let context = context.clone();
+                move |err| Error::from_docker_error(err, context())
+            })
+            .and_then({
+                let context = context.clone();
+                move |container| {
+                    // The image is saved by the name the module was created with rather than
+                    // by its id, which would leave the tags out of the archive.
+                    container
+                        .config()
+                        .and_then(|config| config.image())
+                        .map(ToOwned::to_owned)
+                        .ok_or_else(|| {
+                            Error::from(
+                                ErrorKind::NotFound("Module has no image".to_string())
+                                    .context(context()),
+                            )
+                        })
+                }
+            })
+            .and_then({
+                let context = context.clone();
+                move |image| {
+                    client
+                        .image_api()
+                        .image_get(&image)
+                        .map_err(move |err| Error::from_docker_error(err, context()))
+                        .map(|archive| (image, archive))
+                }
+            })
+            .and_then(move |(image, archive)| {
+                archive
+                    .concat2()
+                    .map_err(move |err| {
+                        Error::from(err.context(ErrorKind::Docker).context(context()))
+                    })
+                    .map(|archive| (image, archive))
+            })
+            .then(move |result| match result {
+                Ok((image, archive)) => {
+                    info!("Successfully exported image {} of module {}", image, id);
+                    Ok(archive.to_vec())
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
+    }
+
+    fn import_images(&self, archive: Vec<u8>) -> Self::ImportImagesFuture {
+        info!("Importing images...");
+
+        let result =
+            self.client
+                .image_api()
+                .image_load(archive, true)
+                .then(|result| match result {
+                    Ok(()) => {
+                        info!("Successfully imported images");
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_docker_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                });
+        Box::new(result)
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        debug!("Listing images...");
+
+        let mut filters = HashMap::new();
+        filters.insert("label", LABELS.deref());
+        let filters = match serde_json::to_string(&filters)
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages))
+        {
+            Ok(filters) => filters,
+            Err(err) => return Box::new(future::err(Error::from(err))),
+        };
+
+        let in_use = self
+            .client
+            .container_api()
+            .container_list(true, 0, false, &filters)
+            .map(|containers| {
+                containers
+                    .iter()
+                    .map(|container| container.image_id().clone())
+                    .collect::<HashSet<_>>()
+            });
+        let images = self.client.image_api().image_list(false, "", false);
+
+        let result = in_use
+            .join(images)
+            .map(|(in_use, images)| {
+                images
+                    .into_iter()
+                    .map(|image| {
+                        let tags = image_gc::tags(&image).map(ToOwned::to_owned).collect();
+                        let size = u64::try_from(*image.size()).unwrap_or_default();
+                        ImageInfo::new(image.id().clone(), tags, size)
+                            .with_in_use(in_use.contains(image.id()))
+                    })
+                    .collect()
+            })
+            .then(|result| match result {
+                Ok(images) => {
+                    debug!("Successfully listed images");
+                    Ok(images)
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ListImages),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
                 }
-
-                result
             });
         Box::new(result)
     }
 
-    fn list_with_details(&self) -> Self::ListWithDetailsStream {
-        list_with_details(self)
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        info!("Pruning images...");
+
+        // Without `dangling=false` docker only removes the images that have no tag.
+        let result = self
+            .client
+            .image_api()
+            .image_prune(r#"{"dangling":["false"]}"#)
+            .then(|result| match result {
+                Ok(response) => {
+                    let images: Vec<String> = response
+                        .images_deleted()
+                        .unwrap_or_default()
+                        .iter()
+                        .filter_map(|item| item.deleted().map(ToOwned::to_owned))
+                        .collect();
+                    let space_reclaimed = response
+                        .space_reclaimed()
+                        .and_then(|space| u64::try_from(space).ok())
+                        .unwrap_or_default();
+                    info!(
+                        "Successfully pruned {} images, reclaiming {} bytes",
+                        images.len(),
+                        space_reclaimed
+                    );
+                    Ok(PrunedImages::new(images, space_reclaimed))
+                }
+                Err(err) => {
+                    let err = Error::from_docker_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+        Box::new(result)
     }
 
-    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
-        info!("Getting logs for module {}...", id);
+    fn top(&self, id: &str) -> Self::TopFuture {
+        debug!("Listing processes of module {}...", id);
         let id = id.to_string();
 
-        let tail = &options.tail().to_string();
         let result = self
             .client
             .container_api()
-            .container_logs(
-                &id,
-                options.follow(),
-                true,
-                true,
-                options.since(),
-                false,
-                tail,
-            )
-            .then(|result| match result {
-                Ok(logs) => {
-                    info!("Successfully got logs for module {}", id);
-                    Ok(Logs(id, logs))
+            .container_top(&id, "")
+            .then(move |result| match result {
+                Ok(top) => {
+                    debug!("Successfully listed processes of module {}", id);
+                    Ok(ModuleProcesses::new(
+                        top.titles().map_or_else(Vec::new, ToOwned::to_owned),
+                        top.processes().map_or_else(Vec::new, ToOwned::to_owned),
+                    ))
                 }
                 Err(err) => {
                     let err = Error::from_docker_error(
                         err,
-                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id)),
+                        ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(id)),
                     );
                     log_failure(Level::Warn, &err);
                     Err(err)
@@ -821,21 +1910,61 @@ impl ModuleRuntime for DockerModuleRuntime {
         Box::new(result)
     }
 
-    fn registry(&self) -> &Self::ModuleRegistry {
-        self
-    }
+    fn validate(&self, module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        debug!("Validating module {}...", module.name());
+        let name = module.name().to_string();
 
-    fn remove_all(&self) -> Self::RemoveAllFuture {
-        let self_for_remove = self.clone();
-        Box::new(self.list().and_then(move |list| {
-            let n = list.into_iter().map(move |c| {
-                <DockerModuleRuntime as ModuleRuntime>::remove(&self_for_remove, c.name())
+        if module.type_() != DOCKER_MODULE_TYPE {
+            return Box::new(future::err(Error::from(
+                ErrorKind::InvalidModuleType(module.type_().to_string()).context(
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ValidateModule(name)),
+                ),
+            )));
+        }
+
+        if let Err(err) = validate_module(&module) {
+            let err = Error::from(err.context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ValidateModule(name),
+            )));
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        let result = self
+            .check_engine(
+                &name,
+                module.config().create_options(),
+                &RuntimeOperation::ValidateModule(name.clone()),
+            )
+            .map(move |()| debug!("Successfully validated module {}", name))
+            .map_err(|err| {
+                log_failure(Level::Warn, &err);
+                err
             });
-            future::join_all(n).map(|_| ())
-        }))
+        Box::new(result)
+    }
+
+    fn events(&self) -> Self::EventStream {
+        ModuleEvents::new(self.client.clone())
     }
 }
 
+/// Splits the multiplexed output of an exec into its stdout and stderr.
+fn exec_output(output: Body) -> impl Future<Item = (Vec<u8>, Vec<u8>), Error = io::Error> + Send {
+    let output = Chunked::new(output.map_err(|err| io::Error::new(io::ErrorKind::Other, err)));
+    LogDecode::new(output).fold(
+        (vec![], vec![]),
+        |(mut stdout, mut stderr), chunk| -> io::Result<_> {
+            match chunk {
+                LogChunk::Stdout(bytes) => stdout.extend_from_slice(&bytes),
+                LogChunk::Stderr(bytes) => stderr.extend_from_slice(&bytes),
+                LogChunk::Stdin(_) | LogChunk::Unknown(_) => (),
+            }
+            Ok((stdout, stderr))
+        },
+    )
+}
+
 impl Authenticator for DockerModuleRuntime {
     type Error = Error;
     type Request = Request<Body>;
@@ -846,7 +1975,36 @@ impl Authenticator for DockerModuleRuntime {
     }
 }
 
-fn init_client(docker_url: &Url) -> Result<DockerClient<UrlConnector>> {
+// The daemon runs as a dedicated user that only has access to the container engine
+// through the group of its socket, so a missing group membership is reported as such
+// instead of as a generic failure of the first request.
+#[cfg(unix)]
+fn check_socket_access(docker_url: &Url) -> Result<()> {
+    if docker_url.scheme() != UNIX_SCHEME {
+        return Ok(());
+    }
+
+    let path = docker_url
+        .to_uds_file_path()
+        .context(ErrorKind::Initialization)?;
+    match std::os::unix::net::UnixStream::connect(&path) {
+        Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => Err(Error::from(
+            ErrorKind::SocketPermissionDenied(path.display().to_string()),
+        )),
+        // Other failures, like an engine that isn't running yet, are left to the client.
+        _ => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+fn check_socket_access(_docker_url: &Url) -> Result<()> {
+    Ok(())
+}
+
+fn init_client(
+    docker_url: &Url,
+    api_version: Option<ApiVersion>,
+) -> Result<DockerClient<UrlConnector>> {
     // build the hyper client
     let client =
         Client::builder().build(UrlConnector::new(docker_url).context(ErrorKind::Initialization)?);
@@ -863,13 +2021,57 @@ fn init_client(docker_url: &Url) -> Result<DockerClient<UrlConnector>> {
 
     let scheme = docker_url.scheme().to_string();
     configuration.uri_composer = Box::new(move |base_path, path| {
-        Ok(UrlConnector::build_hyper_uri(&scheme, base_path, path)
+        let path = match api_version {
+            Some(api_version) => format!("/v{}{}", api_version, path),
+            None => path.to_string(),
+        };
+        Ok(UrlConnector::build_hyper_uri(&scheme, base_path, &path)
             .context(ErrorKind::Initialization)?)
     });
 
     Ok(DockerClient::new(APIClient::new(configuration)))
 }
 
+// Queries the API versions supported by the container engine and returns a client
+// that prefixes its requests with the highest version both sides support.
+//
+// Engines that can't report their version are talked to without a version prefix,
+// which is what the daemon has always done.
+fn negotiate_api_version(
+    client: &DockerClient<UrlConnector>,
+    docker_url: Url,
+) -> impl Future<Item = DockerClient<UrlConnector>, Error = Error> + Send {
+    let unversioned_client = client.clone();
+
+    client.system_api().system_version().then(move |result| {
+        let api_version = match result {
+            Ok(engine_version) => version::negotiate(
+                engine_version.api_version(),
+                engine_version.min_api_version(),
+            )
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::Init))?,
+            Err(err) => {
+                let err = Error::from_docker_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::Init),
+                );
+                log_failure(Level::Warn, &err);
+                None
+            }
+        };
+
+        if let Some(api_version) = api_version {
+            info!("Using container engine API version {}", api_version);
+            init_client(&docker_url, Some(api_version))
+        } else {
+            warn!(
+                "Could not determine the container engine API version, using the engine's default"
+            );
+            Ok(unversioned_client)
+        }
+    })
+}
+
 #[derive(Debug)]
 pub struct Logs(String, Body);
 
@@ -1037,6 +2239,7 @@ where
 mod tests {
     use super::*;
 
+    use std::collections::BTreeMap;
     use std::path::Path;
 
     use config::{Config, File, FileFormat};
@@ -1046,8 +2249,10 @@ mod tests {
     use serde_json::{self, json, Value as JsonValue};
 
     use edgelet_core::{
-        Certificates, Connect, Listen, ModuleRegistry, ModuleTop, Provisioning, RuntimeSettings,
-        WatchdogSettings,
+        Certificates, Connect, DeviceActionSettings, DiagnosticsSettings, HealthReportSettings,
+        LifecycleHookSettings, Listen, LogFormat, ModuleEvent, ModuleRegistry, ModuleTop,
+        OrphanedModuleSettings, Provisioning, RateLimitSettings, RetrySettings, RuntimeSettings,
+        Tenant, WatchdogSettings,
     };
     use edgelet_test_utils::crypto::TestHsm;
     use provisioning::ReprovisioningStatus;
@@ -1139,6 +2344,19 @@ mod tests {
             .any(|err| err.to_string().contains("Socket file could not be found")));
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn socket_access_is_only_checked_for_unix_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("docker.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+        let url = Url::parse(&format!("unix://{}", path.display())).unwrap();
+        assert!(check_socket_access(&url).is_ok());
+        let url = Url::parse("http://localhost:2375").unwrap();
+        assert!(check_socket_access(&url).is_ok());
+    }
+
     #[test]
     fn merge_env_empty() {
         let cur_env = Some(&[][..]);
@@ -1179,6 +2397,148 @@ mod tests {
         assert_eq!(vec!["k1=v1", "k2=v2", "k3=v3"], merged_env);
     }
 
+    fn restart_policy_json(create_options: &ContainerCreateBody) -> JsonValue {
+        serde_json::to_value(create_options).unwrap()["HostConfig"]["RestartPolicy"].clone()
+    }
+
+    #[test]
+    fn apply_restart_policy_never_leaves_create_options_alone() {
+        let create_options = DockerModuleRuntime::apply_restart_policy(
+            ContainerCreateBody::new(),
+            RestartPolicy::Never,
+            Some(3),
+        );
+        assert_eq!(JsonValue::Null, restart_policy_json(&create_options));
+    }
+
+    #[test]
+    fn apply_restart_policy_on_failure_sets_max_retries() {
+        let create_options = DockerModuleRuntime::apply_restart_policy(
+            ContainerCreateBody::new(),
+            RestartPolicy::OnFailure,
+            Some(3),
+        );
+        assert_eq!(
+            json!({ "Name": "on-failure", "MaximumRetryCount": 3 }),
+            restart_policy_json(&create_options)
+        );
+
+        let create_options = DockerModuleRuntime::apply_restart_policy(
+            ContainerCreateBody::new(),
+            RestartPolicy::OnUnhealthy,
+            None,
+        );
+        assert_eq!(
+            json!({ "Name": "on-failure" }),
+            restart_policy_json(&create_options)
+        );
+    }
+
+    #[test]
+    fn apply_restart_policy_always_is_unless_stopped() {
+        let create_options = DockerModuleRuntime::apply_restart_policy(
+            ContainerCreateBody::new(),
+            RestartPolicy::Always,
+            Some(3),
+        );
+        assert_eq!(
+            json!({ "Name": "unless-stopped" }),
+            restart_policy_json(&create_options)
+        );
+    }
+
+    #[test]
+    fn apply_restart_policy_keeps_create_options_policy() {
+        let create_options: ContainerCreateBody = serde_json::from_value(json!({
+            "HostConfig": {
+                "Privileged": true,
+                "RestartPolicy": { "Name": "always" },
+            },
+        }))
+        .unwrap();
+
+        let create_options = DockerModuleRuntime::apply_restart_policy(
+            create_options,
+            RestartPolicy::OnFailure,
+            Some(3),
+        );
+        assert_eq!(
+            json!({ "Name": "always" }),
+            restart_policy_json(&create_options)
+        );
+        assert_eq!(
+            json!(true),
+            serde_json::to_value(&create_options).unwrap()["HostConfig"]["Privileged"]
+        );
+    }
+
+    #[test]
+    fn apply_oci_runtime_sets_configured_runtime() {
+        let create_options = DockerModuleRuntime::apply_oci_runtime(
+            ContainerCreateBody::new(),
+            Some("kata-runtime"),
+        );
+        assert_eq!(
+            json!("kata-runtime"),
+            serde_json::to_value(&create_options).unwrap()["HostConfig"]["Runtime"]
+        );
+
+        let create_options =
+            DockerModuleRuntime::apply_oci_runtime(ContainerCreateBody::new(), None);
+        assert!(create_options.host_config().is_none());
+    }
+
+    #[test]
+    fn apply_oci_runtime_keeps_create_options_runtime() {
+        let create_options: ContainerCreateBody = serde_json::from_value(json!({
+            "HostConfig": {
+                "Privileged": true,
+                "Runtime": "nvidia",
+            },
+        }))
+        .unwrap();
+
+        let create_options =
+            DockerModuleRuntime::apply_oci_runtime(create_options, Some("kata-runtime"));
+        let create_options = serde_json::to_value(&create_options).unwrap();
+        assert_eq!(json!("nvidia"), create_options["HostConfig"]["Runtime"]);
+        assert_eq!(json!(true), create_options["HostConfig"]["Privileged"]);
+    }
+
+    #[test]
+    fn oci_runtime_prefers_create_options() {
+        let create_options: ContainerCreateBody =
+            serde_json::from_value(json!({ "HostConfig": { "Runtime": "nvidia" } })).unwrap();
+        assert_eq!(
+            Some("nvidia"),
+            oci_runtime(&create_options, Some("kata-runtime"))
+        );
+        assert_eq!(
+            Some("kata-runtime"),
+            oci_runtime(&ContainerCreateBody::new(), Some("kata-runtime"))
+        );
+        assert_eq!(None, oci_runtime(&ContainerCreateBody::new(), None));
+    }
+
+    #[test]
+    fn only_server_errors_fail_pulls_transiently() {
+        let api_error = |code| {
+            DockerError::Api(docker::apis::ApiError {
+                code,
+                content: None,
+            })
+        };
+        assert!(is_transient_pull_error(&api_error(
+            hyper::StatusCode::INTERNAL_SERVER_ERROR
+        )));
+        assert!(!is_transient_pull_error(&api_error(
+            hyper::StatusCode::NOT_FOUND
+        )));
+        assert!(!is_transient_pull_error(&api_error(
+            hyper::StatusCode::UNAUTHORIZED
+        )));
+    }
+
     #[test]
     fn list_with_details_filters_out_deleted_containers() {
         let runtime = prepare_module_runtime_with_known_modules();
@@ -1345,6 +2705,10 @@ mod tests {
             unimplemented!()
         }
 
+        fn parent_hostname(&self) -> Option<&str> {
+            unimplemented!()
+        }
+
         fn connect(&self) -> &Connect {
             unimplemented!()
         }
@@ -1364,6 +2728,50 @@ mod tests {
         fn watchdog(&self) -> &WatchdogSettings {
             unimplemented!()
         }
+
+        fn log_level(&self) -> Option<&str> {
+            unimplemented!()
+        }
+
+        fn log_format(&self) -> LogFormat {
+            unimplemented!()
+        }
+
+        fn tenants(&self) -> &[Tenant] {
+            unimplemented!()
+        }
+
+        fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+            unimplemented!()
+        }
+
+        fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+            unimplemented!()
+        }
+
+        fn retry(&self) -> &RetrySettings {
+            unimplemented!()
+        }
+
+        fn device_actions(&self) -> &DeviceActionSettings {
+            unimplemented!()
+        }
+
+        fn health_report(&self) -> Option<&HealthReportSettings> {
+            unimplemented!()
+        }
+
+        fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+            unimplemented!()
+        }
+
+        fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+            unimplemented!()
+        }
+
+        fn secrets(&self) -> &BTreeMap<String, String> {
+            unimplemented!()
+        }
     }
 
     #[derive(Clone, Copy, Debug, PartialEq)]
@@ -1477,6 +2885,7 @@ mod tests {
             Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
         type LogsFuture = FutureResult<Self::Logs, Self::Error>;
         type RemoveFuture = FutureResult<(), Self::Error>;
+        type RenameFuture = FutureResult<(), Self::Error>;
         type RestartFuture = FutureResult<(), Self::Error>;
         type StartFuture = FutureResult<(), Self::Error>;
         type StopFuture = FutureResult<(), Self::Error>;
@@ -1484,6 +2893,16 @@ mod tests {
         type SystemResourcesFuture =
             Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
         type RemoveAllFuture = FutureResult<(), Self::Error>;
+        type ExecFuture = FutureResult<ModuleExecResult, Self::Error>;
+        type CopyToFuture = FutureResult<(), Self::Error>;
+        type CopyFromFuture = FutureResult<Vec<u8>, Self::Error>;
+        type ExportImageFuture = FutureResult<Vec<u8>, Self::Error>;
+        type ImportImagesFuture = FutureResult<(), Self::Error>;
+        type ListImagesFuture = FutureResult<Vec<ImageInfo>, Self::Error>;
+        type PruneImagesFuture = FutureResult<PrunedImages, Self::Error>;
+        type TopFuture = FutureResult<ModuleProcesses, Self::Error>;
+        type ValidateFuture = FutureResult<(), Self::Error>;
+        type EventStream = Empty<ModuleEvent, Self::Error>;
 
         fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
             unimplemented!()
@@ -1509,6 +2928,10 @@ mod tests {
             unimplemented!()
         }
 
+        fn rename(&self, _id: &str, _new_id: &str) -> Self::RenameFuture {
+            unimplemented!()
+        }
+
         fn system_info(&self) -> Self::SystemInfoFuture {
             unimplemented!()
         }
@@ -1536,6 +2959,46 @@ mod tests {
         fn remove_all(&self) -> Self::RemoveAllFuture {
             unimplemented!()
         }
+
+        fn exec(&self, _id: &str, _command: &[String]) -> Self::ExecFuture {
+            unimplemented!()
+        }
+
+        fn copy_to(&self, _id: &str, _path: &str, _archive: Vec<u8>) -> Self::CopyToFuture {
+            unimplemented!()
+        }
+
+        fn copy_from(&self, _id: &str, _path: &str) -> Self::CopyFromFuture {
+            unimplemented!()
+        }
+
+        fn export_image(&self, _id: &str) -> Self::ExportImageFuture {
+            unimplemented!()
+        }
+
+        fn import_images(&self, _archive: Vec<u8>) -> Self::ImportImagesFuture {
+            unimplemented!()
+        }
+
+        fn list_images(&self) -> Self::ListImagesFuture {
+            unimplemented!()
+        }
+
+        fn prune_images(&self) -> Self::PruneImagesFuture {
+            unimplemented!()
+        }
+
+        fn top(&self, _id: &str) -> Self::TopFuture {
+            unimplemented!()
+        }
+
+        fn validate(&self, _module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+            unimplemented!()
+        }
+
+        fn events(&self) -> Self::EventStream {
+            unimplemented!()
+        }
     }
 
     impl Authenticator for TestModuleList {
@@ -1547,4 +3010,79 @@ mod tests {
             authenticate(self, req)
         }
     }
+
+    fn container(name: &str, public_port: u16, is_module: bool) -> ContainerSummary {
+        let labels = if is_module {
+            json!({ LABEL_KEY: LABEL_VALUE })
+        } else {
+            json!({})
+        };
+        serde_json::from_value(json!({
+            "Id": name,
+            "Names": [format!("/{}", name)],
+            "Image": "image",
+            "ImageID": "image",
+            "Command": "",
+            "Created": 10,
+            "Ports": [{ "PrivatePort": 80, "PublicPort": public_port, "Type": "tcp" }],
+            "Labels": labels,
+            "State": "running",
+            "Status": "",
+            "HostConfig": { "NetworkMode": "" },
+            "NetworkSettings": { "Networks": {} },
+            "Mounts": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn port_conflicts_name_the_module_or_container() {
+        let containers = vec![
+            container("edgeHub", 443, true),
+            container("proxy", 8080, false),
+        ];
+
+        assert_eq!(
+            Some((
+                443,
+                "tcp".to_string(),
+                PortOwner::Module("edgeHub".to_string())
+            )),
+            port_conflict_with_containers("m1", &[(443, "tcp".to_string())], &containers)
+        );
+        assert_eq!(
+            Some((
+                8080,
+                "tcp".to_string(),
+                PortOwner::Container("proxy".to_string())
+            )),
+            port_conflict_with_containers("m1", &[(8080, "tcp".to_string())], &containers)
+        );
+        assert_eq!(
+            None,
+            port_conflict_with_containers("m1", &[(8080, "udp".to_string())], &containers)
+        );
+    }
+
+    #[test]
+    fn port_conflicts_leave_out_the_module_itself() {
+        let containers = vec![container("edgeHub", 443, true)];
+
+        assert_eq!(
+            None,
+            port_conflict_with_containers("edgeHub", &[(443, "tcp".to_string())], &containers)
+        );
+    }
+
+    #[test]
+    fn host_ports_in_use_are_found() {
+        let listener = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(host_port_in_use(port, "tcp"));
+        assert!(!host_port_in_use(port, "sctp"));
+
+        drop(listener);
+        assert!(!host_port_in_use(port, "tcp"));
+    }
 }
@@ -1,21 +1,25 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use config::{Config, Environment};
 use docker::models::{ContainerCreateBodyNetworkingConfig, EndpointSettings, HostConfig};
 use edgelet_core::{
-    Certificates, Connect, Listen, MobyNetwork, ModuleSpec, Provisioning, RuntimeSettings,
-    Settings as BaseSettings, UrlExt, WatchdogSettings,
+    Certificates, Connect, DeviceActionSettings, DiagnosticsSettings, HealthReportSettings,
+    LifecycleHookSettings, Listen, LogFormat, MobyNetwork, ModuleSpec, OrphanedModuleSettings,
+    Provisioning, RateLimitSettings, RetrySettings, RuntimeSettings, Settings as BaseSettings,
+    Tenant, UrlExt, WatchdogSettings, REQUIRED_SETTINGS, SETTINGS_ALIASES,
 };
-use edgelet_utils::YamlFileSource;
+use edgelet_utils::{SettingsFile, YamlFileSource};
 use failure::{Context, Fail, ResultExt};
 
 use url::Url;
 
 use crate::config::DockerConfig;
 use crate::error::{Error, ErrorKind};
+use crate::registry::check_registries;
 
 #[cfg(unix)]
 pub const DEFAULTS: &str = include_str!("../config/unix/default.yaml");
@@ -28,11 +32,58 @@ const EDGE_NETWORKID_KEY: &str = "NetworkId";
 
 const UNIX_SCHEME: &str = "unix";
 
+/// Default interval between two image garbage collection runs (1 day)
+const DEFAULT_IMAGE_GC_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+/// Default minimum age of an image before it can be garbage collected (7 days)
+const DEFAULT_IMAGE_GC_MIN_AGE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Default interval between two checks of the host's resources (5 minutes)
+const DEFAULT_RESOURCE_MONITOR_INTERVAL_SECS: u64 = 5 * 60;
+
+/// Default percentage of free disk space, inodes and memory below which a warning is raised
+const DEFAULT_RESOURCE_MONITOR_MIN_FREE_PERCENT: u8 = 10;
+
+/// Default host paths that give a module control over the host when bind mounted
+#[cfg(unix)]
+const DEFAULT_SENSITIVE_PATHS: &[&str] = &[
+    "/",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/proc",
+    "/root",
+    "/run/docker.sock",
+    "/sys",
+    "/var/lib/docker",
+    "/var/lib/iotedge",
+    "/var/run/docker.sock",
+];
+
+#[cfg(windows)]
+const DEFAULT_SENSITIVE_PATHS: &[&str] = &[
+    r"C:\",
+    r"C:\ProgramData\docker",
+    r"C:\ProgramData\iotedge",
+    r"C:\Windows",
+    r"\\.\pipe\docker_engine",
+];
+
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
 pub struct MobyRuntime {
     #[serde(with = "url_serde")]
     uri: Url,
     network: MobyNetwork,
+    #[serde(default)]
+    image_garbage_collection: ImageGarbageCollection,
+    #[serde(default)]
+    resource_monitor: ResourceMonitor,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    oci_runtime: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    registries: BTreeMap<String, RegistrySettings>,
+    #[serde(default)]
+    privileged_policy: PrivilegedPolicy,
 }
 
 impl MobyRuntime {
@@ -43,6 +94,337 @@ impl MobyRuntime {
     pub fn network(&self) -> &MobyNetwork {
         &self.network
     }
+
+    pub fn image_garbage_collection(&self) -> &ImageGarbageCollection {
+        &self.image_garbage_collection
+    }
+
+    pub fn resource_monitor(&self) -> &ResourceMonitor {
+        &self.resource_monitor
+    }
+
+    /// The OCI runtime of the container engine, e.g. `kata-runtime`, that
+    /// modules run with unless their create options set `HostConfig.Runtime`.
+    /// When not set, the container engine's default runtime is used.
+    pub fn oci_runtime(&self) -> Option<&str> {
+        self.oci_runtime.as_ref().map(AsRef::as_ref)
+    }
+
+    /// Settings of private registries, by address, e.g. `harbor.contoso.com:5000`.
+    pub fn registries(&self) -> &BTreeMap<String, RegistrySettings> {
+        &self.registries
+    }
+
+    pub fn privileged_policy(&self) -> &PrivilegedPolicy {
+        &self.privileged_policy
+    }
+}
+
+/// What the daemon does with a module whose create options give it
+/// privileges over the host.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivilegedPolicyMode {
+    /// The module is created as is.
+    Allow,
+    /// The module is created, and a warning names the privileges it asked for.
+    Audit,
+    /// The module isn't created.
+    Deny,
+}
+
+impl Default for PrivilegedPolicyMode {
+    fn default() -> Self {
+        PrivilegedPolicyMode::Allow
+    }
+}
+
+/// Settings for modules that ask for privileged mode, the host's network or
+/// PID namespace, or bind mounts of sensitive host paths.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct PrivilegedPolicy {
+    #[serde(default)]
+    mode: PrivilegedPolicyMode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exempt_modules: Vec<String>,
+    #[serde(default = "default_sensitive_paths")]
+    sensitive_paths: Vec<PathBuf>,
+}
+
+impl Default for PrivilegedPolicy {
+    fn default() -> Self {
+        PrivilegedPolicy {
+            mode: PrivilegedPolicyMode::default(),
+            exempt_modules: vec![],
+            sensitive_paths: default_sensitive_paths(),
+        }
+    }
+}
+
+impl PrivilegedPolicy {
+    pub fn mode(&self) -> PrivilegedPolicyMode {
+        self.mode
+    }
+
+    pub fn with_mode(mut self, mode: PrivilegedPolicyMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Modules the policy doesn't apply to, by name.
+    pub fn exempt_modules(&self) -> &[String] {
+        &self.exempt_modules
+    }
+
+    pub fn with_exempt_modules(mut self, exempt_modules: Vec<String>) -> Self {
+        self.exempt_modules = exempt_modules;
+        self
+    }
+
+    /// Host paths that modules must not bind mount, along with everything
+    /// under them. The root directory only matches itself.
+    pub fn sensitive_paths(&self) -> &[PathBuf] {
+        &self.sensitive_paths
+    }
+
+    pub fn with_sensitive_paths(mut self, sensitive_paths: Vec<PathBuf>) -> Self {
+        self.sensitive_paths = sensitive_paths;
+        self
+    }
+}
+
+fn default_sensitive_paths() -> Vec<PathBuf> {
+    DEFAULT_SENSITIVE_PATHS.iter().map(PathBuf::from).collect()
+}
+
+/// Settings for a registry whose certificate isn't signed by a CA that the
+/// container engine trusts, e.g. an on-premises registry with a private CA.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct RegistrySettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ca_cert: Option<PathBuf>,
+    #[serde(default)]
+    allow_insecure: bool,
+}
+
+impl RegistrySettings {
+    /// The PEM file of the CA certificate that the registry's certificate is
+    /// signed by. It is installed where the container engine looks for the
+    /// CA certificates of a registry.
+    pub fn ca_cert(&self) -> Option<&Path> {
+        self.ca_cert.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn with_ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.ca_cert = ca_cert;
+        self
+    }
+
+    /// Whether the registry may be pulled from over plain HTTP or without
+    /// verifying its certificate. The container engine also has to list the
+    /// registry in its insecure registries.
+    pub fn allow_insecure(&self) -> bool {
+        self.allow_insecure
+    }
+
+    pub fn with_allow_insecure(mut self, allow_insecure: bool) -> Self {
+        self.allow_insecure = allow_insecure;
+        self
+    }
+}
+
+/// Settings for the periodic removal of images that are not used by any container.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ImageGarbageCollection {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_image_gc_interval_secs")]
+    interval_secs: u64,
+    #[serde(default = "default_image_gc_min_age_secs")]
+    min_age_secs: u64,
+    #[serde(default)]
+    keep_last: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_free_disk_percent: Option<u8>,
+}
+
+impl Default for ImageGarbageCollection {
+    fn default() -> Self {
+        ImageGarbageCollection {
+            enabled: false,
+            interval_secs: DEFAULT_IMAGE_GC_INTERVAL_SECS,
+            min_age_secs: DEFAULT_IMAGE_GC_MIN_AGE_SECS,
+            keep_last: 0,
+            min_free_disk_percent: None,
+        }
+    }
+}
+
+impl ImageGarbageCollection {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// How often garbage collection runs.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval_secs = interval.as_secs();
+        self
+    }
+
+    /// Images created more recently than this are never removed.
+    pub fn min_age(&self) -> Duration {
+        Duration::from_secs(self.min_age_secs)
+    }
+
+    pub fn with_min_age(mut self, min_age: Duration) -> Self {
+        self.min_age_secs = min_age.as_secs();
+        self
+    }
+
+    /// Number of most recent images of each repository that are never removed,
+    /// so that a module can be rolled back to its previous version without a pull.
+    pub fn keep_last(&self) -> usize {
+        self.keep_last
+    }
+
+    pub fn with_keep_last(mut self, keep_last: usize) -> Self {
+        self.keep_last = keep_last;
+        self
+    }
+
+    /// When set, images are only removed while the free space of the disk
+    /// holding the container engine's data is below this percentage.
+    pub fn min_free_disk_percent(&self) -> Option<u8> {
+        self.min_free_disk_percent
+    }
+
+    pub fn with_min_free_disk_percent(mut self, min_free_disk_percent: Option<u8>) -> Self {
+        self.min_free_disk_percent = min_free_disk_percent;
+        self
+    }
+}
+
+fn default_image_gc_interval_secs() -> u64 {
+    DEFAULT_IMAGE_GC_INTERVAL_SECS
+}
+
+fn default_image_gc_min_age_secs() -> u64 {
+    DEFAULT_IMAGE_GC_MIN_AGE_SECS
+}
+
+/// Settings for the periodic check of the free disk space, inodes and memory of the host.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ResourceMonitor {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_resource_monitor_interval_secs")]
+    interval_secs: u64,
+    #[serde(default = "default_resource_monitor_min_free_percent")]
+    min_free_disk_percent: u8,
+    #[serde(default = "default_resource_monitor_min_free_percent")]
+    min_free_inodes_percent: u8,
+    #[serde(default = "default_resource_monitor_min_free_percent")]
+    min_free_memory_percent: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    collect_images_below_disk_percent: Option<u8>,
+}
+
+impl Default for ResourceMonitor {
+    fn default() -> Self {
+        ResourceMonitor {
+            enabled: false,
+            interval_secs: DEFAULT_RESOURCE_MONITOR_INTERVAL_SECS,
+            min_free_disk_percent: DEFAULT_RESOURCE_MONITOR_MIN_FREE_PERCENT,
+            min_free_inodes_percent: DEFAULT_RESOURCE_MONITOR_MIN_FREE_PERCENT,
+            min_free_memory_percent: DEFAULT_RESOURCE_MONITOR_MIN_FREE_PERCENT,
+            collect_images_below_disk_percent: None,
+        }
+    }
+}
+
+impl ResourceMonitor {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// How often the host's resources are checked.
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval_secs = interval.as_secs();
+        self
+    }
+
+    /// A warning is raised while the free space of the disk holding the
+    /// container engine's data is below this percentage.
+    pub fn min_free_disk_percent(&self) -> u8 {
+        self.min_free_disk_percent
+    }
+
+    pub fn with_min_free_disk_percent(mut self, min_free_disk_percent: u8) -> Self {
+        self.min_free_disk_percent = min_free_disk_percent;
+        self
+    }
+
+    /// A warning is raised while the free inodes of the disk holding the
+    /// container engine's data are below this percentage.
+    pub fn min_free_inodes_percent(&self) -> u8 {
+        self.min_free_inodes_percent
+    }
+
+    pub fn with_min_free_inodes_percent(mut self, min_free_inodes_percent: u8) -> Self {
+        self.min_free_inodes_percent = min_free_inodes_percent;
+        self
+    }
+
+    /// A warning is raised while the free memory of the host is below this percentage.
+    pub fn min_free_memory_percent(&self) -> u8 {
+        self.min_free_memory_percent
+    }
+
+    pub fn with_min_free_memory_percent(mut self, min_free_memory_percent: u8) -> Self {
+        self.min_free_memory_percent = min_free_memory_percent;
+        self
+    }
+
+    /// When set, unused images are removed as soon as the free space of the
+    /// disk holding the container engine's data drops below this percentage,
+    /// instead of waiting for the next image garbage collection run.
+    pub fn collect_images_below_disk_percent(&self) -> Option<u8> {
+        self.collect_images_below_disk_percent
+    }
+
+    pub fn with_collect_images_below_disk_percent(
+        mut self,
+        collect_images_below_disk_percent: Option<u8>,
+    ) -> Self {
+        self.collect_images_below_disk_percent = collect_images_below_disk_percent;
+        self
+    }
+}
+
+fn default_resource_monitor_interval_secs() -> u64 {
+    DEFAULT_RESOURCE_MONITOR_INTERVAL_SECS
+}
+
+fn default_resource_monitor_min_free_percent() -> u8 {
+    DEFAULT_RESOURCE_MONITOR_MIN_FREE_PERCENT
 }
 
 /// This struct is the same as the Settings type from the `edgelet_core` crate
@@ -59,12 +441,18 @@ pub struct Settings {
 
 impl Settings {
     pub fn new(filename: &Path) -> Result<Self, LoadSettingsError> {
+        let file = SettingsFile::open(filename)?;
+        file.check(REQUIRED_SETTINGS)?;
+
         let mut config = Config::default();
         config.merge(YamlFileSource::String(DEFAULTS))?;
-        config.merge(YamlFileSource::File(filename.into()))?;
+        file.merge_into(&mut config)?;
         config.merge(Environment::with_prefix("iotedge"))?;
 
-        let mut settings: Self = config.try_into()?;
+        let mut settings: Self = file.deserialize::<Self, BaseSettings<DockerConfig>>(config)?;
+        file.warn_unknown_keys(&settings, SETTINGS_ALIASES);
+
+        check_registries(settings.moby_runtime().registries())?;
 
         init_agent_spec(&mut settings)?;
 
@@ -95,6 +483,10 @@ impl RuntimeSettings for Settings {
         self.base.hostname()
     }
 
+    fn parent_hostname(&self) -> Option<&str> {
+        self.base.parent_hostname()
+    }
+
     fn connect(&self) -> &Connect {
         self.base.connect()
     }
@@ -114,6 +506,50 @@ impl RuntimeSettings for Settings {
     fn watchdog(&self) -> &WatchdogSettings {
         self.base.watchdog()
     }
+
+    fn log_level(&self) -> Option<&str> {
+        self.base.log_level()
+    }
+
+    fn log_format(&self) -> LogFormat {
+        self.base.log_format()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        self.base.tenants()
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        self.base.lifecycle_hook()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.base.workload_rate_limit()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        self.base.retry()
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        self.base.device_actions()
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        self.base.health_report()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        self.base.orphaned_modules()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        self.base.diagnostics()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        self.base.secrets()
+    }
 }
 
 fn init_agent_spec(settings: &mut Settings) -> Result<(), LoadSettingsError> {
@@ -260,13 +696,16 @@ mod tests {
     use std::cmp::Ordering;
     use std::fs::File;
     use std::io::prelude::*;
+    use std::path::PathBuf;
 
     use serde_json::json;
     use tempdir::TempDir;
 
     use edgelet_core::{
-        AttestationMethod, IpamConfig, ManualAuthMethod, ProvisioningType, DEFAULT_NETWORKID,
+        AttestationMethod, DeviceAction, DiagnosticsSinkSettings, DpsTransport, HealthReportTarget,
+        IpamConfig, ManualAuthMethod, ProvisioningType, TpmTcti, DEFAULT_NETWORKID,
     };
+    use edgelet_utils::RetryPolicy;
 
     #[cfg(unix)]
     static GOOD_SETTINGS: &str = "test/linux/sample_settings.yaml";
@@ -281,6 +720,8 @@ mod tests {
     #[cfg(unix)]
     static GOOD_SETTINGS_DPS_SYM_KEY: &str = "test/linux/sample_settings.dps.sym.yaml";
     #[cfg(unix)]
+    static GOOD_SETTINGS_DPS_GROUP_SYM_KEY: &str = "test/linux/sample_settings.dps.sym.group.yaml";
+    #[cfg(unix)]
     static GOOD_SETTINGS_CASE_SENSITIVE: &str = "test/linux/case_sensitive.yaml";
     #[cfg(unix)]
     static GOOD_SETTINGS_DPS_TPM: &str = "test/linux/sample_settings.dps.tpm.yaml";
@@ -293,6 +734,10 @@ mod tests {
     #[cfg(unix)]
     static BAD_SETTINGS_DPS_SYM_KEY: &str = "test/linux/bad_sample_settings.dps.sym.yaml";
     #[cfg(unix)]
+    static BAD_SETTINGS_DPS_SYM_KEY2: &str = "test/linux/bad_sample_settings.dps.sym.2.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_DPS_X509_HSM: &str = "test/linux/sample_settings.dps.x509.hsm.yaml";
+    #[cfg(unix)]
     static BAD_SETTINGS_DPS_X5091: &str = "test/linux/bad_settings.dps.x509.1.yaml";
     #[cfg(unix)]
     static BAD_SETTINGS_DPS_X5092: &str = "test/linux/bad_settings.dps.x509.2.yaml";
@@ -331,6 +776,21 @@ mod tests {
         "test/linux/bad_sample_settings.dyn.repro.yaml";
     #[cfg(unix)]
     static GOOD_SETTINGS_TLS: &str = "test/linux/sample_settings.tls.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_IMAGE_GC: &str = "test/linux/sample_settings.image_gc.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_OCI_RUNTIME: &str = "test/linux/sample_settings.oci_runtime.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_REGISTRIES: &str = "test/linux/sample_settings.registries.yaml";
+    #[cfg(unix)]
+    static BAD_SETTINGS_REGISTRIES: &str = "test/linux/bad_sample_settings.registries.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_PRIVILEGED_POLICY: &str =
+        "test/linux/sample_settings.privileged_policy.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_EST: &str = "test/linux/sample_settings.est.yaml";
+    #[cfg(unix)]
+    static GOOD_SETTINGS_PKCS11: &str = "test/linux/sample_settings.pkcs11.yaml";
 
     #[cfg(windows)]
     static GOOD_SETTINGS: &str = "test/windows/sample_settings.yaml";
@@ -345,6 +805,9 @@ mod tests {
     #[cfg(windows)]
     static GOOD_SETTINGS_DPS_SYM_KEY: &str = "test/windows/sample_settings.dps.sym.yaml";
     #[cfg(windows)]
+    static GOOD_SETTINGS_DPS_GROUP_SYM_KEY: &str =
+        "test/windows/sample_settings.dps.sym.group.yaml";
+    #[cfg(windows)]
     static GOOD_SETTINGS_CASE_SENSITIVE: &str = "test/windows/case_sensitive.yaml";
     #[cfg(windows)]
     static GOOD_SETTINGS_DPS_TPM: &str = "test/windows/sample_settings.dps.tpm.yaml";
@@ -357,6 +820,10 @@ mod tests {
     #[cfg(windows)]
     static BAD_SETTINGS_DPS_SYM_KEY: &str = "test/windows/bad_sample_settings.dps.sym.yaml";
     #[cfg(windows)]
+    static BAD_SETTINGS_DPS_SYM_KEY2: &str = "test/windows/bad_sample_settings.dps.sym.2.yaml";
+    #[cfg(windows)]
+    static GOOD_SETTINGS_DPS_X509_HSM: &str = "test/windows/sample_settings.dps.x509.hsm.yaml";
+    #[cfg(windows)]
     static BAD_SETTINGS_DPS_X5091: &str = "test/windows/bad_settings.dps.x509.1.yaml";
     #[cfg(windows)]
     static BAD_SETTINGS_DPS_X5092: &str = "test/windows/bad_settings.dps.x509.2.yaml";
@@ -396,6 +863,12 @@ mod tests {
         "test/windows/bad_sample_settings.dyn.repro.yaml";
     #[cfg(windows)]
     static GOOD_SETTINGS_TLS: &str = "test/windows/sample_settings.tls.yaml";
+    #[cfg(windows)]
+    static GOOD_SETTINGS_IMAGE_GC: &str = "test/windows/sample_settings.image_gc.yaml";
+    #[cfg(windows)]
+    static GOOD_SETTINGS_EST: &str = "test/windows/sample_settings.est.yaml";
+    #[cfg(windows)]
+    static GOOD_SETTINGS_PKCS11: &str = "test/windows/sample_settings.pkcs11.yaml";
 
     fn unwrap_manual_provisioning(p: &ProvisioningType) -> String {
         match p {
@@ -416,12 +889,20 @@ mod tests {
         let moby1 = MobyRuntime {
             uri: Url::parse("http://test").unwrap(),
             network: MobyNetwork::Name("".to_string()),
+            image_garbage_collection: ImageGarbageCollection::default(),
+            resource_monitor: ResourceMonitor::default(),
+            oci_runtime: None,
+            registries: BTreeMap::new(),
         };
         assert_eq!(DEFAULT_NETWORKID, moby1.network().name());
 
         let moby2 = MobyRuntime {
             uri: Url::parse("http://test").unwrap(),
             network: MobyNetwork::Name("some-network".to_string()),
+            image_garbage_collection: ImageGarbageCollection::default(),
+            resource_monitor: ResourceMonitor::default(),
+            oci_runtime: None,
+            registries: BTreeMap::new(),
         };
         assert_eq!("some-network", moby2.network().name());
     }
@@ -462,6 +943,125 @@ mod tests {
         };
     }
 
+    #[test]
+    fn image_gc_disabled_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let image_gc = settings.moby_runtime().image_garbage_collection();
+        assert!(!image_gc.enabled());
+        assert_eq!(Duration::from_secs(24 * 60 * 60), image_gc.interval());
+        assert_eq!(Duration::from_secs(7 * 24 * 60 * 60), image_gc.min_age());
+        assert_eq!(0, image_gc.keep_last());
+        assert_eq!(None, image_gc.min_free_disk_percent());
+    }
+
+    #[test]
+    fn image_gc_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_IMAGE_GC)).unwrap();
+        let image_gc = settings.moby_runtime().image_garbage_collection();
+        assert!(image_gc.enabled());
+        assert_eq!(Duration::from_secs(3600), image_gc.interval());
+        assert_eq!(Duration::from_secs(172_800), image_gc.min_age());
+        assert_eq!(2, image_gc.keep_last());
+        assert_eq!(Some(20), image_gc.min_free_disk_percent());
+    }
+
+    #[test]
+    fn oci_runtime_not_set_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert_eq!(None, settings.moby_runtime().oci_runtime());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn oci_runtime_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_OCI_RUNTIME)).unwrap();
+        assert_eq!(Some("kata-runtime"), settings.moby_runtime().oci_runtime());
+    }
+
+    #[test]
+    fn registries_not_set_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.moby_runtime().registries().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn registries_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_REGISTRIES)).unwrap();
+        let registries = settings.moby_runtime().registries();
+        assert_eq!(2, registries.len());
+
+        let harbor = &registries["harbor.contoso.com:5000"];
+        assert_eq!(
+            Some(Path::new("test/linux/registry_ca.pem")),
+            harbor.ca_cert()
+        );
+        assert!(!harbor.allow_insecure());
+
+        let lab = &registries["registry.lab"];
+        assert_eq!(None, lab.ca_cert());
+        assert!(lab.allow_insecure());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn registry_without_ca_cert_file_fails() {
+        let err = Settings::new(Path::new(BAD_SETTINGS_REGISTRIES)).unwrap_err();
+        assert_eq!(
+            "Invalid settings for registry harbor.contoso.com:5000: could not read the CA certificate test/linux/missing_ca.pem",
+            err.cause().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn privileged_policy_allows_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let policy = settings.moby_runtime().privileged_policy();
+        assert_eq!(PrivilegedPolicyMode::Allow, policy.mode());
+        assert!(policy.exempt_modules().is_empty());
+        assert_eq!(default_sensitive_paths(), policy.sensitive_paths());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn privileged_policy_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_PRIVILEGED_POLICY)).unwrap();
+        let policy = settings.moby_runtime().privileged_policy();
+        assert_eq!(PrivilegedPolicyMode::Deny, policy.mode());
+        assert_eq!(&["diagnostics".to_string()], policy.exempt_modules());
+        assert_eq!(
+            &[PathBuf::from("/etc"), PathBuf::from("/var/run/docker.sock")],
+            policy.sensitive_paths()
+        );
+    }
+
+    #[test]
+    fn resource_monitor_disabled_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let resource_monitor = settings.moby_runtime().resource_monitor();
+        assert!(!resource_monitor.enabled());
+        assert_eq!(Duration::from_secs(300), resource_monitor.interval());
+        assert_eq!(10, resource_monitor.min_free_disk_percent());
+        assert_eq!(10, resource_monitor.min_free_inodes_percent());
+        assert_eq!(10, resource_monitor.min_free_memory_percent());
+        assert_eq!(None, resource_monitor.collect_images_below_disk_percent());
+    }
+
+    #[test]
+    fn resource_monitor_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_IMAGE_GC)).unwrap();
+        let resource_monitor = settings.moby_runtime().resource_monitor();
+        assert!(resource_monitor.enabled());
+        assert_eq!(Duration::from_secs(60), resource_monitor.interval());
+        assert_eq!(15, resource_monitor.min_free_disk_percent());
+        assert_eq!(20, resource_monitor.min_free_inodes_percent());
+        assert_eq!(5, resource_monitor.min_free_memory_percent());
+        assert_eq!(
+            Some(5),
+            resource_monitor.collect_images_below_disk_percent()
+        );
+    }
+
     #[test]
     fn no_file_gets_error() {
         let settings = Settings::new(Path::new("garbage"));
@@ -485,6 +1085,9 @@ mod tests {
         let settings = Settings::new(Path::new(BAD_SETTINGS_DPS_SYM_KEY));
         assert!(settings.is_err());
 
+        let settings = Settings::new(Path::new(BAD_SETTINGS_DPS_SYM_KEY2));
+        assert!(settings.is_err());
+
         let settings = Settings::new(Path::new(BAD_SETTINGS_DPS_X5091));
         assert!(settings.is_err());
 
@@ -803,12 +1406,168 @@ mod tests {
         }
     }
 
+    #[test]
+    fn manual_x509_authentication_with_pkcs11_key() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let id_cert_path = tmp_dir.path().join("device_id_cert.pem");
+        File::create(&id_cert_path)
+            .unwrap()
+            .write_all(b"CN=Identity Cert")
+            .unwrap();
+        let settings_path = tmp_dir.path().join("test_settings.yaml");
+        let settings_yaml = json!({
+        "provisioning": {
+            "source": "manual",
+            "authentication": {
+                "method": "x509",
+                "iothub_hostname": "something.something.com",
+                "device_id": "something",
+                "identity_cert": Url::from_file_path(&id_cert_path).unwrap().into_string(),
+                "identity_pk": "pkcs11:token=edge;object=device-id;type=private",
+            }
+        }})
+        .to_string();
+        File::create(&settings_path)
+            .unwrap()
+            .write_all(settings_yaml.as_bytes())
+            .unwrap();
+
+        let settings = Settings::new(&settings_path).unwrap();
+        match settings.provisioning().provisioning_type() {
+            ProvisioningType::Manual(manual) => match manual.authentication_method() {
+                ManualAuthMethod::X509(x509) => {
+                    assert_eq!(
+                        Some("pkcs11:token=edge;object=device-id;type=private"),
+                        x509.identity_pk_pkcs11_uri().map(Url::as_str),
+                    );
+                    assert_eq!(
+                        "pkcs11:token=edge;object=device-id;type=private",
+                        x509.identity_pk_uri().unwrap().as_str(),
+                    );
+                    assert!(x509.identity_pk().is_err());
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn missing_dps_setting_reports_line() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("test_settings.yaml");
+        let mut file = File::create(&settings_path).unwrap();
+        write!(
+            file,
+            "hostname: \"localhost\"\nprovisioning:\n  source: \"dps\"\n  global_endpoint: \"https://global.azure-devices-provisioning.net\"\n"
+        )
+        .unwrap();
+
+        let err = Settings::new(&settings_path).unwrap_err();
+        let err = Fail::cause(&err).unwrap().to_string();
+        assert!(
+            err.starts_with(
+                "provisioning.scope_id must be set when provisioning.source is dps (line 3 of"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn drop_ins_are_merged_in_order() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("test_settings.yaml");
+        std::fs::copy(GOOD_SETTINGS, &settings_path).unwrap();
+        let drop_in_dir = tmp_dir.path().join("test_settings.d");
+        std::fs::create_dir(&drop_in_dir).unwrap();
+        std::fs::write(
+            drop_in_dir.join("20-hostname.yaml"),
+            "hostname: \"second\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            drop_in_dir.join("10-hostname.yaml"),
+            "hostname: \"first\"\nwatchdog:\n  max_retries: 7\n",
+        )
+        .unwrap();
+        std::fs::write(
+            drop_in_dir.join("30-hostname.yaml.bak"),
+            "hostname: \"backup\"\n",
+        )
+        .unwrap();
+
+        let settings = Settings::new(&settings_path).unwrap();
+        assert_eq!("second", settings.hostname());
+        assert_eq!(
+            Ordering::Equal,
+            settings.watchdog().max_retries().compare(7)
+        );
+        assert_eq!(
+            "http://0.0.0.0:8080/",
+            settings.listen().management_uri().as_str()
+        );
+    }
+
+    #[test]
+    fn invalid_setting_reports_line() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings_path = tmp_dir.path().join("test_settings.yaml");
+        let contents = std::fs::read_to_string(GOOD_SETTINGS).unwrap().replace(
+            "check_interval_secs: 30",
+            "check_interval_secs:\n    often: true",
+        );
+        std::fs::write(&settings_path, contents).unwrap();
+
+        let settings = SettingsFile::open(&settings_path).unwrap();
+        let line = settings.line("watchdog.check_interval_secs").unwrap();
+        let err = Settings::new(&settings_path).unwrap_err();
+        let err = Fail::cause(&err).unwrap().to_string();
+        assert!(
+            err.contains("for key `watchdog.check_interval_secs`"),
+            "{}",
+            err
+        );
+        assert!(
+            err.ends_with(&format!("(line {} of {})", line, settings_path.display())),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_found() {
+        for sample in &[GOOD_SETTINGS, GOOD_SETTINGS_MANUAL_CS_AUTH] {
+            let settings = Settings::new(Path::new(sample)).unwrap();
+            let file = SettingsFile::open(Path::new(sample)).unwrap();
+            assert_eq!(
+                Vec::<(String, String, usize)>::new(),
+                file.unknown_keys(&settings, SETTINGS_ALIASES),
+                "{}",
+                sample
+            );
+        }
+
+        // This one still has a key from old versions of the config, which is ignored.
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_DPS_DEFAULT)).unwrap();
+        let file = SettingsFile::open(Path::new(GOOD_SETTINGS_DPS_DEFAULT)).unwrap();
+        let unknown = file.unknown_keys(&settings, SETTINGS_ALIASES);
+        assert!(
+            unknown
+                .iter()
+                .any(|(key, _, _)| key == "agent.config.create_options"),
+            "{:?}",
+            unknown
+        );
+    }
+
     #[test]
     fn dps_prov_default_get_settings() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS_DPS_DEFAULT));
         assert!(settings.is_ok());
         let s = settings.unwrap();
         assert_eq!(s.provisioning().dynamic_reprovisioning(), false);
+        assert_eq!(s.parent_hostname(), Some("Parent.Example.com"));
         match s.provisioning().provisioning_type() {
             ProvisioningType::Dps(ref dps) => {
                 assert_eq!(dps.global_endpoint().scheme(), "scheme");
@@ -817,6 +1576,7 @@ mod tests {
                 match dps.attestation() {
                     AttestationMethod::Tpm(ref tpm) => {
                         assert_eq!(tpm.registration_id(), "register me fool");
+                        assert_eq!(tpm.tcti(), None);
                     }
                     _ => unreachable!(),
                 }
@@ -840,6 +1600,10 @@ mod tests {
                 match dps.attestation() {
                     AttestationMethod::Tpm(ref tpm) => {
                         assert_eq!(tpm.registration_id(), "register me fool");
+                        assert_eq!(
+                            tpm.tcti(),
+                            Some(&TpmTcti::Device(PathBuf::from("/dev/tpmrm0")))
+                        );
                     }
                     _ => unreachable!(),
                 }
@@ -855,6 +1619,7 @@ mod tests {
         assert!(settings.is_ok());
         let s = settings.unwrap();
         assert_eq!(s.provisioning().dynamic_reprovisioning(), true);
+        assert_eq!(s.parent_hostname(), None);
         match s.provisioning().provisioning_type() {
             ProvisioningType::Dps(ref dps) => {
                 assert_eq!(dps.global_endpoint().scheme(), "scheme");
@@ -862,11 +1627,34 @@ mod tests {
                 assert_eq!(dps.scope_id(), "i got no time for the jibba-jabba");
                 match dps.attestation() {
                     AttestationMethod::SymmetricKey(ref key) => {
-                        assert_eq!(key.symmetric_key(), "key");
+                        assert_eq!(key.symmetric_key(), Some("key"));
+                        assert_eq!(key.group_symmetric_key(), None);
                         assert_eq!(key.registration_id(), "register me fool");
                     }
                     _ => unreachable!(),
                 }
+                assert_eq!(dps.transport(), DpsTransport::Https);
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn dps_prov_group_symmetric_key_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_DPS_GROUP_SYM_KEY));
+        assert!(settings.is_ok());
+        let s = settings.unwrap();
+        match s.provisioning().provisioning_type() {
+            ProvisioningType::Dps(ref dps) => {
+                match dps.attestation() {
+                    AttestationMethod::SymmetricKey(ref key) => {
+                        assert_eq!(key.symmetric_key(), None);
+                        assert_eq!(key.group_symmetric_key(), Some("groupkey"));
+                        assert_eq!(key.registration_id(), "register me fool");
+                    }
+                    _ => unreachable!(),
+                }
+                assert_eq!(dps.transport(), DpsTransport::Mqtt);
             }
             _ => unreachable!(),
         };
@@ -926,22 +1714,23 @@ mod tests {
                 match dps.attestation() {
                     AttestationMethod::X509(ref x509) => {
                         assert!(x509.registration_id().is_none());
+                        assert!(!x509.is_hsm_identity());
                         assert_eq!(
                             &Url::parse(&format!("file://{}", cert_path.to_str().unwrap()))
                                 .unwrap(),
-                            x509.identity_cert_uri().unwrap(),
+                            x509.identity_cert_uri().unwrap().unwrap(),
                         );
                         assert_eq!(
                             &Url::parse(&format!("file://{}", key_path.to_str().unwrap())).unwrap(),
-                            x509.identity_pk_uri().unwrap(),
+                            x509.identity_pk_uri().unwrap().unwrap(),
                         );
                         assert_eq!(
                             cert_path.to_str().unwrap(),
-                            x509.identity_cert().unwrap().to_str().unwrap(),
+                            x509.identity_cert().unwrap().unwrap().to_str().unwrap(),
                         );
                         assert_eq!(
                             key_path.to_str().unwrap(),
-                            x509.identity_pk().unwrap().to_str().unwrap(),
+                            x509.identity_pk().unwrap().unwrap().to_str().unwrap(),
                         );
                     }
                     _ => unreachable!(),
@@ -971,19 +1760,19 @@ mod tests {
                         assert_eq!(
                             &Url::parse(&format!("file://{}", cert_path.to_str().unwrap()))
                                 .unwrap(),
-                            x509.identity_cert_uri().unwrap(),
+                            x509.identity_cert_uri().unwrap().unwrap(),
                         );
                         assert_eq!(
                             &Url::parse(&format!("file://{}", key_path.to_str().unwrap())).unwrap(),
-                            x509.identity_pk_uri().unwrap(),
+                            x509.identity_pk_uri().unwrap().unwrap(),
                         );
                         assert_eq!(
                             cert_path.to_str().unwrap(),
-                            x509.identity_cert().unwrap().to_str().unwrap(),
+                            x509.identity_cert().unwrap().unwrap().to_str().unwrap(),
                         );
                         assert_eq!(
                             key_path.to_str().unwrap(),
-                            x509.identity_pk().unwrap().to_str().unwrap(),
+                            x509.identity_pk().unwrap().unwrap().to_str().unwrap(),
                         );
                     }
                     _ => unreachable!(),
@@ -993,6 +1782,23 @@ mod tests {
         };
     }
 
+    #[test]
+    fn dps_prov_x509_hsm_identity_get_settings() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_DPS_X509_HSM)).unwrap();
+        match settings.provisioning().provisioning_type() {
+            ProvisioningType::Dps(ref dps) => match dps.attestation() {
+                AttestationMethod::X509(ref x509) => {
+                    assert_eq!(x509.registration_id(), Some("register me fool"));
+                    assert!(x509.is_hsm_identity());
+                    assert!(x509.identity_cert().unwrap().is_none());
+                    assert!(x509.identity_pk().unwrap().is_none());
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+    }
+
     #[test]
     fn external_prov_get_settings() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS_EXTERNAL1));
@@ -1060,6 +1866,120 @@ mod tests {
         let s = settings.unwrap();
         let watchdog_settings = s.watchdog();
         assert_eq!(watchdog_settings.max_retries().compare(3), Ordering::Equal);
+        assert_eq!(watchdog_settings.check_interval(), Duration::from_secs(30));
+        assert_eq!(
+            watchdog_settings.activity_timeout(),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            watchdog_settings.keepalive_interval(),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            watchdog_settings.rollback_period(),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(
+            watchdog_settings.failure_threshold(),
+            edgelet_core::DEFAULT_WATCHDOG_FAILURE_THRESHOLD
+        );
+    }
+
+    #[test]
+    fn workload_rate_limit_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let rate_limit = settings.workload_rate_limit().unwrap();
+        assert_eq!(10, rate_limit.requests_per_second());
+        assert_eq!(10, rate_limit.burst());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.workload_rate_limit().is_none());
+    }
+
+    #[test]
+    fn retry_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let retry = settings.retry();
+        assert_eq!(
+            Some(
+                &RetryPolicy::fixed(Duration::from_secs(5))
+                    .with_max_elapsed(Some(Duration::from_secs(60)))
+            ),
+            retry.image_pull()
+        );
+        assert_eq!(
+            Some(&RetryPolicy::exponential(
+                Duration::from_secs(10),
+                Duration::from_secs(300)
+            )),
+            retry.watchdog()
+        );
+        assert!(retry.provisioning().is_none());
+        assert!(retry.iothub().is_none());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.retry().image_pull().is_none());
+    }
+
+    #[test]
+    fn health_report_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let health_report = settings.health_report().unwrap();
+        assert_eq!(HealthReportTarget::AgentTwin, health_report.target());
+        assert_eq!(Duration::from_secs(600), health_report.interval());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.health_report().is_none());
+    }
+
+    #[test]
+    fn orphaned_module_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let orphaned_modules = settings.orphaned_modules().unwrap();
+        assert_eq!(Duration::from_secs(1800), orphaned_modules.interval());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.orphaned_modules().is_none());
+    }
+
+    #[test]
+    fn diagnostics_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        let diagnostics = settings.diagnostics().unwrap();
+        assert!(diagnostics.consent());
+        assert_eq!(
+            &DiagnosticsSinkSettings::Http {
+                url: Url::parse("https://diagnostics.example.com/reports").unwrap()
+            },
+            diagnostics.sink()
+        );
+        assert_eq!(Duration::from_secs(86_400), diagnostics.interval());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(settings.diagnostics().is_none());
+    }
+
+    #[test]
+    fn device_actions_are_disabled_unless_enabled() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.device_actions().allows(DeviceAction::Reboot));
+        assert!(!settings.device_actions().allows(DeviceAction::Shutdown));
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert!(!settings.device_actions().allows(DeviceAction::Reboot));
+        assert!(!settings.device_actions().allows(DeviceAction::Shutdown));
+    }
+
+    #[test]
+    fn management_auth_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        let auth = settings.listen().management_auth().unwrap();
+        assert_eq!(&["0123456789abcdef".to_string()], auth.tokens());
+        assert_eq!(&["edgeAgent".to_string()], auth.token_modules());
+        assert_eq!(Duration::from_secs(600), auth.token_lifetime());
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.listen().management_auth().is_none());
     }
 
     #[test]
@@ -1071,6 +1991,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn est_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_EST)).unwrap();
+        let est = settings.certificates().est().unwrap();
+        assert_eq!(
+            est.identity_url().unwrap().as_str(),
+            "https://est.example.com/.well-known/est/identity"
+        );
+        assert_eq!(
+            est.device_ca_url().unwrap().as_str(),
+            "https://est.example.com/.well-known/est/ca"
+        );
+        assert_eq!(est.username(), Some("estuser"));
+        assert_eq!(est.password(), Some("estpwd"));
+        assert!(est.bootstrap_identity_cert().unwrap().is_some());
+        assert!(est.bootstrap_identity_pk().unwrap().is_some());
+        assert!(est.trusted_certs().unwrap().is_none());
+    }
+
+    #[test]
+    fn est_settings_are_none_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.certificates().est().is_none());
+    }
+
+    #[test]
+    fn pkcs11_settings_are_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_PKCS11)).unwrap();
+        let certificates = settings.certificates();
+        assert_eq!(
+            certificates
+                .device_cert()
+                .unwrap()
+                .device_ca_pk_pkcs11_uri()
+                .unwrap()
+                .as_str(),
+            "pkcs11:token=edge;object=device-ca;type=private"
+        );
+        assert_eq!(
+            certificates.workload_ca_pk().unwrap().unwrap().as_str(),
+            "pkcs11:token=edge;object=workload-ca;type=private"
+        );
+        assert!(certificates.pkcs11_lib_path().is_some());
+    }
+
+    #[test]
+    fn pkcs11_settings_are_none_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_EST)).unwrap();
+        let certificates = settings.certificates();
+        assert!(certificates
+            .device_cert()
+            .unwrap()
+            .device_ca_pk_pkcs11_uri()
+            .is_none());
+        assert!(certificates.workload_ca_pk().unwrap().is_none());
+        assert!(certificates.pkcs11_lib_path().is_none());
+    }
+
     #[test]
     fn tls_settings_are_none_by_default() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
@@ -1080,6 +2058,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn metrics_uri_is_read() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS_TLS)).unwrap();
+        assert_eq!(
+            settings.listen().metrics_uri().map(ToString::to_string),
+            Some("https://0.0.0.0:9601/".to_string())
+        );
+    }
+
+    #[test]
+    fn metrics_uri_is_none_by_default() {
+        let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
+        assert!(settings.listen().metrics_uri().is_none());
+    }
+
     #[test]
     fn networking_config_is_set() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS)).unwrap();
@@ -0,0 +1,808 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Checks of a module spec that can be made without creating the module, so
+//! that a bad deployment is rejected with a precise error before any running
+//! module is torn down.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use docker::models::{ContainerCreateBody, Mount};
+use edgelet_core::ModuleSpec;
+use edgelet_utils::validate_image_reference;
+
+use crate::config::DockerConfig;
+use crate::error::ErrorKind;
+
+const VOLUME_NAME_REGEX: &str = r"^[a-zA-Z0-9][a-zA-Z0-9_.-]+$";
+
+const PORT_PROTOCOLS: &[&str] = &["tcp", "udp", "sctp"];
+
+const MOUNT_TYPES: &[&str] = &["bind", "volume", "tmpfs", "npipe"];
+
+const PROPAGATION_MODES: &[&str] = &[
+    "private", "rprivate", "shared", "rshared", "slave", "rslave",
+];
+
+/// The Linux signals by name, as docker and podman know them.
+const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", 1),
+    ("INT", 2),
+    ("QUIT", 3),
+    ("ILL", 4),
+    ("TRAP", 5),
+    ("ABRT", 6),
+    ("IOT", 6),
+    ("BUS", 7),
+    ("FPE", 8),
+    ("KILL", 9),
+    ("USR1", 10),
+    ("SEGV", 11),
+    ("USR2", 12),
+    ("PIPE", 13),
+    ("ALRM", 14),
+    ("TERM", 15),
+    ("STKFLT", 16),
+    ("CHLD", 17),
+    ("CONT", 18),
+    ("STOP", 19),
+    ("TSTP", 20),
+    ("TTIN", 21),
+    ("TTOU", 22),
+    ("URG", 23),
+    ("XCPU", 24),
+    ("XFSZ", 25),
+    ("VTALRM", 26),
+    ("PROF", 27),
+    ("WINCH", 28),
+    ("IO", 29),
+    ("POLL", 29),
+    ("PWR", 30),
+    ("SYS", 31),
+];
+
+const SIGRTMIN: i32 = 34;
+const SIGRTMAX: i32 = 64;
+
+lazy_static! {
+    static ref VOLUME_NAME: Regex =
+        Regex::new(VOLUME_NAME_REGEX).expect("This hard-coded regex is expected to be valid.");
+}
+
+/// Checks a module's image reference, port bindings and volumes. The error
+/// is the reason the module is invalid; runtimes report it as the cause of a
+/// `ValidateModule` operation error.
+pub fn validate_module(module: &ModuleSpec<DockerConfig>) -> std::result::Result<(), ErrorKind> {
+    check_image(module.config().image())?;
+    check_create_options(module.config().create_options())
+}
+
+/// Parses the `StopSignal` of a module's create options into a signal number.
+/// Like docker, this takes a number, or a name with or without the `SIG`
+/// prefix, including the real-time signals as `SIGRTMIN+n` and `SIGRTMAX-n`.
+pub fn parse_stop_signal(signal: &str) -> Option<i32> {
+    if let Ok(number) = signal.parse::<i32>() {
+        return if number > 0 && number <= SIGRTMAX {
+            Some(number)
+        } else {
+            None
+        };
+    }
+
+    let upper = signal.to_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+
+    if let Some(offset) = name.strip_prefix("RTMIN") {
+        real_time_offset(offset, '+')
+            .map(|offset| SIGRTMIN + offset)
+            .filter(|number| *number <= SIGRTMAX)
+    } else if let Some(offset) = name.strip_prefix("RTMAX") {
+        real_time_offset(offset, '-')
+            .map(|offset| SIGRTMAX - offset)
+            .filter(|number| *number >= SIGRTMIN)
+    } else {
+        SIGNALS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, number)| *number)
+    }
+}
+
+/// Parses the `+n` after `SIGRTMIN` or the `-n` after `SIGRTMAX`.
+fn real_time_offset(offset: &str, sign: char) -> Option<i32> {
+    if offset.is_empty() {
+        return Some(0);
+    }
+    offset
+        .strip_prefix(sign)
+        .and_then(|n| n.parse::<u8>().ok())
+        .map(i32::from)
+}
+
+/// The host ports, with their protocol, bound by a module's create options.
+/// Bindings that don't parse are left out; `validate_module` reports them.
+pub(crate) fn host_ports(create_options: &ContainerCreateBody) -> HashSet<(u16, String)> {
+    let mut ports = HashSet::new();
+    let bindings = match create_options
+        .host_config()
+        .and_then(|host_config| host_config.port_bindings())
+    {
+        Some(bindings) => bindings,
+        None => return ports,
+    };
+
+    for (container_port, host_bindings) in bindings {
+        let protocol = match parse_container_port(container_port) {
+            Ok((_, protocol)) => protocol,
+            Err(_) => continue,
+        };
+        for host_port in host_bindings.iter().filter_map(|b| b.host_port()) {
+            if let Ok(Some((start, end))) = parse_port_range(host_port) {
+                ports.extend((start..=end).map(|port| (port, protocol.to_string())));
+            }
+        }
+    }
+
+    ports
+}
+
+fn check_image(image: &str) -> std::result::Result<(), ErrorKind> {
+    validate_image_reference(image).map_err(|_| ErrorKind::InvalidImage(image.to_string()))
+}
+
+fn check_create_options(
+    create_options: &ContainerCreateBody,
+) -> std::result::Result<(), ErrorKind> {
+    if let Some(signal) = create_options.stop_signal() {
+        if parse_stop_signal(signal).is_none() {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Invalid stop signal {:?}",
+                signal
+            )));
+        }
+    }
+
+    if let Some(timeout) = create_options.stop_timeout() {
+        if timeout < 0 {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Invalid stop timeout {}",
+                timeout
+            )));
+        }
+    }
+
+    let host_config = match create_options.host_config() {
+        Some(host_config) => host_config,
+        None => return Ok(()),
+    };
+
+    if let Some(runtime) = host_config.runtime() {
+        if runtime.trim().is_empty() {
+            return Err(ErrorKind::InvalidModuleSpec(
+                "Runtime must not be empty".to_string(),
+            ));
+        }
+    }
+
+    if let Some(bindings) = host_config.port_bindings() {
+        let mut bound = HashSet::new();
+
+        // Sorted so that the reported conflict doesn't depend on map order.
+        let mut container_ports: Vec<&String> = bindings.keys().collect();
+        container_ports.sort_unstable();
+        for container_port in container_ports {
+            let (_, protocol) = parse_container_port(container_port)?;
+            for host_port in bindings[container_port]
+                .iter()
+                .filter_map(|b| b.host_port())
+            {
+                if let Some((start, end)) = parse_port_range(host_port).map_err(|()| {
+                    ErrorKind::InvalidModuleSpec(format!(
+                        "Invalid host port {:?} for port {}",
+                        host_port, container_port
+                    ))
+                })? {
+                    for port in start..=end {
+                        if !bound.insert((port, protocol)) {
+                            return Err(ErrorKind::InvalidModuleSpec(format!(
+                                "Host port {}/{} is bound more than once",
+                                port, protocol
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(binds) = host_config.binds() {
+        for bind in binds {
+            check_bind(bind)?;
+        }
+    }
+
+    if let Some(mounts) = host_config.mounts() {
+        for mount in mounts {
+            check_mount(mount)?;
+        }
+    }
+
+    if let Some(tmpfs) = host_config.tmpfs() {
+        // Sorted so that the reported error doesn't depend on map order.
+        let mut targets: Vec<&String> = tmpfs.keys().collect();
+        targets.sort_unstable();
+        for target in targets {
+            check_container_path(target)?;
+            check_tmpfs_options(target, &tmpfs[target])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_mount(mount: &Mount) -> std::result::Result<(), ErrorKind> {
+    let target = mount.target().unwrap_or("");
+    if target.is_empty() {
+        return Err(ErrorKind::InvalidModuleSpec(
+            "Mount is missing a target".to_string(),
+        ));
+    }
+    check_container_path(target)?;
+
+    let type_ = mount._type().unwrap_or("volume");
+    if !MOUNT_TYPES.contains(&type_) {
+        return Err(ErrorKind::InvalidModuleSpec(format!(
+            "Invalid type {:?} for mount {}",
+            type_, target
+        )));
+    }
+
+    if type_ == "bind" {
+        let source = mount.source().unwrap_or("");
+        if !Path::new(source).exists() {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Source {:?} of mount {} does not exist",
+                source, target
+            )));
+        }
+    } else if type_ == "tmpfs" && mount.source().map_or(false, |source| !source.is_empty()) {
+        return Err(ErrorKind::InvalidModuleSpec(format!(
+            "Tmpfs mount {} must not have a source",
+            target
+        )));
+    }
+
+    if let Some(bind_options) = mount.bind_options() {
+        if type_ != "bind" {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Bind options are only allowed for bind mounts, but mount {} is of type {:?}",
+                target, type_
+            )));
+        }
+        if let Some(propagation) = bind_options.propagation() {
+            if !PROPAGATION_MODES.contains(&propagation) {
+                return Err(ErrorKind::InvalidModuleSpec(format!(
+                    "Invalid propagation {:?} for mount {}",
+                    propagation, target
+                )));
+            }
+        }
+    }
+
+    if let Some(tmpfs_options) = mount.tmpfs_options() {
+        if type_ != "tmpfs" {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Tmpfs options are only allowed for tmpfs mounts, but mount {} is of type {:?}",
+                target, type_
+            )));
+        }
+        if let Some(size) = tmpfs_options.size_bytes() {
+            if size < 0 {
+                return Err(ErrorKind::InvalidModuleSpec(format!(
+                    "Invalid size {} for tmpfs mount {}",
+                    size, target
+                )));
+            }
+        }
+        if let Some(mode) = tmpfs_options.mode() {
+            if !(0..=0o7777).contains(&mode) {
+                return Err(ErrorKind::InvalidModuleSpec(format!(
+                    "Invalid mode {:o} for tmpfs mount {}",
+                    mode, target
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the options of a `HostConfig.Tmpfs` entry, a comma-separated list
+/// of mount options like `rw,noexec,size=64m,mode=1777`.
+fn check_tmpfs_options(target: &str, options: &str) -> std::result::Result<(), ErrorKind> {
+    if options.is_empty() {
+        return Ok(());
+    }
+
+    for option in options.split(',') {
+        let valid = match option.find('=') {
+            Some(index) => {
+                let value = &option[index + 1..];
+                match &option[..index] {
+                    "size" => is_tmpfs_size(value),
+                    "mode" => u32::from_str_radix(value, 8).map_or(false, |mode| mode <= 0o7777),
+                    key => !key.is_empty(),
+                }
+            }
+            None => !option.is_empty(),
+        };
+        if !valid {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Invalid option {:?} for tmpfs {}",
+                option, target
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// A tmpfs size is a number of bytes with an optional `k`, `m` or `g`
+/// suffix, or a percentage of the host's memory.
+fn is_tmpfs_size(size: &str) -> bool {
+    let number = size.trim_end_matches(&['k', 'K', 'm', 'M', 'g', 'G', '%'][..]);
+    number.len() + 1 >= size.len() && number.parse::<u64>().is_ok()
+}
+
+/// Parses a container port of the form `port[/protocol]`.
+fn parse_container_port(port: &str) -> std::result::Result<(u16, &str), ErrorKind> {
+    let mut parts = port.splitn(2, '/');
+    let number = parts.next().unwrap_or("");
+    let protocol = parts.next().unwrap_or("tcp");
+
+    match number.parse::<u16>() {
+        Ok(number) if number != 0 && PORT_PROTOCOLS.contains(&protocol) => Ok((number, protocol)),
+        _ => Err(ErrorKind::InvalidModuleSpec(format!(
+            "Invalid port {:?}",
+            port
+        ))),
+    }
+}
+
+/// Parses a host port of the form `port` or `start-end`. An empty host port
+/// lets docker pick one.
+fn parse_port_range(port: &str) -> std::result::Result<Option<(u16, u16)>, ()> {
+    if port.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parts = port.splitn(2, '-');
+    let start = parts.next().unwrap_or("").parse::<u16>().map_err(|_| ())?;
+    let end = match parts.next() {
+        Some(end) => end.parse::<u16>().map_err(|_| ())?,
+        None => start,
+    };
+
+    if start == 0 || end < start {
+        Err(())
+    } else {
+        Ok(Some((start, end)))
+    }
+}
+
+// Windows paths have a drive letter, so binds can't be split on colons the
+// same way; docker checks them when the module is created.
+#[cfg(windows)]
+fn check_bind(_bind: &str) -> std::result::Result<(), ErrorKind> {
+    Ok(())
+}
+
+/// Checks a bind of the form `source:destination[:options]`, where the
+/// source is a host path or a volume name.
+#[cfg(not(windows))]
+fn check_bind(bind: &str) -> std::result::Result<(), ErrorKind> {
+    let parts: Vec<&str> = bind.split(':').collect();
+    let (source, destination) = match parts.as_slice() {
+        [source, destination] | [source, destination, _] => (*source, *destination),
+        _ => {
+            return Err(ErrorKind::InvalidModuleSpec(format!(
+                "Invalid bind {:?}",
+                bind
+            )))
+        }
+    };
+
+    if !source.starts_with('/') && !VOLUME_NAME.is_match(source) {
+        return Err(ErrorKind::InvalidModuleSpec(format!(
+            "Invalid source {:?} for bind {:?}, expected an absolute path or a volume name",
+            source, bind
+        )));
+    }
+
+    check_container_path(destination)
+}
+
+#[cfg(windows)]
+fn check_container_path(_path: &str) -> std::result::Result<(), ErrorKind> {
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn check_container_path(path: &str) -> std::result::Result<(), ErrorKind> {
+    if path.starts_with('/') {
+        Ok(())
+    } else {
+        Err(ErrorKind::InvalidModuleSpec(format!(
+            "Container path {:?} is not absolute",
+            path
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use edgelet_core::ImagePullPolicy;
+
+    use crate::MODULE_TYPE;
+
+    fn module(image: &str, create_options: serde_json::Value) -> ModuleSpec<DockerConfig> {
+        let create_options: ContainerCreateBody = serde_json::from_value(create_options).unwrap();
+        ModuleSpec::new(
+            "mod1".to_string(),
+            MODULE_TYPE.to_string(),
+            DockerConfig::new(image.to_string(), create_options, None).unwrap(),
+            HashMap::new(),
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn error(module: &ModuleSpec<DockerConfig>) -> String {
+        validate_module(module).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn valid_image_references() {
+        for image in &[
+            "ubuntu",
+            "microsoft/azureiotedge-agent:1.0",
+            "mcr.microsoft.com/azureiotedge-hub:1.0.9-linux-amd64",
+            "localhost:5000/my_module",
+            "registry.example.com:443/team/my-module@sha256:4c6b7b8b5a4e5ae2e5b6a2d3e6b1c5d9b9a6d4e3c2b1a09f8e7d6c5b4a392817",
+        ] {
+            assert!(
+                validate_module(&module(image, serde_json::json!({}))).is_ok(),
+                "{}",
+                image
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_image_references() {
+        for image in &[
+            "Ubuntu",
+            "microsoft/agent:",
+            "microsoft//agent",
+            "microsoft/agent:1.0 ",
+            "-microsoft/agent",
+            "microsoft/agent@sha256:abc",
+        ] {
+            let module = module(image, serde_json::json!({}));
+            assert_eq!(format!("Invalid docker image {:?}", image), error(&module));
+        }
+    }
+
+    #[test]
+    fn stop_signals_are_parsed() {
+        for (signal, number) in &[
+            ("SIGTERM", 15),
+            ("SIGINT", 2),
+            ("term", 15),
+            ("SIGRTMIN", 34),
+            ("SIGRTMIN+3", 37),
+            ("SIGRTMAX-2", 62),
+            ("9", 9),
+        ] {
+            assert_eq!(Some(*number), parse_stop_signal(signal), "{}", signal);
+        }
+
+        for signal in &[
+            "",
+            "SIG",
+            "SIGFOO",
+            "0",
+            "65",
+            "-1",
+            "SIGRTMIN-1",
+            "SIGRTMAX+1",
+        ] {
+            assert_eq!(None, parse_stop_signal(signal), "{}", signal);
+        }
+    }
+
+    #[test]
+    fn stop_options_are_checked() {
+        let valid = module(
+            "ubuntu",
+            serde_json::json!({ "StopSignal": "SIGINT", "StopTimeout": 60 }),
+        );
+        assert!(validate_module(&valid).is_ok());
+
+        let bad_signal = module("ubuntu", serde_json::json!({ "StopSignal": "SIGFLUSH" }));
+        assert_eq!("Invalid stop signal \"SIGFLUSH\"", error(&bad_signal));
+
+        let bad_timeout = module("ubuntu", serde_json::json!({ "StopTimeout": -1 }));
+        assert_eq!("Invalid stop timeout -1", error(&bad_timeout));
+    }
+
+    #[test]
+    fn runtime_is_checked() {
+        let valid = module(
+            "ubuntu",
+            serde_json::json!({ "HostConfig": { "Runtime": "kata-runtime" } }),
+        );
+        assert!(validate_module(&valid).is_ok());
+
+        let empty = module(
+            "ubuntu",
+            serde_json::json!({ "HostConfig": { "Runtime": "" } }),
+        );
+        assert_eq!("Runtime must not be empty", error(&empty));
+    }
+
+    #[test]
+    fn port_bindings_are_checked() {
+        let valid = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "PortBindings": {
+                        "80/tcp": [{ "HostPort": "8080" }],
+                        "80/udp": [{ "HostPort": "8080" }],
+                        "443": [{ "HostPort": "8443-8444" }, { "HostPort": "" }],
+                    },
+                },
+            }),
+        );
+        assert!(validate_module(&valid).is_ok());
+        assert_eq!(
+            [(8080, "tcp"), (8080, "udp"), (8443, "tcp"), (8444, "tcp")]
+                .iter()
+                .map(|(port, protocol)| (*port, (*protocol).to_string()))
+                .collect::<HashSet<_>>(),
+            host_ports(valid.config().create_options())
+        );
+
+        let bad_protocol = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": { "PortBindings": { "80/http": [{ "HostPort": "8080" }] } },
+            }),
+        );
+        assert_eq!("Invalid port \"80/http\"", error(&bad_protocol));
+
+        let bad_host_port = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": { "PortBindings": { "80/tcp": [{ "HostPort": "70000" }] } },
+            }),
+        );
+        assert_eq!(
+            "Invalid host port \"70000\" for port 80/tcp",
+            error(&bad_host_port)
+        );
+
+        let conflict = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "PortBindings": {
+                        "80/tcp": [{ "HostPort": "8080" }],
+                        "81/tcp": [{ "HostPort": "8079-8081" }],
+                    },
+                },
+            }),
+        );
+        assert_eq!(
+            "Host port 8080/tcp is bound more than once",
+            error(&conflict)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn volumes_are_checked() {
+        let valid = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Binds": ["/etc/iotedge:/config:ro", "edgehub_data:/data"],
+                    "Mounts": [
+                        { "Type": "bind", "Source": "/tmp", "Target": "/tmp" },
+                        { "Type": "volume", "Source": "logs", "Target": "/logs" },
+                    ],
+                },
+            }),
+        );
+        assert!(validate_module(&valid).is_ok());
+
+        let relative_destination = module(
+            "ubuntu",
+            serde_json::json!({ "HostConfig": { "Binds": ["/etc/iotedge:config"] } }),
+        );
+        assert_eq!(
+            "Container path \"config\" is not absolute",
+            error(&relative_destination)
+        );
+
+        let relative_source = module(
+            "ubuntu",
+            serde_json::json!({ "HostConfig": { "Binds": ["./data:/data"] } }),
+        );
+        assert_eq!(
+            "Invalid source \"./data\" for bind \"./data:/data\", expected an absolute path or a volume name",
+            error(&relative_source)
+        );
+
+        let missing_source = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{ "Type": "bind", "Source": "/does/not/exist", "Target": "/data" }],
+                },
+            }),
+        );
+        assert_eq!(
+            "Source \"/does/not/exist\" of mount /data does not exist",
+            error(&missing_source)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tmpfs_mounts_are_checked() {
+        let valid = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{
+                        "Type": "tmpfs",
+                        "Target": "/scratch",
+                        "TmpfsOptions": { "SizeBytes": 67_108_864, "Mode": 0o1777 },
+                    }],
+                    "Tmpfs": { "/run": "rw,noexec,nosuid,size=65536k", "/cache": "" },
+                },
+            }),
+        );
+        assert!(validate_module(&valid).is_ok());
+
+        let with_source = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{ "Type": "tmpfs", "Source": "/tmp", "Target": "/scratch" }],
+                },
+            }),
+        );
+        assert_eq!(
+            "Tmpfs mount /scratch must not have a source",
+            error(&with_source)
+        );
+
+        let bad_mode = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{
+                        "Type": "tmpfs",
+                        "Target": "/scratch",
+                        "TmpfsOptions": { "Mode": 0o17777 },
+                    }],
+                },
+            }),
+        );
+        assert_eq!(
+            "Invalid mode 17777 for tmpfs mount /scratch",
+            error(&bad_mode)
+        );
+
+        let options_on_volume = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{
+                        "Type": "volume",
+                        "Source": "logs",
+                        "Target": "/logs",
+                        "TmpfsOptions": { "SizeBytes": 1024 },
+                    }],
+                },
+            }),
+        );
+        assert_eq!(
+            "Tmpfs options are only allowed for tmpfs mounts, but mount /logs is of type \"volume\"",
+            error(&options_on_volume)
+        );
+
+        let bad_size = module(
+            "ubuntu",
+            serde_json::json!({ "HostConfig": { "Tmpfs": { "/run": "rw,size=64mb" } } }),
+        );
+        assert_eq!(
+            "Invalid option \"size=64mb\" for tmpfs /run",
+            error(&bad_size)
+        );
+
+        let relative_target = module(
+            "ubuntu",
+            serde_json::json!({ "HostConfig": { "Tmpfs": { "run": "rw" } } }),
+        );
+        assert_eq!(
+            "Container path \"run\" is not absolute",
+            error(&relative_target)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mount_propagation_is_checked() {
+        let valid = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{
+                        "Type": "bind",
+                        "Source": "/tmp",
+                        "Target": "/host/tmp",
+                        "BindOptions": { "Propagation": "rslave" },
+                    }],
+                },
+            }),
+        );
+        assert!(validate_module(&valid).is_ok());
+
+        let bad_propagation = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{
+                        "Type": "bind",
+                        "Source": "/tmp",
+                        "Target": "/host/tmp",
+                        "BindOptions": { "Propagation": "shared-ish" },
+                    }],
+                },
+            }),
+        );
+        assert_eq!(
+            "Invalid propagation \"shared-ish\" for mount /host/tmp",
+            error(&bad_propagation)
+        );
+
+        let propagation_on_volume = module(
+            "ubuntu",
+            serde_json::json!({
+                "HostConfig": {
+                    "Mounts": [{
+                        "Type": "volume",
+                        "Source": "logs",
+                        "Target": "/logs",
+                        "BindOptions": { "Propagation": "rshared" },
+                    }],
+                },
+            }),
+        );
+        assert_eq!(
+            "Bind options are only allowed for bind mounts, but mount /logs is of type \"volume\"",
+            error(&propagation_on_volume)
+        );
+    }
+}
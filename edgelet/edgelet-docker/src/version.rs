@@ -0,0 +1,144 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::str::FromStr;
+
+use failure::ResultExt;
+use log::warn;
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// The oldest docker API version the daemon can work with (Moby 1.13).
+pub const MIN_API_VERSION: ApiVersion = ApiVersion::new(1, 25);
+
+/// The docker API version the `docker` client crate was generated from.
+pub const MAX_API_VERSION: ApiVersion = ApiVersion::new(1, 34);
+
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct ApiVersion {
+    major: u32,
+    minor: u32,
+}
+
+impl ApiVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        ApiVersion { major, minor }
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.trim().splitn(2, '.');
+        let (major, minor) = match (parts.next(), parts.next()) {
+            (Some(major), Some(minor)) => (major, minor),
+            _ => return Err(Error::from(ErrorKind::InvalidApiVersion(s.to_string()))),
+        };
+
+        Ok(ApiVersion {
+            major: major
+                .parse::<u32>()
+                .with_context(|_| ErrorKind::InvalidApiVersion(s.to_string()))?,
+            minor: minor
+                .parse::<u32>()
+                .with_context(|_| ErrorKind::InvalidApiVersion(s.to_string()))?,
+        })
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Selects the API version to use with a container engine that supports the
+/// versions from `server_min` to `server_max`, as reported by its `/version` API.
+///
+/// Returns `None` if the engine doesn't report its API version, in which case
+/// requests should not be prefixed with a version at all.
+pub fn negotiate(server_max: Option<&str>, server_min: Option<&str>) -> Result<Option<ApiVersion>> {
+    let server_max: ApiVersion = match server_max {
+        Some(server_max) => server_max.parse()?,
+        None => return Ok(None),
+    };
+
+    if server_max < MIN_API_VERSION {
+        return Err(Error::from(ErrorKind::UnsupportedApiVersion(
+            server_max.to_string(),
+            MIN_API_VERSION.to_string(),
+        )));
+    }
+
+    let version = std::cmp::min(server_max, MAX_API_VERSION);
+
+    // Engines that have dropped support for the version this client was generated from
+    // are still expected to understand it well enough for what the daemon needs.
+    match server_min.map(str::parse::<ApiVersion>).transpose()? {
+        Some(server_min) if server_min > version => {
+            warn!(
+                "Container engine requires API version {} or newer, which is newer than the latest supported version {}",
+                server_min, MAX_API_VERSION,
+            );
+            Ok(Some(server_min))
+        }
+        _ => Ok(Some(version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_api_version() {
+        assert_eq!(ApiVersion::new(1, 40), "1.40".parse().unwrap());
+        assert!("1".parse::<ApiVersion>().is_err());
+        assert!("1.x".parse::<ApiVersion>().is_err());
+        assert!("".parse::<ApiVersion>().is_err());
+    }
+
+    #[test]
+    fn api_versions_compare_numerically() {
+        assert!(ApiVersion::new(1, 9) < ApiVersion::new(1, 10));
+        assert!(ApiVersion::new(1, 40) < ApiVersion::new(2, 0));
+    }
+
+    #[test]
+    fn negotiate_picks_highest_mutually_supported_version() {
+        assert_eq!(
+            Some(MAX_API_VERSION),
+            negotiate(Some("1.40"), Some("1.12")).unwrap()
+        );
+        assert_eq!(
+            Some(ApiVersion::new(1, 30)),
+            negotiate(Some("1.30"), Some("1.12")).unwrap()
+        );
+    }
+
+    #[test]
+    fn negotiate_uses_engine_minimum_when_newer_than_client() {
+        assert_eq!(
+            Some(ApiVersion::new(1, 44)),
+            negotiate(Some("1.51"), Some("1.44")).unwrap()
+        );
+    }
+
+    #[test]
+    fn negotiate_without_engine_version_is_unversioned() {
+        assert_eq!(None, negotiate(None, None).unwrap());
+    }
+
+    #[test]
+    fn negotiate_fails_for_old_engine() {
+        let err = negotiate(Some("1.24"), None).unwrap_err();
+        match err.kind() {
+            ErrorKind::UnsupportedApiVersion(server, min) => {
+                assert_eq!("1.24", server);
+                assert_eq!("1.25", min);
+            }
+            kind => panic!("Expected `UnsupportedApiVersion` but got {:?}", kind),
+        }
+    }
+}
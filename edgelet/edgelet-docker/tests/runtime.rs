@@ -26,11 +26,12 @@ use docker::models::{
 };
 
 use edgelet_core::{
-    GetTrustBundle, ImagePullPolicy, LogOptions, LogTail, MakeModuleRuntime, Module,
-    ModuleRegistry, ModuleRuntime, ModuleSpec, RegistryOperation, RuntimeOperation,
+    CreateOptions, GetTrustBundle, ImageInfo, ImagePullPolicy, LogOptions, LogTail,
+    MakeModuleRuntime, Module, ModuleRegistry, ModuleRuntime, ModuleSpec, PortProtocol,
+    PrunedImages, RegistryOperation, RuntimeOperation,
 };
 use edgelet_docker::{DockerConfig, DockerModuleRuntime, Settings};
-use edgelet_docker::{Error, ErrorKind};
+use edgelet_docker::{Error, ErrorKind, PortOwner};
 use edgelet_test_utils::crypto::TestHsm;
 use edgelet_test_utils::web::{
     make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
@@ -800,6 +801,7 @@ fn container_create_succeeds() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_with_ports_handler,
         POST "/containers/create" => container_create_handler,
     );
 
@@ -991,6 +993,7 @@ fn container_remove_succeeds() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/m1/stop" => container_stop_handler,
         DELETE "/containers/m1" => container_remove_handler,
     );
 
@@ -1007,110 +1010,1057 @@ fn container_remove_succeeds() {
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| ModuleRuntime::remove(&runtime, "m1"));
+        .and_then(|runtime| ModuleRuntime::remove(&runtime, "m1"));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_list_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/json");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert!(query_map.contains_key("filters"));
+    assert_eq!(
+        query_map.get("filters"),
+        Some(
+            &json!({
+                "label": vec!["net.azure-devices.edge.owner=Microsoft.Azure.Devices.Edge.Agent"]
+            })
+            .to_string()
+        )
+    );
+
+    let mut labels = HashMap::new();
+    labels.insert("l1".to_string(), "v1".to_string());
+    labels.insert("l2".to_string(), "v2".to_string());
+    labels.insert("l3".to_string(), "v3".to_string());
+
+    let modules = vec![
+        ContainerSummary::new(
+            "m1".to_string(),
+            vec!["/m1".to_string()],
+            "nginx:latest".to_string(),
+            "img1".to_string(),
+            "".to_string(),
+            10,
+            vec![],
+            10,
+            10,
+            labels.clone(),
+            "".to_string(),
+            "".to_string(),
+            ContainerHostConfig::new(""),
+            ContainerNetworkSettings::new(HashMap::new()),
+            vec![],
+        ),
+        ContainerSummary::new(
+            "m2".to_string(),
+            vec!["/m2".to_string()],
+            "ubuntu:latest".to_string(),
+            "img2".to_string(),
+            "".to_string(),
+            10,
+            vec![],
+            10,
+            10,
+            labels.clone(),
+            "".to_string(),
+            "".to_string(),
+            ContainerHostConfig::new(""),
+            ContainerNetworkSettings::new(HashMap::new()),
+            vec![],
+        ),
+        ContainerSummary::new(
+            "m3".to_string(),
+            vec!["/m3".to_string()],
+            "mongo:latest".to_string(),
+            "img3".to_string(),
+            "".to_string(),
+            10,
+            vec![],
+            10,
+            10,
+            labels,
+            "".to_string(),
+            "".to_string(),
+            ContainerHostConfig::new(""),
+            ContainerNetworkSettings::new(HashMap::new()),
+            vec![],
+        ),
+    ];
+
+    let response = serde_json::to_string(&modules).unwrap();
+    let response_len = response.len();
+
+    let mut response = Response::new(response.into());
+    response
+        .headers_mut()
+        .typed_insert(&ContentLength(response_len as u64));
+    response
+        .headers_mut()
+        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+    Box::new(future::ok(response))
+}
+
+#[test]
+fn container_list_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.list());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    let modules = runtime.block_on(task).unwrap();
+
+    assert_eq!(3, modules.len());
+
+    assert_eq!("m1", modules[0].name());
+    assert_eq!("m2", modules[1].name());
+    assert_eq!("m3", modules[2].name());
+
+    assert_eq!("img1", modules[0].config().image_id().unwrap());
+    assert_eq!("img2", modules[1].config().image_id().unwrap());
+    assert_eq!("img3", modules[2].config().image_id().unwrap());
+
+    assert_eq!("nginx:latest", modules[0].config().image());
+    assert_eq!("ubuntu:latest", modules[1].config().image());
+    assert_eq!("mongo:latest", modules[2].config().image());
+
+    for module in modules {
+        for i in 0..3 {
+            assert_eq!(
+                module
+                    .config()
+                    .create_options()
+                    .labels()
+                    .unwrap()
+                    .get(&format!("l{}", i + 1)),
+                Some(&format!("v{}", i + 1))
+            );
+        }
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_logs_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/logs");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert!(query_map.contains_key("stdout"));
+    assert!(query_map.contains_key("stderr"));
+    assert!(query_map.contains_key("follow"));
+    assert!(query_map.contains_key("tail"));
+    assert_eq!("true", query_map["follow"]);
+    assert_eq!("all", query_map["tail"]);
+    assert_eq!("100000", query_map["since"]);
+    assert_eq!("200000", query_map["until"]);
+    assert_eq!("true", query_map["timestamps"]);
+
+    let body = vec![
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20, 0x61,
+        0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x76,
+        0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75, 0x65,
+    ];
+
+    Box::new(future::ok(Response::new(body.into())))
+}
+
+#[test]
+fn container_logs_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/logs" => container_logs_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| {
+            let options = LogOptions::new()
+                .with_follow(true)
+                .with_tail(LogTail::All)
+                .with_since(100_000)
+                .with_until(200_000)
+                .with_timestamps(true);
+
+            runtime.logs("mod1", &options)
+        });
+
+    let expected_body = [
+        0x01_u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20,
+        0x61, 0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+        0x76, 0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75,
+        0x65,
+    ];
+
+    let assert = task.and_then(Stream::concat2).and_then(|b| {
+        assert_eq!(&expected_body[..], b.as_ref());
+        Ok(())
+    });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(assert).unwrap();
+}
+
+fn container_exec_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/containers/mod1/exec");
+
+    let response = req
+        .into_body()
+        .concat2()
+        .and_then(|body| {
+            let exec_config: JsonValue = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json!(["cat", "/etc/hostname"]), exec_config["Cmd"]);
+            assert_eq!(json!(true), exec_config["AttachStdout"]);
+            assert_eq!(json!(true), exec_config["AttachStderr"]);
+
+            let response = json!({ "Id": "exec1" }).to_string();
+            let response_len = response.len();
+
+            let mut response = Response::new(response.into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentLength(response_len as u64));
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            *response.status_mut() = hyper::StatusCode::CREATED;
+            Ok(response)
+        })
+        .map_err(|err| panic!("{:?}", err));
+
+    Box::new(response)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn exec_start_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/exec/exec1/start");
+
+    // "mod1\n" on stdout, then "oops" on stderr
+    let body = vec![
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x6d, 0x6f, 0x64, 0x31, 0x0a, 0x02, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x6f, 0x6f, 0x70, 0x73,
+    ];
+
+    Box::new(future::ok(Response::new(body.into())))
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn exec_inspect_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/exec/exec1/json");
+
+    let response = json!({ "ID": "exec1", "Running": false, "ExitCode": 3 }).to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
+
+#[test]
+fn container_exec_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/mod1/exec" => container_exec_handler,
+        POST "/exec/exec1/start" => exec_start_handler,
+        GET "/exec/exec1/json" => exec_inspect_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.exec("mod1", &["cat".to_string(), "/etc/hostname".to_string()]))
+        .map(|result| {
+            assert_eq!(3, result.exit_code());
+            assert_eq!(b"mod1\n", result.stdout());
+            assert_eq!(b"oops", result.stderr());
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+fn container_exec_not_found_handler(_: Request<Body>) -> ResponseFuture {
+    let response = json!({ "message": "No such container: mod1" }).to_string();
+    let mut response = Response::new(response.into());
+    *response.status_mut() = hyper::StatusCode::NOT_FOUND;
+    Box::new(future::ok(response))
+}
+
+#[test]
+fn container_exec_fails_for_missing_module() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/containers/mod1/exec" => container_exec_not_found_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.exec("mod1", &["ls".to_string()]))
+        .then(|result| match result {
+            Ok(_) => panic!("exec should fail for a missing module"),
+            Err(err) => match err.kind() {
+                ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(name)) => {
+                    assert_eq!("mod1", name);
+                    match err.cause().and_then(Fail::downcast_ref::<ErrorKind>) {
+                        Some(ErrorKind::NotFound(_)) => Ok::<_, Error>(()),
+                        cause => panic!("unexpected cause {:?}", cause),
+                    }
+                }
+                kind => panic!("unexpected error kind {:?}", kind),
+            },
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+fn put_container_archive_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::PUT);
+    assert_eq!(req.uri().path(), "/containers/mod1/archive");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!("/tmp", query_map["path"]);
+    assert_eq!(
+        Some("application/x-tar"),
+        req.headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    );
+
+    let response = req
+        .into_body()
+        .concat2()
+        .map(|body| {
+            assert_eq!(b"archive", body.as_ref());
+            Response::new(Body::empty())
+        })
+        .map_err(|err| panic!("{:?}", err));
+
+    Box::new(response)
+}
+
+#[test]
+fn container_copy_to_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        PUT "/containers/mod1/archive" => put_container_archive_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.copy_to("mod1", "/tmp", b"archive".to_vec()));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_archive_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/archive");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!("/var/log/app.log", query_map["path"]);
+
+    Box::new(future::ok(Response::new("archive".into())))
+}
+
+#[test]
+fn container_copy_from_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/archive" => container_archive_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.copy_from("mod1", "/var/log/app.log"))
+        .map(|archive| assert_eq!(b"archive".to_vec(), archive));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_top_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/top");
+
+    let response = json!({
+        "Titles": ["PID", "CMD"],
+        "Processes": [["123", "dotnet Microsoft.Azure.Devices.Edge.Hub.Service.dll"]],
+    })
+    .to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
+
+#[test]
+fn container_top_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/top" => container_top_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.top("mod1"))
+        .map(|processes| {
+            assert_eq!(&["PID".to_string(), "CMD".to_string()], processes.titles());
+            assert_eq!(1, processes.processes().len());
+            assert_eq!("123", processes.processes()[0][0]);
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_inspect_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/mod1/json");
+
+    let response = json!({
+        "Id": "mod1",
+        "Image": "sha256:1234",
+        "Config": {
+            "Image": "edge-module:1.0",
+        },
+    })
+    .to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn image_get_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/images/edge-module:1.0/get");
+
+    Box::new(future::ok(Response::new("archive".into())))
+}
+
+#[test]
+fn container_export_image_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/mod1/json" => container_inspect_handler,
+        GET "/images/edge-module:1.0/get" => image_get_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.export_image("mod1"))
+        .map(|archive| assert_eq!(b"archive".to_vec(), archive));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn image_load_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/images/load");
+    assert_eq!(
+        Some("application/x-tar"),
+        req.headers()
+            .get(hyper::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+    );
+
+    let response = req
+        .into_body()
+        .concat2()
+        .map(|body| {
+            assert_eq!(b"archive", body.as_ref());
+            Response::new(Body::empty())
+        })
+        .map_err(|err| panic!("{:?}", err));
+
+    Box::new(response)
+}
+
+#[test]
+fn image_import_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/images/load" => image_load_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.import_images(b"archive".to_vec()));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn image_list_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/images/json");
+
+    let image = |id: &str, tags: &[&str], size: i64| {
+        json!({
+            "Id": id,
+            "ParentId": "",
+            "RepoTags": tags,
+            "RepoDigests": [],
+            "Created": 1_573_000_000,
+            "Size": size,
+            "SharedSize": -1,
+            "VirtualSize": size,
+            "Labels": {},
+            "Containers": -1,
+        })
+    };
+    let response = json!([
+        image("img1", &["nginx:latest"], 3_000_000_000),
+        image("img4", &["<none>:<none>"], 1024),
+    ])
+    .to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
+
+#[test]
+fn image_list_marks_images_of_modules() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_handler,
+        GET "/images/json" => image_list_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.list_images())
+        .map(|images| {
+            assert_eq!(
+                vec![
+                    ImageInfo::new(
+                        "img1".to_string(),
+                        vec!["nginx:latest".to_string()],
+                        3_000_000_000
+                    )
+                    .with_in_use(true),
+                    ImageInfo::new("img4".to_string(), vec![], 1024),
+                ],
+                images
+            );
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn image_prune_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::POST);
+    assert_eq!(req.uri().path(), "/images/prune");
+
+    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
+        .into_owned()
+        .collect();
+    assert_eq!(
+        Some(&json!({ "dangling": ["false"] }).to_string()),
+        query_map.get("filters")
+    );
+
+    let response = json!({
+        "ImagesDeleted": [
+            { "Untagged": "nginx:1.16" },
+            { "Deleted": "sha256:1234" },
+            { "Deleted": "sha256:5678" },
+        ],
+        "SpaceReclaimed": 2048,
+    })
+    .to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
+
+#[test]
+fn image_prune_reports_removed_images() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        POST "/images/prune" => image_prune_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.prune_images())
+        .map(|pruned| {
+            assert_eq!(
+                PrunedImages::new(
+                    vec!["sha256:1234".to_string(), "sha256:5678".to_string()],
+                    2048
+                ),
+                pruned
+            );
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn container_list_with_ports_handler(req: Request<Body>) -> ResponseFuture {
+    assert_eq!(req.method(), &Method::GET);
+    assert_eq!(req.uri().path(), "/containers/json");
+
+    let response = json!([
+        {
+            "Id": "edgeHub",
+            "Names": ["/edgeHub"],
+            "Image": "mcr.microsoft.com/azureiotedge-hub:1.0",
+            "ImageID": "img1",
+            "Command": "",
+            "Created": 10,
+            "Ports": [{ "PrivatePort": 443, "PublicPort": 443, "Type": "tcp" }],
+            "SizeRw": 10,
+            "SizeRootFs": 10,
+            "Labels": { "net.azure-devices.edge.owner": "Microsoft.Azure.Devices.Edge.Agent" },
+            "State": "running",
+            "Status": "",
+            "HostConfig": { "NetworkMode": "" },
+            "NetworkSettings": { "Networks": {} },
+            "Mounts": [],
+        },
+        {
+            "Id": "proxy",
+            "Names": ["/proxy"],
+            "Image": "nginx:latest",
+            "ImageID": "img2",
+            "Command": "",
+            "Created": 10,
+            "Ports": [{ "PrivatePort": 80, "PublicPort": 8081, "Type": "tcp" }],
+            "SizeRw": 10,
+            "SizeRootFs": 10,
+            "Labels": {},
+            "State": "running",
+            "Status": "",
+            "HostConfig": { "NetworkMode": "" },
+            "NetworkSettings": { "Networks": {} },
+            "Mounts": [],
+        },
+    ])
+    .to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
+
+fn module_with_port_binding(host_port: u16) -> ModuleSpec<DockerConfig> {
+    let create_options = CreateOptions::new()
+        .with_port(8443, PortProtocol::Tcp, host_port)
+        .build()
+        .unwrap();
+
+    ModuleSpec::new(
+        "mod1".to_string(),
+        "docker".to_string(),
+        DockerConfig::new("microsoft/mod1:1.0".to_string(), create_options, None).unwrap(),
+        HashMap::new(),
+        ImagePullPolicy::default(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn validate_succeeds() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_with_ports_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.validate(module_with_port_binding(8443)));
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn validate_fails_for_host_port_used_by_another_module() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_with_ports_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.validate(module_with_port_binding(443)))
+        .then(|result| match result {
+            Ok(()) => panic!("Expected validation to fail"),
+            Err(err) => {
+                match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+                    Some(ErrorKind::PortConflict {
+                        port,
+                        protocol,
+                        owner,
+                    }) => {
+                        assert_eq!(443, *port);
+                        assert_eq!("tcp", protocol);
+                        assert_eq!(&PortOwner::Module("edgeHub".to_string()), owner);
+                    }
+                    kind => panic!("Expected `PortConflict` error but got {:?}.", kind),
+                }
+                assert_eq!(
+                    "Host port 443/tcp is already used by module edgeHub",
+                    Fail::find_root_cause(&err).to_string()
+                );
+                Ok::<_, Error>(())
+            }
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn validate_fails_for_host_port_used_by_another_container() {
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_with_ports_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.validate(module_with_port_binding(8081)))
+        .then(|result| match result {
+            Ok(()) => panic!("Expected validation to fail"),
+            Err(err) => {
+                assert_eq!(
+                    "Host port 8081/tcp is already used by container proxy",
+                    Fail::find_root_cause(&err).to_string()
+                );
+                Ok::<_, Error>(())
+            }
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn create_fails_for_host_port_used_by_another_module() {
+    // there is no route to create the container, as it must not be created
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_with_ports_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(|runtime| runtime.create(module_with_port_binding(443)))
+        .then(|result| match result {
+            Ok(()) => panic!("Expected create to fail"),
+            Err(err) => {
+                match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+                    Some(ErrorKind::PortConflict { owner, .. }) => {
+                        assert_eq!(&PortOwner::Module("edgeHub".to_string()), owner);
+                    }
+                    kind => panic!("Expected `PortConflict` error but got {:?}.", kind),
+                }
+                Ok::<_, Error>(())
+            }
+        });
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+}
+
+#[test]
+fn validate_fails_for_host_port_used_on_the_host() {
+    let listener = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+    let host_port = listener.local_addr().unwrap().port();
+
+    let dispatch_table = routes!(
+        GET "/networks" => default_get_networks_handler(),
+        POST "/networks/create" => default_create_network_handler(),
+        GET "/containers/json" => container_list_with_ports_handler,
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
+        .and_then(move |runtime| runtime.validate(module_with_port_binding(host_port)))
+        .then(|result| match result {
+            Ok(()) => panic!("Expected validation to fail"),
+            Err(err) => {
+                match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+                    Some(ErrorKind::PortConflict { owner, .. }) => {
+                        assert_eq!(&PortOwner::Host, owner);
+                    }
+                    kind => panic!("Expected `PortConflict` error but got {:?}.", kind),
+                }
+                Ok::<_, Error>(())
+            }
+        });
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
     runtime.block_on(task).unwrap();
+    drop(listener);
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn container_list_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::GET);
-    assert_eq!(req.uri().path(), "/containers/json");
-
-    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect();
-    assert!(query_map.contains_key("filters"));
-    assert_eq!(
-        query_map.get("filters"),
-        Some(
-            &json!({
-                "label": vec!["net.azure-devices.edge.owner=Microsoft.Azure.Devices.Edge.Agent"]
-            })
-            .to_string()
-        )
-    );
-
-    let mut labels = HashMap::new();
-    labels.insert("l1".to_string(), "v1".to_string());
-    labels.insert("l2".to_string(), "v2".to_string());
-    labels.insert("l3".to_string(), "v3".to_string());
-
-    let modules = vec![
-        ContainerSummary::new(
-            "m1".to_string(),
-            vec!["/m1".to_string()],
-            "nginx:latest".to_string(),
-            "img1".to_string(),
-            "".to_string(),
-            10,
-            vec![],
-            10,
-            10,
-            labels.clone(),
-            "".to_string(),
-            "".to_string(),
-            ContainerHostConfig::new(""),
-            ContainerNetworkSettings::new(HashMap::new()),
-            vec![],
-        ),
-        ContainerSummary::new(
-            "m2".to_string(),
-            vec!["/m2".to_string()],
-            "ubuntu:latest".to_string(),
-            "img2".to_string(),
-            "".to_string(),
-            10,
-            vec![],
-            10,
-            10,
-            labels.clone(),
-            "".to_string(),
-            "".to_string(),
-            ContainerHostConfig::new(""),
-            ContainerNetworkSettings::new(HashMap::new()),
-            vec![],
-        ),
-        ContainerSummary::new(
-            "m3".to_string(),
-            vec!["/m3".to_string()],
-            "mongo:latest".to_string(),
-            "img3".to_string(),
-            "".to_string(),
-            10,
-            vec![],
-            10,
-            10,
-            labels,
-            "".to_string(),
-            "".to_string(),
-            ContainerHostConfig::new(""),
-            ContainerNetworkSettings::new(HashMap::new()),
-            vec![],
-        ),
-    ];
+fn system_info_with_runtimes_handler(_req: Request<Body>) -> ResponseFuture {
+    let response = json!({
+        "OSType": "linux",
+        "Architecture": "x86_64",
+        "Runtimes": {
+            "runc": { "path": "runc" },
+            "nvidia": { "path": "nvidia-container-runtime" },
+        },
+        "DefaultRuntime": "runc",
+    })
+    .to_string();
+    Box::new(future::ok(Response::new(response.into())))
+}
 
-    let response = serde_json::to_string(&modules).unwrap();
-    let response_len = response.len();
+fn module_with_runtime(runtime: Option<&str>) -> ModuleSpec<DockerConfig> {
+    let create_options = match runtime {
+        Some(runtime) => json!({ "HostConfig": { "Runtime": runtime } }),
+        None => json!({}),
+    };
 
-    let mut response = Response::new(response.into());
-    response
-        .headers_mut()
-        .typed_insert(&ContentLength(response_len as u64));
-    response
-        .headers_mut()
-        .typed_insert(&ContentType(mime::APPLICATION_JSON));
-    Box::new(future::ok(response))
+    ModuleSpec::new(
+        "mod1".to_string(),
+        "docker".to_string(),
+        DockerConfig::new(
+            "microsoft/mod1:1.0".to_string(),
+            serde_json::from_value(create_options).unwrap(),
+            None,
+        )
+        .unwrap(),
+        HashMap::new(),
+        ImagePullPolicy::default(),
+    )
+    .unwrap()
 }
 
 #[test]
-fn container_list_succeeds() {
+fn validate_succeeds_for_runtime_of_the_engine() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        GET "/containers/json" => container_list_handler,
+        GET "/info" => system_info_with_runtimes_handler,
     );
 
     let (server, port) = run_tcp_server(
@@ -1121,77 +2071,26 @@ fn container_list_succeeds() {
 
     let settings = make_settings(Some(json!({
         "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
+            "uri": &format!("http://localhost:{}", port),
+            "oci_runtime": "kata-runtime"
         }
     })));
 
+    // the module's own runtime takes precedence over the configured one
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| runtime.list());
+        .and_then(|runtime| runtime.validate(module_with_runtime(Some("nvidia"))));
 
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    let modules = runtime.block_on(task).unwrap();
-
-    assert_eq!(3, modules.len());
-
-    assert_eq!("m1", modules[0].name());
-    assert_eq!("m2", modules[1].name());
-    assert_eq!("m3", modules[2].name());
-
-    assert_eq!("img1", modules[0].config().image_id().unwrap());
-    assert_eq!("img2", modules[1].config().image_id().unwrap());
-    assert_eq!("img3", modules[2].config().image_id().unwrap());
-
-    assert_eq!("nginx:latest", modules[0].config().image());
-    assert_eq!("ubuntu:latest", modules[1].config().image());
-    assert_eq!("mongo:latest", modules[2].config().image());
-
-    for module in modules {
-        for i in 0..3 {
-            assert_eq!(
-                module
-                    .config()
-                    .create_options()
-                    .labels()
-                    .unwrap()
-                    .get(&format!("l{}", i + 1)),
-                Some(&format!("v{}", i + 1))
-            );
-        }
-    }
-}
-
-#[allow(clippy::needless_pass_by_value)]
-fn container_logs_handler(req: Request<Body>) -> ResponseFuture {
-    assert_eq!(req.method(), &Method::GET);
-    assert_eq!(req.uri().path(), "/containers/mod1/logs");
-
-    let query_map: HashMap<String, String> = parse_query(req.uri().query().unwrap().as_bytes())
-        .into_owned()
-        .collect();
-    assert!(query_map.contains_key("stdout"));
-    assert!(query_map.contains_key("stderr"));
-    assert!(query_map.contains_key("follow"));
-    assert!(query_map.contains_key("tail"));
-    assert_eq!("true", query_map["follow"]);
-    assert_eq!("all", query_map["tail"]);
-    assert_eq!("100000", query_map["since"]);
-
-    let body = vec![
-        0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20, 0x61,
-        0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x76,
-        0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75, 0x65,
-    ];
-
-    Box::new(future::ok(Response::new(body.into())))
+    runtime.block_on(task).unwrap();
 }
 
 #[test]
-fn container_logs_succeeds() {
+fn validate_fails_for_configured_runtime_missing_from_the_engine() {
     let dispatch_table = routes!(
         GET "/networks" => default_get_networks_handler(),
         POST "/networks/create" => default_create_network_handler(),
-        GET "/containers/mod1/logs" => container_logs_handler,
+        GET "/info" => system_info_with_runtimes_handler,
     );
 
     let (server, port) = run_tcp_server(
@@ -1202,35 +2101,30 @@ fn container_logs_succeeds() {
 
     let settings = make_settings(Some(json!({
         "moby_runtime": {
-            "uri": &format!("http://localhost:{}", port)
+            "uri": &format!("http://localhost:{}", port),
+            "oci_runtime": "kata-runtime"
         }
     })));
 
     let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto())
-        .and_then(|runtime| {
-            let options = LogOptions::new()
-                .with_follow(true)
-                .with_tail(LogTail::All)
-                .with_since(100_000);
-
-            runtime.logs("mod1", &options)
+        .and_then(|runtime| runtime.validate(module_with_runtime(None)))
+        .then(|result| match result {
+            Ok(()) => panic!("Expected validation to fail"),
+            Err(err) => {
+                match Fail::find_root_cause(&err).downcast_ref::<ErrorKind>() {
+                    Some(ErrorKind::UnknownOciRuntime { runtime, available }) => {
+                        assert_eq!("kata-runtime", runtime);
+                        assert_eq!("nvidia, runc", available);
+                    }
+                    kind => panic!("Expected `UnknownOciRuntime` error but got {:?}.", kind),
+                }
+                Ok::<_, Error>(())
+            }
         });
 
-    let expected_body = [
-        0x01_u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x52, 0x6f, 0x73, 0x65, 0x73, 0x20,
-        0x61, 0x72, 0x65, 0x20, 0x72, 0x65, 0x64, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
-        0x76, 0x69, 0x6f, 0x6c, 0x65, 0x74, 0x73, 0x20, 0x61, 0x72, 0x65, 0x20, 0x62, 0x6c, 0x75,
-        0x65,
-    ];
-
-    let assert = task.and_then(Stream::concat2).and_then(|b| {
-        assert_eq!(&expected_body[..], b.as_ref());
-        Ok(())
-    });
-
     let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
     runtime.spawn(server);
-    runtime.block_on(assert).unwrap();
+    runtime.block_on(task).unwrap();
 }
 
 #[test]
@@ -1811,6 +2705,65 @@ fn runtime_init_network_exist_do_not_create() {
     assert_eq!(false, *create_got_called_lock_cloned.read().unwrap());
 }
 
+#[test]
+fn runtime_init_negotiates_api_version() {
+    let list_got_called_lock = Arc::new(RwLock::new(false));
+    let list_got_called_lock_cloned = list_got_called_lock.clone();
+
+    let on_version = |_| {
+        let response = json!({
+            "ApiVersion": "1.40",
+            "MinAPIVersion": "1.12",
+        })
+        .to_string();
+        let response_len = response.len();
+
+        let mut response = Response::new(response.into());
+        response
+            .headers_mut()
+            .typed_insert(&ContentLength(response_len as u64));
+        response
+            .headers_mut()
+            .typed_insert(&ContentType(mime::APPLICATION_JSON));
+        Box::new(future::ok(response)) as ResponseFuture
+    };
+
+    // Only the versioned routes are served, so initialization fails unless
+    // the client prefixes its requests with the negotiated version.
+    let dispatch_table = routes!(
+        GET "/version" => on_version,
+        GET "/v1.34/networks" => make_get_networks_handler(move || {
+            let mut list_got_called_w = list_got_called_lock.write().unwrap();
+            *list_got_called_w = true;
+
+            json!([]).to_string()
+        }),
+        POST "/v1.34/networks/create" => default_create_network_handler(),
+    );
+
+    let (server, port) = run_tcp_server(
+        "127.0.0.1",
+        make_req_dispatcher(dispatch_table, Box::new(not_found_handler)),
+    );
+    let server = server.map_err(|err| panic!(err));
+
+    let settings = make_settings(Some(json!({
+        "moby_runtime": {
+            "uri": &format!("http://localhost:{}", port)
+        }
+    })));
+
+    //act
+    let task = DockerModuleRuntime::make_runtime(settings, provisioning_result(), crypto());
+
+    let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+    runtime.spawn(server);
+    runtime.block_on(task).unwrap();
+
+    //assert
+    assert_eq!(true, *list_got_called_lock_cloned.read().unwrap());
+}
+
 #[test]
 fn runtime_system_info_succeeds() {
     let system_info_got_called_lock = Arc::new(RwLock::new(false));
@@ -0,0 +1,427 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Quotes of the TPM for the remote attestation of the device.
+//!
+//! The HSM library has no `TPM2_Quote`, so the few commands that a quote takes
+//! are sent to the TPM here, over the TCTI the daemon is configured with.
+
+use std::convert::TryFrom;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
+
+use edgelet_core::crypto::{Attest, AttestationQuote, MAX_ATTESTATION_NONCE_LENGTH};
+use edgelet_core::{Error as CoreError, ErrorKind as CoreErrorKind, TpmTcti};
+
+use crate::error::{Error, ErrorKind};
+use crate::HsmLock;
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+
+const TPM_CC_CREATE_PRIMARY: u32 = 0x0000_0131;
+const TPM_CC_NV_READ: u32 = 0x0000_014E;
+const TPM_CC_QUOTE: u32 = 0x0000_0158;
+const TPM_CC_FLUSH_CONTEXT: u32 = 0x0000_0165;
+const TPM_CC_NV_READ_PUBLIC: u32 = 0x0000_0169;
+const TPM_CC_READ_PUBLIC: u32 = 0x0000_0173;
+
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_RH_ENDORSEMENT: u32 = 0x4000_000B;
+
+const TPM_ALG_RSA: u16 = 0x0001;
+const TPM_ALG_SHA256: u16 = 0x000B;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ALG_RSASSA: u16 = 0x0014;
+
+/// fixedTPM | fixedParent | sensitiveDataOrigin | userWithAuth | restricted | sign
+const ATTESTATION_KEY_ATTRIBUTES: u32 = 0x0005_0072;
+
+/// The endorsement key that the HSM library makes persistent when the device
+/// is provisioned with TPM attestation.
+const EK_HANDLE: u32 = 0x8101_0001;
+
+/// The NV index of the certificate of the RSA endorsement key, per the TCG EK
+/// credential profile.
+const EK_CERTIFICATE_NV_INDEX: u32 = 0x01C0_0002;
+
+/// How much of an NV index is read at once, which every TPM supports.
+const NV_READ_CHUNK: u16 = 512;
+
+const TPM_RESPONSE_HEADER_LENGTH: usize = 10;
+const TPM_MAX_RESPONSE_LENGTH: usize = 4096;
+
+/// Quotes the SHA-256 bank of PCRs 0 to 23 with an attestation key that is
+/// derived from the endorsement hierarchy. The key is the same for every
+/// quote, since it is created from a fixed template, so a verifier can trust
+/// it once it has checked it against the endorsement key.
+#[derive(Clone)]
+pub struct TpmAttestation {
+    tcti: TpmTcti,
+    hsm_lock: Arc<HsmLock>,
+}
+
+impl TpmAttestation {
+    pub fn new(tcti: TpmTcti, hsm_lock: Arc<HsmLock>) -> Self {
+        TpmAttestation { tcti, hsm_lock }
+    }
+
+    fn quote_inner(&self, nonce: &[u8]) -> Result<AttestationQuote, Error> {
+        if nonce.is_empty() || nonce.len() > MAX_ATTESTATION_NONCE_LENGTH {
+            return Err(Error::from(ErrorKind::InvalidQuoteNonce(nonce.len())));
+        }
+
+        let endorsement_key = self.read_public(EK_HANDLE)?;
+        let endorsement_key_certificate = self.read_nv(EK_CERTIFICATE_NV_INDEX).ok();
+
+        let (handle, attestation_key) = self.create_attestation_key()?;
+        let quote = self.quote_with(handle, nonce);
+        // The key is transient, so it is flushed whether the quote worked or
+        // not, lest it takes up one of the few slots of the TPM.
+        let flushed = self.flush(handle);
+        let (quoted, signature) = quote?;
+        flushed?;
+
+        Ok(
+            AttestationQuote::new(quoted, signature, attestation_key, endorsement_key)
+                .with_endorsement_key_certificate(endorsement_key_certificate),
+        )
+    }
+
+    fn create_attestation_key(&self) -> Result<(u32, Vec<u8>), Error> {
+        let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_CREATE_PRIMARY);
+        command.put_u32(TPM_RH_ENDORSEMENT);
+        command.put_password_session();
+        // TPM2B_SENSITIVE_CREATE without an auth value or data
+        command.put_u16(4);
+        command.put_u16(0);
+        command.put_u16(0);
+        command.put_tpm2b(&attestation_key_template());
+        // outsideInfo and creationPCR
+        command.put_u16(0);
+        command.put_u32(0);
+
+        let response = self.execute("CreatePrimary", command)?;
+        let mut reader = Reader::new(&response);
+        let handle = reader.u32()?;
+        let _parameter_size = reader.u32()?;
+        let public = reader.tpm2b()?.to_vec();
+        Ok((handle, public))
+    }
+
+    fn quote_with(&self, handle: u32, nonce: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_QUOTE);
+        command.put_u32(handle);
+        command.put_password_session();
+        command.put_tpm2b(nonce);
+        // The scheme of the key
+        command.put_u16(TPM_ALG_NULL);
+        // TPML_PCR_SELECTION of PCRs 0 to 23 of the SHA-256 bank
+        command.put_u32(1);
+        command.put_u16(TPM_ALG_SHA256);
+        command.put_u8(3);
+        command.put_bytes(&[0xff, 0xff, 0xff]);
+
+        let response = self.execute("Quote", command)?;
+        let mut reader = Reader::new(&response);
+        let parameter_size = reader.u32()? as usize;
+        let quoted = reader.tpm2b()?.to_vec();
+        let signature_length = parameter_size
+            .checked_sub(2 + quoted.len())
+            .ok_or(ErrorKind::MalformedTpmResponse("Quote"))?;
+        let signature = reader.bytes(signature_length)?.to_vec();
+        Ok((quoted, signature))
+    }
+
+    fn flush(&self, handle: u32) -> Result<(), Error> {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_FLUSH_CONTEXT);
+        command.put_u32(handle);
+        self.execute("FlushContext", command).map(|_| ())
+    }
+
+    fn read_public(&self, handle: u32) -> Result<Vec<u8>, Error> {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_READ_PUBLIC);
+        command.put_u32(handle);
+        let response = self.execute("ReadPublic", command)?;
+        Ok(Reader::new(&response).tpm2b()?.to_vec())
+    }
+
+    fn read_nv(&self, index: u32) -> Result<Vec<u8>, Error> {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_NV_READ_PUBLIC);
+        command.put_u32(index);
+        let response = self.execute("NV_ReadPublic", command)?;
+        // TPMS_NV_PUBLIC: nvIndex, nameAlg, attributes, authPolicy, dataSize
+        let mut reader = Reader::new(&response);
+        let _nv_public_size = reader.u16()?;
+        let _nv_index = reader.u32()?;
+        let _name_alg = reader.u16()?;
+        let _attributes = reader.u32()?;
+        let _auth_policy = reader.tpm2b()?;
+        let size = reader.u16()?;
+
+        let mut data = Vec::with_capacity(usize::from(size));
+        let mut offset: u16 = 0;
+        while offset < size {
+            let chunk = std::cmp::min(NV_READ_CHUNK, size - offset);
+            let mut command = Command::new(TPM_ST_SESSIONS, TPM_CC_NV_READ);
+            command.put_u32(index);
+            command.put_u32(index);
+            command.put_password_session();
+            command.put_u16(chunk);
+            command.put_u16(offset);
+
+            let response = self.execute("NV_Read", command)?;
+            let mut reader = Reader::new(&response);
+            let _parameter_size = reader.u32()?;
+            let read = reader.tpm2b()?;
+            if read.is_empty() {
+                return Err(Error::from(ErrorKind::MalformedTpmResponse("NV_Read")));
+            }
+            data.extend_from_slice(read);
+            offset += u16::try_from(read.len())
+                .map_err(|_| ErrorKind::MalformedTpmResponse("NV_Read"))?;
+        }
+        Ok(data)
+    }
+
+    /// Sends the command to the TPM, and returns the rest of its response
+    /// after the header if the command succeeded.
+    fn execute(&self, name: &'static str, command: Command) -> Result<Vec<u8>, Error> {
+        let response = self.transmit(&command.finish())?;
+        let mut reader = Reader::new(&response);
+        let _tag = reader.u16()?;
+        let size = reader.u32()? as usize;
+        let code = reader.u32()?;
+        if code != 0 {
+            return Err(Error::from(ErrorKind::TpmCommand(name, code)));
+        }
+        if size != response.len() {
+            return Err(Error::from(ErrorKind::MalformedTpmResponse(name)));
+        }
+        Ok(response[TPM_RESPONSE_HEADER_LENGTH..].to_vec())
+    }
+
+    fn transmit(&self, command: &[u8]) -> Result<Vec<u8>, Error> {
+        match &self.tcti {
+            TpmTcti::Device(path) => {
+                let mut device = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path)
+                    .context(ErrorKind::TpmTransport)?;
+                device.write_all(command).context(ErrorKind::TpmTransport)?;
+                // The device hands out the whole response with one read.
+                let mut response = vec![0; TPM_MAX_RESPONSE_LENGTH];
+                let read = device
+                    .read(&mut response)
+                    .context(ErrorKind::TpmTransport)?;
+                response.truncate(read);
+                Ok(response)
+            }
+            TpmTcti::Swtpm { host, port } => {
+                let mut stream =
+                    TcpStream::connect((host.as_str(), *port)).context(ErrorKind::TpmTransport)?;
+                stream.write_all(command).context(ErrorKind::TpmTransport)?;
+                let mut response = vec![0; TPM_RESPONSE_HEADER_LENGTH];
+                stream
+                    .read_exact(&mut response)
+                    .context(ErrorKind::TpmTransport)?;
+                let size = Reader::new(&response[2..]).u32()? as usize;
+                if !(TPM_RESPONSE_HEADER_LENGTH..=TPM_MAX_RESPONSE_LENGTH).contains(&size) {
+                    return Err(Error::from(ErrorKind::MalformedTpmResponse("response")));
+                }
+                response.resize(size, 0);
+                stream
+                    .read_exact(&mut response[TPM_RESPONSE_HEADER_LENGTH..])
+                    .context(ErrorKind::TpmTransport)?;
+                Ok(response)
+            }
+            TpmTcti::Tabrmd(_) => Err(Error::from(ErrorKind::UnsupportedTcti)),
+        }
+    }
+}
+
+impl Attest for TpmAttestation {
+    fn quote(&self, nonce: &[u8]) -> Result<AttestationQuote, CoreError> {
+        self.hsm_lock
+            .measure("quote", || self.quote_inner(nonce))
+            .map_err(|err| CoreError::from(err.context(CoreErrorKind::Attestation)))
+    }
+}
+
+/// The `TPMT_PUBLIC` template of a restricted RSA 2048 signing key that signs
+/// with RSASSA and SHA-256, and is usable without an auth value.
+fn attestation_key_template() -> Vec<u8> {
+    let mut template = Command::default();
+    template.put_u16(TPM_ALG_RSA);
+    template.put_u16(TPM_ALG_SHA256);
+    template.put_u32(ATTESTATION_KEY_ATTRIBUTES);
+    // authPolicy
+    template.put_u16(0);
+    // TPMS_RSA_PARMS: no symmetric algorithm, RSASSA with SHA-256, 2048 bits,
+    // the default exponent
+    template.put_u16(TPM_ALG_NULL);
+    template.put_u16(TPM_ALG_RSASSA);
+    template.put_u16(TPM_ALG_SHA256);
+    template.put_u16(2048);
+    template.put_u32(0);
+    // unique
+    template.put_u16(0);
+    template.bytes
+}
+
+/// A TPM command, in the big-endian encoding of the TPM. The header is
+/// written once the size of the command is known.
+#[derive(Default)]
+struct Command {
+    tag: u16,
+    code: u32,
+    bytes: Vec<u8>,
+}
+
+impl Command {
+    fn new(tag: u16, code: u32) -> Self {
+        Command {
+            tag,
+            code,
+            bytes: vec![],
+        }
+    }
+
+    fn put_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn put_bytes(&mut self, value: &[u8]) {
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn put_tpm2b(&mut self, value: &[u8]) {
+        #[allow(clippy::cast_possible_truncation)]
+        self.put_u16(value.len() as u16);
+        self.put_bytes(value);
+    }
+
+    /// The authorization area with the empty password session, which is all
+    /// the keys and NV indexes that a quote uses ask for.
+    fn put_password_session(&mut self) {
+        self.put_u32(9);
+        self.put_u32(TPM_RS_PW);
+        // nonce, session attributes and hmac
+        self.put_u16(0);
+        self.put_u8(0);
+        self.put_u16(0);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut command = Vec::with_capacity(TPM_RESPONSE_HEADER_LENGTH + self.bytes.len());
+        command.extend_from_slice(&self.tag.to_be_bytes());
+        #[allow(clippy::cast_possible_truncation)]
+        let size = (TPM_RESPONSE_HEADER_LENGTH + self.bytes.len()) as u32;
+        command.extend_from_slice(&size.to_be_bytes());
+        command.extend_from_slice(&self.code.to_be_bytes());
+        command.extend_from_slice(&self.bytes);
+        command
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < length {
+            return Err(Error::from(ErrorKind::MalformedTpmResponse("response")));
+        }
+        let (value, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(value)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn tpm2b(&mut self) -> Result<&'a [u8], Error> {
+        let length = self.u16()?;
+        self.bytes(usize::from(length))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_has_header_with_size() {
+        let mut command = Command::new(TPM_ST_NO_SESSIONS, TPM_CC_FLUSH_CONTEXT);
+        command.put_u32(0x8000_0000);
+
+        assert_eq!(
+            vec![0x80, 0x01, 0, 0, 0, 14, 0, 0, 0x01, 0x65, 0x80, 0, 0, 0],
+            command.finish()
+        );
+    }
+
+    #[test]
+    fn attestation_key_template_is_restricted_signing_key() {
+        let template = attestation_key_template();
+        let mut reader = Reader::new(&template);
+        assert_eq!(TPM_ALG_RSA, reader.u16().unwrap());
+        assert_eq!(TPM_ALG_SHA256, reader.u16().unwrap());
+        assert_eq!(ATTESTATION_KEY_ATTRIBUTES, reader.u32().unwrap());
+        assert!(reader.tpm2b().unwrap().is_empty());
+        assert_eq!(TPM_ALG_NULL, reader.u16().unwrap());
+        assert_eq!(TPM_ALG_RSASSA, reader.u16().unwrap());
+        assert_eq!(TPM_ALG_SHA256, reader.u16().unwrap());
+        assert_eq!(2048, reader.u16().unwrap());
+        assert_eq!(0, reader.u32().unwrap());
+        assert!(reader.tpm2b().unwrap().is_empty());
+        assert!(reader.bytes(1).is_err());
+    }
+
+    #[test]
+    fn reader_rejects_truncated_responses() {
+        let mut reader = Reader::new(&[0, 4, 1, 2]);
+        assert_eq!(
+            &ErrorKind::MalformedTpmResponse("response"),
+            reader.tpm2b().unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn quote_rejects_bad_nonces() {
+        let attestation = TpmAttestation::new(TpmTcti::Tabrmd(None), HsmLock::new());
+
+        let err = attestation.quote_inner(&[]).unwrap_err();
+        assert_eq!(&ErrorKind::InvalidQuoteNonce(0), err.kind());
+
+        let err = attestation.quote_inner(&[0; 65]).unwrap_err();
+        assert_eq!(&ErrorKind::InvalidQuoteNonce(65), err.kind());
+
+        let err = attestation.quote_inner(&[0; 32]).unwrap_err();
+        assert_eq!(&ErrorKind::UnsupportedTcti, err.kind());
+    }
+}
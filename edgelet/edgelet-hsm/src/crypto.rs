@@ -62,7 +62,7 @@ impl Crypto {
 
 impl CoreGetHsmVersion for Crypto {
     fn get_version(&self) -> Result<String, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.crypto
             .get_version()
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
@@ -72,7 +72,7 @@ impl CoreGetHsmVersion for Crypto {
 
 impl CoreMasterEncryptionKey for Crypto {
     fn create_key(&self) -> Result<(), CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.crypto
             .create_master_encryption_key()
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
@@ -80,7 +80,7 @@ impl CoreMasterEncryptionKey for Crypto {
     }
 
     fn destroy_key(&self) -> Result<(), CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.crypto
             .destroy_master_encryption_key()
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
@@ -95,18 +95,18 @@ impl CoreCreateCertificate for Crypto {
         &self,
         properties: &CoreCertificateProperties,
     ) -> Result<Self::Certificate, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
-        let device_ca_alias = self.crypto.get_device_ca_alias();
-        let cert = self
-            .crypto
-            .create_certificate(&convert_properties(properties, &device_ca_alias))
-            .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
-            .map_err(|err| CoreError::from(err.context(CoreErrorKind::CertificateCreate)))?;
+        let cert = self.hsm_lock.measure("create_certificate", || {
+            let device_ca_alias = self.crypto.get_device_ca_alias();
+            self.crypto
+                .create_certificate(&convert_properties(properties, &device_ca_alias))
+                .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::CertificateCreate)))
+        })?;
         Ok(Certificate(cert))
     }
 
     fn destroy_certificate(&self, alias: String) -> Result<(), CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.crypto
             .destroy_certificate(alias)
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
@@ -115,7 +115,7 @@ impl CoreCreateCertificate for Crypto {
     }
 
     fn get_certificate(&self, alias: String) -> Result<Self::Certificate, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         let cert = self
             .crypto
             .get(alias)
@@ -134,11 +134,12 @@ impl CoreEncrypt for Crypto {
         plaintext: &[u8],
         initialization_vector: &[u8],
     ) -> Result<Self::Buffer, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
-        self.crypto
-            .encrypt(client_id, plaintext, initialization_vector)
-            .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
-            .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))
+        self.hsm_lock.measure("encrypt", || {
+            self.crypto
+                .encrypt(client_id, plaintext, initialization_vector)
+                .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))
+        })
     }
 }
 
@@ -151,18 +152,19 @@ impl CoreDecrypt for Crypto {
         ciphertext: &[u8],
         initialization_vector: &[u8],
     ) -> Result<Self::Buffer, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
-        self.crypto
-            .decrypt(client_id, ciphertext, initialization_vector)
-            .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
-            .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))
+        self.hsm_lock.measure("decrypt", || {
+            self.crypto
+                .decrypt(client_id, ciphertext, initialization_vector)
+                .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))
+        })
     }
 }
 
 impl CoreGetIssuerAlias for Crypto {
     fn get_issuer_alias(&self, issuer: CoreCertificateIssuer) -> Result<String, CoreError> {
         if issuer == CoreCertificateIssuer::DeviceCa {
-            let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+            let _hsm_lock = self.hsm_lock.lock();
             Ok(self.crypto.get_device_ca_alias())
         } else {
             Err(CoreError::from(CoreErrorKind::InvalidIssuer))
@@ -174,7 +176,7 @@ impl CoreGetTrustBundle for Crypto {
     type Certificate = Certificate;
 
     fn get_trust_bundle(&self) -> Result<Self::Certificate, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         let cert = self
             .crypto
             .get_trust_bundle()
@@ -186,7 +188,7 @@ impl CoreGetTrustBundle for Crypto {
 
 impl CoreMakeRandom for Crypto {
     fn get_random_bytes(&self, buffer: &mut [u8]) -> Result<(), CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.crypto
             .get_random_bytes(buffer)
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
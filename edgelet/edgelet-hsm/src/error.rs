@@ -23,6 +23,18 @@ pub enum ErrorKind {
     EmptyStrings,
     #[fail(display = "Only Device keys are allowed to be activated")]
     NoModuleActivation,
+    #[fail(display = "Device keys can only be activated, not created or deleted")]
+    NoDeviceKeyManagement,
+    #[fail(display = "A quote takes a nonce of 1 to 64 bytes, not {}", _0)]
+    InvalidQuoteNonce(usize),
+    #[fail(display = "The TPM returned a malformed response to {}", _0)]
+    MalformedTpmResponse(&'static str),
+    #[fail(display = "The TPM failed {} with response code {:#x}", _0, _1)]
+    TpmCommand(&'static str, u32),
+    #[fail(display = "Could not reach the TPM")]
+    TpmTransport,
+    #[fail(display = "Quotes are not supported over the configured TPM TCTI")]
+    UnsupportedTcti,
 }
 
 impl Fail for Error {
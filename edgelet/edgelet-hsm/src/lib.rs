@@ -10,26 +10,117 @@
     clippy::use_self
 )]
 
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
 
+use edgelet_core::Metrics;
+
+mod attestation;
 mod certificate_properties;
 mod crypto;
 mod error;
+mod self_test;
 pub mod tpm;
 pub mod x509;
 
+pub use attestation::TpmAttestation;
 pub use crypto::{Certificate, Crypto};
 pub use error::{Error, ErrorKind};
+pub use self_test::{SELF_TEST_ENCRYPT_DECRYPT, SELF_TEST_RANDOM, SELF_TEST_TRUST_BUNDLE};
 pub use tpm::{TpmKey, TpmKeyStore};
 pub use x509::X509;
 
-#[derive(Debug)]
-pub struct HsmLock(Mutex<()>);
+const HSM_OPERATION_DURATION_METRIC: &str = "iotedged_hsm_operation_duration_seconds";
+const HSM_OPERATION_DURATION_HELP: &str = "Duration of the operations on the HSM in seconds";
+const HSM_OPERATION_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const HSM_OPERATION_FAILURES_METRIC: &str = "iotedged_hsm_operation_failures_total";
+const HSM_OPERATION_FAILURES_HELP: &str = "Number of operations on the HSM that failed";
+
+pub struct HsmLock {
+    lock: Mutex<()>,
+    metrics: Option<Metrics>,
+}
 
 impl HsmLock {
     /// Use this instance of `Arc<HsmLock>` for all operations related to the HSM.
     /// This ensures that access to any HSM operation is serialized by this lock.
     pub fn new() -> Arc<Self> {
-        Arc::new(HsmLock(Mutex::new(())))
+        Arc::new(HsmLock {
+            lock: Mutex::new(()),
+            metrics: None,
+        })
+    }
+
+    /// Like `new`, but the latency and the failures of the signing, encryption
+    /// and certificate creation operations are also recorded in `metrics`.
+    pub fn with_metrics(metrics: Metrics) -> Arc<Self> {
+        Arc::new(HsmLock {
+            lock: Mutex::new(()),
+            metrics: Some(metrics),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().expect("Acquiring HSM lock failed")
+    }
+
+    /// Runs `f` while holding the lock, and records how long it took and
+    /// whether it failed under the given operation name. The time spent
+    /// waiting for the lock is not included.
+    fn measure<T, E>(&self, operation: &str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let _hsm_lock = self.lock();
+        let start = Instant::now();
+        let result = f();
+        if let Some(metrics) = &self.metrics {
+            let elapsed = start.elapsed();
+            #[allow(clippy::cast_precision_loss)]
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            let labels = [("operation", operation)];
+            metrics.observe_histogram(
+                HSM_OPERATION_DURATION_METRIC,
+                HSM_OPERATION_DURATION_HELP,
+                &labels,
+                HSM_OPERATION_DURATION_BUCKETS,
+                elapsed,
+            );
+            if result.is_err() {
+                metrics.inc_counter(
+                    HSM_OPERATION_FAILURES_METRIC,
+                    HSM_OPERATION_FAILURES_HELP,
+                    &labels,
+                );
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Debug for HsmLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HsmLock").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_records_latency_and_failures() {
+        let metrics = Metrics::new();
+        let hsm_lock = HsmLock::with_metrics(metrics.clone());
+
+        hsm_lock.measure("sign", || Ok::<_, ()>(())).unwrap();
+        hsm_lock.measure("sign", || Err::<(), _>(())).unwrap_err();
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE iotedged_hsm_operation_duration_seconds histogram"));
+        assert!(
+            output.contains("iotedged_hsm_operation_duration_seconds_count{operation=\"sign\"} 2")
+        );
+        assert!(output.contains("iotedged_hsm_operation_failures_total{operation=\"sign\"} 1"));
     }
 }
@@ -0,0 +1,119 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use chrono::Utc;
+use failure::Fail;
+
+use edgelet_core::{
+    Certificate as CoreCertificate, Decrypt as CoreDecrypt, Encrypt as CoreEncrypt,
+    Error as CoreError, GetTrustBundle as CoreGetTrustBundle, MakeRandom as CoreMakeRandom,
+    SelfTestReport, SelfTestResult,
+};
+
+use crate::crypto::Crypto;
+
+const SELF_TEST_CLIENT_ID: &[u8] = b"$iotedge-self-test";
+const SELF_TEST_OTHER_CLIENT_ID: &[u8] = b"$iotedge-self-test-other";
+const SELF_TEST_IV_LEN_BYTES: usize = 16;
+const SELF_TEST_RANDOM_LEN_BYTES: usize = 32;
+
+pub const SELF_TEST_RANDOM: &str = "random";
+pub const SELF_TEST_ENCRYPT_DECRYPT: &str = "encrypt-decrypt";
+pub const SELF_TEST_TRUST_BUNDLE: &str = "trust-bundle";
+
+impl Crypto {
+    /// Exercises the HSM backend the same way iotedged uses it in production
+    /// so that a degrading secure element is caught before certificate or
+    /// token operations start failing.
+    ///
+    /// Every step is run regardless of the outcome of the previous ones.
+    pub fn self_test(&self) -> SelfTestReport {
+        SelfTestReport::new(vec![
+            to_result(SELF_TEST_RANDOM, self.self_test_random()),
+            to_result(SELF_TEST_ENCRYPT_DECRYPT, self.self_test_encrypt_decrypt()),
+            to_result(SELF_TEST_TRUST_BUNDLE, self.self_test_trust_bundle()),
+        ])
+    }
+
+    fn self_test_random(&self) -> Result<(), String> {
+        let mut first = [0_u8; SELF_TEST_RANDOM_LEN_BYTES];
+        let mut second = [0_u8; SELF_TEST_RANDOM_LEN_BYTES];
+        self.get_random_bytes(&mut first)
+            .map_err(|err| describe(&err))?;
+        self.get_random_bytes(&mut second)
+            .map_err(|err| describe(&err))?;
+
+        if first.iter().all(|b| *b == 0) || second.iter().all(|b| *b == 0) {
+            return Err("HSM returned an all-zero random buffer".to_string());
+        }
+        if first == second {
+            return Err("HSM returned the same random buffer twice".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn self_test_encrypt_decrypt(&self) -> Result<(), String> {
+        let mut plaintext = [0_u8; SELF_TEST_RANDOM_LEN_BYTES];
+        let mut iv = [0_u8; SELF_TEST_IV_LEN_BYTES];
+        self.get_random_bytes(&mut plaintext)
+            .map_err(|err| describe(&err))?;
+        self.get_random_bytes(&mut iv)
+            .map_err(|err| describe(&err))?;
+
+        let ciphertext = self
+            .encrypt(SELF_TEST_CLIENT_ID, &plaintext, &iv)
+            .map_err(|err| describe(&err))?;
+        if ciphertext.as_ref() == &plaintext[..] {
+            return Err("HSM encryption returned the plaintext unchanged".to_string());
+        }
+
+        let decrypted = self
+            .decrypt(SELF_TEST_CLIENT_ID, ciphertext.as_ref(), &iv)
+            .map_err(|err| describe(&err))?;
+        if decrypted.as_ref() != &plaintext[..] {
+            return Err("HSM decryption did not round trip the encrypted data".to_string());
+        }
+
+        // The ciphertext is authenticated against the client identity, so it
+        // must not be accepted for any other identity.
+        if self
+            .decrypt(SELF_TEST_OTHER_CLIENT_ID, ciphertext.as_ref(), &iv)
+            .is_ok()
+        {
+            return Err("HSM decrypted data that was encrypted for another client".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn self_test_trust_bundle(&self) -> Result<(), String> {
+        let cert = self.get_trust_bundle().map_err(|err| describe(&err))?;
+
+        if cert.pem().map_err(|err| describe(&err))?.is_empty() {
+            return Err("HSM returned an empty trust bundle".to_string());
+        }
+
+        let valid_to = cert.get_valid_to().map_err(|err| describe(&err))?;
+        if valid_to < Utc::now() {
+            return Err(format!("trust bundle certificate expired at {}", valid_to));
+        }
+
+        cert.get_common_name().map_err(|err| describe(&err))?;
+
+        Ok(())
+    }
+}
+
+fn to_result(name: &str, result: Result<(), String>) -> SelfTestResult {
+    match result {
+        Ok(()) => SelfTestResult::passed(name),
+        Err(err) => SelfTestResult::failed(name, err),
+    }
+}
+
+fn describe(err: &CoreError) -> String {
+    <dyn Fail>::iter_chain(err)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
@@ -6,8 +6,8 @@ use bytes::Bytes;
 use failure::Fail;
 
 use edgelet_core::crypto::{
-    Activate, GetHsmVersion as CoreGetHsmVersion, KeyIdentity, KeyStore as CoreKeyStore, Sign,
-    SignatureAlgorithm,
+    Activate, GetHsmVersion as CoreGetHsmVersion, KeyIdentity, KeyStore as CoreKeyStore,
+    ManageKeys, Sign, SignatureAlgorithm,
 };
 use edgelet_core::{Error as CoreError, ErrorKind as CoreErrorKind};
 use hsm::{ManageTpmKeys, SignWithTpm, Tpm, TpmDigest};
@@ -65,7 +65,7 @@ impl TpmKeyStore {
 
     /// Activate and store a private key in the TPM.
     pub fn activate_key(&self, key_value: &Bytes) -> Result<(), Error> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.tpm.activate_identity_key(key_value)?;
         Ok(())
     }
@@ -83,7 +83,7 @@ impl TpmKeyStore {
 
 impl CoreGetHsmVersion for TpmKeyStore {
     fn get_version(&self) -> Result<String, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.tpm
             .get_version()
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
@@ -117,6 +117,45 @@ impl CoreKeyStore for TpmKeyStore {
     }
 }
 
+/// Module keys are never stored in the TPM. Each one is derived from the
+/// activated identity key and its name whenever it signs, so creating a module
+/// key just names it and deleting it has nothing to remove.
+impl ManageKeys for TpmKeyStore {
+    fn create_key(&self, identity: &KeyIdentity, key_name: &str) -> Result<Self::Key, CoreError> {
+        if *identity == KeyIdentity::Device {
+            Err(ErrorKind::NoDeviceKeyManagement)
+                .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))?;
+        }
+        self.get(identity, key_name)
+    }
+
+    fn import_key(
+        &self,
+        identity: &KeyIdentity,
+        key_name: &str,
+        key: &[u8],
+    ) -> Result<Self::Key, CoreError> {
+        if *identity != KeyIdentity::Device {
+            Err(ErrorKind::NoModuleActivation)
+                .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))?;
+        }
+        self.activate_key(&Bytes::from(key))
+            .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))?;
+        self.get(identity, key_name)
+    }
+
+    fn delete_key(&self, identity: &KeyIdentity, key_name: &str) -> Result<(), CoreError> {
+        if *identity == KeyIdentity::Device {
+            Err(ErrorKind::NoDeviceKeyManagement)
+                .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore)))?;
+        }
+        self.get(identity, key_name).map(|_| ())
+    }
+}
+
 impl Activate for TpmKeyStore {
     type Key = TpmKey;
 
@@ -147,8 +186,7 @@ impl Sign for TpmKey {
         _signature_algorithm: SignatureAlgorithm,
         data: &[u8],
     ) -> Result<Self::Signature, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
-        match self.identity {
+        self.hsm_lock.measure("sign", || match self.identity {
             KeyIdentity::Device => self
                 .tpm
                 .sign_with_identity(data)
@@ -170,6 +208,6 @@ impl Sign for TpmKey {
                 )
                 .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
                 .map_err(|err| CoreError::from(err.context(CoreErrorKind::KeyStore))),
-        }
+        })
     }
 }
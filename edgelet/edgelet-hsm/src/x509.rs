@@ -48,7 +48,7 @@ impl X509 {
 
 impl CoreGetHsmVersion for X509 {
     fn get_version(&self) -> Result<String, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         self.x509
             .get_version()
             .map_err(|err| Error::from(err.context(ErrorKind::Hsm)))
@@ -61,7 +61,7 @@ impl CoreGetDeviceIdentityCertificate for X509 {
     type Buffer = HsmPrivateKeySignDigest;
 
     fn get(&self) -> Result<Self::Certificate, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
+        let _hsm_lock = self.hsm_lock.lock();
         let cert = self
             .x509
             .get_certificate_info()
@@ -73,9 +73,10 @@ impl CoreGetDeviceIdentityCertificate for X509 {
     }
 
     fn sign_with_private_key(&self, data: &[u8]) -> Result<Self::Buffer, CoreError> {
-        let _hsm_lock = self.hsm_lock.0.lock().expect("Acquiring HSM lock failed");
-        self.x509
-            .sign_with_private_key(data)
-            .map_err(|err| CoreError::from(err.context(CoreErrorKind::DeviceIdentitySign)))
+        self.hsm_lock.measure("sign", || {
+            self.x509
+                .sign_with_private_key(data)
+                .map_err(|err| CoreError::from(err.context(CoreErrorKind::DeviceIdentitySign)))
+        })
     }
 }
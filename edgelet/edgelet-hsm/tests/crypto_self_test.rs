@@ -0,0 +1,53 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(unused_extern_crates, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+use edgelet_core::{MasterEncryptionKey, SelfTestResult};
+use edgelet_hsm::{
+    Crypto, HsmLock, SELF_TEST_ENCRYPT_DECRYPT, SELF_TEST_RANDOM, SELF_TEST_TRUST_BUNDLE,
+};
+mod test_utils;
+use test_utils::TestHSMEnvSetup;
+
+lazy_static! {
+    static ref LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[test]
+fn crypto_self_test_success() {
+    // arrange
+    let _setup_home_dir = TestHSMEnvSetup::new(&LOCK, None);
+
+    let hsm_lock = HsmLock::new();
+    let crypto = Crypto::new(hsm_lock, 1000).unwrap();
+    crypto
+        .create_key()
+        .expect("Create master key function returned error");
+
+    // act
+    let report = crypto.self_test();
+
+    // assert
+    let names: Vec<&str> = report.results().iter().map(SelfTestResult::name).collect();
+    assert_eq!(
+        vec![
+            SELF_TEST_RANDOM,
+            SELF_TEST_ENCRYPT_DECRYPT,
+            SELF_TEST_TRUST_BUNDLE
+        ],
+        names
+    );
+    for result in report.results() {
+        assert!(
+            result.is_ok(),
+            "self-test {} failed: {:?}",
+            result.name(),
+            result.error()
+        );
+    }
+    assert!(report.is_ok());
+}
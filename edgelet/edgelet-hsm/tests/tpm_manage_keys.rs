@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(unused_extern_crates, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+
+use std::sync::Mutex;
+
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use sha2::Sha256;
+
+use edgelet_core::crypto::Sign;
+use edgelet_core::crypto::Signature;
+use edgelet_core::crypto::SignatureAlgorithm;
+use edgelet_core::KeyIdentity;
+use edgelet_core::ManageKeys;
+use edgelet_hsm::{HsmLock, TpmKeyStore};
+
+mod test_utils;
+use test_utils::TestHSMEnvSetup;
+
+lazy_static! {
+    static ref LOCK: Mutex<()> = Mutex::new(());
+}
+
+const TEST_KEY_BASE64: &str = "D7PuplFy7vIr0349blOugqCxyfMscyVZDoV9Ii0EFnA=";
+
+fn test_helper_compute_hmac(key: &[u8], input: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new(key).unwrap();
+    mac.input(input);
+    mac.result().code().as_slice().to_vec()
+}
+
+// This tests the following:
+//  1) Importing a Device key activates it as the identity key, and the key
+//     that is returned signs with it.
+//  2) Module keys cannot be imported into the TPM.
+#[test]
+fn tpm_import_key() {
+    // arrange
+    let _setup_home_dir = TestHSMEnvSetup::new(&LOCK, None);
+
+    let hsm_lock = HsmLock::new();
+    let key_store = TpmKeyStore::new(hsm_lock).unwrap();
+    let decoded_key = base64::decode(TEST_KEY_BASE64).unwrap();
+    let data_to_be_signed = b"I am the very model of a modern major general";
+
+    // act
+    let key = key_store
+        .import_key(&KeyIdentity::Device, "primary", &decoded_key)
+        .unwrap();
+    let digest = key
+        .sign(SignatureAlgorithm::HMACSHA256, data_to_be_signed)
+        .unwrap();
+    let module_result = key_store.import_key(
+        &KeyIdentity::Module("module1".to_string()),
+        "primary",
+        &decoded_key,
+    );
+
+    // assert
+    assert_eq!(
+        test_helper_compute_hmac(&decoded_key, data_to_be_signed).as_slice(),
+        digest.as_bytes()
+    );
+    assert!(module_result.is_err());
+}
+
+// This tests the following:
+//  1) Module keys can be created and deleted, and are derived from the identity key.
+//  2) Device keys can be neither created nor deleted.
+//  3) Deriving from a key gives the same result as signing with it.
+#[test]
+fn tpm_create_derive_and_delete_key() {
+    // arrange
+    let _setup_home_dir = TestHSMEnvSetup::new(&LOCK, None);
+
+    let hsm_lock = HsmLock::new();
+    let key_store = TpmKeyStore::new(hsm_lock).unwrap();
+    let decoded_key = base64::decode(TEST_KEY_BASE64).unwrap();
+    key_store
+        .import_key(&KeyIdentity::Device, "primary", &decoded_key)
+        .unwrap();
+    let module1_identity = KeyIdentity::Module("module1".to_string());
+    let data_to_be_signed = b"I've information vegetable, animal, and mineral,";
+
+    // act
+    let key = key_store.create_key(&module1_identity, "token").unwrap();
+    let digest = key
+        .sign(SignatureAlgorithm::HMACSHA256, data_to_be_signed)
+        .unwrap();
+    let derived = key_store
+        .derive_key(&module1_identity, "token", data_to_be_signed)
+        .unwrap();
+    let other = key_store
+        .derive_key(&module1_identity, "secrets", data_to_be_signed)
+        .unwrap();
+
+    // assert
+    assert_eq!(digest.as_bytes(), derived.as_ref());
+    assert_ne!(derived.as_ref(), other.as_ref());
+    assert_ne!(
+        test_helper_compute_hmac(&decoded_key, data_to_be_signed).as_slice(),
+        digest.as_bytes()
+    );
+    key_store.delete_key(&module1_identity, "token").unwrap();
+    assert!(key_store
+        .create_key(&KeyIdentity::Device, "primary")
+        .is_err());
+    assert!(key_store
+        .delete_key(&KeyIdentity::Device, "primary")
+        .is_err());
+}
@@ -138,6 +138,20 @@ mod tests {
         assert!(client.is_ok());
     }
 
+    #[test]
+    fn missing_external_provisioning_socket() {
+        let client = ExternalProvisioningClient::new(
+            &(Url::parse("unix:///this/socket/does/not/exist.sock").unwrap()),
+        );
+        match client {
+            Ok(_t) => panic!("Unexpected to succeed with a missing socket."),
+            Err(ref err) => assert_eq!(
+                discriminant(&ErrorKind::InitializeExternalProvisioningClient),
+                discriminant(err.kind())
+            ),
+        };
+    }
+
     struct TestExternalProvisioningApiError(ExternalProvisioningApiError<serde_json::Value>);
 
     impl Clone for TestExternalProvisioningApiError {
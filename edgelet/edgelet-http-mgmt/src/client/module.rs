@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -10,15 +11,20 @@ use futures::future::{self, FutureResult};
 use futures::prelude::*;
 use futures::stream;
 use hyper::{Body, Chunk as HyperChunk, Client};
+use log::Level;
 use management::apis::client::APIClient;
 use management::apis::configuration::Configuration;
-use management::models::{Config, ModuleDetails as HttpModuleDetails};
+use management::models::{
+    Config, Deployment, ModuleDetails as HttpModuleDetails, ModuleSpec as HttpModuleSpec,
+    SettingValue,
+};
 use serde_json;
 use url::Url;
 
 use edgelet_core::*;
 use edgelet_core::{
-    ModuleOperation, RuntimeOperation, SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
+    ImageInfo, ModuleEvent, ModuleOperation, PrunedImages, RuntimeOperation,
+    SystemInfo as CoreSystemInfo, SystemResources, UrlExt,
 };
 use edgelet_docker::{self, DockerConfig};
 use edgelet_http::{UrlConnector, API_VERSION};
@@ -55,6 +61,56 @@ impl ModuleClient {
     }
 }
 
+impl ModuleClient {
+    /// Encrypts the value of a setting with the master encryption key of the daemon,
+    /// for use in config.yaml.
+    pub fn encrypt_setting(&self, value: &str) -> impl Future<Item = String, Error = Error> {
+        self.client
+            .settings_api()
+            .encrypt_setting(
+                &API_VERSION.to_string(),
+                SettingValue::new(value.to_string()),
+            )
+            .map(|setting| setting.value().clone())
+            .map_err(|err| Error::from_mgmt_error(err, ErrorKind::EncryptSetting))
+    }
+
+    /// Returns the most recent log records of the daemon at `level` or a more
+    /// severe one, as text.
+    pub fn daemon_logs(
+        &self,
+        level: Level,
+        tail: &LogTail,
+    ) -> impl Future<Item = Vec<u8>, Error = Error> {
+        self.client
+            .system_information_api()
+            .get_daemon_logs(
+                &API_VERSION.to_string(),
+                &level.to_string().to_lowercase(),
+                &tail.to_string(),
+            )
+            .map_err(|err| Error::from_mgmt_error(err, ErrorKind::DaemonLogs))
+            .and_then(|body| {
+                body.concat2()
+                    .map(|logs| logs.to_vec())
+                    .map_err(|err| Error::from(err.context(ErrorKind::DaemonLogs)))
+            })
+    }
+
+    /// Creates the identities and modules of `modules` and starts the modules,
+    /// without the edge agent.
+    pub fn apply_deployment(
+        &self,
+        modules: Vec<HttpModuleSpec>,
+    ) -> impl Future<Item = Vec<HttpModuleDetails>, Error = Error> {
+        self.client
+            .module_api()
+            .apply_deployment(&API_VERSION.to_string(), Deployment::new(modules))
+            .map(|list| list.modules().to_vec())
+            .map_err(|err| Error::from_mgmt_error(err, ErrorKind::ApplyDeployment))
+    }
+}
+
 impl Clone for ModuleClient {
     fn clone(&self) -> Self {
         ModuleClient {
@@ -114,6 +170,11 @@ fn runtime_status(details: &HttpModuleDetails) -> Result<ModuleRuntimeState, Err
         .status()
         .exit_status()
         .and_then(|e| e.status_code().parse::<i64>().ok());
+    let exit_reason = details
+        .status()
+        .exit_status()
+        .and_then(|e| e.reason())
+        .map(ToOwned::to_owned);
     let exit_time = details
         .status()
         .exit_status()
@@ -124,6 +185,7 @@ fn runtime_status(details: &HttpModuleDetails) -> Result<ModuleRuntimeState, Err
         .with_status(status)
         .with_status_description(description)
         .with_exit_code(exit_code)
+        .with_exit_reason(exit_reason)
         .with_started_at(start_time)
         .with_finished_at(exit_time);
     Ok(state)
@@ -160,6 +222,7 @@ impl ModuleRuntime for ModuleClient {
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = FutureResult<(), Self::Error>;
     type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
@@ -167,6 +230,16 @@ impl ModuleRuntime for ModuleClient {
     type SystemResourcesFuture =
         Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ExecFuture = FutureResult<ModuleExecResult, Self::Error>;
+    type CopyToFuture = FutureResult<(), Self::Error>;
+    type CopyFromFuture = FutureResult<Vec<u8>, Self::Error>;
+    type ExportImageFuture = Box<dyn Future<Item = Vec<u8>, Error = Self::Error> + Send>;
+    type ImportImagesFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ListImagesFuture = Box<dyn Future<Item = Vec<ImageInfo>, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PrunedImages, Error = Self::Error> + Send>;
+    type TopFuture = FutureResult<ModuleProcesses, Self::Error>;
+    type ValidateFuture = FutureResult<(), Self::Error>;
+    type EventStream = stream::Empty<ModuleEvent, Self::Error>;
 
     fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         unimplemented!()
@@ -249,6 +322,10 @@ impl ModuleRuntime for ModuleClient {
         unimplemented!()
     }
 
+    fn rename(&self, _id: &str, _new_id: &str) -> Self::RenameFuture {
+        unimplemented!()
+    }
+
     fn system_info(&self) -> Self::SystemInfoFuture {
         unimplemented!()
     }
@@ -321,6 +398,8 @@ impl ModuleRuntime for ModuleClient {
                 options.follow(),
                 tail,
                 options.since(),
+                options.until(),
+                options.timestamps(),
             )
             .then(|logs| match logs {
                 Ok(logs) => Ok(Logs(id, logs)),
@@ -345,6 +424,103 @@ impl ModuleRuntime for ModuleClient {
             future::join_all(n).map(|_| ())
         }))
     }
+
+    fn exec(&self, _id: &str, _command: &[String]) -> Self::ExecFuture {
+        unimplemented!()
+    }
+
+    fn copy_to(&self, _id: &str, _path: &str, _archive: Vec<u8>) -> Self::CopyToFuture {
+        unimplemented!()
+    }
+
+    fn copy_from(&self, _id: &str, _path: &str) -> Self::CopyFromFuture {
+        unimplemented!()
+    }
+
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture {
+        let id = id.to_string();
+
+        let archive = self
+            .client
+            .module_api()
+            .export_module_image(&API_VERSION.to_string(), &id)
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ExportModuleImage(id)),
+                )
+            });
+        Box::new(archive)
+    }
+
+    fn import_images(&self, archive: Vec<u8>) -> Self::ImportImagesFuture {
+        let import = self
+            .client
+            .image_api()
+            .import_images(&API_VERSION.to_string(), archive)
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages),
+                )
+            });
+        Box::new(import)
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        let images = self
+            .client
+            .image_api()
+            .list_images(&API_VERSION.to_string())
+            .map(|images| {
+                images
+                    .images()
+                    .iter()
+                    .map(|image| {
+                        let size = u64::try_from(image.size()).unwrap_or_default();
+                        ImageInfo::new(image.id().to_string(), image.tags().to_vec(), size)
+                            .with_in_use(image.in_use())
+                    })
+                    .collect()
+            })
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::ListImages),
+                )
+            });
+        Box::new(images)
+    }
+
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        let pruned = self
+            .client
+            .image_api()
+            .prune_images(&API_VERSION.to_string())
+            .map(|pruned| {
+                let space_reclaimed = u64::try_from(pruned.space_reclaimed()).unwrap_or_default();
+                PrunedImages::new(pruned.images().to_vec(), space_reclaimed)
+            })
+            .map_err(|err| {
+                Error::from_mgmt_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages),
+                )
+            });
+        Box::new(pruned)
+    }
+
+    fn top(&self, _id: &str) -> Self::TopFuture {
+        unimplemented!()
+    }
+
+    fn validate(&self, _module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        unimplemented!()
+    }
+
+    fn events(&self) -> Self::EventStream {
+        unimplemented!()
+    }
 }
 
 pub struct Logs(String, Body);
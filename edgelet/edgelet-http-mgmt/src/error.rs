@@ -2,7 +2,7 @@
 
 use std::fmt::{self, Display};
 
-use edgelet_core::{IdentityOperation, ModuleOperation, RuntimeOperation};
+use edgelet_core::{DeviceAction, IdentityOperation, ModuleOperation, RuntimeOperation};
 use edgelet_docker::ErrorKind as DockerErrorKind;
 use edgelet_iothub::Error as IoTHubError;
 use failure::{Backtrace, Context, Fail};
@@ -23,28 +23,55 @@ pub struct Error {
 
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Could not process the agent bootstrap info")]
+    AgentBootstrap,
+
+    #[fail(display = "Could not apply the deployment")]
+    ApplyDeployment,
+
     // Note: This errorkind is always wrapped in another errorkind context
     #[fail(display = "Client error")]
     Client(MgmtError<serde_json::Value>),
 
+    #[fail(display = "Could not get the logs of the daemon")]
+    DaemonLogs,
+
+    #[fail(display = "Could not {} the device", _0)]
+    DeviceAction(DeviceAction),
+
+    #[fail(display = "Device {} is not enabled in the settings", _0)]
+    DeviceActionDisabled(DeviceAction),
+
+    #[fail(display = "Could not encrypt the setting")]
+    EncryptSetting,
+
     #[fail(display = "{}", _0)]
     IdentityOperation(IdentityOperation),
 
     #[fail(display = "Could not initialize module client")]
     InitializeModuleClient,
 
+    #[fail(display = "The API token of the request is not valid")]
+    InvalidApiToken,
+
     #[fail(display = "Invalid API version {:?}", _0)]
     InvalidApiVersion(String),
 
     #[fail(display = "A request to Azure IoT Hub failed")]
     IotHub,
 
+    #[fail(display = "Could not process the log settings")]
+    LogSettings,
+
     #[fail(display = "Request body is malformed")]
     MalformedRequestBody,
 
     #[fail(display = "The request parameter `{}` is malformed", _0)]
     MalformedRequestParameter(&'static str),
 
+    #[fail(display = "The request is missing an API token")]
+    MissingApiToken,
+
     #[fail(display = "The request is missing required parameter `{}`", _0)]
     MissingRequiredParameter(&'static str),
 
@@ -54,18 +81,30 @@ pub enum ErrorKind {
     #[fail(display = "State not modified")]
     NotModified,
 
+    #[fail(display = "Could not process operation {:?}", _0)]
+    Operation(String),
+
+    #[fail(display = "Operation {:?} was not found", _0)]
+    OperationNotFound(String),
+
     #[fail(display = "Could not prepare update for module {:?}", _0)]
     PrepareUpdateModule(String),
 
     #[fail(display = "Could not reprovision device")]
     ReprovisionDevice,
 
+    #[fail(display = "Could not revoke the certificates of module {:?}", _0)]
+    RevokeCertificates(String),
+
     #[fail(display = "{}", _0)]
     RuntimeOperation(RuntimeOperation),
 
     #[fail(display = "Could not start management service")]
     StartService,
 
+    #[fail(display = "Could not process the startup state")]
+    StartupState,
+
     #[fail(display = "Could not update module {:?}", _0)]
     UpdateModule(String),
 }
@@ -129,8 +168,12 @@ impl IntoResponse for Error {
             if let Some(cause) = Fail::find_root_cause(&self).downcast_ref::<DockerErrorKind>() {
                 match cause {
                     DockerErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
-                    DockerErrorKind::Conflict => StatusCode::CONFLICT,
+                    DockerErrorKind::Conflict(_) => StatusCode::CONFLICT,
                     DockerErrorKind::NotModified => StatusCode::NOT_MODIFIED,
+                    DockerErrorKind::InvalidImage(_)
+                    | DockerErrorKind::InvalidModuleSpec(_)
+                    | DockerErrorKind::InvalidModuleType(_) => StatusCode::BAD_REQUEST,
+                    DockerErrorKind::PrivilegedModule { .. } => StatusCode::FORBIDDEN,
                     _ => StatusCode::INTERNAL_SERVER_ERROR,
                 }
             } else {
@@ -139,6 +182,11 @@ impl IntoResponse for Error {
                     | ErrorKind::MalformedRequestBody
                     | ErrorKind::MalformedRequestParameter(_)
                     | ErrorKind::MissingRequiredParameter(_) => StatusCode::BAD_REQUEST,
+                    ErrorKind::InvalidApiToken | ErrorKind::MissingApiToken => {
+                        StatusCode::UNAUTHORIZED
+                    }
+                    ErrorKind::DeviceActionDisabled(_) => StatusCode::FORBIDDEN,
+                    ErrorKind::OperationNotFound(_) => StatusCode::NOT_FOUND,
                     _ => {
                         error!("Internal server error: {}", message);
                         StatusCode::INTERNAL_SERVER_ERROR
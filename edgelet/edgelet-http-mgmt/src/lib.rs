@@ -20,6 +20,7 @@ pub use client::ModuleClient;
 pub use error::{Error, ErrorKind};
 pub use server::ListModules;
 pub use server::ManagementService;
+pub use server::{ModuleSnapshot, MODULE_SNAPSHOT_FILENAME};
 
 pub trait IntoResponse {
     fn into_response(self) -> Response<Body>;
@@ -0,0 +1,135 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde_json;
+
+use edgelet_core::AgentBootstrap;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::AgentBootstrap as AgentBootstrapResponse;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetAgentBootstrap {
+    bootstrap: AgentBootstrap,
+}
+
+impl GetAgentBootstrap {
+    pub fn new(bootstrap: AgentBootstrap) -> Self {
+        GetAgentBootstrap { bootstrap }
+    }
+}
+
+impl Handler<Parameters> for GetAgentBootstrap {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get Agent Bootstrap");
+
+        let response = write_response(&self.bootstrap)
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+fn write_response(bootstrap: &AgentBootstrap) -> Result<Response<Body>, Error> {
+    let mut body = AgentBootstrapResponse::new(
+        bootstrap.iothub_hostname().to_string(),
+        bootstrap.device_id().to_string(),
+        bootstrap.module_id().to_string(),
+        bootstrap.gateway_hostname().to_string(),
+        bootstrap.auth_scheme().to_string(),
+        bootstrap.workload_uri().to_string(),
+        bootstrap.management_uri().to_string(),
+    );
+    if let Some(parent_hostname) = bootstrap.parent_hostname() {
+        body.set_parent_hostname(parent_hostname.to_string());
+    }
+    let body = serde_json::to_string(&body).context(ErrorKind::AgentBootstrap)?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, body.len().to_string().as_str())
+        .body(body.into())
+        .context(ErrorKind::AgentBootstrap)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use super::*;
+
+    fn bootstrap() -> AgentBootstrap {
+        AgentBootstrap::new(
+            "hub1.azure-devices.net".to_string(),
+            "device1".to_string(),
+            "$edgeAgent".to_string(),
+            "gateway1".to_string(),
+            "sasToken".to_string(),
+            "unix:///var/run/iotedge/workload.sock".to_string(),
+            "unix:///var/run/iotedge/mgmt.sock".to_string(),
+        )
+    }
+
+    #[test]
+    fn returns_bootstrap_info() {
+        // arrange
+        let handler =
+            GetAgentBootstrap::new(bootstrap().with_parent_hostname(Some("parent1".to_string())));
+        let request = Request::get("http://localhost/agent/bootstrap")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let bootstrap: AgentBootstrapResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("hub1.azure-devices.net", bootstrap.iothub_hostname());
+        assert_eq!("device1", bootstrap.device_id());
+        assert_eq!("$edgeAgent", bootstrap.module_id());
+        assert_eq!("gateway1", bootstrap.gateway_hostname());
+        assert_eq!(Some(&"parent1".to_string()), bootstrap.parent_hostname());
+        assert_eq!("sasToken", bootstrap.auth_scheme());
+        assert_eq!(
+            "unix:///var/run/iotedge/workload.sock",
+            bootstrap.workload_uri()
+        );
+        assert_eq!(
+            "unix:///var/run/iotedge/mgmt.sock",
+            bootstrap.management_uri()
+        );
+    }
+
+    #[test]
+    fn parent_hostname_is_left_out_when_there_is_none() {
+        // arrange
+        let handler = GetAgentBootstrap::new(bootstrap());
+        let request = Request::get("http://localhost/agent/bootstrap")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        let body = response.into_body().concat2().wait().unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body.get("parentHostname").is_none());
+        assert_eq!("gateway1", body["gatewayHostname"]);
+    }
+}
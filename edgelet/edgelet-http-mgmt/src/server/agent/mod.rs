@@ -0,0 +1,4 @@
+// Copyright (c) Microsoft. All rights reserved.
+mod bootstrap;
+
+pub use self::bootstrap::GetAgentBootstrap;
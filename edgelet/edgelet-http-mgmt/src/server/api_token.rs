@@ -0,0 +1,166 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::future;
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Request};
+use log::{debug, warn};
+
+use edgelet_core::watchdog::ActivityMonitor;
+use edgelet_core::{ApiTokenOwner, ApiTokens};
+use edgelet_http::middleware::{Middleware, Next, ResponseFuture};
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+const BEARER_SCHEME: &str = "bearer";
+
+/// Checks that the request carries one of `tokens` as its bearer token.
+/// Rejected requests are logged, since they are either a misconfigured caller
+/// or someone probing the management API.
+pub fn check_api_token(tokens: &ApiTokens, req: &Request<Body>) -> Result<ApiTokenOwner, Error> {
+    let result = bearer_token(req)
+        .ok_or(ErrorKind::MissingApiToken)
+        .and_then(|token| tokens.verify(token).ok_or(ErrorKind::InvalidApiToken));
+
+    match result {
+        Ok(owner) => {
+            match &owner {
+                ApiTokenOwner::Config => debug!(
+                    "{} {} is authenticated with a token of the config file",
+                    req.method(),
+                    req.uri().path()
+                ),
+                ApiTokenOwner::Module(module_id) => debug!(
+                    "{} {} is authenticated with a token of module {}",
+                    req.method(),
+                    req.uri().path(),
+                    module_id
+                ),
+            }
+            Ok(owner)
+        }
+        Err(kind) => {
+            warn!(
+                "Rejecting management API request {} {}: {}",
+                req.method(),
+                req.uri().path(),
+                kind
+            );
+            Err(Error::from(kind))
+        }
+    }
+}
+
+/// Rejects requests without a valid API token, when tokens are required, and
+/// records the others as activity of the edge runtime.
+pub struct ApiTokenCheck {
+    api_tokens: Option<ApiTokens>,
+    activity: ActivityMonitor,
+}
+
+impl ApiTokenCheck {
+    pub fn new(api_tokens: Option<ApiTokens>, activity: ActivityMonitor) -> Self {
+        ApiTokenCheck {
+            api_tokens,
+            activity,
+        }
+    }
+}
+
+impl Middleware for ApiTokenCheck {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> ResponseFuture {
+        if let Some(api_tokens) = &self.api_tokens {
+            if let Err(err) = check_api_token(api_tokens, &req) {
+                return Box::new(future::ok(err.into_response()));
+            }
+        }
+
+        self.activity.record();
+        next.run(req)
+    }
+}
+
+fn bearer_token(req: &Request<Body>) -> Option<&str> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?.trim();
+    let mut parts = value.splitn(2, ' ');
+    let scheme = parts.next()?;
+    let token = parts.next()?.trim();
+    if scheme.eq_ignore_ascii_case(BEARER_SCHEME) && !token.is_empty() {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ManagementAuthSettings;
+
+    use super::*;
+
+    fn tokens() -> ApiTokens {
+        let settings: ManagementAuthSettings =
+            serde_json::from_str(r#"{ "tokens": ["secret"], "token_modules": ["edgeAgent"] }"#)
+                .unwrap();
+        ApiTokens::new(&settings)
+    }
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut req = Request::get("http://localhost/modules");
+        if let Some(authorization) = authorization {
+            req.header(AUTHORIZATION, authorization);
+        }
+        req.body(Body::default()).unwrap()
+    }
+
+    #[test]
+    fn valid_tokens_are_accepted() {
+        let tokens = tokens();
+        let minted = tokens.mint("edgeAgent").unwrap();
+
+        assert_eq!(
+            ApiTokenOwner::Config,
+            check_api_token(&tokens, &request(Some("Bearer secret"))).unwrap()
+        );
+        assert_eq!(
+            ApiTokenOwner::Config,
+            check_api_token(&tokens, &request(Some("bearer  secret "))).unwrap()
+        );
+        assert_eq!(
+            ApiTokenOwner::Module("edgeAgent".to_string()),
+            check_api_token(
+                &tokens,
+                &request(Some(&format!("Bearer {}", minted.token())))
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_tokens_are_rejected() {
+        let tokens = tokens();
+
+        for authorization in &[None, Some("Bearer"), Some("Bearer "), Some("Basic secret")] {
+            match check_api_token(&tokens, &request(*authorization))
+                .unwrap_err()
+                .kind()
+            {
+                ErrorKind::MissingApiToken => (),
+                kind => panic!("expected MissingApiToken but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_tokens_are_rejected() {
+        let tokens = tokens();
+
+        match check_api_token(&tokens, &request(Some("Bearer secret2")))
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::InvalidApiToken => (),
+            kind => panic!("expected InvalidApiToken but got {:?}", kind),
+        }
+    }
+}
@@ -1,4 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
+mod power;
 mod reprovision;
 
+pub use self::power::PowerDevice;
 pub use self::reprovision::ReprovisionDevice;
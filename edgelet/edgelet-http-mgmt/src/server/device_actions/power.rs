@@ -0,0 +1,132 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::sync::mpsc::UnboundedSender;
+use futures::{Future, IntoFuture};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{debug, info};
+
+use edgelet_core::DeviceAction;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Asks the daemon to stop the modules and then reboot or shut down the
+/// host. The action is refused unless it is enabled in the settings.
+pub struct PowerDevice {
+    action: DeviceAction,
+    enabled: bool,
+    initiate_action: UnboundedSender<DeviceAction>,
+}
+
+impl PowerDevice {
+    pub fn new(
+        action: DeviceAction,
+        enabled: bool,
+        initiate_action: UnboundedSender<DeviceAction>,
+    ) -> Self {
+        PowerDevice {
+            action,
+            enabled,
+            initiate_action,
+        }
+    }
+}
+
+impl Handler<Parameters> for PowerDevice {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Device {}", self.action);
+
+        let action = self.action;
+        let response = if self.enabled {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::DeviceActionDisabled(action)))
+        }
+        .and_then(|_| {
+            info!("Device {} requested through the management API", action);
+            self.initiate_action
+                .unbounded_send(action)
+                .map_err(|_| Error::from(ErrorKind::DeviceAction(action)))
+        })
+        .and_then(|_| -> Result<_, Error> {
+            let response = Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::default())
+                .context(ErrorKind::DeviceAction(action))?;
+
+            Ok(response)
+        })
+        .or_else(|e| Ok(e.into_response()))
+        .into_future();
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::sync::mpsc;
+    use futures::Stream;
+
+    use super::*;
+
+    #[test]
+    fn enabled_action_is_sent() {
+        // arrange
+        let (action_tx, action_rx) = mpsc::unbounded();
+        let handler = PowerDevice::new(DeviceAction::Reboot, true, action_tx);
+        let request = Request::post("http://localhost/device/reboot")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+        let (action, _) = action_rx.into_future().wait().ok().unwrap();
+        assert_eq!(Some(DeviceAction::Reboot), action);
+    }
+
+    #[test]
+    fn disabled_action_is_forbidden() {
+        // arrange
+        let (action_tx, action_rx) = mpsc::unbounded();
+        let handler = PowerDevice::new(DeviceAction::Shutdown, false, action_tx);
+        let request = Request::post("http://localhost/device/shutdown")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        drop(handler);
+        assert_eq!(0, action_rx.collect().wait().unwrap().len());
+    }
+
+    #[test]
+    fn action_fails_when_daemon_is_not_listening() {
+        // arrange
+        let (action_tx, mut action_rx) = mpsc::unbounded();
+        action_rx.close();
+        let handler = PowerDevice::new(DeviceAction::Reboot, true, action_tx);
+        let request = Request::post("http://localhost/device/reboot")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+}
@@ -9,9 +9,13 @@ use hyper::{Body, Request, Response, StatusCode};
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec, LifecycleEvent,
+    LifecycleHooks, Operation, OperationJournal,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
+use edgelet_utils::validate_module_id;
 use management::models::{Identity, IdentitySpec as CreateIdentitySpec};
 
 use crate::error::{Error, ErrorKind};
@@ -19,14 +23,30 @@ use crate::IntoResponse;
 
 pub struct CreateIdentity<I> {
     id_manager: Arc<Mutex<I>>,
+    hooks: LifecycleHooks,
+    journal: OperationJournal,
 }
 
 impl<I> CreateIdentity<I> {
     pub fn new(id_manager: I) -> Self {
         CreateIdentity {
             id_manager: Arc::new(Mutex::new(id_manager)),
+            hooks: LifecycleHooks::new(),
+            journal: OperationJournal::new(),
         }
     }
+
+    /// Tells `hooks` about the identities that were created.
+    pub fn with_lifecycle_hooks(mut self, hooks: LifecycleHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Records the identities that are being created in `journal`.
+    pub fn with_journal(mut self, journal: OperationJournal) -> Self {
+        self.journal = journal;
+        self
+    }
 }
 
 impl<I> Handler<Parameters> for CreateIdentity<I>
@@ -40,43 +60,57 @@ where
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let id_mgr = self.id_manager.clone();
+        let hooks = self.hooks.clone();
+        let journal = self.journal.clone();
         let response = read_request(req)
             .and_then(move |spec| {
                 let mut rid = id_mgr.lock().unwrap();
 
                 let module_id = spec.module_id().to_string();
+                let operation = journal.begin(Operation::CreateIdentity {
+                    name: module_id.clone(),
+                });
 
-                rid.create(spec).then(|identity| -> Result<_, Error> {
-                    let identity = identity.with_context(|_| {
-                        ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(module_id))
-                    })?;
-
-                    let module_id = identity.module_id().to_string();
+                rid.create(spec)
+                    .then(move |identity| -> Result<_, Error> {
+                        journal.finish(operation);
+                        let identity = identity.with_context(|_| {
+                            ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
+                                module_id,
+                            ))
+                        })?;
 
-                    let identity = Identity::new(
-                        module_id.clone(),
-                        identity.managed_by().to_string(),
-                        identity.generation_id().to_string(),
-                        identity.auth_type().to_string(),
-                    );
+                        let module_id = identity.module_id().to_string();
 
-                    let b = serde_json::to_string(&identity).with_context(|_| {
-                        ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
+                        let identity = Identity::new(
                             module_id.clone(),
-                        ))
-                    })?;
-                    let response = Response::builder()
-                        .status(StatusCode::OK)
-                        .header(CONTENT_TYPE, "application/json")
-                        .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                        .body(b.into())
-                        .with_context(|_| {
+                            identity.managed_by().to_string(),
+                            identity.generation_id().to_string(),
+                            identity.auth_type().to_string(),
+                        );
+
+                        let b = serde_json::to_string(&identity).with_context(|_| {
                             ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
-                                module_id,
+                                module_id.clone(),
                             ))
                         })?;
-                    Ok(response)
-                })
+                        let response = Response::builder()
+                            .status(StatusCode::OK)
+                            .header(CONTENT_TYPE, "application/json")
+                            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                            .body(b.into())
+                            .with_context(|_| {
+                                ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
+                                    module_id.clone(),
+                                ))
+                            })?;
+                        Ok((module_id, response))
+                    })
+                    .and_then(move |(module_id, response)| {
+                        hooks
+                            .notify(LifecycleEvent::IdentityCreated, &module_id)
+                            .then(|_| Ok(response))
+                    })
             })
             .or_else(|e| Ok(e.into_response()));
 
@@ -89,6 +123,7 @@ fn read_request(req: Request<Body>) -> impl Future<Item = IdentitySpec, Error =
         let b = b.context(ErrorKind::MalformedRequestBody)?;
         let create_req = serde_json::from_slice::<CreateIdentitySpec>(&b)
             .context(ErrorKind::MalformedRequestBody)?;
+        validate_module_id(create_req.module_id()).context(ErrorKind::MalformedRequestBody)?;
         let mut spec = IdentitySpec::new(create_req.module_id().to_string());
         if let Some(m) = create_req.managed_by() {
             spec = spec.with_managed_by(m.to_string());
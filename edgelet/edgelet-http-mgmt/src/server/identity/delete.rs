@@ -6,23 +6,43 @@ use failure::{Fail, ResultExt};
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
 
-use edgelet_core::{IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    IdentityManager, IdentityOperation, IdentitySpec, LifecycleEvent, LifecycleHooks, Operation,
+    OperationJournal,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::identity_name;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct DeleteIdentity<I> {
     id_manager: Mutex<I>,
+    hooks: LifecycleHooks,
+    journal: OperationJournal,
 }
 
 impl<I> DeleteIdentity<I> {
     pub fn new(id_manager: I) -> Self {
         DeleteIdentity {
             id_manager: Mutex::new(id_manager),
+            hooks: LifecycleHooks::new(),
+            journal: OperationJournal::new(),
         }
     }
+
+    /// Tells `hooks` about the identities that were deleted.
+    pub fn with_lifecycle_hooks(mut self, hooks: LifecycleHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Records the identities that are being deleted in `journal`.
+    pub fn with_journal(mut self, journal: OperationJournal) -> Self {
+        self.journal = journal;
+        self
+    }
 }
 
 impl<I> Handler<Parameters> for DeleteIdentity<I>
@@ -34,25 +54,36 @@ where
         _req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let hooks = self.hooks.clone();
+        let response = identity_name(&params)
             .map(|name| {
                 let name = name.to_string();
+                let journal = self.journal.clone();
+                let operation = journal.begin(Operation::DeleteIdentity { name: name.clone() });
 
                 self.id_manager
                     .lock()
                     .unwrap()
                     .delete(IdentitySpec::new(name.clone()))
-                    .then(|result| match result {
-                        Ok(_) => Ok(name),
-                        Err(err) => Err(Error::from(err.context(ErrorKind::IdentityOperation(
-                            IdentityOperation::DeleteIdentity(name),
-                        )))),
+                    .then(move |result| {
+                        journal.finish(operation);
+                        match result {
+                            Ok(()) => Ok(name),
+                            Err(err) => {
+                                Err(Error::from(err.context(ErrorKind::IdentityOperation(
+                                    IdentityOperation::DeleteIdentity(name),
+                                ))))
+                            }
+                        }
                     })
             })
             .into_future()
             .flatten()
+            .and_then(move |name| {
+                hooks
+                    .notify(LifecycleEvent::IdentityDeleted, &name)
+                    .then(|_| Ok(name))
+            })
             .and_then(|name| {
                 Ok(Response::builder()
                     .status(StatusCode::NO_CONTENT)
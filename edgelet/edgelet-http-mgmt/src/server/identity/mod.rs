@@ -3,13 +3,31 @@
 mod create;
 mod delete;
 mod list;
+mod regenerate;
 mod update;
 
 pub use self::create::CreateIdentity;
 pub use self::delete::DeleteIdentity;
 pub use self::list::ListIdentities;
+pub use self::regenerate::RegenerateIdentityKeys;
 pub use self::update::UpdateIdentity;
 
+use failure::ResultExt;
+
+use edgelet_http::route::Parameters;
+use edgelet_utils::validate_module_id;
+
+use crate::error::{Error, ErrorKind};
+
+/// The `name` parameter of the route, which is the identity of a module.
+fn identity_name(params: &Parameters) -> Result<&str, Error> {
+    let name = params
+        .name("name")
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))?;
+    validate_module_id(name).context(ErrorKind::MalformedRequestParameter("name"))?;
+    Ok(name)
+}
+
 #[cfg(test)]
 mod tests {
     use hyper::{Body, Response, StatusCode};
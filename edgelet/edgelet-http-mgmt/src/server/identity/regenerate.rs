@@ -0,0 +1,148 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Mutex;
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde_json;
+
+use edgelet_core::{Identity as CoreIdentity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::Identity;
+
+use super::identity_name;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Gives a module identity new keys, for when its credentials were
+/// compromised. The response has the new generation ID of the identity, which
+/// the modules that use it need to authenticate.
+pub struct RegenerateIdentityKeys<I> {
+    id_manager: Mutex<I>,
+}
+
+impl<I> RegenerateIdentityKeys<I> {
+    pub fn new(id_manager: I) -> Self {
+        RegenerateIdentityKeys {
+            id_manager: Mutex::new(id_manager),
+        }
+    }
+}
+
+impl<I> Handler<Parameters> for RegenerateIdentityKeys<I>
+where
+    I: 'static + IdentityManager + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = identity_name(&params)
+            .map(|name| {
+                let name = name.to_string();
+                info!("Regenerating the keys of identity {}", name);
+
+                self.id_manager
+                    .lock()
+                    .unwrap()
+                    .regenerate(IdentitySpec::new(name.clone()))
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::IdentityOperation(
+                            IdentityOperation::RegenerateIdentity(name),
+                        )))
+                    })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|identity| {
+                let module_id = identity.module_id().to_string();
+                let identity = Identity::new(
+                    module_id.clone(),
+                    identity.managed_by().to_string(),
+                    identity.generation_id().to_string(),
+                    identity.auth_type().to_string(),
+                );
+                let b = serde_json::to_string(&identity).with_context(|_| {
+                    ErrorKind::IdentityOperation(IdentityOperation::RegenerateIdentity(
+                        module_id.clone(),
+                    ))
+                })?;
+
+                Ok(Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::IdentityOperation(
+                        IdentityOperation::RegenerateIdentity(module_id),
+                    ))?)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::AuthType;
+    use edgelet_test_utils::identity::{TestIdentity, TestIdentityManager};
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    #[test]
+    fn regenerate_succeeds() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![
+            TestIdentity::new("m1", "iotedge", "1", AuthType::Sas),
+            TestIdentity::new("m2", "iotedge", "2", AuthType::Sas),
+        ]);
+        let handler = RegenerateIdentityKeys::new(manager);
+        let request = Request::post("http://localhost/identities/m2/regenerate")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "m2".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let identity: Identity = serde_json::from_slice(&body).unwrap();
+        assert_eq!("m2", identity.module_id());
+        assert_eq!("iotedge", identity.managed_by());
+        assert_ne!("2", identity.generation_id());
+    }
+
+    #[test]
+    fn regenerate_unknown_identity_fails() {
+        // arrange
+        let manager = TestIdentityManager::new(vec![]);
+        let handler = RegenerateIdentityKeys::new(manager);
+        let request = Request::post("http://localhost/identities/m1/regenerate")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "m1".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not regenerate the keys of identity m1\n\tcaused by: Module not found",
+            error.message()
+        );
+    }
+}
@@ -14,6 +14,7 @@ use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::{Identity, UpdateIdentity as UpdateIdentityRequest};
 
+use super::identity_name;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -41,9 +42,7 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let id_manager = self.id_manager.clone();
 
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let response = identity_name(&params)
             .map(|name| {
                 let name = name.to_string();
                 read_request(name.clone(), req).map(|spec| (spec, name))
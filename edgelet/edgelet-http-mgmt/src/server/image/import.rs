@@ -0,0 +1,128 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, Stream};
+use hyper::{Body, Request, Response, StatusCode};
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Loads the images of the archive in the request body, so that modules can
+/// be updated on devices that can't reach a registry.
+pub struct ImportImages<M> {
+    runtime: M,
+}
+
+impl<M> ImportImages<M> {
+    pub fn new(runtime: M) -> Self {
+        ImportImages { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ImportImages<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(|b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                Ok(b.to_vec())
+            })
+            .and_then(move |archive| {
+                runtime.import_images(archive).map_err(|err| {
+                    Error::from(
+                        err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages)),
+                    )
+                })
+            })
+            .and_then(|()| -> Result<_, Error> {
+                let response = Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::default())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = ImportImages::new(runtime(Ok(module)));
+        let request = Request::post("http://localhost/images/import")
+            .body("archive".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = ImportImages::new(runtime(Err(Error::General)));
+        let request = Request::post("http://localhost/images/import")
+            .body("archive".into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not import images\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::convert::TryFrom;
+
+use failure::{Fail, ResultExt};
+use futures::Future;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::{ImageDetails, ImageList};
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Lists the images the runtime has stored, so that operators can tell which
+/// of them are taking up disk space without being used by a module.
+pub struct ListImages<M> {
+    runtime: M,
+}
+
+impl<M> ListImages<M> {
+    pub fn new(runtime: M) -> Self {
+        ListImages { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ListImages<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = self
+            .runtime
+            .list_images()
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages)))
+            })
+            .and_then(|images| -> Result<_, Error> {
+                let images = images
+                    .iter()
+                    .map(|image| {
+                        ImageDetails::new(
+                            image.id().to_string(),
+                            image.tags().to_vec(),
+                            i64::try_from(image.size()).unwrap_or(i64::max_value()),
+                            image.in_use(),
+                        )
+                    })
+                    .collect();
+                let b = serde_json::to_string(&ImageList::new(images))
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages))?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = ListImages::new(runtime(Ok(module)));
+        let request = Request::get("http://localhost/images")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let list: ImageList = serde_json::from_slice(&body).unwrap();
+        let images = list.images();
+        assert_eq!(2, images.len());
+        assert_eq!("sha256:in-use", images[0].id());
+        assert_eq!(&["microsoft/test-image".to_string()], images[0].tags());
+        assert_eq!(1024, images[0].size());
+        assert!(images[0].in_use());
+        assert_eq!("sha256:unused", images[1].id());
+        assert!(images[1].tags().is_empty());
+        assert!(!images[1].in_use());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = ListImages::new(runtime(Err(Error::General)));
+        let request = Request::get("http://localhost/images")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not list images\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
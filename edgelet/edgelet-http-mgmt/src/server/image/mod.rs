@@ -0,0 +1,8 @@
+// Copyright (c) Microsoft. All rights reserved.
+mod import;
+mod list;
+mod prune;
+
+pub use self::import::ImportImages;
+pub use self::list::ListImages;
+pub use self::prune::PruneImages;
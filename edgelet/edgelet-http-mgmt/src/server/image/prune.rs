@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::convert::TryFrom;
+
+use failure::{Fail, ResultExt};
+use futures::Future;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::PrunedImages;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Removes the images that no container was created from, such as the ones
+/// left behind when modules were updated to newer images.
+pub struct PruneImages<M> {
+    runtime: M,
+}
+
+impl<M> PruneImages<M> {
+    pub fn new(runtime: M) -> Self {
+        PruneImages { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for PruneImages<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = self
+            .runtime
+            .prune_images()
+            .map_err(|err| {
+                Error::from(err.context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages)))
+            })
+            .and_then(|pruned| -> Result<_, Error> {
+                let pruned = PrunedImages::new(
+                    pruned.images().to_vec(),
+                    i64::try_from(pruned.space_reclaimed()).unwrap_or(i64::max_value()),
+                );
+                let b = serde_json::to_string(&pruned)
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = PruneImages::new(runtime(Ok(module)));
+        let request = Request::post("http://localhost/images/prune")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let pruned: PrunedImages = serde_json::from_slice(&body).unwrap();
+        assert_eq!(&["sha256:unused".to_string()], pruned.images());
+        assert_eq!(2048, pruned.space_reclaimed());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = PruneImages::new(runtime(Err(Error::General)));
+        let request = Request::post("http://localhost/images/prune")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not prune images\n\tcaused by: General error",
+            error.message()
+        );
+    }
+}
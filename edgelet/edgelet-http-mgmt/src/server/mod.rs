@@ -1,5 +1,7 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::sync::Arc;
+
 use failure::{Compat, Fail, ResultExt};
 use futures::sync::mpsc::UnboundedSender;
 use futures::{future, Future};
@@ -10,23 +12,39 @@ use lazy_static::lazy_static;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use edgelet_core::watchdog::{ActivityMonitor, AgentRollback, WatchdogStatus};
 use edgelet_core::{
-    Authenticator, IdentityManager, Module, ModuleRuntime, ModuleRuntimeErrorReason, Policy,
+    AgentBootstrap, ApiTokens, Authenticator, BootOrder, CertificateRevocationList, ClockSkew,
+    DeviceAction, DeviceActionSettings, Encrypt, IdentityManager, LifecycleHooks, LogBuffer,
+    LogController, MakeRandom, Module, ModuleRuntime, ModuleRuntimeErrorReason, OperationJournal,
+    OperationTracker, Policy, SecretStore, SelfTestStatus, StartupState,
 };
 use edgelet_http::authentication::Authentication;
 use edgelet_http::authorization::Authorization;
+use edgelet_http::middleware::{Chain, ChainBuilder};
+use edgelet_http::openapi::OpenApiHandler;
 use edgelet_http::route::*;
 use edgelet_http::router;
 use edgelet_http::Version;
 
+mod agent;
+mod api_token;
 mod device_actions;
 mod identity;
+mod image;
 mod module;
+mod operation;
+mod settings;
 mod system_info;
 
+use self::agent::*;
+use self::api_token::ApiTokenCheck;
 use self::device_actions::*;
 use self::identity::*;
+use self::image::*;
 pub use self::module::*;
+use self::operation::*;
+use self::settings::*;
 use self::system_info::*;
 use crate::error::{Error, ErrorKind};
 
@@ -36,59 +54,109 @@ lazy_static! {
 
 #[derive(Clone)]
 pub struct ManagementService {
-    inner: RouterService<RegexRecognizer>,
+    inner: Chain<RouterService<RegexRecognizer>>,
 }
 
 impl ManagementService {
-    pub fn new<M, I>(
+    pub fn new<M, I, C>(
         runtime: &M,
         identity: &I,
+        crypto: C,
         initiate_shutdown_and_reprovision: UnboundedSender<()>,
+        device_actions: &DeviceActionSettings,
+        initiate_device_action: UnboundedSender<DeviceAction>,
+        provisioning_payload: Option<serde_json::Value>,
+        warnings: Vec<String>,
+        crl: CertificateRevocationList,
+        activity: ActivityMonitor,
+        watchdog_status: WatchdogStatus,
+        agent_rollback: Option<AgentRollback<<M::Module as Module>::Config>>,
+        agent_bootstrap: AgentBootstrap,
+        log_controller: LogController,
+        log_buffer: LogBuffer,
+        startup_state: StartupState,
+        lifecycle_hooks: LifecycleHooks,
+        journal: OperationJournal,
+        api_tokens: Option<ApiTokens>,
+        secret_store: Arc<dyn SecretStore + Send + Sync>,
+        clock_skew: ClockSkew,
+        boot_order: BootOrder,
+        module_snapshot: ModuleSnapshot,
+        hsm_self_test: SelfTestStatus,
     ) -> impl Future<Item = Self, Error = Error>
     where
         M: ModuleRuntime + Authenticator<Request = Request<Body>> + Clone + Send + Sync + 'static,
         for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
-        <M::Module as Module>::Config: DeserializeOwned + Serialize,
+        <M::Module as Module>::Config: Clone + DeserializeOwned + Serialize + Send,
         M::Logs: Into<Body>,
         I: IdentityManager + Clone + Send + Sync + 'static,
         I::Identity: Serialize,
         <M::AuthenticateFuture as Future>::Error: Fail,
+        C: Encrypt + MakeRandom + Clone + Send + Sync + 'static,
     {
+        let operations = OperationTracker::new();
         let router = router!(
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()),
-            post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules"                           => CreateModule::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/modules"                           => ListModules::new(runtime.clone()).with_snapshot(module_snapshot),
+            post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules"                           => CreateModule::new(runtime.clone()).with_journal(journal.clone()).with_secret_store(secret_store.clone()).with_boot_order(boot_order.clone()).with_operations(operations.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/deployment"                        => ApplyDeployment::new(runtime.clone(), identity.clone(), agent_bootstrap.clone()).with_journal(journal.clone()).with_secret_store(secret_store.clone()).with_boot_order(boot_order.clone()),
             get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)"           => GetModule,
-            put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => UpdateModule::new(runtime.clone()),
+            put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => UpdateModule::new(runtime.clone()).with_rollback(agent_rollback).with_journal(journal.clone()).with_secret_store(secret_store).with_boot_order(boot_order.clone()),
             post    Version2019_01_30 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)/prepareupdate"   => PrepareUpdateModule::new(runtime.clone()),
-            delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => DeleteModule::new(runtime.clone()),
-            post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/start"     => StartModule::new(runtime.clone()),
-            post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stop"      => StopModule::new(runtime.clone()),
-            post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/restart"   => RestartModule::new(runtime.clone()),
+            delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)"           => DeleteModule::new(runtime.clone()).with_journal(journal.clone()).with_boot_order(boot_order),
+            post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/start"     => StartModule::new(runtime.clone()).with_lifecycle_hooks(lifecycle_hooks.clone()),
+            post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/stop"      => StopModule::new(runtime.clone()).with_lifecycle_hooks(lifecycle_hooks.clone()),
+            post    Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/restart"   => RestartModule::new(runtime.clone()).with_lifecycle_hooks(lifecycle_hooks.clone()),
             get     Version2018_06_28 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/logs"      => ModuleLogs::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/modules/(?P<name>[^/]+)/top"       => TopModule::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)/image"     => ExportModuleImage::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)/validate"  => ValidateModule::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/modules/(?P<name>[^/]+)/certificates/revoke" => RevokeModuleCertificates::new(crl),
+
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/images"                            => ListImages::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/images/import"                     => ImportImages::new(runtime.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/images/prune"                      => PruneImages::new(runtime.clone()),
+
+            get     Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/operations/(?P<id>[^/]+)"          => GetOperation::new(operations),
 
             get     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => ListIdentities::new(identity.clone()),
-            post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => CreateIdentity::new(identity.clone()),
+            post    Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities"                        => CreateIdentity::new(identity.clone()).with_lifecycle_hooks(lifecycle_hooks.clone()).with_journal(journal.clone()),
             put     Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)"        => UpdateIdentity::new(identity.clone()),
-            delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)"        => DeleteIdentity::new(identity.clone()),
+            delete  Version2018_06_28 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)"        => DeleteIdentity::new(identity.clone()).with_lifecycle_hooks(lifecycle_hooks).with_journal(journal),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/identities/(?P<name>[^/]+)/regenerate" => RegenerateIdentityKeys::new(identity.clone()),
 
-            get     Version2018_06_28 runtime Policy::Anonymous             => "/systeminfo"                        => GetSystemInfo::new(runtime.clone()),
+            get     Version2018_06_28 runtime Policy::Anonymous             => "/systeminfo"                        => GetSystemInfo::new(runtime.clone(), provisioning_payload, warnings).with_watchdog_status(watchdog_status).with_clock_skew(clock_skew).with_hsm_self_test(hsm_self_test),
             get     Version2019_11_05 runtime Policy::Anonymous             => "/systeminfo/resources"              => GetSystemResources::new(runtime.clone()),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/systeminfo/logging"                => GetLogSettings::new(log_controller.clone()),
+            put     Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/systeminfo/logging"                => SetLogSettings::new(log_controller),
+            get     Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/logs"                              => GetDaemonLogs::new(log_buffer),
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/systeminfo/startup"                => GetStartupState::new(startup_state),
+
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/settings/encrypt"                  => EncryptSetting::new(crypto),
+
+            get     Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/agent/bootstrap"                   => GetAgentBootstrap::new(agent_bootstrap),
 
             post    Version2019_10_22 runtime Policy::Module(&*AGENT_NAME)  => "/device/reprovision"                => ReprovisionDevice::new(initiate_shutdown_and_reprovision),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/device/reboot"                     => PowerDevice::new(DeviceAction::Reboot, device_actions.allows(DeviceAction::Reboot), initiate_device_action.clone()),
+            post    Version2019_11_05 runtime Policy::Module(&*AGENT_NAME)  => "/device/shutdown"                   => PowerDevice::new(DeviceAction::Shutdown, device_actions.allows(DeviceAction::Shutdown), initiate_device_action),
+
+            get     Version2019_11_05 runtime Policy::Anonymous             => "/swagger.json"                      => OpenApiHandler::new(management::OPENAPI_SPEC),
         );
 
         router.new_service().then(|inner| {
             let inner = inner.context(ErrorKind::StartService)?;
+            let inner = ChainBuilder::new()
+                .with(ApiTokenCheck::new(api_tokens, activity))
+                .finish(inner);
             Ok(ManagementService { inner })
         })
     }
 }
 
 impl Service for ManagementService {
-    type ReqBody = <RouterService<RegexRecognizer> as Service>::ReqBody;
-    type ResBody = <RouterService<RegexRecognizer> as Service>::ResBody;
-    type Error = <RouterService<RegexRecognizer> as Service>::Error;
-    type Future = <RouterService<RegexRecognizer> as Service>::Future;
+    type ReqBody = <Chain<RouterService<RegexRecognizer>> as Service>::ReqBody;
+    type ResBody = <Chain<RouterService<RegexRecognizer>> as Service>::ResBody;
+    type Error = <Chain<RouterService<RegexRecognizer>> as Service>::Error;
+    type Future = <Chain<RouterService<RegexRecognizer>> as Service>::Future;
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
         self.inner.call(req)
@@ -107,3 +175,202 @@ impl NewService for ManagementService {
         future::ok(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use futures::sync::mpsc;
+
+    use hyper::StatusCode;
+
+    use edgelet_core::{
+        AuthId, Decrypt, Error as CoreError, LocalSecretStore, LogFormat, LogSettings,
+        MakeModuleRuntime,
+    };
+    use edgelet_http::openapi::{operations, route_operations};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::identity::TestIdentityManager;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    #[derive(Clone)]
+    struct TestCrypto;
+
+    impl Encrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn encrypt(
+            &self,
+            _client_id: &[u8],
+            plaintext: &[u8],
+            _initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            Ok(plaintext.to_vec())
+        }
+    }
+
+    impl Decrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn decrypt(
+            &self,
+            _client_id: &[u8],
+            ciphertext: &[u8],
+            _initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            Ok(ciphertext.to_vec())
+        }
+    }
+
+    impl MakeRandom for TestCrypto {
+        fn get_random_bytes(&self, _buffer: &mut [u8]) -> Result<(), CoreError> {
+            Ok(())
+        }
+    }
+
+    fn management_service(runtime: &TestRuntime<Error, TestSettings>) -> ManagementService {
+        let (reprovision, _) = mpsc::unbounded();
+        let (device_action, _) = mpsc::unbounded();
+        let log_controller = LogController::new(
+            || LogSettings::new(LogFormat::Text, "info".to_string()),
+            |_| (),
+        );
+        ManagementService::new(
+            runtime,
+            &TestIdentityManager::new(vec![]),
+            TestCrypto,
+            reprovision,
+            &DeviceActionSettings::default(),
+            device_action,
+            None,
+            vec![],
+            CertificateRevocationList::new(),
+            ActivityMonitor::new(),
+            WatchdogStatus::new(),
+            None,
+            AgentBootstrap::new(
+                "hub1".to_string(),
+                "d1".to_string(),
+                "$edgeAgent".to_string(),
+                "gateway1".to_string(),
+                "sasToken".to_string(),
+                "unix:///var/run/iotedge/workload.sock".to_string(),
+                "unix:///var/run/iotedge/mgmt.sock".to_string(),
+            ),
+            log_controller,
+            LogBuffer::new(0),
+            StartupState::default(),
+            LifecycleHooks::new(),
+            OperationJournal::new(),
+            None,
+            Arc::new(LocalSecretStore::new(BTreeMap::new())),
+            ClockSkew::new(),
+            BootOrder::new(),
+            ModuleSnapshot::new(),
+            SelfTestStatus::new(),
+        )
+        .wait()
+        .unwrap()
+    }
+
+    #[test]
+    fn routes_match_openapi_spec() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap();
+        let service = management_service(&runtime);
+
+        assert_eq!(
+            operations(management::OPENAPI_SPEC).unwrap(),
+            route_operations(&service.inner.inner().routes())
+        );
+    }
+
+    #[test]
+    fn regenerate_identity_keys_rejects_callers_other_than_agent() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_auth_id(AuthId::Value("mod1".into()));
+        let mut service = management_service(&runtime);
+
+        let request =
+            Request::post("http://localhost/identities/edgeHub/regenerate?api-version=2019-11-05")
+                .body(Body::default())
+                .unwrap();
+        let response = service.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[test]
+    fn encrypt_setting_rejects_callers_other_than_agent() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_auth_id(AuthId::Value("mod1".into()));
+        let mut service = management_service(&runtime);
+
+        let request = Request::post("http://localhost/settings/encrypt?api-version=2019-11-05")
+            .body(r#"{"value":"secret"}"#.into())
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[test]
+    fn export_module_image_rejects_callers_other_than_agent() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_auth_id(AuthId::Value("mod1".into()));
+        let mut service = management_service(&runtime);
+
+        let request = Request::get("http://localhost/modules/mod1/image?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[test]
+    fn daemon_logs_rejects_callers_other_than_agent() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_auth_id(AuthId::Value("mod1".into()));
+        let mut service = management_service(&runtime);
+
+        let request = Request::get("http://localhost/logs?api-version=2019-11-05")
+            .body(Body::default())
+            .unwrap();
+        let response = service.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+}
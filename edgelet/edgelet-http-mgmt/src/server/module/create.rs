@@ -1,9 +1,11 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use failure::ResultExt;
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
 use futures::future::Either;
-use futures::{Future, Stream};
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Request, Response, StatusCode};
 use log::debug;
 use serde::de::DeserializeOwned;
@@ -11,7 +13,9 @@ use serde::Serialize;
 use serde_json;
 
 use edgelet_core::{
-    ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleStatus, RuntimeOperation,
+    resolve_secret_refs, BootOrder, ImagePullPolicy, LocalSecretStore, Module, ModuleRegistry,
+    ModuleRuntime, ModuleStatus, Operation, OperationJournal, OperationKind, OperationReporter,
+    OperationTracker, RuntimeOperation, SecretStore,
 };
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
@@ -19,15 +23,57 @@ use management::models::*;
 
 use super::{spec_to_core, spec_to_details};
 use crate::error::{Error, ErrorKind};
+use crate::server::operation_response;
 use crate::IntoResponse;
 
+const PREFER: &str = "prefer";
+const PREFERENCE_APPLIED: &str = "preference-applied";
+const RESPOND_ASYNC: &str = "respond-async";
+
 pub struct CreateModule<M> {
     runtime: M,
+    journal: OperationJournal,
+    secret_store: Arc<dyn SecretStore + Send + Sync>,
+    boot_order: BootOrder,
+    operations: Option<OperationTracker>,
 }
 
 impl<M> CreateModule<M> {
     pub fn new(runtime: M) -> Self {
-        CreateModule { runtime }
+        CreateModule {
+            runtime,
+            journal: OperationJournal::new(),
+            secret_store: Arc::new(LocalSecretStore::default()),
+            boot_order: BootOrder::new(),
+            operations: None,
+        }
+    }
+
+    /// Records the modules that are being created in `journal`.
+    pub fn with_journal(mut self, journal: OperationJournal) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Resolves the secret references in the env of modules from
+    /// `secret_store`.
+    pub fn with_secret_store(mut self, secret_store: Arc<dyn SecretStore + Send + Sync>) -> Self {
+        self.secret_store = secret_store;
+        self
+    }
+
+    /// Records the priorities of the modules that are created in
+    /// `boot_order`.
+    pub fn with_boot_order(mut self, boot_order: BootOrder) -> Self {
+        self.boot_order = boot_order;
+        self
+    }
+
+    /// Creates modules in the background when clients ask for it with
+    /// `Prefer: respond-async`, and tracks their progress in `operations`.
+    pub fn with_operations(mut self, operations: OperationTracker) -> Self {
+        self.operations = Some(operations);
+        self
     }
 }
 
@@ -42,92 +88,178 @@ where
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let runtime = self.runtime.clone();
+        let journal = self.journal.clone();
+        let secret_store = self.secret_store.clone();
+        let boot_order = self.boot_order.clone();
+        let operations = self.operations.clone().filter(|_| prefers_async(&req));
         let response = req
             .into_body()
             .concat2()
-            .then(|b| {
+            .then(move |b| {
                 let b = b.context(ErrorKind::MalformedRequestBody)?;
                 let spec = serde_json::from_slice::<ModuleSpec>(&b)
                     .context(ErrorKind::MalformedRequestBody)?;
                 let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
+                let core_spec = resolve_secret_refs(core_spec, &*secret_store)
+                    .context(ErrorKind::MalformedRequestBody)?;
                 Ok((spec, core_spec))
             })
-            .and_then(move |(spec, core_spec)| {
-                let module_name = spec.name().to_string();
-                let image_pull_policy = core_spec.image_pull_policy();
-
-                let pull_future = match image_pull_policy {
-                    ImagePullPolicy::OnCreate => Either::A(
-                        runtime
-                            .registry()
-                            .pull(core_spec.config())
-                            .then(move |result| {
-                                result.with_context(|_| {
-                                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                        module_name.clone(),
-                                    ))
-                                })?;
-                                Ok((module_name, true))
-                            }),
-                    ),
-                    ImagePullPolicy::Never => Either::B(futures::future::ok((module_name, false))),
-                };
-
-                pull_future.and_then(move |(name, image_pulled)| -> Result<_, Error> {
-                    if image_pulled {
-                        debug!("Successfully pulled new image for module {}", name)
-                    } else {
-                        debug!(
-                            "Skipped pulling image for module {} as per pull policy",
-                            name
-                        )
-                    }
-
-                    Ok(runtime
-                        .create(core_spec)
-                        .then(move |result| -> Result<_, Error> {
-                            result.with_context(|_| {
-                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                    name.clone(),
-                                ))
-                            })?;
-                            let details = spec_to_details(&spec, ModuleStatus::Stopped);
-                            let b = serde_json::to_string(&details).with_context(|_| {
-                                ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
-                                    name.clone(),
-                                ))
-                            })?;
-                            let response = Response::builder()
-                                .status(StatusCode::CREATED)
-                                .header(CONTENT_TYPE, "application/json")
-                                .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                                .body(b.into())
-                                .context(ErrorKind::RuntimeOperation(
-                                    RuntimeOperation::CreateModule(name),
-                                ))?;
-                            Ok(response)
-                        }))
-                })
+            .and_then(move |(spec, core_spec)| match operations {
+                // The module is created in the background, and the client
+                // follows the operation instead of waiting for the response.
+                Some(operations) => {
+                    let reporter = operations.start(OperationKind::CreateModule, spec.name());
+                    let operation = operations.get(reporter.id()).ok_or_else(|| {
+                        Error::from(ErrorKind::OperationNotFound(reporter.id().to_string()))
+                    });
+                    let created = create(
+                        runtime,
+                        journal,
+                        boot_order,
+                        spec,
+                        core_spec,
+                        Some(reporter.clone()),
+                    )
+                    .then(move |result| {
+                        match result {
+                            Ok(_) => reporter.succeed(),
+                            Err(err) => reporter.fail(
+                                <dyn Fail>::iter_chain(&err)
+                                    .map(ToString::to_string)
+                                    .collect::<Vec<_>>()
+                                    .join("\n\tcaused by: "),
+                            ),
+                        }
+                        Ok(())
+                    });
+                    hyper::rt::spawn(created);
+
+                    let response = operation
+                        .and_then(|operation| operation_response(&operation, StatusCode::ACCEPTED))
+                        .map(|mut response| {
+                            response.headers_mut().insert(
+                                PREFERENCE_APPLIED,
+                                HeaderValue::from_static(RESPOND_ASYNC),
+                            );
+                            response
+                        });
+                    Either::A(response.into_future())
+                }
+                None => Either::B(create(runtime, journal, boot_order, spec, core_spec, None)),
             })
-            .flatten()
             .or_else(|e| Ok(e.into_response()));
 
         Box::new(response)
     }
 }
 
+/// Whether the client asked to have the module created in the background,
+/// with `Prefer: respond-async`.
+fn prefers_async(req: &Request<Body>) -> bool {
+    req.headers()
+        .get_all(PREFER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| preference.trim().eq_ignore_ascii_case(RESPOND_ASYNC))
+}
+
+fn create<M>(
+    runtime: M,
+    journal: OperationJournal,
+    boot_order: BootOrder,
+    spec: ModuleSpec,
+    core_spec: edgelet_core::ModuleSpec<<M::Module as Module>::Config>,
+    reporter: Option<OperationReporter>,
+) -> impl Future<Item = Response<Body>, Error = Error>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    let report = move |percent, message| {
+        if let Some(reporter) = &reporter {
+            reporter.progress(percent, message);
+        }
+    };
+
+    let module_name = spec.name().clone();
+    let image_pull_policy = core_spec.image_pull_policy();
+
+    let pull_future = match image_pull_policy {
+        ImagePullPolicy::OnCreate => {
+            report(0, "Pulling image");
+            Either::A(
+                runtime
+                    .registry()
+                    .pull(core_spec.config())
+                    .then(move |result| {
+                        result.with_context(|_| {
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                                module_name.clone(),
+                            ))
+                        })?;
+                        Ok((module_name, true))
+                    }),
+            )
+        }
+        ImagePullPolicy::Never => Either::B(futures::future::ok((module_name, false))),
+    };
+
+    pull_future.and_then(move |(name, image_pulled)| {
+        if image_pulled {
+            debug!("Successfully pulled new image for module {}", name);
+        } else {
+            debug!(
+                "Skipped pulling image for module {} as per pull policy",
+                name
+            );
+        }
+
+        report(50, "Creating module");
+        let operation = journal.begin(Operation::CreateModule { name: name.clone() });
+        let priority = core_spec.priority();
+        let wait_for_healthy = core_spec.wait_for_healthy();
+        let depends_on = core_spec.depends_on().to_vec();
+        runtime
+            .create(core_spec)
+            .then(move |result| -> Result<_, Error> {
+                journal.finish(operation);
+                result.with_context(|_| {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name.clone()))
+                })?;
+                boot_order.record(&name, priority, wait_for_healthy, depends_on);
+                let details = spec_to_details(&spec, ModuleStatus::Stopped);
+                let b = serde_json::to_string(&details).with_context(|_| {
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name.clone()))
+                })?;
+                let response = Response::builder()
+                    .status(StatusCode::CREATED)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(
+                        name,
+                    )))?;
+                Ok(response)
+            })
+    })
+}
+
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
+
     use chrono::prelude::*;
+    use futures::future;
     use hyper::Request;
     use lazy_static::lazy_static;
     use serde_json::json;
 
-    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus};
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState, ModuleStatus, OperationStatus};
     use edgelet_http::route::Parameters;
     use edgelet_test_utils::crypto::TestHsm;
     use edgelet_test_utils::module::*;
-    use management::models::{Config, ErrorResponse};
+    use management::models::{Config, EnvVar, ErrorResponse};
 
     use super::*;
     use crate::server::module::tests::Error;
@@ -194,6 +326,45 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn creates_module_in_background_when_preferred() {
+        // arrange
+        let operations = OperationTracker::new();
+        let handler = CreateModule::new(RUNTIME.clone()).with_operations(operations.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let mut spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        spec.set_image_pull_policy("on-create".to_string());
+        let request = Request::post("http://localhost/modules")
+            .header("Prefer", "respond-async")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let received = Arc::new(Mutex::new(None));
+        let responses = received.clone();
+        hyper::rt::run(future::lazy(move || {
+            handler
+                .handle(request, Parameters::new())
+                .map(move |response| *responses.lock().unwrap() = Some(response))
+                .map_err(|err| panic!("{:?}", err))
+        }));
+        let response = received.lock().unwrap().take().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+        assert_eq!(RESPOND_ASYNC, response.headers()[PREFERENCE_APPLIED]);
+        let location = response.headers()[hyper::header::LOCATION].clone();
+        let body = response.into_body().concat2().wait().unwrap();
+        let operation: management::models::Operation = serde_json::from_slice(&body).unwrap();
+        assert_eq!(format!("/operations/{}", operation.id()), location);
+        assert_eq!("create_module", operation.kind());
+        assert_eq!("test-module", operation.target());
+
+        let operation = operations.get(operation.id()).unwrap();
+        assert_eq!(OperationStatus::Succeeded, operation.status());
+        assert_eq!(100, operation.percent());
+    }
+
     #[test]
     fn bad_body() {
         let handler = CreateModule::new(RUNTIME.clone());
@@ -322,4 +493,60 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn bad_restart_policy() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let mut spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        spec.set_restart_policy("sometimes".to_string());
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error_response: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                let expected = "Request body is malformed\n\tcaused by: Invalid restart policy configuration \"sometimes\"";
+                assert_eq!(expected, error_response.message());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn unknown_secret() {
+        let handler = CreateModule::new(RUNTIME.clone());
+        let env = EnvVar::new("API_KEY".to_string(), "secretref:api-key".to_string());
+        let config = Config::new(json!({"image":"microsoft/test-image"})).with_env(vec![env]);
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::post("http://localhost/modules")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error_response: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                let expected = "Request body is malformed\n\tcaused by: Module test-module references secret \"api-key\", which is not in the secret store";
+                assert_eq!(expected, error_response.message());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
 }
@@ -4,20 +4,40 @@ use failure::{Fail, ResultExt};
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
 
-use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_core::{BootOrder, ModuleRuntime, Operation, OperationJournal, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::module_name;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct DeleteModule<M> {
     runtime: M,
+    journal: OperationJournal,
+    boot_order: BootOrder,
 }
 
 impl<M> DeleteModule<M> {
     pub fn new(runtime: M) -> Self {
-        DeleteModule { runtime }
+        DeleteModule {
+            runtime,
+            journal: OperationJournal::new(),
+            boot_order: BootOrder::new(),
+        }
+    }
+
+    /// Records the modules that are being removed in `journal`.
+    pub fn with_journal(mut self, journal: OperationJournal) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Forgets the priorities of the modules that are removed in
+    /// `boot_order`.
+    pub fn with_boot_order(mut self, boot_order: BootOrder) -> Self {
+        self.boot_order = boot_order;
+        self
     }
 }
 
@@ -30,17 +50,24 @@ where
         _req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let response = module_name(&params)
             .map(|name| {
                 let name = name.to_string();
-
-                self.runtime.remove(&name).then(|result| match result {
-                    Ok(_) => Ok(name),
-                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
-                        RuntimeOperation::RemoveModule(name),
-                    )))),
+                let journal = self.journal.clone();
+                let boot_order = self.boot_order.clone();
+                let operation = journal.begin(Operation::RemoveModule { name: name.clone() });
+
+                self.runtime.remove(&name).then(move |result| {
+                    journal.finish(operation);
+                    match result {
+                        Ok(()) => {
+                            boot_order.remove(&name);
+                            Ok(name)
+                        }
+                        Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::RemoveModule(name),
+                        )))),
+                    }
                 })
             })
             .into_future()
@@ -106,6 +133,33 @@ mod tests {
         assert_eq!(StatusCode::NO_CONTENT, response.status());
     }
 
+    #[test]
+    fn runtime_error_finishes_operation() {
+        // arrange
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Err(Error::General));
+        let journal = OperationJournal::new();
+        let handler = DeleteModule::new(runtime).with_journal(journal.clone());
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::delete("http://localhost/modules/test")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        assert!(journal.pending().is_empty());
+    }
+
     #[test]
     fn delete_bad_params() {
         // arrange
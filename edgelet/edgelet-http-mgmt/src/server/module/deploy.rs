@@ -0,0 +1,374 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::{future, stream, Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{
+    resolve_secret_refs, AgentBootstrap, BootOrder, Identity, IdentityManager, IdentityOperation,
+    IdentitySpec, LocalSecretStore, Module, ModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleSpec as CoreModuleSpec, ModuleStatus, Operation, OperationJournal, SecretStore,
+};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::{Error as HttpError, API_VERSION};
+use management::models::{Deployment, ModuleList};
+
+use super::update::pull_image;
+use super::{spec_to_core, spec_to_details};
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// The identities of the modules of a local deployment are managed like the
+/// ones the edge agent creates.
+const MANAGED_BY: &str = "IotEdge";
+
+/// Applies a deployment without the edge agent, for development and for
+/// bootstrapping a device that can't get its deployment from the hub. The
+/// identity of each module is created unless it exists, and the module is
+/// created again with the environment the edge agent would give it, and
+/// started.
+pub struct ApplyDeployment<M, I> {
+    runtime: M,
+    id_manager: Arc<Mutex<I>>,
+    bootstrap: AgentBootstrap,
+    journal: OperationJournal,
+    secret_store: Arc<dyn SecretStore + Send + Sync>,
+    boot_order: BootOrder,
+}
+
+impl<M, I> ApplyDeployment<M, I> {
+    pub fn new(runtime: M, id_manager: I, bootstrap: AgentBootstrap) -> Self {
+        ApplyDeployment {
+            runtime,
+            id_manager: Arc::new(Mutex::new(id_manager)),
+            bootstrap,
+            journal: OperationJournal::new(),
+            secret_store: Arc::new(LocalSecretStore::default()),
+            boot_order: BootOrder::new(),
+        }
+    }
+
+    /// Records the modules that are being replaced in `journal`.
+    pub fn with_journal(mut self, journal: OperationJournal) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Resolves the secret references in the env of modules from
+    /// `secret_store`.
+    pub fn with_secret_store(mut self, secret_store: Arc<dyn SecretStore + Send + Sync>) -> Self {
+        self.secret_store = secret_store;
+        self
+    }
+
+    /// Records the priorities of the modules that are deployed in
+    /// `boot_order`.
+    pub fn with_boot_order(mut self, boot_order: BootOrder) -> Self {
+        self.boot_order = boot_order;
+        self
+    }
+}
+
+impl<M, I> Handler<Parameters> for ApplyDeployment<M, I>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    for<'r> &'r M::Error: Into<ModuleRuntimeErrorReason>,
+    <M::Module as Module>::Config: Clone + DeserializeOwned + Serialize + Send,
+    I: 'static + IdentityManager + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+        let id_manager = self.id_manager.clone();
+        let bootstrap = self.bootstrap.clone();
+        let journal = self.journal.clone();
+        let secret_store = self.secret_store.clone();
+        let boot_order = self.boot_order.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let deployment: Deployment =
+                    serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
+
+                // All the modules are checked before any of them is touched,
+                // so that a bad deployment leaves the device as it was.
+                deployment
+                    .into_modules()
+                    .into_iter()
+                    .map(|spec| {
+                        let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
+                        let core_spec = resolve_secret_refs(core_spec, &*secret_store)
+                            .context(ErrorKind::MalformedRequestBody)?;
+                        Ok((spec, core_spec))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()
+            })
+            .and_then(move |modules| {
+                stream::iter_ok(modules)
+                    .and_then(move |(spec, core_spec)| {
+                        deploy_module(
+                            runtime.clone(),
+                            id_manager.clone(),
+                            &bootstrap,
+                            journal.clone(),
+                            boot_order.clone(),
+                            core_spec,
+                        )
+                        .map(move |()| spec_to_details(&spec, ModuleStatus::Running))
+                    })
+                    .collect()
+            })
+            .and_then(|details| -> Result<_, Error> {
+                let b = serde_json::to_string(&ModuleList::new(details))
+                    .context(ErrorKind::ApplyDeployment)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
+                    .body(b.into())
+                    .context(ErrorKind::ApplyDeployment)?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+fn deploy_module<M, I>(
+    runtime: M,
+    id_manager: Arc<Mutex<I>>,
+    bootstrap: &AgentBootstrap,
+    journal: OperationJournal,
+    boot_order: BootOrder,
+    mut core_spec: CoreModuleSpec<M::Config>,
+) -> impl Future<Item = (), Error = Error> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    for<'r> &'r M::Error: Into<ModuleRuntimeErrorReason>,
+    M::Config: DeserializeOwned + Serialize,
+    I: 'static + IdentityManager + Send,
+{
+    let name = core_spec.name().to_string();
+    let bootstrap = bootstrap.clone();
+    info!("Deploying module {}", name);
+
+    get_or_create_identity(id_manager, name.clone()).and_then(move |generation_id| {
+        // The env of the deployment wins over what the edge agent would
+        // set, like it does for the modules that the edge agent creates.
+        let mut env = bootstrap.module_env(&name, &generation_id);
+        env.insert("IOTEDGE_APIVERSION".to_string(), API_VERSION.to_string());
+        env.extend(core_spec.env().clone());
+        *core_spec.env_mut() = env;
+
+        let priority = core_spec.priority();
+        let wait_for_healthy = core_spec.wait_for_healthy();
+        let depends_on = core_spec.depends_on().to_vec();
+        let operation = journal.begin(Operation::UpdateModule {
+            name: name.clone(),
+            previous: None,
+        });
+
+        // The image is pulled before the module is removed, so that a module
+        // whose image can't be pulled keeps running as it was.
+        pull_image(&runtime, &core_spec)
+            .and_then(move |()| {
+                runtime.remove(&name).then(move |result| match result {
+                    Ok(()) => Ok((core_spec, name, runtime)),
+                    Err(err) => match (&err).into() {
+                        ModuleRuntimeErrorReason::NotFound => Ok((core_spec, name, runtime)),
+                        ModuleRuntimeErrorReason::Other => {
+                            Err(Error::from(err.context(ErrorKind::UpdateModule(name))))
+                        }
+                    },
+                })
+            })
+            .and_then(|(core_spec, name, runtime)| {
+                runtime.create(core_spec).then(|result| {
+                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                    Ok((name, runtime))
+                })
+            })
+            .and_then(|(name, runtime)| {
+                runtime.start(&name).then(|result| {
+                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                    Ok(name)
+                })
+            })
+            .then(move |result| {
+                journal.finish(operation);
+                let name = result?;
+                boot_order.record(&name, priority, wait_for_healthy, depends_on);
+                info!("Deployed module {}", name);
+                Ok(())
+            })
+    })
+}
+
+// Returns the generation ID of the identity of module `name`, which is created
+// if it doesn't exist yet.
+fn get_or_create_identity<I>(
+    id_manager: Arc<Mutex<I>>,
+    name: String,
+) -> impl Future<Item = String, Error = Error> + Send
+where
+    I: 'static + IdentityManager + Send,
+{
+    let get = id_manager
+        .lock()
+        .unwrap()
+        .get(IdentitySpec::new(name.clone()));
+
+    get.then(move |identity| {
+        let identity = identity.with_context(|_| {
+            ErrorKind::IdentityOperation(IdentityOperation::GetIdentity(name.clone()))
+        })?;
+        let generation_id = identity.map(|identity| identity.generation_id().to_string());
+        Ok((generation_id, name))
+    })
+    .and_then(move |(generation_id, name)| {
+        if let Some(generation_id) = generation_id {
+            return future::Either::A(future::ok(generation_id));
+        }
+
+        info!("Creating identity {}", name);
+        let create = id_manager
+            .lock()
+            .unwrap()
+            .create(IdentitySpec::new(name.clone()).with_managed_by(MANAGED_BY.to_string()));
+        future::Either::B(create.then(move |identity| {
+            let identity = identity.with_context(|_| {
+                ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(name))
+            })?;
+            Ok(identity.generation_id().to_string())
+        }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use edgelet_core::{AuthType, MakeModuleRuntime};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::identity::{TestIdentity, TestIdentityManager};
+    use edgelet_test_utils::module::{
+        TestConfig, TestModule, TestProvisioningResult, TestRuntime, TestSettings,
+    };
+    use management::models::{Config, EnvVar, ModuleSpec};
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error as TestError;
+
+    fn bootstrap() -> AgentBootstrap {
+        AgentBootstrap::new(
+            "hub.example.com".to_string(),
+            "device1".to_string(),
+            "$edgeAgent".to_string(),
+            "gateway.example.com".to_string(),
+            "sasToken".to_string(),
+            "unix:///var/run/iotedge/workload.sock".to_string(),
+            "unix:///var/run/iotedge/mgmt.sock".to_string(),
+        )
+    }
+
+    fn runtime() -> TestRuntime<TestError, TestSettings> {
+        let module = TestModule::new(
+            "test-module".to_string(),
+            TestConfig::new("microsoft/test-image".to_string()),
+            Ok(edgelet_core::ModuleRuntimeState::default()),
+        );
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module))
+    }
+
+    fn deployment() -> Body {
+        let config = Config::new(json!({"image":"microsoft/test-image"}))
+            .with_env(vec![EnvVar::new("K1".to_string(), "V1".to_string())]);
+        let spec = ModuleSpec::new("m1".to_string(), "docker".to_string(), config).with_priority(1);
+        serde_json::to_string(&Deployment::new(vec![spec]))
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn deployment_creates_modules() {
+        // arrange
+        let manager =
+            TestIdentityManager::new(vec![TestIdentity::new("m1", "IotEdge", "1", AuthType::Sas)])
+                .with_fail_get(false);
+        let handler = ApplyDeployment::new(runtime(), manager, bootstrap());
+        let request = Request::post("http://localhost/deployment")
+            .body(deployment())
+            .unwrap();
+
+        // act
+        let response = handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let list: ModuleList = serde_json::from_slice(&body).unwrap();
+        assert_eq!(1, list.modules().len());
+        assert_eq!("m1", list.modules()[0].name());
+        assert_eq!(
+            "running",
+            list.modules()[0].status().runtime_status().status()
+        );
+    }
+
+    #[test]
+    fn module_env_includes_bootstrap() {
+        let env: HashMap<String, String> = bootstrap().module_env("m1", "g1");
+
+        assert_eq!("m1", env["IOTEDGE_MODULEID"]);
+        assert_eq!("g1", env["IOTEDGE_MODULEGENERATIONID"]);
+        assert_eq!("device1", env["IOTEDGE_DEVICEID"]);
+        assert_eq!(
+            "unix:///var/run/iotedge/workload.sock",
+            env["IOTEDGE_WORKLOADURI"]
+        );
+    }
+
+    #[test]
+    fn malformed_deployment_fails() {
+        // arrange
+        let handler =
+            ApplyDeployment::new(runtime(), TestIdentityManager::new(vec![]), bootstrap());
+        let request = Request::post("http://localhost/deployment")
+            .body(Body::from(r#"{"modules":[{"name":"m1"}]}"#))
+            .unwrap();
+
+        // act
+        let response = handler
+            .handle(request, Parameters::default())
+            .wait()
+            .unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+
+use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::module_name;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct ExportModuleImage<M> {
+    runtime: M,
+}
+
+impl<M> ExportModuleImage<M> {
+    pub fn new(runtime: M) -> Self {
+        ExportModuleImage { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ExportModuleImage<M>
+where
+    M: 'static + ModuleRuntime + Send,
+{
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = module_name(&params)
+            .map(|name| {
+                let name = name.to_string();
+
+                self.runtime
+                    .export_image(&name)
+                    .then(|result| match result {
+                        Ok(archive) => Ok((name, archive)),
+                        Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                            RuntimeOperation::ExportModuleImage(name),
+                        )))),
+                    })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, archive)| {
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/x-tar")
+                    .header(CONTENT_LENGTH, archive.len().to_string().as_str())
+                    .body(archive.into())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::ExportModuleImage(name),
+                    ))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_http::route::Parameters;
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = ExportModuleImage::new(runtime(Ok(module)));
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::get("http://localhost/modules/test/image")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            "application/x-tar",
+            *response.headers().get(CONTENT_TYPE).unwrap()
+        );
+        let body = response.into_body().concat2().wait().unwrap();
+        assert_eq!(b"archive", body.as_ref());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = ExportModuleImage::new(runtime(Err(Error::General)));
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::get("http://localhost/modules/test/image")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not export the image of module test\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn bad_params() {
+        // arrange
+        let handler = ExportModuleImage::new(runtime(Err(Error::General)));
+        let request = Request::get("http://localhost/modules/test/image")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -1,28 +1,46 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use failure::ResultExt;
-use futures::{Future, Stream};
-use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use futures::{future, Future, Stream};
+use hyper::header::HeaderValue;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH, WARNING};
 use hyper::{Body, Request, Response, StatusCode};
 use log::debug;
 use serde::Serialize;
 use serde_json;
+use sha2::{Digest, Sha256};
 
 use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeState, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
 
+use super::ModuleSnapshot;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
+/// The warning that a list of modules from before the daemon restarted is
+/// answered with.
+const STALE_WARNING: &str = "110 - \"Response is Stale\"";
+
 pub struct ListModules<M> {
     runtime: M,
+    snapshot: Option<ModuleSnapshot>,
 }
 
 impl<M> ListModules<M> {
     pub fn new(runtime: M) -> Self {
-        ListModules { runtime }
+        ListModules {
+            runtime,
+            snapshot: None,
+        }
+    }
+
+    /// Lists the modules from `snapshot` until it is refreshed, and keeps it
+    /// up to date with the lists that are answered.
+    pub fn with_snapshot(mut self, snapshot: ModuleSnapshot) -> Self {
+        self.snapshot = Some(snapshot);
+        self
     }
 }
 
@@ -33,31 +51,30 @@ where
 {
     fn handle(
         &self,
-        _req: Request<Body>,
+        req: Request<Body>,
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         debug!("List modules");
 
-        let response = self
-            .runtime
-            .list_with_details()
-            .collect()
-            .then(|result| -> Result<_, Error> {
-                let details: Result<_, Error> = result
-                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
-                    .into_iter()
-                    .map(|(module, state)| core_to_details(&module, &state))
-                    .collect();
-                let body = ModuleList::new(details?);
-                let b = serde_json::to_string(&body)
-                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
-                let response = Response::builder()
-                    .status(StatusCode::OK)
-                    .header(CONTENT_TYPE, "application/json")
-                    .header(CONTENT_LENGTH, b.len().to_string().as_str())
-                    .body(b.into())
-                    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
-                Ok(response)
+        let if_none_match = req
+            .headers()
+            .get(IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+        if let Some(body) = self.snapshot.as_ref().and_then(ModuleSnapshot::stale_body) {
+            debug!("Listing modules from the snapshot taken before the daemon started");
+            let response = list_response(body, if_none_match, true)
+                .unwrap_or_else(IntoResponse::into_response);
+            return Box::new(future::ok(response));
+        }
+
+        let snapshot = self.snapshot.clone();
+        let response = list_body(&self.runtime)
+            .and_then(move |body| {
+                if let Some(snapshot) = snapshot {
+                    snapshot.update(&body);
+                }
+                list_response(body, if_none_match, false)
             })
             .or_else(|e| Ok(e.into_response()));
 
@@ -65,6 +82,68 @@ where
     }
 }
 
+/// Lists the modules of `runtime` as the body of a `ListModules` response.
+pub(crate) fn list_body<M>(runtime: &M) -> impl Future<Item = String, Error = Error>
+where
+    M: 'static + ModuleRuntime + Send,
+    <M::Module as Module>::Config: Serialize,
+{
+    runtime
+        .list_with_details()
+        .collect()
+        .then(|result| -> Result<_, Error> {
+            let details: Result<_, Error> = result
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?
+                .into_iter()
+                .map(|(module, state)| core_to_details(&module, &state))
+                .collect();
+            let body = ModuleList::new(details?);
+            let b = serde_json::to_string(&body)
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+            Ok(b)
+        })
+}
+
+fn list_response(
+    b: String,
+    if_none_match: Option<String>,
+    stale: bool,
+) -> Result<Response<Body>, Error> {
+    // The edge agent polls the list of modules, which rarely changes, so an
+    // unchanged list isn't sent again.
+    let etag = format!("\"{:x}\"", Sha256::digest(b.as_bytes()));
+    let mut response = if if_none_match.map_or(false, |tags| etag_matches(&tags, &etag)) {
+        Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(ETAG, etag.as_str())
+            .body(Body::empty())
+    } else {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "application/json")
+            .header(CONTENT_LENGTH, b.len().to_string().as_str())
+            .header(ETAG, etag.as_str())
+            .body(b.into())
+    }
+    .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules))?;
+
+    if stale {
+        response
+            .headers_mut()
+            .insert(WARNING, HeaderValue::from_static(STALE_WARNING));
+    }
+    Ok(response)
+}
+
+/// Whether `etag` is one of the entity tags of an `If-None-Match` header,
+/// which are compared weakly.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/"))
+}
+
 fn core_to_details<M>(module: &M, state: &ModuleRuntimeState) -> Result<ModuleDetails, Error>
 where
     M: 'static + Module + Send,
@@ -83,7 +162,11 @@ where
     }
     if let Some(code) = state.exit_code() {
         if let Some(finished_at) = state.finished_at() {
-            status.set_exit_status(ExitStatus::new(finished_at.to_rfc3339(), code.to_string()));
+            let mut exit_status = ExitStatus::new(finished_at.to_rfc3339(), code.to_string());
+            if let Some(reason) = state.exit_reason() {
+                exit_status.set_reason(reason.to_string());
+            }
+            status.set_exit_status(exit_status);
         }
     }
 
@@ -246,4 +329,104 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn unchanged_list_is_not_modified() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = ListModules::new(runtime);
+        let request = |if_none_match: Option<&str>| {
+            let mut request = Request::get("http://localhost/modules");
+            if let Some(if_none_match) = if_none_match {
+                request.header(IF_NONE_MATCH, if_none_match);
+            }
+            request.body(Body::default()).unwrap()
+        };
+        let response = handler
+            .handle(request(None), Parameters::new())
+            .wait()
+            .unwrap();
+        let etag = response.headers()[ETAG].to_str().unwrap().to_string();
+
+        // act
+        let unchanged = handler
+            .handle(request(Some(&etag)), Parameters::new())
+            .wait()
+            .unwrap();
+        let changed = handler
+            .handle(request(Some("\"other\"")), Parameters::new())
+            .wait()
+            .unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(StatusCode::NOT_MODIFIED, unchanged.status());
+        assert_eq!(etag, unchanged.headers()[ETAG].to_str().unwrap());
+        let body = unchanged.into_body().concat2().wait().unwrap();
+        assert!(body.is_empty());
+        assert_eq!(StatusCode::OK, changed.status());
+    }
+
+    #[test]
+    fn stale_snapshot_is_listed_until_refreshed() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let stale = r#"{"modules":[]}"#;
+        let snapshot = ModuleSnapshot::stale(stale.to_string());
+        let handler = ListModules::new(runtime.clone()).with_snapshot(snapshot.clone());
+        let request = || {
+            Request::get("http://localhost/modules")
+                .body(Body::default())
+                .unwrap()
+        };
+
+        // act
+        let from_snapshot = handler.handle(request(), Parameters::new()).wait().unwrap();
+        snapshot.refresh(&runtime).wait().unwrap();
+        let from_runtime = handler.handle(request(), Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(STALE_WARNING, from_snapshot.headers()[WARNING]);
+        let body = from_snapshot.into_body().concat2().wait().unwrap();
+        assert_eq!(stale.as_bytes(), body.as_ref());
+
+        assert!(from_runtime.headers().get(WARNING).is_none());
+        let body = from_runtime.into_body().concat2().wait().unwrap();
+        let list: ModuleList = serde_json::from_slice(&body).unwrap();
+        assert_eq!("test-module", list.modules()[0].name());
+    }
+
+    #[test]
+    fn etags_are_compared_weakly() {
+        assert!(etag_matches("\"a\"", "\"a\""));
+        assert!(etag_matches("\"b\", W/\"a\"", "\"a\""));
+        assert!(etag_matches("*", "\"a\""));
+        assert!(!etag_matches("\"b\"", "\"a\""));
+    }
 }
@@ -9,6 +9,7 @@ use edgelet_core::{parse_since, LogOptions, LogTail, ModuleRuntime, RuntimeOpera
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::module_name;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -34,9 +35,7 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let runtime = self.runtime.clone();
 
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let response = module_name(&params)
             .and_then(|name| {
                 let name = name.to_string();
                 let options = req
@@ -84,10 +83,22 @@ fn parse_options(query: &str) -> Result<LogOptions, Error> {
         .find(|&(ref key, _)| key == "since")
         .map_or_else(|| Ok(0), |(_, val)| parse_since(val))
         .context(ErrorKind::MalformedRequestParameter("since"))?;
+    let until = parse
+        .iter()
+        .find(|&(ref key, _)| key == "until")
+        .map_or_else(|| Ok(0), |(_, val)| parse_since(val))
+        .context(ErrorKind::MalformedRequestParameter("until"))?;
+    let timestamps = parse
+        .iter()
+        .find(|&(ref key, _)| key == "timestamps")
+        .map_or_else(|| Ok(false), |(_, val)| val.parse::<bool>())
+        .context(ErrorKind::MalformedRequestParameter("timestamps"))?;
     let options = LogOptions::new()
         .with_follow(follow)
         .with_tail(tail)
-        .with_since(since);
+        .with_since(since)
+        .with_until(until)
+        .with_timestamps(timestamps);
     Ok(options)
 }
 
@@ -106,11 +117,13 @@ mod tests {
 
     #[test]
     fn correct_logoptions() {
-        let query = "follow=true&tail=6&since=1551885923";
+        let query = "follow=true&tail=6&since=1551885923&until=1551889523&timestamps=true";
         let options = parse_options(&query).unwrap();
         assert_eq!(LogTail::Num(6), *options.tail());
         assert_eq!(true, options.follow());
         assert_eq!(1_551_885_923, options.since());
+        assert_eq!(1_551_889_523, options.until());
+        assert_eq!(true, options.timestamps());
     }
 
     #[test]
@@ -120,6 +133,8 @@ mod tests {
         assert_eq!(LogTail::default(), *options.tail());
         assert_eq!(false, options.follow());
         assert_eq!(0, options.since());
+        assert_eq!(0, options.until());
+        assert_eq!(false, options.timestamps());
     }
 
     #[test]
@@ -155,6 +170,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn logoption_until_error() {
+        let query = "since=1551885923&until=15abc";
+        let options = parse_options(&query);
+        assert!(options.is_err());
+        assert_eq!(
+            "The request parameter `until` is malformed",
+            options.err().unwrap().to_string()
+        );
+    }
+
     #[test]
     fn test_success() {
         let state = ModuleRuntimeState::default()
@@ -2,39 +2,63 @@
 
 use std::collections::HashMap;
 
-use failure::Fail;
+use failure::{Fail, ResultExt};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 
 use edgelet_core::{
     ImagePullPolicy, Module, ModuleRuntime, ModuleSpec as CoreModuleSpec, ModuleStatus,
+    RestartPolicy,
 };
+use edgelet_http::route::Parameters;
+use edgelet_utils::{validate_env_var_name, validate_module_name};
 use management::models::*;
 
 use crate::error::{Error, ErrorKind};
 
 mod create;
 mod delete;
+mod deploy;
+mod export_image;
 mod get;
 mod list;
 mod logs;
 mod prepare_update;
 mod restart;
+mod revoke_certificates;
+mod snapshot;
 mod start;
 mod stop;
+mod top;
 mod update;
+mod validate;
 
 pub use self::create::CreateModule;
 pub use self::delete::DeleteModule;
+pub use self::deploy::ApplyDeployment;
+pub use self::export_image::ExportModuleImage;
 pub use self::get::GetModule;
 pub use self::list::ListModules;
 pub use self::logs::ModuleLogs;
 pub use self::prepare_update::PrepareUpdateModule;
 pub use self::restart::RestartModule;
+pub use self::revoke_certificates::RevokeModuleCertificates;
+pub use self::snapshot::{ModuleSnapshot, MODULE_SNAPSHOT_FILENAME};
 pub use self::start::StartModule;
 pub use self::stop::StopModule;
+pub use self::top::TopModule;
 pub use self::update::UpdateModule;
+pub use self::validate::ValidateModule;
+
+/// The `name` parameter of the route, which has to be a valid module name.
+fn module_name(params: &Parameters) -> Result<&str, Error> {
+    let name = params
+        .name("name")
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))?;
+    validate_module_name(name).context(ErrorKind::MalformedRequestParameter("name"))?;
+    Ok(name)
+}
 
 fn spec_to_core<M>(
     spec: &ModuleSpec,
@@ -45,12 +69,19 @@ where
     <M::Module as Module>::Config: DeserializeOwned + Serialize,
 {
     let name = spec.name().to_string();
+    if let Err(err) = validate_module_name(&name) {
+        return Err(Error::from(err.context(context)));
+    }
+
     let type_ = spec.type_().to_string();
-    let env = spec.config().env().map_or_else(HashMap::new, |vars| {
+    let env: HashMap<String, String> = spec.config().env().map_or_else(HashMap::new, |vars| {
         vars.iter()
             .map(|var| (var.key().clone(), var.value().clone()))
             .collect()
     });
+    if let Some(err) = env.keys().find_map(|key| validate_env_var_name(key).err()) {
+        return Err(Error::from(err.context(context)));
+    }
 
     let config = match serde_json::from_value(spec.config().settings().clone()) {
         Ok(config) => config,
@@ -65,8 +96,21 @@ where
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
+    let restart_policy = match spec
+        .restart_policy()
+        .map_or(Ok(RestartPolicy::default()), str::parse)
+    {
+        Ok(restart_policy) => restart_policy,
+        Err(err) => return Err(Error::from(err.context(context))),
+    };
+
     let module_spec = match CoreModuleSpec::new(name, type_, config, env, image_pull_policy) {
-        Ok(module_spec) => module_spec,
+        Ok(module_spec) => module_spec
+            .with_restart_policy(restart_policy)
+            .with_restart_max_retries(spec.restart_max_retries())
+            .with_priority(spec.priority())
+            .with_wait_for_healthy(spec.wait_for_healthy().unwrap_or(false))
+            .with_depends_on(spec.depends_on().map_or_else(Vec::new, ToOwned::to_owned)),
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
@@ -100,7 +144,7 @@ pub mod tests {
     use hyper::{Body, Response, StatusCode};
     use serde_json;
 
-    use edgelet_core::RuntimeOperation;
+    use edgelet_core::{ModuleRuntimeErrorReason, RuntimeOperation};
     use edgelet_docker::{Error as DockerError, ErrorKind as DockerErrorKind};
     use management::models::ErrorResponse;
 
@@ -124,6 +168,12 @@ pub mod tests {
         }
     }
 
+    impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+        fn from(_err: &'a Error) -> Self {
+            ModuleRuntimeErrorReason::Other
+        }
+    }
+
     #[test]
     fn not_found() {
         // arrange
@@ -163,9 +213,13 @@ pub mod tests {
     fn conflict() {
         // arrange
         let error = MgmtError::from(
-            DockerError::from(DockerErrorKind::Conflict.context(
-                DockerErrorKind::RuntimeOperation(RuntimeOperation::StartModule("m1".to_string())),
-            ))
+            DockerError::from(
+                DockerErrorKind::Conflict("Conflict with current operation".to_string()).context(
+                    DockerErrorKind::RuntimeOperation(RuntimeOperation::StartModule(
+                        "m1".to_string(),
+                    )),
+                ),
+            )
             .context(ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(
                 "m1".to_string(),
             ))),
@@ -218,6 +272,41 @@ pub mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn invalid_module_spec() {
+        // arrange
+        let error = MgmtError::from(
+            DockerError::from(
+                DockerErrorKind::InvalidModuleSpec("Invalid port \"80/http\"".to_string()).context(
+                    DockerErrorKind::RuntimeOperation(RuntimeOperation::ValidateModule(
+                        "m1".to_string(),
+                    )),
+                ),
+            )
+            .context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ValidateModule("m1".to_string()),
+            )),
+        );
+
+        // act
+        let response = error.into_response();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not validate module m1\n\tcaused by: Could not validate module m1\n\tcaused by: Invalid port \"80/http\"",
+                    error.message()
+                );
+                Ok(())
+            }).wait()
+            .unwrap();
+    }
+
     #[test]
     fn internal_server() {
         // arrange
@@ -4,20 +4,31 @@ use failure::{Fail, ResultExt};
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
 
-use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_core::{LifecycleEvent, LifecycleHooks, ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::module_name;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct RestartModule<M> {
     runtime: M,
+    hooks: LifecycleHooks,
 }
 
 impl<M> RestartModule<M> {
     pub fn new(runtime: M) -> Self {
-        RestartModule { runtime }
+        RestartModule {
+            runtime,
+            hooks: LifecycleHooks::new(),
+        }
+    }
+
+    /// Tells `hooks` about the modules that were restarted.
+    pub fn with_lifecycle_hooks(mut self, hooks: LifecycleHooks) -> Self {
+        self.hooks = hooks;
+        self
     }
 }
 
@@ -30,9 +41,8 @@ where
         _req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let hooks = self.hooks.clone();
+        let response = module_name(&params)
             .map(|name| {
                 let name = name.to_string();
 
@@ -45,6 +55,13 @@ where
             })
             .into_future()
             .flatten()
+            .and_then(move |name| {
+                let started = name.clone();
+                hooks
+                    .notify(LifecycleEvent::ModuleStopped, &name)
+                    .and_then(move |()| hooks.notify(LifecycleEvent::ModuleStarted, &started))
+                    .then(|_| Ok(name))
+            })
             .and_then(|name| {
                 Ok(Response::builder()
                     .status(StatusCode::NO_CONTENT)
@@ -0,0 +1,98 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+
+use edgelet_core::CertificateRevocationList;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::module_name;
+use crate::error::ErrorKind;
+use crate::IntoResponse;
+
+pub struct RevokeModuleCertificates {
+    crl: CertificateRevocationList,
+}
+
+impl RevokeModuleCertificates {
+    pub fn new(crl: CertificateRevocationList) -> Self {
+        RevokeModuleCertificates { crl }
+    }
+}
+
+impl Handler<Parameters> for RevokeModuleCertificates {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = module_name(&params)
+            .and_then(|name| {
+                let count = self
+                    .crl
+                    .revoke_module(name)
+                    .context(ErrorKind::RevokeCertificates(name.to_string()))?;
+                info!("Revoked {} certificate(s) of module {}", count, name);
+
+                let response = Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::default())
+                    .context(ErrorKind::RevokeCertificates(name.to_string()))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use edgelet_http::route::Parameters;
+
+    use super::*;
+
+    const TEST_PEM: &[u8] = b"-----BEGIN CERTIFICATE-----\ndGVzdA==\n-----END CERTIFICATE-----\n";
+
+    #[test]
+    fn success() {
+        // arrange
+        let crl = CertificateRevocationList::new();
+        crl.record("test", TEST_PEM, Utc::now() + Duration::days(1))
+            .unwrap();
+        let handler = RevokeModuleCertificates::new(crl.clone());
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "test".to_string())]);
+        let request = Request::post("http://localhost/modules/test/certificates/revoke")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(1, crl.revoked().len());
+        assert_eq!("test", crl.revoked()[0].module_id());
+    }
+
+    #[test]
+    fn bad_params() {
+        // arrange
+        let handler = RevokeModuleCertificates::new(CertificateRevocationList::new());
+        let request = Request::post("http://localhost/modules/test/certificates/revoke")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
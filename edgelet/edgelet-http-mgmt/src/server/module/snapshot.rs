@@ -0,0 +1,151 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use futures::Future;
+use log::{info, warn};
+use serde::Serialize;
+
+use edgelet_core::{Module, ModuleRuntime};
+
+use super::list::list_body;
+use crate::error::Error;
+
+/// Name of the file in the home directory that holds the last list of modules
+/// the daemon answered with.
+pub const MODULE_SNAPSHOT_FILENAME: &str = "module_snapshot.json";
+
+#[derive(Debug, Default)]
+struct State {
+    body: Option<String>,
+    synced: bool,
+}
+
+/// The last list of modules that the management API answered with, kept
+/// across restarts of the daemon.
+///
+/// Until the list is refreshed from the container runtime after the daemon
+/// starts, the modules are listed from the snapshot, so that the edge agent
+/// doesn't get errors while the runtime is still catching up.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleSnapshot {
+    state: Arc<Mutex<State>>,
+    path: Option<PathBuf>,
+}
+
+impl ModuleSnapshot {
+    /// Keeps the snapshot in memory only, so there is nothing to list from
+    /// before it is refreshed.
+    pub fn new() -> Self {
+        ModuleSnapshot::default()
+    }
+
+    /// Keeps the snapshot in the file at `path`.
+    pub fn load(path: PathBuf) -> Self {
+        let body = fs::read_to_string(&path).ok();
+
+        ModuleSnapshot {
+            state: Arc::new(Mutex::new(State {
+                body,
+                synced: false,
+            })),
+            path: Some(path),
+        }
+    }
+
+    /// A snapshot with `body` from before the daemon started, for tests.
+    #[cfg(test)]
+    pub(crate) fn stale(body: String) -> Self {
+        ModuleSnapshot {
+            state: Arc::new(Mutex::new(State {
+                body: Some(body),
+                synced: false,
+            })),
+            path: None,
+        }
+    }
+
+    /// Lists the modules from the runtime into the snapshot, after which the
+    /// snapshot is no longer used to answer.
+    pub fn refresh<M>(&self, runtime: &M) -> impl Future<Item = (), Error = Error>
+    where
+        M: 'static + ModuleRuntime + Send,
+        <M::Module as Module>::Config: Serialize,
+    {
+        let snapshot = self.clone();
+        list_body(runtime).map(move |body| {
+            if !snapshot.lock().synced {
+                info!("Module list is in sync with the container runtime");
+            }
+            snapshot.update(&body);
+        })
+    }
+
+    /// The list of modules from before the daemon started, if it wasn't
+    /// refreshed yet.
+    pub(crate) fn stale_body(&self) -> Option<String> {
+        let state = self.lock();
+        if state.synced {
+            None
+        } else {
+            state.body.clone()
+        }
+    }
+
+    pub(crate) fn update(&self, body: &str) {
+        let mut state = self.lock();
+        state.synced = true;
+        if state.body.as_ref().map(String::as_str) == Some(body) {
+            return;
+        }
+        state.body = Some(body.to_string());
+
+        if let Some(path) = &self.path {
+            if let Err(err) = write_atomically(path, body) {
+                warn!(
+                    "Could not write the module snapshot to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("Failed to acquire the module snapshot lock")
+    }
+}
+
+// The file is replaced as a whole, so that it holds either the old or the new
+// list if the device loses power while it is written.
+fn write_atomically(path: &Path, body: &str) -> Result<(), std::io::Error> {
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(body.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_is_stale_until_updated() {
+        let snapshot = ModuleSnapshot::new();
+        assert_eq!(None, snapshot.stale_body());
+
+        let snapshot = ModuleSnapshot::stale("{\"modules\":[]}".to_string());
+        assert_eq!(Some("{\"modules\":[]}".to_string()), snapshot.stale_body());
+
+        snapshot.update("{\"modules\":[]}");
+        assert_eq!(None, snapshot.stale_body());
+    }
+}
@@ -4,20 +4,31 @@ use failure::{Fail, ResultExt};
 use futures::{Future, IntoFuture};
 use hyper::{Body, Request, Response, StatusCode};
 
-use edgelet_core::{ModuleRuntime, RuntimeOperation};
+use edgelet_core::{LifecycleEvent, LifecycleHooks, ModuleRuntime, RuntimeOperation};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::module_name;
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct StartModule<M> {
     runtime: M,
+    hooks: LifecycleHooks,
 }
 
 impl<M> StartModule<M> {
     pub fn new(runtime: M) -> Self {
-        StartModule { runtime }
+        StartModule {
+            runtime,
+            hooks: LifecycleHooks::new(),
+        }
+    }
+
+    /// Tells `hooks` about the modules that were started.
+    pub fn with_lifecycle_hooks(mut self, hooks: LifecycleHooks) -> Self {
+        self.hooks = hooks;
+        self
     }
 }
 
@@ -30,9 +41,8 @@ where
         _req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let hooks = self.hooks.clone();
+        let response = module_name(&params)
             .map(|name| {
                 let name = name.to_string();
 
@@ -45,6 +55,11 @@ where
             })
             .into_future()
             .flatten()
+            .and_then(move |name| {
+                hooks
+                    .notify(LifecycleEvent::ModuleStarted, &name)
+                    .then(|_| Ok(name))
+            })
             .and_then(|name| {
                 Ok(Response::builder()
                     .status(StatusCode::NO_CONTENT)
@@ -137,4 +152,28 @@ mod tests {
         // assert
         assert_eq!(StatusCode::BAD_REQUEST, response.status());
     }
+
+    #[test]
+    fn start_invalid_name() {
+        // arrange
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap();
+        let handler = StartModule::new(runtime);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "$edgeAgent".to_string())]);
+        let request = Request::post("http://localhost/modules/$edgeAgent/start")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
 }
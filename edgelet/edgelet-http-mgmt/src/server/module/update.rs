@@ -1,18 +1,23 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use failure::ResultExt;
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
 use futures::future::Either;
 use futures::{future, Future, Stream};
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Request, Response, StatusCode};
-use log::debug;
-use log::info;
+use log::{debug, info, warn};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
 use url::form_urlencoded::parse as parse_query;
 
-use edgelet_core::{ImagePullPolicy, Module, ModuleRegistry, ModuleRuntime, ModuleStatus};
+use edgelet_core::watchdog::AgentRollback;
+use edgelet_core::{
+    resolve_secret_refs, BootOrder, ImagePullPolicy, LocalSecretStore, Module, ModuleRegistry,
+    ModuleRuntime, ModuleSpec, ModuleStatus, Operation, OperationJournal, SecretStore,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
@@ -20,20 +25,70 @@ use super::{spec_to_core, spec_to_details};
 use crate::error::{Error, ErrorKind};
 use crate::IntoResponse;
 
-pub struct UpdateModule<M> {
+/// The old module is renamed to this while a module is swapped for its
+/// update.
+const PREVIOUS_MODULE_SUFFIX: &str = "_previous";
+
+pub struct UpdateModule<M>
+where
+    M: ModuleRuntime,
+{
     runtime: M,
+    rollback: Option<AgentRollback<<M::Module as Module>::Config>>,
+    journal: OperationJournal,
+    secret_store: Arc<dyn SecretStore + Send + Sync>,
+    boot_order: BootOrder,
 }
 
-impl<M> UpdateModule<M> {
+impl<M> UpdateModule<M>
+where
+    M: ModuleRuntime,
+{
     pub fn new(runtime: M) -> Self {
-        UpdateModule { runtime }
+        UpdateModule {
+            runtime,
+            rollback: None,
+            journal: OperationJournal::new(),
+            secret_store: Arc::new(LocalSecretStore::default()),
+            boot_order: BootOrder::new(),
+        }
+    }
+
+    /// Records updates of the edge runtime module in `rollback`, so that the
+    /// watchdog can roll them back.
+    pub fn with_rollback(
+        mut self,
+        rollback: Option<AgentRollback<<M::Module as Module>::Config>>,
+    ) -> Self {
+        self.rollback = rollback;
+        self
+    }
+
+    /// Records the modules that are being updated in `journal`.
+    pub fn with_journal(mut self, journal: OperationJournal) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Resolves the secret references in the env of modules from
+    /// `secret_store`.
+    pub fn with_secret_store(mut self, secret_store: Arc<dyn SecretStore + Send + Sync>) -> Self {
+        self.secret_store = secret_store;
+        self
+    }
+
+    /// Records the priorities of the modules that are updated in
+    /// `boot_order`.
+    pub fn with_boot_order(mut self, boot_order: BootOrder) -> Self {
+        self.boot_order = boot_order;
+        self
     }
 }
 
 impl<M> Handler<Parameters> for UpdateModule<M>
 where
     M: 'static + ModuleRuntime + Clone + Send + Sync,
-    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+    <M::Module as Module>::Config: Clone + DeserializeOwned + Serialize + Send,
 {
     fn handle(
         &self,
@@ -41,25 +96,33 @@ where
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let runtime = self.runtime.clone();
-
-        let start: bool = req
-            .uri()
-            .query()
-            .and_then(|query| {
-                parse_query(query.as_bytes())
-                    .find(|&(ref key, _)| key == "start")
-                    .and_then(|(_, v)| if v == "false" { None } else { Some(()) })
-                    .map(|_| true)
-            })
-            .unwrap_or_else(|| false);
+        let rollback = self.rollback.clone();
+        let journal = self.journal.clone();
+        let secret_store = self.secret_store.clone();
+        let boot_order = self.boot_order.clone();
+
+        let query_flag = |name: &str| -> bool {
+            req.uri()
+                .query()
+                .and_then(|query| {
+                    parse_query(query.as_bytes())
+                        .find(|(key, _)| key == name)
+                        .map(|(_, v)| v != "false")
+                })
+                .unwrap_or(false)
+        };
+        let start = query_flag("start");
+        let swap = query_flag("swap");
 
         let response = req
             .into_body()
             .concat2()
-            .then(|b| -> Result<_, Error> {
+            .then(move |b| -> Result<_, Error> {
                 let b = b.context(ErrorKind::MalformedRequestBody)?;
                 let spec = serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
                 let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
+                let core_spec = resolve_secret_refs(core_spec, &*secret_store)
+                    .context(ErrorKind::MalformedRequestBody)?;
                 Ok((core_spec, spec))
             })
             .and_then(move |(core_spec, spec)| {
@@ -71,52 +134,30 @@ where
                     info!("Updating module {}", name);
                 }
 
-                runtime.remove(&name).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((core_spec, spec, name, runtime))
-                })
-            })
-            .and_then(|(core_spec, spec, name, runtime)| {
-                debug!("Removed existing module {}", name);
-
-                match core_spec.image_pull_policy() {
-                    ImagePullPolicy::OnCreate => {
-                        Either::A(runtime.registry().pull(core_spec.config()).then(|result| {
-                            result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                            Ok((core_spec, spec, name, runtime, true))
-                        }))
-                    }
-                    ImagePullPolicy::Never => {
-                        Either::B(futures::future::ok((core_spec, spec, name, runtime, false)))
-                    }
-                }
-            })
-            .and_then(|(core_spec, spec, name, runtime, image_pulled)| {
-                if image_pulled {
-                    debug!("Successfully pulled new image for module {}", name)
+                let previous = if swap {
+                    Some(format!("{}{}", name, PREVIOUS_MODULE_SUFFIX))
                 } else {
-                    debug!(
-                        "Skipped pulling image for module {} as per pull policy",
-                        name
-                    )
-                }
-
-                runtime.create(core_spec).then(|result| {
-                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                    Ok((name, spec, runtime))
-                })
-            })
-            .and_then(move |(name, spec, runtime)| {
-                debug!("Created module {}", name);
-                if start {
-                    info!("Starting module {}", name);
-                    future::Either::A(runtime.start(&name).then(|result| {
-                        result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
-                        Ok((ModuleStatus::Running, spec, name))
-                    }))
+                    None
+                };
+                let operation = journal.begin(Operation::UpdateModule {
+                    name: name.clone(),
+                    previous,
+                });
+                let priority = core_spec.priority();
+                let wait_for_healthy = core_spec.wait_for_healthy();
+                let depends_on = core_spec.depends_on().to_vec();
+
+                let status = if swap {
+                    Either::A(swap_module(runtime, rollback, core_spec, start))
                 } else {
-                    future::Either::B(future::ok((ModuleStatus::Stopped, spec, name)))
-                }
+                    Either::B(replace_module(runtime, rollback, core_spec, start))
+                };
+                status.then(move |result| {
+                    journal.finish(operation);
+                    let status = result?;
+                    boot_order.record(&name, priority, wait_for_healthy, depends_on);
+                    Ok((status, spec, name))
+                })
             })
             .and_then(|(status, spec, name)| -> Result<_, Error> {
                 let details = spec_to_details(&spec, status);
@@ -136,6 +177,222 @@ where
     }
 }
 
+pub(super) fn pull_image<M>(
+    runtime: &M,
+    core_spec: &ModuleSpec<M::Config>,
+) -> impl Future<Item = (), Error = Error> + Send
+where
+    M: ModuleRuntime,
+{
+    let name = core_spec.name().to_string();
+    match core_spec.image_pull_policy() {
+        ImagePullPolicy::OnCreate => Either::A(runtime.registry().pull(core_spec.config()).then(
+            move |result| {
+                result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                debug!("Successfully pulled new image for module {}", name);
+                Ok(())
+            },
+        )),
+        ImagePullPolicy::Never => {
+            debug!(
+                "Skipped pulling image for module {} as per pull policy",
+                name
+            );
+            Either::B(future::ok(()))
+        }
+    }
+}
+
+/// Removes the module and creates it again from `core_spec`. The module is
+/// gone if creating or starting it fails.
+fn replace_module<M>(
+    runtime: M,
+    rollback: Option<AgentRollback<M::Config>>,
+    core_spec: ModuleSpec<M::Config>,
+    start: bool,
+) -> impl Future<Item = ModuleStatus, Error = Error> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    M::Config: DeserializeOwned + Serialize,
+{
+    let name = core_spec.name().to_string();
+
+    runtime
+        .remove(&name)
+        .then(move |result| {
+            result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+            debug!("Removed existing module {}", name);
+            if let Some(rollback) = rollback {
+                rollback.record_update(&core_spec);
+            }
+            Ok((core_spec, name, runtime))
+        })
+        .and_then(|(core_spec, name, runtime)| {
+            pull_image(&runtime, &core_spec).map(|()| (core_spec, name, runtime))
+        })
+        .and_then(|(core_spec, name, runtime)| {
+            runtime.create(core_spec).then(|result| {
+                result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                Ok((name, runtime))
+            })
+        })
+        .and_then(move |(name, runtime)| {
+            debug!("Created module {}", name);
+            if start {
+                info!("Starting module {}", name);
+                Either::A(runtime.start(&name).then(move |result| {
+                    result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                    Ok(ModuleStatus::Running)
+                }))
+            } else {
+                Either::B(future::ok(ModuleStatus::Stopped))
+            }
+        })
+}
+
+/// Replaces the module with one created from `core_spec` without taking the
+/// old module down first. The image is pulled and the old module is renamed
+/// out of the way while it keeps running, and it is only removed once the new
+/// module is created and, if `start` is set, started. If any of that fails,
+/// the new module is removed and the old one is put back, so a failed update
+/// leaves the old module as it was.
+fn swap_module<M>(
+    runtime: M,
+    rollback: Option<AgentRollback<M::Config>>,
+    core_spec: ModuleSpec<M::Config>,
+    start: bool,
+) -> impl Future<Item = ModuleStatus, Error = Error> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    M::Config: DeserializeOwned + Serialize,
+{
+    let name = core_spec.name().to_string();
+    let previous = format!("{}{}", name, PREVIOUS_MODULE_SUFFIX);
+
+    pull_image(&runtime, &core_spec)
+        .and_then(move |()| {
+            // A module left behind by an update that was interrupted is in the
+            // way of the rename, and it is not the one that runs now anyway.
+            runtime
+                .remove(&previous)
+                .then(|_| Ok((core_spec, name, previous, runtime)))
+        })
+        .and_then(|(core_spec, name, previous, runtime)| {
+            runtime.rename(&name, &previous).then(|result| {
+                result.with_context(|_| ErrorKind::UpdateModule(name.clone()))?;
+                debug!("Renamed existing module {} to {}", name, previous);
+                Ok((core_spec, name, previous, runtime))
+            })
+        })
+        .and_then(|(core_spec, name, previous, runtime)| {
+            let recorded_spec = core_spec.clone();
+            runtime.create(core_spec).then(move |result| match result {
+                Ok(()) => {
+                    debug!("Created module {}", name);
+                    Either::A(future::ok((recorded_spec, name, previous, runtime)))
+                }
+                Err(err) => {
+                    let err = Error::from(err.context(ErrorKind::UpdateModule(name.clone())));
+                    Either::B(restore_module(runtime, name, previous, false).then(|_| Err(err)))
+                }
+            })
+        })
+        .and_then(move |(core_spec, name, previous, runtime)| {
+            if start {
+                info!("Starting module {}", name);
+                let started = runtime
+                    .stop(&previous, None)
+                    .and_then({
+                        let runtime = runtime.clone();
+                        let name = name.clone();
+                        move |()| runtime.start(&name)
+                    })
+                    .then(move |result| match result {
+                        Ok(()) => Either::A(future::ok((
+                            core_spec,
+                            name,
+                            previous,
+                            runtime,
+                            ModuleStatus::Running,
+                        ))),
+                        Err(err) => {
+                            let err =
+                                Error::from(err.context(ErrorKind::UpdateModule(name.clone())));
+                            Either::B(
+                                restore_module(runtime, name, previous, true).then(|_| Err(err)),
+                            )
+                        }
+                    });
+                Either::A(started)
+            } else {
+                Either::B(future::ok((
+                    core_spec,
+                    name,
+                    previous,
+                    runtime,
+                    ModuleStatus::Stopped,
+                )))
+            }
+        })
+        .and_then(move |(core_spec, name, previous, runtime, status)| {
+            if let Some(rollback) = rollback {
+                rollback.record_update(&core_spec);
+            }
+
+            runtime.remove(&previous).then(move |result| {
+                // The new module is in place, so the update went through even
+                // if the old one is left behind. The next update removes it.
+                match result {
+                    Ok(()) => debug!("Removed previous module {}", previous),
+                    Err(err) => warn!(
+                        "Could not remove previous module {} after updating module {}: {}",
+                        previous, name, err
+                    ),
+                }
+                Ok(status)
+            })
+        })
+}
+
+/// Removes the new module `name` and puts the old module back in its place,
+/// starting it again if it was stopped for the new module to start.
+fn restore_module<M>(
+    runtime: M,
+    name: String,
+    previous: String,
+    start: bool,
+) -> impl Future<Item = (), Error = ()> + Send
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+{
+    warn!(
+        "Update of module {} failed, restoring the previous module",
+        name
+    );
+
+    runtime
+        .remove(&name)
+        .then(move |_| {
+            runtime
+                .rename(&previous, &name)
+                .map(move |()| (runtime, name))
+        })
+        .and_then(move |(runtime, name)| {
+            if start {
+                Either::A(runtime.start(&name).map(|()| name))
+            } else {
+                Either::B(future::ok(name))
+            }
+        })
+        .then(|result| {
+            match result {
+                Ok(name) => info!("Restored previous module {}", name),
+                Err(err) => warn!("Could not restore previous module: {}", err),
+            }
+            Ok(())
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use chrono::prelude::*;
@@ -251,6 +508,60 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn success_swap_start() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let mut spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        spec.set_image_pull_policy("on-create".to_string());
+        let request = Request::put("http://localhost/modules/test-module?start&swap")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+                assert_eq!("test-module", details.name());
+                assert_eq!("running", details.status().runtime_status().status());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn success_swap_without_start() {
+        let handler = UpdateModule::new(RUNTIME.clone());
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        let request = Request::put("http://localhost/modules/test-module?swap=true&start=false")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let details: ModuleDetails = serde_json::from_slice(&b).unwrap();
+                assert_eq!("stopped", details.status().runtime_status().status());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn bad_body() {
         let handler = UpdateModule::new(RUNTIME.clone());
@@ -315,6 +626,44 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn runtime_error_swap() {
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Err(Error::General));
+        let handler = UpdateModule::new(runtime);
+        let config = Config::new(json!({"image":"microsoft/test-image"}));
+        let mut spec = ModuleSpec::new("test-module".to_string(), "docker".to_string(), config);
+        spec.set_image_pull_policy("never".to_string());
+        let request = Request::put("http://localhost/modules/test-module?start&swap")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Could not update module \"test-module\"\n\tcaused by: General error",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn bad_settings() {
         let runtime = TestRuntime::make_runtime(
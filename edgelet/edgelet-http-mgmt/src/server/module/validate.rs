@@ -0,0 +1,167 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, Stream};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::spec_to_core;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct ValidateModule<M> {
+    runtime: M,
+}
+
+impl<M> ValidateModule<M> {
+    pub fn new(runtime: M) -> Self {
+        ValidateModule { runtime }
+    }
+}
+
+impl<M> Handler<Parameters> for ValidateModule<M>
+where
+    M: 'static + ModuleRuntime + Clone + Send + Sync,
+    <M::Module as Module>::Config: DeserializeOwned + Serialize,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let runtime = self.runtime.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(|b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let spec = serde_json::from_slice(&b).context(ErrorKind::MalformedRequestBody)?;
+                let core_spec = spec_to_core::<M>(&spec, ErrorKind::MalformedRequestBody)?;
+                Ok(core_spec)
+            })
+            .and_then(move |core_spec| {
+                let name = core_spec.name().to_string();
+                runtime.validate(core_spec).then(|result| match result {
+                    Ok(()) => Ok(name),
+                    Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::ValidateModule(name),
+                    )))),
+                })
+            })
+            .and_then(|name| -> Result<_, Error> {
+                debug!("Successfully validated module {}", name);
+
+                let response = Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::default())
+                    .context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::ValidateModule(name),
+                    ))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{MakeModuleRuntime, ModuleRuntimeState};
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+    use management::models::{Config, ErrorResponse, ModuleSpec};
+    use serde_json::json;
+
+    use super::*;
+    use crate::server::module::tests::Error;
+
+    fn runtime(
+        module: Result<TestModule<Error, TestConfig>, Error>,
+    ) -> TestRuntime<Error, TestSettings> {
+        TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(module)
+    }
+
+    fn request(config: serde_json::Value) -> Request<Body> {
+        let spec = ModuleSpec::new(
+            "test-module".to_string(),
+            "docker".to_string(),
+            Config::new(config),
+        );
+        Request::post("http://localhost/modules/test-module/validate")
+            .body(serde_json::to_string(&spec).unwrap().into())
+            .unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let handler = ValidateModule::new(runtime(Ok(module)));
+        let request = request(json!({"image":"microsoft/test-image"}));
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+    }
+
+    #[test]
+    fn runtime_error() {
+        // arrange
+        let handler = ValidateModule::new(runtime(Err(Error::General)));
+        let request = request(json!({"image":"microsoft/test-image"}));
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Could not validate module test-module\n\tcaused by: General error",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn bad_settings() {
+        // arrange
+        let handler = ValidateModule::new(runtime(Err(Error::General)));
+        let request = request(json!({}));
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Request body is malformed\n\tcaused by: missing field `image`",
+            error.message()
+        );
+    }
+}
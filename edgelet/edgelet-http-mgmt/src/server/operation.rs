@@ -0,0 +1,140 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+
+use edgelet_core::{OperationProgress, OperationTracker};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::Operation;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetOperation {
+    operations: OperationTracker,
+}
+
+impl GetOperation {
+    pub fn new(operations: OperationTracker) -> Self {
+        GetOperation { operations }
+    }
+}
+
+impl Handler<Parameters> for GetOperation {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = params
+            .name("id")
+            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("id")))
+            .and_then(|id| {
+                debug!("Get operation {}", id);
+                let operation = self
+                    .operations
+                    .get(id)
+                    .ok_or_else(|| ErrorKind::OperationNotFound(id.to_string()))?;
+                operation_response(&operation, StatusCode::OK)
+            })
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+/// The response with the progress of `operation`, and where it can be
+/// followed.
+pub fn operation_response(
+    operation: &OperationProgress,
+    status: StatusCode,
+) -> Result<Response<Body>, Error> {
+    let mut body = Operation::new(
+        operation.id().to_string(),
+        operation.kind().to_string(),
+        operation.target().to_string(),
+        operation.status().to_string(),
+        i32::from(operation.percent()),
+        operation.started_at().to_rfc3339(),
+    );
+    if let Some(message) = operation.message() {
+        body.set_message(message.to_string());
+    }
+    if let Some(finished_at) = operation.finished_at() {
+        body.set_finished_at(finished_at.to_rfc3339());
+    }
+    let b = serde_json::to_string(&body)
+        .with_context(|_| ErrorKind::Operation(operation.id().to_string()))?;
+
+    let response = Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, b.len().to_string().as_str())
+        .header(LOCATION, format!("/operations/{}", operation.id()).as_str())
+        .body(b.into())
+        .with_context(|_| ErrorKind::Operation(operation.id().to_string()))?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::OperationKind;
+    use futures::Stream;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    #[test]
+    fn returns_progress_of_operation() {
+        // arrange
+        let operations = OperationTracker::new();
+        let reporter = operations.start(OperationKind::CreateModule, "tempSensor");
+        reporter.progress(50, "Creating module");
+        let handler = GetOperation::new(operations);
+        let request = Request::get(format!("http://localhost/operations/{}", reporter.id()))
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("id".to_string()), reporter.id().to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let operation: Operation = serde_json::from_slice(&body).unwrap();
+        assert_eq!(reporter.id(), operation.id());
+        assert_eq!("create_module", operation.kind());
+        assert_eq!("tempSensor", operation.target());
+        assert_eq!("running", operation.status());
+        assert_eq!(50, operation.percent());
+        assert_eq!(Some("Creating module"), operation.message());
+        assert_eq!(None, operation.finished_at());
+    }
+
+    #[test]
+    fn unknown_operation_is_not_found() {
+        // arrange
+        let handler = GetOperation::new(OperationTracker::new());
+        let request = Request::get("http://localhost/operations/unknown")
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("id".to_string()), "unknown".to_string())]);
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Operation \"unknown\" was not found", error.message());
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+
+use edgelet_core::{encrypt_setting, Encrypt, MakeRandom};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::SettingValue;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Encrypts a value for config.yaml with the master encryption key, so that
+/// `iotedge config encrypt` can keep secrets out of the file in plain text.
+pub struct EncryptSetting<C> {
+    crypto: C,
+}
+
+impl<C> EncryptSetting<C> {
+    pub fn new(crypto: C) -> Self {
+        EncryptSetting { crypto }
+    }
+}
+
+impl<C> Handler<Parameters> for EncryptSetting<C>
+where
+    C: Encrypt + MakeRandom + Clone + Send + Sync + 'static,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Encrypt Setting");
+
+        let crypto = self.crypto.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(move |b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let request = serde_json::from_slice::<SettingValue>(&b)
+                    .context(ErrorKind::MalformedRequestBody)?;
+                let encrypted =
+                    encrypt_setting(&crypto, request.value()).context(ErrorKind::EncryptSetting)?;
+
+                let body = serde_json::to_string(&SettingValue::new(encrypted))
+                    .context(ErrorKind::EncryptSetting)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::EncryptSetting)?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::{decrypt_setting, Decrypt, Error as CoreError, ENCRYPTED_SETTING_PREFIX};
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct TestCrypto;
+
+    impl Encrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn encrypt(
+            &self,
+            _client_id: &[u8],
+            plaintext: &[u8],
+            _initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            let mut rev = plaintext.to_vec();
+            rev.reverse(); // this "encrypt" function simply reverses the buffer's contents
+            Ok(rev)
+        }
+    }
+
+    impl Decrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn decrypt(
+            &self,
+            client_id: &[u8],
+            ciphertext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            self.encrypt(client_id, ciphertext, initialization_vector)
+        }
+    }
+
+    impl MakeRandom for TestCrypto {
+        fn get_random_bytes(&self, buffer: &mut [u8]) -> Result<(), CoreError> {
+            for b in buffer {
+                *b = 7;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encrypts_setting() {
+        // arrange
+        let handler = EncryptSetting::new(TestCrypto);
+        let request = Request::post("http://localhost/settings/encrypt")
+            .body(r#"{"value":"secret"}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let setting: SettingValue = serde_json::from_slice(&b).unwrap();
+                assert!(setting.value().starts_with(ENCRYPTED_SETTING_PREFIX));
+                assert_eq!(
+                    "secret",
+                    decrypt_setting(&TestCrypto, "key", setting.value()).unwrap()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_body() {
+        // arrange
+        let handler = EncryptSetting::new(TestCrypto);
+        let request = Request::post("http://localhost/settings/encrypt")
+            .body(r#"{"plaintext":"secret"}"#.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert!(error.message().starts_with("Request body is malformed"));
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+}
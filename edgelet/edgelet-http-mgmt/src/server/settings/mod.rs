@@ -0,0 +1,4 @@
+// Copyright (c) Microsoft. All rights reserved.
+mod encrypt;
+
+pub use self::encrypt::EncryptSetting;
@@ -8,7 +8,10 @@ use log::debug;
 use serde::Serialize;
 use serde_json;
 
-use edgelet_core::{Module, ModuleRuntime, RuntimeOperation};
+use edgelet_core::watchdog::{EdgeRuntimeState, WatchdogStatus};
+use edgelet_core::{
+    ClockSkew, Module, ModuleRuntime, RuntimeOperation, SelfTestReport, SelfTestStatus,
+};
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use management::models::*;
@@ -18,11 +21,45 @@ use crate::IntoResponse;
 
 pub struct GetSystemInfo<M> {
     runtime: M,
+    provisioning_payload: Option<serde_json::Value>,
+    warnings: Vec<String>,
+    watchdog_status: Option<WatchdogStatus>,
+    clock_skew: Option<ClockSkew>,
+    hsm_self_test: Option<SelfTestStatus>,
 }
 
 impl<M> GetSystemInfo<M> {
-    pub fn new(runtime: M) -> Self {
-        GetSystemInfo { runtime }
+    pub fn new(
+        runtime: M,
+        provisioning_payload: Option<serde_json::Value>,
+        warnings: Vec<String>,
+    ) -> Self {
+        GetSystemInfo {
+            runtime,
+            provisioning_payload,
+            warnings,
+            watchdog_status: None,
+            clock_skew: None,
+            hsm_self_test: None,
+        }
+    }
+
+    pub fn with_watchdog_status(mut self, watchdog_status: WatchdogStatus) -> Self {
+        self.watchdog_status = Some(watchdog_status);
+        self
+    }
+
+    /// Warns when the device clock is too far off from the time of the cloud.
+    pub fn with_clock_skew(mut self, clock_skew: ClockSkew) -> Self {
+        self.clock_skew = Some(clock_skew);
+        self
+    }
+
+    /// Reports the most recent self-test of the crypto backend, and warns
+    /// about the steps of it that failed.
+    pub fn with_hsm_self_test(mut self, hsm_self_test: SelfTestStatus) -> Self {
+        self.hsm_self_test = Some(hsm_self_test);
+        self
     }
 }
 
@@ -38,18 +75,48 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         debug!("Get System Information");
 
+        let provisioning_payload = self.provisioning_payload.clone();
+        let mut warnings = self.warnings.clone();
+        if let Some(state) = self.watchdog_status.as_ref().map(WatchdogStatus::state) {
+            if state != EdgeRuntimeState::Healthy {
+                warnings.push(format!("The edge runtime module is {}.", state));
+            }
+        }
+        if let Some(warning) = self.clock_skew.as_ref().and_then(ClockSkew::warning) {
+            warnings.push(warning);
+        }
+        let hsm_self_test = self.hsm_self_test.as_ref().and_then(SelfTestStatus::report);
+        if let Some(report) = &hsm_self_test {
+            for failure in report.failures() {
+                warnings.push(format!(
+                    "The HSM self-test {} failed: {}",
+                    failure.name(),
+                    failure.error().unwrap_or_default()
+                ));
+            }
+        }
         let response = self
             .runtime
             .system_info()
-            .then(|system_info| -> Result<_, Error> {
+            .then(move |system_info| -> Result<_, Error> {
                 let system_info = system_info
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
+                warnings.extend_from_slice(system_info.warnings());
 
-                let body = SystemInfo::new(
+                let mut body = SystemInfo::new(
                     system_info.os_type().to_string(),
                     system_info.architecture().to_string(),
                     system_info.version().to_string(),
                 );
+                if let Some(provisioning_payload) = provisioning_payload {
+                    body.set_provisioning_payload(provisioning_payload);
+                }
+                if !warnings.is_empty() {
+                    body.set_warnings(warnings);
+                }
+                if let Some(report) = hsm_self_test {
+                    body.set_hsm_self_test(hsm_self_test_model(&report));
+                }
 
                 let b = serde_json::to_string(&body)
                     .context(ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo))?;
@@ -68,6 +135,21 @@ where
     }
 }
 
+fn hsm_self_test_model(report: &SelfTestReport) -> HsmSelfTest {
+    let results = report
+        .results()
+        .iter()
+        .map(|result| {
+            let mut model = HsmSelfTestResult::new(result.name().to_string());
+            if let Some(error) = result.error() {
+                model.set_error(error.to_string());
+            }
+            model
+        })
+        .collect();
+    HsmSelfTest::new(report.timestamp().to_rfc3339(), results)
+}
+
 #[cfg(test)]
 mod tests {
     use edgelet_core::{self, MakeModuleRuntime, ModuleRuntimeState};
@@ -95,7 +177,7 @@ mod tests {
         .wait()
         .unwrap()
         .with_module(Ok(module));
-        let handler = GetSystemInfo::new(runtime);
+        let handler = GetSystemInfo::new(runtime, None, vec![]);
         let request = Request::get("http://localhost/info")
             .body(Body::default())
             .unwrap();
@@ -118,6 +200,8 @@ mod tests {
                     edgelet_core::version_with_source_version(),
                     system_info.version(),
                 );
+                assert_eq!(None, system_info.provisioning_payload());
+                assert_eq!(None, system_info.warnings());
 
                 Ok(())
             })
@@ -125,6 +209,204 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn system_info_includes_provisioning_payload() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler =
+            GetSystemInfo::new(runtime, Some(serde_json::json!({ "tier": "gold" })), vec![]);
+        let request = Request::get("http://localhost/info")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let system_info: SystemInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            Some(&serde_json::json!({ "tier": "gold" })),
+            system_info.provisioning_payload()
+        );
+    }
+
+    #[test]
+    fn system_info_includes_warnings() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let handler = GetSystemInfo::new(
+            runtime,
+            None,
+            vec!["Quickstart certificates are in use".to_string()],
+        );
+        let request = Request::get("http://localhost/info")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let system_info: SystemInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            Some(&["Quickstart certificates are in use".to_string()][..]),
+            system_info.warnings()
+        );
+    }
+
+    #[test]
+    fn system_info_includes_watchdog_status() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let watchdog_status = WatchdogStatus::new();
+        watchdog_status.set_state(EdgeRuntimeState::Broken);
+        let handler =
+            GetSystemInfo::new(runtime, None, vec![]).with_watchdog_status(watchdog_status);
+        let request = Request::get("http://localhost/info")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let system_info: SystemInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            Some(
+                &[format!(
+                    "The edge runtime module is {}.",
+                    EdgeRuntimeState::Broken
+                )][..]
+            ),
+            system_info.warnings()
+        );
+    }
+
+    #[test]
+    fn system_info_includes_clock_skew() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let clock_skew = ClockSkew::new();
+        clock_skew.record(chrono::Utc::now() + chrono::Duration::hours(1));
+        let handler = GetSystemInfo::new(runtime, None, vec![]).with_clock_skew(clock_skew);
+        let request = Request::get("http://localhost/info")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let system_info: SystemInfo = serde_json::from_slice(&body).unwrap();
+        let warnings = system_info.warnings().unwrap();
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].starts_with("The device clock is"));
+    }
+
+    #[test]
+    fn system_info_includes_hsm_self_test() {
+        // arrange
+        let config = TestConfig::new("microsoft/test-image".to_string());
+        let module: TestModule<Error, _> = TestModule::new(
+            "test-module".to_string(),
+            config,
+            Ok(ModuleRuntimeState::default()),
+        );
+        let runtime = TestRuntime::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap()
+        .with_module(Ok(module));
+        let hsm_self_test = SelfTestStatus::new();
+        hsm_self_test.record(SelfTestReport::new(vec![
+            edgelet_core::SelfTestResult::passed("random"),
+            edgelet_core::SelfTestResult::failed("sign-verify", "HSM failure".to_string()),
+        ]));
+        let handler = GetSystemInfo::new(runtime, None, vec![]).with_hsm_self_test(hsm_self_test);
+        let request = Request::get("http://localhost/info")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let system_info: SystemInfo = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            Some(&["The HSM self-test sign-verify failed: HSM failure".to_string()][..]),
+            system_info.warnings()
+        );
+        let results = system_info.hsm_self_test().unwrap().results();
+        assert_eq!(2, results.len());
+        assert_eq!(None, results[0].error());
+        assert_eq!(Some(&"HSM failure".to_string()), results[1].error());
+    }
+
     #[test]
     fn system_info_failed() {
         // arrange
@@ -136,7 +418,7 @@ mod tests {
         .wait()
         .unwrap()
         .with_module(Err(Error::General));
-        let handler = GetSystemInfo::new(runtime);
+        let handler = GetSystemInfo::new(runtime, None, vec![]);
         let request = Request::get("http://localhost/modules")
             .body(Body::default())
             .unwrap();
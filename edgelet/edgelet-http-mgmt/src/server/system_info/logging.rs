@@ -0,0 +1,336 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::{debug, Level};
+use serde_json;
+use url::form_urlencoded;
+
+use edgelet_core::{LogBuffer, LogController, LogFormat, LogSettings, LogTail};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::LogSettings as LogSettingsResponse;
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetLogSettings {
+    log_controller: LogController,
+}
+
+impl GetLogSettings {
+    pub fn new(log_controller: LogController) -> Self {
+        GetLogSettings { log_controller }
+    }
+}
+
+impl Handler<Parameters> for GetLogSettings {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get Log Settings");
+
+        let response = write_response(&self.log_controller.settings())
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+pub struct SetLogSettings {
+    log_controller: LogController,
+}
+
+impl SetLogSettings {
+    pub fn new(log_controller: LogController) -> Self {
+        SetLogSettings { log_controller }
+    }
+}
+
+impl Handler<Parameters> for SetLogSettings {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let log_controller = self.log_controller.clone();
+
+        let response = req
+            .into_body()
+            .concat2()
+            .then(|b| -> Result<_, Error> {
+                let b = b.context(ErrorKind::MalformedRequestBody)?;
+                let request = serde_json::from_slice::<LogSettingsResponse>(&b)
+                    .context(ErrorKind::MalformedRequestBody)?;
+                let format = request
+                    .format()
+                    .parse::<LogFormat>()
+                    .context(ErrorKind::MalformedRequestBody)?;
+                Ok(LogSettings::new(format, request.level().clone()))
+            })
+            .and_then(move |settings| {
+                log_controller.set_settings(&settings);
+                write_response(&log_controller.settings())
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+/// Returns the most recent log records of the daemon as text, one per line.
+pub struct GetDaemonLogs {
+    log_buffer: LogBuffer,
+}
+
+impl GetDaemonLogs {
+    pub fn new(log_buffer: LogBuffer) -> Self {
+        GetDaemonLogs { log_buffer }
+    }
+}
+
+impl Handler<Parameters> for GetDaemonLogs {
+    fn handle(
+        &self,
+        req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get Daemon Logs");
+
+        let log_buffer = self.log_buffer.clone();
+
+        let response = req
+            .uri()
+            .query()
+            .map_or_else(|| Ok((Level::Trace, LogTail::All)), parse_log_filter)
+            .and_then(|(level, tail)| {
+                let mut body = String::new();
+                for record in log_buffer.records(level, &tail) {
+                    body.push_str(&record.to_string());
+                    body.push('\n');
+                }
+
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::DaemonLogs)?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+fn parse_log_filter(query: &str) -> Result<(Level, LogTail), Error> {
+    let parse: Vec<_> = form_urlencoded::parse(query.as_bytes()).collect();
+    let level = parse
+        .iter()
+        .find(|&(ref key, _)| key == "level")
+        .map_or_else(|| Ok(Level::Trace), |(_, val)| val.parse::<Level>())
+        .map_err(|_| ErrorKind::MalformedRequestParameter("level"))?;
+    let tail = parse
+        .iter()
+        .find(|&(ref key, _)| key == "tail")
+        .map_or_else(|| Ok(LogTail::default()), |(_, val)| val.parse::<LogTail>())
+        .context(ErrorKind::MalformedRequestParameter("tail"))?;
+    Ok((level, tail))
+}
+
+fn write_response(settings: &LogSettings) -> Result<Response<Body>, Error> {
+    let body =
+        LogSettingsResponse::new(settings.format().to_string(), settings.level().to_string());
+    let body = serde_json::to_string(&body).context(ErrorKind::LogSettings)?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, body.len().to_string().as_str())
+        .body(body.into())
+        .context(ErrorKind::LogSettings)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{TimeZone, Utc};
+    use edgelet_core::LogRecord;
+    use management::models::ErrorResponse;
+
+    use super::*;
+
+    fn test_controller() -> LogController {
+        let state = Arc::new(Mutex::new(LogSettings::new(
+            LogFormat::Text,
+            "info".to_string(),
+        )));
+        let current = state.clone();
+        LogController::new(
+            move || current.lock().unwrap().clone(),
+            move |settings| *state.lock().unwrap() = settings.clone(),
+        )
+    }
+
+    #[test]
+    fn get_returns_current_settings() {
+        // arrange
+        let handler = GetLogSettings::new(test_controller());
+        let request = Request::get("http://localhost/systeminfo/logging")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let settings: LogSettingsResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!("text", settings.format());
+                assert_eq!("info", settings.level());
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn set_changes_settings() {
+        // arrange
+        let controller = test_controller();
+        let handler = SetLogSettings::new(controller.clone());
+        let body = r#"{"format":"json","level":"info,edgelet_docker=debug"}"#;
+        let request = Request::put("http://localhost/systeminfo/logging")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            LogSettings::new(LogFormat::Json, "info,edgelet_docker=debug".to_string()),
+            controller.settings()
+        );
+    }
+
+    #[test]
+    fn set_rejects_unknown_format() {
+        // arrange
+        let controller = test_controller();
+        let handler = SetLogSettings::new(controller.clone());
+        let body = r#"{"format":"xml","level":"debug"}"#;
+        let request = Request::put("http://localhost/systeminfo/logging")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "Request body is malformed\n\tcaused by: Invalid log format \"xml\"",
+                    error.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+        assert_eq!(LogFormat::Text, controller.settings().format());
+    }
+
+    fn test_log_buffer() -> LogBuffer {
+        let log_buffer = LogBuffer::new(10);
+        for (level, message) in &[
+            (Level::Info, "Starting daemon"),
+            (Level::Debug, "Reading config"),
+            (Level::Warn, "Clock is off"),
+            (Level::Error, "Provisioning failed"),
+        ] {
+            log_buffer.push(LogRecord::new(
+                Utc.ymd(2019, 11, 5).and_hms(12, 0, 0),
+                *level,
+                "iotedged".to_string(),
+                (*message).to_string(),
+            ));
+        }
+        log_buffer
+    }
+
+    #[test]
+    fn daemon_logs_returns_all_records() {
+        // arrange
+        let handler = GetDaemonLogs::new(test_log_buffer());
+        let request = Request::get("http://localhost/logs")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(4, body.lines().count());
+        assert_eq!(
+            "2019-11-05T12:00:00.000Z [INFO] - [iotedged] Starting daemon",
+            body.lines().next().unwrap()
+        );
+    }
+
+    #[test]
+    fn daemon_logs_filters_by_level_and_tail() {
+        // arrange
+        let handler = GetDaemonLogs::new(test_log_buffer());
+        let request = Request::get("http://localhost/logs?level=warn&tail=1")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        assert_eq!(
+            "2019-11-05T12:00:00.000Z [ERROR] - [iotedged] Provisioning failed\n",
+            std::str::from_utf8(&body).unwrap()
+        );
+    }
+
+    #[test]
+    fn daemon_logs_rejects_unknown_level() {
+        // arrange
+        let handler = GetDaemonLogs::new(test_log_buffer());
+        let request = Request::get("http://localhost/logs?level=loud")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
@@ -1,6 +1,10 @@
 // Copyright (c) Microsoft. All rights reserved.
 mod get;
+mod logging;
 mod resources;
+mod startup;
 
 pub use self::get::GetSystemInfo;
+pub use self::logging::{GetDaemonLogs, GetLogSettings, SetLogSettings};
 pub use self::resources::GetSystemResources;
+pub use self::startup::GetStartupState;
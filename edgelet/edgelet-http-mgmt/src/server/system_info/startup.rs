@@ -0,0 +1,116 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::debug;
+use serde_json;
+
+use edgelet_core::StartupState;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use management::models::{
+    StartupFailure as StartupFailureResponse, StartupState as StartupStateResponse,
+};
+
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+pub struct GetStartupState {
+    startup_state: StartupState,
+}
+
+impl GetStartupState {
+    pub fn new(startup_state: StartupState) -> Self {
+        GetStartupState { startup_state }
+    }
+}
+
+impl Handler<Parameters> for GetStartupState {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        debug!("Get Startup State");
+
+        let response = write_response(&self.startup_state)
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+fn write_response(startup_state: &StartupState) -> Result<Response<Body>, Error> {
+    let mut body = StartupStateResponse::new(startup_state.stage().to_string());
+    if let Some(failure) = startup_state.last_failure() {
+        body.set_last_failure(StartupFailureResponse::new(
+            failure.stage().to_string(),
+            failure.message().to_string(),
+            failure.time().to_rfc3339(),
+        ));
+    }
+    let body = serde_json::to_string(&body).context(ErrorKind::StartupState)?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "application/json")
+        .header(CONTENT_LENGTH, body.len().to_string().as_str())
+        .body(body.into())
+        .context(ErrorKind::StartupState)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::StartupStage;
+    use futures::Stream;
+
+    use super::*;
+
+    #[test]
+    fn returns_stage_and_last_failure() {
+        // arrange
+        let startup_state = StartupState::default();
+        startup_state.enter(StartupStage::Provisioning);
+        startup_state.fail("Could not provision the device".to_string());
+        startup_state.enter(StartupStage::Serving);
+        let handler = GetStartupState::new(startup_state);
+        let request = Request::get("http://localhost/systeminfo/startup")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let state: StartupStateResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("serving", state.stage());
+        let failure = state.last_failure().unwrap();
+        assert_eq!("provisioning", failure.stage());
+        assert_eq!("Could not provision the device", failure.message());
+    }
+
+    #[test]
+    fn last_failure_is_left_out_when_there_is_none() {
+        // arrange
+        let handler = GetStartupState::new(StartupState::default());
+        let request = Request::get("http://localhost/systeminfo/startup")
+            .body(Body::default())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        // assert
+        let body = response.into_body().concat2().wait().unwrap();
+        assert_eq!(
+            r#"{"stage":"loading_config"}"#,
+            std::str::from_utf8(&body).unwrap()
+        );
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+use edgelet_core::CertificateRevocationList;
+
+/// Caches the trust bundle and the server certificates recently issued to
+/// modules, so that a module restarting, or many modules starting at once,
+/// don't each have the HSM issue a new certificate.
+///
+/// The cache only holds certificates issued by the current workload CA, so it
+/// has to be invalidated whenever the CA certificates are rotated.
+#[derive(Clone, Debug)]
+pub struct CertificateCache {
+    state: Arc<Mutex<State>>,
+    ttl: Duration,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    trust_bundle: Option<String>,
+    server_certs: HashMap<ServerCertKey, CachedCert>,
+}
+
+/// The request parameters that determine the contents of a server certificate.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct ServerCertKey {
+    alias: String,
+    common_name: String,
+}
+
+impl ServerCertKey {
+    pub(crate) fn new(alias: String, common_name: String) -> Self {
+        ServerCertKey { alias, common_name }
+    }
+}
+
+#[derive(Debug)]
+struct CachedCert {
+    body: String,
+    issued_at: DateTime<Utc>,
+    expiration: DateTime<Utc>,
+}
+
+impl CertificateCache {
+    /// Creates a cache that reuses a server certificate for `ttl` after it
+    /// was issued.
+    pub fn new(ttl: Duration) -> Self {
+        CertificateCache {
+            state: Arc::new(Mutex::new(State::default())),
+            ttl,
+        }
+    }
+
+    /// Drops everything in the cache, e.g. after the CA certificates were
+    /// rotated.
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().expect("Failed to acquire the cache lock");
+        state.trust_bundle = None;
+        state.server_certs.clear();
+    }
+
+    pub(crate) fn trust_bundle(&self) -> Option<String> {
+        self.state
+            .lock()
+            .expect("Failed to acquire the cache lock")
+            .trust_bundle
+            .clone()
+    }
+
+    pub(crate) fn set_trust_bundle(&self, trust_bundle: String) {
+        self.state
+            .lock()
+            .expect("Failed to acquire the cache lock")
+            .trust_bundle = Some(trust_bundle);
+    }
+
+    /// Returns the response body of a server certificate issued for `key`,
+    /// if it was issued recently, expires no later than `expiration` and
+    /// hasn't been revoked since.
+    pub(crate) fn server_cert(
+        &self,
+        key: &ServerCertKey,
+        module_id: &str,
+        expiration: DateTime<Utc>,
+        crl: &CertificateRevocationList,
+    ) -> Option<String> {
+        let now = Utc::now();
+
+        let state = self.state.lock().expect("Failed to acquire the cache lock");
+        let cert = state.server_certs.get(key)?;
+        if now - cert.issued_at >= self.ttl
+            || cert.expiration <= now
+            || cert.expiration > expiration
+        {
+            return None;
+        }
+
+        let revoked = crl.revoked().iter().any(|revoked| {
+            revoked.module_id() == module_id
+                && revoked
+                    .revoked_at()
+                    .map_or(false, |revoked_at| revoked_at >= cert.issued_at)
+        });
+        if revoked {
+            return None;
+        }
+
+        Some(cert.body.clone())
+    }
+
+    pub(crate) fn insert_server_cert(
+        &self,
+        key: ServerCertKey,
+        body: String,
+        expiration: DateTime<Utc>,
+    ) {
+        let now = Utc::now();
+        let ttl = self.ttl;
+
+        let mut state = self.state.lock().expect("Failed to acquire the cache lock");
+        state
+            .server_certs
+            .retain(|_, cert| now - cert.issued_at < ttl && cert.expiration > now);
+        state.server_certs.insert(
+            key,
+            CachedCert {
+                body,
+                issued_at: now,
+                expiration,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PEM: &[u8] = b"-----BEGIN CERTIFICATE-----\ndGVzdA==\n-----END CERTIFICATE-----\n";
+
+    fn key() -> ServerCertKey {
+        ServerCertKey::new("beeblebroxIserver".to_string(), "marvin".to_string())
+    }
+
+    #[test]
+    fn server_cert_hit() {
+        let cache = CertificateCache::new(Duration::minutes(5));
+        let crl = CertificateRevocationList::new();
+        let now = Utc::now();
+
+        cache.insert_server_cert(key(), "cert".to_string(), now + Duration::hours(1));
+        assert_eq!(
+            Some("cert".to_string()),
+            cache.server_cert(&key(), "beeblebrox", now + Duration::hours(2), &crl)
+        );
+    }
+
+    #[test]
+    fn server_cert_other_params_miss() {
+        let cache = CertificateCache::new(Duration::minutes(5));
+        let crl = CertificateRevocationList::new();
+        let now = Utc::now();
+
+        cache.insert_server_cert(key(), "cert".to_string(), now + Duration::hours(1));
+        let other = ServerCertKey::new("beeblebroxIserver".to_string(), "trillian".to_string());
+        assert_eq!(
+            None,
+            cache.server_cert(&other, "beeblebrox", now + Duration::hours(2), &crl)
+        );
+    }
+
+    #[test]
+    fn server_cert_outliving_request_miss() {
+        let cache = CertificateCache::new(Duration::minutes(5));
+        let crl = CertificateRevocationList::new();
+        let now = Utc::now();
+
+        cache.insert_server_cert(key(), "cert".to_string(), now + Duration::hours(2));
+        assert_eq!(
+            None,
+            cache.server_cert(&key(), "beeblebrox", now + Duration::hours(1), &crl)
+        );
+    }
+
+    #[test]
+    fn server_cert_past_ttl_miss() {
+        let cache = CertificateCache::new(Duration::zero());
+        let crl = CertificateRevocationList::new();
+        let now = Utc::now();
+
+        cache.insert_server_cert(key(), "cert".to_string(), now + Duration::hours(1));
+        assert_eq!(
+            None,
+            cache.server_cert(&key(), "beeblebrox", now + Duration::hours(2), &crl)
+        );
+    }
+
+    #[test]
+    fn server_cert_revoked_miss() {
+        let cache = CertificateCache::new(Duration::minutes(5));
+        let crl = CertificateRevocationList::new();
+        let now = Utc::now();
+
+        cache.insert_server_cert(key(), "cert".to_string(), now + Duration::hours(1));
+        crl.record("beeblebrox", TEST_PEM, now + Duration::hours(1))
+            .unwrap();
+        crl.revoke_module("beeblebrox").unwrap();
+        assert_eq!(
+            None,
+            cache.server_cert(&key(), "beeblebrox", now + Duration::hours(2), &crl)
+        );
+    }
+
+    #[test]
+    fn invalidate_drops_everything() {
+        let cache = CertificateCache::new(Duration::minutes(5));
+        let crl = CertificateRevocationList::new();
+        let now = Utc::now();
+
+        cache.set_trust_bundle("bundle".to_string());
+        cache.insert_server_cert(key(), "cert".to_string(), now + Duration::hours(1));
+        cache.invalidate();
+
+        assert_eq!(None, cache.trust_bundle());
+        assert_eq!(
+            None,
+            cache.server_cert(&key(), "beeblebrox", now + Duration::hours(2), &crl)
+        );
+    }
+}
@@ -20,6 +20,12 @@ pub struct Error {
 
 #[derive(Clone, Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Could not quote the state of the device")]
+    Attestation,
+
+    #[fail(display = "The device has no TPM to quote its state with")]
+    AttestationNotAvailable,
+
     #[fail(display = "Certificate has an invalid private key")]
     BadPrivateKey,
 
@@ -29,12 +35,30 @@ pub enum ErrorKind {
     #[fail(display = "{}", _0)]
     EncryptionOperation(EncryptionOperation),
 
+    #[fail(display = "The label of a derived key must not be empty")]
+    EmptyDerivedKeyLabel,
+
+    #[fail(display = "Invalid length {} for a derived key", _0)]
+    InvalidDerivedKeyLength(u32),
+
+    #[fail(display = "A quote takes a nonce of 1 to 64 bytes, not {}", _0)]
+    InvalidQuoteNonce(usize),
+
     #[fail(display = "Request body is malformed")]
     MalformedRequestBody,
 
     #[fail(display = "The request parameter `{}` is malformed", _0)]
     MalformedRequestParameter(&'static str),
 
+    #[fail(display = "Could not mint a management API token for module {:?}", _0)]
+    ManagementToken(String),
+
+    #[fail(
+        display = "Module {:?} is not allowed to get management API tokens",
+        _0
+    )]
+    ManagementTokenNotAllowed(String),
+
     #[fail(display = "The request is missing required parameter `{}`", _0)]
     MissingRequiredParameter(&'static str),
 
@@ -92,7 +116,12 @@ impl IntoResponse for Error {
 
         let status_code = match *self.kind() {
             ErrorKind::ModuleNotFound(_) => StatusCode::NOT_FOUND,
-            ErrorKind::MalformedRequestBody
+            ErrorKind::ManagementTokenNotAllowed(_) => StatusCode::FORBIDDEN,
+            ErrorKind::AttestationNotAvailable => StatusCode::NOT_IMPLEMENTED,
+            ErrorKind::EmptyDerivedKeyLabel
+            | ErrorKind::InvalidDerivedKeyLength(_)
+            | ErrorKind::InvalidQuoteNonce(_)
+            | ErrorKind::MalformedRequestBody
             | ErrorKind::MalformedRequestParameter(_)
             | ErrorKind::MissingRequiredParameter(_) => StatusCode::BAD_REQUEST,
             _ => {
@@ -127,6 +156,7 @@ impl IntoResponse for Error {
 #[derive(Clone, Copy, Debug)]
 pub enum CertOperation {
     CreateIdentityCert,
+    GetRevocationList,
     GetServerCert,
 }
 
@@ -134,6 +164,9 @@ impl fmt::Display for CertOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CertOperation::CreateIdentityCert => write!(f, "Could not create identity cert"),
+            CertOperation::GetRevocationList => {
+                write!(f, "Could not get certificate revocation list")
+            }
             CertOperation::GetServerCert => write!(f, "Could not get server cert"),
         }
     }
@@ -142,6 +175,7 @@ impl fmt::Display for CertOperation {
 #[derive(Clone, Copy, Debug)]
 pub enum EncryptionOperation {
     Decrypt,
+    DeriveKey,
     Encrypt,
     GetTrustBundle,
     Sign,
@@ -151,6 +185,7 @@ impl fmt::Display for EncryptionOperation {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             EncryptionOperation::Decrypt => write!(f, "Could not decrypt"),
+            EncryptionOperation::DeriveKey => write!(f, "Could not derive key"),
             EncryptionOperation::Encrypt => write!(f, "Could not encrypt"),
             EncryptionOperation::GetTrustBundle => write!(f, "Could not get trust bundle"),
             EncryptionOperation::Sign => write!(f, "Could not sign"),
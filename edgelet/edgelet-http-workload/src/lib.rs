@@ -12,9 +12,11 @@
 
 use hyper::{Body, Response};
 
+mod cache;
 mod error;
 mod server;
 
+pub use crate::cache::CertificateCache;
 pub use crate::server::WorkloadService;
 
 pub trait IntoResponse {
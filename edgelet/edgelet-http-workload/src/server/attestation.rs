@@ -0,0 +1,200 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use workload::models::{QuoteRequest, QuoteResponse};
+
+use edgelet_core::{Attest, MAX_ATTESTATION_NONCE_LENGTH};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::module_name;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Quotes the state of the device with its TPM over the nonce of a verifier,
+/// so that modules can attest the device remotely without access to the TPM
+/// themselves. Without `attestation`, the device has no TPM to quote with.
+pub struct QuoteHandler<A> {
+    attestation: Option<A>,
+}
+
+impl<A> QuoteHandler<A> {
+    pub fn new(attestation: Option<A>) -> Self {
+        QuoteHandler { attestation }
+    }
+}
+
+fn quote<A: Attest>(attestation: &A, request: &QuoteRequest) -> Result<QuoteResponse, Error> {
+    let nonce = base64::decode(request.nonce()).context(ErrorKind::MalformedRequestBody)?;
+    if nonce.is_empty() || nonce.len() > MAX_ATTESTATION_NONCE_LENGTH {
+        return Err(Error::from(ErrorKind::InvalidQuoteNonce(nonce.len())));
+    }
+
+    let quote = attestation.quote(&nonce).context(ErrorKind::Attestation)?;
+    let response = QuoteResponse::new(
+        base64::encode(quote.quoted()),
+        base64::encode(quote.signature()),
+        base64::encode(quote.attestation_key()),
+        base64::encode(quote.endorsement_key()),
+    );
+    Ok(match quote.endorsement_key_certificate() {
+        Some(certificate) => response.with_endorsement_key_certificate(base64::encode(certificate)),
+        None => response,
+    })
+}
+
+impl<A> Handler<Parameters> for QuoteHandler<A>
+where
+    A: 'static + Attest + Clone + Send + Sync,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let attestation = self.attestation.clone();
+        let response = module_name(&params)
+            .and_then(|name| {
+                let attestation =
+                    attestation.ok_or_else(|| Error::from(ErrorKind::AttestationNotAvailable))?;
+                Ok((name.to_string(), attestation))
+            })
+            .map(|(name, attestation)| {
+                req.into_body().concat2().then(|body| {
+                    let body = body.context(ErrorKind::Attestation)?;
+                    Ok((name, attestation, body))
+                })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(name, attestation, body)| -> Result<_, Error> {
+                let request: QuoteRequest =
+                    serde_json::from_slice(&body).context(ErrorKind::MalformedRequestBody)?;
+                let response = quote(&attestation, &request)?;
+                info!("Quoted the state of the device for module {}", name);
+
+                let body = serde_json::to_string(&response).context(ErrorKind::Attestation)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::Attestation)?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_test_utils::crypto::TestHsm;
+    use workload::models::ErrorResponse;
+
+    use super::*;
+
+    fn request_quote(attestation: Option<TestHsm>, nonce: &str) -> Response<Body> {
+        let handler = QuoteHandler::new(attestation);
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), "verifier".to_string())]);
+        let request = Request::post("http://localhost/modules/verifier/attestation/quote")
+            .body(
+                serde_json::to_string(&QuoteRequest::new(nonce.to_string()))
+                    .unwrap()
+                    .into(),
+            )
+            .unwrap();
+
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn error_message(response: Response<Body>) -> String {
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        error.message().clone()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let nonce = base64::encode(b"nonce of the verifier");
+
+        // act
+        let response = request_quote(Some(TestHsm::default()), &nonce);
+
+        // assert
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let response: QuoteResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(&nonce, response.quoted());
+        assert_eq!(&base64::encode(b"signature"), response.signature());
+        assert_eq!(
+            &base64::encode(b"attestation key"),
+            response.attestation_key()
+        );
+        assert_eq!(
+            &base64::encode(b"endorsement key"),
+            response.endorsement_key()
+        );
+        assert_eq!(None, response.endorsement_key_certificate());
+    }
+
+    #[test]
+    fn nonce_must_fit_quote() {
+        // arrange
+        let nonce = base64::encode(&[0; 65][..]);
+
+        // act
+        let response = request_quote(Some(TestHsm::default()), &nonce);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            "A quote takes a nonce of 1 to 64 bytes, not 65",
+            error_message(response)
+        );
+    }
+
+    #[test]
+    fn nonce_must_be_base64() {
+        // act
+        let response = request_quote(Some(TestHsm::default()), "not base64!");
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn device_without_tpm_cannot_quote() {
+        // arrange
+        let nonce = base64::encode(b"nonce");
+
+        // act
+        let response = request_quote(None, &nonce);
+
+        // assert
+        assert_eq!(StatusCode::NOT_IMPLEMENTED, response.status());
+        assert_eq!(
+            "The device has no TPM to quote its state with",
+            error_message(response)
+        );
+    }
+
+    #[test]
+    fn failed_quote_is_server_error() {
+        // arrange
+        let nonce = base64::encode(b"nonce");
+
+        // act
+        let response = request_quote(Some(TestHsm::default().with_fail_call(true)), &nonce);
+
+        // assert
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+}
@@ -7,7 +7,8 @@ use hyper::{Body, Request, Response};
 use serde_json;
 
 use edgelet_core::{
-    Certificate, CertificateProperties, CertificateType, CreateCertificate, WorkloadConfig,
+    Certificate, CertificateProperties, CertificateRevocationList, CertificateType,
+    CreateCertificate, WorkloadConfig,
 };
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
@@ -15,16 +16,27 @@ use edgelet_utils::{ensure_not_empty_with_context, prepare_cert_uri_module};
 use workload::models::IdentityCertificateRequest;
 
 use crate::error::{CertOperation, Error, ErrorKind};
+use crate::server::module_name;
 use crate::IntoResponse;
 
 pub struct IdentityCertHandler<T: CreateCertificate, W: WorkloadConfig> {
     hsm: T,
     config: W,
+    crl: CertificateRevocationList,
 }
 
 impl<T: CreateCertificate, W: WorkloadConfig> IdentityCertHandler<T, W> {
     pub fn new(hsm: T, config: W) -> Self {
-        IdentityCertHandler { hsm, config }
+        IdentityCertHandler {
+            hsm,
+            config,
+            crl: CertificateRevocationList::new(),
+        }
+    }
+
+    pub fn with_revocation_list(mut self, crl: CertificateRevocationList) -> Self {
+        self.crl = crl;
+        self
     }
 }
 
@@ -41,11 +53,10 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let hsm = self.hsm.clone();
         let cfg = self.config.clone();
+        let crl = self.crl.clone();
         let max_duration = cfg.get_cert_max_duration(CertificateType::Client);
 
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let response = module_name(&params)
             .map(|module_id| {
                 let cn = module_id.to_string();
                 let alias = format!("{}identity", module_id);
@@ -80,6 +91,7 @@ where
                     ErrorKind::MalformedRequestParameter("name")
                 })?;
 
+                let module_id = cn.clone();
                 let sans = vec![module_uri];
                 let props = CertificateProperties::new(
                     expiration,
@@ -90,6 +102,8 @@ where
                 .with_san_entries(sans);
                 refresh_cert(
                     &hsm,
+                    &crl,
+                    &module_id,
                     alias,
                     &props,
                     ErrorKind::CertOperation(CertOperation::CreateIdentityCert),
@@ -120,6 +134,8 @@ mod tests {
     use hyper::StatusCode;
 
     const MAX_DURATION_SEC: u64 = 7200;
+    const TEST_CERT_PEM: &[u8] =
+        b"-----BEGIN CERTIFICATE-----\ndGVzdA==\n-----END CERTIFICATE-----\n";
 
     #[derive(Clone, Default)]
     struct TestHsm {
@@ -244,6 +260,7 @@ mod tests {
                 let expected_uri = test_module_uri("beeblebrox");
                 assert!(props.san_entries().unwrap().contains(&expected_uri));
                 Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
                     .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string()))))
             }),
             TestWorkloadData::default(),
@@ -282,7 +299,9 @@ mod tests {
                 assert!(MAX_DURATION_SEC >= *props.validity_in_secs());
                 let expected_uri = test_module_uri("beeblebrox");
                 assert!(props.san_entries().unwrap().contains(&expected_uri));
-                Ok(TestCert::default().with_private_key(PrivateKey::Ref("Betelgeuse".to_string())))
+                Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
+                    .with_private_key(PrivateKey::Ref("Betelgeuse".to_string())))
             }),
             TestWorkloadData::default(),
         );
@@ -321,6 +340,7 @@ mod tests {
                 let expected_uri = test_module_uri("beeblebrox");
                 assert!(props.san_entries().unwrap().contains(&expected_uri));
                 Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
                     .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string()))))
             }),
             TestWorkloadData::default(),
@@ -359,6 +379,7 @@ mod tests {
                 let expected_uri = test_module_uri("beeblebrox");
                 assert!(props.san_entries().unwrap().contains(&expected_uri));
                 Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
                     .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string()))))
             }),
             TestWorkloadData::default(),
@@ -463,6 +484,7 @@ mod tests {
                 let expected_uri = test_module_uri("beeblebrox");
                 assert!(props.san_entries().unwrap().contains(&expected_uri));
                 Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
                     .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string()))))
             }),
             TestWorkloadData::default(),
@@ -8,7 +8,10 @@ use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Response, StatusCode};
 use serde_json;
 
-use edgelet_core::{Certificate, CertificateProperties, CreateCertificate, KeyBytes, PrivateKey};
+use edgelet_core::{
+    Certificate, CertificateProperties, CertificateRevocationList, CreateCertificate, KeyBytes,
+    PrivateKey,
+};
 use edgelet_utils::ensure_not_empty_with_context;
 use workload::models::{CertificateResponse, PrivateKey as PrivateKeyResponse};
 
@@ -65,10 +68,26 @@ fn compute_validity(expiration: &str, max_duration_sec: i64, context: ErrorKind)
 
 fn refresh_cert<T: CreateCertificate>(
     hsm: &T,
+    crl: &CertificateRevocationList,
+    module_id: &str,
     alias: String,
     props: &CertificateProperties,
     context: ErrorKind,
 ) -> Result<Response<Body>> {
+    let (body, _) = issue_cert(hsm, crl, module_id, alias, props, context.clone())?;
+    created_response(body, context)
+}
+
+/// Issues a new certificate in place of the one under `alias`, and returns
+/// the response body along with the expiration of the certificate.
+fn issue_cert<T: CreateCertificate>(
+    hsm: &T,
+    crl: &CertificateRevocationList,
+    module_id: &str,
+    alias: String,
+    props: &CertificateProperties,
+    context: ErrorKind,
+) -> Result<(String, DateTime<Utc>)> {
     if let Err(err) = hsm.destroy_certificate(alias) {
         return Err(Error::from(err.context(context)));
     };
@@ -78,13 +97,29 @@ fn refresh_cert<T: CreateCertificate>(
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
-    let cert = cert_to_response(&cert, context.clone())?;
+    let cert_response = cert_to_response(&cert, context.clone())?;
 
-    let body = match serde_json::to_string(&cert) {
+    // Track the certificate so that it can be revoked later on
+    let expiration = match cert
+        .pem()
+        .and_then(|pem| cert.get_valid_to().map(|valid_to| (pem, valid_to)))
+        .and_then(|(pem, valid_to)| {
+            crl.record(module_id, pem.as_ref(), valid_to)?;
+            Ok(valid_to)
+        }) {
+        Ok(expiration) => expiration,
+        Err(err) => return Err(Error::from(err.context(context))),
+    };
+
+    let body = match serde_json::to_string(&cert_response) {
         Ok(body) => body,
         Err(err) => return Err(Error::from(err.context(context))),
     };
 
+    Ok((body, expiration))
+}
+
+fn created_response(body: String, context: ErrorKind) -> Result<Response<Body>> {
     let response = Response::builder()
         .status(StatusCode::CREATED)
         .header(CONTENT_TYPE, "application/json")
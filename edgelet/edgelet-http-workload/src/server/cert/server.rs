@@ -1,13 +1,16 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use super::{compute_validity, refresh_cert};
+use super::{compute_validity, created_response, issue_cert};
+use chrono::{Duration, Utc};
 use failure::ResultExt;
 use futures::{future, Future, IntoFuture, Stream};
 use hyper::{Body, Request, Response};
+use log::debug;
 use serde_json;
 
 use edgelet_core::{
-    Certificate, CertificateProperties, CertificateType, CreateCertificate, WorkloadConfig,
+    Certificate, CertificateProperties, CertificateRevocationList, CertificateType,
+    CreateCertificate, WorkloadConfig,
 };
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
@@ -16,17 +19,36 @@ use edgelet_utils::{
 };
 use workload::models::ServerCertificateRequest;
 
+use crate::cache::{CertificateCache, ServerCertKey};
 use crate::error::{CertOperation, Error, ErrorKind};
+use crate::server::module_name;
 use crate::IntoResponse;
 
 pub struct ServerCertHandler<T: CreateCertificate, W: WorkloadConfig> {
     hsm: T,
     config: W,
+    crl: CertificateRevocationList,
+    cache: Option<CertificateCache>,
 }
 
 impl<T: CreateCertificate, W: WorkloadConfig> ServerCertHandler<T, W> {
     pub fn new(hsm: T, config: W) -> Self {
-        ServerCertHandler { hsm, config }
+        ServerCertHandler {
+            hsm,
+            config,
+            crl: CertificateRevocationList::new(),
+            cache: None,
+        }
+    }
+
+    pub fn with_revocation_list(mut self, crl: CertificateRevocationList) -> Self {
+        self.crl = crl;
+        self
+    }
+
+    pub fn with_cache(mut self, cache: Option<CertificateCache>) -> Self {
+        self.cache = cache;
+        self
     }
 }
 impl<T, W> Handler<Parameters> for ServerCertHandler<T, W>
@@ -42,11 +64,11 @@ where
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let hsm = self.hsm.clone();
         let cfg = self.config.clone();
+        let crl = self.crl.clone();
+        let cache = self.cache.clone();
         let max_duration = cfg.get_cert_max_duration(CertificateType::Server);
 
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let response = module_name(&params)
             .and_then(|name| {
                 let genid = params
                     .name("genid")
@@ -85,6 +107,22 @@ where
                 let common_name = cert_req.common_name();
                 ensure_not_empty_with_context(common_name, || ErrorKind::MalformedRequestBody)?;
 
+                // reuse a certificate that was issued for the same parameters a moment ago,
+                // as long as it doesn't outlive the requested expiration
+                let key = ServerCertKey::new(alias.clone(), common_name.to_string());
+                #[allow(clippy::cast_possible_wrap)]
+                let valid_until = Utc::now() + Duration::seconds(expiration as i64);
+                if let Some(body) = cache
+                    .as_ref()
+                    .and_then(|cache| cache.server_cert(&key, &module_id, valid_until, &crl))
+                {
+                    debug!("Reusing the cached server certificate {}", alias);
+                    return created_response(
+                        body,
+                        ErrorKind::CertOperation(CertOperation::GetServerCert),
+                    );
+                }
+
                 // add a DNS SAN entry in the server cert that uses the module identifier as
                 // an alternative DNS name; we also need to add the common_name that we are using
                 // as a DNS name since the presence of a DNS name SAN will take precedence over
@@ -102,13 +140,18 @@ where
                     alias.clone(),
                 )
                 .with_san_entries(sans);
-                let body = refresh_cert(
+                let (body, cert_expiration) = issue_cert(
                     &hsm,
+                    &crl,
+                    &module_id,
                     alias,
                     &props,
                     ErrorKind::CertOperation(CertOperation::GetServerCert),
                 )?;
-                Ok(body)
+                if let Some(cache) = &cache {
+                    cache.insert_server_cert(key, body.clone(), cert_expiration);
+                }
+                created_response(body, ErrorKind::CertOperation(CertOperation::GetServerCert))
             })
             .or_else(|e| future::ok(e.into_response()));
 
@@ -119,6 +162,7 @@ where
 #[cfg(test)]
 mod tests {
     use std::result::Result as StdResult;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
 
     use chrono::offset::Utc;
@@ -134,6 +178,8 @@ mod tests {
     use workload::models::{CertificateResponse, ErrorResponse, ServerCertificateRequest};
 
     const MAX_DURATION_SEC: u64 = 7200;
+    const TEST_CERT_PEM: &[u8] =
+        b"-----BEGIN CERTIFICATE-----\ndGVzdA==\n-----END CERTIFICATE-----\n";
 
     #[derive(Clone, Default)]
     struct TestHsm {
@@ -565,6 +611,7 @@ mod tests {
                 assert_eq!("DNS:2020marvin, DNS:beeblebrox", san_entries[0]);
                 assert!(MAX_DURATION_SEC >= *props.validity_in_secs());
                 Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
                     .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string()))))
             }),
             TestWorkloadData::default(),
@@ -606,7 +653,9 @@ mod tests {
                 assert_eq!("beeblebroxIserver", props.alias());
                 assert_eq!(CertificateType::Server, *props.certificate_type());
                 assert!(MAX_DURATION_SEC >= *props.validity_in_secs());
-                Ok(TestCert::default().with_private_key(PrivateKey::Ref("Betelgeuse".to_string())))
+                Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
+                    .with_private_key(PrivateKey::Ref("Betelgeuse".to_string())))
             }),
             TestWorkloadData::default(),
         );
@@ -639,6 +688,82 @@ mod tests {
         assert_eq!(Some("Betelgeuse"), cert_resp.private_key().ref_());
     }
 
+    #[test]
+    fn issued_cert_can_be_revoked() {
+        let crl = CertificateRevocationList::new();
+        let handler = ServerCertHandler::new(
+            TestHsm::default().with_on_create(|_| {
+                Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
+                    .with_valid_to(Utc::now() + Duration::hours(1))
+                    .with_private_key(PrivateKey::Ref("Betelgeuse".to_string())))
+            }),
+            TestWorkloadData::default(),
+        )
+        .with_revocation_list(crl.clone());
+
+        let cert_req = ServerCertificateRequest::new(
+            "marvin".to_string(),
+            (Utc::now() + Duration::hours(1)).to_rfc3339(),
+        );
+
+        let request =
+            Request::get("http://localhost/modules/beeblebrox/genid/I/certificate/server")
+                .body(serde_json::to_string(&cert_req).unwrap().into())
+                .unwrap();
+
+        let params = Parameters::with_captures(vec![
+            (Some("name".to_string()), "beeblebrox".to_string()),
+            (Some("genid".to_string()), "I".to_string()),
+        ]);
+        let response = handler.handle(request, params).wait().unwrap();
+
+        assert_eq!(StatusCode::CREATED, response.status());
+        assert_eq!(0, crl.revoke_module("marvin").unwrap());
+        assert_eq!(1, crl.revoke_module("beeblebrox").unwrap());
+        assert_eq!("beeblebrox", crl.revoked()[0].module_id());
+    }
+
+    #[test]
+    fn cached_cert_reused() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let handler = ServerCertHandler::new(
+            TestHsm::default().with_on_create({
+                let created = created.clone();
+                move |_| {
+                    created.fetch_add(1, Ordering::SeqCst);
+                    Ok(TestCert::default()
+                        .with_cert(TEST_CERT_PEM.to_vec())
+                        .with_valid_to(Utc::now() + Duration::minutes(30))
+                        .with_private_key(PrivateKey::Ref("Betelgeuse".to_string())))
+                }
+            }),
+            TestWorkloadData::default(),
+        )
+        .with_cache(Some(CertificateCache::new(Duration::minutes(5))));
+
+        for common_name in &["marvin", "marvin", "trillian"] {
+            let cert_req = ServerCertificateRequest::new(
+                (*common_name).to_string(),
+                (Utc::now() + Duration::hours(1)).to_rfc3339(),
+            );
+
+            let request =
+                Request::get("http://localhost/modules/beeblebrox/genid/I/certificate/server")
+                    .body(serde_json::to_string(&cert_req).unwrap().into())
+                    .unwrap();
+
+            let params = Parameters::with_captures(vec![
+                (Some("name".to_string()), "beeblebrox".to_string()),
+                (Some("genid".to_string()), "I".to_string()),
+            ]);
+            let response = handler.handle(request, params).wait().unwrap();
+            assert_eq!(StatusCode::CREATED, response.status());
+        }
+
+        assert_eq!(2, created.load(Ordering::SeqCst));
+    }
+
     #[test]
     fn long_expiration_capped_to_max_duration_ok() {
         let handler = ServerCertHandler::new(
@@ -648,6 +773,7 @@ mod tests {
                 assert_eq!(CertificateType::Server, *props.certificate_type());
                 assert_eq!(MAX_DURATION_SEC, *props.validity_in_secs());
                 Ok(TestCert::default()
+                    .with_cert(TEST_CERT_PEM.to_vec())
                     .with_private_key(PrivateKey::Key(KeyBytes::Pem("Betelgeuse".to_string()))))
             }),
             TestWorkloadData::default(),
@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+
+use edgelet_core::CertificateRevocationList;
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use workload::models::{RevocationListResponse, RevokedCertificate};
+
+use crate::error::{CertOperation, Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Serves the module certificates that were revoked, so that modules which
+/// accept connections from other modules (like edgeHub) can reject them.
+pub struct RevocationListHandler {
+    crl: CertificateRevocationList,
+}
+
+impl RevocationListHandler {
+    pub fn new(crl: CertificateRevocationList) -> Self {
+        RevocationListHandler { crl }
+    }
+}
+
+impl Handler<Parameters> for RevocationListHandler {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let revoked = self
+            .crl
+            .revoked()
+            .into_iter()
+            .filter_map(|cert| {
+                cert.revoked_at().map(|revoked_at| {
+                    RevokedCertificate::new(
+                        cert.module_id().to_string(),
+                        cert.thumbprint().to_string(),
+                        cert.expiration().to_rfc3339(),
+                        revoked_at.to_rfc3339(),
+                    )
+                })
+            })
+            .collect();
+
+        let response = serde_json::to_string(&RevocationListResponse::new(revoked))
+            .context(ErrorKind::CertOperation(CertOperation::GetRevocationList))
+            .and_then(|body| {
+                Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::CertOperation(CertOperation::GetRevocationList))
+            })
+            .map_err(Error::from)
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use futures::Stream;
+
+    use super::*;
+
+    #[test]
+    fn empty_list() {
+        let handler = RevocationListHandler::new(CertificateRevocationList::new());
+        let request = Request::get("http://localhost/crl")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let crl: RevocationListResponse = serde_json::from_slice(&body).unwrap();
+        assert!(crl.revoked().is_empty());
+    }
+
+    #[test]
+    fn lists_revoked_certificates() {
+        let crl = CertificateRevocationList::new();
+        let expiration = Utc::now() + Duration::days(1);
+        crl.record(
+            "mod1",
+            b"-----BEGIN CERTIFICATE-----\ndGVzdA==\n-----END CERTIFICATE-----\n",
+            expiration,
+        )
+        .unwrap();
+        crl.record(
+            "mod2",
+            b"-----BEGIN CERTIFICATE-----\nb3RoZXI=\n-----END CERTIFICATE-----\n",
+            expiration,
+        )
+        .unwrap();
+        crl.revoke_module("mod1").unwrap();
+        let handler = RevocationListHandler::new(crl);
+        let request = Request::get("http://localhost/crl")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let crl: RevocationListResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(1, crl.revoked().len());
+        assert_eq!("mod1", crl.revoked()[0].module_id());
+        assert_eq!(
+            "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08",
+            crl.revoked()[0].thumbprint()
+        );
+        assert_eq!(&expiration.to_rfc3339(), crl.revoked()[0].expiration());
+    }
+}
@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use base64;
+use failure::ResultExt;
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json;
+use workload::models::{DeriveKeyRequest, DeriveKeyResponse};
+
+use edgelet_core::crypto::{KeyIdentity, KeyStore, Sign, Signature, SignatureAlgorithm};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+
+use super::module_name;
+use crate::error::{EncryptionOperation, Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Length of a derived key when the request doesn't ask for one, which is
+/// the length of one HMAC-SHA256 block.
+const DEFAULT_DERIVED_KEY_LENGTH: u32 = 32;
+
+/// The most HKDF can derive from one key with HMAC-SHA256.
+const MAX_DERIVED_KEY_LENGTH: u32 = 255 * 32;
+
+/// Derives keys for single purposes, such as storage encryption or message
+/// authentication, from a key of the module, so that a module gets unrelated
+/// keys for them without ever seeing the key they come from.
+pub struct DeriveKeyHandler<K>
+where
+    K: 'static + KeyStore + Clone,
+{
+    key_store: K,
+}
+
+impl<K> DeriveKeyHandler<K>
+where
+    K: 'static + KeyStore + Clone,
+{
+    pub fn new(key_store: K) -> Self {
+        DeriveKeyHandler { key_store }
+    }
+}
+
+/// Derives a key from the key `request.key_id()` of module `id` with the
+/// expand step of HKDF (RFC 5869), with the label of the request as the info.
+/// The key of the module is already a uniformly random key, so it serves as
+/// the pseudorandom key without the extract step, and HMAC-SHA256 with it
+/// never leaves the key store.
+pub fn derive_key<K: KeyStore>(
+    key_store: &K,
+    id: String,
+    request: &DeriveKeyRequest,
+) -> Result<DeriveKeyResponse, Error> {
+    if request.label().is_empty() {
+        return Err(Error::from(ErrorKind::EmptyDerivedKeyLabel));
+    }
+    let length = request.length().unwrap_or(DEFAULT_DERIVED_KEY_LENGTH);
+    if length == 0 || length > MAX_DERIVED_KEY_LENGTH {
+        return Err(Error::from(ErrorKind::InvalidDerivedKeyLength(length)));
+    }
+
+    let k = key_store
+        .get(&KeyIdentity::Module(id.clone()), request.key_id())
+        .context(ErrorKind::ModuleNotFound(id))?;
+
+    let length = length as usize;
+    let mut derived = Vec::with_capacity(length);
+    let mut block: Vec<u8> = vec![];
+    let mut counter: u8 = 1;
+    while derived.len() < length {
+        let mut input = block;
+        input.extend_from_slice(request.label().as_bytes());
+        input.push(counter);
+        block = k
+            .sign(SignatureAlgorithm::HMACSHA256, &input)
+            .context(ErrorKind::EncryptionOperation(
+                EncryptionOperation::DeriveKey,
+            ))?
+            .as_bytes()
+            .to_vec();
+        derived.extend_from_slice(&block);
+        counter = counter.wrapping_add(1);
+    }
+    derived.truncate(length);
+
+    Ok(DeriveKeyResponse::new(base64::encode(&derived)))
+}
+
+impl<K> Handler<Parameters> for DeriveKeyHandler<K>
+where
+    K: 'static + KeyStore + Clone + Send,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let response = module_name(&params)
+            .and_then(|name| {
+                let genid = params
+                    .name("genid")
+                    .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("genid")))?;
+                Ok((name, genid))
+            })
+            .map(|(name, genid)| {
+                let id = name.to_string();
+                let genid = genid.to_string();
+                let key_store = self.key_store.clone();
+
+                req.into_body().concat2().then(|body| {
+                    let body = body.context(ErrorKind::EncryptionOperation(
+                        EncryptionOperation::DeriveKey,
+                    ))?;
+                    Ok((id, genid, key_store, body))
+                })
+            })
+            .into_future()
+            .flatten()
+            .and_then(|(id, genid, key_store, body)| -> Result<_, Error> {
+                let request: DeriveKeyRequest =
+                    serde_json::from_slice(&body).context(ErrorKind::MalformedRequestBody)?;
+                let key_id = format!("{}{}", request.key_id(), genid);
+                let response = derive_key(&key_store, id, &request.with_key_id(key_id))?;
+                let body = serde_json::to_string(&response).context(
+                    ErrorKind::EncryptionOperation(EncryptionOperation::DeriveKey),
+                )?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::EncryptionOperation(
+                        EncryptionOperation::DeriveKey,
+                    ))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()));
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::crypto::MemoryKey;
+    use edgelet_core::{Error as CoreError, ErrorKind as CoreErrorKind};
+    use workload::models::ErrorResponse;
+
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct TestKeyStore {
+        key: MemoryKey,
+    }
+
+    impl KeyStore for TestKeyStore {
+        type Key = MemoryKey;
+
+        fn get(&self, identity: &KeyIdentity, key_name: &str) -> Result<Self::Key, CoreError> {
+            match identity {
+                KeyIdentity::Module(ref m) if m == "test" && key_name == "primaryg1" => {
+                    Ok(self.key.clone())
+                }
+                _ => Err(CoreError::from(CoreErrorKind::KeyStoreItemNotFound)),
+            }
+        }
+    }
+
+    fn derive(request: &DeriveKeyRequest) -> Response<Body> {
+        let handler = DeriveKeyHandler::new(TestKeyStore {
+            key: MemoryKey::new("key"),
+        });
+        let parameters = Parameters::with_captures(vec![
+            (Some("name".to_string()), "test".to_string()),
+            (Some("genid".to_string()), "g1".to_string()),
+        ]);
+        let request = Request::post("http://localhost/modules/test/genid/g1/derive")
+            .body(serde_json::to_string(request).unwrap().into())
+            .unwrap();
+
+        handler.handle(request, parameters).wait().unwrap()
+    }
+
+    fn derived_key(response: Response<Body>) -> Vec<u8> {
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let response: DeriveKeyResponse = serde_json::from_slice(&body).unwrap();
+        base64::decode(response.key()).unwrap()
+    }
+
+    #[test]
+    fn success() {
+        // arrange
+        let request = DeriveKeyRequest::new("primary".to_string(), "storage".to_string());
+
+        // act
+        let response = derive(&request);
+
+        // assert
+        assert_eq!(
+            "YB0swIgnalumaF2FsCExNtuDvKSFpv7Ajcxhjj5FqOc=",
+            base64::encode(&derived_key(response))
+        );
+    }
+
+    #[test]
+    fn labels_give_different_keys() {
+        // arrange
+        let storage = DeriveKeyRequest::new("primary".to_string(), "storage".to_string());
+        let messaging = DeriveKeyRequest::new("primary".to_string(), "messaging".to_string());
+
+        // act
+        let storage = derived_key(derive(&storage));
+        let messaging = derived_key(derive(&messaging));
+
+        // assert
+        assert_ne!(storage, messaging);
+    }
+
+    #[test]
+    fn long_keys_continue_short_ones() {
+        // arrange
+        let short = DeriveKeyRequest::new("primary".to_string(), "storage".to_string());
+        let long =
+            DeriveKeyRequest::new("primary".to_string(), "storage".to_string()).with_length(70);
+
+        // act
+        let short = derived_key(derive(&short));
+        let long = derived_key(derive(&long));
+
+        // assert
+        assert_eq!(70, long.len());
+        assert_eq!(short[..], long[..32]);
+    }
+
+    #[test]
+    fn invalid_length() {
+        // arrange
+        let request =
+            DeriveKeyRequest::new("primary".to_string(), "storage".to_string()).with_length(0);
+
+        // act
+        let response = derive(&request);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("Invalid length 0 for a derived key", error.message());
+    }
+
+    #[test]
+    fn empty_label() {
+        // arrange
+        let request = DeriveKeyRequest::new("primary".to_string(), String::new());
+
+        // act
+        let response = derive(&request);
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+
+    #[test]
+    fn not_found() {
+        // arrange
+        let request = DeriveKeyRequest::new("secondary".to_string(), "storage".to_string());
+
+        // act
+        let response = derive(&request);
+
+        // assert
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::{Future, IntoFuture};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use log::info;
+use serde_json;
+
+use edgelet_core::{ApiTokens, ErrorKind as CoreErrorKind};
+use edgelet_http::route::{Handler, Parameters};
+use edgelet_http::Error as HttpError;
+use workload::models::ManagementTokenResponse;
+
+use super::module_name;
+use crate::error::{Error, ErrorKind};
+use crate::IntoResponse;
+
+/// Mints tokens for the modules that call the management API when it listens
+/// on TCP. Without `api_tokens`, the management API doesn't take tokens and
+/// no module gets one.
+pub struct ManagementTokenHandler {
+    api_tokens: Option<ApiTokens>,
+}
+
+impl ManagementTokenHandler {
+    pub fn new(api_tokens: Option<ApiTokens>) -> Self {
+        ManagementTokenHandler { api_tokens }
+    }
+}
+
+impl Handler<Parameters> for ManagementTokenHandler {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
+        let api_tokens = self.api_tokens.clone();
+        let response = module_name(&params)
+            .and_then(|name| {
+                let name = name.to_string();
+                let api_tokens =
+                    api_tokens.ok_or_else(|| ErrorKind::ManagementTokenNotAllowed(name.clone()))?;
+                let token = api_tokens.mint(&name).map_err(|err| match err.kind() {
+                    CoreErrorKind::ApiTokenNotAllowed(_) => {
+                        Error::from(ErrorKind::ManagementTokenNotAllowed(name.clone()))
+                    }
+                    _ => Error::from(err.context(ErrorKind::ManagementToken(name.clone()))),
+                })?;
+                info!("Minted a management API token for module {}", name);
+
+                let body = serde_json::to_string(&ManagementTokenResponse::new(
+                    token.token().to_string(),
+                    token.expiration().to_rfc3339(),
+                ))
+                .with_context(|_| ErrorKind::ManagementToken(name.clone()))?;
+                let response = Response::builder()
+                    .status(StatusCode::CREATED)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::ManagementToken(name))?;
+                Ok(response)
+            })
+            .or_else(|e| Ok(e.into_response()))
+            .into_future();
+
+        Box::new(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::ManagementAuthSettings;
+    use futures::Stream;
+    use workload::models::ErrorResponse;
+
+    use super::*;
+
+    fn api_tokens() -> ApiTokens {
+        let settings: ManagementAuthSettings =
+            serde_json::from_str(r#"{ "token_modules": ["edgeAgent"] }"#).unwrap();
+        ApiTokens::new(&settings)
+    }
+
+    fn request(name: &str) -> (Request<Body>, Parameters) {
+        let request = Request::post(format!("http://localhost/modules/{}/managementtoken", name))
+            .body(Body::default())
+            .unwrap();
+        let parameters =
+            Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())]);
+        (request, parameters)
+    }
+
+    #[test]
+    fn allowed_module_gets_token() {
+        let api_tokens = api_tokens();
+        let handler = ManagementTokenHandler::new(Some(api_tokens.clone()));
+        let (request, parameters) = request("edgeAgent");
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        assert_eq!(StatusCode::CREATED, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let token: ManagementTokenResponse = serde_json::from_slice(&body).unwrap();
+        assert!(api_tokens.verify(token.token()).is_some());
+    }
+
+    #[test]
+    fn other_modules_are_forbidden() {
+        let handler = ManagementTokenHandler::new(Some(api_tokens()));
+        let (request, parameters) = request("tempSensor");
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            "Module \"tempSensor\" is not allowed to get management API tokens",
+            error.message()
+        );
+    }
+
+    #[test]
+    fn no_module_is_allowed_without_tokens() {
+        let handler = ManagementTokenHandler::new(None);
+        let (request, parameters) = request("edgeAgent");
+
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        assert_eq!(StatusCode::FORBIDDEN, response.status());
+    }
+}
@@ -1,31 +1,43 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+mod attestation;
 mod cert;
+mod crl;
 mod decrypt;
+mod derive;
 mod encrypt;
+mod management_token;
 mod sign;
 mod trust_bundle;
 
 use edgelet_core::{
-    Authenticator, CreateCertificate, Decrypt, Encrypt, GetTrustBundle, KeyStore, Module,
-    ModuleRuntime, ModuleRuntimeErrorReason, Policy, WorkloadConfig,
+    ApiTokens, Attest, Authenticator, CertificateRevocationList, CreateCertificate, Decrypt, Encrypt, GetTrustBundle,
+    KeyStore, Module, ModuleRuntime, ModuleRuntimeErrorReason, Policy, WorkloadConfig,
 };
 use edgelet_http::authentication::Authentication;
 use edgelet_http::authorization::Authorization;
+use edgelet_http::openapi::OpenApiHandler;
+use edgelet_http::rate_limit::{RateLimit, RateLimiter};
 use edgelet_http::route::*;
 use edgelet_http::{router, Version};
 use edgelet_http_mgmt::ListModules;
+use edgelet_utils::validate_module_id;
 use failure::{Compat, Fail, ResultExt};
 use futures::{future, Future};
 use hyper::service::{NewService, Service};
 use hyper::{Body, Request};
 use serde::Serialize;
 
+use self::attestation::QuoteHandler;
 use self::cert::{IdentityCertHandler, ServerCertHandler};
+use self::crl::RevocationListHandler;
 use self::decrypt::DecryptHandler;
+use self::derive::DeriveKeyHandler;
 use self::encrypt::EncryptHandler;
+use self::management_token::ManagementTokenHandler;
 use self::sign::SignHandler;
 use self::trust_bundle::TrustBundleHandler;
+use crate::cache::CertificateCache;
 use crate::error::{Error, ErrorKind};
 
 #[derive(Clone)]
@@ -34,15 +46,21 @@ pub struct WorkloadService {
 }
 
 impl WorkloadService {
-    pub fn new<K, H, M, W>(
+    pub fn new<K, H, M, W, A>(
         key_store: &K,
         hsm: H,
         runtime: &M,
         config: W,
+        crl: CertificateRevocationList,
+        cache: Option<CertificateCache>,
+        rate_limiter: Option<RateLimiter>,
+        api_tokens: Option<ApiTokens>,
+        attestation: Option<A>,
     ) -> impl Future<Item = Self, Error = Error>
     where
         K: KeyStore + Clone + Send + Sync + 'static,
         H: CreateCertificate + Decrypt + Encrypt + GetTrustBundle + Clone + Send + Sync + 'static,
+        A: Attest + Clone + Send + Sync + 'static,
         M: ModuleRuntime + Authenticator<Request = Request<Body>> + Clone + Send + Sync + 'static,
         for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
         <M::Module as Module>::Config: Serialize,
@@ -52,13 +70,19 @@ impl WorkloadService {
     {
         let router = router!(
             get   Version2018_06_28 runtime Policy::Anonymous => "/modules" => ListModules::new(runtime.clone()),
-            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/sign"     => SignHandler::new(key_store.clone()),
-            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/decrypt"  => DecryptHandler::new(hsm.clone()),
-            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/encrypt"  => EncryptHandler::new(hsm.clone()),
-            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/certificate/identity"            => IdentityCertHandler::new(hsm.clone(), config.clone()),
-            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/certificate/server" => ServerCertHandler::new(hsm.clone(), config),
+            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/sign"     => RateLimit::new(SignHandler::new(key_store.clone()), rate_limiter.clone()),
+            post  Version2019_11_05 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/derive"   => RateLimit::new(DeriveKeyHandler::new(key_store.clone()), rate_limiter.clone()),
+            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/decrypt"  => RateLimit::new(DecryptHandler::new(hsm.clone()), rate_limiter.clone()),
+            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/encrypt"  => RateLimit::new(EncryptHandler::new(hsm.clone()), rate_limiter.clone()),
+            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/certificate/identity"            => RateLimit::new(IdentityCertHandler::new(hsm.clone(), config.clone()).with_revocation_list(crl.clone()), rate_limiter.clone()),
+            post  Version2018_06_28 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/certificate/server" => RateLimit::new(ServerCertHandler::new(hsm.clone(), config).with_revocation_list(crl.clone()).with_cache(cache.clone()), rate_limiter.clone()),
+            post  Version2019_11_05 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/managementtoken"                 => ManagementTokenHandler::new(api_tokens),
+            post  Version2019_11_05 runtime Policy::Caller =>    "/modules/(?P<name>[^/]+)/attestation/quote"               => RateLimit::new(QuoteHandler::new(attestation), rate_limiter),
 
-            get   Version2018_06_28 runtime Policy::Anonymous => "/trust-bundle" => TrustBundleHandler::new(hsm),
+            get   Version2018_06_28 runtime Policy::Anonymous => "/trust-bundle" => TrustBundleHandler::new(hsm).with_cache(cache),
+            get   Version2019_11_05 runtime Policy::Anonymous => "/crl" => RevocationListHandler::new(crl),
+
+            get   Version2019_11_05 runtime Policy::Anonymous => "/swagger.json" => OpenApiHandler::new(workload::OPENAPI_SPEC),
         );
 
         router.new_service().then(|inner| {
@@ -91,3 +115,141 @@ impl NewService for WorkloadService {
         future::ok(self.clone())
     }
 }
+
+/// The `name` parameter of the route, which is the identity of a module.
+fn module_name(params: &Parameters) -> Result<&str, Error> {
+    let name = params
+        .name("name")
+        .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))?;
+    validate_module_id(name).context(ErrorKind::MalformedRequestParameter("name"))?;
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use edgelet_core::crypto::MemoryKeyStore;
+    use edgelet_core::{
+        CertificateProperties, CertificateType, Error as CoreError, ErrorKind as CoreErrorKind,
+        MakeModuleRuntime,
+    };
+    use edgelet_http::openapi::{operations, route_operations};
+    use edgelet_test_utils::cert::TestCert;
+    use edgelet_test_utils::crypto::TestHsm;
+    use edgelet_test_utils::module::*;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, Fail)]
+    pub enum Error {
+        #[fail(display = "General error")]
+        General,
+    }
+
+    impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+        fn from(_err: &'a Error) -> Self {
+            ModuleRuntimeErrorReason::Other
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestCrypto;
+
+    impl CreateCertificate for TestCrypto {
+        type Certificate = TestCert;
+
+        fn create_certificate(
+            &self,
+            _properties: &CertificateProperties,
+        ) -> Result<Self::Certificate, CoreError> {
+            Err(CoreError::from(CoreErrorKind::KeyStore))
+        }
+
+        fn destroy_certificate(&self, _alias: String) -> Result<(), CoreError> {
+            Ok(())
+        }
+
+        fn get_certificate(&self, _alias: String) -> Result<Self::Certificate, CoreError> {
+            Err(CoreError::from(CoreErrorKind::KeyStore))
+        }
+    }
+
+    impl Decrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn decrypt(
+            &self,
+            _client_id: &[u8],
+            ciphertext: &[u8],
+            _initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            Ok(ciphertext.to_vec())
+        }
+    }
+
+    impl Encrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn encrypt(
+            &self,
+            _client_id: &[u8],
+            plaintext: &[u8],
+            _initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            Ok(plaintext.to_vec())
+        }
+    }
+
+    impl GetTrustBundle for TestCrypto {
+        type Certificate = TestCert;
+
+        fn get_trust_bundle(&self) -> Result<Self::Certificate, CoreError> {
+            Ok(TestCert::default())
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestWorkloadConfig;
+
+    impl WorkloadConfig for TestWorkloadConfig {
+        fn iot_hub_name(&self) -> &str {
+            "hub1"
+        }
+
+        fn device_id(&self) -> &str {
+            "d1"
+        }
+
+        fn get_cert_max_duration(&self, _cert_type: CertificateType) -> i64 {
+            10_000_000
+        }
+    }
+
+    #[test]
+    fn routes_match_openapi_spec() {
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            TestSettings::new(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap();
+        let service = WorkloadService::new(
+            &MemoryKeyStore::new(),
+            TestCrypto,
+            &runtime,
+            TestWorkloadConfig,
+            CertificateRevocationList::new(),
+            None,
+            None,
+            None,
+            None::<TestHsm>,
+        )
+        .wait()
+        .unwrap();
+
+        assert_eq!(
+            operations(workload::OPENAPI_SPEC).unwrap(),
+            route_operations(&service.inner.routes())
+        );
+    }
+}
@@ -12,6 +12,7 @@ use edgelet_core::crypto::{KeyIdentity, KeyStore, Sign, Signature, SignatureAlgo
 use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 
+use super::module_name;
 use crate::error::{EncryptionOperation, Error, ErrorKind};
 use crate::IntoResponse;
 
@@ -56,9 +57,7 @@ where
         req: Request<Body>,
         params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
-        let response = params
-            .name("name")
-            .ok_or_else(|| Error::from(ErrorKind::MissingRequiredParameter("name")))
+        let response = module_name(&params)
             .and_then(|name| {
                 let genid = params
                     .name("genid")
@@ -285,6 +284,48 @@ mod tests {
         assert_eq!(StatusCode::BAD_REQUEST, response.status());
     }
 
+    #[test]
+    fn sign_invalid_name() {
+        // arrange
+        let key = MemoryKey::new("key");
+        let store = TestKeyStore::new(key);
+        let handler = SignHandler::new(store);
+
+        let sign_request = SignRequest::new(
+            "primary".to_string(),
+            "hmac".to_string(),
+            base64::encode("The quick brown fox jumps over the lazy dog"),
+        );
+        let body = serde_json::to_string(&sign_request).unwrap();
+
+        let parameters = Parameters::with_captures(vec![
+            (Some("name".to_string()), "my module".to_string()),
+            (Some("genid".to_string()), "g1".to_string()),
+        ]);
+        let request = Request::post("http://localhost/modules/my%20module/genid/g1/sign")
+            .body(body.into())
+            .unwrap();
+
+        // act
+        let response = handler.handle(request, parameters).wait().unwrap();
+
+        // assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        response
+            .into_body()
+            .concat2()
+            .and_then(|b| {
+                let error_response: ErrorResponse = serde_json::from_slice(&b).unwrap();
+                assert_eq!(
+                    "The request parameter `name` is malformed\n\tcaused by: Invalid module identity \"my module\"",
+                    error_response.message()
+                );
+                Ok(())
+            })
+            .wait()
+            .unwrap();
+    }
+
     #[test]
     fn sign_bad_params_genid() {
         // arrange
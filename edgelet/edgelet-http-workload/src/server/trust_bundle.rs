@@ -13,11 +13,13 @@ use edgelet_http::route::{Handler, Parameters};
 use edgelet_http::Error as HttpError;
 use workload::models::TrustBundleResponse;
 
+use crate::cache::CertificateCache;
 use crate::error::{EncryptionOperation, Error, ErrorKind};
 use crate::IntoResponse;
 
 pub struct TrustBundleHandler<T: GetTrustBundle> {
     hsm: T,
+    cache: Option<CertificateCache>,
 }
 
 impl<T> TrustBundleHandler<T>
@@ -25,7 +27,12 @@ where
     T: 'static + GetTrustBundle + Clone,
 {
     pub fn new(hsm: T) -> Self {
-        TrustBundleHandler { hsm }
+        TrustBundleHandler { hsm, cache: None }
+    }
+
+    pub fn with_cache(mut self, cache: Option<CertificateCache>) -> Self {
+        self.cache = cache;
+        self
     }
 }
 
@@ -39,21 +46,8 @@ where
         _params: Parameters,
     ) -> Box<dyn Future<Item = Response<Body>, Error = HttpError> + Send> {
         let response = self
-            .hsm
-            .get_trust_bundle()
-            .context(ErrorKind::EncryptionOperation(
-                EncryptionOperation::GetTrustBundle,
-            ))
-            .map_err(Error::from)
+            .trust_bundle()
             .and_then(|cert| -> Result<_, Error> {
-                let cert = cert.pem().context(ErrorKind::EncryptionOperation(
-                    EncryptionOperation::GetTrustBundle,
-                ))?;
-                let cert = str::from_utf8(cert.as_ref())
-                    .context(ErrorKind::EncryptionOperation(
-                        EncryptionOperation::GetTrustBundle,
-                    ))?
-                    .to_string();
                 let body = serde_json::to_string(&TrustBundleResponse::new(cert)).context(
                     ErrorKind::EncryptionOperation(EncryptionOperation::GetTrustBundle),
                 )?;
@@ -74,6 +68,37 @@ where
     }
 }
 
+impl<T> TrustBundleHandler<T>
+where
+    T: GetTrustBundle,
+{
+    fn trust_bundle(&self) -> Result<String, Error> {
+        if let Some(cert) = self.cache.as_ref().and_then(CertificateCache::trust_bundle) {
+            return Ok(cert);
+        }
+
+        let cert = self
+            .hsm
+            .get_trust_bundle()
+            .context(ErrorKind::EncryptionOperation(
+                EncryptionOperation::GetTrustBundle,
+            ))?;
+        let cert = cert.pem().context(ErrorKind::EncryptionOperation(
+            EncryptionOperation::GetTrustBundle,
+        ))?;
+        let cert = str::from_utf8(cert.as_ref())
+            .context(ErrorKind::EncryptionOperation(
+                EncryptionOperation::GetTrustBundle,
+            ))?
+            .to_string();
+
+        if let Some(cache) = &self.cache {
+            cache.set_trust_bundle(cert.clone());
+        }
+        Ok(cert)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use futures::Future;
@@ -153,4 +178,35 @@ mod tests {
             .wait()
             .unwrap();
     }
+
+    #[test]
+    fn cached_after_first_get() {
+        let cache = CertificateCache::new(chrono::Duration::minutes(5));
+        let handler = TrustBundleHandler::new(
+            TestHsm::default().with_cert(TestCert::default().with_cert(b"boo".to_vec())),
+        )
+        .with_cache(Some(cache.clone()));
+        let request = Request::get("http://localhost/trust-bundle")
+            .body("".into())
+            .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(Some("boo".to_string()), cache.trust_bundle());
+
+        // a failing HSM is not consulted while the trust bundle is cached
+        let handler = TrustBundleHandler::new(TestHsm::default().with_fail_call(true))
+            .with_cache(Some(cache.clone()));
+        let request = Request::get("http://localhost/trust-bundle")
+            .body("".into())
+            .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        cache.invalidate();
+        let request = Request::get("http://localhost/trust-bundle")
+            .body("".into())
+            .unwrap();
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
 }
@@ -28,9 +28,9 @@ use tokio::prelude::*;
 
 use edgelet_core::crypto::MemoryKeyStore;
 use edgelet_core::{
-    AuthId, Certificate, CertificateIssuer, CertificateProperties, CertificateType,
-    CreateCertificate, MakeModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState,
-    ModuleStatus, WorkloadConfig, IOTEDGED_CA_ALIAS,
+    AuthId, Certificate, CertificateIssuer, CertificateProperties, CertificateRevocationList,
+    CertificateType, CreateCertificate, MakeModuleRuntime, ModuleRuntimeErrorReason,
+    ModuleRuntimeState, ModuleStatus, WorkloadConfig, IOTEDGED_CA_ALIAS,
 };
 use edgelet_hsm::{Crypto, HsmLock};
 use edgelet_http_workload::WorkloadService;
@@ -181,9 +181,19 @@ fn create_workload_service(module_id: &str) -> (WorkloadService, Crypto) {
     };
 
     (
-        WorkloadService::new(&key_store, crypto.clone(), &runtime, config)
-            .wait()
-            .unwrap(),
+        WorkloadService::new(
+            &key_store,
+            crypto.clone(),
+            &runtime,
+            config,
+            CertificateRevocationList::new(),
+            None,
+            None,
+            None,
+            None::<TestHsm>,
+        )
+        .wait()
+        .unwrap(),
         crypto,
     )
 }
@@ -2,22 +2,31 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 use chrono::{DateTime, Duration, Utc};
 use failure::{Fail, ResultExt};
+use futures::future::{self, Either, Loop};
 use futures::{Future, IntoFuture, Stream};
-use hyper::{self, Body, Method, Request, Response};
-use log::debug;
+use hyper::header::HeaderMap;
+use hyper::{self, Body, Method, Request, Response, StatusCode};
+use log::{debug, warn};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json;
+use tokio::timer::Delay;
 use typed_headers::{http, mime, ContentLength, ContentType, HeaderMapExt};
 use url::form_urlencoded::Serializer as UrlSerializer;
 use url::Url;
 
-use edgelet_utils::ensure_not_empty_with_context;
+use edgelet_core::ClockSkew;
+use edgelet_utils::{ensure_not_empty_with_context, RetryPolicy};
 
 use crate::error::{Error, ErrorKind};
+use crate::throttle::{self, Throttle};
+
+/// How often a throttled request is sent again before the client gives up.
+const MAX_THROTTLED_RETRIES: u32 = 5;
 
 pub trait TokenSource {
     type Error;
@@ -62,6 +71,8 @@ pub struct Client<C, T> {
     api_version: String,
     host_name: Url,
     user_agent: Option<String>,
+    throttle: Option<Arc<Throttle>>,
+    clock_skew: Option<ClockSkew>,
 }
 
 impl<C, T> Client<C, T>
@@ -86,6 +97,8 @@ where
             api_version,
             host_name,
             user_agent: None,
+            throttle: None,
+            clock_skew: None,
         };
 
         Ok(client)
@@ -101,6 +114,30 @@ where
         self
     }
 
+    /// Makes the client hold off when the server throttles it. Throttled
+    /// requests are sent again after the time the server asks for, and all the
+    /// clones of the client wait along with them.
+    pub fn with_throttling(mut self) -> Self {
+        self.throttle = Some(Arc::new(Throttle::new(throttle::default_policy())));
+        self
+    }
+
+    /// Like `with_throttling`, but throttled responses that don't say when to
+    /// retry are retried by `policy`, and no throttled request is sent again
+    /// past its maximum elapsed time.
+    pub fn with_throttling_policy(mut self, policy: RetryPolicy) -> Self {
+        self.throttle = Some(Arc::new(Throttle::new(policy)));
+        self
+    }
+
+    /// Makes the client keep track of how far the local clock is off from the
+    /// time of the server, and give SAS tokens an expiry by the time of the
+    /// server instead of the local one.
+    pub fn with_clock_skew(mut self, clock_skew: ClockSkew) -> Self {
+        self.clock_skew = Some(clock_skew);
+        self
+    }
+
     pub fn inner(&self) -> &C {
         &self.inner
     }
@@ -120,7 +157,11 @@ where
     fn add_sas_token(&self, req: &mut Request<Body>, path: &str) -> Result<(), Error> {
         if let Some(ref source) = self.token_source {
             let token_duration = Duration::hours(1);
-            let expiry = Utc::now() + token_duration;
+            let now = self
+                .clock_skew
+                .as_ref()
+                .map_or_else(Utc::now, ClockSkew::now);
+            let expiry = now + token_duration;
             let token = source.get(&expiry).context(ErrorKind::TokenSource)?;
             debug!(
                 "Success generating token for request {} {}",
@@ -146,6 +187,57 @@ where
         body: Option<BodyT>,
         add_if_match: bool,
     ) -> impl Future<Item = Option<ResponseT>, Error = Error>
+    where
+        BodyT: Serialize,
+        ResponseT: 'static + DeserializeOwned,
+    {
+        self.request_with_headers(method, path, query, body, add_if_match)
+            .map(|(response, _headers)| response)
+    }
+
+    /// Like `request`, but also returns the headers of the response.
+    pub fn request_with_headers<BodyT, ResponseT>(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<HashMap<&str, &str>>,
+        body: Option<BodyT>,
+        add_if_match: bool,
+    ) -> impl Future<Item = (Option<ResponseT>, HeaderMap), Error = Error>
+    where
+        BodyT: Serialize,
+        ResponseT: 'static + DeserializeOwned,
+    {
+        let if_match = if add_if_match { Some("*") } else { None };
+        self.send(method, path, query, body, if_match)
+    }
+
+    /// Like `request`, but the request only succeeds if the resource still has
+    /// the entity tag `etag`, i.e. it wasn't changed since it was read.
+    pub fn request_if_match<BodyT, ResponseT>(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<HashMap<&str, &str>>,
+        body: Option<BodyT>,
+        etag: &str,
+    ) -> impl Future<Item = Option<ResponseT>, Error = Error>
+    where
+        BodyT: Serialize,
+        ResponseT: 'static + DeserializeOwned,
+    {
+        self.send(method, path, query, body, Some(etag))
+            .map(|(response, _headers)| response)
+    }
+
+    fn send<BodyT, ResponseT>(
+        &self,
+        method: Method,
+        path: &str,
+        query: Option<HashMap<&str, &str>>,
+        body: Option<BodyT>,
+        if_match: Option<&str>,
+    ) -> impl Future<Item = (Option<ResponseT>, HeaderMap), Error = Error>
     where
         BodyT: Serialize,
         ResponseT: 'static + DeserializeOwned,
@@ -162,7 +254,8 @@ where
 
         // build the full url
         let path_query = format!("{}?{}", path, query);
-        self.host_name
+        let request = self
+            .host_name
             .join(&path_query)
             .with_context(|_| ErrorKind::UrlJoin(self.host_name.clone(), path_query))
             .context(ErrorKind::Http)
@@ -176,20 +269,24 @@ where
                     req.header(http::header::USER_AGENT, &**user_agent);
                 }
 
-                // add an `If-Match: "*"` header if we've been asked to
-                if add_if_match {
-                    req.header(http::header::IF_MATCH, "*");
+                // add an `If-Match` header if we've been asked to
+                if let Some(if_match) = if_match {
+                    req.header(http::header::IF_MATCH, if_match);
                 }
 
                 // add request body if there is any
-                let mut req = if let Some(body) = body {
-                    let serialized = serde_json::to_string(&body).context(ErrorKind::Http)?;
-                    let serialized_len = serialized.len();
-                    let mut req = req.body(Body::from(serialized)).context(ErrorKind::Http)?;
+                let body = body
+                    .map(|body| serde_json::to_string(&body))
+                    .transpose()
+                    .context(ErrorKind::Http)?;
+                let mut req = if let Some(body) = &body {
+                    let mut req = req
+                        .body(Body::from(body.clone()))
+                        .context(ErrorKind::Http)?;
                     req.headers_mut()
                         .typed_insert(&ContentType(mime::APPLICATION_JSON));
                     req.headers_mut()
-                        .typed_insert(&ContentLength(serialized_len as u64));
+                        .typed_insert(&ContentLength(body.len() as u64));
                     req
                 } else {
                     req.body(Body::empty()).context(ErrorKind::Http)?
@@ -198,39 +295,125 @@ where
                 // add sas token
                 self.add_sas_token(&mut req, path)?;
 
-                Ok(req)
-            })
-            .map(|req| {
-                self.inner
-                    .call(req)
-                    .then(|resp| resp.context(ErrorKind::Http).map_err(Error::from))
-                    .and_then(|resp| {
-                        let (http::response::Parts { status, .. }, body) = resp.into_parts();
-                        body.concat2().then(move |res| {
-                            let body = res.context(ErrorKind::Http)?;
-                            Ok((status, body))
+                let (parts, _) = req.into_parts();
+                Ok(PreparedRequest { parts, body })
+            });
+
+        // A throttled request is sent again once the server lets it, if the
+        // client respects throttling.
+        let inner = self.inner.clone();
+        let throttle = self.throttle.clone();
+        let clock_skew = self.clock_skew.clone();
+        request
+            .into_future()
+            .and_then(move |request| {
+                let started = Instant::now();
+                future::loop_fn(0, move |attempt| {
+                    let inner = inner.clone();
+                    let throttle = throttle.clone();
+                    let clock_skew = clock_skew.clone();
+                    let req = request.to_request();
+
+                    wait_for(throttle.as_ref().and_then(|throttle| throttle.resume_at()))
+                        .and_then(move |()| {
+                            inner
+                                .call(req)
+                                .then(|resp| resp.context(ErrorKind::Http).map_err(Error::from))
                         })
-                    })
-                    .and_then(|(status, body)| {
-                        if status.is_success() {
-                            Ok(body)
-                        } else {
-                            Err(Error::http_with_error_response(status, &*body))
-                        }
-                    })
-                    .and_then(|body| {
-                        if body.len() == 0 {
-                            Ok(None)
-                        } else {
-                            Ok(Some(
-                                serde_json::from_slice::<ResponseT>(&body)
-                                    .context(ErrorKind::Http)?,
-                            ))
-                        }
-                    })
+                        .and_then(move |resp| {
+                            let (
+                                http::response::Parts {
+                                    status, headers, ..
+                                },
+                                body,
+                            ) = resp.into_parts();
+                            // Rejected requests are recorded too, since a
+                            // token that expired by the time of the server is
+                            // rejected with a 401.
+                            if let (Some(clock_skew), Some(server_time)) =
+                                (clock_skew, server_time(&headers))
+                            {
+                                clock_skew.record(server_time);
+                            }
+                            body.concat2().then(move |res| {
+                                let body = res.context(ErrorKind::Http)?;
+                                Ok((status, headers, body))
+                            })
+                        })
+                        .map(move |(status, headers, body)| match throttle {
+                            Some(throttle)
+                                if status == StatusCode::TOO_MANY_REQUESTS
+                                    && attempt < MAX_THROTTLED_RETRIES =>
+                            {
+                                let wait = throttle.throttled(throttle::retry_after(&headers));
+                                if throttle.policy().allows(started, wait) {
+                                    warn!(
+                                        "Request was throttled, retrying in {} milliseconds",
+                                        wait.as_millis()
+                                    );
+                                    Loop::Continue(attempt + 1)
+                                } else {
+                                    Loop::Break((status, headers, body))
+                                }
+                            }
+                            Some(throttle) if status.is_success() => {
+                                throttle.succeeded();
+                                Loop::Break((status, headers, body))
+                            }
+                            _ => Loop::Break((status, headers, body)),
+                        })
+                })
             })
-            .into_future()
-            .flatten()
+            .and_then(|(status, headers, body)| {
+                if status.is_success() {
+                    Ok((headers, body))
+                } else {
+                    Err(Error::http_with_error_response(status, &*body))
+                }
+            })
+            .and_then(|(headers, body)| {
+                if body.len() == 0 {
+                    Ok((None, headers))
+                } else {
+                    let response =
+                        serde_json::from_slice::<ResponseT>(&body).context(ErrorKind::Http)?;
+                    Ok((Some(response), headers))
+                }
+            })
+    }
+}
+
+// A request that can be sent more than once.
+struct PreparedRequest {
+    parts: http::request::Parts,
+    body: Option<String>,
+}
+
+impl PreparedRequest {
+    fn to_request(&self) -> Request<Body> {
+        let mut req = Request::new(self.body.clone().map_or_else(Body::empty, Body::from));
+        *req.method_mut() = self.parts.method.clone();
+        *req.uri_mut() = self.parts.uri.clone();
+        *req.version_mut() = self.parts.version;
+        *req.headers_mut() = self.parts.headers.clone();
+        req
+    }
+}
+
+/// Reads the time of the server from the `Date` header of a response.
+fn server_time(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    let date = headers.get(http::header::DATE)?.to_str().ok()?;
+    DateTime::parse_from_rfc2822(date)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+fn wait_for(resume_at: Option<Instant>) -> impl Future<Item = (), Error = Error> {
+    match resume_at {
+        Some(resume_at) => Either::A(
+            Delay::new(resume_at).map_err(|err| Error::from(err.context(ErrorKind::Http))),
+        ),
+        None => Either::B(future::ok(())),
     }
 }
 
@@ -245,6 +428,8 @@ where
             api_version: self.api_version.clone(),
             host_name: self.host_name.clone(),
             user_agent: self.user_agent.clone(),
+            throttle: self.throttle.clone(),
+            clock_skew: self.clock_skew.clone(),
         }
     }
 }
@@ -254,6 +439,7 @@ mod tests {
     use super::*;
     use std::collections::HashMap;
     use std::str;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     use chrono::{DateTime, Utc};
     use futures::future;
@@ -372,6 +558,32 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn request_with_headers_returns_response_headers() {
+        let host_name = Url::parse("http://localhost").unwrap();
+        let token_source: Option<StaticTokenSource> = None;
+
+        let handler = |_req: Request<Body>| {
+            let response = Response::builder()
+                .header(hyper::header::RETRY_AFTER, "3")
+                .body(r#""response""#.into())
+                .unwrap();
+            future::ok(response)
+        };
+        let client =
+            Client::new(handler, token_source, "2018-04-10".to_string(), host_name).unwrap();
+
+        let task =
+            client.request_with_headers::<String, String>(Method::GET, "/boo", None, None, false);
+
+        let (response, headers) = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+        assert_eq!(Some("response".to_string()), response);
+        assert_eq!("3", headers.get(hyper::header::RETRY_AFTER).unwrap());
+    }
+
     #[test]
     fn request_adds_api_version_with_other_query_params() {
         let api_version = "2018-04-10".to_string();
@@ -506,6 +718,145 @@ mod tests {
             .unwrap();
     }
 
+    // Sends a request to a server that throttles the first one.
+    fn request_throttled_once(client_throttling: bool) -> (Result<Option<String>, Error>, usize) {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let token_source: Option<StaticTokenSource> = None;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handler = {
+            let calls = calls.clone();
+            move |_req: Request<Body>| {
+                let response = if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .header("x-ms-retry-after-ms", "10")
+                        .body(Body::empty())
+                        .unwrap()
+                } else {
+                    let mut response = Response::new(r#""response""#.into());
+                    response
+                        .headers_mut()
+                        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                    response
+                };
+                Ok(response)
+            }
+        };
+        let mut client = Client::new(handler, token_source, api_version, host_name).unwrap();
+        if client_throttling {
+            client = client.with_throttling();
+        }
+
+        let task = client.request::<String, String>(Method::GET, "/boo", None, None, false);
+        let result = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task);
+        (result, calls.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn request_is_sent_again_after_throttling() {
+        let (result, calls) = request_throttled_once(true);
+        assert_eq!(Some("response".to_string()), result.unwrap());
+        assert_eq!(2, calls);
+    }
+
+    #[test]
+    fn request_fails_when_throttled_without_throttling() {
+        let (result, calls) = request_throttled_once(false);
+        match result.unwrap_err().kind() {
+            ErrorKind::HttpWithErrorResponse(StatusCode::TOO_MANY_REQUESTS, _) => (),
+            kind => panic!("Expected `HttpWithErrorResponse` but got {:?}", kind),
+        }
+        assert_eq!(1, calls);
+    }
+
+    #[derive(Clone)]
+    struct RecordingTokenSource {
+        expiries: Arc<std::sync::Mutex<Vec<DateTime<Utc>>>>,
+    }
+
+    impl TokenSource for RecordingTokenSource {
+        type Error = Error;
+        fn get(&self, expiry: &DateTime<Utc>) -> Result<String, Error> {
+            self.expiries.lock().unwrap().push(*expiry);
+            Ok("token".to_string())
+        }
+    }
+
+    #[test]
+    fn token_expiry_compensates_clock_skew() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let token_source = RecordingTokenSource {
+            expiries: Arc::new(std::sync::Mutex::new(vec![])),
+        };
+
+        // The server is two hours ahead, and rejects the token that expires
+        // by the local clock.
+        let handler = |_req: Request<Body>| {
+            let server_time = Utc::now() + Duration::hours(2);
+            Ok(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(hyper::header::DATE, server_time.to_rfc2822().as_str())
+                .body(Body::empty())
+                .unwrap())
+        };
+        let clock_skew = ClockSkew::new();
+        let client = Client::new(handler, Some(token_source.clone()), api_version, host_name)
+            .unwrap()
+            .with_clock_skew(clock_skew.clone());
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        for _ in 0..2 {
+            let task = client.request::<String, String>(Method::GET, "/boo", None, None, false);
+            runtime.block_on(task).unwrap_err();
+        }
+
+        assert!(clock_skew.offset() > Duration::minutes(119));
+        let expiries = token_source.expiries.lock().unwrap();
+        assert!(expiries[0] - Utc::now() < Duration::minutes(61));
+        assert!(expiries[1] - Utc::now() > Duration::minutes(179));
+    }
+
+    #[test]
+    fn request_if_match_adds_etag() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+        let response = r#""response""#;
+        let token_source: Option<StaticTokenSource> = None;
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(
+                req.headers().get(hyper::header::IF_MATCH).unwrap(),
+                r#""AAAAAAAAAAE=""#
+            );
+
+            let mut response = Response::new(response.into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Ok(response)
+        };
+        let client = Client::new(handler, token_source, api_version, host_name).unwrap();
+
+        let task = client.request_if_match::<String, _>(
+            Method::PUT,
+            "/boo",
+            None,
+            None,
+            r#""AAAAAAAAAAE=""#,
+        );
+
+        let _result: String = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap()
+            .unwrap();
+    }
+
     #[test]
     fn request_adds_body() {
         let api_version = "2018-04-10".to_string();
@@ -0,0 +1,169 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, IntoFuture, Stream};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request};
+use url::Url;
+
+use edgelet_core::{DiagnosticsReport, DiagnosticsSink};
+
+use crate::client::ClientImpl;
+use crate::error::{Error, ErrorKind};
+
+/// Publishes the diagnostics of the daemon by POSTing them as JSON to `url`.
+pub struct HttpDiagnosticsSink<C> {
+    client: Arc<C>,
+    url: Url,
+}
+
+impl<C> HttpDiagnosticsSink<C> {
+    pub fn new(client: C, url: Url) -> Self {
+        HttpDiagnosticsSink {
+            client: Arc::new(client),
+            url,
+        }
+    }
+}
+
+impl<C> Clone for HttpDiagnosticsSink<C> {
+    fn clone(&self) -> Self {
+        HttpDiagnosticsSink {
+            client: self.client.clone(),
+            url: self.url.clone(),
+        }
+    }
+}
+
+impl<C> DiagnosticsSink for HttpDiagnosticsSink<C>
+where
+    C: ClientImpl + 'static,
+{
+    type Error = Error;
+    type PublishFuture = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn publish(&self, report: &DiagnosticsReport) -> Self::PublishFuture {
+        let url = self.url.clone();
+        let client = self.client.clone();
+
+        let request = serde_json::to_string(report)
+            .context(ErrorKind::Http)
+            .and_then(|body| {
+                Request::post(url.as_str())
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(Body::from(body))
+                    .context(ErrorKind::Http)
+            })
+            .map_err(Error::from);
+
+        let published = request
+            .into_future()
+            .and_then(move |request| {
+                client
+                    .call(request)
+                    .then(|response| response.context(ErrorKind::Http).map_err(Error::from))
+            })
+            .and_then(|response| {
+                let (parts, body) = response.into_parts();
+                if parts.status.is_success() {
+                    Either::A(future::ok(()))
+                } else {
+                    Either::B(body.concat2().then(move |body| {
+                        let body = body
+                            .map(|body| String::from_utf8_lossy(&body).into_owned())
+                            .unwrap_or_default();
+                        Err(Error::from(ErrorKind::HttpWithErrorResponse(
+                            parts.status,
+                            body,
+                        )))
+                    }))
+                }
+            })
+            .map_err(move |err| Error::from(err.context(ErrorKind::PublishDiagnostics(url))));
+
+        Box::new(published)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::{TimeZone, Utc};
+    use hyper::{Method, Response, StatusCode};
+
+    use super::*;
+
+    fn report() -> DiagnosticsReport {
+        DiagnosticsReport::new("1.0.9".to_string(), Utc.ymd(2019, 11, 5).and_hms(12, 0, 0))
+            .with_watchdog_restarts(2)
+    }
+
+    #[test]
+    fn report_is_posted_as_json() {
+        let received = Arc::new(Mutex::new(None));
+        let requests = received.clone();
+        let client = move |req: Request<Body>| {
+            assert_eq!(&Method::POST, req.method());
+            assert_eq!("https://diagnostics.example.com/reports", req.uri());
+            assert_eq!("application/json", req.headers()[CONTENT_TYPE]);
+
+            let requests = requests.clone();
+            req.into_body().concat2().map(move |body| {
+                let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                *requests.lock().unwrap() = Some(body);
+                Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .body(Body::empty())
+                    .unwrap()
+            })
+        };
+        let sink = HttpDiagnosticsSink::new(
+            client,
+            Url::parse("https://diagnostics.example.com/reports").unwrap(),
+        );
+
+        sink.publish(&report()).wait().unwrap();
+
+        assert_eq!(
+            Some(serde_json::to_value(report()).unwrap()),
+            *received.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn rejected_report_fails() {
+        let client = |_: Request<Body>| -> Result<_, hyper::Error> {
+            Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body("not allowed".into())
+                .unwrap())
+        };
+        let sink = HttpDiagnosticsSink::new(
+            client,
+            Url::parse("https://diagnostics.example.com/reports").unwrap(),
+        );
+
+        let err = sink.publish(&report()).wait().unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::PublishDiagnostics(
+                Url::parse("https://diagnostics.example.com/reports").unwrap()
+            ),
+            err.kind()
+        );
+        assert_eq!(
+            Some(&ErrorKind::HttpWithErrorResponse(
+                StatusCode::FORBIDDEN,
+                "not allowed".to_string()
+            )),
+            err.cause()
+                .and_then(|cause| cause.downcast_ref::<Error>())
+                .map(Error::kind)
+        );
+    }
+}
@@ -56,6 +56,12 @@ pub enum ErrorKind {
     #[fail(display = "An error occurred obtaining the client identity private key")]
     IdentityPrivateKey,
 
+    #[fail(
+        display = "The client identity private key {} is kept in a PKCS#11 token, which TLS client authentication can't use. Keep the key in a file instead.",
+        _0
+    )]
+    IdentityPrivateKeyInToken(String),
+
     #[fail(display = "Reading identity private key from PEM bytes failed {}", _0)]
     IdentityPrivateKeyRead(String),
 
@@ -84,6 +90,9 @@ pub enum ErrorKind {
     #[fail(display = "Module not found")]
     ModuleNotFound(String),
 
+    #[fail(display = "Could not read the OpenAPI document of the API")]
+    OpenApiSpec,
+
     #[fail(display = "An error occurred for path {}", _0)]
     Path(String),
 
@@ -96,9 +105,21 @@ pub enum ErrorKind {
     )]
     PKCS12Identity(String),
 
+    #[fail(display = "Could not publish diagnostics to {}", _0)]
+    PublishDiagnostics(Url),
+
+    #[fail(display = "Request body is larger than {} bytes", _0)]
+    PayloadTooLarge(u64),
+
+    #[fail(display = "Module {} made too many requests", _0)]
+    RateLimited(String),
+
     #[fail(display = "An error occurred in the service")]
     ServiceError,
 
+    #[fail(display = "Could not set the permissions of socket {}", _0)]
+    SocketPermissions(String),
+
     #[fail(display = "An error occurred configuring the TLS stack")]
     TlsBootstrapError,
 
@@ -111,6 +132,12 @@ pub enum ErrorKind {
     #[fail(display = "Could not parse trust bundle")]
     TrustBundle,
 
+    #[fail(display = "Group {:?} does not exist", _0)]
+    UnknownGroup(String),
+
+    #[fail(display = "User {:?} does not exist", _0)]
+    UnknownUser(String),
+
     #[fail(
         display = "Could not form well-formed URL by joining {:?} with {:?}",
         _0, _1
@@ -178,6 +205,8 @@ impl IntoResponse for Error {
         let status_code = match *self.kind() {
             ErrorKind::Authorization | ErrorKind::ModuleNotFound(_) => StatusCode::NOT_FOUND,
             ErrorKind::InvalidApiVersion(_) => StatusCode::BAD_REQUEST,
+            ErrorKind::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorKind::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -35,7 +35,7 @@ use native_tls::Identity;
 #[cfg(unix)]
 use native_tls::TlsAcceptor;
 use openssl::pkcs12::Pkcs12;
-use openssl::pkey::PKey;
+use openssl::pkey::{PKey, PKeyRef, Private};
 use openssl::stack::Stack;
 use openssl::x509::X509;
 #[cfg(target_os = "linux")]
@@ -53,20 +53,28 @@ pub mod authentication;
 pub mod authorization;
 pub mod certificate_manager;
 pub mod client;
+mod diagnostics;
 pub mod error;
 pub mod logging;
+pub mod metrics;
+pub mod middleware;
+pub mod openapi;
 mod pid;
+pub mod rate_limit;
 pub mod route;
+mod throttle;
 mod unix;
 mod util;
 mod version;
 
 pub use certificate_manager::CertificateManager;
+pub use diagnostics::HttpDiagnosticsSink;
 pub use error::{BindListenerType, Error, ErrorKind, InvalidUrlReason};
 pub use pid::Pid;
+pub use unix::set_socket_permissions;
 pub use util::proxy::MaybeProxyClient;
 pub use util::UrlConnector;
-pub use version::{Version, API_VERSION};
+pub use version::{api_version, Version, API_VERSION};
 
 use crate::pid::PidService;
 use crate::util::incoming::Incoming;
@@ -80,6 +88,8 @@ const TCP_SCHEME: &str = "tcp";
 #[cfg(target_os = "linux")]
 const FD_SCHEME: &str = "fd";
 
+const PKCS11_URI_SCHEME: &[u8] = b"pkcs11:";
+
 #[derive(Clone)]
 pub struct PemCertificate {
     cert: Vec<u8>,
@@ -127,25 +137,31 @@ impl PemCertificate {
     }
 
     pub fn get_identity(&self) -> Result<Identity, Error> {
-        let mut certs = X509::stack_from_pem(&self.cert).context(ErrorKind::IdentityCertificate)?;
-
-        // the first cert is the identity cert and the other certs are part of the CA
-        // chain; we skip the server cert and build an OpenSSL cert stack with the
-        // other certs
-        let mut ca_certs = Stack::new().context(ErrorKind::IdentityCertificate)?;
-        for cert in certs.drain(1..) {
-            ca_certs
-                .push(cert)
-                .context(ErrorKind::IdentityCertificate)?;
-        }
+        let certs = X509::stack_from_pem(&self.cert).context(ErrorKind::IdentityCertificate)?;
 
         let key = match &self.key {
+            // The HSM only refers to keys that it can't read out of their token.
+            Some(k) if k.starts_with(PKCS11_URI_SCHEME) => {
+                return Err(Error::from(ErrorKind::IdentityPrivateKeyInToken(
+                    String::from_utf8_lossy(k).into_owned(),
+                )))
+            }
             Some(k) => PKey::private_key_from_pem(&k)
                 .with_context(|err| ErrorKind::IdentityPrivateKeyRead(err.to_string())),
             None => return Err(Error::from(ErrorKind::IdentityPrivateKey)),
         }?;
 
-        let identity_cert = &certs[0];
+        let (identity_cert, chain) = split_identity_chain(certs, &key)?;
+
+        // the remaining certs are the intermediate CA chain, which is sent
+        // along with the identity cert so that the server can build a path
+        // to its trusted root
+        let mut ca_certs = Stack::new().context(ErrorKind::IdentityCertificate)?;
+        for cert in chain {
+            ca_certs
+                .push(cert)
+                .context(ErrorKind::IdentityCertificate)?;
+        }
 
         let mut builder = Pkcs12::builder();
         builder.ca(ca_certs);
@@ -169,6 +185,29 @@ impl PemCertificate {
     }
 }
 
+// Picks the identity cert out of a PEM bundle as the one whose public key
+// matches the private key, so that bundles with the chain listed before the
+// leaf (as some HSMs return them) still work. Bundles without a matching cert
+// fall back to treating the first cert as the identity cert.
+fn split_identity_chain(
+    mut certs: Vec<X509>,
+    key: &PKeyRef<Private>,
+) -> Result<(X509, Vec<X509>), Error> {
+    if certs.is_empty() {
+        return Err(Error::from(ErrorKind::IdentityCertificate));
+    }
+
+    let position = certs
+        .iter()
+        .position(|cert| {
+            cert.public_key()
+                .map_or(false, |public_key| public_key.public_eq(key))
+        })
+        .unwrap_or(0);
+    let identity_cert = certs.remove(position);
+    Ok((identity_cert, certs))
+}
+
 impl Debug for PemCertificate {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         // do not print either the username, password or private key
@@ -311,35 +350,9 @@ impl HyperExt for Http {
         S: NewService<ReqBody = Body>,
     {
         let incoming = match url.scheme() {
-            HTTP_SCHEME | TCP_SCHEME => {
-                let addr = url
-                    .to_socket_addrs()
-                    .context(ErrorKind::InvalidUrl(url.to_string()))?
-                    .next()
-                    .ok_or_else(|| {
-                        ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
-                            InvalidUrlReason::NoAddress,
-                        )
-                    })?;
-
-                let listener = TcpListener::bind(&addr)
-                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(addr)))?;
-                Incoming::Tcp(listener)
-            }
+            HTTP_SCHEME | TCP_SCHEME => Incoming::Tcp(bind_tcp(&url)?),
             #[cfg(unix)]
             HTTPS_SCHEME => {
-                let addr = url
-                    .to_socket_addrs()
-                    .context(ErrorKind::InvalidUrl(url.to_string()))?
-                    .next()
-                    .ok_or_else(|| {
-                        ErrorKind::InvalidUrlWithReason(
-                            url.to_string(),
-                            InvalidUrlReason::NoAddress,
-                        )
-                    })?;
-
                 let cert = tls_params
                     .as_ref()
                     .map(|params| params.cert_manager.get_pkcs12_certificate())
@@ -365,8 +378,7 @@ impl HyperExt for Http {
                     .context(ErrorKind::TlsBootstrapError)?;
                 let tls_acceptor = tokio_tls::TlsAcceptor::from(tls_acceptor);
 
-                let listener = TcpListener::bind(&addr)
-                    .with_context(|_| ErrorKind::BindListener(BindListenerType::Address(addr)))?;
+                let listener = bind_tcp(&url)?;
                 Incoming::Tls(listener, tls_acceptor, Mutex::new(vec![]))
             }
             UNIX_SCHEME => {
@@ -436,6 +448,34 @@ impl HyperExt for Http {
     }
 }
 
+// A host name can resolve to addresses of a family the host has no stack for,
+// e.g. `localhost` to `::1` on an IPv4-only host, so every address is tried in
+// turn. Literal addresses like `[::]` resolve to themselves.
+fn bind_tcp(url: &Url) -> Result<TcpListener, Error> {
+    let addrs = url
+        .to_socket_addrs()
+        .context(ErrorKind::InvalidUrl(url.to_string()))?;
+
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpListener::bind(&addr) {
+            Ok(listener) => return Ok(listener),
+            Err(err) => {
+                debug!("Could not bind {}: {}", addr, err);
+                last_err =
+                    Some(err.context(ErrorKind::BindListener(BindListenerType::Address(addr))));
+            }
+        }
+    }
+
+    Err(match last_err {
+        Some(err) => err.into(),
+        None => {
+            ErrorKind::InvalidUrlWithReason(url.to_string(), InvalidUrlReason::NoAddress).into()
+        }
+    })
+}
+
 #[cfg_attr(not(unix), allow(dead_code))]
 pub struct TlsAcceptorParams<'a, C>
 where
@@ -456,3 +496,127 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::x509::X509NameBuilder;
+
+    #[test]
+    fn bind_tcp_reports_address_in_use() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let err = bind_tcp(&Url::parse(&format!("http://{}", addr)).unwrap()).unwrap_err();
+        match err.kind() {
+            ErrorKind::BindListener(BindListenerType::Address(bound)) => assert_eq!(addr, *bound),
+            kind => panic!("Expected a BindListener error but got {:?}", kind),
+        }
+    }
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn cert(common_name: &str, key: &PKeyRef<Private>, issuer_key: &PKeyRef<Private>) -> X509 {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn common_name(cert: &X509) -> String {
+        let entry = cert
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .unwrap();
+        String::from_utf8(entry.data().as_slice().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn identity_cert_is_picked_from_chain_by_private_key() {
+        let ca_key = key();
+        let device_key = key();
+        let certs = vec![
+            cert("intermediate", &ca_key, &ca_key),
+            cert("device", &device_key, &ca_key),
+        ];
+
+        let (identity_cert, chain) = split_identity_chain(certs, &device_key).unwrap();
+
+        assert_eq!("device", common_name(&identity_cert));
+        assert_eq!(1, chain.len());
+        assert_eq!("intermediate", common_name(&chain[0]));
+    }
+
+    #[test]
+    fn first_cert_is_identity_cert_when_none_match() {
+        let ca_key = key();
+        let certs = vec![
+            cert("device", &ca_key, &ca_key),
+            cert("intermediate", &ca_key, &ca_key),
+        ];
+
+        let (identity_cert, chain) = split_identity_chain(certs, &key()).unwrap();
+
+        assert_eq!("device", common_name(&identity_cert));
+        assert_eq!(1, chain.len());
+    }
+
+    #[test]
+    fn pem_bundle_with_chain_first_makes_identity() {
+        let ca_key = key();
+        let device_key = key();
+        let mut pem = cert("intermediate", &ca_key, &ca_key).to_pem().unwrap();
+        pem.extend(cert("device", &device_key, &ca_key).to_pem().unwrap());
+
+        let pem_cert = PemCertificate::new(
+            pem,
+            Some(device_key.private_key_to_pem_pkcs8().unwrap()),
+            None,
+            None,
+        );
+
+        pem_cert.get_identity().unwrap();
+    }
+
+    #[test]
+    fn key_in_pkcs11_token_cannot_make_identity() {
+        let ca_key = key();
+        let pem_cert = PemCertificate::new(
+            cert("device", &key(), &ca_key).to_pem().unwrap(),
+            Some(b"pkcs11:token=edge;object=device-id;type=private".to_vec()),
+            None,
+            None,
+        );
+
+        let err = pem_cert.get_identity().err().unwrap();
+
+        assert_eq!(
+            &ErrorKind::IdentityPrivateKeyInToken(
+                "pkcs11:token=edge;object=device-id;type=private".to_string()
+            ),
+            err.kind()
+        );
+    }
+}
@@ -4,40 +4,27 @@
 use chrono::prelude::*;
 use futures::prelude::*;
 use hyper::header::{CONTENT_LENGTH, USER_AGENT};
-use hyper::service::{NewService, Service};
-use hyper::Request;
+use hyper::{Body, Request};
 use log::info;
 
 use edgelet_core::AuthId;
 
-#[derive(Clone)]
-pub struct LoggingService<T> {
+use crate::middleware::{Middleware, Next, ResponseFuture};
+
+/// Logs every request with its response, in the style of a web server's
+/// access log.
+pub struct Logging {
     label: String,
-    inner: T,
 }
 
-impl<T> LoggingService<T> {
-    pub fn new(label: String, inner: T) -> Self {
-        LoggingService { label, inner }
+impl Logging {
+    pub fn new(label: String) -> Self {
+        Logging { label }
     }
 }
 
-impl<T> Service for LoggingService<T>
-where
-    T: Service,
-    <T as Service>::Future: Send + 'static,
-{
-    type ReqBody = T::ReqBody;
-    type ResBody = T::ResBody;
-    type Error = T::Error;
-    type Future = Box<
-        dyn Future<
-                Item = <<T as Service>::Future as Future>::Item,
-                Error = <<T as Service>::Future as Future>::Error,
-            > + Send,
-    >;
-
-    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+impl Middleware for Logging {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> ResponseFuture {
         let label = self.label.clone();
         let uri = req.uri().query().map_or_else(
             || req.uri().path().to_string(),
@@ -55,7 +42,7 @@ where
             .get::<AuthId>()
             .map_or_else(|| "-".to_string(), ToString::to_string);
 
-        let inner = self.inner.call(req);
+        let inner = next.run(req);
 
         Box::new(inner.map(move |response| {
             let body_length = response
@@ -79,27 +66,3 @@ where
         }))
     }
 }
-
-impl<T> NewService for LoggingService<T>
-where
-    T: NewService,
-    <T as NewService>::Future: Send + 'static,
-    LoggingService<<T as NewService>::Service>: Service,
-    // <<T as NewService>::Service as Service>::Future: Send + 'static,
-{
-    type ReqBody = <LoggingService<<T as NewService>::Service> as Service>::ReqBody;
-    type ResBody = <LoggingService<<T as NewService>::Service> as Service>::ResBody;
-    type Error = <LoggingService<<T as NewService>::Service> as Service>::Error;
-    type Service = LoggingService<<T as NewService>::Service>;
-    type Future = Box<dyn Future<Item = Self::Service, Error = Self::InitError> + Send>;
-    type InitError = <T as NewService>::InitError;
-
-    fn new_service(&self) -> Self::Future {
-        let label = self.label.clone();
-        Box::new(
-            self.inner
-                .new_service()
-                .map(|inner| LoggingService { label, inner }),
-        )
-    }
-}
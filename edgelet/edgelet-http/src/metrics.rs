@@ -0,0 +1,371 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{NewService, Service};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use edgelet_core::Metrics;
+
+use crate::middleware::{Middleware, Next, ResponseFuture};
+use crate::route::MatchedRoute;
+
+const HTTP_REQUESTS_METRIC: &str = "iotedged_http_requests_total";
+const HTTP_REQUESTS_HELP: &str = "Number of requests served by the daemon's APIs";
+const HTTP_REQUEST_DURATION_METRIC: &str = "iotedged_http_request_duration_seconds";
+const HTTP_REQUEST_DURATION_HELP: &str = "Time taken to serve requests by the daemon's APIs";
+const HTTP_REQUESTS_IN_FLIGHT_METRIC: &str = "iotedged_http_requests_in_flight";
+const HTTP_REQUESTS_IN_FLIGHT_HELP: &str = "Number of requests being served by the daemon's APIs";
+
+/// Upper bounds of the request duration buckets, in seconds. Most requests
+/// take milliseconds, while those that sign with the HSM or pull images can
+/// take seconds.
+const HTTP_REQUEST_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+/// The status label of requests that failed without a response, e.g. because
+/// reading their body failed.
+const ERROR_STATUS: &str = "error";
+
+const METRICS_PATH: &str = "/metrics";
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The route label of requests that matched no route, so that requests for
+/// arbitrary paths don't each add a time series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Counts the requests served by an API, how many are being served and how
+/// long they took, by route template, e.g. `/modules/{name}/restart`, and
+/// status code.
+pub struct RequestMetrics {
+    api: String,
+    metrics: Metrics,
+}
+
+impl RequestMetrics {
+    pub fn new(api: String, metrics: Metrics) -> Self {
+        RequestMetrics { api, metrics }
+    }
+}
+
+impl Middleware for RequestMetrics {
+    fn call(&self, mut req: Request<Body>, next: Next<'_>) -> ResponseFuture {
+        let api = self.api.clone();
+        let metrics = self.metrics.clone();
+        let method = req.method().to_string();
+        let matched_route = MatchedRoute::new();
+        req.extensions_mut().insert(matched_route.clone());
+        let start = Instant::now();
+
+        // The router matches the request as it is passed on, before the
+        // request is answered, so its route is known from here on.
+        let inner = next.run(req);
+        let route = matched_route
+            .path()
+            .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+        let in_flight = InFlight::new(metrics.clone(), api.clone(), route.clone());
+
+        Box::new(inner.then(move |result| {
+            drop(in_flight);
+
+            let status = result.as_ref().map_or_else(
+                |_| ERROR_STATUS.to_string(),
+                |response| response.status().as_u16().to_string(),
+            );
+            let labels = [
+                ("api", api.as_str()),
+                ("method", method.as_str()),
+                ("route", route.as_str()),
+                ("status", status.as_str()),
+            ];
+            let elapsed = start.elapsed();
+            #[allow(clippy::cast_precision_loss)]
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+            metrics.inc_counter(HTTP_REQUESTS_METRIC, HTTP_REQUESTS_HELP, &labels);
+            metrics.observe_histogram(
+                HTTP_REQUEST_DURATION_METRIC,
+                HTTP_REQUEST_DURATION_HELP,
+                &labels,
+                HTTP_REQUEST_DURATION_BUCKETS,
+                elapsed,
+            );
+
+            result
+        }))
+    }
+}
+
+/// Counts a request as in flight until it is dropped, so that a request whose
+/// connection is closed before it is answered isn't counted forever.
+struct InFlight {
+    metrics: Metrics,
+    api: String,
+    route: String,
+}
+
+impl InFlight {
+    fn new(metrics: Metrics, api: String, route: String) -> Self {
+        let in_flight = InFlight {
+            metrics,
+            api,
+            route,
+        };
+        in_flight.add(1.0);
+        in_flight
+    }
+
+    fn add(&self, delta: f64) {
+        self.metrics.add_gauge(
+            HTTP_REQUESTS_IN_FLIGHT_METRIC,
+            HTTP_REQUESTS_IN_FLIGHT_HELP,
+            &[("api", self.api.as_str()), ("route", self.route.as_str())],
+            delta,
+        );
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.add(-1.0);
+    }
+}
+
+/// Serves the metrics of the daemon at `/metrics`, to be scraped by Prometheus.
+#[derive(Clone)]
+pub struct MetricsEndpoint {
+    metrics: Metrics,
+}
+
+impl MetricsEndpoint {
+    pub fn new(metrics: Metrics) -> Self {
+        MetricsEndpoint { metrics }
+    }
+}
+
+impl Service for MetricsEndpoint {
+    type ReqBody = Body;
+    type ResBody = Body;
+    type Error = hyper::Error;
+    type Future = future::FutureResult<Response<Body>, hyper::Error>;
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let response = if req.uri().path() != METRICS_PATH {
+            status_response(StatusCode::NOT_FOUND)
+        } else if req.method() != Method::GET {
+            status_response(StatusCode::METHOD_NOT_ALLOWED)
+        } else {
+            let body = self.metrics.render();
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, METRICS_CONTENT_TYPE)
+                .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                .body(body.into())
+                .expect("response builder failure")
+        };
+
+        future::ok(response)
+    }
+}
+
+impl NewService for MetricsEndpoint {
+    type ReqBody = Body;
+    type ResBody = Body;
+    type Error = hyper::Error;
+    type Service = Self;
+    type Future = future::FutureResult<Self::Service, Self::InitError>;
+    type InitError = hyper::Error;
+
+    fn new_service(&self) -> Self::Future {
+        future::ok(self.clone())
+    }
+}
+
+/// How many requests the APIs answered with an error, by API and status code,
+/// e.g. "workload 500". Routes are left out, so that the counts can be shared
+/// without telling anything about the modules of the device.
+pub fn error_counts(metrics: &Metrics) -> BTreeMap<String, u64> {
+    let mut counts = BTreeMap::new();
+    for (labels, value) in metrics.counter_values(HTTP_REQUESTS_METRIC) {
+        let label = |name: &str| {
+            labels
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if let (Some(api), Some(status)) = (label("api"), label("status")) {
+            if status.parse::<u16>().map_or(false, |status| status >= 400) {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let value = value as u64;
+                *counts.entry(format!("{} {}", api, status)).or_insert(0) += value;
+            }
+        }
+    }
+    counts
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("response builder failure")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::middleware::{Chain, ChainBuilder};
+    use crate::route::{
+        Builder, Parameters, RegexRecognizer, RegexRoutesBuilder, Router, RouterService,
+    };
+    use crate::{Error, Version};
+
+    use super::*;
+
+    #[allow(clippy::needless_pass_by_value)]
+    fn restart(
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+        Box::new(future::ok(status_response(StatusCode::NO_CONTENT)))
+    }
+
+    fn service(metrics: &Metrics) -> Chain<RouterService<RegexRecognizer>> {
+        let inner = Router::from(
+            RegexRoutesBuilder::default()
+                .post(
+                    Version::Version2018_06_28,
+                    "/modules/(?P<name>[^/]+)/restart",
+                    restart,
+                )
+                .finish(),
+        )
+        .new_service()
+        .wait()
+        .unwrap();
+        ChainBuilder::new()
+            .with_metrics("mgmt".to_string(), metrics.clone())
+            .finish(inner)
+    }
+
+    #[test]
+    fn middleware_counts_requests() {
+        let metrics = Metrics::new();
+        let mut service = service(&metrics);
+
+        let request =
+            Request::post("http://localhost/modules/edgeHub/restart?api-version=2018-06-28")
+                .body(Body::empty())
+                .unwrap();
+        let response = service.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        let rendered = metrics.render();
+        assert!(rendered.contains(
+            "iotedged_http_requests_total{api=\"mgmt\",method=\"POST\",route=\"/modules/{name}/restart\",status=\"204\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "iotedged_http_request_duration_seconds_count{api=\"mgmt\",method=\"POST\",route=\"/modules/{name}/restart\",status=\"204\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "iotedged_http_request_duration_seconds_bucket{api=\"mgmt\",method=\"POST\",route=\"/modules/{name}/restart\",status=\"204\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "iotedged_http_requests_in_flight{api=\"mgmt\",route=\"/modules/{name}/restart\"} 0\n"
+        ));
+    }
+
+    #[test]
+    fn middleware_counts_unmatched_requests_under_one_route() {
+        let metrics = Metrics::new();
+        let mut service = service(&metrics);
+
+        for path in &["/modules/edgeHub/restart", "/a", "/b/c"] {
+            let request =
+                Request::get(format!("http://localhost{}?api-version=2018-06-28", path).as_str())
+                    .body(Body::empty())
+                    .unwrap();
+            let response = service.call(request).wait().unwrap();
+            assert_eq!(StatusCode::NOT_FOUND, response.status());
+        }
+
+        let rendered = metrics.render();
+        assert!(rendered.contains(
+            "iotedged_http_requests_total{api=\"mgmt\",method=\"GET\",route=\"unmatched\",status=\"404\"} 3\n"
+        ));
+        assert!(!rendered.contains("route=\"/"));
+    }
+
+    #[test]
+    fn middleware_counts_requests_in_flight() {
+        let metrics = Metrics::new();
+        let in_flight = InFlight::new(
+            metrics.clone(),
+            "workload".to_string(),
+            "/trust-bundle".to_string(),
+        );
+        assert!(metrics.render().contains(
+            "iotedged_http_requests_in_flight{api=\"workload\",route=\"/trust-bundle\"} 1\n"
+        ));
+
+        drop(in_flight);
+        assert!(metrics.render().contains(
+            "iotedged_http_requests_in_flight{api=\"workload\",route=\"/trust-bundle\"} 0\n"
+        ));
+    }
+
+    #[test]
+    fn error_counts_are_by_api_and_status() {
+        let metrics = Metrics::new();
+        let request = |api: &str, route: &str, status: &str| {
+            let labels = [
+                ("api", api),
+                ("method", "GET"),
+                ("route", route),
+                ("status", status),
+            ];
+            metrics.inc_counter(HTTP_REQUESTS_METRIC, HTTP_REQUESTS_HELP, &labels);
+        };
+        request("mgmt", "/modules", "200");
+        request("mgmt", "/modules/{name}", "404");
+        request("mgmt", "/identities/{name}", "404");
+        request("workload", "/modules/{name}/certificate/server", "500");
+
+        let mut expected = BTreeMap::new();
+        expected.insert("mgmt 404".to_string(), 2);
+        expected.insert("workload 500".to_string(), 1);
+        assert_eq!(expected, error_counts(&metrics));
+    }
+
+    #[test]
+    fn endpoint_serves_metrics() {
+        let metrics = Metrics::new();
+        metrics.inc_counter("test_total", "A test counter", &[]);
+        let mut endpoint = MetricsEndpoint::new(metrics);
+
+        let request = Request::get("http://localhost/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let response = endpoint.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            METRICS_CONTENT_TYPE,
+            response.headers().get(CONTENT_TYPE).unwrap()
+        );
+    }
+
+    #[test]
+    fn endpoint_only_serves_metrics_path() {
+        let mut endpoint = MetricsEndpoint::new(Metrics::new());
+
+        let request = Request::get("http://localhost/modules")
+            .body(Body::empty())
+            .unwrap();
+        let response = endpoint.call(request).wait().unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The layers that the APIs of the daemon put around their routes, for the
+//! concerns that are the same for every request: logging, authentication,
+//! API version checks, body limits and metrics.
+//!
+//! A `Chain` runs its middleware in the order they were added to the
+//! `ChainBuilder`, so the first one sees the request first and the response
+//! last. Every middleware either passes the request on to the `Next` one, or
+//! answers it itself.
+
+use std::sync::Arc;
+
+use failure::{Compat, Fail};
+use futures::{future, Future, Stream};
+use hyper::header::CONTENT_LENGTH;
+use hyper::service::{NewService, Service};
+use hyper::{Body, Request, Response};
+
+use edgelet_core::Metrics;
+
+use crate::logging::Logging;
+use crate::metrics::RequestMetrics;
+use crate::version::api_version;
+use crate::{Error, ErrorKind, IntoResponse};
+
+pub type ResponseFuture = Box<dyn Future<Item = Response<Body>, Error = Compat<Error>> + Send>;
+
+pub trait Middleware: Send + Sync {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> ResponseFuture;
+}
+
+/// The rest of a chain, after the middleware it is passed to.
+pub struct Next<'a> {
+    middleware: &'a [Box<dyn Middleware>],
+    service: &'a mut dyn FnMut(Request<Body>) -> ResponseFuture,
+}
+
+impl Next<'_> {
+    pub fn run(self, req: Request<Body>) -> ResponseFuture {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.call(
+                req,
+                Next {
+                    middleware: rest,
+                    service: self.service,
+                },
+            ),
+            None => (self.service)(req),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ChainBuilder {
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+impl ChainBuilder {
+    pub fn new() -> Self {
+        ChainBuilder::default()
+    }
+
+    pub fn with<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    pub fn with_logging(self, label: String) -> Self {
+        self.with(Logging::new(label))
+    }
+
+    pub fn with_metrics(self, api: String, metrics: Metrics) -> Self {
+        self.with(RequestMetrics::new(api, metrics))
+    }
+
+    pub fn with_version_check(self) -> Self {
+        self.with(VersionCheck)
+    }
+
+    pub fn with_body_limit(self, max_bytes: u64) -> Self {
+        self.with(BodyLimit::new(max_bytes))
+    }
+
+    pub fn finish<S>(self, inner: S) -> Chain<S> {
+        Chain {
+            middleware: Arc::new(self.middleware),
+            inner,
+        }
+    }
+}
+
+pub struct Chain<S> {
+    middleware: Arc<Vec<Box<dyn Middleware>>>,
+    inner: S,
+}
+
+impl<S> Chain<S> {
+    /// The service that the chain passes requests on to.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> Clone for Chain<S>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Chain {
+            middleware: self.middleware.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S> Service for Chain<S>
+where
+    S: Service<ReqBody = Body, ResBody = Body, Error = Compat<Error>>,
+    S::Future: Send + 'static,
+{
+    type ReqBody = Body;
+    type ResBody = Body;
+    type Error = Compat<Error>;
+    type Future = ResponseFuture;
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let middleware = self.middleware.clone();
+        let inner = &mut self.inner;
+        let mut service = |req| -> ResponseFuture { Box::new(inner.call(req)) };
+        Next {
+            middleware: &middleware,
+            service: &mut service,
+        }
+        .run(req)
+    }
+}
+
+impl<S> NewService for Chain<S>
+where
+    S: Service<ReqBody = Body, ResBody = Body, Error = Compat<Error>> + Clone,
+    S::Future: Send + 'static,
+{
+    type ReqBody = Body;
+    type ResBody = Body;
+    type Error = Compat<Error>;
+    type Service = Self;
+    type Future = future::FutureResult<Self::Service, Self::InitError>;
+    type InitError = Compat<Error>;
+
+    fn new_service(&self) -> Self::Future {
+        future::ok(self.clone())
+    }
+}
+
+/// Rejects requests without a known `api-version` before they are routed.
+pub struct VersionCheck;
+
+impl Middleware for VersionCheck {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> ResponseFuture {
+        match api_version(&req) {
+            Ok(_) => next.run(req),
+            Err(err) => Box::new(future::ok(err.into_response())),
+        }
+    }
+}
+
+/// Rejects requests with a body of more than `max_bytes`, either up front
+/// from their `Content-Length`, or once that much of a chunked body came in.
+pub struct BodyLimit {
+    max_bytes: u64,
+}
+
+impl BodyLimit {
+    pub fn new(max_bytes: u64) -> Self {
+        BodyLimit { max_bytes }
+    }
+}
+
+impl Middleware for BodyLimit {
+    fn call(&self, req: Request<Body>, next: Next<'_>) -> ResponseFuture {
+        let max_bytes = self.max_bytes;
+
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.map_or(false, |length| length > max_bytes) {
+            return Box::new(future::ok(
+                Error::from(ErrorKind::PayloadTooLarge(max_bytes)).into_response(),
+            ));
+        }
+
+        let (parts, body) = req.into_parts();
+        let mut received = 0_u64;
+        let body = body
+            .map_err(|err| -> Box<dyn std::error::Error + Send + Sync> { Box::new(err) })
+            .and_then(move |chunk| {
+                received += chunk.len() as u64;
+                if received > max_bytes {
+                    let err: Box<dyn std::error::Error + Send + Sync> =
+                        Box::new(Error::from(ErrorKind::PayloadTooLarge(max_bytes)).compat());
+                    Err(err)
+                } else {
+                    Ok(chunk)
+                }
+            });
+
+        next.run(Request::from_parts(parts, Body::wrap_stream(body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use hyper::StatusCode;
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct EchoService;
+
+    impl Service for EchoService {
+        type ReqBody = Body;
+        type ResBody = Body;
+        type Error = Compat<Error>;
+        type Future = ResponseFuture;
+
+        fn call(&mut self, req: Request<Body>) -> Self::Future {
+            Box::new(
+                req.into_body()
+                    .concat2()
+                    .then(|body| -> Result<_, Compat<Error>> {
+                        Ok(match body {
+                            Ok(body) => Response::new(body.into()),
+                            Err(_) => Response::builder()
+                                .status(StatusCode::BAD_REQUEST)
+                                .body(Body::empty())
+                                .unwrap(),
+                        })
+                    }),
+            )
+        }
+    }
+
+    struct Record {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Middleware for Record {
+        fn call(&self, req: Request<Body>, next: Next<'_>) -> ResponseFuture {
+            self.calls.lock().unwrap().push(self.name);
+            next.run(req)
+        }
+    }
+
+    fn request(uri: &str, body: &'static str) -> Request<Body> {
+        Request::post(uri).body(body.into()).unwrap()
+    }
+
+    #[test]
+    fn middleware_runs_in_order() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let mut chain = ChainBuilder::new()
+            .with(Record {
+                name: "first",
+                calls: calls.clone(),
+            })
+            .with(Record {
+                name: "second",
+                calls: calls.clone(),
+            })
+            .finish(EchoService);
+
+        let response = chain
+            .call(request("http://localhost/?api-version=2019-11-05", "hi"))
+            .wait()
+            .unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(vec!["first", "second"], *calls.lock().unwrap());
+    }
+
+    #[test]
+    fn version_check_rejects_unknown_versions() {
+        let mut chain = ChainBuilder::new().with_version_check().finish(EchoService);
+
+        let response = chain
+            .call(request("http://localhost/?api-version=2017-01-01", ""))
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+
+        let response = chain
+            .call(request("http://localhost/?api-version=2018-06-28", ""))
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    #[test]
+    fn body_limit_rejects_large_bodies() {
+        let mut chain = ChainBuilder::new().with_body_limit(4).finish(EchoService);
+
+        let response = chain
+            .call(request("http://localhost/", "1234"))
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let mut large = request("http://localhost/", "12345");
+        large
+            .headers_mut()
+            .insert(CONTENT_LENGTH, "5".parse().unwrap());
+        let response = chain.call(large).wait().unwrap();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, response.status());
+
+        // Without a Content-Length, the body is cut off once it is too large.
+        let response = chain
+            .call(request("http://localhost/", "12345"))
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    }
+}
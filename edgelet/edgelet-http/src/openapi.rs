@@ -0,0 +1,181 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeSet;
+
+use failure::ResultExt;
+use futures::{future, Future};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::Value;
+
+use crate::error::{Error, ErrorKind};
+use crate::route::{Handler, Parameters, RouteDescription};
+use crate::IntoResponse;
+
+const METHODS: &[&str] = &["get", "put", "post", "delete", "options", "head", "patch"];
+
+/// Serves the Swagger document of an API, which is kept in YAML, as JSON, so
+/// that clients for the API can be generated from what the daemon serves.
+pub struct OpenApiHandler {
+    spec: &'static str,
+}
+
+impl OpenApiHandler {
+    pub fn new(spec: &'static str) -> Self {
+        OpenApiHandler { spec }
+    }
+}
+
+impl Handler<Parameters> for OpenApiHandler {
+    fn handle(
+        &self,
+        _req: Request<Body>,
+        _params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+        let response = parse(self.spec)
+            .and_then(|spec| {
+                let body = serde_json::to_string(&spec).context(ErrorKind::OpenApiSpec)?;
+                let response = Response::builder()
+                    .status(StatusCode::OK)
+                    .header(CONTENT_TYPE, "application/json")
+                    .header(CONTENT_LENGTH, body.len().to_string().as_str())
+                    .body(body.into())
+                    .context(ErrorKind::OpenApiSpec)?;
+                Ok(response)
+            })
+            .unwrap_or_else(IntoResponse::into_response);
+
+        Box::new(future::ok(response))
+    }
+}
+
+/// Returns the operations that a Swagger document describes, as pairs of an
+/// HTTP method in upper case and a path, e.g. `("GET", "/modules/{name}")`.
+pub fn operations(spec: &str) -> Result<BTreeSet<(String, String)>, Error> {
+    let spec = parse(spec)?;
+    let mut operations = BTreeSet::new();
+    if let Some(paths) = spec.get("paths").and_then(Value::as_object) {
+        for (path, item) in paths {
+            let path = match path.trim_end_matches('/') {
+                "" => "/",
+                path => path,
+            };
+            if let Some(item) = item.as_object() {
+                for method in item.keys().filter(|key| METHODS.contains(&key.as_str())) {
+                    operations.insert((method.to_uppercase(), path.to_string()));
+                }
+            }
+        }
+    }
+    Ok(operations)
+}
+
+/// Returns the operations of the routes of an API, in the form of `operations`.
+pub fn route_operations(routes: &[RouteDescription]) -> BTreeSet<(String, String)> {
+    routes
+        .iter()
+        .map(|route| (route.method().to_string(), route.path().to_string()))
+        .collect()
+}
+
+fn parse(spec: &str) -> Result<Value, Error> {
+    let spec = serde_yaml::from_str(spec).context(ErrorKind::OpenApiSpec)?;
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+    use hyper::Method;
+
+    use super::*;
+    use crate::Version;
+
+    const SPEC: &str = r"
+swagger: '2.0'
+info:
+  title: Test API
+  version: '2019-11-05'
+paths:
+  /modules:
+    get:
+      operationId: ListModules
+    post:
+      operationId: CreateModule
+  '/modules/{name}/':
+    parameters:
+      - in: path
+        name: name
+        type: string
+    delete:
+      operationId: DeleteModule
+";
+
+    #[test]
+    fn lists_operations() {
+        let operations: Vec<_> = operations(SPEC).unwrap().into_iter().collect();
+        assert_eq!(
+            vec![
+                ("DELETE".to_string(), "/modules/{name}".to_string()),
+                ("GET".to_string(), "/modules".to_string()),
+                ("POST".to_string(), "/modules".to_string()),
+            ],
+            operations
+        );
+    }
+
+    #[test]
+    fn lists_route_operations() {
+        let routes = vec![
+            RouteDescription::new(
+                Method::GET,
+                Version::Version2018_06_28,
+                "/modules".to_string(),
+            ),
+            RouteDescription::new(
+                Method::DELETE,
+                Version::Version2019_11_05,
+                "/modules/{name}".to_string(),
+            ),
+        ];
+        assert_eq!(
+            operations(SPEC)
+                .unwrap()
+                .into_iter()
+                .filter(|(method, _)| method != "POST")
+                .collect::<BTreeSet<_>>(),
+            route_operations(&routes)
+        );
+    }
+
+    #[test]
+    fn serves_spec_as_json() {
+        let handler = OpenApiHandler::new(SPEC);
+        let request = Request::get("http://localhost/swagger.json")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::OK, response.status());
+        let body = response.into_body().concat2().wait().unwrap();
+        let spec: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!("2.0", spec["swagger"]);
+        assert_eq!(
+            "ListModules",
+            spec["paths"]["/modules"]["get"]["operationId"]
+        );
+    }
+
+    #[test]
+    fn invalid_spec_is_an_error() {
+        let handler = OpenApiHandler::new("paths: [");
+        let request = Request::get("http://localhost/swagger.json")
+            .body(Body::default())
+            .unwrap();
+
+        let response = handler.handle(request, Parameters::new()).wait().unwrap();
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{future, Future};
+use hyper::header::RETRY_AFTER;
+use hyper::{Body, Request, Response};
+use log::{info, warn};
+
+use crate::route::{Handler, Parameters};
+use crate::{Error, ErrorKind, IntoResponse};
+
+/// A token bucket for every caller, shared by all the handlers that draw from
+/// the same budget. Every request takes a token, and the buckets refill at the
+/// configured rate up to the burst size.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    throttled: bool,
+}
+
+impl RateLimiter {
+    /// Buckets refill at no less than one request per second, and hold at
+    /// least one request.
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        RateLimiter {
+            rate: f64::from(cmp::max(requests_per_second, 1)),
+            burst: f64::from(cmp::max(burst, 1)),
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes a token from the bucket of `caller`. If it is empty, returns how
+    /// long it takes for the next token to come in.
+    pub fn acquire(&self, caller: &str) -> Result<(), Duration> {
+        self.acquire_at(caller, Instant::now())
+    }
+
+    fn acquire_at(&self, caller: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .expect("Failed to acquire the rate limiter lock");
+        let burst = self.burst;
+        let bucket = buckets.entry(caller.to_string()).or_insert_with(|| Bucket {
+            tokens: burst,
+            updated: now,
+            throttled: false,
+        });
+
+        let elapsed = now.duration_since(bucket.updated);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            if bucket.throttled {
+                info!("Module {} is no longer rate limited", caller);
+                bucket.throttled = false;
+            }
+            return Ok(());
+        }
+
+        // Only the first rejection is logged, a module that is stuck in a
+        // loop would flood the log otherwise.
+        if !bucket.throttled {
+            warn!(
+                "Module {} exceeded {} requests per second, rejecting its requests",
+                caller, self.rate
+            );
+            bucket.throttled = true;
+        }
+
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+    }
+}
+
+/// Rejects requests with `429 Too Many Requests` once the module in the `name`
+/// parameter ran out of tokens. It has to be behind the authorization of the
+/// caller, so that the `name` parameter is the module that sent the request.
+pub struct RateLimit<H> {
+    limiter: Option<RateLimiter>,
+    inner: Arc<H>,
+}
+
+impl<H> RateLimit<H> {
+    /// Without a `limiter`, requests are passed on as they come.
+    pub fn new(inner: H, limiter: Option<RateLimiter>) -> Self {
+        RateLimit {
+            limiter,
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl<H> Handler<Parameters> for RateLimit<H>
+where
+    H: Handler<Parameters> + Sync,
+{
+    fn handle(
+        &self,
+        req: Request<Body>,
+        params: Parameters,
+    ) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+        let acquired = match (&self.limiter, params.name("name")) {
+            (Some(limiter), Some(name)) => limiter
+                .acquire(name)
+                .map_err(|wait| (name.to_string(), wait)),
+            _ => Ok(()),
+        };
+
+        match acquired {
+            Ok(()) => self.inner.handle(req, params),
+            Err((name, wait)) => {
+                let mut response = Error::from(ErrorKind::RateLimited(name)).into_response();
+                // Retry-After is in whole seconds, so round up to not have the
+                // module come back too early.
+                let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                response.headers_mut().insert(
+                    RETRY_AFTER,
+                    retry_after
+                        .to_string()
+                        .parse()
+                        .expect("a number is a valid header value"),
+                );
+                Box::new(future::ok(response))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+
+    use super::*;
+
+    struct TestHandler;
+
+    impl Handler<Parameters> for TestHandler {
+        fn handle(
+            &self,
+            _req: Request<Body>,
+            _params: Parameters,
+        ) -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+            Box::new(future::ok(Response::new(Body::empty())))
+        }
+    }
+
+    fn params(name: &str) -> Parameters {
+        Parameters::with_captures(vec![(Some("name".to_string()), name.to_string())])
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::new(2, 3);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.acquire_at("edgeHub", start));
+        }
+        assert_eq!(
+            Err(Duration::from_millis(500)),
+            limiter.acquire_at("edgeHub", start)
+        );
+
+        let later = start + Duration::from_millis(500);
+        assert_eq!(Ok(()), limiter.acquire_at("edgeHub", later));
+        assert!(limiter.acquire_at("edgeHub", later).is_err());
+
+        // the bucket doesn't fill past the burst size
+        let much_later = later + Duration::from_secs(30);
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.acquire_at("edgeHub", much_later));
+        }
+        assert!(limiter.acquire_at("edgeHub", much_later).is_err());
+    }
+
+    #[test]
+    fn callers_have_separate_buckets() {
+        let limiter = RateLimiter::new(1, 1);
+        let now = Instant::now();
+
+        assert_eq!(Ok(()), limiter.acquire_at("tempSensor", now));
+        assert!(limiter.acquire_at("tempSensor", now).is_err());
+        assert_eq!(Ok(()), limiter.acquire_at("edgeHub", now));
+    }
+
+    #[test]
+    fn handler_responds_with_too_many_requests() {
+        let handler = RateLimit::new(TestHandler, Some(RateLimiter::new(1, 1)));
+
+        let response = handler
+            .handle(Request::default(), params("tempSensor"))
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let response = handler
+            .handle(Request::default(), params("tempSensor"))
+            .wait()
+            .unwrap();
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+        assert_eq!("1", response.headers()[RETRY_AFTER]);
+    }
+
+    #[test]
+    fn handler_without_limiter_passes_requests_on() {
+        let handler = RateLimit::new(TestHandler, None);
+
+        for _ in 0..10 {
+            let response = handler
+                .handle(Request::default(), params("tempSensor"))
+                .wait()
+                .unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+        }
+    }
+}
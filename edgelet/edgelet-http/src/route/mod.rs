@@ -4,16 +4,15 @@
 /// with some changes to improve usability of the captured parameters
 /// when using regex based routes.
 use std::clone::Clone;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use failure::{Compat, Fail};
 use futures::{future, Future};
 use hyper::service::{NewService, Service};
 use hyper::{Body, Method, Request, Response, StatusCode};
-use url::form_urlencoded::parse as parse_query;
 
-use crate::error::{Error, ErrorKind};
-use crate::version::Version;
+use crate::error::Error;
+use crate::version::{api_version, Version};
 use crate::IntoResponse;
 
 pub mod macros;
@@ -44,7 +43,9 @@ where
     }
 }
 
-pub type HandlerParamsPair<'a, P> = (&'a dyn Handler<P>, P);
+/// The handler of a route that a request matched, its parameters, and the
+/// path of the route in the template syntax of Swagger.
+pub type RouteMatch<'a, P> = (&'a dyn Handler<P>, P, &'a str);
 
 pub trait Recognizer {
     type Parameters: 'static;
@@ -54,7 +55,69 @@ pub trait Recognizer {
         method: &Method,
         version: Version,
         path: &str,
-    ) -> Result<HandlerParamsPair<'_, Self::Parameters>, StatusCode>;
+    ) -> Result<RouteMatch<'_, Self::Parameters>, StatusCode>;
+
+    fn routes(&self) -> Vec<RouteDescription>;
+}
+
+/// Describes a route of a `Recognizer`, with its path in the template syntax
+/// of Swagger, e.g. `/modules/{name}`, so that the routes of an API can be
+/// checked against its Swagger document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteDescription {
+    method: Method,
+    version: Version,
+    path: String,
+}
+
+impl RouteDescription {
+    pub fn new(method: Method, version: Version, path: String) -> Self {
+        RouteDescription {
+            method,
+            version,
+            path,
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+/// Where a `RouterService` records the template of the route that it matched
+/// a request to, e.g. `/modules/{name}`, for the middleware in front of it.
+/// Such middleware adds one to the extensions of the request before passing
+/// it on. It stays empty if the request didn't reach the router, or matched
+/// no route.
+#[derive(Clone, Debug, Default)]
+pub struct MatchedRoute(Arc<Mutex<Option<String>>>);
+
+impl MatchedRoute {
+    pub fn new() -> Self {
+        MatchedRoute::default()
+    }
+
+    pub fn path(&self) -> Option<String> {
+        self.0
+            .lock()
+            .expect("Failed to acquire the matched route lock")
+            .clone()
+    }
+
+    fn set_path(&self, path: &str) {
+        *self
+            .0
+            .lock()
+            .expect("Failed to acquire the matched route lock") = Some(path.to_string());
+    }
 }
 
 pub trait Builder: Sized {
@@ -145,6 +208,15 @@ where
     }
 }
 
+impl<R> RouterService<R>
+where
+    R: Recognizer,
+{
+    pub fn routes(&self) -> Vec<RouteDescription> {
+        self.inner.routes()
+    }
+}
+
 impl<R> Service for RouterService<R>
 where
     R: Recognizer,
@@ -155,22 +227,15 @@ where
     type Future = Box<dyn Future<Item = Response<Self::ResBody>, Error = Self::Error> + Send>;
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
-        let api_version = {
-            let query = req.uri().query();
-            query.and_then(|query| {
-                let mut query = parse_query(query.as_bytes());
-                let (_, api_version) = query.find(|&(ref key, _)| key == "api-version")?;
-
-                api_version.parse().ok()
-            })
-        };
-
-        match api_version {
-            Some(api_version) => {
+        match api_version(&req) {
+            Ok(api_version) => {
                 let method = req.method().clone();
                 let path = req.uri().path().to_owned();
                 match self.inner.recognize(&method, api_version, &path) {
-                    Ok((handler, params)) => {
+                    Ok((handler, params, route)) => {
+                        if let Some(matched_route) = req.extensions().get::<MatchedRoute>() {
+                            matched_route.set_path(route);
+                        }
                         Box::new(handler.handle(req, params).map_err(Fail::compat))
                     }
                     Err(code) => Box::new(future::ok(
@@ -181,9 +246,7 @@ where
                     )),
                 }
             }
-            None => Box::new(future::ok(
-                Error::from(ErrorKind::InvalidApiVersion(String::new())).into_response(),
-            )),
+            Err(err) => Box::new(future::ok(err.into_response())),
         }
     }
 }
@@ -5,10 +5,11 @@ use std::collections::HashMap;
 use std::default::Default;
 
 use hyper::{Method, StatusCode};
+use lazy_static::lazy_static;
 use percent_encoding::percent_decode;
 use regex::Regex;
 
-use super::{Builder, Handler, HandlerParamsPair, Recognizer};
+use super::{Builder, Handler, Recognizer, RouteDescription, RouteMatch};
 use crate::version::Version;
 
 pub trait IntoCaptures {
@@ -66,6 +67,7 @@ impl Default for Parameters {
 
 struct RegexRoute {
     pattern: Regex,
+    path: String,
     handler: Box<dyn Handler<Parameters> + Sync>,
     version: Version,
 }
@@ -83,6 +85,7 @@ impl Builder for RegexRoutesBuilder {
         S: AsRef<str>,
         H: Handler<<Self::Recognizer as Recognizer>::Parameters> + Sync,
     {
+        let path = template_path(pattern.as_ref());
         let pattern = normalize_pattern(pattern.as_ref());
         let pattern = Regex::new(&pattern).expect("failed to compile regex");
         let handler = Box::new(handler);
@@ -91,6 +94,7 @@ impl Builder for RegexRoutesBuilder {
             .or_insert_with(Vec::new)
             .push(RegexRoute {
                 pattern,
+                path,
                 handler,
                 version,
             });
@@ -116,17 +120,28 @@ impl Recognizer for RegexRecognizer {
         method: &Method,
         api_version: Version,
         path: &str,
-    ) -> Result<HandlerParamsPair<'_, Self::Parameters>, StatusCode> {
+    ) -> Result<RouteMatch<'_, Self::Parameters>, StatusCode> {
         let routes = self.routes.get(method).ok_or(StatusCode::NOT_FOUND)?;
         for route in routes {
             if api_version >= route.version {
                 if let Some(params) = match_route(&route.pattern, path) {
-                    return Ok((&*route.handler, params));
+                    return Ok((&*route.handler, params, &route.path));
                 }
             }
         }
         Err(StatusCode::NOT_FOUND)
     }
+
+    fn routes(&self) -> Vec<RouteDescription> {
+        self.routes
+            .iter()
+            .flat_map(|(method, routes)| {
+                routes.iter().map(move |route| {
+                    RouteDescription::new(method.clone(), route.version, route.path.clone())
+                })
+            })
+            .collect()
+    }
 }
 
 fn match_route(re: &Regex, path: &str) -> Option<Parameters> {
@@ -147,6 +162,24 @@ fn match_route(re: &Regex, path: &str) -> Option<Parameters> {
     })
 }
 
+/// Turns the named captures of a route pattern into the parameters of an
+/// Swagger path template, e.g. `/modules/(?P<name>[^/]+)` into `/modules/{name}`.
+fn template_path(pattern: &str) -> String {
+    lazy_static! {
+        static ref CAPTURE: Regex = Regex::new(r"\(\?P<(\w+)>[^)]*\)").expect("invalid regex");
+    }
+
+    let path = CAPTURE.replace_all(pattern.trim(), "{$1}");
+    match path
+        .trim_start_matches('^')
+        .trim_end_matches('$')
+        .trim_end_matches('/')
+    {
+        "" => "/".to_string(),
+        path => path.to_string(),
+    }
+}
+
 fn normalize_pattern(pattern: &str) -> Cow<'_, str> {
     let pattern = pattern
         .trim()
@@ -190,4 +223,14 @@ mod tests {
         let params = match_route(&pattern, "/test/mi%2fke").expect("failed to get params");
         assert_eq!("mi/ke", params.name("name").unwrap());
     }
+
+    #[test]
+    fn template_path_names_parameters() {
+        assert_eq!(
+            "/modules/{name}/genid/{genid}/sign",
+            template_path("/modules/(?P<name>[^/]+)/genid/(?P<genid>[^/]+)/sign")
+        );
+        assert_eq!("/identities", template_path("/identities/"));
+        assert_eq!("/", template_path(""));
+    }
 }
@@ -0,0 +1,146 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cmp;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::header::HeaderMap;
+
+use edgelet_utils::RetryPolicy;
+
+/// How long requests wait after the first throttled response that doesn't
+/// say when to retry, unless the retry policy says otherwise. The wait doubles
+/// with every further throttled response.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Azure services send the time to wait in milliseconds in this header, in
+/// addition to the standard `Retry-After` header in seconds.
+const RETRY_AFTER_MS_HEADER: &str = "x-ms-retry-after-ms";
+
+/// The backoff state of a server that throttles requests. It is shared by all
+/// the clones of a client, so that concurrent requests all hold off once one
+/// of them is throttled instead of each hammering the server on its own.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    policy: RetryPolicy,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    resume_at: Option<Instant>,
+    throttled: u32,
+}
+
+impl Throttle {
+    pub(crate) fn new(policy: RetryPolicy) -> Self {
+        Throttle {
+            policy,
+            state: Mutex::new(State {
+                resume_at: None,
+                throttled: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// When requests may be sent again, if they are held off right now.
+    pub(crate) fn resume_at(&self) -> Option<Instant> {
+        self.lock()
+            .resume_at
+            .filter(|resume_at| *resume_at > Instant::now())
+    }
+
+    /// Records that a request was throttled, and returns how long requests
+    /// are held off. The server's `retry_after` is used if it sent one.
+    pub(crate) fn throttled(&self, retry_after: Option<Duration>) -> Duration {
+        let mut state = self.lock();
+        let wait = retry_after.unwrap_or_else(|| self.policy.delay(state.throttled));
+        state.throttled = state.throttled.saturating_add(1);
+
+        let resume_at = Instant::now() + wait;
+        state.resume_at = Some(
+            state
+                .resume_at
+                .map_or(resume_at, |current| cmp::max(current, resume_at)),
+        );
+        wait
+    }
+
+    /// Records that a request went through, so the next throttled response
+    /// that doesn't say when to retry starts over with the initial backoff.
+    pub(crate) fn succeeded(&self) {
+        self.lock().throttled = 0;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("Failed to acquire the throttle lock")
+    }
+}
+
+pub(crate) fn default_policy() -> RetryPolicy {
+    RetryPolicy::exponential(INITIAL_BACKOFF, MAX_BACKOFF)
+}
+
+/// Reads how long the server asks clients to wait from the headers of a
+/// throttled response.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+
+    header(RETRY_AFTER_MS_HEADER)
+        .map(Duration::from_millis)
+        .or_else(|| header(hyper::header::RETRY_AFTER.as_str()).map(Duration::from_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_prefers_milliseconds() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, retry_after(&headers));
+
+        headers.insert(hyper::header::RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(Some(Duration::from_secs(3)), retry_after(&headers));
+
+        headers.insert(RETRY_AFTER_MS_HEADER, "1500".parse().unwrap());
+        assert_eq!(Some(Duration::from_millis(1500)), retry_after(&headers));
+    }
+
+    #[test]
+    fn backoff_doubles_until_success() {
+        let throttle = Throttle::new(default_policy());
+        assert_eq!(None, throttle.resume_at());
+
+        assert_eq!(INITIAL_BACKOFF, throttle.throttled(None));
+        assert!(throttle.resume_at().is_some());
+        assert_eq!(INITIAL_BACKOFF * 2, throttle.throttled(None));
+        assert_eq!(
+            Duration::from_millis(10),
+            throttle.throttled(Some(Duration::from_millis(10)))
+        );
+        assert_eq!(INITIAL_BACKOFF * 8, throttle.throttled(None));
+
+        throttle.succeeded();
+        assert_eq!(INITIAL_BACKOFF, throttle.throttled(None));
+    }
+
+    #[test]
+    fn backoff_follows_policy() {
+        let throttle = Throttle::new(RetryPolicy::fixed(Duration::from_millis(500)));
+        assert_eq!(Duration::from_millis(500), throttle.throttled(None));
+        assert_eq!(Duration::from_millis(500), throttle.throttled(None));
+    }
+}
@@ -1,24 +1,38 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+#[cfg(unix)]
+use std::ffi::CString;
 use std::fs;
 #[cfg(unix)]
-use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::Path;
+#[cfg(unix)]
+use std::{mem, ptr};
 
 use failure::ResultExt;
 use log::debug;
 #[cfg(unix)]
 use nix::sys::stat::{umask, Mode};
 #[cfg(unix)]
+use nix::unistd::{chown, Gid, Uid};
+#[cfg(unix)]
 use scopeguard::defer;
 #[cfg(unix)]
 use tokio_uds::UnixListener;
 #[cfg(windows)]
 use tokio_uds_windows::UnixListener;
+use url::Url;
+
+use edgelet_core::{SocketPermissions, UrlExt, UNIX_SCHEME};
 
 use crate::error::{Error, ErrorKind};
 use crate::util::{incoming::Incoming, socket_file_exists};
 
+/// The size of the buffer the user and group databases fill in. It is the
+/// usual value of `_SC_GETPW_R_SIZE_MAX`, and more than enough for one entry.
+#[cfg(unix)]
+const NSS_BUFFER_LEN: usize = 16 * 1024;
+
 pub fn listener<P: AsRef<Path>>(path: P) -> Result<Incoming, Error> {
     let listener = if socket_file_exists(path.as_ref()) {
         // get the previous file's metadata
@@ -50,6 +64,121 @@ pub fn listener<P: AsRef<Path>>(path: P) -> Result<Incoming, Error> {
     Ok(listener)
 }
 
+/// Gives the socket file of the listener bound to `url` the mode, owner and
+/// group of `permissions`, so that access to it doesn't depend on the umask.
+/// Only `unix://` listeners have a socket file to change; the ones systemd
+/// passes in with `fd://` are created with the permissions of the socket unit.
+pub fn set_socket_permissions(url: &Url, permissions: &SocketPermissions) -> Result<(), Error> {
+    if url.scheme() != UNIX_SCHEME {
+        return Ok(());
+    }
+
+    let path = url
+        .to_uds_file_path()
+        .map_err(|_| ErrorKind::InvalidUrl(url.to_string()))?;
+    set_permissions(&path, permissions)
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &Path, permissions: &SocketPermissions) -> Result<(), Error> {
+    let owner = permissions.owner().map(user_id).transpose()?;
+    let group = permissions.group().map(group_id).transpose()?;
+    if owner.is_some() || group.is_some() {
+        debug!(
+            "changing owner of {} to {:?}:{:?}...",
+            path.display(),
+            owner,
+            group
+        );
+        chown(path, owner, group)
+            .with_context(|_| ErrorKind::SocketPermissions(path.display().to_string()))?;
+    }
+
+    if let Some(mode) = permissions.mode() {
+        debug!("setting permissions {:#o} for {}...", mode, path.display());
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|_| ErrorKind::SocketPermissions(path.display().to_string()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn set_permissions(path: &Path, permissions: &SocketPermissions) -> Result<(), Error> {
+    // Socket files on Windows are protected by their ACL, which unix modes and
+    // owners don't map to.
+    if permissions.mode().is_some()
+        || permissions.owner().is_some()
+        || permissions.group().is_some()
+    {
+        debug!(
+            "ignoring socket permissions for {} on Windows",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Looks up a user by its name, or takes it as a uid if it is a number.
+#[cfg(unix)]
+fn user_id(owner: &str) -> Result<Uid, Error> {
+    if let Ok(uid) = owner.parse() {
+        return Ok(Uid::from_raw(uid));
+    }
+
+    let name = CString::new(owner).map_err(|_| ErrorKind::UnknownUser(owner.to_string()))?;
+    let mut buf: Vec<libc::c_char> = vec![0; NSS_BUFFER_LEN];
+    // Safe because passwd is plain old data that getpwnam_r fills in.
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    // Safe because all the pointers outlive the call, and buf.len() is the
+    // length of buf.
+    let ret = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret == 0 && !result.is_null() {
+        Ok(Uid::from_raw(passwd.pw_uid))
+    } else {
+        Err(Error::from(ErrorKind::UnknownUser(owner.to_string())))
+    }
+}
+
+/// Looks up a group by its name, or takes it as a gid if it is a number.
+#[cfg(unix)]
+fn group_id(group: &str) -> Result<Gid, Error> {
+    if let Ok(gid) = group.parse() {
+        return Ok(Gid::from_raw(gid));
+    }
+
+    let name = CString::new(group).map_err(|_| ErrorKind::UnknownGroup(group.to_string()))?;
+    let mut buf: Vec<libc::c_char> = vec![0; NSS_BUFFER_LEN];
+    // Safe because group is plain old data that getgrnam_r fills in.
+    let mut entry: libc::group = unsafe { mem::zeroed() };
+    let mut result = ptr::null_mut();
+    // Safe because all the pointers outlive the call, and buf.len() is the
+    // length of buf.
+    let ret = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut entry,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret == 0 && !result.is_null() {
+        Ok(Gid::from_raw(entry.gr_gid))
+    } else {
+        Err(Error::from(ErrorKind::UnknownGroup(group.to_string())))
+    }
+}
+
 #[cfg(unix)]
 fn get_metadata(path: &Path) -> Result<fs::Metadata, Error> {
     let metadata =
@@ -110,4 +239,50 @@ mod tests {
 
         dir.close().unwrap();
     }
+
+    #[test]
+    fn test_set_socket_permissions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("permissions.sock");
+        let url = Url::from_file_path(&path).unwrap();
+        let url = Url::parse(&url.as_str().replacen("file", "unix", 1)).unwrap();
+
+        let listener = listener(&path).unwrap();
+        let _srv = listener.for_each(move |(_socket, _addr)| Ok(()));
+
+        let file_stat = stat(&path).unwrap();
+        let permissions: SocketPermissions = serde_json::from_value(serde_json::json!({
+            "mode": "0640",
+            "owner": file_stat.st_uid.to_string(),
+            "group": file_stat.st_gid.to_string(),
+        }))
+        .unwrap();
+        set_socket_permissions(&url, &permissions).unwrap();
+
+        let file_stat = stat(&path).unwrap();
+        assert_eq!(0o640, file_stat.st_mode & 0o777);
+
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn test_set_socket_permissions_unknown_group() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("unknown.sock");
+        let url = Url::from_file_path(&path).unwrap();
+        let url = Url::parse(&url.as_str().replacen("file", "unix", 1)).unwrap();
+
+        let listener = listener(&path).unwrap();
+        let _srv = listener.for_each(move |(_socket, _addr)| Ok(()));
+
+        let permissions: SocketPermissions =
+            serde_json::from_value(serde_json::json!({ "group": "no-such-group" })).unwrap();
+        let err = set_socket_permissions(&url, &permissions).unwrap_err();
+        assert_eq!(
+            &ErrorKind::UnknownGroup("no-such-group".to_string()),
+            err.kind()
+        );
+
+        dir.close().unwrap();
+    }
 }
@@ -3,6 +3,11 @@
 use std::fmt;
 use std::str::FromStr;
 
+use hyper::Request;
+use url::form_urlencoded::parse as parse_query;
+
+use crate::error::{Error, ErrorKind};
+
 pub const API_VERSION: Version = Version::Version2019_11_05;
 
 #[derive(Clone, Copy, Debug, PartialOrd, PartialEq)]
@@ -37,3 +42,20 @@ impl fmt::Display for Version {
         }
     }
 }
+
+/// The version of the API that a request asks for in its `api-version` query
+/// parameter.
+pub fn api_version<B>(req: &Request<B>) -> Result<Version, Error> {
+    let api_version = req.uri().query().and_then(|query| {
+        parse_query(query.as_bytes())
+            .find(|(key, _)| key == "api-version")
+            .map(|(_, api_version)| api_version.into_owned())
+    });
+
+    match api_version {
+        Some(api_version) => api_version
+            .parse()
+            .map_err(|()| Error::from(ErrorKind::InvalidApiVersion(api_version))),
+        None => Err(Error::from(ErrorKind::InvalidApiVersion(String::new()))),
+    }
+}
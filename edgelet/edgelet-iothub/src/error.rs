@@ -26,6 +26,15 @@ pub enum ErrorKind {
     #[fail(display = "{}", _0)]
     IdentityOperation(IdentityOperation),
 
+    #[fail(display = "Could not publish the diagnostics of the daemon")]
+    PublishDiagnostics,
+
+    #[fail(display = "Could not regenerate the keys of identity {}: {}", _0, _1)]
+    RegenerateIdentityWithReason(String, IdentityOperationReason),
+
+    #[fail(display = "Could not report the health of the daemon")]
+    ReportHealth,
+
     #[fail(display = "Could not update identity {}: {}", _0, _1)]
     UpdateIdentityWithReason(String, IdentityOperationReason),
 }
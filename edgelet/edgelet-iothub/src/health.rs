@@ -0,0 +1,110 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+/// The tag of a twin that the daemon reports its health in. The daemon owns
+/// the tag, and replaces it as a whole with every report.
+pub const HEALTH_TAG: &str = "iotedged";
+
+/// The health of the daemon, as it is reported in the hub so that it can be
+/// seen even while the edge runtime module is down.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonHealth {
+    version: String,
+    reported_at: DateTime<Utc>,
+    certificate_expirations: BTreeMap<String, DateTime<Utc>>,
+    warnings: Vec<String>,
+    edge_runtime: String,
+    watchdog_restarts: u64,
+}
+
+impl DaemonHealth {
+    pub fn new(version: String, reported_at: DateTime<Utc>) -> Self {
+        DaemonHealth {
+            version,
+            reported_at,
+            certificate_expirations: BTreeMap::new(),
+            warnings: vec![],
+            edge_runtime: String::new(),
+            watchdog_restarts: 0,
+        }
+    }
+
+    /// Records that certificate `name` expires at `expiration`.
+    pub fn with_certificate_expiration(mut self, name: &str, expiration: DateTime<Utc>) -> Self {
+        self.certificate_expirations
+            .insert(name.to_string(), expiration);
+        self
+    }
+
+    /// Problems with the host that the runtime noticed, e.g. that it is
+    /// running out of disk space.
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    /// The state of the edge runtime module as the watchdog sees it, and how
+    /// many times the watchdog started it since the daemon started.
+    pub fn with_watchdog(mut self, edge_runtime: String, watchdog_restarts: u64) -> Self {
+        self.edge_runtime = edge_runtime;
+        self.watchdog_restarts = watchdog_restarts;
+        self
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn reported_at(&self) -> DateTime<Utc> {
+        self.reported_at
+    }
+
+    pub fn certificate_expirations(&self) -> &BTreeMap<String, DateTime<Utc>> {
+        &self.certificate_expirations
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    pub fn edge_runtime(&self) -> &str {
+        &self.edge_runtime
+    }
+
+    pub fn watchdog_restarts(&self) -> u64 {
+        self.watchdog_restarts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn health_is_reported_in_camel_case() {
+        let health = DaemonHealth::new("1.0.9".to_string(), Utc.ymd(2019, 11, 5).and_hms(12, 0, 0))
+            .with_certificate_expiration("device_ca", Utc.ymd(2020, 2, 3).and_hms(0, 0, 0))
+            .with_warnings(vec!["Only 4.0% of disk space is free".to_string()])
+            .with_watchdog("healthy".to_string(), 2);
+
+        assert_eq!(
+            json!({
+                "version": "1.0.9",
+                "reportedAt": "2019-11-05T12:00:00Z",
+                "certificateExpirations": { "device_ca": "2020-02-03T00:00:00Z" },
+                "warnings": ["Only 4.0% of disk space is free"],
+                "edgeRuntime": "healthy",
+                "watchdogRestarts": 2,
+            }),
+            serde_json::to_value(&health).unwrap()
+        );
+    }
+}
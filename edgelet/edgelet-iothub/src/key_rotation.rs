@@ -0,0 +1,169 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde_derive::{Deserialize, Serialize};
+
+/// Name of the file under the iotedged home directory that holds the key
+/// rotations of the module identities.
+pub const KEY_ROTATIONS_FILENAME: &str = "identity_key_rotations.json";
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct KeyRotation {
+    hub_generation_id: String,
+    rotation: u32,
+}
+
+impl KeyRotation {
+    fn generation_id(&self) -> String {
+        format!("{}.{}", self.hub_generation_id, self.rotation)
+    }
+}
+
+/// The keys of a module identity are derived from its generation ID, which the
+/// hub only changes when the identity is recreated. To give an identity new
+/// keys without recreating it, its keys are instead derived from the generation
+/// ID with the number of times they were rotated appended, and that is the
+/// generation ID the identity is reported with.
+///
+/// The rotations are kept in a file, since the hub has no place for them. A
+/// rotation is dropped once the hub reports another generation ID for the
+/// identity, i.e. it was recreated.
+#[derive(Clone, Debug, Default)]
+pub struct KeyRotations {
+    rotations: Arc<Mutex<BTreeMap<String, KeyRotation>>>,
+    path: Option<PathBuf>,
+}
+
+impl KeyRotations {
+    /// Keeps the rotations in memory only.
+    pub fn new() -> Self {
+        KeyRotations::default()
+    }
+
+    /// Keeps the rotations in the file at `path`.
+    pub fn load(path: PathBuf) -> Self {
+        let rotations = fs::read(&path)
+            .ok()
+            .and_then(|rotations| serde_json::from_slice(&rotations).ok())
+            .unwrap_or_default();
+
+        KeyRotations {
+            rotations: Arc::new(Mutex::new(rotations)),
+            path: Some(path),
+        }
+    }
+
+    /// The generation ID the keys of the module identity `module_id` are
+    /// derived from, given the generation ID the hub has for it.
+    pub(crate) fn generation_id(&self, module_id: &str, hub_generation_id: &str) -> String {
+        self.lock()
+            .get(module_id)
+            .filter(|rotation| rotation.hub_generation_id == hub_generation_id)
+            .map_or_else(|| hub_generation_id.to_string(), KeyRotation::generation_id)
+    }
+
+    /// The generation ID the next keys of `module_id` are derived from. It
+    /// only takes effect once it is recorded with `record`.
+    pub(crate) fn next_generation_id(&self, module_id: &str, hub_generation_id: &str) -> String {
+        self.next_rotation(module_id, hub_generation_id)
+            .generation_id()
+    }
+
+    /// Records that the keys of `module_id` in the hub were rotated.
+    pub(crate) fn record(&self, module_id: &str, hub_generation_id: &str) -> io::Result<()> {
+        let rotation = self.next_rotation(module_id, hub_generation_id);
+        let mut rotations = self.lock();
+        rotations.insert(module_id.to_string(), rotation);
+        self.save(&rotations)
+    }
+
+    /// Forgets the rotations of `module_id`, once its identity is deleted.
+    pub(crate) fn remove(&self, module_id: &str) -> io::Result<()> {
+        let mut rotations = self.lock();
+        if rotations.remove(module_id).is_some() {
+            self.save(&rotations)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn next_rotation(&self, module_id: &str, hub_generation_id: &str) -> KeyRotation {
+        let rotation = self
+            .lock()
+            .get(module_id)
+            .filter(|rotation| rotation.hub_generation_id == hub_generation_id)
+            .map_or(1, |rotation| rotation.rotation + 1);
+        KeyRotation {
+            hub_generation_id: hub_generation_id.to_string(),
+            rotation,
+        }
+    }
+
+    fn save(&self, rotations: &BTreeMap<String, KeyRotation>) -> io::Result<()> {
+        match &self.path {
+            Some(path) => write_atomically(path, rotations),
+            None => Ok(()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, KeyRotation>> {
+        self.rotations
+            .lock()
+            .expect("Failed to acquire the key rotations lock")
+    }
+}
+
+fn write_atomically(path: &Path, rotations: &BTreeMap<String, KeyRotation>) -> io::Result<()> {
+    let contents = serde_json::to_vec(rotations)?;
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn rotations_change_the_generation_id() {
+        let rotations = KeyRotations::new();
+        assert_eq!("g1", rotations.generation_id("m1", "g1"));
+        assert_eq!("g1.1", rotations.next_generation_id("m1", "g1"));
+
+        rotations.record("m1", "g1").unwrap();
+        assert_eq!("g1.1", rotations.generation_id("m1", "g1"));
+        assert_eq!("g1.2", rotations.next_generation_id("m1", "g1"));
+        assert_eq!("g2", rotations.generation_id("m2", "g2"));
+
+        // The identity was recreated.
+        assert_eq!("g3", rotations.generation_id("m1", "g3"));
+        assert_eq!("g3.1", rotations.next_generation_id("m1", "g3"));
+
+        rotations.remove("m1").unwrap();
+        assert_eq!("g1", rotations.generation_id("m1", "g1"));
+    }
+
+    #[test]
+    fn rotations_are_kept_in_the_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(KEY_ROTATIONS_FILENAME);
+
+        let rotations = KeyRotations::load(path.clone());
+        rotations.record("m1", "g1").unwrap();
+        rotations.record("m1", "g1").unwrap();
+
+        let rotations = KeyRotations::load(path);
+        assert_eq!("g1.2", rotations.generation_id("m1", "g1"));
+    }
+}
@@ -10,31 +10,48 @@
 )]
 
 mod error;
+mod health;
+mod key_rotation;
 
+use std::collections::HashMap;
 use std::convert::AsRef;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use failure::{Fail, ResultExt};
 use futures::future::{self, Either};
-use futures::Future;
+use futures::sync::mpsc::UnboundedSender;
+use futures::{stream, Future, Stream};
+use log::{info, warn};
 use percent_encoding::{define_encode_set, percent_encode, PATH_SEGMENT_ENCODE_SET};
+use serde_json::json;
 use url::form_urlencoded::Serializer as UrlSerializer;
 
 use edgelet_core::crypto::{KeyIdentity, KeyStore, Sign, Signature, SignatureAlgorithm};
-use edgelet_core::{AuthType, Identity, IdentityManager, IdentityOperation, IdentitySpec};
+use edgelet_core::{
+    AuthType, ClockSkew, DiagnosticsReport, DiagnosticsSink, HealthReportTarget, Identity,
+    IdentityManager, IdentityOperation, IdentitySpec,
+};
 use edgelet_http::client::{ClientImpl, TokenSource};
 use iothubservice::{
-    AuthMechanism, AuthType as HubAuthType, DeviceClient, ErrorKind as HubErrorKind, Module,
-    ModuleOperationReason as HubReason, SymmetricKey,
+    AuthMechanism, AuthType as HubAuthType, DeviceClient, Error as HubError,
+    ErrorKind as HubErrorKind, Module, ModuleOperationReason as HubReason, SymmetricKey,
 };
 
 pub use crate::error::{Error, ErrorKind, IdentityOperationReason};
+pub use crate::health::{DaemonHealth, HEALTH_TAG};
+pub use crate::key_rotation::{KeyRotations, KEY_ROTATIONS_FILENAME};
 
 const KEY_PRIMARY: &str = "primary";
 const KEY_SECONDARY: &str = "secondary";
 
+/// The most requests a batch of identity operations has in flight at once.
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// A cached SAS token is renewed once it expires within this many seconds.
+const TOKEN_RENEWAL_MARGIN_SECS: i64 = 10 * 60;
+
 define_encode_set! {
     pub IOTHUB_ENCODE_SET = [PATH_SEGMENT_ENCODE_SET] | { '=' }
 }
@@ -42,11 +59,23 @@ define_encode_set! {
 #[derive(Debug, PartialEq, serde_derive::Serialize)]
 pub struct HubIdentity {
     hub_module: Module,
+    #[serde(skip)]
+    generation_id: Option<String>,
 }
 
 impl HubIdentity {
     pub fn new(hub_module: Module) -> HubIdentity {
-        HubIdentity { hub_module }
+        HubIdentity {
+            hub_module,
+            generation_id: None,
+        }
+    }
+
+    /// Reports the identity with `generation_id` instead of the generation ID
+    /// of the hub, since its keys were rotated.
+    pub fn with_generation_id(mut self, generation_id: String) -> HubIdentity {
+        self.generation_id = Some(generation_id);
+        self
     }
 
     pub fn hub_module(&self) -> &Module {
@@ -64,7 +93,11 @@ impl Identity for HubIdentity {
     }
 
     fn generation_id(&self) -> &str {
-        self.hub_module.generation_id().unwrap_or("")
+        self.generation_id
+            .as_ref()
+            .map(AsRef::as_ref)
+            .or_else(|| self.hub_module.generation_id())
+            .unwrap_or("")
     }
 
     fn auth_type(&self) -> AuthType {
@@ -92,8 +125,19 @@ where
 {
     key_store: K,
     client: DeviceClient<C, SasTokenSource<D>>,
+    // The modules as last read from or written to the hub, with their ETags.
+    cache: Mutex<HashMap<String, Module>>,
 }
 
+#[derive(Clone, Debug)]
+struct CachedToken {
+    token: String,
+    expiry: DateTime<Utc>,
+}
+
+/// Signs SAS tokens for the device with the device key. Signing with a key in
+/// the HSM can be slow, e.g. with a TPM, so tokens are cached per resource URI
+/// and only signed again when they are about to expire.
 pub struct SasTokenSource<K>
 where
     K: Sign + Clone,
@@ -101,6 +145,8 @@ where
     hub_id: String,
     device_id: String,
     key: K,
+    cache: Arc<Mutex<HashMap<String, CachedToken>>>,
+    clock_skew: Option<ClockSkew>,
 }
 
 impl<K> SasTokenSource<K>
@@ -112,23 +158,48 @@ where
             hub_id,
             device_id,
             key,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            clock_skew: None,
         }
     }
-}
 
-impl<K> TokenSource for SasTokenSource<K>
-where
-    K: Sign + Clone,
-{
-    type Error = Error;
+    /// Renews cached tokens by the time of the server rather than the local
+    /// one. The expiries of the tokens come from the client, which has to
+    /// share `clock_skew`.
+    pub fn with_clock_skew(mut self, clock_skew: ClockSkew) -> Self {
+        self.clock_skew = Some(clock_skew);
+        self
+    }
 
-    fn get(&self, expiry: &DateTime<Utc>) -> Result<String, Error> {
-        let expiry = expiry.timestamp().to_string();
+    fn get_at(&self, now: DateTime<Utc>, expiry: &DateTime<Utc>) -> Result<String, Error> {
         let audience = format!("{}/devices/{}", self.hub_id, self.device_id);
-
         let resource_uri =
             percent_encode(audience.to_lowercase().as_bytes(), IOTHUB_ENCODE_SET).to_string();
-        let sig_data = format!("{}\n{}", &resource_uri, expiry);
+
+        let mut cache = self
+            .cache
+            .lock()
+            .expect("Failed to acquire the SAS token cache lock");
+        if let Some(cached) = cache.get(&resource_uri) {
+            if cached.expiry - now > Duration::seconds(TOKEN_RENEWAL_MARGIN_SECS) {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let token = self.sign(&resource_uri, expiry)?;
+        cache.insert(
+            resource_uri,
+            CachedToken {
+                token: token.clone(),
+                expiry: *expiry,
+            },
+        );
+        Ok(token)
+    }
+
+    fn sign(&self, resource_uri: &str, expiry: &DateTime<Utc>) -> Result<String, Error> {
+        let expiry = expiry.timestamp().to_string();
+        let sig_data = format!("{}\n{}", resource_uri, expiry);
 
         let signature = self
             .key
@@ -144,6 +215,21 @@ where
     }
 }
 
+impl<K> TokenSource for SasTokenSource<K>
+where
+    K: Sign + Clone,
+{
+    type Error = Error;
+
+    fn get(&self, expiry: &DateTime<Utc>) -> Result<String, Error> {
+        let now = self
+            .clock_skew
+            .as_ref()
+            .map_or_else(Utc::now, ClockSkew::now);
+        self.get_at(now, expiry)
+    }
+}
+
 impl<K> Clone for SasTokenSource<K>
 where
     K: Sign + Clone,
@@ -153,6 +239,8 @@ where
             hub_id: self.hub_id.clone(),
             device_id: self.device_id.clone(),
             key: self.key.clone(),
+            cache: self.cache.clone(),
+            clock_skew: self.clock_skew.clone(),
         }
     }
 }
@@ -165,6 +253,8 @@ where
     D: 'static + Sign + Clone,
 {
     state: Arc<State<K, C, D>>,
+    reprovision: Option<UnboundedSender<()>>,
+    key_rotations: KeyRotations,
     phantom: PhantomData<D>,
 }
 
@@ -177,11 +267,88 @@ where
 {
     pub fn new(key_store: K, client: DeviceClient<C, SasTokenSource<D>>) -> Self {
         HubIdentityManager {
-            state: Arc::new(State { key_store, client }),
+            state: Arc::new(State {
+                key_store,
+                client,
+                cache: Mutex::new(HashMap::new()),
+            }),
+            reprovision: None,
+            key_rotations: KeyRotations::new(),
             phantom: PhantomData,
         }
     }
 
+    /// Keeps the key rotations of the identities in `key_rotations`, which
+    /// should outlive the daemon.
+    pub fn with_key_rotations(mut self, key_rotations: KeyRotations) -> Self {
+        self.key_rotations = key_rotations;
+        self
+    }
+
+    /// Signals `reprovision` when the hub reports that the device has been
+    /// disabled or no longer exists, so that the daemon can register the
+    /// device again.
+    pub fn with_reprovision_signal(mut self, reprovision: UnboundedSender<()>) -> Self {
+        self.reprovision = Some(reprovision);
+        self
+    }
+
+    fn watch_device<F>(&self, future: F) -> impl Future<Item = F::Item, Error = Error>
+    where
+        F: Future<Error = Error>,
+    {
+        let reprovision = self.reprovision.clone();
+        future.map_err(move |err| {
+            if let Some(reprovision) = reprovision {
+                let device_unavailable = Fail::iter_chain(&err)
+                    .filter_map(|cause| cause.downcast_ref::<HubError>())
+                    .any(HubError::is_device_unavailable);
+                if device_unavailable {
+                    warn!("IoT Hub reports that this device is disabled or no longer exists. Reprovisioning the device...");
+                    let _ = reprovision.unbounded_send(());
+                }
+            }
+            err
+        })
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<String, Module>> {
+        self.state
+            .cache
+            .lock()
+            .expect("Failed to acquire the module cache lock")
+    }
+
+    fn cache_module(&self, module: &Module) {
+        if let Some(module_id) = module.module_id() {
+            self.cache().insert(module_id.to_string(), module.clone());
+        }
+    }
+
+    fn cached_etag(&self, module_id: &str) -> Option<String> {
+        self.cache()
+            .get(module_id)
+            .and_then(Module::etag)
+            .map(ToString::to_string)
+    }
+
+    // The generation ID the keys of `module` are derived from.
+    fn generation_id(&self, module: &Module) -> Option<String> {
+        let module_id = module.module_id()?;
+        let hub_generation_id = module.generation_id()?;
+        Some(
+            self.key_rotations
+                .generation_id(module_id, hub_generation_id),
+        )
+    }
+
+    fn identity(&self, module: Module) -> HubIdentity {
+        match self.generation_id(&module) {
+            Some(generation_id) => HubIdentity::new(module).with_generation_id(generation_id),
+            None => HubIdentity::new(module),
+        }
+    }
+
     fn get_key_pair(&self, id: &str, generation_id: &str) -> Result<(K::Key, K::Key), Error> {
         self.state
             .key_store
@@ -203,6 +370,159 @@ where
     }
 }
 
+impl<K, C, D> HubIdentityManager<K, C, D>
+where
+    K: 'static + KeyStore + Send + Sync,
+    K::Key: AsRef<[u8]> + Clone + Send,
+    C: 'static + ClientImpl,
+    D: 'static + Sign + Clone + Send + Sync,
+{
+    /// Creates the identities of `ids`. The hub takes module identities one
+    /// at a time, so the identities are created with concurrent requests.
+    pub fn create_many(
+        &self,
+        ids: Vec<IdentitySpec>,
+    ) -> impl Future<Item = Vec<HubIdentity>, Error = Error> + Send {
+        let idman = self.clone();
+        stream::iter_ok(ids)
+            .map(move |id| idman.clone().create(id))
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .collect()
+    }
+
+    /// Updates the identities of `ids` with concurrent requests.
+    pub fn update_many(
+        &self,
+        ids: Vec<IdentitySpec>,
+    ) -> impl Future<Item = Vec<HubIdentity>, Error = Error> + Send {
+        let idman = self.clone();
+        stream::iter_ok(ids)
+            .map(move |id| idman.clone().update(id))
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .collect()
+    }
+
+    /// Deletes the identities of `ids` with concurrent requests.
+    pub fn delete_many(
+        &self,
+        ids: Vec<IdentitySpec>,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let idman = self.clone();
+        stream::iter_ok(ids)
+            .map(move |id| idman.clone().delete(id))
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .for_each(|()| Ok(()))
+    }
+
+    /// Reports `health` in the tag `HEALTH_TAG` of the twin of `target`,
+    /// where `agent_id` is the module ID of the edge runtime module.
+    pub fn report_health(
+        &self,
+        target: HealthReportTarget,
+        agent_id: &str,
+        health: &DaemonHealth,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        let module_id = match target {
+            HealthReportTarget::DeviceTwin => None,
+            HealthReportTarget::AgentTwin => Some(agent_id),
+        };
+        // The tag is cleared before it is set, so that certificates and
+        // warnings that are gone don't linger from an earlier report.
+        let tags = json!({ HEALTH_TAG: null });
+        let health = json!({ HEALTH_TAG: health });
+        let client = self.state.client.clone();
+        let module_id_owned = module_id.map(ToString::to_string);
+
+        let report = self
+            .state
+            .client
+            .update_twin_tags(module_id, &tags)
+            .and_then(move |()| {
+                client.update_twin_tags(module_id_owned.as_ref().map(AsRef::as_ref), &health)
+            })
+            .map_err(|err| Error::from(err.context(ErrorKind::ReportHealth)));
+        self.watch_device(report)
+    }
+
+    // Updates the module with the ETag it had when it was last read, so that
+    // keys derived from its generation ID aren't written to a module that was
+    // recreated in the meantime. If the module was changed in another way, the
+    // update is repeated with its current ETag.
+    fn update_module(
+        &self,
+        module_id: String,
+        generation_id: String,
+        auth: AuthMechanism,
+        managed_by: Option<String>,
+    ) -> Box<dyn Future<Item = HubIdentity, Error = Error> + Send> {
+        let idman = self.clone();
+        let etag = self.cached_etag(&module_id);
+        let retry_auth = auth.clone();
+        let operation_id = module_id.clone();
+
+        let update = self
+            .upsert_module(
+                module_id.clone(),
+                auth,
+                managed_by.as_ref().map(AsRef::as_ref),
+                etag,
+            )
+            .or_else(move |err| -> Box<dyn Future<Item = _, Error = _> + Send> {
+                match err.kind() {
+                    HubErrorKind::UpsertModuleWithReason(_, HubReason::ModuleChanged) => (),
+                    _ => return Box::new(future::err(err)),
+                }
+
+                idman.cache().remove(&module_id);
+                let retry = idman.clone();
+                Box::new(
+                    idman
+                        .state
+                        .client
+                        .get_module_by_id(module_id.clone())
+                        .and_then(move |module| {
+                            if retry.generation_id(&module).as_ref() == Some(&generation_id) {
+                                let etag = module.etag().map(ToString::to_string);
+                                Either::A(retry.upsert_module(
+                                    module_id,
+                                    retry_auth,
+                                    managed_by.as_ref().map(AsRef::as_ref),
+                                    etag,
+                                ))
+                            } else {
+                                Either::B(future::err(err))
+                            }
+                        }),
+                )
+            });
+
+        let idman = self.clone();
+        Box::new(update.then(move |module| {
+            let module = module.with_context(|_| {
+                ErrorKind::IdentityOperation(IdentityOperation::UpdateIdentity(operation_id))
+            })?;
+            idman.cache_module(&module);
+            Ok(idman.identity(module))
+        }))
+    }
+
+    fn upsert_module(
+        &self,
+        module_id: String,
+        auth: AuthMechanism,
+        managed_by: Option<&str>,
+        etag: Option<String>,
+    ) -> impl Future<Item = Module, Error = HubError> + Send {
+        let client = &self.state.client;
+        match etag {
+            Some(etag) => {
+                Either::A(client.update_module_if_match(module_id, Some(auth), managed_by, &etag))
+            }
+            None => Either::B(client.update_module(module_id, Some(auth), managed_by)),
+        }
+    }
+}
+
 fn build_key_name(key_name: &str, generation_id: &str) -> String {
     format!("{}{}", key_name, generation_id)
 }
@@ -217,11 +537,36 @@ where
     fn clone(&self) -> Self {
         HubIdentityManager {
             state: self.state.clone(),
+            reprovision: self.reprovision.clone(),
+            key_rotations: self.key_rotations.clone(),
             phantom: PhantomData,
         }
     }
 }
 
+// The diagnostics are sent as a device-to-cloud message of the device, so they
+// end up wherever the routes of the hub send the messages of the device.
+impl<K, C, D> DiagnosticsSink for HubIdentityManager<K, C, D>
+where
+    K: 'static + KeyStore + Send + Sync,
+    K::Key: AsRef<[u8]> + Clone + Send,
+    C: 'static + ClientImpl,
+    D: 'static + Sign + Clone + Send + Sync,
+{
+    type Error = Error;
+    type PublishFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+
+    fn publish(&self, report: &DiagnosticsReport) -> Self::PublishFuture {
+        let event = json!(report);
+        let publish = self
+            .state
+            .client
+            .send_event(&event)
+            .map_err(|err| Error::from(err.context(ErrorKind::PublishDiagnostics)));
+        Box::new(self.watch_device(publish))
+    }
+}
+
 impl<K, C, D> IdentityManager for HubIdentityManager<K, C, D>
 where
     K: 'static + KeyStore + Send + Sync,
@@ -236,6 +581,7 @@ where
     type ListFuture = Box<dyn Future<Item = Vec<Self::Identity>, Error = Self::Error> + Send>;
     type GetFuture = Box<dyn Future<Item = Option<Self::Identity>, Error = Self::Error> + Send>;
     type DeleteFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RegenerateFuture = Box<dyn Future<Item = Self::Identity, Error = Self::Error> + Send>;
 
     fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
         // This code first creates a module in the hub with the auth type
@@ -245,37 +591,39 @@ where
         // the hub.
         let idman = self.clone();
         let module_id = id.module_id().to_string();
-        Box::new(
-            self.state
-                .client
-                .create_module(
-                    module_id.clone(),
-                    Some(AuthMechanism::default().with_type(HubAuthType::None)),
-                    id.managed_by(),
-                )
-                .then(|module| {
-                    let module = module.with_context(|_| {
-                        ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
-                            module_id.clone(),
-                        ))
-                    })?;
-
-                    if let (Some(module_id2), Some(generation_id)) =
-                        (module.module_id(), module.generation_id())
-                    {
-                        idman.get_key_pair(module_id2, generation_id).map(
-                            |(primary_key, secondary_key)| {
-                                (primary_key, secondary_key, idman, module_id)
-                            },
-                        )
-                    } else {
-                        Err(Error::from(ErrorKind::CreateIdentityWithReason(
-                            module_id,
-                            IdentityOperationReason::InvalidHubResponse,
-                        )))
-                    }
-                })
-                .and_then(move |(primary_key, secondary_key, idman, module_id)| {
+        let create = self
+            .state
+            .client
+            .create_module(
+                module_id.clone(),
+                Some(AuthMechanism::default().with_type(HubAuthType::None)),
+                id.managed_by(),
+            )
+            .then(|module| {
+                let module = module.with_context(|_| {
+                    ErrorKind::IdentityOperation(IdentityOperation::CreateIdentity(
+                        module_id.clone(),
+                    ))
+                })?;
+
+                if let (Some(module_id2), Some(generation_id)) =
+                    (module.module_id(), module.generation_id())
+                {
+                    let etag = module.etag().map(ToString::to_string);
+                    idman.get_key_pair(module_id2, generation_id).map(
+                        |(primary_key, secondary_key)| {
+                            (primary_key, secondary_key, etag, idman, module_id)
+                        },
+                    )
+                } else {
+                    Err(Error::from(ErrorKind::CreateIdentityWithReason(
+                        module_id,
+                        IdentityOperationReason::InvalidHubResponse,
+                    )))
+                }
+            })
+            .and_then(
+                move |(primary_key, secondary_key, etag, idman, module_id)| {
                     let auth = AuthMechanism::default()
                         .with_type(HubAuthType::Sas)
                         .with_symmetric_key(
@@ -284,26 +632,35 @@ where
                                 .with_secondary_key(base64::encode(secondary_key.as_ref())),
                         );
 
+                    // The keys are derived from the generation ID of the module that
+                    // was just created, so they must not be written to a module that
+                    // was recreated in the meantime.
                     idman
-                        .state
-                        .client
-                        .update_module(id.module_id().to_string(), Some(auth), id.managed_by())
+                        .upsert_module(id.module_id().to_string(), auth, id.managed_by(), etag)
                         .map_err(|err| {
                             Error::from(err.context(ErrorKind::CreateIdentityWithReason(
                                 module_id,
                                 IdentityOperationReason::InvalidHubResponse,
                             )))
                         })
-                        .map(HubIdentity::new)
-                }),
-        )
+                        .map(move |module| {
+                            idman.cache_module(&module);
+                            idman.identity(module)
+                        })
+                },
+            );
+
+        Box::new(self.watch_device(create))
     }
 
     fn update(&mut self, id: IdentitySpec) -> Self::UpdateFuture {
         let module_id = id.module_id().to_string();
 
         let result = if let Some(generation_id) = id.generation_id() {
-            match self.get_key_pair(&module_id, generation_id) {
+            // A caller that still has the generation ID of the hub for an
+            // identity with rotated keys mustn't put the old keys back.
+            let generation_id = self.key_rotations.generation_id(&module_id, generation_id);
+            match self.get_key_pair(&module_id, &generation_id) {
                 Ok((primary_key, secondary_key)) => {
                     let auth = AuthMechanism::default()
                         .with_type(HubAuthType::Sas)
@@ -313,17 +670,12 @@ where
                                 .with_secondary_key(base64::encode(secondary_key.as_ref())),
                         );
 
-                    Either::A(
-                        self.state
-                            .client
-                            .update_module(module_id.clone(), Some(auth), id.managed_by())
-                            .map_err(|err| {
-                                Error::from(err.context(ErrorKind::IdentityOperation(
-                                    IdentityOperation::UpdateIdentity(module_id),
-                                )))
-                            })
-                            .map(HubIdentity::new),
-                    )
+                    Either::A(self.update_module(
+                        module_id,
+                        generation_id,
+                        auth,
+                        id.managed_by().map(ToString::to_string),
+                    ))
                 }
 
                 Err(err) => Either::B(future::err(err)),
@@ -337,52 +689,170 @@ where
             )))
         };
 
-        Box::new(result)
+        Box::new(self.watch_device(result))
     }
 
     fn list(&self) -> Self::ListFuture {
         Box::new(
-            self.state
-                .client
-                .list_modules()
-                .map_err(|err| {
-                    Error::from(err.context(ErrorKind::IdentityOperation(
-                        IdentityOperation::ListIdentities,
-                    )))
-                })
-                .map(|modules| modules.into_iter().map(HubIdentity::new).collect()),
+            self.watch_device(
+                self.state
+                    .client
+                    .list_modules()
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::IdentityOperation(
+                            IdentityOperation::ListIdentities,
+                        )))
+                    })
+                    .map({
+                        let idman = self.clone();
+                        move |modules| {
+                            let mut cache = idman.cache();
+                            cache.clear();
+                            for module in &modules {
+                                if let Some(module_id) = module.module_id() {
+                                    cache.insert(module_id.to_string(), module.clone());
+                                }
+                            }
+                            modules
+                                .into_iter()
+                                .map(|module| idman.identity(module))
+                                .collect()
+                        }
+                    }),
+            ),
         )
     }
 
     fn get(&self, id: IdentitySpec) -> Self::GetFuture {
         let module_id = id.module_id().to_string();
+        let idman = self.clone();
 
-        Box::new(self.state.client.get_module_by_id(module_id.clone()).then(
-            |module| match module {
-                Ok(module) => Ok(Some(HubIdentity::new(module))),
-                Err(err) => {
-                    if let HubErrorKind::GetModuleWithReason(_, HubReason::ModuleNotFound) =
-                        err.kind()
-                    {
-                        Ok(None)
-                    } else {
-                        Err(Error::from(err.context(ErrorKind::IdentityOperation(
-                            IdentityOperation::GetIdentity(module_id),
-                        ))))
+        let module =
+            self.state.client.get_module_by_id(module_id.clone()).then(
+                move |module| match module {
+                    Ok(module) => {
+                        idman.cache_module(&module);
+                        Ok(Some(idman.identity(module)))
                     }
-                }
-            },
-        ))
+                    Err(err) => {
+                        if let HubErrorKind::GetModuleWithReason(_, HubReason::ModuleNotFound) =
+                            err.kind()
+                        {
+                            idman.cache().remove(&module_id);
+                            Ok(None)
+                        } else {
+                            Err(Error::from(err.context(ErrorKind::IdentityOperation(
+                                IdentityOperation::GetIdentity(module_id),
+                            ))))
+                        }
+                    }
+                },
+            );
+
+        Box::new(self.watch_device(module))
     }
 
     fn delete(&mut self, id: IdentitySpec) -> Self::DeleteFuture {
         let module_id = id.module_id().to_string();
+        self.cache().remove(&module_id);
 
-        Box::new(self.state.client.delete_module(&module_id).map_err(|err| {
-            Error::from(err.context(ErrorKind::IdentityOperation(
-                IdentityOperation::DeleteIdentity(module_id),
-            )))
-        }))
+        let key_rotations = self.key_rotations.clone();
+        let delete =
+            self.state
+                .client
+                .delete_module(&module_id)
+                .then(move |result| -> Result<_, Error> {
+                    result.with_context(|_| {
+                        ErrorKind::IdentityOperation(IdentityOperation::DeleteIdentity(
+                            module_id.clone(),
+                        ))
+                    })?;
+                    if let Err(err) = key_rotations.remove(&module_id) {
+                        warn!(
+                            "Could not forget the key rotations of identity {}: {}",
+                            module_id, err
+                        );
+                    }
+                    Ok(())
+                });
+
+        Box::new(self.watch_device(delete))
+    }
+
+    fn regenerate(&mut self, id: IdentitySpec) -> Self::RegenerateFuture {
+        // The new keys are derived from a generation ID that is only recorded
+        // once the hub has them, so that a failed update leaves the identity
+        // with its old keys.
+        let idman = self.clone();
+        let module_id = id.module_id().to_string();
+        let operation_id = module_id.clone();
+        let regenerate = self
+            .state
+            .client
+            .get_module_by_id(module_id.clone())
+            .then(move |module| -> Result<_, Error> {
+                let module = module.with_context(|_| {
+                    ErrorKind::IdentityOperation(IdentityOperation::RegenerateIdentity(
+                        module_id.clone(),
+                    ))
+                })?;
+                let hub_generation_id = module
+                    .generation_id()
+                    .map(ToString::to_string)
+                    .ok_or_else(|| {
+                        Error::from(ErrorKind::RegenerateIdentityWithReason(
+                            module_id.clone(),
+                            IdentityOperationReason::InvalidHubResponse,
+                        ))
+                    })?;
+                let generation_id = idman
+                    .key_rotations
+                    .next_generation_id(&module_id, &hub_generation_id);
+                let (primary_key, secondary_key) =
+                    idman.get_key_pair(&module_id, &generation_id)?;
+                let auth = AuthMechanism::default()
+                    .with_type(HubAuthType::Sas)
+                    .with_symmetric_key(
+                        SymmetricKey::default()
+                            .with_primary_key(base64::encode(primary_key.as_ref()))
+                            .with_secondary_key(base64::encode(secondary_key.as_ref())),
+                    );
+
+                // The keys must not be written to an identity that was
+                // recreated since it was read.
+                let update = idman
+                    .upsert_module(
+                        module_id.clone(),
+                        auth,
+                        module.managed_by(),
+                        module.etag().map(ToString::to_string),
+                    )
+                    .then(move |module| {
+                        let module = module.with_context(|_| {
+                            ErrorKind::IdentityOperation(IdentityOperation::RegenerateIdentity(
+                                module_id.clone(),
+                            ))
+                        })?;
+                        idman
+                            .key_rotations
+                            .record(&module_id, &hub_generation_id)
+                            .with_context(|_| {
+                                ErrorKind::IdentityOperation(IdentityOperation::RegenerateIdentity(
+                                    module_id.clone(),
+                                ))
+                            })?;
+                        idman.cache_module(&module);
+                        Ok(idman.identity(module))
+                    });
+                Ok(update)
+            })
+            .flatten()
+            .map(move |identity| {
+                info!("Regenerated the keys of identity {}", operation_id);
+                identity
+            });
+
+        Box::new(self.watch_device(regenerate))
     }
 }
 
@@ -392,12 +862,13 @@ mod tests {
 
     use bytes::Bytes;
     use chrono::TimeZone;
+    use futures::sync::mpsc;
     use futures::Stream;
     use hyper::{self, Body, Method, Request, Response, StatusCode};
     use typed_headers::{mime, ContentType, HeaderMapExt};
     use url::Url;
 
-    use edgelet_core::crypto::{MemoryKey, MemoryKeyStore};
+    use edgelet_core::crypto::{DerivedKeyStore, MemoryKey, MemoryKeyStore};
     use edgelet_http::client::Client;
 
     #[test]
@@ -607,6 +1078,142 @@ mod tests {
         assert_eq!(hub_identity.hub_module(), &expected_module_result);
     }
 
+    // Lists the modules, then updates m1 while the hub reports that m1 was
+    // changed since it was listed, and now has the generation ID
+    // `current_generation_id`.
+    fn update_changed_module(
+        current_generation_id: &'static str,
+    ) -> (Result<HubIdentity, Error>, Vec<String>) {
+        let mut key_store = MemoryKeyStore::new();
+        key_store.insert(
+            &KeyIdentity::Module("m1".to_string()),
+            &format!("{}{}", KEY_PRIMARY, "g1"),
+            MemoryKey::new("pkey"),
+        );
+        key_store.insert(
+            &KeyIdentity::Module("m1".to_string()),
+            &format!("{}{}", KEY_SECONDARY, "g1"),
+            MemoryKey::new("skey"),
+        );
+
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler = {
+            let requests = requests.clone();
+            move |req: Request<Body>| {
+                let if_match = req
+                    .headers()
+                    .get(hyper::header::IF_MATCH)
+                    .map(|value| value.to_str().unwrap().to_string());
+                requests.lock().unwrap().push(format!(
+                    "{} {} {}",
+                    req.method(),
+                    req.uri().path(),
+                    if_match.clone().unwrap_or_default()
+                ));
+
+                let module = Module::default()
+                    .with_device_id("d1".to_string())
+                    .with_module_id("m1".to_string());
+                let (status, body) = match (req.method(), if_match.as_ref().map(AsRef::as_ref)) {
+                    (&Method::GET, _) if req.uri().path() == "/devices/d1/modules" => (
+                        StatusCode::OK,
+                        serde_json::to_string(&vec![module
+                            .with_generation_id("g1".to_string())
+                            .with_etag("e1".to_string())])
+                        .unwrap(),
+                    ),
+                    (&Method::GET, _) => (
+                        StatusCode::OK,
+                        serde_json::to_string(
+                            &module
+                                .with_generation_id(current_generation_id.to_string())
+                                .with_etag("e2".to_string()),
+                        )
+                        .unwrap(),
+                    ),
+                    (&Method::PUT, Some("e1")) => (
+                        StatusCode::PRECONDITION_FAILED,
+                        r#"{"Message":"ErrorCode:PreconditionFailed;Precondition failed"}"#
+                            .to_string(),
+                    ),
+                    (&Method::PUT, _) => (
+                        StatusCode::OK,
+                        serde_json::to_string(
+                            &module
+                                .with_generation_id("g1".to_string())
+                                .with_etag("e3".to_string()),
+                        )
+                        .unwrap(),
+                    ),
+                    _ => panic!("unexpected request {:?}", req),
+                };
+
+                let mut response = Response::new(Body::from(body));
+                *response.status_mut() = status;
+                response
+                    .headers_mut()
+                    .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                Ok(response)
+            }
+        };
+
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(
+            handler,
+            Some(token_source),
+            "2018-04-10".to_string(),
+            Url::parse("http://localhost").unwrap(),
+        )
+        .unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let mut identity_manager = HubIdentityManager::new(key_store, device_client);
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.block_on(identity_manager.list()).unwrap();
+        let result = runtime.block_on(
+            identity_manager
+                .update(IdentitySpec::new("m1".to_string()).with_generation_id("g1".to_string())),
+        );
+
+        let requests = requests.lock().unwrap().clone();
+        (result, requests)
+    }
+
+    #[test]
+    fn update_repeats_with_current_etag_when_module_changed() {
+        let (result, requests) = update_changed_module("g1");
+
+        assert_eq!(Some("e3"), result.unwrap().hub_module().etag());
+        assert_eq!(
+            vec![
+                "GET /devices/d1/modules ",
+                "PUT /devices/d1/modules/m1 e1",
+                "GET /devices/d1/modules/m1 ",
+                "PUT /devices/d1/modules/m1 e2",
+            ],
+            requests
+        );
+    }
+
+    #[test]
+    fn update_fails_when_module_was_recreated() {
+        let (result, requests) = update_changed_module("g2");
+
+        assert!(result.is_err());
+        assert_eq!(
+            vec![
+                "GET /devices/d1/modules ",
+                "PUT /devices/d1/modules/m1 e1",
+                "GET /devices/d1/modules/m1 ",
+            ],
+            requests
+        );
+    }
+
     #[test]
     fn list_succeeds() {
         let m1pkey = "m1pkey";
@@ -729,6 +1336,104 @@ mod tests {
         }
     }
 
+    #[test]
+    fn regenerate_rotates_keys() {
+        let key_store = DerivedKeyStore::new(MemoryKey::new("root"));
+        let expected_primary_key = key_store
+            .get(
+                &KeyIdentity::Module("m1".to_string()),
+                &build_key_name(KEY_PRIMARY, "g1.1"),
+            )
+            .map(|key| base64::encode(key.as_ref()))
+            .unwrap();
+
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler = {
+            let requests = requests.clone();
+            move |req: Request<Body>| {
+                let if_match = req
+                    .headers()
+                    .get(hyper::header::IF_MATCH)
+                    .map(|value| value.to_str().unwrap().to_string());
+                requests.lock().unwrap().push(format!(
+                    "{} {} {}",
+                    req.method(),
+                    req.uri().path(),
+                    if_match.unwrap_or_default()
+                ));
+
+                let module = Module::default()
+                    .with_device_id("d1".to_string())
+                    .with_module_id("m1".to_string())
+                    .with_generation_id("g1".to_string())
+                    .with_managed_by("iotedge".to_string());
+                let response = if req.method() == Method::GET {
+                    Either::A(future::ok::<_, hyper::Error>(
+                        module.with_etag("e1".to_string()),
+                    ))
+                } else {
+                    Either::B(req.into_body().concat2().map(|body| {
+                        let update: Module = serde_json::from_slice(&body).unwrap();
+                        module
+                            .with_etag("e2".to_string())
+                            .with_authentication(update.authentication().unwrap().clone())
+                    }))
+                };
+                response.map(|module| {
+                    let mut response =
+                        Response::new(Body::from(serde_json::to_string(&module).unwrap()));
+                    response
+                        .headers_mut()
+                        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                    response
+                })
+            }
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(
+            handler,
+            Some(token_source),
+            "2018-04-10".to_string(),
+            Url::parse("http://localhost").unwrap(),
+        )
+        .unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let mut identity_manager = HubIdentityManager::new(key_store, device_client);
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let identity = runtime
+            .block_on(identity_manager.regenerate(IdentitySpec::new("m1".to_string())))
+            .unwrap();
+
+        assert_eq!("g1.1", identity.generation_id());
+        assert_eq!(
+            Some(&*expected_primary_key),
+            identity
+                .hub_module()
+                .authentication()
+                .and_then(AuthMechanism::symmetric_key)
+                .and_then(SymmetricKey::primary_key)
+        );
+        assert_eq!(
+            vec![
+                "GET /devices/d1/modules/m1 ",
+                "PUT /devices/d1/modules/m1 e1",
+            ],
+            *requests.lock().unwrap()
+        );
+
+        // The identity keeps the new generation ID when it is read again.
+        let identity = runtime
+            .block_on(identity_manager.get(IdentitySpec::new("m1".to_string())))
+            .unwrap()
+            .unwrap();
+        assert_eq!("g1.1", identity.generation_id());
+    }
+
     #[test]
     fn get_succeeds() {
         let m1pkey = "m1pkey";
@@ -839,6 +1544,74 @@ mod tests {
         assert_eq!(None, hub_identity);
     }
 
+    #[test]
+    fn get_device_not_found_signals_reprovision() {
+        let key_store = MemoryKeyStore::new();
+
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(r#"{"Message":"ErrorCode:DeviceNotFound;Device d1 not found"}"#.into())
+                .expect("could not build hyper::Response");
+            Ok(response)
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(handler, Some(token_source), api_version, host_name).unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let (tx, rx) = mpsc::unbounded();
+        let identity_manager =
+            HubIdentityManager::new(key_store, device_client).with_reprovision_signal(tx);
+        let task = identity_manager.get(IdentitySpec::new("m1".to_string()));
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.block_on(task).unwrap_err();
+        drop(identity_manager);
+        let signals = runtime.block_on(rx.collect()).unwrap();
+        assert_eq!(1, signals.len());
+    }
+
+    #[test]
+    fn list_server_error_does_not_signal_reprovision() {
+        let key_store = MemoryKeyStore::new();
+
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let response = Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .expect("could not build hyper::Response");
+            Ok(response)
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(handler, Some(token_source), api_version, host_name).unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let (tx, rx) = mpsc::unbounded();
+        let identity_manager =
+            HubIdentityManager::new(key_store, device_client).with_reprovision_signal(tx);
+        let task = identity_manager.list();
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        runtime.block_on(task).unwrap_err();
+        drop(identity_manager);
+        let signals = runtime.block_on(rx.collect()).unwrap();
+        assert!(signals.is_empty());
+    }
+
     #[test]
     fn delete_succeeds() {
         let key_store = MemoryKeyStore::new();
@@ -871,6 +1644,59 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn report_health_replaces_tag_of_agent_twin() {
+        let key_store = MemoryKeyStore::new();
+
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let patches = Arc::new(Mutex::new(vec![]));
+        let received = patches.clone();
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::PATCH);
+            assert_eq!(req.uri().path(), "/twins/d1/modules/$edgeAgent");
+
+            let received = received.clone();
+            req.into_body().concat2().and_then(move |body| {
+                let patch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                received.lock().unwrap().push(patch.clone());
+
+                let mut response = Response::new(patch.to_string().into());
+                response
+                    .headers_mut()
+                    .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                Ok(response)
+            })
+        };
+        let token_source = SasTokenSource::new(
+            "hub".to_string(),
+            "device".to_string(),
+            MemoryKey::new("device"),
+        );
+        let client = Client::new(handler, Some(token_source), api_version, host_name).unwrap();
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+
+        let identity_manager = HubIdentityManager::new(key_store, device_client);
+        let health = DaemonHealth::new("1.0.9".to_string(), Utc.ymd(2019, 11, 5).and_hms(12, 0, 0))
+            .with_watchdog("healthy".to_string(), 0);
+        let task =
+            identity_manager.report_health(HealthReportTarget::AgentTwin, "$edgeAgent", &health);
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                json!({ "tags": { "iotedged": null } }),
+                json!({ "tags": { "iotedged": health } }),
+            ],
+            *patches.lock().unwrap()
+        );
+    }
+
     #[test]
     fn token_source_success() {
         // arrange
@@ -912,4 +1738,31 @@ mod tests {
         );
         assert_eq!(expected, token);
     }
+
+    #[test]
+    fn token_source_reuses_token_until_renewal() {
+        let key = MemoryKey::new(base64::decode("key").unwrap());
+        let token_source = SasTokenSource::new("hub".to_string(), "device".to_string(), key);
+        let now = Utc.ymd(2018, 4, 26).and_hms(20, 0, 0);
+        let expiry = now + Duration::hours(1);
+
+        let token = token_source.get_at(now, &expiry).unwrap();
+        assert!(token.ends_with(&format!("se={}", expiry.timestamp())));
+
+        // The cached token is still valid for long enough.
+        let later = now + Duration::minutes(30);
+        assert_eq!(
+            token,
+            token_source
+                .clone()
+                .get_at(later, &(later + Duration::hours(1)))
+                .unwrap()
+        );
+
+        // The cached token is about to expire, so a new one is signed.
+        let later = expiry - Duration::minutes(5);
+        let renewal_expiry = later + Duration::hours(1);
+        let renewed = token_source.get_at(later, &renewal_expiry).unwrap();
+        assert!(renewed.ends_with(&format!("se={}", renewal_expiry.timestamp())));
+    }
 }
@@ -61,6 +61,9 @@ pub enum ErrorKind {
     #[fail(display = "{}", _0)]
     NotFound(String),
 
+    #[fail(display = "{} is not supported by the kubernetes runtime", _0)]
+    NotSupported(&'static str),
+
     #[fail(display = "Config parsing error")]
     Config,
 
@@ -149,7 +152,13 @@ impl From<Context<ErrorKind>> for Error {
 
 impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
     fn from(err: &'a Error) -> Self {
-        match Fail::find_root_cause(err).downcast_ref::<ErrorKind>() {
+        // The root cause is an Error rather than an ErrorKind if it was made with
+        // Error::from(kind) before context was added to it.
+        let root_cause = Fail::find_root_cause(err);
+        let kind = root_cause
+            .downcast_ref::<ErrorKind>()
+            .or_else(|| root_cause.downcast_ref::<Error>().map(Error::kind));
+        match kind {
             Some(ErrorKind::NotFound(_)) => ModuleRuntimeErrorReason::NotFound,
             _ => ModuleRuntimeErrorReason::Other,
         }
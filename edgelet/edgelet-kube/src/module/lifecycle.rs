@@ -0,0 +1,335 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::Fail;
+use futures::future::IntoFuture;
+use futures::{Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+use k8s_openapi::api::apps::v1 as api_apps;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as api_meta;
+
+use edgelet_core::RuntimeOperation;
+use kube_client::TokenSource;
+
+use crate::constants::EDGE_RESTARTED_AT;
+use crate::convert::sanitize_dns_value;
+use crate::error::Error;
+use crate::{ErrorKind, KubeModuleRuntime};
+
+/// Starts a module by scaling its deployment back up to one pod.
+pub fn start_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::StartModule(id.to_string());
+    update_deployment(runtime, id, |deployment| set_replicas(deployment, 1))
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+/// Stops a module by scaling its deployment down to no pods, so that it
+/// isn't started again until the module is.
+pub fn stop_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::StopModule(id.to_string());
+    update_deployment(runtime, id, |deployment| set_replicas(deployment, 0))
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+/// Restarts a module the way `kubectl rollout restart` does: changing an
+/// annotation of the pod template makes the deployment replace its pods.
+pub fn restart_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::RestartModule(id.to_string());
+    let restarted_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+        .to_string();
+    update_deployment(runtime, id, move |deployment| {
+        set_restarted_at(deployment, restarted_at)
+    })
+    .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+fn update_deployment<T, S, F>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+    update: F,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+    F: FnOnce(&mut api_apps::Deployment) + Send + 'static,
+{
+    let id = id.to_string();
+
+    sanitize_dns_value(&id)
+        .map(|name| {
+            let client_copy = runtime.client();
+            let namespace_copy = runtime.settings().namespace().to_owned();
+
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_deployments(
+                    runtime.settings().namespace(),
+                    Some(&name),
+                    Some(&runtime.settings().device_hub_selector()),
+                )
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then(move |deployments| {
+                    let mut deployment = deployments
+                        .items
+                        .into_iter()
+                        .find(|deployment| {
+                            deployment.metadata.as_ref().map_or(false, |meta| {
+                                meta.name.as_ref().map_or(false, |n| *n == name)
+                            })
+                        })
+                        .ok_or_else(|| Error::from(ErrorKind::NotFound(id)))?;
+                    update(&mut deployment);
+                    Ok((name, deployment))
+                })
+                .and_then(move |(name, deployment)| {
+                    client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .replace_deployment(namespace_copy.as_str(), &name, &deployment)
+                        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                        .map(|_| ())
+                })
+        })
+        .into_future()
+        .flatten()
+}
+
+fn set_replicas(deployment: &mut api_apps::Deployment, replicas: i32) {
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.replicas = Some(replicas);
+    }
+}
+
+fn set_restarted_at(deployment: &mut api_apps::Deployment, restarted_at: String) {
+    if let Some(spec) = deployment.spec.as_mut() {
+        spec.template
+            .metadata
+            .get_or_insert_with(api_meta::ObjectMeta::default)
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .insert(EDGE_RESTARTED_AT.to_string(), restarted_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_core::ModuleRuntimeErrorReason;
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use super::*;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_stops_module_by_scaling_deployment_down() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+            PUT format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => replace_deployment_handler(|deployment| {
+                assert_eq!(0, deployment["spec"]["replicas"]);
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = stop_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_starts_module_by_scaling_deployment_up() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(0),
+            PUT format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => replace_deployment_handler(|deployment| {
+                assert_eq!(1, deployment["spec"]["replicas"]);
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = start_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_restarts_module_by_annotating_pod_template() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+            PUT format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => replace_deployment_handler(|deployment| {
+                let annotations = &deployment["spec"]["template"]["metadata"]["annotations"];
+                assert!(annotations[EDGE_RESTARTED_AT].is_string());
+                assert_eq!("$edgeHub", annotations["net.azure-devices.edge.original-moduleid"]);
+            }),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = restart_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+    }
+
+    #[test]
+    fn it_fails_to_start_missing_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/apis/apps/v1/namespaces/{}/deployments", settings.namespace()) => deployment_list_handler(1),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = start_module(&runtime, "tempSensor");
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::RuntimeOperation(RuntimeOperation::StartModule("tempSensor".to_string())),
+            err.kind()
+        );
+        match ModuleRuntimeErrorReason::from(&err) {
+            ModuleRuntimeErrorReason::NotFound => (),
+            _ => panic!("Expected a NotFound error, got {}", err),
+        }
+    }
+
+    fn deployment_list_handler(replicas: i32) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, move || {
+                json!({
+                    "kind": "DeploymentList",
+                    "apiVersion": "apps/v1",
+                    "items": [
+                        {
+                            "metadata": {
+                                "name": "edgehub",
+                                "namespace": "msiot-dwr-hub-dwr-ha3",
+                            },
+                            "spec": {
+                                "replicas": replicas,
+                                "selector": {
+                                    "matchLabels": {
+                                        "net.azure-devices.edge.module": "edgehub"
+                                    }
+                                },
+                                "template": {
+                                    "metadata": {
+                                        "labels": {
+                                            "net.azure-devices.edge.module": "edgehub"
+                                        },
+                                        "annotations": {
+                                            "net.azure-devices.edge.original-moduleid": "$edgeHub"
+                                        }
+                                    },
+                                    "spec": {
+                                        "containers": [
+                                            {
+                                                "image": "edgehub:1.0",
+                                                "name": "edgehub"
+                                            }
+                                        ]
+                                    }
+                                }
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn replace_deployment_handler(
+        check: impl Fn(&serde_json::Value) + Clone + Send + 'static,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            let check = check.clone();
+            Box::new(req.into_body().concat2().map(move |body| {
+                let deployment: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                check(&deployment);
+                hyper::Response::builder()
+                    .status(StatusCode::OK)
+                    .body(deployment.to_string().into())
+                    .unwrap()
+            }))
+        }
+    }
+}
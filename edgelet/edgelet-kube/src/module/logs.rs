@@ -0,0 +1,254 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::Fail;
+use futures::future::IntoFuture;
+use futures::{Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+
+use edgelet_core::{LogOptions, LogTail, RuntimeOperation};
+use kube_client::TokenSource;
+
+use crate::constants::EDGE_MODULE_LABEL;
+use crate::convert::sanitize_dns_value;
+use crate::error::Error;
+use crate::{ErrorKind, KubeModuleRuntime};
+
+/// Reads the logs of the module container in the pod of a module. They are
+/// read with a single request to the API server, so they can't be followed.
+pub fn module_logs<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+    options: &LogOptions,
+) -> impl Future<Item = String, Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let id = id.to_string();
+    let operation = RuntimeOperation::GetModuleLogs(id.clone());
+
+    let result = if options.follow() {
+        Err(Error::from(ErrorKind::NotSupported("Following logs")))
+    } else if options.until() > 0 {
+        Err(Error::from(ErrorKind::NotSupported(
+            "Getting logs until a time",
+        )))
+    } else {
+        sanitize_dns_value(&id)
+    };
+
+    let tail_lines = match options.tail() {
+        LogTail::All => None,
+        LogTail::Num(lines) => Some(i64::try_from(*lines).unwrap_or(i64::max_value())),
+    };
+    let since_seconds = since_seconds(options.since());
+    let timestamps = options.timestamps();
+
+    result
+        .map(|name| {
+            let client_copy = runtime.client();
+            let namespace_copy = runtime.settings().namespace().to_owned();
+            let module_selector = format!("{}={}", EDGE_MODULE_LABEL, name);
+            let selector = match runtime.settings().device_hub_selector() {
+                "" => module_selector,
+                device_hub_selector => format!("{},{}", device_hub_selector, module_selector),
+            };
+
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .list_pods(runtime.settings().namespace(), Some(&selector))
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then(move |pods| {
+                    pods.items
+                        .into_iter()
+                        .filter_map(|pod| pod.metadata.and_then(|meta| meta.name))
+                        .next()
+                        .ok_or_else(|| Error::from(ErrorKind::NotFound(id)))
+                })
+                .and_then(move |pod_name| {
+                    client_copy
+                        .lock()
+                        .expect("Unexpected lock error")
+                        .borrow_mut()
+                        .get_pod_logs(
+                            &namespace_copy,
+                            &pod_name,
+                            Some(&name),
+                            tail_lines,
+                            since_seconds,
+                            timestamps,
+                        )
+                        .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                })
+        })
+        .into_future()
+        .flatten()
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+/// Converts the time to get logs since, in seconds since the epoch like for
+/// docker, to the number of seconds before now that the API server expects.
+fn since_seconds(since: i32) -> Option<i64> {
+    if since <= 0 {
+        return None;
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| i64::try_from(now.as_secs()).unwrap_or(i64::max_value()))
+        .unwrap_or_default();
+    Some((now - i64::from(since)).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, Response, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_core::ModuleRuntimeErrorReason;
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use super::*;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_reads_logs_of_module_container() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => list_pod_handler(),
+            GET format!("/api/v1/namespaces/{}/pods/edgehub-5c7d9d7f8-x2x9z/log", settings.namespace()) => pod_log_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let options = LogOptions::new().with_tail(LogTail::Num(10));
+        let task = module_logs(&runtime, "$edgeHub", &options);
+
+        let mut runtime = Runtime::new().unwrap();
+        let logs = runtime.block_on(task).unwrap();
+
+        assert_eq!("line 1\nline 2\n", logs);
+    }
+
+    #[test]
+    fn it_does_not_follow_logs() {
+        let settings = make_settings(None);
+
+        let dispatch_table = btreemap!();
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let options = LogOptions::new().with_follow(true);
+        let task = module_logs(&runtime, "$edgeHub", &options);
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs("$edgeHub".to_string())),
+            err.kind()
+        );
+        assert_eq!(
+            Some(&ErrorKind::NotSupported("Following logs")),
+            Fail::find_root_cause(&err)
+                .downcast_ref::<Error>()
+                .map(Error::kind)
+        );
+    }
+
+    #[test]
+    fn it_fails_to_read_logs_of_module_without_pod() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => list_pod_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = module_logs(&runtime, "tempSensor", &LogOptions::new());
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+
+        match ModuleRuntimeErrorReason::from(&err) {
+            ModuleRuntimeErrorReason::NotFound => (),
+            _ => panic!("Expected a NotFound error, got {}", err),
+        }
+    }
+
+    #[test]
+    fn since_seconds_counts_back_from_now() {
+        assert_eq!(None, since_seconds(0));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let since = i32::try_from(now - 60).unwrap();
+        let seconds = since_seconds(since).unwrap();
+        assert!((60..70).contains(&seconds));
+    }
+
+    // Like the API server, only lists the pod of edgeHub if the label selector
+    // asks for its pods.
+    fn list_pod_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            let query = req.uri().query().unwrap_or_default().to_string();
+            response(StatusCode::OK, move || {
+                let items = if query.contains("edgehub") {
+                    vec![json!({
+                        "kind": "Pod",
+                        "metadata": {
+                            "name": "edgehub-5c7d9d7f8-x2x9z",
+                            "labels": {
+                                "net.azure-devices.edge.module": "edgehub"
+                            }
+                        }
+                    })]
+                } else {
+                    vec![]
+                };
+                json!({
+                    "kind": "PodList",
+                    "items": items
+                })
+                .to_string()
+            })
+        }
+    }
+
+    fn pod_log_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |req| {
+            let query = req.uri().query().unwrap_or_default();
+            assert!(query.contains("container=edgehub"));
+            assert!(query.contains("tailLines=10"));
+            Box::new(future::ok(Response::new(Body::from("line 1\nline 2\n"))))
+        }
+    }
+}
@@ -2,10 +2,16 @@
 
 mod authentication;
 mod create;
+mod lifecycle;
+mod logs;
+mod remove;
 mod trust_bundle;
 
 pub use authentication::authenticate;
 pub use create::create_module;
+pub use lifecycle::{restart_module, start_module, stop_module};
+pub use logs::module_logs;
+pub use remove::remove_module;
 pub use trust_bundle::init_trust_bundle;
 
 use edgelet_core::{Module, ModuleRuntimeState, ModuleStatus};
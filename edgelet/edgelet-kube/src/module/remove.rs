@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::Fail;
+use futures::future::{Either, IntoFuture};
+use futures::{future, Future, Stream};
+use hyper::service::Service;
+use hyper::Body;
+
+use edgelet_core::RuntimeOperation;
+use kube_client::TokenSource;
+
+use crate::constants::EDGE_EDGE_AGENT_NAME;
+use crate::convert::sanitize_dns_value;
+use crate::error::Error;
+use crate::{ErrorKind, KubeModuleRuntime};
+
+/// Removes a module by deleting its deployment, and then the service account
+/// and, for the edge agent, the role binding that were created along with it.
+pub fn remove_module<T, S>(
+    runtime: &KubeModuleRuntime<T, S>,
+    id: &str,
+) -> impl Future<Item = (), Error = Error>
+where
+    T: TokenSource + Send + 'static,
+    S: Service + Send + 'static,
+    S::ReqBody: From<Vec<u8>>,
+    S::ResBody: Stream,
+    Body: From<S::ResBody>,
+    S::Error: Fail,
+    S::Future: Send,
+{
+    let operation = RuntimeOperation::RemoveModule(id.to_string());
+
+    sanitize_dns_value(id)
+        .map(|name| {
+            let client_copy = runtime.client();
+            let namespace_copy = runtime.settings().namespace().to_owned();
+
+            runtime
+                .client()
+                .lock()
+                .expect("Unexpected lock error")
+                .borrow_mut()
+                .delete_deployment(runtime.settings().namespace(), &name)
+                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                .and_then({
+                    let client_copy = client_copy.clone();
+                    let namespace_copy = namespace_copy.clone();
+                    let name = name.clone();
+                    move |_| {
+                        client_copy
+                            .lock()
+                            .expect("Unexpected lock error")
+                            .borrow_mut()
+                            .delete_service_account(&namespace_copy, &name)
+                            .map_err(|err| Error::from(err.context(ErrorKind::KubeClient)))
+                    }
+                })
+                .and_then(move |_| {
+                    // a role binding is only created for edge agent
+                    if name == EDGE_EDGE_AGENT_NAME {
+                        Either::A(
+                            client_copy
+                                .lock()
+                                .expect("Unexpected lock error")
+                                .borrow_mut()
+                                .delete_role_binding(&namespace_copy, &name)
+                                .map_err(|err| Error::from(err.context(ErrorKind::KubeClient))),
+                        )
+                    } else {
+                        Either::B(future::ok(()))
+                    }
+                })
+        })
+        .into_future()
+        .flatten()
+        .map_err(|err| Error::from(err.context(ErrorKind::RuntimeOperation(operation))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, StatusCode};
+    use maplit::btreemap;
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    use edgelet_test_utils::routes;
+    use edgelet_test_utils::web::{
+        make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
+    };
+
+    use super::*;
+    use crate::tests::{create_runtime, make_settings, not_found_handler, response};
+
+    #[test]
+    fn it_deletes_deployment_and_service_account_of_module() {
+        let settings = make_settings(None);
+        let deleted = Arc::new(Mutex::new(vec![]));
+
+        let dispatch_table = routes!(
+            DELETE format!("/apis/apps/v1/namespaces/{}/deployments/edgehub", settings.namespace()) => delete_handler("deployment", &deleted),
+            DELETE format!("/api/v1/namespaces/{}/serviceaccounts/edgehub", settings.namespace()) => delete_handler("service account", &deleted),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = remove_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+
+        assert_eq!(
+            vec!["deployment", "service account"],
+            *deleted.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_deletes_role_binding_of_edgeagent() {
+        let settings = make_settings(None);
+        let deleted = Arc::new(Mutex::new(vec![]));
+
+        let dispatch_table = routes!(
+            DELETE format!("/apis/apps/v1/namespaces/{}/deployments/edgeagent", settings.namespace()) => delete_handler("deployment", &deleted),
+            DELETE format!("/api/v1/namespaces/{}/serviceaccounts/edgeagent", settings.namespace()) => delete_handler("service account", &deleted),
+            DELETE format!("/apis/rbac.authorization.k8s.io/v1/namespaces/{}/rolebindings/edgeagent", settings.namespace()) => delete_handler("role binding", &deleted),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = remove_module(&runtime, "$edgeAgent");
+
+        let mut runtime = Runtime::new().unwrap();
+        runtime.block_on(task).unwrap();
+
+        assert_eq!(
+            vec!["deployment", "service account", "role binding"],
+            *deleted.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_fails_to_remove_module_without_deployment() {
+        let settings = make_settings(None);
+
+        let dispatch_table = btreemap!();
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = remove_module(&runtime, "$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).unwrap_err();
+
+        assert_eq!(
+            &ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule("$edgeHub".to_string())),
+            err.kind()
+        );
+    }
+
+    fn delete_handler(
+        kind: &'static str,
+        deleted: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        let deleted = deleted.clone();
+        move |_| {
+            deleted.lock().unwrap().push(kind);
+            response(StatusCode::OK, || {
+                json!({
+                    "kind": "Status",
+                    "apiVersion": "v1",
+                    "status": "Success",
+                })
+                .to_string()
+            })
+        }
+    }
+}
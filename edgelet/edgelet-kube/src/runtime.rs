@@ -14,17 +14,21 @@ use hyper::{Body, Chunk as HyperChunk, Request};
 use hyper_tls::HttpsConnector;
 
 use edgelet_core::{
-    AuthId, Authenticator, GetTrustBundle, LogOptions, MakeModuleRuntime, ModuleRegistry,
-    ModuleRuntime, ModuleRuntimeState, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
+    AuthId, Authenticator, GetTrustBundle, ImageInfo, LogOptions, MakeModuleRuntime, Module,
+    ModuleEvent, ModuleExecResult, ModuleProcesses, ModuleRegistry, ModuleRuntime,
+    ModuleRuntimeState, ModuleSpec, ProvisioningResult as CoreProvisioningResult, PrunedImages,
     RuntimeOperation, SystemInfo, SystemResources,
 };
-use edgelet_docker::DockerConfig;
+use edgelet_docker::{validate_module, DockerConfig};
 use kube_client::{get_config, Client as KubeClient, HttpClient, TokenSource, ValueToken};
 use provisioning::ProvisioningResult;
 
 use crate::convert::pod_to_module;
 use crate::error::{Error, ErrorKind};
-use crate::module::{authenticate, create_module, init_trust_bundle, KubeModule};
+use crate::module::{
+    authenticate, create_module, init_trust_bundle, module_logs, remove_module, restart_module,
+    start_module, stop_module, KubeModule,
+};
 use crate::registry::create_image_pull_secrets;
 use crate::settings::Settings;
 
@@ -156,6 +160,7 @@ where
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
     type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = future::FutureResult<(), Self::Error>;
     type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
     type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
@@ -163,29 +168,60 @@ where
     type SystemResourcesFuture =
         Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
     type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ExecFuture = future::FutureResult<ModuleExecResult, Self::Error>;
+    type CopyToFuture = future::FutureResult<(), Self::Error>;
+    type CopyFromFuture = future::FutureResult<Vec<u8>, Self::Error>;
+    type ExportImageFuture = future::FutureResult<Vec<u8>, Self::Error>;
+    type ImportImagesFuture = future::FutureResult<(), Self::Error>;
+    type ListImagesFuture = future::FutureResult<Vec<ImageInfo>, Self::Error>;
+    type PruneImagesFuture = future::FutureResult<PrunedImages, Self::Error>;
+    type TopFuture = future::FutureResult<ModuleProcesses, Self::Error>;
+    type ValidateFuture = future::FutureResult<(), Self::Error>;
+    type EventStream = stream::Empty<ModuleEvent, Self::Error>;
 
     fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         Box::new(create_module(self, module))
     }
 
-    fn get(&self, _id: &str) -> Self::GetFuture {
-        unimplemented!()
+    fn get(&self, id: &str) -> Self::GetFuture {
+        let id = id.to_string();
+        let result = self
+            .list()
+            .and_then(move |modules| {
+                modules
+                    .into_iter()
+                    .find(|module| module.name() == id)
+                    .ok_or_else(|| Error::from(ErrorKind::NotFound(id)))
+            })
+            .and_then(|module| module.runtime_state().map(|state| (module, state)));
+
+        Box::new(result)
     }
 
-    fn start(&self, _id: &str) -> Self::StartFuture {
-        Box::new(future::ok(()))
+    fn start(&self, id: &str) -> Self::StartFuture {
+        Box::new(start_module(self, id))
     }
 
-    fn stop(&self, _id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
-        Box::new(future::ok(()))
+    // Pods are given the termination grace period of their deployment.
+    fn stop(&self, id: &str, _wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        Box::new(stop_module(self, id))
     }
 
-    fn restart(&self, _id: &str) -> Self::RestartFuture {
-        Box::new(future::ok(()))
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        Box::new(restart_module(self, id))
     }
 
-    fn remove(&self, _id: &str) -> Self::RemoveFuture {
-        Box::new(future::ok(()))
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        Box::new(remove_module(self, id))
+    }
+
+    // Modules are deployments owned by the edge agent, which names them.
+    fn rename(&self, id: &str, _new_id: &str) -> Self::RenameFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Renaming modules").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::RenameModule(id.to_string()),
+            )),
+        ))
     }
 
     fn system_info(&self) -> Self::SystemInfoFuture {
@@ -292,8 +328,9 @@ where
         Box::new(stream::empty())
     }
 
-    fn logs(&self, _id: &str, _options: &LogOptions) -> Self::LogsFuture {
-        Box::new(future::ok(Logs("".to_string(), Body::empty())))
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        let id = id.to_string();
+        Box::new(module_logs(self, &id, options).map(|logs| Logs(id, Body::from(logs))))
     }
 
     fn registry(&self) -> &Self::ModuleRegistry {
@@ -303,6 +340,82 @@ where
     fn remove_all(&self) -> Self::RemoveAllFuture {
         Box::new(future::ok(()))
     }
+
+    // The pod exec API streams over a websocket upgrade, which the
+    // kubernetes client doesn't support.
+    fn exec(&self, id: &str, _command: &[String]) -> Self::ExecFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Running commands").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ExecModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn copy_to(&self, id: &str, _path: &str, _archive: Vec<u8>) -> Self::CopyToFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Copying files").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CopyToModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn copy_from(&self, id: &str, _path: &str) -> Self::CopyFromFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Copying files").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CopyFromModule(id.to_string()),
+            )),
+        ))
+    }
+
+    // Images are pulled by the nodes of the cluster, not by the daemon.
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Exporting images").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ExportModuleImage(id.to_string()),
+            )),
+        ))
+    }
+
+    fn import_images(&self, _archive: Vec<u8>) -> Self::ImportImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Importing images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages)),
+        ))
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Listing images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages)),
+        ))
+    }
+
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Pruning images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages)),
+        ))
+    }
+
+    fn top(&self, id: &str) -> Self::TopFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Listing processes").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::TopModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn validate(&self, module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        future::result(validate_module(&module).map_err(|err| {
+            Error::from(err.context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ValidateModule(module.name().to_string()),
+            )))
+        }))
+    }
+
+    fn events(&self) -> Self::EventStream {
+        stream::empty()
+    }
 }
 
 impl<T, S> Authenticator for KubeModuleRuntime<T, S>
@@ -383,7 +496,7 @@ mod tests {
     use serde_json::json;
     use tokio::runtime::Runtime;
 
-    use edgelet_core::ModuleRuntime;
+    use edgelet_core::{Module, ModuleRuntime, ModuleRuntimeErrorReason, ModuleStatus};
     use edgelet_test_utils::routes;
     use edgelet_test_utils::web::{
         make_req_dispatcher, HttpMethod, RequestHandler, RequestPath, ResponseFuture,
@@ -459,6 +572,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn runtime_get_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => list_pod_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.get("$edgeHub");
+
+        let mut runtime = Runtime::new().unwrap();
+        let (module, state) = runtime.block_on(task).unwrap();
+
+        assert_eq!(module.name(), "$edgeHub");
+        assert_eq!(module.config().image(), "edgehub:1.0");
+        assert_eq!(state.status(), &ModuleStatus::Running);
+    }
+
+    #[test]
+    fn runtime_get_missing_module() {
+        let settings = make_settings(None);
+
+        let dispatch_table = routes!(
+            GET format!("/api/v1/namespaces/{}/pods", settings.namespace()) => list_pod_handler(),
+        );
+
+        let handler = make_req_dispatcher(dispatch_table, Box::new(not_found_handler));
+        let service = service_fn(handler);
+        let runtime = create_runtime(settings, service);
+
+        let task = runtime.get("$edgeAgent");
+
+        let mut runtime = Runtime::new().unwrap();
+        let err = runtime.block_on(task).err().unwrap();
+
+        match ModuleRuntimeErrorReason::from(&err) {
+            ModuleRuntimeErrorReason::NotFound => (),
+            _ => panic!("Expected a NotFound error, got {}", err),
+        }
+    }
+
+    fn list_pod_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
+        move |_| {
+            response(StatusCode::OK, || {
+                json!({
+                    "kind" : "PodList",
+                    "items" : [
+                        {
+                            "kind": "Pod",
+                            "metadata": {
+                                "name": "edgehub",
+                                "labels": {
+                                    "net.azure-devices.edge.module": "edgehub"
+                                },
+                                "annotations": {
+                                    "net.azure-devices.edge.original-moduleid": "$edgeHub"
+                                }
+                            },
+                            "spec": {
+                                "containers": [
+                                    {
+                                        "image": "edgehub:1.0",
+                                        "name": "edgehub"
+                                    }
+                                ]
+                            }
+                        }
+                    ]
+                })
+                .to_string()
+            })
+        }
+    }
+
     fn list_node_handler() -> impl Fn(Request<Body>) -> ResponseFuture + Clone {
         move |_| {
             response(StatusCode::OK, || {
@@ -1,14 +1,17 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use config::{Config, Environment};
 use edgelet_core::{
-    Certificates, Connect, Listen, ModuleSpec, Provisioning, RuntimeSettings,
-    Settings as BaseSettings, WatchdogSettings,
+    Certificates, Connect, DeviceActionSettings, DiagnosticsSettings, HealthReportSettings,
+    LifecycleHookSettings, Listen, LogFormat, ModuleSpec, OrphanedModuleSettings, Provisioning,
+    RateLimitSettings, RetrySettings, RuntimeSettings, Settings as BaseSettings, Tenant,
+    WatchdogSettings, REQUIRED_SETTINGS, SETTINGS_ALIASES,
 };
 use edgelet_docker::{DockerConfig, DEFAULTS};
-use edgelet_utils::YamlFileSource;
+use edgelet_utils::{SettingsFile, YamlFileSource};
 use failure::ResultExt;
 
 use crate::error::Error;
@@ -30,20 +33,24 @@ pub struct Settings {
 
 impl Settings {
     pub fn new(filename: &Path) -> Result<Self, Error> {
+        let file = SettingsFile::open(filename).context(ErrorKind::Config)?;
+        file.check(REQUIRED_SETTINGS).context(ErrorKind::Config)?;
+
         let mut config = Config::default();
         config
             .merge(YamlFileSource::String(DEFAULTS))
             .context(ErrorKind::Config)?;
 
-        config
-            .merge(YamlFileSource::File(filename.into()))
-            .context(ErrorKind::Config)?;
+        file.merge_into(&mut config).context(ErrorKind::Config)?;
 
         config
             .merge(Environment::with_prefix("iotedge"))
             .context(ErrorKind::Config)?;
 
-        let settings = config.try_into().context(ErrorKind::Config)?;
+        let settings: Self = file
+            .deserialize::<Self, BaseSettings<DockerConfig>>(config)
+            .context(ErrorKind::Config)?;
+        file.warn_unknown_keys(&settings, SETTINGS_ALIASES);
 
         Ok(settings)
     }
@@ -111,6 +118,10 @@ impl RuntimeSettings for Settings {
         self.base.hostname()
     }
 
+    fn parent_hostname(&self) -> Option<&str> {
+        self.base.parent_hostname()
+    }
+
     fn connect(&self) -> &Connect {
         self.base.connect()
     }
@@ -130,6 +141,50 @@ impl RuntimeSettings for Settings {
     fn watchdog(&self) -> &WatchdogSettings {
         self.base.watchdog()
     }
+
+    fn log_level(&self) -> Option<&str> {
+        self.base.log_level()
+    }
+
+    fn log_format(&self) -> LogFormat {
+        self.base.log_format()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        self.base.tenants()
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        self.base.lifecycle_hook()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.base.workload_rate_limit()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        self.base.retry()
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        self.base.device_actions()
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        self.base.health_report()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        self.base.orphaned_modules()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        self.base.diagnostics()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        self.base.secrets()
+    }
 }
 
 #[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! A minimal client for podman's libpod REST API.
+//!
+//! Podman serves both a docker compatible API and its own libpod API on the
+//! same socket. The libpod API is used because it is the one that exposes
+//! podman specific behavior, such as host pids of rootless containers.
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future, Stream};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Client, Method, Request, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::form_urlencoded::Serializer as UrlSerializer;
+use url::Url;
+
+use edgelet_core::UrlExt;
+use edgelet_http::UrlConnector;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::models::{ErrorModel, PruneReport};
+
+/// Every libpod route is served under a version prefix. Podman accepts any
+/// version here, but the request and response formats used by this crate
+/// are the ones introduced with podman 4.
+const API_PREFIX: &str = "/v4.0.0/libpod";
+
+const REGISTRY_AUTH: &str = "x-registry-auth";
+
+#[derive(Clone)]
+pub struct PodmanClient {
+    client: Client<UrlConnector, Body>,
+    scheme: String,
+    base_path: String,
+}
+
+impl PodmanClient {
+    pub fn new(url: &Url) -> Result<Self> {
+        let client =
+            Client::builder().build(UrlConnector::new(url).context(ErrorKind::Initialization)?);
+
+        // extract base path - the bit that comes after the scheme
+        let base_path = url
+            .to_base_path()
+            .context(ErrorKind::Initialization)?
+            .to_str()
+            .ok_or(ErrorKind::Initialization)?
+            .to_string();
+
+        Ok(PodmanClient {
+            client,
+            scheme: url.scheme().to_string(),
+            base_path,
+        })
+    }
+
+    /// Sends a request and resolves to the response body when podman reports
+    /// success. "Not modified" counts as success, since that is how podman
+    /// says that a container is already in the requested state.
+    pub fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Vec<u8>>,
+        registry_auth: Option<String>,
+    ) -> impl Future<Item = Body, Error = Error> + Send {
+        self.request(
+            method,
+            path,
+            body.map(|body| (body, "application/json")),
+            registry_auth,
+        )
+    }
+
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<(Vec<u8>, &'static str)>,
+        registry_auth: Option<String>,
+    ) -> impl Future<Item = Body, Error = Error> + Send {
+        let path = format!("{}{}", API_PREFIX, path);
+        let request = UrlConnector::build_hyper_uri(&self.scheme, &self.base_path, &path)
+            .context(ErrorKind::Hyper)
+            .map_err(Error::from)
+            .and_then(|uri| {
+                let mut request = Request::builder();
+                request.method(method).uri(uri);
+                let body = body.map(|(body, content_type)| {
+                    request.header(CONTENT_TYPE, content_type);
+                    body
+                });
+                if let Some(registry_auth) = registry_auth {
+                    let value = HeaderValue::from_str(&registry_auth).context(ErrorKind::Hyper)?;
+                    request.header(REGISTRY_AUTH, value);
+                }
+                request
+                    .body(body.map_or_else(Body::empty, Body::from))
+                    .context(ErrorKind::Hyper)
+                    .map_err(Error::from)
+            });
+
+        let client = self.client.clone();
+        future::result(request)
+            .and_then(move |request| {
+                client
+                    .request(request)
+                    .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+            })
+            .and_then(|response| {
+                let (parts, body) = response.into_parts();
+                if parts.status.is_success() || parts.status == StatusCode::NOT_MODIFIED {
+                    future::Either::A(future::ok(body))
+                } else {
+                    future::Either::B(
+                        body.concat2()
+                            .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                            .and_then(move |body| Err(error_from_response(parts.status, &body))),
+                    )
+                }
+            })
+    }
+
+    pub fn get<R>(&self, path: &str) -> impl Future<Item = R, Error = Error> + Send
+    where
+        R: DeserializeOwned + Send + 'static,
+    {
+        read_json(self.send(Method::GET, path, None, None))
+    }
+
+    pub fn post<T, R>(&self, path: &str, body: &T) -> impl Future<Item = R, Error = Error> + Send
+    where
+        T: Serialize,
+        R: DeserializeOwned + Send + 'static,
+    {
+        let body = serde_json::to_vec(body)
+            .context(ErrorKind::Hyper)
+            .map_err(Error::from);
+
+        let client = self.clone();
+        let path = path.to_string();
+        future::result(body)
+            .and_then(move |body| read_json(client.send(Method::POST, &path, Some(body), None)))
+    }
+
+    /// Uploads a tar archive, such as the files to extract into a container.
+    pub fn put_archive(
+        &self,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        self.send_archive(Method::PUT, path, archive)
+    }
+
+    /// Uploads a tar archive of images, as written by `podman save`, to load them.
+    pub fn load_images(&self, archive: Vec<u8>) -> impl Future<Item = (), Error = Error> + Send {
+        self.send_archive(Method::POST, "/images/load", archive)
+    }
+
+    /// Removes the images that no container was created from.
+    pub fn prune_images(&self) -> impl Future<Item = Vec<PruneReport>, Error = Error> + Send {
+        read_json(self.send(
+            Method::POST,
+            &with_query("/images/prune", &[("all", "true")]),
+            None,
+            None,
+        ))
+    }
+
+    fn send_archive(
+        &self,
+        method: Method,
+        path: &str,
+        archive: Vec<u8>,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        self.request(method, path, Some((archive, "application/x-tar")), None)
+            .and_then(|body| {
+                body.for_each(|_| Ok(()))
+                    .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+            })
+    }
+
+    /// Sends a request whose response body isn't needed.
+    pub fn execute(
+        &self,
+        method: Method,
+        path: &str,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        self.send(method, path, None, None).and_then(|body| {
+            body.for_each(|_| Ok(()))
+                .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+        })
+    }
+}
+
+fn read_json<F, R>(body: F) -> impl Future<Item = R, Error = Error> + Send
+where
+    F: Future<Item = Body, Error = Error> + Send,
+    R: DeserializeOwned + Send + 'static,
+{
+    body.and_then(|body| {
+        body.concat2()
+            .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+    })
+    .and_then(|body| {
+        serde_json::from_slice(&body)
+            .context(ErrorKind::InvalidResponse)
+            .map_err(Error::from)
+    })
+}
+
+fn error_from_response(status: StatusCode, body: &[u8]) -> Error {
+    let message = serde_json::from_slice::<ErrorModel>(body)
+        .ok()
+        .and_then(|error| error.message)
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+
+    match status {
+        StatusCode::NOT_FOUND => Error::from(ErrorKind::NotFound(message)),
+        status => Error::from(ErrorKind::Podman(status.as_u16(), message)),
+    }
+}
+
+/// Appends url encoded query parameters to a path.
+pub fn with_query(path: &str, params: &[(&str, &str)]) -> String {
+    let query = UrlSerializer::new(String::new())
+        .extend_pairs(params)
+        .finish();
+    if query.is_empty() {
+        path.to_string()
+    } else {
+        format!("{}?{}", path, query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_found_maps_to_not_found() {
+        let err = error_from_response(
+            StatusCode::NOT_FOUND,
+            br#"{"cause":"no such container","message":"no container with name or ID \"mod1\" found: no such container","response":404}"#,
+        );
+
+        match err.kind() {
+            ErrorKind::NotFound(message) => assert_eq!(
+                "no container with name or ID \"mod1\" found: no such container",
+                message
+            ),
+            kind => panic!("Expected `NotFound` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn other_errors_keep_status_and_body() {
+        let err = error_from_response(StatusCode::INTERNAL_SERVER_ERROR, b"boom\n");
+
+        match err.kind() {
+            ErrorKind::Podman(500, message) => assert_eq!("boom", message),
+            kind => panic!("Expected `Podman` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn query_is_encoded() {
+        assert_eq!("/images/pull", with_query("/images/pull", &[]));
+        assert_eq!(
+            "/images/pull?reference=mcr.microsoft.com%2Fazureiotedge-agent%3A1.0",
+            with_query(
+                "/images/pull",
+                &[("reference", "mcr.microsoft.com/azureiotedge-agent:1.0")]
+            )
+        );
+    }
+}
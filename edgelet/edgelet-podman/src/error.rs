@@ -0,0 +1,110 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::fmt::Display;
+
+use failure::{Backtrace, Context, Fail};
+
+use edgelet_core::{ModuleRuntimeErrorReason, RegistryOperation, RuntimeOperation};
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[derive(Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "Could not clone create options")]
+    CloneCreateOptions,
+
+    #[fail(display = "Config parsing error")]
+    Config,
+
+    #[fail(display = "Podman request failed")]
+    Hyper,
+
+    #[fail(display = "{}", _0)]
+    ImagePull(String),
+
+    #[fail(display = "Could not initialize module runtime")]
+    Initialization,
+
+    #[fail(display = "Invalid module name {:?}", _0)]
+    InvalidModuleName(String),
+
+    #[fail(display = "Invalid module type {:?}", _0)]
+    InvalidModuleType(String),
+
+    #[fail(display = "Podman returned an invalid response")]
+    InvalidResponse,
+
+    #[fail(display = "{}", _0)]
+    NotFound(String),
+
+    #[fail(display = "Podman returned status {}: {}", _0, _1)]
+    Podman(u16, String),
+
+    #[fail(display = "{}", _0)]
+    RegistryOperation(RegistryOperation),
+
+    #[fail(display = "{}", _0)]
+    RuntimeOperation(RuntimeOperation),
+}
+
+impl Fail for Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.inner.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.inner.backtrace()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+
+    // Keeps "not found" errors from podman as the root cause so that callers
+    // can tell them apart from other failures.
+    pub fn from_podman_error(err: Error, context: ErrorKind) -> Self {
+        match err.kind() {
+            ErrorKind::NotFound(message) => {
+                ErrorKind::NotFound(message.clone()).context(context).into()
+            }
+            _ => err.context(context).into(),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            inner: Context::new(kind),
+        }
+    }
+}
+
+impl From<Context<ErrorKind>> for Error {
+    fn from(inner: Context<ErrorKind>) -> Self {
+        Error { inner }
+    }
+}
+
+impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+    fn from(err: &'a Error) -> Self {
+        match Fail::find_root_cause(err).downcast_ref::<ErrorKind>() {
+            Some(ErrorKind::NotFound(_)) => ModuleRuntimeErrorReason::NotFound,
+            _ => ModuleRuntimeErrorReason::Other,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::module_name_repetitions,
+    clippy::must_use_candidate,
+    clippy::too_many_lines,
+    clippy::use_self
+)]
+
+//! Module runtime backed by podman's libpod REST API. This allows modules to
+//! run on hosts that ship podman instead of docker, such as RHEL and Fedora,
+//! including when podman runs rootless under an unprivileged user.
+
+mod client;
+mod error;
+mod models;
+mod module;
+mod runtime;
+mod settings;
+
+pub use error::{Error, ErrorKind, Result};
+pub use module::PodmanModule;
+pub use runtime::PodmanModuleRuntime;
+pub use settings::{PodmanRuntime, Settings};
@@ -0,0 +1,235 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! The parts of the libpod API's request and response bodies that are used to
+//! run modules. Podman ignores fields it doesn't know and leaves out the ones
+//! that aren't set, so everything here is optional or has a default.
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct ErrorModel {
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// The body of a container create request.
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Serialize)]
+pub struct SpecGenerator {
+    pub name: String,
+    pub image: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entrypoint: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub command: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<Mount>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<NamedVolume>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub netns: Option<Namespace>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub networks: BTreeMap<String, PerNetworkOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub portmappings: Vec<PortMapping>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_policy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub restart_tries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_signal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_timeout: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub privileged: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct Mount {
+    pub destination: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub source: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct NamedVolume {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Dest")]
+    pub dest: String,
+    #[serde(rename = "Options", skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct Namespace {
+    pub nsmode: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde_derive::Serialize)]
+pub struct PerNetworkOptions {}
+
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize)]
+pub struct PortMapping {
+    pub container_port: u16,
+    pub host_port: u16,
+    pub protocol: String,
+}
+
+#[derive(Debug, serde_derive::Deserialize)]
+pub struct CreateResponse {
+    #[serde(rename = "Id")]
+    pub id: String,
+}
+
+/// The body of an exec create request.
+#[derive(Debug, serde_derive::Serialize)]
+pub struct ExecCreate {
+    #[serde(rename = "AttachStdout")]
+    pub attach_stdout: bool,
+    #[serde(rename = "AttachStderr")]
+    pub attach_stderr: bool,
+    #[serde(rename = "Cmd")]
+    pub cmd: Vec<String>,
+}
+
+#[derive(Debug, serde_derive::Serialize)]
+pub struct ExecStart {
+    #[serde(rename = "Detach")]
+    pub detach: bool,
+    #[serde(rename = "Tty")]
+    pub tty: bool,
+}
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct ExecInspect {
+    #[serde(rename = "ExitCode", default)]
+    pub exit_code: i64,
+}
+
+/// An entry of a container list response.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct ListContainer {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "Names", default)]
+    pub names: Vec<String>,
+    #[serde(rename = "Image", default)]
+    pub image: String,
+    #[serde(rename = "ImageID", default)]
+    pub image_id: String,
+}
+
+/// A container inspect response.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct InspectContainer {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "Name", default)]
+    pub name: String,
+    #[serde(rename = "Image", default)]
+    pub image: String,
+    #[serde(rename = "ImageName", default)]
+    pub image_name: String,
+    #[serde(rename = "State", default)]
+    pub state: InspectState,
+    #[serde(rename = "RestartCount", default)]
+    pub restart_count: i32,
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct InspectState {
+    #[serde(rename = "Status", default)]
+    pub status: String,
+    #[serde(rename = "ExitCode", default)]
+    pub exit_code: i64,
+    #[serde(rename = "Pid", default)]
+    pub pid: i32,
+    #[serde(rename = "StartedAt", default)]
+    pub started_at: String,
+    #[serde(rename = "FinishedAt", default)]
+    pub finished_at: String,
+}
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct TopResponse {
+    #[serde(rename = "Titles", default)]
+    pub titles: Vec<String>,
+    #[serde(rename = "Processes", default)]
+    pub processes: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct Info {
+    #[serde(default)]
+    pub host: HostInfo,
+    #[serde(default)]
+    pub version: VersionInfo,
+}
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct HostInfo {
+    #[serde(default)]
+    pub arch: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default)]
+    pub security: SecurityInfo,
+}
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct SecurityInfo {
+    #[serde(default)]
+    pub rootless: bool,
+}
+
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct VersionInfo {
+    #[serde(rename = "Version", default)]
+    pub version: String,
+}
+
+/// One of the JSON documents streamed back while an image is pulled.
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct PullReport {
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// An entry of an image list response.
+#[derive(Clone, Debug, Default, serde_derive::Deserialize)]
+pub struct ListImage {
+    #[serde(rename = "Id")]
+    pub id: String,
+    // Podman sends null rather than an empty list for untagged images
+    #[serde(rename = "RepoTags", default)]
+    pub repo_tags: Option<Vec<String>>,
+    #[serde(rename = "Size", default)]
+    pub size: i64,
+}
+
+/// The outcome of removing one image while pruning.
+#[derive(Debug, Default, serde_derive::Deserialize)]
+pub struct PruneReport {
+    #[serde(rename = "Id")]
+    pub id: String,
+    #[serde(rename = "Err", default)]
+    pub err: Option<String>,
+    #[serde(rename = "Size", default)]
+    pub size: u64,
+}
+
+#[derive(Debug, serde_derive::Serialize)]
+pub struct NetworkCreate {
+    pub name: String,
+    pub driver: String,
+    pub dns_enabled: bool,
+}
@@ -0,0 +1,662 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+use chrono::prelude::*;
+use failure::ResultExt;
+use futures::Future;
+use log::warn;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use docker::models::ContainerCreateBody;
+use edgelet_core::{
+    Module, ModuleRuntimeState, ModuleSpec, ModuleStatus, RestartPolicy, RuntimeOperation,
+};
+use edgelet_docker::{parse_stop_signal, DockerConfig, MODULE_TYPE};
+use edgelet_utils::ensure_not_empty_with_context;
+
+use crate::client::{with_query, PodmanClient};
+use crate::error::{Error, ErrorKind, Result};
+use crate::models::{
+    InspectContainer, InspectState, Mount, NamedVolume, Namespace, PerNetworkOptions, PortMapping,
+    SpecGenerator, TopResponse,
+};
+
+pub const LABEL_KEY: &str = "net.azure-devices.edge.owner";
+pub const LABEL_VALUE: &str = "Microsoft.Azure.Devices.Edge.Agent";
+
+/// The `top` descriptor for the pid of a process on the host. Pids in the
+/// container's own pid namespace wouldn't match callers of the workload API,
+/// and for rootless containers podman's default `pid` descriptor isn't
+/// even the pid in the user namespace of the podman service.
+const HOST_PID_DESCRIPTOR: &str = "hpid";
+
+pub struct PodmanModule {
+    client: PodmanClient,
+    id: String,
+    name: String,
+    config: DockerConfig,
+}
+
+impl std::fmt::Debug for PodmanModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PodmanModule")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl PodmanModule {
+    pub(crate) fn new(
+        client: PodmanClient,
+        id: String,
+        name: String,
+        config: DockerConfig,
+    ) -> Result<Self> {
+        ensure_not_empty_with_context(&name, || ErrorKind::InvalidModuleName(name.clone()))?;
+
+        Ok(PodmanModule {
+            client,
+            id,
+            name,
+            config,
+        })
+    }
+
+    /// The id podman assigned to the module's container.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The host pids of the processes running in the module's container.
+    pub fn top(&self) -> impl Future<Item = Vec<i32>, Error = Error> + Send {
+        let name = self.name.clone();
+        self.client
+            .get(&with_query(
+                &format!("{}/top", container_path(&self.name)),
+                &[("ps_args", HOST_PID_DESCRIPTOR)],
+            ))
+            .map(|top: TopResponse| host_pids(&top))
+            .map_err(|err| {
+                Error::from_podman_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(name)),
+                )
+            })
+    }
+}
+
+impl Module for PodmanModule {
+    type Config = DockerConfig;
+    type Error = Error;
+    type RuntimeStateFuture =
+        Box<dyn Future<Item = ModuleRuntimeState, Error = Self::Error> + Send>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn type_(&self) -> &str {
+        MODULE_TYPE
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn runtime_state(&self) -> Self::RuntimeStateFuture {
+        let name = self.name.clone();
+        Box::new(
+            self.client
+                .get(&format!("{}/json", container_path(&self.name)))
+                .map(|container: InspectContainer| runtime_state(&container))
+                .map_err(|err| {
+                    Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name)),
+                    )
+                }),
+        )
+    }
+}
+
+/// The path of a container's libpod routes.
+pub fn container_path(name: &str) -> String {
+    format!(
+        "/containers/{}",
+        utf8_percent_encode(name, PATH_SEGMENT_ENCODE_SET)
+    )
+}
+
+pub fn runtime_state(container: &InspectContainer) -> ModuleRuntimeState {
+    let InspectState {
+        status,
+        exit_code,
+        pid,
+        started_at,
+        finished_at,
+    } = &container.state;
+
+    let (module_status, exit_code) = match status.as_str() {
+        "configured" | "created" | "initialized" | "paused" | "stopping" => {
+            (ModuleStatus::Stopped, None)
+        }
+        "exited" | "stopped" | "removing" if *exit_code == 0 => {
+            (ModuleStatus::Stopped, Some(*exit_code))
+        }
+        "exited" | "stopped" | "removing" => (ModuleStatus::Failed, Some(*exit_code)),
+        "running" => (ModuleStatus::Running, None),
+        _ => (ModuleStatus::Unknown, None),
+    };
+
+    let image_id = if container.image.is_empty() {
+        None
+    } else {
+        Some(container.image.clone())
+    };
+
+    ModuleRuntimeState::default()
+        .with_status(module_status)
+        .with_exit_code(exit_code)
+        .with_status_description(Some(status.clone()))
+        .with_started_at(timestamp(started_at))
+        .with_finished_at(timestamp(finished_at))
+        .with_image_id(image_id)
+        .with_pid(Some(*pid).filter(|pid| *pid > 0))
+        .with_restart_count(Some(container.restart_count))
+}
+
+// Podman reports the zero time for containers that haven't started or
+// finished yet.
+fn timestamp(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .filter(|timestamp| timestamp.timestamp() > 0)
+}
+
+fn host_pids(top: &TopResponse) -> Vec<i32> {
+    let column = top
+        .titles
+        .iter()
+        .position(|title| title.eq_ignore_ascii_case(HOST_PID_DESCRIPTOR));
+
+    column.map_or_else(Vec::new, |column| {
+        top.processes
+            .iter()
+            .filter_map(|process| process.get(column))
+            .filter_map(|pid| pid.trim().parse().ok())
+            .collect()
+    })
+}
+
+/// Translates a module spec into a libpod container spec.
+///
+/// Only the parts of the docker create options that have an equivalent in
+/// podman are used: `Hostname`, `Entrypoint`, `Cmd`, `Env`, `Labels`,
+/// `StopSignal`, `StopTimeout`, the networks in `NetworkingConfig`, and
+/// `Binds`, `Mounts`, `PortBindings`, `Privileged` and `RestartPolicy` of
+/// `HostConfig`.
+pub fn spec_generator(module: &ModuleSpec<DockerConfig>) -> Result<SpecGenerator> {
+    let create_options = module
+        .config()
+        .clone_create_options()
+        .context(ErrorKind::CloneCreateOptions)?;
+    let host_config = create_options.host_config();
+
+    let mut spec = SpecGenerator {
+        name: module.name().to_string(),
+        image: module.config().image().to_string(),
+        entrypoint: create_options
+            .entrypoint()
+            .map_or_else(Vec::new, ToOwned::to_owned),
+        command: create_options
+            .cmd()
+            .map_or_else(Vec::new, ToOwned::to_owned),
+        env: env(&create_options, module),
+        hostname: create_options.hostname().map(ToOwned::to_owned),
+        portmappings: port_mappings(&create_options, module.name()),
+        privileged: host_config
+            .and_then(|host_config| host_config.privileged())
+            .copied()
+            .unwrap_or_default(),
+        // Podman takes the signal's number; validation rejects names that
+        // don't parse.
+        stop_signal: create_options.stop_signal().and_then(parse_stop_signal),
+        stop_timeout: create_options
+            .stop_timeout()
+            .and_then(|timeout| u32::try_from(timeout).ok()),
+        ..SpecGenerator::default()
+    };
+
+    if let Some(labels) = create_options.labels() {
+        spec.labels
+            .extend(labels.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    spec.labels
+        .insert(LABEL_KEY.to_string(), LABEL_VALUE.to_string());
+
+    add_mounts(&mut spec, &create_options, module.name());
+
+    // As with docker, the edge agent decides which network a module is on.
+    if let Some(endpoints) = create_options
+        .networking_config()
+        .and_then(|networking_config| networking_config.endpoints_config())
+    {
+        spec.networks = endpoints
+            .keys()
+            .map(|network| (network.clone(), PerNetworkOptions::default()))
+            .collect();
+    }
+    if !spec.networks.is_empty() {
+        spec.netns = Some(Namespace {
+            nsmode: "bridge".to_string(),
+        });
+    }
+
+    let (restart_policy, restart_tries) = restart_policy(&create_options, module);
+    spec.restart_policy = restart_policy;
+    spec.restart_tries = restart_tries;
+
+    Ok(spec)
+}
+
+// Variables in the create options take precedence over the module's, as with
+// the docker runtime.
+fn env(
+    create_options: &ContainerCreateBody,
+    module: &ModuleSpec<DockerConfig>,
+) -> BTreeMap<String, String> {
+    let mut env: BTreeMap<String, String> = module
+        .env()
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    if let Some(create_env) = create_options.env() {
+        env.extend(create_env.iter().filter_map(|s| {
+            let mut tokens = s.splitn(2, '=');
+            tokens
+                .next()
+                .map(|key| (key.to_string(), tokens.next().unwrap_or("").to_string()))
+        }));
+    }
+
+    env
+}
+
+// Binds of host paths become bind mounts, other binds name a volume.
+fn add_mounts(spec: &mut SpecGenerator, create_options: &ContainerCreateBody, name: &str) {
+    let host_config = match create_options.host_config() {
+        Some(host_config) => host_config,
+        None => return,
+    };
+
+    for bind in host_config.binds().unwrap_or_default() {
+        let mut parts = bind.splitn(3, ':');
+        let (source, destination) =
+            if let (Some(source), Some(destination)) = (parts.next(), parts.next()) {
+                (source, destination)
+            } else {
+                warn!("Ignoring invalid bind {:?} of module {}", bind, name);
+                continue;
+            };
+        let options: Vec<String> = parts
+            .next()
+            .map(|options| options.split(',').map(ToOwned::to_owned).collect())
+            .unwrap_or_default();
+
+        if Path::new(source).is_absolute() {
+            spec.mounts.push(Mount {
+                destination: destination.to_string(),
+                type_: "bind".to_string(),
+                source: source.to_string(),
+                options,
+            });
+        } else {
+            spec.volumes.push(NamedVolume {
+                name: source.to_string(),
+                dest: destination.to_string(),
+                options,
+            });
+        }
+    }
+
+    for mount in host_config.mounts().unwrap_or_default() {
+        let mut options = if mount.read_only().copied().unwrap_or_default() {
+            vec!["ro".to_string()]
+        } else {
+            vec![]
+        };
+        if let Some(propagation) = mount.bind_options().and_then(|o| o.propagation()) {
+            options.push(propagation.to_string());
+        }
+        if let Some(tmpfs_options) = mount.tmpfs_options() {
+            if let Some(size) = tmpfs_options.size_bytes() {
+                options.push(format!("size={}", size));
+            }
+            if let Some(mode) = tmpfs_options.mode() {
+                options.push(format!("mode={:o}", mode));
+            }
+        }
+        match (mount._type(), mount.source(), mount.target()) {
+            (Some("bind"), Some(source), Some(target)) => spec.mounts.push(Mount {
+                destination: target.to_string(),
+                type_: "bind".to_string(),
+                source: source.to_string(),
+                options,
+            }),
+            (Some("volume"), Some(source), Some(target)) => spec.volumes.push(NamedVolume {
+                name: source.to_string(),
+                dest: target.to_string(),
+                options,
+            }),
+            (Some("tmpfs"), _, Some(target)) => spec.mounts.push(tmpfs_mount(target, options)),
+            _ => warn!("Ignoring unsupported mount of module {}", name),
+        }
+    }
+
+    for (target, options) in host_config.tmpfs().into_iter().flatten() {
+        let options = options
+            .split(',')
+            .filter(|option| !option.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        spec.mounts.push(tmpfs_mount(target, options));
+    }
+}
+
+fn tmpfs_mount(target: &str, options: Vec<String>) -> Mount {
+    Mount {
+        destination: target.to_string(),
+        type_: "tmpfs".to_string(),
+        source: "tmpfs".to_string(),
+        options,
+    }
+}
+
+fn port_mappings(create_options: &ContainerCreateBody, name: &str) -> Vec<PortMapping> {
+    let port_bindings = match create_options
+        .host_config()
+        .and_then(|host_config| host_config.port_bindings())
+    {
+        Some(port_bindings) => port_bindings,
+        None => return vec![],
+    };
+
+    let mut mappings = vec![];
+    for (port, bindings) in port_bindings {
+        let mut parts = port.splitn(2, '/');
+        let container_port =
+            if let Some(container_port) = parts.next().and_then(|port| port.parse().ok()) {
+                container_port
+            } else {
+                warn!("Ignoring invalid port {:?} of module {}", port, name);
+                continue;
+            };
+        let protocol = parts.next().unwrap_or("tcp");
+
+        for binding in bindings {
+            let host_port = binding
+                .host_port()
+                .and_then(|host_port| host_port.parse().ok())
+                .unwrap_or(container_port);
+            mappings.push(PortMapping {
+                container_port,
+                host_port,
+                protocol: protocol.to_string(),
+            });
+        }
+    }
+    mappings.sort_by_key(|mapping| (mapping.container_port, mapping.host_port));
+    mappings
+}
+
+// A restart policy in the create options takes precedence over the module's,
+// as with the docker runtime.
+fn restart_policy(
+    create_options: &ContainerCreateBody,
+    module: &ModuleSpec<DockerConfig>,
+) -> (Option<String>, Option<u32>) {
+    let create_options_policy = create_options
+        .host_config()
+        .and_then(|host_config| host_config.restart_policy());
+    if let Some(policy) = create_options_policy {
+        if let Some(name) = policy.name().filter(|name| !name.is_empty()) {
+            let tries = policy
+                .maximum_retry_count()
+                .and_then(|count| std::convert::TryFrom::try_from(count).ok())
+                .filter(|count| *count > 0);
+            return (Some(name.to_string()), tries);
+        }
+    }
+
+    match module.restart_policy() {
+        RestartPolicy::Never => (None, None),
+        // Podman can't tell an unhealthy container apart from a healthy one,
+        // so it can only take care of the failures.
+        RestartPolicy::OnFailure | RestartPolicy::OnUnhealthy => {
+            (Some("on-failure".to_string()), module.restart_max_retries())
+        }
+        RestartPolicy::Always => (Some("unless-stopped".to_string()), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use edgelet_core::{ContainerRestartPolicy, CreateOptions, ImagePullPolicy};
+
+    fn module(create_options: serde_json::Value) -> ModuleSpec<DockerConfig> {
+        let create_options: ContainerCreateBody = serde_json::from_value(create_options).unwrap();
+        let mut env = HashMap::new();
+        env.insert("K1".to_string(), "module".to_string());
+        env.insert("K2".to_string(), "module".to_string());
+
+        ModuleSpec::new(
+            "mod1".to_string(),
+            MODULE_TYPE.to_string(),
+            DockerConfig::new("ubuntu:18.04".to_string(), create_options, None).unwrap(),
+            env,
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn spec_generator_translates_create_options() {
+        let module = module(serde_json::json!({
+            "Hostname": "host1",
+            "Entrypoint": ["/bin/sh"],
+            "Cmd": ["-c", "sleep 1"],
+            "Env": ["K2=create_options"],
+            "Labels": { "L1": "V1" },
+            "StopSignal": "SIGINT",
+            "StopTimeout": 30,
+            "HostConfig": {
+                "Binds": ["/data:/data:ro", "vol1:/vol1"],
+                "PortBindings": { "8883/tcp": [{ "HostPort": "18883" }] },
+                "Privileged": true,
+            },
+            "NetworkingConfig": { "EndpointsConfig": { "azure-iot-edge": {} } },
+        }));
+
+        let spec = spec_generator(&module).unwrap();
+
+        assert_eq!("mod1", spec.name);
+        assert_eq!("ubuntu:18.04", spec.image);
+        assert_eq!(Some("host1".to_string()), spec.hostname);
+        assert_eq!(vec!["/bin/sh".to_string()], spec.entrypoint);
+        assert_eq!(vec!["-c".to_string(), "sleep 1".to_string()], spec.command);
+        assert_eq!("module", spec.env["K1"]);
+        assert_eq!("create_options", spec.env["K2"]);
+        assert_eq!("V1", spec.labels["L1"]);
+        assert_eq!(LABEL_VALUE, spec.labels[LABEL_KEY]);
+        assert_eq!(Some(2), spec.stop_signal);
+        assert_eq!(Some(30), spec.stop_timeout);
+        assert_eq!(
+            vec![Mount {
+                destination: "/data".to_string(),
+                type_: "bind".to_string(),
+                source: "/data".to_string(),
+                options: vec!["ro".to_string()],
+            }],
+            spec.mounts
+        );
+        assert_eq!(
+            vec![NamedVolume {
+                name: "vol1".to_string(),
+                dest: "/vol1".to_string(),
+                options: vec![],
+            }],
+            spec.volumes
+        );
+        assert_eq!(
+            vec![PortMapping {
+                container_port: 8883,
+                host_port: 18883,
+                protocol: "tcp".to_string(),
+            }],
+            spec.portmappings
+        );
+        assert!(spec.privileged);
+        assert!(spec.networks.contains_key("azure-iot-edge"));
+        assert_eq!(Some("bridge".to_string()), spec.netns.map(|ns| ns.nsmode));
+    }
+
+    #[test]
+    fn spec_generator_without_network_uses_podman_default() {
+        let spec = spec_generator(&module(serde_json::json!({}))).unwrap();
+
+        assert!(spec.networks.is_empty());
+        assert_eq!(None, spec.netns);
+    }
+
+    #[test]
+    fn restart_policy_from_module() {
+        let module = module(serde_json::json!({}))
+            .with_restart_policy(RestartPolicy::OnFailure)
+            .with_restart_max_retries(Some(3));
+        let spec = spec_generator(&module).unwrap();
+        assert_eq!(Some("on-failure".to_string()), spec.restart_policy);
+        assert_eq!(Some(3), spec.restart_tries);
+
+        let module = module.with_restart_policy(RestartPolicy::Always);
+        let spec = spec_generator(&module).unwrap();
+        assert_eq!(Some("unless-stopped".to_string()), spec.restart_policy);
+    }
+
+    #[test]
+    fn restart_policy_from_create_options_wins() {
+        let create_options = CreateOptions::new()
+            .with_restart_policy(ContainerRestartPolicy::Always)
+            .build()
+            .unwrap();
+        let module = module(create_options).with_restart_policy(RestartPolicy::OnFailure);
+        let spec = spec_generator(&module).unwrap();
+
+        assert_eq!(Some("always".to_string()), spec.restart_policy);
+        assert_eq!(None, spec.restart_tries);
+    }
+
+    #[test]
+    fn runtime_state_from_inspect() {
+        let container: InspectContainer = serde_json::from_value(serde_json::json!({
+            "Id": "abc",
+            "Name": "mod1",
+            "Image": "sha256:123",
+            "State": {
+                "Status": "exited",
+                "ExitCode": 1,
+                "Pid": 0,
+                "StartedAt": "2019-10-01T00:00:00.123456789Z",
+                "FinishedAt": "0001-01-01T00:00:00Z",
+            },
+            "RestartCount": 2,
+        }))
+        .unwrap();
+
+        let state = runtime_state(&container);
+
+        assert_eq!(&ModuleStatus::Failed, state.status());
+        assert_eq!(Some(1), state.exit_code());
+        assert_eq!(Some("exited"), state.status_description());
+        assert_eq!(
+            Some(&Utc.ymd(2019, 10, 1).and_hms_nano(0, 0, 0, 123_456_789)),
+            state.started_at()
+        );
+        assert_eq!(None, state.finished_at());
+        assert_eq!(Some("sha256:123"), state.image_id());
+        assert_eq!(None, state.pid());
+        assert_eq!(Some(2), state.restart_count());
+    }
+
+    #[test]
+    fn host_pids_reads_hpid_column() {
+        let top = TopResponse {
+            titles: vec!["HPID".to_string()],
+            processes: vec![vec!["123".to_string()], vec!["456".to_string()]],
+        };
+        assert_eq!(vec![123, 456], host_pids(&top));
+
+        let top = TopResponse {
+            titles: vec!["PID".to_string()],
+            processes: vec![vec!["1".to_string()]],
+        };
+        assert!(host_pids(&top).is_empty());
+    }
+
+    #[test]
+    fn spec_generator_translates_tmpfs_and_propagation() {
+        let module = module(serde_json::json!({
+            "HostConfig": {
+                "Mounts": [
+                    {
+                        "Type": "bind",
+                        "Source": "/dev",
+                        "Target": "/host/dev",
+                        "BindOptions": { "Propagation": "rslave" },
+                    },
+                    {
+                        "Type": "tmpfs",
+                        "Target": "/scratch",
+                        "TmpfsOptions": { "SizeBytes": 1_048_576, "Mode": 0o700 },
+                    },
+                ],
+                "Tmpfs": { "/run": "rw,noexec" },
+            },
+        }));
+
+        let spec = spec_generator(&module).unwrap();
+
+        assert_eq!(
+            vec![
+                Mount {
+                    destination: "/host/dev".to_string(),
+                    type_: "bind".to_string(),
+                    source: "/dev".to_string(),
+                    options: vec!["rslave".to_string()],
+                },
+                Mount {
+                    destination: "/scratch".to_string(),
+                    type_: "tmpfs".to_string(),
+                    source: "tmpfs".to_string(),
+                    options: vec!["size=1048576".to_string(), "mode=700".to_string()],
+                },
+                Mount {
+                    destination: "/run".to_string(),
+                    type_: "tmpfs".to_string(),
+                    source: "tmpfs".to_string(),
+                    options: vec!["rw".to_string(), "noexec".to_string()],
+                },
+            ],
+            spec.mounts
+        );
+    }
+}
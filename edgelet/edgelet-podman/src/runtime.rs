@@ -0,0 +1,1140 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io;
+use std::time::Duration;
+
+use failure::{Fail, ResultExt};
+use futures::future::Either;
+use futures::prelude::*;
+use futures::{future, stream, Async, Stream};
+use hyper::{Body, Chunk as HyperChunk, Method, Request};
+use log::{debug, info, Level};
+use url::percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET, PATH_SEGMENT_ENCODE_SET};
+
+use docker::models::ContainerCreateBody;
+use edgelet_core::{
+    AuthId, Authenticator, Chunked, GetTrustBundle, ImageInfo, LogChunk, LogDecode, LogOptions,
+    MakeModuleRuntime, Module, ModuleEvent, ModuleExecResult, ModuleId, ModuleProcesses,
+    ModuleRegistry, ModuleRuntime, ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec,
+    PrunedImages, RegistryOperation, RuntimeOperation, SystemInfo, SystemResources,
+};
+use edgelet_docker::{read_image_archive, validate_module, DockerConfig, MODULE_TYPE};
+use edgelet_http::Pid;
+use edgelet_utils::log_failure;
+use provisioning::ProvisioningResult;
+
+use crate::client::{with_query, PodmanClient};
+use crate::error::{Error, ErrorKind, Result};
+use crate::models::{
+    CreateResponse, ExecCreate, ExecInspect, ExecStart, Info, InspectContainer, ListContainer,
+    ListImage, NetworkCreate, PullReport, TopResponse,
+};
+use crate::module::{
+    container_path, runtime_state, spec_generator, PodmanModule, LABEL_KEY, LABEL_VALUE,
+};
+use crate::settings::Settings;
+
+#[derive(Clone)]
+pub struct PodmanModuleRuntime {
+    client: PodmanClient,
+    settings: Settings,
+}
+
+impl PodmanModuleRuntime {
+    fn list_containers(&self) -> impl Future<Item = Vec<ListContainer>, Error = Error> + Send {
+        let filters = serde_json::json!({
+            "label": [format!("{}={}", LABEL_KEY, LABEL_VALUE)],
+        })
+        .to_string();
+
+        self.client.get(&with_query(
+            "/containers/json",
+            &[("all", "true"), ("filters", &filters)],
+        ))
+    }
+
+    fn inspect(&self, name: &str) -> impl Future<Item = InspectContainer, Error = Error> + Send {
+        self.client.get(&format!("{}/json", container_path(name)))
+    }
+
+    fn module(
+        &self,
+        id: String,
+        name: &str,
+        image: String,
+        image_id: String,
+    ) -> Result<PodmanModule> {
+        // libpod reports names without docker's leading slash, but be lenient
+        let name = name.trim_start_matches('/').to_string();
+
+        let mut config = DockerConfig::new(image, ContainerCreateBody::new(), None).context(
+            ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name.clone())),
+        )?;
+        if !image_id.is_empty() {
+            config = config.with_image_id(image_id);
+        }
+
+        PodmanModule::new(self.client.clone(), id, name, config)
+    }
+
+    // Podman only creates a network when it's asked to, and the edge agent
+    // expects the network to exist when it creates modules.
+    fn ensure_network(&self) -> impl Future<Item = (), Error = Error> + Send {
+        let client = self.client.clone();
+        let network = self.settings.podman_runtime().network().to_string();
+        info!("Using runtime network id {}", network);
+
+        self.client
+            .execute(
+                Method::GET,
+                &format!(
+                    "/networks/{}/exists",
+                    utf8_percent_encode(&network, PATH_SEGMENT_ENCODE_SET)
+                ),
+            )
+            .then(move |result| match result {
+                Ok(()) => Either::A(future::ok(())),
+                Err(ref err) if is_not_found(err) => Either::B(
+                    client
+                        .post(
+                            "/networks/create",
+                            &NetworkCreate {
+                                name: network,
+                                driver: "bridge".to_string(),
+                                dns_enabled: true,
+                            },
+                        )
+                        .map(|_: serde_json::Value| ()),
+                ),
+                Err(err) => Either::A(future::err(err)),
+            })
+    }
+}
+
+impl ModuleRegistry for PodmanModuleRuntime {
+    type Error = Error;
+    type PullFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type Config = DockerConfig;
+
+    fn pull(&self, config: &Self::Config) -> Self::PullFuture {
+        let image = config.image().to_string();
+
+        if let Some(image_archive) = config.image_archive() {
+            info!(
+                "Loading image {} from {}...",
+                image,
+                image_archive.display()
+            );
+
+            let client = self.client.clone();
+            let archive = read_image_archive(image_archive).with_context(|_| {
+                ErrorKind::RegistryOperation(RegistryOperation::LoadImage(image.clone()))
+            });
+            return Box::new(
+                future::result(archive)
+                    .from_err()
+                    .and_then(move |archive| {
+                        client.load_images(archive).then(|result| match result {
+                            Ok(()) => Ok(image),
+                            Err(err) => Err(Error::from_podman_error(
+                                err,
+                                ErrorKind::RegistryOperation(RegistryOperation::LoadImage(image)),
+                            )),
+                        })
+                    })
+                    .then(|result| match result {
+                        Ok(image) => {
+                            info!("Successfully loaded image {}", image);
+                            Ok(())
+                        }
+                        Err(err) => {
+                            log_failure(Level::Warn, &err);
+                            Err(err)
+                        }
+                    }),
+            );
+        }
+
+        info!("Pulling image {}...", image);
+
+        let creds: Result<Option<String>> = config.auth().map_or_else(
+            || Ok(None),
+            |a| {
+                let json = serde_json::to_string(a).with_context(|_| {
+                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image.clone()))
+                })?;
+                Ok(Some(base64::encode(&json)))
+            },
+        );
+
+        let client = self.client.clone();
+        let path = with_query(
+            "/images/pull",
+            &[("reference", &image), ("policy", "always")],
+        );
+        Box::new(
+            future::result(creds)
+                .and_then(move |creds| {
+                    client
+                        .send(Method::POST, &path, None, creds)
+                        .and_then(|body| {
+                            body.concat2()
+                                .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                        })
+                })
+                .and_then(|body| check_pull_reports(&body))
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully pulled image {}", image);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn remove(&self, name: &str) -> Self::RemoveFuture {
+        info!("Removing image {}...", name);
+
+        let name = name.to_string();
+        Box::new(
+            self.client
+                .execute(
+                    Method::DELETE,
+                    &format!("/images/{}", utf8_percent_encode(&name, DEFAULT_ENCODE_SET)),
+                )
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully removed image {}", name);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RegistryOperation(RegistryOperation::RemoveImage(name)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+}
+
+impl MakeModuleRuntime for PodmanModuleRuntime {
+    type Config = DockerConfig;
+    type Settings = Settings;
+    type ProvisioningResult = ProvisioningResult;
+    type ModuleRuntime = Self;
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Self, Error = Self::Error> + Send>;
+
+    fn make_runtime(
+        settings: Settings,
+        _: ProvisioningResult,
+        _: impl GetTrustBundle,
+    ) -> Self::Future {
+        info!("Initializing module runtime...");
+
+        let uri = settings.podman_runtime().uri();
+        let client = match PodmanClient::new(&uri) {
+            Ok(client) => client,
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
+        let runtime = PodmanModuleRuntime { client, settings };
+        let fut = runtime
+            .client
+            .get("/info")
+            .and_then(move |info: Info| {
+                info!(
+                    "Using podman {} at {}{}",
+                    info.version.version,
+                    uri,
+                    if info.host.security.rootless {
+                        " in rootless mode"
+                    } else {
+                        ""
+                    }
+                );
+                runtime.ensure_network().map(|_| runtime)
+            })
+            .then(|result| match result {
+                Ok(runtime) => {
+                    info!("Successfully initialized module runtime");
+                    Ok(runtime)
+                }
+                Err(err) => {
+                    let err = Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::Init),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            });
+
+        Box::new(fut)
+    }
+}
+
+impl ModuleRuntime for PodmanModuleRuntime {
+    type Error = Error;
+    type Config = DockerConfig;
+    type Module = PodmanModule;
+    type ModuleRegistry = Self;
+    type Chunk = Chunk;
+    type Logs = Logs;
+
+    type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type GetFuture =
+        Box<dyn Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type ListFuture = Box<dyn Future<Item = Vec<Self::Module>, Error = Self::Error> + Send>;
+    type ListWithDetailsStream =
+        Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type SystemInfoFuture = Box<dyn Future<Item = SystemInfo, Error = Self::Error> + Send>;
+    type SystemResourcesFuture =
+        Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
+    type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = ModuleExecResult, Error = Self::Error> + Send>;
+    type CopyToFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type CopyFromFuture = Box<dyn Future<Item = Vec<u8>, Error = Self::Error> + Send>;
+    type ExportImageFuture = Box<dyn Future<Item = Vec<u8>, Error = Self::Error> + Send>;
+    type ImportImagesFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ListImagesFuture = Box<dyn Future<Item = Vec<ImageInfo>, Error = Self::Error> + Send>;
+    type PruneImagesFuture = Box<dyn Future<Item = PrunedImages, Error = Self::Error> + Send>;
+    type TopFuture = Box<dyn Future<Item = ModuleProcesses, Error = Self::Error> + Send>;
+    type ValidateFuture = future::FutureResult<(), Self::Error>;
+    type EventStream = stream::Empty<ModuleEvent, Self::Error>;
+
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        info!("Creating module {}...", module.name());
+
+        // we only want "docker" modules
+        if module.type_() != MODULE_TYPE {
+            return Box::new(future::err(Error::from(ErrorKind::InvalidModuleType(
+                module.type_().to_string(),
+            ))));
+        }
+
+        let name = module.name().to_string();
+        let spec = match spec_generator(&module) {
+            Ok(spec) => spec,
+            Err(err) => {
+                let err = Error::from_podman_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+                );
+                log_failure(Level::Warn, &err);
+                return Box::new(future::err(err));
+            }
+        };
+
+        debug!(
+            "Creating container {} with image {}",
+            name,
+            module.config().image()
+        );
+
+        Box::new(self.client.post("/containers/create", &spec).then(
+            move |result: Result<CreateResponse>| match result {
+                Ok(response) => {
+                    debug!("Created container {} for module {}", response.id, name);
+                    info!("Successfully created module {}", name);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            },
+        ))
+    }
+
+    fn get(&self, id: &str) -> Self::GetFuture {
+        debug!("Getting module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        Box::new(
+            self.inspect(&id)
+                .and_then(move |container| {
+                    let state = runtime_state(&container);
+                    runtime
+                        .module(
+                            container.id,
+                            &container.name,
+                            container.image_name,
+                            container.image,
+                        )
+                        .map(|module| (module, state))
+                })
+                .map_err(|err| {
+                    Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(id)),
+                    )
+                }),
+        )
+    }
+
+    fn start(&self, id: &str) -> Self::StartFuture {
+        info!("Starting module {}...", id);
+
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .execute(Method::POST, &format!("{}/start", container_path(&id)))
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully started module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        info!("Stopping module {}...", id);
+
+        // Without a timeout, podman waits as long as the module's own stop
+        // timeout before it kills it.
+        let path = format!("{}/stop", container_path(id));
+        let path = match wait_before_kill {
+            Some(wait_before_kill) => with_query(
+                &path,
+                &[("timeout", &wait_before_kill.as_secs().to_string())],
+            ),
+            None => path,
+        };
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .execute(Method::POST, &path)
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully stopped module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        info!("Restarting module {}...", id);
+
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .execute(Method::POST, &format!("{}/restart", container_path(&id)))
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully restarted module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        info!("Removing module {}...", id);
+
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .execute(
+                    Method::DELETE,
+                    &with_query(&container_path(&id), &[("force", "true")]),
+                )
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully removed module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RemoveModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn rename(&self, id: &str, new_id: &str) -> Self::RenameFuture {
+        info!("Renaming module {} to {}...", id, new_id);
+
+        let id = id.to_string();
+        let new_id = new_id.to_string();
+        Box::new(
+            self.client
+                .execute(
+                    Method::POST,
+                    &with_query(
+                        &format!("{}/rename", container_path(&id)),
+                        &[("name", &new_id)],
+                    ),
+                )
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully renamed module {} to {}", id, new_id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RenameModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn system_info(&self) -> Self::SystemInfoFuture {
+        info!("Querying system info...");
+
+        Box::new(
+            self.client
+                .get("/info")
+                .then(|result: Result<Info>| match result {
+                    Ok(info) => {
+                        info!("Successfully queried system info");
+                        Ok(SystemInfo::new(info.host.os, info.host.arch))
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::SystemInfo),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        // TODO: add support for system resources with podman
+        Box::new(future::ok(SystemResources::new(
+            0,
+            0,
+            0.0,
+            0,
+            0,
+            vec![],
+            "".to_owned(),
+        )))
+    }
+
+    fn list(&self) -> Self::ListFuture {
+        debug!("Listing modules...");
+
+        let runtime = self.clone();
+        Box::new(
+            self.list_containers()
+                .and_then(move |containers| {
+                    containers
+                        .into_iter()
+                        .map(|container| {
+                            let name = container.names.first().cloned().unwrap_or_default();
+                            runtime.module(container.id, &name, container.image, container.image_id)
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+                .map_err(|err| {
+                    let err = Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ListModules),
+                    );
+                    log_failure(Level::Warn, &err);
+                    err
+                }),
+        )
+    }
+
+    fn list_with_details(&self) -> Self::ListWithDetailsStream {
+        Box::new(
+            self.list()
+                .map(|modules| {
+                    stream::iter_ok(modules).and_then(|module| {
+                        module.runtime_state().then(|result| match result {
+                            Ok(state) => Ok(Some((module, state))),
+                            // The module was removed after it was listed
+                            Err(ref err) if is_not_found(err) => Ok(None),
+                            Err(err) => Err(err),
+                        })
+                    })
+                })
+                .flatten_stream()
+                .filter_map(|module| module),
+        )
+    }
+
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        info!("Getting logs for module {}...", id);
+
+        // libpod streams logs in docker's format, so they are passed through
+        let follow = options.follow().to_string();
+        let since = options.since().to_string();
+        let until = options.until().to_string();
+        let timestamps = options.timestamps().to_string();
+        let tail = options.tail().to_string();
+        let mut params = vec![
+            ("stdout", "true"),
+            ("stderr", "true"),
+            ("follow", follow.as_str()),
+            ("since", since.as_str()),
+            ("timestamps", timestamps.as_str()),
+            ("tail", tail.as_str()),
+        ];
+        // an until of 0 would be taken as the epoch, leaving no logs
+        if options.until() != 0 {
+            params.push(("until", until.as_str()));
+        }
+        let path = with_query(&format!("{}/logs", container_path(id)), &params);
+
+        let id = id.to_string();
+        Box::new(self.client.send(Method::GET, &path, None, None).then(
+            move |result| match result {
+                Ok(logs) => {
+                    info!("Successfully got logs for module {}", id);
+                    Ok(Logs(id, logs))
+                }
+                Err(err) => {
+                    let err = Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            },
+        ))
+    }
+
+    fn registry(&self) -> &Self::ModuleRegistry {
+        self
+    }
+
+    fn remove_all(&self) -> Self::RemoveAllFuture {
+        let runtime = self.clone();
+        Box::new(self.list().and_then(move |modules| {
+            future::join_all(
+                modules
+                    .into_iter()
+                    .map(move |module| ModuleRuntime::remove(&runtime, module.name())),
+            )
+            .map(|_| ())
+        }))
+    }
+
+    fn exec(&self, id: &str, command: &[String]) -> Self::ExecFuture {
+        info!("Running command in module {}...", id);
+
+        let exec = ExecCreate {
+            attach_stdout: true,
+            attach_stderr: true,
+            cmd: command.to_vec(),
+        };
+        let start = serde_json::to_vec(&ExecStart {
+            detach: false,
+            tty: false,
+        })
+        .expect("exec start request is serializable");
+
+        let client = self.client.clone();
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .post(&format!("{}/exec", container_path(&id)), &exec)
+                .and_then(move |response: CreateResponse| {
+                    let exec_path = format!(
+                        "/exec/{}",
+                        utf8_percent_encode(&response.id, PATH_SEGMENT_ENCODE_SET)
+                    );
+                    client
+                        .send(
+                            Method::POST,
+                            &format!("{}/start", exec_path),
+                            Some(start),
+                            None,
+                        )
+                        .and_then(|body| {
+                            exec_output(body)
+                                .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                        })
+                        .and_then(move |(stdout, stderr)| {
+                            client.get(&format!("{}/json", exec_path)).map(
+                                move |inspect: ExecInspect| {
+                                    ModuleExecResult::new(inspect.exit_code, stdout, stderr)
+                                },
+                            )
+                        })
+                })
+                .then(move |result| match result {
+                    Ok(result) => {
+                        info!("Successfully ran command in module {}", id);
+                        Ok(result)
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn copy_to(&self, id: &str, path: &str, archive: Vec<u8>) -> Self::CopyToFuture {
+        info!("Copying files to {} in module {}...", path, id);
+
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .put_archive(
+                    &with_query(
+                        &format!("{}/archive", container_path(&id)),
+                        &[("path", path)],
+                    ),
+                    archive,
+                )
+                .then(move |result| match result {
+                    Ok(()) => {
+                        info!("Successfully copied files to module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CopyToModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn copy_from(&self, id: &str, path: &str) -> Self::CopyFromFuture {
+        info!("Copying {} from module {}...", path, id);
+
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .send(
+                    Method::GET,
+                    &with_query(
+                        &format!("{}/archive", container_path(&id)),
+                        &[("path", path)],
+                    ),
+                    None,
+                    None,
+                )
+                .and_then(|body| {
+                    body.concat2()
+                        .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                })
+                .then(move |result| match result {
+                    Ok(archive) => {
+                        info!("Successfully copied files from module {}", id);
+                        Ok(archive.to_vec())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::CopyFromModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture {
+        info!("Exporting the image of module {}...", id);
+
+        let id = id.to_string();
+        let client = self.client.clone();
+        Box::new(
+            self.inspect(&id)
+                .and_then(move |container| {
+                    // The image is saved by name rather than by id, which would leave the
+                    // tags out of the archive.
+                    let image = container.image_name;
+                    client
+                        .send(
+                            Method::GET,
+                            &with_query(
+                                &format!(
+                                    "/images/{}/get",
+                                    utf8_percent_encode(&image, DEFAULT_ENCODE_SET)
+                                ),
+                                &[("format", "docker-archive")],
+                            ),
+                            None,
+                            None,
+                        )
+                        .and_then(|body| {
+                            body.concat2()
+                                .map_err(|err| Error::from(err.context(ErrorKind::Hyper)))
+                        })
+                        .map(|archive| (image, archive))
+                })
+                .then(move |result| match result {
+                    Ok((image, archive)) => {
+                        info!("Successfully exported image {} of module {}", image, id);
+                        Ok(archive.to_vec())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ExportModuleImage(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn import_images(&self, archive: Vec<u8>) -> Self::ImportImagesFuture {
+        info!("Importing images...");
+
+        Box::new(
+            self.client
+                .load_images(archive)
+                .then(|result| match result {
+                    Ok(()) => {
+                        info!("Successfully imported images");
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        debug!("Listing images...");
+
+        let in_use = self.list_containers().map(|containers| {
+            containers
+                .into_iter()
+                .map(|container| container.image_id)
+                .collect::<HashSet<_>>()
+        });
+        let images = self.client.get::<Vec<ListImage>>("/images/json");
+
+        Box::new(
+            in_use
+                .join(images)
+                .map(|(in_use, images)| {
+                    images
+                        .into_iter()
+                        .map(|image| {
+                            let in_use = in_use.contains(&image.id);
+                            let size = u64::try_from(image.size).unwrap_or_default();
+                            ImageInfo::new(image.id, image.repo_tags.unwrap_or_default(), size)
+                                .with_in_use(in_use)
+                        })
+                        .collect()
+                })
+                .map_err(|err| {
+                    let err = Error::from_podman_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ListImages),
+                    );
+                    log_failure(Level::Warn, &err);
+                    err
+                }),
+        )
+    }
+
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        info!("Pruning images...");
+
+        Box::new(self.client.prune_images().then(|result| match result {
+            Ok(reports) => {
+                // Images that couldn't be removed are reported alongside the removed ones
+                let removed: Vec<_> = reports
+                    .into_iter()
+                    .filter(|report| report.err.is_none())
+                    .collect();
+                let space_reclaimed = removed.iter().map(|report| report.size).sum();
+                let images: Vec<_> = removed.into_iter().map(|report| report.id).collect();
+                info!(
+                    "Successfully pruned {} images, reclaiming {} bytes",
+                    images.len(),
+                    space_reclaimed
+                );
+                Ok(PrunedImages::new(images, space_reclaimed))
+            }
+            Err(err) => {
+                let err = Error::from_podman_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages),
+                );
+                log_failure(Level::Warn, &err);
+                Err(err)
+            }
+        }))
+    }
+
+    fn top(&self, id: &str) -> Self::TopFuture {
+        debug!("Listing processes of module {}...", id);
+
+        let id = id.to_string();
+        Box::new(
+            self.client
+                .get(&format!("{}/top", container_path(&id)))
+                .then(move |result: Result<TopResponse>| match result {
+                    Ok(top) => {
+                        debug!("Successfully listed processes of module {}", id);
+                        Ok(ModuleProcesses::new(top.titles, top.processes))
+                    }
+                    Err(err) => {
+                        let err = Error::from_podman_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn validate(&self, module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        debug!("Validating module {}...", module.name());
+        let context = || {
+            ErrorKind::RuntimeOperation(RuntimeOperation::ValidateModule(module.name().to_string()))
+        };
+
+        if module.type_() != MODULE_TYPE {
+            return future::err(Error::from(
+                ErrorKind::InvalidModuleType(module.type_().to_string()).context(context()),
+            ));
+        }
+
+        let result = validate_module(&module).map_err(|err| Error::from(err.context(context())));
+        if let Err(err) = &result {
+            log_failure(Level::Warn, err);
+        }
+        future::result(result)
+    }
+
+    fn events(&self) -> Self::EventStream {
+        stream::empty()
+    }
+}
+
+// Exec output is multiplexed the same way as logs.
+fn exec_output(output: Body) -> impl Future<Item = (Vec<u8>, Vec<u8>), Error = io::Error> + Send {
+    let output = Chunked::new(output.map_err(|err| io::Error::new(io::ErrorKind::Other, err)));
+    LogDecode::new(output).fold(
+        (vec![], vec![]),
+        |(mut stdout, mut stderr), chunk| -> io::Result<_> {
+            match chunk {
+                LogChunk::Stdout(bytes) => stdout.extend_from_slice(&bytes),
+                LogChunk::Stderr(bytes) => stderr.extend_from_slice(&bytes),
+                LogChunk::Stdin(_) | LogChunk::Unknown(_) => (),
+            }
+            Ok((stdout, stderr))
+        },
+    )
+}
+
+impl Authenticator for PodmanModuleRuntime {
+    type Error = Error;
+    type Request = Request<Body>;
+    type AuthenticateFuture = Box<dyn Future<Item = AuthId, Error = Self::Error> + Send>;
+
+    fn authenticate(&self, req: &Self::Request) -> Self::AuthenticateFuture {
+        let pid = req
+            .extensions()
+            .get::<Pid>()
+            .cloned()
+            .unwrap_or_else(|| Pid::None);
+
+        let expected_module_id = req.extensions().get::<ModuleId>().cloned();
+
+        Box::new(match (pid, expected_module_id) {
+            (Pid::None, _) | (Pid::Value(_), None) => Either::A(future::ok(AuthId::None)),
+            (Pid::Any, _) => Either::A(future::ok(AuthId::Any)),
+            // The caller is the module if its pid is one of the module's
+            // processes, as seen from the host.
+            (Pid::Value(pid), Some(expected_module_id)) => Either::B(
+                self.get(&expected_module_id.to_string())
+                    .and_then(|(module, _)| module.top())
+                    .then(move |result| match result {
+                        Ok(pids) if pids.contains(&pid) => Ok(AuthId::Value(expected_module_id)),
+                        Ok(_) => {
+                            info!("Unable to find a module for caller pid: {}", pid);
+                            Ok(AuthId::None)
+                        }
+                        Err(ref err) if is_not_found(err) => Ok(AuthId::None),
+                        Err(err) => {
+                            log_failure(Level::Warn, &err);
+                            Err(err)
+                        }
+                    }),
+            ),
+        })
+    }
+}
+
+fn is_not_found(err: &Error) -> bool {
+    match (err.kind(), ModuleRuntimeErrorReason::from(err)) {
+        (ErrorKind::NotFound(_), _) | (_, ModuleRuntimeErrorReason::NotFound) => true,
+        _ => false,
+    }
+}
+
+// An image pull responds with a stream of JSON documents that report its
+// progress. Failures are reported in that stream rather than with a status.
+fn check_pull_reports(body: &[u8]) -> Result<()> {
+    for report in serde_json::Deserializer::from_slice(body).into_iter::<PullReport>() {
+        let report = report.context(ErrorKind::InvalidResponse)?;
+        if let Some(error) = report.error {
+            return Err(Error::from(ErrorKind::ImagePull(error)));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct Logs(String, Body);
+
+impl Stream for Logs {
+    type Item = Chunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.1.poll() {
+            Ok(Async::Ready(chunk)) => Ok(Async::Ready(chunk.map(Chunk))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::GetModuleLogs(self.0.clone()),
+            )))),
+        }
+    }
+}
+
+impl From<Logs> for Body {
+    fn from(logs: Logs) -> Self {
+        logs.1
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk(HyperChunk);
+
+impl IntoIterator for Chunk {
+    type Item = u8;
+    type IntoIter = <HyperChunk as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Extend<u8> for Chunk {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = u8>,
+    {
+        self.0.extend(iter)
+    }
+}
+
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pull_reports_without_error_succeed() {
+        let body = br#"{"stream":"Trying to pull mcr.microsoft.com/azureiotedge-agent:1.0...\n"}
+{"images":["abc"],"id":"abc"}
+"#;
+        assert!(check_pull_reports(body).is_ok());
+    }
+
+    #[test]
+    fn pull_reports_with_error_fail() {
+        let body = br#"{"stream":"Trying to pull boo:1.0...\n"}
+{"error":"initializing source docker://boo:1.0: reading manifest 1.0 in docker.io/library/boo: requested access to the resource is denied"}
+"#;
+        match check_pull_reports(body).unwrap_err().kind() {
+            ErrorKind::ImagePull(message) => assert!(message.contains("access")),
+            kind => panic!("Expected `ImagePull` but got {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,389 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use config::{Config, Environment};
+use docker::models::{ContainerCreateBodyNetworkingConfig, EndpointSettings, HostConfig};
+use edgelet_core::{
+    Certificates, Connect, DeviceActionSettings, DiagnosticsSettings, HealthReportSettings,
+    LifecycleHookSettings, Listen, LogFormat, ModuleSpec, OrphanedModuleSettings, Provisioning,
+    RateLimitSettings, RetrySettings, RuntimeSettings, Settings as BaseSettings, Tenant, UrlExt,
+    WatchdogSettings, REQUIRED_SETTINGS, SETTINGS_ALIASES,
+};
+use edgelet_docker::{DockerConfig, DEFAULTS};
+use edgelet_utils::{SettingsFile, YamlFileSource};
+use failure::ResultExt;
+use url::Url;
+
+use crate::error::{Error, ErrorKind};
+
+/// The socket of the system wide podman service.
+const ROOTFUL_URI: &str = "unix:///run/podman/podman.sock";
+
+const DEFAULT_NETWORK: &str = "azure-iot-edge";
+
+/// This is the key for the podman network Id, which the edge agent reads the
+/// same way it does with docker.
+const EDGE_NETWORKID_KEY: &str = "NetworkId";
+
+const UNIX_SCHEME: &str = "unix";
+
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct PodmanRuntime {
+    #[serde(with = "url_serde", default, skip_serializing_if = "Option::is_none")]
+    uri: Option<Url>,
+    #[serde(default = "default_network")]
+    network: String,
+}
+
+impl Default for PodmanRuntime {
+    fn default() -> Self {
+        PodmanRuntime {
+            uri: None,
+            network: default_network(),
+        }
+    }
+}
+
+impl PodmanRuntime {
+    /// The socket of the podman service. When it isn't configured, this is
+    /// the system wide socket if iotedged runs as root, and the socket of the
+    /// user's rootless podman service otherwise.
+    pub fn uri(&self) -> Url {
+        self.uri.clone().unwrap_or_else(|| {
+            default_uri(
+                euid(),
+                std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from),
+            )
+        })
+    }
+
+    /// The network modules are attached to.
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+}
+
+#[cfg(unix)]
+fn euid() -> u32 {
+    // Safe because geteuid has no preconditions and can't fail.
+    unsafe { libc::geteuid() }
+}
+
+// Podman only runs rootless on linux.
+#[cfg(not(unix))]
+fn euid() -> u32 {
+    0
+}
+
+fn default_uri(euid: u32, xdg_runtime_dir: Option<PathBuf>) -> Url {
+    if euid == 0 {
+        return Url::parse(ROOTFUL_URI).expect("default podman uri is valid");
+    }
+
+    let runtime_dir =
+        xdg_runtime_dir.unwrap_or_else(|| PathBuf::from(format!("/run/user/{}", euid)));
+    let socket = runtime_dir.join("podman").join("podman.sock");
+    Url::parse(&format!("{}://{}", UNIX_SCHEME, socket.display()))
+        .unwrap_or_else(|_| Url::parse(ROOTFUL_URI).expect("default podman uri is valid"))
+}
+
+fn default_network() -> String {
+    DEFAULT_NETWORK.to_string()
+}
+
+/// This struct is the same as the Settings type from the `edgelet_docker`
+/// crate except that modules are run by podman instead of docker.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Settings {
+    #[serde(flatten)]
+    base: BaseSettings<DockerConfig>,
+    #[serde(default)]
+    podman_runtime: PodmanRuntime,
+}
+
+impl Settings {
+    pub fn new(filename: &Path) -> Result<Self, Error> {
+        let file = SettingsFile::open(filename).context(ErrorKind::Config)?;
+        file.check(REQUIRED_SETTINGS).context(ErrorKind::Config)?;
+
+        let mut config = Config::default();
+        config
+            .merge(YamlFileSource::String(DEFAULTS))
+            .context(ErrorKind::Config)?;
+
+        file.merge_into(&mut config).context(ErrorKind::Config)?;
+
+        config
+            .merge(Environment::with_prefix("iotedge"))
+            .context(ErrorKind::Config)?;
+
+        let mut settings: Self = file
+            .deserialize::<Self, BaseSettings<DockerConfig>>(config)
+            .context(ErrorKind::Config)?;
+        file.warn_unknown_keys(&settings, SETTINGS_ALIASES);
+
+        agent_vol_mount(&mut settings)?;
+        agent_env(&mut settings);
+        agent_networking(&mut settings)?;
+
+        Ok(settings)
+    }
+
+    pub fn podman_runtime(&self) -> &PodmanRuntime {
+        &self.podman_runtime
+    }
+}
+
+impl RuntimeSettings for Settings {
+    type Config = DockerConfig;
+
+    fn provisioning(&self) -> &Provisioning {
+        self.base.provisioning()
+    }
+
+    fn agent(&self) -> &ModuleSpec<DockerConfig> {
+        self.base.agent()
+    }
+
+    fn agent_mut(&mut self) -> &mut ModuleSpec<DockerConfig> {
+        self.base.agent_mut()
+    }
+
+    fn hostname(&self) -> &str {
+        self.base.hostname()
+    }
+
+    fn parent_hostname(&self) -> Option<&str> {
+        self.base.parent_hostname()
+    }
+
+    fn connect(&self) -> &Connect {
+        self.base.connect()
+    }
+
+    fn listen(&self) -> &Listen {
+        self.base.listen()
+    }
+
+    fn homedir(&self) -> &Path {
+        self.base.homedir()
+    }
+
+    fn certificates(&self) -> &Certificates {
+        self.base.certificates()
+    }
+
+    fn watchdog(&self) -> &WatchdogSettings {
+        self.base.watchdog()
+    }
+
+    fn log_level(&self) -> Option<&str> {
+        self.base.log_level()
+    }
+
+    fn log_format(&self) -> LogFormat {
+        self.base.log_format()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        self.base.tenants()
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        self.base.lifecycle_hook()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.base.workload_rate_limit()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        self.base.retry()
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        self.base.device_actions()
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        self.base.health_report()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        self.base.orphaned_modules()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        self.base.diagnostics()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        self.base.secrets()
+    }
+}
+
+// Mounts the workload and management sockets into the edge agent, the same
+// way the docker runtime does.
+fn agent_vol_mount(settings: &mut Settings) -> Result<(), Error> {
+    let create_options = settings
+        .agent()
+        .config()
+        .clone_create_options()
+        .context(ErrorKind::Config)?;
+    let host_config = create_options
+        .host_config()
+        .cloned()
+        .unwrap_or_else(HostConfig::new);
+    let mut binds = host_config.binds().map_or_else(Vec::new, ToOwned::to_owned);
+
+    for uri in &[
+        settings.connect().management_uri(),
+        settings.connect().workload_uri(),
+    ] {
+        if uri.scheme() == UNIX_SCHEME {
+            let path = uri.to_uds_file_path().context(ErrorKind::Config)?;
+            let path = path.to_str().ok_or(ErrorKind::Config)?.to_string();
+            let bind = format!("{}:{}", &path, &path);
+            if !binds.contains(&bind) {
+                binds.push(bind);
+            }
+        }
+    }
+
+    if !binds.is_empty() {
+        let host_config = host_config.with_binds(binds);
+        let create_options = create_options.with_host_config(host_config);
+
+        settings
+            .agent_mut()
+            .config_mut()
+            .set_create_options(create_options);
+    }
+
+    Ok(())
+}
+
+fn agent_env(settings: &mut Settings) {
+    let network_id = settings.podman_runtime().network().to_string();
+    settings
+        .agent_mut()
+        .env_mut()
+        .insert(EDGE_NETWORKID_KEY.to_string(), network_id);
+}
+
+fn agent_networking(settings: &mut Settings) -> Result<(), Error> {
+    let network_id = settings.podman_runtime().network().to_string();
+
+    let create_options = settings
+        .agent()
+        .config()
+        .clone_create_options()
+        .context(ErrorKind::Config)?;
+
+    let mut network_config = create_options
+        .networking_config()
+        .cloned()
+        .unwrap_or_else(ContainerCreateBodyNetworkingConfig::new);
+
+    let mut endpoints_config = network_config
+        .endpoints_config()
+        .cloned()
+        .unwrap_or_else(HashMap::new);
+
+    if !endpoints_config.contains_key(network_id.as_str()) {
+        endpoints_config.insert(network_id, EndpointSettings::new());
+        network_config = network_config.with_endpoints_config(endpoints_config);
+        let create_options = create_options.with_networking_config(network_config);
+
+        settings
+            .agent_mut()
+            .config_mut()
+            .set_create_options(create_options);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    fn load(yaml: &str) -> Settings {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+        Settings::new(file.path()).unwrap()
+    }
+
+    const SETTINGS: &str = r#"
+provisioning:
+  source: "manual"
+  device_connection_string: "HostName=moo.azure-devices.net;DeviceId=boo;SharedAccessKey=boo"
+"#;
+
+    #[test]
+    fn root_uses_system_socket() {
+        assert_eq!(
+            "unix:///run/podman/podman.sock",
+            default_uri(0, Some(PathBuf::from("/run/user/0"))).as_str()
+        );
+    }
+
+    #[test]
+    fn rootless_uses_user_socket() {
+        assert_eq!(
+            "unix:///run/user/1000/podman/podman.sock",
+            default_uri(1000, None).as_str()
+        );
+        assert_eq!(
+            "unix:///tmp/xdg/podman/podman.sock",
+            default_uri(1000, Some(PathBuf::from("/tmp/xdg"))).as_str()
+        );
+    }
+
+    #[test]
+    fn podman_runtime_is_configurable() {
+        let settings = load(&format!(
+            r#"{}
+podman_runtime:
+  uri: "unix:///var/run/podman.sock"
+  network: "edge"
+"#,
+            SETTINGS
+        ));
+
+        assert_eq!(
+            "unix:///var/run/podman.sock",
+            settings.podman_runtime().uri().as_str()
+        );
+        assert_eq!("edge", settings.podman_runtime().network());
+    }
+
+    #[test]
+    fn agent_gets_network_and_socket_mounts() {
+        let settings = load(SETTINGS);
+
+        assert_eq!("azure-iot-edge", settings.podman_runtime().network());
+        assert_eq!(
+            Some(&"azure-iot-edge".to_string()),
+            settings.agent().env().get(EDGE_NETWORKID_KEY)
+        );
+
+        let create_options = settings.agent().config().create_options();
+        let binds = create_options.host_config().unwrap().binds().unwrap();
+        assert!(
+            binds.contains(&"/var/run/iotedge/mgmt.sock:/var/run/iotedge/mgmt.sock".to_string())
+        );
+        assert!(create_options
+            .networking_config()
+            .unwrap()
+            .endpoints_config()
+            .unwrap()
+            .contains_key("azure-iot-edge"));
+    }
+}
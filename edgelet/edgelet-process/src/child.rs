@@ -0,0 +1,295 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Modules that run as child processes of the daemon.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use failure::{Fail, ResultExt};
+use futures::{Future, Stream};
+use log::warn;
+use tokio::timer::Interval;
+
+use edgelet_core::{ModuleRuntimeState, ModuleStatus, RuntimeOperation};
+
+use crate::command::ModuleCommand;
+use crate::error::{Error, ErrorKind, Result};
+
+/// How often a stopping module is checked for having exited.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct ChildProcess {
+    child: Child,
+    started_at: DateTime<Utc>,
+    exited: Option<(i64, DateTime<Utc>)>,
+}
+
+impl ChildProcess {
+    // Reaps the process if it has exited, and returns whether it is still running.
+    fn is_running(&mut self) -> bool {
+        if self.exited.is_none() {
+            match self.child.try_wait() {
+                Ok(Some(status)) => self.exited = Some((exit_code(status), Utc::now())),
+                Ok(None) => (),
+                Err(err) => warn!(
+                    "Could not check module process {}: {}",
+                    self.child.id(),
+                    err
+                ),
+            }
+        }
+
+        self.exited.is_none()
+    }
+}
+
+/// The module processes started by this daemon, by module name.
+#[derive(Clone, Default)]
+pub struct Children {
+    children: Arc<Mutex<HashMap<String, ChildProcess>>>,
+}
+
+impl Children {
+    pub fn start(&self, name: &str, command: &ModuleCommand, dir: &Path, log: &Path) -> Result<()> {
+        let mut children = self.children.lock().expect("children lock poisoned");
+        if let Some(child) = children.get_mut(name) {
+            if child.is_running() {
+                return Ok(());
+            }
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log)
+            .context(ErrorKind::ModuleDirectory)?;
+        let stderr = log.try_clone().context(ErrorKind::ModuleDirectory)?;
+
+        let child = Command::new(&command.program)
+            .args(&command.args)
+            .env_clear()
+            .envs(&command.env)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(log)
+            .stderr(stderr)
+            .spawn()
+            .map_err(|err| ErrorKind::Command(command.program.clone(), err.to_string()))?;
+
+        children.insert(
+            name.to_string(),
+            ChildProcess {
+                child,
+                started_at: Utc::now(),
+                exited: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Asks a module's process to stop, and kills it if it hasn't after the
+    /// given timeout.
+    pub fn stop(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> impl Future<Item = (), Error = Error> + Send {
+        self.terminate(name);
+
+        let deadline = Instant::now() + timeout;
+        let children = self.clone();
+        let killer = self.clone();
+        let name = name.to_string();
+        let name_for_kill = name.clone();
+        Interval::new(Instant::now(), STOP_POLL_INTERVAL)
+            .map_err({
+                let name = name.clone();
+                move |err| {
+                    Error::from(err.context(ErrorKind::RuntimeOperation(
+                        RuntimeOperation::StopModule(name.clone()),
+                    )))
+                }
+            })
+            .take_while(move |_| Ok(children.is_running(&name) && Instant::now() < deadline))
+            .for_each(|_| Ok(()))
+            .map(move |_| killer.kill(&name_for_kill))
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        let mut children = self.children.lock().expect("children lock poisoned");
+        children
+            .get_mut(name)
+            .map_or(false, ChildProcess::is_running)
+    }
+
+    /// The state of a module's process. Modules that haven't been started by
+    /// this daemon are reported as stopped.
+    pub fn state(&self, name: &str) -> ModuleRuntimeState {
+        let mut children = self.children.lock().expect("children lock poisoned");
+        let child = match children.get_mut(name) {
+            Some(child) => child,
+            None => {
+                return ModuleRuntimeState::default()
+                    .with_status(ModuleStatus::Stopped)
+                    .with_status_description(Some("created".to_string()))
+            }
+        };
+
+        let state = ModuleRuntimeState::default().with_started_at(Some(child.started_at));
+        if child.is_running() {
+            state
+                .with_status(ModuleStatus::Running)
+                .with_status_description(Some("running".to_string()))
+                .with_pid(std::convert::TryFrom::try_from(child.child.id()).ok())
+        } else {
+            let (exit_code, finished_at) = child.exited.expect("exited process has an exit code");
+            state
+                .with_status(if exit_code == 0 {
+                    ModuleStatus::Stopped
+                } else {
+                    ModuleStatus::Failed
+                })
+                .with_status_description(Some("exited".to_string()))
+                .with_exit_code(Some(exit_code))
+                .with_finished_at(Some(finished_at))
+        }
+    }
+
+    /// Forgets a module's process, killing it if it is still running.
+    pub fn remove(&self, name: &str) {
+        self.kill(name);
+        let mut children = self.children.lock().expect("children lock poisoned");
+        children.remove(name);
+    }
+
+    fn terminate(&self, name: &str) {
+        let mut children = self.children.lock().expect("children lock poisoned");
+        if let Some(child) = children.get_mut(name) {
+            if child.is_running() {
+                if let Err(err) = terminate(&mut child.child) {
+                    warn!("Could not stop module {}: {}", name, err);
+                }
+            }
+        }
+    }
+
+    fn kill(&self, name: &str) {
+        let mut children = self.children.lock().expect("children lock poisoned");
+        if let Some(child) = children.get_mut(name) {
+            if child.is_running() {
+                warn!("Module {} did not stop in time, killing it", name);
+                if let Err(err) = child.child.kill().and_then(|_| child.child.wait()) {
+                    warn!("Could not kill module {}: {}", name, err);
+                }
+                child.is_running();
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = Pid::from_raw(child.id() as i32);
+    kill(pid, Signal::SIGTERM).map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+}
+
+// There is no graceful way to stop a process on Windows.
+#[cfg(windows)]
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    child.kill()
+}
+
+// Processes killed by a signal report 128 plus the signal number, like a
+// shell would.
+#[cfg(unix)]
+fn exit_code(status: ExitStatus) -> i64 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .map_or(-1, i64::from)
+}
+
+#[cfg(windows)]
+fn exit_code(status: ExitStatus) -> i64 {
+    status.code().map_or(-1, i64::from)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use tempfile::TempDir;
+
+    fn command(program: &str, args: &[&str]) -> ModuleCommand {
+        ModuleCommand {
+            program: program.to_string(),
+            args: args.iter().map(ToString::to_string).collect(),
+            env: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn exited_process_reports_exit_code_and_log() {
+        let dir = TempDir::new().unwrap();
+        let log = dir.path().join("module.log");
+        let children = Children::default();
+
+        assert_eq!(&ModuleStatus::Stopped, children.state("mod1").status());
+
+        children
+            .start(
+                "mod1",
+                &command("/bin/sh", &["-c", "echo hello; exit 3"]),
+                dir.path(),
+                &log,
+            )
+            .unwrap();
+        while children.is_running("mod1") {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let state = children.state("mod1");
+        assert_eq!(&ModuleStatus::Failed, state.status());
+        assert_eq!(Some(3), state.exit_code());
+        assert_eq!(None, state.pid());
+        assert_eq!("hello\n", std::fs::read_to_string(&log).unwrap());
+    }
+
+    #[test]
+    fn stop_terminates_process() {
+        let dir = TempDir::new().unwrap();
+        let children = Children::default();
+        children
+            .start(
+                "mod1",
+                &command("/bin/sleep", &["30"]),
+                dir.path(),
+                &dir.path().join("module.log"),
+            )
+            .unwrap();
+        assert!(children.state("mod1").pid().is_some());
+        assert_eq!(&ModuleStatus::Running, children.state("mod1").status());
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime
+            .block_on(children.stop("mod1", Duration::from_secs(10)))
+            .unwrap();
+
+        let state = children.state("mod1");
+        assert!(!children.is_running("mod1"));
+        assert_eq!(Some(128 + 15), state.exit_code());
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use failure::ResultExt;
+
+use edgelet_core::{Connect, ModuleSpec};
+use edgelet_docker::DockerConfig;
+
+use crate::error::{ErrorKind, Result};
+
+/// Modules find the daemon's APIs through these variables, the same ones the
+/// edge agent is started with.
+const WORKLOAD_URI_KEY: &str = "IOTEDGE_WORKLOADURI";
+const MANAGEMENT_URI_KEY: &str = "IOTEDGE_MANAGEMENTURI";
+
+/// Modules don't inherit the daemon's environment, except for the search
+/// path of executables.
+pub const PATH_KEY: &str = "PATH";
+
+/// What a module process is started with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl ModuleCommand {
+    /// Translates a module spec into the command that runs it.
+    ///
+    /// The module's image is the program that is run, unless the create
+    /// options have an `Entrypoint`, in which case that is run like docker
+    /// would. `Cmd` is appended to the arguments. Variables in the create
+    /// options' `Env` take precedence over the module's, and the workload and
+    /// management URIs are added when the module doesn't set them itself.
+    pub fn new(module: &ModuleSpec<DockerConfig>, connect: &Connect) -> Result<Self> {
+        let create_options = module
+            .config()
+            .clone_create_options()
+            .context(ErrorKind::CloneCreateOptions)?;
+
+        let mut entrypoint = create_options
+            .entrypoint()
+            .map_or_else(Vec::new, ToOwned::to_owned)
+            .into_iter();
+        let program = entrypoint
+            .next()
+            .unwrap_or_else(|| module.config().image().to_string());
+        let mut args: Vec<String> = entrypoint.collect();
+        if let Some(cmd) = create_options.cmd() {
+            args.extend(cmd.iter().cloned());
+        }
+
+        let mut env: BTreeMap<String, String> = module
+            .env()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        if let Some(create_env) = create_options.env() {
+            env.extend(create_env.iter().filter_map(|s| {
+                let mut tokens = s.splitn(2, '=');
+                tokens
+                    .next()
+                    .map(|key| (key.to_string(), tokens.next().unwrap_or("").to_string()))
+            }));
+        }
+        env.entry(WORKLOAD_URI_KEY.to_string())
+            .or_insert_with(|| connect.workload_uri().to_string());
+        env.entry(MANAGEMENT_URI_KEY.to_string())
+            .or_insert_with(|| connect.management_uri().to_string());
+        if let Ok(path) = std::env::var(PATH_KEY) {
+            env.entry(PATH_KEY.to_string()).or_insert(path);
+        }
+
+        Ok(ModuleCommand { program, args, env })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use docker::models::ContainerCreateBody;
+    use edgelet_core::ImagePullPolicy;
+    use edgelet_docker::MODULE_TYPE;
+
+    fn connect() -> Connect {
+        serde_json::from_value(serde_json::json!({
+            "workload_uri": "unix:///var/run/iotedge/workload.sock",
+            "management_uri": "unix:///var/run/iotedge/mgmt.sock",
+        }))
+        .unwrap()
+    }
+
+    fn module(create_options: serde_json::Value, env: &[(&str, &str)]) -> ModuleSpec<DockerConfig> {
+        let create_options: ContainerCreateBody = serde_json::from_value(create_options).unwrap();
+        let env: HashMap<String, String> = env
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect();
+
+        ModuleSpec::new(
+            "mod1".to_string(),
+            MODULE_TYPE.to_string(),
+            DockerConfig::new("/usr/bin/mod1".to_string(), create_options, None).unwrap(),
+            env,
+            ImagePullPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn image_is_the_program() {
+        let command = ModuleCommand::new(
+            &module(serde_json::json!({ "Cmd": ["--verbose"] }), &[]),
+            &connect(),
+        )
+        .unwrap();
+
+        assert_eq!("/usr/bin/mod1", command.program);
+        assert_eq!(vec!["--verbose".to_string()], command.args);
+    }
+
+    #[test]
+    fn entrypoint_replaces_the_program() {
+        let command = ModuleCommand::new(
+            &module(
+                serde_json::json!({
+                    "Entrypoint": ["/usr/bin/python3", "main.py"],
+                    "Cmd": ["--verbose"],
+                }),
+                &[],
+            ),
+            &connect(),
+        )
+        .unwrap();
+
+        assert_eq!("/usr/bin/python3", command.program);
+        assert_eq!(
+            vec!["main.py".to_string(), "--verbose".to_string()],
+            command.args
+        );
+    }
+
+    #[test]
+    fn env_is_merged_and_uris_are_injected() {
+        let command = ModuleCommand::new(
+            &module(
+                serde_json::json!({ "Env": ["K2=create_options"] }),
+                &[("K1", "module"), ("K2", "module")],
+            ),
+            &connect(),
+        )
+        .unwrap();
+
+        assert_eq!("module", command.env["K1"]);
+        assert_eq!("create_options", command.env["K2"]);
+        assert_eq!(
+            "unix:///var/run/iotedge/workload.sock",
+            command.env[WORKLOAD_URI_KEY]
+        );
+        assert_eq!(
+            "unix:///var/run/iotedge/mgmt.sock",
+            command.env[MANAGEMENT_URI_KEY]
+        );
+    }
+
+    #[test]
+    fn module_uris_are_kept() {
+        let command = ModuleCommand::new(
+            &module(
+                serde_json::json!({}),
+                &[(WORKLOAD_URI_KEY, "http://localhost:15581")],
+            ),
+            &connect(),
+        )
+        .unwrap();
+
+        assert_eq!("http://localhost:15581", command.env[WORKLOAD_URI_KEY]);
+    }
+}
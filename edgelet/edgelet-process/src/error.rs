@@ -0,0 +1,107 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::fmt::Display;
+
+use failure::{Backtrace, Context, Fail};
+
+use edgelet_core::{ModuleRuntimeErrorReason, RegistryOperation, RuntimeOperation};
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[derive(Debug, Fail)]
+pub enum ErrorKind {
+    #[fail(display = "Could not clone create options")]
+    CloneCreateOptions,
+
+    #[fail(display = "Command {:?} failed: {}", _0, _1)]
+    Command(String, String),
+
+    #[fail(display = "Config parsing error")]
+    Config,
+
+    #[fail(display = "Invalid module name {:?}", _0)]
+    InvalidModuleName(String),
+
+    #[fail(display = "Invalid module type {:?}", _0)]
+    InvalidModuleType(String),
+
+    #[fail(display = "Module directory error")]
+    ModuleDirectory,
+
+    #[fail(display = "{}", _0)]
+    NotFound(String),
+
+    #[fail(display = "{} is not supported by the process runtime", _0)]
+    NotSupported(&'static str),
+
+    #[fail(display = "Program {:?} was not found", _0)]
+    ProgramNotFound(String),
+
+    #[fail(display = "{}", _0)]
+    RegistryOperation(RegistryOperation),
+
+    #[fail(display = "{}", _0)]
+    RuntimeOperation(RuntimeOperation),
+}
+
+impl Fail for Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.inner.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.inner.backtrace()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+
+    // Keeps "not found" errors as the root cause so that callers can tell
+    // them apart from other failures.
+    pub fn from_process_error(err: Error, context: ErrorKind) -> Self {
+        match err.kind() {
+            ErrorKind::NotFound(message) => {
+                ErrorKind::NotFound(message.clone()).context(context).into()
+            }
+            _ => err.context(context).into(),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            inner: Context::new(kind),
+        }
+    }
+}
+
+impl From<Context<ErrorKind>> for Error {
+    fn from(inner: Context<ErrorKind>) -> Self {
+        Error { inner }
+    }
+}
+
+impl<'a> From<&'a Error> for ModuleRuntimeErrorReason {
+    fn from(err: &'a Error) -> Self {
+        match Fail::find_root_cause(err).downcast_ref::<ErrorKind>() {
+            Some(ErrorKind::NotFound(_)) => ModuleRuntimeErrorReason::NotFound,
+            _ => ModuleRuntimeErrorReason::Other,
+        }
+    }
+}
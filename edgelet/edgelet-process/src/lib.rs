@@ -0,0 +1,32 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::module_name_repetitions,
+    clippy::must_use_candidate,
+    clippy::too_many_lines,
+    clippy::use_self
+)]
+
+//! Module runtime that runs modules as processes on the host instead of in
+//! containers, for devices that are too constrained to run a container engine.
+//!
+//! Modules are deployed with the same settings as docker modules, except that
+//! the image is the path of the module's executable. They are either started
+//! as child processes of the daemon or as transient systemd units.
+
+mod child;
+mod command;
+mod error;
+mod logs;
+mod module;
+mod runtime;
+mod settings;
+mod systemd;
+
+pub use error::{Error, ErrorKind, Result};
+pub use module::ProcessModule;
+pub use runtime::ProcessModuleRuntime;
+pub use settings::{Launcher, ProcessRuntime, Settings};
@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::Fail;
+use futures::{Async, Poll, Stream};
+use hyper::{Body, Chunk as HyperChunk};
+
+use edgelet_core::{LogTail, RuntimeOperation};
+
+use crate::error::{Error, ErrorKind};
+
+/// Converts the output of a module to docker's log stream format, which is
+/// what callers of the management API expect. Module processes write their
+/// stdout and stderr to the same place, so all of it is reported as stdout.
+pub fn to_docker_frames(output: &str, tail: &LogTail) -> Vec<u8> {
+    let lines: Vec<&str> = output.lines().collect();
+    let skip = match tail {
+        LogTail::All => 0,
+        LogTail::Num(tail) => {
+            let tail = std::convert::TryFrom::try_from(*tail).unwrap_or(usize::max_value());
+            lines.len().saturating_sub(tail)
+        }
+    };
+
+    let mut logs = vec![];
+    for line in lines.into_iter().skip(skip) {
+        #[allow(clippy::cast_possible_truncation)]
+        let len = (line.len() + 1) as u32;
+        logs.extend_from_slice(&[1, 0, 0, 0]);
+        logs.extend_from_slice(&len.to_be_bytes());
+        logs.extend_from_slice(line.as_bytes());
+        logs.push(b'\n');
+    }
+    logs
+}
+
+#[derive(Debug)]
+pub struct Logs(pub(crate) String, pub(crate) Body);
+
+impl Stream for Logs {
+    type Item = Chunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.1.poll() {
+            Ok(Async::Ready(chunk)) => Ok(Async::Ready(chunk.map(Chunk))),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(Error::from(err.context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::GetModuleLogs(self.0.clone()),
+            )))),
+        }
+    }
+}
+
+impl From<Logs> for Body {
+    fn from(logs: Logs) -> Self {
+        logs.1
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk(HyperChunk);
+
+impl IntoIterator for Chunk {
+    type Item = u8;
+    type IntoIter = <HyperChunk as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Extend<u8> for Chunk {
+    fn extend<T>(&mut self, iter: T)
+    where
+        T: IntoIterator<Item = u8>,
+    {
+        self.0.extend(iter)
+    }
+}
+
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: &str) -> Vec<u8> {
+        let mut frame = vec![1, 0, 0, 0];
+        #[allow(clippy::cast_possible_truncation)]
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(message.as_bytes());
+        frame
+    }
+
+    #[test]
+    fn lines_become_stdout_frames() {
+        assert_eq!(
+            [frame("first\n"), frame("second\n")].concat(),
+            to_docker_frames("first\nsecond\n", &LogTail::All)
+        );
+    }
+
+    #[test]
+    fn tail_keeps_last_lines() {
+        assert_eq!(
+            frame("second\n"),
+            to_docker_frames("first\nsecond\n", &LogTail::Num(1))
+        );
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::Future;
+
+use edgelet_core::{Module, ModuleRuntimeState};
+use edgelet_docker::{DockerConfig, MODULE_TYPE};
+use edgelet_utils::ensure_not_empty_with_context;
+
+use crate::error::{Error, ErrorKind, Result};
+use crate::runtime::ProcessModuleRuntime;
+
+pub struct ProcessModule {
+    runtime: ProcessModuleRuntime,
+    name: String,
+    config: DockerConfig,
+}
+
+impl std::fmt::Debug for ProcessModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProcessModule")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl ProcessModule {
+    pub(crate) fn new(
+        runtime: ProcessModuleRuntime,
+        name: String,
+        config: DockerConfig,
+    ) -> Result<Self> {
+        ensure_not_empty_with_context(&name, || ErrorKind::InvalidModuleName(name.clone()))?;
+
+        Ok(ProcessModule {
+            runtime,
+            name,
+            config,
+        })
+    }
+}
+
+impl Module for ProcessModule {
+    type Config = DockerConfig;
+    type Error = Error;
+    type RuntimeStateFuture =
+        Box<dyn Future<Item = ModuleRuntimeState, Error = Self::Error> + Send>;
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn type_(&self) -> &str {
+        MODULE_TYPE
+    }
+
+    fn config(&self) -> &Self::Config {
+        &self.config
+    }
+
+    fn runtime_state(&self) -> Self::RuntimeStateFuture {
+        Box::new(self.runtime.state(&self.name))
+    }
+}
@@ -0,0 +1,846 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use failure::{Fail, ResultExt};
+use futures::future::Either;
+use futures::prelude::*;
+use futures::{future, stream, Stream};
+use hyper::{Body, Request};
+use log::{debug, info, warn, Level};
+
+use edgelet_core::{
+    AuthId, Authenticator, GetTrustBundle, ImageInfo, LogOptions, MakeModuleRuntime, Module,
+    ModuleEvent, ModuleExecResult, ModuleId, ModuleProcesses, ModuleRegistry, ModuleRuntime,
+    ModuleRuntimeErrorReason, ModuleRuntimeState, ModuleSpec, PrunedImages, RegistryOperation,
+    RuntimeOperation, RuntimeSettings, SystemInfo, SystemResources,
+};
+use edgelet_docker::{DockerConfig, MODULE_TYPE};
+use edgelet_http::Pid;
+use edgelet_utils::log_failure;
+use provisioning::ProvisioningResult;
+
+use crate::child::Children;
+use crate::command::{ModuleCommand, PATH_KEY};
+use crate::error::{Error, ErrorKind, Result};
+use crate::logs::{to_docker_frames, Chunk, Logs};
+use crate::module::ProcessModule;
+use crate::settings::{Launcher, Settings};
+use crate::systemd;
+
+/// How long a module is given to stop before it is killed, when the caller
+/// doesn't say. This matches docker's default.
+const DEFAULT_STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The module's spec, as it was created, in the module's directory.
+const SPEC_FILE: &str = "module.json";
+
+/// The output of child processes, in the module's directory.
+const LOG_FILE: &str = "module.log";
+
+#[derive(Clone)]
+pub struct ProcessModuleRuntime {
+    settings: Settings,
+    children: Children,
+}
+
+impl ProcessModuleRuntime {
+    fn module_directory(&self, name: &str) -> PathBuf {
+        self.settings.modules_directory().join(name)
+    }
+
+    fn read_spec(&self, name: &str) -> Result<ModuleSpec<DockerConfig>> {
+        let path = self.module_directory(name).join(SPEC_FILE);
+        let spec = fs::read(&path).map_err(|err| {
+            if err.kind() == io::ErrorKind::NotFound {
+                Error::from(ErrorKind::NotFound(format!("No such module: {}", name)))
+            } else {
+                Error::from(err.context(ErrorKind::ModuleDirectory))
+            }
+        })?;
+        let spec = serde_json::from_slice(&spec).context(ErrorKind::ModuleDirectory)?;
+        Ok(spec)
+    }
+
+    fn module(&self, spec: &ModuleSpec<DockerConfig>) -> Result<ProcessModule> {
+        ProcessModule::new(self.clone(), spec.name().to_string(), spec.config().clone())
+    }
+
+    pub(crate) fn state(
+        &self,
+        name: &str,
+    ) -> impl Future<Item = ModuleRuntimeState, Error = Error> + Send {
+        let runtime = self.clone();
+        let name = name.to_string();
+        let id = name.clone();
+        future::lazy(move || {
+            runtime.read_spec(&id)?;
+            match runtime.settings.process_runtime().launcher() {
+                Launcher::Child => Ok(runtime.children.state(&id)),
+                Launcher::Systemd => systemd::state(&id),
+            }
+        })
+        .map_err(|err| {
+            Error::from_process_error(
+                err,
+                ErrorKind::RuntimeOperation(RuntimeOperation::GetModule(name)),
+            )
+        })
+    }
+}
+
+impl ModuleRegistry for ProcessModuleRuntime {
+    type Error = Error;
+    type PullFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type Config = DockerConfig;
+
+    // Module executables are installed on the host ahead of time, so there is
+    // nothing to pull. The executable is only checked for.
+    fn pull(&self, config: &Self::Config) -> Self::PullFuture {
+        let image = config.image().to_string();
+        info!("Checking for module executable {}...", image);
+
+        if Path::new(&image).is_absolute() && !Path::new(&image).exists() {
+            let err = Error::from(
+                ErrorKind::NotFound(format!("No such executable: {}", image)).context(
+                    ErrorKind::RegistryOperation(RegistryOperation::PullImage(image)),
+                ),
+            );
+            log_failure(Level::Warn, &err);
+            return Box::new(future::err(err));
+        }
+
+        Box::new(future::ok(()))
+    }
+
+    fn remove(&self, name: &str) -> Self::RemoveFuture {
+        debug!("Not removing module executable {}", name);
+        Box::new(future::ok(()))
+    }
+}
+
+impl MakeModuleRuntime for ProcessModuleRuntime {
+    type Config = DockerConfig;
+    type Settings = Settings;
+    type ProvisioningResult = ProvisioningResult;
+    type ModuleRuntime = Self;
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Self, Error = Self::Error> + Send>;
+
+    fn make_runtime(
+        settings: Settings,
+        _: ProvisioningResult,
+        _: impl GetTrustBundle,
+    ) -> Self::Future {
+        info!("Initializing module runtime...");
+
+        let modules_directory = settings.modules_directory();
+        let result = fs::create_dir_all(&modules_directory)
+            .context(ErrorKind::ModuleDirectory)
+            .context(ErrorKind::RuntimeOperation(RuntimeOperation::Init))
+            .map_err(Error::from)
+            .map(|_| {
+                info!(
+                    "Running modules as {} in {}",
+                    match settings.process_runtime().launcher() {
+                        Launcher::Child => "child processes",
+                        Launcher::Systemd => "systemd units",
+                    },
+                    modules_directory.display()
+                );
+                info!("Successfully initialized module runtime");
+                ProcessModuleRuntime {
+                    settings,
+                    children: Children::default(),
+                }
+            })
+            .map_err(|err| {
+                log_failure(Level::Warn, &err);
+                err
+            });
+
+        Box::new(future::result(result))
+    }
+}
+
+impl ModuleRuntime for ProcessModuleRuntime {
+    type Error = Error;
+    type Config = DockerConfig;
+    type Module = ProcessModule;
+    type ModuleRegistry = Self;
+    type Chunk = Chunk;
+    type Logs = Logs;
+
+    type CreateFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type GetFuture =
+        Box<dyn Future<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type ListFuture = Box<dyn Future<Item = Vec<Self::Module>, Error = Self::Error> + Send>;
+    type ListWithDetailsStream =
+        Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
+    type LogsFuture = Box<dyn Future<Item = Self::Logs, Error = Self::Error> + Send>;
+    type RemoveFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type RenameFuture = future::FutureResult<(), Self::Error>;
+    type RestartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StartFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type StopFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type SystemInfoFuture = Box<dyn Future<Item = SystemInfo, Error = Self::Error> + Send>;
+    type SystemResourcesFuture =
+        Box<dyn Future<Item = SystemResources, Error = Self::Error> + Send>;
+    type RemoveAllFuture = Box<dyn Future<Item = (), Error = Self::Error> + Send>;
+    type ExecFuture = Box<dyn Future<Item = ModuleExecResult, Error = Self::Error> + Send>;
+    type CopyToFuture = future::FutureResult<(), Self::Error>;
+    type CopyFromFuture = future::FutureResult<Vec<u8>, Self::Error>;
+    type ExportImageFuture = future::FutureResult<Vec<u8>, Self::Error>;
+    type ImportImagesFuture = future::FutureResult<(), Self::Error>;
+    type ListImagesFuture = future::FutureResult<Vec<ImageInfo>, Self::Error>;
+    type PruneImagesFuture = future::FutureResult<PrunedImages, Self::Error>;
+    type TopFuture = Box<dyn Future<Item = ModuleProcesses, Error = Self::Error> + Send>;
+    type ValidateFuture = future::FutureResult<(), Self::Error>;
+    type EventStream = stream::Empty<ModuleEvent, Self::Error>;
+
+    fn create(&self, module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
+        info!("Creating module {}...", module.name());
+
+        // we only want "docker" modules
+        if module.type_() != MODULE_TYPE {
+            return Box::new(future::err(Error::from(ErrorKind::InvalidModuleType(
+                module.type_().to_string(),
+            ))));
+        }
+
+        let name = module.name().to_string();
+        let directory = self.module_directory(&name);
+        let result = validate_name(&name)
+            .and_then(|_| {
+                fs::create_dir_all(&directory).context(ErrorKind::ModuleDirectory)?;
+                let spec = serde_json::to_vec(&module).context(ErrorKind::ModuleDirectory)?;
+                fs::write(directory.join(SPEC_FILE), spec).context(ErrorKind::ModuleDirectory)?;
+                Ok(())
+            })
+            .map_err(|err| {
+                let err = Error::from_process_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::CreateModule(name.clone())),
+                );
+                log_failure(Level::Warn, &err);
+                err
+            })
+            .map(|_| info!("Successfully created module {}", name));
+
+        Box::new(future::result(result))
+    }
+
+    fn get(&self, id: &str) -> Self::GetFuture {
+        debug!("Getting module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        Box::new(
+            future::result(self.read_spec(&id).and_then(|spec| self.module(&spec)))
+                .and_then(move |module| runtime.state(&id).map(|state| (module, state))),
+        )
+    }
+
+    fn start(&self, id: &str) -> Self::StartFuture {
+        info!("Starting module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        Box::new(future::lazy(move || {
+            let result = runtime.read_spec(&id).and_then(|spec| {
+                let command = ModuleCommand::new(&spec, runtime.settings.connect())?;
+                let directory = runtime.module_directory(&id);
+                match runtime.settings.process_runtime().launcher() {
+                    Launcher::Child => {
+                        runtime
+                            .children
+                            .start(&id, &command, &directory, &directory.join(LOG_FILE))
+                    }
+                    Launcher::Systemd => {
+                        systemd::start(&id, &command, &directory, spec.restart_policy())
+                    }
+                }
+            });
+
+            match result {
+                Ok(()) => {
+                    info!("Successfully started module {}", id);
+                    Ok(())
+                }
+                Err(err) => {
+                    let err = Error::from_process_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::StartModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            }
+        }))
+    }
+
+    fn stop(&self, id: &str, wait_before_kill: Option<Duration>) -> Self::StopFuture {
+        info!("Stopping module {}...", id);
+
+        let id = id.to_string();
+        let stopped = match (
+            self.read_spec(&id),
+            self.settings.process_runtime().launcher(),
+        ) {
+            (Err(err), _) => Either::A(future::err(err)),
+            (Ok(_), Launcher::Child) => Either::B(
+                self.children
+                    .stop(&id, wait_before_kill.unwrap_or(DEFAULT_STOP_TIMEOUT)),
+            ),
+            // systemd gives the module the unit's stop timeout
+            (Ok(_), Launcher::Systemd) => Either::A(future::result(systemd::stop(&id))),
+        };
+
+        Box::new(stopped.then(move |result| match result {
+            Ok(()) => {
+                info!("Successfully stopped module {}", id);
+                Ok(())
+            }
+            Err(err) => {
+                let err = Error::from_process_error(
+                    err,
+                    ErrorKind::RuntimeOperation(RuntimeOperation::StopModule(id)),
+                );
+                log_failure(Level::Warn, &err);
+                Err(err)
+            }
+        }))
+    }
+
+    fn restart(&self, id: &str) -> Self::RestartFuture {
+        info!("Restarting module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        let name = id.clone();
+        Box::new(
+            self.stop(&id, None)
+                .and_then(move |_| runtime.start(&name))
+                .then(move |result| match result {
+                    Ok(_) => {
+                        info!("Successfully restarted module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        let err = Error::from_process_error(
+                            err,
+                            ErrorKind::RuntimeOperation(RuntimeOperation::RestartModule(id)),
+                        );
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    fn remove(&self, id: &str) -> Self::RemoveFuture {
+        info!("Removing module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        Box::new(
+            self.stop(&id, None)
+                .and_then(move |_| {
+                    runtime.children.remove(&id);
+                    fs::remove_dir_all(runtime.module_directory(&id))
+                        .context(ErrorKind::ModuleDirectory)
+                        .map_err(Error::from)
+                        .map(|_| id)
+                })
+                .then(|result| match result {
+                    Ok(id) => {
+                        info!("Successfully removed module {}", id);
+                        Ok(())
+                    }
+                    Err(err) => {
+                        log_failure(Level::Warn, &err);
+                        Err(err)
+                    }
+                }),
+        )
+    }
+
+    // The running process of a module is tracked under its name, and its
+    // directory holds its spec and logs, so it can't be renamed while it runs.
+    fn rename(&self, id: &str, _new_id: &str) -> Self::RenameFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Renaming modules").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::RenameModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn system_info(&self) -> Self::SystemInfoFuture {
+        // Modules always run on this host.
+        Box::new(future::ok(SystemInfo::new(
+            std::env::consts::OS.to_string(),
+            std::env::consts::ARCH.to_string(),
+        )))
+    }
+
+    fn system_resources(&self) -> Self::SystemResourcesFuture {
+        // TODO: add support for system resources with module processes
+        Box::new(future::ok(SystemResources::new(
+            0,
+            0,
+            0.0,
+            0,
+            0,
+            vec![],
+            "".to_owned(),
+        )))
+    }
+
+    fn list(&self) -> Self::ListFuture {
+        debug!("Listing modules...");
+
+        let runtime = self.clone();
+        Box::new(future::lazy(move || {
+            let entries = match fs::read_dir(runtime.settings.modules_directory()) {
+                Ok(entries) => entries,
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+                Err(err) => {
+                    let err = Error::from(
+                        err.context(ErrorKind::ModuleDirectory)
+                            .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListModules)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    return Err(err);
+                }
+            };
+
+            let mut modules = vec![];
+            for entry in entries.filter_map(std::result::Result::ok) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                match runtime
+                    .read_spec(&name)
+                    .and_then(|spec| runtime.module(&spec))
+                {
+                    Ok(module) => modules.push(module),
+                    // Not a module directory
+                    Err(ref err) if is_not_found(err) => (),
+                    Err(err) => warn!("Ignoring module {}: {}", name, err),
+                }
+            }
+            Ok(modules)
+        }))
+    }
+
+    fn list_with_details(&self) -> Self::ListWithDetailsStream {
+        Box::new(
+            self.list()
+                .map(|modules| {
+                    stream::iter_ok(modules).and_then(|module| {
+                        module.runtime_state().then(|result| match result {
+                            Ok(state) => Ok(Some((module, state))),
+                            // The module was removed after it was listed
+                            Err(ref err) if is_not_found(err) => Ok(None),
+                            Err(err) => Err(err),
+                        })
+                    })
+                })
+                .flatten_stream()
+                .filter_map(|module| module),
+        )
+    }
+
+    fn logs(&self, id: &str, options: &LogOptions) -> Self::LogsFuture {
+        info!("Getting logs for module {}...", id);
+
+        if options.follow() {
+            warn!("Following module logs is not supported with module processes");
+        }
+
+        let runtime = self.clone();
+        let options = LogOptions::new()
+            .with_tail(*options.tail())
+            .with_since(options.since());
+        let id = id.to_string();
+        Box::new(future::lazy(move || {
+            let result = runtime.read_spec(&id).and_then(|_| {
+                match runtime.settings.process_runtime().launcher() {
+                    // Child process output has no timestamps, so it can't be
+                    // filtered by time.
+                    Launcher::Child => {
+                        let output = fs::read(runtime.module_directory(&id).join(LOG_FILE))
+                            .or_else(|err| match err.kind() {
+                                io::ErrorKind::NotFound => Ok(vec![]),
+                                _ => Err(err),
+                            });
+                        let output = output.context(ErrorKind::ModuleDirectory)?;
+                        Ok(String::from_utf8_lossy(&output).into_owned())
+                    }
+                    Launcher::Systemd => systemd::logs(&id, &options)
+                        .map(|output| String::from_utf8_lossy(&output).into_owned()),
+                }
+            });
+
+            match result {
+                Ok(output) => {
+                    let logs = to_docker_frames(&output, options.tail());
+                    Ok(Logs(id, Body::from(logs)))
+                }
+                Err(err) => {
+                    let err = Error::from_process_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::GetModuleLogs(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            }
+        }))
+    }
+
+    fn registry(&self) -> &Self::ModuleRegistry {
+        self
+    }
+
+    fn remove_all(&self) -> Self::RemoveAllFuture {
+        let runtime = self.clone();
+        Box::new(self.list().and_then(move |modules| {
+            future::join_all(
+                modules
+                    .into_iter()
+                    .map(move |module| ModuleRuntime::remove(&runtime, module.name())),
+            )
+            .map(|_| ())
+        }))
+    }
+
+    // The command is run with the module's environment, in its directory.
+    fn exec(&self, id: &str, command: &[String]) -> Self::ExecFuture {
+        info!("Running command in module {}...", id);
+
+        let runtime = self.clone();
+        let id = id.to_string();
+        let command = command.to_vec();
+        Box::new(future::lazy(move || {
+            let result = runtime.read_spec(&id).and_then(|spec| {
+                let module_command = ModuleCommand::new(&spec, runtime.settings.connect())?;
+                let (program, args) = command.split_first().ok_or_else(|| {
+                    ErrorKind::Command(String::new(), "empty command".to_string())
+                })?;
+                let output = Command::new(program)
+                    .args(args)
+                    .env_clear()
+                    .envs(&module_command.env)
+                    .current_dir(runtime.module_directory(&id))
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(|err| ErrorKind::Command(program.clone(), err.to_string()))?;
+                Ok(ModuleExecResult::new(
+                    output.status.code().map_or(-1, i64::from),
+                    output.stdout,
+                    output.stderr,
+                ))
+            });
+
+            match result {
+                Ok(result) => {
+                    info!("Successfully ran command in module {}", id);
+                    Ok(result)
+                }
+                Err(err) => {
+                    let err = Error::from_process_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::ExecModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    Err(err)
+                }
+            }
+        }))
+    }
+
+    // Modules share the host's filesystem, so there is nothing to copy into.
+    fn copy_to(&self, id: &str, _path: &str, _archive: Vec<u8>) -> Self::CopyToFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Copying files").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CopyToModule(id.to_string()),
+            )),
+        ))
+    }
+
+    fn copy_from(&self, id: &str, _path: &str) -> Self::CopyFromFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Copying files").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::CopyFromModule(id.to_string()),
+            )),
+        ))
+    }
+
+    // Module executables aren't images, so there is nothing to export or import.
+    fn export_image(&self, id: &str) -> Self::ExportImageFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Exporting images").context(ErrorKind::RuntimeOperation(
+                RuntimeOperation::ExportModuleImage(id.to_string()),
+            )),
+        ))
+    }
+
+    fn import_images(&self, _archive: Vec<u8>) -> Self::ImportImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Importing images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ImportImages)),
+        ))
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Listing images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::ListImages)),
+        ))
+    }
+
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        future::err(Error::from(
+            ErrorKind::NotSupported("Pruning images")
+                .context(ErrorKind::RuntimeOperation(RuntimeOperation::PruneImages)),
+        ))
+    }
+
+    // The module's processes are its main process and all of its descendants.
+    fn top(&self, id: &str) -> Self::TopFuture {
+        debug!("Listing processes of module {}...", id);
+
+        let id = id.to_string();
+        Box::new(
+            self.state(&id)
+                .map(|state| {
+                    ModuleProcesses::new(
+                        vec!["PID".to_string(), "PPID".to_string(), "CMD".to_string()],
+                        state.pid().map_or_else(Vec::new, processes),
+                    )
+                })
+                .map_err(|err| {
+                    let err = Error::from_process_error(
+                        err,
+                        ErrorKind::RuntimeOperation(RuntimeOperation::TopModule(id)),
+                    );
+                    log_failure(Level::Warn, &err);
+                    err
+                }),
+        )
+    }
+
+    // A module can be started if its name is usable as a directory and its
+    // program can be found.
+    fn validate(&self, module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        debug!("Validating module {}...", module.name());
+
+        let name = module.name().to_string();
+        let result = if module.type_() == MODULE_TYPE {
+            Ok(())
+        } else {
+            Err(Error::from(ErrorKind::InvalidModuleType(
+                module.type_().to_string(),
+            )))
+        }
+        .and_then(|_| validate_name(&name))
+        .and_then(|_| ModuleCommand::new(&module, self.settings.connect()))
+        .and_then(|command| {
+            if program_exists(&command.program, command.env.get(PATH_KEY)) {
+                Ok(())
+            } else {
+                Err(Error::from(ErrorKind::ProgramNotFound(command.program)))
+            }
+        })
+        .map_err(|err| {
+            let err = Error::from_process_error(
+                err,
+                ErrorKind::RuntimeOperation(RuntimeOperation::ValidateModule(name)),
+            );
+            log_failure(Level::Warn, &err);
+            err
+        });
+
+        future::result(result)
+    }
+
+    fn events(&self) -> Self::EventStream {
+        stream::empty()
+    }
+}
+
+impl Authenticator for ProcessModuleRuntime {
+    type Error = Error;
+    type Request = Request<Body>;
+    type AuthenticateFuture = Box<dyn Future<Item = AuthId, Error = Self::Error> + Send>;
+
+    fn authenticate(&self, req: &Self::Request) -> Self::AuthenticateFuture {
+        let pid = req
+            .extensions()
+            .get::<Pid>()
+            .copied()
+            .unwrap_or_else(|| Pid::None);
+
+        let expected_module_id = req.extensions().get::<ModuleId>().cloned();
+
+        Box::new(match (pid, expected_module_id) {
+            (Pid::None, _) | (Pid::Value(_), None) => Either::A(future::ok(AuthId::None)),
+            (Pid::Any, _) => Either::A(future::ok(AuthId::Any)),
+            // The caller is the module if it is the module's process or one
+            // of its descendants.
+            (Pid::Value(pid), Some(expected_module_id)) => Either::B(
+                self.state(&expected_module_id.to_string())
+                    .then(move |result| match result {
+                        Ok(state) => match state.pid() {
+                            Some(module_pid) if is_descendant(pid, module_pid) => {
+                                Ok(AuthId::Value(expected_module_id))
+                            }
+                            _ => {
+                                info!("Unable to find a module for caller pid: {}", pid);
+                                Ok(AuthId::None)
+                            }
+                        },
+                        Err(ref err) if is_not_found(err) => Ok(AuthId::None),
+                        Err(err) => {
+                            log_failure(Level::Warn, &err);
+                            Err(err)
+                        }
+                    }),
+            ),
+        })
+    }
+}
+
+// Module names are used as directory and unit names.
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(&['/', '\\'][..]) {
+        Err(Error::from(ErrorKind::InvalidModuleName(name.to_string())))
+    } else {
+        Ok(())
+    }
+}
+
+// Programs without a directory are looked up in the module's search path,
+// as they are when the module is started. Relative paths are resolved when
+// the module starts, so only absolute ones are checked.
+fn program_exists(program: &str, path: Option<&String>) -> bool {
+    let program = Path::new(program);
+    if program.is_absolute() {
+        program.is_file()
+    } else if program.components().count() > 1 {
+        true
+    } else {
+        path.map_or(false, |path| {
+            std::env::split_paths(path).any(|dir| dir.join(program).is_file())
+        })
+    }
+}
+
+fn is_not_found(err: &Error) -> bool {
+    match (err.kind(), ModuleRuntimeErrorReason::from(err)) {
+        (ErrorKind::NotFound(_), _) | (_, ModuleRuntimeErrorReason::NotFound) => true,
+        _ => false,
+    }
+}
+
+fn is_descendant(pid: i32, ancestor: i32) -> bool {
+    let mut pid = pid;
+    while pid > 1 {
+        if pid == ancestor {
+            return true;
+        }
+        pid = match parent_pid(pid) {
+            Some(parent) => parent,
+            None => return false,
+        };
+    }
+    false
+}
+
+// Processes that exit while they are listed are left out.
+fn processes(module_pid: i32) -> Vec<Vec<String>> {
+    let mut pids: Vec<i32> = fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .filter(|&pid| is_descendant(pid, module_pid))
+                .collect()
+        })
+        .unwrap_or_default();
+    pids.sort_unstable();
+
+    pids.into_iter()
+        .filter_map(|pid| {
+            let ppid = parent_pid(pid)?;
+            let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+            let cmd = cmdline
+                .split(|&b| b == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(String::from_utf8_lossy)
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(vec![pid.to_string(), ppid.to_string(), cmd])
+        })
+        .collect()
+}
+
+// The parent pid is the second field after the process name, which is in
+// parentheses and can itself contain spaces and parentheses.
+fn parent_pid(pid: i32) -> Option<i32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_names_must_be_file_names() {
+        assert!(validate_name("mod1").is_ok());
+        assert!(validate_name("edge-hub_1").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("..").is_err());
+        assert!(validate_name("../mod1").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn programs_are_found_in_the_search_path() {
+        let path = "/usr/local/bin:/bin".to_string();
+
+        assert!(program_exists("/bin/sh", None));
+        assert!(!program_exists("/bin/does-not-exist", None));
+        assert!(program_exists("sh", Some(&path)));
+        assert!(!program_exists("does-not-exist", Some(&path)));
+        assert!(!program_exists("sh", None));
+        assert!(program_exists("bin/mod1", None));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn processes_include_the_module_process() {
+        #[allow(clippy::cast_possible_wrap)]
+        let pid = std::process::id() as i32;
+        let processes = processes(pid);
+
+        assert_eq!(pid.to_string(), processes[0][0]);
+        assert!(!processes[0][2].is_empty());
+    }
+
+    #[test]
+    fn process_is_its_own_and_its_parents_descendant() {
+        #[allow(clippy::cast_possible_wrap)]
+        let pid = std::process::id() as i32;
+        let parent = parent_pid(pid).unwrap();
+
+        assert!(is_descendant(pid, pid));
+        assert!(is_descendant(pid, parent));
+        assert!(!is_descendant(parent, pid));
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use config::{Config, Environment};
+use edgelet_core::{
+    Certificates, Connect, DeviceActionSettings, DiagnosticsSettings, HealthReportSettings,
+    LifecycleHookSettings, Listen, LogFormat, ModuleSpec, OrphanedModuleSettings, Provisioning,
+    RateLimitSettings, RetrySettings, RuntimeSettings, Settings as BaseSettings, Tenant,
+    WatchdogSettings, REQUIRED_SETTINGS, SETTINGS_ALIASES,
+};
+use edgelet_docker::{DockerConfig, DEFAULTS};
+use edgelet_utils::{SettingsFile, YamlFileSource};
+use failure::ResultExt;
+
+use crate::error::{Error, ErrorKind};
+
+const MODULES_DIRECTORY: &str = "modules";
+
+/// How module processes are started.
+#[derive(Clone, Copy, Debug, PartialEq, serde_derive::Deserialize, serde_derive::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Launcher {
+    /// Modules are child processes of the daemon, and stop with it.
+    Child,
+    /// Modules are transient systemd units, which keep running when the
+    /// daemon restarts and log to the journal.
+    Systemd,
+}
+
+impl Default for Launcher {
+    fn default() -> Self {
+        Launcher::Child
+    }
+}
+
+#[derive(Clone, Debug, Default, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct ProcessRuntime {
+    #[serde(default)]
+    launcher: Launcher,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modules_directory: Option<PathBuf>,
+}
+
+impl ProcessRuntime {
+    pub fn launcher(&self) -> Launcher {
+        self.launcher
+    }
+}
+
+/// This struct is the same as the Settings type from the `edgelet_docker`
+/// crate except that modules are run as host processes instead of docker
+/// containers.
+#[derive(Clone, Debug, serde_derive::Deserialize, serde_derive::Serialize)]
+pub struct Settings {
+    #[serde(flatten)]
+    base: BaseSettings<DockerConfig>,
+    #[serde(default)]
+    process_runtime: ProcessRuntime,
+}
+
+impl Settings {
+    pub fn new(filename: &Path) -> Result<Self, Error> {
+        let file = SettingsFile::open(filename).context(ErrorKind::Config)?;
+        file.check(REQUIRED_SETTINGS).context(ErrorKind::Config)?;
+
+        let mut config = Config::default();
+        config
+            .merge(YamlFileSource::String(DEFAULTS))
+            .context(ErrorKind::Config)?;
+
+        file.merge_into(&mut config).context(ErrorKind::Config)?;
+
+        config
+            .merge(Environment::with_prefix("iotedge"))
+            .context(ErrorKind::Config)?;
+
+        let settings: Self = file
+            .deserialize::<Self, BaseSettings<DockerConfig>>(config)
+            .context(ErrorKind::Config)?;
+        file.warn_unknown_keys(&settings, SETTINGS_ALIASES);
+        Ok(settings)
+    }
+
+    pub fn process_runtime(&self) -> &ProcessRuntime {
+        &self.process_runtime
+    }
+
+    /// The directory that holds a directory for each module, with its spec,
+    /// its working directory and its log. Defaults to a directory under the
+    /// daemon's home directory.
+    pub fn modules_directory(&self) -> PathBuf {
+        self.process_runtime
+            .modules_directory
+            .clone()
+            .unwrap_or_else(|| self.homedir().join(MODULES_DIRECTORY))
+    }
+}
+
+impl RuntimeSettings for Settings {
+    type Config = DockerConfig;
+
+    fn provisioning(&self) -> &Provisioning {
+        self.base.provisioning()
+    }
+
+    fn agent(&self) -> &ModuleSpec<DockerConfig> {
+        self.base.agent()
+    }
+
+    fn agent_mut(&mut self) -> &mut ModuleSpec<DockerConfig> {
+        self.base.agent_mut()
+    }
+
+    fn hostname(&self) -> &str {
+        self.base.hostname()
+    }
+
+    fn parent_hostname(&self) -> Option<&str> {
+        self.base.parent_hostname()
+    }
+
+    fn connect(&self) -> &Connect {
+        self.base.connect()
+    }
+
+    fn listen(&self) -> &Listen {
+        self.base.listen()
+    }
+
+    fn homedir(&self) -> &Path {
+        self.base.homedir()
+    }
+
+    fn certificates(&self) -> &Certificates {
+        self.base.certificates()
+    }
+
+    fn watchdog(&self) -> &WatchdogSettings {
+        self.base.watchdog()
+    }
+
+    fn log_level(&self) -> Option<&str> {
+        self.base.log_level()
+    }
+
+    fn log_format(&self) -> LogFormat {
+        self.base.log_format()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        self.base.tenants()
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        self.base.lifecycle_hook()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        self.base.workload_rate_limit()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        self.base.retry()
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        self.base.device_actions()
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        self.base.health_report()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        self.base.orphaned_modules()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        self.base.diagnostics()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        self.base.secrets()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    fn load(yaml: &str) -> Settings {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+        Settings::new(file.path()).unwrap()
+    }
+
+    const SETTINGS: &str = r#"
+provisioning:
+  source: "manual"
+  device_connection_string: "HostName=moo.azure-devices.net;DeviceId=boo;SharedAccessKey=boo"
+homedir: "/var/lib/iotedge"
+"#;
+
+    #[test]
+    fn process_runtime_defaults_to_child_processes() {
+        let settings = load(SETTINGS);
+
+        assert_eq!(Launcher::Child, settings.process_runtime().launcher());
+        assert_eq!(
+            Path::new("/var/lib/iotedge/modules"),
+            settings.modules_directory()
+        );
+    }
+
+    #[test]
+    fn process_runtime_is_configurable() {
+        let settings = load(&format!(
+            r#"{}
+process_runtime:
+  launcher: "systemd"
+  modules_directory: "/opt/modules"
+"#,
+            SETTINGS
+        ));
+
+        assert_eq!(Launcher::Systemd, settings.process_runtime().launcher());
+        assert_eq!(Path::new("/opt/modules"), settings.modules_directory());
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Modules that run as transient systemd units, controlled with the
+//! `systemd-run`, `systemctl` and `journalctl` commands.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use chrono::{DateTime, Duration, Utc};
+
+use edgelet_core::{LogOptions, LogTail, ModuleRuntimeState, ModuleStatus, RestartPolicy};
+
+use crate::command::ModuleCommand;
+use crate::error::{Error, ErrorKind, Result};
+
+const UNIT_PREFIX: &str = "iotedge-module-";
+
+const SHOW_PROPERTIES: &str = "LoadState,ActiveState,SubState,MainPID,ExecMainStatus,\
+                               ExecMainStartTimestampMonotonic,ExecMainExitTimestampMonotonic,\
+                               NRestarts";
+
+pub fn unit_name(name: &str) -> String {
+    format!("{}{}.service", UNIT_PREFIX, name)
+}
+
+pub fn start(
+    name: &str,
+    command: &ModuleCommand,
+    dir: &Path,
+    restart_policy: RestartPolicy,
+) -> Result<()> {
+    let unit = unit_name(name);
+    if properties(&unit)?.get("ActiveState").map(String::as_str) == Some("active") {
+        return Ok(());
+    }
+
+    // A unit that failed stays loaded, and would keep its name from being used again.
+    let _ = run(Command::new("systemctl").arg("reset-failed").arg(&unit));
+
+    let mut systemd_run = Command::new("systemd-run");
+    systemd_run
+        .arg(format!("--unit={}", unit))
+        .arg(format!("--description=Azure IoT Edge module {}", name))
+        .arg(format!("--working-directory={}", dir.display()))
+        .arg(format!("--property=Restart={}", restart(restart_policy)));
+    for (key, value) in &command.env {
+        systemd_run.arg(format!("--setenv={}={}", key, value));
+    }
+    systemd_run
+        .arg("--")
+        .arg(&command.program)
+        .args(&command.args);
+
+    run(&mut systemd_run).map(|_| ())
+}
+
+pub fn stop(name: &str) -> Result<()> {
+    let unit = unit_name(name);
+    if properties(&unit)?.get("LoadState").map(String::as_str) == Some("not-found") {
+        return Ok(());
+    }
+
+    run(Command::new("systemctl").arg("stop").arg(&unit)).map(|_| ())
+}
+
+pub fn state(name: &str) -> Result<ModuleRuntimeState> {
+    let properties = properties(&unit_name(name))?;
+    Ok(runtime_state(&properties, boot_time()))
+}
+
+pub fn logs(name: &str, options: &LogOptions) -> Result<Vec<u8>> {
+    let mut journalctl = Command::new("journalctl");
+    journalctl
+        .arg(format!("--unit={}", unit_name(name)))
+        .arg("--output=cat")
+        .arg("--no-pager");
+    if let LogTail::Num(tail) = options.tail() {
+        journalctl.arg(format!("--lines={}", tail));
+    }
+    if options.since() > 0 {
+        journalctl.arg(format!("--since=@{}", options.since()));
+    }
+
+    run(&mut journalctl).map(String::into_bytes)
+}
+
+fn restart(restart_policy: RestartPolicy) -> &'static str {
+    match restart_policy {
+        RestartPolicy::Never => "no",
+        // systemd can't tell an unhealthy module apart from a healthy one, so
+        // it can only take care of the failures.
+        RestartPolicy::OnFailure | RestartPolicy::OnUnhealthy => "on-failure",
+        RestartPolicy::Always => "always",
+    }
+}
+
+fn properties(unit: &str) -> Result<HashMap<String, String>> {
+    let output = run(Command::new("systemctl")
+        .arg("show")
+        .arg(unit)
+        .arg(format!("--property={}", SHOW_PROPERTIES)))?;
+    Ok(parse_properties(&output))
+}
+
+fn parse_properties(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, '=');
+            Some((parts.next()?.to_string(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+fn runtime_state(
+    properties: &HashMap<String, String>,
+    boot_time: DateTime<Utc>,
+) -> ModuleRuntimeState {
+    let property = |key: &str| properties.get(key).map_or("", String::as_str);
+    let exit_code: i64 = property("ExecMainStatus").parse().unwrap_or_default();
+    let started_at = timestamp(property("ExecMainStartTimestampMonotonic"), boot_time);
+    let finished_at = timestamp(property("ExecMainExitTimestampMonotonic"), boot_time);
+
+    let (status, exit_code) = match (
+        property("LoadState"),
+        property("ActiveState"),
+        property("SubState"),
+    ) {
+        // The unit of a module that hasn't been started yet, or that stopped
+        // successfully and was unloaded.
+        ("not-found", _, _) => (ModuleStatus::Stopped, None),
+        (_, "activating", "auto-restart") | (_, "failed", _) => {
+            (ModuleStatus::Failed, Some(exit_code))
+        }
+        (_, "active", _) | (_, "activating", _) | (_, "reloading", _) => {
+            (ModuleStatus::Running, None)
+        }
+        (_, "inactive", _) | (_, "deactivating", _) if exit_code == 0 => {
+            (ModuleStatus::Stopped, finished_at.map(|_| exit_code))
+        }
+        (_, "inactive", _) | (_, "deactivating", _) => (ModuleStatus::Failed, Some(exit_code)),
+        _ => (ModuleStatus::Unknown, None),
+    };
+
+    let description = match property("SubState") {
+        "" => "created",
+        sub_state => sub_state,
+    };
+
+    ModuleRuntimeState::default()
+        .with_status(status)
+        .with_status_description(Some(description.to_string()))
+        .with_exit_code(exit_code)
+        .with_started_at(started_at)
+        .with_finished_at(finished_at)
+        .with_pid(property("MainPID").parse().ok().filter(|pid| *pid > 0))
+        .with_restart_count(property("NRestarts").parse().ok())
+}
+
+// systemd reports the time since boot in microseconds, and 0 for events that
+// haven't happened.
+fn timestamp(micros: &str, boot_time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    micros
+        .parse::<i64>()
+        .ok()
+        .filter(|micros| *micros > 0)
+        .map(|micros| boot_time + Duration::microseconds(micros))
+}
+
+fn boot_time() -> DateTime<Utc> {
+    let uptime = std::fs::read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|uptime| uptime.split_whitespace().next()?.parse::<f64>().ok())
+        .unwrap_or_default();
+
+    #[allow(clippy::cast_possible_truncation)]
+    let uptime = Duration::milliseconds((uptime * 1000.0) as i64);
+    Utc::now() - uptime
+}
+
+fn run(command: &mut Command) -> Result<String> {
+    let program = format!("{:?}", command);
+    let output = command
+        .output()
+        .map_err(|err| Error::from(ErrorKind::Command(program.clone(), err.to_string())))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Error::from(ErrorKind::Command(
+            program,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    fn state(output: &str) -> ModuleRuntimeState {
+        runtime_state(
+            &parse_properties(output),
+            Utc.ymd(2019, 10, 1).and_hms(0, 0, 0),
+        )
+    }
+
+    #[test]
+    fn running_unit() {
+        let state = state(
+            "LoadState=loaded\nActiveState=active\nSubState=running\nMainPID=1234\n\
+             ExecMainStatus=0\nExecMainStartTimestampMonotonic=5000000\n\
+             ExecMainExitTimestampMonotonic=0\nNRestarts=2\n",
+        );
+
+        assert_eq!(&ModuleStatus::Running, state.status());
+        assert_eq!(Some("running"), state.status_description());
+        assert_eq!(Some(1234), state.pid());
+        assert_eq!(None, state.exit_code());
+        assert_eq!(
+            Some(&Utc.ymd(2019, 10, 1).and_hms(0, 0, 5)),
+            state.started_at()
+        );
+        assert_eq!(None, state.finished_at());
+        assert_eq!(Some(2), state.restart_count());
+    }
+
+    #[test]
+    fn failed_unit() {
+        let state = state(
+            "LoadState=loaded\nActiveState=failed\nSubState=failed\nMainPID=0\n\
+             ExecMainStatus=3\nExecMainStartTimestampMonotonic=5000000\n\
+             ExecMainExitTimestampMonotonic=6000000\nNRestarts=0\n",
+        );
+
+        assert_eq!(&ModuleStatus::Failed, state.status());
+        assert_eq!(Some(3), state.exit_code());
+        assert_eq!(None, state.pid());
+        assert_eq!(
+            Some(&Utc.ymd(2019, 10, 1).and_hms(0, 0, 6)),
+            state.finished_at()
+        );
+    }
+
+    #[test]
+    fn missing_unit_is_stopped() {
+        let state = state("LoadState=not-found\nActiveState=inactive\nSubState=dead\nMainPID=0\n");
+
+        assert_eq!(&ModuleStatus::Stopped, state.status());
+        assert_eq!(None, state.exit_code());
+    }
+
+    #[test]
+    fn unit_names_are_prefixed() {
+        assert_eq!("iotedge-module-mod1.service", unit_name("mod1"));
+    }
+}
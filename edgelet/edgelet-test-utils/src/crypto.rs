@@ -1,6 +1,8 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use edgelet_core::{Error as CoreError, ErrorKind as CoreErrorKind, GetTrustBundle};
+use edgelet_core::{
+    Attest, AttestationQuote, Error as CoreError, ErrorKind as CoreErrorKind, GetTrustBundle,
+};
 
 use crate::cert::TestCert;
 
@@ -33,3 +35,19 @@ impl GetTrustBundle for TestHsm {
         }
     }
 }
+
+// The quote is the nonce itself, so that tests can tell it was passed on.
+impl Attest for TestHsm {
+    fn quote(&self, nonce: &[u8]) -> Result<AttestationQuote, CoreError> {
+        if self.fail_call {
+            Err(CoreError::from(CoreErrorKind::Attestation))
+        } else {
+            Ok(AttestationQuote::new(
+                nonce.to_vec(),
+                b"signature".to_vec(),
+                b"attestation key".to_vec(),
+                b"endorsement key".to_vec(),
+            ))
+        }
+    }
+}
@@ -107,6 +107,7 @@ impl IdentityManager for TestIdentityManager {
     type ListFuture = FutureResult<Vec<Self::Identity>, Self::Error>;
     type GetFuture = FutureResult<Option<Self::Identity>, Self::Error>;
     type DeleteFuture = FutureResult<(), Self::Error>;
+    type RegenerateFuture = FutureResult<Self::Identity, Self::Error>;
 
     fn create(&mut self, id: IdentitySpec) -> Self::CreateFuture {
         if self.fail_create {
@@ -188,4 +189,18 @@ impl IdentityManager for TestIdentityManager {
             .ok_or(Error::ModuleNotFound)
             .into_future()
     }
+
+    fn regenerate(&mut self, id: IdentitySpec) -> Self::RegenerateFuture {
+        self.gen_id_sentinel += 1;
+        let gen_id_sentinel = self.gen_id_sentinel;
+        self.identities
+            .iter_mut()
+            .find(|m| m.module_id() == id.module_id())
+            .map(|module| {
+                module.generation_id = format!("{}", gen_id_sentinel);
+                module.clone()
+            })
+            .ok_or(Error::ModuleNotFound)
+            .into_future()
+    }
 }
@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
 use std::path::Path;
 use std::time::Duration;
@@ -94,6 +95,10 @@ impl RuntimeSettings for TestSettings {
         unimplemented!()
     }
 
+    fn parent_hostname(&self) -> Option<&str> {
+        unimplemented!()
+    }
+
     fn connect(&self) -> &Connect {
         unimplemented!()
     }
@@ -113,6 +118,50 @@ impl RuntimeSettings for TestSettings {
     fn watchdog(&self) -> &WatchdogSettings {
         unimplemented!()
     }
+
+    fn log_level(&self) -> Option<&str> {
+        unimplemented!()
+    }
+
+    fn log_format(&self) -> LogFormat {
+        unimplemented!()
+    }
+
+    fn tenants(&self) -> &[Tenant] {
+        unimplemented!()
+    }
+
+    fn lifecycle_hook(&self) -> Option<&LifecycleHookSettings> {
+        unimplemented!()
+    }
+
+    fn workload_rate_limit(&self) -> Option<&RateLimitSettings> {
+        unimplemented!()
+    }
+
+    fn retry(&self) -> &RetrySettings {
+        unimplemented!()
+    }
+
+    fn device_actions(&self) -> &DeviceActionSettings {
+        unimplemented!()
+    }
+
+    fn health_report(&self) -> Option<&HealthReportSettings> {
+        unimplemented!()
+    }
+
+    fn orphaned_modules(&self) -> Option<&OrphanedModuleSettings> {
+        unimplemented!()
+    }
+
+    fn diagnostics(&self) -> Option<&DiagnosticsSettings> {
+        unimplemented!()
+    }
+
+    fn secrets(&self) -> &BTreeMap<String, String> {
+        unimplemented!()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -191,6 +240,7 @@ where
     module: Option<Result<TestModule<E, S::Config>, E>>,
     registry: TestRegistry<E, S::Config>,
     settings: S,
+    auth_id: AuthId,
 }
 
 impl<E, S> TestRuntime<E, S>
@@ -207,6 +257,11 @@ where
         self.registry = registry;
         self
     }
+
+    pub fn with_auth_id(mut self, auth_id: AuthId) -> Self {
+        self.auth_id = auth_id;
+        self
+    }
 }
 
 impl<E, S> Authenticator for TestRuntime<E, S>
@@ -219,7 +274,7 @@ where
     type AuthenticateFuture = Box<dyn Future<Item = AuthId, Error = Self::Error> + Send>;
 
     fn authenticate(&self, _req: &Self::Request) -> Self::AuthenticateFuture {
-        Box::new(future::ok(AuthId::Any))
+        Box::new(future::ok(self.auth_id.clone()))
     }
 }
 
@@ -309,6 +364,7 @@ where
             module: None,
             registry: TestRegistry::new(None),
             settings,
+            auth_id: AuthId::Any,
         })
     }
 }
@@ -333,12 +389,23 @@ where
         Box<dyn Stream<Item = (Self::Module, ModuleRuntimeState), Error = Self::Error> + Send>;
     type LogsFuture = FutureResult<Self::Logs, Self::Error>;
     type RemoveFuture = FutureResult<(), Self::Error>;
+    type RenameFuture = FutureResult<(), Self::Error>;
     type RestartFuture = FutureResult<(), Self::Error>;
     type StartFuture = FutureResult<(), Self::Error>;
     type StopFuture = FutureResult<(), Self::Error>;
     type SystemInfoFuture = FutureResult<SystemInfo, Self::Error>;
     type SystemResourcesFuture = FutureResult<SystemResources, Self::Error>;
     type RemoveAllFuture = FutureResult<(), Self::Error>;
+    type ExecFuture = FutureResult<ModuleExecResult, Self::Error>;
+    type CopyToFuture = FutureResult<(), Self::Error>;
+    type CopyFromFuture = FutureResult<Vec<u8>, Self::Error>;
+    type ExportImageFuture = FutureResult<Vec<u8>, Self::Error>;
+    type ImportImagesFuture = FutureResult<(), Self::Error>;
+    type ListImagesFuture = FutureResult<Vec<ImageInfo>, Self::Error>;
+    type PruneImagesFuture = FutureResult<PrunedImages, Self::Error>;
+    type TopFuture = FutureResult<ModuleProcesses, Self::Error>;
+    type ValidateFuture = FutureResult<(), Self::Error>;
+    type EventStream = stream::Empty<ModuleEvent, Self::Error>;
 
     fn create(&self, _module: ModuleSpec<Self::Config>) -> Self::CreateFuture {
         match self.module.as_ref().unwrap() {
@@ -382,6 +449,13 @@ where
         }
     }
 
+    fn rename(&self, _id: &str, _new_id: &str) -> Self::RenameFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
     fn system_info(&self) -> Self::SystemInfoFuture {
         match self.module.as_ref().unwrap() {
             Ok(_) => future::ok(SystemInfo::new(
@@ -444,4 +518,87 @@ where
     fn remove_all(&self) -> Self::RemoveAllFuture {
         future::ok(())
     }
+
+    // The command is echoed back, like running it with `echo` would.
+    fn exec(&self, _id: &str, command: &[String]) -> Self::ExecFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(ModuleExecResult::new(
+                0,
+                format!("{}\n", command.join(" ")).into_bytes(),
+                vec![],
+            )),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn copy_to(&self, _id: &str, _path: &str, _archive: Vec<u8>) -> Self::CopyToFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn copy_from(&self, _id: &str, _path: &str) -> Self::CopyFromFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(vec![]),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn export_image(&self, _id: &str) -> Self::ExportImageFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(b"archive".to_vec()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn import_images(&self, _archive: Vec<u8>) -> Self::ImportImagesFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn list_images(&self) -> Self::ListImagesFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(vec![
+                ImageInfo::new(
+                    "sha256:in-use".to_string(),
+                    vec!["microsoft/test-image".to_string()],
+                    1024,
+                )
+                .with_in_use(true),
+                ImageInfo::new("sha256:unused".to_string(), vec![], 2048),
+            ]),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn prune_images(&self) -> Self::PruneImagesFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(PrunedImages::new(vec!["sha256:unused".to_string()], 2048)),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn top(&self, _id: &str) -> Self::TopFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(ModuleProcesses::new(
+                vec!["PID".to_string(), "CMD".to_string()],
+                vec![vec!["1".to_string(), "test".to_string()]],
+            )),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn validate(&self, _module: ModuleSpec<Self::Config>) -> Self::ValidateFuture {
+        match self.module.as_ref().unwrap() {
+            Ok(_) => future::ok(()),
+            Err(ref e) => future::err(e.clone()),
+        }
+    }
+
+    fn events(&self) -> Self::EventStream {
+        stream::empty()
+    }
 }
@@ -26,6 +26,21 @@ pub enum ErrorKind {
     #[fail(display = "Argument is empty or only has whitespace - [{}]", _0)]
     ArgumentEmpty(String),
 
+    #[fail(display = "Invalid environment variable name {:?}", _0)]
+    InvalidEnvVarName(String),
+
+    #[fail(display = "Invalid image reference {:?}", _0)]
+    InvalidImage(String),
+
+    #[fail(display = "Invalid module identity {:?}", _0)]
+    InvalidModuleId(String),
+
+    #[fail(
+        display = "Invalid module name {:?}, it must be 2 to 128 letters, digits, '_', '.' or '-' and start with a letter or digit",
+        _0
+    )]
+    InvalidModuleName(String),
+
     #[fail(display = "Could not clone value via serde")]
     SerdeClone,
 }
@@ -15,7 +15,10 @@
 mod error;
 mod logging;
 pub mod macros;
+mod retry;
 mod ser_de;
+mod settings_file;
+mod validate;
 mod yaml_file_source;
 
 use std::collections::HashMap;
@@ -23,7 +26,12 @@ use std::collections::HashMap;
 pub use crate::error::{Error, ErrorKind};
 pub use crate::logging::log_failure;
 pub use crate::macros::ensure_not_empty_with_context;
+pub use crate::retry::{Backoff, RetryPolicy};
 pub use crate::ser_de::{serde_clone, serialize_ordered, string_or_struct};
+pub use crate::settings_file::{RequiredSetting, SettingsFile};
+pub use crate::validate::{
+    validate_env_var_name, validate_image_reference, validate_module_id, validate_module_name,
+};
 pub use crate::yaml_file_source::YamlFileSource;
 
 pub fn parse_query(query: &str) -> HashMap<&str, &str> {
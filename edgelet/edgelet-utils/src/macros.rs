@@ -419,6 +419,66 @@ macro_rules! fensure_not_empty {
     };
 }
 
+/// Internal macro used for implementing other validation macros.
+///
+/// Not to be directly invoked. Use one of the other `ensure*` macros.
+#[macro_export]
+macro_rules! ensure_valid_impl {
+    ($val:expr, $validate:path, $bail:tt) => {
+        match $val {
+            val => match $validate(&val) {
+                Ok(()) => val,
+                Err(err) => $bail!(err.kind().clone()),
+            },
+        }
+    };
+}
+
+/// Check a value with one of the `validate_*` functions and bail with the
+/// error it returns if the value is invalid.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate edgelet_utils;
+/// # use edgelet_utils::{validate_module_name, Error};
+/// #[derive(Debug)]
+/// struct Foo {
+///     module: String,
+/// }
+///
+/// impl Foo {
+///     fn new(module: String) -> Result<Foo, Error> {
+///         Ok(Foo {
+///             module: ensure_valid!(module, validate_module_name),
+///         })
+///     }
+/// }
+///
+/// fn main() {
+///     // prints InvalidModuleName error
+///     println!("{:?}", Foo::new("my module".to_string()));
+/// }
+/// ```
+#[macro_export]
+macro_rules! ensure_valid {
+    ($val:expr, $validate:path) => {
+        ensure_valid_impl!($val, $validate, bail)
+    };
+}
+
+/// Check a value with one of the `validate_*` functions and bail with the
+/// error it returns if the value is invalid.
+///
+/// Use this macro when your function returns a `Box<Future<T, E>>` instead of
+/// a `Result<T, E>`. For usage examples see documentation for `ensure_valid!`.
+#[macro_export]
+macro_rules! fensure_valid {
+    ($val:expr, $validate:path) => {
+        ensure_valid_impl!($val, $validate, fbail)
+    };
+}
+
 pub fn ensure_not_empty_with_context<D, F>(value: &str, context: F) -> Result<(), Context<D>>
 where
     D: fmt::Display + Send + Sync,
@@ -440,6 +500,7 @@ mod tests {
     use futures::prelude::*;
 
     use crate::error::{Error, ErrorKind};
+    use crate::validate::{validate_env_var_name, validate_module_name};
 
     fn check_value<T, F>(expected: &T, f: F)
     where
@@ -616,6 +677,42 @@ mod tests {
         });
     }
 
+    #[test]
+    fn validate_ensure_valid() {
+        let validator: Box<dyn Fn(&Error) -> bool> = Box::new(|err| {
+            mem::discriminant(err.kind())
+                == mem::discriminant(&ErrorKind::InvalidModuleName(String::new()))
+        });
+
+        check_value(&"edgeHub", || {
+            Ok(ensure_valid!("edgeHub", validate_module_name))
+        });
+        check_error(validator.as_ref(), || {
+            Ok(ensure_valid!("edge hub", validate_module_name))
+        });
+    }
+
+    #[test]
+    fn validate_fensure_valid() {
+        let validator: Box<dyn Fn(&Error) -> bool> = Box::new(|err| {
+            mem::discriminant(err.kind())
+                == mem::discriminant(&ErrorKind::InvalidEnvVarName(String::new()))
+        });
+
+        check_fvalue(&"PATH".to_string(), || {
+            Box::new(future::ok(fensure_valid!(
+                "PATH".to_string(),
+                validate_env_var_name
+            )))
+        });
+        check_ferror(validator.as_ref(), || {
+            Box::new(future::ok(fensure_valid!(
+                "K=V".to_string(),
+                validate_env_var_name
+            )))
+        });
+    }
+
     #[test]
     fn validate_fensure_not_empty() {
         let validator: Box<dyn Fn(&Error) -> bool> = Box::new(|err| {
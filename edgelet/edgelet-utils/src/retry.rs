@@ -0,0 +1,200 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::cmp;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+
+/// Whether the delay between attempts stays the same or doubles with every
+/// failed attempt.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    Fixed,
+    Exponential,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::Exponential
+    }
+}
+
+/// How an operation that fails is retried: how long to wait before each
+/// attempt, and for how long to keep trying at all.
+///
+/// With jitter, a random part of up to half of each delay is taken off, so
+/// that devices that failed together don't all retry together.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct RetryPolicy {
+    #[serde(default)]
+    backoff: Backoff,
+    initial_delay_millis: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_delay_secs: Option<u64>,
+    #[serde(default)]
+    jitter: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_elapsed_secs: Option<u64>,
+}
+
+impl RetryPolicy {
+    pub fn fixed(delay: Duration) -> Self {
+        RetryPolicy {
+            backoff: Backoff::Fixed,
+            initial_delay_millis: millis(delay),
+            max_delay_secs: None,
+            jitter: false,
+            max_elapsed_secs: None,
+        }
+    }
+
+    pub fn exponential(initial_delay: Duration, max_delay: Duration) -> Self {
+        RetryPolicy {
+            backoff: Backoff::Exponential,
+            initial_delay_millis: millis(initial_delay),
+            max_delay_secs: Some(max_delay.as_secs()),
+            jitter: false,
+            max_elapsed_secs: None,
+        }
+    }
+
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_max_elapsed(mut self, max_elapsed: Option<Duration>) -> Self {
+        self.max_elapsed_secs = max_elapsed.map(|max_elapsed| max_elapsed.as_secs());
+        self
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        self.backoff
+    }
+
+    pub fn initial_delay(&self) -> Duration {
+        Duration::from_millis(self.initial_delay_millis)
+    }
+
+    pub fn max_delay(&self) -> Option<Duration> {
+        self.max_delay_secs.map(Duration::from_secs)
+    }
+
+    pub fn jitter(&self) -> bool {
+        self.jitter
+    }
+
+    /// How long after the first attempt no further attempt is started.
+    /// Without it, the operation is retried for as long as its caller lets it.
+    pub fn max_elapsed(&self) -> Option<Duration> {
+        self.max_elapsed_secs.map(Duration::from_secs)
+    }
+
+    /// The delay before retry number `retry`, counted from 0, before jitter
+    /// is applied.
+    pub fn base_delay(&self, retry: u32) -> Duration {
+        let millis = match self.backoff {
+            Backoff::Fixed => self.initial_delay_millis,
+            Backoff::Exponential => self
+                .initial_delay_millis
+                .saturating_mul(2_u64.saturating_pow(retry)),
+        };
+        let millis = self.max_delay_secs.map_or(millis, |max_delay_secs| {
+            cmp::min(millis, max_delay_secs.saturating_mul(1000))
+        });
+        Duration::from_millis(millis)
+    }
+
+    /// The delay before retry number `retry`, counted from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self.base_delay(retry);
+        if self.jitter {
+            let millis = millis(delay);
+            let jitter = rand::thread_rng().gen_range(0, millis / 2 + 1);
+            Duration::from_millis(millis - jitter)
+        } else {
+            delay
+        }
+    }
+
+    /// Whether an attempt that starts after `delay` from now is still within
+    /// the maximum elapsed time of an operation first attempted at `started`.
+    pub fn allows(&self, started: Instant, delay: Duration) -> bool {
+        self.max_elapsed().map_or(true, |max_elapsed| {
+            Instant::now() + delay < started + max_elapsed
+        })
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(duration.subsec_millis()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_delay_stays_the_same() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(5));
+        assert_eq!(Duration::from_secs(5), policy.delay(1));
+        assert_eq!(Duration::from_secs(5), policy.delay(10));
+    }
+
+    #[test]
+    fn exponential_delay_doubles_up_to_max() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), Duration::from_secs(60));
+        assert_eq!(Duration::from_secs(1), policy.delay(0));
+        assert_eq!(Duration::from_secs(2), policy.delay(1));
+        assert_eq!(Duration::from_secs(32), policy.delay(5));
+        assert_eq!(Duration::from_secs(60), policy.delay(6));
+        assert_eq!(Duration::from_secs(60), policy.delay(200));
+    }
+
+    #[test]
+    fn jitter_takes_off_up_to_half() {
+        let policy = RetryPolicy::exponential(Duration::from_secs(1), Duration::from_secs(60))
+            .with_jitter(true);
+        for retry in 0..10 {
+            let base = policy.base_delay(retry);
+            let delay = policy.delay(retry);
+            assert!(delay <= base);
+            assert!(delay >= base / 2);
+        }
+    }
+
+    #[test]
+    fn max_elapsed_limits_attempts() {
+        let policy = RetryPolicy::fixed(Duration::from_secs(1));
+        assert!(policy.allows(Instant::now(), Duration::from_secs(3600)));
+
+        let policy = policy.with_max_elapsed(Some(Duration::from_secs(10)));
+        assert!(policy.allows(Instant::now(), Duration::from_secs(1)));
+        assert!(!policy.allows(Instant::now(), Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn policy_is_read_from_settings() {
+        let policy: RetryPolicy = serde_json::from_str(
+            r#"{ "initial_delay_millis": 500, "max_delay_secs": 30, "jitter": true }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            RetryPolicy::exponential(Duration::from_millis(500), Duration::from_secs(30))
+                .with_jitter(true),
+            policy
+        );
+
+        let policy: RetryPolicy = serde_json::from_str(
+            r#"{ "backoff": "fixed", "initial_delay_millis": 2000, "max_elapsed_secs": 60 }"#,
+        )
+        .unwrap();
+        assert_eq!(Backoff::Fixed, policy.backoff());
+        assert_eq!(Some(Duration::from_secs(60)), policy.max_elapsed());
+    }
+}
@@ -0,0 +1,667 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use config::{Config, ConfigError};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, TScalarStyle};
+
+use crate::YamlFileSource;
+
+/// The extensions of the files in the drop-in directory that are read.
+const DROP_IN_EXTENSIONS: &[&str] = &["yaml", "yml"];
+
+/// A setting that has to be set in the config file when another setting has a given value,
+/// e.g. the scope ID when provisioning through DPS.
+///
+/// Keys are dotted paths into the config file, like `provisioning.scope_id`.
+#[derive(Clone, Copy, Debug)]
+pub struct RequiredSetting {
+    pub when: &'static str,
+    pub is: &'static str,
+    pub required: &'static [&'static str],
+}
+
+/// The keys of a YAML config file and the lines they are on.
+///
+/// The settings themselves are read with [`YamlFileSource`] and deserialized by the config crate,
+/// which neither knows where a value came from nor complains about keys it doesn't know. This is used
+/// alongside it so that an invalid config points at the offending line, and so that a misspelled key
+/// is reported instead of silently falling back to its default.
+///
+/// The config file can be split into drop-in fragments, e.g. so that a package can own the certificate
+/// settings. For `/etc/iotedge/config.yaml` they are the `.yaml` files in `/etc/iotedge/config.d`,
+/// which are merged over the config file in the order of their names.
+#[derive(Clone, Debug, Default)]
+pub struct SettingsFile {
+    files: Vec<PathBuf>,
+    origins: Vec<String>,
+    keys: Vec<Key>,
+    scalars: HashMap<String, Option<String>>,
+}
+
+#[derive(Clone, Debug)]
+struct Key {
+    path: String,
+    origin: usize,
+    line: usize,
+}
+
+impl SettingsFile {
+    /// Reads the config file at `path` and its drop-in fragments.
+    pub fn open(path: &Path) -> Result<Self, ConfigError> {
+        let mut settings = SettingsFile::read(path)?;
+        for drop_in in drop_ins(path).map_err(|err| ConfigError::Foreign(Box::new(err)))? {
+            settings.merge(SettingsFile::read(&drop_in)?);
+        }
+        Ok(settings)
+    }
+
+    pub fn parse(origin: &str, contents: &str) -> Result<Self, ConfigError> {
+        let mut receiver = Receiver::default();
+        Parser::new(contents.chars())
+            .load(&mut receiver, false)
+            .map_err(|err| ConfigError::Foreign(Box::new(err)))?;
+
+        Ok(SettingsFile {
+            files: vec![],
+            origins: vec![origin.to_string()],
+            keys: receiver.keys,
+            scalars: receiver.scalars,
+        })
+    }
+
+    fn read(path: &Path) -> Result<Self, ConfigError> {
+        let mut file = File::open(path).map_err(|err| ConfigError::Foreign(Box::new(err)))?;
+        let mut contents = String::new();
+        let _ = file
+            .read_to_string(&mut contents)
+            .map_err(|err| ConfigError::Foreign(Box::new(err)))?;
+
+        let mut settings = SettingsFile::parse(&path.to_string_lossy(), &contents)?;
+        settings.files.push(path.to_path_buf());
+        Ok(settings)
+    }
+
+    /// Adds the keys of `other` on top of the keys of this file.
+    fn merge(&mut self, other: SettingsFile) {
+        let offset = self.origins.len();
+        self.files.extend(other.files);
+        self.origins.extend(other.origins);
+        self.keys.extend(other.keys.into_iter().map(|key| Key {
+            origin: key.origin + offset,
+            ..key
+        }));
+        self.scalars.extend(other.scalars);
+    }
+
+    /// Merges all the files that were read into `config`, the config file first.
+    pub fn merge_into(&self, config: &mut Config) -> Result<(), ConfigError> {
+        for file in &self.files {
+            config.merge(YamlFileSource::File(file.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// The line the given key is on, if the file sets it. If more than one file sets it,
+    /// this is the line in the file that wins.
+    pub fn line(&self, path: &str) -> Option<usize> {
+        self.find(path).map(|key| key.line)
+    }
+
+    fn find(&self, path: &str) -> Option<&Key> {
+        self.keys.iter().rev().find(|key| key.path == path)
+    }
+
+    /// Fails on the first required setting that the file doesn't set.
+    pub fn check(&self, rules: &[RequiredSetting]) -> Result<(), ConfigError> {
+        for rule in rules {
+            let matches = match self.scalars.get(rule.when) {
+                Some(Some(value)) => value == rule.is,
+                _ => false,
+            };
+            if !matches {
+                continue;
+            }
+
+            for required in rule.required {
+                if !self.is_set(required) {
+                    let (origin, line) = self.location(rule.when);
+                    return Err(ConfigError::Foreign(Box::new(
+                        SettingsFileError::Required {
+                            origin,
+                            key: (*required).to_string(),
+                            rule: *rule,
+                            line,
+                        },
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deserializes the settings from `config`, pointing at the line of the offending key if that fails.
+    ///
+    /// `B` is the part of the settings that `S` flattens into itself. serde doesn't keep the key of an
+    /// invalid value in the error when deserializing a flattened field, so `B` is deserialized on its own
+    /// to find it.
+    pub fn deserialize<S, B>(&self, config: Config) -> Result<S, ConfigError>
+    where
+        S: DeserializeOwned,
+        B: DeserializeOwned,
+    {
+        config.clone().try_into().map_err(|err| match err {
+            ConfigError::Type { key: Some(_), .. } => self.locate(err),
+            err => match config.try_into::<B>() {
+                Err(base_err @ ConfigError::Type { key: Some(_), .. }) => self.locate(base_err),
+                _ => err,
+            },
+        })
+    }
+
+    /// Adds the line of the offending key to an error from deserializing the settings,
+    /// if the error names a key that the file sets.
+    fn locate(&self, err: ConfigError) -> ConfigError {
+        let key = match &err {
+            ConfigError::Type { key: Some(key), .. } => self.find(key),
+            _ => None,
+        };
+
+        match key {
+            Some(key) => ConfigError::Foreign(Box::new(SettingsFileError::Invalid {
+                origin: self.origins[key.origin].clone(),
+                line: key.line,
+                err,
+            })),
+            None => err,
+        }
+    }
+
+    /// The keys the file sets that didn't end up in `settings`, i.e. the keys that were ignored
+    /// when deserializing the settings, most likely because they are misspelled.
+    ///
+    /// `aliases` are the keys that are read under one name but serialized under another,
+    /// so they are never reported.
+    ///
+    /// Every unknown key comes with the file and the line it is on.
+    pub fn unknown_keys<S>(&self, settings: &S, aliases: &[&str]) -> Vec<(String, String, usize)>
+    where
+        S: Serialize,
+    {
+        let settings = match serde_json::to_value(settings) {
+            Ok(settings) => settings,
+            Err(_) => return vec![],
+        };
+
+        let mut unknown: Vec<(String, String, usize)> = vec![];
+        for key in &self.keys {
+            if self.scalars.get(&key.path) == Some(&None)
+                || aliases.iter().any(|alias| is_below(&key.path, alias))
+                || unknown.iter().any(|(parent, origin, _)| {
+                    origin == &self.origins[key.origin] && is_below(&key.path, parent)
+                })
+            {
+                continue;
+            }
+
+            if !is_known(&settings, &key.path) {
+                unknown.push((key.path.clone(), self.origins[key.origin].clone(), key.line));
+            }
+        }
+
+        unknown
+    }
+
+    /// Logs a warning for every key that [`unknown_keys`](SettingsFile::unknown_keys) returns.
+    pub fn warn_unknown_keys<S>(&self, settings: &S, aliases: &[&str])
+    where
+        S: Serialize,
+    {
+        for (key, origin, line) in self.unknown_keys(settings, aliases) {
+            warn!(
+                "Ignoring unknown setting {} at line {} of {}",
+                key, line, origin
+            );
+        }
+    }
+
+    fn is_set(&self, path: &str) -> bool {
+        match self.scalars.get(path) {
+            Some(value) => value.is_some(),
+            None => self.find(path).is_some(),
+        }
+    }
+
+    fn location(&self, path: &str) -> (String, usize) {
+        match self.find(path) {
+            Some(key) => (self.origins[key.origin].clone(), key.line),
+            None => (self.origins.first().cloned().unwrap_or_default(), 0),
+        }
+    }
+}
+
+/// The drop-in fragments of the config file at `path`, in the order they have to be merged.
+/// They are the YAML files in the directory named like the config file, but with a `.d` extension.
+fn drop_ins(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = path.with_extension("d");
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+
+    let mut drop_ins = vec![];
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let path = entry.path();
+        let is_yaml = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) => DROP_IN_EXTENSIONS.contains(&extension),
+            None => false,
+        };
+        if is_yaml && !name.to_string_lossy().starts_with('.') && path.is_file() {
+            drop_ins.push(path);
+        }
+    }
+
+    drop_ins.sort();
+    Ok(drop_ins)
+}
+
+/// Whether `path` is `parent` or one of the keys below it.
+fn is_below(path: &str, parent: &str) -> bool {
+    if !path.starts_with(parent) {
+        return false;
+    }
+
+    let rest = &path[parent.len()..];
+    rest.is_empty() || rest.starts_with('.') || rest.starts_with('[')
+}
+
+/// Whether the key at `path` is in the serialized settings. Only mappings are looked into,
+/// anything else below a known key (or a key whose value doesn't serialize) counts as known.
+fn is_known(settings: &JsonValue, path: &str) -> bool {
+    let mut value = settings;
+    for segment in segments(path) {
+        value = match (value, segment) {
+            (JsonValue::Object(map), Segment::Key(key)) => match map.get(key) {
+                Some(value) => value,
+                None => return false,
+            },
+            (JsonValue::Array(values), Segment::Index(index)) => match values.get(index) {
+                Some(value) => value,
+                None => return true,
+            },
+            _ => return true,
+        };
+    }
+
+    true
+}
+
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+fn segments(path: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    for part in path.split('.') {
+        let mut pieces = part.split('[');
+        if let Some(key) = pieces.next() {
+            segments.push(Segment::Key(key));
+        }
+        for index in pieces {
+            if let Ok(index) = index.trim_end_matches(']').parse() {
+                segments.push(Segment::Index(index));
+            }
+        }
+    }
+    segments
+}
+
+/// Records the path and line of every mapping key, and the value of every scalar, of a YAML document.
+/// Keys are joined with `.` and sequence items are written as `[index]`, the same way the config crate
+/// names them in its errors.
+#[derive(Default)]
+struct Receiver {
+    stack: Vec<Frame>,
+    keys: Vec<Key>,
+    scalars: HashMap<String, Option<String>>,
+}
+
+enum Frame {
+    Mapping { path: String, key: Option<String> },
+    Sequence { path: String, index: usize },
+}
+
+impl Receiver {
+    /// The path of the value that the current event is about, if it is a value and not a key.
+    fn value_path(&mut self) -> Option<String> {
+        match self.stack.last_mut() {
+            Some(Frame::Mapping { key, .. }) => key.take(),
+            Some(Frame::Sequence { path, index }) => {
+                let item = format!("{}[{}]", path, index);
+                *index += 1;
+                Some(item)
+            }
+            None => Some(String::new()),
+        }
+    }
+
+    fn is_key(&self) -> bool {
+        match self.stack.last() {
+            Some(Frame::Mapping { key, .. }) => key.is_none(),
+            _ => false,
+        }
+    }
+}
+
+impl MarkedEventReceiver for Receiver {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(value, style, _, _) => {
+                if self.is_key() {
+                    let path = match self.stack.last() {
+                        Some(Frame::Mapping { path, .. }) if !path.is_empty() => {
+                            format!("{}.{}", path, value)
+                        }
+                        _ => value,
+                    };
+                    self.keys.push(Key {
+                        path: path.clone(),
+                        origin: 0,
+                        line: mark.line(),
+                    });
+                    if let Some(Frame::Mapping { key, .. }) = self.stack.last_mut() {
+                        *key = Some(path);
+                    }
+                } else if let Some(path) = self.value_path() {
+                    let is_null = style == TScalarStyle::Plain
+                        && (value.is_empty() || value == "~" || value.eq_ignore_ascii_case("null"));
+                    self.scalars
+                        .insert(path, if is_null { None } else { Some(value) });
+                }
+            }
+
+            Event::MappingStart(_) => {
+                let path = self.value_path().unwrap_or_default();
+                self.stack.push(Frame::Mapping { path, key: None });
+            }
+
+            Event::SequenceStart(_) => {
+                let path = self.value_path().unwrap_or_default();
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            }
+
+            Event::MappingEnd | Event::SequenceEnd => {
+                let _ = self.stack.pop();
+            }
+
+            Event::Alias(_) => {
+                let _ = self.value_path();
+            }
+
+            _ => (),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum SettingsFileError {
+    Invalid {
+        origin: String,
+        err: ConfigError,
+        line: usize,
+    },
+    Required {
+        origin: String,
+        key: String,
+        rule: RequiredSetting,
+        line: usize,
+    },
+}
+
+impl std::fmt::Display for SettingsFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingsFileError::Invalid { origin, err, line } => {
+                write!(f, "{} (line {} of {})", err, line, origin)
+            }
+            SettingsFileError::Required {
+                origin,
+                key,
+                rule,
+                line,
+            } => write!(
+                f,
+                "{} must be set when {} is {} (line {} of {})",
+                key, rule.when, rule.is, line, origin
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SettingsFileError {}
+
+#[cfg(test)]
+mod tests {
+    use config::Environment;
+    use serde_derive::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::YamlFileSource;
+
+    const SETTINGS: &str = r#"
+provisioning:
+  source: "dps"
+  global_endpoint: "https://global.azure-devices-provisioning.net"
+  attestation:
+    method: "symmetric_key"
+
+hostname: "localhost"
+tenants:
+  - name: "a"
+    limits: 3
+watchdog:
+  max_retries:
+"#;
+
+    const RULES: &[RequiredSetting] = &[
+        RequiredSetting {
+            when: "provisioning.source",
+            is: "dps",
+            required: &["provisioning.global_endpoint", "provisioning.attestation"],
+        },
+        RequiredSetting {
+            when: "provisioning.source",
+            is: "manual",
+            required: &["provisioning.device_connection_string"],
+        },
+    ];
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct RuntimeSettings {
+        #[serde(flatten)]
+        base: Settings,
+        runtime: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Settings {
+        provisioning: Provisioning,
+        hostname: String,
+        #[serde(default)]
+        tenants: Vec<Tenant>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Provisioning {
+        source: String,
+        global_endpoint: String,
+        scope_id: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Tenant {
+        name: String,
+        limit: Option<u32>,
+    }
+
+    #[test]
+    fn keys_have_lines() {
+        let file = SettingsFile::parse("config.yaml", SETTINGS).unwrap();
+
+        assert_eq!(Some(2), file.line("provisioning"));
+        assert_eq!(Some(3), file.line("provisioning.source"));
+        assert_eq!(Some(6), file.line("provisioning.attestation.method"));
+        assert_eq!(Some(8), file.line("hostname"));
+        assert_eq!(Some(11), file.line("tenants[0].limits"));
+        assert_eq!(None, file.line("provisioning.scope_id"));
+    }
+
+    #[test]
+    fn check_required_settings() {
+        let file = SettingsFile::parse("config.yaml", SETTINGS).unwrap();
+        file.check(RULES).unwrap();
+
+        let rules = &[RequiredSetting {
+            when: "provisioning.source",
+            is: "dps",
+            required: &["provisioning.scope_id"],
+        }];
+        let err = file.check(rules).unwrap_err();
+        assert_eq!(
+            "provisioning.scope_id must be set when provisioning.source is dps (line 3 of config.yaml)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn drop_ins_are_merged() {
+        let mut file = SettingsFile::parse(
+            "config.yaml",
+            "provisioning:\n  source: \"dps\"\n  global_endpoint: \"https://global.azure-devices-provisioning.net\"\n",
+        )
+        .unwrap();
+        let rules = &[RequiredSetting {
+            when: "provisioning.source",
+            is: "dps",
+            required: &["provisioning.scope_id"],
+        }];
+        assert!(file.check(rules).is_err());
+
+        file.merge(
+            SettingsFile::parse(
+                "config.d/50-dps.yaml",
+                "hostname: \"edge1\"\nprovisioning:\n  scope_id: \"0ne00000000\"\n",
+            )
+            .unwrap(),
+        );
+        file.check(rules).unwrap();
+        assert_eq!(Some(3), file.line("provisioning.scope_id"));
+        assert_eq!(Some(2), file.line("provisioning.source"));
+
+        file.merge(
+            SettingsFile::parse("config.d/90-dps.yaml", "provisioning:\n  scope_id:\n").unwrap(),
+        );
+        let err = file.check(rules).unwrap_err();
+        assert_eq!(
+            "provisioning.scope_id must be set when provisioning.source is dps (line 2 of config.yaml)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn null_does_not_set_required_setting() {
+        let file = SettingsFile::parse(
+            "config.yaml",
+            "provisioning:\n  source: \"dps\"\n  global_endpoint:\n  attestation: {}\n",
+        )
+        .unwrap();
+
+        let err = file.check(RULES).unwrap_err();
+        assert_eq!(
+            "provisioning.global_endpoint must be set when provisioning.source is dps (line 2 of config.yaml)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_value_has_line() {
+        const CONTENTS: &str =
+            "provisioning:\n  source: \"dps\"\n  global_endpoint:\n    url: \"a\"\nhostname: \"h\"\n";
+        let file = SettingsFile::parse("config.yaml", CONTENTS).unwrap();
+
+        let mut config = Config::default();
+        config.merge(YamlFileSource::String(CONTENTS)).unwrap();
+        config.merge(Environment::with_prefix("iotedge")).unwrap();
+
+        let err = file
+            .deserialize::<RuntimeSettings, Settings>(config.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("invalid type"), "{}", err);
+        assert!(err.contains("provisioning.global_endpoint"), "{}", err);
+        assert!(err.ends_with("(line 3 of config.yaml)"), "{}", err);
+
+        let err = file
+            .deserialize::<Settings, Settings>(config)
+            .unwrap_err()
+            .to_string();
+        assert!(err.ends_with("(line 3 of config.yaml)"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_once() {
+        let file = SettingsFile::parse("config.yaml", SETTINGS).unwrap();
+        let settings = Settings {
+            provisioning: Provisioning {
+                source: "dps".to_string(),
+                global_endpoint: "https://global.azure-devices-provisioning.net".to_string(),
+                scope_id: None,
+            },
+            hostname: "localhost".to_string(),
+            tenants: vec![Tenant {
+                name: "a".to_string(),
+                limit: None,
+            }],
+        };
+
+        // watchdog.max_retries is not reported since it is null, and neither is
+        // provisioning.attestation.method since provisioning.attestation already is.
+        let unknown = |aliases| -> Vec<(String, usize)> {
+            file.unknown_keys(&settings, aliases)
+                .into_iter()
+                .map(|(key, origin, line)| {
+                    assert_eq!("config.yaml", origin);
+                    (key, line)
+                })
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                ("provisioning.attestation".to_string(), 5),
+                ("tenants[0].limits".to_string(), 11),
+                ("watchdog".to_string(), 12),
+            ],
+            unknown(&[])
+        );
+        assert_eq!(
+            vec![
+                ("tenants[0].limits".to_string(), 11),
+                ("watchdog".to_string(), 12),
+            ],
+            unknown(&["provisioning.attestation"])
+        );
+    }
+}
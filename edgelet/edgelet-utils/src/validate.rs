@@ -0,0 +1,176 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Checks of names that come in through the APIs, so that a bad name is
+//! rejected with an error that says what is wrong with it, instead of failing
+//! in the container engine.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::error::{Error, ErrorKind};
+
+/// Modules are containers named after the module, so their names follow the
+/// container name rules of the engine. Module identities in the hub are at
+/// most 128 characters long.
+const MODULE_NAME_REGEX: &str = r"^[a-zA-Z0-9][a-zA-Z0-9_.-]{1,127}$";
+
+/// Module identities in the hub are at most 128 ASCII letters, digits and
+/// some special characters. The identities of the edge runtime modules start
+/// with `$`.
+const MODULE_ID_REGEX: &str = r"^[a-zA-Z0-9\-.+%_#*?!(),:=@$']{1,128}$";
+
+/// Image references follow the grammar of the docker distribution library:
+/// an optional registry, a lowercase repository path, and an optional tag
+/// and digest.
+const IMAGE_REFERENCE_REGEX: &str = r"^(?:(?:[a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9-]*[a-zA-Z0-9])(?:\.(?:[a-zA-Z0-9]|[a-zA-Z0-9][a-zA-Z0-9-]*[a-zA-Z0-9]))*(?::[0-9]+)?/)?[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*(?:/[a-z0-9]+(?:(?:[._]|__|-+)[a-z0-9]+)*)*(?::[A-Za-z0-9_][A-Za-z0-9_.-]{0,127})?(?:@[A-Za-z][A-Za-z0-9]*(?:[-_+.][A-Za-z][A-Za-z0-9]*)*:[0-9a-fA-F]{32,})?$";
+
+/// The longest repository name, excluding the tag and digest, docker accepts.
+const IMAGE_NAME_MAX_LENGTH: usize = 255;
+
+lazy_static! {
+    static ref MODULE_NAME: Regex =
+        Regex::new(MODULE_NAME_REGEX).expect("This hard-coded regex is expected to be valid.");
+    static ref MODULE_ID: Regex =
+        Regex::new(MODULE_ID_REGEX).expect("This hard-coded regex is expected to be valid.");
+    static ref IMAGE_REFERENCE: Regex =
+        Regex::new(IMAGE_REFERENCE_REGEX).expect("This hard-coded regex is expected to be valid.");
+}
+
+pub fn validate_module_name(name: &str) -> Result<(), Error> {
+    if MODULE_NAME.is_match(name) {
+        Ok(())
+    } else {
+        Err(Error::from(ErrorKind::InvalidModuleName(name.to_string())))
+    }
+}
+
+pub fn validate_module_id(id: &str) -> Result<(), Error> {
+    if MODULE_ID.is_match(id) {
+        Ok(())
+    } else {
+        Err(Error::from(ErrorKind::InvalidModuleId(id.to_string())))
+    }
+}
+
+pub fn validate_image_reference(image: &str) -> Result<(), Error> {
+    // The tag follows the last colon, unless that colon separates a
+    // registry's port.
+    let name = image.split('@').next().unwrap_or(image);
+    let name = match name.rfind(':') {
+        Some(index) if !name[index..].contains('/') => &name[..index],
+        _ => name,
+    };
+
+    if name.len() > IMAGE_NAME_MAX_LENGTH || !IMAGE_REFERENCE.is_match(image) {
+        Err(Error::from(ErrorKind::InvalidImage(image.to_string())))
+    } else {
+        Ok(())
+    }
+}
+
+/// Environment variables are passed to the container as `name=value`, so a
+/// name can't contain `=`, and it can't be empty.
+pub fn validate_env_var_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.contains('=') || name.contains('\0') {
+        Err(Error::from(ErrorKind::InvalidEnvVarName(name.to_string())))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_names() {
+        for name in &[
+            "edgeHub",
+            "tempSensor",
+            "my_module.v2",
+            "a1",
+            &"m".repeat(128),
+        ] {
+            assert!(validate_module_name(name).is_ok(), "{}", name);
+        }
+
+        for name in &[
+            "",
+            "m",
+            "$edgeAgent",
+            "-module",
+            "my module",
+            "mod/ule",
+            "mod:ule",
+            &"m".repeat(129),
+        ] {
+            match validate_module_name(name).unwrap_err().kind() {
+                ErrorKind::InvalidModuleName(n) => assert_eq!(name, n),
+                kind => panic!("expected InvalidModuleName but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn module_ids() {
+        for id in &["$edgeHub", "edgeHub", "my-module:v2", "a", "m@(1)"] {
+            assert!(validate_module_id(id).is_ok(), "{}", id);
+        }
+
+        for id in &[
+            "",
+            "my module",
+            "mod/ule",
+            "mod\\ule",
+            "módulo",
+            &"m".repeat(129),
+        ] {
+            match validate_module_id(id).unwrap_err().kind() {
+                ErrorKind::InvalidModuleId(i) => assert_eq!(id, i),
+                kind => panic!("expected InvalidModuleId but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn image_references() {
+        for image in &[
+            "ubuntu",
+            "microsoft/azureiotedge-agent:1.0",
+            "mcr.microsoft.com/azureiotedge-hub:1.0.9-linux-amd64",
+            "localhost:5000/my_module",
+            "registry.example.com:443/team/my-module@sha256:4c6b7b8b5a4e5ae2e5b6a2d3e6b1c5d9b9a6d4e3c2b1a09f8e7d6c5b4a392817",
+        ] {
+            assert!(validate_image_reference(image).is_ok(), "{}", image);
+        }
+
+        for image in &[
+            "Ubuntu",
+            "microsoft/agent:",
+            "microsoft//agent",
+            "microsoft/agent:1.0 ",
+            "-microsoft/agent",
+            "microsoft/agent@sha256:abc",
+            &format!("registry.io/{}:1.0", "a".repeat(250)),
+        ] {
+            match validate_image_reference(image).unwrap_err().kind() {
+                ErrorKind::InvalidImage(i) => assert_eq!(image, i),
+                kind => panic!("expected InvalidImage but got {:?}", kind),
+            }
+        }
+    }
+
+    #[test]
+    fn env_var_names() {
+        for name in &["PATH", "RuntimeLogLevel", "my.var", "1"] {
+            assert!(validate_env_var_name(name).is_ok(), "{}", name);
+        }
+
+        for name in &["", "K=V", "K\0"] {
+            match validate_env_var_name(name).unwrap_err().kind() {
+                ErrorKind::InvalidEnvVarName(n) => assert_eq!(name, n),
+                kind => panic!("expected InvalidEnvVarName but got {:?}", kind),
+            }
+        }
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::env;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
@@ -14,6 +15,9 @@ use yaml_rust::{Yaml, YamlLoader};
 /// We use this to parse our config.yaml instead of `config::File` because `config::File` lower-cases all field names that it reads.
 /// This causes issues with fields like `agent.config.createOptions` since the config crate returns `agent.config.createoptions`
 /// which the serde deserializer ignores.
+///
+/// String values can refer to environment variables as `${NAME}`, or as `${NAME:-default}` to fall back to `default`
+/// when `NAME` is not set, so that one config file can be templated across devices. `$${` stands for a literal `${`.
 #[derive(Clone, Debug)]
 pub enum YamlFileSource {
     File(PathBuf),
@@ -85,7 +89,11 @@ impl Source for YamlFileSource {
 /// The only difference is the fallback `_` case at the end.
 fn from_yaml_value(uri: Option<&String>, value: Yaml) -> Result<Value, ConfigError> {
     match value {
-        Yaml::String(value) => Ok(Value::new(uri, value)),
+        Yaml::String(value) => Ok(Value::new(
+            uri,
+            interpolate(&value, |name| env::var(name).ok())
+                .map_err(|err| ConfigError::Foreign(Box::new(err)))?,
+        )),
         Yaml::Real(value) => {
             // TODO: Figure out in what cases this can fail?
             Ok(Value::new(
@@ -132,10 +140,56 @@ fn from_yaml_value(uri: Option<&String>, value: Yaml) -> Result<Value, ConfigErr
     }
 }
 
+/// Replaces the `${NAME}` and `${NAME:-default}` references in `value` with the values `lookup` returns for them.
+fn interpolate<F>(value: &str, lookup: F) -> Result<String, YamlFileSourceError>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        // `$${` is a literal `${`
+        if rest[..start].ends_with('$') {
+            result.push_str(&rest[..start]);
+            result.push('{');
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        result.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or_else(|| {
+            // Only the name is part of the error, the rest of the value may well be a secret.
+            let name = rest[start + 2..]
+                .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .next()
+                .unwrap_or_default();
+            YamlFileSourceError::UnterminatedVariable(name.to_string())
+        })?;
+        let reference = &rest[start + 2..start + end];
+
+        let mut parts = reference.splitn(2, ":-");
+        let name = parts.next().unwrap_or_default();
+        let default = parts.next();
+        match (lookup(name), default) {
+            (Some(variable), _) => result.push_str(&variable),
+            (None, Some(default)) => result.push_str(default),
+            (None, None) => return Err(YamlFileSourceError::UndefinedVariable(name.to_string())),
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
 #[derive(Debug)]
 enum YamlFileSourceError {
     MoreThanOneDocument,
+    UndefinedVariable(String),
     UnrecognizedYamlValue(Yaml),
+    UnterminatedVariable(String),
 }
 
 impl std::fmt::Display for YamlFileSourceError {
@@ -144,11 +198,103 @@ impl std::fmt::Display for YamlFileSourceError {
             YamlFileSourceError::MoreThanOneDocument => {
                 write!(f, "more than one YAML document provided")
             }
+            YamlFileSourceError::UndefinedVariable(name) => write!(
+                f,
+                "environment variable {} is not set and has no default",
+                name
+            ),
             YamlFileSourceError::UnrecognizedYamlValue(value) => {
                 write!(f, "unrecognized YAML value {:?}", value)
             }
+            YamlFileSourceError::UnterminatedVariable(name) => write!(
+                f,
+                "environment variable reference ${{{} is missing its closing brace",
+                name
+            ),
         }
     }
 }
 
 impl std::error::Error for YamlFileSourceError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "DEVICE_ID" => Some("device1".to_string()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn interpolate_variables() {
+        assert_eq!("no variables", interpolate("no variables", lookup).unwrap());
+        assert_eq!(
+            "DeviceId=device1;SharedAccessKey=key",
+            interpolate("DeviceId=${DEVICE_ID};SharedAccessKey=key", lookup).unwrap()
+        );
+        assert_eq!(
+            "device1/device1",
+            interpolate("${DEVICE_ID}/${DEVICE_ID}", lookup).unwrap()
+        );
+        assert_eq!("", interpolate("${EMPTY}", lookup).unwrap());
+    }
+
+    #[test]
+    fn interpolate_defaults() {
+        assert_eq!(
+            "registry.example.com",
+            interpolate("${REGISTRY:-registry.example.com}", lookup).unwrap()
+        );
+        assert_eq!("", interpolate("${REGISTRY:-}", lookup).unwrap());
+        assert_eq!(
+            "device1",
+            interpolate("${DEVICE_ID:-fallback}", lookup).unwrap()
+        );
+    }
+
+    #[test]
+    fn interpolate_escape() {
+        assert_eq!(
+            "${DEVICE_ID} is device1",
+            interpolate("$${DEVICE_ID} is ${DEVICE_ID}", lookup).unwrap()
+        );
+        assert_eq!("$5 and $", interpolate("$5 and $", lookup).unwrap());
+    }
+
+    #[test]
+    fn interpolate_errors() {
+        assert_eq!(
+            "environment variable REGISTRY is not set and has no default",
+            interpolate("${REGISTRY}", lookup).unwrap_err().to_string()
+        );
+        assert_eq!(
+            "environment variable reference ${DEVICE_ID is missing its closing brace",
+            interpolate("SharedAccessKey=${DEVICE_ID;key=secret", lookup)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn file_values_are_interpolated() {
+        std::env::set_var("YAML_FILE_SOURCE_TEST_HOSTNAME", "edge1");
+        let values = YamlFileSource::String(
+            "hostname: \"${YAML_FILE_SOURCE_TEST_HOSTNAME}\"\nagent:\n  name: \"${YAML_FILE_SOURCE_TEST_AGENT:-edgeAgent}\"\n",
+        )
+        .collect()
+        .unwrap();
+
+        assert_eq!("edge1", values["hostname"].clone().into_str().unwrap());
+        assert_eq!(
+            "edgeAgent",
+            values["agent"].clone().into_table().unwrap()["name"]
+                .clone()
+                .into_str()
+                .unwrap()
+        );
+    }
+}
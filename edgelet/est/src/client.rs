@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::{Fail, ResultExt};
+use futures::future::{self, Either};
+use futures::{Future, Stream};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Method, Request, StatusCode};
+use log::debug;
+use openssl::x509::{X509ReqRef, X509};
+use url::Url;
+
+use edgelet_http::client::ClientImpl;
+
+use crate::error::{Error, ErrorKind};
+use crate::pkcs7;
+
+const CONTENT_TRANSFER_ENCODING: &str = "content-transfer-encoding";
+
+/// Talks to the EST server at `url`, which is the base of the EST operations
+/// (for example `https://est.example.com/.well-known/est`, or the same with
+/// an additional CA label path segment).
+///
+/// The server authenticates the client with HTTP basic authentication, when
+/// credentials are set, and with the TLS client certificate of `client`.
+pub struct EstClient<C> {
+    client: C,
+    url: Url,
+    credentials: Option<(String, String)>,
+}
+
+impl<C> EstClient<C>
+where
+    C: ClientImpl,
+{
+    pub fn new(client: C, url: Url) -> Self {
+        EstClient {
+            client,
+            url,
+            credentials: None,
+        }
+    }
+
+    pub fn with_basic_auth(mut self, username: String, password: String) -> Self {
+        self.credentials = Some((username, password));
+        self
+    }
+
+    /// Gets the CA certificates of the EST server, which the certificates it
+    /// issues chain up to.
+    pub fn ca_certs(&self) -> impl Future<Item = Vec<X509>, Error = Error> {
+        self.request("cacerts", None)
+    }
+
+    /// Requests a new certificate for the key that signed `csr`.
+    pub fn simple_enroll(&self, csr: &X509ReqRef) -> impl Future<Item = Vec<X509>, Error = Error> {
+        self.request("simpleenroll", Some(csr))
+    }
+
+    /// Renews a certificate. The server expects the client to authenticate
+    /// with the certificate that is being renewed.
+    pub fn simple_reenroll(
+        &self,
+        csr: &X509ReqRef,
+    ) -> impl Future<Item = Vec<X509>, Error = Error> {
+        self.request("simplereenroll", Some(csr))
+    }
+
+    fn request(
+        &self,
+        operation: &str,
+        csr: Option<&X509ReqRef>,
+    ) -> impl Future<Item = Vec<X509>, Error = Error> {
+        let request = match self.build_request(operation, csr) {
+            Ok(request) => request,
+            Err(err) => return Either::A(future::err(err)),
+        };
+        debug!("EST {} {}", request.method(), request.uri());
+
+        Either::B(
+            self.client
+                .call(request)
+                .map_err(|err| Error::from(err.context(ErrorKind::Http)))
+                .and_then(|response| {
+                    let status = response.status();
+                    response
+                        .into_body()
+                        .concat2()
+                        .map_err(|err| Error::from(err.context(ErrorKind::Http)))
+                        .and_then(move |body| parse_response(status, &body))
+                }),
+        )
+    }
+
+    fn build_request(
+        &self,
+        operation: &str,
+        csr: Option<&X509ReqRef>,
+    ) -> Result<Request<Body>, Error> {
+        let uri = format!("{}/{}", self.url.as_str().trim_end_matches('/'), operation);
+        let mut builder = Request::builder();
+        builder.uri(uri.as_str());
+
+        if let Some((username, password)) = &self.credentials {
+            let credentials = base64::encode(&format!("{}:{}", username, password));
+            builder.header(AUTHORIZATION, format!("Basic {}", credentials));
+        }
+
+        let request = match csr {
+            Some(csr) => {
+                let der = csr.to_der().context(ErrorKind::CreateCsr)?;
+                builder
+                    .method(Method::POST)
+                    .header(CONTENT_TYPE, "application/pkcs10")
+                    .header(CONTENT_TRANSFER_ENCODING, "base64")
+                    .body(Body::from(base64::encode(&der)))
+            }
+            None => builder.method(Method::GET).body(Body::empty()),
+        };
+        let request = request.context(ErrorKind::InvalidUrl(uri))?;
+        Ok(request)
+    }
+}
+
+fn parse_response(status: StatusCode, body: &[u8]) -> Result<Vec<X509>, Error> {
+    match status {
+        StatusCode::OK => pkcs7::certificates_from_base64(body),
+        StatusCode::ACCEPTED => Err(Error::from(ErrorKind::EnrollmentPending)),
+        StatusCode::UNAUTHORIZED => Err(Error::from(ErrorKind::Unauthorized)),
+        status => Err(Error::from(ErrorKind::Response(status.as_u16()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{Response, StatusCode};
+
+    use super::*;
+    use crate::csr::{create_csr, create_private_key, CertificateUsage};
+    use crate::pkcs7::tests::{certs_only, self_signed};
+
+    fn est_url() -> Url {
+        Url::parse("https://est.example.com/.well-known/est/").unwrap()
+    }
+
+    fn certs_response(certs: &[X509]) -> Response<Body> {
+        Response::new(Body::from(base64::encode(&certs_only(certs))))
+    }
+
+    fn status_response(status: StatusCode) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = status;
+        response
+    }
+
+    #[test]
+    fn ca_certs_gets_certificates() {
+        let root = self_signed("root");
+        let root_der = root.to_der().unwrap();
+        let client = EstClient::new(
+            move |req: Request<Body>| {
+                assert_eq!(Method::GET, *req.method());
+                assert_eq!(
+                    "https://est.example.com/.well-known/est/cacerts",
+                    req.uri().to_string()
+                );
+                assert!(req.headers().get(AUTHORIZATION).is_none());
+                Ok(certs_response(&[root.clone()]))
+            },
+            est_url(),
+        );
+
+        let certs = client.ca_certs().wait().unwrap();
+        assert_eq!(1, certs.len());
+        assert_eq!(root_der, certs[0].to_der().unwrap());
+    }
+
+    #[test]
+    fn simple_enroll_posts_csr_with_basic_auth() {
+        let key = create_private_key().unwrap();
+        let csr = create_csr("my-device", CertificateUsage::Identity, &key).unwrap();
+        let csr_der = csr.to_der().unwrap();
+        let issued = self_signed("my-device");
+
+        let client = EstClient::new(
+            move |req: Request<Body>| {
+                assert_eq!(Method::POST, *req.method());
+                assert_eq!(
+                    "https://est.example.com/.well-known/est/simpleenroll",
+                    req.uri().to_string()
+                );
+                assert_eq!("application/pkcs10", req.headers()[CONTENT_TYPE]);
+                assert_eq!("base64", req.headers()[CONTENT_TRANSFER_ENCODING]);
+                // "user:pass"
+                assert_eq!("Basic dXNlcjpwYXNz", req.headers()[AUTHORIZATION]);
+
+                let body = req.into_body().concat2().wait().unwrap();
+                assert_eq!(csr_der, base64::decode(&body).unwrap());
+                Ok(certs_response(&[issued.clone()]))
+            },
+            est_url(),
+        )
+        .with_basic_auth("user".to_string(), "pass".to_string());
+
+        let certs = client.simple_enroll(&csr).wait().unwrap();
+        assert_eq!(1, certs.len());
+    }
+
+    #[test]
+    fn simple_reenroll_posts_to_reenroll() {
+        let key = create_private_key().unwrap();
+        let csr = create_csr("edge ca", CertificateUsage::Ca, &key).unwrap();
+        let issued = self_signed("edge ca");
+
+        let client = EstClient::new(
+            move |req: Request<Body>| {
+                assert_eq!(
+                    "https://est.example.com/.well-known/est/simplereenroll",
+                    req.uri().to_string()
+                );
+                Ok(certs_response(&[issued.clone()]))
+            },
+            est_url(),
+        );
+
+        let certs = client.simple_reenroll(&csr).wait().unwrap();
+        assert_eq!(1, certs.len());
+    }
+
+    #[test]
+    fn enroll_fails_when_pending() {
+        let key = create_private_key().unwrap();
+        let csr = create_csr("my-device", CertificateUsage::Identity, &key).unwrap();
+        let client = EstClient::new(
+            |_req: Request<Body>| Ok(status_response(StatusCode::ACCEPTED)),
+            est_url(),
+        );
+
+        let err = client.simple_enroll(&csr).wait().unwrap_err();
+        assert_eq!(&ErrorKind::EnrollmentPending, err.kind());
+    }
+
+    #[test]
+    fn enroll_fails_when_unauthorized() {
+        let key = create_private_key().unwrap();
+        let csr = create_csr("my-device", CertificateUsage::Identity, &key).unwrap();
+        let client = EstClient::new(
+            |_req: Request<Body>| Ok(status_response(StatusCode::UNAUTHORIZED)),
+            est_url(),
+        );
+
+        let err = client.simple_enroll(&csr).wait().unwrap_err();
+        assert_eq!(&ErrorKind::Unauthorized, err.kind());
+    }
+
+    #[test]
+    fn ca_certs_fails_on_error_status() {
+        let client = EstClient::new(
+            |_req: Request<Body>| Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR)),
+            est_url(),
+        );
+
+        let err = client.ca_certs().wait().unwrap_err();
+        assert_eq!(&ErrorKind::Response(500), err.kind());
+    }
+
+    #[test]
+    fn ca_certs_fails_on_malformed_response() {
+        let client = EstClient::new(
+            |_req: Request<Body>| Ok(Response::new(Body::from("not base64!"))),
+            est_url(),
+        );
+
+        let err = client.ca_certs().wait().unwrap_err();
+        assert_eq!(&ErrorKind::MalformedResponse, err.kind());
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use failure::ResultExt;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, PKeyRef, Private};
+use openssl::rsa::Rsa;
+use openssl::stack::Stack;
+use openssl::x509::extension::{BasicConstraints, ExtendedKeyUsage, KeyUsage};
+use openssl::x509::{X509Name, X509Req, X509ReqBuilder};
+
+use crate::error::{Error, ErrorKind};
+
+const RSA_KEY_BITS: u32 = 2048;
+
+/// What the enrolled certificate is going to be used for, which decides the
+/// extensions requested for it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CertificateUsage {
+    /// A CA certificate that issues the workload CA and module certificates
+    Ca,
+    /// A client certificate that the device authenticates with
+    Identity,
+}
+
+pub fn create_private_key() -> Result<PKey<Private>, Error> {
+    let rsa = Rsa::generate(RSA_KEY_BITS).context(ErrorKind::CreatePrivateKey)?;
+    let key = PKey::from_rsa(rsa).context(ErrorKind::CreatePrivateKey)?;
+    Ok(key)
+}
+
+pub fn create_csr(
+    common_name: &str,
+    usage: CertificateUsage,
+    key: &PKeyRef<Private>,
+) -> Result<X509Req, Error> {
+    let mut name = X509Name::builder().context(ErrorKind::CreateCsr)?;
+    name.append_entry_by_text("CN", common_name)
+        .context(ErrorKind::CreateCsr)?;
+    let name = name.build();
+
+    let mut extensions = Stack::new().context(ErrorKind::CreateCsr)?;
+    match usage {
+        CertificateUsage::Ca => {
+            extensions
+                .push(
+                    BasicConstraints::new()
+                        .critical()
+                        .ca()
+                        .build()
+                        .context(ErrorKind::CreateCsr)?,
+                )
+                .context(ErrorKind::CreateCsr)?;
+            extensions
+                .push(
+                    KeyUsage::new()
+                        .critical()
+                        .digital_signature()
+                        .key_cert_sign()
+                        .crl_sign()
+                        .build()
+                        .context(ErrorKind::CreateCsr)?,
+                )
+                .context(ErrorKind::CreateCsr)?;
+        }
+        CertificateUsage::Identity => {
+            extensions
+                .push(
+                    KeyUsage::new()
+                        .critical()
+                        .digital_signature()
+                        .key_encipherment()
+                        .build()
+                        .context(ErrorKind::CreateCsr)?,
+                )
+                .context(ErrorKind::CreateCsr)?;
+            extensions
+                .push(
+                    ExtendedKeyUsage::new()
+                        .client_auth()
+                        .build()
+                        .context(ErrorKind::CreateCsr)?,
+                )
+                .context(ErrorKind::CreateCsr)?;
+        }
+    }
+
+    let mut builder = X509ReqBuilder::new().context(ErrorKind::CreateCsr)?;
+    builder.set_version(0).context(ErrorKind::CreateCsr)?;
+    builder
+        .set_subject_name(&name)
+        .context(ErrorKind::CreateCsr)?;
+    builder.set_pubkey(key).context(ErrorKind::CreateCsr)?;
+    builder
+        .add_extensions(&extensions)
+        .context(ErrorKind::CreateCsr)?;
+    builder
+        .sign(key, MessageDigest::sha256())
+        .context(ErrorKind::CreateCsr)?;
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::nid::Nid;
+
+    use super::*;
+
+    #[test]
+    fn csr_has_common_name_and_is_signed_by_key() {
+        let key = create_private_key().unwrap();
+        let csr = create_csr("my-device", CertificateUsage::Identity, &key).unwrap();
+
+        let common_name = csr
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .unwrap()
+            .data()
+            .as_utf8()
+            .unwrap()
+            .to_string();
+        assert_eq!("my-device", common_name);
+        assert!(csr.verify(&key).unwrap());
+    }
+
+    #[test]
+    fn ca_csr_is_signed_by_key() {
+        let key = create_private_key().unwrap();
+        let csr = create_csr("edge ca", CertificateUsage::Ca, &key).unwrap();
+
+        let pem = String::from_utf8(csr.to_pem().unwrap()).unwrap();
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE REQUEST-----"));
+        assert!(csr.verify(&key).unwrap());
+    }
+}
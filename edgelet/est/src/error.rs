@@ -0,0 +1,74 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+use std::fmt::Display;
+
+use failure::{Backtrace, Context, Fail};
+
+#[derive(Debug)]
+pub struct Error {
+    inner: Context<ErrorKind>,
+}
+
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum ErrorKind {
+    #[fail(display = "Could not create the certificate signing request")]
+    CreateCsr,
+
+    #[fail(display = "Could not create the private key")]
+    CreatePrivateKey,
+
+    #[fail(display = "The EST server accepted the request but has not issued the certificate yet")]
+    EnrollmentPending,
+
+    #[fail(display = "Could not send the request to the EST server")]
+    Http,
+
+    #[fail(display = "The EST server URL {} is invalid", _0)]
+    InvalidUrl(String),
+
+    #[fail(display = "The EST server returned a malformed PKCS#7 response")]
+    MalformedResponse,
+
+    #[fail(display = "The EST server responded with status {}", _0)]
+    Response(u16),
+
+    #[fail(display = "The EST server rejected the credentials")]
+    Unauthorized,
+}
+
+impl Fail for Error {
+    fn cause(&self) -> Option<&dyn Fail> {
+        self.inner.cause()
+    }
+
+    fn backtrace(&self) -> Option<&Backtrace> {
+        self.inner.backtrace()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.inner, f)
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> &ErrorKind {
+        self.inner.get_context()
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            inner: Context::new(kind),
+        }
+    }
+}
+
+impl From<Context<ErrorKind>> for Error {
+    fn from(inner: Context<ErrorKind>) -> Self {
+        Error { inner }
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms, warnings)]
+#![deny(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::module_name_repetitions,
+    clippy::must_use_candidate,
+    clippy::use_self
+)]
+
+//! A client for Enrollment over Secure Transport (EST, RFC 7030), used to
+//! enroll and renew certificates with an enterprise CA.
+
+mod client;
+mod csr;
+pub mod error;
+mod pkcs7;
+
+pub use crate::client::EstClient;
+pub use crate::csr::{create_csr, create_private_key, CertificateUsage};
+pub use crate::error::{Error, ErrorKind};
@@ -0,0 +1,227 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! EST servers return certificates as a base64 encoded "certs-only" PKCS#7
+//! `SignedData` structure. Only the certificates are of interest, so this walks
+//! just enough of the DER encoding to pick them out.
+
+use failure::ResultExt;
+use openssl::x509::X509;
+
+use crate::error::{Error, ErrorKind};
+
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OID: u8 = 0x06;
+const TAG_CONTEXT_0: u8 = 0xa0;
+
+/// 1.2.840.113549.1.7.2
+const OID_SIGNED_DATA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+
+pub fn certificates_from_base64(body: &[u8]) -> Result<Vec<X509>, Error> {
+    let body: Vec<u8> = body
+        .iter()
+        .cloned()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let der = base64::decode(&body).context(ErrorKind::MalformedResponse)?;
+    certificates_from_der(&der)
+}
+
+pub fn certificates_from_der(der: &[u8]) -> Result<Vec<X509>, Error> {
+    let content_info = expect(der, TAG_SEQUENCE)?.0;
+
+    let (content_type, rest) = expect(content_info, TAG_OID)?;
+    if content_type != OID_SIGNED_DATA {
+        return Err(Error::from(ErrorKind::MalformedResponse));
+    }
+
+    let content = expect(rest, TAG_CONTEXT_0)?.0;
+    let signed_data = expect(content, TAG_SEQUENCE)?.0;
+
+    let (_version, rest) = expect(signed_data, TAG_INTEGER)?;
+    let (_digest_algorithms, rest) = expect(rest, TAG_SET)?;
+    let (_encap_content_info, rest) = expect(rest, TAG_SEQUENCE)?;
+
+    // The certificates are optional, but a response without any is useless
+    let mut certificates = expect(rest, TAG_CONTEXT_0)?.0;
+
+    let mut result = vec![];
+    while !certificates.is_empty() {
+        let (_, rest) = expect(certificates, TAG_SEQUENCE)?;
+        let certificate = &certificates[..certificates.len() - rest.len()];
+        result.push(X509::from_der(certificate).context(ErrorKind::MalformedResponse)?);
+        certificates = rest;
+    }
+
+    if result.is_empty() {
+        Err(Error::from(ErrorKind::MalformedResponse))
+    } else {
+        Ok(result)
+    }
+}
+
+/// Reads a DER element with the given tag, and returns its contents and
+/// whatever follows it.
+fn expect(data: &[u8], tag: u8) -> Result<(&[u8], &[u8]), Error> {
+    if data.len() < 2 || data[0] != tag {
+        return Err(Error::from(ErrorKind::MalformedResponse));
+    }
+
+    let (len, header_len) = if data[1] < 0x80 {
+        (usize::from(data[1]), 2)
+    } else {
+        let num_bytes = usize::from(data[1] & 0x7f);
+        if num_bytes == 0 || num_bytes > 4 || data.len() < 2 + num_bytes {
+            return Err(Error::from(ErrorKind::MalformedResponse));
+        }
+        let len = data[2..2 + num_bytes]
+            .iter()
+            .fold(0, |len, b| (len << 8) | usize::from(*b));
+        (len, 2 + num_bytes)
+    };
+
+    if data.len() - header_len < len {
+        return Err(Error::from(ErrorKind::MalformedResponse));
+    }
+
+    let end = header_len + len;
+    Ok((&data[header_len..end], &data[end..]))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Name, X509};
+
+    use super::*;
+
+    pub fn self_signed(common_name: &str) -> X509 {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        let name = name.build();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut result = vec![tag];
+        let len = contents.len();
+        if len < 0x80 {
+            result.push(len as u8);
+        } else if len < 0x100 {
+            result.extend_from_slice(&[0x81, len as u8]);
+        } else {
+            result.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]);
+        }
+        result.extend_from_slice(contents);
+        result
+    }
+
+    /// Builds a certs-only PKCS#7 structure like EST servers return.
+    pub fn certs_only(certs: &[X509]) -> Vec<u8> {
+        let certs: Vec<u8> = certs
+            .iter()
+            .flat_map(|cert| cert.to_der().unwrap())
+            .collect();
+
+        let mut signed_data = tlv(TAG_INTEGER, &[1]);
+        signed_data.extend(tlv(TAG_SET, &[]));
+        signed_data.extend(tlv(
+            TAG_SEQUENCE,
+            &tlv(
+                TAG_OID,
+                &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01],
+            ),
+        ));
+        signed_data.extend(tlv(TAG_CONTEXT_0, &certs));
+        signed_data.extend(tlv(TAG_SET, &[]));
+
+        let mut content_info = tlv(TAG_OID, OID_SIGNED_DATA);
+        content_info.extend(tlv(TAG_CONTEXT_0, &tlv(TAG_SEQUENCE, &signed_data)));
+        tlv(TAG_SEQUENCE, &content_info)
+    }
+
+    #[test]
+    fn parses_certificates() {
+        let root = self_signed("root");
+        let leaf = self_signed("leaf");
+        let der = certs_only(&[leaf.clone(), root.clone()]);
+
+        let certs = certificates_from_der(&der).unwrap();
+        assert_eq!(2, certs.len());
+        assert_eq!(leaf.to_der().unwrap(), certs[0].to_der().unwrap());
+        assert_eq!(root.to_der().unwrap(), certs[1].to_der().unwrap());
+    }
+
+    #[test]
+    fn parses_base64_with_line_breaks() {
+        let cert = self_signed("leaf");
+        let encoded = base64::encode(&certs_only(&[cert.clone()]));
+        let wrapped: Vec<u8> = encoded
+            .as_bytes()
+            .chunks(64)
+            .flat_map(|line| line.iter().cloned().chain(b"\r\n".iter().cloned()))
+            .collect();
+
+        let certs = certificates_from_base64(&wrapped).unwrap();
+        assert_eq!(1, certs.len());
+        assert_eq!(cert.to_der().unwrap(), certs[0].to_der().unwrap());
+    }
+
+    #[test]
+    fn rejects_response_without_certificates() {
+        let der = certs_only(&[]);
+        assert_eq!(
+            &ErrorKind::MalformedResponse,
+            certificates_from_der(&der).unwrap_err().kind()
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_response() {
+        let der = certs_only(&[self_signed("leaf")]);
+        assert_eq!(
+            &ErrorKind::MalformedResponse,
+            certificates_from_der(&der[..der.len() - 10])
+                .unwrap_err()
+                .kind()
+        );
+    }
+
+    #[test]
+    fn rejects_other_content_types() {
+        let der = tlv(
+            TAG_SEQUENCE,
+            &tlv(
+                TAG_OID,
+                &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x01],
+            ),
+        );
+        assert_eq!(
+            &ErrorKind::MalformedResponse,
+            certificates_from_der(&der).unwrap_err().kind()
+        );
+    }
+}
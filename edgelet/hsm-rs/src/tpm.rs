@@ -181,6 +181,11 @@ pub struct TpmBuffer {
     len: usize,
 }
 
+// The buffer is only read after it was returned, and the free function of the
+// interface can be called from any thread.
+unsafe impl Send for TpmBuffer {}
+unsafe impl Sync for TpmBuffer {}
+
 impl Drop for TpmBuffer {
     fn drop(&mut self) {
         let free_fn = self
@@ -14,6 +14,7 @@
 #include "hsm_err.h"
 #include "hsm_key.h"
 #include "hsm_log.h"
+#include "hsm_store_protect.h"
 #include "hsm_utils.h"
 
 //##############################################################################
@@ -671,11 +672,30 @@ static STRING_HANDLE normalize_alias_file_path(const char *alias)
     return result;
 }
 
+static char* get_pkcs11_pk_uri(const char *alias)
+{
+    char *result = NULL;
+
+    // the workload CA key can be kept in a PKCS#11 token instead of under the
+    // HSM base directory
+    if ((strcmp(alias, EDGE_CA_ALIAS) == 0) &&
+        (hsm_get_env(ENV_WORKLOAD_CA_PK_URI, &result) == 0) &&
+        (result != NULL) &&
+        !is_pkcs11_uri(result))
+    {
+        LOG_ERROR("Env %s is not a PKCS#11 URI: '%s'", ENV_WORKLOAD_CA_PK_URI, result);
+        FREEIF(result);
+    }
+
+    return result;
+}
+
 static int build_cert_file_paths(const char *alias, STRING_HANDLE cert_file, STRING_HANDLE pk_file)
 {
     int result;
     const char *base_dir_path = get_base_dir();
     STRING_HANDLE normalized_alias;
+    char *pk_uri = NULL;
 
     if ((normalized_alias = normalize_alias_file_path(alias)) == NULL)
     {
@@ -694,6 +714,18 @@ static int build_cert_file_paths(const char *alias, STRING_HANDLE cert_file, STR
             LOG_ERROR("Could not construct path to certificate for %s", alias);
             result = __FAILURE__;
         }
+        else if ((pk_file != NULL) && ((pk_uri = get_pkcs11_pk_uri(alias)) != NULL))
+        {
+            if (STRING_concat(pk_file, pk_uri) != 0)
+            {
+                LOG_ERROR("Could not construct PKCS#11 URI of private key for %s", alias);
+                result = __FAILURE__;
+            }
+            else
+            {
+                result = 0;
+            }
+        }
         else if ((pk_file != NULL) &&
                  ((STRING_concat(pk_file, base_dir_path) != 0) ||
                   (STRING_concat(pk_file, SLASH)  != 0) ||
@@ -711,6 +743,7 @@ static int build_cert_file_paths(const char *alias, STRING_HANDLE cert_file, STR
         }
         STRING_delete(normalized_alias);
     }
+    free(pk_uri);
 
     return result;
 }
@@ -770,7 +803,7 @@ static int save_encryption_key_to_file(const char *key_name, unsigned char *key,
             LOG_ERROR("Key file path NULL");
             result = __FAILURE__;
         }
-        else if (write_buffer_to_file(key_file, key, key_size, true) != 0)
+        else if (store_protect_write_file(key_file, key, key_size) != 0)
         {
             LOG_ERROR("Could not write key to file");
             result = __FAILURE__;
@@ -811,7 +844,7 @@ static int load_encryption_key_from_file(CRYPTO_STORE* store, const char *key_na
             LOG_ERROR("Key file path NULL");
             result = __FAILURE__;
         }
-        else if (((key = read_file_into_buffer(key_file, &key_size)) == NULL) ||
+        else if (((key = store_protect_read_file(key_file, &key_size)) == NULL) ||
                   (key_size == 0))
         {
             LOG_INFO("Could not read encryption key from file. "
@@ -886,7 +919,8 @@ static CERT_INFO_HANDLE prepare_cert_info_handle
         LOG_ERROR("Private key file path is NULL");
         result = NULL;
     }
-    else if ((private_key_contents = read_file_into_buffer(pk_file, &private_key_size)) == NULL)
+    else if (!is_pkcs11_uri(pk_file) &&
+             ((private_key_contents = store_protect_read_file(pk_file, &private_key_size)) == NULL))
     {
         LOG_ERROR("Could not load private key into buffer %s", pk_file);
         result = NULL;
@@ -901,6 +935,14 @@ static CERT_INFO_HANDLE prepare_cert_info_handle
         LOG_ERROR("Could not read certificate into buffer %s", cert_file);
         result = NULL;
     }
+    else if (is_pkcs11_uri(pk_file))
+    {
+        // keys in a PKCS#11 token cannot be read out of it, so refer to them instead
+        result = certificate_info_create(cert_contents,
+                                         pk_file,
+                                         strlen(pk_file),
+                                         PRIVATE_KEY_REFERENCE);
+    }
     else
     {
         result = certificate_info_create(cert_contents,
@@ -1438,7 +1480,8 @@ static int remove_if_cert_and_key_exist_by_alias
         const char *cert_file_path = STRING_c_str(alias_cert_handle);
         const char *key_file_path = STRING_c_str(alias_pk_handle);
 
-        if (!is_file_valid(cert_file_path) || !is_file_valid(key_file_path))
+        if (!is_file_valid(cert_file_path) ||
+            (!is_pkcs11_uri(key_file_path) && !is_file_valid(key_file_path)))
         {
             LOG_ERROR("Certificate and key file for alias do not exist %s", alias);
             result = __FAILURE__;
@@ -1450,7 +1493,8 @@ static int remove_if_cert_and_key_exist_by_alias
                 LOG_ERROR("Could not delete certificate file for alias %s", alias);
                 result = __FAILURE__;
             }
-            else if (delete_file(key_file_path) != 0)
+            // keys in a PKCS#11 token outlive the certificates issued for them
+            else if (!is_pkcs11_uri(key_file_path) && (delete_file(key_file_path) != 0))
             {
                 LOG_ERROR("Could not delete key file for alias %s", alias);
                 result = __FAILURE__;
@@ -1507,7 +1551,8 @@ static int load_if_cert_and_key_exist_by_alias
         const char *cert_file_path = STRING_c_str(alias_cert_handle);
         const char *key_file_path = STRING_c_str(alias_pk_handle);
         bool verify_status = false;
-        if (is_file_valid(cert_file_path) && is_file_valid(key_file_path))
+        if (is_file_valid(cert_file_path) &&
+            (is_pkcs11_uri(key_file_path) || is_file_valid(key_file_path)))
         {
             if (verify_certificate_helper(handle, alias, issuer_alias,
                                           cert_file_path, key_file_path,
@@ -1780,7 +1825,8 @@ static int hsm_provision_edge_id_certificate(void)
 
         if (device_id_pk_path != NULL)
         {
-            if ((strlen(device_id_pk_path) != 0) && is_file_valid(device_id_pk_path))
+            if ((strlen(device_id_pk_path) != 0) &&
+                (is_pkcs11_uri(device_id_pk_path) || is_file_valid(device_id_pk_path)))
             {
                 mask |= 1 << i; i++;
             }
@@ -1884,7 +1930,8 @@ static int hsm_provision_edge_ca_certificates(uint64_t auto_generated_ca_lifetim
 
         if (device_ca_pk_path != NULL)
         {
-            if ((strlen(device_ca_pk_path) != 0) && is_file_valid(device_ca_pk_path))
+            if ((strlen(device_ca_pk_path) != 0) &&
+                (is_pkcs11_uri(device_ca_pk_path) || is_file_valid(device_ca_pk_path)))
             {
                 mask |= 1 << i; i++;
             }
@@ -2015,10 +2062,19 @@ static int edge_hsm_client_store_create(const char* store_name, uint64_t auto_ge
             LOG_ERROR("Could not create HSM store");
             result = __FAILURE__;
         }
+        else if (store_protect_init(get_base_dir()) != 0)
+        {
+            LOG_ERROR("Could not set up encryption of the HSM store");
+            destroy_store(g_crypto_store);
+            g_crypto_store = NULL;
+            g_hsm_state = HSM_STATE_PROVISIONING_ERROR;
+            result = __FAILURE__;
+        }
         else
         {
             if (hsm_provision(auto_generated_ca_lifetime) != 0)
             {
+                store_protect_deinit();
                 destroy_store(g_crypto_store);
                 g_crypto_store = NULL;
                 g_hsm_state = HSM_STATE_PROVISIONING_ERROR;
@@ -2061,6 +2117,7 @@ static int edge_hsm_client_store_destroy(const char* store_name)
         if (g_store_ref_count == 0)
         {
             result = hsm_deprovision();
+            store_protect_deinit();
             destroy_store(g_crypto_store);
             g_hsm_state = HSM_STATE_UNPROVISIONED;
             g_crypto_store = NULL;
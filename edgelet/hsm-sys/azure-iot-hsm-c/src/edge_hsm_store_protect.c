@@ -0,0 +1,821 @@
+#include <errno.h>
+#include <limits.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
+    #include <windows.h>
+    #include <wincrypt.h>
+#else
+    #include <unistd.h>
+#endif
+
+#include <openssl/crypto.h>
+#include <openssl/evp.h>
+#include <openssl/rand.h>
+
+#include "azure_c_shared_utility/gballoc.h"
+#include "azure_c_shared_utility/strings.h"
+#if !(defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows)
+    #include "azure_utpm_c/tpm_codec.h"
+    #include "azure_utpm_c/Marshal_fp.h"
+#endif
+
+#include "hsm_err.h"
+#include "hsm_log.h"
+#include "hsm_store_protect.h"
+#include "hsm_utils.h"
+
+#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
+    static const char *SLASH = "\\";
+#else
+    static const char *SLASH = "/";
+#endif
+
+#define MASTER_KEY_SIZE     32
+#define ENVELOPE_IV_SIZE    12
+#define ENVELOPE_TAG_SIZE   16
+
+// Encrypted files start with this header, a magic followed by a version byte,
+// then the IV, the GCM tag and the ciphertext. The header is authenticated
+// along with the ciphertext.
+static const unsigned char ENVELOPE_HEADER[] = { 'I', 'E', 'H', 'S', 'M', 'E', 'N', 'C', 0x01 };
+#define ENVELOPE_MAGIC_SIZE (sizeof(ENVELOPE_HEADER) - 1)
+#define ENVELOPE_OVERHEAD   (sizeof(ENVELOPE_HEADER) + ENVELOPE_IV_SIZE + ENVELOPE_TAG_SIZE)
+
+// The sealed master key file starts with a byte telling what sealed it
+static const char *MASTER_KEY_FILE = "store_master_key.sealed";
+#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
+    static const unsigned char SEALED_BY_DPAPI = 'D';
+#else
+    static const unsigned char SEALED_BY_TPM = 'T';
+#endif
+
+// Only the directories of the store that hold keys are migrated, so that key
+// files provided by the user, such as the device CA key, are never rewritten
+static const char *PROTECTED_DIRS[] = { "cert_keys", "enc_keys" };
+
+static bool g_has_master_key = false;
+static unsigned char g_master_key[MASTER_KEY_SIZE];
+static STRING_HANDLE g_protected_dir_prefixes[sizeof(PROTECTED_DIRS) / sizeof(PROTECTED_DIRS[0])];
+
+//##############################################################################
+// File envelope
+//##############################################################################
+// Only the magic is checked here, so that truncated files and files of
+// another version are rejected rather than taken for plaintext
+static bool is_envelope(const unsigned char *data, size_t data_size)
+{
+    return (data_size >= ENVELOPE_MAGIC_SIZE) &&
+           (memcmp(data, ENVELOPE_HEADER, ENVELOPE_MAGIC_SIZE) == 0);
+}
+
+static unsigned char* seal_envelope
+(
+    const unsigned char *data,
+    size_t data_size,
+    size_t *envelope_size
+)
+{
+    unsigned char *result;
+    EVP_CIPHER_CTX *ctx = NULL;
+    int len;
+
+    if (data_size > (size_t)INT_MAX - ENVELOPE_OVERHEAD)
+    {
+        LOG_ERROR("Data is too large to encrypt");
+        result = NULL;
+    }
+    else if ((result = malloc(data_size + ENVELOPE_OVERHEAD)) == NULL)
+    {
+        LOG_ERROR("Could not allocate memory for encrypted data");
+    }
+    else
+    {
+        unsigned char *iv = result + sizeof(ENVELOPE_HEADER);
+        unsigned char *tag = iv + ENVELOPE_IV_SIZE;
+        unsigned char *ciphertext = tag + ENVELOPE_TAG_SIZE;
+
+        memcpy(result, ENVELOPE_HEADER, sizeof(ENVELOPE_HEADER));
+        if (RAND_bytes(iv, ENVELOPE_IV_SIZE) != 1)
+        {
+            LOG_ERROR("Could not generate IV");
+            FREEIF(result);
+        }
+        else if (((ctx = EVP_CIPHER_CTX_new()) == NULL) ||
+                 (EVP_EncryptInit_ex(ctx, EVP_aes_256_gcm(), NULL, NULL, NULL) != 1) ||
+                 (EVP_CIPHER_CTX_ctrl(ctx, EVP_CTRL_GCM_SET_IVLEN, ENVELOPE_IV_SIZE, NULL) != 1) ||
+                 (EVP_EncryptInit_ex(ctx, NULL, NULL, g_master_key, iv) != 1) ||
+                 (EVP_EncryptUpdate(ctx, NULL, &len, ENVELOPE_HEADER, (int)sizeof(ENVELOPE_HEADER)) != 1) ||
+                 (EVP_EncryptUpdate(ctx, ciphertext, &len, data, (int)data_size) != 1) ||
+                 (EVP_EncryptFinal_ex(ctx, ciphertext + len, &len) != 1) ||
+                 (EVP_CIPHER_CTX_ctrl(ctx, EVP_CTRL_GCM_GET_TAG, ENVELOPE_TAG_SIZE, tag) != 1))
+        {
+            LOG_ERROR("Could not encrypt data");
+            FREEIF(result);
+        }
+        else
+        {
+            *envelope_size = data_size + ENVELOPE_OVERHEAD;
+        }
+    }
+
+    if (ctx != NULL)
+    {
+        EVP_CIPHER_CTX_free(ctx);
+    }
+
+    return result;
+}
+
+static unsigned char* open_envelope
+(
+    const unsigned char *envelope,
+    size_t envelope_size,
+    size_t *data_size
+)
+{
+    unsigned char *result;
+    EVP_CIPHER_CTX *ctx = NULL;
+    const unsigned char *iv = envelope + sizeof(ENVELOPE_HEADER);
+    const unsigned char *tag = iv + ENVELOPE_IV_SIZE;
+    const unsigned char *ciphertext = tag + ENVELOPE_TAG_SIZE;
+    size_t ciphertext_size = (envelope_size >= ENVELOPE_OVERHEAD) ? envelope_size - ENVELOPE_OVERHEAD : 0;
+    int len;
+
+    if (envelope_size < ENVELOPE_OVERHEAD)
+    {
+        LOG_ERROR("Encrypted data is truncated");
+        result = NULL;
+    }
+    else if (memcmp(envelope, ENVELOPE_HEADER, sizeof(ENVELOPE_HEADER)) != 0)
+    {
+        LOG_ERROR("Encrypted data has an unsupported version %u", (unsigned int)envelope[ENVELOPE_MAGIC_SIZE]);
+        result = NULL;
+    }
+    else if (envelope_size > (size_t)INT_MAX)
+    {
+        LOG_ERROR("Encrypted data is too large to decrypt");
+        result = NULL;
+    }
+    // allocate at least one byte so that empty files still get a buffer
+    else if ((result = malloc(ciphertext_size + 1)) == NULL)
+    {
+        LOG_ERROR("Could not allocate memory for decrypted data");
+    }
+    else if (((ctx = EVP_CIPHER_CTX_new()) == NULL) ||
+             (EVP_DecryptInit_ex(ctx, EVP_aes_256_gcm(), NULL, NULL, NULL) != 1) ||
+             (EVP_CIPHER_CTX_ctrl(ctx, EVP_CTRL_GCM_SET_IVLEN, ENVELOPE_IV_SIZE, NULL) != 1) ||
+             (EVP_DecryptInit_ex(ctx, NULL, NULL, g_master_key, iv) != 1) ||
+             (EVP_DecryptUpdate(ctx, NULL, &len, ENVELOPE_HEADER, (int)sizeof(ENVELOPE_HEADER)) != 1) ||
+             (EVP_DecryptUpdate(ctx, result, &len, ciphertext, (int)ciphertext_size) != 1) ||
+             (EVP_CIPHER_CTX_ctrl(ctx, EVP_CTRL_GCM_SET_TAG, ENVELOPE_TAG_SIZE, (void*)tag) != 1) ||
+             (EVP_DecryptFinal_ex(ctx, result + len, &len) != 1))
+    {
+        LOG_ERROR("Could not decrypt data. It was not encrypted with the master key of this store or it was tampered with.");
+        OPENSSL_cleanse(result, ciphertext_size);
+        FREEIF(result);
+    }
+    else
+    {
+        *data_size = ciphertext_size;
+    }
+
+    if (ctx != NULL)
+    {
+        EVP_CIPHER_CTX_free(ctx);
+    }
+
+    return result;
+}
+
+// Writes a file next to the one it replaces first, so that a crash halfway
+// through never leaves a key file that is neither the old nor the new one
+static int replace_file(const char *file_name, const unsigned char *data, size_t data_size)
+{
+    int result;
+    STRING_HANDLE temp_file;
+
+    if ((temp_file = STRING_construct_sprintf("%s.tmp", file_name)) == NULL)
+    {
+        LOG_ERROR("Could not construct temporary file path for %s", file_name);
+        result = __FAILURE__;
+    }
+    else
+    {
+        if (write_buffer_to_file(STRING_c_str(temp_file), data, data_size, true) != 0)
+        {
+            LOG_ERROR("Could not write temporary file for %s", file_name);
+            result = __FAILURE__;
+        }
+#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
+        else if (!MoveFileExA(STRING_c_str(temp_file), file_name, MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH))
+        {
+            LOG_ERROR("Could not replace file %s. GetLastError=%08x", file_name, GetLastError());
+            (void)delete_file(STRING_c_str(temp_file));
+            result = __FAILURE__;
+        }
+#else
+        else if (rename(STRING_c_str(temp_file), file_name) != 0)
+        {
+            LOG_ERROR("Could not replace file %s. Errno: %s.", file_name, strerror(errno));
+            (void)delete_file(STRING_c_str(temp_file));
+            result = __FAILURE__;
+        }
+#endif
+        else
+        {
+            result = 0;
+        }
+        STRING_delete(temp_file);
+    }
+
+    return result;
+}
+
+static bool is_protected_file(const char *file_name)
+{
+    bool result = false;
+    size_t index;
+
+    for (index = 0; index < sizeof(g_protected_dir_prefixes) / sizeof(g_protected_dir_prefixes[0]); index++)
+    {
+        const char *prefix;
+        if ((g_protected_dir_prefixes[index] != NULL) &&
+            ((prefix = STRING_c_str(g_protected_dir_prefixes[index])) != NULL) &&
+            (strncmp(file_name, prefix, strlen(prefix)) == 0))
+        {
+            result = true;
+            break;
+        }
+    }
+
+    return result;
+}
+
+static void migrate_file(const char *file_name, const unsigned char *data, size_t data_size)
+{
+    size_t envelope_size = 0;
+    unsigned char *envelope;
+
+    if ((envelope = seal_envelope(data, data_size, &envelope_size)) == NULL)
+    {
+        LOG_ERROR("Could not encrypt %s, it stays unencrypted", file_name);
+    }
+    else
+    {
+        if (replace_file(file_name, envelope, envelope_size) != 0)
+        {
+            LOG_ERROR("Could not encrypt %s, it stays unencrypted", file_name);
+        }
+        else
+        {
+            LOG_INFO("Encrypted key file %s", file_name);
+        }
+        free(envelope);
+    }
+}
+
+//##############################################################################
+// Master key sealing
+//##############################################################################
+#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
+
+// DPAPI ties the master key to the account of the daemon on this machine
+static bool can_seal(void)
+{
+    return true;
+}
+
+static unsigned char* seal_master_key(const unsigned char *master_key, size_t *sealed_size)
+{
+    unsigned char *result;
+    DATA_BLOB plaintext, sealed;
+
+    plaintext.pbData = (BYTE*)master_key;
+    plaintext.cbData = MASTER_KEY_SIZE;
+    if (!CryptProtectData(&plaintext, L"iotedge hsm store", NULL, NULL, NULL, CRYPTPROTECT_UI_FORBIDDEN, &sealed))
+    {
+        LOG_ERROR("Could not seal master key with DPAPI. GetLastError=%08x", GetLastError());
+        result = NULL;
+    }
+    else
+    {
+        if ((result = malloc(sealed.cbData + 1)) == NULL)
+        {
+            LOG_ERROR("Could not allocate memory for sealed master key");
+        }
+        else
+        {
+            result[0] = SEALED_BY_DPAPI;
+            memcpy(result + 1, sealed.pbData, sealed.cbData);
+            *sealed_size = sealed.cbData + 1;
+        }
+        LocalFree(sealed.pbData);
+    }
+
+    return result;
+}
+
+static int unseal_master_key(const unsigned char *sealed_key, size_t sealed_size, unsigned char *master_key)
+{
+    int result;
+    DATA_BLOB sealed, plaintext;
+
+    sealed.pbData = (BYTE*)sealed_key + 1;
+    sealed.cbData = (DWORD)(sealed_size - 1);
+    if (sealed_key[0] != SEALED_BY_DPAPI)
+    {
+        LOG_ERROR("Master key was not sealed with DPAPI");
+        result = __FAILURE__;
+    }
+    else if (!CryptUnprotectData(&sealed, NULL, NULL, NULL, NULL, CRYPTPROTECT_UI_FORBIDDEN, &plaintext))
+    {
+        LOG_ERROR("Could not unseal master key with DPAPI. GetLastError=%08x", GetLastError());
+        result = __FAILURE__;
+    }
+    else
+    {
+        if (plaintext.cbData != MASTER_KEY_SIZE)
+        {
+            LOG_ERROR("Unsealed master key has an invalid size %u", plaintext.cbData);
+            result = __FAILURE__;
+        }
+        else
+        {
+            memcpy(master_key, plaintext.pbData, MASTER_KEY_SIZE);
+            result = 0;
+        }
+        SecureZeroMemory(plaintext.pbData, plaintext.cbData);
+        LocalFree(plaintext.pbData);
+    }
+
+    return result;
+}
+
+#else
+
+// The master key is the HMAC of a random salt with a key that only the TPM
+// holds. The key is created under the storage root key, so its blobs on disk
+// are useless without the TPM they were created with.
+static TPM2B_AUTH      NullAuth = { .t = {0,  {0}} };
+static TSS_SESSION     NullPwSession;
+static const UINT32 TPM_20_SRK_HANDLE = HR_PERSISTENT | 0x00000001;
+
+#define TPM_BLOB_BUFFER_SIZE 1024
+
+static TPM2B_PUBLIC* get_srk_template(void)
+{
+    static TPM2B_PUBLIC srk_template = { 0,     // size will be computed during marshaling
+    {
+        TPM_ALG_RSA,                            // TPMI_ALG_PUBLIC      type
+        TPM_ALG_SHA256,                         // TPMI_ALG_HASH        nameAlg
+        { 0 },                                  // TPMA_OBJECT  objectAttributes (set below)
+        { .t = {0, {0}} },                      // TPM2B_DIGEST         authPolicy
+        { .rsaDetail = {{0}, {0}, 0, 0} },      // TPMU_PUBLIC_PARMS    parameters (set below)
+        { .sym.b = {0} }                        // TPMU_PUBLIC_ID       unique
+    } };
+    srk_template.publicArea.objectAttributes = ToTpmaObject(
+        Restricted | Decrypt | FixedTPM | FixedParent | NoDA | UserWithAuth | SensitiveDataOrigin);
+    srk_template.publicArea.parameters.rsaDetail.symmetric.algorithm = TPM_ALG_AES;
+    srk_template.publicArea.parameters.rsaDetail.symmetric.keyBits.sym = 128;
+    srk_template.publicArea.parameters.rsaDetail.symmetric.mode.sym = TPM_ALG_CFB;
+    srk_template.publicArea.parameters.rsaDetail.scheme.scheme = TPM_ALG_NULL;
+    srk_template.publicArea.parameters.rsaDetail.keyBits = 2048;
+    return &srk_template;
+}
+
+static TPM2B_PUBLIC* get_hmac_key_template(void)
+{
+    static TPM2B_PUBLIC hmac_template = { 0,    // size will be computed during marshaling
+    {
+        TPM_ALG_KEYEDHASH,                      // TPMI_ALG_PUBLIC      type
+        TPM_ALG_SHA256,                         // TPMI_ALG_HASH        nameAlg
+        { 0 },                                  // TPMA_OBJECT  objectAttributes (set below)
+        { .t = {0, {0}} },                      // TPM2B_DIGEST         authPolicy
+        { .keyedHashDetail = {{0}} },           // TPMU_PUBLIC_PARMS    parameters (set below)
+        { .keyedHash.b = {0} }                  // TPMU_PUBLIC_ID       unique
+    } };
+    hmac_template.publicArea.objectAttributes = ToTpmaObject(
+        Sign | FixedTPM | FixedParent | NoDA | UserWithAuth);
+    hmac_template.publicArea.parameters.keyedHashDetail.scheme.scheme = TPM_ALG_HMAC;
+    hmac_template.publicArea.parameters.keyedHashDetail.scheme.details.hmac.hashAlg = TPM_ALG_SHA256;
+    return &hmac_template;
+}
+
+static bool can_seal(void)
+{
+    char *tcti = NULL;
+    bool result;
+
+    if ((hsm_get_env("IOTEDGE_TPM_TCTI", &tcti) == 0) && (tcti != NULL))
+    {
+        result = true;
+    }
+    else
+    {
+        result = (access("/dev/tpmrm0", R_OK | W_OK) == 0) ||
+                 (access("/dev/tpm0", R_OK | W_OK) == 0);
+    }
+    free(tcti);
+
+    return result;
+}
+
+static int open_tpm(TSS_DEVICE *tpm_device)
+{
+    int result;
+    TPM2B_PUBLIC srk_pub;
+
+    memset(&srk_pub, 0, sizeof(srk_pub));
+    if (TSS_CreatePwAuthSession(&NullAuth, &NullPwSession) != TPM_RC_SUCCESS)
+    {
+        LOG_ERROR("Failure calling TSS_CreatePwAuthSession");
+        result = __FAILURE__;
+    }
+    else if (Initialize_TPM_Codec(tpm_device) != TPM_RC_SUCCESS)
+    {
+        LOG_ERROR("Failure initializing TPM Codec");
+        result = __FAILURE__;
+    }
+    else if (TSS_CreatePersistentKey(tpm_device, TPM_20_SRK_HANDLE, &NullPwSession, TPM_RH_OWNER, get_srk_template(), &srk_pub) == 0)
+    {
+        LOG_ERROR("Failure creating persistent key for Storage Root key");
+        Deinit_TPM_Codec(tpm_device);
+        result = __FAILURE__;
+    }
+    else
+    {
+        result = 0;
+    }
+
+    return result;
+}
+
+static int tpm_hmac
+(
+    TSS_DEVICE *tpm_device,
+    TPM2B_PUBLIC *hmac_pub,
+    TPM2B_PRIVATE *hmac_priv,
+    const unsigned char *salt,
+    unsigned char *master_key
+)
+{
+    int result;
+    TPM_HANDLE hmac_handle = 0;
+    TPM2B_MAX_BUFFER buffer;
+    TPM2B_DIGEST digest;
+
+    memset(&digest, 0, sizeof(digest));
+    buffer.t.size = MASTER_KEY_SIZE;
+    memcpy(buffer.t.buffer, salt, MASTER_KEY_SIZE);
+
+    if (TPM2_Load(tpm_device, &NullPwSession, TPM_20_SRK_HANDLE, hmac_priv, hmac_pub, &hmac_handle, NULL) != TPM_RC_SUCCESS)
+    {
+        LOG_ERROR("Could not load master key into the TPM");
+        result = __FAILURE__;
+    }
+    else
+    {
+        if (TPM2_HMAC(tpm_device, &NullPwSession, hmac_handle, &buffer, TPM_ALG_SHA256, &digest) != TPM_RC_SUCCESS)
+        {
+            LOG_ERROR("Could not compute master key with the TPM");
+            result = __FAILURE__;
+        }
+        else if (digest.t.size != MASTER_KEY_SIZE)
+        {
+            LOG_ERROR("TPM computed a master key of invalid size %u", digest.t.size);
+            result = __FAILURE__;
+        }
+        else
+        {
+            memcpy(master_key, digest.t.buffer, MASTER_KEY_SIZE);
+            result = 0;
+        }
+        OPENSSL_cleanse(&digest, sizeof(digest));
+        (void)TPM2_FlushContext(tpm_device, hmac_handle);
+    }
+
+    return result;
+}
+
+// The sealed file holds the salt and the marshaled public and private parts
+// of the HMAC key, and the master key comes out of the TPM rather than being
+// stored, so master_key is an output here as well
+static unsigned char* seal_master_key(unsigned char *master_key, size_t *sealed_size)
+{
+    unsigned char *result = NULL;
+    TSS_DEVICE tpm_device;
+    TPM2B_SENSITIVE_CREATE sen_create;
+    TPM2B_PUBLIC hmac_pub;
+    TPM2B_PRIVATE hmac_priv;
+    unsigned char salt[MASTER_KEY_SIZE];
+
+    memset(&tpm_device, 0, sizeof(tpm_device));
+    memset(&sen_create, 0, sizeof(sen_create));
+    memset(&hmac_pub, 0, sizeof(hmac_pub));
+    memset(&hmac_priv, 0, sizeof(hmac_priv));
+
+    if ((RAND_bytes(salt, MASTER_KEY_SIZE) != 1) ||
+        (RAND_bytes(sen_create.sensitive.data.t.buffer, MASTER_KEY_SIZE) != 1))
+    {
+        LOG_ERROR("Could not generate master key");
+    }
+    else if (open_tpm(&tpm_device) != 0)
+    {
+        LOG_ERROR("Could not open the TPM to seal the master key");
+    }
+    else
+    {
+        sen_create.sensitive.data.t.size = MASTER_KEY_SIZE;
+        if (TSS_Create(&tpm_device, &NullPwSession, TPM_20_SRK_HANDLE, &sen_create, get_hmac_key_template(), &hmac_priv, &hmac_pub) != TPM_RC_SUCCESS)
+        {
+            LOG_ERROR("Could not create master key in the TPM");
+        }
+        else if (tpm_hmac(&tpm_device, &hmac_pub, &hmac_priv, salt, master_key) != 0)
+        {
+            LOG_ERROR("Could not compute master key");
+        }
+        else if ((result = malloc(1 + MASTER_KEY_SIZE + TPM_BLOB_BUFFER_SIZE)) == NULL)
+        {
+            LOG_ERROR("Could not allocate memory for sealed master key");
+        }
+        else
+        {
+            BYTE *pos = result + 1 + MASTER_KEY_SIZE;
+            INT32 remaining = TPM_BLOB_BUFFER_SIZE;
+
+            result[0] = SEALED_BY_TPM;
+            memcpy(result + 1, salt, MASTER_KEY_SIZE);
+            (void)TPM2B_PUBLIC_Marshal(&hmac_pub, &pos, &remaining);
+            (void)TPM2B_PRIVATE_Marshal(&hmac_priv, &pos, &remaining);
+            if (remaining <= 0)
+            {
+                LOG_ERROR("Could not marshal master key blobs");
+                OPENSSL_cleanse(master_key, MASTER_KEY_SIZE);
+                FREEIF(result);
+            }
+            else
+            {
+                *sealed_size = (size_t)(pos - result);
+            }
+        }
+        Deinit_TPM_Codec(&tpm_device);
+    }
+    OPENSSL_cleanse(&sen_create, sizeof(sen_create));
+
+    return result;
+}
+
+static int unseal_master_key(const unsigned char *sealed_key, size_t sealed_size, unsigned char *master_key)
+{
+    int result;
+    TSS_DEVICE tpm_device;
+    TPM2B_PUBLIC hmac_pub;
+    TPM2B_PRIVATE hmac_priv;
+    BYTE *pos = (BYTE*)sealed_key + 1 + MASTER_KEY_SIZE;
+    INT32 remaining = (INT32)(sealed_size - 1 - MASTER_KEY_SIZE);
+
+    memset(&tpm_device, 0, sizeof(tpm_device));
+    memset(&hmac_pub, 0, sizeof(hmac_pub));
+    memset(&hmac_priv, 0, sizeof(hmac_priv));
+
+    if ((sealed_size <= 1 + MASTER_KEY_SIZE) || (sealed_key[0] != SEALED_BY_TPM))
+    {
+        LOG_ERROR("Master key was not sealed with a TPM");
+        result = __FAILURE__;
+    }
+    else if ((TPM2B_PUBLIC_Unmarshal(&hmac_pub, &pos, &remaining, TRUE) != TPM_RC_SUCCESS) ||
+             (TPM2B_PRIVATE_Unmarshal(&hmac_priv, &pos, &remaining) != TPM_RC_SUCCESS))
+    {
+        LOG_ERROR("Sealed master key is malformed");
+        result = __FAILURE__;
+    }
+    else if (open_tpm(&tpm_device) != 0)
+    {
+        LOG_ERROR("Could not open the TPM to unseal the master key");
+        result = __FAILURE__;
+    }
+    else
+    {
+        result = tpm_hmac(&tpm_device, &hmac_pub, &hmac_priv, sealed_key + 1, master_key);
+        Deinit_TPM_Codec(&tpm_device);
+    }
+
+    return result;
+}
+
+#endif
+
+static int load_or_create_master_key(const char *master_key_file)
+{
+    int result;
+    unsigned char *sealed_key;
+    size_t sealed_size = 0;
+
+    if (is_file_valid(master_key_file))
+    {
+        if (((sealed_key = read_file_into_buffer(master_key_file, &sealed_size)) == NULL) ||
+            (sealed_size <= 1))
+        {
+            LOG_ERROR("Could not read sealed master key %s", master_key_file);
+            result = __FAILURE__;
+        }
+        else if (unseal_master_key(sealed_key, sealed_size, g_master_key) != 0)
+        {
+            LOG_ERROR("Could not unseal the master key of the store. "
+                      "Keys of the store can only be read on the device they were created on.");
+            result = __FAILURE__;
+        }
+        else
+        {
+            g_has_master_key = true;
+            result = 0;
+        }
+        free(sealed_key);
+    }
+    else if (!can_seal())
+    {
+        // there is no log level between info and error, and this should not
+        // go unnoticed on devices that expect their keys to be protected
+        LOG_ERROR("Warning: there is no TPM to seal a master key with, so keys of the store are NOT encrypted at rest");
+        result = 0;
+    }
+#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
+    else if (RAND_bytes(g_master_key, MASTER_KEY_SIZE) != 1)
+    {
+        LOG_ERROR("Could not generate master key");
+        result = __FAILURE__;
+    }
+#endif
+    else if ((sealed_key = seal_master_key(g_master_key, &sealed_size)) == NULL)
+    {
+        LOG_ERROR("Could not seal a master key for the store");
+        OPENSSL_cleanse(g_master_key, MASTER_KEY_SIZE);
+        result = __FAILURE__;
+    }
+    else
+    {
+        if (replace_file(master_key_file, sealed_key, sealed_size) != 0)
+        {
+            LOG_ERROR("Could not save sealed master key %s", master_key_file);
+            OPENSSL_cleanse(g_master_key, MASTER_KEY_SIZE);
+            result = __FAILURE__;
+        }
+        else
+        {
+            LOG_INFO("Created master key to encrypt keys of the store at rest");
+            g_has_master_key = true;
+            result = 0;
+        }
+        free(sealed_key);
+    }
+
+    return result;
+}
+
+//##############################################################################
+// Store protection API
+//##############################################################################
+int store_protect_init(const char *base_dir)
+{
+    int result;
+    STRING_HANDLE master_key_file;
+    size_t index;
+
+    if ((base_dir == NULL) || (strlen(base_dir) == 0))
+    {
+        LOG_ERROR("Invalid base dir parameter");
+        result = __FAILURE__;
+    }
+    else if (g_has_master_key)
+    {
+        result = 0;
+    }
+    else if ((master_key_file = STRING_construct_sprintf("%s%s%s", base_dir, SLASH, MASTER_KEY_FILE)) == NULL)
+    {
+        LOG_ERROR("Could not construct path to master key");
+        result = __FAILURE__;
+    }
+    else
+    {
+        result = 0;
+        for (index = 0; index < sizeof(PROTECTED_DIRS) / sizeof(PROTECTED_DIRS[0]); index++)
+        {
+            STRING_delete(g_protected_dir_prefixes[index]);
+            if ((g_protected_dir_prefixes[index] = STRING_construct_sprintf("%s%s%s%s", base_dir, SLASH, PROTECTED_DIRS[index], SLASH)) == NULL)
+            {
+                LOG_ERROR("Could not construct path to %s", PROTECTED_DIRS[index]);
+                result = __FAILURE__;
+            }
+        }
+
+        if ((result == 0) && (load_or_create_master_key(STRING_c_str(master_key_file)) != 0))
+        {
+            result = __FAILURE__;
+        }
+
+        if (result != 0)
+        {
+            store_protect_deinit();
+        }
+        STRING_delete(master_key_file);
+    }
+
+    return result;
+}
+
+void store_protect_deinit(void)
+{
+    size_t index;
+
+    OPENSSL_cleanse(g_master_key, MASTER_KEY_SIZE);
+    g_has_master_key = false;
+    for (index = 0; index < sizeof(g_protected_dir_prefixes) / sizeof(g_protected_dir_prefixes[0]); index++)
+    {
+        STRING_delete(g_protected_dir_prefixes[index]);
+        g_protected_dir_prefixes[index] = NULL;
+    }
+}
+
+bool store_protect_is_enabled(void)
+{
+    return g_has_master_key;
+}
+
+void* store_protect_read_file(const char *file_name, size_t *output_buffer_size)
+{
+    unsigned char *result;
+    size_t file_size = 0;
+
+    if (output_buffer_size != NULL)
+    {
+        *output_buffer_size = 0;
+    }
+
+    if ((result = read_file_into_buffer(file_name, &file_size)) == NULL)
+    {
+        LOG_DEBUG("Could not read file %s", file_name);
+    }
+    else if (is_envelope(result, file_size))
+    {
+        unsigned char *data;
+        size_t data_size = 0;
+
+        if (!g_has_master_key)
+        {
+            LOG_ERROR("File %s is encrypted but the store has no master key", file_name);
+            data = NULL;
+        }
+        else
+        {
+            data = open_envelope(result, file_size, &data_size);
+        }
+        free(result);
+        result = data;
+        file_size = data_size;
+    }
+    else if (g_has_master_key && is_protected_file(file_name))
+    {
+        // a store from before encryption at rest, or from a device without a
+        // master key until now
+        migrate_file(file_name, result, file_size);
+    }
+
+    if ((result != NULL) && (output_buffer_size != NULL))
+    {
+        *output_buffer_size = file_size;
+    }
+
+    return result;
+}
+
+int store_protect_write_file(const char *file_name, const unsigned char *data, size_t data_size)
+{
+    int result;
+
+    if (!g_has_master_key)
+    {
+        result = write_buffer_to_file(file_name, data, data_size, true);
+    }
+    else
+    {
+        size_t envelope_size = 0;
+        unsigned char *envelope;
+
+        if ((envelope = seal_envelope(data, data_size, &envelope_size)) == NULL)
+        {
+            LOG_ERROR("Could not encrypt %s", file_name);
+            result = __FAILURE__;
+        }
+        else
+        {
+            result = replace_file(file_name, envelope, envelope_size);
+            free(envelope);
+        }
+    }
+
+    return result;
+}
@@ -18,6 +18,7 @@
 #include <openssl/bio.h>
 #include <openssl/err.h>
 #include <openssl/ec.h>
+#include <openssl/engine.h>
 #include <openssl/pem.h>
 #include <openssl/rand.h>
 #include <openssl/x509.h>
@@ -28,9 +29,11 @@
 #include "azure_c_shared_utility/hmacsha256.h"
 #include "edge_openssl_common.h"
 
+#include "hsm_constants.h"
 #include "hsm_err.h"
 #include "hsm_key.h"
 #include "hsm_log.h"
+#include "hsm_store_protect.h"
 #include "hsm_utils.h"
 
 //#################################################################################################
@@ -50,6 +53,9 @@
 
 #define DEFAULT_EC_CURVE_NAME "secp256k1"
 
+// OpenSSL engine (libp11) through which keys in a PKCS#11 token are used
+#define PKCS11_ENGINE_ID "pkcs11"
+
 // openssl ASN1 time format defines
 #define ASN1_TIME_STRING_UTC_FORMAT 0x17
 #define ASN1_TIME_STRING_UTC_LEN 13
@@ -88,6 +94,12 @@ typedef struct CERT_KEY_TAG CERT_KEY;
 
 static void destroy_evp_key(EVP_PKEY *evp_key);
 
+//#################################################################################################
+// Globals
+//#################################################################################################
+
+static ENGINE *g_pkcs11_engine = NULL;
+
 //#################################################################################################
 // Utilities
 //#################################################################################################
@@ -645,69 +657,138 @@ static int write_certificate_file
     return result;
 }
 
-static EVP_PKEY* load_private_key_file(const char* key_file_name)
+static ENGINE* get_pkcs11_engine(void)
+{
+    if (g_pkcs11_engine == NULL)
+    {
+        ENGINE *engine;
+        char *lib_path = NULL;
+
+        if ((engine = ENGINE_by_id(PKCS11_ENGINE_ID)) == NULL)
+        {
+            LOG_ERROR("Could not load the OpenSSL %s engine", PKCS11_ENGINE_ID);
+        }
+        else
+        {
+            if (hsm_get_env(ENV_PKCS11_LIB_PATH, &lib_path) != 0)
+            {
+                LOG_ERROR("Failed to read env variable %s", ENV_PKCS11_LIB_PATH);
+            }
+            else if ((lib_path != NULL) &&
+                     (ENGINE_ctrl_cmd_string(engine, "MODULE_PATH", lib_path, 0) != 1))
+            {
+                LOG_ERROR("Could not configure the PKCS#11 library %s", lib_path);
+            }
+            else if (ENGINE_init(engine) != 1)
+            {
+                LOG_ERROR("Could not initialize the OpenSSL %s engine", PKCS11_ENGINE_ID);
+            }
+            else
+            {
+                // the engine stays initialized for as long as the process runs, so that
+                // keys loaded through it remain usable
+                g_pkcs11_engine = engine;
+            }
+            // ENGINE_init takes a reference of its own
+            ENGINE_free(engine);
+            free(lib_path);
+        }
+    }
+
+    return g_pkcs11_engine;
+}
+
+static EVP_PKEY* load_pkcs11_private_key(const char* key_uri)
 {
     EVP_PKEY* evp_key;
-    BIO* key_file = BIO_new_file(key_file_name, "rb");
-    if (key_file == NULL)
+    ENGINE* engine;
+
+    if ((engine = get_pkcs11_engine()) == NULL)
     {
-        LOG_ERROR("Failure to open key file %s", key_file_name);
+        LOG_ERROR("Could not load private key %s without a PKCS#11 engine", key_uri);
         evp_key = NULL;
     }
-    else
+    else if ((evp_key = ENGINE_load_private_key(engine, key_uri, NULL, NULL)) == NULL)
     {
-        evp_key = PEM_read_bio_PrivateKey(key_file, NULL, NULL, NULL);
-        if (evp_key == NULL)
-        {
-            LOG_ERROR("Failure PEM_read_bio_PrivateKey for %s", key_file_name);
-        }
-        BIO_free_all(key_file);
+        LOG_ERROR("Failure ENGINE_load_private_key for %s", key_uri);
     }
 
     return evp_key;
 }
 
-static int write_private_key_file(EVP_PKEY* evp_key, const char* key_file_name)
+static EVP_PKEY* load_private_key_file(const char* key_file_name)
 {
-    int result;
+    EVP_PKEY* evp_key;
 
-#if defined __WINDOWS__ || defined _WIN32 || defined _WIN64 || defined _Windows
-    BIO* key_file = BIO_new_file(key_file_name, "wb");
-    if (key_file == NULL)
+    if (is_pkcs11_uri(key_file_name))
     {
-        LOG_ERROR("Failure opening key file for writing for %s", key_file_name);
-        result = __FAILURE__;
+        evp_key = load_pkcs11_private_key(key_file_name);
     }
     else
     {
-        if (!PEM_write_bio_PrivateKey(key_file, evp_key, NULL, NULL, 0, NULL, NULL))
+        void *key_contents;
+        size_t key_size = 0;
+        BIO* key_bio;
+
+        // key files may be encrypted at rest, so they are read through the store
+        if ((key_contents = store_protect_read_file(key_file_name, &key_size)) == NULL)
         {
-            LOG_ERROR("Unable to write private key to file %s", key_file_name);
-            result = __FAILURE__;
+            LOG_ERROR("Failure to open key file %s", key_file_name);
+            evp_key = NULL;
         }
         else
         {
-            result = 0;
+            if ((key_size > INT_MAX) ||
+                ((key_bio = BIO_new_mem_buf(key_contents, (int)key_size)) == NULL))
+            {
+                LOG_ERROR("Failure creating BIO for key file %s", key_file_name);
+                evp_key = NULL;
+            }
+            else
+            {
+                evp_key = PEM_read_bio_PrivateKey(key_bio, NULL, NULL, NULL);
+                if (evp_key == NULL)
+                {
+                    LOG_ERROR("Failure PEM_read_bio_PrivateKey for %s", key_file_name);
+                }
+                BIO_free_all(key_bio);
+            }
+            OPENSSL_cleanse(key_contents, key_size);
+            free(key_contents);
         }
-        BIO_free_all(key_file);
     }
-#else
-    int fd = OPEN_HELPER(key_file_name);
-    if (fd == -1)
+
+    return evp_key;
+}
+
+static int write_private_key_file(EVP_PKEY* evp_key, const char* key_file_name)
+{
+    int result;
+    BIO* key_bio;
+
+    if ((key_bio = BIO_new(BIO_s_mem())) == NULL)
     {
-        LOG_ERROR("Failure opening key file for writing for %s. Errno %d, %s",
-                  key_file_name, errno, strerror(errno));
+        LOG_ERROR("Failure creating new BIO handle for %s", key_file_name);
         result = __FAILURE__;
     }
     else
     {
-        BIO *key_file;
-        if ((key_file = BIO_new_fd(fd, 0)) == NULL)
+        char* key_contents = NULL;
+        long key_size = 0;
+
+        if (!PEM_write_bio_PrivateKey(key_bio, evp_key, NULL, NULL, 0, NULL, NULL))
+        {
+            LOG_ERROR("Unable to write private key to file %s", key_file_name);
+            result = __FAILURE__;
+        }
+        else if (((key_size = BIO_get_mem_data(key_bio, &key_contents)) <= 0) ||
+                 (key_contents == NULL))
         {
-            LOG_ERROR("Failure creating new BIO handle for %s", key_file_name);
+            LOG_ERROR("Unable to get PEM of private key for %s", key_file_name);
             result = __FAILURE__;
         }
-        else if (!PEM_write_bio_PrivateKey(key_file, evp_key, NULL, NULL, 0, NULL, NULL))
+        // key files are encrypted at rest when the store has a master key
+        else if (store_protect_write_file(key_file_name, (unsigned char*)key_contents, (size_t)key_size) != 0)
         {
             LOG_ERROR("Unable to write private key to file %s", key_file_name);
             result = __FAILURE__;
@@ -716,10 +797,12 @@ static int write_private_key_file(EVP_PKEY* evp_key, const char* key_file_name)
         {
             result = 0;
         }
-        BIO_free_all(key_file);
-        (void)CLOSE_HELPER(fd);
+        if ((key_contents != NULL) && (key_size > 0))
+        {
+            OPENSSL_cleanse(key_contents, (size_t)key_size);
+        }
+        BIO_free_all(key_bio);
     }
-#endif
 
     return result;
 }
@@ -1293,7 +1376,23 @@ static int generate_cert_key
     EVP_PKEY* evp_key;
     *result_evp_key = NULL;
 
-    if ((evp_key = generate_evp_key(cert_type, issuer_certificate, key_props)) == NULL)
+    if (is_pkcs11_uri(key_file_name))
+    {
+        // keys in a PKCS#11 token are created ahead of time and never leave it,
+        // so the certificate is issued for the existing key
+        if ((evp_key = load_pkcs11_private_key(key_file_name)) == NULL)
+        {
+            LOG_ERROR("Error loading PKCS#11 key %s", key_file_name);
+            result = __FAILURE__;
+        }
+        else
+        {
+            LOG_DEBUG("Using PKCS#11 private key %s", key_file_name);
+            result = 0;
+            *result_evp_key = evp_key;
+        }
+    }
+    else if ((evp_key = generate_evp_key(cert_type, issuer_certificate, key_props)) == NULL)
     {
         LOG_ERROR("Error generating EVP key in %s", key_file_name);
         result = __FAILURE__;
@@ -11,6 +11,8 @@ extern const char* const ENV_TRUSTED_CA_CERTS_PATH;
 extern const char* const ENV_REGISTRATION_ID;
 extern const char* const ENV_DEVICE_ID_CERTIFICATE_PATH;
 extern const char* const ENV_DEVICE_ID_PRIVATE_KEY_PATH;
+extern const char* const ENV_WORKLOAD_CA_PK_URI;
+extern const char* const ENV_PKCS11_LIB_PATH;
 
 /* HSM directory name under IOTEDGE_HOMEDIR */
 extern const char* const DEFAULT_EDGE_HOME_DIR_UNIX;
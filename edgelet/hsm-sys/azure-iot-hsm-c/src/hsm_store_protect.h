@@ -0,0 +1,23 @@
+#ifndef HSM_STORE_PROTECT_H
+#define HSM_STORE_PROTECT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include "umock_c/umock_c_prod.h"
+
+// Keys of the store are kept in files encrypted with a master key, which is
+// itself sealed by the TPM on Linux and by DPAPI on Windows. Until
+// store_protect_init has found or created a master key, or when there is
+// nothing to seal it with, files are read and written as they are.
+MOCKABLE_FUNCTION(, int, store_protect_init, const char*, base_dir);
+MOCKABLE_FUNCTION(, void, store_protect_deinit);
+MOCKABLE_FUNCTION(, bool, store_protect_is_enabled);
+
+// Reads a key file of the store, decrypting it when it is encrypted. Files of
+// the store under base_dir that are still in plaintext are encrypted in place.
+MOCKABLE_FUNCTION(, void*, store_protect_read_file, const char*, file_name, size_t*, output_buffer_size);
+
+// Writes a key file of the store, encrypted when there is a master key.
+MOCKABLE_FUNCTION(, int, store_protect_write_file, const char*, file_name, const unsigned char*, data, size_t, data_size);
+
+#endif  //HSM_STORE_PROTECT_H
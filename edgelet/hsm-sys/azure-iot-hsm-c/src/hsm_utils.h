@@ -1,7 +1,9 @@
 #ifndef HSM_UTILS_H
 #define HSM_UTILS_H
 
+#include <stdbool.h>
 #include <stddef.h>
+#include <string.h>
 #include "umock_c/umock_c_prod.h"
 
 //##############################################################################
@@ -25,4 +27,14 @@ MOCKABLE_FUNCTION(, int, delete_file, const char*, file_name);
 MOCKABLE_FUNCTION(, int, make_dir, const char*, dir_path);
 MOCKABLE_FUNCTION(, int, hsm_get_env, const char*, key, char**, output);
 
+// Keys kept in a PKCS#11 token are referenced by their PKCS#11 URI (RFC 7512)
+// wherever a key file path is expected otherwise
+static inline bool is_pkcs11_uri(const char* key_uri)
+{
+    static const char PKCS11_URI_SCHEME[] = "pkcs11:";
+
+    return (key_uri != NULL) &&
+           (strncmp(key_uri, PKCS11_URI_SCHEME, sizeof(PKCS11_URI_SCHEME) - 1) == 0);
+}
+
 #endif  //HSM_UTILS_H
@@ -49,6 +49,7 @@ static void test_hook_gballoc_free(void* ptr)
 #include "certificate_info.h"
 #include "hsm_certificate_props.h"
 #include "hsm_key.h"
+#include "hsm_store_protect.h"
 #include "hsm_utils.h"
 
 MOCKABLE_FUNCTION(, CERT_INFO_HANDLE, certificate_info_create, const char*, certificate, const void*, private_key, size_t, priv_key_len, PRIVATE_KEY_TYPE, pk_type);
@@ -283,6 +284,16 @@ BEGIN_TEST_SUITE(edge_hsm_store_unittests)
 
         //REGISTER_GLOBAL_MOCK_HOOK(BUFFER_create, test_hook_BUFFER_create);
         REGISTER_GLOBAL_MOCK_FAIL_RETURN(BUFFER_create, NULL);
+
+        REGISTER_GLOBAL_MOCK_RETURN(store_protect_init, 0);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(store_protect_init, 1);
+
+        REGISTER_GLOBAL_MOCK_RETURN(store_protect_is_enabled, false);
+
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(store_protect_read_file, NULL);
+
+        REGISTER_GLOBAL_MOCK_RETURN(store_protect_write_file, 0);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(store_protect_write_file, 1);
     }
 
     TEST_SUITE_CLEANUP(TestClassCleanup)
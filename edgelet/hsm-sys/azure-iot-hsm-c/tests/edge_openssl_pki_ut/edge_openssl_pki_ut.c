@@ -77,6 +77,7 @@ static char *test_helper_strdup(const char *s);
 #define ENABLE_MOCKS
 #include "azure_c_shared_utility/gballoc.h"
 #include "edge_openssl_common.h"
+#include "hsm_store_protect.h"
 #include "hsm_utils.h"
 
 MOCKABLE_FUNCTION(, EVP_PKEY*, EVP_PKEY_new);
@@ -113,15 +114,21 @@ MOCKABLE_FUNCTION(, int, EC_GROUP_get_curve_name, const EC_GROUP*, group);
     MOCKABLE_FUNCTION(, int, X509_get_ext_by_NID, const X509*, x, int, nid, int, lastpos);
     MOCKABLE_FUNCTION(, EVP_MD_CTX*, EVP_MD_CTX_new);
     MOCKABLE_FUNCTION(, void, EVP_MD_CTX_free, EVP_MD_CTX*, ctx);
+    MOCKABLE_FUNCTION(, const BIO_METHOD*, BIO_s_mem);
+    MOCKABLE_FUNCTION(, BIO*, BIO_new, const BIO_METHOD*, type);
 #else
     MOCKABLE_FUNCTION(, int, EVP_PKEY_bits, EVP_PKEY*, pkey);
     MOCKABLE_FUNCTION(, X509_NAME*, X509_get_subject_name, X509*, a);
     MOCKABLE_FUNCTION(, int, X509_get_ext_by_NID, X509*, x, int, nid, int, lastpos);
     MOCKABLE_FUNCTION(, EVP_MD_CTX*, EVP_MD_CTX_create);
     MOCKABLE_FUNCTION(, void, EVP_MD_CTX_destroy, EVP_MD_CTX*, ctx);
+    MOCKABLE_FUNCTION(, BIO_METHOD*, BIO_s_mem);
+    MOCKABLE_FUNCTION(, BIO*, BIO_new, BIO_METHOD*, type);
 #endif
 
 MOCKABLE_FUNCTION(, BIO*, BIO_new_file, const char*, filename, const char*, mode);
+MOCKABLE_FUNCTION(, BIO*, BIO_new_mem_buf, const void*, buf, int, len);
+MOCKABLE_FUNCTION(, long, BIO_ctrl, BIO*, bp, int, cmd, long, larg, void*, parg);
 MOCKABLE_FUNCTION(, int, PEM_X509_INFO_write_bio, BIO*, bp, X509_INFO*, xi, EVP_CIPHER*, enc, unsigned char*, kstr, int, klen, pem_password_cb*, cb, void*, u);
 MOCKABLE_FUNCTION(, int, BIO_write, BIO*, b, const void*, in, int, inl);
 MOCKABLE_FUNCTION(, void, BIO_free_all, BIO*, bio);
@@ -287,6 +294,7 @@ static TEST_MUTEX_HANDLE g_dllByDll;
 #define TEST_ENGINE_IMPL (ENGINE*)0x2035
 #define TEST_EVP_PKEY_CTX (EVP_PKEY_CTX*)0x2036
 #define HMAC_SHA256_SIZE 256
+#define TEST_BIO_METHOD (BIO_METHOD*)0x2037
 
 typedef struct VERIFY_CERT_TEST_PARAMS_TAG
 {
@@ -724,6 +732,88 @@ static void* test_hook_read_file_into_buffer
     return data;
 }
 
+static void* test_hook_store_protect_read_file
+(
+    const char *file_name,
+    size_t *output_buffer_size
+)
+{
+    (void)file_name;
+    size_t test_data_len = strlen(TEST_ISSUER_KEY_DATA);
+    size_t test_data_size = test_data_len + 1;
+    void *data = test_hook_gballoc_malloc(test_data_size);
+    ASSERT_IS_NOT_NULL(data, "Line:" TOSTRING(__LINE__));
+    memset(data, 0, test_data_size);
+    memcpy(data, TEST_ISSUER_KEY_DATA, test_data_len);
+    if (output_buffer_size) *output_buffer_size = test_data_size;
+    return data;
+}
+
+static int test_hook_store_protect_write_file
+(
+    const char *file_name,
+    const unsigned char *data,
+    size_t data_size
+)
+{
+    (void)file_name;
+    (void)data;
+    (void)data_size;
+
+    return 0;
+}
+
+#if ((OPENSSL_VERSION_NUMBER & 0xFFF00000L) >= 0x10100000L)
+static BIO* test_hook_BIO_new(const BIO_METHOD* type)
+#else
+static BIO* test_hook_BIO_new(BIO_METHOD* type)
+#endif
+{
+    (void)type;
+
+    return TEST_BIO_WRITE_KEY;
+}
+
+#if ((OPENSSL_VERSION_NUMBER & 0xFFF00000L) >= 0x10100000L)
+static const BIO_METHOD* test_hook_BIO_s_mem(void)
+#else
+static BIO_METHOD* test_hook_BIO_s_mem(void)
+#endif
+{
+    return TEST_BIO_METHOD;
+}
+
+static BIO* test_hook_BIO_new_mem_buf(const void *buf, int len)
+{
+    (void)buf;
+    (void)len;
+
+    return TEST_BIO;
+}
+
+// the PEM of a key written to a memory BIO is scrubbed after it is
+// written to the store, so it is handed out in a writable buffer
+static char g_test_key_pem[] = TEST_ISSUER_KEY_DATA;
+
+static long test_hook_BIO_ctrl(BIO *bp, int cmd, long larg, void *parg)
+{
+    long result;
+    (void)bp;
+    (void)larg;
+
+    if ((cmd == BIO_CTRL_INFO) && (parg != NULL))
+    {
+        *(char**)parg = g_test_key_pem;
+        result = (long)(sizeof(g_test_key_pem) - 1);
+    }
+    else
+    {
+        result = 0;
+    }
+
+    return result;
+}
+
 static BIO* test_hook_BIO_new_fd(int fd,int close_flag)
 {
     (void)fd;
@@ -1411,7 +1501,11 @@ static void test_helper_cert_create_with_subject
         ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
         i++;
 
-        STRICT_EXPECTED_CALL(BIO_new_file(TEST_ISSUER_KEY_FILE, "rb"));
+        STRICT_EXPECTED_CALL(store_protect_read_file(TEST_ISSUER_KEY_FILE, IGNORED_PTR_ARG));
+        ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+        failed_function_list[i++] = 1;
+
+        EXPECTED_CALL(BIO_new_mem_buf(IGNORED_PTR_ARG, IGNORED_NUM_ARG));
         ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
         failed_function_list[i++] = 1;
 
@@ -1423,6 +1517,10 @@ static void test_helper_cert_create_with_subject
         ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
         i++;
 
+        EXPECTED_CALL(gballoc_free(IGNORED_PTR_ARG));
+        ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+        i++;
+
         STRICT_EXPECTED_CALL(X509_get_pubkey(TEST_ISSUER_X509)).SetReturn(TEST_ISSUER_PUB_KEY);
         ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
         failed_function_list[i++] = 1;
@@ -1448,38 +1546,30 @@ static void test_helper_cert_create_with_subject
         i++;
     }
 
-    if (test_helper_is_windows())
-    {
-        STRICT_EXPECTED_CALL(BIO_new_file(TEST_KEY_FILE, "wb")).SetReturn(TEST_BIO_WRITE_KEY);
-        ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
-        failed_function_list[i++] = 1;
-    }
-    else
-    {
-        STRICT_EXPECTED_CALL(mocked_OPEN(TEST_KEY_FILE, EXPECTED_CREATE_FLAGS, EXPECTED_MODE_FLAGS)).SetReturn(TEST_WRITE_PRIVATE_KEY_FD);
-        ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
-        failed_function_list[i++] = 1;
+    EXPECTED_CALL(BIO_s_mem());
+    ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+    i++;
 
-        STRICT_EXPECTED_CALL(BIO_new_fd(TEST_WRITE_PRIVATE_KEY_FD, 0)).SetReturn(TEST_BIO_WRITE_KEY);
-        ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
-        failed_function_list[i++] = 1;
-    }
+    STRICT_EXPECTED_CALL(BIO_new(TEST_BIO_METHOD)).SetReturn(TEST_BIO_WRITE_KEY);
+    ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+    failed_function_list[i++] = 1;
 
     STRICT_EXPECTED_CALL(PEM_write_bio_PrivateKey(TEST_BIO_WRITE_KEY, TEST_EVP_KEY, NULL, NULL, 0, NULL, NULL));
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     failed_function_list[i++] = 1;
 
+    STRICT_EXPECTED_CALL(BIO_ctrl(TEST_BIO_WRITE_KEY, BIO_CTRL_INFO, 0, IGNORED_PTR_ARG));
+    ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+    failed_function_list[i++] = 1;
+
+    STRICT_EXPECTED_CALL(store_protect_write_file(TEST_KEY_FILE, IGNORED_PTR_ARG, IGNORED_NUM_ARG));
+    ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+    failed_function_list[i++] = 1;
+
     STRICT_EXPECTED_CALL(BIO_free_all(TEST_BIO_WRITE_KEY));
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     i++;
 
-    if (!test_helper_is_windows())
-    {
-        STRICT_EXPECTED_CALL(mocked_CLOSE(TEST_WRITE_PRIVATE_KEY_FD));
-        ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
-        i++;
-    }
-
     STRICT_EXPECTED_CALL(X509_new());
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     failed_function_list[i++] = 1;
@@ -2070,7 +2160,11 @@ static void test_helper_create_cert_key
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     i++;
 
-    STRICT_EXPECTED_CALL(BIO_new_file(TEST_KEY_FILE, "rb"));
+    STRICT_EXPECTED_CALL(store_protect_read_file(TEST_KEY_FILE, IGNORED_PTR_ARG));
+    ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+    failed_function_list[i++] = 1;
+
+    EXPECTED_CALL(BIO_new_mem_buf(IGNORED_PTR_ARG, IGNORED_NUM_ARG));
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     failed_function_list[i++] = 1;
 
@@ -2082,6 +2176,10 @@ static void test_helper_create_cert_key
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     i++;
 
+    EXPECTED_CALL(gballoc_free(IGNORED_PTR_ARG));
+    ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
+    i++;
+
     EXPECTED_CALL(gballoc_malloc(IGNORED_NUM_ARG));
     ASSERT_IS_TRUE((i < failed_function_size), "Line:" TOSTRING(__LINE__));
     failed_function_list[i++] = 1;
@@ -2239,6 +2337,23 @@ BEGIN_TEST_SUITE(edge_openssl_pki_unittests)
         REGISTER_GLOBAL_MOCK_HOOK(BIO_new_fd, test_hook_BIO_new_fd);
         REGISTER_GLOBAL_MOCK_FAIL_RETURN(BIO_new_fd, NULL);
 
+        REGISTER_GLOBAL_MOCK_HOOK(BIO_s_mem, test_hook_BIO_s_mem);
+
+        REGISTER_GLOBAL_MOCK_HOOK(BIO_new, test_hook_BIO_new);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(BIO_new, NULL);
+
+        REGISTER_GLOBAL_MOCK_HOOK(BIO_new_mem_buf, test_hook_BIO_new_mem_buf);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(BIO_new_mem_buf, NULL);
+
+        REGISTER_GLOBAL_MOCK_HOOK(BIO_ctrl, test_hook_BIO_ctrl);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(BIO_ctrl, 0);
+
+        REGISTER_GLOBAL_MOCK_HOOK(store_protect_read_file, test_hook_store_protect_read_file);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(store_protect_read_file, NULL);
+
+        REGISTER_GLOBAL_MOCK_HOOK(store_protect_write_file, test_hook_store_protect_write_file);
+        REGISTER_GLOBAL_MOCK_FAIL_RETURN(store_protect_write_file, 1);
+
         REGISTER_GLOBAL_MOCK_HOOK(PEM_X509_INFO_write_bio, test_hook_PEM_X509_INFO_write_bio);
         REGISTER_GLOBAL_MOCK_FAIL_RETURN(PEM_X509_INFO_write_bio, 0);
 
@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::net::IpAddr;
+
+use failure::{self, Context, ResultExt};
+
+use crate::check::{checker::Checker, Check, CheckResult};
+
+/// The logging drivers the container engine can read module logs back from,
+/// which `iotedge logs` and the log upload of edgeAgent depend on.
+const READABLE_LOG_DRIVERS: &[&str] = &["json-file", "local"];
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct ContainerEngineConfig {
+    info: Option<DockerInfo>,
+    dns: Option<Vec<String>>,
+    host_nameservers: Option<Vec<String>>,
+}
+
+impl Checker for ContainerEngineConfig {
+    fn id(&self) -> &'static str {
+        "container-engine-config"
+    }
+    fn description(&self) -> &'static str {
+        "production readiness: container engine configuration"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl ContainerEngineConfig {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let docker_host_arg = if let Some(docker_host_arg) = &check.docker_host_arg {
+            docker_host_arg
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        let output = super::docker(docker_host_arg, &["info", "--format", "{{json .}}"])
+            .map_err(|(_, err)| err)
+            .context("Could not query the container engine configuration")?;
+        let info: DockerInfo = serde_json::from_slice(&output)
+            .context("Could not parse the container engine configuration")?;
+        self.info = Some(info.clone());
+
+        // The DNS servers of the engine are only in its config file, which
+        // the engine may run without.
+        self.dns = File::open(&check.container_engine_config_path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, DaemonConfig>(file).ok())
+            .and_then(|daemon_config| daemon_config.dns);
+        self.host_nameservers = host_nameservers();
+
+        let problems = audit(
+            &info,
+            self.dns.as_ref().map(AsRef::as_ref),
+            self.host_nameservers.as_ref().map(AsRef::as_ref),
+        );
+        if problems.is_empty() {
+            return Ok(CheckResult::Ok);
+        }
+
+        Ok(CheckResult::Warning(
+            Context::new(format!(
+                "Container engine is configured in a way that is known to break IoT Edge:\n{}",
+                problems.join("\n"),
+            ))
+            .into(),
+        ))
+    }
+}
+
+/// The configuration of the engine that modules can't override in their
+/// create options, or that breaks the daemon and the CLI.
+///
+/// Whether live restore is enabled is reported, but not flagged: it only
+/// decides whether modules keep running while the engine restarts.
+fn audit(
+    info: &DockerInfo,
+    dns: Option<&[String]>,
+    host_nameservers: Option<&[String]>,
+) -> Vec<String> {
+    let mut problems = vec![];
+
+    if let Some(logging_driver) = &info.logging_driver {
+        if !READABLE_LOG_DRIVERS.contains(&&**logging_driver) {
+            problems.push(format!(
+                "- The default logging driver is {:?}, from which the container engine can't read \
+                 module logs back. `iotedge logs` and the log upload of edgeAgent won't work for \
+                 modules that don't set a logging driver. Use \"json-file\" or \"local\" instead.",
+                logging_driver,
+            ));
+        }
+    }
+
+    match info.driver.as_ref().map(AsRef::as_ref) {
+        Some("vfs") => problems.push(
+            "- The storage driver is \"vfs\", which copies all the layers of an image for every \
+             module and quickly runs out of disk space. Use \"overlay2\" instead."
+                .to_owned(),
+        ),
+        Some("devicemapper") => problems.push(
+            "- The storage driver is \"devicemapper\", which is deprecated and slow. Use \
+             \"overlay2\" instead."
+                .to_owned(),
+        ),
+        Some("overlay2") if info.driver_status("Supports d_type") == Some("false") => problems
+            .push(
+                "- The storage driver is \"overlay2\" on a file system without d_type support, \
+                 where module files randomly go missing. Format the file system of the engine's \
+                 data root with d_type support (ftype=1 for xfs)."
+                    .to_owned(),
+            ),
+        _ => (),
+    }
+
+    if let Some("none") = info.cgroup_driver.as_ref().map(AsRef::as_ref) {
+        problems.push(
+            "- The container engine runs without a cgroup driver, so the memory and CPU limits \
+             in the create options of modules are ignored."
+                .to_owned(),
+        );
+    }
+
+    // Without DNS servers of its own, the engine gives modules the ones of the
+    // host, except loopback ones which they can't reach. With only loopback
+    // ones, as with systemd-resolved, modules fall back to public DNS servers
+    // that networks commonly block.
+    let engine_has_dns = !dns.unwrap_or(&[]).is_empty();
+    if let Some(host_nameservers) = host_nameservers {
+        let only_loopback = !host_nameservers.is_empty()
+            && host_nameservers
+                .iter()
+                .all(|nameserver| match nameserver.parse::<IpAddr>() {
+                    Ok(ip) => ip.is_loopback(),
+                    Err(_) => false,
+                });
+        if !engine_has_dns && only_loopback {
+            problems.push(
+                "- The host only has loopback DNS servers, which modules can't reach, and the \
+                 container engine has none of its own, so modules use public DNS servers \
+                 instead. Set \"dns\" in the container engine config file."
+                    .to_owned(),
+            );
+        }
+    }
+
+    problems
+}
+
+#[cfg(unix)]
+fn host_nameservers() -> Option<Vec<String>> {
+    let resolv_conf = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    Some(
+        resolv_conf
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                match (fields.next(), fields.next()) {
+                    (Some("nameserver"), Some(nameserver)) => Some(nameserver.to_owned()),
+                    _ => None,
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(windows)]
+fn host_nameservers() -> Option<Vec<String>> {
+    None
+}
+
+#[derive(serde_derive::Deserialize, serde_derive::Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+struct DockerInfo {
+    logging_driver: Option<String>,
+    driver: Option<String>,
+    #[serde(default)]
+    driver_status: Vec<Vec<String>>,
+    cgroup_driver: Option<String>,
+    live_restore_enabled: Option<bool>,
+}
+
+impl DockerInfo {
+    fn driver_status(&self, name: &str) -> Option<&str> {
+        self.driver_status
+            .iter()
+            .find(|status| status.first().map(AsRef::as_ref) == Some(name))
+            .and_then(|status| status.get(1))
+            .map(AsRef::as_ref)
+    }
+}
+
+#[derive(serde_derive::Deserialize)]
+struct DaemonConfig {
+    dns: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(json: &str) -> DockerInfo {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn recommended_config_is_fine() {
+        let info = info(
+            r#"{
+                "LoggingDriver": "json-file",
+                "Driver": "overlay2",
+                "DriverStatus": [["Backing Filesystem", "extfs"], ["Supports d_type", "true"]],
+                "CgroupDriver": "cgroupfs",
+                "LiveRestoreEnabled": false
+            }"#,
+        );
+
+        assert!(audit(&info, None, Some(&["10.0.0.2".to_owned()])).is_empty());
+        assert!(audit(
+            &info,
+            Some(&["1.1.1.1".to_owned()]),
+            Some(&["127.0.0.53".to_owned()])
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn breaking_config_is_flagged() {
+        let info = info(
+            r#"{
+                "LoggingDriver": "journald",
+                "Driver": "overlay2",
+                "DriverStatus": [["Backing Filesystem", "xfs"], ["Supports d_type", "false"]],
+                "CgroupDriver": "none"
+            }"#,
+        );
+
+        let problems = audit(&info, Some(&[]), Some(&["127.0.0.53".to_owned()]));
+        assert_eq!(4, problems.len());
+        assert!(problems[0].contains("\"journald\""));
+        assert!(problems[1].contains("d_type"));
+        assert!(problems[2].contains("cgroup driver"));
+        assert!(problems[3].contains("loopback DNS servers"));
+    }
+
+    #[test]
+    fn deprecated_storage_drivers_are_flagged() {
+        for driver in &["vfs", "devicemapper"] {
+            let info = info(&format!(r#"{{ "Driver": "{}" }}"#, driver));
+            let problems = audit(&info, None, None);
+            assert_eq!(1, problems.len());
+            assert!(problems[0].contains(driver));
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use std::net::TcpStream;
 
-use failure::{Context, ResultExt};
+use failure::{Context, Fail, ResultExt};
 
 use edgelet_core::{self, ProvisioningType, RuntimeSettings};
 
@@ -55,7 +55,8 @@ impl HostConnectDpsEndpoint {
     }
 }
 
-// Resolves the given `ToSocketAddrs`, then connects to the first address via TCP and completes a TLS handshake.
+// Connects to the given `ToSocketAddrs` and completes a TLS handshake.
+// The TLS stream is returned for checks that go on to send requests over it.
 //
 // `tls_hostname` is used for SNI validation and certificate hostname validation.
 //
@@ -64,25 +65,8 @@ pub fn resolve_and_tls_handshake(
     to_socket_addrs: &impl std::net::ToSocketAddrs,
     tls_hostname: &str,
     hostname_display: &str,
-) -> Result<(), failure::Error> {
-    let host_addr = to_socket_addrs
-        .to_socket_addrs()
-        .with_context(|_| {
-            format!(
-                "Could not connect to {} : could not resolve hostname",
-                hostname_display,
-            )
-        })?
-        .next()
-        .ok_or_else(|| {
-            Context::new(format!(
-                "Could not connect to {} : could not resolve hostname: no addresses found",
-                hostname_display,
-            ))
-        })?;
-
-    let stream = TcpStream::connect_timeout(&host_addr, std::time::Duration::from_secs(10))
-        .with_context(|_| format!("Could not connect to {}", hostname_display))?;
+) -> Result<native_tls::TlsStream<TcpStream>, failure::Error> {
+    let stream = connect(to_socket_addrs, hostname_display)?;
 
     let tls_connector = native_tls::TlsConnector::new().with_context(|_| {
         format!(
@@ -91,7 +75,7 @@ pub fn resolve_and_tls_handshake(
         )
     })?;
 
-    let _ = tls_connector
+    let stream = tls_connector
         .connect(tls_hostname, stream)
         .with_context(|_| {
             format!(
@@ -100,5 +84,42 @@ pub fn resolve_and_tls_handshake(
             )
         })?;
 
-    Ok(())
+    Ok(stream)
+}
+
+// Resolves the given `ToSocketAddrs`, then connects to the first address that accepts a TCP connection.
+// Addresses are tried in turn since the host may only have one of IPv4 and IPv6.
+pub fn connect(
+    to_socket_addrs: &impl std::net::ToSocketAddrs,
+    hostname_display: &str,
+) -> Result<TcpStream, failure::Error> {
+    let host_addrs = to_socket_addrs.to_socket_addrs().with_context(|_| {
+        format!(
+            "Could not connect to {} : could not resolve hostname",
+            hostname_display,
+        )
+    })?;
+
+    let mut last_err = None;
+    let mut stream = None;
+    for host_addr in host_addrs {
+        match TcpStream::connect_timeout(&host_addr, std::time::Duration::from_secs(10)) {
+            Ok(connected) => {
+                stream = Some(connected);
+                break;
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    match (stream, last_err) {
+        (Some(stream), _) => Ok(stream),
+        (None, Some(err)) => Err(err
+            .context(format!("Could not connect to {}", hostname_display))
+            .into()),
+        (None, None) => Err(Context::new(format!(
+            "Could not connect to {} : could not resolve hostname: no addresses found",
+            hostname_display,
+        ))
+        .into()),
+    }
 }
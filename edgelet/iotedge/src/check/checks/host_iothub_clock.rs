@@ -0,0 +1,123 @@
+use std::io::{Read, Write};
+
+use chrono::{DateTime, Utc};
+use failure::{self, Context, ResultExt};
+
+use crate::check::{
+    checker::Checker, upstream_protocol_port::UpstreamProtocolPort, Check, CheckResult,
+};
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct HostIotHubClock {
+    iothub_hostname: Option<String>,
+    offset: Option<i64>,
+}
+
+impl Checker for HostIotHubClock {
+    fn id(&self) -> &'static str {
+        "host-iothub-clock"
+    }
+    fn description(&self) -> &'static str {
+        "host time is in sync with IoT Hub"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl HostIotHubClock {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let iothub_hostname = if let Some(iothub_hostname) = &check.iothub_hostname {
+            iothub_hostname
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+        self.iothub_hostname = Some(iothub_hostname.clone());
+
+        // Unlike the NTP server of `host-local-time`, IoT Hub can be reached
+        // wherever the daemon works at all, and its time is the one that SAS
+        // tokens are checked against.
+        let port = UpstreamProtocolPort::Https.as_port();
+        let mut stream = super::host_connect_dps_endpoint::resolve_and_tls_handshake(
+            &(&**iothub_hostname, port),
+            iothub_hostname,
+            &format!("{}:{}", iothub_hostname, port),
+        )?;
+        stream
+            .get_ref()
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .context("Could not set the read timeout of the connection to IoT Hub")?;
+
+        write!(
+            stream,
+            "HEAD / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            iothub_hostname,
+        )
+        .context("Could not send a request to IoT Hub")?;
+        let mut response = vec![];
+        stream
+            .read_to_end(&mut response)
+            .context("Could not read the response of IoT Hub")?;
+        let local_time = Utc::now();
+
+        let server_time = server_time(&String::from_utf8_lossy(&response))
+            .ok_or_else(|| Context::new("The response of IoT Hub does not have a Date header"))?;
+        let offset = server_time - local_time;
+        self.offset = Some(offset.num_seconds());
+
+        if let Some(warning) = edgelet_core::clock_skew_warning(offset) {
+            return Ok(CheckResult::Warning(Context::new(warning).into()));
+        }
+
+        Ok(CheckResult::Ok)
+    }
+}
+
+/// Reads the time of the server from the `Date` header of an HTTP response.
+fn server_time(response: &str) -> Option<DateTime<Utc>> {
+    response
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| {
+            let separator = line.find(':')?;
+            let (name, value) = (&line[..separator], &line[separator + 1..]);
+            if name.trim().eq_ignore_ascii_case("date") {
+                DateTime::parse_from_rfc2822(value.trim()).ok()
+            } else {
+                None
+            }
+        })
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn server_time_is_read_from_date_header() {
+        let response = "HTTP/1.1 404 Not Found\r\n\
+                        Content-Length: 0\r\n\
+                        date: Sun, 06 Nov 1994 08:49:37 GMT\r\n\
+                        \r\n";
+
+        assert_eq!(
+            Some(Utc.ymd(1994, 11, 6).and_hms(8, 49, 37)),
+            server_time(response)
+        );
+    }
+
+    #[test]
+    fn missing_date_header_is_none() {
+        let response = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\nDate: body";
+
+        assert_eq!(None, server_time(response));
+    }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Write;
+
+use failure::{self, Context, ResultExt};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use openssl::x509::{X509NameRef, X509Ref};
+
+use edgelet_core::{self, ProvisioningType, RuntimeSettings};
+
+use crate::check::{
+    checker::Checker, upstream_protocol_port::UpstreamProtocolPort, Check, CheckResult,
+};
+
+/// The common names of the root CAs that the public endpoints of IoT Hub, DPS and
+/// the Microsoft and Docker Hub container registries chain to.
+const EXPECTED_ROOTS: &[&str] = &[
+    "Baltimore CyberTrust Root",
+    "DigiCert Global Root CA",
+    "DigiCert Global Root G2",
+    "DigiCert Global Root G3",
+    "Microsoft RSA Root Certificate Authority 2017",
+    "Microsoft ECC Root Certificate Authority 2017",
+    "Amazon Root CA 1",
+];
+
+const DOCKER_HUB_HOSTNAME: &str = "registry-1.docker.io";
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct HostTlsInspection {
+    chains: Vec<PresentedChain>,
+}
+
+/// The top of the certificate chain that an endpoint presented.
+#[derive(serde_derive::Serialize)]
+struct PresentedChain {
+    endpoint: String,
+    root: String,
+    sha256_fingerprint: String,
+    expected: bool,
+}
+
+impl Checker for HostTlsInspection {
+    fn id(&self) -> &'static str {
+        "host-tls-inspection"
+    }
+    fn description(&self) -> &'static str {
+        "outbound TLS connections are not intercepted by a TLS inspection proxy"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl HostTlsInspection {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let settings = if let Some(settings) = &check.settings {
+            settings
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        let mut endpoints = vec![];
+        if let Some(iothub_hostname) = &check.iothub_hostname {
+            endpoints.push((
+                iothub_hostname.clone(),
+                UpstreamProtocolPort::Https.as_port(),
+            ));
+        }
+        if let ProvisioningType::Dps(dps) = settings.provisioning().provisioning_type() {
+            let dps_endpoint = dps.global_endpoint();
+            if let Some(dps_hostname) = dps_endpoint.host_str() {
+                endpoints.push((
+                    dps_hostname.to_owned(),
+                    dps_endpoint.port_or_known_default().unwrap_or(443),
+                ));
+            }
+        }
+        if let Some(registry) = registry_endpoint(settings.agent().config().image()) {
+            endpoints.push(registry);
+        }
+
+        for (hostname, port) in endpoints {
+            let chain = presented_chain(&hostname, port)?;
+            self.chains.push(chain);
+        }
+
+        let intercepted: Vec<&PresentedChain> =
+            self.chains.iter().filter(|chain| !chain.expected).collect();
+        if intercepted.is_empty() {
+            return Ok(CheckResult::Ok);
+        }
+
+        let mut message = String::from(
+            "The certificates presented by some endpoints were not issued by the public CAs that these endpoints use:\n",
+        );
+        for chain in intercepted {
+            writeln!(
+                message,
+                "    {} presented a chain issued by {:?} (SHA-256 fingerprint {})",
+                chain.endpoint, chain.root, chain.sha256_fingerprint,
+            )?;
+        }
+        message.push_str(
+            "This usually means that a proxy or firewall on the network inspects TLS traffic. \
+             The Edge Hub and other modules do not trust the certificates of such a proxy, so they will fail to connect \
+             even if the host does. Exempt these endpoints from TLS inspection, or add the CA certificate of the proxy \
+             with the fingerprint above to the trust bundle of the device and its modules.",
+        );
+
+        Ok(CheckResult::Warning(Context::new(message).into()))
+    }
+}
+
+/// Performs a TLS handshake with the endpoint without verifying its certificates,
+/// so that chains that the host doesn't trust can be inspected too, and describes
+/// the top of the chain it presented.
+fn presented_chain(hostname: &str, port: u16) -> Result<PresentedChain, failure::Error> {
+    let endpoint = format!("{}:{}", hostname, port);
+    let stream = super::host_connect_dps_endpoint::connect(&(hostname, port), &endpoint)?;
+
+    let mut connector = SslConnector::builder(SslMethod::tls()).with_context(|_| {
+        format!(
+            "Could not connect to {} : could not create TLS connector",
+            endpoint
+        )
+    })?;
+    connector.set_verify(SslVerifyMode::NONE);
+    let stream = connector.build().connect(hostname, stream).map_err(|err| {
+        Context::new(format!(
+            "Could not connect to {} : could not complete TLS handshake: {}",
+            endpoint, err
+        ))
+    })?;
+
+    let top = stream
+        .ssl()
+        .peer_cert_chain()
+        .and_then(|chain| chain.iter().last())
+        .ok_or_else(|| Context::new(format!("{} did not present any certificates", endpoint)))?;
+
+    // The root itself is usually not part of the chain, in which case the issuer
+    // of the topmost certificate names it.
+    let root = common_name(top.issuer_name()).unwrap_or_default();
+    let sha256_fingerprint = fingerprint(top)?;
+    let expected = EXPECTED_ROOTS.contains(&&*root);
+
+    Ok(PresentedChain {
+        endpoint,
+        root,
+        sha256_fingerprint,
+        expected,
+    })
+}
+
+fn common_name(name: &X509NameRef) -> Option<String> {
+    name.entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|common_name| common_name.to_string())
+}
+
+fn fingerprint(cert: &X509Ref) -> Result<String, failure::Error> {
+    let digest = cert
+        .digest(MessageDigest::sha256())
+        .context("Could not compute the fingerprint of a certificate")?;
+    Ok(digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+/// The hostname and port of the registry that an image is pulled from. Images
+/// without a registry come from Docker Hub. Registries on the device itself
+/// are left out, since their traffic doesn't go through a proxy.
+fn registry_endpoint(image: &str) -> Option<(String, u16)> {
+    let registry = match image.find('/') {
+        Some(index)
+            if image[..index].contains('.')
+                || image[..index].contains(':')
+                || &image[..index] == "localhost" =>
+        {
+            &image[..index]
+        }
+        _ => DOCKER_HUB_HOSTNAME,
+    };
+
+    let (hostname, port) = match registry.rfind(':') {
+        Some(index) => (&registry[..index], registry[index + 1..].parse().ok()?),
+        None => (registry, 443),
+    };
+
+    if hostname == "localhost" || hostname == "127.0.0.1" {
+        None
+    } else {
+        Some((hostname.to_owned(), port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_endpoint_of_image() {
+        assert_eq!(
+            Some(("mcr.microsoft.com".to_owned(), 443)),
+            registry_endpoint("mcr.microsoft.com/azureiotedge-agent:1.0")
+        );
+        assert_eq!(
+            Some(("myregistry.azurecr.io".to_owned(), 5000)),
+            registry_endpoint("myregistry.azurecr.io:5000/team/agent")
+        );
+        assert_eq!(
+            Some((DOCKER_HUB_HOSTNAME.to_owned(), 443)),
+            registry_endpoint("microsoft/azureiotedge-agent:1.0")
+        );
+        assert_eq!(
+            Some((DOCKER_HUB_HOSTNAME.to_owned(), 443)),
+            registry_endpoint("ubuntu")
+        );
+        assert_eq!(None, registry_endpoint("localhost:5000/agent"));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use failure::{self, Context, Fail, ResultExt};
+
+use edgelet_core::{RuntimeSettings, SelfTestReport, HSM_SELF_TEST_FILENAME};
+
+use crate::check::{checker::Checker, Check, CheckResult};
+
+// iotedged runs the self-test every 10 minutes, so a report older than this
+// means the daemon is not running or has stopped testing the HSM.
+const MAX_REPORT_AGE_MINS: i64 = 30;
+
+#[derive(Default, serde_derive::Serialize)]
+pub(crate) struct HsmSelfTest {
+    report_path: Option<PathBuf>,
+    report: Option<SelfTestReport>,
+}
+
+impl Checker for HsmSelfTest {
+    fn id(&self) -> &'static str {
+        "hsm-self-test"
+    }
+    fn description(&self) -> &'static str {
+        "HSM crypto backend self-test"
+    }
+    fn execute(&mut self, check: &mut Check) -> CheckResult {
+        self.inner_execute(check)
+            .unwrap_or_else(CheckResult::Failed)
+    }
+    fn get_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+impl HsmSelfTest {
+    fn inner_execute(&mut self, check: &mut Check) -> Result<CheckResult, failure::Error> {
+        let settings = if let Some(settings) = &check.settings {
+            settings
+        } else {
+            return Ok(CheckResult::Skipped);
+        };
+
+        let report_path = settings.homedir().join(HSM_SELF_TEST_FILENAME);
+        self.report_path = Some(report_path.clone());
+
+        let file = match File::open(&report_path) {
+            Ok(file) => file,
+            Err(err) => {
+                return Ok(CheckResult::Warning(
+                    err.context(format!(
+                        "Could not open HSM self-test report {}. Make sure the IoT Edge daemon is running \
+                         and that this command has permission to read the daemon's home directory.",
+                        report_path.display(),
+                    ))
+                    .into(),
+                ));
+            }
+        };
+
+        let report: SelfTestReport = serde_json::from_reader(file).with_context(|_| {
+            format!(
+                "Could not parse HSM self-test report {}",
+                report_path.display(),
+            )
+        })?;
+        self.report = Some(report.clone());
+
+        if !report.is_ok() {
+            let failures: Vec<String> = report
+                .failures()
+                .map(|failure| {
+                    format!(
+                        "{}: {}",
+                        failure.name(),
+                        failure.error().unwrap_or_default()
+                    )
+                })
+                .collect();
+            return Err(Context::new(format!(
+                "HSM self-test at {} failed. The HSM backend may be degraded and certificate \
+                 issuance is likely to fail:\n{}",
+                report.timestamp(),
+                failures.join("\n"),
+            ))
+            .into());
+        }
+
+        let age = chrono::Utc::now() - report.timestamp();
+        if age > chrono::Duration::minutes(MAX_REPORT_AGE_MINS) {
+            return Ok(CheckResult::Warning(
+                Context::new(format!(
+                    "Last HSM self-test ran at {} ({} minutes ago). Make sure the IoT Edge daemon is running.",
+                    report.timestamp(),
+                    age.num_minutes(),
+                ))
+                .into(),
+            ));
+        }
+
+        Ok(CheckResult::Ok)
+    }
+}
@@ -41,8 +41,14 @@ impl IdentityCertificateExpiry {
         match settings.provisioning().provisioning_type() {
             ProvisioningType::Dps(dps) => {
                 if let AttestationMethod::X509(x509_info) = dps.attestation() {
+                    let path = if let Some(path) = x509_info.identity_cert()? {
+                        path
+                    } else {
+                        // the identity certificate is held by the HSM
+                        self.provisioning_mode = Some("dps-x509-hsm");
+                        return Ok(CheckResult::Ignored);
+                    };
                     self.provisioning_mode = Some("dps-x509");
-                    let path = x509_info.identity_cert()?;
 
                     let result =
                         CertificateValidity::parse("DPS identity certificate".to_owned(), path)?;
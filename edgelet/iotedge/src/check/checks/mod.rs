@@ -1,6 +1,7 @@
 mod certificates_quickstart;
 mod connect_management_uri;
 mod container_connect_iothub;
+mod container_engine_config;
 mod container_engine_dns;
 mod container_engine_installed;
 mod container_engine_ipv6;
@@ -9,8 +10,11 @@ mod container_engine_logrotate;
 mod container_local_time;
 mod host_connect_dps_endpoint;
 mod host_connect_iothub;
+mod host_iothub_clock;
 mod host_local_time;
+mod host_tls_inspection;
 mod hostname;
+mod hsm_self_test;
 mod identity_certificate_expiry;
 mod iotedged_version;
 mod storage_mounted_from_host;
@@ -21,6 +25,7 @@ mod windows_host_version;
 pub(crate) use self::certificates_quickstart::CertificatesQuickstart;
 pub(crate) use self::connect_management_uri::ConnectManagementUri;
 pub(crate) use self::container_connect_iothub::get_host_container_iothub_tests;
+pub(crate) use self::container_engine_config::ContainerEngineConfig;
 pub(crate) use self::container_engine_dns::ContainerEngineDns;
 pub(crate) use self::container_engine_installed::ContainerEngineInstalled;
 pub(crate) use self::container_engine_ipv6::ContainerEngineIPv6;
@@ -29,8 +34,11 @@ pub(crate) use self::container_engine_logrotate::ContainerEngineLogrotate;
 pub(crate) use self::container_local_time::ContainerLocalTime;
 pub(crate) use self::host_connect_dps_endpoint::HostConnectDpsEndpoint;
 pub(crate) use self::host_connect_iothub::get_host_connect_iothub_tests;
+pub(crate) use self::host_iothub_clock::HostIotHubClock;
 pub(crate) use self::host_local_time::HostLocalTime;
+pub(crate) use self::host_tls_inspection::HostTlsInspection;
 pub(crate) use self::hostname::Hostname;
+pub(crate) use self::hsm_self_test::HsmSelfTest;
 pub(crate) use self::identity_certificate_expiry::IdentityCertificateExpiry;
 pub(crate) use self::iotedged_version::IotedgedVersion;
 pub(crate) use self::storage_mounted_from_host::{EdgeAgentStorageMounted, EdgeHubStorageMounted};
@@ -1,6 +1,8 @@
 use failure::{self, Context, ResultExt};
 
-use edgelet_core::{self, ManualAuthMethod, ProvisioningType, RuntimeSettings};
+use edgelet_core::{
+    self, ManualAuthMethod, ProvisioningType, RuntimeSettings, ENCRYPTED_SETTING_PREFIX,
+};
 
 use crate::check::{checker::Checker, Check, CheckResult};
 
@@ -36,6 +38,19 @@ impl WellFormedConnectionString {
 
         if let ProvisioningType::Manual(manual) = settings.provisioning().provisioning_type() {
             let hub = match manual.authentication_method() {
+                ManualAuthMethod::DeviceConnectionString(cs)
+                    if cs
+                        .device_connection_string()
+                        .starts_with(ENCRYPTED_SETTING_PREFIX) =>
+                {
+                    // Only the daemon can decrypt the connection string.
+                    if check.iothub_hostname.is_none() {
+                        let warning = "Device connection string is encrypted, so 'iotedge check' is not able to discover the device's backing IoT Hub.\n\
+                                        To run connectivity checks in this configuration please specify the backing IoT Hub name using --iothub-hostname switch.";
+                        return Ok(CheckResult::Warning(Context::new(warning).into()));
+                    }
+                    return Ok(CheckResult::Ok);
+                }
                 ManualAuthMethod::DeviceConnectionString(cs) => {
                     let (_, device, hub) = cs.parse_device_connection_string().context(
                                 "Invalid connection string format detected.\n\
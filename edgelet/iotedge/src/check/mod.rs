@@ -235,8 +235,10 @@ impl Check {
                     Box::new(ContainerEngineIPv6::default()),
                     Box::new(IdentityCertificateExpiry::default()),
                     Box::new(CertificatesQuickstart::default()),
+                    Box::new(HsmSelfTest::default()),
                     Box::new(ContainerEngineIsMoby::default()),
                     Box::new(ContainerEngineLogrotate::default()),
+                    Box::new(ContainerEngineConfig::default()),
                     Box::new(EdgeAgentStorageMounted::default()),
                     Box::new(EdgeHubStorageMounted::default()),
                 ],
@@ -245,6 +247,8 @@ impl Check {
                 let mut tests: Vec<Box<dyn Checker>> = Vec::new();
                 tests.push(Box::new(HostConnectDpsEndpoint::default()));
                 tests.extend(get_host_connect_iothub_tests());
+                tests.push(Box::new(HostIotHubClock::default()));
+                tests.push(Box::new(HostTlsInspection::default()));
                 tests.extend(get_host_container_iothub_tests());
                 tests
             }),
@@ -0,0 +1,331 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+
+use edgelet_http_mgmt::ModuleClient;
+use management::models::{Config, EnvVar, ModuleSpec};
+
+use crate::error::{Error, ErrorKind};
+use crate::Command;
+
+/// The edge agent is the module that applies deployments, so it is left out
+/// of the ones that are applied without it.
+const EDGE_AGENT: &str = "edgeAgent";
+
+/// Applies a deployment.json without the edge agent, by having the daemon
+/// create the identities and modules of the deployment and start them. It is
+/// meant for development and for bootstrapping a device that can't get its
+/// deployment from the hub.
+///
+/// The modules get the environment the edge agent would give them, but not the
+/// mounts, so a module that talks to the workload API needs the socket in the
+/// binds of its createOptions.
+pub struct Deploy<W> {
+    path: PathBuf,
+    client: ModuleClient,
+    output: Arc<Mutex<W>>,
+}
+
+impl<W> Deploy<W> {
+    pub fn new(path: PathBuf, client: ModuleClient, output: W) -> Self {
+        Deploy {
+            path,
+            client,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<W> Command for Deploy<W>
+where
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let modules = match fs::read(&self.path)
+            .context(ErrorKind::ReadFromFile)
+            .map_err(Error::from)
+            .and_then(|deployment| deployment_modules(&deployment))
+        {
+            Ok(modules) => modules,
+            Err(err) => return Box::new(future::err(err)),
+        };
+
+        let write = self.output.clone();
+        let result = self
+            .client
+            .apply_deployment(modules)
+            .map_err(|err| Error::from(err.context(ErrorKind::ApplyDeployment)))
+            .and_then(move |modules| {
+                let mut w = write.lock().unwrap();
+                for module in modules {
+                    writeln!(w, "Deployed module {}", module.name())
+                        .context(ErrorKind::WriteToStdout)?;
+                }
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AgentDesiredProperties {
+    #[serde(default)]
+    runtime: Runtime,
+    #[serde(default)]
+    system_modules: BTreeMap<String, DesiredModule>,
+    #[serde(default)]
+    modules: BTreeMap<String, DesiredModule>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Runtime {
+    #[serde(default)]
+    settings: RuntimeSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuntimeSettings {
+    #[serde(default)]
+    registry_credentials: BTreeMap<String, RegistryCredential>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistryCredential {
+    username: String,
+    password: String,
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DesiredModule {
+    #[serde(rename = "type")]
+    type_: String,
+    settings: ModuleSettings,
+    #[serde(default)]
+    env: BTreeMap<String, EnvValue>,
+    restart_policy: Option<String>,
+    image_pull_policy: Option<String>,
+    priority: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModuleSettings {
+    image: String,
+    // createOptions, and createOptions01 and so on for the parts of create
+    // options that don't fit in one property of the twin.
+    #[serde(flatten)]
+    create_options: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EnvValue {
+    value: String,
+}
+
+/// Reads the modules of a deployment, which is either a whole deployment.json
+/// or just the desired properties of the edge agent.
+fn deployment_modules(deployment: &[u8]) -> Result<Vec<ModuleSpec>, Error> {
+    let deployment: Value = serde_json::from_slice(deployment)
+        .context(ErrorKind::MalformedDeployment("not JSON".to_string()))?;
+    let desired = match deployment.pointer("/modulesContent/$edgeAgent/properties.desired") {
+        Some(desired) => desired.clone(),
+        None => deployment,
+    };
+    let desired: AgentDesiredProperties = serde_json::from_value(desired).context(
+        ErrorKind::MalformedDeployment("not the desired properties of the edge agent".to_string()),
+    )?;
+
+    let credentials = desired.runtime.settings.registry_credentials;
+    desired
+        .system_modules
+        .into_iter()
+        .filter(|(name, _)| name != EDGE_AGENT)
+        .chain(desired.modules)
+        .map(|(name, module)| module_spec(name, module, &credentials))
+        .collect()
+}
+
+fn module_spec(
+    name: String,
+    module: DesiredModule,
+    credentials: &BTreeMap<String, RegistryCredential>,
+) -> Result<ModuleSpec, Error> {
+    let create_options: String = module
+        .settings
+        .create_options
+        .iter()
+        .filter(|(key, _)| key.starts_with("createOptions"))
+        .map(|(_, value)| value.as_str())
+        .collect();
+
+    let mut settings = json!({ "image": module.settings.image });
+    if !create_options.is_empty() {
+        let create_options: Value = serde_json::from_str(&create_options).with_context(|_| {
+            ErrorKind::MalformedDeployment(format!(
+                "the createOptions of module {} are not JSON",
+                name
+            ))
+        })?;
+        settings["createOptions"] = create_options;
+    }
+    if let Some(credential) = credentials.values().find(|credential| {
+        module
+            .settings
+            .image
+            .starts_with(&format!("{}/", credential.address))
+    }) {
+        settings["auth"] = json!({
+            "username": credential.username,
+            "password": credential.password,
+            "serveraddress": credential.address,
+        });
+    }
+
+    let env = module
+        .env
+        .into_iter()
+        .map(|(key, value)| EnvVar::new(key, value.value))
+        .collect();
+    let mut spec = ModuleSpec::new(name, module.type_, Config::new(settings).with_env(env));
+    if let Some(restart_policy) = module.restart_policy {
+        spec.set_restart_policy(restart_policy);
+    }
+    if let Some(image_pull_policy) = module.image_pull_policy {
+        spec.set_image_pull_policy(image_pull_policy);
+    }
+    if let Some(priority) = module.priority {
+        spec.set_priority(priority);
+    }
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deployment_modules_are_read() {
+        let deployment = json!({
+            "modulesContent": {
+                "$edgeAgent": {
+                    "properties.desired": {
+                        "runtime": {
+                            "type": "docker",
+                            "settings": {
+                                "registryCredentials": {
+                                    "contoso": {
+                                        "username": "user",
+                                        "password": "secret",
+                                        "address": "contoso.azurecr.io"
+                                    }
+                                }
+                            }
+                        },
+                        "systemModules": {
+                            "edgeAgent": {
+                                "type": "docker",
+                                "settings": { "image": "mcr.microsoft.com/azureiotedge-agent:1.0" }
+                            },
+                            "edgeHub": {
+                                "type": "docker",
+                                "status": "running",
+                                "restartPolicy": "always",
+                                "priority": 0,
+                                "settings": {
+                                    "image": "mcr.microsoft.com/azureiotedge-hub:1.0",
+                                    "createOptions": "{\"HostConfig\":",
+                                    "createOptions01": "{\"Privileged\":true}}"
+                                }
+                            }
+                        },
+                        "modules": {
+                            "sensor": {
+                                "type": "docker",
+                                "imagePullPolicy": "never",
+                                "settings": { "image": "contoso.azurecr.io/sensor:1.0" },
+                                "env": { "INTERVAL": { "value": "5" } }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        let modules = deployment_modules(deployment.to_string().as_bytes()).unwrap();
+
+        assert_eq!(2, modules.len());
+
+        let edge_hub = &modules[0];
+        assert_eq!("edgeHub", edge_hub.name());
+        assert_eq!("docker", edge_hub.type_());
+        assert_eq!(Some("always"), edge_hub.restart_policy());
+        assert_eq!(Some(0), edge_hub.priority());
+        assert_eq!(
+            &json!({
+                "image": "mcr.microsoft.com/azureiotedge-hub:1.0",
+                "createOptions": { "HostConfig": { "Privileged": true } },
+            }),
+            edge_hub.config().settings()
+        );
+
+        let sensor = &modules[1];
+        assert_eq!("sensor", sensor.name());
+        assert_eq!(Some("never"), sensor.image_pull_policy());
+        assert_eq!(None, sensor.priority());
+        assert_eq!("user", sensor.config().settings()["auth"]["username"]);
+        let env = sensor.config().env().unwrap();
+        assert_eq!("INTERVAL", env[0].key());
+        assert_eq!("5", env[0].value());
+    }
+
+    #[test]
+    fn desired_properties_alone_are_read() {
+        let desired = json!({
+            "modules": {
+                "sensor": {
+                    "type": "docker",
+                    "settings": { "image": "sensor:1.0" }
+                }
+            }
+        });
+
+        let modules = deployment_modules(desired.to_string().as_bytes()).unwrap();
+
+        assert_eq!(1, modules.len());
+        assert_eq!("sensor", modules[0].name());
+        assert_eq!(None, modules[0].config().settings().get("auth"));
+    }
+
+    #[test]
+    fn malformed_create_options_fail() {
+        let desired = json!({
+            "modules": {
+                "sensor": {
+                    "type": "docker",
+                    "settings": { "image": "sensor:1.0", "createOptions": "{" }
+                }
+            }
+        });
+
+        let err = deployment_modules(desired.to_string().as_bytes()).unwrap_err();
+
+        assert_eq!(
+            "The deployment is malformed: the createOptions of module sensor are not JSON",
+            err.to_string()
+        );
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::Future;
+
+use edgelet_http_mgmt::ModuleClient;
+
+use crate::error::{Error, ErrorKind};
+use crate::Command;
+
+/// Encrypts a value with the master encryption key of the daemon, and writes
+/// what to put in config.yaml instead of the value.
+pub struct EncryptSetting<W> {
+    value: String,
+    client: ModuleClient,
+    output: Arc<Mutex<W>>,
+}
+
+impl<W> EncryptSetting<W> {
+    pub fn new(value: String, client: ModuleClient, output: W) -> Self {
+        EncryptSetting {
+            value,
+            client,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<W> Command for EncryptSetting<W>
+where
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let write = self.output.clone();
+        let result = self
+            .client
+            .encrypt_setting(&self.value)
+            .map_err(|err| Error::from(err.context(ErrorKind::EncryptSetting)))
+            .and_then(move |encrypted| {
+                let mut w = write.lock().unwrap();
+                writeln!(w, "{}", encrypted).context(ErrorKind::WriteToStdout)?;
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
@@ -12,6 +12,9 @@ pub struct Error {
 
 #[derive(Clone, Debug, Fail)]
 pub enum ErrorKind {
+    #[fail(display = "Could not apply the deployment")]
+    ApplyDeployment,
+
     #[fail(display = "Invalid value for --host parameter")]
     BadHostParameter,
 
@@ -21,24 +24,51 @@ pub enum ErrorKind {
     #[fail(display = "Invalid value for --tail parameter")]
     BadTailParameter,
 
+    #[fail(display = "Invalid value for --until parameter")]
+    BadUntilParameter,
+
     #[fail(display = "")]
     Diagnostics,
 
+    #[fail(display = "Could not encrypt the setting")]
+    EncryptSetting,
+
+    #[fail(display = "Could not export the image of the module")]
+    ExportImage,
+
     #[fail(
         display = "Error while fetching latest versions of edge components: {}",
         _0
     )]
     FetchLatestVersions(FetchLatestVersionsReason),
 
+    #[fail(display = "Could not import the images")]
+    ImportImages,
+
     #[fail(display = "Could not initialize tokio runtime")]
     InitializeTokio,
 
+    #[fail(display = "Could not list the images")]
+    ListImages,
+
+    #[fail(display = "The deployment is malformed: {}", _0)]
+    MalformedDeployment(String),
+
     #[fail(display = "Missing --host parameter")]
     MissingHostParameter,
 
     #[fail(display = "A module runtime error occurred")]
     ModuleRuntime,
 
+    #[fail(display = "Could not prune the images")]
+    PruneImages,
+
+    #[fail(display = "Could not read from file")]
+    ReadFromFile,
+
+    #[fail(display = "Could not read from stdin")]
+    ReadFromStdin,
+
     #[fail(display = "Could not generate support bundle")]
     SupportBundle,
 
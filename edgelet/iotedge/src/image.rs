@@ -0,0 +1,244 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use failure::{Fail, ResultExt};
+use futures::{future, Future};
+use tabwriter::TabWriter;
+
+use edgelet_core::{ImageInfo, ModuleRuntime};
+
+use crate::error::{Error, ErrorKind};
+use crate::Command;
+
+/// Writes an archive of the image of a module, for `ImportImages` to load on
+/// a device that can't reach a registry.
+pub struct ExportImage<M, W> {
+    id: String,
+    runtime: M,
+    output: Arc<Mutex<W>>,
+}
+
+impl<M, W> ExportImage<M, W> {
+    pub fn new(id: String, runtime: M, output: W) -> Self {
+        ExportImage {
+            id,
+            runtime,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<M, W> Command for ExportImage<M, W>
+where
+    M: 'static + ModuleRuntime + Clone,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let write = self.output.clone();
+        let result = self
+            .runtime
+            .export_image(&self.id)
+            .map_err(|err| Error::from(err.context(ErrorKind::ExportImage)))
+            .and_then(move |archive| {
+                let mut w = write.lock().unwrap();
+                w.write_all(&archive).context(ErrorKind::WriteToFile)?;
+                w.flush().context(ErrorKind::WriteToFile)?;
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
+
+/// Loads the images of an archive written by `ExportImage`, such as one
+/// carried over on a USB drive.
+pub struct ImportImages<M> {
+    path: PathBuf,
+    runtime: M,
+}
+
+impl<M> ImportImages<M> {
+    pub fn new(path: PathBuf, runtime: M) -> Self {
+        ImportImages { path, runtime }
+    }
+}
+
+impl<M> Command for ImportImages<M>
+where
+    M: 'static + ModuleRuntime + Clone,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let archive = match fs::read(&self.path).context(ErrorKind::ReadFromFile) {
+            Ok(archive) => archive,
+            Err(err) => return Box::new(future::err(Error::from(err))),
+        };
+
+        let result = self
+            .runtime
+            .import_images(archive)
+            .map_err(|err| Error::from(err.context(ErrorKind::ImportImages)));
+        Box::new(result)
+    }
+}
+
+/// Lists the images the container runtime has stored, and whether a current
+/// module was created from each of them.
+pub struct ListImages<M, W> {
+    runtime: M,
+    output: Arc<Mutex<TabWriter<W>>>,
+}
+
+impl<M, W> ListImages<M, W>
+where
+    W: Write,
+{
+    pub fn new(runtime: M, output: W) -> Self {
+        let tab = TabWriter::new(output).minwidth(15);
+        ListImages {
+            runtime,
+            output: Arc::new(Mutex::new(tab)),
+        }
+    }
+}
+
+impl<M, W> Command for ListImages<M, W>
+where
+    M: 'static + ModuleRuntime + Clone,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let write = self.output.clone();
+        let result = self
+            .runtime
+            .list_images()
+            .map_err(|err| Error::from(err.context(ErrorKind::ListImages)))
+            .and_then(move |mut images| {
+                // Images of current modules first, then the ones that prune would remove
+                images.sort_by(|a, b| b.in_use().cmp(&a.in_use()).then(a.tags().cmp(b.tags())));
+
+                let mut w = write.lock().unwrap();
+                writeln!(w, "ID\tTAGS\tSIZE\tIN USE").context(ErrorKind::WriteToStdout)?;
+                for image in images {
+                    writeln!(
+                        w,
+                        "{}\t{}\t{}\t{}",
+                        short_id(image.id()),
+                        tags(&image),
+                        human_size(image.size()),
+                        if image.in_use() { "yes" } else { "no" },
+                    )
+                    .context(ErrorKind::WriteToStdout)?;
+                }
+                w.flush().context(ErrorKind::WriteToStdout)?;
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
+
+/// Removes the images that no container was created from.
+pub struct PruneImages<M, W> {
+    runtime: M,
+    output: Arc<Mutex<W>>,
+}
+
+impl<M, W> PruneImages<M, W> {
+    pub fn new(runtime: M, output: W) -> Self {
+        PruneImages {
+            runtime,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+}
+
+impl<M, W> Command for PruneImages<M, W>
+where
+    M: 'static + ModuleRuntime + Clone,
+    W: 'static + Write + Send,
+{
+    type Future = Box<dyn Future<Item = (), Error = Error> + Send>;
+
+    fn execute(self) -> Self::Future {
+        let write = self.output.clone();
+        let result = self
+            .runtime
+            .prune_images()
+            .map_err(|err| Error::from(err.context(ErrorKind::PruneImages)))
+            .and_then(move |pruned| {
+                let mut w = write.lock().unwrap();
+                for image in pruned.images() {
+                    writeln!(w, "Deleted: {}", image).context(ErrorKind::WriteToStdout)?;
+                }
+                writeln!(
+                    w,
+                    "Total reclaimed space: {}",
+                    human_size(pruned.space_reclaimed())
+                )
+                .context(ErrorKind::WriteToStdout)?;
+                w.flush().context(ErrorKind::WriteToStdout)?;
+                Ok(())
+            });
+        Box::new(result)
+    }
+}
+
+/// The ID of an image without its digest algorithm, shortened like docker does.
+fn short_id(id: &str) -> &str {
+    let id = id.splitn(2, ':').last().unwrap_or(id);
+    id.get(..12).unwrap_or(id)
+}
+
+fn tags(image: &ImageInfo) -> String {
+    if image.tags().is_empty() {
+        "<none>".to_string()
+    } else {
+        image.tags().join(", ")
+    }
+}
+
+/// Formats a size in bytes with decimal units, like docker does.
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "kB", "MB", "GB", "TB"];
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn images_are_described_like_docker_does() {
+        assert_eq!(
+            "9f2b3e1c4d5a",
+            short_id("sha256:9f2b3e1c4d5a6b7c8d9e0f1a2b3c4d5e")
+        );
+        assert_eq!("abc", short_id("abc"));
+
+        assert_eq!("512B", human_size(512));
+        assert_eq!("1.5kB", human_size(1500));
+        assert_eq!("221.5MB", human_size(221_458_432));
+        assert_eq!("2.1GB", human_size(2_147_483_648));
+    }
+}
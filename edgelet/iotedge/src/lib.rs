@@ -17,7 +17,10 @@ use futures::Future;
 use serde_derive::Deserialize;
 
 mod check;
+mod deploy;
+mod encrypt;
 mod error;
+mod image;
 mod list;
 mod logs;
 mod restart;
@@ -26,7 +29,10 @@ mod unknown;
 mod version;
 
 pub use crate::check::{Check, OutputFormat};
+pub use crate::deploy::Deploy;
+pub use crate::encrypt::EncryptSetting;
 pub use crate::error::{Error, ErrorKind, FetchLatestVersionsReason};
+pub use crate::image::{ExportImage, ImportImages, ListImages, PruneImages};
 pub use crate::list::List;
 pub use crate::logs::Logs;
 pub use crate::restart::Restart;
@@ -5,7 +5,8 @@
 #![allow(clippy::similar_names)]
 
 use std::borrow::Cow;
-use std::io;
+use std::fs::File;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process;
 
@@ -194,6 +195,72 @@ fn run() -> Result<(), Error> {
                 ),
         )
         .subcommand(SubCommand::with_name("check-list").about("List the checks that are run for 'iotedge check'"))
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Manage the configuration of the daemon")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("encrypt")
+                        .about("Encrypt a secret with the HSM of the device, to use as a value in config.yaml")
+                        .arg(
+                            Arg::with_name("VALUE")
+                                .help("The value to encrypt. Read from stdin if not given, which keeps it out of the shell history")
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("deploy")
+                .about("Apply a deployment locally, without the edge agent, for development and offline setup")
+                .long_about("Apply a deployment locally, without the edge agent, for development and offline setup.\n\nThe identities of the modules are created, and the modules are created again and started with the environment the edge agent would give them. The edge agent itself is left out. Modules that use the workload API need its socket in the binds of their createOptions.")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("Sets the path of the deployment.json, or of a file with just the desired properties of the edge agent")
+                        .required(true)
+                        .index(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("image")
+                .about("Move module images to and from devices that can't reach a registry")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Export the image of a module to an archive")
+                        .arg(
+                            Arg::with_name("MODULE")
+                                .help("Sets the module whose image to export")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .help("Location to output the archive to. Use - for stdout. Defaults to <MODULE>.tar")
+                                .long("output")
+                                .short("o")
+                                .takes_value(true)
+                                .value_name("FILENAME"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("import")
+                        .about("Import the images of an archive written by 'iotedge image export'")
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("Sets the path of the archive, such as one on a USB drive")
+                                .required(true)
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("images")
+                .about("List the images stored by the container runtime")
+                .subcommand(
+                    SubCommand::with_name("prune")
+                        .about("Remove the images that no container was created from"),
+                ),
+        )
         .subcommand(SubCommand::with_name("list").about("List modules"))
         .subcommand(
             SubCommand::with_name("restart")
@@ -230,6 +297,19 @@ fn run() -> Result<(), Error> {
                         .value_name("DURATION or TIMESTAMP")
                         .default_value("1 day"),
                 )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only return logs before this time, as a duration (1 day, 90 minutes, 2 days 3 hours 2 minutes), rfc3339 timestamp, or UNIX timestamp")
+                        .long("until")
+                        .takes_value(true)
+                        .value_name("DURATION or TIMESTAMP"),
+                )
+                .arg(
+                    Arg::with_name("timestamps")
+                        .help("Show the time at which each line was logged")
+                        .short("t")
+                        .long("timestamps"),
+                )
                 .arg(
                     Arg::with_name("follow")
                         .help("Follow output log")
@@ -337,6 +417,52 @@ fn run() -> Result<(), Error> {
             .and_then(Command::execute),
         ),
         ("check-list", _) => Check::print_list(),
+        ("config", Some(args)) => match args.subcommand() {
+            ("encrypt", Some(args)) => {
+                let value = if let Some(value) = args.value_of("VALUE") {
+                    value.to_string()
+                } else {
+                    let mut value = String::new();
+                    io::stdin()
+                        .read_line(&mut value)
+                        .context(ErrorKind::ReadFromStdin)?;
+                    value.trim_end_matches(&['\r', '\n'][..]).to_string()
+                };
+                tokio_runtime
+                    .block_on(EncryptSetting::new(value, runtime()?, io::stdout()).execute())
+            }
+            (command, _) => tokio_runtime.block_on(Unknown::new(command.to_string()).execute()),
+        },
+        ("deploy", Some(args)) => {
+            let path = args.value_of_os("FILE").unwrap().into();
+            tokio_runtime.block_on(Deploy::new(path, runtime()?, io::stdout()).execute())
+        }
+        ("image", Some(args)) => match args.subcommand() {
+            ("export", Some(args)) => {
+                let id = args.value_of("MODULE").unwrap().to_string();
+                let output: Box<dyn Write + Send> = match args.value_of_os("output") {
+                    Some(location) if location == "-" => Box::new(io::stdout()),
+                    location => {
+                        let location = location
+                            .map_or_else(|| PathBuf::from(format!("{}.tar", id)), PathBuf::from);
+                        Box::new(File::create(location).context(ErrorKind::WriteToFile)?)
+                    }
+                };
+                tokio_runtime.block_on(ExportImage::new(id, runtime()?, output).execute())
+            }
+            ("import", Some(args)) => {
+                let path = args.value_of_os("FILE").unwrap().into();
+                tokio_runtime.block_on(ImportImages::new(path, runtime()?).execute())
+            }
+            (command, _) => tokio_runtime.block_on(Unknown::new(command.to_string()).execute()),
+        },
+        ("images", Some(args)) => match args.subcommand() {
+            ("prune", _) => {
+                tokio_runtime.block_on(PruneImages::new(runtime()?, io::stdout()).execute())
+            }
+            ("", _) => tokio_runtime.block_on(ListImages::new(runtime()?, io::stdout()).execute()),
+            (command, _) => tokio_runtime.block_on(Unknown::new(command.to_string()).execute()),
+        },
         ("list", _) => tokio_runtime.block_on(List::new(runtime()?, io::stdout()).execute()),
         ("restart", Some(args)) => tokio_runtime.block_on(
             Restart::new(
@@ -363,10 +489,19 @@ fn run() -> Result<(), Error> {
                 .transpose()
                 .context(ErrorKind::BadSinceParameter)?
                 .expect("arg has a default value");
+            let until = args
+                .value_of("until")
+                .map(|s| parse_since(s))
+                .transpose()
+                .context(ErrorKind::BadUntilParameter)?
+                .unwrap_or(0);
+            let timestamps = args.is_present("timestamps");
             let options = LogOptions::new()
                 .with_follow(follow)
                 .with_tail(tail)
-                .with_since(since);
+                .with_since(since)
+                .with_until(until)
+                .with_timestamps(timestamps);
             tokio_runtime.block_on(Logs::new(id, options, runtime()?).execute())
         }
         ("support-bundle", Some(args)) => {
@@ -390,6 +525,7 @@ fn run() -> Result<(), Error> {
                 OutputLocation::File(location.to_owned())
             };
 
+            let runtime = runtime()?;
             tokio_runtime.block_on(
                 SupportBundle::new(
                     options,
@@ -397,8 +533,9 @@ fn run() -> Result<(), Error> {
                     verbose,
                     iothub_hostname,
                     output_location,
-                    runtime()?,
+                    runtime.clone(),
                 )
+                .with_daemon_log_client(runtime)
                 .execute(),
             )
         }
@@ -11,10 +11,12 @@ use std::process::Command as ShellCommand;
 use chrono::{DateTime, Local, NaiveDateTime, Utc};
 use failure::Fail;
 use futures::{Future, Stream};
+use log::Level;
 use tokio::prelude::*;
 use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
 use edgelet_core::{LogOptions, LogTail, Module, ModuleRuntime};
+use edgelet_http_mgmt::ModuleClient;
 
 use crate::error::{Error, ErrorKind};
 use crate::logs::pull_logs;
@@ -27,6 +29,7 @@ pub struct SupportBundle<M> {
     verbose: bool,
     iothub_hostname: Option<String>,
     output_location: OutputLocation,
+    daemon_log_client: Option<ModuleClient>,
 }
 
 struct BundleState<M, W>
@@ -38,6 +41,7 @@ where
     include_ms_only: bool,
     verbose: bool,
     iothub_hostname: Option<String>,
+    daemon_log_client: Option<ModuleClient>,
     file_options: FileOptions,
     zip_writer: ZipWriter<W>,
 }
@@ -97,9 +101,17 @@ where
             verbose,
             iothub_hostname,
             output_location,
+            daemon_log_client: None,
         }
     }
 
+    /// Also bundles the most recent logs of the daemon from its management
+    /// API, which are available even where the system logs can't be read.
+    pub fn with_daemon_log_client(mut self, client: ModuleClient) -> Self {
+        self.daemon_log_client = Some(client);
+        self
+    }
+
     fn make_file_state(self) -> Result<BundleState<M, File>, Error> {
         let writer = File::create(Path::new(self.output_location.get_file_location()))
             .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
@@ -124,6 +136,7 @@ where
             include_ms_only: self.include_ms_only,
             verbose: self.verbose,
             iothub_hostname: self.iothub_hostname,
+            daemon_log_client: self.daemon_log_client,
             file_options,
             zip_writer,
         })
@@ -138,6 +151,7 @@ where
             .and_then(Self::write_check)
             .and_then(Self::write_module_logs)
             .and_then(Self::write_edgelet_log)
+            .and_then(Self::write_daemon_log_buffer)
             .and_then(Self::write_docker_log)
             .and_then(Self::write_all_inspects)
             .and_then(Self::write_all_network_inspects)
@@ -234,6 +248,7 @@ where
             include_ms_only,
             verbose,
             iothub_hostname,
+            daemon_log_client,
             file_options,
             mut zip_writer,
         } = state;
@@ -251,6 +266,7 @@ where
                         include_ms_only,
                         verbose,
                         iothub_hostname,
+                        daemon_log_client,
                         file_options,
                         zip_writer: zw,
                     };
@@ -315,6 +331,50 @@ where
         Ok(state)
     }
 
+    fn write_daemon_log_buffer<W>(
+        mut state: BundleState<M, W>,
+    ) -> impl Future<Item = BundleState<M, W>, Error = Error>
+    where
+        W: Write + Seek + Send,
+    {
+        let client = if let Some(client) = state.daemon_log_client.take() {
+            client
+        } else {
+            return future::Either::A(future::ok(state));
+        };
+
+        state.print_verbose("Getting recent logs from iotedged");
+        future::Either::B(
+            client
+                .daemon_logs(Level::Trace, &LogTail::All)
+                .then(move |logs| -> Result<_, Error> {
+                    let (file_name, output) = match logs {
+                        Ok(logs) => ("iotedged_recent.txt", logs),
+                        Err(err) => {
+                            println!("Could not get recent logs from iotedged. Including error in bundle.\nError message: {}", err);
+                            ("iotedged_recent_err.txt", err.to_string().into_bytes())
+                        }
+                    };
+
+                    state
+                        .zip_writer
+                        .start_file_from_path(
+                            &Path::new("logs").join(file_name),
+                            state.file_options,
+                        )
+                        .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+                    state
+                        .zip_writer
+                        .write(&output)
+                        .map_err(|err| Error::from(err.context(ErrorKind::SupportBundle)))?;
+
+                    state.print_verbose("Got recent logs from iotedged");
+                    Ok(state)
+                }),
+        )
+    }
+
     fn write_docker_log<W>(mut state: BundleState<M, W>) -> Result<BundleState<M, W>, Error>
     where
         W: Write + Seek + Send,
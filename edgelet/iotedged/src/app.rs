@@ -1,17 +1,24 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::ffi::{OsStr, OsString};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{crate_authors, crate_description, crate_name, App, Arg};
 use failure::ResultExt;
-use log::info;
+use log::{info, warn};
 
 use edgelet_core;
+use edgelet_core::RuntimeSettings;
+#[cfg(feature = "runtime-cri")]
+use edgelet_cri::Settings;
 #[cfg(feature = "runtime-docker")]
 use edgelet_docker::Settings;
 #[cfg(feature = "runtime-kubernetes")]
 use edgelet_kube::Settings;
+#[cfg(feature = "runtime-podman")]
+use edgelet_podman::Settings;
+#[cfg(feature = "runtime-process")]
+use edgelet_process::Settings;
 
 use crate::error::{Error, ErrorKind, InitializeErrorReason};
 use crate::logging;
@@ -47,7 +54,7 @@ fn create_app(default_config_file: &OsStr) -> App<'_, '_> {
     }
 }
 
-fn init_common(running_as_windows_service: bool) -> Result<Settings, Error> {
+fn init_common(running_as_windows_service: bool) -> Result<(Settings, PathBuf), Error> {
     let default_config_file = if cfg!(windows) {
         let program_data: PathBuf =
             std::env::var_os("PROGRAMDATA").map_or_else(|| r"C:\ProgramData".into(), Into::into);
@@ -74,6 +81,12 @@ fn init_common(running_as_windows_service: bool) -> Result<Settings, Error> {
 
     if cfg!(feature = "runtime-kubernetes") {
         info!("Starting Azure IoT Edge Security Daemon - Kubernetes mode");
+    } else if cfg!(feature = "runtime-cri") {
+        info!("Starting Azure IoT Edge Security Daemon - CRI mode");
+    } else if cfg!(feature = "runtime-podman") {
+        info!("Starting Azure IoT Edge Security Daemon - podman mode");
+    } else if cfg!(feature = "runtime-process") {
+        info!("Starting Azure IoT Edge Security Daemon - process mode");
     } else {
         info!("Starting Azure IoT Edge Security Daemon");
     };
@@ -87,21 +100,31 @@ fn init_common(running_as_windows_service: bool) -> Result<Settings, Error> {
 
     info!("Using config file: {}", config_file.display());
 
-    let settings = Settings::new(&config_file)
-        .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+    let settings = load_settings(&config_file)?;
 
-    Ok(settings)
+    if cfg!(windows) && !settings.tenants().is_empty() {
+        warn!("Tenants are only supported on Linux and are not started.");
+    }
+
+    Ok((settings, config_file))
 }
 
-pub fn init() -> Result<Settings, Error> {
+/// Returns the settings along with the path of the config file they were loaded from.
+pub fn init() -> Result<(Settings, PathBuf), Error> {
     init_common(false)
 }
 
 #[cfg(windows)]
-pub fn init_win_svc() -> Result<Settings, Error> {
+pub fn init_win_svc() -> Result<(Settings, PathBuf), Error> {
     init_common(true)
 }
 
+pub fn load_settings(config_file: &Path) -> Result<Settings, Error> {
+    let settings = Settings::new(config_file)
+        .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+    Ok(settings)
+}
+
 #[cfg(windows)]
 pub fn init_win_svc_logging() {
     logging::init_win_log();
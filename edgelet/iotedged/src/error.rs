@@ -5,6 +5,7 @@ use std::fmt::Display;
 #[cfg(windows)]
 use std::sync::Mutex;
 
+use edgelet_core::DeviceAction;
 use edgelet_core::Error as CoreError;
 use edgelet_core::ErrorKind as CoreErrorKind;
 use edgelet_http::Error as HttpError;
@@ -28,6 +29,15 @@ pub enum ErrorKind {
     #[fail(display = "The certificate management expiration timer encountered a failure.")]
     CertificateExpirationManagement,
 
+    #[fail(display = "The CA certificates could not be renewed")]
+    CertificateRenewal,
+
+    #[fail(display = "The device CA certificate was removed to be regenerated")]
+    DeviceCaRenewal,
+
+    #[fail(display = "The device {} failed", _0)]
+    DeviceAction(DeviceAction),
+
     #[fail(display = "The device has been de-provisioned")]
     DeviceDeprovisioned,
 
@@ -37,9 +47,15 @@ pub enum ErrorKind {
     #[fail(display = "Invalid signed token was provided.")]
     InvalidSignedToken,
 
+    #[fail(display = "Tenant {:?} is misconfigured: {}", _0, _1)]
+    InvalidTenant(String, String),
+
     #[fail(display = "The management service encountered an error")]
     ManagementService,
 
+    #[fail(display = "The metrics service encountered an error")]
+    MetricsService,
+
     #[fail(display = "The reprovisioning operation failed")]
     ReprovisionFailure,
 
@@ -150,7 +166,8 @@ impl From<&ErrorKind> for i32 {
             ErrorKind::Initialize(InitializeErrorReason::InvalidDeviceConfig) => 150,
             ErrorKind::Initialize(InitializeErrorReason::InvalidHubConfig) => 151,
             ErrorKind::InvalidSignedToken => 152,
-            ErrorKind::Initialize(InitializeErrorReason::LoadSettings) => 153,
+            ErrorKind::Initialize(InitializeErrorReason::LoadSettings)
+            | ErrorKind::InvalidTenant(_, _) => 153,
             ErrorKind::DeviceDeprovisioned => 154,
             _ => 1,
         }
@@ -159,6 +176,7 @@ impl From<&ErrorKind> for i32 {
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InitializeErrorReason {
+    CertificateRevocationList,
     CertificateSettings,
     CreateCertificateManager,
     CreateMasterEncryptionKey,
@@ -167,11 +185,16 @@ pub enum InitializeErrorReason {
     CreateTlsCertificate,
     DestroyWorkloadCa,
     DeviceClient,
+    DpsPayload,
     DpsProvisioningClient,
+    DpsTpmTransport,
     EdgeRuntime,
+    EstEnrollment,
+    EstSettings,
     ExternalProvisioningClient(ExternalProvisioningErrorReason),
     Hsm,
     HttpClient,
+    HomeDirOwnership,
     HybridAuthDirCreate,
     HybridAuthKeyCreate,
     HybridAuthKeyGet,
@@ -188,15 +211,20 @@ pub enum InitializeErrorReason {
     LoadSettings,
     ManagementService,
     ManualProvisioningClient,
+    MetricsService,
     ModuleRuntime,
+    ParentHostname,
     PrepareWorkloadCa,
+    QuickstartCa,
     #[cfg(windows)]
     RegisterWindowsService,
     RemoveExistingModules,
     SaveSettings,
     #[cfg(windows)]
     StartWindowsService,
+    Tenants,
     Tokio,
+    UpdateModuleIdentities,
     WorkloadService,
 }
 
@@ -217,6 +245,10 @@ pub enum ExternalProvisioningErrorReason {
 impl fmt::Display for InitializeErrorReason {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            InitializeErrorReason::CertificateRevocationList => {
+                write!(f, "Could not load the certificate revocation list")
+            }
+
             InitializeErrorReason::CertificateSettings => {
                 write!(f, "Could not configure Edge gateway certificates")
             }
@@ -247,12 +279,27 @@ impl fmt::Display for InitializeErrorReason {
 
             InitializeErrorReason::DeviceClient => write!(f, "Could not initialize device client"),
 
+            InitializeErrorReason::DpsPayload => {
+                write!(f, "Could not read the DPS custom allocation payload")
+            }
+
             InitializeErrorReason::DpsProvisioningClient => {
                 write!(f, "Could not initialize DPS provisioning client")
             }
 
+            InitializeErrorReason::DpsTpmTransport => write!(
+                f,
+                "DPS registration with TPM attestation is only supported over HTTPS"
+            ),
+
             InitializeErrorReason::EdgeRuntime => write!(f, "Could not initialize edge runtime"),
 
+            InitializeErrorReason::EstEnrollment => {
+                write!(f, "Could not enroll a certificate over EST")
+            }
+
+            InitializeErrorReason::EstSettings => write!(f, "Invalid EST settings"),
+
             InitializeErrorReason::ExternalProvisioningClient(x) => write!(
                 f,
                 "Could not initialize external provisioning client. {}",
@@ -261,6 +308,11 @@ impl fmt::Display for InitializeErrorReason {
 
             InitializeErrorReason::Hsm => write!(f, "Could not initialize HSM"),
 
+            InitializeErrorReason::HomeDirOwnership => write!(
+                f,
+                "The home directory contains files that are not owned by the user the daemon runs as"
+            ),
+
             InitializeErrorReason::HttpClient => write!(f, "Could not initialize HTTP client"),
 
             InitializeErrorReason::HybridAuthDirCreate => {
@@ -324,14 +376,22 @@ impl fmt::Display for InitializeErrorReason {
                 write!(f, "Could not initialize manual provisioning client")
             }
 
+            InitializeErrorReason::MetricsService => write!(f, "Could not start metrics service"),
+
             InitializeErrorReason::ModuleRuntime => {
                 write!(f, "Could not initialize module runtime")
             }
 
+            InitializeErrorReason::ParentHostname => write!(f, "Invalid parent hostname"),
+
             InitializeErrorReason::PrepareWorkloadCa => {
                 write!(f, "Could not prepare workload CA certificate")
             }
 
+            InitializeErrorReason::QuickstartCa => {
+                write!(f, "Could not prepare the quickstart CA certificates")
+            }
+
             #[cfg(windows)]
             InitializeErrorReason::RegisterWindowsService => {
                 write!(f, "Could not register Windows Service control handle")
@@ -348,8 +408,17 @@ impl fmt::Display for InitializeErrorReason {
                 write!(f, "Could not start as Windows Service")
             }
 
+            InitializeErrorReason::Tenants => write!(f, "Could not start the tenants"),
+
             InitializeErrorReason::Tokio => write!(f, "Could not initialize tokio runtime"),
 
+            InitializeErrorReason::UpdateModuleIdentities => {
+                write!(
+                    f,
+                    "Could not update module identities for the new device credentials"
+                )
+            }
+
             InitializeErrorReason::WorkloadService => write!(f, "Could not start workload service"),
         }
     }
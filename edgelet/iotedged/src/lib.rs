@@ -16,7 +16,11 @@
 pub mod app;
 mod error;
 pub mod logging;
+mod notify;
+mod power;
 pub mod signal;
+#[cfg(unix)]
+mod tenants;
 pub mod workload;
 
 #[cfg(not(target_os = "windows"))]
@@ -26,24 +30,30 @@ pub mod unix;
 pub mod windows;
 
 use futures::sync::mpsc;
+use std::cmp;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::fs::{DirBuilder, File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+use chrono::{DateTime, Utc};
 use failure::{Context, Fail, ResultExt};
-use futures::future::{Either, IntoFuture};
-use futures::sync::oneshot::{self, Receiver};
+use futures::future::{Either, IntoFuture, Shared};
+use futures::sync::oneshot::{self, Receiver, Sender};
 use futures::{future, Future, Stream};
 use hyper::server::conn::Http;
 use hyper::{Body, Request, Uri};
-use log::{debug, info, Level};
+use log::{debug, info, warn, Level};
+use openssl::pkey::PKey;
+use openssl::x509::X509 as X509Certificate;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
+use tokio::timer::{Delay, Interval};
 use url::Url;
 
 use dps::DPS_API_VERSION;
@@ -53,31 +63,49 @@ use edgelet_core::crypto::{
     MasterEncryptionKey, MemoryKey, MemoryKeyStore, Sign, Signature, SignatureAlgorithm,
     IOTEDGED_CA_ALIAS,
 };
-use edgelet_core::watchdog::Watchdog;
+use edgelet_core::watchdog::{
+    ActivityMonitor, AgentRollback, Watchdog, WatchdogStatus, AGENT_ROLLBACK_FILENAME,
+};
 use edgelet_core::{
-    AttestationMethod, Authenticator, Certificate, CertificateIssuer, CertificateProperties,
-    CertificateType, Dps, MakeModuleRuntime, ManualAuthMethod, Module, ModuleRuntime,
-    ModuleRuntimeErrorReason, ModuleSpec, ProvisioningResult as CoreProvisioningResult,
-    ProvisioningType, RuntimeSettings, SymmetricKeyAttestationInfo, TpmAttestationInfo,
-    WorkloadConfig, X509AttestationInfo,
+    decrypt_settings, self_test_sign, AgentBootstrap, ApiTokens, AttestationMethod,
+    AuthType as IdentityAuthType, Authenticator, BootOrder, Certificate, CertificateIssuer,
+    CertificateProperties, CertificateRevocationList, CertificateType, Certificates, ClockSkew,
+    CommandHook, DeviceAction, DeviceActionSettings, DiagnosticsReport, DiagnosticsSettings,
+    DiagnosticsSink, DiagnosticsSinkSettings, Dps, DpsTransport, Est, HealthReportSettings,
+    Identity, IdentityManager, IdentitySpec, LifecycleHook, LifecycleHooks, Listen,
+    LocalSecretStore, LogController, MakeModuleRuntime, ManualAuthMethod, Metrics, Module,
+    ModuleRuntime, ModuleRuntimeErrorReason, ModuleSpec, OperationJournal, OrphanedModuleSettings,
+    Protocol, ProvisioningResult as CoreProvisioningResult, ProvisioningType, RuntimeSettings,
+    SecretStore, SelfTestReport, SelfTestStatus, StartupStage, StartupState,
+    SymmetricKeyAttestationInfo, TpmAttestationInfo, TpmTcti, WorkloadConfig, X509AttestationInfo,
+    BOOT_ORDER_FILENAME, HSM_SELF_TEST_FILENAME, OPERATION_JOURNAL_FILENAME,
+    STARTUP_STATE_FILENAME,
 };
 use edgelet_hsm::tpm::{TpmKey, TpmKeyStore};
-use edgelet_hsm::{Crypto, HsmLock, X509};
+use edgelet_hsm::{Crypto, HsmLock, TpmAttestation, X509};
 use edgelet_http::certificate_manager::CertificateManager;
 use edgelet_http::client::{Client as HttpClient, ClientImpl};
-use edgelet_http::logging::LoggingService;
-use edgelet_http::{HyperExt, MaybeProxyClient, PemCertificate, TlsAcceptorParams, API_VERSION};
+use edgelet_http::metrics::MetricsEndpoint;
+use edgelet_http::middleware::ChainBuilder;
+use edgelet_http::rate_limit::RateLimiter;
+use edgelet_http::{
+    set_socket_permissions, HttpDiagnosticsSink, HyperExt, MaybeProxyClient, PemCertificate,
+    TlsAcceptorParams, API_VERSION,
+};
 use edgelet_http_external_provisioning::ExternalProvisioningClient;
-use edgelet_http_mgmt::ManagementService;
-use edgelet_http_workload::WorkloadService;
-use edgelet_iothub::{HubIdentityManager, SasTokenSource};
-use edgelet_utils::log_failure;
+use edgelet_http_mgmt::{ManagementService, ModuleSnapshot, MODULE_SNAPSHOT_FILENAME};
+use edgelet_http_workload::{CertificateCache, WorkloadService};
+use edgelet_iothub::{
+    DaemonHealth, HubIdentityManager, KeyRotations, SasTokenSource, KEY_ROTATIONS_FILENAME,
+};
+use edgelet_utils::{log_failure, RetryPolicy};
 pub use error::{Error, ErrorKind, InitializeErrorReason};
+use est::{CertificateUsage, EstClient};
 use hsm::tpm::Tpm;
 use hsm::ManageTpmKeys;
 use iothubservice::DeviceClient;
 use provisioning::provisioning::{
-    AuthType, BackupProvisioning, CredentialSource, DpsSymmetricKeyProvisioning,
+    derive_device_key, AuthType, BackupProvisioning, CredentialSource, DpsSymmetricKeyProvisioning,
     DpsTpmProvisioning, DpsX509Provisioning, ExternalProvisioning, ManualProvisioning, Provision,
     ProvisioningResult, ReprovisioningStatus,
 };
@@ -101,6 +129,11 @@ const HOSTNAME_KEY: &str = "IOTEDGE_IOTHUBHOSTNAME";
 /// network so that TLS cert validation works.
 const GATEWAY_HOSTNAME_KEY: &str = "EDGEDEVICEHOSTNAME";
 
+/// This variable holds the host name of the parent edge device, when the edge
+/// device is nested under another one. The edge agent passes it on to the
+/// modules so that they connect upstream through the parent device.
+const PARENT_HOSTNAME_KEY: &str = "IOTEDGE_PARENTHOSTNAME";
+
 /// This variable holds the IoT Hub device identifier.
 const DEVICEID_KEY: &str = "IOTEDGE_DEVICEID";
 
@@ -134,6 +167,18 @@ const EDGE_RUNTIME_MODE: &str = "iotedged";
 #[cfg(feature = "runtime-kubernetes")]
 const EDGE_RUNTIME_MODE: &str = "kubernetes";
 
+/// This is the edge runtime mode - it should be iotedged too when modules are run by a CRI runtime instead of docker.
+#[cfg(feature = "runtime-cri")]
+const EDGE_RUNTIME_MODE: &str = "iotedged";
+
+/// This is the edge runtime mode - it should be iotedged too when modules are run by podman instead of docker.
+#[cfg(feature = "runtime-podman")]
+const EDGE_RUNTIME_MODE: &str = "iotedged";
+
+/// This is the edge runtime mode - it should be iotedged too when modules are run as host processes.
+#[cfg(feature = "runtime-process")]
+const EDGE_RUNTIME_MODE: &str = "iotedged";
+
 /// The HSM lib expects this variable to be set with home directory of the daemon.
 const HOMEDIR_KEY: &str = "IOTEDGE_HOMEDIR";
 
@@ -142,6 +187,11 @@ const DEVICE_CA_CERT_KEY: &str = "IOTEDGE_DEVICE_CA_CERT";
 const DEVICE_CA_PK_KEY: &str = "IOTEDGE_DEVICE_CA_PK";
 const TRUSTED_CA_CERTS_KEY: &str = "IOTEDGE_TRUSTED_CA_CERTS";
 
+/// The HSM lib issues the workload CA certificate for the PKCS#11 key in this variable, when it is
+/// set, and loads PKCS#11 keys through the library in `IOTEDGE_PKCS11_LIB_PATH`.
+const WORKLOAD_CA_PK_KEY: &str = "IOTEDGE_WORKLOAD_CA_PK";
+const PKCS11_LIB_PATH_KEY: &str = "IOTEDGE_PKCS11_LIB_PATH";
+
 /// The HSM lib expects this variable to be set to the endpoint of the external provisioning environment in the 'external'
 /// provisioning mode.
 const EXTERNAL_PROVISIONING_ENDPOINT_KEY: &str = "IOTEDGE_EXTERNAL_PROVISIONING_ENDPOINT";
@@ -157,6 +207,63 @@ const EDGE_PROVISIONING_BACKUP_FILENAME: &str = "provisioning_backup.json";
 /// This is the name of the settings backup file
 const EDGE_SETTINGS_STATE_FILENAME: &str = "settings_state";
 
+/// This is the name of the backup file of the settings without the device
+/// credentials, used to tell a credential rotation apart from other changes
+const EDGE_SETTINGS_BASE_STATE_FILENAME: &str = "settings_base_state";
+
+/// This is the name of the file that marks that the device CA was removed to be
+/// regenerated, so that the modules are restarted once the daemon is back
+const EDGE_CA_RENEWAL_FILENAME: &str = "ca_renewal";
+
+/// This is the name of the file in the home directory that asks for the
+/// quickstart CA certificates to be regenerated on the next start
+const QUICKSTART_CA_RESET_FILENAME: &str = "quickstart_ca_reset";
+
+/// This is the alias of the self-signed root of the quickstart certificates
+const QUICKSTART_OWNER_CA_ALIAS: &str = "edge_owner_ca";
+
+/// The HSM keeps the private keys of the quickstart certificates in this
+/// directory under the home directory
+const QUICKSTART_CA_KEYS_SUBDIR: &str = "hsm/cert_keys";
+
+/// This is the name of the file in the home directory that keeps track of the
+/// certificates issued to modules and which of them were revoked
+const WORKLOAD_CRL_FILENAME: &str = "workload_certificates.json";
+
+/// Requests to the workload API carry data to sign or encrypt and certificate
+/// requests, which are all small. The management API has no limit, since it
+/// takes images to import.
+const WORKLOAD_MAX_REQUEST_BODY: u64 = 4 * 1024 * 1024;
+
+const QUICKSTART_CA_WARNING: &str = "The device is using automatically generated quickstart \
+    certificates, which are not intended for production.";
+
+/// These settings can be changed by reloading the configuration, without
+/// restarting the daemon
+const RELOADABLE_SETTINGS: &[&str] = &[
+    "/agent",
+    "/certificates/ca_renewal_threshold_days",
+    "/certificates/restart_modules_on_ca_renewal",
+    "/log_format",
+    "/log_level",
+    "/watchdog",
+];
+
+/// These provisioning settings hold device credentials that can be rotated
+/// without reconfiguring the device
+const PROVISIONING_CREDENTIAL_SETTINGS: &[&str] = &[
+    "identity_cert",
+    "identity_pk",
+    "symmetric_key",
+    "group_symmetric_key",
+];
+
+/// This is the connection string parameter holding the device key
+const SHARED_ACCESS_KEY_PARAMETER: &str = "SharedAccessKey=";
+
+/// Module identities created by the edge agent are owned by this value
+const EDGE_MODULE_MANAGED_BY: &str = "IotEdge";
+
 /// This is the name of the hybrid id subdirectory that will
 /// contain the hybrid key and other related files
 const EDGE_HYBRID_IDENTITY_SUBDIR: &str = "hybrid_id";
@@ -196,6 +303,14 @@ const EDGE_SETTINGS_SUBDIR: &str = "cache";
 /// This is the DPS registration ID env variable key
 const DPS_REGISTRATION_ID_ENV_KEY: &str = "IOTEDGE_REGISTRATION_ID";
 
+/// The HSM lib reaches the TPM through the TCTI in this variable, when it is set,
+/// instead of looking for the TPM device itself.
+const TPM_TCTI_ENV_KEY: &str = "IOTEDGE_TPM_TCTI";
+
+/// The resource manager of the kernel, which lets the daemon send its own
+/// commands to the TPM next to those of the HSM library.
+const TPM_RESOURCE_MANAGER_DEVICE: &str = "/dev/tpmrm0";
+
 /// This is the edge device identity certificate file path env variable key.
 /// This is used for both DPS attestation and manual authentication modes.
 const DEVICE_IDENTITY_CERT_PATH_ENV_KEY: &str = "IOTEDGE_DEVICE_IDENTITY_CERT";
@@ -204,6 +319,7 @@ const DEVICE_IDENTITY_CERT_PATH_ENV_KEY: &str = "IOTEDGE_DEVICE_IDENTITY_CERT";
 const DEVICE_IDENTITY_KEY_PATH_ENV_KEY: &str = "IOTEDGE_DEVICE_IDENTITY_PK";
 
 const IOTEDGED_COMMONNAME: &str = "iotedged workload ca";
+const IOTEDGED_DEVICE_CA_COMMONNAME: &str = "iotedged device ca";
 const IOTEDGED_TLS_COMMONNAME: &str = "iotedged";
 // 5 mins
 const IOTEDGED_MIN_EXPIRATION_DURATION: i64 = 5 * 60;
@@ -211,21 +327,44 @@ const IOTEDGED_MIN_EXPIRATION_DURATION: i64 = 5 * 60;
 const IOTEDGE_ID_CERT_MAX_DURATION_SECS: i64 = 2 * 3600;
 // 90 days
 const IOTEDGE_SERVER_CERT_MAX_DURATION_SECS: i64 = 90 * 24 * 3600;
+// 10 mins
+const IOTEDGE_SERVER_CERT_CACHE_SECS: i64 = 10 * 60;
 
 // HSM lib version that the iotedge runtime required
 const IOTEDGE_COMPAT_HSM_VERSION: &str = "1.0.3";
 
+/// Interval between self-tests of the HSM crypto backend
+const HSM_SELF_TEST_FREQUENCY_SECS: u64 = 10 * 60;
+
+/// How often registering with DPS is retried after the daemon started from the
+/// provisioning backup because DPS could not be reached.
+const DPS_REGISTRATION_RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How often listing the modules is retried after the daemon started, until
+/// the module snapshot is in sync with the container runtime.
+const MODULE_SNAPSHOT_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+const PROVISIONING_METRIC: &str = "iotedged_provisioning_info";
+const PROVISIONING_HELP: &str = "How the device was provisioned when the daemon started";
+
 #[derive(PartialEq)]
 enum StartApiReturnStatus {
+    Reload,
+    RenewCertificates,
     Restart,
     Shutdown,
 }
 
+type LoadSettings<S> = Box<dyn Fn() -> Result<S, Error>>;
+
 pub struct Main<M>
 where
     M: MakeModuleRuntime,
 {
     settings: M::Settings,
+    load_settings: Option<LoadSettings<M::Settings>>,
+    startup_state: StartupState,
+    lifecycle_hooks: LifecycleHooks,
 }
 
 #[derive(Debug, PartialEq)]
@@ -234,10 +373,10 @@ enum ProvisioningAuthMethod {
     SharedAccessKey,
 }
 
-#[derive(Debug)]
 struct IdentityCertificateData {
     common_name: String,
     thumbprint: String,
+    pem: PemCertificate,
 }
 
 impl<M> Main<M>
@@ -246,34 +385,128 @@ where
     M::ModuleRuntime: 'static + Authenticator<Request = Request<Body>> + Clone + Send + Sync,
     <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config:
         Clone + DeserializeOwned + Serialize,
-    M::Settings: 'static + Clone + Serialize,
+    M::Settings: 'static + Clone + DeserializeOwned + Serialize,
     <M::ModuleRuntime as ModuleRuntime>::Logs: Into<Body>,
     <M::ModuleRuntime as Authenticator>::Error: Fail + Sync,
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
 {
     pub fn new(settings: M::Settings) -> Self {
-        Main { settings }
+        let startup_state = StartupState::load(settings.homedir().join(STARTUP_STATE_FILENAME));
+        Main {
+            settings,
+            load_settings: None,
+            startup_state,
+            lifecycle_hooks: LifecycleHooks::new(),
+        }
+    }
+
+    /// Lets the daemon reload its configuration without restarting, with
+    /// `load_settings` reading the configuration file again.
+    pub fn with_settings_reload<F>(mut self, load_settings: F) -> Self
+    where
+        F: Fn() -> Result<M::Settings, Error> + 'static,
+    {
+        self.load_settings = Some(Box::new(load_settings));
+        self
+    }
+
+    /// Tells `hook` about modules being started and stopped, and about module
+    /// identities being created and deleted through the management API. This
+    /// is in addition to the hook command in the configuration, if any.
+    pub fn with_lifecycle_hook<H>(mut self, hook: H) -> Self
+    where
+        H: LifecycleHook + Send + Sync + 'static,
+    {
+        self.lifecycle_hooks = self.lifecycle_hooks.with_hook(hook);
+        self
+    }
+
+    /// The startup stage of the daemon, which can be watched while it runs, e.g.
+    /// to tell the service manager when the daemon finished starting.
+    pub fn startup_state(&self) -> &StartupState {
+        &self.startup_state
+    }
+
+    /// Runs the daemon until the shutdown signal fires. The startup stage the
+    /// daemon is in, and the error it failed with, are recorded in the home
+    /// directory so that they can be looked at after the daemon exited.
+    pub fn run_until<F, G>(self, make_shutdown_signal: G) -> Result<(), Error>
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+        G: Fn() -> F,
+    {
+        let startup_state = self.startup_state.clone();
+
+        let result = self.run_stages(make_shutdown_signal, &startup_state);
+        if let Err(err) = &result {
+            let mut message = err.to_string();
+            for cause in Fail::iter_causes(err) {
+                message.push_str(&format!("\n\tcaused by: {}", cause));
+            }
+            startup_state.fail(message);
+        }
+        result
     }
 
     // Allowing cognitive complexity errors for now. TODO: Refactor method later.
     #[allow(clippy::cognitive_complexity)]
-    pub fn run_until<F, G>(self, make_shutdown_signal: G) -> Result<(), Error>
+    fn run_stages<F, G>(
+        self,
+        make_shutdown_signal: G,
+        startup_state: &StartupState,
+    ) -> Result<(), Error>
     where
         F: Future<Item = (), Error = ()> + Send + 'static,
         G: Fn() -> F,
     {
-        let Main { settings } = self;
-        let hsm_lock = HsmLock::new();
+        let Main {
+            settings,
+            load_settings,
+            mut lifecycle_hooks,
+            ..
+        } = self;
+        // The metrics are kept across restarts of the APIs, and include the
+        // latency and failures of the operations on the HSM.
+        let metrics = Metrics::new();
+        let hsm_lock = HsmLock::with_metrics(metrics.clone());
+        let attestation = tpm_attestation(&settings, &hsm_lock);
+
+        logging::set_log_settings(&logging::configured_log_settings(&settings));
+
+        if let Some(hook) = settings.lifecycle_hook() {
+            info!(
+                "Running {} for module lifecycle events.",
+                hook.command().display()
+            );
+            lifecycle_hooks = lifecycle_hooks.with_hook(CommandHook::new(
+                hook.command().to_path_buf(),
+                hook.args().to_vec(),
+                hook.timeout(),
+            ));
+        }
+
+        check_homedir_ownership(settings.homedir())?;
 
         let mut tokio_runtime = tokio::runtime::Runtime::new()
             .context(ErrorKind::Initialize(InitializeErrorReason::Tokio))?;
 
+        // How far the device clock is off from the time of DPS and IoT Hub is
+        // kept across restarts of the APIs too, so that it isn't learned again
+        // from a rejected request every time.
+        let clock_skew = ClockSkew::new();
+
         let (external_provisioning_info, external_provisioning) =
             get_external_provisioning_info(&settings, &mut tokio_runtime)?;
 
         set_iot_edge_env_vars(&settings, &external_provisioning_info)
             .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
 
+        startup_state.enter(StartupStage::CertificateInit);
+
+        // The HSM reads the device CA and identity certificates when it is
+        // initialized, so they have to be enrolled before that.
+        enroll_est_certificates(&settings, &mut tokio_runtime)?;
+
         let auto_generated_ca_lifetime_seconds =
             settings.certificates().auto_generated_ca_lifetime_seconds();
 
@@ -301,6 +534,19 @@ where
         ))?;
         info!("Finished initializing hsm.");
 
+        // Encrypted settings can only be decrypted now that the master encryption key exists,
+        // so everything before this, like the EST enrollment, sees them still encrypted.
+        let settings = decrypt_settings(&crypto, &settings)
+            .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+
+        // Exercise the HSM now and periodically once the device is provisioned, so
+        // that a degrading backend shows up in the logs, in the management API and
+        // in `iotedge check` before certificate issuance starts failing. The
+        // periodic runs also sign with the device key, which is only known then.
+        let hsm_self_test_path = Path::new(&settings.homedir()).join(HSM_SELF_TEST_FILENAME);
+        let hsm_self_test = SelfTestStatus::new();
+        record_hsm_self_test(crypto.self_test(), &hsm_self_test_path, &hsm_self_test);
+
         let (hyper_client, device_cert_identity_data) = prepare_httpclient_and_identity_data(
             hsm_lock.clone(),
             &settings,
@@ -317,10 +563,26 @@ where
                 InitializeErrorReason::CreateCacheDirectory,
             ))?;
 
+        if settings.certificates().device_cert().is_none() {
+            protect_quickstart_ca_keys(settings.homedir())?;
+            if reset_quickstart_ca(&crypto, settings.homedir())? {
+                if settings.certificates().restart_modules_on_ca_renewal() {
+                    fs::write(cache_subdir_path.join(EDGE_CA_RENEWAL_FILENAME), "")
+                        .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+                }
+
+                // Same as for a renewal, the HSM only generates the quickstart certificates
+                // when it is initialized, so the daemon exits to be restarted.
+                return Err(Error::from(ErrorKind::DeviceCaRenewal));
+            }
+        }
+
         macro_rules! start_edgelet {
-            ($key_store:ident, $provisioning_result:ident, $root_key:ident, $force_reprovision:ident, $id_cert_thumprint:ident, $provision:ident,) => {{
+            ($key_store:ident, $provisioning_result:ident, $root_key:ident, $force_reprovision:ident, $id_cert_thumprint:ident, $provision:ident, $registration:ident,) => {{
                 info!("Finished provisioning edge device.");
+                record_provisioning_state(&metrics, &settings, $provisioning_result.reconfigure());
 
+                startup_state.enter(StartupStage::RuntimeInit);
                 let runtime = init_runtime::<M>(
                     settings.clone(),
                     &mut tokio_runtime,
@@ -347,7 +609,7 @@ where
                 }
 
                 // Detect if the settings were changed and if the device needs to be reconfigured
-                check_settings_state::<M, _>(
+                let credentials_changed = check_settings_state::<M, _>(
                     &cache_subdir_path,
                     EDGE_SETTINGS_STATE_FILENAME,
                     &settings,
@@ -357,14 +619,66 @@ where
                     $id_cert_thumprint,
                 )?;
 
+                if credentials_changed {
+                    // The keys of the module identities are derived from the device key, so
+                    // they are updated in IoT Hub before the modules are started again. The
+                    // settings are only saved afterwards, so that an interrupted update is
+                    // retried on the next start.
+                    info!("Updating module identities for the new device credentials...");
+                    let id_man = hub_identity_manager(
+                        hyper_client.clone(),
+                        &$key_store,
+                        $root_key.clone(),
+                        $provisioning_result.hub_name(),
+                        $provisioning_result.device_id(),
+                        settings.parent_hostname(),
+                        settings.retry().iothub(),
+                        &clock_skew,
+                        settings.homedir(),
+                    )?;
+                    tokio_runtime.block_on(update_module_identities(id_man))?;
+                    save_settings_state(
+                        &cache_subdir_path,
+                        EDGE_SETTINGS_STATE_FILENAME,
+                        &settings,
+                        $id_cert_thumprint,
+                    )?;
+                    info!("Finished updating module identities.");
+                }
+
+                // The daemon exited earlier to have the device CA regenerated, so the
+                // modules are restarted to pick up certificates issued by the new CA.
+                if settings.certificates().restart_modules_on_ca_renewal()
+                    && fs::remove_file(cache_subdir_path.join(EDGE_CA_RENEWAL_FILENAME)).is_ok()
+                {
+                    restart_modules::<M>(&runtime, &mut tokio_runtime)?;
+                }
+
+                tokio_runtime.spawn(start_hsm_self_test(
+                    crypto.clone(),
+                    $root_key.clone(),
+                    hsm_self_test_path.clone(),
+                    hsm_self_test.clone(),
+                ));
+
                 let cfg = WorkloadData::new(
                     $provisioning_result.hub_name().to_string(),
                     $provisioning_result.device_id().to_string(),
                     IOTEDGE_ID_CERT_MAX_DURATION_SECS,
                     IOTEDGE_SERVER_CERT_MAX_DURATION_SECS,
                 );
+                startup_state.enter(StartupStage::Serving);
+
+                // The trust bundle and the server certificates issued to modules are cached
+                // across restarts of the APIs, until the CA certificates are rotated.
+                let cert_cache =
+                    CertificateCache::new(chrono::Duration::seconds(IOTEDGE_SERVER_CERT_CACHE_SECS));
+
                 // This "do-while" loop runs until a StartApiReturnStatus::Shutdown
                 // is received. If the TLS cert needs a restart, we will loop again.
+                // A reload of the configuration also starts the APIs again, with
+                // the new settings.
+                let mut settings = settings.clone();
                 loop {
                     let (code, should_reprovision) = start_api::<_, _, _, _, _, M>(
                         &settings,
@@ -372,10 +686,19 @@ where
                         &runtime,
                         &$key_store,
                         cfg.clone(),
+                        $provisioning_result.payload().cloned(),
                         $root_key.clone(),
                         make_shutdown_signal(),
                         &crypto,
                         &mut tokio_runtime,
+                        &metrics,
+                        &clock_skew,
+                        &hsm_self_test,
+                        startup_state,
+                        &lifecycle_hooks,
+                        $registration.clone(),
+                        attestation.clone(),
+                        &cert_cache,
                     )?;
 
                     if should_reprovision {
@@ -393,13 +716,39 @@ where
                         return Err(Error::from(ErrorKind::DeviceDeprovisioned))
                     }
 
-                    if code != StartApiReturnStatus::Restart {
+                    if code == StartApiReturnStatus::RenewCertificates {
+                        cert_cache.invalidate();
+                        renew_certificates::<M, _>(
+                            &settings,
+                            &runtime,
+                            &crypto,
+                            &cache_subdir_path,
+                            &mut tokio_runtime,
+                        )?;
+                    } else if code == StartApiReturnStatus::Reload {
+                        cert_cache.invalidate();
+                        if let Some(load_settings) = &load_settings {
+                            settings = reload_settings::<M>(
+                                settings,
+                                load_settings().and_then(|new_settings| {
+                                    Ok(decrypt_settings(&crypto, &new_settings).context(
+                                        ErrorKind::Initialize(InitializeErrorReason::LoadSettings),
+                                    )?)
+                                }),
+                                &runtime,
+                                &cache_subdir_path,
+                                &mut tokio_runtime,
+                                $id_cert_thumprint,
+                            )?;
+                        }
+                    } else if code != StartApiReturnStatus::Restart {
                         break;
                     }
                 }
             }};
         }
 
+        startup_state.enter(StartupStage::Provisioning);
         info!("Provisioning edge device...");
         let hybrid_id_subdir_path =
             Path::new(&settings.homedir()).join(EDGE_HYBRID_IDENTITY_SUBDIR);
@@ -432,6 +781,7 @@ where
                             force_module_reprovision,
                             None,
                             manual,
+                            None,
                         );
                     }
                     ManualAuthMethod::X509(x509) => {
@@ -463,6 +813,7 @@ where
                             force_module_reprovision,
                             thumbprint_op,
                             manual,
+                            None,
                         );
                     }
                 };
@@ -503,6 +854,7 @@ where
                                 force_module_reprovision,
                                 None,
                                 external_provisioning_val,
+                                None,
                             );
                         } else {
                             let (derived_key_store, tpm_key) = external_provision_tpm(hsm_lock)?;
@@ -513,6 +865,7 @@ where
                                 force_module_reprovision,
                                 None,
                                 external_provisioning_val,
+                                None,
                             );
                         }
                     }
@@ -539,25 +892,40 @@ where
                             force_module_reprovision,
                             thumbprint_op,
                             external_provisioning_val,
+                            None,
                         );
                     }
                 };
             }
             ProvisioningType::Dps(dps) => {
                 let dps_path = cache_subdir_path.join(EDGE_PROVISIONING_BACKUP_FILENAME);
+                let dps_endpoint =
+                    upstream_endpoint(dps.global_endpoint(), settings.parent_hostname())?;
 
                 match dps.attestation() {
                     AttestationMethod::Tpm(ref tpm) => {
                         info!("Starting provisioning edge device via TPM...");
-                        let (tpm_instance, dps_tpm) =
-                            dps_tpm_provision_init(&dps, hyper_client.clone(), tpm)?;
+                        let (tpm_instance, dps_tpm) = dps_tpm_provision_init(
+                            &dps,
+                            &dps_endpoint,
+                            hyper_client.clone(),
+                            tpm,
+                            settings.retry().provisioning(),
+                            &clock_skew,
+                        )?;
+                        let dps_tpm = Arc::new(dps_tpm);
+                        let tpm_hsm = TpmKeyStore::from_hsm(tpm_instance, hsm_lock).context(
+                            ErrorKind::Initialize(InitializeErrorReason::DpsProvisioningClient),
+                        )?;
                         let (key_store, provisioning_result, root_key) = dps_tpm_provision(
                             dps_path,
+                            &crypto,
                             &mut tokio_runtime,
-                            hsm_lock,
-                            tpm_instance,
+                            tpm_hsm.clone(),
                             &dps_tpm,
                         )?;
+                        let registration =
+                            dps_registration(dps_tpm.clone(), tpm_hsm, &provisioning_result);
 
                         start_edgelet!(
                             key_store,
@@ -566,22 +934,33 @@ where
                             force_module_reprovision,
                             None,
                             dps_tpm,
+                            registration,
                         );
                     }
                     AttestationMethod::SymmetricKey(ref symmetric_key_info) => {
                         info!("Starting provisioning edge device via symmetric key...");
                         let (memory_hsm, dps_symmetric_key) = dps_symmetric_key_provision_init(
                             &dps,
+                            &dps_endpoint,
                             hyper_client.clone(),
                             symmetric_key_info,
+                            settings.retry().provisioning(),
+                            &clock_skew,
                         )?;
+                        let dps_symmetric_key = Arc::new(dps_symmetric_key);
                         let (key_store, provisioning_result, root_key) =
                             dps_symmetric_key_provision(
                                 dps_path,
+                                &crypto,
                                 &mut tokio_runtime,
-                                memory_hsm,
+                                memory_hsm.clone(),
                                 &dps_symmetric_key,
                             )?;
+                        let registration = dps_registration(
+                            dps_symmetric_key.clone(),
+                            memory_hsm,
+                            &provisioning_result,
+                        );
 
                         start_edgelet!(
                             key_store,
@@ -590,6 +969,7 @@ where
                             force_module_reprovision,
                             None,
                             dps_symmetric_key,
+                            registration,
                         );
                     }
                     AttestationMethod::X509(ref x509_info) => {
@@ -600,19 +980,26 @@ where
 
                         let (memory_hsm, dps_x509) = dps_x509_provision_init(
                             &dps,
+                            &dps_endpoint,
                             hyper_client.clone(),
                             x509_info,
                             hybrid_identity_key,
-                            &id_data.common_name,
+                            &id_data,
+                            settings.retry().provisioning(),
+                            &clock_skew,
                         )?;
 
+                        let dps_x509 = Arc::new(dps_x509);
                         let (key_store, provisioning_result, root_key) = dps_x509_provision(
-                            memory_hsm,
+                            memory_hsm.clone(),
                             &dps_x509,
                             dps_path,
+                            &crypto,
                             &mut tokio_runtime,
                             id_data.thumbprint.clone(),
                         )?;
+                        let registration =
+                            dps_registration(dps_x509.clone(), memory_hsm, &provisioning_result);
                         let thumbprint_op = Some(id_data.thumbprint.as_str());
                         start_edgelet!(
                             key_store,
@@ -621,6 +1008,7 @@ where
                             force_module_reprovision,
                             thumbprint_op,
                             dps_x509,
+                            registration,
                         );
                     }
                 }
@@ -732,6 +1120,17 @@ where
                         ExternalProvisioningErrorReason::DownloadIdentityPrivateKey,
                     ),
                 ))?;
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+
+                    file.set_permissions(fs::Permissions::from_mode(0o600))
+                        .context(ErrorKind::Initialize(
+                            InitializeErrorReason::ExternalProvisioningClient(
+                                ExternalProvisioningErrorReason::DownloadIdentityPrivateKey,
+                            ),
+                        ))?;
+                }
                 file.write_all(&pk_bytes).context(ErrorKind::Initialize(
                     InitializeErrorReason::ExternalProvisioningClient(
                         ExternalProvisioningErrorReason::DownloadIdentityPrivateKey,
@@ -744,6 +1143,51 @@ where
     Ok(())
 }
 
+fn start_hsm_self_test<K>(
+    crypto: Crypto,
+    device_key: K,
+    path: PathBuf,
+    status: SelfTestStatus,
+) -> impl Future<Item = (), Error = ()>
+where
+    K: Sign + Send + 'static,
+{
+    let frequency = Duration::from_secs(HSM_SELF_TEST_FREQUENCY_SECS);
+    Interval::new(Instant::now(), frequency)
+        .map_err(|err| warn!("HSM self-test timer failed: {}", err))
+        .for_each(move |_| {
+            let report = crypto.self_test().with_result(self_test_sign(&device_key));
+            record_hsm_self_test(report, &path, &status);
+            Ok(())
+        })
+}
+
+fn record_hsm_self_test(report: SelfTestReport, path: &Path, status: &SelfTestStatus) {
+    if report.is_ok() {
+        debug!("HSM self-test passed");
+    } else {
+        for failure in report.failures() {
+            warn!(
+                "HSM self-test {} failed: {}",
+                failure.name(),
+                failure.error().unwrap_or_default()
+            );
+        }
+    }
+
+    let result = serde_json::to_vec(&report)
+        .map_err(failure::Error::from)
+        .and_then(|report| fs::write(path, report).map_err(failure::Error::from));
+    if let Err(err) = result {
+        warn!(
+            "Could not write HSM self-test report to {}: {}",
+            path.display(),
+            err
+        );
+    }
+    status.record(report);
+}
+
 fn set_iot_edge_env_vars<S>(
     settings: &S,
     provisioning_result: &Option<ProvisioningResult>,
@@ -762,7 +1206,8 @@ where
 
     match certificates.device_cert().as_ref() {
         None => {
-            info!("Transparent gateway certificates not found, operating in quick start mode...")
+            info!("Transparent gateway certificates not found, operating in quick start mode...");
+            warn!("{}", QUICKSTART_CA_WARNING);
         }
         Some(&c) => {
             let path = c.device_ca_cert().context(ErrorKind::Initialize(
@@ -774,14 +1219,19 @@ where
             );
             env::set_var(DEVICE_CA_CERT_KEY, path);
 
-            let path = c.device_ca_pk().context(ErrorKind::Initialize(
-                InitializeErrorReason::CertificateSettings,
-            ))?;
-            info!(
-                "Configuring the Device private key using {:?}.",
-                path.as_os_str()
-            );
-            env::set_var(DEVICE_CA_PK_KEY, path);
+            if let Some(uri) = c.device_ca_pk_pkcs11_uri() {
+                info!("Configuring the Device private key in the PKCS#11 token.");
+                env::set_var(DEVICE_CA_PK_KEY, uri.as_str());
+            } else {
+                let path = c.device_ca_pk().context(ErrorKind::Initialize(
+                    InitializeErrorReason::CertificateSettings,
+                ))?;
+                info!(
+                    "Configuring the Device private key using {:?}.",
+                    path.as_os_str()
+                );
+                env::set_var(DEVICE_CA_PK_KEY, path);
+            }
 
             let path = c.trusted_ca_certs().context(ErrorKind::Initialize(
                 InitializeErrorReason::CertificateSettings,
@@ -794,6 +1244,23 @@ where
         }
     };
 
+    if let Some(uri) = certificates
+        .workload_ca_pk()
+        .context(ErrorKind::Initialize(
+            InitializeErrorReason::CertificateSettings,
+        ))?
+    {
+        info!("Configuring the workload CA private key in the PKCS#11 token.");
+        env::set_var(WORKLOAD_CA_PK_KEY, uri.as_str());
+    }
+    if let Some(path) = certificates.pkcs11_lib_path() {
+        info!(
+            "Configuring the PKCS#11 library using {:?}.",
+            path.as_os_str()
+        );
+        env::set_var(PKCS11_LIB_PATH_KEY, path);
+    }
+
     match settings.provisioning().provisioning_type() {
         ProvisioningType::Manual(manual) => {
             if let ManualAuthMethod::X509(x509) = manual.authentication_method() {
@@ -802,10 +1269,15 @@ where
                 ))?;
                 env::set_var(DEVICE_IDENTITY_CERT_PATH_ENV_KEY, path.as_os_str());
 
-                let path = x509.identity_pk().context(ErrorKind::Initialize(
-                    InitializeErrorReason::IdentityCertificateSettings,
-                ))?;
-                env::set_var(DEVICE_IDENTITY_KEY_PATH_ENV_KEY, path.as_os_str());
+                if let Some(uri) = x509.identity_pk_pkcs11_uri() {
+                    info!("Configuring the device identity private key in the PKCS#11 token.");
+                    env::set_var(DEVICE_IDENTITY_KEY_PATH_ENV_KEY, uri.as_str());
+                } else {
+                    let path = x509.identity_pk().context(ErrorKind::Initialize(
+                        InitializeErrorReason::IdentityCertificateSettings,
+                    ))?;
+                    env::set_var(DEVICE_IDENTITY_KEY_PATH_ENV_KEY, path.as_os_str());
+                }
             }
         }
         ProvisioningType::External(_external) => {
@@ -846,6 +1318,11 @@ where
                     DPS_REGISTRATION_ID_ENV_KEY,
                     tpm.registration_id().to_string(),
                 );
+
+                if let Some(tcti) = tpm.tcti() {
+                    info!("Configuring the TPM TCTI as {}.", tcti);
+                    env::set_var(TPM_TCTI_ENV_KEY, tcti.to_string());
+                }
             }
             AttestationMethod::SymmetricKey(ref symmetric_key_info) => {
                 env::set_var(
@@ -858,15 +1335,19 @@ where
                     env::set_var(DPS_REGISTRATION_ID_ENV_KEY, val.to_string());
                 }
 
-                let path = x509_info.identity_cert().context(ErrorKind::Initialize(
+                let cert_path = x509_info.identity_cert().context(ErrorKind::Initialize(
                     InitializeErrorReason::IdentityCertificateSettings,
                 ))?;
-                env::set_var(DEVICE_IDENTITY_CERT_PATH_ENV_KEY, path.as_os_str());
-
-                let path = x509_info.identity_pk().context(ErrorKind::Initialize(
+                let key_path = x509_info.identity_pk().context(ErrorKind::Initialize(
                     InitializeErrorReason::IdentityCertificateSettings,
                 ))?;
-                env::set_var(DEVICE_IDENTITY_KEY_PATH_ENV_KEY, path.as_os_str());
+
+                if let (Some(cert_path), Some(key_path)) = (cert_path, key_path) {
+                    env::set_var(DEVICE_IDENTITY_CERT_PATH_ENV_KEY, cert_path.as_os_str());
+                    env::set_var(DEVICE_IDENTITY_KEY_PATH_ENV_KEY, key_path.as_os_str());
+                } else {
+                    info!("Using the device identity certificate and private key held by the HSM.");
+                }
             }
         },
     }
@@ -938,18 +1419,332 @@ fn prepare_httpclient_and_identity_data_for_x509_provisioning(
         InitializeErrorReason::InvalidDeviceCertCredentials,
     ))?;
 
-    let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?, Some(pem), None)
+    let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?, Some(pem.clone()), None)
         .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
 
     let cert_data = IdentityCertificateData {
         common_name,
         thumbprint,
+        pem,
     };
     info!("Finished initializing hsm X509 interface...");
 
     Ok((hyper_client, Some(cert_data)))
 }
 
+/// A certificate that is enrolled over EST and stored in files.
+struct EstEnrollment<'a> {
+    url: &'a Url,
+    common_name: &'a str,
+    usage: CertificateUsage,
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    /// Where to store the CA certificates of the EST server, if anywhere
+    trusted_ca_certs_path: Option<PathBuf>,
+}
+
+fn enroll_est_certificates<S>(
+    settings: &S,
+    tokio_runtime: &mut tokio::runtime::Runtime,
+) -> Result<(), Error>
+where
+    S: RuntimeSettings,
+{
+    let certificates = settings.certificates();
+    let est = match certificates.est() {
+        Some(est) => est,
+        None => return Ok(()),
+    };
+    let threshold = certificates.ca_renewal_threshold_seconds();
+
+    if let Some(url) = est.device_ca_url() {
+        let device_cert = certificates
+            .device_cert()
+            .ok_or(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?;
+        let enrollment = EstEnrollment {
+            url,
+            common_name: IOTEDGED_DEVICE_CA_COMMONNAME,
+            usage: CertificateUsage::Ca,
+            cert_path: device_cert.device_ca_cert().context(ErrorKind::Initialize(
+                InitializeErrorReason::CertificateSettings,
+            ))?,
+            key_path: device_cert.device_ca_pk().context(ErrorKind::Initialize(
+                InitializeErrorReason::CertificateSettings,
+            ))?,
+            trusted_ca_certs_path: Some(device_cert.trusted_ca_certs().context(
+                ErrorKind::Initialize(InitializeErrorReason::CertificateSettings),
+            )?),
+        };
+        enroll_est_certificate(est, &enrollment, threshold, tokio_runtime)?;
+    }
+
+    if let Some(url) = est.identity_url() {
+        let (common_name, cert_path, key_path) = match settings.provisioning().provisioning_type() {
+            ProvisioningType::Manual(manual) => match manual.authentication_method() {
+                ManualAuthMethod::X509(x509) => (
+                    x509.device_id(),
+                    x509.identity_cert().context(ErrorKind::Initialize(
+                        InitializeErrorReason::IdentityCertificateSettings,
+                    ))?,
+                    x509.identity_pk().context(ErrorKind::Initialize(
+                        InitializeErrorReason::IdentityCertificateSettings,
+                    ))?,
+                ),
+                ManualAuthMethod::DeviceConnectionString(_) => {
+                    return Err(Error::from(ErrorKind::Initialize(
+                        InitializeErrorReason::EstSettings,
+                    )))
+                }
+            },
+            ProvisioningType::Dps(dps) => match dps.attestation() {
+                AttestationMethod::X509(x509) => {
+                    let cert_path = x509.identity_cert().context(ErrorKind::Initialize(
+                        InitializeErrorReason::IdentityCertificateSettings,
+                    ))?;
+                    let key_path = x509.identity_pk().context(ErrorKind::Initialize(
+                        InitializeErrorReason::IdentityCertificateSettings,
+                    ))?;
+                    match (cert_path, key_path) {
+                        (Some(cert_path), Some(key_path)) => (
+                            x509.registration_id()
+                                .unwrap_or_else(|| settings.hostname()),
+                            cert_path,
+                            key_path,
+                        ),
+                        _ => {
+                            return Err(Error::from(ErrorKind::Initialize(
+                                InitializeErrorReason::EstSettings,
+                            )))
+                        }
+                    }
+                }
+                _ => {
+                    return Err(Error::from(ErrorKind::Initialize(
+                        InitializeErrorReason::EstSettings,
+                    )))
+                }
+            },
+            ProvisioningType::External(_) => {
+                return Err(Error::from(ErrorKind::Initialize(
+                    InitializeErrorReason::EstSettings,
+                )))
+            }
+        };
+
+        let enrollment = EstEnrollment {
+            url,
+            common_name,
+            usage: CertificateUsage::Identity,
+            cert_path,
+            key_path,
+            trusted_ca_certs_path: None,
+        };
+        enroll_est_certificate(est, &enrollment, threshold, tokio_runtime)?;
+    }
+
+    Ok(())
+}
+
+fn enroll_est_certificate(
+    est: &Est,
+    enrollment: &EstEnrollment<'_>,
+    threshold: u64,
+    tokio_runtime: &mut tokio::runtime::Runtime,
+) -> Result<(), Error> {
+    // A certificate that is still valid authenticates its own renewal, so the
+    // bootstrap credentials are only needed for the first enrollment.
+    let current = match read_est_certificate(&enrollment.cert_path, &enrollment.key_path) {
+        Some((identity, valid_to)) => {
+            let remaining = valid_to.timestamp() - chrono::Utc::now().timestamp();
+            #[allow(clippy::cast_possible_wrap)]
+            let threshold = threshold as i64;
+            if remaining > threshold {
+                info!(
+                    "Certificate {} is valid until {}.",
+                    enrollment.cert_path.display(),
+                    valid_to
+                );
+                return Ok(());
+            }
+            if remaining > 0 {
+                Some(identity)
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+
+    let trust_bundle = match est
+        .trusted_certs()
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?
+    {
+        Some(path) => Some(PemCertificate::new(
+            fs::read(path).context(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?,
+            None,
+            None,
+            None,
+        )),
+        None => None,
+    };
+    let reenroll = current.is_some();
+    let identity = match current {
+        Some(identity) => Some(identity),
+        None => est_bootstrap_identity(est)?,
+    };
+
+    let hyper_client = MaybeProxyClient::new(get_proxy_uri(None)?, identity, trust_bundle)
+        .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+    let mut client = EstClient::new(hyper_client, enrollment.url.clone());
+    if let Some(username) = est.username() {
+        client = client.with_basic_auth(
+            username.to_string(),
+            est.password().unwrap_or_default().to_string(),
+        );
+    }
+
+    let key = est::create_private_key()
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+    let csr = est::create_csr(enrollment.common_name, enrollment.usage, &key)
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+
+    let certs = if reenroll {
+        info!(
+            "Renewing certificate {} over EST...",
+            enrollment.cert_path.display()
+        );
+        match tokio_runtime.block_on(client.simple_reenroll(&csr)) {
+            Ok(certs) => certs,
+            Err(err) => {
+                // The certificate is still valid, so the device can start without
+                // the EST server, e.g. while it is offline, and renew it on the next start.
+                log_failure(Level::Warn, &err);
+                warn!(
+                    "Could not renew certificate {}, continuing with the current certificate.",
+                    enrollment.cert_path.display()
+                );
+                return Ok(());
+            }
+        }
+    } else {
+        info!(
+            "Enrolling certificate {} over EST...",
+            enrollment.cert_path.display()
+        );
+        tokio_runtime
+            .block_on(client.simple_enroll(&csr))
+            .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?
+    };
+
+    if let Some(path) = &enrollment.trusted_ca_certs_path {
+        let ca_certs = tokio_runtime
+            .block_on(client.ca_certs())
+            .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+        write_est_file(path, &certificates_to_pem(&ca_certs)?, false)?;
+    }
+
+    let key_pem = key
+        .private_key_to_pem_pkcs8()
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+    write_est_file(&enrollment.key_path, &key_pem, true)?;
+    write_est_file(&enrollment.cert_path, &certificates_to_pem(&certs)?, false)?;
+    info!("Finished enrolling certificate over EST.");
+
+    Ok(())
+}
+
+/// Reads a certificate enrolled earlier, along with when it expires. Anything
+/// that cannot be used to renew it, such as a key that does not belong to the
+/// certificate, counts as no certificate.
+fn read_est_certificate(
+    cert_path: &Path,
+    key_path: &Path,
+) -> Option<(PemCertificate, chrono::DateTime<chrono::Utc>)> {
+    let cert_pem = fs::read(cert_path).ok()?;
+    let key_pem = fs::read(key_path).ok()?;
+
+    let cert = X509Certificate::stack_from_pem(&cert_pem)
+        .ok()?
+        .into_iter()
+        .next()?;
+    let key = PKey::private_key_from_pem(&key_pem).ok()?;
+    if !cert.public_key().ok()?.public_eq(&key) {
+        return None;
+    }
+
+    // Asn1TimeRef can only be converted by printing it
+    let valid_to = chrono::NaiveDateTime::parse_from_str(
+        &cert.not_after().to_string(),
+        "%b %e %H:%M:%S %Y GMT",
+    )
+    .ok()?;
+    Some((
+        PemCertificate::new(cert_pem, Some(key_pem), None, None),
+        chrono::DateTime::from_utc(valid_to, chrono::Utc),
+    ))
+}
+
+fn est_bootstrap_identity(est: &Est) -> Result<Option<PemCertificate>, Error> {
+    let cert_path = est
+        .bootstrap_identity_cert()
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?;
+    let key_path = est
+        .bootstrap_identity_pk()
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?;
+
+    match (cert_path, key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = fs::read(cert_path)
+                .context(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?;
+            let key = fs::read(key_path)
+                .context(ErrorKind::Initialize(InitializeErrorReason::EstSettings))?;
+            Ok(Some(PemCertificate::new(cert, Some(key), None, None)))
+        }
+        (None, None) => Ok(None),
+        _ => Err(Error::from(ErrorKind::Initialize(
+            InitializeErrorReason::EstSettings,
+        ))),
+    }
+}
+
+fn certificates_to_pem(certs: &[X509Certificate]) -> Result<Vec<u8>, Error> {
+    let mut pem = vec![];
+    for cert in certs {
+        pem.extend(
+            cert.to_pem()
+                .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?,
+        );
+    }
+    Ok(pem)
+}
+
+fn write_est_file(path: &Path, contents: &[u8], private: bool) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .create(parent)
+            .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+    }
+
+    let mut file =
+        File::create(path).context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if private {
+            file.set_permissions(fs::Permissions::from_mode(0o600))
+                .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+        }
+    }
+    #[cfg(windows)]
+    let _ = private;
+
+    file.write_all(contents)
+        .context(ErrorKind::Initialize(InitializeErrorReason::EstEnrollment))?;
+    Ok(())
+}
+
 fn get_thumbprint<T: Certificate>(id_cert: &T) -> Result<String, Error> {
     let cert_pem = id_cert
         .pem()
@@ -1046,18 +1841,511 @@ where
     Ok(())
 }
 
-fn prepare_master_hybrid_identity_key<S, C>(
-    settings: &S,
-    crypto: &C,
-    subdir: &Path,
-    hybrid_id_filename: &str,
-    iv_filename: &str,
-    provisioning_result: Option<&ProvisioningResult>,
-) -> Result<(bool, Option<Vec<u8>>), Error>
-where
-    S: RuntimeSettings,
-    C: CreateCertificate + Decrypt + Encrypt + MakeRandom,
-{
+/// Restricts the private keys of the quickstart certificates to the user the
+/// daemon runs as. Keys written by earlier versions of the HSM could be
+/// readable by other users.
+/// The daemon runs as a dedicated user, so it can't update files in the home
+/// directory that are owned by another user, e.g. ones left behind by an older
+/// version of the daemon that ran as root. This fails early with a hint on how
+/// to migrate the home directory instead of failing in the middle of startup.
+fn check_homedir_ownership(homedir: &Path) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        // Safe because geteuid has no preconditions and can't fail.
+        let euid = unsafe { libc::geteuid() };
+        if euid != 0 {
+            if let Some(path) = foreign_owned_entry(homedir, euid) {
+                warn!(
+                    "{} is not owned by the user the daemon runs as. Run `sudo chown -R {} {}` to migrate the home directory.",
+                    path.display(),
+                    euid,
+                    homedir.display()
+                );
+                return Err(Error::from(ErrorKind::Initialize(
+                    InitializeErrorReason::HomeDirOwnership,
+                )));
+            }
+        }
+    }
+    #[cfg(windows)]
+    let _ = homedir;
+
+    Ok(())
+}
+
+/// Returns the first file or directory under `dir` that isn't owned by `uid`.
+#[cfg(unix)]
+fn foreign_owned_entry(dir: &Path, uid: u32) -> Option<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let entries = fs::read_dir(dir).ok()?;
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.uid() != uid {
+            return Some(path);
+        }
+        if metadata.is_dir() {
+            if let Some(path) = foreign_owned_entry(&path, uid) {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+fn protect_quickstart_ca_keys(homedir: &Path) -> Result<(), Error> {
+    let keys_dir = homedir.join(QUICKSTART_CA_KEYS_SUBDIR);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(&keys_dir, fs::Permissions::from_mode(0o700))
+            .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+        let entries = fs::read_dir(&keys_dir)
+            .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+        for entry in entries {
+            let path = entry
+                .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?
+                .path();
+            if path.is_file() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+                    .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+            }
+        }
+    }
+    #[cfg(windows)]
+    let _ = keys_dir;
+
+    Ok(())
+}
+
+/// The quickstart certificates are kept across restarts and only regenerated
+/// when they expire, or when the user creates the reset file in the home
+/// directory. Returns whether the certificates were removed to be regenerated.
+fn reset_quickstart_ca<C>(crypto: &C, homedir: &Path) -> Result<bool, Error>
+where
+    C: CreateCertificate + GetIssuerAlias,
+{
+    let reset_path = homedir.join(QUICKSTART_CA_RESET_FILENAME);
+    if !reset_path.exists() {
+        return Ok(false);
+    }
+
+    info!("Removing the quickstart CA certificates so that they are regenerated...");
+    destroy_workload_ca(crypto)?;
+    let device_ca_alias = crypto
+        .get_issuer_alias(CertificateIssuer::DeviceCa)
+        .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+    crypto
+        .destroy_certificate(device_ca_alias)
+        .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+    crypto
+        .destroy_certificate(QUICKSTART_OWNER_CA_ALIAS.to_string())
+        .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+
+    // Remove the request last, so that a failed reset is retried on the next start
+    fs::remove_file(&reset_path)
+        .context(ErrorKind::Initialize(InitializeErrorReason::QuickstartCa))?;
+    Ok(true)
+}
+
+/// Returns how long until the workload CA, or the device CA if the daemon can
+/// regenerate it, comes within `threshold` seconds of its expiry. `None` means
+/// that there is nothing the daemon can renew ahead of time.
+fn ca_renewal_delay<C>(
+    crypto: &C,
+    threshold: u64,
+    device_ca_renewable: bool,
+) -> Result<Option<Duration>, Error>
+where
+    C: CreateCertificate + GetIssuerAlias,
+{
+    if threshold == 0 {
+        return Ok(None);
+    }
+
+    let device_ca_alias = crypto
+        .get_issuer_alias(CertificateIssuer::DeviceCa)
+        .context(ErrorKind::CertificateRenewal)?;
+    let device_ca_expiry = crypto
+        .get_certificate(device_ca_alias)
+        .and_then(|cert| cert.get_valid_to())
+        .context(ErrorKind::CertificateRenewal)?;
+    let workload_ca_expiry = crypto
+        .get_certificate(IOTEDGED_CA_ALIAS.to_string())
+        .and_then(|cert| cert.get_valid_to())
+        .context(ErrorKind::CertificateRenewal)?;
+
+    let now = chrono::Utc::now().timestamp();
+    #[allow(clippy::cast_possible_wrap)]
+    let threshold = threshold as i64;
+
+    if !device_ca_renewable && device_ca_expiry.timestamp() - now <= threshold {
+        warn!(
+            "The device CA certificate expires on {:?} and has to be renewed manually.",
+            device_ca_expiry
+        );
+        return Ok(None);
+    }
+
+    let renew_at = cmp::min(workload_ca_expiry, device_ca_expiry).timestamp() - threshold;
+    #[allow(clippy::cast_sign_loss)]
+    let delay = cmp::max(renew_at - now, 0) as u64;
+    Ok(Some(Duration::from_secs(delay)))
+}
+
+/// The daemon can renew the device CA when it generates it itself, or when
+/// it enrolls it over EST.
+fn device_ca_renewable(certificates: &Certificates) -> bool {
+    certificates.device_cert().is_none()
+        || certificates.est().and_then(Est::device_ca_url).is_some()
+}
+
+// The expiry of the CA certificates is only reported, so a certificate that
+// can't be read is left out of the metrics rather than failing the start.
+fn record_certificate_expirations<C>(crypto: &C, metrics: &Metrics)
+where
+    C: CreateCertificate + GetIssuerAlias,
+{
+    for (name, expiration) in certificate_expirations(crypto) {
+        metrics.set_certificate_expiration(name, expiration);
+    }
+}
+
+// The expiries of the device CA and workload CA certificates, of those that
+// could be read.
+fn certificate_expirations<C>(crypto: &C) -> Vec<(&'static str, DateTime<Utc>)>
+where
+    C: CreateCertificate + GetIssuerAlias,
+{
+    let device_ca = crypto
+        .get_issuer_alias(CertificateIssuer::DeviceCa)
+        .and_then(|alias| crypto.get_certificate(alias));
+    let workload_ca = crypto.get_certificate(IOTEDGED_CA_ALIAS.to_string());
+
+    let mut expirations = vec![];
+    for (name, cert) in [("device_ca", device_ca), ("workload_ca", workload_ca)] {
+        match cert.and_then(|cert| cert.get_valid_to()) {
+            Ok(expiration) => expirations.push((name, expiration)),
+            Err(err) => debug!(
+                "Could not read the expiry of the {} certificate: {}",
+                name, err
+            ),
+        }
+    }
+    expirations
+}
+
+/// Reports the health of the daemon in the hub as configured by `settings`,
+/// right away and then at every interval, until `stop` is signaled. A report
+/// that fails is logged, and doesn't keep the next one from being made.
+fn report_health<M, HC, K, C>(
+    settings: &HealthReportSettings,
+    runtime: M,
+    id_man: HubIdentityManager<DerivedKeyStore<K>, HC, K>,
+    crypto: C,
+    watchdog_status: WatchdogStatus,
+    stop: Receiver<()>,
+) -> impl Future<Item = (), Error = ()>
+where
+    M: ModuleRuntime + Clone + Send + 'static,
+    HC: ClientImpl + 'static,
+    K: Sign + Clone + Send + Sync + 'static,
+    C: CreateCertificate + GetIssuerAlias + Clone + Send + 'static,
+{
+    let target = settings.target();
+    info!(
+        "Reporting the health of the daemon in the hub every {} seconds.",
+        settings.interval().as_secs()
+    );
+
+    Interval::new(Instant::now(), settings.interval())
+        .map_err(|err| warn!("Health report timer failed: {}", err))
+        .for_each(move |_| {
+            let id_man = id_man.clone();
+            let expirations = certificate_expirations(&crypto);
+            let watchdog_status = watchdog_status.clone();
+
+            // The warnings of the runtime, like low disk space, are left out
+            // of the report if the runtime can't be reached.
+            runtime
+                .system_info()
+                .then(move |system_info| {
+                    let warnings = system_info
+                        .map(|system_info| system_info.warnings().to_vec())
+                        .unwrap_or_default();
+                    let health = expirations.into_iter().fold(
+                        DaemonHealth::new(
+                            edgelet_core::version_with_source_version().to_string(),
+                            Utc::now(),
+                        )
+                        .with_warnings(warnings)
+                        .with_watchdog(
+                            watchdog_status.state().to_string(),
+                            watchdog_status.restarts(),
+                        ),
+                        |health, (name, expiration)| {
+                            health.with_certificate_expiration(name, expiration)
+                        },
+                    );
+                    id_man.report_health(target, EDGE_RUNTIME_MODULEID, &health)
+                })
+                .then(|result| {
+                    match result {
+                        Ok(()) => debug!("Reported the health of the daemon in the hub."),
+                        Err(err) => log_failure(Level::Warn, &err),
+                    }
+                    Ok(())
+                })
+        })
+        .select(stop.then(|_| Ok(())))
+        .then(|_| Ok(()))
+}
+
+/// Removes the modules that have no module identity anymore, as configured by
+/// `settings`, right away and then at every interval, until `stop` is
+/// signaled.
+fn remove_orphaned_modules<M, HC, K>(
+    settings: &OrphanedModuleSettings,
+    runtime: M,
+    id_man: HubIdentityManager<DerivedKeyStore<K>, HC, K>,
+    journal: OperationJournal,
+    stop: Receiver<()>,
+) -> impl Future<Item = (), Error = ()>
+where
+    M: ModuleRuntime + Clone + Send + 'static,
+    for<'r> &'r <M as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+    HC: ClientImpl + 'static,
+    K: Sign + Clone + Send + Sync + 'static,
+{
+    info!(
+        "Removing orphaned modules every {} seconds.",
+        settings.interval().as_secs()
+    );
+
+    Interval::new(Instant::now(), settings.interval())
+        .map_err(|err| warn!("Orphaned module timer failed: {}", err))
+        .for_each(move |_| {
+            edgelet_core::remove_orphaned_modules(
+                &runtime,
+                &id_man,
+                &journal,
+                EDGE_RUNTIME_MODULE_NAME,
+            )
+            .then(|result| {
+                match result {
+                    Ok(removed) => {
+                        if !removed.is_empty() {
+                            info!("Removed orphaned modules {}", removed.join(", "));
+                        }
+                    }
+                    Err(err) => log_failure(Level::Warn, &err),
+                }
+                Ok(())
+            })
+        })
+        .select(stop.then(|_| Ok(())))
+        .then(|_| Ok(()))
+}
+
+/// Publishes the anonymized diagnostics of the daemon to `sink`, right away
+/// and then at every interval, until `stop` is signaled. A publication that
+/// fails is logged, and doesn't keep the next one from being made.
+#[allow(clippy::too_many_arguments)]
+fn publish_diagnostics<M, S>(
+    settings: &DiagnosticsSettings,
+    sink: S,
+    runtime: M,
+    provisioning: &'static str,
+    metrics: Metrics,
+    watchdog_status: WatchdogStatus,
+    startup_state: StartupState,
+    stop: Receiver<()>,
+) -> impl Future<Item = (), Error = ()>
+where
+    M: ModuleRuntime + Clone + Send + 'static,
+    S: DiagnosticsSink + Clone + Send + 'static,
+    S::PublishFuture: 'static,
+{
+    info!(
+        "Publishing diagnostics of the daemon every {} seconds.",
+        settings.interval().as_secs()
+    );
+
+    Interval::new(Instant::now(), settings.interval())
+        .map_err(|err| warn!("Diagnostics timer failed: {}", err))
+        .for_each(move |_| {
+            let report = DiagnosticsReport::new(edgelet_core::version().to_string(), Utc::now())
+                .with_provisioning(provisioning.to_string())
+                .with_watchdog_restarts(watchdog_status.restarts())
+                .with_startup_failure(startup_state.last_failure().map(|failure| failure.stage()))
+                .with_errors(edgelet_http::metrics::error_counts(&metrics));
+
+            // The platform is left out of the report if the runtime can't be
+            // reached.
+            let report = runtime.system_info().then(|system_info| {
+                Ok::<_, ()>(match system_info {
+                    Ok(system_info) => report.with_platform(
+                        system_info.os_type().to_string(),
+                        system_info.architecture().to_string(),
+                    ),
+                    Err(_) => report,
+                })
+            });
+            let sink = sink.clone();
+            report
+                .and_then(move |report| sink.publish(&report).then(Ok))
+                .map(|result| match result {
+                    Ok(()) => debug!("Published the diagnostics of the daemon."),
+                    Err(err) => log_failure(Level::Warn, &err),
+                })
+        })
+        .select(stop.then(|_| Ok(())))
+        .then(|_| Ok(()))
+}
+
+/// Modules can quote the state of the device with its TPM when the device is
+/// provisioned with TPM attestation. The TPM is reached over the TCTI of the
+/// attestation, or else through the resource manager of the kernel, which the
+/// HSM library shares it with.
+fn tpm_attestation<S>(settings: &S, hsm_lock: &Arc<HsmLock>) -> Option<TpmAttestation>
+where
+    S: RuntimeSettings,
+{
+    match settings.provisioning().provisioning_type() {
+        ProvisioningType::Dps(dps) => match dps.attestation() {
+            AttestationMethod::Tpm(tpm) => Some(TpmAttestation::new(
+                tpm.tcti()
+                    .cloned()
+                    .unwrap_or_else(|| TpmTcti::Device(PathBuf::from(TPM_RESOURCE_MANAGER_DEVICE))),
+                hsm_lock.clone(),
+            )),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn record_provisioning_state<S>(metrics: &Metrics, settings: &S, status: ReprovisioningStatus)
+where
+    S: RuntimeSettings,
+{
+    metrics.set_gauge(
+        PROVISIONING_METRIC,
+        PROVISIONING_HELP,
+        &[
+            ("method", provisioning_method(settings)),
+            ("status", &format!("{:?}", status)),
+        ],
+        1.0,
+    );
+}
+
+fn provisioning_method<S>(settings: &S) -> &'static str
+where
+    S: RuntimeSettings,
+{
+    match settings.provisioning().provisioning_type() {
+        ProvisioningType::Manual(_) => "manual",
+        ProvisioningType::Dps(_) => "dps",
+        ProvisioningType::External(_) => "external",
+    }
+}
+
+fn renew_certificates<M, C>(
+    settings: &M::Settings,
+    runtime: &M::ModuleRuntime,
+    crypto: &C,
+    subdir: &Path,
+    tokio_runtime: &mut tokio::runtime::Runtime,
+) -> Result<(), Error>
+where
+    M: MakeModuleRuntime + 'static,
+    C: CreateCertificate + GetIssuerAlias,
+{
+    let certificates = settings.certificates();
+
+    if device_ca_renewable(certificates) {
+        let device_ca_alias = crypto
+            .get_issuer_alias(CertificateIssuer::DeviceCa)
+            .context(ErrorKind::CertificateRenewal)?;
+        let device_ca_expiry = crypto
+            .get_certificate(device_ca_alias.clone())
+            .and_then(|cert| cert.get_valid_to())
+            .context(ErrorKind::CertificateRenewal)?;
+
+        #[allow(clippy::cast_possible_wrap)]
+        let threshold = certificates.ca_renewal_threshold_seconds() as i64;
+        if device_ca_expiry.timestamp() - chrono::Utc::now().timestamp() <= threshold {
+            destroy_workload_ca(crypto)?;
+            if certificates.device_cert().is_none() {
+                info!("Removing the device CA certificate so that it is regenerated...");
+                crypto
+                    .destroy_certificate(device_ca_alias)
+                    .context(ErrorKind::CertificateRenewal)?;
+            }
+            if certificates.restart_modules_on_ca_renewal() {
+                fs::write(subdir.join(EDGE_CA_RENEWAL_FILENAME), "")
+                    .context(ErrorKind::CertificateRenewal)?;
+            }
+
+            // The HSM only generates or loads the device CA when it is initialized, so
+            // return an error here to let the daemon exit with an error code. `systemd`
+            // restarts the daemon, which then regenerates or re-enrolls the device CA
+            // and issues a new workload CA certificate.
+            return Err(Error::from(ErrorKind::DeviceCaRenewal));
+        }
+    }
+
+    info!("Renewing the workload CA certificate...");
+    destroy_workload_ca(crypto)?;
+    prepare_workload_ca(crypto)?;
+    info!("Finished renewing the workload CA certificate.");
+
+    if certificates.restart_modules_on_ca_renewal() {
+        restart_modules::<M>(runtime, tokio_runtime)?;
+    }
+    Ok(())
+}
+
+fn restart_modules<M>(
+    runtime: &M::ModuleRuntime,
+    tokio_runtime: &mut tokio::runtime::Runtime,
+) -> Result<(), Error>
+where
+    M: MakeModuleRuntime + 'static,
+{
+    info!("Restarting modules to renew their certificates...");
+    let modules = tokio_runtime
+        .block_on(runtime.list())
+        .context(ErrorKind::CertificateRenewal)?;
+    for module in modules {
+        if let Err(err) = tokio_runtime.block_on(runtime.restart(module.name())) {
+            warn!("Could not restart module {}", module.name());
+            log_failure(Level::Warn, &err);
+        }
+    }
+    info!("Finished restarting modules.");
+    Ok(())
+}
+
+fn prepare_master_hybrid_identity_key<S, C>(
+    settings: &S,
+    crypto: &C,
+    subdir: &Path,
+    hybrid_id_filename: &str,
+    iv_filename: &str,
+    provisioning_result: Option<&ProvisioningResult>,
+) -> Result<(bool, Option<Vec<u8>>), Error>
+where
+    S: RuntimeSettings,
+    C: CreateCertificate + Decrypt + Encrypt + MakeRandom,
+{
     if get_provisioning_auth_method(settings, provisioning_result)? == ProvisioningAuthMethod::X509
     {
         let (new_key_created, hybrid_id_key) =
@@ -1210,22 +2498,58 @@ where
     Ok(base64::encode(&Sha256::digest_str(&s)))
 }
 
+// Computes the digest of the settings without the device credentials. The
+// hub and device id in a connection string are kept, so that moving the device
+// is not mistaken for a credential rotation.
+fn compute_settings_base_digest<S>(settings: &S) -> Result<String, DiffError>
+where
+    S: RuntimeSettings + Serialize,
+{
+    let mut value = serde_json::to_value(settings)?;
+    for pointer in &["/provisioning/authentication", "/provisioning/attestation"] {
+        if let Some(serde_json::Value::Object(section)) = value.pointer_mut(pointer) {
+            for key in PROVISIONING_CREDENTIAL_SETTINGS {
+                section.remove(*key);
+            }
+
+            if let Some(serde_json::Value::String(connection_string)) =
+                section.get_mut("device_connection_string")
+            {
+                *connection_string = connection_string
+                    .split(';')
+                    .filter(|part| !part.starts_with(SHARED_ACCESS_KEY_PARAMETER))
+                    .collect::<Vec<_>>()
+                    .join(";");
+            }
+        }
+    }
+    let s = serde_json::to_string(&value)?;
+    Ok(base64::encode(&Sha256::digest_str(&s)))
+}
+
 fn diff_with_cached<S>(settings: &S, path: &Path, id_cert_thumbprint: Option<&str>) -> bool
 where
     S: RuntimeSettings + Serialize,
 {
-    fn diff_with_cached_inner<S>(
-        cached_settings: &S,
+    diff_digest_with_cached(compute_settings_digest(settings, id_cert_thumbprint), path)
+}
+
+fn diff_base_with_cached<S>(settings: &S, path: &Path) -> bool
+where
+    S: RuntimeSettings + Serialize,
+{
+    diff_digest_with_cached(compute_settings_base_digest(settings), path)
+}
+
+fn diff_digest_with_cached(digest: Result<String, DiffError>, path: &Path) -> bool {
+    fn diff_digest_with_cached_inner(
+        digest: Result<String, DiffError>,
         path: &Path,
-        id_cert_thumbprint: Option<&str>,
-    ) -> Result<bool, DiffError>
-    where
-        S: RuntimeSettings + Serialize,
-    {
+    ) -> Result<bool, DiffError> {
         let mut file = OpenOptions::new().read(true).open(path)?;
         let mut buffer = String::new();
         file.read_to_string(&mut buffer)?;
-        let encoded = compute_settings_digest(cached_settings, id_cert_thumbprint)?;
+        let encoded = digest?;
         if encoded == buffer {
             debug!("Config state matches supplied config.");
             Ok(false)
@@ -1234,7 +2558,7 @@ where
         }
     }
 
-    match diff_with_cached_inner(settings, path, id_cert_thumbprint) {
+    match diff_digest_with_cached_inner(digest, path) {
         Ok(result) => result,
 
         Err(err) => {
@@ -1261,6 +2585,9 @@ impl From<serde_json::Error> for DiffError {
     }
 }
 
+// Returns whether only the device credentials changed, in which case the
+// modules are kept and their identities need to be updated before the settings
+// are saved.
 fn check_settings_state<M, C>(
     subdir: &Path,
     filename: &str,
@@ -1269,7 +2596,7 @@ fn check_settings_state<M, C>(
     crypto: &C,
     tokio_runtime: &mut tokio::runtime::Runtime,
     id_cert_thumbprint: Option<&str>,
-) -> Result<(), Error>
+) -> Result<bool, Error>
 where
     M: MakeModuleRuntime + 'static,
     M::Settings: Serialize,
@@ -1277,13 +2604,20 @@ where
 {
     info!("Detecting if configuration file has changed...");
     let path = subdir.join(filename);
+    let base_path = subdir.join(EDGE_SETTINGS_BASE_STATE_FILENAME);
     let mut reconfig_reqd = false;
+    let mut credentials_changed = false;
     let diff = diff_with_cached(settings, &path, id_cert_thumbprint);
-    if diff {
+    if diff && diff_base_with_cached(settings, &base_path) {
         info!("Change to configuration file detected.");
         reconfig_reqd = true;
     } else {
-        info!("No change to configuration file detected.");
+        if diff {
+            info!("Change to device credentials detected. Existing modules will be kept.");
+            credentials_changed = true;
+        } else {
+            info!("No change to configuration file detected.");
+        }
 
         #[allow(clippy::single_match_else)]
         match prepare_workload_ca(crypto) {
@@ -1304,8 +2638,11 @@ where
             tokio_runtime,
             id_cert_thumbprint,
         )?;
+    } else if !credentials_changed {
+        // Settings saved by earlier versions of the daemon have no base state yet.
+        save_settings_base_state(subdir, settings)?;
     }
-    Ok(())
+    Ok(credentials_changed && !reconfig_reqd)
 }
 
 fn get_provisioning_auth_method<S>(
@@ -1370,8 +2707,6 @@ where
     // configuration and shouldn't stall the current configuration because of that
     let _u = fs::remove_dir_all(subdir);
 
-    let path = subdir.join(filename);
-
     DirBuilder::new()
         .recursive(true)
         .create(subdir)
@@ -1382,16 +2717,195 @@ where
     // regenerate the workload CA certificate
     destroy_workload_ca(crypto)?;
     prepare_workload_ca(crypto)?;
-    let mut file =
-        File::create(path).context(ErrorKind::Initialize(InitializeErrorReason::SaveSettings))?;
+    save_settings_state(subdir, filename, settings, id_cert_thumbprint)
+}
+
+fn save_settings_state<S>(
+    subdir: &Path,
+    filename: &str,
+    settings: &S,
+    id_cert_thumbprint: Option<&str>,
+) -> Result<(), Error>
+where
+    S: RuntimeSettings + Serialize,
+{
     let digest = compute_settings_digest(settings, id_cert_thumbprint)
         .context(ErrorKind::Initialize(InitializeErrorReason::SaveSettings))?;
-    file.write_all(digest.as_bytes())
+    fs::write(subdir.join(filename), digest)
+        .context(ErrorKind::Initialize(InitializeErrorReason::SaveSettings))?;
+
+    save_settings_base_state(subdir, settings)
+}
+
+fn save_settings_base_state<S>(subdir: &Path, settings: &S) -> Result<(), Error>
+where
+    S: RuntimeSettings + Serialize,
+{
+    let digest = compute_settings_base_digest(settings)
+        .context(ErrorKind::Initialize(InitializeErrorReason::SaveSettings))?;
+    fs::write(subdir.join(EDGE_SETTINGS_BASE_STATE_FILENAME), digest)
         .context(ErrorKind::Initialize(InitializeErrorReason::SaveSettings))?;
 
     Ok(())
 }
 
+// Applies the parts of the new configuration that can change without restarting
+// the daemon. The APIs and the watchdog are started again with the returned
+// settings, and the edge runtime module is recreated if its settings changed.
+// Configurations that can't be loaded, or that change anything else, are
+// ignored until the daemon is restarted.
+fn reload_settings<M>(
+    settings: M::Settings,
+    new_settings: Result<M::Settings, Error>,
+    runtime: &M::ModuleRuntime,
+    subdir: &Path,
+    tokio_runtime: &mut tokio::runtime::Runtime,
+    id_cert_thumbprint: Option<&str>,
+) -> Result<M::Settings, Error>
+where
+    M: MakeModuleRuntime + 'static,
+    M::Settings: Serialize,
+    for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
+{
+    let new_settings = match new_settings {
+        Ok(new_settings) => new_settings,
+        Err(err) => {
+            warn!("Could not reload the configuration, keeping the current one.");
+            log_failure(Level::Warn, &err);
+            return Ok(settings);
+        }
+    };
+
+    let value = serde_json::to_value(&settings)
+        .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+    let new_value = serde_json::to_value(&new_settings)
+        .context(ErrorKind::Initialize(InitializeErrorReason::LoadSettings))?;
+    if without_reloadable_settings(value.clone()) != without_reloadable_settings(new_value.clone())
+    {
+        warn!(
+            "The configuration changed in a way that needs a restart of the daemon, keeping the current one."
+        );
+        return Ok(settings);
+    }
+
+    logging::set_log_settings(&logging::configured_log_settings(&new_settings));
+
+    if value.get("agent") != new_value.get("agent") {
+        info!("Edge runtime settings changed, recreating the edge runtime module...");
+        let removed = runtime
+            .remove(EDGE_RUNTIME_MODULE_NAME)
+            .or_else(|err| match (&err).into() {
+                ModuleRuntimeErrorReason::NotFound => Ok(()),
+                ModuleRuntimeErrorReason::Other => Err(err),
+            });
+        if let Err(err) = tokio_runtime.block_on(removed) {
+            warn!("Could not remove the edge runtime module, it keeps its current settings.");
+            log_failure(Level::Warn, &err);
+        }
+    }
+
+    // The reloaded settings are the ones in effect, so they must not be taken
+    // for a change to the configuration on the next start.
+    save_settings_state(
+        subdir,
+        EDGE_SETTINGS_STATE_FILENAME,
+        &new_settings,
+        id_cert_thumbprint,
+    )?;
+
+    info!("Reloaded the configuration.");
+    Ok(new_settings)
+}
+
+fn without_reloadable_settings(mut value: serde_json::Value) -> serde_json::Value {
+    for pointer in RELOADABLE_SETTINGS {
+        let (parent, key) = pointer.split_at(pointer.rfind('/').unwrap_or_default());
+        if let Some(serde_json::Value::Object(section)) = value.pointer_mut(parent) {
+            section.remove(&key[1..]);
+        }
+    }
+    value
+}
+
+// Updates the keys of the module identities managed by this device, which are
+// derived from the device key.
+fn update_module_identities<K, HC>(
+    id_man: HubIdentityManager<DerivedKeyStore<K>, HC, K>,
+) -> impl Future<Item = (), Error = Error>
+where
+    K: Sign + Clone + Send + Sync + 'static,
+    HC: ClientImpl + 'static,
+{
+    id_man
+        .list()
+        .and_then(move |identities| {
+            let specs = identities
+                .into_iter()
+                .filter(|identity| {
+                    identity.auth_type() == IdentityAuthType::Sas
+                        && (identity.module_id().starts_with('$')
+                            || identity.managed_by() == EDGE_MODULE_MANAGED_BY)
+                })
+                .map(|identity| {
+                    info!("Updating identity for module {}", identity.module_id());
+                    let mut spec = IdentitySpec::new(identity.module_id().to_string())
+                        .with_generation_id(identity.generation_id().to_string());
+                    if !identity.managed_by().is_empty() {
+                        spec = spec.with_managed_by(identity.managed_by().to_string());
+                    }
+                    spec
+                })
+                .collect();
+            id_man.update_many(specs).map(|_| ())
+        })
+        .map_err(|err| {
+            Error::from(err.context(ErrorKind::Initialize(
+                InitializeErrorReason::UpdateModuleIdentities,
+            )))
+        })
+}
+
+fn hub_identity_manager<HC, K>(
+    hyper_client: HC,
+    key_store: &DerivedKeyStore<K>,
+    root_key: K,
+    hub_name: &str,
+    device_id: &str,
+    parent_hostname: Option<&str>,
+    retry_policy: Option<&RetryPolicy>,
+    clock_skew: &ClockSkew,
+    homedir: &Path,
+) -> Result<HubIdentityManager<DerivedKeyStore<K>, HC, K>, Error>
+where
+    HC: ClientImpl + 'static,
+    K: Sign + Clone + Send + Sync + 'static,
+{
+    let hostname = format!("https://{}", hub_name);
+    let endpoint =
+        Url::parse(&hostname).context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
+    // The tokens are still issued for the hub when the requests go through a parent device.
+    let token_source = SasTokenSource::new(hub_name.to_string(), device_id.to_string(), root_key)
+        .with_clock_skew(clock_skew.clone());
+    let http_client = HttpClient::new(
+        hyper_client,
+        Some(token_source),
+        IOTHUB_API_VERSION.to_string(),
+        upstream_endpoint(&endpoint, parent_hostname)?,
+    )
+    .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?
+    .with_clock_skew(clock_skew.clone());
+    let mut device_client = DeviceClient::new(http_client, device_id.to_string())
+        .context(ErrorKind::Initialize(InitializeErrorReason::DeviceClient))?;
+    if let Some(retry_policy) = retry_policy {
+        device_client = device_client.with_retry_policy(retry_policy.clone());
+    }
+
+    // Identities whose keys were regenerated keep their new keys across restarts.
+    let key_rotations = KeyRotations::load(homedir.join(KEY_ROTATIONS_FILENAME));
+
+    Ok(HubIdentityManager::new(key_store.clone(), device_client).with_key_rotations(key_rotations))
+}
+
 #[allow(clippy::too_many_arguments)]
 fn start_api<HC, K, F, C, W, M>(
     settings: &M::Settings,
@@ -1399,19 +2913,30 @@ fn start_api<HC, K, F, C, W, M>(
     runtime: &M::ModuleRuntime,
     key_store: &DerivedKeyStore<K>,
     workload_config: W,
+    provisioning_payload: Option<serde_json::Value>,
     root_key: K,
     shutdown_signal: F,
     crypto: &C,
     tokio_runtime: &mut tokio::runtime::Runtime,
+    metrics: &Metrics,
+    clock_skew: &ClockSkew,
+    hsm_self_test: &SelfTestStatus,
+    startup_state: &StartupState,
+    lifecycle_hooks: &LifecycleHooks,
+    dps_registration: Option<DpsRegistration>,
+    attestation: Option<TpmAttestation>,
+    cert_cache: &CertificateCache,
 ) -> Result<(StartApiReturnStatus, bool), Error>
 where
     F: Future<Item = (), Error = ()> + Send + 'static,
-    HC: ClientImpl + 'static,
+    HC: ClientImpl + Clone + 'static,
     K: Sign + Clone + Send + Sync + 'static,
     C: CreateCertificate
         + Decrypt
         + Encrypt
+        + GetIssuerAlias
         + GetTrustBundle
+        + MakeRandom
         + MasterEncryptionKey
         + Clone
         + Send
@@ -1429,22 +2954,56 @@ where
 {
     let hub_name = workload_config.iot_hub_name().to_string();
     let device_id = workload_config.device_id().to_string();
-    let hostname = format!("https://{}", hub_name);
-    let token_source = SasTokenSource::new(hub_name.clone(), device_id.clone(), root_key);
-    let http_client = HttpClient::new(
+    let (mgmt_stop_and_reprovision_tx, mgmt_stop_and_reprovision_rx) = mpsc::unbounded();
+
+    let diagnostics_client = hyper_client.clone();
+
+    // IoT Hub reporting the device as disabled or deleted, for example after DPS
+    // reassigned it to another hub, also shuts down the daemon to reprovision the device.
+    let id_man = hub_identity_manager(
         hyper_client,
-        Some(token_source),
-        IOTHUB_API_VERSION.to_string(),
-        Url::parse(&hostname).context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?,
-    )
-    .context(ErrorKind::Initialize(InitializeErrorReason::HttpClient))?;
-    let device_client = DeviceClient::new(http_client, device_id.clone())
-        .context(ErrorKind::Initialize(InitializeErrorReason::DeviceClient))?;
-    let id_man = HubIdentityManager::new(key_store.clone(), device_client);
+        key_store,
+        root_key,
+        &hub_name,
+        &device_id,
+        settings.parent_hostname(),
+        settings.retry().iothub(),
+        clock_skew,
+        settings.homedir(),
+    )?;
+    let id_man = if settings.provisioning().dynamic_reprovisioning() {
+        id_man.with_reprovision_signal(mgmt_stop_and_reprovision_tx.clone())
+    } else {
+        id_man
+    };
+
+    // Module operations the daemon was in the middle of when it last stopped
+    // are finished or rolled back before the management API takes new ones.
+    // Failures are logged by the journal, and don't keep the daemon from
+    // starting.
+    let journal = OperationJournal::load(settings.homedir().join(OPERATION_JOURNAL_FILENAME));
+    let _ = tokio_runtime.block_on(journal.replay(runtime, &id_man));
+
+    // Modules with a priority are started in its order before the edge agent,
+    // which would otherwise start them all at once when it applies the
+    // deployment.
+    let boot_order = BootOrder::load(settings.homedir().join(BOOT_ORDER_FILENAME));
+    let _ = tokio_runtime.block_on(boot_order.start_modules(runtime, settings.agent().name()));
+
+    // The edge agent's module listings are answered from the snapshot taken
+    // before the daemon restarted until the runtime lists the modules again.
+    let module_snapshot = ModuleSnapshot::load(settings.homedir().join(MODULE_SNAPSHOT_FILENAME));
+    tokio_runtime.spawn(refresh_module_snapshot(
+        module_snapshot.clone(),
+        runtime.clone(),
+    ));
 
     let (mgmt_tx, mgmt_rx) = oneshot::channel();
-    let (mgmt_stop_and_reprovision_tx, mgmt_stop_and_reprovision_rx) = mpsc::unbounded();
     let (work_tx, work_rx) = oneshot::channel();
+    let (metrics_tx, metrics_rx) = oneshot::channel();
+    let (mgmt_listening_tx, mgmt_listening_rx) = oneshot::channel();
+    let (work_listening_tx, work_listening_rx) = oneshot::channel();
+    let (keepalive_tx, keepalive_rx) = oneshot::channel::<()>();
 
     let edgelet_cert_props = CertificateProperties::new(
         settings.certificates().auto_generated_ca_lifetime_seconds(),
@@ -1477,13 +3036,167 @@ where
 
     let cert_manager = Arc::new(cert_manager);
 
+    record_certificate_expirations(crypto, metrics);
+
+    let crl = CertificateRevocationList::load(&settings.homedir().join(WORKLOAD_CRL_FILENAME))
+        .context(ErrorKind::Initialize(
+            InitializeErrorReason::CertificateRevocationList,
+        ))?;
+
+    // Renew the CA certificates ahead of their expiry, before the module
+    // certificates issued by them stop being trusted.
+    let certificates = settings.certificates();
+    let ca_renewal = match ca_renewal_delay(
+        crypto,
+        certificates.ca_renewal_threshold_seconds(),
+        device_ca_renewable(certificates),
+    )? {
+        Some(delay) => {
+            info!(
+                "CA certificates are due for renewal in {} seconds.",
+                delay.as_secs()
+            );
+            Either::A(
+                Delay::new(Instant::now() + delay)
+                    .map_err(|err| Error::from(err.context(ErrorKind::CertificateRenewal))),
+            )
+        }
+        None => Either::B(future::empty()),
+    };
+
+    let restart_signal = restart_rx
+        .then(|res| {
+            if res.is_err() {
+                debug!("The restart signal failed, shutting down.");
+                return Ok(StartApiReturnStatus::Shutdown);
+            }
+            Ok(StartApiReturnStatus::Restart)
+        })
+        .select(ca_renewal.map(|()| StartApiReturnStatus::RenewCertificates))
+        .map(|(status, _)| status)
+        .map_err(|(err, _)| err)
+        .select(signal::reload().then(|_| -> Result<_, Error> { Ok(StartApiReturnStatus::Reload) }))
+        .map(|(status, _)| status)
+        .map_err(|(err, _)| err);
+
+    // The management API activity tells the watchdog whether the edge runtime
+    // is making progress, and the management API reports back what the
+    // watchdog makes of it.
+    let activity = ActivityMonitor::new();
+    let watchdog_status = WatchdogStatus::new();
+
+    let agent_bootstrap = agent_bootstrap(&hub_name, &device_id, settings);
+
+    // The daemon reports its own health in the hub, if so configured, so that
+    // it can be seen even while the edge runtime module is down.
+    let (health_tx, health_rx) = oneshot::channel();
+    if let Some(health_report) = settings.health_report() {
+        tokio_runtime.spawn(report_health(
+            health_report,
+            runtime.clone(),
+            id_man.clone(),
+            crypto.clone(),
+            watchdog_status.clone(),
+            health_rx,
+        ));
+    }
+
+    // Modules that no identity corresponds to anymore are removed, if so
+    // configured, so that they don't keep holding on to their ports.
+    let (orphans_tx, orphans_rx) = oneshot::channel();
+    if let Some(orphaned_modules) = settings.orphaned_modules() {
+        tokio_runtime.spawn(remove_orphaned_modules(
+            orphaned_modules,
+            runtime.clone(),
+            id_man.clone(),
+            journal.clone(),
+            orphans_rx,
+        ));
+    }
+
+    // Anonymized diagnostics of the daemon are published for the health
+    // analytics of a fleet of devices, but only with the explicit consent of
+    // the owner of the device.
+    let (diagnostics_tx, diagnostics_rx) = oneshot::channel();
+    if let Some(diagnostics) = settings.diagnostics() {
+        if diagnostics.consent() {
+            let provisioning = provisioning_method(settings);
+            match diagnostics.sink() {
+                DiagnosticsSinkSettings::IotHub => tokio_runtime.spawn(publish_diagnostics(
+                    diagnostics,
+                    id_man.clone(),
+                    runtime.clone(),
+                    provisioning,
+                    metrics.clone(),
+                    watchdog_status.clone(),
+                    startup_state.clone(),
+                    diagnostics_rx,
+                )),
+                DiagnosticsSinkSettings::Http { url } => tokio_runtime.spawn(publish_diagnostics(
+                    diagnostics,
+                    HttpDiagnosticsSink::new(diagnostics_client, url.clone()),
+                    runtime.clone(),
+                    provisioning,
+                    metrics.clone(),
+                    watchdog_status.clone(),
+                    startup_state.clone(),
+                    diagnostics_rx,
+                )),
+            };
+        } else {
+            info!("Diagnostics are configured without consent, so none are published.");
+        }
+    }
+
+    // Updates of the edge runtime module are recorded by the management API,
+    // and rolled back by the watchdog if the updated module keeps failing.
+    let agent_rollback = settings.watchdog().rollback_period().map(|period| {
+        AgentRollback::load(
+            EDGE_RUNTIME_MODULE_NAME.to_string(),
+            period,
+            settings.homedir().join(AGENT_ROLLBACK_FILENAME),
+        )
+    });
+
+    let api_tokens = management_api_tokens(settings.listen());
+
+    // The log settings can be changed through the management API while the
+    // daemon is running.
+    let log_controller = LogController::new(logging::log_settings, logging::set_log_settings);
+
+    // A reboot or shutdown of the device asked for through the management
+    // API stops the edge runtime module the way a shutdown of the daemon
+    // does, and then the other modules, before the host takes the action.
+    let (device_action_tx, device_action_rx) = mpsc::unbounded();
+    let device_action = Arc::new(Mutex::new(None));
+
     let mgmt = start_management::<_, _, _, M>(
         settings,
         runtime,
         &id_man,
         mgmt_rx,
+        mgmt_listening_tx,
         cert_manager.clone(),
+        crypto.clone(),
         mgmt_stop_and_reprovision_tx,
+        settings.device_actions(),
+        device_action_tx,
+        provisioning_payload,
+        crl.clone(),
+        activity.clone(),
+        watchdog_status.clone(),
+        agent_rollback.clone(),
+        agent_bootstrap.clone(),
+        log_controller,
+        startup_state.clone(),
+        lifecycle_hooks.clone(),
+        journal,
+        api_tokens.clone(),
+        metrics.clone(),
+        clock_skew.clone(),
+        boot_order.clone(),
+        module_snapshot,
+        hsm_self_test.clone(),
     );
 
     let workload = start_workload::<_, _, _, _, M>(
@@ -1491,19 +3204,39 @@ where
         key_store,
         runtime,
         work_rx,
+        work_listening_tx,
         crypto,
-        cert_manager,
+        cert_manager.clone(),
         workload_config,
+        crl,
+        cert_cache.clone(),
+        api_tokens,
+        attestation,
+        metrics.clone(),
     );
 
+    let metrics_server = match settings.listen().metrics_uri() {
+        Some(url) => Either::A(start_metrics(
+            url,
+            settings.listen().min_tls_version(),
+            metrics.clone(),
+            &cert_manager,
+            metrics_rx,
+        )?),
+        None => Either::B(future::ok(())),
+    };
+
     let (runt_tx, runt_rx) = oneshot::channel();
     let edge_rt = start_runtime::<_, _, M>(
         runtime.clone(),
         &id_man,
-        &hub_name,
-        &device_id,
+        &agent_bootstrap,
         &settings,
         runt_rx,
+        activity,
+        watchdog_status,
+        agent_rollback,
+        metrics.clone(),
     )?;
 
     // This mpsc sender/receiver is used for getting notifications from the mgmt service
@@ -1525,6 +3258,23 @@ where
         futures::future::Either::A(future::empty())
     };
 
+    // A device that started from the provisioning backup is also reprovisioned
+    // once DPS assigned it to another hub in the meantime.
+    let registration_changed = match dps_registration {
+        Some(registration) => Either::A(registration.then(|result| match result {
+            Ok(ref changed) if **changed => {
+                info!("DPS assigned the device to another hub, shutting down to reprovision.");
+                Either::A(future::ok(None))
+            }
+            _ => Either::B(future::empty()),
+        })),
+        None => Either::B(future::empty()),
+    };
+    let mgmt_stop_and_reprovision_signaled = mgmt_stop_and_reprovision_signaled
+        .select(registration_changed)
+        .map(|(reprovision, _)| reprovision)
+        .map_err(|(err, _)| err);
+
     let edge_rt_with_mgmt_signal = edge_rt.select2(mgmt_stop_and_reprovision_signaled).then(
         |res: Result<
             Either<((), _), (Option<Error>, _)>,
@@ -1552,41 +3302,121 @@ where
     // Wait for the watchdog to finish, and then send signal to the workload and management services.
     // This way the edgeAgent can finish shutting down all modules.
     let edge_rt_with_cleanup = edge_rt_with_mgmt_signal
-        .select2(restart_rx)
+        .select2(restart_signal)
         .then(move |res| {
             mgmt_tx.send(()).unwrap_or(());
             work_tx.send(()).unwrap_or(());
+            metrics_tx.send(()).unwrap_or(());
+            keepalive_tx.send(()).unwrap_or(());
+            health_tx.send(()).unwrap_or(());
+            orphans_tx.send(()).unwrap_or(());
+            diagnostics_tx.send(()).unwrap_or(());
 
             // A -> EdgeRt + Mgmt Stop and Reprovision Signal Future
-            // B -> Restart Signal Future
+            // B -> Restart or CA Renewal Signal Future
             match res {
                 Ok(Either::A((x, _))) => Ok((StartApiReturnStatus::Shutdown, x.1)).into_future(),
-                Ok(Either::B(_)) => Ok((StartApiReturnStatus::Restart, false)).into_future(),
+                Ok(Either::B((status, _))) => Ok((status, false)).into_future(),
                 Err(Either::A((err, _))) => Err(err).into_future(),
-                Err(Either::B(_)) => {
-                    debug!("The restart signal failed, shutting down.");
-                    Ok((StartApiReturnStatus::Shutdown, false)).into_future()
-                }
+                Err(Either::B((err, _))) => Err(err).into_future(),
             }
         });
 
-    let shutdown = shutdown_signal.map(move |_| {
-        debug!("shutdown signaled");
-        // Signal the watchdog to shutdown
-        runt_tx.send(()).unwrap_or(());
+    let requested_action = device_action.clone();
+    let device_action_signaled = device_action_rx.into_future().then(move |result| {
+        if let Ok((Some(action), _)) = result {
+            info!("Stopping the modules to {} the device.", action);
+            *requested_action
+                .lock()
+                .expect("Failed to acquire the device action lock") = Some(action);
+            Either::A(future::ok(()))
+        } else {
+            Either::B(future::empty())
+        }
     });
+
+    let shutdown = shutdown_signal
+        .select(device_action_signaled)
+        .then(move |_| {
+            debug!("shutdown signaled");
+            // Signal the watchdog to shutdown
+            runt_tx.send(()).unwrap_or(());
+            Ok(())
+        });
     tokio_runtime.spawn(shutdown);
 
+    // systemd considers the daemon started once both APIs are listening, and
+    // restarts it if the event loop stops sending keepalives from then on.
+    let watchdog_settings = settings.watchdog().clone();
+    let notify_service_manager = mgmt_listening_rx
+        .join(work_listening_rx)
+        .then(move |listening| match listening {
+            Ok(_) => {
+                notify::ready();
+                Either::A(notify::keepalive(&watchdog_settings))
+            }
+            Err(_) => Either::B(future::ok(())),
+        })
+        .select(keepalive_rx.then(|_| Ok(())))
+        .then(|_| Ok(()));
+    tokio_runtime.spawn(notify_service_manager);
+
     let services = mgmt
-        .join4(workload, edge_rt_with_cleanup, expiration_timer)
+        .join5(
+            workload,
+            edge_rt_with_cleanup,
+            expiration_timer,
+            metrics_server,
+        )
         .then(|result| match result {
-            Ok(((), (), (code, should_reprovision), ())) => Ok((code, should_reprovision)),
+            Ok(((), (), (code, should_reprovision), (), ())) => Ok((code, should_reprovision)),
             Err(err) => Err(err),
         });
     let (restart_code, should_reprovision) = tokio_runtime.block_on(services)?;
+
+    let device_action = *device_action
+        .lock()
+        .expect("Failed to acquire the device action lock");
+    if let Some(action) = device_action {
+        let _ = tokio_runtime.block_on(boot_order.stop_modules(runtime, settings.agent().name()));
+        power::take(action)?;
+        return Ok((StartApiReturnStatus::Shutdown, false));
+    }
+
+    // The other modules keep running while the daemon is stopped, but those
+    // that depend on each other are stopped in order, in case the host is
+    // shutting down.
+    if restart_code == StartApiReturnStatus::Shutdown && !should_reprovision {
+        let _ = tokio_runtime
+            .block_on(boot_order.stop_dependent_modules(runtime, settings.agent().name()));
+    }
+
     Ok((restart_code, should_reprovision))
 }
 
+/// Lists the modules into `snapshot`, retrying until the runtime answers.
+fn refresh_module_snapshot<R>(
+    snapshot: ModuleSnapshot,
+    runtime: R,
+) -> impl Future<Item = (), Error = ()>
+where
+    R: ModuleRuntime + Send + 'static,
+    <R::Module as Module>::Config: Serialize,
+{
+    future::loop_fn((), move |()| {
+        snapshot.refresh(&runtime).then(|result| match result {
+            Ok(()) => Either::A(future::ok(future::Loop::Break(()))),
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                Either::B(
+                    Delay::new(Instant::now() + MODULE_SNAPSHOT_REFRESH_INTERVAL)
+                        .then(|_| Ok(future::Loop::Continue(()))),
+                )
+            }
+        })
+    })
+}
+
 fn init_runtime<M>(
     settings: M::Settings,
     tokio_runtime: &mut tokio::runtime::Runtime,
@@ -1660,12 +3490,44 @@ fn manual_provision_x509(
     tokio_runtime.block_on(provision)
 }
 
+// A nested edge device sends its DPS and IoT Hub requests to its parent device
+// instead, which relays them upstream.
+fn upstream_endpoint(endpoint: &Url, parent_hostname: Option<&str>) -> Result<Url, Error> {
+    let mut endpoint = endpoint.clone();
+    if let Some(parent_hostname) = parent_hostname {
+        endpoint
+            .set_host(Some(parent_hostname))
+            .context(ErrorKind::Initialize(InitializeErrorReason::ParentHostname))?;
+    }
+    Ok(endpoint)
+}
+
+// Reads the custom allocation payload that is sent to DPS with the registration request.
+fn dps_payload(dps: &Dps) -> Result<Option<serde_json::Value>, Error> {
+    let path = match dps
+        .payload()
+        .context(ErrorKind::Initialize(InitializeErrorReason::DpsPayload))?
+    {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let payload =
+        fs::read(path).context(ErrorKind::Initialize(InitializeErrorReason::DpsPayload))?;
+    let payload = serde_json::from_slice(&payload)
+        .context(ErrorKind::Initialize(InitializeErrorReason::DpsPayload))?;
+    Ok(Some(payload))
+}
+
 fn dps_x509_provision_init<HC>(
     dps: &Dps,
+    endpoint: &Url,
     hyper_client: HC,
     x509_info: &X509AttestationInfo,
     hybrid_identity_key: Option<Vec<u8>>,
-    common_name: &str,
+    id_data: &IdentityCertificateData,
+    retry_policy: Option<&RetryPolicy>,
+    clock_skew: &ClockSkew,
 ) -> Result<(MemoryKeyStore, DpsX509Provisioning<HC>), Error>
 where
     HC: 'static + ClientImpl,
@@ -1673,7 +3535,7 @@ where
     // use the client provided registration id if provided else use the CN
     let reg_id = match x509_info.registration_id() {
         Some(id) => id.to_string(),
-        None => common_name.to_string(),
+        None => id_data.common_name.clone(),
     };
 
     let key_bytes = hybrid_identity_key
@@ -1685,14 +3547,19 @@ where
         .context(ErrorKind::ActivateSymmetricKey)?;
     let dps_x509 = DpsX509Provisioning::new(
         hyper_client,
-        dps.global_endpoint().clone(),
+        endpoint.clone(),
         dps.scope_id().to_string(),
         reg_id,
         DPS_API_VERSION.to_string(),
     )
     .context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
-    ))?;
+    ))?
+    .with_payload(dps_payload(dps)?)
+    .with_retry_policy(retry_policy.cloned())
+    .with_transport(dps.transport())
+    .with_identity_certificate(id_data.pem.clone())
+    .with_clock_skew(clock_skew.clone());
 
     Ok((memory_hsm, dps_x509))
 }
@@ -1702,13 +3569,14 @@ fn dps_x509_provision<HC>(
     memory_hsm: MemoryKeyStore,
     dps: &DpsX509Provisioning<HC>,
     backup_path: PathBuf,
+    crypto: &Crypto,
     tokio_runtime: &mut tokio::runtime::Runtime,
     cert_thumbprint: String,
 ) -> Result<(DerivedKeyStore<MemoryKey>, ProvisioningResult, MemoryKey), Error>
 where
     HC: 'static + ClientImpl,
 {
-    let provision_with_file_backup = BackupProvisioning::new(dps, backup_path);
+    let provision_with_file_backup = BackupProvisioning::new(dps, backup_path, crypto.clone());
 
     let provision = provision_with_file_backup
         .provision(memory_hsm.clone())
@@ -1817,15 +3685,28 @@ fn external_provision_x509(
 
 fn dps_symmetric_key_provision_init<HC>(
     provisioning: &Dps,
+    endpoint: &Url,
     hyper_client: HC,
     key: &SymmetricKeyAttestationInfo,
+    retry_policy: Option<&RetryPolicy>,
+    clock_skew: &ClockSkew,
 ) -> Result<(MemoryKeyStore, DpsSymmetricKeyProvisioning<HC>), Error>
 where
     HC: 'static + ClientImpl,
 {
     let mut memory_hsm = MemoryKeyStore::new();
-    let key_bytes =
-        base64::decode(key.symmetric_key()).context(ErrorKind::SymmetricKeyMalformed)?;
+    let key_bytes = match (key.symmetric_key(), key.group_symmetric_key()) {
+        (Some(symmetric_key), _) => {
+            base64::decode(symmetric_key).context(ErrorKind::SymmetricKeyMalformed)?
+        }
+        (None, Some(group_symmetric_key)) => {
+            let group_key_bytes =
+                base64::decode(group_symmetric_key).context(ErrorKind::SymmetricKeyMalformed)?;
+            derive_device_key(&group_key_bytes, key.registration_id())
+                .context(ErrorKind::ActivateSymmetricKey)?
+        }
+        (None, None) => return Err(Error::from(ErrorKind::SymmetricKeyMalformed)),
+    };
 
     memory_hsm
         .activate_identity_key(KeyIdentity::Device, "primary".to_string(), key_bytes)
@@ -1833,19 +3714,24 @@ where
 
     let dps = DpsSymmetricKeyProvisioning::new(
         hyper_client,
-        provisioning.global_endpoint().clone(),
+        endpoint.clone(),
         provisioning.scope_id().to_string(),
         key.registration_id().to_string(),
         DPS_API_VERSION.to_string(),
     )
     .context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
-    ))?;
+    ))?
+    .with_payload(dps_payload(provisioning)?)
+    .with_retry_policy(retry_policy.cloned())
+    .with_transport(provisioning.transport())
+    .with_clock_skew(clock_skew.clone());
     Ok((memory_hsm, dps))
 }
 
 fn dps_symmetric_key_provision<HC>(
     backup_path: PathBuf,
+    crypto: &Crypto,
     tokio_runtime: &mut tokio::runtime::Runtime,
     memory_hsm: MemoryKeyStore,
     dps: &DpsSymmetricKeyProvisioning<HC>,
@@ -1853,7 +3739,7 @@ fn dps_symmetric_key_provision<HC>(
 where
     HC: 'static + ClientImpl,
 {
-    let provision_with_file_backup = BackupProvisioning::new(dps, backup_path);
+    let provision_with_file_backup = BackupProvisioning::new(dps, backup_path, crypto.clone());
 
     let provision =
         provision_with_file_backup
@@ -1877,12 +3763,21 @@ where
 
 fn dps_tpm_provision_init<HC>(
     provisioning: &Dps,
+    endpoint: &Url,
     hyper_client: HC,
     tpm_attestation_info: &TpmAttestationInfo,
+    retry_policy: Option<&RetryPolicy>,
+    clock_skew: &ClockSkew,
 ) -> Result<(Tpm, DpsTpmProvisioning<HC>), Error>
 where
     HC: 'static + ClientImpl,
 {
+    if provisioning.transport() != DpsTransport::Https {
+        return Err(Error::from(ErrorKind::Initialize(
+            InitializeErrorReason::DpsTpmTransport,
+        )));
+    }
+
     let tpm = Tpm::new().context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
     ))?;
@@ -1909,7 +3804,7 @@ where
     ))?;
     let dps = DpsTpmProvisioning::new(
         hyper_client,
-        provisioning.global_endpoint().clone(),
+        endpoint.clone(),
         provisioning.scope_id().to_string(),
         tpm_attestation_info.registration_id().to_string(),
         DPS_API_VERSION.to_string(),
@@ -1918,24 +3813,24 @@ where
     )
     .context(ErrorKind::Initialize(
         InitializeErrorReason::DpsProvisioningClient,
-    ))?;
+    ))?
+    .with_payload(dps_payload(provisioning)?)
+    .with_retry_policy(retry_policy.cloned())
+    .with_clock_skew(clock_skew.clone());
     Ok((tpm, dps))
 }
 
 fn dps_tpm_provision<HC>(
     backup_path: PathBuf,
+    crypto: &Crypto,
     tokio_runtime: &mut tokio::runtime::Runtime,
-    hsm_lock: Arc<HsmLock>,
-    tpm: Tpm,
+    tpm_hsm: TpmKeyStore,
     dps: &DpsTpmProvisioning<HC>,
 ) -> Result<(DerivedKeyStore<TpmKey>, ProvisioningResult, TpmKey), Error>
 where
     HC: 'static + ClientImpl,
 {
-    let tpm_hsm = TpmKeyStore::from_hsm(tpm, hsm_lock).context(ErrorKind::Initialize(
-        InitializeErrorReason::DpsProvisioningClient,
-    ))?;
-    let provision_with_file_backup = BackupProvisioning::new(dps, backup_path);
+    let provision_with_file_backup = BackupProvisioning::new(dps, backup_path, crypto.clone());
     let provision = provision_with_file_backup
         .provision(tpm_hsm.clone())
         .map_err(|err| {
@@ -1956,13 +3851,70 @@ where
     tokio_runtime.block_on(provision)
 }
 
+/// Whether registering with DPS in the background assigned the device to
+/// another hub or device id than the provisioning backup it started from.
+type DpsRegistration = Shared<Box<dyn Future<Item = bool, Error = ()> + Send>>;
+
+/// Keeps registering with DPS when the daemon had to start from the backup of an
+/// earlier provisioning, so that a device that booted without connectivity still
+/// picks up a new assignment once DPS can be reached. The backup is left alone,
+/// so that the daemon reconfigures the device when it starts again.
+fn dps_registration<P>(
+    provision: Arc<P>,
+    key_activator: P::Hsm,
+    provisioning_result: &ProvisioningResult,
+) -> Option<DpsRegistration>
+where
+    P: Provision + Send + Sync + 'static,
+    P::Hsm: Clone + Send + 'static,
+{
+    if !provisioning_result.restored() {
+        return None;
+    }
+
+    warn!(
+        "Started with the provisioning backup, registering with DPS will be retried every {} seconds.",
+        DPS_REGISTRATION_RETRY_INTERVAL.as_secs()
+    );
+    let hub_name = provisioning_result.hub_name().to_string();
+    let device_id = provisioning_result.device_id().to_string();
+
+    let registration = future::loop_fn((), move |()| {
+        let provision = provision.clone();
+        let key_activator = key_activator.clone();
+        let hub_name = hub_name.clone();
+        let device_id = device_id.clone();
+
+        Delay::new(Instant::now() + DPS_REGISTRATION_RETRY_INTERVAL)
+            .then(move |_| provision.provision(key_activator))
+            .then(move |result| match result {
+                Ok(prov_result) => {
+                    info!("Successful DPS provisioning.");
+                    Ok(future::Loop::Break(
+                        prov_result.hub_name() != hub_name || prov_result.device_id() != device_id,
+                    ))
+                }
+                Err(err) => {
+                    log_failure(Level::Warn, &err);
+                    Ok(future::Loop::Continue(()))
+                }
+            })
+    });
+
+    let registration: Box<dyn Future<Item = bool, Error = ()> + Send> = Box::new(registration);
+    Some(registration.shared())
+}
+
 fn start_runtime<K, HC, M>(
     runtime: M::ModuleRuntime,
     id_man: &HubIdentityManager<DerivedKeyStore<K>, HC, K>,
-    hostname: &str,
-    device_id: &str,
+    agent_bootstrap: &AgentBootstrap,
     settings: &M::Settings,
     shutdown: Receiver<()>,
+    activity: ActivityMonitor,
+    watchdog_status: WatchdogStatus,
+    agent_rollback: Option<AgentRollback<<M::ModuleRuntime as ModuleRuntime>::Config>>,
+    metrics: Metrics,
 ) -> Result<impl Future<Item = (), Error = Error>, Error>
 where
     K: 'static + Sign + Clone + Send + Sync,
@@ -1975,7 +3927,7 @@ where
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
 {
     let spec = settings.agent().clone();
-    let env = build_env(spec.env(), hostname, device_id, settings);
+    let env = build_env(spec.env(), agent_bootstrap);
     let spec = ModuleSpec::<<M::ModuleRuntime as ModuleRuntime>::Config>::new(
         EDGE_RUNTIME_MODULE_NAME.to_string(),
         spec.type_().to_string(),
@@ -1985,7 +3937,16 @@ where
     )
     .context(ErrorKind::Initialize(InitializeErrorReason::EdgeRuntime))?;
 
-    let watchdog = Watchdog::new(runtime, id_man.clone(), settings.watchdog().max_retries());
+    let mut watchdog = Watchdog::new(runtime, id_man.clone(), settings.watchdog().clone())
+        .with_activity_monitor(activity)
+        .with_status(watchdog_status)
+        .with_metrics(metrics);
+    if let Some(restart_policy) = settings.retry().watchdog() {
+        watchdog = watchdog.with_restart_policy(restart_policy.clone());
+    }
+    if let Some(agent_rollback) = agent_rollback {
+        watchdog = watchdog.with_rollback(agent_rollback);
+    }
     let runtime_future = watchdog
         .run_until(spec, EDGE_RUNTIME_MODULEID, shutdown.map_err(|_| ()))
         .map_err(Error::from);
@@ -1993,45 +3954,78 @@ where
     Ok(runtime_future)
 }
 
-// Add the environment variables needed by the EdgeAgent.
-fn build_env<S>(
-    spec_env: &HashMap<String, String>,
-    hostname: &str,
-    device_id: &str,
-    settings: &S,
-) -> HashMap<String, String>
+// What the EdgeAgent needs to connect to the hub and to the daemon. It is
+// passed to the EdgeAgent in environment variables, and served on the
+// management API.
+fn agent_bootstrap<S>(hostname: &str, device_id: &str, settings: &S) -> AgentBootstrap
 where
     S: RuntimeSettings,
 {
+    #[cfg(any(
+        feature = "runtime-docker",
+        feature = "runtime-cri",
+        feature = "runtime-podman",
+        feature = "runtime-process"
+    ))]
+    let (workload_uri, management_uri) = (
+        settings.connect().workload_uri().to_string(),
+        settings.connect().management_uri().to_string(),
+    );
+    #[cfg(feature = "runtime-kubernetes")]
+    let (workload_uri, management_uri) = (
+        format!(
+            "http://localhost:{}",
+            settings.connect().workload_uri().port().unwrap_or(80u16)
+        ),
+        format!(
+            "http://localhost:{}",
+            settings.connect().management_uri().port().unwrap_or(80u16)
+        ),
+    );
+
+    AgentBootstrap::new(
+        hostname.to_string(),
+        device_id.to_string(),
+        EDGE_RUNTIME_MODULEID.to_string(),
+        settings.hostname().to_lowercase(),
+        AUTH_SCHEME.to_string(),
+        workload_uri,
+        management_uri,
+    )
+    .with_parent_hostname(settings.parent_hostname().map(str::to_lowercase))
+}
+
+// Add the environment variables needed by the EdgeAgent.
+fn build_env(
+    spec_env: &HashMap<String, String>,
+    bootstrap: &AgentBootstrap,
+) -> HashMap<String, String> {
     let mut env = HashMap::new();
-    env.insert(HOSTNAME_KEY.to_string(), hostname.to_string());
+    env.insert(
+        HOSTNAME_KEY.to_string(),
+        bootstrap.iothub_hostname().to_string(),
+    );
     env.insert(
         GATEWAY_HOSTNAME_KEY.to_string(),
-        settings.hostname().to_string().to_lowercase(),
+        bootstrap.gateway_hostname().to_string(),
     );
-    env.insert(DEVICEID_KEY.to_string(), device_id.to_string());
-    env.insert(MODULEID_KEY.to_string(), EDGE_RUNTIME_MODULEID.to_string());
-
-    #[cfg(feature = "runtime-docker")]
-    let (workload_uri, management_uri) = (
-        settings.connect().workload_uri().to_string(),
-        settings.connect().management_uri().to_string(),
+    if let Some(parent_hostname) = bootstrap.parent_hostname() {
+        env.insert(PARENT_HOSTNAME_KEY.to_string(), parent_hostname.to_string());
+    }
+    env.insert(DEVICEID_KEY.to_string(), bootstrap.device_id().to_string());
+    env.insert(MODULEID_KEY.to_string(), bootstrap.module_id().to_string());
+    env.insert(
+        WORKLOAD_URI_KEY.to_string(),
+        bootstrap.workload_uri().to_string(),
     );
-    #[cfg(feature = "runtime-kubernetes")]
-    let (workload_uri, management_uri) = (
-        format!(
-            "http://localhost:{}",
-            settings.connect().workload_uri().port().unwrap_or(80u16)
-        ),
-        format!(
-            "http://localhost:{}",
-            settings.connect().management_uri().port().unwrap_or(80u16)
-        ),
+    env.insert(
+        MANAGEMENT_URI_KEY.to_string(),
+        bootstrap.management_uri().to_string(),
+    );
+    env.insert(
+        AUTHSCHEME_KEY.to_string(),
+        bootstrap.auth_scheme().to_string(),
     );
-
-    env.insert(WORKLOAD_URI_KEY.to_string(), workload_uri);
-    env.insert(MANAGEMENT_URI_KEY.to_string(), management_uri);
-    env.insert(AUTHSCHEME_KEY.to_string(), AUTH_SCHEME.to_string());
     env.insert(
         EDGE_RUNTIME_MODE_KEY.to_string(),
         EDGE_RUNTIME_MODE.to_string(),
@@ -2043,23 +4037,73 @@ where
     env
 }
 
+// The callers of a management API that listens on TCP can't be told apart by
+// their process, so they have to present API tokens when those are set up.
+fn management_api_tokens(listen: &Listen) -> Option<ApiTokens> {
+    let uri = listen.management_uri();
+    let tcp = match uri.scheme() {
+        "http" | "https" | "tcp" => true,
+        _ => false,
+    };
+
+    match listen.management_auth() {
+        Some(auth) if tcp => Some(ApiTokens::new(auth)),
+        Some(_) => {
+            info!(
+                "The management API listens on {}, API tokens are not required.",
+                uri
+            );
+            None
+        }
+        None if tcp => {
+            warn!(
+                "The management API listens on {} without authentication. Set listen.management_auth in the config file to require API tokens.",
+                uri
+            );
+            None
+        }
+        None => None,
+    }
+}
+
 fn start_management<C, K, HC, M>(
     settings: &M::Settings,
     runtime: &M::ModuleRuntime,
     id_man: &HubIdentityManager<DerivedKeyStore<K>, HC, K>,
     shutdown: Receiver<()>,
+    listening: Sender<()>,
     cert_manager: Arc<CertificateManager<C>>,
+    crypto: C,
     initiate_shutdown_and_reprovision: mpsc::UnboundedSender<()>,
+    device_actions: &DeviceActionSettings,
+    initiate_device_action: mpsc::UnboundedSender<DeviceAction>,
+    provisioning_payload: Option<serde_json::Value>,
+    crl: CertificateRevocationList,
+    activity: ActivityMonitor,
+    watchdog_status: WatchdogStatus,
+    agent_rollback: Option<AgentRollback<<M::ModuleRuntime as ModuleRuntime>::Config>>,
+    agent_bootstrap: AgentBootstrap,
+    log_controller: LogController,
+    startup_state: StartupState,
+    lifecycle_hooks: LifecycleHooks,
+    journal: OperationJournal,
+    api_tokens: Option<ApiTokens>,
+    metrics: Metrics,
+    clock_skew: ClockSkew,
+    boot_order: BootOrder,
+    module_snapshot: ModuleSnapshot,
+    hsm_self_test: SelfTestStatus,
 ) -> impl Future<Item = (), Error = Error>
 where
-    C: CreateCertificate + Clone,
+    C: CreateCertificate + Encrypt + MakeRandom + Clone + Send + Sync + 'static,
     K: 'static + Sign + Clone + Send + Sync,
     HC: 'static + ClientImpl + Send + Sync,
     M: MakeModuleRuntime,
     M::ModuleRuntime: Authenticator<Request = Request<Body>> + Send + Sync + Clone + 'static,
     <<M::ModuleRuntime as Authenticator>::AuthenticateFuture as Future>::Error: Fail,
     for<'r> &'r <M::ModuleRuntime as ModuleRuntime>::Error: Into<ModuleRuntimeErrorReason>,
-    <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config: DeserializeOwned + Serialize,
+    <<M::ModuleRuntime as ModuleRuntime>::Module as Module>::Config:
+        Clone + DeserializeOwned + Serialize + Send,
     <M::ModuleRuntime as ModuleRuntime>::Logs: Into<Body>,
 {
     info!("Starting management API...");
@@ -2067,29 +4111,97 @@ where
     let label = "mgmt".to_string();
     let url = settings.listen().management_uri().clone();
     let min_protocol_version = settings.listen().min_tls_version();
+    let socket_permissions = settings.listen().management_socket().cloned();
+    let secret_store: Arc<dyn SecretStore + Send + Sync> =
+        Arc::new(LocalSecretStore::new(settings.secrets().clone()));
 
-    ManagementService::new(runtime, id_man, initiate_shutdown_and_reprovision)
-        .then(move |service| -> Result<_, Error> {
-            let service = service.context(ErrorKind::Initialize(
-                InitializeErrorReason::ManagementService,
-            ))?;
-            let service = LoggingService::new(label, service);
+    let mut warnings = vec![];
+    if settings.certificates().device_cert().is_none() {
+        warnings.push(QUICKSTART_CA_WARNING.to_string());
+    }
+
+    ManagementService::new(
+        runtime,
+        id_man,
+        crypto,
+        initiate_shutdown_and_reprovision,
+        device_actions,
+        initiate_device_action,
+        provisioning_payload,
+        warnings,
+        crl,
+        activity,
+        watchdog_status,
+        agent_rollback,
+        agent_bootstrap,
+        log_controller,
+        logging::log_buffer(),
+        startup_state,
+        lifecycle_hooks,
+        journal,
+        api_tokens,
+        secret_store,
+        clock_skew,
+        boot_order,
+        module_snapshot,
+        hsm_self_test,
+    )
+    .then(move |service| -> Result<_, Error> {
+        let service = service.context(ErrorKind::Initialize(
+            InitializeErrorReason::ManagementService,
+        ))?;
+        let service = ChainBuilder::new()
+            .with_logging(label.clone())
+            .with_metrics(label, metrics)
+            .with_version_check()
+            .finish(service);
 
-            let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
+        let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
 
-            let run = Http::new()
-                .bind_url(url.clone(), service, Some(tls_params))
-                .map_err(|err| {
-                    err.context(ErrorKind::Initialize(
-                        InitializeErrorReason::ManagementService,
-                    ))
-                })?
-                .run_until(shutdown.map_err(|_| ()))
-                .map_err(|err| Error::from(err.context(ErrorKind::ManagementService)));
-            info!("Listening on {} with 1 thread for management API.", url);
-            Ok(run)
-        })
-        .flatten()
+        let server = Http::new()
+            .bind_url(url.clone(), service, Some(tls_params))
+            .map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::ManagementService,
+                ))
+            })?;
+        if let Some(socket_permissions) = &socket_permissions {
+            set_socket_permissions(&url, socket_permissions).map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::ManagementService,
+                ))
+            })?;
+        }
+        let run = server
+            .run_until(shutdown.map_err(|_| ()))
+            .map_err(|err| Error::from(err.context(ErrorKind::ManagementService)));
+        info!("Listening on {} with 1 thread for management API.", url);
+        listening.send(()).unwrap_or(());
+        Ok(run)
+    })
+    .flatten()
+}
+
+fn start_metrics<C>(
+    url: &Url,
+    min_protocol_version: Protocol,
+    metrics: Metrics,
+    cert_manager: &CertificateManager<C>,
+    shutdown: Receiver<()>,
+) -> Result<impl Future<Item = (), Error = Error>, Error>
+where
+    C: CreateCertificate + Clone,
+{
+    info!("Starting metrics endpoint...");
+
+    let tls_params = TlsAcceptorParams::new(cert_manager, min_protocol_version);
+    let run = Http::new()
+        .bind_url(url.clone(), MetricsEndpoint::new(metrics), Some(tls_params))
+        .map_err(|err| err.context(ErrorKind::Initialize(InitializeErrorReason::MetricsService)))?
+        .run_until(shutdown.map_err(|_| ()))
+        .map_err(|err| Error::from(err.context(ErrorKind::MetricsService)));
+    info!("Listening on {} with 1 thread for metrics.", url);
+    Ok(run)
 }
 
 fn start_workload<K, C, CE, W, M>(
@@ -2097,9 +4209,15 @@ fn start_workload<K, C, CE, W, M>(
     key_store: &K,
     runtime: &M::ModuleRuntime,
     shutdown: Receiver<()>,
+    listening: Sender<()>,
     crypto: &C,
     cert_manager: Arc<CertificateManager<CE>>,
     config: W,
+    crl: CertificateRevocationList,
+    cert_cache: CertificateCache,
+    api_tokens: Option<ApiTokens>,
+    attestation: Option<TpmAttestation>,
+    metrics: Metrics,
 ) -> impl Future<Item = (), Error = Error>
 where
     K: KeyStore + Clone + Send + Sync + 'static,
@@ -2128,29 +4246,58 @@ where
     let label = "work".to_string();
     let url = settings.listen().workload_uri().clone();
     let min_protocol_version = settings.listen().min_tls_version();
+    let socket_permissions = settings.listen().workload_socket().cloned();
 
-    WorkloadService::new(key_store, crypto.clone(), runtime, config)
-        .then(move |service| -> Result<_, Error> {
-            let service = service.context(ErrorKind::Initialize(
-                InitializeErrorReason::WorkloadService,
-            ))?;
-            let service = LoggingService::new(label, service);
+    let rate_limiter = settings
+        .workload_rate_limit()
+        .map(|limit| RateLimiter::new(limit.requests_per_second(), limit.burst()));
+
+    WorkloadService::new(
+        key_store,
+        crypto.clone(),
+        runtime,
+        config,
+        crl,
+        Some(cert_cache),
+        rate_limiter,
+        api_tokens,
+        attestation,
+    )
+    .then(move |service| -> Result<_, Error> {
+        let service = service.context(ErrorKind::Initialize(
+            InitializeErrorReason::WorkloadService,
+        ))?;
+        let service = ChainBuilder::new()
+            .with_logging(label.clone())
+            .with_metrics(label, metrics)
+            .with_version_check()
+            .with_body_limit(WORKLOAD_MAX_REQUEST_BODY)
+            .finish(service);
 
-            let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
+        let tls_params = TlsAcceptorParams::new(&cert_manager, min_protocol_version);
 
-            let run = Http::new()
-                .bind_url(url.clone(), service, Some(tls_params))
-                .map_err(|err| {
-                    err.context(ErrorKind::Initialize(
-                        InitializeErrorReason::WorkloadService,
-                    ))
-                })?
-                .run_until(shutdown.map_err(|_| ()))
-                .map_err(|err| Error::from(err.context(ErrorKind::WorkloadService)));
-            info!("Listening on {} with 1 thread for workload API.", url);
-            Ok(run)
-        })
-        .flatten()
+        let server = Http::new()
+            .bind_url(url.clone(), service, Some(tls_params))
+            .map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::WorkloadService,
+                ))
+            })?;
+        if let Some(socket_permissions) = &socket_permissions {
+            set_socket_permissions(&url, socket_permissions).map_err(|err| {
+                err.context(ErrorKind::Initialize(
+                    InitializeErrorReason::WorkloadService,
+                ))
+            })?;
+        }
+        let run = server
+            .run_until(shutdown.map_err(|_| ()))
+            .map_err(|err| Error::from(err.context(ErrorKind::WorkloadService)));
+        info!("Listening on {} with 1 thread for workload API.", url);
+        listening.send(()).unwrap_or(());
+        Ok(run)
+    })
+    .flatten()
 }
 
 #[cfg(test)]
@@ -2168,6 +4315,7 @@ mod tests {
 
     use edgelet_core::{
         KeyBytes, ModuleRuntimeState, PrivateKey, DEFAULT_AUTO_GENERATED_CA_LIFETIME_DAYS,
+        DEFAULT_CA_RENEWAL_THRESHOLD_DAYS,
     };
     use edgelet_docker::{DockerConfig, DockerModuleRuntime, Settings};
     use edgelet_test_utils::cert::TestCert;
@@ -2420,6 +4568,178 @@ mod tests {
         );
     }
 
+    #[test]
+    fn settings_without_ca_renewal_threshold_uses_default() {
+        let _guard = LOCK.lock().unwrap();
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS1)).unwrap();
+        assert_eq!(
+            u64::from(DEFAULT_CA_RENEWAL_THRESHOLD_DAYS) * 86_400,
+            settings.certificates().ca_renewal_threshold_seconds()
+        );
+        assert!(!settings.certificates().restart_modules_on_ca_renewal());
+    }
+
+    #[test]
+    fn settings_with_ca_renewal_threshold_beyond_ca_lifetime_is_capped() {
+        let _guard = LOCK.lock().unwrap();
+
+        let settings = Settings::new(Path::new(GOOD_SETTINGS2)).unwrap();
+        // Provided value is 2 days, which is more than the 1 day CA lifetime
+        assert_eq!(
+            43_200,
+            settings.certificates().ca_renewal_threshold_seconds()
+        );
+        assert!(settings.certificates().restart_modules_on_ca_renewal());
+    }
+
+    #[test]
+    fn ca_renewal_delay_is_none_when_disabled() {
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: false,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        assert_eq!(None, ca_renewal_delay(&crypto, 0, true).unwrap());
+    }
+
+    #[test]
+    fn ca_renewal_delay_is_time_until_threshold() {
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: false,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        // The test certificates expire in an hour
+        let delay = ca_renewal_delay(&crypto, 600, true).unwrap().unwrap();
+        assert!(delay.as_secs() > 2900 && delay.as_secs() <= 3000);
+    }
+
+    #[test]
+    fn ca_renewal_delay_is_zero_within_threshold() {
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: false,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        let delay = ca_renewal_delay(&crypto, 86_400, true).unwrap().unwrap();
+        assert_eq!(0, delay.as_secs());
+    }
+
+    #[test]
+    fn ca_renewal_delay_is_none_for_expiring_device_ca_from_settings() {
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: false,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        assert_eq!(None, ca_renewal_delay(&crypto, 86_400, false).unwrap());
+    }
+
+    #[test]
+    fn ca_renewal_delay_fails_without_device_ca() {
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: true,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        match ca_renewal_delay(&crypto, 600, true).unwrap_err().kind() {
+            ErrorKind::CertificateRenewal => (),
+            kind => panic!("Expected `CertificateRenewal` but got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn reset_quickstart_ca_does_nothing_without_reset_file() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: true,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        assert!(!reset_quickstart_ca(&crypto, tmp_dir.path()).unwrap());
+    }
+
+    #[test]
+    fn reset_quickstart_ca_removes_reset_file() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let reset_path = tmp_dir.path().join(QUICKSTART_CA_RESET_FILENAME);
+        fs::write(&reset_path, "").unwrap();
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: false,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        assert!(reset_quickstart_ca(&crypto, tmp_dir.path()).unwrap());
+        assert!(!reset_path.exists());
+    }
+
+    #[test]
+    fn reset_quickstart_ca_keeps_reset_file_on_failure() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let reset_path = tmp_dir.path().join(QUICKSTART_CA_RESET_FILENAME);
+        fs::write(&reset_path, "").unwrap();
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: true,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        match reset_quickstart_ca(&crypto, tmp_dir.path())
+            .unwrap_err()
+            .kind()
+        {
+            ErrorKind::Initialize(InitializeErrorReason::QuickstartCa) => (),
+            kind => panic!("Expected `QuickstartCa` but got {:?}", kind),
+        }
+        assert!(reset_path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn foreign_owned_entry_finds_files_of_other_users() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let cache_dir = tmp_dir.path().join(EDGE_SETTINGS_SUBDIR);
+        fs::create_dir_all(&cache_dir).unwrap();
+        fs::write(cache_dir.join(EDGE_SETTINGS_STATE_FILENAME), "state").unwrap();
+        let uid = fs::metadata(tmp_dir.path()).unwrap().uid();
+
+        assert_eq!(None, foreign_owned_entry(tmp_dir.path(), uid));
+        assert_eq!(
+            Some(cache_dir),
+            foreign_owned_entry(tmp_dir.path(), uid + 1)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn protect_quickstart_ca_keys_restricts_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let keys_dir = tmp_dir.path().join(QUICKSTART_CA_KEYS_SUBDIR);
+        fs::create_dir_all(&keys_dir).unwrap();
+        fs::set_permissions(&keys_dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let key_path = keys_dir.join("device_ca_alias.key.pem");
+        fs::write(&key_path, "key").unwrap();
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        protect_quickstart_ca_keys(tmp_dir.path()).unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(0o700, mode(&keys_dir));
+        assert_eq!(0o600, mode(&key_path));
+    }
+
     #[test]
     fn settings_with_invalid_issuer_ca_fails() {
         let tmp_dir = TempDir::new("blah").unwrap();
@@ -2654,6 +4974,148 @@ mod tests {
         assert_ne!(written1, written);
     }
 
+    fn settings_with_connection_string(dir: &Path, connection_string: &str) -> Settings {
+        let path = dir.join("config.yaml");
+        let yaml = fs::read_to_string(GOOD_SETTINGS).unwrap().replace(
+            "HostName=something.something.com;DeviceId=something;SharedAccessKey=QXp1cmUgSW9UIEVkZ2U=",
+            connection_string,
+        );
+        fs::write(&path, yaml).unwrap();
+        Settings::new(&path).unwrap()
+    }
+
+    #[test]
+    fn upstream_endpoint_uses_parent_hostname() {
+        let endpoint = Url::parse("https://global.azure-devices-provisioning.net").unwrap();
+
+        assert_eq!(
+            "https://parent.example.com/",
+            upstream_endpoint(&endpoint, Some("parent.example.com"))
+                .unwrap()
+                .as_str()
+        );
+        assert_eq!(endpoint, upstream_endpoint(&endpoint, None).unwrap());
+        assert!(upstream_endpoint(&endpoint, Some("parent host")).is_err());
+    }
+
+    #[test]
+    fn build_env_includes_parent_hostname() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let path = tmp_dir.path().join("config.yaml");
+        let yaml = format!(
+            "{}\nparent_hostname: \"Parent.Example.com\"\n",
+            fs::read_to_string(GOOD_SETTINGS).unwrap()
+        );
+        fs::write(&path, yaml).unwrap();
+        let settings = Settings::new(&path).unwrap();
+
+        let env = build_env(
+            &HashMap::new(),
+            &agent_bootstrap("hub", "device", &settings),
+        );
+
+        assert_eq!(
+            Some("parent.example.com"),
+            env.get(PARENT_HOSTNAME_KEY).map(String::as_str)
+        );
+    }
+
+    #[test]
+    fn settings_base_digest_ignores_device_key() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let settings = settings_with_connection_string(
+            tmp_dir.path(),
+            "HostName=something.something.com;DeviceId=something;SharedAccessKey=QXp1cmUgSW9UIEVkZ2U=",
+        );
+        let rotated = settings_with_connection_string(
+            tmp_dir.path(),
+            "HostName=something.something.com;DeviceId=something;SharedAccessKey=bmV3IGtleQ==",
+        );
+        let moved = settings_with_connection_string(
+            tmp_dir.path(),
+            "HostName=something.something.com;DeviceId=other;SharedAccessKey=QXp1cmUgSW9UIEVkZ2U=",
+        );
+
+        assert_ne!(
+            compute_settings_digest(&settings, None).unwrap(),
+            compute_settings_digest(&rotated, None).unwrap()
+        );
+        assert_eq!(
+            compute_settings_base_digest(&settings).unwrap(),
+            compute_settings_base_digest(&rotated).unwrap()
+        );
+        assert_ne!(
+            compute_settings_base_digest(&settings).unwrap(),
+            compute_settings_base_digest(&moved).unwrap()
+        );
+    }
+
+    #[test]
+    fn settings_credentials_change_keeps_modules() {
+        let tmp_dir = TempDir::new("blah").unwrap();
+        let cache_dir = tmp_dir.path().join("cache");
+        fs::create_dir(&cache_dir).unwrap();
+        let settings = settings_with_connection_string(
+            tmp_dir.path(),
+            "HostName=something.something.com;DeviceId=something;SharedAccessKey=QXp1cmUgSW9UIEVkZ2U=",
+        );
+        let runtime = TestRuntime::<Error, _>::make_runtime(
+            settings.clone(),
+            TestProvisioningResult::new(),
+            TestHsm::default(),
+        )
+        .wait()
+        .unwrap();
+        let crypto = TestCrypto {
+            use_expired_ca: false,
+            fail_device_ca_alias: false,
+            fail_decrypt: false,
+            fail_encrypt: false,
+        };
+        let mut tokio_runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut check = |settings: &Settings| {
+            check_settings_state::<TestRuntime<_, Settings>, _>(
+                &cache_dir,
+                "settings_state",
+                settings,
+                &runtime,
+                &crypto,
+                &mut tokio_runtime,
+                None,
+            )
+            .unwrap()
+        };
+
+        assert!(!check(&settings));
+        assert!(!check(&settings));
+
+        // Only the device key changed, so the settings aren't saved until the
+        // module identities are updated.
+        let rotated = settings_with_connection_string(
+            tmp_dir.path(),
+            "HostName=something.something.com;DeviceId=something;SharedAccessKey=bmV3IGtleQ==",
+        );
+        assert!(check(&rotated));
+        assert!(diff_with_cached(
+            &rotated,
+            &cache_dir.join("settings_state"),
+            None
+        ));
+        assert!(check(&rotated));
+
+        // A different device is a full reconfiguration.
+        let moved = settings_with_connection_string(
+            tmp_dir.path(),
+            "HostName=something.something.com;DeviceId=other;SharedAccessKey=bmV3IGtleQ==",
+        );
+        assert!(!check(&moved));
+        assert!(!diff_with_cached(
+            &moved,
+            &cache_dir.join("settings_state"),
+            None
+        ));
+    }
+
     #[test]
     fn get_proxy_uri_recognizes_https_proxy() {
         // Use existing "https_proxy" env var if it's set, otherwise invent one
@@ -2778,6 +5240,37 @@ mod tests {
         assert!(diff_with_cached(&settings, Path::new("i dont exist"), None));
     }
 
+    #[test]
+    fn without_reloadable_settings_ignores_only_reloadable_changes() {
+        let current = json!({
+            "agent": { "name": "edgeAgent" },
+            "certificates": { "ca_renewal_threshold_days": 1, "device_ca_cert": "ca.pem" },
+            "hostname": "host1",
+            "log_level": "info",
+        });
+        let reloaded = json!({
+            "agent": { "name": "edgeAgent2" },
+            "certificates": { "ca_renewal_threshold_days": 5, "device_ca_cert": "ca.pem" },
+            "hostname": "host1",
+            "log_level": "debug",
+        });
+        assert_eq!(
+            without_reloadable_settings(current.clone()),
+            without_reloadable_settings(reloaded)
+        );
+
+        let renamed = json!({
+            "agent": { "name": "edgeAgent" },
+            "certificates": { "ca_renewal_threshold_days": 1, "device_ca_cert": "ca.pem" },
+            "hostname": "host2",
+            "log_level": "info",
+        });
+        assert_ne!(
+            without_reloadable_settings(current),
+            without_reloadable_settings(renamed)
+        );
+    }
+
     #[test]
     fn get_provisioning_auth_method_returns_sas_key_for_manual_connection_string() {
         let settings = Settings::new(Path::new(GOOD_SETTINGS1)).unwrap();
@@ -1,14 +1,20 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use std::env;
-use std::io::Write;
+use std::io::{self, Write};
+use std::sync::RwLock;
 
 #[cfg(target_os = "windows")]
 use clap::crate_name;
 
+use chrono::Utc;
+use edgelet_core::{LogBuffer, LogFormat, LogRecord, LogSettings, RuntimeSettings};
 use edgelet_utils::log_failure;
 use env_logger;
-use log::{Level, LevelFilter};
+use env_logger::fmt::Formatter;
+use lazy_static::lazy_static;
+use log::{info, Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
 #[cfg(target_os = "windows")]
 use win_logger::EventLogger;
 
@@ -17,43 +23,178 @@ use crate::error::Error;
 #[cfg(target_os = "windows")]
 const IOTEDGED_SERVICE_NAME: &str = crate_name!();
 const ENV_LOG: &str = "IOTEDGE_LOG";
+const DEFAULT_LOG_LEVEL: &str = "info";
+const LOG_BUFFER_CAPACITY: usize = 1000;
 
-pub fn init() {
-    env_logger::Builder::new()
-        .format(|fmt, record| {
-            let level = match record.level() {
-                Level::Trace => "TRCE",
-                Level::Debug => "DBUG",
-                Level::Info => "INFO",
-                Level::Warn => "WARN",
-                Level::Error => "ERR!",
-            };
-            let timestamp = fmt.timestamp();
-
-            if record.level() >= Level::Debug {
-                writeln!(
-                    fmt,
-                    "<{}>{} [{}] - [{}] {}",
-                    syslog_level(record.level()),
-                    timestamp,
-                    level,
-                    record.target(),
-                    record.args()
-                )
-            } else {
-                writeln!(
-                    fmt,
-                    "<{}>{} [{}] - {}",
-                    syslog_level(record.level()),
-                    timestamp,
-                    level,
-                    record.args()
-                )
+lazy_static! {
+    // The logger that the log records are forwarded to, along with the settings
+    // it was built from. It is replaced when the log settings are changed.
+    static ref LOGGER: RwLock<Option<(LogSettings, env_logger::Logger)>> = RwLock::new(None);
+
+    // The most recent records that were logged, for the management API.
+    static ref LOG_BUFFER: LogBuffer = LogBuffer::new(LOG_BUFFER_CAPACITY);
+}
+
+struct ReloadableLogger;
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        LOGGER
+            .read()
+            .expect("Failed to acquire the logger lock")
+            .as_ref()
+            .map_or(false, |(_, logger)| logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if let Some((_, logger)) = LOGGER
+            .read()
+            .expect("Failed to acquire the logger lock")
+            .as_ref()
+        {
+            if logger.matches(record) {
+                logger.log(record);
+                LOG_BUFFER.push(LogRecord::new(
+                    Utc::now(),
+                    record.level(),
+                    record.target().to_string(),
+                    record.args().to_string(),
+                ));
             }
-        })
+        }
+    }
+
+    fn flush(&self) {
+        if let Some((_, logger)) = LOGGER
+            .read()
+            .expect("Failed to acquire the logger lock")
+            .as_ref()
+        {
+            logger.flush();
+        }
+    }
+}
+
+pub fn init() {
+    let settings = LogSettings::new(LogFormat::Text, env_log_level());
+    let logger = build_logger(&settings);
+    log::set_max_level(logger.filter());
+    *LOGGER.write().expect("Failed to acquire the logger lock") = Some((settings, logger));
+    log::set_boxed_logger(Box::new(ReloadableLogger)).expect("Could not initialize the logger");
+}
+
+/// Returns the log settings from the configuration of the daemon. The
+/// `IOTEDGE_LOG` environment variable is used when no log level is configured.
+pub fn configured_log_settings<S>(settings: &S) -> LogSettings
+where
+    S: RuntimeSettings,
+{
+    LogSettings::new(
+        settings.log_format(),
+        settings
+            .log_level()
+            .map_or_else(env_log_level, ToString::to_string),
+    )
+}
+
+/// Returns the log settings of the logger set up by `init`.
+pub fn log_settings() -> LogSettings {
+    LOGGER
+        .read()
+        .expect("Failed to acquire the logger lock")
+        .as_ref()
+        .map_or_else(
+            || LogSettings::new(LogFormat::Text, env_log_level()),
+            |(settings, _)| settings.clone(),
+        )
+}
+
+/// Returns the buffer of the most recent records logged by the logger set up
+/// by `init`.
+pub fn log_buffer() -> LogBuffer {
+    LOG_BUFFER.clone()
+}
+
+/// Changes the log settings of the logger set up by `init`.
+pub fn set_log_settings(settings: &LogSettings) {
+    let mut current = LOGGER.write().expect("Failed to acquire the logger lock");
+    match &*current {
+        Some((current_settings, _)) if current_settings != settings => {
+            let logger = build_logger(settings);
+            log::set_max_level(logger.filter());
+            *current = Some((settings.clone(), logger));
+            drop(current);
+            info!(
+                "Log settings changed to format {} and level {:?}",
+                settings.format(),
+                settings.level()
+            );
+        }
+        _ => (),
+    }
+}
+
+fn env_log_level() -> String {
+    env::var(ENV_LOG)
+        .ok()
+        .filter(|level| !level.is_empty())
+        .unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string())
+}
+
+fn build_logger(settings: &LogSettings) -> env_logger::Logger {
+    let mut builder = env_logger::Builder::new();
+    match settings.format() {
+        LogFormat::Text => builder.format(format_text),
+        LogFormat::Json => builder.format(format_json),
+    };
+    builder
         .filter_level(LevelFilter::Info)
-        .parse(&env::var(ENV_LOG).unwrap_or_default())
-        .init();
+        .parse(settings.level())
+        .build()
+}
+
+fn format_text(fmt: &mut Formatter, record: &Record<'_>) -> io::Result<()> {
+    let level = match record.level() {
+        Level::Trace => "TRCE",
+        Level::Debug => "DBUG",
+        Level::Info => "INFO",
+        Level::Warn => "WARN",
+        Level::Error => "ERR!",
+    };
+    let timestamp = fmt.timestamp();
+
+    if record.level() >= Level::Debug {
+        writeln!(
+            fmt,
+            "<{}>{} [{}] - [{}] {}",
+            syslog_level(record.level()),
+            timestamp,
+            level,
+            record.target(),
+            record.args()
+        )
+    } else {
+        writeln!(
+            fmt,
+            "<{}>{} [{}] - {}",
+            syslog_level(record.level()),
+            timestamp,
+            level,
+            record.args()
+        )
+    }
+}
+
+// Writes each record as a JSON object on a line of its own, so that the logs
+// can be picked up by a log collector without parsing the text format.
+fn format_json(fmt: &mut Formatter, record: &Record<'_>) -> io::Result<()> {
+    let line = json!({
+        "timestamp": fmt.timestamp().to_string(),
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    writeln!(fmt, "{}", line)
 }
 
 #[cfg(target_os = "windows")]
@@ -0,0 +1,134 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Notifications to the service manager, which is systemd on Linux. systemd
+//! waits for the daemon to report that it is ready before it considers the
+//! service started, and restarts the daemon when it stops sending keepalives.
+
+use std::time::Duration;
+
+use futures::Future;
+use log::warn;
+
+use edgelet_core::WatchdogSettings;
+
+type Keepalive = Box<dyn Future<Item = (), Error = ()> + Send>;
+
+/// Tells the service manager that the daemon is ready, which is once the
+/// management and workload APIs are listening.
+pub fn ready() {
+    imp::ready();
+}
+
+/// Tells the service manager that the daemon is still alive, until the
+/// returned future is dropped. Keepalives are only sent when the service
+/// manager watches the daemon, otherwise the future resolves right away.
+pub fn keepalive(settings: &WatchdogSettings) -> Keepalive {
+    imp::keepalive(settings)
+}
+
+/// Returns how often keepalives are sent to a service manager that restarts
+/// the daemon if it doesn't hear from it within `timeout`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn keepalive_interval(configured: Option<Duration>, timeout: Duration) -> Duration {
+    match configured {
+        Some(interval) if interval < timeout => interval,
+        Some(interval) => {
+            warn!(
+                "The keepalive interval of {} seconds is not shorter than the watchdog timeout of {} seconds of the service manager, using half of the timeout instead.",
+                interval.as_secs(),
+                timeout.as_secs()
+            );
+            timeout / 2
+        }
+        None => timeout / 2,
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::time::Instant;
+
+    use futures::{future, Stream};
+    use log::{info, warn, Level};
+    use tokio::timer::Interval;
+
+    use edgelet_core::WatchdogSettings;
+    use edgelet_utils::log_failure;
+
+    use super::{keepalive_interval, Keepalive};
+
+    pub(super) fn ready() {
+        match systemd::notify_ready() {
+            Ok(true) => info!("Notified systemd that the daemon is ready."),
+            Ok(false) => (),
+            Err(err) => log_failure(Level::Warn, &err),
+        }
+    }
+
+    pub(super) fn keepalive(settings: &WatchdogSettings) -> Keepalive {
+        let timeout = match systemd::watchdog_interval() {
+            Ok(Some(timeout)) => timeout,
+            Ok(None) => return Box::new(future::ok(())),
+            Err(err) => {
+                log_failure(Level::Warn, &err);
+                return Box::new(future::ok(()));
+            }
+        };
+
+        let interval = keepalive_interval(settings.keepalive_interval(), timeout);
+        info!(
+            "Sending keepalives to systemd every {} milliseconds.",
+            interval.as_millis()
+        );
+
+        let keepalive = Interval::new(Instant::now(), interval)
+            .map_err(|err| warn!("Keepalive timer failed: {}", err))
+            .for_each(|_| {
+                if let Err(err) = systemd::notify_watchdog() {
+                    log_failure(Level::Warn, &err);
+                }
+                Ok(())
+            });
+        Box::new(keepalive)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use futures::future;
+
+    use edgelet_core::WatchdogSettings;
+
+    use super::Keepalive;
+
+    pub(super) fn ready() {}
+
+    pub(super) fn keepalive(_settings: &WatchdogSettings) -> Keepalive {
+        Box::new(future::ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keepalive_interval_defaults_to_half_of_timeout() {
+        assert_eq!(
+            Duration::from_secs(15),
+            keepalive_interval(None, Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn keepalive_interval_must_be_shorter_than_timeout() {
+        assert_eq!(
+            Duration::from_secs(10),
+            keepalive_interval(Some(Duration::from_secs(10)), Duration::from_secs(30))
+        );
+        assert_eq!(
+            Duration::from_secs(15),
+            keepalive_interval(Some(Duration::from_secs(30)), Duration::from_secs(30))
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Reboots or shuts down the host, once the daemon has stopped the modules
+//! for a device action asked for through the management API.
+
+use std::process::Command;
+
+use failure::ResultExt;
+use log::info;
+
+use edgelet_core::DeviceAction;
+
+use crate::error::{Error, ErrorKind};
+
+/// Runs the command of the host that takes `action`. The command returns
+/// once the host has started to reboot or shut down, and the daemon is
+/// stopped along with the other services.
+pub fn take(action: DeviceAction) -> Result<(), Error> {
+    let (program, args) = imp::command(action);
+    info!(
+        "Running {} {} to {} the device",
+        program,
+        args.join(" "),
+        action
+    );
+
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .context(ErrorKind::DeviceAction(action))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::from(ErrorKind::DeviceAction(action)))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+mod imp {
+    use edgelet_core::DeviceAction;
+
+    pub(super) fn command(action: DeviceAction) -> (&'static str, &'static [&'static str]) {
+        match action {
+            DeviceAction::Reboot => ("systemctl", &["reboot"]),
+            DeviceAction::Shutdown => ("systemctl", &["poweroff"]),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use edgelet_core::DeviceAction;
+
+    pub(super) fn command(action: DeviceAction) -> (&'static str, &'static [&'static str]) {
+        match action {
+            DeviceAction::Reboot => ("shutdown", &["/r", "/t", "0"]),
+            DeviceAction::Shutdown => ("shutdown", &["/s", "/t", "0"]),
+        }
+    }
+}
@@ -7,19 +7,27 @@ use futures::Future;
 
 type ShutdownSignal = Box<dyn Future<Item = (), Error = ()> + Send>;
 
+type ReloadSignal = Box<dyn Future<Item = (), Error = ()> + Send>;
+
 pub fn shutdown() -> ShutdownSignal {
     imp::shutdown()
 }
 
+/// Resolves when the daemon is asked to reload its configuration, which is on
+/// SIGHUP. There is no such signal on Windows, so it never resolves there.
+pub fn reload() -> ReloadSignal {
+    imp::reload()
+}
+
 #[cfg(unix)]
 mod imp {
     use std::fmt;
 
     use futures::{future, Future, Stream};
     use log::info;
-    use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+    use tokio_signal::unix::{Signal, SIGHUP, SIGINT, SIGTERM};
 
-    use super::ShutdownSignal;
+    use super::{ReloadSignal, ShutdownSignal};
 
     pub(super) fn shutdown() -> ShutdownSignal {
         let signals = [SIGINT, SIGTERM].iter().map(|&sig| {
@@ -40,12 +48,28 @@ mod imp {
         Box::new(on_any_signal)
     }
 
+    pub(super) fn reload() -> ReloadSignal {
+        let on_sighup = Signal::new(SIGHUP)
+            .flatten_stream()
+            .into_future()
+            .map(|_| {
+                info!(
+                    target: "iotedged::signal",
+                    "Received {}, reloading configuration",
+                    DisplaySignal(SIGHUP),
+                );
+            })
+            .map_err(|_| unreachable!("Signal never returns an error"));
+        Box::new(on_sighup)
+    }
+
     #[derive(Clone, Copy)]
     struct DisplaySignal(i32);
 
     impl fmt::Display for DisplaySignal {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             let s = match self.0 {
+                SIGHUP => "SIGHUP",
                 SIGINT => "SIGINT",
                 SIGTERM => "SIGTERM",
                 other => return write!(f, "signal {}", other),
@@ -57,11 +81,11 @@ mod imp {
 
 #[cfg(not(unix))]
 mod imp {
-    use futures::{Future, Stream};
+    use futures::{future, Future, Stream};
     use log::info;
     use tokio_signal;
 
-    use super::ShutdownSignal;
+    use super::{ReloadSignal, ShutdownSignal};
 
     pub(super) fn shutdown() -> ShutdownSignal {
         let on_ctrl_c = tokio_signal::ctrl_c()
@@ -76,4 +100,8 @@ mod imp {
             .map_err(|_| unreachable!("ctrl_c never returns errors"));
         Box::new(on_ctrl_c)
     }
+
+    pub(super) fn reload() -> ReloadSignal {
+        Box::new(future::empty())
+    }
 }
@@ -0,0 +1,480 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Runs the tenants of the daemon, which are further device identities hosted
+//! on the same device. The HSM, and the environment the daemon configures it
+//! with, are shared by the whole process, so every tenant is run by an iotedged
+//! process of its own with the tenant's config file. The daemon restarts these
+//! processes when they exit, and stops them when it shuts down.
+
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use edgelet_core::{RuntimeSettings, Tenant, UrlExt, UNIX_SCHEME};
+use failure::{Fail, ResultExt};
+use log::{error, info, warn};
+use url::Url;
+
+use crate::error::{Error, ErrorKind, InitializeErrorReason};
+
+const TENANT_RESTART_MIN_DELAY: Duration = Duration::from_secs(1);
+const TENANT_RESTART_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// A tenant that ran for this long before it exited is restarted right away.
+const TENANT_RESTART_RESET_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// The exit code of a daemon that could not load its settings, which is not
+/// going to get any better by restarting it.
+const LOAD_SETTINGS_EXIT_CODE: i32 = 153;
+
+/// Environment variables the service manager passes to the daemon itself, and
+/// which the tenants must not act on.
+const HOST_ONLY_ENV_VARS: &[&str] = &[
+    "LISTEN_FDNAMES",
+    "LISTEN_FDS",
+    "LISTEN_PID",
+    "NOTIFY_SOCKET",
+    "WATCHDOG_PID",
+    "WATCHDOG_USEC",
+];
+
+/// The tenants run by the daemon.
+pub struct Tenants {
+    stopping: Arc<AtomicBool>,
+    running: Vec<RunningTenant>,
+}
+
+struct RunningTenant {
+    name: String,
+    pid: Arc<Mutex<Option<u32>>>,
+    thread: JoinHandle<()>,
+}
+
+impl Tenants {
+    /// Checks that the tenants in `settings` are isolated from the host and
+    /// from each other, and starts them. `load_settings` loads the settings of
+    /// a tenant from its config file.
+    pub fn start<S, L>(settings: &S, load_settings: L) -> Result<Self, Error>
+    where
+        S: RuntimeSettings,
+        L: Fn(&Path) -> Result<S, Error>,
+    {
+        check_tenants(settings, load_settings)?;
+
+        let program =
+            env::current_exe().context(ErrorKind::Initialize(InitializeErrorReason::Tenants))?;
+
+        // The daemon configures the HSM through environment variables as it
+        // starts, which must not leak into the tenants started after that.
+        let environment: Vec<(OsString, OsString)> = env::vars_os()
+            .filter(|(key, _)| !HOST_ONLY_ENV_VARS.iter().any(|var| key == var))
+            .collect();
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let mut running = vec![];
+        for tenant in settings.tenants() {
+            let mut command = Command::new(&program);
+            command
+                .arg("--config-file")
+                .arg(tenant.config_file())
+                .env_clear()
+                .envs(environment.iter().cloned());
+
+            let name = tenant.name().to_string();
+            let pid = Arc::new(Mutex::new(None));
+            let thread = {
+                let name = name.clone();
+                let pid = pid.clone();
+                let stopping = stopping.clone();
+                thread::Builder::new()
+                    .name(format!("tenant-{}", name))
+                    .spawn(move || supervise(&name, command, &pid, &stopping))
+                    .context(ErrorKind::Initialize(InitializeErrorReason::Tenants))?
+            };
+            running.push(RunningTenant { name, pid, thread });
+        }
+
+        Ok(Tenants { stopping, running })
+    }
+
+    /// Stops the tenants, and waits for them to shut down.
+    pub fn stop(self) {
+        self.stopping.store(true, Ordering::SeqCst);
+
+        for tenant in &self.running {
+            if let Some(pid) = *lock(&tenant.pid) {
+                info!("Stopping tenant {}...", tenant.name);
+                terminate(pid);
+            }
+            tenant.thread.thread().unpark();
+        }
+
+        for tenant in self.running {
+            if tenant.thread.join().is_err() {
+                error!("The supervisor of tenant {} panicked.", tenant.name);
+            }
+        }
+    }
+}
+
+/// Runs the process of a tenant until the daemon stops, and restarts it with
+/// an increasing delay whenever it exits.
+fn supervise(name: &str, mut command: Command, pid: &Mutex<Option<u32>>, stopping: &AtomicBool) {
+    let mut delay = TENANT_RESTART_MIN_DELAY;
+
+    while !stopping.load(Ordering::SeqCst) {
+        info!("Starting tenant {}...", name);
+        let started = Instant::now();
+        let status = command.spawn().and_then(|mut child| {
+            *lock(pid) = Some(child.id());
+            if stopping.load(Ordering::SeqCst) {
+                // The daemon started stopping before it could see the process.
+                terminate(child.id());
+            }
+            let status = child.wait();
+            *lock(pid) = None;
+            status
+        });
+
+        if stopping.load(Ordering::SeqCst) {
+            break;
+        }
+
+        match status {
+            Ok(status) if status.code() == Some(LOAD_SETTINGS_EXIT_CODE) => {
+                error!(
+                    "Tenant {} could not load its settings and is not restarted.",
+                    name
+                );
+                return;
+            }
+            Ok(status) => warn!("Tenant {} exited with {}.", name, status),
+            Err(err) => warn!("Could not run tenant {}: {}", name, err),
+        }
+
+        if started.elapsed() >= TENANT_RESTART_RESET_AFTER {
+            delay = TENANT_RESTART_MIN_DELAY;
+        }
+        info!("Restarting tenant {} in {} seconds.", name, delay.as_secs());
+
+        let deadline = Instant::now() + delay;
+        while !stopping.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::park_timeout(deadline - now);
+        }
+        delay = std::cmp::min(delay * 2, TENANT_RESTART_MAX_DELAY);
+    }
+
+    info!("Tenant {} stopped.", name);
+}
+
+/// Checks that the tenants in `settings` have distinct names, and neither
+/// share their home directory nor their API sockets with the host or with
+/// each other.
+fn check_tenants<S, L>(settings: &S, load_settings: L) -> Result<(), Error>
+where
+    S: RuntimeSettings,
+    L: Fn(&Path) -> Result<S, Error>,
+{
+    let mut identities = vec![Identity::new("the host".to_string(), settings)];
+    let mut names = HashSet::new();
+
+    for tenant in settings.tenants() {
+        let name = tenant.name();
+        if name.is_empty() {
+            return Err(invalid_tenant(name, "the name is empty".to_string()));
+        }
+        if !names.insert(name) {
+            return Err(invalid_tenant(
+                name,
+                "the name is used more than once".to_string(),
+            ));
+        }
+
+        let tenant_settings = load_settings(tenant.config_file()).map_err(|err| {
+            Error::from(err.context(ErrorKind::InvalidTenant(
+                name.to_string(),
+                format!(
+                    "could not load config file {}",
+                    tenant.config_file().display()
+                ),
+            )))
+        })?;
+        if !tenant_settings.tenants().is_empty() {
+            return Err(invalid_tenant(
+                name,
+                "tenants can't have tenants of their own".to_string(),
+            ));
+        }
+
+        let identity = Identity::new(format!("tenant {}", name), &tenant_settings);
+        identity.check_isolated(tenant, &identities)?;
+        identities.push(identity);
+    }
+
+    Ok(())
+}
+
+/// The resources of a device identity that must not be shared with others.
+struct Identity {
+    owner: String,
+    homedir: PathBuf,
+    management_uri: Url,
+    workload_uri: Url,
+}
+
+impl Identity {
+    fn new<S>(owner: String, settings: &S) -> Self
+    where
+        S: RuntimeSettings,
+    {
+        Identity {
+            owner,
+            homedir: settings.homedir().to_path_buf(),
+            management_uri: settings.listen().management_uri().clone(),
+            workload_uri: settings.listen().workload_uri().clone(),
+        }
+    }
+
+    fn check_isolated(&self, tenant: &Tenant, others: &[Identity]) -> Result<(), Error> {
+        let name = tenant.name();
+
+        // The sockets the service manager passes to the daemon are the host's.
+        for uri in &[&self.management_uri, &self.workload_uri] {
+            if uri.scheme() == "fd" {
+                return Err(invalid_tenant(
+                    name,
+                    format!("socket activation ({}) is only available to the host", uri),
+                ));
+            }
+        }
+
+        for other in others {
+            if self.homedir == other.homedir {
+                return Err(invalid_tenant(
+                    name,
+                    format!(
+                        "it shares its home directory {} with {}",
+                        self.homedir.display(),
+                        other.owner
+                    ),
+                ));
+            }
+
+            let uris = [
+                ("management", &self.management_uri),
+                ("workload", &self.workload_uri),
+            ];
+            for (api, uri) in &uris {
+                if same_socket(uri, &other.management_uri) || same_socket(uri, &other.workload_uri)
+                {
+                    return Err(invalid_tenant(
+                        name,
+                        format!(
+                            "its {} API listens on {}, which is used by {}",
+                            api, uri, other.owner
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn same_socket(uri: &Url, other: &Url) -> bool {
+    if uri.scheme() == UNIX_SCHEME && other.scheme() == UNIX_SCHEME {
+        if let (Ok(path), Ok(other_path)) = (uri.to_uds_file_path(), other.to_uds_file_path()) {
+            return path == other_path;
+        }
+    }
+    uri == other
+}
+
+fn invalid_tenant(name: &str, reason: String) -> Error {
+    Error::from(ErrorKind::InvalidTenant(name.to_string(), reason))
+}
+
+fn lock(pid: &Mutex<Option<u32>>) -> std::sync::MutexGuard<'_, Option<u32>> {
+    pid.lock().expect("Failed to acquire the tenant lock")
+}
+
+fn terminate(pid: u32) {
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = pid as libc::pid_t;
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::app;
+
+    fn write_settings(
+        dir: &Path,
+        file: &str,
+        homedir: &str,
+        port: u16,
+        tenants: &[(&str, &str)],
+    ) -> PathBuf {
+        let mut settings = format!(
+            r#"
+provisioning:
+  source: "manual"
+  device_connection_string: "HostName=something.something.com;DeviceId=something;SharedAccessKey=QXp1cmUgSW9UIEVkZ2U="
+agent:
+  name: "edgeAgent"
+  type: "docker"
+  env: {{}}
+  config:
+    image: "microsoft/azureiotedge-agent:1.0"
+    auth: {{}}
+hostname: "localhost"
+connect:
+  workload_uri: "http://localhost:{work}"
+  management_uri: "http://localhost:{mgmt}"
+listen:
+  workload_uri: "http://0.0.0.0:{work}"
+  management_uri: "http://0.0.0.0:{mgmt}"
+homedir: "{homedir}"
+moby_runtime:
+  uri: "http://localhost:2375"
+"#,
+            mgmt = port,
+            work = port + 1,
+            homedir = homedir,
+        );
+        if !tenants.is_empty() {
+            settings.push_str("tenants:\n");
+            for (name, config_file) in tenants {
+                settings.push_str(&format!(
+                    "  - name: \"{}\"\n    config_file: \"{}\"\n",
+                    name,
+                    dir.join(config_file).display()
+                ));
+            }
+        }
+
+        let path = dir.join(file);
+        fs::write(&path, settings).unwrap();
+        path
+    }
+
+    fn check(host: &Path) -> Result<(), Error> {
+        let settings = app::load_settings(host).unwrap();
+        check_tenants(&settings, app::load_settings)
+    }
+
+    fn invalid_tenant_name(result: Result<(), Error>) -> String {
+        match result.unwrap_err().kind() {
+            ErrorKind::InvalidTenant(name, _) => name.clone(),
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn isolated_tenants_are_valid() {
+        let tmp_dir = TempDir::new("tenants").unwrap();
+        let dir = tmp_dir.path();
+        write_settings(dir, "a.yaml", "/var/lib/iotedge/a", 9080, &[]);
+        write_settings(dir, "b.yaml", "/var/lib/iotedge/b", 9090, &[]);
+        let host = write_settings(
+            dir,
+            "host.yaml",
+            "/var/lib/iotedge",
+            8080,
+            &[("a", "a.yaml"), ("b", "b.yaml")],
+        );
+
+        check(&host).unwrap();
+    }
+
+    #[test]
+    fn tenant_sharing_homedir_is_invalid() {
+        let tmp_dir = TempDir::new("tenants").unwrap();
+        let dir = tmp_dir.path();
+        write_settings(dir, "a.yaml", "/var/lib/iotedge/a", 9080, &[]);
+        write_settings(dir, "b.yaml", "/var/lib/iotedge/a", 9090, &[]);
+        let host = write_settings(
+            dir,
+            "host.yaml",
+            "/var/lib/iotedge",
+            8080,
+            &[("a", "a.yaml"), ("b", "b.yaml")],
+        );
+
+        assert_eq!("b", invalid_tenant_name(check(&host)));
+    }
+
+    #[test]
+    fn tenant_sharing_socket_is_invalid() {
+        let tmp_dir = TempDir::new("tenants").unwrap();
+        let dir = tmp_dir.path();
+        write_settings(dir, "a.yaml", "/var/lib/iotedge/a", 8081, &[]);
+        let host = write_settings(
+            dir,
+            "host.yaml",
+            "/var/lib/iotedge",
+            8080,
+            &[("a", "a.yaml")],
+        );
+
+        assert_eq!("a", invalid_tenant_name(check(&host)));
+    }
+
+    #[test]
+    fn tenant_names_are_unique() {
+        let tmp_dir = TempDir::new("tenants").unwrap();
+        let dir = tmp_dir.path();
+        write_settings(dir, "a.yaml", "/var/lib/iotedge/a", 9080, &[]);
+        write_settings(dir, "b.yaml", "/var/lib/iotedge/b", 9090, &[]);
+        let host = write_settings(
+            dir,
+            "host.yaml",
+            "/var/lib/iotedge",
+            8080,
+            &[("a", "a.yaml"), ("a", "b.yaml")],
+        );
+
+        assert_eq!("a", invalid_tenant_name(check(&host)));
+    }
+
+    #[test]
+    fn tenants_cannot_have_tenants() {
+        let tmp_dir = TempDir::new("tenants").unwrap();
+        let dir = tmp_dir.path();
+        write_settings(dir, "b.yaml", "/var/lib/iotedge/b", 9090, &[]);
+        write_settings(
+            dir,
+            "a.yaml",
+            "/var/lib/iotedge/a",
+            9080,
+            &[("b", "b.yaml")],
+        );
+        let host = write_settings(
+            dir,
+            "host.yaml",
+            "/var/lib/iotedge",
+            8080,
+            &[("a", "a.yaml")],
+        );
+
+        assert_eq!("a", invalid_tenant_name(check(&host)));
+    }
+}
@@ -3,6 +3,7 @@
 use crate::app;
 use crate::error::Error;
 use crate::signal;
+use crate::tenants::Tenants;
 
 #[cfg(feature = "runtime-docker")]
 type ModuleRuntime = edgelet_docker::DockerModuleRuntime;
@@ -11,11 +12,24 @@ type ModuleRuntime = edgelet_kube::KubeModuleRuntime<
     kube_client::ValueToken,
     kube_client::HttpClient<hyper_tls::HttpsConnector<hyper::client::HttpConnector>, hyper::Body>,
 >;
+#[cfg(feature = "runtime-cri")]
+type ModuleRuntime = edgelet_cri::CriModuleRuntime;
+#[cfg(feature = "runtime-podman")]
+type ModuleRuntime = edgelet_podman::PodmanModuleRuntime;
+#[cfg(feature = "runtime-process")]
+type ModuleRuntime = edgelet_process::ProcessModuleRuntime;
 
 pub fn run() -> Result<(), Error> {
-    let settings = app::init()?;
-    let main = super::Main::<ModuleRuntime>::new(settings);
+    let (settings, config_file) = app::init()?;
 
-    main.run_until(signal::shutdown)?;
-    Ok(())
+    // The tenants run alongside the host's own device identity, until the
+    // daemon shuts down.
+    let tenants = Tenants::start(&settings, app::load_settings)?;
+
+    let main = super::Main::<ModuleRuntime>::new(settings)
+        .with_settings_reload(move || app::load_settings(&config_file));
+
+    let result = main.run_until(signal::shutdown);
+    tenants.stop();
+    result
 }
@@ -2,6 +2,8 @@
 
 use std::env;
 use std::ffi::OsString;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 
 use clap::crate_name;
@@ -16,6 +18,8 @@ use windows_service::service_control_handler::{
 };
 use windows_service::{define_windows_service, service_dispatcher};
 
+use edgelet_core::{StartupStage, StartupState};
+
 use crate::app;
 use crate::error::{Error, ErrorKind, InitializeErrorReason, ServiceError};
 use crate::logging;
@@ -28,30 +32,35 @@ type ModuleRuntime = edgelet_kube::KubeModuleRuntime<
     kube_client::ValueToken,
     kube_client::HttpClient<hyper_tls::HttpsConnector<hyper::client::HttpConnector>, hyper::Body>,
 >;
+#[cfg(feature = "runtime-cri")]
+type ModuleRuntime = edgelet_cri::CriModuleRuntime;
+#[cfg(feature = "runtime-podman")]
+type ModuleRuntime = edgelet_podman::PodmanModuleRuntime;
+#[cfg(feature = "runtime-process")]
+type ModuleRuntime = edgelet_process::ProcessModuleRuntime;
 
 const RUN_AS_CONSOLE_KEY: &str = "IOTEDGE_RUN_AS_CONSOLE";
 const IOTEDGED_SERVICE_NAME: &str = crate_name!();
 
+/// How often the checkpoint of a pending start or stop is advanced. Provisioning and
+/// stopping the modules can take minutes, so the SCM is told regularly that the
+/// service is still making progress.
+const PENDING_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the SCM waits for the next checkpoint before it considers the service hung.
+const PENDING_WAIT_HINT: Duration = Duration::from_secs(30);
+
 define_windows_service!(ffi_service_main, iotedge_service_main);
 
 fn iotedge_service_main(args: Vec<OsString>) {
-    match run_as_service(args) {
-        Ok(status_handle) => {
-            // Graceful shutdown
-            info!("Stopping {} service...", IOTEDGED_SERVICE_NAME);
-            update_service_state(status_handle, ServiceState::Stopped).unwrap();
-            info!("Stopped {} service.", IOTEDGED_SERVICE_NAME);
-        }
-
-        Err(err) => {
-            error!("Error while running service. Quitting.");
-            logging::log_error(&err);
-            std::process::exit(1);
-        }
+    if let Err(err) = run_as_service(args) {
+        error!("Error while running service. Quitting.");
+        logging::log_error(&err);
+        std::process::exit(1);
     }
 }
 
-fn run_as_service(_: Vec<OsString>) -> Result<ServiceStatusHandle, Error> {
+fn run_as_service(_: Vec<OsString>) -> Result<(), Error> {
     // Configure a Signal Future to alert us if Windows shuts us down.
     let windows_signal = signal_future::signal();
     let ws_signaler = windows_signal.clone();
@@ -60,7 +69,9 @@ fn run_as_service(_: Vec<OsString>) -> Result<ServiceStatusHandle, Error> {
     let status_handle = register(
         IOTEDGED_SERVICE_NAME,
         move |control_event| match control_event {
-            ServiceControl::Shutdown | ServiceControl::Stop => {
+            // Pre-shutdown is sent before the system shuts down, while there's still
+            // time to stop the modules gracefully.
+            ServiceControl::Preshutdown | ServiceControl::Shutdown | ServiceControl::Stop => {
                 info!("{} service is shutting down", IOTEDGED_SERVICE_NAME);
 
                 let mut windows_signal = windows_signal.clone();
@@ -77,22 +88,53 @@ fn run_as_service(_: Vec<OsString>) -> Result<ServiceStatusHandle, Error> {
         InitializeErrorReason::RegisterWindowsService,
     ))?;
 
+    let status = ServiceStatusReporter::new(status_handle);
+    match run_service(&status, ws_signaler) {
+        Ok(()) => {
+            // Graceful shutdown
+            info!("Stopping {} service...", IOTEDGED_SERVICE_NAME);
+            status.set(ServiceState::Stopped)?;
+            info!("Stopped {} service.", IOTEDGED_SERVICE_NAME);
+            Ok(())
+        }
+        Err(err) => {
+            // Reporting the exit code, rather than just exiting, lets the recovery
+            // actions of the service tell a failure apart from a crash.
+            #[allow(clippy::cast_sign_loss)]
+            let exit_code = ServiceExitCode::ServiceSpecific(i32::from(err.kind()) as u32);
+            if let Err(err) = status.stop_with(exit_code) {
+                logging::log_error(&err);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn run_service(
+    status: &ServiceStatusReporter,
+    ws_signaler: signal_future::SignalFuture,
+) -> Result<(), Error> {
+    status.set(ServiceState::StartPending)?;
+
     // initialize iotedged
     info!("Initializing {} service.", IOTEDGED_SERVICE_NAME);
-    let settings = app::init_win_svc()?;
+    let (settings, _) = app::init_win_svc()?;
     let main = super::Main::<ModuleRuntime>::new(settings);
 
-    // tell Windows we're all set
-    update_service_state(status_handle, ServiceState::Running)?;
+    // The service keeps reporting that it is starting until the daemon serves its APIs,
+    // which can take a while when the device has to be provisioned first.
+    status.report_progress(main.startup_state().clone());
 
     // start running
     info!("Starting {} service.", IOTEDGED_SERVICE_NAME);
+    let status = status.clone();
     main.run_until(move || {
+        let status = status.clone();
         signal::shutdown()
             .select(ws_signaler.clone())
             .map(move |_| {
                 info!("Stopping {} service.", IOTEDGED_SERVICE_NAME);
-                if let Err(err) = update_service_state(status_handle, ServiceState::StopPending) {
+                if let Err(err) = status.set(ServiceState::StopPending) {
                     error!(
                         "An error occurred while setting service status to STOP_PENDING: {:?}",
                         err,
@@ -102,11 +144,11 @@ fn run_as_service(_: Vec<OsString>) -> Result<ServiceStatusHandle, Error> {
             .map_err(|_| ())
     })?;
 
-    Ok(status_handle)
+    Ok(())
 }
 
 pub fn run_as_console() -> Result<(), Error> {
-    let settings = app::init()?;
+    let (settings, _) = app::init()?;
     let main = super::Main::<ModuleRuntime>::new(settings);
 
     main.run_until(signal::shutdown)?;
@@ -135,18 +177,97 @@ pub fn run() -> Result<(), Error> {
     }
 }
 
-fn update_service_state(
-    status_handle: ServiceStatusHandle,
-    current_state: ServiceState,
-) -> Result<(), Error> {
-    status_handle
+/// Reports the state of the service to the SCM. While the service is starting
+/// or stopping, the checkpoint of the pending state is advanced regularly.
+#[derive(Clone)]
+struct ServiceStatusReporter {
+    inner: Arc<Mutex<ReportedStatus>>,
+}
+
+struct ReportedStatus {
+    handle: ServiceStatusHandle,
+    state: ServiceState,
+    checkpoint: u32,
+}
+
+impl ServiceStatusReporter {
+    fn new(handle: ServiceStatusHandle) -> Self {
+        ServiceStatusReporter {
+            inner: Arc::new(Mutex::new(ReportedStatus {
+                handle,
+                state: ServiceState::StartPending,
+                checkpoint: 0,
+            })),
+        }
+    }
+
+    fn set(&self, state: ServiceState) -> Result<(), Error> {
+        let mut status = self.lock();
+        status.state = state;
+        status.checkpoint = 0;
+        update_service_state(&status, ServiceExitCode::Win32(0))
+    }
+
+    fn stop_with(&self, exit_code: ServiceExitCode) -> Result<(), Error> {
+        let mut status = self.lock();
+        status.state = ServiceState::Stopped;
+        status.checkpoint = 0;
+        update_service_state(&status, exit_code)
+    }
+
+    /// Advances the checkpoint of a pending state until the service stopped, and
+    /// reports the service as running once the daemon finished starting.
+    fn report_progress(&self, startup_state: StartupState) {
+        let reporter = self.clone();
+        thread::spawn(move || loop {
+            thread::sleep(PENDING_CHECKPOINT_INTERVAL);
+
+            let mut status = reporter.lock();
+            match status.state {
+                ServiceState::StartPending if startup_state.stage() == StartupStage::Serving => {
+                    status.state = ServiceState::Running;
+                    status.checkpoint = 0;
+                }
+                ServiceState::StartPending | ServiceState::StopPending => status.checkpoint += 1,
+                ServiceState::Stopped => break,
+                _ => continue,
+            }
+
+            if let Err(err) = update_service_state(&status, ServiceExitCode::Win32(0)) {
+                logging::log_error(&err);
+            }
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, ReportedStatus> {
+        self.inner
+            .lock()
+            .expect("Failed to acquire the service status lock")
+    }
+}
+
+fn update_service_state(status: &ReportedStatus, exit_code: ServiceExitCode) -> Result<(), Error> {
+    // A stop requested while the daemon is still starting is handled as soon as
+    // it serves its APIs.
+    let stop_controls = ServiceControlAccept::STOP
+        | ServiceControlAccept::SHUTDOWN
+        | ServiceControlAccept::PRESHUTDOWN;
+    let (controls_accepted, wait_hint) = match status.state {
+        ServiceState::StartPending => (stop_controls, PENDING_WAIT_HINT),
+        ServiceState::StopPending => (ServiceControlAccept::empty(), PENDING_WAIT_HINT),
+        ServiceState::Stopped => (ServiceControlAccept::empty(), Duration::default()),
+        _ => (stop_controls, Duration::default()),
+    };
+
+    status
+        .handle
         .set_service_status(ServiceStatus {
             service_type: ServiceType::OwnProcess,
-            current_state,
-            controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
-            exit_code: ServiceExitCode::Win32(0),
-            checkpoint: 0,
-            wait_hint: Duration::default(),
+            current_state: status.state,
+            controls_accepted,
+            exit_code,
+            checkpoint: status.checkpoint,
+            wait_hint,
         })
         .context(ErrorKind::UpdateWindowsServiceState)?;
     Ok(())
@@ -5,12 +5,13 @@ use futures::future::{self, Either};
 use futures::Future;
 use hyper::{Method, StatusCode};
 use percent_encoding::{define_encode_set, percent_encode, PercentEncode, PATH_SEGMENT_ENCODE_SET};
+use serde_json::{json, Value};
 
 use edgelet_http::client::{Client, ClientImpl, TokenSource};
 use edgelet_http::error::ErrorKind as HttpErrorKind;
-use edgelet_utils::ensure_not_empty_with_context;
+use edgelet_utils::{ensure_not_empty_with_context, RetryPolicy};
 
-use crate::error::{Error, ErrorKind, ModuleOperationReason};
+use crate::error::{is_device_not_found, Error, ErrorKind, ModuleOperationReason};
 use crate::model::{AuthMechanism, Module};
 
 define_encode_set! {
@@ -33,7 +34,19 @@ where
             ErrorKind::InvalidDeviceId(device_id.clone())
         })?;
 
-        Ok(DeviceClient { client, device_id })
+        // IoT Hub throttles devices that send too many requests, and might
+        // block them if they keep on sending requests regardless.
+        Ok(DeviceClient {
+            client: client.with_throttling(),
+            device_id,
+        })
+    }
+
+    /// Retries the requests that the hub throttled by `policy` instead of the
+    /// default backoff.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.client = self.client.with_throttling_policy(policy);
+        self
     }
 
     pub fn device_id(&self) -> &str {
@@ -46,7 +59,7 @@ where
         authentication: Option<AuthMechanism>,
         managed_by: Option<&str>,
     ) -> impl Future<Item = Module, Error = Error> {
-        self.upsert_module(module_id, authentication, managed_by, false)
+        self.upsert_module(module_id, authentication, managed_by, None)
     }
 
     pub fn update_module(
@@ -55,7 +68,20 @@ where
         authentication: Option<AuthMechanism>,
         managed_by: Option<&str>,
     ) -> impl Future<Item = Module, Error = Error> {
-        self.upsert_module(module_id, authentication, managed_by, true)
+        self.upsert_module(module_id, authentication, managed_by, Some("*"))
+    }
+
+    /// Updates the module only if it wasn't changed since it was read with the
+    /// entity tag `etag`. Otherwise the update fails with
+    /// `ModuleOperationReason::ModuleChanged`.
+    pub fn update_module_if_match(
+        &self,
+        module_id: String,
+        authentication: Option<AuthMechanism>,
+        managed_by: Option<&str>,
+        etag: &str,
+    ) -> impl Future<Item = Module, Error = Error> {
+        self.upsert_module(module_id, authentication, managed_by, Some(etag))
     }
 
     fn upsert_module(
@@ -63,7 +89,7 @@ where
         module_id: String,
         authentication: Option<AuthMechanism>,
         managed_by: Option<&str>,
-        add_if_match: bool,
+        if_match: Option<&str>,
     ) -> impl Future<Item = Module, Error = Error> {
         if module_id.trim().is_empty() {
             Either::B(future::err(Error::from(ErrorKind::UpsertModuleWithReason(
@@ -83,30 +109,46 @@ where
                 module = module.with_managed_by(managed_by.to_string());
             }
 
-            let res = self
-                .client
-                .request::<Module, Module>(
+            let path = format!(
+                "/devices/{}/modules/{}",
+                url_encode(&self.device_id),
+                url_encode(&module_id)
+            );
+            let request = match if_match {
+                Some(etag) => Either::A(self.client.request_if_match::<Module, Module>(
                     Method::PUT,
-                    &format!(
-                        "/devices/{}/modules/{}",
-                        url_encode(&self.device_id),
-                        url_encode(&module_id)
-                    ),
+                    &path,
                     None,
                     Some(module),
-                    add_if_match,
-                )
-                .then(|module| {
-                    let module =
-                        module.with_context(|_| ErrorKind::UpsertModule(module_id.clone()))?;
-                    let module = module.ok_or_else(|| {
+                    etag,
+                )),
+                None => Either::B(self.client.request::<Module, Module>(
+                    Method::PUT,
+                    &path,
+                    None,
+                    Some(module),
+                    false,
+                )),
+            };
+
+            let res = request.then(|module| {
+                let module = module.map_err(|err| match err.kind() {
+                    HttpErrorKind::HttpWithErrorResponse(StatusCode::PRECONDITION_FAILED, _) => {
                         Error::from(ErrorKind::UpsertModuleWithReason(
-                            module_id,
-                            ModuleOperationReason::ModuleNotFound,
+                            module_id.clone(),
+                            ModuleOperationReason::ModuleChanged,
                         ))
-                    })?;
-                    Ok(module)
-                });
+                    }
+                    _ => Error::from(err.context(ErrorKind::UpsertModule(module_id.clone()))),
+                })?;
+                let module = module.ok_or_else(|| {
+                    Error::from(ErrorKind::UpsertModuleWithReason(
+                        module_id,
+                        ModuleOperationReason::ModuleNotFound,
+                    ))
+                })?;
+                Ok(module)
+            });
 
             Either::A(res)
         }
@@ -140,17 +182,17 @@ where
                         ModuleOperationReason::ModuleNotFound,
                     ))),
 
-                    Err(err) => Err({
-                        if let HttpErrorKind::HttpWithErrorResponse(StatusCode::NOT_FOUND, _) =
-                            err.kind()
+                    // A missing device is also reported as 404, but isn't a missing module.
+                    Err(err) => Err(match err.kind() {
+                        HttpErrorKind::HttpWithErrorResponse(StatusCode::NOT_FOUND, body)
+                            if !is_device_not_found(body) =>
                         {
                             Error::from(ErrorKind::GetModuleWithReason(
                                 module_id,
                                 ModuleOperationReason::ModuleNotFound,
                             ))
-                        } else {
-                            Error::from(err.context(ErrorKind::GetModule(module_id)))
                         }
+                        _ => Error::from(err.context(ErrorKind::GetModule(module_id))),
                     }),
                 });
 
@@ -203,6 +245,52 @@ where
             Either::A(res)
         }
     }
+
+    /// Merges `tags` into the tags of the twin of the device, or of its
+    /// module `module_id`. Tags that are set to null are removed.
+    pub fn update_twin_tags(
+        &self,
+        module_id: Option<&str>,
+        tags: &Value,
+    ) -> impl Future<Item = (), Error = Error> {
+        let path = match module_id {
+            Some(module_id) => format!(
+                "/twins/{}/modules/{}",
+                url_encode(&self.device_id),
+                url_encode(module_id)
+            ),
+            None => format!("/twins/{}", url_encode(&self.device_id)),
+        };
+        let twin_id = module_id.map_or_else(
+            || self.device_id.clone(),
+            |module_id| format!("{}/{}", self.device_id, module_id),
+        );
+
+        self.client
+            .request::<Value, Value>(
+                Method::PATCH,
+                &path,
+                None,
+                Some(json!({ "tags": tags })),
+                false,
+            )
+            .map_err(|err| Error::from(err.context(ErrorKind::UpdateTwin(twin_id))))
+            .map(|_| ())
+    }
+
+    /// Sends `body` to the hub as a device-to-cloud message of the device.
+    pub fn send_event(&self, body: &Value) -> impl Future<Item = (), Error = Error> {
+        self.client
+            .request::<Value, Value>(
+                Method::POST,
+                &format!("/devices/{}/messages/events", url_encode(&self.device_id)),
+                None,
+                Some(body.clone()),
+                false,
+            )
+            .map_err(|err| Error::from(err.context(ErrorKind::SendEvent)))
+            .map(|_| ())
+    }
 }
 
 impl<C, T> Clone for DeviceClient<C, T>
@@ -306,7 +394,7 @@ mod tests {
         let name = "";
 
         let task = device_client
-            .upsert_module(name.to_string(), None, None, false)
+            .upsert_module(name.to_string(), None, None, None)
             .then(|result| match result {
                 Ok(_) => panic!("Excepted err got success"),
                 Err(err) => match err.kind() {
@@ -335,7 +423,7 @@ mod tests {
         let name = "     ";
 
         let task = device_client
-            .upsert_module(name.to_string(), None, None, false)
+            .upsert_module(name.to_string(), None, None, None)
             .then(|result| match result {
                 Ok(_) => panic!("Excepted err got success"),
                 Err(err) => match err.kind() {
@@ -399,7 +487,7 @@ mod tests {
                 "m1".to_string(),
                 Some(auth),
                 Some(&"iotedge".to_string()),
-                false,
+                None,
             )
             .then(|result| {
                 assert_eq!(expected_response, result.unwrap());
@@ -461,7 +549,7 @@ mod tests {
                 "m1".to_string(),
                 Some(auth),
                 Some(&"iotedge".to_string()),
-                true,
+                Some("*"),
             )
             .then(|result| {
                 assert_eq!(expected_response, result.unwrap());
@@ -742,4 +830,205 @@ mod tests {
             .block_on(task)
             .unwrap();
     }
+
+    #[test]
+    fn modules_get_device_not_found() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let response = Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(r#"{"Message":"ErrorCode:DeviceNotFound;Device d1 not found"}"#.into())
+                .expect("could not build hyper::Response");
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client
+            .get_module_by_id("m1".to_string())
+            .then(|module| {
+                let err = module.unwrap_err();
+                assert_eq!(ErrorKind::GetModule("m1".to_string()), *err.kind());
+                assert!(err.is_device_unavailable());
+                Ok::<_, Error>(())
+            });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn modules_list_device_disabled() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let response = Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(r#"{"Message":"ErrorCode:IotHubUnauthorizedAccess;Unauthorized"}"#.into())
+                .expect("could not build hyper::Response");
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client.list_modules().then(|modules| {
+            let err = modules.unwrap_err();
+            assert_eq!(ErrorKind::ListModules, *err.kind());
+            assert!(err.is_device_unavailable());
+            Ok::<_, Error>(())
+        });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn module_update_if_match_sends_etag() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::PUT);
+            assert_eq!(req.uri().path(), "/devices/d1/modules/m1");
+            assert_eq!(
+                req.headers().get(hyper::header::IF_MATCH).unwrap(),
+                r#""AAAAAAAAAAE=""#
+            );
+
+            let module = Module::default()
+                .with_device_id("d1".to_string())
+                .with_module_id("m1".to_string())
+                .with_etag(r#""AAAAAAAAAAI=""#.to_string());
+            let mut response = Response::new(serde_json::to_string(&module).unwrap().into());
+            response
+                .headers_mut()
+                .typed_insert(&ContentType(mime::APPLICATION_JSON));
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client
+            .update_module_if_match("m1".to_string(), None, None, r#""AAAAAAAAAAE=""#)
+            .then(|module| {
+                assert_eq!(Some(r#""AAAAAAAAAAI=""#), module.unwrap().etag());
+                Ok::<_, Error>(())
+            });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn module_update_if_match_fails_for_changed_module() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |_req: Request<Body>| {
+            let response = Response::builder()
+                .status(StatusCode::PRECONDITION_FAILED)
+                .body(r#"{"Message":"ErrorCode:PreconditionFailed;Precondition failed"}"#.into())
+                .expect("could not build hyper::Response");
+            Ok(response)
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client
+            .update_module_if_match("m1".to_string(), None, None, r#""AAAAAAAAAAE=""#)
+            .then(|module| {
+                assert_eq!(
+                    ErrorKind::UpsertModuleWithReason(
+                        "m1".to_string(),
+                        ModuleOperationReason::ModuleChanged
+                    ),
+                    *module.unwrap_err().kind()
+                );
+                Ok::<_, Error>(())
+            });
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn twin_tags_update_request() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::PATCH);
+            assert_eq!(req.uri().path(), "/twins/d1/modules/$edgeAgent");
+
+            req.into_body()
+                .concat2()
+                .and_then(|req_body| Ok(serde_json::from_slice::<Value>(&req_body).unwrap()))
+                .and_then(|patch| {
+                    assert_eq!(
+                        json!({ "tags": { "iotedged": { "version": "1.0" } } }),
+                        patch
+                    );
+
+                    let mut response = Response::new(patch.to_string().into());
+                    response
+                        .headers_mut()
+                        .typed_insert(&ContentType(mime::APPLICATION_JSON));
+                    Ok(response)
+                })
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client.update_twin_tags(
+            Some("$edgeAgent"),
+            &json!({ "iotedged": { "version": "1.0" } }),
+        );
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
+
+    #[test]
+    fn send_event_request() {
+        let api_version = "2018-04-10".to_string();
+        let host_name = Url::parse("http://localhost").unwrap();
+
+        let handler = move |req: Request<Body>| {
+            assert_eq!(req.method(), &Method::POST);
+            assert_eq!(req.uri().path(), "/devices/d1/messages/events");
+
+            req.into_body()
+                .concat2()
+                .and_then(|req_body| Ok(serde_json::from_slice::<Value>(&req_body).unwrap()))
+                .and_then(|event| {
+                    assert_eq!(json!({ "version": "1.0" }), event);
+
+                    let mut response = Response::new(Body::empty());
+                    *response.status_mut() = hyper::StatusCode::NO_CONTENT;
+                    Ok(response)
+                })
+        };
+        let client = Client::new(handler, Some(NullTokenSource), api_version, host_name).unwrap();
+
+        let device_client = DeviceClient::new(client, "d1".to_string()).unwrap();
+        let task = device_client.send_event(&json!({ "version": "1.0" }));
+
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+    }
 }
@@ -6,6 +6,9 @@ use std::fmt::Display;
 use failure::{Backtrace, Context, Fail};
 use hyper::StatusCode;
 
+use edgelet_http::Error as HttpError;
+use edgelet_http::ErrorKind as HttpErrorKind;
+
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
@@ -37,6 +40,12 @@ pub enum ErrorKind {
     #[fail(display = "Could not list modules: {}", _0)]
     ListModulesWithReason(ModuleOperationReason),
 
+    #[fail(display = "Could not send a message to the hub")]
+    SendEvent,
+
+    #[fail(display = "Could not update the tags of twin {}", _0)]
+    UpdateTwin(String),
+
     #[fail(display = "Could not upsert module {}", _0)]
     UpsertModule(String),
 
@@ -64,6 +73,25 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// Whether the hub rejected the request because the device itself has been
+    /// disabled or no longer exists, for example after it was reassigned to
+    /// another hub.
+    pub fn is_device_unavailable(&self) -> bool {
+        Fail::iter_chain(self)
+            .filter_map(|cause| cause.downcast_ref::<HttpError>())
+            .any(|err| match err.kind() {
+                HttpErrorKind::HttpWithErrorResponse(StatusCode::UNAUTHORIZED, _) => true,
+                HttpErrorKind::HttpWithErrorResponse(StatusCode::NOT_FOUND, body) => {
+                    is_device_not_found(body)
+                }
+                _ => false,
+            })
+    }
+}
+
+pub(crate) fn is_device_not_found(body: &str) -> bool {
+    body.contains("DeviceNotFound")
 }
 
 impl From<ErrorKind> for Error {
@@ -84,6 +112,7 @@ impl From<Context<ErrorKind>> for Error {
 pub enum ModuleOperationReason {
     EmptyModuleId,
     EmptyResponse,
+    ModuleChanged,
     ModuleNotFound,
 }
 
@@ -95,6 +124,9 @@ impl Display for ModuleOperationReason {
                 f,
                 "IoT Hub returned an empty response when a value was expected"
             ),
+            ModuleOperationReason::ModuleChanged => {
+                write!(f, "Module was changed since it was last read")
+            }
             ModuleOperationReason::ModuleNotFound => write!(f, "Module not found"),
         }
     }
@@ -254,6 +254,8 @@ pub struct Module {
     generation_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     authentication: Option<AuthMechanism>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
 }
 
 impl Module {
@@ -264,6 +266,7 @@ impl Module {
             device_id: None,
             generation_id: None,
             authentication: None,
+            etag: None,
         }
     }
 
@@ -311,6 +314,17 @@ impl Module {
     pub fn authentication(&self) -> Option<&AuthMechanism> {
         self.authentication.as_ref()
     }
+
+    pub fn with_etag(mut self, etag: String) -> Self {
+        self.etag = Some(etag);
+        self
+    }
+
+    /// The entity tag of the module, which changes whenever the module is
+    /// changed in the hub.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_ref().map(AsRef::as_ref)
+    }
 }
 
 impl Default for Module {
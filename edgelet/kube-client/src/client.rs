@@ -395,6 +395,38 @@ where
             .flatten()
     }
 
+    pub fn get_pod_logs(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        container: Option<&str>,
+        tail_lines: Option<i64>,
+        since_seconds: Option<i64>,
+        timestamps: bool,
+    ) -> impl Future<Item = String, Error = Error> {
+        let params = api_core::ReadNamespacedPodLogOptional {
+            container,
+            tail_lines,
+            since_seconds,
+            timestamps: Some(timestamps),
+            ..api_core::ReadNamespacedPodLogOptional::default()
+        };
+        api_core::Pod::read_namespaced_pod_log(name, namespace, params)
+            .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::PodLog))))
+            .map(|req| {
+                self.request(req, false)
+                    .and_then(|response| match response {
+                        api_core::ReadNamespacedPodLogResponse::Ok(logs) => Ok(logs),
+                        _ => Err(Error::from(ErrorKind::Response(RequestType::PodLog))),
+                    })
+                    .map_err(|err| {
+                        Error::from(err.context(ErrorKind::Response(RequestType::PodLog)))
+                    })
+            })
+            .into_future()
+            .flatten()
+    }
+
     pub fn list_nodes(&mut self) -> impl Future<Item = api_core::NodeList, Error = Error> {
         api_core::Node::list_node(ListOptional::default())
             .map_err(|err| Error::from(err.context(ErrorKind::Request(RequestType::NodeList))))
@@ -1088,6 +1120,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_pod_logs_success() {
+        const NAMESPACE: &str = "custom-namespace";
+        const NAME: &str = "edgehub-5c7d9d7f8-x2x9z";
+        let service = service_fn(|req: Request<Body>| -> Result<Response<Body>, HyperError> {
+            let p = req.uri().path();
+            let q = req.uri().query().unwrap();
+            assert!(p.contains(NAMESPACE));
+            assert!(p.contains(NAME));
+            assert!(p.ends_with("/log"));
+            assert!(q.contains("container=edgehub"));
+            assert!(q.contains("tailLines=10"));
+            assert!(q.contains("sinceSeconds=60"));
+            assert!(q.contains("timestamps=true"));
+            Ok(Response::new(Body::from("line 1\nline 2\n")))
+        });
+
+        let mut client = make_test_client(service);
+
+        let fut = client
+            .get_pod_logs(NAMESPACE, NAME, Some("edgehub"), Some(10), Some(60), true)
+            .map(|logs| {
+                assert_eq!("line 1\nline 2\n", logs);
+            });
+
+        Runtime::new()
+            .unwrap()
+            .block_on(fut)
+            .expect("Expected future to be OK");
+    }
+
+    #[test]
+    fn get_pod_logs_error_response() {
+        const NAMESPACE: &str = "custom-namespace";
+        const NAME: &str = "edgehub-5c7d9d7f8-x2x9z";
+        let service = service_fn(
+            |_req: Request<Body>| -> Result<Response<Body>, HyperError> {
+                let res = Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap();
+                Ok(res)
+            },
+        );
+
+        let mut client = make_test_client(service);
+
+        let fut = client.get_pod_logs(NAMESPACE, NAME, None, None, None, false);
+
+        if let Err(err) = Runtime::new().unwrap().block_on(fut) {
+            assert_eq!(err.kind(), &ErrorKind::Response(RequestType::PodLog))
+        } else {
+            panic!("Expected and error result")
+        }
+    }
+
     const LIST_NODE_RESPONSE: &str = r###"{
             "kind" : "NodeList",
             "items" : [
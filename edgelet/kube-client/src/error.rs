@@ -138,6 +138,7 @@ pub enum RequestType {
     DeploymentReplace,
     DeploymentDelete,
     PodList,
+    PodLog,
     NodeList,
     SecretList,
     SecretCreate,
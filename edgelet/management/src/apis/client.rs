@@ -6,7 +6,9 @@ use hyper;
 pub struct APIClient {
     device_actions_api: Box<dyn crate::apis::DeviceActionsApi>,
     identity_api: Box<dyn crate::apis::IdentityApi>,
+    image_api: Box<dyn crate::apis::ImageApi>,
     module_api: Box<dyn crate::apis::ModuleApi>,
+    settings_api: Box<dyn crate::apis::SettingsApi>,
     system_information_api: Box<dyn crate::apis::SystemInformationApi>,
 }
 
@@ -22,7 +24,9 @@ impl APIClient {
                 configuration.clone(),
             )),
             identity_api: Box::new(crate::apis::IdentityApiClient::new(configuration.clone())),
+            image_api: Box::new(crate::apis::ImageApiClient::new(configuration.clone())),
             module_api: Box::new(crate::apis::ModuleApiClient::new(configuration.clone())),
+            settings_api: Box::new(crate::apis::SettingsApiClient::new(configuration.clone())),
             system_information_api: Box::new(crate::apis::SystemInformationApiClient::new(
                 configuration,
             )),
@@ -37,10 +41,18 @@ impl APIClient {
         self.identity_api.as_ref()
     }
 
+    pub fn image_api(&self) -> &dyn crate::apis::ImageApi {
+        self.image_api.as_ref()
+    }
+
     pub fn module_api(&self) -> &dyn crate::apis::ModuleApi {
         self.module_api.as_ref()
     }
 
+    pub fn settings_api(&self) -> &dyn crate::apis::SettingsApi {
+        self.settings_api.as_ref()
+    }
+
     pub fn system_information_api(&self) -> &dyn crate::apis::SystemInformationApi {
         self.system_information_api.as_ref()
     }
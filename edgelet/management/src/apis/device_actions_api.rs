@@ -34,6 +34,14 @@ pub trait DeviceActionsApi: Send + Sync {
         &self,
         api_version: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    fn reboot_device(
+        &self,
+        api_version: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    fn shutdown_device(
+        &self,
+        api_version: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
 }
 
 impl<C> DeviceActionsApi for DeviceActionsApiClient<C>
@@ -45,6 +53,35 @@ where
     fn reprovision_device(
         &self,
         api_version: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+        self.post_device_action(api_version, "reprovision")
+    }
+
+    fn reboot_device(
+        &self,
+        api_version: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+        self.post_device_action(api_version, "reboot")
+    }
+
+    fn shutdown_device(
+        &self,
+        api_version: &str,
+    ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
+        self.post_device_action(api_version, "shutdown")
+    }
+}
+
+impl<C> DeviceActionsApiClient<C>
+where
+    C: hyper::client::connect::Connect + 'static,
+    <C as hyper::client::connect::Connect>::Transport: 'static,
+    <C as hyper::client::connect::Connect>::Future: 'static,
+{
+    fn post_device_action(
+        &self,
+        api_version: &str,
+        action: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -53,7 +90,7 @@ where
         let query = ::url::form_urlencoded::Serializer::new(String::new())
             .append_pair("api-version", &api_version.to_string())
             .finish();
-        let uri_str = format!("/device/reprovision?{}", query);
+        let uri_str = format!("/device/{}?{}", action, query);
 
         let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
         let mut req = hyper::Request::builder();
@@ -46,6 +46,11 @@ pub trait IdentityApi: Send + Sync {
         &self,
         api_version: &str,
     ) -> Box<dyn Future<Item = crate::models::IdentityList, Error = Error<serde_json::Value>>>;
+    fn regenerate_identity_keys(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = crate::models::Identity, Error = Error<serde_json::Value>>>;
 }
 
 impl<C> IdentityApi for IdentityApiClient<C>
@@ -228,4 +233,62 @@ where
                 }),
         )
     }
+
+    fn regenerate_identity_keys(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = crate::models::Identity, Error = Error<serde_json::Value>>> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::POST;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!(
+            "/identities/{name}/regenerate?{}",
+            query,
+            name = percent_encode(name.as_bytes(), PATH_SEGMENT_ENCODE_SET)
+        );
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|body| {
+                    let parsed: Result<crate::models::Identity, _> = serde_json::from_slice(&body);
+                    parsed.map_err(Error::from)
+                }),
+        )
+    }
 }
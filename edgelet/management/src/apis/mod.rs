@@ -52,8 +52,12 @@ mod device_actions_api;
 pub use self::device_actions_api::{DeviceActionsApi, DeviceActionsApiClient};
 mod identity_api;
 pub use self::identity_api::{IdentityApi, IdentityApiClient};
+mod image_api;
+pub use self::image_api::{ImageApi, ImageApiClient};
 mod module_api;
 pub use self::module_api::{ModuleApi, ModuleApiClient};
+mod settings_api;
+pub use self::settings_api::{SettingsApi, SettingsApiClient};
 mod system_information_api;
 pub use self::system_information_api::{SystemInformationApi, SystemInformationApiClient};
 
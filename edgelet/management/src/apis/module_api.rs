@@ -31,6 +31,11 @@ impl<C: hyper::client::connect::Connect> ModuleApiClient<C> {
 }
 
 pub trait ModuleApi: Send + Sync {
+    fn apply_deployment(
+        &self,
+        api_version: &str,
+        deployment: crate::models::Deployment,
+    ) -> Box<dyn Future<Item = crate::models::ModuleList, Error = Error<serde_json::Value>> + Send>;
     fn create_module(
         &self,
         api_version: &str,
@@ -41,6 +46,11 @@ pub trait ModuleApi: Send + Sync {
         api_version: &str,
         name: &str,
     ) -> Box<dyn Future<Item = (), Error = Error<serde_json::Value>>>;
+    fn export_module_image(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = Vec<u8>, Error = Error<serde_json::Value>> + Send>;
     fn get_module(
         &self,
         api_version: &str,
@@ -57,6 +67,8 @@ pub trait ModuleApi: Send + Sync {
         follow: bool,
         tail: &str,
         since: i32,
+        until: i32,
+        timestamps: bool,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn restart_module(
         &self,
@@ -87,6 +99,69 @@ where
     <C as hyper::client::connect::Connect>::Transport: 'static,
     <C as hyper::client::connect::Connect>::Future: 'static,
 {
+    fn apply_deployment(
+        &self,
+        api_version: &str,
+        deployment: crate::models::Deployment,
+    ) -> Box<dyn Future<Item = crate::models::ModuleList, Error = Error<serde_json::Value>> + Send>
+    {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::POST;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!("/deployment?{}", query);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let serialized = serde_json::to_string(&deployment).unwrap();
+        let serialized_len = serialized.len();
+
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let mut req = req
+            .body(hyper::Body::from(serialized))
+            .expect("could not build hyper::Request");
+        req.headers_mut()
+            .typed_insert(&typed_headers::ContentType(mime::APPLICATION_JSON));
+        req.headers_mut()
+            .typed_insert(&typed_headers::ContentLength(serialized_len as u64));
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                })
+                .and_then(|body| {
+                    let parsed: Result<crate::models::ModuleList, _> =
+                        serde_json::from_slice(&body);
+                    parsed.map_err(Error::from)
+                }),
+        )
+    }
+
     fn create_module(
         &self,
         api_version: &str,
@@ -205,6 +280,60 @@ where
         )
     }
 
+    fn export_module_image(
+        &self,
+        api_version: &str,
+        name: &str,
+    ) -> Box<dyn Future<Item = Vec<u8>, Error = Error<serde_json::Value>> + Send> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::GET;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .finish();
+        let uri_str = format!(
+            "/modules/{name}/image?{}",
+            query,
+            name = percent_encode(name.as_bytes(), PATH_SEGMENT_ENCODE_SET)
+        );
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    body.concat2()
+                        .and_then(move |body| Ok((status, body)))
+                        .map_err(Error::from)
+                })
+                .and_then(|(status, body)| {
+                    if status.is_success() {
+                        Ok(body.to_vec())
+                    } else {
+                        Err(Error::from((status, &*body)))
+                    }
+                }),
+        )
+    }
+
     fn get_module(
         &self,
         api_version: &str,
@@ -327,6 +456,8 @@ where
         follow: bool,
         tail: &str,
         since: i32,
+        until: i32,
+        timestamps: bool,
     ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
         let configuration: &configuration::Configuration<C> = self.configuration.borrow();
 
@@ -337,6 +468,8 @@ where
             .append_pair("follow", &follow.to_string())
             .append_pair("tail", &tail.to_string())
             .append_pair("since", &since.to_string())
+            .append_pair("until", &until.to_string())
+            .append_pair("timestamps", &timestamps.to_string())
             .finish();
         let uri_str = format!(
             "/modules/{name}/logs?{}",
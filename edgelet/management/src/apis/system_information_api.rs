@@ -29,6 +29,12 @@ impl<C: hyper::client::connect::Connect> SystemInformationApiClient<C> {
 }
 
 pub trait SystemInformationApi: Send + Sync {
+    fn get_daemon_logs(
+        &self,
+        api_version: &str,
+        level: &str,
+        tail: &str,
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send>;
     fn get_system_info(
         &self,
         api_version: &str,
@@ -41,6 +47,55 @@ where
     <C as hyper::client::connect::Connect>::Transport: 'static,
     <C as hyper::client::connect::Connect>::Future: 'static,
 {
+    fn get_daemon_logs(
+        &self,
+        api_version: &str,
+        level: &str,
+        tail: &str,
+    ) -> Box<dyn Future<Item = hyper::Body, Error = Error<serde_json::Value>> + Send> {
+        let configuration: &configuration::Configuration<C> = self.configuration.borrow();
+
+        let method = hyper::Method::GET;
+
+        let query = ::url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("api-version", &api_version.to_string())
+            .append_pair("level", &level.to_string())
+            .append_pair("tail", &tail.to_string())
+            .finish();
+        let uri_str = format!("/logs?{}", query);
+
+        let uri = (configuration.uri_composer)(&configuration.base_path, &uri_str);
+        // TODO(farcaller): handle error
+        // if let Err(e) = uri {
+        //     return Box::new(futures::future::err(e));
+        // }
+        let mut req = hyper::Request::builder();
+        req.method(method).uri(uri.unwrap());
+        if let Some(ref user_agent) = configuration.user_agent {
+            req.header(http::header::USER_AGENT, &**user_agent);
+        }
+        let req = req
+            .body(hyper::Body::empty())
+            .expect("could not build hyper::Request");
+
+        // send request
+        Box::new(
+            configuration
+                .client
+                .request(req)
+                .map_err(Error::from)
+                .and_then(|resp| {
+                    let (http::response::Parts { status, .. }, body) = resp.into_parts();
+                    if status.is_success() {
+                        Ok(body)
+                    } else {
+                        let b: &[u8] = &[];
+                        Err(Error::from((status, b)))
+                    }
+                }),
+        )
+    }
+
     fn get_system_info(
         &self,
         api_version: &str,
@@ -0,0 +1,172 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentBootstrap {
+    /// The host name of the hub the device is registered with.
+    #[serde(rename = "iotHubHostname")]
+    iothub_hostname: String,
+    /// The ID of the device.
+    #[serde(rename = "deviceId")]
+    device_id: String,
+    /// The ID of the edge runtime module's identity.
+    #[serde(rename = "moduleId")]
+    module_id: String,
+    /// The host name modules use to connect to the edge hub.
+    #[serde(rename = "gatewayHostname")]
+    gateway_hostname: String,
+    /// The host name of the parent device, if the device is a child in a nested deployment.
+    #[serde(rename = "parentHostname", skip_serializing_if = "Option::is_none")]
+    parent_hostname: Option<String>,
+    /// How the edge runtime module authenticates with the hub.
+    #[serde(rename = "authScheme")]
+    auth_scheme: String,
+    /// The URI of the workload API.
+    #[serde(rename = "workloadUri")]
+    workload_uri: String,
+    /// The URI of the management API.
+    #[serde(rename = "managementUri")]
+    management_uri: String,
+}
+
+impl AgentBootstrap {
+    pub fn new(
+        iothub_hostname: String,
+        device_id: String,
+        module_id: String,
+        gateway_hostname: String,
+        auth_scheme: String,
+        workload_uri: String,
+        management_uri: String,
+    ) -> Self {
+        AgentBootstrap {
+            iothub_hostname,
+            device_id,
+            module_id,
+            gateway_hostname,
+            parent_hostname: None,
+            auth_scheme,
+            workload_uri,
+            management_uri,
+        }
+    }
+
+    pub fn set_iothub_hostname(&mut self, iothub_hostname: String) {
+        self.iothub_hostname = iothub_hostname;
+    }
+
+    pub fn with_iothub_hostname(mut self, iothub_hostname: String) -> Self {
+        self.iothub_hostname = iothub_hostname;
+        self
+    }
+
+    pub fn iothub_hostname(&self) -> &String {
+        &self.iothub_hostname
+    }
+
+    pub fn set_device_id(&mut self, device_id: String) {
+        self.device_id = device_id;
+    }
+
+    pub fn with_device_id(mut self, device_id: String) -> Self {
+        self.device_id = device_id;
+        self
+    }
+
+    pub fn device_id(&self) -> &String {
+        &self.device_id
+    }
+
+    pub fn set_module_id(&mut self, module_id: String) {
+        self.module_id = module_id;
+    }
+
+    pub fn with_module_id(mut self, module_id: String) -> Self {
+        self.module_id = module_id;
+        self
+    }
+
+    pub fn module_id(&self) -> &String {
+        &self.module_id
+    }
+
+    pub fn set_gateway_hostname(&mut self, gateway_hostname: String) {
+        self.gateway_hostname = gateway_hostname;
+    }
+
+    pub fn with_gateway_hostname(mut self, gateway_hostname: String) -> Self {
+        self.gateway_hostname = gateway_hostname;
+        self
+    }
+
+    pub fn gateway_hostname(&self) -> &String {
+        &self.gateway_hostname
+    }
+
+    pub fn set_parent_hostname(&mut self, parent_hostname: String) {
+        self.parent_hostname = Some(parent_hostname);
+    }
+
+    pub fn with_parent_hostname(mut self, parent_hostname: String) -> Self {
+        self.parent_hostname = Some(parent_hostname);
+        self
+    }
+
+    pub fn parent_hostname(&self) -> Option<&String> {
+        self.parent_hostname.as_ref()
+    }
+
+    pub fn reset_parent_hostname(&mut self) {
+        self.parent_hostname = None;
+    }
+
+    pub fn set_auth_scheme(&mut self, auth_scheme: String) {
+        self.auth_scheme = auth_scheme;
+    }
+
+    pub fn with_auth_scheme(mut self, auth_scheme: String) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    pub fn auth_scheme(&self) -> &String {
+        &self.auth_scheme
+    }
+
+    pub fn set_workload_uri(&mut self, workload_uri: String) {
+        self.workload_uri = workload_uri;
+    }
+
+    pub fn with_workload_uri(mut self, workload_uri: String) -> Self {
+        self.workload_uri = workload_uri;
+        self
+    }
+
+    pub fn workload_uri(&self) -> &String {
+        &self.workload_uri
+    }
+
+    pub fn set_management_uri(&mut self, management_uri: String) {
+        self.management_uri = management_uri;
+    }
+
+    pub fn with_management_uri(mut self, management_uri: String) -> Self {
+        self.management_uri = management_uri;
+        self
+    }
+
+    pub fn management_uri(&self) -> &String {
+        &self.management_uri
+    }
+}
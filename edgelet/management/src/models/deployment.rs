@@ -0,0 +1,42 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Deployment {
+    #[serde(rename = "modules")]
+    modules: Vec<crate::models::ModuleSpec>,
+}
+
+impl Deployment {
+    pub fn new(modules: Vec<crate::models::ModuleSpec>) -> Self {
+        Deployment { modules }
+    }
+
+    pub fn set_modules(&mut self, modules: Vec<crate::models::ModuleSpec>) {
+        self.modules = modules;
+    }
+
+    pub fn with_modules(mut self, modules: Vec<crate::models::ModuleSpec>) -> Self {
+        self.modules = modules;
+        self
+    }
+
+    pub fn modules(&self) -> &[crate::models::ModuleSpec] {
+        &self.modules
+    }
+
+    pub fn into_modules(self) -> Vec<crate::models::ModuleSpec> {
+        self.modules
+    }
+}
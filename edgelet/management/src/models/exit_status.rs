@@ -18,6 +18,8 @@ pub struct ExitStatus {
     exit_time: String,
     #[serde(rename = "statusCode")]
     status_code: String,
+    #[serde(rename = "reason", skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
 }
 
 impl ExitStatus {
@@ -25,6 +27,7 @@ impl ExitStatus {
         ExitStatus {
             exit_time,
             status_code,
+            reason: None,
         }
     }
 
@@ -53,4 +56,21 @@ impl ExitStatus {
     pub fn status_code(&self) -> &String {
         &self.status_code
     }
+
+    pub fn set_reason(&mut self, reason: String) {
+        self.reason = Some(reason);
+    }
+
+    pub fn with_reason(mut self, reason: String) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_reason(&mut self) {
+        self.reason = None;
+    }
 }
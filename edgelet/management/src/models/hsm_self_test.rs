@@ -0,0 +1,54 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HsmSelfTest {
+    /// When the self-test ran, in RFC 3339 format.
+    #[serde(rename = "timestamp")]
+    timestamp: String,
+    #[serde(rename = "results")]
+    results: Vec<crate::models::HsmSelfTestResult>,
+}
+
+impl HsmSelfTest {
+    pub fn new(timestamp: String, results: Vec<crate::models::HsmSelfTestResult>) -> Self {
+        HsmSelfTest { timestamp, results }
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: String) {
+        self.timestamp = timestamp;
+    }
+
+    pub fn with_timestamp(mut self, timestamp: String) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn timestamp(&self) -> &String {
+        &self.timestamp
+    }
+
+    pub fn set_results(&mut self, results: Vec<crate::models::HsmSelfTestResult>) {
+        self.results = results;
+    }
+
+    pub fn with_results(mut self, results: Vec<crate::models::HsmSelfTestResult>) -> Self {
+        self.results = results;
+        self
+    }
+
+    pub fn results(&self) -> &[crate::models::HsmSelfTestResult] {
+        &self.results
+    }
+}
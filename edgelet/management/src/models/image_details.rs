@@ -0,0 +1,88 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageDetails {
+    #[serde(rename = "id")]
+    id: String,
+    #[serde(rename = "tags")]
+    tags: Vec<String>,
+    #[serde(rename = "size")]
+    size: i64,
+    #[serde(rename = "inUse")]
+    in_use: bool,
+}
+
+impl ImageDetails {
+    pub fn new(id: String, tags: Vec<String>, size: i64, in_use: bool) -> Self {
+        ImageDetails {
+            id,
+            tags,
+            size,
+            in_use,
+        }
+    }
+
+    pub fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+
+    pub fn with_id(mut self, id: String) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn set_size(&mut self, size: i64) {
+        self.size = size;
+    }
+
+    pub fn with_size(mut self, size: i64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    pub fn set_in_use(&mut self, in_use: bool) {
+        self.in_use = in_use;
+    }
+
+    pub fn with_in_use(mut self, in_use: bool) -> Self {
+        self.in_use = in_use;
+        self
+    }
+
+    pub fn in_use(&self) -> bool {
+        self.in_use
+    }
+}
@@ -0,0 +1,38 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageList {
+    #[serde(rename = "images")]
+    images: Vec<crate::models::ImageDetails>,
+}
+
+impl ImageList {
+    pub fn new(images: Vec<crate::models::ImageDetails>) -> Self {
+        ImageList { images }
+    }
+
+    pub fn set_images(&mut self, images: Vec<crate::models::ImageDetails>) {
+        self.images = images;
+    }
+
+    pub fn with_images(mut self, images: Vec<crate::models::ImageDetails>) -> Self {
+        self.images = images;
+        self
+    }
+
+    pub fn images(&self) -> &[crate::models::ImageDetails] {
+        &self.images
+    }
+}
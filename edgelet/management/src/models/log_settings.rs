@@ -0,0 +1,54 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogSettings {
+    #[serde(rename = "format")]
+    format: String,
+    /// The log levels, in the syntax of the `IOTEDGE_LOG` environment variable.
+    #[serde(rename = "level")]
+    level: String,
+}
+
+impl LogSettings {
+    pub fn new(format: String, level: String) -> Self {
+        LogSettings { format, level }
+    }
+
+    pub fn set_format(&mut self, format: String) {
+        self.format = format;
+    }
+
+    pub fn with_format(mut self, format: String) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn format(&self) -> &String {
+        &self.format
+    }
+
+    pub fn set_level(&mut self, level: String) {
+        self.level = level;
+    }
+
+    pub fn with_level(mut self, level: String) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn level(&self) -> &String {
+        &self.level
+    }
+}
@@ -1,27 +1,51 @@
+mod agent_bootstrap;
+pub use self::agent_bootstrap::AgentBootstrap;
 mod config;
 pub use self::config::Config;
+mod deployment;
+pub use self::deployment::Deployment;
 mod env_var;
 pub use self::env_var::EnvVar;
 mod error_response;
 pub use self::error_response::ErrorResponse;
 mod exit_status;
 pub use self::exit_status::ExitStatus;
+mod hsm_self_test;
+pub use self::hsm_self_test::HsmSelfTest;
+mod hsm_self_test_result;
+pub use self::hsm_self_test_result::HsmSelfTestResult;
 mod identity;
 pub use self::identity::Identity;
 mod identity_list;
 pub use self::identity_list::IdentityList;
 mod identity_spec;
 pub use self::identity_spec::IdentitySpec;
+mod image_details;
+pub use self::image_details::ImageDetails;
+mod image_list;
+pub use self::image_list::ImageList;
 mod update_identity;
 pub use self::update_identity::UpdateIdentity;
+mod log_settings;
+pub use self::log_settings::LogSettings;
 mod module_details;
 pub use self::module_details::ModuleDetails;
 mod module_list;
 pub use self::module_list::ModuleList;
 mod module_spec;
 pub use self::module_spec::ModuleSpec;
+mod operation;
+pub use self::operation::Operation;
+mod pruned_images;
+pub use self::pruned_images::PrunedImages;
 mod runtime_status;
 pub use self::runtime_status::RuntimeStatus;
+mod setting_value;
+pub use self::setting_value::SettingValue;
+mod startup_failure;
+pub use self::startup_failure::StartupFailure;
+mod startup_state;
+pub use self::startup_state::StartupState;
 mod status;
 pub use self::status::Status;
 mod system_info;
@@ -23,6 +23,16 @@ pub struct ModuleSpec {
     config: crate::models::Config,
     #[serde(rename = "imagePullPolicy", skip_serializing_if = "Option::is_none")]
     image_pull_policy: Option<String>,
+    #[serde(rename = "restartPolicy", skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<String>,
+    #[serde(rename = "restartMaxRetries", skip_serializing_if = "Option::is_none")]
+    restart_max_retries: Option<u32>,
+    #[serde(rename = "priority", skip_serializing_if = "Option::is_none")]
+    priority: Option<u32>,
+    #[serde(rename = "waitForHealthy", skip_serializing_if = "Option::is_none")]
+    wait_for_healthy: Option<bool>,
+    #[serde(rename = "dependsOn", skip_serializing_if = "Option::is_none")]
+    depends_on: Option<Vec<String>>,
 }
 
 impl ModuleSpec {
@@ -32,6 +42,11 @@ impl ModuleSpec {
             type_,
             config,
             image_pull_policy: None,
+            restart_policy: None,
+            restart_max_retries: None,
+            priority: None,
+            wait_for_healthy: None,
+            depends_on: None,
         }
     }
 
@@ -90,4 +105,89 @@ impl ModuleSpec {
     pub fn reset_image_pull_policy(&mut self) {
         self.image_pull_policy = None;
     }
+
+    pub fn set_restart_policy(&mut self, restart_policy: String) {
+        self.restart_policy = Some(restart_policy);
+    }
+
+    pub fn with_restart_policy(mut self, restart_policy: String) -> Self {
+        self.restart_policy = Some(restart_policy);
+        self
+    }
+
+    pub fn restart_policy(&self) -> Option<&str> {
+        self.restart_policy.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_restart_policy(&mut self) {
+        self.restart_policy = None;
+    }
+
+    pub fn set_restart_max_retries(&mut self, restart_max_retries: u32) {
+        self.restart_max_retries = Some(restart_max_retries);
+    }
+
+    pub fn with_restart_max_retries(mut self, restart_max_retries: u32) -> Self {
+        self.restart_max_retries = Some(restart_max_retries);
+        self
+    }
+
+    pub fn restart_max_retries(&self) -> Option<u32> {
+        self.restart_max_retries
+    }
+
+    pub fn reset_restart_max_retries(&mut self) {
+        self.restart_max_retries = None;
+    }
+
+    pub fn set_priority(&mut self, priority: u32) {
+        self.priority = Some(priority);
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn priority(&self) -> Option<u32> {
+        self.priority
+    }
+
+    pub fn reset_priority(&mut self) {
+        self.priority = None;
+    }
+
+    pub fn set_wait_for_healthy(&mut self, wait_for_healthy: bool) {
+        self.wait_for_healthy = Some(wait_for_healthy);
+    }
+
+    pub fn with_wait_for_healthy(mut self, wait_for_healthy: bool) -> Self {
+        self.wait_for_healthy = Some(wait_for_healthy);
+        self
+    }
+
+    pub fn wait_for_healthy(&self) -> Option<bool> {
+        self.wait_for_healthy
+    }
+
+    pub fn reset_wait_for_healthy(&mut self) {
+        self.wait_for_healthy = None;
+    }
+
+    pub fn set_depends_on(&mut self, depends_on: Vec<String>) {
+        self.depends_on = Some(depends_on);
+    }
+
+    pub fn with_depends_on(mut self, depends_on: Vec<String>) -> Self {
+        self.depends_on = Some(depends_on);
+        self
+    }
+
+    pub fn depends_on(&self) -> Option<&[String]> {
+        self.depends_on.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_depends_on(&mut self) {
+        self.depends_on = None;
+    }
 }
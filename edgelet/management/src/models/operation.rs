@@ -0,0 +1,174 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Operation {
+    #[serde(rename = "id")]
+    id: String,
+    /// The kind of operation, e.g. create_module.
+    #[serde(rename = "kind")]
+    kind: String,
+    /// The module or image the operation acts on.
+    #[serde(rename = "target")]
+    target: String,
+    /// running, succeeded or failed.
+    #[serde(rename = "status")]
+    status: String,
+    /// An estimate of how much of the operation is done, from 0 to 100.
+    #[serde(rename = "percent")]
+    percent: i32,
+    /// What the operation is doing while it runs, or why it failed.
+    #[serde(rename = "message", skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// When the operation started, in RFC 3339 format.
+    #[serde(rename = "startedAt")]
+    started_at: String,
+    /// When the operation finished, in RFC 3339 format.
+    #[serde(rename = "finishedAt", skip_serializing_if = "Option::is_none")]
+    finished_at: Option<String>,
+}
+
+impl Operation {
+    pub fn new(
+        id: String,
+        kind: String,
+        target: String,
+        status: String,
+        percent: i32,
+        started_at: String,
+    ) -> Self {
+        Operation {
+            id,
+            kind,
+            target,
+            status,
+            percent,
+            message: None,
+            started_at,
+            finished_at: None,
+        }
+    }
+
+    pub fn set_id(&mut self, id: String) {
+        self.id = id;
+    }
+
+    pub fn with_id(mut self, id: String) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> &String {
+        &self.id
+    }
+
+    pub fn set_kind(&mut self, kind: String) {
+        self.kind = kind;
+    }
+
+    pub fn with_kind(mut self, kind: String) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn kind(&self) -> &String {
+        &self.kind
+    }
+
+    pub fn set_target(&mut self, target: String) {
+        self.target = target;
+    }
+
+    pub fn with_target(mut self, target: String) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target(&self) -> &String {
+        &self.target
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = status;
+    }
+
+    pub fn with_status(mut self, status: String) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn status(&self) -> &String {
+        &self.status
+    }
+
+    pub fn set_percent(&mut self, percent: i32) {
+        self.percent = percent;
+    }
+
+    pub fn with_percent(mut self, percent: i32) -> Self {
+        self.percent = percent;
+        self
+    }
+
+    pub fn percent(&self) -> i32 {
+        self.percent
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = Some(message);
+        self
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_message(&mut self) {
+        self.message = None;
+    }
+
+    pub fn set_started_at(&mut self, started_at: String) {
+        self.started_at = started_at;
+    }
+
+    pub fn with_started_at(mut self, started_at: String) -> Self {
+        self.started_at = started_at;
+        self
+    }
+
+    pub fn started_at(&self) -> &String {
+        &self.started_at
+    }
+
+    pub fn set_finished_at(&mut self, finished_at: String) {
+        self.finished_at = Some(finished_at);
+    }
+
+    pub fn with_finished_at(mut self, finished_at: String) -> Self {
+        self.finished_at = Some(finished_at);
+        self
+    }
+
+    pub fn finished_at(&self) -> Option<&str> {
+        self.finished_at.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_finished_at(&mut self) {
+        self.finished_at = None;
+    }
+}
@@ -0,0 +1,56 @@
+/*
+ * IoT Edge Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrunedImages {
+    #[serde(rename = "images")]
+    images: Vec<String>,
+    #[serde(rename = "spaceReclaimed")]
+    space_reclaimed: i64,
+}
+
+impl PrunedImages {
+    pub fn new(images: Vec<String>, space_reclaimed: i64) -> Self {
+        PrunedImages {
+            images,
+            space_reclaimed,
+        }
+    }
+
+    pub fn set_images(&mut self, images: Vec<String>) {
+        self.images = images;
+    }
+
+    pub fn with_images(mut self, images: Vec<String>) -> Self {
+        self.images = images;
+        self
+    }
+
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+
+    pub fn set_space_reclaimed(&mut self, space_reclaimed: i64) {
+        self.space_reclaimed = space_reclaimed;
+    }
+
+    pub fn with_space_reclaimed(mut self, space_reclaimed: i64) -> Self {
+        self.space_reclaimed = space_reclaimed;
+        self
+    }
+
+    pub fn space_reclaimed(&self) -> i64 {
+        self.space_reclaimed
+    }
+}
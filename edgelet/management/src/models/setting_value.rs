@@ -0,0 +1,39 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SettingValue {
+    /// The value of a setting in config.yaml.
+    #[serde(rename = "value")]
+    value: String,
+}
+
+impl SettingValue {
+    pub fn new(value: String) -> Self {
+        SettingValue { value }
+    }
+
+    pub fn set_value(&mut self, value: String) {
+        self.value = value;
+    }
+
+    pub fn with_value(mut self, value: String) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn value(&self) -> &String {
+        &self.value
+    }
+}
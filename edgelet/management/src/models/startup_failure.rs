@@ -0,0 +1,74 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartupFailure {
+    /// The startup stage the daemon failed in.
+    #[serde(rename = "stage")]
+    stage: String,
+    #[serde(rename = "message")]
+    message: String,
+    /// When the daemon failed, in RFC 3339 format.
+    #[serde(rename = "time")]
+    time: String,
+}
+
+impl StartupFailure {
+    pub fn new(stage: String, message: String, time: String) -> Self {
+        StartupFailure {
+            stage,
+            message,
+            time,
+        }
+    }
+
+    pub fn set_stage(&mut self, stage: String) {
+        self.stage = stage;
+    }
+
+    pub fn with_stage(mut self, stage: String) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn stage(&self) -> &String {
+        &self.stage
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    pub fn with_message(mut self, message: String) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn message(&self) -> &String {
+        &self.message
+    }
+
+    pub fn set_time(&mut self, time: String) {
+        self.time = time;
+    }
+
+    pub fn with_time(mut self, time: String) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn time(&self) -> &String {
+        &self.time
+    }
+}
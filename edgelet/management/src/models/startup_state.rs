@@ -0,0 +1,61 @@
+/*
+ * IoT Edge Module Management API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-11-05
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StartupState {
+    /// The startup stage the daemon is in.
+    #[serde(rename = "stage")]
+    stage: String,
+    #[serde(rename = "lastFailure", skip_serializing_if = "Option::is_none")]
+    last_failure: Option<crate::models::StartupFailure>,
+}
+
+impl StartupState {
+    pub fn new(stage: String) -> Self {
+        StartupState {
+            stage,
+            last_failure: None,
+        }
+    }
+
+    pub fn set_stage(&mut self, stage: String) {
+        self.stage = stage;
+    }
+
+    pub fn with_stage(mut self, stage: String) -> Self {
+        self.stage = stage;
+        self
+    }
+
+    pub fn stage(&self) -> &String {
+        &self.stage
+    }
+
+    pub fn set_last_failure(&mut self, last_failure: crate::models::StartupFailure) {
+        self.last_failure = Some(last_failure);
+    }
+
+    pub fn with_last_failure(mut self, last_failure: crate::models::StartupFailure) -> Self {
+        self.last_failure = Some(last_failure);
+        self
+    }
+
+    pub fn last_failure(&self) -> Option<&crate::models::StartupFailure> {
+        self.last_failure.as_ref()
+    }
+
+    pub fn reset_last_failure(&mut self) {
+        self.last_failure = None;
+    }
+}
@@ -20,6 +20,15 @@ pub struct SystemInfo {
     architecture: String,
     #[serde(rename = "version")]
     version: String,
+    #[serde(
+        rename = "provisioningPayload",
+        skip_serializing_if = "Option::is_none"
+    )]
+    provisioning_payload: Option<Value>,
+    #[serde(rename = "warnings", skip_serializing_if = "Option::is_none")]
+    warnings: Option<Vec<String>>,
+    #[serde(rename = "hsmSelfTest", skip_serializing_if = "Option::is_none")]
+    hsm_self_test: Option<crate::models::HsmSelfTest>,
 }
 
 impl SystemInfo {
@@ -28,6 +37,9 @@ impl SystemInfo {
             os_type,
             architecture,
             version,
+            provisioning_payload: None,
+            warnings: None,
+            hsm_self_test: None,
         }
     }
 
@@ -69,4 +81,55 @@ impl SystemInfo {
     pub fn version(&self) -> &String {
         &self.version
     }
+
+    pub fn set_provisioning_payload(&mut self, provisioning_payload: Value) {
+        self.provisioning_payload = Some(provisioning_payload);
+    }
+
+    pub fn with_provisioning_payload(mut self, provisioning_payload: Value) -> Self {
+        self.provisioning_payload = Some(provisioning_payload);
+        self
+    }
+
+    pub fn provisioning_payload(&self) -> Option<&Value> {
+        self.provisioning_payload.as_ref()
+    }
+
+    pub fn reset_provisioning_payload(&mut self) {
+        self.provisioning_payload = None;
+    }
+
+    pub fn set_warnings(&mut self, warnings: Vec<String>) {
+        self.warnings = Some(warnings);
+    }
+
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.warnings = Some(warnings);
+        self
+    }
+
+    pub fn warnings(&self) -> Option<&[String]> {
+        self.warnings.as_ref().map(AsRef::as_ref)
+    }
+
+    pub fn reset_warnings(&mut self) {
+        self.warnings = None;
+    }
+
+    pub fn set_hsm_self_test(&mut self, hsm_self_test: crate::models::HsmSelfTest) {
+        self.hsm_self_test = Some(hsm_self_test);
+    }
+
+    pub fn with_hsm_self_test(mut self, hsm_self_test: crate::models::HsmSelfTest) -> Self {
+        self.hsm_self_test = Some(hsm_self_test);
+        self
+    }
+
+    pub fn hsm_self_test(&self) -> Option<&crate::models::HsmSelfTest> {
+        self.hsm_self_test.as_ref()
+    }
+
+    pub fn reset_hsm_self_test(&mut self) {
+        self.hsm_self_test = None;
+    }
 }
@@ -9,7 +9,7 @@
     clippy::use_self
 )]
 
-use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
 
 use failure::ResultExt;
 
@@ -36,7 +36,13 @@ where
         .next()
         .ok_or(ErrorKind::ResolveNtpPoolHostname(None))?;
 
-    let socket = UdpSocket::bind("0.0.0.0:0").context(ErrorKind::BindLocalSocket)?;
+    // The local socket has to be of the server address's family, or sending
+    // to an IPv6 server fails, as does binding 0.0.0.0 on an IPv6-only host.
+    let local_addr: SocketAddr = match addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local_addr).context(ErrorKind::BindLocalSocket)?;
     socket
         .set_read_timeout(Some(std::time::Duration::from_secs(10)))
         .context(ErrorKind::SetReadTimeoutOnSocket)?;
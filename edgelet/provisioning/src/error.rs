@@ -18,9 +18,15 @@ pub enum ErrorKind {
     #[fail(display = "Could not restore previous provisioning result")]
     CouldNotRestore,
 
+    #[fail(display = "Could not derive the device key from the group enrollment key")]
+    DeriveDeviceKey,
+
     #[fail(display = "Could not initialize DPS provisioning client")]
     DpsInitialization,
 
+    #[fail(display = "The identity certificate is needed to register with DPS over MQTT")]
+    DpsMqttIdentityCertificate,
+
     #[fail(display = "Failure during external provisioning. {}", _0)]
     ExternalProvisioning(ExternalProvisioningErrorReason),
 
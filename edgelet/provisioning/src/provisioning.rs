@@ -12,16 +12,22 @@ use futures::future::Either;
 use futures::{future, Future, IntoFuture};
 use log::info;
 use serde_derive::{Deserialize, Serialize};
-use serde_json;
+use serde_json::{self, Value};
 use url::Url;
 
+use dps::mqtt::{MqttAuth, MqttDpsClient};
 use dps::registration::{DpsAuthKind, DpsClient, DpsTokenSource};
-use edgelet_core::crypto::{Activate, KeyIdentity, KeyStore, MemoryKey, MemoryKeyStore};
-use edgelet_core::ProvisioningResult as CoreProvisioningResult;
+use dps::{Error as DpsError, RegistrationInfo};
+use edgelet_core::crypto::{
+    Activate, Decrypt, Encrypt, KeyIdentity, KeyStore, MakeRandom, MemoryKey, MemoryKeyStore, Sign,
+    Signature, SignatureAlgorithm,
+};
+use edgelet_core::{ClockSkew, DpsTransport, ProvisioningResult as CoreProvisioningResult};
 use edgelet_hsm::tpm::{TpmKey, TpmKeyStore};
 use edgelet_http::client::{Client as HttpClient, ClientImpl};
+use edgelet_http::PemCertificate;
 use edgelet_http_external_provisioning::ExternalProvisioningInterface;
-use edgelet_utils::log_failure;
+use edgelet_utils::{log_failure, RetryPolicy};
 use external_provisioning::models::Credentials as ExternalProvisioningCredentials;
 use hsm::TpmKey as HsmTpmKey;
 use log::{debug, Level};
@@ -164,6 +170,10 @@ pub struct ProvisioningResult {
     reconfigure: ReprovisioningStatus,
     #[serde(skip_serializing_if = "Option::is_none")]
     credentials: Option<Credentials>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<Value>,
+    #[serde(skip)]
+    restored: bool,
 }
 
 impl ProvisioningResult {
@@ -180,9 +190,16 @@ impl ProvisioningResult {
             sha256_thumbprint: sha256_thumbprint.map(&str::to_owned),
             reconfigure,
             credentials,
+            payload: None,
+            restored: false,
         }
     }
 
+    pub fn with_payload(mut self, payload: Option<Value>) -> Self {
+        self.payload = payload;
+        self
+    }
+
     pub fn reconfigure(&self) -> ReprovisioningStatus {
         self.reconfigure
     }
@@ -190,6 +207,18 @@ impl ProvisioningResult {
     pub fn credentials(&self) -> Option<&Credentials> {
         self.credentials.as_ref()
     }
+
+    /// The custom allocation payload returned by DPS.
+    pub fn payload(&self) -> Option<&Value> {
+        self.payload.as_ref()
+    }
+
+    /// Whether this result was restored from the backup of an earlier
+    /// provisioning because the provisioning service failed, for example
+    /// because it could not be reached.
+    pub fn restored(&self) -> bool {
+        self.restored
+    }
 }
 
 impl CoreProvisioningResult for ProvisioningResult {
@@ -249,6 +278,8 @@ impl Provision for ManualProvisioning {
                 reconfigure: ReprovisioningStatus::DeviceDataNotUpdated,
                 sha256_thumbprint: None,
                 credentials: None,
+                payload: None,
+                restored: false,
             })
             .map_err(|err| Error::from(err.context(ErrorKind::Provision)));
         Box::new(result.into_future())
@@ -436,7 +467,9 @@ where
                     hub_name: device_provisioning_info.hub_name().to_string(),
                     reconfigure,
                     sha256_thumbprint: None,
-                    credentials: Some(credentials)
+                    credentials: Some(credentials),
+                    payload: None,
+                    restored: false,
                 })
             });
 
@@ -470,6 +503,8 @@ where
     registration_id: String,
     hsm_tpm_ek: HsmTpmKey,
     hsm_tpm_srk: HsmTpmKey,
+    payload: Option<Value>,
+    retry_policy: Option<RetryPolicy>,
 }
 
 impl<C> DpsTpmProvisioning<C>
@@ -499,9 +534,31 @@ where
             registration_id,
             hsm_tpm_ek,
             hsm_tpm_srk,
+            payload: None,
+            retry_policy: None,
         };
         Ok(result)
     }
+
+    /// Sends a custom allocation payload with the registration request.
+    pub fn with_payload(mut self, payload: Option<Value>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Retries the registration requests that fail because DPS is throttling
+    /// or unavailable by `retry_policy`, if any.
+    pub fn with_retry_policy(mut self, retry_policy: Option<RetryPolicy>) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Records how far the local clock is off from the time of DPS, and gives
+    /// the SAS tokens of the registration an expiry by the time of DPS.
+    pub fn with_clock_skew(mut self, clock_skew: ClockSkew) -> Self {
+        self.client = self.client.with_clock_skew(clock_skew);
+        self
+    }
 }
 
 impl<C> Provision for DpsTpmProvisioning<C>
@@ -522,12 +579,16 @@ where
             self.registration_id.clone(),
             DpsAuthKind::Tpm { ek, srk },
             key_activator,
-        );
+        )
+        .map(|c| {
+            c.with_payload(self.payload.clone())
+                .with_retry_policy(self.retry_policy.clone())
+        });
 
         let d = match c {
             Ok(c) => Either::A(
                 c.register()
-                    .map(|(device_id, hub_name, _substatus)| {
+                    .map(|(device_id, hub_name, _substatus, payload)| {
                         info!(
                             "DPS registration assigned device \"{}\" in hub \"{}\"",
                             device_id, hub_name
@@ -542,6 +603,8 @@ where
                             reconfigure: ReprovisioningStatus::InitialAssignment,
                             sha256_thumbprint: None,
                             credentials: None,
+                            payload,
+                            restored: false,
                         }
                     })
                     .map_err(|err| Error::from(err.context(ErrorKind::Provision))),
@@ -563,8 +626,12 @@ where
     C: ClientImpl,
 {
     client: HttpClient<C, DpsTokenSource<MemoryKey>>,
+    endpoint: Url,
     scope_id: String,
     registration_id: String,
+    payload: Option<Value>,
+    retry_policy: Option<RetryPolicy>,
+    transport: DpsTransport,
 }
 
 impl<C> DpsSymmetricKeyProvisioning<C>
@@ -582,16 +649,83 @@ where
             client_impl,
             None as Option<DpsTokenSource<MemoryKey>>,
             api_version,
-            endpoint,
+            endpoint.clone(),
         )
         .context(ErrorKind::DpsInitialization)?;
         let result = DpsSymmetricKeyProvisioning {
             client,
+            endpoint,
             scope_id,
             registration_id,
+            payload: None,
+            retry_policy: None,
+            transport: DpsTransport::default(),
         };
         Ok(result)
     }
+
+    /// Sends a custom allocation payload with the registration request.
+    pub fn with_payload(mut self, payload: Option<Value>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Retries the registration requests that fail because DPS is throttling
+    /// or unavailable by `retry_policy`, if any.
+    pub fn with_retry_policy(mut self, retry_policy: Option<RetryPolicy>) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Records how far the local clock is off from the time of DPS, and gives
+    /// the SAS tokens of the registration an expiry by the time of DPS.
+    pub fn with_clock_skew(mut self, clock_skew: ClockSkew) -> Self {
+        self.client = self.client.with_clock_skew(clock_skew);
+        self
+    }
+
+    /// Registers over the given transport instead of HTTPS.
+    pub fn with_transport(mut self, transport: DpsTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    fn register(
+        &self,
+        key_activator: MemoryKeyStore,
+    ) -> Result<Box<dyn Future<Item = RegistrationInfo, Error = DpsError> + Send>, Error>
+    where
+        C: 'static,
+    {
+        match self.transport {
+            DpsTransport::Https => {
+                let client = DpsClient::new(
+                    self.client.clone(),
+                    self.scope_id.clone(),
+                    self.registration_id.clone(),
+                    DpsAuthKind::SymmetricKey,
+                    key_activator,
+                )
+                .context(ErrorKind::Provision)?;
+                Ok(client
+                    .with_payload(self.payload.clone())
+                    .with_retry_policy(self.retry_policy.clone())
+                    .register())
+            }
+            DpsTransport::Mqtt => {
+                let key = key_activator
+                    .get(&KeyIdentity::Device, "primary")
+                    .context(ErrorKind::Provision)?;
+                let client = MqttDpsClient::new(
+                    mqtt_host(&self.endpoint)?,
+                    self.scope_id.clone(),
+                    self.registration_id.clone(),
+                    MqttAuth::SymmetricKey(key),
+                );
+                Ok(client.with_payload(self.payload.clone()).register())
+            }
+        }
+    }
 }
 
 impl<C> Provision for DpsSymmetricKeyProvisioning<C>
@@ -604,18 +738,10 @@ where
         &self,
         key_activator: Self::Hsm,
     ) -> Box<dyn Future<Item = ProvisioningResult, Error = Error> + Send> {
-        let c = DpsClient::new(
-            self.client.clone(),
-            self.scope_id.clone(),
-            self.registration_id.clone(),
-            DpsAuthKind::SymmetricKey,
-            key_activator,
-        );
-
-        let d = match c {
-            Ok(c) => Either::A(
-                c.register()
-                    .map(|(device_id, hub_name, substatus)| {
+        let d = match self.register(key_activator) {
+            Ok(registration) => Either::A(
+                registration
+                    .map(|(device_id, hub_name, substatus, payload)| {
                         info!(
                             "DPS registration assigned device \"{}\" in hub \"{}\"",
                             device_id, hub_name
@@ -630,11 +756,13 @@ where
                             reconfigure,
                             sha256_thumbprint: None,
                             credentials: None,
+                            payload,
+                            restored: false,
                         }
                     })
                     .map_err(|err| Error::from(err.context(ErrorKind::Provision))),
             ),
-            Err(err) => Either::B(future::err(Error::from(err.context(ErrorKind::Provision)))),
+            Err(err) => Either::B(future::err(err)),
         };
         Box::new(d)
     }
@@ -645,13 +773,36 @@ where
     }
 }
 
+// DPS accepts MQTT connections on the same host name as HTTPS requests.
+fn mqtt_host(endpoint: &Url) -> Result<String, Error> {
+    endpoint
+        .host_str()
+        .map(ToString::to_string)
+        .ok_or_else(|| Error::from(ErrorKind::DpsInitialization))
+}
+
+/// Derives the key of a device registering through a symmetric key group
+/// enrollment. DPS computes the same key from the group's key, so the device
+/// can register without having been given its own key.
+pub fn derive_device_key(group_key: &[u8], registration_id: &str) -> Result<Vec<u8>, Error> {
+    let signature = MemoryKey::new(group_key)
+        .sign(SignatureAlgorithm::HMACSHA256, registration_id.as_bytes())
+        .context(ErrorKind::DeriveDeviceKey)?;
+    Ok(signature.as_bytes().to_vec())
+}
+
 pub struct DpsX509Provisioning<C>
 where
     C: ClientImpl,
 {
     client: HttpClient<C, DpsTokenSource<MemoryKey>>,
+    endpoint: Url,
     scope_id: String,
     registration_id: String,
+    payload: Option<Value>,
+    retry_policy: Option<RetryPolicy>,
+    transport: DpsTransport,
+    identity_cert: Option<PemCertificate>,
 }
 
 impl<C> DpsX509Provisioning<C>
@@ -669,16 +820,92 @@ where
             client_impl,
             None as Option<DpsTokenSource<MemoryKey>>,
             api_version,
-            endpoint,
+            endpoint.clone(),
         )
         .context(ErrorKind::DpsInitialization)?;
         let result = DpsX509Provisioning {
             client,
+            endpoint,
             scope_id,
             registration_id,
+            payload: None,
+            retry_policy: None,
+            transport: DpsTransport::default(),
+            identity_cert: None,
         };
         Ok(result)
     }
+
+    /// Sends a custom allocation payload with the registration request.
+    pub fn with_payload(mut self, payload: Option<Value>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Retries the registration requests that fail because DPS is throttling
+    /// or unavailable by `retry_policy`, if any.
+    pub fn with_retry_policy(mut self, retry_policy: Option<RetryPolicy>) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Records how far the local clock is off from the time of DPS, and gives
+    /// the SAS tokens of the registration an expiry by the time of DPS.
+    pub fn with_clock_skew(mut self, clock_skew: ClockSkew) -> Self {
+        self.client = self.client.with_clock_skew(clock_skew);
+        self
+    }
+
+    /// Registers over the given transport instead of HTTPS.
+    pub fn with_transport(mut self, transport: DpsTransport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// The identity certificate to authenticate with when registering over
+    /// MQTT. Over HTTPS, the client given to `new` authenticates instead.
+    pub fn with_identity_certificate(mut self, identity_cert: PemCertificate) -> Self {
+        self.identity_cert = Some(identity_cert);
+        self
+    }
+
+    fn register(
+        &self,
+        key_activator: MemoryKeyStore,
+    ) -> Result<Box<dyn Future<Item = RegistrationInfo, Error = DpsError> + Send>, Error>
+    where
+        C: 'static,
+    {
+        match self.transport {
+            DpsTransport::Https => {
+                let client = DpsClient::new(
+                    self.client.clone(),
+                    self.scope_id.clone(),
+                    self.registration_id.clone(),
+                    DpsAuthKind::X509,
+                    key_activator,
+                )
+                .context(ErrorKind::Provision)?;
+                Ok(client
+                    .with_payload(self.payload.clone())
+                    .with_retry_policy(self.retry_policy.clone())
+                    .register())
+            }
+            DpsTransport::Mqtt => {
+                let identity_cert = self
+                    .identity_cert
+                    .clone()
+                    .ok_or(ErrorKind::DpsMqttIdentityCertificate)?;
+                let client = MqttDpsClient::<MemoryKey>::new(
+                    mqtt_host(&self.endpoint)?,
+                    self.scope_id.clone(),
+                    self.registration_id.clone(),
+                    MqttAuth::X509(identity_cert),
+                );
+                Ok(client.with_payload(self.payload.clone()).register())
+            }
+        }
+    }
 }
 
 impl<C> Provision for DpsX509Provisioning<C>
@@ -691,18 +918,10 @@ where
         &self,
         key_activator: Self::Hsm,
     ) -> Box<dyn Future<Item = ProvisioningResult, Error = Error> + Send> {
-        let c = DpsClient::new(
-            self.client.clone(),
-            self.scope_id.clone(),
-            self.registration_id.clone(),
-            DpsAuthKind::X509,
-            key_activator,
-        );
-
-        let d = match c {
-            Ok(c) => Either::A(
-                c.register()
-                    .map(|(device_id, hub_name, substatus)| {
+        let d = match self.register(key_activator) {
+            Ok(registration) => Either::A(
+                registration
+                    .map(|(device_id, hub_name, substatus, payload)| {
                         info!(
                             "DPS registration assigned device \"{}\" in hub \"{}\"",
                             device_id, hub_name
@@ -719,11 +938,13 @@ where
                             reconfigure,
                             sha256_thumbprint: None,
                             credentials: None,
+                            payload,
+                            restored: false,
                         }
                     })
                     .map_err(|err| Error::from(err.context(ErrorKind::Provision))),
             ),
-            Err(err) => Either::B(future::err(Error::from(err.context(ErrorKind::Provision)))),
+            Err(err) => Either::B(future::err(err)),
         };
         Box::new(d)
     }
@@ -734,46 +955,86 @@ where
     }
 }
 
-pub struct BackupProvisioning<'a, P> {
+/// Client ID under which the provisioning backup is encrypted with the
+/// HSM's master encryption key.
+const BACKUP_CRYPTO_ID: &str = "$iotedge-provisioning-backup";
+const BACKUP_CRYPTO_IV_LEN_BYTES: usize = 16;
+
+/// Caches the result of the underlying provisioner on disk, encrypted with
+/// the HSM, so that the device can start with its last known identity when
+/// the provisioning service is unreachable.
+pub struct BackupProvisioning<'a, P, C> {
     underlying: &'a P,
     path: PathBuf,
+    crypto: C,
 }
 
-impl<'a, P: 'a> BackupProvisioning<'a, P> {
-    pub fn new(provisioner: &'a P, path: PathBuf) -> Self {
+impl<'a, P: 'a, C> BackupProvisioning<'a, P, C>
+where
+    C: Encrypt + Decrypt + MakeRandom,
+{
+    pub fn new(provisioner: &'a P, path: PathBuf, crypto: C) -> Self {
         BackupProvisioning {
             underlying: provisioner,
             path,
+            crypto,
         }
     }
 
-    fn backup(prov_result: &ProvisioningResult, path: PathBuf) -> Result<(), Error> {
+    fn backup(prov_result: &ProvisioningResult, path: PathBuf, crypto: &C) -> Result<(), Error> {
+        let buffer = serde_json::to_vec(&prov_result).context(ErrorKind::CouldNotBackup)?;
+
+        // The file holds the IV followed by the ciphertext.
+        let mut iv = [0; BACKUP_CRYPTO_IV_LEN_BYTES];
+        crypto
+            .get_random_bytes(&mut iv)
+            .context(ErrorKind::CouldNotBackup)?;
+        let ciphertext = crypto
+            .encrypt(BACKUP_CRYPTO_ID.as_bytes(), &buffer, &iv)
+            .context(ErrorKind::CouldNotBackup)?;
+
         // create a file if it doesn't exist, else open it for writing
         let mut file = File::create(path).context(ErrorKind::CouldNotBackup)?;
-        let buffer = serde_json::to_string(&prov_result).context(ErrorKind::CouldNotBackup)?;
-        file.write_all(buffer.as_bytes())
+        file.write_all(&iv).context(ErrorKind::CouldNotBackup)?;
+        file.write_all(ciphertext.as_ref())
             .context(ErrorKind::CouldNotBackup)?;
         Ok(())
     }
 
-    fn restore(path: PathBuf) -> Result<ProvisioningResult, Error> {
+    fn restore(path: PathBuf, crypto: &C) -> Result<ProvisioningResult, Error> {
         let mut file = File::open(path).context(ErrorKind::CouldNotRestore)?;
-        let mut buffer = String::new();
+        let mut buffer = vec![];
         let _ = file
-            .read_to_string(&mut buffer)
+            .read_to_end(&mut buffer)
             .context(ErrorKind::CouldNotRestore)?;
         info!("Restoring device credentials from backup");
-        let mut prov_result: ProvisioningResult =
-            serde_json::from_str(&buffer).context(ErrorKind::CouldNotRestore)?;
+
+        let decrypted = if buffer.len() > BACKUP_CRYPTO_IV_LEN_BYTES {
+            let (iv, ciphertext) = buffer.split_at(BACKUP_CRYPTO_IV_LEN_BYTES);
+            crypto
+                .decrypt(BACKUP_CRYPTO_ID.as_bytes(), ciphertext, iv)
+                .ok()
+                .and_then(|plaintext| serde_json::from_slice(plaintext.as_ref()).ok())
+        } else {
+            None
+        };
+
+        // Backups written before they were encrypted hold the plain JSON.
+        let mut prov_result: ProvisioningResult = match decrypted {
+            Some(prov_result) => prov_result,
+            None => serde_json::from_slice(&buffer).context(ErrorKind::CouldNotRestore)?,
+        };
         prov_result.reconfigure = ReprovisioningStatus::DeviceDataNotUpdated;
+        prov_result.restored = true;
         Ok(prov_result)
     }
 
     fn diff_with_backup_inner(
         path: PathBuf,
         prov_result: &ProvisioningResult,
+        crypto: &C,
     ) -> Result<bool, serde_json::Error> {
-        match Self::restore(path) {
+        match Self::restore(path, crypto) {
             Ok(restored_prov_result) => {
                 let buffer = serde_json::to_string(&restored_prov_result)?;
                 let buffer = Sha256::digest_str(&buffer);
@@ -795,8 +1056,8 @@ impl<'a, P: 'a> BackupProvisioning<'a, P> {
         }
     }
 
-    fn diff_with_backup(path: PathBuf, prov_result: &ProvisioningResult) -> bool {
-        match Self::diff_with_backup_inner(path, prov_result) {
+    fn diff_with_backup(path: PathBuf, prov_result: &ProvisioningResult, crypto: &C) -> bool {
+        match Self::diff_with_backup_inner(path, prov_result, crypto) {
             Ok(result) => result,
             Err(err) => {
                 log_failure(Level::Debug, &err);
@@ -806,9 +1067,10 @@ impl<'a, P: 'a> BackupProvisioning<'a, P> {
     }
 }
 
-impl<'a, P: 'a> Provision for BackupProvisioning<'a, P>
+impl<'a, P: 'a, C> Provision for BackupProvisioning<'a, P, C>
 where
     P: Provision,
+    C: 'static + Encrypt + Decrypt + MakeRandom + Clone + Send,
 {
     type Hsm = P::Hsm;
 
@@ -819,6 +1081,8 @@ where
         let path = self.path.clone();
         let restore_path = self.path.clone();
         let path_on_err = self.path.clone();
+        let crypto = self.crypto.clone();
+        let crypto_on_err = self.crypto.clone();
         Box::new(
             self.underlying
                 .provision(key_activator)
@@ -826,7 +1090,7 @@ where
                     debug!("Provisioning result {:?}", prov_result);
                     let reconfigure = match prov_result.reconfigure {
                         ReprovisioningStatus::DeviceDataUpdated => {
-                            if Self::diff_with_backup(restore_path, &prov_result) {
+                            if Self::diff_with_backup(restore_path, &prov_result, &crypto) {
                                 info!("Provisioning credentials were changed.");
                                 ReprovisioningStatus::InitialAssignment
                             } else {
@@ -838,14 +1102,14 @@ where
                     };
 
                     prov_result.reconfigure = reconfigure;
-                    match Self::backup(&prov_result, path) {
+                    match Self::backup(&prov_result, path, &crypto) {
                         Ok(_) => Either::A(future::ok(prov_result.clone())),
                         Err(err) => Either::B(future::err(err)),
                     }
                 })
                 .or_else(move |err| {
                     log_failure(Level::Warn, &err);
-                    match Self::restore(path_on_err) {
+                    match Self::restore(path_on_err, &crypto_on_err) {
                         Ok(prov_result) => Either::A(future::ok(prov_result)),
                         Err(err) => Either::B(future::err(err)),
                     }
@@ -871,6 +1135,74 @@ mod tests {
 
     use crate::error::ErrorKind;
 
+    #[test]
+    fn derive_device_key_from_group_key() {
+        let group_key = base64::decode("dGhpcyBpcyBhIGdyb3VwIGVucm9sbG1lbnQga2V5").unwrap();
+        let device_key = derive_device_key(&group_key, "device-01").unwrap();
+        assert_eq!(
+            "Harg5Ntb2lBiA/2STKy0AKsPr8MZaNeprAGj32zzWXo=",
+            base64::encode(&device_key)
+        );
+    }
+
+    // Stands in for the HSM by XOR-ing the data with the IV, and rejects
+    // data it did not encrypt.
+    #[derive(Clone)]
+    struct TestCrypto {}
+
+    const TEST_CRYPTO_TAG: &[u8] = b"test-crypto";
+
+    impl MakeRandom for TestCrypto {
+        fn get_random_bytes(&self, buffer: &mut [u8]) -> Result<(), CoreError> {
+            for b in buffer.iter_mut() {
+                *b = 0x5A;
+            }
+            Ok(())
+        }
+    }
+
+    impl Encrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn encrypt(
+            &self,
+            client_id: &[u8],
+            plaintext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            assert_eq!(BACKUP_CRYPTO_ID.as_bytes(), client_id);
+            let mut ciphertext = TEST_CRYPTO_TAG.to_vec();
+            ciphertext.extend(
+                plaintext
+                    .iter()
+                    .zip(initialization_vector.iter().cycle())
+                    .map(|(b, iv)| b ^ iv ^ 0xAA),
+            );
+            Ok(ciphertext)
+        }
+    }
+
+    impl Decrypt for TestCrypto {
+        type Buffer = Vec<u8>;
+
+        fn decrypt(
+            &self,
+            client_id: &[u8],
+            ciphertext: &[u8],
+            initialization_vector: &[u8],
+        ) -> Result<Self::Buffer, CoreError> {
+            assert_eq!(BACKUP_CRYPTO_ID.as_bytes(), client_id);
+            if !ciphertext.starts_with(TEST_CRYPTO_TAG) {
+                return Err(CoreError::from(edgelet_core::ErrorKind::KeyStore));
+            }
+            Ok(ciphertext[TEST_CRYPTO_TAG.len()..]
+                .iter()
+                .zip(initialization_vector.iter().cycle())
+                .map(|(b, iv)| b ^ iv ^ 0xAA)
+                .collect())
+        }
+    }
+
     struct TestProvisioning {}
 
     impl Provision for TestProvisioning {
@@ -886,6 +1218,8 @@ mod tests {
                 reconfigure: ReprovisioningStatus::DeviceDataUpdated,
                 sha256_thumbprint: None,
                 credentials: None,
+                payload: None,
+                restored: false,
             }))
         }
 
@@ -909,6 +1243,8 @@ mod tests {
                 reconfigure: ReprovisioningStatus::DeviceDataUpdated,
                 sha256_thumbprint: None,
                 credentials: None,
+                payload: None,
+                restored: false,
             }))
         }
 
@@ -1028,13 +1364,16 @@ mod tests {
         let tmp_dir = TempDir::new("backup").unwrap();
         let file_path = tmp_dir.path().join("dps_backup.json");
         let file_path_clone = file_path.clone();
-        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path);
+        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path, TestCrypto {});
         let task = prov_wrapper
             .provision(MemoryKeyStore::new())
             .then(|result| {
                 let _ = result.expect("Unexpected");
-                let result =
-                    BackupProvisioning::<ManualProvisioning>::restore(file_path_clone).unwrap();
+                let result = BackupProvisioning::<ManualProvisioning, _>::restore(
+                    file_path_clone,
+                    &TestCrypto {},
+                )
+                .unwrap();
                 assert_eq!(result.device_id(), "TestDevice");
                 assert_eq!(result.hub_name(), "TestHub");
                 Ok::<_, Error>(())
@@ -1051,21 +1390,25 @@ mod tests {
         let tmp_dir = TempDir::new("backup").unwrap();
         let file_path = tmp_dir.path().join("dps_backup.json");
         let file_path_clone = file_path.clone();
-        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path);
+        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path, TestCrypto {});
         let task = prov_wrapper.provision(MemoryKeyStore::new());
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
             .block_on(task)
             .unwrap();
 
-        let prov_wrapper_err =
-            BackupProvisioning::new(&TestProvisioningWithError {}, file_path_clone);
+        let prov_wrapper_err = BackupProvisioning::new(
+            &TestProvisioningWithError {},
+            file_path_clone,
+            TestCrypto {},
+        );
         let task1 = prov_wrapper_err
             .provision(MemoryKeyStore::new())
             .then(|result| {
                 let prov_result = result.expect("Unexpected");
                 assert_eq!(prov_result.device_id(), "TestDevice");
                 assert_eq!(prov_result.hub_name(), "TestHub");
+                assert!(prov_result.restored());
                 Ok::<_, Error>(())
             });
         tokio::runtime::current_thread::Runtime::new()
@@ -1080,14 +1423,15 @@ mod tests {
         let tmp_dir = TempDir::new("backup").unwrap();
         let file_path = tmp_dir.path().join("dps_backup.json");
         let file_path_clone = file_path.clone();
-        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path);
+        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path, TestCrypto {});
         let task = prov_wrapper.provision(MemoryKeyStore::new());
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
             .block_on(task)
             .unwrap();
 
-        let prov_wrapper_err = BackupProvisioning::new(&TestProvisioning {}, file_path_clone);
+        let prov_wrapper_err =
+            BackupProvisioning::new(&TestProvisioning {}, file_path_clone, TestCrypto {});
         let task1 = prov_wrapper_err
             .provision(MemoryKeyStore::new())
             .then(|result| {
@@ -1112,15 +1456,18 @@ mod tests {
         let tmp_dir = TempDir::new("backup").unwrap();
         let file_path = tmp_dir.path().join("dps_backup.json");
         let file_path_clone = file_path.clone();
-        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path);
+        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path, TestCrypto {});
         let task = prov_wrapper.provision(MemoryKeyStore::new());
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
             .block_on(task)
             .unwrap();
 
-        let prov_wrapper_err =
-            BackupProvisioning::new(&TestProvisioningWithError {}, file_path_clone);
+        let prov_wrapper_err = BackupProvisioning::new(
+            &TestProvisioningWithError {},
+            file_path_clone,
+            TestCrypto {},
+        );
         let task1 = prov_wrapper_err
             .provision(MemoryKeyStore::new())
             .then(|result| {
@@ -1145,14 +1492,15 @@ mod tests {
         let tmp_dir = TempDir::new("backup").unwrap();
         let file_path = tmp_dir.path().join("dps_backup.json");
         let file_path_clone = file_path.clone();
-        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path);
+        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path, TestCrypto {});
         let task = prov_wrapper.provision(MemoryKeyStore::new());
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
             .block_on(task)
             .unwrap();
 
-        let prov_wrapper_err = BackupProvisioning::new(&TestReprovisioning {}, file_path_clone);
+        let prov_wrapper_err =
+            BackupProvisioning::new(&TestReprovisioning {}, file_path_clone, TestCrypto {});
         let task1 = prov_wrapper_err
             .provision(MemoryKeyStore::new())
             .then(|result| {
@@ -1177,15 +1525,18 @@ mod tests {
         let tmp_dir = TempDir::new("backup").unwrap();
         let file_path = tmp_dir.path().join("dps_backup.json");
         let file_path_wrong = tmp_dir.path().join("dps_backup_wrong.json");
-        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path);
+        let prov_wrapper = BackupProvisioning::new(&test_provisioner, file_path, TestCrypto {});
         let task = prov_wrapper.provision(MemoryKeyStore::new());
         tokio::runtime::current_thread::Runtime::new()
             .unwrap()
             .block_on(task)
             .unwrap();
 
-        let prov_wrapper_err =
-            BackupProvisioning::new(&TestProvisioningWithError {}, file_path_wrong);
+        let prov_wrapper_err = BackupProvisioning::new(
+            &TestProvisioningWithError {},
+            file_path_wrong,
+            TestCrypto {},
+        );
         let task1 = prov_wrapper_err
             .provision(MemoryKeyStore::new())
             .then(|result| {
@@ -1201,6 +1552,73 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn backup_is_encrypted() {
+        let test_provisioner = TestProvisioning {};
+        let tmp_dir = TempDir::new("backup").unwrap();
+        let file_path = tmp_dir.path().join("dps_backup.json");
+        let prov_wrapper =
+            BackupProvisioning::new(&test_provisioner, file_path.clone(), TestCrypto {});
+        let task = prov_wrapper.provision(MemoryKeyStore::new());
+        tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+
+        let contents = std::fs::read(&file_path).unwrap();
+        assert!(serde_json::from_slice::<ProvisioningResult>(&contents).is_err());
+        assert!(!String::from_utf8_lossy(&contents).contains("TestHub"));
+
+        let result =
+            BackupProvisioning::<ManualProvisioning, _>::restore(file_path, &TestCrypto {})
+                .unwrap();
+        assert_eq!(result.device_id(), "TestDevice");
+        assert_eq!(result.hub_name(), "TestHub");
+    }
+
+    #[test]
+    fn restore_plaintext_backup() {
+        let tmp_dir = TempDir::new("backup").unwrap();
+        let file_path = tmp_dir.path().join("dps_backup.json");
+        std::fs::write(
+            &file_path,
+            r#"{"device_id":"TestDevice","hub_name":"TestHub","sha256_thumbprint":null,"credentials":null}"#,
+        )
+        .unwrap();
+
+        let prov_wrapper_err =
+            BackupProvisioning::new(&TestProvisioningWithError {}, file_path, TestCrypto {});
+        let task = prov_wrapper_err.provision(MemoryKeyStore::new());
+        let prov_result = tokio::runtime::current_thread::Runtime::new()
+            .unwrap()
+            .block_on(task)
+            .unwrap();
+        assert_eq!(prov_result.device_id(), "TestDevice");
+        assert_eq!(prov_result.hub_name(), "TestHub");
+        assert_eq!(
+            prov_result.reconfigure(),
+            ReprovisioningStatus::DeviceDataNotUpdated
+        );
+    }
+
+    #[test]
+    fn prov_result_payload_round_trips() {
+        let prov_result = ProvisioningResult::new(
+            "something",
+            "something",
+            None,
+            ReprovisioningStatus::InitialAssignment,
+            None,
+        )
+        .with_payload(Some(serde_json::json!({ "tier": "gold" })));
+        let json = serde_json::to_string(&prov_result).unwrap();
+        let restored: ProvisioningResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            Some(&serde_json::json!({ "tier": "gold" })),
+            restored.payload()
+        );
+    }
+
     #[test]
     fn prov_result_serialize_skips_reconfigure_flag() {
         let json = serde_json::to_string(&ProvisioningResult {
@@ -1209,6 +1627,8 @@ mod tests {
             reconfigure: ReprovisioningStatus::DeviceDataNotUpdated,
             sha256_thumbprint: None,
             credentials: None,
+            payload: None,
+            restored: false,
         })
         .unwrap();
         assert_eq!(
@@ -0,0 +1,177 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Implements the daemon interface for service notifications, which tells the
+//! service manager when the daemon is ready and that it is still alive.
+//! Based off of [`sd_notify`](https://www.freedesktop.org/software/systemd/man/sd_notify.html)
+
+use std::env;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use failure::ResultExt;
+use log::debug;
+use nix::sys::socket::{self, AddressFamily, MsgFlags, SockAddr, SockFlag, SockType, UnixAddr};
+use nix::unistd::{self, Pid};
+
+use crate::error::{Error, ErrorKind};
+
+const ENV_NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+const ENV_WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+const ENV_WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+const STATE_READY: &str = "READY=1";
+const STATE_WATCHDOG: &str = "WATCHDOG=1";
+
+/// Tells the service manager that the daemon finished starting up.
+///
+/// Returns `false` when the daemon is not run by a service manager that
+/// expects notifications.
+pub fn notify_ready() -> Result<bool, Error> {
+    notify(STATE_READY)
+}
+
+/// Tells the service manager that the daemon is still alive, which resets its
+/// watchdog timer.
+///
+/// Returns `false` when the daemon is not run by a service manager that
+/// expects notifications.
+pub fn notify_watchdog() -> Result<bool, Error> {
+    notify(STATE_WATCHDOG)
+}
+
+/// Sends a list of newline-separated `VARIABLE=value` assignments to the
+/// service manager.
+///
+/// Returns `false` when the daemon is not run by a service manager that
+/// expects notifications.
+pub fn notify(state: &str) -> Result<bool, Error> {
+    let path = match env::var(ENV_NOTIFY_SOCKET) {
+        Ok(path) => path,
+        Err(_) => return Ok(false),
+    };
+    debug!("{} {}, sending {:?}", ENV_NOTIFY_SOCKET, path, state);
+
+    // A leading '@' refers to a socket in the abstract namespace, which the
+    // standard library can't address.
+    if let Some(name) = path.strip_prefix('@') {
+        let addr = UnixAddr::new_abstract(name.as_bytes())
+            .with_context(|_| ErrorKind::InvalidVar(ENV_NOTIFY_SOCKET.to_string()))?;
+        let fd = socket::socket(
+            AddressFamily::Unix,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .context(ErrorKind::Syscall("socket"))?;
+        let sent = socket::sendto(
+            fd,
+            state.as_bytes(),
+            &SockAddr::Unix(addr),
+            MsgFlags::empty(),
+        )
+        .context(ErrorKind::Syscall("sendto"));
+        let _ = unistd::close(fd);
+        sent?;
+    } else {
+        UnixDatagram::unbound()
+            .context(ErrorKind::Syscall("socket"))?
+            .send_to(state.as_bytes(), &path)
+            .context(ErrorKind::Syscall("sendto"))?;
+    }
+
+    Ok(true)
+}
+
+/// Returns the interval within which the service manager expects the daemon
+/// to call `notify_watchdog`, or `None` if the watchdog is not enabled for
+/// this process.
+pub fn watchdog_interval() -> Result<Option<Duration>, Error> {
+    let usec_str = match env::var(ENV_WATCHDOG_USEC) {
+        Ok(usec) => usec,
+        Err(_) => return Ok(None),
+    };
+    debug!("{} {}", ENV_WATCHDOG_USEC, usec_str);
+
+    if let Ok(pid_str) = env::var(ENV_WATCHDOG_PID) {
+        debug!("{} {}", ENV_WATCHDOG_PID, pid_str);
+        let pid = Pid::from_raw(
+            pid_str
+                .parse::<i32>()
+                .context(ErrorKind::ParsePid(ENV_WATCHDOG_PID.to_string()))?,
+        );
+        if pid != Pid::this() {
+            return Ok(None);
+        }
+    }
+
+    let usec = usec_str
+        .parse::<u64>()
+        .with_context(|_| ErrorKind::InvalidVar(ENV_WATCHDOG_USEC.to_string()))?;
+    if usec == 0 {
+        return Err(ErrorKind::InvalidVar(ENV_WATCHDOG_USEC.to_string()).into());
+    }
+
+    Ok(Some(Duration::from_micros(usec)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use lazy_static::lazy_static;
+
+    use super::*;
+
+    lazy_static! {
+        static ref LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    fn lock_env<'a>() -> MutexGuard<'a, ()> {
+        LOCK.lock().unwrap()
+    }
+
+    #[test]
+    fn test_notify_without_socket() {
+        let _l = lock_env();
+        env::remove_var(ENV_NOTIFY_SOCKET);
+        assert!(!notify_ready().unwrap());
+    }
+
+    #[test]
+    fn test_notify_sends_state() {
+        let _l = lock_env();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        env::set_var(ENV_NOTIFY_SOCKET, &path);
+
+        assert!(notify_watchdog().unwrap());
+        env::remove_var(ENV_NOTIFY_SOCKET);
+
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(STATE_WATCHDOG.as_bytes(), &buf[..len]);
+    }
+
+    #[test]
+    fn test_watchdog_interval() {
+        let _l = lock_env();
+        env::remove_var(ENV_WATCHDOG_USEC);
+        env::remove_var(ENV_WATCHDOG_PID);
+        assert_eq!(None, watchdog_interval().unwrap());
+
+        env::set_var(ENV_WATCHDOG_USEC, "30000000");
+        assert_eq!(Some(Duration::from_secs(30)), watchdog_interval().unwrap());
+
+        env::set_var(ENV_WATCHDOG_PID, Pid::this().to_string());
+        assert_eq!(Some(Duration::from_secs(30)), watchdog_interval().unwrap());
+
+        env::set_var(ENV_WATCHDOG_PID, "1");
+        assert_eq!(None, watchdog_interval().unwrap());
+
+        env::remove_var(ENV_WATCHDOG_PID);
+        env::set_var(ENV_WATCHDOG_USEC, "soon");
+        assert!(watchdog_interval().is_err());
+        env::remove_var(ENV_WATCHDOG_USEC);
+    }
+}
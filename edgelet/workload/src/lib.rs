@@ -11,3 +11,6 @@
 
 pub mod apis;
 pub mod models;
+
+/// The Swagger document of the latest version of the API, in YAML.
+pub const OPENAPI_SPEC: &str = include_str!("../../api/workloadVersion_2019_01_30.yaml");
@@ -0,0 +1,79 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeriveKeyRequest {
+    /// Name of the key to derive from.
+    #[serde(rename = "keyId")]
+    key_id: String,
+    /// What the derived key is for. Different labels give unrelated keys.
+    #[serde(rename = "label")]
+    label: String,
+    /// Length of the derived key in bytes. Defaults to 32.
+    #[serde(rename = "length", skip_serializing_if = "Option::is_none")]
+    length: Option<u32>,
+}
+
+impl DeriveKeyRequest {
+    pub fn new(key_id: String, label: String) -> Self {
+        DeriveKeyRequest {
+            key_id,
+            label,
+            length: None,
+        }
+    }
+
+    pub fn set_key_id(&mut self, key_id: String) {
+        self.key_id = key_id;
+    }
+
+    pub fn with_key_id(mut self, key_id: String) -> Self {
+        self.key_id = key_id;
+        self
+    }
+
+    pub fn key_id(&self) -> &String {
+        &self.key_id
+    }
+
+    pub fn set_label(&mut self, label: String) {
+        self.label = label;
+    }
+
+    pub fn with_label(mut self, label: String) -> Self {
+        self.label = label;
+        self
+    }
+
+    pub fn label(&self) -> &String {
+        &self.label
+    }
+
+    pub fn set_length(&mut self, length: u32) {
+        self.length = Some(length);
+    }
+
+    pub fn with_length(mut self, length: u32) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    pub fn length(&self) -> Option<u32> {
+        self.length
+    }
+
+    pub fn reset_length(&mut self) {
+        self.length = None;
+    }
+}
@@ -0,0 +1,39 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeriveKeyResponse {
+    /// The derived key.
+    #[serde(rename = "key")]
+    key: String,
+}
+
+impl DeriveKeyResponse {
+    pub fn new(key: String) -> Self {
+        DeriveKeyResponse { key }
+    }
+
+    pub fn set_key(&mut self, key: String) {
+        self.key = key;
+    }
+
+    pub fn with_key(mut self, key: String) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn key(&self) -> &String {
+        &self.key
+    }
+}
@@ -0,0 +1,55 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ManagementTokenResponse {
+    /// The bearer token to call the management API with.
+    #[serde(rename = "token")]
+    token: String,
+    /// Token expiration date-time (ISO 8601)
+    #[serde(rename = "expiration")]
+    expiration: String,
+}
+
+impl ManagementTokenResponse {
+    pub fn new(token: String, expiration: String) -> Self {
+        ManagementTokenResponse { token, expiration }
+    }
+
+    pub fn set_token(&mut self, token: String) {
+        self.token = token;
+    }
+
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn token(&self) -> &String {
+        &self.token
+    }
+
+    pub fn set_expiration(&mut self, expiration: String) {
+        self.expiration = expiration;
+    }
+
+    pub fn with_expiration(mut self, expiration: String) -> Self {
+        self.expiration = expiration;
+        self
+    }
+
+    pub fn expiration(&self) -> &String {
+        &self.expiration
+    }
+}
@@ -4,6 +4,10 @@ mod decrypt_request;
 pub use self::decrypt_request::DecryptRequest;
 mod decrypt_response;
 pub use self::decrypt_response::DecryptResponse;
+mod derive_key_request;
+pub use self::derive_key_request::DeriveKeyRequest;
+mod derive_key_response;
+pub use self::derive_key_response::DeriveKeyResponse;
 mod encrypt_request;
 pub use self::encrypt_request::EncryptRequest;
 mod encrypt_response;
@@ -12,8 +16,18 @@ mod error_response;
 pub use self::error_response::ErrorResponse;
 mod identity_certificate_request;
 pub use self::identity_certificate_request::IdentityCertificateRequest;
+mod management_token_response;
+pub use self::management_token_response::ManagementTokenResponse;
 mod private_key;
 pub use self::private_key::PrivateKey;
+mod quote_request;
+pub use self::quote_request::QuoteRequest;
+mod quote_response;
+pub use self::quote_response::QuoteResponse;
+mod revocation_list_response;
+pub use self::revocation_list_response::RevocationListResponse;
+mod revoked_certificate;
+pub use self::revoked_certificate::RevokedCertificate;
 mod server_certificate_request;
 pub use self::server_certificate_request::ServerCertificateRequest;
 mod sign_request;
@@ -0,0 +1,39 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuoteRequest {
+    /// The nonce of the verifier that the quote is made over, 1 to 64 bytes.
+    #[serde(rename = "nonce")]
+    nonce: String,
+}
+
+impl QuoteRequest {
+    pub fn new(nonce: String) -> Self {
+        QuoteRequest { nonce }
+    }
+
+    pub fn set_nonce(&mut self, nonce: String) {
+        self.nonce = nonce;
+    }
+
+    pub fn with_nonce(mut self, nonce: String) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn nonce(&self) -> &String {
+        &self.nonce
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuoteResponse {
+    /// The TPMS_ATTEST structure that the TPM signed.
+    #[serde(rename = "quoted")]
+    quoted: String,
+    /// The TPMT_SIGNATURE over the quoted structure.
+    #[serde(rename = "signature")]
+    signature: String,
+    /// The TPMT_PUBLIC area of the attestation key that signed the quote.
+    #[serde(rename = "attestationKey")]
+    attestation_key: String,
+    /// The TPMT_PUBLIC area of the endorsement key of the TPM.
+    #[serde(rename = "endorsementKey")]
+    endorsement_key: String,
+    /// The DER certificate of the endorsement key, if the TPM has one.
+    #[serde(
+        rename = "endorsementKeyCertificate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    endorsement_key_certificate: Option<String>,
+}
+
+impl QuoteResponse {
+    pub fn new(
+        quoted: String,
+        signature: String,
+        attestation_key: String,
+        endorsement_key: String,
+    ) -> Self {
+        QuoteResponse {
+            quoted,
+            signature,
+            attestation_key,
+            endorsement_key,
+            endorsement_key_certificate: None,
+        }
+    }
+
+    pub fn set_quoted(&mut self, quoted: String) {
+        self.quoted = quoted;
+    }
+
+    pub fn with_quoted(mut self, quoted: String) -> Self {
+        self.quoted = quoted;
+        self
+    }
+
+    pub fn quoted(&self) -> &String {
+        &self.quoted
+    }
+
+    pub fn set_signature(&mut self, signature: String) {
+        self.signature = signature;
+    }
+
+    pub fn with_signature(mut self, signature: String) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    pub fn signature(&self) -> &String {
+        &self.signature
+    }
+
+    pub fn set_attestation_key(&mut self, attestation_key: String) {
+        self.attestation_key = attestation_key;
+    }
+
+    pub fn with_attestation_key(mut self, attestation_key: String) -> Self {
+        self.attestation_key = attestation_key;
+        self
+    }
+
+    pub fn attestation_key(&self) -> &String {
+        &self.attestation_key
+    }
+
+    pub fn set_endorsement_key(&mut self, endorsement_key: String) {
+        self.endorsement_key = endorsement_key;
+    }
+
+    pub fn with_endorsement_key(mut self, endorsement_key: String) -> Self {
+        self.endorsement_key = endorsement_key;
+        self
+    }
+
+    pub fn endorsement_key(&self) -> &String {
+        &self.endorsement_key
+    }
+
+    pub fn set_endorsement_key_certificate(&mut self, endorsement_key_certificate: String) {
+        self.endorsement_key_certificate = Some(endorsement_key_certificate);
+    }
+
+    pub fn with_endorsement_key_certificate(mut self, endorsement_key_certificate: String) -> Self {
+        self.endorsement_key_certificate = Some(endorsement_key_certificate);
+        self
+    }
+
+    pub fn endorsement_key_certificate(&self) -> Option<&String> {
+        self.endorsement_key_certificate.as_ref()
+    }
+
+    pub fn reset_endorsement_key_certificate(&mut self) {
+        self.endorsement_key_certificate = None;
+    }
+}
@@ -0,0 +1,39 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevocationListResponse {
+    /// Certificates issued by the workload CA that were revoked and have not expired yet.
+    #[serde(rename = "revoked")]
+    revoked: Vec<crate::models::RevokedCertificate>,
+}
+
+impl RevocationListResponse {
+    pub fn new(revoked: Vec<crate::models::RevokedCertificate>) -> Self {
+        RevocationListResponse { revoked }
+    }
+
+    pub fn set_revoked(&mut self, revoked: Vec<crate::models::RevokedCertificate>) {
+        self.revoked = revoked;
+    }
+
+    pub fn with_revoked(mut self, revoked: Vec<crate::models::RevokedCertificate>) -> Self {
+        self.revoked = revoked;
+        self
+    }
+
+    pub fn revoked(&self) -> &[crate::models::RevokedCertificate] {
+        &self.revoked
+    }
+}
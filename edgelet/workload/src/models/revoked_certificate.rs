@@ -0,0 +1,97 @@
+/*
+ * IoT Edge Module Workload API
+ *
+ * No description provided (generated by Swagger Codegen https://github.com/swagger-api/swagger-codegen)
+ *
+ * OpenAPI spec version: 2019-01-30
+ *
+ * Generated by: https://github.com/swagger-api/swagger-codegen.git
+ */
+
+use serde_derive::{Deserialize, Serialize};
+#[allow(unused_imports)]
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RevokedCertificate {
+    /// Name of the module the certificate was issued to.
+    #[serde(rename = "moduleId")]
+    module_id: String,
+    /// Hex encoded SHA-256 digest of the DER encoded certificate.
+    #[serde(rename = "thumbprint")]
+    thumbprint: String,
+    /// Certificate expiration date and time (ISO 8601)
+    #[serde(rename = "expiration")]
+    expiration: String,
+    /// Date and time (ISO 8601) when the certificate was revoked
+    #[serde(rename = "revocationDate")]
+    revocation_date: String,
+}
+
+impl RevokedCertificate {
+    pub fn new(
+        module_id: String,
+        thumbprint: String,
+        expiration: String,
+        revocation_date: String,
+    ) -> Self {
+        RevokedCertificate {
+            module_id,
+            thumbprint,
+            expiration,
+            revocation_date,
+        }
+    }
+
+    pub fn set_module_id(&mut self, module_id: String) {
+        self.module_id = module_id;
+    }
+
+    pub fn with_module_id(mut self, module_id: String) -> Self {
+        self.module_id = module_id;
+        self
+    }
+
+    pub fn module_id(&self) -> &String {
+        &self.module_id
+    }
+
+    pub fn set_thumbprint(&mut self, thumbprint: String) {
+        self.thumbprint = thumbprint;
+    }
+
+    pub fn with_thumbprint(mut self, thumbprint: String) -> Self {
+        self.thumbprint = thumbprint;
+        self
+    }
+
+    pub fn thumbprint(&self) -> &String {
+        &self.thumbprint
+    }
+
+    pub fn set_expiration(&mut self, expiration: String) {
+        self.expiration = expiration;
+    }
+
+    pub fn with_expiration(mut self, expiration: String) -> Self {
+        self.expiration = expiration;
+        self
+    }
+
+    pub fn expiration(&self) -> &String {
+        &self.expiration
+    }
+
+    pub fn set_revocation_date(&mut self, revocation_date: String) {
+        self.revocation_date = revocation_date;
+    }
+
+    pub fn with_revocation_date(mut self, revocation_date: String) -> Self {
+        self.revocation_date = revocation_date;
+        self
+    }
+
+    pub fn revocation_date(&self) -> &String {
+        &self.revocation_date
+    }
+}
@@ -48,6 +48,7 @@ pub enum ErrorKind {
     InvalidConnectState,
     InvalidUrlScheme,
     Io(IoError),
+    Kube(String),
     MissingPath,
     ModuleRuntime(String),
     ParseInt(ParseIntError),
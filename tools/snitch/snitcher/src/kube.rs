@@ -0,0 +1,111 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use futures::future::{self, Either};
+use futures::{stream, Future, Stream};
+use k8s_openapi::api::core::v1 as api_core;
+use kube_client::{get_config, Client as KubeClient};
+use log::{debug, info};
+
+use crate::error::{Error, ErrorKind};
+
+// the labels and annotations edgelet puts on the pods of the modules it runs
+// on kubernetes
+const EDGE_MODULE_LABEL: &str = "net.azure-devices.edge.module";
+const EDGE_ORIGINAL_MODULEID: &str = "net.azure-devices.edge.original-moduleid";
+
+struct PodModule {
+    name: String,
+    pod: String,
+    container: String,
+    status: String,
+}
+
+pub fn get_module_logs(
+    namespace: &str,
+) -> impl Future<Item = (Vec<(String, String)>, Vec<String>), Error = Error> + Send {
+    info!(
+        "Fetching module logs from kubernetes namespace {}",
+        namespace
+    );
+
+    let namespace = namespace.to_string();
+    get_config()
+        .map(KubeClient::new)
+        .map_err(|err| Error::new(ErrorKind::Kube(err.to_string())))
+        .map(|mut client| {
+            debug!("Listing module pods");
+            let fut = client
+                .list_pods(&namespace, Some(EDGE_MODULE_LABEL))
+                .map_err(|err| Error::new(ErrorKind::Kube(err.to_string())))
+                .and_then(move |pods| {
+                    let modules: Vec<PodModule> =
+                        pods.items.iter().filter_map(pod_module).collect();
+                    let statuses = modules.iter().map(|module| module.status.clone()).collect();
+
+                    stream::iter_ok(modules)
+                        .and_then(move |module| {
+                            debug!("Got logs for pod {}", module.pod);
+                            client
+                                .get_pod_logs(
+                                    &namespace,
+                                    &module.pod,
+                                    Some(module.container.as_str()),
+                                )
+                                .map_err(|err| Error::new(ErrorKind::Kube(err.to_string())))
+                                .map(move |logs| {
+                                    let logs = if logs.is_empty() {
+                                        "<no logs>".to_string()
+                                    } else {
+                                        logs
+                                    };
+                                    (module.name, logs)
+                                })
+                        })
+                        .collect()
+                        .map(move |logs| (logs, statuses))
+                });
+
+            Either::A(fut)
+        })
+        .unwrap_or_else(|err| Either::B(future::err(err)))
+}
+
+fn pod_module(pod: &api_core::Pod) -> Option<PodModule> {
+    let metadata = pod.metadata.as_ref()?;
+    let pod_name = metadata.name.clone()?;
+    let container = metadata.labels.as_ref()?.get(EDGE_MODULE_LABEL)?.clone();
+
+    // the module's name is kept as is in an annotation, since labels can't
+    // hold it; strip the "$" of the system modules so that logs are named like
+    // the ones of a docker run
+    let name = metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(EDGE_ORIGINAL_MODULEID))
+        .map_or_else(
+            || container.clone(),
+            |name| name.trim_start_matches('$').to_string(),
+        );
+
+    let pod_status = pod.status.as_ref();
+    let phase = pod_status
+        .and_then(|status| status.phase.as_ref())
+        .map_or("Unknown", String::as_str);
+    let container_status = pod_status
+        .and_then(|status| status.container_statuses.as_ref())
+        .and_then(|statuses| statuses.iter().find(|status| status.name == container));
+    let status = match container_status {
+        Some(container_status) => format!(
+            "Module {} (pod {}) is {}, ready: {}, restarts: {}",
+            name, pod_name, phase, container_status.ready, container_status.restart_count
+        ),
+        None => format!("Module {} (pod {}) is {}", name, pod_name, phase),
+    };
+
+    Some(PodModule {
+        name,
+        pod: pod_name,
+        container,
+        status,
+    })
+}
@@ -5,22 +5,16 @@
 pub mod client;
 pub mod connect;
 pub mod error;
+pub mod kube;
 pub mod report;
 pub mod settings;
+pub mod upload;
 
 use std::io;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use azure_sdk_for_rust::prelude::{
-    BlobNameSupport, BodySupport, ContainerNameSupport, ContentTypeSupport, PrefixSupport,
-    PublicAccessSupport,
-};
-use azure_sdk_for_rust::storage::client::{
-    Blob, Client as AzureStorageClient, Container as AzureStorageContainer,
-};
-use azure_sdk_for_rust::storage::container::PublicAccess;
-use bytes::{BufMut, Bytes};
+use bytes::BufMut;
 use chrono::Utc;
 use connect::HyperClientService;
 use edgelet_core::{Chunked, LogChunk, LogDecode, LogOptions, Module, ModuleRuntime, ModuleStatus};
@@ -33,15 +27,23 @@ use humantime::format_duration;
 use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use hyper::{Body, Client as HyperClient, Method, Request};
 use hyper_tls::HttpsConnector;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use report::{MessageAnalysis, Report};
 use serde_json::Value as JsonValue;
 use settings::Settings;
 use tokio::timer::{Delay, Interval};
+use upload::PendingUpload;
 
 const LOGS_FILE_NAME: &str = "logs.tar.gz";
 
 pub fn schedule_reports(settings: &Settings) -> impl Future<Item = (), Error = Error> + Send {
+    // finish the uploads that a restart cut short, so that their reports
+    // aren't lost
+    let resume_uploads = upload::resume_pending(settings).or_else(|err| {
+        warn!("Could not resume unfinished uploads: {:?}", err);
+        Ok(())
+    });
+
     // we schedule one report at the end of the test run
     let run_at = Instant::now() + *settings.test_duration();
     info!(
@@ -73,7 +75,9 @@ pub fn schedule_reports(settings: &Settings) -> impl Future<Item = (), Error = E
         Either::B(future::ok::<(), Error>(()))
     };
 
-    last_report.join(periodic_report).map(|_| ())
+    resume_uploads
+        .join3(last_report, periodic_report)
+        .map(|_| ())
 }
 
 pub fn do_report(settings: Settings) -> impl Future<Item = (), Error = Error> + Send {
@@ -82,13 +86,19 @@ pub fn do_report(settings: Settings) -> impl Future<Item = (), Error = Error> +
     let report = Arc::new(Mutex::new(Report::new(format!("{}", settings.build_id()))));
     let timestamp = Utc::now().timestamp();
 
-    // collect docker logs from all running containers
+    // collect logs from all running modules, from docker or from kubernetes
+    // along with the statuses of their pods
     let add_log_files = {
         let report = report.clone();
         let report_copy = report.clone();
         let settings_copy = settings.clone();
-        get_module_logs(&settings)
-            .and_then(move |module_logs| {
+        let module_logs = if let Some(namespace) = settings.kube_namespace() {
+            Either::A(kube::get_module_logs(namespace))
+        } else {
+            Either::B(get_module_logs(&settings).map(|module_logs| (module_logs, vec![])))
+        };
+        module_logs
+            .and_then(move |(module_logs, statuses)| {
                 info!("Fetched module logs.");
 
                 // add each log as a file into the report
@@ -99,6 +109,10 @@ pub fn do_report(settings: Settings) -> impl Future<Item = (), Error = Error> +
                         .add_file(&format!("./{}.log", container_name), logs.as_bytes());
                 }
 
+                for status in statuses {
+                    report.lock().unwrap().add_notes(status);
+                }
+
                 // write all the files in the report into blob storage
                 info!("Compressing module logs");
                 let buffer = Vec::new();
@@ -180,58 +194,16 @@ pub fn upload_file(
         name, report_id
     );
 
-    let report_id = report_id.to_owned();
-    let name = name.to_owned();
-    let data = Bytes::from(data);
-    AzureStorageClient::new(
-        settings.blob_storage_account(),
-        settings.blob_storage_master_key(),
+    // the file is saved before it is uploaded, so that the upload can resume
+    // if snitcher restarts before it is done
+    PendingUpload::create(
+        settings.upload_dir(),
+        report_id,
+        name,
+        "application/gzip",
+        data,
     )
-    .map(|client| {
-        let fut = client
-            .list_containers()
-            .with_prefix(&report_id)
-            .finalize()
-            .and_then(move |containers| {
-                if containers
-                    .incomplete_vector
-                    .vector
-                    .iter()
-                    .find(|c| &c.name == &report_id)
-                    .is_none()
-                {
-                    debug!(
-                        "Blob container {} not found. Creating container.",
-                        report_id
-                    );
-                    Either::A(
-                        client
-                            .create_container()
-                            .with_container_name(&report_id)
-                            .with_public_access(PublicAccess::Container)
-                            .finalize()
-                            .map(|_| (client, report_id)),
-                    )
-                } else {
-                    debug!("Blob container {} already exists.", report_id);
-                    Either::B(future::ok((client, report_id)))
-                }
-            })
-            .and_then(move |(client, report_id)| {
-                client
-                    .put_block_blob()
-                    .with_container_name(&report_id)
-                    .with_blob_name(&name)
-                    .with_body(data.as_ref())
-                    .with_content_type("application/gzip")
-                    .finalize()
-                    .map(|_| ())
-            })
-            .map_err(Error::from);
-
-        Either::A(fut)
-    })
-    .map_err(Error::from)
+    .map(|pending| Either::A(upload::upload_blocks(settings, pending)))
     .unwrap_or_else(|err| Either::B(future::err(err)))
 }
 
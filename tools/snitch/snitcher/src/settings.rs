@@ -3,6 +3,7 @@
 use std::collections::HashMap;
 use std::default::Default;
 use std::env;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use serde::Deserialize;
@@ -21,6 +22,8 @@ const ANALYZER_URL_KEY: &str = "ANALYZER_URL";
 const BLOB_STORAGE_ACCOUNT_KEY: &str = "BLOB_STORAGE_ACCOUNT";
 const BLOB_STORAGE_MASTER_KEY_KEY: &str = "BLOB_STORAGE_MASTER_KEY";
 const MANAGEMENT_URI_KEY: &str = "MANAGEMENT_URI";
+const KUBE_NAMESPACE_KEY: &str = "KUBE_NAMESPACE";
+const UPLOAD_DIR_KEY: &str = "UPLOAD_DIR";
 
 static DEFAULT_SETTINGS: &str = include_str!("settings.yaml");
 
@@ -86,6 +89,8 @@ pub struct Settings {
     reporting_interval: Option<Duration>,
     #[serde(with = "url_serde")]
     management_uri: Url,
+    kube_namespace: Option<String>,
+    upload_dir: PathBuf,
 }
 
 impl Default for Settings {
@@ -115,6 +120,14 @@ impl Settings {
             self.management_uri = Url::parse(&management_uri)?;
         }
 
+        // the modules run on kubernetes instead of docker when a namespace is
+        // given, and their logs are fetched through the kubernetes API
+        self.kube_namespace = get_env(KUBE_NAMESPACE_KEY).ok();
+
+        if let Ok(upload_dir) = get_env(UPLOAD_DIR_KEY) {
+            self.upload_dir = PathBuf::from(upload_dir);
+        }
+
         self.reporting_interval = get_env(REPORTING_INTERVAL_IN_SECS_KEY)
             .ok()
             .and_then(|interval| interval.parse().ok())
@@ -154,4 +167,12 @@ impl Settings {
     pub fn management_uri(&self) -> &Url {
         &self.management_uri
     }
+
+    pub fn kube_namespace(&self) -> Option<&str> {
+        self.kube_namespace.as_ref().map(String::as_str)
+    }
+
+    pub fn upload_dir(&self) -> &Path {
+        &self.upload_dir
+    }
 }
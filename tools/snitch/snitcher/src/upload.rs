@@ -0,0 +1,387 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Uploads of report artifacts to blob storage, one block at a time. The
+//! artifact and the blocks that were uploaded so far are kept on disk, so that
+//! an upload that is cut short, e.g. by the device rebooting, resumes where it
+//! stopped the next time snitcher starts instead of being lost.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use azure_sdk_for_rust::prelude::{
+    BlobNameSupport, BlockIdSupport, BlockListSupport, BodySupport, ContainerNameSupport,
+    ContentTypeSupport, PrefixSupport, PublicAccessSupport,
+};
+use azure_sdk_for_rust::storage::blob::{BlobBlockType, BlockList};
+use azure_sdk_for_rust::storage::client::{
+    Blob, Client as AzureStorageClient, Container as AzureStorageContainer,
+};
+use azure_sdk_for_rust::storage::container::PublicAccess;
+use bytes::Bytes;
+use futures::future::{self, loop_fn, Either, Loop};
+use futures::Future;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::timer::Delay;
+
+use crate::error::{Error, Result};
+use crate::settings::Settings;
+
+/// Size of the blocks the artifacts are uploaded in. A block is the most that
+/// is uploaded again after a restart.
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// How many times a block, or the final commit of the blocks, is attempted
+/// before the upload is given up until the next restart.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// How long to wait between two attempts.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+const DATA_EXTENSION: &str = "data";
+const STATE_EXTENSION: &str = "json";
+
+/// The progress of an upload, saved next to the artifact after every block.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadState {
+    container: String,
+    blob: String,
+    content_type: String,
+    block_size: usize,
+    block_count: usize,
+    uploaded_blocks: Vec<usize>,
+}
+
+/// An artifact waiting to be uploaded, in full or in part.
+#[derive(Debug)]
+pub struct PendingUpload {
+    state: UploadState,
+    state_path: PathBuf,
+    data_path: PathBuf,
+}
+
+impl PendingUpload {
+    /// Saves `data` in `dir` to be uploaded to `blob` in `container`.
+    pub fn create(
+        dir: &Path,
+        container: &str,
+        blob: &str,
+        content_type: &str,
+        data: &[u8],
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        // the blob name has a directory of its own, as in "1568112000/logs.tar.gz"
+        let stem = format!("{}-{}", container, blob.replace('/', "_"));
+        let data_path = dir.join(&stem).with_extension(DATA_EXTENSION);
+        let state_path = dir.join(&stem).with_extension(STATE_EXTENSION);
+        fs::write(&data_path, data)?;
+
+        let upload = PendingUpload {
+            state: UploadState {
+                container: container.to_string(),
+                blob: blob.to_string(),
+                content_type: content_type.to_string(),
+                block_size: BLOCK_SIZE,
+                block_count: (data.len() + BLOCK_SIZE - 1) / BLOCK_SIZE,
+                uploaded_blocks: vec![],
+            },
+            state_path,
+            data_path,
+        };
+
+        // the state is written last, so that an upload is only resumed once
+        // its artifact is complete
+        upload.save()?;
+        Ok(upload)
+    }
+
+    /// Loads the uploads that were left unfinished in `dir`. An upload whose
+    /// files can't be read is dropped.
+    pub fn load_all(dir: &Path) -> Result<Vec<Self>> {
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut uploads = vec![];
+        for entry in fs::read_dir(dir)? {
+            let state_path = entry?.path();
+            if state_path.extension() != Some(OsStr::new(STATE_EXTENSION)) {
+                continue;
+            }
+            let data_path = state_path.with_extension(DATA_EXTENSION);
+
+            let state = fs::read(&state_path)
+                .map_err(Error::from)
+                .and_then(|state| serde_json::from_slice(&state).map_err(Error::from));
+            match state {
+                Ok(state) if data_path.exists() => uploads.push(PendingUpload {
+                    state,
+                    state_path,
+                    data_path,
+                }),
+                Ok(_) => {
+                    warn!(
+                        "Dropping upload {} whose data is missing",
+                        state_path.display()
+                    );
+                    let _ = fs::remove_file(&state_path);
+                }
+                Err(err) => {
+                    warn!(
+                        "Dropping upload {} that could not be read: {:?}",
+                        state_path.display(),
+                        err
+                    );
+                    let _ = fs::remove_file(&state_path);
+                    let _ = fs::remove_file(&data_path);
+                }
+            }
+        }
+        Ok(uploads)
+    }
+
+    pub fn container(&self) -> &str {
+        &self.state.container
+    }
+
+    pub fn blob(&self) -> &str {
+        &self.state.blob
+    }
+
+    fn remaining_blocks(&self) -> Vec<usize> {
+        (0..self.state.block_count)
+            .filter(|index| !self.state.uploaded_blocks.contains(index))
+            .collect()
+    }
+
+    fn block(&self, data: &Bytes, index: usize) -> Bytes {
+        let start = index * self.state.block_size;
+        let end = (start + self.state.block_size).min(data.len());
+        data.slice(start, end)
+    }
+
+    fn mark_uploaded(&mut self, index: usize) -> Result<()> {
+        self.state.uploaded_blocks.push(index);
+        self.save()
+    }
+
+    // Starts the upload over, e.g. once the storage service dropped the
+    // blocks that weren't committed for too long.
+    fn reset(&mut self) -> Result<()> {
+        self.state.uploaded_blocks.clear();
+        self.save()
+    }
+
+    fn finish(self) -> Result<()> {
+        fs::remove_file(&self.state_path)?;
+        fs::remove_file(&self.data_path)?;
+        Ok(())
+    }
+
+    // The state file is replaced as a whole, so that a restart while it is
+    // written leaves either the old or the new state.
+    fn save(&self) -> Result<()> {
+        let temp_path = self.state_path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_vec(&self.state)?)?;
+        fs::rename(&temp_path, &self.state_path)?;
+        Ok(())
+    }
+}
+
+/// Block ids must all have the same length within a blob.
+fn block_id(index: usize) -> Vec<u8> {
+    format!("{:08}", index).into_bytes()
+}
+
+/// Resumes the uploads that were left unfinished when snitcher last stopped.
+/// An upload that fails is kept to be resumed the next time.
+pub fn resume_pending(settings: &Settings) -> impl Future<Item = (), Error = Error> + Send {
+    let uploads = match PendingUpload::load_all(settings.upload_dir()) {
+        Ok(uploads) => uploads,
+        Err(err) => return Either::A(future::err(err)),
+    };
+    if !uploads.is_empty() {
+        info!("Resuming {} unfinished upload(s)", uploads.len());
+    }
+
+    let settings = settings.clone();
+    Either::B(
+        crate::seq(uploads.into_iter().map(move |upload| {
+            let container = upload.container().to_string();
+            let blob = upload.blob().to_string();
+            upload_blocks(&settings, upload).then(move |result| {
+                match result {
+                    Ok(()) => info!("Resumed upload of {}/{} complete", container, blob),
+                    Err(err) => warn!("Resumed upload of {}/{} failed: {:?}", container, blob, err),
+                }
+                Ok::<(), Error>(())
+            })
+        }))
+        .map(|_| ()),
+    )
+}
+
+/// Uploads the blocks of `upload` that weren't uploaded yet, one after the
+/// other, and then commits them into the blob.
+pub fn upload_blocks(
+    settings: &Settings,
+    upload: PendingUpload,
+) -> impl Future<Item = (), Error = Error> + Send {
+    let data = match fs::read(&upload.data_path) {
+        Ok(data) => Bytes::from(data),
+        Err(err) => return Either::A(future::err(Error::from(err))),
+    };
+    let client = match AzureStorageClient::new(
+        settings.blob_storage_account(),
+        settings.blob_storage_master_key(),
+    ) {
+        Ok(client) => Arc::new(client),
+        Err(err) => return Either::A(future::err(Error::from(err))),
+    };
+
+    let remaining = upload.remaining_blocks();
+    debug!(
+        "Uploading {} of {} blocks of blob {} in container {}",
+        remaining.len(),
+        upload.state.block_count,
+        upload.blob(),
+        upload.container()
+    );
+
+    let fut =
+        ensure_container(client.clone(), upload.container().to_string()).and_then(move |()| {
+            loop_fn(
+                (upload, remaining.into_iter()),
+                move |(mut upload, mut remaining)| match remaining.next() {
+                    Some(index) => {
+                        let client = client.clone();
+                        let block = upload.block(&data, index);
+                        let container = upload.container().to_string();
+                        let blob = upload.blob().to_string();
+                        let fut = retry(format!("block {} of {}", index, blob), move || {
+                            client
+                                .put_block()
+                                .with_container_name(&container)
+                                .with_blob_name(&blob)
+                                .with_block_id(&block_id(index))
+                                .with_body(block.as_ref())
+                                .finalize()
+                                .map(|_| ())
+                                .map_err(Error::from)
+                        })
+                        .and_then(move |()| {
+                            upload.mark_uploaded(index)?;
+                            Ok(Loop::Continue((upload, remaining)))
+                        });
+                        Either::A(fut)
+                    }
+                    None => Either::B(commit_blocks(client.clone(), upload).map(Loop::Break)),
+                },
+            )
+        });
+
+    Either::B(fut)
+}
+
+fn commit_blocks(
+    client: Arc<AzureStorageClient>,
+    mut upload: PendingUpload,
+) -> impl Future<Item = (), Error = Error> + Send {
+    let container = upload.container().to_string();
+    let blob = upload.blob().to_string();
+    let content_type = upload.state.content_type.clone();
+    let block_ids: Vec<Vec<u8>> = (0..upload.state.block_count).map(block_id).collect();
+
+    retry(format!("commit of {}", blob), move || {
+        let block_list = BlockList {
+            blocks: block_ids
+                .iter()
+                .map(|block_id| BlobBlockType::Uncommitted(block_id.as_slice()))
+                .collect(),
+        };
+        client
+            .put_block_list()
+            .with_container_name(&container)
+            .with_blob_name(&blob)
+            .with_block_list(&block_list)
+            .with_content_type(&content_type)
+            .finalize()
+            .map(|_| ())
+            .map_err(Error::from)
+    })
+    .then(move |result| match result {
+        Ok(()) => upload.finish(),
+        Err(err) => {
+            // the service may have dropped the uncommitted blocks, so the
+            // next attempt uploads all of them again
+            upload.reset()?;
+            Err(err)
+        }
+    })
+}
+
+fn ensure_container(
+    client: Arc<AzureStorageClient>,
+    container: String,
+) -> impl Future<Item = (), Error = Error> + Send {
+    client
+        .list_containers()
+        .with_prefix(&container)
+        .finalize()
+        .map_err(Error::from)
+        .and_then(move |containers| {
+            if containers
+                .incomplete_vector
+                .vector
+                .iter()
+                .any(|c| c.name == container)
+            {
+                debug!("Blob container {} already exists.", container);
+                Either::B(future::ok(()))
+            } else {
+                debug!(
+                    "Blob container {} not found. Creating container.",
+                    container
+                );
+                Either::A(
+                    client
+                        .create_container()
+                        .with_container_name(&container)
+                        .with_public_access(PublicAccess::Container)
+                        .finalize()
+                        .map(|_| ())
+                        .map_err(Error::from),
+                )
+            }
+        })
+}
+
+fn retry<F, R>(what: String, mut f: F) -> impl Future<Item = (), Error = Error> + Send
+where
+    F: FnMut() -> R + Send,
+    R: Future<Item = (), Error = Error> + Send,
+{
+    loop_fn(1, move |attempt| {
+        let what = what.clone();
+        f().then(move |result| match result {
+            Ok(()) => Either::A(future::ok(Loop::Break(()))),
+            Err(err) if attempt < MAX_ATTEMPTS => {
+                warn!(
+                    "Upload of {} failed on attempt {}, retrying: {:?}",
+                    what, attempt, err
+                );
+                Either::B(
+                    Delay::new(Instant::now() + RETRY_DELAY)
+                        .map_err(Error::from)
+                        .map(move |_| Loop::Continue(attempt + 1)),
+                )
+            }
+            Err(err) => Either::A(future::err(err)),
+        })
+    })
+}